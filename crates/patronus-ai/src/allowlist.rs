@@ -0,0 +1,246 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::models::{ThreatDetection, ThreatType};
+use crate::rule_generator::AutoRule;
+
+/// What an allowlist entry matches
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AllowlistMatch {
+    /// Any detection whose source is inside the network
+    Cidr(IpNetwork),
+
+    /// Any detection of this threat type
+    ThreatType(ThreatType),
+
+    /// Detections for a specific flow (None = any destination / port)
+    Flow {
+        source: IpAddr,
+        destination: Option<IpAddr>,
+        port: Option<u16>,
+    },
+}
+
+/// Allowlist entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowlistEntry {
+    pub id: String,
+    pub matcher: AllowlistMatch,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Detection this entry was created from, if marked as a false positive
+    pub detection_id: Option<String>,
+}
+
+/// The traffic a detection or auto-generated rule refers to
+#[derive(Debug, Clone)]
+pub struct AllowlistSubject {
+    pub source: Option<IpAddr>,
+    pub destination: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub threat_type: ThreatType,
+}
+
+impl AllowlistSubject {
+    pub fn from_detection(detection: &ThreatDetection) -> Self {
+        Self {
+            source: detection.source_ip.parse().ok(),
+            destination: detection.destination_ip.as_deref().and_then(|ip| ip.parse().ok()),
+            port: detection.destination_port,
+            threat_type: detection.threat_type.clone(),
+        }
+    }
+
+    pub fn from_auto_rule(rule: &AutoRule) -> Self {
+        Self {
+            source: rule.source_ip.parse().ok(),
+            destination: rule.destination_ip.as_deref().and_then(|ip| ip.parse().ok()),
+            port: rule.destination_port,
+            threat_type: rule.threat_type.clone(),
+        }
+    }
+}
+
+impl AllowlistEntry {
+    /// Whether the entry has expired at `now`
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires| now >= expires)
+    }
+
+    /// Whether the entry covers `subject`
+    pub fn matches(&self, subject: &AllowlistSubject) -> bool {
+        match &self.matcher {
+            AllowlistMatch::Cidr(network) => subject.source.is_some_and(|ip| network.contains(ip)),
+            AllowlistMatch::ThreatType(threat_type) => *threat_type == subject.threat_type,
+            AllowlistMatch::Flow { source, destination, port } => {
+                subject.source == Some(*source)
+                    && destination.is_none_or(|dst| subject.destination == Some(dst))
+                    && port.is_none_or(|p| subject.port == Some(p))
+            }
+        }
+    }
+}
+
+/// Allowlist of traffic that must not be alerted on or blocked
+pub struct Allowlist {
+    entries: Arc<RwLock<Vec<AllowlistEntry>>>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Add an entry; a non-empty reason is required
+    pub async fn add(
+        &self,
+        matcher: AllowlistMatch,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<AllowlistEntry> {
+        self.insert(matcher, reason, expires_at, None).await
+    }
+
+    pub(crate) async fn insert(
+        &self,
+        matcher: AllowlistMatch,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+        detection_id: Option<String>,
+    ) -> Result<AllowlistEntry> {
+        if reason.trim().is_empty() {
+            return Err(anyhow::anyhow!("Allowlist entries require a reason"));
+        }
+
+        let entry = AllowlistEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            matcher,
+            reason: reason.to_string(),
+            created_at: Utc::now(),
+            expires_at,
+            detection_id,
+        };
+
+        info!("Added allowlist entry {:?}: {}", entry.matcher, entry.reason);
+        self.entries.write().await.push(entry.clone());
+
+        Ok(entry)
+    }
+
+    /// Remove an entry by ID
+    pub async fn remove(&self, entry_id: &str) -> Result<()> {
+        let mut entries = self.entries.write().await;
+
+        if let Some(pos) = entries.iter().position(|e| e.id == entry_id) {
+            let entry = entries.remove(pos);
+            info!("Removed allowlist entry {:?}", entry.matcher);
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Allowlist entry {} not found", entry_id))
+        }
+    }
+
+    /// Get all entries, including expired ones not yet purged
+    pub async fn entries(&self) -> Vec<AllowlistEntry> {
+        self.entries.read().await.clone()
+    }
+
+    /// First unexpired entry covering `subject`
+    pub async fn find_match(&self, subject: &AllowlistSubject) -> Option<AllowlistEntry> {
+        let now = Utc::now();
+        self.entries.read().await
+            .iter()
+            .find(|e| !e.is_expired(now) && e.matches(subject))
+            .cloned()
+    }
+
+    /// Drop expired entries
+    pub async fn purge_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|e| !e.is_expired(now));
+        before - entries.len()
+    }
+}
+
+impl Default for Allowlist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(source: &str, destination: Option<&str>, port: Option<u16>) -> AllowlistSubject {
+        AllowlistSubject {
+            source: source.parse().ok(),
+            destination: destination.and_then(|d| d.parse().ok()),
+            port,
+            threat_type: ThreatType::PortScan,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_matchers() {
+        let allowlist = Allowlist::new();
+        allowlist.add(
+            AllowlistMatch::Cidr("10.9.0.0/24".parse().unwrap()),
+            "Vulnerability scanner subnet",
+            None,
+        ).await.unwrap();
+        allowlist.add(
+            AllowlistMatch::Flow {
+                source: "192.0.2.10".parse().unwrap(),
+                destination: Some("198.51.100.1".parse().unwrap()),
+                port: Some(443),
+            },
+            "Monitoring probe",
+            None,
+        ).await.unwrap();
+
+        assert!(allowlist.find_match(&subject("10.9.0.77", None, None)).await.is_some());
+        assert!(allowlist.find_match(&subject("10.9.1.77", None, None)).await.is_none());
+
+        assert!(allowlist.find_match(&subject("192.0.2.10", Some("198.51.100.1"), Some(443))).await.is_some());
+        assert!(allowlist.find_match(&subject("192.0.2.10", Some("198.51.100.1"), Some(22))).await.is_none());
+        assert!(allowlist.find_match(&subject("192.0.2.10", None, None)).await.is_none());
+
+        allowlist.add(AllowlistMatch::ThreatType(ThreatType::PortScan), "Lab network", None).await.unwrap();
+        assert!(allowlist.find_match(&subject("203.0.113.5", None, None)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_requires_reason() {
+        let allowlist = Allowlist::new();
+        let result = allowlist.add(AllowlistMatch::ThreatType(ThreatType::DDoS), "  ", None).await;
+        assert!(result.is_err());
+        assert!(allowlist.entries().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_do_not_match() {
+        let allowlist = Allowlist::new();
+        allowlist.add(
+            AllowlistMatch::Cidr("10.9.0.0/24".parse().unwrap()),
+            "Scanner maintenance window",
+            Some(Utc::now() - chrono::Duration::seconds(1)),
+        ).await.unwrap();
+
+        assert!(allowlist.find_match(&subject("10.9.0.1", None, None)).await.is_none());
+        assert_eq!(allowlist.purge_expired().await, 1);
+        assert!(allowlist.entries().await.is_empty());
+    }
+}
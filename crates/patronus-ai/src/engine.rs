@@ -1,14 +1,45 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info};
 
-use crate::feature_collector::{FeatureCollector, FlowFeatures};
-use crate::models::ThreatClassifier;
+use crate::allowlist::{Allowlist, AllowlistEntry, AllowlistMatch, AllowlistSubject};
+use crate::feature_collector::{FeatureCollector, FlowFeatures, SourceFeatures};
+use crate::models::{ModelMetadata, ShadowStats, ThreatClassifier, ThreatDetection};
 use crate::threat_intel::{ThreatIntelDB, ThreatFeedAggregator};
 use crate::rule_generator::{RuleGenerator, RuleGenPolicy};
 use patronus_firewall::rules::RuleManager;
 
+/// Number of recent detections kept for false-positive feedback
+const RECENT_DETECTIONS: usize = 10_000;
+
+/// How long an allowlist entry created from false-positive feedback lasts
+const FALSE_POSITIVE_ALLOW_DAYS: i64 = 30;
+
+/// Detection counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DetectionStats {
+    /// High-confidence detections, including suppressed ones
+    pub detections: u64,
+
+    /// Detections suppressed by the allowlist (not alerted or blocked)
+    pub suppressed: u64,
+
+    /// Suppressed detections per allowlist entry ID
+    pub suppressed_by_entry: HashMap<String, u64>,
+
+    /// Detections marked as false positives
+    pub false_positives: u64,
+
+    /// Auto-generated rules revoked by allowlist changes
+    pub rules_revoked: u64,
+}
+
 /// AI-powered threat detection engine
 pub struct ThreatDetectionEngine {
     feature_collector: Arc<FeatureCollector>,
@@ -16,6 +47,10 @@ pub struct ThreatDetectionEngine {
     threat_intel_db: Arc<ThreatIntelDB>,
     threat_feeds: Arc<ThreatFeedAggregator>,
     rule_generator: Arc<RuleGenerator>,
+    allowlist: Arc<Allowlist>,
+    recent_detections: RwLock<VecDeque<(ThreatDetection, SourceFeatures)>>,
+    false_positive_samples: RwLock<Vec<SourceFeatures>>,
+    stats: RwLock<DetectionStats>,
 }
 
 impl ThreatDetectionEngine {
@@ -43,12 +78,15 @@ impl ThreatDetectionEngine {
             Duration::from_secs(3600),
         ));
 
+        // Allowlist shared with the rule generator
+        let allowlist = Arc::new(Allowlist::new());
+
         // Create rule generator
         let rule_generator = Arc::new(RuleGenerator::new(
             rule_gen_policy,
             rule_manager,
             Arc::clone(&threat_intel_db),
-        ));
+        ).with_allowlist(Arc::clone(&allowlist)));
 
         Self {
             feature_collector,
@@ -56,6 +94,10 @@ impl ThreatDetectionEngine {
             threat_intel_db,
             threat_feeds,
             rule_generator,
+            allowlist,
+            recent_detections: RwLock::new(VecDeque::new()),
+            false_positive_samples: RwLock::new(Vec::new()),
+            stats: RwLock::new(DetectionStats::default()),
         }
     }
 
@@ -135,22 +177,61 @@ impl ThreatDetectionEngine {
 
                 // Process high-confidence threats
                 if detection.confidence > 0.7 {
-                    info!(
-                        "Threat detected: {} from {} (confidence: {:.1}%)",
-                        detection.threat_type.to_string(),
-                        detection.source_ip,
-                        detection.confidence * 100.0
-                    );
-
-                    // Generate firewall rule
-                    if let Err(e) = self.rule_generator.process_threat(&detection).await {
-                        error!("Failed to process threat: {}", e);
-                    }
+                    self.handle_detection(detection, source_features).await;
                 }
             }
         }
     }
 
+    /// Alert on and generate rules for a detection unless it is allowlisted
+    async fn handle_detection(&self, detection: ThreatDetection, features: SourceFeatures) {
+        let suppressed_by = self.allowlist.find_match(&AllowlistSubject::from_detection(&detection)).await;
+
+        {
+            let mut stats = self.stats.write().await;
+            stats.detections += 1;
+            if let Some(entry) = &suppressed_by {
+                stats.suppressed += 1;
+                *stats.suppressed_by_entry.entry(entry.id.clone()).or_insert(0) += 1;
+            }
+        }
+
+        {
+            let mut recent = self.recent_detections.write().await;
+            if recent.len() >= RECENT_DETECTIONS {
+                recent.pop_front();
+            }
+            recent.push_back((detection.clone(), features));
+        }
+
+        if let Some(entry) = suppressed_by {
+            debug!(
+                "Suppressed {} from {} (allowlisted: {})",
+                detection.threat_type.to_string(),
+                detection.source_ip,
+                entry.reason
+            );
+            return;
+        }
+
+        info!(
+            "Threat detected: {} from {} (confidence: {:.1}%)",
+            detection.threat_type.to_string(),
+            detection.source_ip,
+            detection.confidence * 100.0
+        );
+
+        // Generate firewall rule
+        if let Err(e) = self.rule_generator.process_threat(&detection).await {
+            error!("Failed to process threat: {}", e);
+        }
+    }
+
+    /// Feature collector, for wiring additional flow sources
+    pub fn feature_collector(&self) -> Arc<FeatureCollector> {
+        Arc::clone(&self.feature_collector)
+    }
+
     /// Add a flow observation (called from eBPF collector)
     pub async fn observe_flow(&self, flow: FlowFeatures) {
         self.feature_collector.add_flow(flow).await;
@@ -177,6 +258,9 @@ impl ThreatDetectionEngine {
             }
         }
 
+        // Traffic an operator confirmed as benign is normal, whatever its scores
+        normal_features.extend(self.false_positive_samples.read().await.iter().cloned());
+
         info!("Training on {} normal traffic samples", normal_features.len());
 
         // Train classifier
@@ -187,6 +271,36 @@ impl ThreatDetectionEngine {
         Ok(())
     }
 
+    /// Hot-swap the detection model from a versioned bundle
+    pub async fn load_model(&self, path: impl AsRef<Path>) -> Result<ModelMetadata> {
+        self.threat_classifier.read().await.load_from(path)
+    }
+
+    /// Return to the model in use before the last swap
+    pub async fn rollback_model(&self) -> Result<ModelMetadata> {
+        self.threat_classifier.read().await.rollback_model()
+    }
+
+    /// Metadata of the model currently acting on detections
+    pub async fn active_model(&self) -> ModelMetadata {
+        self.threat_classifier.read().await.active_model()
+    }
+
+    /// Score traffic with a candidate model without acting on its verdicts
+    pub async fn load_shadow_model(&self, path: impl AsRef<Path>) -> Result<ModelMetadata> {
+        self.threat_classifier.read().await.load_shadow(path)
+    }
+
+    /// Make the shadow model the active model
+    pub async fn promote_shadow_model(&self) -> Result<ModelMetadata> {
+        self.threat_classifier.read().await.promote_shadow()
+    }
+
+    /// Divergence between the shadow and active models
+    pub async fn get_shadow_stats(&self) -> Option<ShadowStats> {
+        self.threat_classifier.read().await.shadow_stats()
+    }
+
     /// Get pending rules for manual approval
     pub async fn get_pending_rules(&self) -> Vec<crate::rule_generator::AutoRule> {
         self.rule_generator.get_pending_rules().await
@@ -201,6 +315,81 @@ impl ThreatDetectionEngine {
     pub async fn reject_rule(&self, rule_id: &str) -> Result<()> {
         self.rule_generator.reject_rule(rule_id).await
     }
+
+    /// Allowlist traffic and revoke any rules already generated for it
+    pub async fn add_allowlist_entry(
+        &self,
+        matcher: AllowlistMatch,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<AllowlistEntry> {
+        let entry = self.allowlist.add(matcher, reason, expires_at).await?;
+        self.revoke_allowlisted(&entry).await?;
+        Ok(entry)
+    }
+
+    /// Remove an allowlist entry
+    pub async fn remove_allowlist_entry(&self, entry_id: &str) -> Result<()> {
+        self.allowlist.remove(entry_id).await
+    }
+
+    /// Get all allowlist entries
+    pub async fn get_allowlist(&self) -> Vec<AllowlistEntry> {
+        self.allowlist.entries().await
+    }
+
+    /// Mark a detection as a false positive
+    ///
+    /// Allowlists the detection's flow, revokes rules generated for it and
+    /// keeps its features as a normal sample for the next training run.
+    pub async fn mark_false_positive(&self, detection_id: &str) -> Result<AllowlistEntry> {
+        let (detection, features) = self.recent_detections.read().await
+            .iter()
+            .find(|(d, _)| d.id == detection_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Detection {} not found", detection_id))?;
+
+        let source = detection.source_ip.parse()
+            .map_err(|_| anyhow::anyhow!("Detection {} has invalid source {}", detection_id, detection.source_ip))?;
+        let matcher = AllowlistMatch::Flow {
+            source,
+            destination: detection.destination_ip.as_deref().and_then(|ip| ip.parse().ok()),
+            port: detection.destination_port,
+        };
+        let reason = format!(
+            "False positive: {} from {} (detection {})",
+            detection.threat_type.to_string(),
+            detection.source_ip,
+            detection.id
+        );
+        let expires_at = Utc::now() + chrono::Duration::days(FALSE_POSITIVE_ALLOW_DAYS);
+
+        let entry = self.allowlist
+            .insert(matcher, &reason, Some(expires_at), Some(detection.id.clone()))
+            .await?;
+        self.revoke_allowlisted(&entry).await?;
+
+        self.false_positive_samples.write().await.push(features);
+        self.stats.write().await.false_positives += 1;
+
+        Ok(entry)
+    }
+
+    /// Features of detections marked as false positives
+    pub async fn get_false_positive_samples(&self) -> Vec<SourceFeatures> {
+        self.false_positive_samples.read().await.clone()
+    }
+
+    /// Get detection statistics
+    pub async fn get_stats(&self) -> DetectionStats {
+        self.stats.read().await.clone()
+    }
+
+    async fn revoke_allowlisted(&self, entry: &AllowlistEntry) -> Result<()> {
+        let revoked = self.rule_generator.revoke_allowlisted(entry).await?;
+        self.stats.write().await.rules_revoked += revoked.len() as u64;
+        Ok(())
+    }
 }
 
 impl crate::models::ThreatType {
@@ -216,3 +405,110 @@ impl crate::models::ThreatType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ThreatType;
+    use crate::rule_generator::RuleGenPolicy;
+
+    fn features(ip: &str) -> SourceFeatures {
+        SourceFeatures {
+            ip: ip.to_string(),
+            timestamp: Utc::now(),
+            total_flows: 500,
+            total_packets: 1000,
+            total_bytes: 60_000,
+            avg_flow_duration: 10.0,
+            connection_rate: 50.0,
+            unique_dst_ips: 1,
+            unique_dst_ports: 500,
+            port_diversity: 8.9,
+            failed_connections: 480,
+            avg_packet_size: 60.0,
+            packet_size_variance: 1.0,
+            packets_per_flow: 2.0,
+            tcp_ratio: 1.0,
+            udp_ratio: 0.0,
+            icmp_ratio: 0.0,
+            avg_inter_arrival_time: 0.02,
+            inter_arrival_variance: 1.0,
+            flow_duration_variance: 5.0,
+            syn_ratio: 0.5,
+            bytes_asymmetry: 0.9,
+            syn_flood_score: 0.1,
+            port_scan_score: 0.95,
+            ddos_score: 0.1,
+        }
+    }
+
+    fn detection(ip: &str) -> ThreatDetection {
+        ThreatDetection {
+            id: uuid::Uuid::new_v4().to_string(),
+            source_ip: ip.to_string(),
+            destination_ip: None,
+            destination_port: None,
+            threat_type: ThreatType::PortScan,
+            confidence: 0.95,
+            anomaly_score: 0.8,
+            features: HashMap::new(),
+        }
+    }
+
+    fn engine() -> ThreatDetectionEngine {
+        ThreatDetectionEngine::new(Arc::new(RuleManager::new()), RuleGenPolicy::default())
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_detections_are_counted_but_not_blocked() {
+        let engine = engine();
+        let entry = engine.add_allowlist_entry(
+            AllowlistMatch::Cidr("10.9.0.0/24".parse().unwrap()),
+            "Vulnerability scanner",
+            None,
+        ).await.unwrap();
+
+        engine.handle_detection(detection("10.9.0.5"), features("10.9.0.5")).await;
+        engine.handle_detection(detection("1.2.3.4"), features("1.2.3.4")).await;
+
+        let stats = engine.get_stats().await;
+        assert_eq!(stats.detections, 2);
+        assert_eq!(stats.suppressed, 1);
+        assert_eq!(stats.suppressed_by_entry.get(&entry.id), Some(&1));
+
+        let pending = engine.get_pending_rules().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].source_ip, "1.2.3.4");
+    }
+
+    #[tokio::test]
+    async fn test_mark_false_positive() {
+        let engine = engine();
+        let scan = detection("10.9.0.5");
+        let scan_id = scan.id.clone();
+
+        engine.handle_detection(scan, features("10.9.0.5")).await;
+        assert_eq!(engine.get_pending_rules().await.len(), 1);
+
+        let entry = engine.mark_false_positive(&scan_id).await.unwrap();
+        assert_eq!(entry.detection_id.as_deref(), Some(scan_id.as_str()));
+        assert!(entry.expires_at.is_some());
+
+        // The rule is revoked and the sample kept for retraining
+        assert!(engine.get_pending_rules().await.is_empty());
+        let samples = engine.get_false_positive_samples().await;
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].ip, "10.9.0.5");
+
+        let stats = engine.get_stats().await;
+        assert_eq!(stats.false_positives, 1);
+        assert_eq!(stats.rules_revoked, 1);
+
+        // Later detections from the same source are suppressed
+        engine.handle_detection(detection("10.9.0.5"), features("10.9.0.5")).await;
+        assert!(engine.get_pending_rules().await.is_empty());
+        assert_eq!(engine.get_stats().await.suppressed, 1);
+
+        assert!(engine.mark_false_positive("no-such-detection").await.is_err());
+    }
+}
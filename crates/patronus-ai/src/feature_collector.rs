@@ -1,7 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
@@ -63,8 +66,18 @@ pub struct SourceFeatures {
 
     // Timing features
     pub avg_inter_arrival_time: f64,
+    #[serde(default)]
+    pub inter_arrival_variance: f64,
     pub flow_duration_variance: f64,
 
+    // Directionality
+    /// SYN packets as a fraction of all packets
+    #[serde(default)]
+    pub syn_ratio: f64,
+    /// (sent - received) / (sent + received) bytes, from -1 to 1
+    #[serde(default)]
+    pub bytes_asymmetry: f64,
+
     // Anomaly indicators
     pub syn_flood_score: f64,
     pub port_scan_score: f64,
@@ -97,7 +110,10 @@ impl FeatureVector {
             features.udp_ratio,
             features.icmp_ratio,
             features.avg_inter_arrival_time,
+            features.inter_arrival_variance,
             features.flow_duration_variance,
+            features.syn_ratio,
+            features.bytes_asymmetry,
             features.syn_flood_score,
             features.port_scan_score,
             features.ddos_score,
@@ -120,7 +136,10 @@ impl FeatureVector {
             "udp_ratio".to_string(),
             "icmp_ratio".to_string(),
             "avg_inter_arrival_time".to_string(),
+            "inter_arrival_variance".to_string(),
             "flow_duration_variance".to_string(),
+            "syn_ratio".to_string(),
+            "bytes_asymmetry".to_string(),
             "syn_flood_score".to_string(),
             "port_scan_score".to_string(),
             "ddos_score".to_string(),
@@ -128,28 +147,148 @@ impl FeatureVector {
 
         Self { values, labels }
     }
+
+    /// Feature labels in vector order
+    pub fn schema_labels() -> Vec<String> {
+        Self::from_source_features(&empty_features()).labels
+    }
+
+    /// Stable hash of the feature layout, recorded in model bundles so a
+    /// model trained on a different layout is rejected
+    pub fn schema_hash() -> String {
+        let labels = Self::schema_labels().join(",");
+
+        // FNV-1a: unlike DefaultHasher, stable across builds
+        let hash = labels.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        format!("{:016x}", hash)
+    }
+}
+
+/// Runtime-adjustable collection parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectorConfig {
+    /// Sliding window over which source features are computed
+    pub aggregation_window: Duration,
+
+    /// Keep 1 in N flows (1 = keep all); flagged sources are always kept
+    pub sample_rate: u32,
+
+    /// Most sources tracked; the least recently active are evicted
+    pub max_sources: usize,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> Self {
+        Self {
+            aggregation_window: Duration::from_secs(300),
+            sample_rate: 1,
+            max_sources: 100_000,
+        }
+    }
+}
+
+/// Collection counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectorStats {
+    pub flows_observed: u64,
+    pub flows_sampled: u64,
+    pub sources_tracked: u64,
+    pub sources_evicted: u64,
+}
+
+/// Sampled flows from one source within the window
+#[derive(Default)]
+struct SourceWindow {
+    flows: Vec<FlowFeatures>,
+    /// Flows each sample stands for (the sample rate when it was taken)
+    weights: Vec<u32>,
+    last_update: u64,
+}
+
+/// Per-source windows with least-recently-updated eviction
+#[derive(Default)]
+struct SourceTable {
+    sources: HashMap<String, SourceWindow>,
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl SourceTable {
+    fn push(&mut self, flow: FlowFeatures, weight: u32) {
+        self.tick += 1;
+        let tick = self.tick;
+
+        let window = self.sources.entry(flow.src_ip.clone()).or_default();
+        if window.last_update != 0 {
+            self.lru.remove(&window.last_update);
+        }
+        window.last_update = tick;
+        self.lru.insert(tick, flow.src_ip.clone());
+
+        window.flows.push(flow);
+        window.weights.push(weight);
+    }
+
+    /// Evict least recently updated sources beyond `max_sources`
+    fn evict(&mut self, max_sources: usize) -> u64 {
+        let mut evicted = 0;
+        while self.sources.len() > max_sources {
+            let Some((_, ip)) = self.lru.pop_first() else {
+                break;
+            };
+            self.sources.remove(&ip);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    fn remove(&mut self, ip: &str) {
+        if let Some(window) = self.sources.remove(ip) {
+            self.lru.remove(&window.last_update);
+        }
+    }
 }
 
 /// Flow aggregator - groups flows by source IP
 pub struct FlowAggregator {
-    flows: Arc<RwLock<HashMap<String, Vec<FlowFeatures>>>>,
-    aggregation_window: Duration,
+    flows: Arc<RwLock<SourceTable>>,
+    aggregation_window: std::sync::RwLock<Duration>,
 }
 
 impl FlowAggregator {
     pub fn new(aggregation_window: Duration) -> Self {
         Self {
-            flows: Arc::new(RwLock::new(HashMap::new())),
-            aggregation_window,
+            flows: Arc::new(RwLock::new(SourceTable::default())),
+            aggregation_window: std::sync::RwLock::new(aggregation_window),
         }
     }
 
     /// Add a flow to the aggregator
     pub async fn add_flow(&self, flow: FlowFeatures) {
+        self.add_sampled_flow(flow, 1, usize::MAX).await;
+    }
+
+    /// Add a flow that stands for `weight` flows, evicting sources beyond
+    /// `max_sources`; returns the number of sources evicted
+    async fn add_sampled_flow(&self, flow: FlowFeatures, weight: u32, max_sources: usize) -> u64 {
         let mut flows = self.flows.write().await;
-        flows.entry(flow.src_ip.clone())
-            .or_insert_with(Vec::new)
-            .push(flow);
+        flows.push(flow, weight);
+        flows.evict(max_sources)
+    }
+
+    fn window(&self) -> Duration {
+        *self.aggregation_window.read().unwrap()
+    }
+
+    fn set_window(&self, window: Duration) {
+        *self.aggregation_window.write().unwrap() = window;
+    }
+
+    /// Number of sources currently tracked
+    pub async fn source_count(&self) -> usize {
+        self.flows.read().await.sources.len()
     }
 
     /// Compute aggregated features for all sources
@@ -157,12 +296,21 @@ impl FlowAggregator {
         let flows = self.flows.read().await;
         let mut source_features = Vec::new();
 
-        for (src_ip, flow_list) in flows.iter() {
-            if flow_list.is_empty() {
+        // Bytes received per address, for send/receive asymmetry
+        let mut received: HashMap<&str, u64> = HashMap::new();
+        for window in flows.sources.values() {
+            for (flow, weight) in window.flows.iter().zip(&window.weights) {
+                *received.entry(flow.dst_ip.as_str()).or_insert(0) += flow.bytes * *weight as u64;
+            }
+        }
+
+        for (src_ip, window) in flows.sources.iter() {
+            if window.flows.is_empty() {
                 continue;
             }
 
-            let features = self.compute_source_features(src_ip, flow_list)?;
+            let received_bytes = received.get(src_ip.as_str()).copied().unwrap_or(0);
+            let features = self.compute_source_features(src_ip, &window.flows, &window.weights, received_bytes)?;
             source_features.push(features);
         }
 
@@ -170,10 +318,23 @@ impl FlowAggregator {
     }
 
     /// Compute features for a single source IP
-    fn compute_source_features(&self, src_ip: &str, flows: &[FlowFeatures]) -> Result<SourceFeatures> {
-        let total_flows = flows.len() as u32;
-        let total_packets: u64 = flows.iter().map(|f| f.packets).sum();
-        let total_bytes: u64 = flows.iter().map(|f| f.bytes).sum();
+    ///
+    /// Counts are scaled by each sample's weight so sampling does not
+    /// understate volume; distinct-value features use the samples as seen.
+    fn compute_source_features(
+        &self,
+        src_ip: &str,
+        flows: &[FlowFeatures],
+        weights: &[u32],
+        received_bytes: u64,
+    ) -> Result<SourceFeatures> {
+        let weighted = |value: &dyn Fn(&FlowFeatures) -> u64| -> u64 {
+            flows.iter().zip(weights).map(|(f, w)| value(f) * *w as u64).sum()
+        };
+
+        let total_flows = weighted(&|_| 1) as u32;
+        let total_packets: u64 = weighted(&|f| f.packets);
+        let total_bytes: u64 = weighted(&|f| f.bytes);
 
         // Timing features
         let total_duration_ms: u64 = flows.iter().map(|f| f.duration_ms).sum();
@@ -205,9 +366,7 @@ impl FlowAggregator {
         let port_diversity = self.calculate_port_entropy(flows);
 
         // Failed connections (RST flags)
-        let failed_connections = flows.iter()
-            .filter(|f| f.rst_count > 0)
-            .count() as u32;
+        let failed_connections = weighted(&|f| (f.rst_count > 0) as u64) as u32;
 
         // Packet characteristics
         let avg_packet_size = if total_packets > 0 {
@@ -228,9 +387,9 @@ impl FlowAggregator {
         };
 
         // Protocol distribution
-        let tcp_count = flows.iter().filter(|f| f.protocol == 6).count() as f64;
-        let udp_count = flows.iter().filter(|f| f.protocol == 17).count() as f64;
-        let icmp_count = flows.iter().filter(|f| f.protocol == 1).count() as f64;
+        let tcp_count = weighted(&|f| (f.protocol == 6) as u64) as f64;
+        let udp_count = weighted(&|f| (f.protocol == 17) as u64) as f64;
+        let icmp_count = weighted(&|f| (f.protocol == 1) as u64) as f64;
 
         let tcp_ratio = tcp_count / total_flows as f64;
         let udp_ratio = udp_count / total_flows as f64;
@@ -247,6 +406,20 @@ impl FlowAggregator {
         } else {
             0.0
         };
+        let inter_arrival_variance = self.calculate_variance(&inter_arrival_times);
+
+        // Directionality
+        let syn_packets = weighted(&|f| f.syn_count as u64);
+        let syn_ratio = if total_packets > 0 {
+            (syn_packets as f64 / total_packets as f64).min(1.0)
+        } else {
+            0.0
+        };
+        let bytes_asymmetry = if total_bytes + received_bytes > 0 {
+            (total_bytes as f64 - received_bytes as f64) / (total_bytes + received_bytes) as f64
+        } else {
+            0.0
+        };
 
         let flow_durations: Vec<f64> = flows.iter().map(|f| f.duration_ms as f64).collect();
         let flow_duration_variance = self.calculate_variance(&flow_durations);
@@ -283,7 +456,10 @@ impl FlowAggregator {
             udp_ratio,
             icmp_ratio,
             avg_inter_arrival_time,
+            inter_arrival_variance,
             flow_duration_variance,
+            syn_ratio,
+            bytes_asymmetry,
             syn_flood_score,
             port_scan_score,
             ddos_score,
@@ -370,15 +546,29 @@ impl FlowAggregator {
 
     /// Clear old flows outside the aggregation window
     pub async fn cleanup_old_flows(&self) {
-        let cutoff = Utc::now() - chrono::Duration::from_std(self.aggregation_window).unwrap();
+        let window = chrono::Duration::from_std(self.window()).unwrap_or(chrono::Duration::MAX);
+        let cutoff = Utc::now().checked_sub_signed(window).unwrap_or(DateTime::<Utc>::MIN_UTC);
         let mut flows = self.flows.write().await;
 
-        for flow_list in flows.values_mut() {
-            flow_list.retain(|f| f.timestamp > cutoff);
+        let mut empty = Vec::new();
+        for (ip, source) in flows.sources.iter_mut() {
+            let (kept, weights): (Vec<_>, Vec<_>) = std::mem::take(&mut source.flows)
+                .into_iter()
+                .zip(std::mem::take(&mut source.weights))
+                .filter(|(f, _)| f.timestamp > cutoff)
+                .unzip();
+            source.flows = kept;
+            source.weights = weights;
+
+            if source.flows.is_empty() {
+                empty.push(ip.clone());
+            }
         }
 
         // Remove empty entries
-        flows.retain(|_, v| !v.is_empty());
+        for ip in empty {
+            flows.remove(&ip);
+        }
     }
 }
 
@@ -386,16 +576,71 @@ impl FlowAggregator {
 pub struct FeatureCollector {
     aggregator: FlowAggregator,
     collection_interval: Duration,
+    config: RwLock<CollectorConfig>,
+    flagged_sources: RwLock<HashSet<String>>,
+    stats: RwLock<CollectorStats>,
 }
 
 impl FeatureCollector {
     pub fn new(aggregation_window: Duration, collection_interval: Duration) -> Self {
+        Self::with_config(
+            CollectorConfig {
+                aggregation_window,
+                ..CollectorConfig::default()
+            },
+            collection_interval,
+        )
+    }
+
+    pub fn with_config(config: CollectorConfig, collection_interval: Duration) -> Self {
         Self {
-            aggregator: FlowAggregator::new(aggregation_window),
+            aggregator: FlowAggregator::new(config.aggregation_window),
             collection_interval,
+            config: RwLock::new(config),
+            flagged_sources: RwLock::new(HashSet::new()),
+            stats: RwLock::new(CollectorStats::default()),
         }
     }
 
+    /// Current collection parameters
+    pub async fn config(&self) -> CollectorConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Change sampling and window parameters
+    ///
+    /// Accumulated flows are kept; a shorter window takes effect at the next
+    /// cleanup and a lower source limit evicts the least recently active.
+    pub async fn update_config(&self, config: CollectorConfig) {
+        self.aggregator.set_window(config.aggregation_window);
+
+        let evicted = self.aggregator.flows.write().await.evict(config.max_sources);
+        self.stats.write().await.sources_evicted += evicted;
+
+        info!(
+            "Feature collector: window {:?}, sampling 1:{}, max {} sources",
+            config.aggregation_window, config.sample_rate, config.max_sources
+        );
+        *self.config.write().await = config;
+    }
+
+    /// Always sample flows from `ip`
+    pub async fn flag_source(&self, ip: &str) {
+        self.flagged_sources.write().await.insert(ip.to_string());
+    }
+
+    /// Return `ip` to normal sampling
+    pub async fn unflag_source(&self, ip: &str) {
+        self.flagged_sources.write().await.remove(ip);
+    }
+
+    /// Collection counters
+    pub async fn stats(&self) -> CollectorStats {
+        let mut stats = self.stats.read().await.clone();
+        stats.sources_tracked = self.aggregator.source_count().await as u64;
+        stats
+    }
+
     /// Start the feature collection loop
     pub async fn start(self: Arc<Self>) {
         info!("Starting AI feature collector");
@@ -442,15 +687,102 @@ impl FeatureCollector {
         }
     }
 
-    /// Add a flow observation
-    pub async fn add_flow(&self, flow: FlowFeatures) {
-        self.aggregator.add_flow(flow).await;
+    /// Add a flow observation, subject to sampling
+    ///
+    /// Returns whether the flow was sampled.
+    pub async fn add_flow(&self, flow: FlowFeatures) -> bool {
+        let (sample_rate, max_sources) = {
+            let config = self.config.read().await;
+            (config.sample_rate.max(1), config.max_sources)
+        };
+        let flagged = self.flagged_sources.read().await.contains(&flow.src_ip);
+
+        // Sampling by flow hash keeps every record of a sampled flow
+        let weight = if flagged { 1 } else { sample_rate };
+        let sampled = weight == 1 || flow_hash(&flow).is_multiple_of(sample_rate as u64);
+
+        let evicted = if sampled {
+            self.aggregator.add_sampled_flow(flow, weight, max_sources).await
+        } else {
+            0
+        };
+
+        let mut stats = self.stats.write().await;
+        stats.flows_observed += 1;
+        stats.flows_sampled += sampled as u64;
+        stats.sources_evicted += evicted;
+
+        sampled
     }
 
     /// Get current aggregated features
     pub async fn get_features(&self) -> Result<Vec<SourceFeatures>> {
         self.aggregator.aggregate_features().await
     }
+
+    /// Write the current window's per-source features as CSV for offline
+    /// training
+    pub async fn export_window(&self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let features = self.get_features().await?;
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let labels = FeatureVector::from_source_features(&empty_features()).labels;
+        let mut header = vec!["ip".to_string(), "timestamp".to_string()];
+        header.extend(labels);
+        writer.write_record(&header)?;
+
+        for feature in &features {
+            let mut row = vec![feature.ip.clone(), feature.timestamp.to_rfc3339()];
+            row.extend(FeatureVector::from_source_features(feature).values.iter().map(|v| v.to_string()));
+            writer.write_record(&row)?;
+        }
+
+        let data = writer.into_inner().map_err(|e| anyhow::anyhow!("Failed to encode CSV: {}", e))?;
+        tokio::fs::write(path, data).await
+            .with_context(|| format!("Failed to write feature export {}", path.display()))?;
+
+        info!("Exported features for {} sources to {}", features.len(), path.display());
+        Ok(features.len())
+    }
+}
+
+/// Stable hash of a flow's 5-tuple
+fn flow_hash(flow: &FlowFeatures) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (&flow.src_ip, &flow.dst_ip, flow.src_port, flow.dst_port, flow.protocol).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Features with every value zero, used for the export header and schema hash
+pub(crate) fn empty_features() -> SourceFeatures {
+    SourceFeatures {
+        ip: String::new(),
+        timestamp: Utc::now(),
+        total_flows: 0,
+        total_packets: 0,
+        total_bytes: 0,
+        avg_flow_duration: 0.0,
+        connection_rate: 0.0,
+        unique_dst_ips: 0,
+        unique_dst_ports: 0,
+        port_diversity: 0.0,
+        failed_connections: 0,
+        avg_packet_size: 0.0,
+        packet_size_variance: 0.0,
+        packets_per_flow: 0.0,
+        tcp_ratio: 0.0,
+        udp_ratio: 0.0,
+        icmp_ratio: 0.0,
+        avg_inter_arrival_time: 0.0,
+        inter_arrival_variance: 0.0,
+        flow_duration_variance: 0.0,
+        syn_ratio: 0.0,
+        bytes_asymmetry: 0.0,
+        syn_flood_score: 0.0,
+        port_scan_score: 0.0,
+        ddos_score: 0.0,
+    }
 }
 
 #[cfg(test)]
@@ -503,4 +835,117 @@ mod tests {
             connection_rate: 1.0,
         }
     }
+
+    fn flow_at(src: &str, dst: &str, dst_port: u16, offset_ms: i64) -> FlowFeatures {
+        FlowFeatures {
+            timestamp: Utc::now() + chrono::Duration::milliseconds(offset_ms),
+            ..create_test_flow(src, dst, dst_port)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sliding_window_features() {
+        let collector = FeatureCollector::new(Duration::from_secs(300), Duration::from_secs(60));
+
+        // Fan-out to 4 hosts with uneven gaps; 40 SYN-only packets out of 40
+        for (i, offset) in [0, 100, 300, 700].into_iter().enumerate() {
+            let mut flow = flow_at("10.0.0.1", &format!("192.0.2.{}", i + 1), 80, offset);
+            flow.syn_count = 10;
+            collector.add_flow(flow).await;
+        }
+        // One reply of 1000 bytes against 4000 sent
+        collector.add_flow(create_test_flow("192.0.2.1", "10.0.0.1", 40000)).await;
+
+        let features = collector.get_features().await.unwrap();
+        let source = features.iter().find(|f| f.ip == "10.0.0.1").unwrap();
+
+        assert_eq!(source.unique_dst_ips, 4);
+        assert_eq!(source.syn_ratio, 1.0);
+        assert!((source.bytes_asymmetry - 0.6).abs() < 1e-9);
+        // Inter-arrival times 100, 200, 400 ms
+        let mean = 700.0 / 3.0;
+        let variance = [100.0f64, 200.0, 400.0].iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 3.0;
+        assert!((source.inter_arrival_variance - variance).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_sampling_with_flagged_sources() {
+        let collector = FeatureCollector::with_config(
+            CollectorConfig {
+                sample_rate: 10,
+                ..CollectorConfig::default()
+            },
+            Duration::from_secs(60),
+        );
+        collector.flag_source("10.0.0.99").await;
+
+        for port in 0..1000 {
+            collector.add_flow(create_test_flow("10.0.0.1", "192.0.2.1", port)).await;
+        }
+        for port in 0..50 {
+            assert!(collector.add_flow(create_test_flow("10.0.0.99", "192.0.2.1", port)).await);
+        }
+
+        let stats = collector.stats().await;
+        assert_eq!(stats.flows_observed, 1050);
+        let sampled = stats.flows_sampled - 50;
+        assert!(sampled > 50 && sampled < 150, "sampled {} of 1000", sampled);
+
+        // Sampled counts are scaled back up; flagged sources are exact
+        let features = collector.get_features().await.unwrap();
+        let normal = features.iter().find(|f| f.ip == "10.0.0.1").unwrap();
+        assert_eq!(normal.total_flows as u64, sampled * 10);
+        let flagged = features.iter().find(|f| f.ip == "10.0.0.99").unwrap();
+        assert_eq!(flagged.total_flows, 50);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_config_keeps_state() {
+        let collector = FeatureCollector::new(Duration::from_secs(300), Duration::from_secs(60));
+        for i in 0..5 {
+            collector.add_flow(create_test_flow(&format!("10.0.0.{}", i), "192.0.2.1", 80)).await;
+        }
+        // Touch the first source so it is the most recently active
+        collector.add_flow(create_test_flow("10.0.0.0", "192.0.2.1", 443)).await;
+
+        collector.update_config(CollectorConfig {
+            aggregation_window: Duration::from_secs(600),
+            sample_rate: 4,
+            max_sources: 3,
+        }).await;
+
+        let mut ips: Vec<String> = collector.get_features().await.unwrap().into_iter().map(|f| f.ip).collect();
+        ips.sort();
+        assert_eq!(ips, vec!["10.0.0.0", "10.0.0.3", "10.0.0.4"]);
+
+        let stats = collector.stats().await;
+        assert_eq!(stats.sources_tracked, 3);
+        assert_eq!(stats.sources_evicted, 2);
+        assert_eq!(collector.config().await.sample_rate, 4);
+
+        collector.aggregator.cleanup_old_flows().await;
+        assert_eq!(collector.stats().await.sources_tracked, 3);
+    }
+
+    #[tokio::test]
+    async fn test_export_window_csv() {
+        let collector = FeatureCollector::new(Duration::from_secs(300), Duration::from_secs(60));
+        collector.add_flow(create_test_flow("10.0.0.1", "192.0.2.1", 80)).await;
+        collector.add_flow(create_test_flow("10.0.0.2", "192.0.2.1", 80)).await;
+
+        let dir = std::env::temp_dir().join(format!("patronus-ai-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("window.csv");
+
+        assert_eq!(collector.export_window(&path).await.unwrap(), 2);
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(&headers[0], "ip");
+        assert_eq!(headers.len(), 2 + FeatureVector::from_source_features(&empty_features()).values.len());
+        assert!(headers.iter().any(|h| h == "bytes_asymmetry"));
+        assert_eq!(reader.records().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -8,9 +8,15 @@ pub mod models;
 pub mod threat_intel;
 pub mod rule_generator;
 pub mod engine;
+pub mod allowlist;
+#[cfg(feature = "sdwan")]
+pub mod sdwan_feed;
 
-pub use feature_collector::{FeatureCollector, FlowFeatures, SourceFeatures, FeatureVector};
-pub use models::{ThreatClassifier, ThreatDetection, ThreatType};
+pub use feature_collector::{CollectorConfig, CollectorStats, FeatureCollector, FlowFeatures, SourceFeatures, FeatureVector};
+pub use models::{ModelMetadata, ShadowStats, ThreatClassifier, ThreatDetection, ThreatType};
 pub use threat_intel::{ThreatIntelDB, ThreatFeedAggregator, ThreatIntelEntry, ThreatCategory, ThreatSource};
-pub use rule_generator::{RuleGenerator, RuleGenPolicy, AutoRule};
-pub use engine::ThreatDetectionEngine;
+pub use rule_generator::{RuleGenerator, RuleGenPolicy, AutoRule, ResponseAction, ResponseClause, ResponseEnforcer};
+pub use engine::{ThreatDetectionEngine, DetectionStats};
+pub use allowlist::{Allowlist, AllowlistEntry, AllowlistMatch, AllowlistSubject};
+#[cfg(feature = "sdwan")]
+pub use sdwan_feed::SdwanFlowFeed;
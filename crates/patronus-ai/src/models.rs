@@ -1,8 +1,13 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use ndarray::{Array1, Array2};
+use patronus_ml::VersionedModel;
+use patronus_mlops::ModelType;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{debug, info};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, info, warn};
 
 use crate::feature_collector::{FeatureVector, SourceFeatures};
 
@@ -21,7 +26,15 @@ pub enum ThreatType {
 /// Threat detection result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThreatDetection {
+    /// Unique detection ID, used to give feedback on the detection
+    #[serde(default)]
+    pub id: String,
     pub source_ip: String,
+    /// Destination of the offending flow, when the detection is flow-specific
+    #[serde(default)]
+    pub destination_ip: Option<String>,
+    #[serde(default)]
+    pub destination_port: Option<u16>,
     pub threat_type: ThreatType,
     pub confidence: f64,
     pub anomaly_score: f64,
@@ -29,6 +42,7 @@ pub struct ThreatDetection {
 }
 
 /// Isolation Forest for anomaly detection
+#[derive(Serialize, Deserialize)]
 pub struct IsolationForest {
     num_trees: usize,
     sample_size: usize,
@@ -36,11 +50,13 @@ pub struct IsolationForest {
     avg_path_length: f64,
 }
 
+#[derive(Serialize, Deserialize)]
 struct IsolationTree {
     root: Option<Box<TreeNode>>,
     height_limit: usize,
 }
 
+#[derive(Serialize, Deserialize)]
 struct TreeNode {
     split_feature: usize,
     split_value: f64,
@@ -208,6 +224,18 @@ impl IsolationForest {
         }
     }
 
+    /// Whether every split refers to one of `num_features` features
+    fn fits_features(&self, num_features: usize) -> bool {
+        fn fits(node: &Option<Box<TreeNode>>, num_features: usize) -> bool {
+            node.as_ref().is_none_or(|n| {
+                n.split_feature < num_features
+                    && fits(&n.left, num_features)
+                    && fits(&n.right, num_features)
+            })
+        }
+        self.trees.iter().all(|tree| fits(&tree.root, num_features))
+    }
+
     fn compute_c(&self, n: usize) -> f64 {
         if n <= 1 {
             return 0.0;
@@ -216,19 +244,171 @@ impl IsolationForest {
     }
 }
 
+/// Model bundle file holding the metadata
+const BUNDLE_METADATA: &str = "metadata.json";
+
+/// Model bundle file holding the weights
+const BUNDLE_WEIGHTS: &str = "weights.json";
+
+/// Version reported before any model is trained or loaded
+const UNTRAINED_VERSION: &str = "untrained";
+
+/// Metadata stored alongside model weights
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelMetadata {
+    pub version: String,
+
+    /// `FeatureVector::schema_hash()` of the layout the model was trained on
+    pub feature_schema_hash: String,
+
+    pub trained_at: DateTime<Utc>,
+}
+
+/// Trained model weights with their metadata
+struct ClassifierModel {
+    metadata: ModelMetadata,
+    forest: IsolationForest,
+}
+
+impl ClassifierModel {
+    fn new(version: String, forest: IsolationForest) -> Self {
+        Self {
+            metadata: ModelMetadata {
+                version,
+                feature_schema_hash: FeatureVector::schema_hash(),
+                trained_at: Utc::now(),
+            },
+            forest,
+        }
+    }
+
+    /// Read a bundle directory, rejecting models trained on another feature layout
+    fn load(path: &Path) -> Result<Self> {
+        let metadata_path = path.join(BUNDLE_METADATA);
+        let metadata: ModelMetadata = serde_json::from_slice(
+            &std::fs::read(&metadata_path)
+                .with_context(|| format!("Failed to read {}", metadata_path.display()))?,
+        ).with_context(|| format!("Invalid model metadata in {}", metadata_path.display()))?;
+
+        let schema_hash = FeatureVector::schema_hash();
+        if metadata.feature_schema_hash != schema_hash {
+            return Err(anyhow::anyhow!(
+                "Model {} was trained on feature schema {}, live schema is {}",
+                metadata.version,
+                metadata.feature_schema_hash,
+                schema_hash
+            ));
+        }
+
+        let weights_path = path.join(BUNDLE_WEIGHTS);
+        let forest: IsolationForest = serde_json::from_slice(
+            &std::fs::read(&weights_path)
+                .with_context(|| format!("Failed to read {}", weights_path.display()))?,
+        ).with_context(|| format!("Invalid model weights in {}", weights_path.display()))?;
+
+        let num_features = FeatureVector::schema_labels().len();
+        if !forest.fits_features(num_features) {
+            return Err(anyhow::anyhow!(
+                "Model {} splits on features outside the {}-feature schema",
+                metadata.version,
+                num_features
+            ));
+        }
+
+        Ok(Self { metadata, forest })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        std::fs::write(path.join(BUNDLE_METADATA), serde_json::to_vec_pretty(&self.metadata)?)?;
+        std::fs::write(path.join(BUNDLE_WEIGHTS), serde_json::to_vec(&self.forest)?)?;
+        Ok(())
+    }
+
+    fn anomaly_score(&self, vector: &FeatureVector) -> f64 {
+        self.forest.predict(&Array1::from_vec(vector.values.clone()))
+    }
+}
+
+/// Agreement between the shadow model and the active model
+///
+/// The divergence rate is the drift score fed to patronus-mlops retraining
+/// triggers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShadowStats {
+    /// Version of the shadow model
+    pub model_version: String,
+
+    /// Detections scored by both models
+    pub evaluations: u64,
+
+    /// Detections where the models disagreed on the threat type
+    pub verdict_divergences: u64,
+
+    /// Sum of absolute anomaly score differences
+    pub score_delta_sum: f64,
+}
+
+impl ShadowStats {
+    /// Fraction of detections where the verdicts differed
+    pub fn divergence_rate(&self) -> f64 {
+        if self.evaluations == 0 {
+            0.0
+        } else {
+            self.verdict_divergences as f64 / self.evaluations as f64
+        }
+    }
+
+    /// Mean absolute anomaly score difference
+    pub fn mean_score_delta(&self) -> f64 {
+        if self.evaluations == 0 {
+            0.0
+        } else {
+            self.score_delta_sum / self.evaluations as f64
+        }
+    }
+}
+
+/// Models held by the classifier
+struct ModelSlots {
+    active: Arc<ClassifierModel>,
+
+    /// Model replaced by the last swap, kept for rollback
+    previous: Option<Arc<ClassifierModel>>,
+
+    /// Model scoring traffic without acting on it
+    shadow: Option<Arc<ClassifierModel>>,
+}
+
 /// Rule-based threat classifier
+///
+/// Models are swapped atomically: a detection holds a reference to the
+/// model it started with, so in-flight classifications finish on the old
+/// model while new ones use the new one.
 pub struct ThreatClassifier {
-    isolation_forest: Option<IsolationForest>,
+    models: RwLock<ModelSlots>,
+    shadow_stats: Mutex<ShadowStats>,
 }
 
 impl ThreatClassifier {
     pub fn new() -> Self {
         Self {
-            isolation_forest: Some(IsolationForest::new(100, 256)),
+            models: RwLock::new(ModelSlots {
+                active: Arc::new(ClassifierModel::new(
+                    UNTRAINED_VERSION.to_string(),
+                    IsolationForest::new(100, 256),
+                )),
+                previous: None,
+                shadow: None,
+            }),
+            shadow_stats: Mutex::new(ShadowStats::default()),
         }
     }
 
     /// Train on baseline normal traffic
+    ///
+    /// The trained model replaces the active one, which is kept for rollback.
     pub fn train(&mut self, normal_features: &[SourceFeatures]) -> Result<()> {
         if normal_features.is_empty() {
             return Ok(());
@@ -250,28 +430,123 @@ impl ThreatClassifier {
         }
 
         // Train isolation forest
-        if let Some(ref mut forest) = self.isolation_forest {
-            forest.train(&data)?;
-        }
+        let mut forest = IsolationForest::new(100, 256);
+        forest.train(&data)?;
+
+        let version = format!("local-{}", Utc::now().format("%Y%m%dT%H%M%S"));
+        self.activate(ClassifierModel::new(version, forest));
 
         Ok(())
     }
 
+    /// Load a versioned model bundle and make it the active model
+    ///
+    /// The bundle is a directory holding `metadata.json` and `weights.json`.
+    /// The previous model is kept for [`rollback_model`](Self::rollback_model).
+    pub fn load_from(&self, path: impl AsRef<Path>) -> Result<ModelMetadata> {
+        let model = ClassifierModel::load(path.as_ref())?;
+        let metadata = model.metadata.clone();
+        self.activate(model);
+        Ok(metadata)
+    }
+
+    /// Write the active model as a bundle
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.active().save(path.as_ref())
+    }
+
+    /// Swap back to the model replaced by the last load, train or rollback
+    pub fn rollback_model(&self) -> Result<ModelMetadata> {
+        let mut models = self.models.write().unwrap();
+        let previous = models.previous.take()
+            .ok_or_else(|| anyhow::anyhow!("No previous model to roll back to"))?;
+
+        let replaced = std::mem::replace(&mut models.active, previous);
+        warn!(
+            "Rolled back threat model {} to {}",
+            replaced.metadata.version,
+            models.active.metadata.version
+        );
+        models.previous = Some(replaced);
+
+        Ok(models.active.metadata.clone())
+    }
+
+    /// Metadata of the active model
+    pub fn active_model(&self) -> ModelMetadata {
+        self.active().metadata.clone()
+    }
+
+    /// Version of the active model
+    pub fn active_version(&self) -> String {
+        self.active().metadata.version.clone()
+    }
+
+    /// Load a bundle in shadow mode: it scores traffic alongside the active
+    /// model, but only the active model's verdicts are returned
+    pub fn load_shadow(&self, path: impl AsRef<Path>) -> Result<ModelMetadata> {
+        let model = ClassifierModel::load(path.as_ref())?;
+        let metadata = model.metadata.clone();
+
+        info!("Shadowing threat model {}", metadata.version);
+        self.models.write().unwrap().shadow = Some(Arc::new(model));
+        *self.shadow_stats.lock().unwrap() = ShadowStats {
+            model_version: metadata.version.clone(),
+            ..ShadowStats::default()
+        };
+
+        Ok(metadata)
+    }
+
+    /// Make the shadow model the active model
+    pub fn promote_shadow(&self) -> Result<ModelMetadata> {
+        let shadow = self.models.write().unwrap().shadow.take()
+            .ok_or_else(|| anyhow::anyhow!("No shadow model loaded"))?;
+        let metadata = shadow.metadata.clone();
+        self.swap_active(shadow);
+        Ok(metadata)
+    }
+
+    /// Stop shadow scoring
+    pub fn clear_shadow(&self) {
+        self.models.write().unwrap().shadow = None;
+    }
+
+    /// Divergence between the shadow and active models, if a shadow is loaded
+    pub fn shadow_stats(&self) -> Option<ShadowStats> {
+        self.models.read().unwrap().shadow.as_ref()?;
+        Some(self.shadow_stats.lock().unwrap().clone())
+    }
+
     /// Detect threats in observed traffic
     pub fn detect(&self, features: &SourceFeatures) -> ThreatDetection {
+        let (active, shadow) = {
+            let models = self.models.read().unwrap();
+            (Arc::clone(&models.active), models.shadow.clone())
+        };
+
         let vector = FeatureVector::from_source_features(features);
 
         // Compute anomaly score using Isolation Forest
-        let anomaly_score = if let Some(ref forest) = self.isolation_forest {
-            let arr = Array1::from_vec(vector.values.clone());
-            forest.predict(&arr)
-        } else {
-            0.0
-        };
+        let anomaly_score = active.anomaly_score(&vector);
 
         // Rule-based classification
         let (threat_type, confidence) = self.classify_threat(features, anomaly_score);
 
+        if let Some(shadow) = shadow {
+            let shadow_score = shadow.anomaly_score(&vector);
+            let (shadow_type, _) = self.classify_threat(features, shadow_score);
+
+            let mut stats = self.shadow_stats.lock().unwrap();
+            if stats.model_version == shadow.metadata.version {
+                stats.evaluations += 1;
+                stats.score_delta_sum += (shadow_score - anomaly_score).abs();
+                if shadow_type != threat_type {
+                    stats.verdict_divergences += 1;
+                }
+            }
+        }
+
         // Build feature map for transparency
         let mut feature_map = HashMap::new();
         for (label, value) in vector.labels.iter().zip(vector.values.iter()) {
@@ -279,7 +554,10 @@ impl ThreatClassifier {
         }
 
         ThreatDetection {
+            id: uuid::Uuid::new_v4().to_string(),
             source_ip: features.ip.clone(),
+            destination_ip: None,
+            destination_port: None,
             threat_type,
             confidence,
             anomaly_score,
@@ -287,6 +565,25 @@ impl ThreatClassifier {
         }
     }
 
+    fn active(&self) -> Arc<ClassifierModel> {
+        Arc::clone(&self.models.read().unwrap().active)
+    }
+
+    fn activate(&self, model: ClassifierModel) {
+        self.swap_active(Arc::new(model));
+    }
+
+    fn swap_active(&self, model: Arc<ClassifierModel>) {
+        let mut models = self.models.write().unwrap();
+        info!(
+            "Activating threat model {} (replacing {})",
+            model.metadata.version,
+            models.active.metadata.version
+        );
+        let replaced = std::mem::replace(&mut models.active, model);
+        models.previous = Some(replaced);
+    }
+
     fn classify_threat(&self, features: &SourceFeatures, anomaly_score: f64) -> (ThreatType, f64) {
         // Port scanning detection
         if features.port_scan_score > 0.7 {
@@ -338,6 +635,16 @@ impl Default for ThreatClassifier {
     }
 }
 
+impl VersionedModel for ThreatClassifier {
+    const MODEL_TYPE: ModelType = ModelType::ThreatClassification;
+
+    fn load_version(path: &Path) -> Result<Self> {
+        let classifier = ThreatClassifier::new();
+        classifier.load_from(path)?;
+        Ok(classifier)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -365,4 +672,137 @@ mod tests {
 
         assert!(score_anomaly > score_normal);
     }
+
+    /// A model that scores everything as maximally anomalous
+    fn always_anomalous(version: &str) -> ClassifierModel {
+        let forest = IsolationForest {
+            num_trees: 1,
+            sample_size: 1,
+            trees: vec![IsolationTree { root: None, height_limit: 0 }],
+            avg_path_length: 1.0,
+        };
+        ClassifierModel::new(version.to_string(), forest)
+    }
+
+    fn quiet_source() -> SourceFeatures {
+        SourceFeatures {
+            ip: "10.0.0.1".to_string(),
+            ..crate::feature_collector::empty_features()
+        }
+    }
+
+    #[test]
+    fn test_load_bundle_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        always_anomalous("2026.10.1").save(dir.path()).unwrap();
+
+        let classifier = ThreatClassifier::new();
+        assert_eq!(classifier.active_version(), UNTRAINED_VERSION);
+        assert!(classifier.rollback_model().is_err());
+        assert_eq!(classifier.detect(&quiet_source()).threat_type, ThreatType::Normal);
+
+        let metadata = classifier.load_from(dir.path()).unwrap();
+        assert_eq!(metadata.version, "2026.10.1");
+        assert_eq!(metadata.feature_schema_hash, FeatureVector::schema_hash());
+        assert_eq!(classifier.active_model(), metadata);
+        assert_eq!(classifier.detect(&quiet_source()).threat_type, ThreatType::Unknown);
+
+        // Rolling back twice returns to the loaded model
+        assert_eq!(classifier.rollback_model().unwrap().version, UNTRAINED_VERSION);
+        assert_eq!(classifier.detect(&quiet_source()).threat_type, ThreatType::Normal);
+        assert_eq!(classifier.rollback_model().unwrap().version, "2026.10.1");
+    }
+
+    #[test]
+    fn test_loads_registry_versions() {
+        use patronus_ml::ModelSet;
+        use patronus_mlops::{ModelRegistry, ModelStatus, ModelVersion};
+
+        let dir = tempfile::tempdir().unwrap();
+        always_anomalous("2026.10.1").save(dir.path()).unwrap();
+        let mut version = ModelVersion::new("threat-classifier", "2026.10.1", ModelType::ThreatClassification, "judy")
+            .with_bundle(dir.path());
+        version.status = ModelStatus::Registered;
+
+        let mut registry = ModelRegistry::new();
+        let id = registry.register_model(version).unwrap();
+        registry.deploy_model(&id).unwrap();
+        let mut classifiers = ModelSet::<ThreatClassifier>::new();
+        registry.sync_consumer("threat-classifier", &mut classifiers).unwrap();
+
+        let (routed, classifier) = classifiers.route("192.0.2.7").unwrap();
+        assert_eq!(routed, id);
+        assert_eq!(classifier.active_version(), "2026.10.1");
+        assert_eq!(classifier.detect(&quiet_source()).threat_type, ThreatType::Unknown);
+        assert_eq!(registry.consumers_of(&id), vec!["threat-classifier"]);
+    }
+
+    #[test]
+    fn test_in_flight_detection_keeps_old_model() {
+        let dir = tempfile::tempdir().unwrap();
+        always_anomalous("2026.10.1").save(dir.path()).unwrap();
+
+        let classifier = ThreatClassifier::new();
+        let in_flight = classifier.active();
+        classifier.load_from(dir.path()).unwrap();
+
+        assert_eq!(in_flight.metadata.version, UNTRAINED_VERSION);
+        assert_eq!(classifier.active_version(), "2026.10.1");
+    }
+
+    #[test]
+    fn test_rejects_mismatched_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut model = always_anomalous("old-layout");
+        model.metadata.feature_schema_hash = "0000000000000000".to_string();
+        model.save(dir.path()).unwrap();
+
+        let classifier = ThreatClassifier::new();
+        let err = classifier.load_from(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("feature schema"));
+        assert_eq!(classifier.active_version(), UNTRAINED_VERSION);
+        assert!(classifier.load_shadow(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut model = always_anomalous("bad-weights");
+        model.forest.trees[0].root = Some(Box::new(TreeNode {
+            split_feature: FeatureVector::schema_labels().len(),
+            split_value: 1.0,
+            left: None,
+            right: None,
+        }));
+        model.save(dir.path()).unwrap();
+
+        assert!(ThreatClassifier::new().load_from(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_shadow_mode_records_divergence() {
+        let dir = tempfile::tempdir().unwrap();
+        always_anomalous("candidate").save(dir.path()).unwrap();
+
+        let classifier = ThreatClassifier::new();
+        assert!(classifier.shadow_stats().is_none());
+        classifier.load_shadow(dir.path()).unwrap();
+
+        // Only the active model's verdict is returned
+        for _ in 0..4 {
+            assert_eq!(classifier.detect(&quiet_source()).threat_type, ThreatType::Normal);
+        }
+
+        let stats = classifier.shadow_stats().unwrap();
+        assert_eq!(stats.model_version, "candidate");
+        assert_eq!(stats.evaluations, 4);
+        assert_eq!(stats.verdict_divergences, 4);
+        assert_eq!(stats.divergence_rate(), 1.0);
+        assert!((stats.mean_score_delta() - 1.0).abs() < 1e-9);
+
+        assert_eq!(classifier.promote_shadow().unwrap().version, "candidate");
+        assert_eq!(classifier.active_version(), "candidate");
+        assert!(classifier.shadow_stats().is_none());
+        assert_eq!(classifier.rollback_model().unwrap().version, UNTRAINED_VERSION);
+    }
 }
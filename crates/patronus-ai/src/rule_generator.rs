@@ -1,4 +1,5 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use patronus_core::types::{FirewallRule, FirewallAction, ChainType};
 use patronus_firewall::rules::RuleManager;
@@ -7,6 +8,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
+use crate::allowlist::{Allowlist, AllowlistEntry, AllowlistSubject};
 use crate::models::{ThreatDetection, ThreatType};
 use crate::threat_intel::{ThreatIntelDB, ThreatCategory};
 
@@ -20,6 +22,95 @@ pub struct AutoRule {
     pub threat_type: ThreatType,
     pub confidence: f64,
     pub auto_expire: Option<DateTime<Utc>>,
+
+    /// Traffic the rule was generated for, used to match allowlist entries
+    #[serde(default)]
+    pub source_ip: String,
+    #[serde(default)]
+    pub destination_ip: Option<String>,
+    #[serde(default)]
+    pub destination_port: Option<u16>,
+
+    /// Detection that produced the rule (None for threat intel rules)
+    #[serde(default)]
+    pub detection_id: Option<String>,
+
+    /// Response taken for the threat
+    #[serde(default)]
+    pub action: ResponseAction,
+
+    /// Name of the policy clause that produced the rule
+    #[serde(default)]
+    pub policy_clause: String,
+
+    /// Lifetime in seconds, extended each time the threat re-occurs
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+
+    /// When the threat was last seen
+    #[serde(default = "Utc::now")]
+    pub last_seen: DateTime<Utc>,
+
+    /// Number of detections covered by this rule
+    #[serde(default)]
+    pub occurrences: u32,
+}
+
+/// Response to a detected threat
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ResponseAction {
+    /// Drop all traffic from the source
+    #[default]
+    Block,
+
+    /// Limit the source to a packet rate
+    RateLimit { packets_per_second: u32 },
+
+    /// Log an alert without changing the firewall
+    AlertOnly,
+
+    /// Move the source into a quarantine VLAN
+    Quarantine { vlan: u16 },
+}
+
+/// Policy clause mapping a threat type to a response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseClause {
+    pub name: String,
+    pub threat_type: ThreatType,
+    pub action: ResponseAction,
+
+    /// Minimum classifier confidence for the clause to apply
+    pub min_confidence: f64,
+
+    /// Only act if threat intelligence also lists the source; otherwise
+    /// the detection is downgraded to an alert
+    pub require_intel_corroboration: bool,
+
+    /// Rule lifetime in seconds (None = permanent)
+    pub ttl_secs: Option<u64>,
+}
+
+impl ResponseClause {
+    pub fn new(name: &str, threat_type: ThreatType, action: ResponseAction, min_confidence: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            threat_type,
+            action,
+            min_confidence,
+            require_intel_corroboration: false,
+            ttl_secs: Some(24 * 3600),
+        }
+    }
+}
+
+/// Enforces response actions the packet filter cannot express
+/// (rate limiting, VLAN quarantine)
+#[async_trait]
+pub trait ResponseEnforcer: Send + Sync {
+    async fn enforce(&self, rule: &AutoRule) -> Result<()>;
+
+    async fn release(&self, rule: &AutoRule) -> Result<()>;
 }
 
 /// Rule generation policy
@@ -35,10 +126,20 @@ pub struct RuleGenPolicy {
     pub auto_expire_secs: Option<u64>,
 
     /// Generate rules for these threat types
+    ///
+    /// Threat types without a response clause are blocked when listed here.
     pub enabled_threats: Vec<ThreatType>,
 
     /// Maximum number of auto-generated rules
     pub max_rules: usize,
+
+    /// Per-threat-type responses; the first clause for a type applies
+    #[serde(default)]
+    pub responses: Vec<ResponseClause>,
+
+    /// Log what would be done without creating or applying rules
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 impl Default for RuleGenPolicy {
@@ -53,7 +154,34 @@ impl Default for RuleGenPolicy {
                 ThreatType::DDoS,
             ],
             max_rules: 1000,
+            responses: vec![
+                ResponseClause::new("block-port-scan", ThreatType::PortScan, ResponseAction::Block, 0.8),
+                ResponseClause::new("block-syn-flood", ThreatType::SynFlood, ResponseAction::Block, 0.8),
+                ResponseClause::new("block-ddos", ThreatType::DDoS, ResponseAction::Block, 0.8),
+            ],
+            dry_run: false,
+        }
+    }
+}
+
+impl RuleGenPolicy {
+    /// Clause that applies to `threat_type`
+    ///
+    /// Falls back to a block clause built from `enabled_threats`,
+    /// `min_confidence` and `auto_expire_secs`.
+    pub fn clause_for(&self, threat_type: &ThreatType) -> Option<ResponseClause> {
+        if let Some(clause) = self.responses.iter().find(|c| c.threat_type == *threat_type) {
+            return Some(clause.clone());
         }
+
+        self.enabled_threats.contains(threat_type).then(|| ResponseClause {
+            name: "default".to_string(),
+            threat_type: threat_type.clone(),
+            action: ResponseAction::Block,
+            min_confidence: self.min_confidence,
+            require_intel_corroboration: false,
+            ttl_secs: self.auto_expire_secs,
+        })
     }
 }
 
@@ -62,8 +190,11 @@ pub struct RuleGenerator {
     policy: RuleGenPolicy,
     rule_manager: Arc<RuleManager>,
     threat_intel: Arc<ThreatIntelDB>,
+    allowlist: Arc<Allowlist>,
+    enforcer: Option<Arc<dyn ResponseEnforcer>>,
     generated_rules: Arc<RwLock<Vec<AutoRule>>>,
     pending_approval: Arc<RwLock<Vec<AutoRule>>>,
+    dry_run_rules: Arc<RwLock<Vec<AutoRule>>>,
 }
 
 impl RuleGenerator {
@@ -76,21 +207,47 @@ impl RuleGenerator {
             policy,
             rule_manager,
             threat_intel,
+            allowlist: Arc::new(Allowlist::new()),
+            enforcer: None,
             generated_rules: Arc::new(RwLock::new(Vec::new())),
             pending_approval: Arc::new(RwLock::new(Vec::new())),
+            dry_run_rules: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
+    /// Consult a shared allowlist before generating rules
+    pub fn with_allowlist(mut self, allowlist: Arc<Allowlist>) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Enforce rate-limit and quarantine responses through `enforcer`
+    pub fn with_enforcer(mut self, enforcer: Arc<dyn ResponseEnforcer>) -> Self {
+        self.enforcer = Some(enforcer);
+        self
+    }
+
     /// Process a threat detection and possibly generate a rule
+    ///
+    /// A threat that re-occurs while a rule for it exists extends that
+    /// rule's lifetime instead of creating a new one.
     pub async fn process_threat(&self, detection: &ThreatDetection) -> Result<Option<AutoRule>> {
-        // Check if this threat type is enabled
-        if !self.policy.enabled_threats.contains(&detection.threat_type) {
-            return Ok(None);
+        let auto_rule = match self.plan_rule(detection).await? {
+            Some(rule) => rule,
+            None => return Ok(None),
+        };
+
+        if self.policy.dry_run {
+            let recorded = self.record_dry_run(auto_rule).await;
+            if let Some(rule) = &recorded {
+                info!("DRY RUN: would apply {:?} to {} ({}, clause {})",
+                    rule.action, detection.source_ip, rule.reason, rule.policy_clause);
+            }
+            return Ok(recorded);
         }
 
-        // Check confidence threshold
-        if detection.confidence < self.policy.min_confidence {
-            return Ok(None);
+        if let Some(existing) = self.refresh_existing(&auto_rule).await {
+            return Ok(Some(existing));
         }
 
         // Check if we've hit the max rules limit
@@ -100,22 +257,6 @@ impl RuleGenerator {
             return Ok(None);
         }
 
-        // Generate rule based on threat type
-        let rule = self.generate_rule_for_threat(detection).await?;
-
-        let auto_rule = AutoRule {
-            id: uuid::Uuid::new_v4().to_string(),
-            created_at: Utc::now(),
-            rule,
-            reason: format!("{:?} detected with {:.0}% confidence",
-                detection.threat_type, detection.confidence * 100.0),
-            threat_type: detection.threat_type.clone(),
-            confidence: detection.confidence,
-            auto_expire: self.policy.auto_expire_secs.map(|secs| {
-                Utc::now() + chrono::Duration::seconds(secs as i64)
-            }),
-        };
-
         if self.policy.auto_approve {
             // Auto-approve and apply
             self.apply_rule(&auto_rule).await?;
@@ -132,6 +273,106 @@ impl RuleGenerator {
         Ok(Some(auto_rule))
     }
 
+    /// Rules that would be created for `detections`, without applying or
+    /// queueing anything
+    pub async fn simulate(&self, detections: &[ThreatDetection]) -> Result<Vec<AutoRule>> {
+        let mut rules: Vec<AutoRule> = Vec::new();
+
+        for detection in detections {
+            if let Some(rule) = self.plan_rule(detection).await? {
+                // Repeat detections extend one rule rather than adding more
+                if !rules.iter().any(|r| r.source_ip == rule.source_ip && r.threat_type == rule.threat_type) {
+                    rules.push(rule);
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Decide the response to a detection
+    async fn plan_rule(&self, detection: &ThreatDetection) -> Result<Option<AutoRule>> {
+        // Never generate rules for allowlisted traffic
+        if let Some(entry) = self.allowlist.find_match(&AllowlistSubject::from_detection(detection)).await {
+            info!("Not generating rule for {}: allowlisted ({})", detection.source_ip, entry.reason);
+            return Ok(None);
+        }
+
+        let clause = match self.policy.clause_for(&detection.threat_type) {
+            Some(clause) => clause,
+            None => return Ok(None),
+        };
+
+        // Check confidence threshold
+        if detection.confidence < clause.min_confidence {
+            return Ok(None);
+        }
+
+        let mut action = clause.action.clone();
+        let mut reason = format!("{:?} detected with {:.0}% confidence",
+            detection.threat_type, detection.confidence * 100.0);
+
+        if clause.require_intel_corroboration && action != ResponseAction::AlertOnly {
+            if self.threat_intel.is_threat(&detection.source_ip).await {
+                reason.push_str(", corroborated by threat intel");
+            } else {
+                action = ResponseAction::AlertOnly;
+                reason.push_str(", not corroborated by threat intel");
+            }
+        }
+
+        // Generate rule based on threat type
+        let rule = self.generate_rule_for_threat(detection).await?;
+        let now = Utc::now();
+
+        Ok(Some(AutoRule {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: now,
+            rule,
+            reason,
+            threat_type: detection.threat_type.clone(),
+            confidence: detection.confidence,
+            auto_expire: clause.ttl_secs.map(|secs| now + chrono::Duration::seconds(secs as i64)),
+            source_ip: detection.source_ip.clone(),
+            destination_ip: detection.destination_ip.clone(),
+            destination_port: detection.destination_port,
+            detection_id: Some(detection.id.clone()),
+            action,
+            policy_clause: clause.name,
+            ttl_secs: clause.ttl_secs,
+            last_seen: now,
+            occurrences: 1,
+        }))
+    }
+
+    /// Extend the lifetime of an existing rule for the same threat
+    async fn refresh_existing(&self, candidate: &AutoRule) -> Option<AutoRule> {
+        for rules in [&self.generated_rules, &self.pending_approval] {
+            if let Some(existing) = refresh_in(rules, candidate).await {
+                return Some(existing);
+            }
+        }
+
+        None
+    }
+
+    /// Record what dry-run mode would have done, under the same limits as
+    /// live generation: a repeat extends its earlier record, and nothing new
+    /// is recorded past `max_rules`
+    async fn record_dry_run(&self, auto_rule: AutoRule) -> Option<AutoRule> {
+        if let Some(existing) = refresh_in(&self.dry_run_rules, &auto_rule).await {
+            return Some(existing);
+        }
+
+        let mut rules = self.dry_run_rules.write().await;
+        if rules.len() >= self.policy.max_rules {
+            warn!("DRY RUN: maximum auto-generated rules ({}) reached", self.policy.max_rules);
+            return None;
+        }
+        rules.push(auto_rule.clone());
+        Some(auto_rule)
+    }
+
     async fn generate_rule_for_threat(&self, detection: &ThreatDetection) -> Result<FirewallRule> {
         let name = format!("AUTO-{}-{}",
             detection.threat_type.to_string().to_uppercase(),
@@ -226,8 +467,52 @@ impl RuleGenerator {
     }
 
     async fn apply_rule(&self, auto_rule: &AutoRule) -> Result<()> {
-        self.rule_manager.add_filter_rule(auto_rule.rule.clone()).await
-            .map_err(|e| anyhow::anyhow!("Failed to add rule: {}", e))
+        match &auto_rule.action {
+            ResponseAction::Block => {
+                self.rule_manager.add_filter_rule(auto_rule.rule.clone()).await
+                    .map_err(|e| anyhow::anyhow!("Failed to add rule: {}", e))
+            }
+            ResponseAction::AlertOnly => {
+                warn!("Threat alert for {}: {}", auto_rule.source_ip, auto_rule.reason);
+                Ok(())
+            }
+            action => match &self.enforcer {
+                Some(enforcer) => enforcer.enforce(auto_rule).await,
+                None => {
+                    warn!("No enforcer for {:?}; alerting only for {}: {}",
+                        action, auto_rule.source_ip, auto_rule.reason);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Undo the effect of an applied rule
+    async fn withdraw_rule(&self, auto_rule: &AutoRule) -> Result<()> {
+        match &auto_rule.action {
+            ResponseAction::Block => {
+                // The firewall assigns IDs on insert, so find the rule by name
+                let rule_id = match auto_rule.rule.id {
+                    Some(id) => Some(id),
+                    None => self.rule_manager.list_filter_rules().await
+                        .map_err(|e| anyhow::anyhow!("Failed to list rules: {}", e))?
+                        .into_iter()
+                        .find(|r| r.name == auto_rule.rule.name)
+                        .and_then(|r| r.id),
+                };
+
+                if let Some(rule_id) = rule_id {
+                    self.rule_manager.remove_filter_rule(rule_id).await
+                        .map_err(|e| anyhow::anyhow!("Failed to remove rule: {}", e))?;
+                }
+                Ok(())
+            }
+            ResponseAction::AlertOnly => Ok(()),
+            _ => match &self.enforcer {
+                Some(enforcer) => enforcer.release(auto_rule).await,
+                None => Ok(()),
+            },
+        }
     }
 
     /// Approve a pending rule
@@ -268,6 +553,11 @@ impl RuleGenerator {
         self.generated_rules.read().await.clone()
     }
 
+    /// Get rules that dry-run mode would have created
+    pub async fn get_dry_run_rules(&self) -> Vec<AutoRule> {
+        self.dry_run_rules.read().await.clone()
+    }
+
     /// Clean up expired rules
     pub async fn cleanup_expired_rules(&self) -> Result<()> {
         let now = Utc::now();
@@ -285,20 +575,59 @@ impl RuleGenerator {
             true
         });
 
+        drop(generated);
+
         // Remove expired rules from firewall
         for rule in expired_rules {
-            if let Some(rule_id) = rule.rule.id {
-                if let Err(e) = self.rule_manager.remove_filter_rule(rule_id).await {
-                    warn!("Failed to delete expired rule {}: {}", rule.rule.name, e);
-                } else {
-                    info!("Removed expired rule: {}", rule.rule.name);
-                }
+            if let Err(e) = self.withdraw_rule(&rule).await {
+                warn!("Failed to delete expired rule {}: {}", rule.rule.name, e);
+            } else {
+                info!("Removed expired rule: {}", rule.rule.name);
             }
         }
 
         Ok(())
     }
 
+    /// Revoke pending and applied rules covered by `entry`
+    ///
+    /// Called whenever the allowlist grows so rules generated before the
+    /// entry existed stop blocking allowlisted traffic.
+    pub async fn revoke_allowlisted(&self, entry: &AllowlistEntry) -> Result<Vec<AutoRule>> {
+        let covered = |rule: &AutoRule| entry.matches(&AllowlistSubject::from_auto_rule(rule));
+        let mut revoked = Vec::new();
+
+        self.pending_approval.write().await.retain(|rule| {
+            if covered(rule) {
+                revoked.push(rule.clone());
+                return false;
+            }
+            true
+        });
+
+        let mut applied = Vec::new();
+        self.generated_rules.write().await.retain(|rule| {
+            if covered(rule) {
+                applied.push(rule.clone());
+                return false;
+            }
+            true
+        });
+
+        for rule in applied {
+            if let Err(e) = self.withdraw_rule(&rule).await {
+                warn!("Failed to delete allowlisted rule {}: {}", rule.rule.name, e);
+            }
+            revoked.push(rule);
+        }
+
+        for rule in &revoked {
+            info!("Revoked rule {} (allowlisted: {})", rule.rule.name, entry.reason);
+        }
+
+        Ok(revoked)
+    }
+
     /// Start automatic cleanup task
     pub async fn start_cleanup_task(self: Arc<Self>) {
         info!("Starting rule cleanup task");
@@ -338,6 +667,16 @@ impl RuleGenerator {
                 continue;
             }
 
+            let subject = AllowlistSubject {
+                source: ip.parse().ok(),
+                destination: None,
+                port: None,
+                threat_type: ThreatType::Unknown,
+            };
+            if self.allowlist.find_match(&subject).await.is_some() {
+                continue;
+            }
+
             let rule = FirewallRule {
                 id: None,
                 name: format!("THREAT-INTEL-{}-{}",
@@ -371,9 +710,25 @@ impl RuleGenerator {
                 auto_expire: self.policy.auto_expire_secs.map(|secs| {
                     Utc::now() + chrono::Duration::seconds(secs as i64)
                 }),
+                source_ip: ip.clone(),
+                destination_ip: None,
+                destination_port: None,
+                detection_id: None,
+                action: ResponseAction::Block,
+                policy_clause: "threat-intel".to_string(),
+                ttl_secs: self.policy.auto_expire_secs,
+                last_seen: Utc::now(),
+                occurrences: 1,
             };
 
-            if self.policy.auto_approve {
+            if self.policy.dry_run {
+                let Some(auto_rule) = self.record_dry_run(auto_rule).await else {
+                    continue;
+                };
+                info!("DRY RUN: would block {} ({})", ip, auto_rule.reason);
+                new_rules.push(auto_rule);
+                continue;
+            } else if self.policy.auto_approve {
                 self.apply_rule(&auto_rule).await?;
                 self.generated_rules.write().await.push(auto_rule.clone());
             } else {
@@ -388,9 +743,39 @@ impl RuleGenerator {
     }
 }
 
+/// Extend the lifetime of the rule in `rules` for the same threat as `candidate`
+async fn refresh_in(rules: &RwLock<Vec<AutoRule>>, candidate: &AutoRule) -> Option<AutoRule> {
+    let mut rules = rules.write().await;
+    let existing = rules.iter_mut().find(|r| {
+        r.source_ip == candidate.source_ip && r.threat_type == candidate.threat_type
+    })?;
+
+    existing.last_seen = candidate.last_seen;
+    existing.occurrences += 1;
+    existing.confidence = existing.confidence.max(candidate.confidence);
+    if let Some(secs) = existing.ttl_secs {
+        existing.auto_expire = Some(candidate.last_seen + chrono::Duration::seconds(secs as i64));
+    }
+    Some(existing.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::allowlist::AllowlistMatch;
+
+    fn detection(source_ip: &str) -> ThreatDetection {
+        ThreatDetection {
+            id: uuid::Uuid::new_v4().to_string(),
+            source_ip: source_ip.to_string(),
+            destination_ip: None,
+            destination_port: None,
+            threat_type: ThreatType::PortScan,
+            confidence: 0.9,
+            anomaly_score: 0.85,
+            features: std::collections::HashMap::new(),
+        }
+    }
 
     #[tokio::test]
     async fn test_rule_generation() {
@@ -405,13 +790,7 @@ mod tests {
 
         let generator = RuleGenerator::new(policy, rule_manager, threat_intel);
 
-        let detection = ThreatDetection {
-            source_ip: "1.2.3.4".to_string(),
-            threat_type: ThreatType::PortScan,
-            confidence: 0.9,
-            anomaly_score: 0.85,
-            features: std::collections::HashMap::new(),
-        };
+        let detection = detection("1.2.3.4");
 
         let result = generator.process_threat(&detection).await;
         assert!(result.is_ok());
@@ -421,4 +800,193 @@ mod tests {
         // Rule is pending approval, so returns None
         assert!(auto_rule.is_none() || auto_rule.is_some());
     }
+
+    #[tokio::test]
+    async fn test_allowlisted_detection_generates_no_rule() {
+        let allowlist = Arc::new(Allowlist::new());
+        allowlist.add(AllowlistMatch::Cidr("10.9.0.0/24".parse().unwrap()), "Vulnerability scanner", None)
+            .await
+            .unwrap();
+
+        let generator = RuleGenerator::new(
+            RuleGenPolicy::default(),
+            Arc::new(RuleManager::new()),
+            Arc::new(ThreatIntelDB::default()),
+        ).with_allowlist(allowlist);
+
+        assert!(generator.process_threat(&detection("10.9.0.5")).await.unwrap().is_none());
+        assert!(generator.process_threat(&detection("1.2.3.4")).await.unwrap().is_some());
+        assert_eq!(generator.get_pending_rules().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_revokes_existing_rules() {
+        let allowlist = Arc::new(Allowlist::new());
+        let generator = RuleGenerator::new(
+            RuleGenPolicy::default(),
+            Arc::new(RuleManager::new()),
+            Arc::new(ThreatIntelDB::default()),
+        ).with_allowlist(Arc::clone(&allowlist));
+
+        generator.process_threat(&detection("10.9.0.5")).await.unwrap();
+        generator.process_threat(&detection("1.2.3.4")).await.unwrap();
+
+        let entry = allowlist
+            .add(AllowlistMatch::Cidr("10.9.0.0/24".parse().unwrap()), "Vulnerability scanner", None)
+            .await
+            .unwrap();
+        let revoked = generator.revoke_allowlisted(&entry).await.unwrap();
+
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].source_ip, "10.9.0.5");
+
+        let pending = generator.get_pending_rules().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].source_ip, "1.2.3.4");
+    }
+
+    fn generator(policy: RuleGenPolicy, threat_intel: Arc<ThreatIntelDB>) -> RuleGenerator {
+        RuleGenerator::new(policy, Arc::new(RuleManager::new()), threat_intel)
+    }
+
+    fn typed(source_ip: &str, threat_type: ThreatType, confidence: f64) -> ThreatDetection {
+        ThreatDetection {
+            threat_type,
+            confidence,
+            ..detection(source_ip)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_type_actions_and_thresholds() {
+        let policy = RuleGenPolicy {
+            responses: vec![
+                ResponseClause::new("block-scans", ThreatType::PortScan, ResponseAction::Block, 0.8),
+                ResponseClause::new(
+                    "limit-floods",
+                    ThreatType::SynFlood,
+                    ResponseAction::RateLimit { packets_per_second: 100 },
+                    0.7,
+                ),
+                ResponseClause::new("watch-c2", ThreatType::C2Communication, ResponseAction::AlertOnly, 0.5),
+                ResponseClause::new(
+                    "quarantine-exfil",
+                    ThreatType::DataExfiltration,
+                    ResponseAction::Quarantine { vlan: 999 },
+                    0.6,
+                ),
+            ],
+            ..Default::default()
+        };
+        let generator = generator(policy, Arc::new(ThreatIntelDB::default()));
+
+        let rules = generator.simulate(&[
+            typed("10.0.0.1", ThreatType::PortScan, 0.75),
+            typed("10.0.0.2", ThreatType::PortScan, 0.85),
+            typed("10.0.0.3", ThreatType::SynFlood, 0.75),
+            typed("10.0.0.4", ThreatType::C2Communication, 0.55),
+            typed("10.0.0.5", ThreatType::DataExfiltration, 0.65),
+            typed("10.0.0.6", ThreatType::DDoS, 0.99),
+        ]).await.unwrap();
+
+        let actions: Vec<(&str, &ResponseAction, &str)> = rules.iter()
+            .map(|r| (r.source_ip.as_str(), &r.action, r.policy_clause.as_str()))
+            .collect();
+        assert_eq!(actions, vec![
+            ("10.0.0.2", &ResponseAction::Block, "block-scans"),
+            ("10.0.0.3", &ResponseAction::RateLimit { packets_per_second: 100 }, "limit-floods"),
+            ("10.0.0.4", &ResponseAction::AlertOnly, "watch-c2"),
+            ("10.0.0.5", &ResponseAction::Quarantine { vlan: 999 }, "quarantine-exfil"),
+            // No clause for DDoS: falls back to enabled_threats
+            ("10.0.0.6", &ResponseAction::Block, "default"),
+        ]);
+
+        // Simulation has no side effects
+        assert!(generator.get_pending_rules().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corroboration_required() {
+        let threat_intel = Arc::new(ThreatIntelDB::default());
+        threat_intel.add_entry(crate::threat_intel::ThreatIntelEntry {
+            ip: "203.0.113.9".to_string(),
+            categories: vec![ThreatCategory::Scanner],
+            confidence: 0.9,
+            last_seen: Utc::now(),
+            source: crate::threat_intel::ThreatSource::EmergingThreats,
+            description: None,
+            country: None,
+            asn: None,
+        }).await;
+
+        let mut clause = ResponseClause::new("corroborated-block", ThreatType::PortScan, ResponseAction::Block, 0.8);
+        clause.require_intel_corroboration = true;
+        let policy = RuleGenPolicy {
+            responses: vec![clause],
+            ..Default::default()
+        };
+        let generator = generator(policy, threat_intel);
+
+        let rules = generator.simulate(&[
+            detection("203.0.113.9"),
+            detection("198.51.100.7"),
+        ]).await.unwrap();
+
+        assert_eq!(rules[0].action, ResponseAction::Block);
+        assert_eq!(rules[1].action, ResponseAction::AlertOnly);
+    }
+
+    #[tokio::test]
+    async fn test_reoccurring_threat_extends_ttl() {
+        let generator = generator(RuleGenPolicy::default(), Arc::new(ThreatIntelDB::default()));
+
+        let first = generator.process_threat(&detection("10.0.0.1")).await.unwrap().unwrap();
+        assert_eq!(first.ttl_secs, Some(24 * 3600));
+        assert_eq!(first.policy_clause, "block-port-scan");
+
+        let second = generator.process_threat(&detection("10.0.0.1")).await.unwrap().unwrap();
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.occurrences, 2);
+        assert!(second.auto_expire.unwrap() >= first.auto_expire.unwrap());
+        assert_eq!(generator.get_pending_rules().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_records_without_applying() {
+        let policy = RuleGenPolicy {
+            auto_approve: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        let generator = generator(policy, Arc::new(ThreatIntelDB::default()));
+
+        let rule = generator.process_threat(&detection("10.0.0.1")).await.unwrap();
+        assert!(rule.is_some());
+
+        assert_eq!(generator.get_dry_run_rules().await.len(), 1);
+        assert!(generator.get_generated_rules().await.is_empty());
+        assert!(generator.get_pending_rules().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_applies_live_limits() {
+        let policy = RuleGenPolicy {
+            dry_run: true,
+            max_rules: 2,
+            ..Default::default()
+        };
+        let generator = generator(policy, Arc::new(ThreatIntelDB::default()));
+
+        let first = generator.process_threat(&detection("10.0.0.1")).await.unwrap().unwrap();
+        let repeat = generator.process_threat(&detection("10.0.0.1")).await.unwrap().unwrap();
+        assert_eq!(repeat.id, first.id);
+        assert_eq!(repeat.occurrences, 2);
+
+        assert!(generator.process_threat(&detection("10.0.0.2")).await.unwrap().is_some());
+        assert!(generator.process_threat(&detection("10.0.0.3")).await.unwrap().is_none());
+
+        let recorded = generator.get_dry_run_rules().await;
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].occurrences, 2);
+    }
 }
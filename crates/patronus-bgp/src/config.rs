@@ -1,5 +1,7 @@
 //! BGP configuration types
 
+use crate::messages::{AFI_IPV4, AFI_IPV6, SAFI_UNICAST};
+use crate::route::RouteAttributes;
 use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
@@ -27,6 +29,10 @@ pub struct BgpConfig {
     /// Timers
     #[serde(default)]
     pub timers: TimersConfig,
+
+    /// Route flap dampening (disabled when unset)
+    #[serde(default)]
+    pub dampening: Option<DampeningConfig>,
 }
 
 /// BGP neighbor configuration
@@ -61,6 +67,154 @@ pub struct NeighborConfig {
     /// Next hop self
     #[serde(default)]
     pub next_hop_self: bool,
+
+    /// Address families activated for this neighbor
+    #[serde(default = "default_address_families")]
+    pub address_families: Vec<AddressFamily>,
+
+    /// Maximum number of prefixes accepted from this neighbor
+    #[serde(default)]
+    pub prefix_limit: Option<PrefixLimitConfig>,
+}
+
+/// Maximum-prefix limit for a neighbor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixLimitConfig {
+    /// Session is torn down when more prefixes than this are received
+    pub max_prefixes: u32,
+
+    /// Percentage of `max_prefixes` at which a warning is raised
+    #[serde(default = "default_warning_threshold")]
+    pub warning_threshold_pct: u8,
+
+    /// Re-enable the session after this idle period (seconds); stays down if unset
+    #[serde(default)]
+    pub restart_interval_secs: Option<u64>,
+}
+
+impl PrefixLimitConfig {
+    /// Prefix count at which the warning is raised
+    pub fn warning_threshold(&self) -> u32 {
+        (self.max_prefixes as u64 * self.warning_threshold_pct as u64 / 100) as u32
+    }
+
+    /// Idle period before an automatic restart
+    pub fn restart_interval(&self) -> Option<Duration> {
+        self.restart_interval_secs.map(Duration::from_secs)
+    }
+}
+
+fn default_warning_threshold() -> u8 {
+    75
+}
+
+/// Route flap dampening parameters (RFC 2439)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DampeningConfig {
+    /// Time for the penalty to decay by half (seconds)
+    #[serde(default = "default_half_life")]
+    pub half_life_secs: u64,
+
+    /// Penalty above which a route is suppressed
+    #[serde(default = "default_suppress_threshold")]
+    pub suppress_threshold: u32,
+
+    /// Penalty below which a suppressed route is reused
+    #[serde(default = "default_reuse_threshold")]
+    pub reuse_threshold: u32,
+
+    /// Maximum time a route may stay suppressed (seconds)
+    #[serde(default = "default_max_suppress")]
+    pub max_suppress_secs: u64,
+
+    /// Penalty added for each flap
+    #[serde(default = "default_flap_penalty")]
+    pub flap_penalty: u32,
+}
+
+impl Default for DampeningConfig {
+    fn default() -> Self {
+        Self {
+            half_life_secs: default_half_life(),
+            suppress_threshold: default_suppress_threshold(),
+            reuse_threshold: default_reuse_threshold(),
+            max_suppress_secs: default_max_suppress(),
+            flap_penalty: default_flap_penalty(),
+        }
+    }
+}
+
+impl DampeningConfig {
+    /// Half-life as a duration
+    pub fn half_life(&self) -> Duration {
+        Duration::from_secs(self.half_life_secs)
+    }
+}
+
+fn default_half_life() -> u64 {
+    900
+}
+
+fn default_suppress_threshold() -> u32 {
+    2000
+}
+
+fn default_reuse_threshold() -> u32 {
+    750
+}
+
+fn default_max_suppress() -> u64 {
+    3600
+}
+
+fn default_flap_penalty() -> u32 {
+    1000
+}
+
+impl NeighborConfig {
+    /// Whether an address family is activated for this neighbor
+    pub fn is_family_active(&self, family: AddressFamily) -> bool {
+        self.address_families.contains(&family)
+    }
+}
+
+/// BGP address family (AFI/SAFI)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// IPv4 unicast
+    Ipv4Unicast,
+
+    /// IPv6 unicast
+    Ipv6Unicast,
+}
+
+impl AddressFamily {
+    /// Address Family Identifier
+    pub fn afi(&self) -> u16 {
+        match self {
+            AddressFamily::Ipv4Unicast => AFI_IPV4,
+            AddressFamily::Ipv6Unicast => AFI_IPV6,
+        }
+    }
+
+    /// Subsequent Address Family Identifier
+    pub fn safi(&self) -> u8 {
+        SAFI_UNICAST
+    }
+
+    /// Look up a supported family from its AFI/SAFI
+    pub fn from_afi_safi(afi: u16, safi: u8) -> Option<Self> {
+        match (afi, safi) {
+            (AFI_IPV4, SAFI_UNICAST) => Some(AddressFamily::Ipv4Unicast),
+            (AFI_IPV6, SAFI_UNICAST) => Some(AddressFamily::Ipv6Unicast),
+            _ => None,
+        }
+    }
+}
+
+fn default_address_families() -> Vec<AddressFamily> {
+    vec![AddressFamily::Ipv4Unicast]
 }
 
 /// Network configuration for advertisement
@@ -84,6 +238,31 @@ pub struct RouteMapConfig {
     pub rules: Vec<RouteMapRule>,
 }
 
+impl RouteMapConfig {
+    /// Evaluate the route map against a route of either family
+    ///
+    /// Rules are tried in sequence order; the first rule whose conditions all
+    /// match decides. Set actions are applied on permit. Routes matching no
+    /// rule are denied.
+    pub fn apply<R: RouteAttributes>(&self, route: &mut R) -> RouteMapAction {
+        let mut rules: Vec<&RouteMapRule> = self.rules.iter().collect();
+        rules.sort_by_key(|r| r.sequence);
+
+        for rule in rules {
+            if rule.match_conditions.iter().all(|c| c.matches(route)) {
+                if rule.action == RouteMapAction::Permit {
+                    for action in &rule.set_actions {
+                        action.apply(route);
+                    }
+                }
+                return rule.action;
+            }
+        }
+
+        RouteMapAction::Deny
+    }
+}
+
 /// Route map rule
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RouteMapRule {
@@ -130,6 +309,46 @@ pub enum MatchCondition {
     Prefix { prefix: IpNetwork },
 }
 
+impl MatchCondition {
+    /// Whether a route satisfies this condition
+    pub fn matches<R: RouteAttributes>(&self, route: &R) -> bool {
+        match self {
+            MatchCondition::Prefix { prefix } => {
+                let network = route.network();
+                network.is_ipv4() == prefix.is_ipv4()
+                    && network.prefix() >= prefix.prefix()
+                    && prefix.contains(network.ip())
+            }
+            MatchCondition::Community { community } => {
+                route.communities().iter().any(|c| c == community)
+            }
+            MatchCondition::AsPath { pattern } => as_path_matches(route.as_path(), pattern),
+            MatchCondition::PrefixList { name } => {
+                tracing::warn!("Prefix list '{}' is not supported in route maps", name);
+                false
+            }
+        }
+    }
+}
+
+/// Match an AS path against a pattern of space-separated ASNs with optional
+/// `^` / `$` anchors, e.g. `^65001`, `65002 65003$` or `_65010_`
+fn as_path_matches(as_path: &[u16], pattern: &str) -> bool {
+    let pattern = pattern.trim().replace('_', " ");
+    let anchored_start = pattern.starts_with('^');
+    let anchored_end = pattern.ends_with('$');
+    let body = pattern.trim_start_matches('^').trim_end_matches('$').trim();
+
+    let path = as_path.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" ");
+
+    match (anchored_start, anchored_end) {
+        (true, true) => path == body,
+        (true, false) => path == body || path.starts_with(&format!("{} ", body)),
+        (false, true) => path == body || path.ends_with(&format!(" {}", body)),
+        (false, false) => format!(" {} ", path).contains(&format!(" {} ", body)),
+    }
+}
+
 /// Set action for route maps
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -150,6 +369,26 @@ pub enum SetAction {
     NextHop { ip: IpAddr },
 }
 
+impl SetAction {
+    /// Apply this action to a route
+    pub fn apply<R: RouteAttributes>(&self, route: &mut R) {
+        match self {
+            SetAction::LocalPreference { value } => route.set_local_pref(*value),
+            SetAction::Med { value } => route.set_med(*value),
+            SetAction::Community { community } => route.add_community(community.clone()),
+            SetAction::AsPathPrepend { asn, count } => {
+                let asn = u16::try_from(*asn).unwrap_or(23456); // AS_TRANS
+                route.prepend_as(asn, *count);
+            }
+            SetAction::NextHop { ip } => {
+                if !route.set_next_hop(*ip) {
+                    tracing::warn!("Ignoring next hop {} of the wrong address family", ip);
+                }
+            }
+        }
+    }
+}
+
 /// BGP timers configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimersConfig {
@@ -224,4 +463,79 @@ mod tests {
         assert_eq!(timers.holdtime_duration(), Duration::from_secs(90));
         assert_eq!(timers.connect_retry_duration(), Duration::from_secs(120));
     }
+
+    fn route_map(rules: Vec<RouteMapRule>) -> RouteMapConfig {
+        RouteMapConfig {
+            name: "test".to_string(),
+            rules,
+        }
+    }
+
+    #[test]
+    fn test_route_map_applies_to_both_families() {
+        use crate::route::{BgpRoute, BgpRoute6};
+
+        let map = route_map(vec![
+            RouteMapRule {
+                sequence: 10,
+                action: RouteMapAction::Deny,
+                match_conditions: vec![MatchCondition::Prefix { prefix: "10.99.0.0/16".parse().unwrap() }],
+                set_actions: vec![],
+            },
+            RouteMapRule {
+                sequence: 20,
+                action: RouteMapAction::Permit,
+                match_conditions: vec![MatchCondition::AsPath { pattern: "^65001".to_string() }],
+                set_actions: vec![
+                    SetAction::LocalPreference { value: 200 },
+                    SetAction::AsPathPrepend { asn: 65000, count: 2 },
+                ],
+            },
+        ]);
+
+        let mut v4 = BgpRoute::new("10.1.0.0/24".parse().unwrap(), "192.168.1.1".parse().unwrap(), vec![65001]);
+        assert_eq!(map.apply(&mut v4), RouteMapAction::Permit);
+        assert_eq!(v4.local_pref, 200);
+        assert_eq!(v4.as_path, vec![65000, 65000, 65001]);
+
+        let mut v6 = BgpRoute6::new("2001:db8:1::/48".parse().unwrap(), "2001:db8::1".parse().unwrap(), vec![65001]);
+        assert_eq!(map.apply(&mut v6), RouteMapAction::Permit);
+        assert_eq!(v6.local_pref, 200);
+
+        let mut denied = BgpRoute::new("10.99.1.0/24".parse().unwrap(), "192.168.1.1".parse().unwrap(), vec![65001]);
+        assert_eq!(map.apply(&mut denied), RouteMapAction::Deny);
+        assert_eq!(denied.local_pref, 100);
+
+        // Implicit deny when no rule matches
+        let mut other = BgpRoute6::new("2001:db8:2::/48".parse().unwrap(), "2001:db8::2".parse().unwrap(), vec![65002]);
+        assert_eq!(map.apply(&mut other), RouteMapAction::Deny);
+    }
+
+    #[test]
+    fn test_prefix_match_is_family_aware() {
+        use crate::route::BgpRoute6;
+
+        let cond = MatchCondition::Prefix { prefix: "2001:db8::/32".parse().unwrap() };
+        let route = BgpRoute6::new("2001:db8:1::/48".parse().unwrap(), "2001:db8::1".parse().unwrap(), vec![]);
+        assert!(cond.matches(&route));
+
+        let v4_cond = MatchCondition::Prefix { prefix: "0.0.0.0/0".parse().unwrap() };
+        assert!(!v4_cond.matches(&route));
+    }
+
+    #[test]
+    fn test_as_path_patterns() {
+        assert!(as_path_matches(&[65001, 65002], "^65001"));
+        assert!(!as_path_matches(&[65001, 65002], "^65002"));
+        assert!(as_path_matches(&[65001, 65002], "65002$"));
+        assert!(as_path_matches(&[65001, 65002, 65003], "_65002_"));
+        assert!(!as_path_matches(&[65001], "6500"));
+    }
+
+    #[test]
+    fn test_neighbor_address_families_default() {
+        let neighbor: NeighborConfig = serde_json::from_str(r#"{"ip": "10.0.0.1", "asn": 65001}"#).unwrap();
+        assert!(neighbor.is_family_active(AddressFamily::Ipv4Unicast));
+        assert!(!neighbor.is_family_active(AddressFamily::Ipv6Unicast));
+    }
 }
@@ -0,0 +1,247 @@
+//! Route flap dampening (RFC 2439)
+//!
+//! Each flap adds a fixed penalty to the prefix; the penalty decays
+//! exponentially with the configured half-life. A prefix whose penalty
+//! crosses the suppress threshold is not advertised until it decays below
+//! the reuse threshold.
+
+use crate::config::DampeningConfig;
+use ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Dampening state for a single prefix
+#[derive(Debug, Clone)]
+pub struct DampeningState {
+    /// Penalty as of `updated`
+    penalty: f64,
+
+    /// When the penalty was last brought up to date
+    updated: Instant,
+
+    /// When the prefix was suppressed, if it currently is
+    suppressed_since: Option<Instant>,
+
+    /// Total flaps recorded
+    flaps: u32,
+}
+
+impl DampeningState {
+    /// Number of flaps recorded for the prefix
+    pub fn flaps(&self) -> u32 {
+        self.flaps
+    }
+
+    /// Whether the prefix is currently suppressed
+    pub fn is_suppressed(&self) -> bool {
+        self.suppressed_since.is_some()
+    }
+}
+
+/// Route flap dampening table
+#[derive(Debug, Clone)]
+pub struct RouteDampening {
+    config: DampeningConfig,
+    entries: HashMap<IpNetwork, DampeningState>,
+}
+
+impl RouteDampening {
+    /// Create a new dampening table
+    pub fn new(config: DampeningConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Dampening parameters
+    pub fn config(&self) -> &DampeningConfig {
+        &self.config
+    }
+
+    /// Highest penalty a prefix can accumulate
+    ///
+    /// Capping the penalty bounds suppression to `max_suppress_secs`.
+    fn penalty_ceiling(&self) -> f64 {
+        let half_lives = self.config.max_suppress_secs as f64 / self.config.half_life_secs.max(1) as f64;
+        self.config.reuse_threshold as f64 * 2f64.powf(half_lives)
+    }
+
+    fn decayed(&self, state: &DampeningState, now: Instant) -> f64 {
+        decay(state, now, &self.config)
+    }
+
+    /// Record a flap (withdrawal) of `prefix`
+    ///
+    /// Returns true if this flap caused the prefix to become suppressed.
+    pub fn record_flap(&mut self, prefix: IpNetwork, now: Instant) -> bool {
+        let ceiling = self.penalty_ceiling();
+        let penalty = self.penalty(&prefix, now) + self.config.flap_penalty as f64;
+        let suppress = self.config.suppress_threshold as f64;
+
+        let state = self.entries.entry(prefix).or_insert(DampeningState {
+            penalty: 0.0,
+            updated: now,
+            suppressed_since: None,
+            flaps: 0,
+        });
+
+        state.penalty = penalty.min(ceiling);
+        state.updated = now;
+        state.flaps += 1;
+
+        if state.suppressed_since.is_none() && state.penalty >= suppress {
+            info!("Suppressing flapping prefix {} (penalty {:.0})", prefix, state.penalty);
+            state.suppressed_since = Some(now);
+            return true;
+        }
+
+        false
+    }
+
+    /// Current penalty for `prefix`
+    pub fn penalty(&self, prefix: &IpNetwork, now: Instant) -> f64 {
+        self.entries
+            .get(prefix)
+            .map(|s| self.decayed(s, now))
+            .unwrap_or(0.0)
+    }
+
+    /// Whether `prefix` is currently suppressed
+    pub fn is_suppressed(&self, prefix: &IpNetwork) -> bool {
+        self.entries.get(prefix).is_some_and(|s| s.is_suppressed())
+    }
+
+    /// Dampening state for `prefix`
+    pub fn state(&self, prefix: &IpNetwork) -> Option<&DampeningState> {
+        self.entries.get(prefix)
+    }
+
+    /// Number of suppressed prefixes
+    pub fn suppressed_count(&self) -> usize {
+        self.entries.values().filter(|s| s.is_suppressed()).count()
+    }
+
+    /// Release suppressed prefixes whose penalty has decayed below the reuse
+    /// threshold (or that hit the maximum suppress time)
+    ///
+    /// Returns the prefixes that became usable again. Entries whose penalty
+    /// has decayed to insignificance are forgotten.
+    pub fn reuse(&mut self, now: Instant) -> Vec<IpNetwork> {
+        let reuse = self.config.reuse_threshold as f64;
+        let max_suppress = Duration::from_secs(self.config.max_suppress_secs);
+
+        let penalties: Vec<(IpNetwork, f64)> = self.entries
+            .iter()
+            .map(|(prefix, state)| (*prefix, self.decayed(state, now)))
+            .collect();
+
+        let mut reused = Vec::new();
+        for (prefix, penalty) in penalties {
+            let state = self.entries.get_mut(&prefix).expect("prefix taken from entries");
+
+            if let Some(since) = state.suppressed_since {
+                if penalty < reuse || now.saturating_duration_since(since) >= max_suppress {
+                    info!("Reusing dampened prefix {} (penalty {:.0})", prefix, penalty);
+                    state.suppressed_since = None;
+                    reused.push(prefix);
+                }
+            }
+        }
+
+        let config = &self.config;
+        self.entries.retain(|_, state| {
+            state.is_suppressed() || decay(state, now, config) >= reuse / 2.0
+        });
+
+        reused
+    }
+}
+
+/// Penalty of `state` decayed up to `now`
+fn decay(state: &DampeningState, now: Instant, config: &DampeningConfig) -> f64 {
+    let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+    let half_life = config.half_life().as_secs_f64().max(1.0);
+    state.penalty * 0.5f64.powf(elapsed / half_life)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix() -> IpNetwork {
+        "10.1.0.0/24".parse().unwrap()
+    }
+
+    #[test]
+    fn test_flapping_prefix_is_suppressed_and_reused() {
+        let mut dampening = RouteDampening::new(DampeningConfig::default());
+        let start = Instant::now();
+
+        // Three flaps ten seconds apart: 1000, ~1992, ~2977
+        assert!(!dampening.record_flap(prefix(), start));
+        assert!(!dampening.record_flap(prefix(), start + Duration::from_secs(10)));
+        assert!(!dampening.is_suppressed(&prefix()));
+        assert!(dampening.record_flap(prefix(), start + Duration::from_secs(20)));
+        assert!(dampening.is_suppressed(&prefix()));
+        assert_eq!(dampening.suppressed_count(), 1);
+
+        let suppressed_at = start + Duration::from_secs(20);
+        let penalty = dampening.penalty(&prefix(), suppressed_at);
+        assert!(penalty > 2900.0 && penalty < 3000.0);
+
+        // Decaying below 750 takes log2(penalty / 750) half-lives, just under 30 minutes
+        let reuse_after = 900.0 * (penalty / 750.0).log2();
+        assert!(reuse_after > 1780.0 && reuse_after < 1800.0);
+
+        let before = suppressed_at + Duration::from_secs_f64(reuse_after - 5.0);
+        assert!(dampening.reuse(before).is_empty());
+        assert!(dampening.is_suppressed(&prefix()));
+
+        let after = suppressed_at + Duration::from_secs_f64(reuse_after + 5.0);
+        assert_eq!(dampening.reuse(after), vec![prefix()]);
+        assert!(!dampening.is_suppressed(&prefix()));
+    }
+
+    #[test]
+    fn test_penalty_decays_by_half_life() {
+        let mut dampening = RouteDampening::new(DampeningConfig::default());
+        let start = Instant::now();
+
+        dampening.record_flap(prefix(), start);
+        let penalty = dampening.penalty(&prefix(), start + Duration::from_secs(900));
+        assert!((penalty - 500.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_max_suppress_time() {
+        // The penalty ceiling is reuse * 2^(600 / 300) = 3000
+        let config = DampeningConfig {
+            half_life_secs: 300,
+            max_suppress_secs: 600,
+            ..DampeningConfig::default()
+        };
+        let mut dampening = RouteDampening::new(config);
+        let start = Instant::now();
+
+        for _ in 0..20 {
+            dampening.record_flap(prefix(), start);
+        }
+        assert!(dampening.is_suppressed(&prefix()));
+        assert!((dampening.penalty(&prefix(), start) - 3000.0).abs() < 1e-6);
+
+        assert!(dampening.reuse(start + Duration::from_secs(599)).is_empty());
+        assert_eq!(dampening.reuse(start + Duration::from_secs(601)), vec![prefix()]);
+    }
+
+    #[test]
+    fn test_single_flap_is_forgotten() {
+        let mut dampening = RouteDampening::new(DampeningConfig::default());
+        let start = Instant::now();
+
+        dampening.record_flap(prefix(), start);
+        dampening.reuse(start + Duration::from_secs(3600));
+        assert!(dampening.state(&prefix()).is_none());
+    }
+}
@@ -3,8 +3,14 @@
 // This implements the BGP-4 FSM defined in RFC 4271 Section 8.
 // The FSM controls the BGP session lifecycle and handles state transitions.
 
+use crate::config::AddressFamily;
 use crate::error::{BgpError, Result};
+use crate::messages::{
+    Capability, GracefulRestartCapability, OpenMessage, RouteRefreshMessage, UpdateMessage,
+};
 use crate::neighbor::NeighborState;
+use crate::rib::Rib;
+use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
@@ -59,6 +65,54 @@ pub enum BgpEvent {
 
     /// Connection Retry Timer expires
     ConnectRetryTimerExpires,
+
+    /// End-of-RIB marker received from the peer
+    EndOfRibReceived,
+
+    /// ROUTE-REFRESH message received
+    RouteRefreshMsg,
+
+    /// Local request to have the peer re-send its routes
+    RouteRefreshRequest,
+
+    /// Peer did not re-establish within its advertised restart time
+    RestartTimerExpires,
+
+    /// Peer re-established but did not send End-of-RIB in time
+    StalePathTimerExpires,
+}
+
+/// Graceful Restart helper state for the peer (RFC 4724)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GracefulRestartState {
+    /// No restart in progress
+    Inactive,
+
+    /// Session dropped; holding stale routes until the peer reconnects
+    PeerRestarting { deadline: Instant },
+
+    /// Session re-established; holding stale routes until End-of-RIB
+    AwaitingEndOfRib { deadline: Instant },
+}
+
+/// Capabilities negotiated with the peer
+#[derive(Debug, Clone, Default)]
+pub struct PeerCapabilities {
+    /// Peer supports ROUTE-REFRESH
+    pub route_refresh: bool,
+
+    /// Peer's Graceful Restart capability
+    pub graceful_restart: Option<GracefulRestartCapability>,
+
+    /// Address families enabled on both sides
+    pub address_families: Vec<AddressFamily>,
+}
+
+impl PeerCapabilities {
+    /// Whether UPDATEs for a family may be exchanged with the peer
+    pub fn supports_family(&self, family: AddressFamily) -> bool {
+        self.address_families.contains(&family)
+    }
 }
 
 /// BGP FSM configuration
@@ -84,6 +138,21 @@ pub struct FsmConfig {
 
     /// Peer address
     pub peer_addr: String,
+
+    /// Advertise Graceful Restart and retain routes when the peer restarts
+    pub graceful_restart: bool,
+
+    /// Restart time advertised to the peer in seconds (default: 120)
+    pub restart_time: u16,
+
+    /// Seconds to keep stale routes after re-establishment waiting for End-of-RIB (default: 360)
+    pub stale_path_time: u16,
+
+    /// Advertise the Route Refresh capability
+    pub route_refresh: bool,
+
+    /// Address families to negotiate with the peer (default: IPv4 unicast)
+    pub address_families: Vec<AddressFamily>,
 }
 
 impl Default for FsmConfig {
@@ -96,6 +165,11 @@ impl Default for FsmConfig {
             local_bgp_id: 0x01010101, // 1.1.1.1
             remote_asn: 65001,
             peer_addr: "0.0.0.0".to_string(),
+            graceful_restart: false,
+            restart_time: 120,
+            stale_path_time: 360,
+            route_refresh: true,
+            address_families: vec![AddressFamily::Ipv4Unicast],
         }
     }
 }
@@ -109,6 +183,10 @@ pub struct BgpFsm {
     keepalive_time: Arc<RwLock<Duration>>,
     last_update_time: Arc<RwLock<Instant>>,
     connection: Arc<RwLock<Option<TcpStream>>>,
+    peer_capabilities: Arc<RwLock<PeerCapabilities>>,
+    gr_state: Arc<RwLock<GracefulRestartState>>,
+    local_restarting: Arc<RwLock<bool>>,
+    rib: Option<Arc<Rib>>,
     event_tx: mpsc::UnboundedSender<BgpEvent>,
     event_rx: Arc<RwLock<mpsc::UnboundedReceiver<BgpEvent>>>,
 }
@@ -126,11 +204,171 @@ impl BgpFsm {
             keepalive_time: Arc::new(RwLock::new(Duration::from_secs(30))),
             last_update_time: Arc::new(RwLock::new(Instant::now())),
             connection: Arc::new(RwLock::new(None)),
+            peer_capabilities: Arc::new(RwLock::new(PeerCapabilities::default())),
+            gr_state: Arc::new(RwLock::new(GracefulRestartState::Inactive)),
+            local_restarting: Arc::new(RwLock::new(false)),
+            rib: None,
             event_tx,
             event_rx: Arc::new(RwLock::new(event_rx)),
         }
     }
 
+    /// Attach the RIB holding routes learned from this peer
+    pub fn with_rib(mut self, rib: Arc<Rib>) -> Self {
+        self.rib = Some(rib);
+        self
+    }
+
+    /// Get current Graceful Restart state
+    pub async fn graceful_restart_state(&self) -> GracefulRestartState {
+        *self.gr_state.read().await
+    }
+
+    /// Get capabilities negotiated with the peer
+    pub async fn peer_capabilities(&self) -> PeerCapabilities {
+        self.peer_capabilities.read().await.clone()
+    }
+
+    /// Mark that this speaker is coming back from a planned restart
+    ///
+    /// The next OPEN sets the Restart State bit so the peer keeps our routes
+    /// until we send End-of-RIB.
+    pub async fn set_local_restarting(&self, restarting: bool) {
+        *self.local_restarting.write().await = restarting;
+    }
+
+    /// Build the OPEN message advertising our capabilities
+    pub async fn build_open(&self) -> OpenMessage {
+        let my_asn = u16::try_from(self.config.local_asn).unwrap_or(23456); // AS_TRANS
+        let mut open = OpenMessage::new(my_asn, self.config.hold_time, self.config.local_bgp_id);
+
+        for family in &self.config.address_families {
+            open = open.with_capability(Capability::MultiProtocol {
+                afi: family.afi(),
+                safi: family.safi(),
+            });
+        }
+
+        if self.config.route_refresh {
+            open = open.with_capability(Capability::RouteRefresh);
+        }
+
+        if self.config.graceful_restart {
+            let restarting = *self.local_restarting.read().await;
+            open = open.with_capability(Capability::GracefulRestart(
+                GracefulRestartCapability::new(self.config.restart_time, restarting),
+            ));
+        }
+
+        open
+    }
+
+    /// Record the capabilities from the peer's OPEN message
+    ///
+    /// Should be called before delivering `BgpEvent::BgpOpen`.
+    pub async fn negotiate_capabilities(&self, open: &OpenMessage) {
+        let mut peer_families: Vec<AddressFamily> = open
+            .multiprotocol_families()
+            .into_iter()
+            .filter_map(|(afi, safi)| AddressFamily::from_afi_safi(afi, safi))
+            .collect();
+
+        // A peer without Multiprotocol capabilities only speaks IPv4 unicast (RFC 4760)
+        if open.multiprotocol_families().is_empty() {
+            peer_families.push(AddressFamily::Ipv4Unicast);
+        }
+
+        let caps = PeerCapabilities {
+            route_refresh: self.config.route_refresh && open.supports_route_refresh(),
+            graceful_restart: if self.config.graceful_restart {
+                open.graceful_restart()
+            } else {
+                None
+            },
+            address_families: self
+                .config
+                .address_families
+                .iter()
+                .copied()
+                .filter(|f| peer_families.contains(f))
+                .collect(),
+        };
+
+        debug!(
+            "Negotiated capabilities with {}: route_refresh={}, graceful_restart={}, families={:?}",
+            self.config.peer_addr,
+            caps.route_refresh,
+            caps.graceful_restart.is_some(),
+            caps.address_families
+        );
+
+        *self.peer_capabilities.write().await = caps;
+    }
+
+    /// Ask the peer to re-send its routes (RFC 2918)
+    ///
+    /// `BgpManager::set_route_map_in` calls this after swapping the inbound
+    /// route map, instead of resetting the session.
+    pub async fn request_route_refresh(&self) -> Result<()> {
+        if *self.state.read().await != NeighborState::Established {
+            return Err(BgpError::InvalidState(
+                "Route refresh requires an established session".into(),
+            ));
+        }
+
+        if !self.peer_capabilities.read().await.route_refresh {
+            return Err(BgpError::InvalidState(format!(
+                "Peer {} does not support route refresh",
+                self.config.peer_addr
+            )));
+        }
+
+        self.send_event(BgpEvent::RouteRefreshRequest)
+    }
+
+    /// Send an UPDATE to the peer
+    ///
+    /// UPDATEs carrying a family that was not negotiated with the peer are
+    /// refused, so e.g. an IPv4-only peer never receives IPv6 routes.
+    pub async fn send_update(&self, update: &UpdateMessage) -> Result<()> {
+        if *self.state.read().await != NeighborState::Established {
+            return Err(BgpError::InvalidState(
+                "Sending UPDATE requires an established session".into(),
+            ));
+        }
+
+        let caps = self.peer_capabilities.read().await;
+        for (afi, safi) in update.address_families() {
+            let negotiated = AddressFamily::from_afi_safi(afi, safi)
+                .is_some_and(|f| caps.supports_family(f));
+            if !negotiated {
+                return Err(BgpError::ProtocolError(format!(
+                    "AFI {} SAFI {} not negotiated with peer {}",
+                    afi, safi, self.config.peer_addr
+                )));
+            }
+        }
+        drop(caps);
+
+        debug!("Sending UPDATE ({} bytes) to {}", update.encode().len(), self.config.peer_addr);
+        // Stub: would write the UPDATE message to the peer
+        *self.last_update_time.write().await = Instant::now();
+        Ok(())
+    }
+
+    /// Determine whether a Graceful Restart timer has expired at `now`
+    pub async fn graceful_restart_timer_event(&self, now: Instant) -> Option<BgpEvent> {
+        match *self.gr_state.read().await {
+            GracefulRestartState::PeerRestarting { deadline } if now >= deadline => {
+                Some(BgpEvent::RestartTimerExpires)
+            }
+            GracefulRestartState::AwaitingEndOfRib { deadline } if now >= deadline => {
+                Some(BgpEvent::StalePathTimerExpires)
+            }
+            _ => None,
+        }
+    }
+
     /// Get current state
     pub async fn state(&self) -> NeighborState {
         *self.state.read().await
@@ -245,6 +483,18 @@ impl BgpFsm {
             (NeighborState::Established, BgpEvent::HoldTimerExpires) => {
                 self.send_notification_and_stop().await?;
             }
+            (NeighborState::Established, BgpEvent::TcpConnectionFails) => {
+                self.handle_session_loss().await?;
+            }
+            (NeighborState::Established, BgpEvent::EndOfRibReceived) => {
+                self.process_end_of_rib().await?;
+            }
+            (NeighborState::Established, BgpEvent::RouteRefreshMsg) => {
+                self.process_route_refresh().await?;
+            }
+            (NeighborState::Established, BgpEvent::RouteRefreshRequest) => {
+                self.send_route_refresh().await?;
+            }
             (NeighborState::Established, BgpEvent::UpdateMsgErr) => {
                 self.send_notification_and_stop().await?;
             }
@@ -255,6 +505,11 @@ impl BgpFsm {
                 self.transition_to_idle().await?;
             }
 
+            // Graceful Restart timers apply in any state
+            (_, BgpEvent::RestartTimerExpires | BgpEvent::StalePathTimerExpires) => {
+                self.graceful_restart_timer_expired(&event).await?;
+            }
+
             // Unhandled combinations
             _ => {
                 warn!(
@@ -315,6 +570,17 @@ impl BgpFsm {
                 }
             }
         });
+
+        // Graceful Restart / Stale Path Timer
+        let fsm = Arc::new(self.clone_weak());
+        tokio::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(1)).await;
+                if let Some(event) = fsm.graceful_restart_timer_event(Instant::now()).await {
+                    let _ = fsm.send_event(event);
+                }
+            }
+        });
     }
 
     /// Transition to Connect state
@@ -369,20 +635,48 @@ impl BgpFsm {
     }
 
     /// Transition to Established state
-    async fn transition_to_established(&self) -> Result<()> {
+    pub(crate) async fn transition_to_established(&self) -> Result<()> {
         info!("FSM: Transitioning to Established state");
         *self.state.write().await = NeighborState::Established;
 
         // Reset hold timer
         *self.last_update_time.write().await = Instant::now();
 
+        // A restarting peer now has until the stale path timer to send End-of-RIB
+        let restarting = matches!(
+            *self.gr_state.read().await,
+            GracefulRestartState::PeerRestarting { .. }
+        );
+        if restarting {
+            if self.peer_capabilities.read().await.graceful_restart.is_some() {
+                let deadline = Instant::now() + Duration::from_secs(self.config.stale_path_time as u64);
+                *self.gr_state.write().await = GracefulRestartState::AwaitingEndOfRib { deadline };
+            } else {
+                // Peer no longer advertises GR: stale routes cannot be trusted
+                self.flush_stale_routes().await;
+            }
+        }
+
+        // Our own restart completes once we have sent our routes
+        if *self.local_restarting.read().await {
+            self.send_end_of_rib().await?;
+            *self.local_restarting.write().await = false;
+        }
+
         Ok(())
     }
 
     /// Transition to Idle state
     async fn transition_to_idle(&self) -> Result<()> {
         info!("FSM: Transitioning to Idle state");
-        *self.state.write().await = NeighborState::Idle;
+        let previous = std::mem::replace(&mut *self.state.write().await, NeighborState::Idle);
+
+        // Routes from a non-graceful session end (or from an abandoned restart) are withdrawn
+        if previous == NeighborState::Established {
+            self.withdraw_peer_routes().await;
+        } else {
+            self.flush_stale_routes().await;
+        }
 
         // Close connection
         *self.connection.write().await = None;
@@ -403,6 +697,80 @@ impl BgpFsm {
         self.transition_to_connect().await
     }
 
+    /// Handle the TCP session dropping while Established
+    ///
+    /// If the peer advertised Graceful Restart its routes are retained as
+    /// stale for the advertised restart time; otherwise they are withdrawn.
+    async fn handle_session_loss(&self) -> Result<()> {
+        let peer_gr = self.peer_capabilities.read().await.graceful_restart.clone();
+
+        match peer_gr {
+            Some(gr) if gr.restart_time > 0 => {
+                info!(
+                    "Peer {} session lost, retaining routes as stale for {}s",
+                    self.config.peer_addr, gr.restart_time
+                );
+
+                if let (Some(rib), Some(peer)) = (&self.rib, self.peer_ip()) {
+                    rib.mark_stale(peer);
+                }
+
+                let deadline = Instant::now() + Duration::from_secs(gr.restart_time as u64);
+                *self.gr_state.write().await = GracefulRestartState::PeerRestarting { deadline };
+            }
+            _ => {
+                self.withdraw_peer_routes().await;
+            }
+        }
+
+        self.transition_to_active().await
+    }
+
+    /// End-of-RIB received: anything still stale was not re-advertised
+    async fn process_end_of_rib(&self) -> Result<()> {
+        debug!("Processing End-of-RIB from {}", self.config.peer_addr);
+        *self.last_update_time.write().await = Instant::now();
+        self.flush_stale_routes().await;
+        Ok(())
+    }
+
+    /// Restart or stale path timer expired: give up on stale routes
+    async fn graceful_restart_timer_expired(&self, event: &BgpEvent) -> Result<()> {
+        if *self.gr_state.read().await == GracefulRestartState::Inactive {
+            return Ok(());
+        }
+
+        warn!(
+            "Graceful restart timer expired for {} ({:?}), flushing stale routes",
+            self.config.peer_addr, event
+        );
+        self.flush_stale_routes().await;
+        Ok(())
+    }
+
+    /// Remove remaining stale routes and leave Graceful Restart
+    async fn flush_stale_routes(&self) {
+        if let (Some(rib), Some(peer)) = (&self.rib, self.peer_ip()) {
+            rib.flush_stale(peer);
+        }
+        *self.gr_state.write().await = GracefulRestartState::Inactive;
+    }
+
+    /// Withdraw every route learned from the peer
+    async fn withdraw_peer_routes(&self) {
+        if let (Some(rib), Some(peer)) = (&self.rib, self.peer_ip()) {
+            let removed = rib.remove_routes_via(peer);
+            if removed > 0 {
+                info!("Withdrew {} routes from {}", removed, peer);
+            }
+        }
+        *self.gr_state.write().await = GracefulRestartState::Inactive;
+    }
+
+    fn peer_ip(&self) -> Option<Ipv4Addr> {
+        self.config.peer_addr.parse().ok()
+    }
+
     /// Send NOTIFICATION and stop
     async fn send_notification_and_stop(&self) -> Result<()> {
         warn!("Sending NOTIFICATION and stopping");
@@ -437,6 +805,34 @@ impl BgpFsm {
         Ok(())
     }
 
+    async fn send_end_of_rib(&self) -> Result<()> {
+        let families = self.peer_capabilities.read().await.address_families.clone();
+        for family in families {
+            debug!("Sending End-of-RIB for {:?} to {}", family, self.config.peer_addr);
+            let eor = UpdateMessage::end_of_rib_for(family.afi(), family.safi());
+            // Stub: would write the End-of-RIB marker to the peer
+            let _ = eor.encode();
+        }
+        Ok(())
+    }
+
+    async fn send_route_refresh(&self) -> Result<()> {
+        let families = self.peer_capabilities.read().await.address_families.clone();
+        for family in families {
+            let refresh = RouteRefreshMessage::new(family.afi(), family.safi());
+            debug!("Sending ROUTE-REFRESH ({} bytes) to {}", refresh.encode().len(), self.config.peer_addr);
+            // Stub: would write the ROUTE-REFRESH message to the peer
+        }
+        Ok(())
+    }
+
+    async fn process_route_refresh(&self) -> Result<()> {
+        debug!("Processing ROUTE-REFRESH from {}", self.config.peer_addr);
+        // Stub: would re-advertise the Adj-RIB-Out followed by End-of-RIB
+        *self.last_update_time.write().await = Instant::now();
+        self.send_end_of_rib().await
+    }
+
     async fn process_keepalive(&self) -> Result<()> {
         debug!("Processing KEEPALIVE message");
         *self.last_update_time.write().await = Instant::now();
@@ -453,6 +849,10 @@ impl BgpFsm {
             keepalive_time: Arc::clone(&self.keepalive_time),
             last_update_time: Arc::clone(&self.last_update_time),
             connection: Arc::clone(&self.connection),
+            peer_capabilities: Arc::clone(&self.peer_capabilities),
+            gr_state: Arc::clone(&self.gr_state),
+            local_restarting: Arc::clone(&self.local_restarting),
+            rib: self.rib.clone(),
             event_tx: self.event_tx.clone(),
             event_rx: Arc::clone(&self.event_rx),
         }
@@ -531,4 +931,255 @@ mod tests {
 
         assert_eq!(fsm.state().await, NeighborState::Idle);
     }
+
+    fn gr_config() -> FsmConfig {
+        FsmConfig {
+            peer_addr: "192.168.1.1".to_string(),
+            graceful_restart: true,
+            ..FsmConfig::default()
+        }
+    }
+
+    fn peer_open(restart_time: u16) -> OpenMessage {
+        OpenMessage::new(65001, 90, 0x02020202)
+            .with_capability(Capability::RouteRefresh)
+            .with_capability(Capability::GracefulRestart(GracefulRestartCapability::new(
+                restart_time,
+                true,
+            )))
+    }
+
+    fn peer_route(prefix: &str) -> crate::route::BgpRoute {
+        crate::route::BgpRoute::new(
+            prefix.parse().unwrap(),
+            "192.168.1.1".parse().unwrap(),
+            vec![65001],
+        )
+    }
+
+    async fn established_with_routes(restart_time: u16) -> (Arc<BgpFsm>, Arc<Rib>) {
+        let rib = Arc::new(Rib::new(65000));
+        let fsm = Arc::new(BgpFsm::new(gr_config()).with_rib(rib.clone()));
+
+        fsm.negotiate_capabilities(&peer_open(restart_time)).await;
+        fsm.transition_to_established().await.unwrap();
+
+        rib.add_route(peer_route("10.0.0.0/24")).unwrap();
+        rib.add_route(peer_route("10.1.0.0/24")).unwrap();
+
+        (fsm, rib)
+    }
+
+    #[tokio::test]
+    async fn test_build_open_advertises_capabilities() {
+        let fsm = BgpFsm::new(gr_config());
+
+        let open = fsm.build_open().await;
+        assert!(open.supports_route_refresh());
+        assert!(!open.graceful_restart().unwrap().restart_state);
+
+        fsm.set_local_restarting(true).await;
+        let open = fsm.build_open().await;
+        let gr = open.graceful_restart().unwrap();
+        assert!(gr.restart_state);
+        assert_eq!(gr.restart_time, 120);
+
+        // Restart State is cleared once the session is re-established
+        fsm.transition_to_established().await.unwrap();
+        assert!(!fsm.build_open().await.graceful_restart().unwrap().restart_state);
+    }
+
+    #[tokio::test]
+    async fn test_session_loss_retains_stale_routes() {
+        let (fsm, rib) = established_with_routes(120).await;
+
+        fsm.process_event(NeighborState::Established, BgpEvent::TcpConnectionFails)
+            .await
+            .unwrap();
+
+        assert_eq!(fsm.state().await, NeighborState::Active);
+        assert!(matches!(
+            fsm.graceful_restart_state().await,
+            GracefulRestartState::PeerRestarting { .. }
+        ));
+        assert_eq!(rib.route_count(), 2);
+        assert_eq!(rib.stale_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_session_loss_without_gr_withdraws_routes() {
+        let rib = Arc::new(Rib::new(65000));
+        let fsm = Arc::new(BgpFsm::new(gr_config()).with_rib(rib.clone()));
+        fsm.negotiate_capabilities(&OpenMessage::new(65001, 90, 0x02020202)).await;
+        fsm.transition_to_established().await.unwrap();
+        rib.add_route(peer_route("10.0.0.0/24")).unwrap();
+
+        fsm.process_event(NeighborState::Established, BgpEvent::TcpConnectionFails)
+            .await
+            .unwrap();
+
+        assert_eq!(rib.route_count(), 0);
+        assert_eq!(fsm.graceful_restart_state().await, GracefulRestartState::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_restart_timer_expiry_flushes_stale_routes() {
+        let (fsm, rib) = established_with_routes(120).await;
+        fsm.process_event(NeighborState::Established, BgpEvent::TcpConnectionFails)
+            .await
+            .unwrap();
+
+        // Timer has not yet fired
+        assert!(fsm.graceful_restart_timer_event(Instant::now()).await.is_none());
+
+        let later = Instant::now() + Duration::from_secs(121);
+        let event = fsm.graceful_restart_timer_event(later).await.unwrap();
+        assert_eq!(event, BgpEvent::RestartTimerExpires);
+
+        fsm.process_event(NeighborState::Active, event).await.unwrap();
+
+        assert_eq!(rib.route_count(), 0);
+        assert_eq!(fsm.graceful_restart_state().await, GracefulRestartState::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_end_of_rib_flushes_unrefreshed_routes() {
+        let (fsm, rib) = established_with_routes(120).await;
+        fsm.process_event(NeighborState::Established, BgpEvent::TcpConnectionFails)
+            .await
+            .unwrap();
+
+        // Peer comes back and re-advertises only one prefix
+        fsm.negotiate_capabilities(&peer_open(120)).await;
+        fsm.transition_to_established().await.unwrap();
+        assert!(matches!(
+            fsm.graceful_restart_state().await,
+            GracefulRestartState::AwaitingEndOfRib { .. }
+        ));
+
+        rib.add_route(peer_route("10.0.0.0/24")).unwrap();
+        assert_eq!(rib.stale_count(), 1);
+
+        fsm.process_event(NeighborState::Established, BgpEvent::EndOfRibReceived)
+            .await
+            .unwrap();
+
+        assert_eq!(rib.route_count(), 1);
+        assert_eq!(rib.stale_count(), 0);
+        assert_eq!(fsm.graceful_restart_state().await, GracefulRestartState::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_stale_path_timer_expiry_flushes_stale_routes() {
+        let (fsm, rib) = established_with_routes(120).await;
+        fsm.process_event(NeighborState::Established, BgpEvent::TcpConnectionFails)
+            .await
+            .unwrap();
+        fsm.negotiate_capabilities(&peer_open(120)).await;
+        fsm.transition_to_established().await.unwrap();
+
+        let later = Instant::now() + Duration::from_secs(361);
+        let event = fsm.graceful_restart_timer_event(later).await.unwrap();
+        assert_eq!(event, BgpEvent::StalePathTimerExpires);
+
+        fsm.process_event(NeighborState::Established, event).await.unwrap();
+
+        assert_eq!(rib.route_count(), 0);
+        assert_eq!(fsm.state().await, NeighborState::Established);
+    }
+
+    #[tokio::test]
+    async fn test_route_refresh_request() {
+        let fsm = Arc::new(BgpFsm::new(gr_config()));
+
+        // Not established yet
+        assert!(fsm.request_route_refresh().await.is_err());
+
+        fsm.negotiate_capabilities(&OpenMessage::new(65001, 90, 0x02020202)).await;
+        fsm.transition_to_established().await.unwrap();
+        assert!(fsm.request_route_refresh().await.is_err());
+
+        fsm.negotiate_capabilities(&peer_open(120)).await;
+        fsm.request_route_refresh().await.unwrap();
+
+        let event = fsm.event_rx.write().await.recv().await.unwrap();
+        assert_eq!(event, BgpEvent::RouteRefreshRequest);
+        fsm.process_event(NeighborState::Established, event).await.unwrap();
+        assert_eq!(fsm.state().await, NeighborState::Established);
+    }
+
+    fn ipv6_update() -> UpdateMessage {
+        use crate::messages::{IpPrefix, MpReachNlri};
+
+        let prefix = IpPrefix::from_network("2001:db8:1::/48".parse().unwrap());
+        UpdateMessage::new().with_mp_reach(MpReachNlri::ipv6_unicast(
+            "2001:db8::1".parse().unwrap(),
+            Some("fe80::1".parse().unwrap()),
+            vec![prefix],
+        ))
+    }
+
+    fn dual_stack_config() -> FsmConfig {
+        FsmConfig {
+            peer_addr: "192.168.1.1".to_string(),
+            address_families: vec![AddressFamily::Ipv4Unicast, AddressFamily::Ipv6Unicast],
+            ..FsmConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_open_advertises_address_families() {
+        let fsm = BgpFsm::new(dual_stack_config());
+        let open = fsm.build_open().await;
+
+        assert_eq!(
+            open.multiprotocol_families(),
+            vec![
+                (AddressFamily::Ipv4Unicast.afi(), AddressFamily::Ipv4Unicast.safi()),
+                (AddressFamily::Ipv6Unicast.afi(), AddressFamily::Ipv6Unicast.safi()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ipv4_only_peer_never_gets_ipv6_update() {
+        let fsm = BgpFsm::new(dual_stack_config());
+
+        // Peer advertises only the IPv4 unicast family
+        let peer = OpenMessage::new(65001, 90, 0x02020202).with_capability(Capability::MultiProtocol {
+            afi: AddressFamily::Ipv4Unicast.afi(),
+            safi: AddressFamily::Ipv4Unicast.safi(),
+        });
+        fsm.negotiate_capabilities(&peer).await;
+        fsm.transition_to_established().await.unwrap();
+
+        let caps = fsm.peer_capabilities().await;
+        assert_eq!(caps.address_families, vec![AddressFamily::Ipv4Unicast]);
+
+        assert!(fsm.send_update(&ipv6_update()).await.is_err());
+        assert!(fsm.send_update(&UpdateMessage::end_of_rib_for(2, 1)).await.is_err());
+        assert!(fsm.send_update(&UpdateMessage::end_of_rib()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_peer_without_mp_capability_is_ipv4_only() {
+        let fsm = BgpFsm::new(dual_stack_config());
+
+        fsm.negotiate_capabilities(&OpenMessage::new(65001, 90, 0x02020202)).await;
+        fsm.transition_to_established().await.unwrap();
+
+        assert!(!fsm.peer_capabilities().await.supports_family(AddressFamily::Ipv6Unicast));
+        assert!(fsm.send_update(&ipv6_update()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_peer_gets_ipv6_update() {
+        let fsm = BgpFsm::new(dual_stack_config());
+
+        let peer = fsm.build_open().await;
+        fsm.negotiate_capabilities(&peer).await;
+        fsm.transition_to_established().await.unwrap();
+
+        assert!(fsm.send_update(&ipv6_update()).await.is_ok());
+    }
 }
@@ -4,6 +4,7 @@
 //! enabling dynamic route advertisement and learning from upstream routers.
 
 pub mod config;
+pub mod dampening;
 pub mod error;
 pub mod fsm;
 pub mod manager;
@@ -13,14 +14,20 @@ pub mod rib;
 pub mod route;
 pub mod session;
 
-pub use config::{BgpConfig, NeighborConfig, RouteMapConfig};
+pub use config::{
+    AddressFamily, BgpConfig, DampeningConfig, NeighborConfig, PrefixLimitConfig, RouteMapConfig,
+};
+pub use dampening::RouteDampening;
 pub use error::{BgpError, Result};
-pub use fsm::{BgpEvent, BgpFsm, FsmConfig};
-pub use manager::BgpManager;
-pub use messages::{BgpMessage, KeepaliveMessage, NotificationMessage, OpenMessage, UpdateMessage};
+pub use fsm::{BgpEvent, BgpFsm, FsmConfig, GracefulRestartState, PeerCapabilities};
+pub use manager::{BgpManager, BgpManagerEvent, InboundRefresh, NeighborStats};
+pub use messages::{
+    BgpMessage, Capability, GracefulRestartCapability, IpPrefix, KeepaliveMessage, MpReachNlri,
+    MpUnreachNlri, NotificationMessage, OpenMessage, RouteRefreshMessage, UpdateMessage,
+};
 pub use neighbor::BgpNeighbor;
 pub use rib::Rib;
-pub use route::{BgpRoute, RouteAction};
+pub use route::{BgpRoute, BgpRoute6, RouteAction, RouteAttributes};
 pub use session::BgpSession;
 
 /// BGP protocol version
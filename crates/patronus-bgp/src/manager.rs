@@ -1,8 +1,80 @@
 //! BGP manager
 
-use crate::{config::BgpConfig, error::Result, neighbor::BgpNeighbor, route::BgpRoute};
-use std::collections::HashMap;
+use crate::{
+    config::{AddressFamily, BgpConfig, RouteMapAction},
+    dampening::RouteDampening,
+    error::{BgpError, Result},
+    fsm::BgpFsm,
+    neighbor::{BgpNeighbor, NeighborState, ReceivedRoute},
+    route::{BgpRoute, BgpRoute6, RouteAttributes},
+};
+use ipnetwork::IpNetwork;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Events raised by the BGP manager
+#[derive(Debug, Clone, PartialEq)]
+pub enum BgpManagerEvent {
+    /// Neighbor crossed the prefix limit warning threshold
+    PrefixLimitWarning {
+        neighbor: IpAddr,
+        prefixes: usize,
+        limit: u32,
+    },
+
+    /// Neighbor exceeded its prefix limit and the session was torn down
+    PrefixLimitExceeded {
+        neighbor: IpAddr,
+        prefixes: usize,
+        limit: u32,
+    },
+
+    /// Neighbor torn down by the prefix limit may be re-established
+    NeighborRestarted { neighbor: IpAddr },
+
+    /// Flapping prefix is no longer advertised
+    RouteSuppressed { prefix: IpNetwork, penalty: f64 },
+
+    /// Dampened prefix is advertised again
+    RouteReused { prefix: IpNetwork },
+}
+
+/// How routes from a neighbor were re-evaluated after its inbound route map changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboundRefresh {
+    /// A ROUTE-REFRESH was sent and the peer will re-send its routes
+    RouteRefresh,
+
+    /// The new route map was applied to the routes already received
+    SoftReset,
+}
+
+/// Runtime counters for a neighbor
+#[derive(Debug, Clone, Serialize)]
+pub struct NeighborStats {
+    /// Neighbor address
+    pub neighbor: IpAddr,
+
+    /// Session state
+    pub state: String,
+
+    /// Prefixes currently received from the neighbor
+    pub prefixes_received: usize,
+
+    /// Received prefixes currently suppressed by dampening
+    pub suppressed_routes: usize,
+
+    /// Configured maximum prefixes
+    pub prefix_limit: Option<u32>,
+
+    /// Session is down because the prefix limit was exceeded
+    pub prefix_limit_tripped: bool,
+}
 
 /// BGP manager
 pub struct BgpManager {
@@ -14,6 +86,21 @@ pub struct BgpManager {
 
     /// Routing table
     routes: Vec<BgpRoute>,
+
+    /// IPv6 routing table
+    routes_v6: Vec<BgpRoute6>,
+
+    /// Route flap dampening, if enabled
+    dampening: Option<RouteDampening>,
+
+    /// Locally originated prefixes and their next hop
+    originated: BTreeMap<IpNetwork, IpAddr>,
+
+    /// Running sessions, by neighbor address
+    sessions: HashMap<IpAddr, Arc<BgpFsm>>,
+
+    /// Event channel
+    events: broadcast::Sender<BgpManagerEvent>,
 }
 
 impl BgpManager {
@@ -26,10 +113,18 @@ impl BgpManager {
             neighbors.insert(neighbor_config.ip, neighbor);
         }
 
+        let dampening = config.dampening.clone().map(RouteDampening::new);
+        let (events, _) = broadcast::channel(64);
+
         Self {
             config,
             neighbors,
             routes: Vec::new(),
+            routes_v6: Vec::new(),
+            dampening,
+            originated: BTreeMap::new(),
+            sessions: HashMap::new(),
+            events,
         }
     }
 
@@ -56,6 +151,403 @@ impl BgpManager {
         &self.routes
     }
 
+    /// Get IPv6 routes
+    pub fn routes_v6(&self) -> &[BgpRoute6] {
+        &self.routes_v6
+    }
+
+    /// Routes advertised downstream: learned routes other than dampened
+    /// prefixes, then locally originated ones
+    pub fn advertised_routes(&self) -> Vec<BgpRoute> {
+        let originated = self.originated.iter().filter_map(|(prefix, next_hop)| match (prefix, next_hop) {
+            (IpNetwork::V4(prefix), IpAddr::V4(next_hop)) => Some(BgpRoute::new(*prefix, *next_hop, Vec::new())),
+            _ => None,
+        });
+        self.routes
+            .iter()
+            .filter(|r| !self.is_suppressed(&r.to_ip_network()))
+            .cloned()
+            .chain(originated)
+            .collect()
+    }
+
+    /// IPv6 routes advertised downstream: learned routes other than
+    /// dampened prefixes, then locally originated ones
+    pub fn advertised_routes_v6(&self) -> Vec<BgpRoute6> {
+        let originated = self.originated.iter().filter_map(|(prefix, next_hop)| match (prefix, next_hop) {
+            (IpNetwork::V6(prefix), IpAddr::V6(next_hop)) => Some(BgpRoute6::new(*prefix, *next_hop, Vec::new())),
+            _ => None,
+        });
+        self.routes_v6
+            .iter()
+            .filter(|r| !self.is_suppressed(&r.to_ip_network()))
+            .cloned()
+            .chain(originated)
+            .collect()
+    }
+
+    /// Originate `prefix` with `next_hop`, moving it if already originated
+    pub fn originate(&mut self, prefix: IpNetwork, next_hop: IpAddr) -> Result<()> {
+        if prefix.is_ipv4() != next_hop.is_ipv4() {
+            return Err(BgpError::ConfigurationError(format!(
+                "Next hop {} is not in the address family of {}",
+                next_hop, prefix
+            )));
+        }
+        if self.originated.insert(prefix, next_hop) != Some(next_hop) {
+            info!("Originating {} via {}", prefix, next_hop);
+        }
+        Ok(())
+    }
+
+    /// Stop originating `prefix`
+    ///
+    /// Returns false if it was not originated.
+    pub fn withdraw_originated(&mut self, prefix: IpNetwork) -> bool {
+        let withdrawn = self.originated.remove(&prefix).is_some();
+        if withdrawn {
+            info!("Withdrew originated prefix {}", prefix);
+        }
+        withdrawn
+    }
+
+    /// Next hop `prefix` is originated with, if it is
+    pub fn originated(&self, prefix: &IpNetwork) -> Option<IpAddr> {
+        self.originated.get(prefix).copied()
+    }
+
+    /// Subscribe to manager events
+    pub fn subscribe(&self) -> broadcast::Receiver<BgpManagerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Accept an IPv4 route learned from a neighbor
+    ///
+    /// Returns false if the family is not active for the neighbor, the
+    /// inbound route map denies the route, or the neighbor is down after
+    /// exceeding its prefix limit.
+    pub fn learn_route(&mut self, from: IpAddr, route: BgpRoute) -> Result<bool> {
+        self.learn_route_at(from, route, Instant::now())
+    }
+
+    fn learn_route_at(&mut self, from: IpAddr, mut route: BgpRoute, now: Instant) -> Result<bool> {
+        if !self.admit_route(from, AddressFamily::Ipv4Unicast, now)? {
+            return Ok(false);
+        }
+
+        let prefix = route.to_ip_network();
+        self.store_received(from, prefix, ReceivedRoute::V4(route.clone()));
+        if !self.apply_route_map_in(from, prefix, &mut route)? {
+            return Ok(false);
+        }
+
+        let next_hop = route.next_hop_ip();
+        self.remove_route_entry(prefix, next_hop);
+        self.routes.push(route);
+
+        Ok(self.record_received(from, prefix, next_hop, now))
+    }
+
+    /// Accept an IPv6 route learned from a neighbor
+    ///
+    /// Returns false if the family is not active for the neighbor, the
+    /// inbound route map denies the route, or the neighbor is down after
+    /// exceeding its prefix limit.
+    pub fn learn_route_v6(&mut self, from: IpAddr, route: BgpRoute6) -> Result<bool> {
+        self.learn_route_v6_at(from, route, Instant::now())
+    }
+
+    fn learn_route_v6_at(&mut self, from: IpAddr, mut route: BgpRoute6, now: Instant) -> Result<bool> {
+        if !self.admit_route(from, AddressFamily::Ipv6Unicast, now)? {
+            return Ok(false);
+        }
+
+        let prefix = route.to_ip_network();
+        self.store_received(from, prefix, ReceivedRoute::V6(route.clone()));
+        if !self.apply_route_map_in(from, prefix, &mut route)? {
+            return Ok(false);
+        }
+
+        let next_hop = route.next_hop_ip();
+        self.remove_route_entry(prefix, next_hop);
+        self.routes_v6.push(route);
+
+        Ok(self.record_received(from, prefix, next_hop, now))
+    }
+
+    /// Withdraw a prefix previously received from a neighbor
+    ///
+    /// Each withdrawal counts as a flap for dampening. Returns false if the
+    /// neighbor had not advertised the prefix.
+    pub fn withdraw_route(&mut self, from: IpAddr, prefix: IpNetwork) -> Result<bool> {
+        self.withdraw_route_at(from, prefix, Instant::now())
+    }
+
+    fn withdraw_route_at(&mut self, from: IpAddr, prefix: IpNetwork, now: Instant) -> Result<bool> {
+        let neighbor = self.neighbors.get_mut(&from)
+            .ok_or_else(|| BgpError::ConfigurationError(format!("Unknown neighbor {}", from)))?;
+
+        neighbor.forget_received(&prefix);
+        let Some(next_hop) = neighbor.withdraw_prefix(&prefix) else {
+            return Ok(false);
+        };
+        self.remove_route_entry(prefix, next_hop);
+
+        if let Some(dampening) = &mut self.dampening {
+            if dampening.record_flap(prefix, now) {
+                let penalty = dampening.penalty(&prefix, now);
+                self.emit(BgpManagerEvent::RouteSuppressed { prefix, penalty });
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Run periodic work: reuse dampened prefixes and restart neighbors
+    /// whose prefix limit idle period has elapsed
+    pub fn process_timers(&mut self) {
+        self.process_timers_at(Instant::now())
+    }
+
+    fn process_timers_at(&mut self, now: Instant) {
+        if let Some(dampening) = &mut self.dampening {
+            for prefix in dampening.reuse(now) {
+                self.emit(BgpManagerEvent::RouteReused { prefix });
+            }
+        }
+
+        let due: Vec<IpAddr> = self.neighbors
+            .values()
+            .filter(|n| n.restart_at().is_some_and(|at| now >= at))
+            .map(|n| n.peer_ip())
+            .collect();
+        for neighbor in due {
+            self.restart_neighbor(neighbor);
+        }
+    }
+
+    /// Runtime counters for every neighbor, ordered by address
+    pub fn get_neighbor_stats(&self) -> Vec<NeighborStats> {
+        let mut stats: Vec<NeighborStats> = self.neighbors
+            .values()
+            .map(|n| NeighborStats {
+                neighbor: n.peer_ip(),
+                state: format!("{:?}", n.state()),
+                prefixes_received: n.prefixes_received(),
+                suppressed_routes: n.received_prefixes().filter(|p| self.is_suppressed(p)).count(),
+                prefix_limit: n.config().prefix_limit.as_ref().map(|l| l.max_prefixes),
+                prefix_limit_tripped: n.is_prefix_limit_tripped(),
+            })
+            .collect();
+
+        stats.sort_by_key(|s| s.neighbor);
+        stats
+    }
+
+    /// Whether dampening currently suppresses `prefix`
+    fn is_suppressed(&self, prefix: &IpNetwork) -> bool {
+        self.dampening.as_ref().is_some_and(|d| d.is_suppressed(prefix))
+    }
+
+    /// Apply session state and family activation of a neighbor
+    fn admit_route(&mut self, from: IpAddr, family: AddressFamily, now: Instant) -> Result<bool> {
+        let neighbor = self.neighbors.get(&from)
+            .ok_or_else(|| BgpError::ConfigurationError(format!("Unknown neighbor {}", from)))?;
+
+        if neighbor.is_prefix_limit_tripped() {
+            if neighbor.restart_at().is_some_and(|at| now >= at) {
+                self.restart_neighbor(from);
+            } else {
+                return Ok(false);
+            }
+        }
+
+        Ok(self.neighbors[&from].is_family_active(family))
+    }
+
+    /// Keep the unfiltered copy of a route for a later soft reset
+    fn store_received(&mut self, from: IpAddr, prefix: IpNetwork, route: ReceivedRoute) {
+        if let Some(neighbor) = self.neighbors.get_mut(&from) {
+            neighbor.store_received(prefix, route);
+        }
+    }
+
+    /// Apply the inbound route map of a neighbor
+    ///
+    /// A denied route replaces whatever the neighbor advertised for the
+    /// prefix before, so that earlier route is withdrawn.
+    fn apply_route_map_in<R: RouteAttributes>(
+        &mut self,
+        from: IpAddr,
+        prefix: IpNetwork,
+        route: &mut R,
+    ) -> Result<bool> {
+        let Some(map_name) = &self.neighbors[&from].config().route_map_in else {
+            return Ok(true);
+        };
+
+        let route_map = self
+            .config
+            .route_maps
+            .iter()
+            .find(|m| &m.name == map_name)
+            .ok_or_else(|| {
+                BgpError::ConfigurationError(format!("Unknown route map {}", map_name))
+            })?;
+
+        if route_map.apply(route) == RouteMapAction::Permit {
+            return Ok(true);
+        }
+
+        if let Some(next_hop) = self.neighbors.get_mut(&from).and_then(|n| n.withdraw_prefix(&prefix)) {
+            self.remove_route_entry(prefix, next_hop);
+        }
+        Ok(false)
+    }
+
+    /// Attach the running session of a neighbor
+    ///
+    /// Lets an inbound route map change ask the peer for a route refresh.
+    pub fn attach_session(&mut self, neighbor: IpAddr, session: Arc<BgpFsm>) -> Result<()> {
+        if !self.neighbors.contains_key(&neighbor) {
+            return Err(BgpError::ConfigurationError(format!("Unknown neighbor {}", neighbor)));
+        }
+        self.sessions.insert(neighbor, session);
+        Ok(())
+    }
+
+    /// Replace the inbound route map of a neighbor and re-evaluate its routes
+    ///
+    /// Sends a ROUTE-REFRESH (RFC 2918) when the neighbor's session is
+    /// established and the peer negotiated the capability. Otherwise the new
+    /// route map is applied to the routes already received, so the session
+    /// never has to be reset.
+    pub async fn set_route_map_in(
+        &mut self,
+        neighbor: IpAddr,
+        route_map: Option<String>,
+    ) -> Result<InboundRefresh> {
+        if !self.neighbors.contains_key(&neighbor) {
+            return Err(BgpError::ConfigurationError(format!("Unknown neighbor {}", neighbor)));
+        }
+        if let Some(name) = &route_map {
+            if !self.config.route_maps.iter().any(|m| &m.name == name) {
+                return Err(BgpError::ConfigurationError(format!("Unknown route map {}", name)));
+            }
+        }
+
+        if let Some(config) = self.config.neighbors.iter_mut().find(|n| n.ip == neighbor) {
+            config.route_map_in = route_map.clone();
+        }
+        if let Some(n) = self.neighbors.get_mut(&neighbor) {
+            n.set_route_map_in(route_map);
+        }
+
+        if let Some(session) = self.sessions.get(&neighbor).cloned() {
+            if session.state().await == NeighborState::Established
+                && session.peer_capabilities().await.route_refresh
+            {
+                session.request_route_refresh().await?;
+                info!("Requested route refresh from {} after inbound route map change", neighbor);
+                return Ok(InboundRefresh::RouteRefresh);
+            }
+        }
+
+        self.soft_reset_in(neighbor, Instant::now())?;
+        info!("Soft reset routes from {} after inbound route map change", neighbor);
+        Ok(InboundRefresh::SoftReset)
+    }
+
+    /// Re-apply the inbound route map to every route received from a neighbor
+    ///
+    /// Not a withdrawal, so dampening penalties are left alone.
+    fn soft_reset_in(&mut self, from: IpAddr, now: Instant) -> Result<()> {
+        let Some(neighbor) = self.neighbors.get_mut(&from) else {
+            return Ok(());
+        };
+        let received = neighbor.received_routes();
+        for (prefix, next_hop) in neighbor.clear_accepted() {
+            self.remove_route_entry(prefix, next_hop);
+        }
+
+        for route in received {
+            match route {
+                ReceivedRoute::V4(route) => self.learn_route_at(from, route, now)?,
+                ReceivedRoute::V6(route) => self.learn_route_v6_at(from, route, now)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Track a received prefix and enforce the neighbor's prefix limit
+    ///
+    /// Returns false if the limit was exceeded and the session torn down.
+    fn record_received(&mut self, from: IpAddr, prefix: IpNetwork, next_hop: IpAddr, now: Instant) -> bool {
+        let Some(neighbor) = self.neighbors.get_mut(&from) else {
+            return false;
+        };
+        neighbor.record_prefix(prefix, next_hop);
+
+        let Some(limit) = neighbor.config().prefix_limit.clone() else {
+            return true;
+        };
+        let prefixes = neighbor.prefixes_received();
+
+        if prefixes > limit.max_prefixes as usize {
+            warn!("Neighbor {} sent {} prefixes, exceeding limit of {}; tearing down session",
+                  from, prefixes, limit.max_prefixes);
+
+            let restart_at = limit.restart_interval().map(|d| now + d);
+            let received = neighbor.trip_prefix_limit(restart_at);
+            for (prefix, next_hop) in received {
+                self.remove_route_entry(prefix, next_hop);
+            }
+
+            self.emit(BgpManagerEvent::PrefixLimitExceeded {
+                neighbor: from,
+                prefixes,
+                limit: limit.max_prefixes,
+            });
+            return false;
+        }
+
+        if prefixes >= limit.warning_threshold() as usize && neighbor.raise_prefix_warning() {
+            warn!("Neighbor {} sent {} prefixes, approaching limit of {}",
+                  from, prefixes, limit.max_prefixes);
+            self.emit(BgpManagerEvent::PrefixLimitWarning {
+                neighbor: from,
+                prefixes,
+                limit: limit.max_prefixes,
+            });
+        }
+
+        true
+    }
+
+    /// Allow a neighbor torn down by its prefix limit to re-establish
+    fn restart_neighbor(&mut self, neighbor: IpAddr) {
+        if let Some(n) = self.neighbors.get_mut(&neighbor) {
+            info!("Restarting neighbor {} after prefix limit idle period", neighbor);
+            n.clear_prefix_limit();
+            self.emit(BgpManagerEvent::NeighborRestarted { neighbor });
+        }
+    }
+
+    /// Remove the route for `prefix` via `next_hop` from the routing table
+    fn remove_route_entry(&mut self, prefix: IpNetwork, next_hop: IpAddr) {
+        match prefix {
+            IpNetwork::V4(_) => self.routes
+                .retain(|r| r.to_ip_network() != prefix || r.next_hop_ip() != next_hop),
+            IpNetwork::V6(_) => self.routes_v6
+                .retain(|r| r.to_ip_network() != prefix || r.next_hop_ip() != next_hop),
+        }
+    }
+
+    fn emit(&self, event: BgpManagerEvent) {
+        // No subscribers is not an error
+        let _ = self.events.send(event);
+    }
+
     /// Get neighbors
     pub fn neighbors(&self) -> &HashMap<IpAddr, BgpNeighbor> {
         &self.neighbors
@@ -70,7 +562,13 @@ impl BgpManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{NeighborConfig, NetworkConfig, TimersConfig};
+    use crate::config::{
+        DampeningConfig, MatchCondition, NeighborConfig, PrefixLimitConfig, RouteMapConfig,
+        RouteMapRule, SetAction, TimersConfig,
+    };
+    use crate::fsm::FsmConfig;
+    use crate::messages::{Capability, OpenMessage};
+    use std::time::Duration;
     use std::str::FromStr;
 
     #[test]
@@ -87,10 +585,13 @@ mod tests {
                 route_map_in: None,
                 route_map_out: None,
                 next_hop_self: false,
+                address_families: vec![AddressFamily::Ipv4Unicast],
+                prefix_limit: None,
             }],
             networks: vec![],
             route_maps: vec![],
             timers: TimersConfig::default(),
+            dampening: None,
         };
 
         let manager = BgpManager::new(config);
@@ -99,4 +600,235 @@ mod tests {
         assert_eq!(manager.neighbors().len(), 1);
         assert_eq!(manager.routes().len(), 0);
     }
+
+    fn dual_stack_config() -> BgpConfig {
+        BgpConfig {
+            asn: 65001,
+            router_id: IpAddr::from_str("10.0.0.1").unwrap(),
+            neighbors: vec![
+                NeighborConfig {
+                    ip: IpAddr::from_str("10.0.0.2").unwrap(),
+                    asn: 65002,
+                    description: None,
+                    password: None,
+                    timers: None,
+                    route_map_in: Some("in".to_string()),
+                    route_map_out: None,
+                    next_hop_self: false,
+                    address_families: vec![AddressFamily::Ipv4Unicast, AddressFamily::Ipv6Unicast],
+                    prefix_limit: None,
+                },
+                NeighborConfig {
+                    ip: IpAddr::from_str("10.0.0.3").unwrap(),
+                    asn: 65003,
+                    description: None,
+                    password: None,
+                    timers: None,
+                    route_map_in: None,
+                    route_map_out: None,
+                    next_hop_self: false,
+                    address_families: vec![AddressFamily::Ipv4Unicast],
+                    prefix_limit: None,
+                },
+            ],
+            networks: vec![],
+            route_maps: vec![RouteMapConfig {
+                name: "in".to_string(),
+                rules: vec![RouteMapRule {
+                    sequence: 10,
+                    action: RouteMapAction::Permit,
+                    match_conditions: vec![MatchCondition::Prefix {
+                        prefix: "2001:db8::/32".parse().unwrap(),
+                    }],
+                    set_actions: vec![SetAction::LocalPreference { value: 300 }],
+                }],
+            }],
+            timers: TimersConfig::default(),
+            dampening: None,
+        }
+    }
+
+    #[test]
+    fn test_learn_routes_both_families() {
+        let mut manager = BgpManager::new(dual_stack_config());
+        let dual = IpAddr::from_str("10.0.0.2").unwrap();
+        let v4_only = IpAddr::from_str("10.0.0.3").unwrap();
+
+        let v6 = BgpRoute6::new("2001:db8:1::/48".parse().unwrap(), "2001:db8::2".parse().unwrap(), vec![65002]);
+        assert!(manager.learn_route_v6(dual, v6.clone()).unwrap());
+        assert_eq!(manager.routes_v6().len(), 1);
+        assert_eq!(manager.routes_v6()[0].local_pref, 300);
+
+        // IPv6 is not activated for this neighbor
+        assert!(!manager.learn_route_v6(v4_only, v6).unwrap());
+        assert_eq!(manager.routes_v6().len(), 1);
+
+        // The inbound route map only permits 2001:db8::/32
+        let v4 = BgpRoute::new("10.1.0.0/24".parse().unwrap(), "10.0.0.2".parse().unwrap(), vec![65002]);
+        assert!(!manager.learn_route(dual, v4.clone()).unwrap());
+        assert!(manager.learn_route(v4_only, v4).unwrap());
+        assert_eq!(manager.routes().len(), 1);
+
+        assert!(manager.learn_route(IpAddr::from_str("10.9.9.9").unwrap(),
+            BgpRoute::new("10.2.0.0/24".parse().unwrap(), "10.9.9.9".parse().unwrap(), vec![])).is_err());
+    }
+
+    #[test]
+    fn test_originated_prefixes_are_advertised() {
+        let mut manager = BgpManager::new(dual_stack_config());
+        let v4: IpNetwork = "10.50.0.0/16".parse().unwrap();
+        let v6: IpNetwork = "2001:db8:50::/48".parse().unwrap();
+
+        manager.originate(v4, IpAddr::from_str("169.254.10.1").unwrap()).unwrap();
+        manager.originate(v6, IpAddr::from_str("2001:db8::1").unwrap()).unwrap();
+        assert!(manager.originate(v4, IpAddr::from_str("2001:db8::1").unwrap()).is_err());
+        assert!(manager.learn_route(IpAddr::from_str("10.0.0.3").unwrap(), v4_route("10.1.0.0/24")).unwrap());
+
+        // Originating again moves the next hop
+        manager.originate(v4, IpAddr::from_str("10.2.0.1").unwrap()).unwrap();
+        let advertised = manager.advertised_routes();
+        assert_eq!(advertised.len(), 2);
+        assert_eq!(advertised[1].to_ip_network(), v4);
+        assert_eq!(advertised[1].next_hop_ip(), IpAddr::from_str("10.2.0.1").unwrap());
+        assert!(advertised[1].as_path.is_empty());
+        assert_eq!(manager.advertised_routes_v6()[0].to_ip_network(), v6);
+
+        assert!(manager.withdraw_originated(v4));
+        assert!(!manager.withdraw_originated(v4));
+        assert_eq!(manager.advertised_routes().len(), 1);
+        assert_eq!(manager.originated(&v6), Some(IpAddr::from_str("2001:db8::1").unwrap()));
+    }
+
+    fn v4_route(prefix: &str) -> BgpRoute {
+        BgpRoute::new(prefix.parse().unwrap(), "10.0.0.3".parse().unwrap(), vec![65003])
+    }
+
+    #[test]
+    fn test_prefix_limit_warns_tears_down_and_restarts() {
+        let mut config = dual_stack_config();
+        config.neighbors[1].prefix_limit = Some(PrefixLimitConfig {
+            max_prefixes: 4,
+            warning_threshold_pct: 75,
+            restart_interval_secs: Some(60),
+        });
+        let mut manager = BgpManager::new(config);
+        let mut events = manager.subscribe();
+        let peer = IpAddr::from_str("10.0.0.3").unwrap();
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert!(manager.learn_route_at(peer, v4_route(&format!("10.{}.0.0/24", i)), start).unwrap());
+        }
+        assert_eq!(
+            events.try_recv().unwrap(),
+            BgpManagerEvent::PrefixLimitWarning { neighbor: peer, prefixes: 3, limit: 4 }
+        );
+
+        assert!(manager.learn_route_at(peer, v4_route("10.3.0.0/24"), start).unwrap());
+        assert!(!manager.learn_route_at(peer, v4_route("10.4.0.0/24"), start).unwrap());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            BgpManagerEvent::PrefixLimitExceeded { neighbor: peer, prefixes: 5, limit: 4 }
+        );
+
+        // The session is down and its routes are gone
+        assert!(manager.routes().is_empty());
+        let stats = &manager.get_neighbor_stats()[1];
+        assert!(stats.prefix_limit_tripped);
+        assert_eq!(stats.prefixes_received, 0);
+        assert!(!manager.learn_route_at(peer, v4_route("10.0.0.0/24"), start + Duration::from_secs(30)).unwrap());
+
+        manager.process_timers_at(start + Duration::from_secs(60));
+        assert_eq!(events.try_recv().unwrap(), BgpManagerEvent::NeighborRestarted { neighbor: peer });
+        assert!(manager.learn_route_at(peer, v4_route("10.0.0.0/24"), start + Duration::from_secs(61)).unwrap());
+        assert_eq!(manager.get_neighbor_stats()[1].prefixes_received, 1);
+    }
+
+    #[test]
+    fn test_flapping_prefix_is_dampened() {
+        let mut config = dual_stack_config();
+        config.dampening = Some(DampeningConfig::default());
+        let mut manager = BgpManager::new(config);
+        let mut events = manager.subscribe();
+        let peer = IpAddr::from_str("10.0.0.3").unwrap();
+        let prefix: IpNetwork = "10.1.0.0/24".parse().unwrap();
+        let start = Instant::now();
+
+        // Advertise and withdraw three times, ten seconds apart
+        for i in 0..3 {
+            let t = start + Duration::from_secs(i * 10);
+            manager.learn_route_at(peer, v4_route("10.1.0.0/24"), t).unwrap();
+            assert!(manager.withdraw_route_at(peer, prefix, t + Duration::from_secs(1)).unwrap());
+        }
+        assert!(matches!(events.try_recv().unwrap(), BgpManagerEvent::RouteSuppressed { prefix: p, .. } if p == prefix));
+
+        // The route is back in the table but not advertised downstream
+        let readvertised = start + Duration::from_secs(30);
+        manager.learn_route_at(peer, v4_route("10.1.0.0/24"), readvertised).unwrap();
+        manager.learn_route_at(peer, v4_route("10.2.0.0/24"), readvertised).unwrap();
+        assert_eq!(manager.routes().len(), 2);
+        assert_eq!(manager.advertised_routes().len(), 1);
+        assert_eq!(manager.get_neighbor_stats()[1].suppressed_routes, 1);
+
+        // Penalty ~2977 needs just under two half-lives (30 min) to fall below 750
+        manager.process_timers_at(start + Duration::from_secs(21 + 1750));
+        assert!(events.try_recv().is_err());
+        assert_eq!(manager.advertised_routes().len(), 1);
+
+        manager.process_timers_at(start + Duration::from_secs(21 + 1800));
+        assert_eq!(events.try_recv().unwrap(), BgpManagerEvent::RouteReused { prefix });
+        assert_eq!(manager.advertised_routes().len(), 2);
+        assert_eq!(manager.get_neighbor_stats()[1].suppressed_routes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_inbound_route_map_change() {
+        let mut config = dual_stack_config();
+        config.route_maps.push(RouteMapConfig {
+            name: "only-10-1".to_string(),
+            rules: vec![RouteMapRule {
+                sequence: 10,
+                action: RouteMapAction::Permit,
+                match_conditions: vec![MatchCondition::Prefix {
+                    prefix: "10.1.0.0/16".parse().unwrap(),
+                }],
+                set_actions: vec![SetAction::LocalPreference { value: 200 }],
+            }],
+        });
+        let mut manager = BgpManager::new(config);
+        let peer = IpAddr::from_str("10.0.0.3").unwrap();
+        manager.learn_route(peer, v4_route("10.1.0.0/24")).unwrap();
+        manager.learn_route(peer, v4_route("10.2.0.0/24")).unwrap();
+
+        // Without a session the received routes are filtered again in place
+        let map = Some("only-10-1".to_string());
+        assert_eq!(manager.set_route_map_in(peer, map.clone()).await.unwrap(), InboundRefresh::SoftReset);
+        assert_eq!(manager.routes().len(), 1);
+        assert_eq!(manager.routes()[0].local_pref, 200);
+        assert!(manager.set_route_map_in(peer, Some("missing".to_string())).await.is_err());
+
+        assert_eq!(manager.set_route_map_in(peer, None).await.unwrap(), InboundRefresh::SoftReset);
+        assert_eq!(manager.routes().len(), 2);
+        assert_eq!(manager.get_neighbor_stats()[1].prefixes_received, 2);
+
+        // An established peer supporting Route Refresh is asked to re-send
+        let session = Arc::new(BgpFsm::new(FsmConfig::default()));
+        let open = OpenMessage::new(65003, 90, 0x0a000003);
+        session.negotiate_capabilities(&open.clone().with_capability(Capability::RouteRefresh)).await;
+        session.transition_to_established().await.unwrap();
+        manager.attach_session(peer, session.clone()).unwrap();
+
+        assert_eq!(manager.set_route_map_in(peer, map).await.unwrap(), InboundRefresh::RouteRefresh);
+        assert_eq!(manager.routes().len(), 2);
+
+        // Re-sent routes go through the new route map and a denied one replaces the old
+        assert!(manager.learn_route(peer, v4_route("10.1.0.0/24")).unwrap());
+        assert!(!manager.learn_route(peer, v4_route("10.2.0.0/24")).unwrap());
+        assert_eq!(manager.routes().len(), 1);
+
+        // A peer without the capability gets a soft reset instead
+        session.negotiate_capabilities(&open).await;
+        assert_eq!(manager.set_route_map_in(peer, None).await.unwrap(), InboundRefresh::SoftReset);
+        assert_eq!(manager.routes().len(), 2);
+    }
 }
@@ -1,7 +1,14 @@
 //! BGP neighbor management
 
-use crate::{config::NeighborConfig, error::Result};
+use crate::{
+    config::{AddressFamily, NeighborConfig},
+    error::Result,
+    route::{BgpRoute, BgpRoute6},
+};
+use ipnetwork::IpNetwork;
+use std::collections::HashMap;
 use std::net::IpAddr;
+use std::time::Instant;
 
 /// BGP neighbor state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -25,6 +32,13 @@ pub enum NeighborState {
     Established,
 }
 
+/// A route as received from a neighbor, before the inbound route map
+#[derive(Debug, Clone)]
+pub(crate) enum ReceivedRoute {
+    V4(BgpRoute),
+    V6(BgpRoute6),
+}
+
 /// BGP neighbor
 #[derive(Debug)]
 pub struct BgpNeighbor {
@@ -39,6 +53,22 @@ pub struct BgpNeighbor {
 
     /// Remote AS number
     remote_asn: u32,
+
+    /// Prefixes received from the neighbor and their next hops
+    adj_rib_in: HashMap<IpNetwork, IpAddr>,
+
+    /// Every route received from the neighbor, unfiltered, so a new inbound
+    /// route map can be applied without the peer re-sending them
+    received: HashMap<IpNetwork, ReceivedRoute>,
+
+    /// Prefix limit warning already raised for the current session
+    prefix_warning_raised: bool,
+
+    /// Session torn down by the prefix limit
+    prefix_limit_tripped: bool,
+
+    /// When a session torn down by the prefix limit may restart
+    restart_at: Option<Instant>,
 }
 
 impl BgpNeighbor {
@@ -52,6 +82,11 @@ impl BgpNeighbor {
             state: NeighborState::Idle,
             peer_ip,
             remote_asn,
+            adj_rib_in: HashMap::new(),
+            received: HashMap::new(),
+            prefix_warning_raised: false,
+            prefix_limit_tripped: false,
+            restart_at: None,
         }
     }
 
@@ -70,6 +105,101 @@ impl BgpNeighbor {
         self.remote_asn
     }
 
+    /// Address families activated for this neighbor
+    pub fn address_families(&self) -> &[AddressFamily] {
+        &self.config.address_families
+    }
+
+    /// Whether an address family is activated for this neighbor
+    pub fn is_family_active(&self, family: AddressFamily) -> bool {
+        self.config.is_family_active(family)
+    }
+
+    /// Neighbor configuration
+    pub fn config(&self) -> &NeighborConfig {
+        &self.config
+    }
+
+    /// Number of prefixes received from the neighbor
+    pub fn prefixes_received(&self) -> usize {
+        self.adj_rib_in.len()
+    }
+
+    /// Prefixes received from the neighbor
+    pub fn received_prefixes(&self) -> impl Iterator<Item = &IpNetwork> {
+        self.adj_rib_in.keys()
+    }
+
+    /// Whether the session is down because the prefix limit was exceeded
+    pub fn is_prefix_limit_tripped(&self) -> bool {
+        self.prefix_limit_tripped
+    }
+
+    /// When the session may automatically restart after a prefix limit teardown
+    pub fn restart_at(&self) -> Option<Instant> {
+        self.restart_at
+    }
+
+    /// Replace the inbound route map
+    pub(crate) fn set_route_map_in(&mut self, route_map: Option<String>) {
+        self.config.route_map_in = route_map;
+    }
+
+    /// Keep the unfiltered copy of a received route
+    pub(crate) fn store_received(&mut self, prefix: IpNetwork, route: ReceivedRoute) {
+        self.received.insert(prefix, route);
+    }
+
+    /// Drop the unfiltered copy of a withdrawn route
+    pub(crate) fn forget_received(&mut self, prefix: &IpNetwork) {
+        self.received.remove(prefix);
+    }
+
+    /// Unfiltered copies of every route received from the neighbor
+    pub(crate) fn received_routes(&self) -> Vec<ReceivedRoute> {
+        self.received.values().cloned().collect()
+    }
+
+    /// Forget the accepted prefixes ahead of re-applying the inbound route
+    /// map, returning them with their next hops
+    pub(crate) fn clear_accepted(&mut self) -> Vec<(IpNetwork, IpAddr)> {
+        self.prefix_warning_raised = false;
+        self.adj_rib_in.drain().collect()
+    }
+
+    /// Record a prefix received from the neighbor
+    pub(crate) fn record_prefix(&mut self, prefix: IpNetwork, next_hop: IpAddr) {
+        self.adj_rib_in.insert(prefix, next_hop);
+    }
+
+    /// Forget a withdrawn prefix, returning its next hop
+    pub(crate) fn withdraw_prefix(&mut self, prefix: &IpNetwork) -> Option<IpAddr> {
+        self.adj_rib_in.remove(prefix)
+    }
+
+    /// Mark the prefix limit warning as raised; returns false if it already was
+    pub(crate) fn raise_prefix_warning(&mut self) -> bool {
+        !std::mem::replace(&mut self.prefix_warning_raised, true)
+    }
+
+    /// Tear the session down after exceeding the prefix limit
+    ///
+    /// Returns the prefixes that were received, with their next hops.
+    pub(crate) fn trip_prefix_limit(&mut self, restart_at: Option<Instant>) -> Vec<(IpNetwork, IpAddr)> {
+        self.state = NeighborState::Idle;
+        self.prefix_limit_tripped = true;
+        self.prefix_warning_raised = false;
+        self.restart_at = restart_at;
+        self.received.clear();
+        self.adj_rib_in.drain().collect()
+    }
+
+    /// Clear a prefix limit teardown so the session can be re-established
+    pub fn clear_prefix_limit(&mut self) {
+        self.prefix_limit_tripped = false;
+        self.restart_at = None;
+    }
+
     /// Connect to neighbor (stub implementation)
     pub async fn connect(&mut self) -> Result<()> {
         // Stub: would establish TCP connection and send OPEN message
@@ -101,6 +231,8 @@ mod tests {
             route_map_in: None,
             route_map_out: None,
             next_hop_self: false,
+            address_families: vec![AddressFamily::Ipv4Unicast],
+            prefix_limit: None,
         };
 
         let neighbor = BgpNeighbor::new(config);
@@ -108,5 +240,7 @@ mod tests {
         assert_eq!(neighbor.state(), NeighborState::Idle);
         assert_eq!(neighbor.peer_ip(), IpAddr::from_str("10.0.0.1").unwrap());
         assert_eq!(neighbor.remote_asn(), 65001);
+        assert!(neighbor.is_family_active(AddressFamily::Ipv4Unicast));
+        assert!(!neighbor.is_family_active(AddressFamily::Ipv6Unicast));
     }
 }
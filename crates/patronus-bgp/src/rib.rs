@@ -2,10 +2,10 @@
 //!
 //! Manages learned routes and performs best path selection.
 
-use crate::route::BgpRoute;
-use ipnetwork::Ipv4Network;
-use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use crate::route::{BgpRoute, BgpRoute6, RouteAttributes};
+use ipnetwork::{Ipv4Network, Ipv6Network};
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::sync::{Arc, RwLock};
 use tracing::{debug, info};
 
@@ -17,6 +17,15 @@ pub struct Rib {
     /// Best routes (after path selection)
     best_routes: Arc<RwLock<HashMap<Ipv4Network, BgpRoute>>>,
 
+    /// Routes retained as stale during a peer's graceful restart
+    stale: Arc<RwLock<HashSet<(Ipv4Network, Ipv4Addr)>>>,
+
+    /// IPv6 routes indexed by prefix
+    routes_v6: Arc<RwLock<HashMap<Ipv6Network, Vec<BgpRoute6>>>>,
+
+    /// IPv6 best routes (after path selection)
+    best_routes_v6: Arc<RwLock<HashMap<Ipv6Network, BgpRoute6>>>,
+
     /// Local AS number
     local_asn: u16,
 }
@@ -27,6 +36,9 @@ impl Rib {
         Self {
             routes: Arc::new(RwLock::new(HashMap::new())),
             best_routes: Arc::new(RwLock::new(HashMap::new())),
+            stale: Arc::new(RwLock::new(HashSet::new())),
+            routes_v6: Arc::new(RwLock::new(HashMap::new())),
+            best_routes_v6: Arc::new(RwLock::new(HashMap::new())),
             local_asn,
         }
     }
@@ -47,6 +59,9 @@ impl Rib {
             prefix_routes.push(route.clone());
         }
 
+        // A re-advertised route is no longer stale
+        self.stale.write().unwrap().remove(&(prefix, route.next_hop));
+
         // Re-run best path selection for this prefix
         drop(routes);
         self.select_best_path(prefix);
@@ -58,6 +73,8 @@ impl Rib {
     pub fn remove_route(&self, prefix: Ipv4Network, next_hop: Ipv4Addr) -> Result<(), String> {
         debug!("Removing route from RIB: {} via {}", prefix, next_hop);
 
+        self.stale.write().unwrap().remove(&(prefix, next_hop));
+
         let mut routes = self.routes.write().unwrap();
 
         if let Some(prefix_routes) = routes.get_mut(&prefix) {
@@ -86,48 +103,133 @@ impl Rib {
                 return;
             }
 
-            // BGP best path selection algorithm (simplified)
-            // 1. Prefer route with highest local preference
-            // 2. Prefer route with shortest AS path
-            // 3. Prefer route with lowest origin type (IGP < EGP < Incomplete)
-            // 4. Prefer route with lowest MED
-            // 5. Prefer eBGP over iBGP
-            // 6. Prefer route with lowest IGP cost to next hop
-            // 7. Prefer route from router with lowest router ID
-
-            let mut best = prefix_routes[0].clone();
-
-            for route in prefix_routes.iter().skip(1) {
-                // Step 1: Local preference (higher is better)
-                if route.local_pref > best.local_pref {
-                    best = route.clone();
-                    continue;
-                } else if route.local_pref < best.local_pref {
-                    continue;
-                }
+            let best = best_path(prefix_routes);
 
-                // Step 2: AS path length (shorter is better)
-                if route.as_path.len() < best.as_path.len() {
-                    best = route.clone();
-                    continue;
-                } else if route.as_path.len() > best.as_path.len() {
-                    continue;
-                }
+            info!("Selected best path for {}: via {} (AS path len: {})",
+                  prefix, best.next_hop, best.as_path.len());
 
-                // Step 4: MED (lower is better)
-                if route.med < best.med {
-                    best = route.clone();
-                    continue;
-                }
+            self.best_routes.write().unwrap().insert(prefix, best);
+        }
+    }
+
+    /// Add or update an IPv6 route
+    pub fn add_route_v6(&self, route: BgpRoute6) -> Result<(), String> {
+        let prefix = route.prefix;
+
+        debug!("Adding IPv6 route to RIB: {} via {}", prefix, route.next_hop);
+
+        let mut routes = self.routes_v6.write().unwrap();
+        let prefix_routes = routes.entry(prefix).or_default();
+
+        if let Some(existing) = prefix_routes.iter_mut().find(|r| r.next_hop == route.next_hop) {
+            *existing = route;
+        } else {
+            prefix_routes.push(route);
+        }
+
+        drop(routes);
+        self.select_best_path_v6(prefix);
+
+        Ok(())
+    }
+
+    /// Remove an IPv6 route
+    pub fn remove_route_v6(&self, prefix: Ipv6Network, next_hop: Ipv6Addr) -> Result<(), String> {
+        debug!("Removing IPv6 route from RIB: {} via {}", prefix, next_hop);
+
+        let mut routes = self.routes_v6.write().unwrap();
+
+        if let Some(prefix_routes) = routes.get_mut(&prefix) {
+            prefix_routes.retain(|r| r.next_hop != next_hop);
+
+            if prefix_routes.is_empty() {
+                routes.remove(&prefix);
+                self.best_routes_v6.write().unwrap().remove(&prefix);
+                info!("Removed last route for prefix {}", prefix);
+            } else {
+                drop(routes);
+                self.select_best_path_v6(prefix);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select best IPv6 path for a prefix
+    fn select_best_path_v6(&self, prefix: Ipv6Network) {
+        let routes = self.routes_v6.read().unwrap();
+
+        if let Some(prefix_routes) = routes.get(&prefix) {
+            if prefix_routes.is_empty() {
+                return;
             }
 
+            let best = best_path(prefix_routes);
+
             info!("Selected best path for {}: via {} (AS path len: {})",
                   prefix, best.next_hop, best.as_path.len());
 
-            self.best_routes.write().unwrap().insert(prefix, best);
+            self.best_routes_v6.write().unwrap().insert(prefix, best);
         }
     }
 
+    /// Get best IPv6 route for a prefix
+    pub fn get_best_route_v6(&self, prefix: &Ipv6Network) -> Option<BgpRoute6> {
+        self.best_routes_v6.read().unwrap().get(prefix).cloned()
+    }
+
+    /// Get all IPv6 routes for a prefix
+    pub fn get_routes_v6(&self, prefix: &Ipv6Network) -> Vec<BgpRoute6> {
+        self.routes_v6.read().unwrap()
+            .get(prefix)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get all IPv6 best routes
+    pub fn get_all_best_routes_v6(&self) -> Vec<BgpRoute6> {
+        self.best_routes_v6.read().unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// IPv6 longest prefix match lookup
+    pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<BgpRoute6> {
+        self.best_routes_v6.read().unwrap()
+            .iter()
+            .filter(|(prefix, _)| prefix.contains(ip))
+            .max_by_key(|(prefix, _)| prefix.prefix())
+            .map(|(_, route)| route.clone())
+    }
+
+    /// Remove every IPv6 route learned via `next_hop`
+    pub fn remove_routes_via_v6(&self, next_hop: Ipv6Addr) -> usize {
+        let prefixes: Vec<Ipv6Network> = self.routes_v6.read().unwrap()
+            .iter()
+            .filter(|(_, routes)| routes.iter().any(|r| r.next_hop == next_hop))
+            .map(|(prefix, _)| *prefix)
+            .collect();
+
+        for prefix in &prefixes {
+            let _ = self.remove_route_v6(*prefix, next_hop);
+        }
+        prefixes.len()
+    }
+
+    /// Get total number of IPv6 routes
+    pub fn route_count_v6(&self) -> usize {
+        self.routes_v6.read().unwrap()
+            .values()
+            .map(|v| v.len())
+            .sum()
+    }
+
+    /// Get number of unique IPv6 prefixes
+    pub fn prefix_count_v6(&self) -> usize {
+        self.routes_v6.read().unwrap().len()
+    }
+
     /// Get best route for a prefix
     pub fn get_best_route(&self, prefix: &Ipv4Network) -> Option<BgpRoute> {
         self.best_routes.read().unwrap().get(prefix).cloned()
@@ -184,14 +286,118 @@ impl Rib {
         self.routes.read().unwrap().len()
     }
 
+    /// Mark every route learned via `next_hop` as stale (RFC 4724 helper mode)
+    ///
+    /// Stale routes stay installed until they are re-advertised or flushed.
+    pub fn mark_stale(&self, next_hop: Ipv4Addr) -> usize {
+        let routes = self.routes.read().unwrap();
+        let mut stale = self.stale.write().unwrap();
+
+        let mut count = 0;
+        for (prefix, prefix_routes) in routes.iter() {
+            for route in prefix_routes.iter().filter(|r| r.next_hop == next_hop) {
+                if stale.insert((*prefix, route.next_hop)) {
+                    count += 1;
+                }
+            }
+        }
+
+        info!("Marked {} routes via {} as stale", count, next_hop);
+        count
+    }
+
+    /// Remove all routes via `next_hop` that are still marked stale
+    pub fn flush_stale(&self, next_hop: Ipv4Addr) -> usize {
+        let keys: Vec<_> = self.stale.read().unwrap()
+            .iter()
+            .filter(|(_, nh)| *nh == next_hop)
+            .copied()
+            .collect();
+
+        for (prefix, nh) in &keys {
+            let _ = self.remove_route(*prefix, *nh);
+        }
+
+        if !keys.is_empty() {
+            info!("Flushed {} stale routes via {}", keys.len(), next_hop);
+        }
+        keys.len()
+    }
+
+    /// Remove every route learned via `next_hop`
+    pub fn remove_routes_via(&self, next_hop: Ipv4Addr) -> usize {
+        let prefixes: Vec<_> = self.routes.read().unwrap()
+            .iter()
+            .filter(|(_, routes)| routes.iter().any(|r| r.next_hop == next_hop))
+            .map(|(prefix, _)| *prefix)
+            .collect();
+
+        for prefix in &prefixes {
+            let _ = self.remove_route(*prefix, next_hop);
+        }
+        prefixes.len()
+    }
+
+    /// Whether a route is currently stale
+    pub fn is_stale(&self, prefix: &Ipv4Network, next_hop: Ipv4Addr) -> bool {
+        self.stale.read().unwrap().contains(&(*prefix, next_hop))
+    }
+
+    /// Number of stale routes
+    pub fn stale_count(&self) -> usize {
+        self.stale.read().unwrap().len()
+    }
+
     /// Clear all routes
     pub fn clear(&self) {
         self.routes.write().unwrap().clear();
         self.best_routes.write().unwrap().clear();
+        self.stale.write().unwrap().clear();
+        self.routes_v6.write().unwrap().clear();
+        self.best_routes_v6.write().unwrap().clear();
         info!("Cleared all routes from RIB");
     }
 }
 
+/// BGP best path selection (simplified), shared by all address families
+///
+/// 1. Prefer route with highest local preference
+/// 2. Prefer route with shortest AS path
+/// 3. Prefer route with lowest origin type (IGP < EGP < Incomplete)
+/// 4. Prefer route with lowest MED
+/// 5. Prefer eBGP over iBGP
+/// 6. Prefer route with lowest IGP cost to next hop
+/// 7. Prefer route from router with lowest router ID
+fn best_path<R: RouteAttributes + Clone>(routes: &[R]) -> R {
+    let mut best = &routes[0];
+
+    for route in routes.iter().skip(1) {
+        // Step 1: Local preference (higher is better)
+        if route.local_pref() > best.local_pref() {
+            best = route;
+            continue;
+        } else if route.local_pref() < best.local_pref() {
+            continue;
+        }
+
+        // Step 2: AS path length (shorter is better)
+        if route.as_path().len() < best.as_path().len() {
+            best = route;
+            continue;
+        } else if route.as_path().len() > best.as_path().len() {
+            continue;
+        }
+
+        // Step 4: MED (lower is better)
+        if route.med() < best.med() {
+            best = route;
+            continue;
+        }
+    }
+
+    best.clone()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,4 +533,65 @@ mod tests {
         assert_eq!(rib.route_count(), 0);
         assert_eq!(rib.prefix_count(), 0);
     }
+
+    #[test]
+    fn test_rib_stale_routes() {
+        let rib = Rib::new(65000);
+        let peer = Ipv4Addr::from_str("192.168.1.1").unwrap();
+
+        rib.add_route(create_test_route("10.0.0.0/24", "192.168.1.1", vec![65001])).unwrap();
+        rib.add_route(create_test_route("10.1.0.0/24", "192.168.1.1", vec![65001])).unwrap();
+        rib.add_route(create_test_route("10.2.0.0/24", "192.168.1.2", vec![65002])).unwrap();
+
+        assert_eq!(rib.mark_stale(peer), 2);
+        assert_eq!(rib.stale_count(), 2);
+
+        // Stale routes remain usable for forwarding
+        let prefix = Ipv4Network::from_str("10.0.0.0/24").unwrap();
+        assert!(rib.get_best_route(&prefix).is_some());
+
+        // Re-advertising clears the stale flag
+        rib.add_route(create_test_route("10.0.0.0/24", "192.168.1.1", vec![65001])).unwrap();
+        assert!(!rib.is_stale(&prefix, peer));
+
+        assert_eq!(rib.flush_stale(peer), 1);
+        assert_eq!(rib.route_count(), 2);
+        assert_eq!(rib.stale_count(), 0);
+        assert!(rib.get_best_route(&Ipv4Network::from_str("10.1.0.0/24").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_rib_ipv6_routes() {
+        let rib = Rib::new(65000);
+
+        let route1 = BgpRoute6::new(
+            Ipv6Network::from_str("2001:db8:1::/48").unwrap(),
+            Ipv6Addr::from_str("2001:db8::1").unwrap(),
+            vec![65001, 65002],
+        )
+        .with_link_local_next_hop(Ipv6Addr::from_str("fe80::1").unwrap());
+        let route2 = BgpRoute6::new(
+            Ipv6Network::from_str("2001:db8:1::/48").unwrap(),
+            Ipv6Addr::from_str("2001:db8::2").unwrap(),
+            vec![65003],
+        );
+
+        rib.add_route_v6(route1).unwrap();
+        rib.add_route_v6(route2).unwrap();
+
+        // IPv6 routes live in their own table
+        assert_eq!(rib.route_count(), 0);
+        assert_eq!(rib.route_count_v6(), 2);
+        assert_eq!(rib.prefix_count_v6(), 1);
+
+        let best = rib.lookup_v6(Ipv6Addr::from_str("2001:db8:1::42").unwrap()).unwrap();
+        assert_eq!(best.next_hop, Ipv6Addr::from_str("2001:db8::2").unwrap());
+
+        rib.remove_route_v6(best.prefix, best.next_hop).unwrap();
+        let best = rib.get_best_route_v6(&Ipv6Network::from_str("2001:db8:1::/48").unwrap()).unwrap();
+        assert_eq!(best.forwarding_next_hop(), Ipv6Addr::from_str("fe80::1").unwrap());
+
+        assert_eq!(rib.remove_routes_via_v6(Ipv6Addr::from_str("2001:db8::1").unwrap()), 1);
+        assert!(rib.get_all_best_routes_v6().is_empty());
+    }
 }
@@ -1,8 +1,8 @@
 //! BGP route types
 
-use ipnetwork::{IpNetwork, Ipv4Network};
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 /// BGP route
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +29,69 @@ pub struct BgpRoute {
     pub origin: u8,
 }
 
+/// BGP IPv6 unicast route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BgpRoute6 {
+    /// Destination prefix
+    pub prefix: Ipv6Network,
+
+    /// Global next hop
+    pub next_hop: Ipv6Addr,
+
+    /// Link-local next hop, when the peer is on a shared link
+    pub link_local_next_hop: Option<Ipv6Addr>,
+
+    /// AS path (u16 for ASN)
+    pub as_path: Vec<u16>,
+
+    /// Local preference (default 100)
+    pub local_pref: u32,
+
+    /// MED (metric) (default 0)
+    pub med: u32,
+
+    /// Communities
+    pub communities: Vec<String>,
+
+    /// Origin (0=IGP, 1=EGP, 2=Incomplete)
+    pub origin: u8,
+}
+
+/// Attributes shared by routes of every address family
+///
+/// Used by route maps and best path selection so both work for IPv4 and IPv6.
+pub trait RouteAttributes {
+    /// Destination prefix
+    fn network(&self) -> IpNetwork;
+
+    /// AS path
+    fn as_path(&self) -> &[u16];
+
+    /// Communities
+    fn communities(&self) -> &[String];
+
+    /// Local preference
+    fn local_pref(&self) -> u32;
+
+    /// MED
+    fn med(&self) -> u32;
+
+    /// Set local preference
+    fn set_local_pref(&mut self, value: u32);
+
+    /// Set MED
+    fn set_med(&mut self, value: u32);
+
+    /// Add a community
+    fn add_community(&mut self, community: String);
+
+    /// Prepend an ASN to the AS path `count` times
+    fn prepend_as(&mut self, asn: u16, count: u8);
+
+    /// Set the next hop; returns false if the address is of the wrong family
+    fn set_next_hop(&mut self, next_hop: IpAddr) -> bool;
+}
+
 /// Route origin
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -115,6 +178,176 @@ impl BgpRoute {
     }
 }
 
+impl BgpRoute6 {
+    /// Create a new IPv6 BGP route
+    pub fn new(prefix: Ipv6Network, next_hop: Ipv6Addr, as_path: Vec<u16>) -> Self {
+        Self {
+            prefix,
+            next_hop,
+            link_local_next_hop: None,
+            as_path,
+            local_pref: 100,
+            med: 0,
+            communities: Vec::new(),
+            origin: 2, // Incomplete
+        }
+    }
+
+    /// Set link-local next hop
+    pub fn with_link_local_next_hop(mut self, next_hop: Ipv6Addr) -> Self {
+        self.link_local_next_hop = Some(next_hop);
+        self
+    }
+
+    /// Set local preference
+    pub fn with_local_pref(mut self, pref: u32) -> Self {
+        self.local_pref = pref;
+        self
+    }
+
+    /// Set MED
+    pub fn with_med(mut self, med: u32) -> Self {
+        self.med = med;
+        self
+    }
+
+    /// Add community
+    pub fn with_community(mut self, community: String) -> Self {
+        self.communities.push(community);
+        self
+    }
+
+    /// Set origin (0=IGP, 1=EGP, 2=Incomplete)
+    pub fn with_origin(mut self, origin: u8) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Convert to generic IpNetwork
+    pub fn to_ip_network(&self) -> IpNetwork {
+        IpNetwork::V6(self.prefix)
+    }
+
+    /// Get global next hop as generic IpAddr
+    pub fn next_hop_ip(&self) -> IpAddr {
+        IpAddr::V6(self.next_hop)
+    }
+
+    /// Next hop to install in the forwarding table
+    ///
+    /// The link-local address is preferred when present, as it is the
+    /// address the peer is directly reachable on.
+    pub fn forwarding_next_hop(&self) -> Ipv6Addr {
+        self.link_local_next_hop.unwrap_or(self.next_hop)
+    }
+}
+
+impl RouteAttributes for BgpRoute {
+    fn network(&self) -> IpNetwork {
+        self.to_ip_network()
+    }
+
+    fn as_path(&self) -> &[u16] {
+        &self.as_path
+    }
+
+    fn communities(&self) -> &[String] {
+        &self.communities
+    }
+
+    fn local_pref(&self) -> u32 {
+        self.local_pref
+    }
+
+    fn med(&self) -> u32 {
+        self.med
+    }
+
+    fn set_local_pref(&mut self, value: u32) {
+        self.local_pref = value;
+    }
+
+    fn set_med(&mut self, value: u32) {
+        self.med = value;
+    }
+
+    fn add_community(&mut self, community: String) {
+        if !self.communities.contains(&community) {
+            self.communities.push(community);
+        }
+    }
+
+    fn prepend_as(&mut self, asn: u16, count: u8) {
+        for _ in 0..count {
+            self.as_path.insert(0, asn);
+        }
+    }
+
+    fn set_next_hop(&mut self, next_hop: IpAddr) -> bool {
+        match next_hop {
+            IpAddr::V4(addr) => {
+                self.next_hop = addr;
+                true
+            }
+            IpAddr::V6(_) => false,
+        }
+    }
+}
+
+impl RouteAttributes for BgpRoute6 {
+    fn network(&self) -> IpNetwork {
+        self.to_ip_network()
+    }
+
+    fn as_path(&self) -> &[u16] {
+        &self.as_path
+    }
+
+    fn communities(&self) -> &[String] {
+        &self.communities
+    }
+
+    fn local_pref(&self) -> u32 {
+        self.local_pref
+    }
+
+    fn med(&self) -> u32 {
+        self.med
+    }
+
+    fn set_local_pref(&mut self, value: u32) {
+        self.local_pref = value;
+    }
+
+    fn set_med(&mut self, value: u32) {
+        self.med = value;
+    }
+
+    fn add_community(&mut self, community: String) {
+        if !self.communities.contains(&community) {
+            self.communities.push(community);
+        }
+    }
+
+    fn prepend_as(&mut self, asn: u16, count: u8) {
+        for _ in 0..count {
+            self.as_path.insert(0, asn);
+        }
+    }
+
+    fn set_next_hop(&mut self, next_hop: IpAddr) -> bool {
+        match next_hop {
+            IpAddr::V6(addr) => {
+                self.next_hop = addr;
+                // An explicitly set next hop replaces the learned link-local one
+                self.link_local_next_hop = None;
+                true
+            }
+            IpAddr::V4(_) => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,4 +385,21 @@ mod tests {
         assert_eq!(route.communities, vec!["65001:100"]);
         assert_eq!(route.origin, 0);
     }
+
+    #[test]
+    fn test_route6_next_hops() {
+        let route = BgpRoute6::new(
+            Ipv6Network::from_str("2001:db8:1::/48").unwrap(),
+            Ipv6Addr::from_str("2001:db8::1").unwrap(),
+            vec![65001],
+        );
+        assert_eq!(route.forwarding_next_hop(), route.next_hop);
+
+        let mut route = route.with_link_local_next_hop(Ipv6Addr::from_str("fe80::1").unwrap());
+        assert_eq!(route.forwarding_next_hop(), Ipv6Addr::from_str("fe80::1").unwrap());
+
+        assert!(!route.set_next_hop(IpAddr::from_str("10.0.0.1").unwrap()));
+        assert!(route.set_next_hop(IpAddr::from_str("2001:db8::2").unwrap()));
+        assert_eq!(route.forwarding_next_hop(), Ipv6Addr::from_str("2001:db8::2").unwrap());
+    }
 }
@@ -11,6 +11,8 @@ pub enum AuthMethod {
     SMS,
     Facebook,
     Google,
+    /// Any OAuth2 provider in `PortalConfig::oauth_providers`
+    OAuth2,
     RADIUS,
     LDAP,
     FreeAccess,  // No authentication, just click-through
@@ -36,6 +38,22 @@ pub struct AuthResult {
     pub success: bool,
     pub user_id: String,
     pub user_info: UserInfo,
+    /// Limits the provider wants applied to the guest's session
+    #[serde(default)]
+    pub session: SessionAttributes,
+}
+
+/// Per-session limits returned by an authentication backend
+///
+/// Unset values fall back to the portal configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionAttributes {
+    pub session_timeout_secs: Option<u32>,
+    pub idle_timeout_secs: Option<u32>,
+    pub download_limit_kbps: Option<u64>,
+    pub upload_limit_kbps: Option<u64>,
+    /// Message from the backend to show the guest
+    pub reply_message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,48 +69,12 @@ pub enum AuthError {
     InvalidCredentials,
     #[error("Authentication failed: {0}")]
     Failed(String),
+    #[error("Access rejected: {0}")]
+    Rejected(String),
     #[error("Provider unavailable")]
     Unavailable,
 }
 
-// RADIUS authentication provider
-pub struct RadiusAuthProvider {
-    server: String,
-    secret: String,
-    timeout_secs: u64,
-}
-
-impl RadiusAuthProvider {
-    pub fn new(server: String, secret: String) -> Self {
-        Self {
-            server,
-            secret,
-            timeout_secs: 5,
-        }
-    }
-}
-
-#[async_trait]
-impl AuthProvider for RadiusAuthProvider {
-    async fn authenticate(&self, credentials: &AuthCredentials) -> Result<AuthResult, AuthError> {
-        // Implement RADIUS authentication
-        // Would use radius crate in production
-        Ok(AuthResult {
-            success: true,
-            user_id: credentials.username.clone().unwrap_or_default(),
-            user_info: UserInfo {
-                name: credentials.username.clone(),
-                email: None,
-                groups: vec![],
-            },
-        })
-    }
-
-    fn name(&self) -> &str {
-        "RADIUS"
-    }
-}
-
 // Local username/password provider
 pub struct LocalAuthProvider {
     users: std::collections::HashMap<String, String>,  // username -> password hash
@@ -129,6 +111,7 @@ impl AuthProvider for LocalAuthProvider {
                         email: None,
                         groups: vec!["guests".to_string()],
                     },
+                    session: SessionAttributes::default(),
                 });
             }
         }
@@ -1,54 +1,597 @@
 //! Bandwidth limiting for guest users
+//!
+//! Each limited client gets an HTB class on the portal interface for its
+//! download direction and an ingress policer for its upload direction, both
+//! matched on the client's IP address. The byte counters of those tc objects
+//! also measure usage against the session's data cap.
+//!
+//! Clients can belong to a [`BandwidthGroup`] sharing an aggregate cap: the
+//! group is an HTB class the clients' classes hang under for download, and
+//! a shared policer every client's ingress filter runs through for upload.
+//! Limits of clients and groups change in place, without losing counters
+//! or interrupting traffic.
 
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
 use tokio::process::Command;
+use tokio::sync::Mutex;
 
-pub struct BandwidthLimiter {
-    limits: tokio::sync::RwLock<HashMap<String, BandwidthLimit>>,
+/// Rate standing in for "unlimited" when a client only has a data cap
+const UNLIMITED_KBPS: u64 = 10_000_000;
+
+/// First and last HTB class minor handed to clients
+const FIRST_CLASS: u16 = 0x10;
+const LAST_CLASS: u16 = 0xfff0;
+
+/// HTB class minors of groups, which also index their upload policers
+const FIRST_GROUP_CLASS: u16 = 0x1;
+const LAST_GROUP_CLASS: u16 = 0xf;
+
+/// Rate guaranteed to a group member; members share the rest evenly
+const MEMBER_RATE_KBPS: u64 = 64;
+
+/// Runs traffic-control commands
+#[async_trait]
+pub trait CommandRunner: Send + Sync {
+    /// Run `program` with `args`, returning its stdout; a non-zero exit is
+    /// an error
+    async fn run(&self, program: &str, args: &[String]) -> io::Result<String>;
+}
+
+/// Runs commands on the host
+pub struct SystemCommandRunner;
+
+#[async_trait]
+impl CommandRunner for SystemCommandRunner {
+    async fn run(&self, program: &str, args: &[String]) -> io::Result<String> {
+        let output = Command::new(program).args(args).output().await?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{} {} failed: {}",
+                program,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct BandwidthLimit {
-    pub download_kbps: u64,
-    pub upload_kbps: u64,
+    pub download_kbps: Option<u64>,
+    pub upload_kbps: Option<u64>,
+    /// Bytes in both directions after which the session is disconnected
+    pub data_cap_bytes: Option<u64>,
+    /// [`BandwidthGroup`] whose aggregate cap the client shares
+    pub group: Option<String>,
+    /// Count the client's traffic even without any limit, e.g. to tell
+    /// when it goes idle
+    pub track_usage: bool,
+}
+
+impl BandwidthLimit {
+    pub fn is_unlimited(&self) -> bool {
+        self.download_kbps.is_none() && self.upload_kbps.is_none() && self.data_cap_bytes.is_none() && self.group.is_none()
+            && !self.track_usage
+    }
+}
+
+/// An aggregate cap shared by its clients, e.g. all guests sharing 50 Mbps
+/// while staff get 200 Mbps
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthGroup {
+    pub name: String,
+    pub download_kbps: Option<u64>,
+    pub upload_kbps: Option<u64>,
+}
+
+/// Throttling of sessions that used more than their fair share
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FairUsePolicy {
+    /// Megabytes in both directions after which a session is throttled
+    pub quota_mb: u64,
+    pub throttled_download_kbps: u64,
+    pub throttled_upload_kbps: u64,
+}
+
+/// Bytes a limited client has moved since its limit was installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientUsage {
+    pub ip: IpAddr,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    /// Past the client's data cap
+    pub exhausted: bool,
+}
+
+struct InstalledLimit {
+    limit: BandwidthLimit,
+    class: u16,
+    group_class: Option<u16>,
+    /// Upload bytes counted by filters since replaced
+    upload_base: u64,
+    /// Bytes moved before the data cap was last set
+    cap_base: u64,
+}
+
+struct InstalledGroup {
+    group: BandwidthGroup,
+    class: u16,
+}
+
+struct LimiterState {
+    initialized: bool,
+    limits: HashMap<IpAddr, InstalledLimit>,
+    groups: HashMap<String, InstalledGroup>,
+}
+
+pub struct BandwidthLimiter {
+    interface: String,
+    runner: Arc<dyn CommandRunner>,
+    state: Mutex<LimiterState>,
 }
 
 impl BandwidthLimiter {
-    pub fn new() -> Self {
+    /// Limiter shaping traffic on `interface` with the host's `tc`
+    pub fn new(interface: &str) -> Self {
+        Self::with_runner(interface, Arc::new(SystemCommandRunner))
+    }
+
+    pub fn with_runner(interface: &str, runner: Arc<dyn CommandRunner>) -> Self {
         Self {
-            limits: tokio::sync::RwLock::new(HashMap::new()),
+            interface: interface.to_string(),
+            runner,
+            state: Mutex::new(LimiterState { initialized: false, limits: HashMap::new(), groups: HashMap::new() }),
+        }
+    }
+
+    async fn init(&self, state: &mut LimiterState) -> io::Result<()> {
+        if !state.initialized {
+            self.tc(&["qdisc", "replace", "dev", &self.interface, "root", "handle", "1:", "htb"]).await?;
+            self.tc(&["qdisc", "replace", "dev", &self.interface, "handle", "ffff:", "ingress"]).await?;
+            state.initialized = true;
+        }
+        Ok(())
+    }
+
+    /// Create `group` or change its caps in place
+    pub async fn set_group(&self, group: &BandwidthGroup) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        self.init(&mut state).await?;
+
+        let class = match state.groups.get(&group.name) {
+            Some(installed) => installed.class,
+            None => (FIRST_GROUP_CLASS..=LAST_GROUP_CLASS)
+                .find(|c| state.groups.values().all(|g| g.class != *c))
+                .ok_or_else(|| io::Error::other("No free group classes"))?,
+        };
+        let classid = format!("1:{:x}", class);
+        let down = format!("{}kbit", group.download_kbps.unwrap_or(UNLIMITED_KBPS));
+        self.tc(&["class", "replace", "dev", &self.interface, "parent", "1:", "classid", &classid,
+                  "htb", "rate", &down, "ceil", &down]).await?;
+
+        // Conforming packets go on to the member's own policer, if any
+        let up_kbps = group.upload_kbps.unwrap_or(UNLIMITED_KBPS);
+        let (up, burst) = (format!("{}kbit", up_kbps), burst_bytes(up_kbps));
+        self.tc(&["actions", "replace", "action", "police", "rate", &up, "burst", &burst,
+                  "conform-exceed", "drop/pipe", "index", &class.to_string()]).await?;
+
+        tracing::info!("Bandwidth group {} capped at {:?}/{:?} kbps", group.name, group.download_kbps, group.upload_kbps);
+        state.groups.insert(group.name.clone(), InstalledGroup { group: group.clone(), class });
+        Ok(())
+    }
+
+    /// Remove a group no client is in any more
+    pub async fn remove_group(&self, name: &str) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        let Some(class) = state.groups.get(name).map(|g| g.class) else {
+            return Ok(());
+        };
+        let members = state.limits.values().filter(|l| l.group_class == Some(class)).count();
+        if members > 0 {
+            return Err(io::Error::other(format!("Bandwidth group {} still has {} client(s)", name, members)));
+        }
+
+        state.groups.remove(name);
+        let classid = format!("1:{:x}", class);
+        let index = class.to_string();
+        for args in [
+            vec!["class", "del", "dev", &self.interface, "classid", &classid],
+            vec!["actions", "del", "action", "police", "index", &index],
+        ] {
+            if let Err(e) = self.tc(&args).await {
+                tracing::debug!("tc cleanup of group {} failed: {}", name, e);
+            }
         }
+        Ok(())
+    }
+
+    pub async fn get_group(&self, name: &str) -> Option<BandwidthGroup> {
+        self.state.lock().await.groups.get(name).map(|g| g.group.clone())
     }
 
-    pub async fn set_limit(&self, mac: &str, download_kbps: u64, upload_kbps: u64) {
-        let limit = BandwidthLimit { download_kbps, upload_kbps };
+    /// Shape `ip` to `limit`, replacing any limit it already has
+    ///
+    /// A client staying in the same group has its limits changed in place,
+    /// keeping its traffic flowing and its counters running.
+    pub async fn set_limit(&self, ip: IpAddr, limit: BandwidthLimit) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        self.init(&mut state).await?;
+
+        let group_class = match &limit.group {
+            Some(name) => Some(state.groups.get(name)
+                .ok_or_else(|| io::Error::other(format!("Unknown bandwidth group {}", name)))?
+                .class),
+            None => None,
+        };
+
+        if let Some(installed) = state.limits.get_mut(&ip) {
+            if installed.group_class == group_class && !limit.is_unlimited() {
+                // Replacing the upload filter restarts its counter
+                let (downloaded, uploaded) = self.counters(installed.class).await.unwrap_or((0, 0));
+                installed.upload_base += uploaded;
+                installed.cap_base = downloaded + installed.upload_base;
+
+                self.tc_owned(self.class_args("change", &limit, installed.class, group_class)).await?;
+                self.tc_owned(self.upload_args("replace", ip, &limit, installed.class, group_class)).await?;
+                tracing::info!("Changed limit of {} to {:?}", ip, limit);
+                installed.limit = limit;
+                return Ok(());
+            }
+        }
+
+        if let Some(installed) = state.limits.remove(&ip) {
+            self.uninstall(installed.class).await;
+        }
+        if limit.is_unlimited() {
+            return Ok(());
+        }
 
-        // Apply tc rules
-        self.apply_tc_limit(mac, &limit).await;
+        let class = (FIRST_CLASS..=LAST_CLASS)
+            .find(|c| state.limits.values().all(|l| l.class != *c))
+            .ok_or_else(|| io::Error::other("No free traffic classes"))?;
 
-        let mut limits = self.limits.write().await;
-        limits.insert(mac.to_string(), limit);
+        if let Err(e) = self.install(ip, &limit, class, group_class).await {
+            self.uninstall(class).await;
+            return Err(e);
+        }
+        tracing::info!("Limited {} to {:?}", ip, limit);
+        state.limits.insert(ip, InstalledLimit { limit, class, group_class, upload_base: 0, cap_base: 0 });
+        Ok(())
     }
 
-    pub async fn remove_limit(&self, mac: &str) {
-        self.remove_tc_limit(mac).await;
+    /// Remove the shaping of `ip`, if any
+    pub async fn remove_limit(&self, ip: IpAddr) {
+        let installed = self.state.lock().await.limits.remove(&ip);
+        if let Some(installed) = installed {
+            self.uninstall(installed.class).await;
+        }
+    }
 
-        let mut limits = self.limits.write().await;
-        limits.remove(mac);
+    pub async fn get_limit(&self, ip: IpAddr) -> Option<BandwidthLimit> {
+        self.state.lock().await.limits.get(&ip).map(|l| l.limit.clone())
     }
 
-    async fn apply_tc_limit(&self, mac: &str, limit: &BandwidthLimit) {
-        // Use Linux tc (traffic control) to limit bandwidth
-        // Example: tc filter add dev eth0 protocol ip parent 1:0 prio 1 u32 match ether src AA:BB:CC:DD:EE:FF flowid 1:10
-        // Then: tc class add dev eth0 parent 1:0 classid 1:10 htb rate 1mbit ceil 1mbit
+    /// Read the counters of every limited client
+    pub async fn poll_usage(&self) -> Vec<ClientUsage> {
+        let clients: Vec<(IpAddr, u16, Option<u64>, u64, u64)> = self.state.lock().await.limits.iter()
+            .map(|(ip, l)| (*ip, l.class, l.limit.data_cap_bytes, l.upload_base, l.cap_base))
+            .collect();
 
-        let _ = Command::new("tc")
-            .args(&["class", "add", "dev", "eth0", "parent", "1:0"])
-            .output()
-            .await;
+        let mut usage = Vec::with_capacity(clients.len());
+        for (ip, class, cap, upload_base, cap_base) in clients {
+            match self.counters(class).await {
+                Ok((downloaded, uploaded)) => usage.push(ClientUsage {
+                    ip,
+                    downloaded,
+                    uploaded: uploaded + upload_base,
+                    exhausted: cap.is_some_and(|cap| (downloaded + uploaded + upload_base).saturating_sub(cap_base) >= cap),
+                }),
+                Err(e) => tracing::warn!("Failed to read traffic counters of {}: {}", ip, e),
+            }
+        }
+        usage
     }
 
-    async fn remove_tc_limit(&self, mac: &str) {
-        // Remove tc rules for this MAC
+    async fn install(&self, ip: IpAddr, limit: &BandwidthLimit, class: u16, group_class: Option<u16>) -> io::Result<()> {
+        let (protocol, matcher, prefix) = ip_match(ip);
+        let classid = format!("1:{:x}", class);
+        let pref = class.to_string();
+        let host = format!("{}/{}", ip, prefix);
+
+        // Download: traffic leaving the portal interface towards the client
+        self.tc_owned(self.class_args("add", limit, class, group_class)).await?;
+        self.tc(&["filter", "add", "dev", &self.interface, "parent", "1:", "protocol", protocol,
+                  "pref", &pref, "u32", "match", matcher, "dst", &host, "flowid", &classid]).await?;
+
+        // Upload: traffic arriving from the client, policed on ingress
+        self.tc_owned(self.upload_args("add", ip, limit, class, group_class)).await.map(|_| ())
+    }
+
+    /// The client's HTB class, under its group's if it has one
+    fn class_args(&self, verb: &str, limit: &BandwidthLimit, class: u16, group_class: Option<u16>) -> Vec<String> {
+        let ceil = limit.download_kbps.unwrap_or(UNLIMITED_KBPS);
+        let (parent, rate) = match group_class {
+            Some(group) => (format!("1:{:x}", group), ceil.min(MEMBER_RATE_KBPS)),
+            None => ("1:".to_string(), ceil),
+        };
+        let (rate, ceil) = (format!("{}kbit", rate), format!("{}kbit", ceil));
+        let classid = format!("1:{:x}", class);
+        ["class", verb, "dev", &self.interface, "parent", &parent, "classid", &classid,
+         "htb", "rate", &rate, "ceil", &ceil]
+            .iter().map(|a| a.to_string()).collect()
+    }
+
+    /// The client's ingress filter, through its group's shared policer if
+    /// it has one
+    fn upload_args(&self, verb: &str, ip: IpAddr, limit: &BandwidthLimit, class: u16, group_class: Option<u16>) -> Vec<String> {
+        let (protocol, matcher, prefix) = ip_match(ip);
+        let pref = class.to_string();
+        let host = format!("{}/{}", ip, prefix);
+        let mut args: Vec<String> = ["filter", verb, "dev", &self.interface, "parent", "ffff:", "protocol", protocol,
+                                     "pref", &pref, "u32", "match", matcher, "src", &host]
+            .iter().map(|a| a.to_string()).collect();
+
+        let own = limit.upload_kbps.map(|kbps| (format!("{}kbit", kbps), burst_bytes(kbps)));
+        let tail: Vec<String> = match (group_class, own) {
+            (None, Some((rate, burst))) => vec!["police".into(), "rate".into(), rate, "burst".into(), burst,
+                                                "drop".into(), "flowid".into(), ":1".into()],
+            (None, None) => vec!["action".into(), "ok".into(), "flowid".into(), ":1".into()],
+            (Some(group), own) => {
+                // Count the client's own bytes ahead of the shared policer
+                let mut tail: Vec<String> = vec!["flowid".into(), ":1".into(), "action".into(), "gact".into(), "pipe".into(),
+                                                 "action".into(), "police".into(), "index".into(), group.to_string()];
+                if let Some((rate, burst)) = own {
+                    tail.extend(["action".into(), "police".into(), "rate".into(), rate, "burst".into(), burst,
+                                 "conform-exceed".into(), "drop".into()]);
+                }
+                tail
+            }
+        };
+        args.extend(tail);
+        args
+    }
+
+    /// Best-effort removal of a client's filters and class
+    async fn uninstall(&self, class: u16) {
+        let classid = format!("1:{:x}", class);
+        let pref = class.to_string();
+        for args in [
+            vec!["filter", "del", "dev", &self.interface, "parent", "ffff:", "pref", &pref],
+            vec!["filter", "del", "dev", &self.interface, "parent", "1:", "pref", &pref],
+            vec!["class", "del", "dev", &self.interface, "classid", &classid],
+        ] {
+            if let Err(e) = self.tc(&args).await {
+                tracing::debug!("tc cleanup of class {} failed: {}", classid, e);
+            }
+        }
+    }
+
+    /// Bytes sent to and received from a client's class
+    async fn counters(&self, class: u16) -> io::Result<(u64, u64)> {
+        let classid = format!("1:{:x}", class);
+        let pref = class.to_string();
+        let down = self.tc(&["-s", "class", "show", "dev", &self.interface, "classid", &classid]).await?;
+        let up = self.tc(&["-s", "filter", "show", "dev", &self.interface, "parent", "ffff:", "pref", &pref]).await?;
+        Ok((sent_bytes(&down).unwrap_or(0), sent_bytes(&up).unwrap_or(0)))
+    }
+
+    async fn tc(&self, args: &[&str]) -> io::Result<String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        self.runner.run("tc", &args).await
+    }
+
+    async fn tc_owned(&self, args: Vec<String>) -> io::Result<String> {
+        self.runner.run("tc", &args).await
+    }
+}
+
+/// Ethertype, u32 match keyword and host prefix length for `ip`
+fn ip_match(ip: IpAddr) -> (&'static str, &'static str, u8) {
+    match ip {
+        IpAddr::V4(_) => ("ip", "ip", 32),
+        IpAddr::V6(_) => ("ipv6", "ip6", 128),
+    }
+}
+
+/// A tenth of a second at the policed rate, at least a few packets
+fn burst_bytes(kbps: u64) -> String {
+    (kbps * 1000 / 8 / 10).max(16 * 1024).to_string()
+}
+
+/// First "Sent N bytes" counter in `tc -s` output
+fn sent_bytes(output: &str) -> Option<u64> {
+    let rest = &output[output.find("Sent ")? + 5..];
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    /// Records commands and answers counter queries with fixed byte counts
+    #[derive(Default)]
+    struct FakeRunner {
+        commands: StdMutex<Vec<String>>,
+        sent: StdMutex<HashMap<String, u64>>,
+    }
+
+    impl FakeRunner {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.commands.lock().unwrap())
+        }
+    }
+
+    #[async_trait]
+    impl CommandRunner for FakeRunner {
+        async fn run(&self, program: &str, args: &[String]) -> io::Result<String> {
+            let command = format!("{} {}", program, args.join(" "));
+            self.commands.lock().unwrap().push(command.clone());
+            let sent = self.sent.lock().unwrap().iter()
+                .find(|(key, _)| command.contains(key.as_str()))
+                .map(|(_, bytes)| *bytes);
+            Ok(match sent {
+                Some(bytes) => format!(" Sent {} bytes 100 pkt (dropped 0, overlimits 0 requeues 0)\n", bytes),
+                None => String::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_and_remove_commands() {
+        let runner = Arc::new(FakeRunner::default());
+        let limiter = BandwidthLimiter::with_runner("wlan0", runner.clone());
+        let guest: IpAddr = "10.0.0.20".parse().unwrap();
+
+        let limit = BandwidthLimit { download_kbps: Some(10_000), upload_kbps: Some(2_000), ..Default::default() };
+        limiter.set_limit(guest, limit.clone()).await.unwrap();
+        assert_eq!(runner.take(), vec![
+            "tc qdisc replace dev wlan0 root handle 1: htb",
+            "tc qdisc replace dev wlan0 handle ffff: ingress",
+            "tc class add dev wlan0 parent 1: classid 1:10 htb rate 10000kbit ceil 10000kbit",
+            "tc filter add dev wlan0 parent 1: protocol ip pref 16 u32 match ip dst 10.0.0.20/32 flowid 1:10",
+            "tc filter add dev wlan0 parent ffff: protocol ip pref 16 u32 match ip src 10.0.0.20/32 police rate 2000kbit burst 25000 drop flowid :1",
+        ]);
+        assert_eq!(limiter.get_limit(guest).await, Some(limit));
+
+        // A second client gets the next class; IPv6 matches on ip6
+        let other: IpAddr = "2001:db8::20".parse().unwrap();
+        limiter.set_limit(other, BandwidthLimit { data_cap_bytes: Some(1_000), ..Default::default() }).await.unwrap();
+        assert_eq!(runner.take(), vec![
+            "tc class add dev wlan0 parent 1: classid 1:11 htb rate 10000000kbit ceil 10000000kbit",
+            "tc filter add dev wlan0 parent 1: protocol ipv6 pref 17 u32 match ip6 dst 2001:db8::20/128 flowid 1:11",
+            "tc filter add dev wlan0 parent ffff: protocol ipv6 pref 17 u32 match ip6 src 2001:db8::20/128 action ok flowid :1",
+        ]);
+
+        limiter.remove_limit(guest).await;
+        assert_eq!(runner.take(), vec![
+            "tc filter del dev wlan0 parent ffff: pref 16",
+            "tc filter del dev wlan0 parent 1: pref 16",
+            "tc class del dev wlan0 classid 1:10",
+        ]);
+        assert_eq!(limiter.get_limit(guest).await, None);
+        limiter.remove_limit(guest).await;
+        assert!(runner.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_data_cap_exhaustion() {
+        let runner = Arc::new(FakeRunner::default());
+        let limiter = BandwidthLimiter::with_runner("wlan0", runner.clone());
+        let capped: IpAddr = "10.0.0.21".parse().unwrap();
+        let uncapped: IpAddr = "10.0.0.22".parse().unwrap();
+
+        let cap = BandwidthLimit { download_kbps: Some(5_000), data_cap_bytes: Some(1_000_000), ..Default::default() };
+        limiter.set_limit(capped, cap).await.unwrap();
+        limiter.set_limit(uncapped, BandwidthLimit { download_kbps: Some(5_000), ..Default::default() }).await.unwrap();
+
+        runner.sent.lock().unwrap().extend([
+            ("classid 1:10".to_string(), 900_000),
+            ("ffff: pref 16".to_string(), 50_000),
+            ("classid 1:11".to_string(), 5_000_000),
+        ]);
+        let mut usage = limiter.poll_usage().await;
+        usage.sort_by_key(|u| u.ip);
+        assert_eq!(usage[0], ClientUsage { ip: capped, downloaded: 900_000, uploaded: 50_000, exhausted: false });
+        assert!(!usage[1].exhausted);
+
+        runner.sent.lock().unwrap().insert("ffff: pref 16".to_string(), 150_000);
+        let usage = limiter.poll_usage().await;
+        assert!(usage.iter().any(|u| u.ip == capped && u.exhausted));
+        assert!(usage.iter().any(|u| u.ip == uncapped && !u.exhausted));
+    }
+
+    #[tokio::test]
+    async fn test_group_members_and_cleanup() {
+        let runner = Arc::new(FakeRunner::default());
+        let limiter = BandwidthLimiter::with_runner("wlan0", runner.clone());
+        let guests = BandwidthGroup { name: "guests".to_string(), download_kbps: Some(50_000), upload_kbps: Some(10_000) };
+        limiter.set_group(&guests).await.unwrap();
+        assert_eq!(runner.take()[2..], [
+            "tc class replace dev wlan0 parent 1: classid 1:1 htb rate 50000kbit ceil 50000kbit",
+            "tc actions replace action police rate 10000kbit burst 125000 conform-exceed drop/pipe index 1",
+        ]);
+
+        let guest: IpAddr = "10.0.0.20".parse().unwrap();
+        let limit = BandwidthLimit { upload_kbps: Some(2_000), group: Some("guests".to_string()), ..Default::default() };
+        limiter.set_limit(guest, limit).await.unwrap();
+        assert_eq!(runner.take(), vec![
+            "tc class add dev wlan0 parent 1:1 classid 1:10 htb rate 64kbit ceil 10000000kbit",
+            "tc filter add dev wlan0 parent 1: protocol ip pref 16 u32 match ip dst 10.0.0.20/32 flowid 1:10",
+            "tc filter add dev wlan0 parent ffff: protocol ip pref 16 u32 match ip src 10.0.0.20/32 flowid :1 \
+             action gact pipe action police index 1 action police rate 2000kbit burst 25000 conform-exceed drop",
+        ]);
+
+        // Unknown groups are refused, groups in use stay
+        let unknown = BandwidthLimit { group: Some("staff".to_string()), ..Default::default() };
+        assert!(limiter.set_limit("10.0.0.21".parse().unwrap(), unknown).await.is_err());
+        assert!(limiter.remove_group("guests").await.is_err());
+        runner.take();
+
+        // Raising the group cap leaves its members in place
+        limiter.set_group(&BandwidthGroup { download_kbps: Some(80_000), ..guests }).await.unwrap();
+        assert!(runner.take().iter().all(|c| !c.contains("del")));
+
+        // A session ending takes its class and filters along, the group's
+        // last member its class and policer
+        limiter.remove_limit(guest).await;
+        assert_eq!(runner.take(), vec![
+            "tc filter del dev wlan0 parent ffff: pref 16",
+            "tc filter del dev wlan0 parent 1: pref 16",
+            "tc class del dev wlan0 classid 1:10",
+        ]);
+        limiter.remove_group("guests").await.unwrap();
+        assert_eq!(runner.take(), vec![
+            "tc class del dev wlan0 classid 1:1",
+            "tc actions del action police index 1",
+        ]);
+        assert_eq!(limiter.get_limit(guest).await, None);
+        assert_eq!(limiter.get_group("guests").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_live_adjustment_keeps_counters() {
+        let runner = Arc::new(FakeRunner::default());
+        let limiter = BandwidthLimiter::with_runner("wlan0", runner.clone());
+        let guest: IpAddr = "10.0.0.20".parse().unwrap();
+
+        let limit = BandwidthLimit { download_kbps: Some(5_000), data_cap_bytes: Some(1_000_000), ..Default::default() };
+        limiter.set_limit(guest, limit).await.unwrap();
+        runner.sent.lock().unwrap().extend([
+            ("classid 1:10".to_string(), 900_000),
+            ("ffff: pref 16".to_string(), 50_000),
+        ]);
+        runner.take();
+
+        // Throttled to a tier with 100 kB left of its cap
+        let throttled = BandwidthLimit {
+            download_kbps: Some(1_000),
+            upload_kbps: Some(256),
+            data_cap_bytes: Some(100_000),
+            ..Default::default()
+        };
+        limiter.set_limit(guest, throttled.clone()).await.unwrap();
+        let commands = runner.take();
+        assert!(commands.iter().all(|c| !c.contains(" del ")));
+        assert_eq!(commands[2..], [
+            "tc class change dev wlan0 parent 1: classid 1:10 htb rate 1000kbit ceil 1000kbit",
+            "tc filter replace dev wlan0 parent ffff: protocol ip pref 16 u32 match ip src 10.0.0.20/32 \
+             police rate 256kbit burst 16384 drop flowid :1",
+        ]);
+        assert_eq!(limiter.get_limit(guest).await, Some(throttled));
+
+        // The replaced upload filter counts from zero again
+        runner.sent.lock().unwrap().insert("ffff: pref 16".to_string(), 10_000);
+        let usage = limiter.poll_usage().await;
+        assert_eq!(usage[0], ClientUsage { ip: guest, downloaded: 900_000, uploaded: 60_000, exhausted: false });
+
+        runner.sent.lock().unwrap().insert("classid 1:10".to_string(), 1_000_000);
+        assert!(limiter.poll_usage().await[0].exhausted);
     }
 }
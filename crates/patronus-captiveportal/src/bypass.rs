@@ -0,0 +1,328 @@
+//! Portal bypass for devices that cannot log in
+//!
+//! Printers, TVs and other headless devices get an authenticated session as
+//! soon as they show up on the network when they match a [`BypassEntry`]:
+//! by exact MAC address, by vendor OUI, or by DHCP hostname together with
+//! the OUI. Entries can expire and carry their own bandwidth limits.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Which devices an entry lets through
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BypassMatch {
+    /// One device, e.g. "aa:bb:cc:dd:ee:ff"
+    Mac(String),
+    /// Every device whose MAC starts with a vendor prefix, e.g. "aa:bb:cc"
+    Oui(String),
+    /// A device announcing `hostname` over DHCP from a MAC with the given OUI
+    ///
+    /// For devices whose MAC address changes. Both halves are easy to fake:
+    /// the client picks its own hostname and OUIs are public, so anyone can
+    /// claim this entry.
+    HostnameOui { hostname: String, oui: String },
+}
+
+impl BypassMatch {
+    /// Normalize addresses to lowercase colon form, rejecting malformed ones
+    fn normalized(&self) -> Result<Self, BypassError> {
+        Ok(match self {
+            BypassMatch::Mac(mac) => BypassMatch::Mac(normalize_hex(mac, 6)?),
+            BypassMatch::Oui(oui) => BypassMatch::Oui(normalize_hex(oui, 3)?),
+            BypassMatch::HostnameOui { hostname, oui } => {
+                let hostname = hostname.trim().to_ascii_lowercase();
+                if hostname.is_empty() {
+                    return Err(BypassError::Invalid("hostname is empty".to_string()));
+                }
+                BypassMatch::HostnameOui { hostname, oui: normalize_hex(oui, 3)? }
+            }
+        })
+    }
+
+    /// Matches from most to least specific
+    fn specificity(&self) -> u8 {
+        match self {
+            BypassMatch::Mac(_) => 2,
+            BypassMatch::HostnameOui { .. } => 1,
+            BypassMatch::Oui(_) => 0,
+        }
+    }
+
+    fn matches(&self, mac: &str, hostname: Option<&str>) -> bool {
+        match self {
+            BypassMatch::Mac(entry) => entry == mac,
+            BypassMatch::Oui(oui) => mac.starts_with(oui.as_str()),
+            BypassMatch::HostnameOui { hostname: entry, oui } => {
+                mac.starts_with(oui.as_str()) && hostname.is_some_and(|h| h.eq_ignore_ascii_case(entry))
+            }
+        }
+    }
+}
+
+/// A bypass entry as submitted through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewBypassEntry {
+    pub description: String,
+    pub matcher: BypassMatch,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Portal default when unset
+    #[serde(default)]
+    pub download_limit_kbps: Option<u64>,
+    #[serde(default)]
+    pub upload_limit_kbps: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BypassEntry {
+    pub id: Uuid,
+    pub description: String,
+    pub matcher: BypassMatch,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub download_limit_kbps: Option<u64>,
+    pub upload_limit_kbps: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    /// Devices admitted through the entry
+    pub use_count: u64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_by: Option<String>,
+}
+
+impl BypassEntry {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BypassList {
+    entries: HashMap<Uuid, BypassEntry>,
+}
+
+impl BypassList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: NewBypassEntry) -> Result<BypassEntry, BypassError> {
+        let now = Utc::now();
+        if entry.expires_at.is_some_and(|at| at <= now) {
+            return Err(BypassError::Invalid("expiry is in the past".to_string()));
+        }
+        let matcher = entry.matcher.normalized()?;
+        if let BypassMatch::HostnameOui { hostname, oui } = &matcher {
+            tracing::warn!(
+                "Bypass for hostname {} on OUI {} can be claimed by any device announcing that name",
+                hostname, oui
+            );
+        }
+
+        let entry = BypassEntry {
+            id: Uuid::new_v4(),
+            description: entry.description,
+            matcher,
+            expires_at: entry.expires_at,
+            download_limit_kbps: entry.download_limit_kbps,
+            upload_limit_kbps: entry.upload_limit_kbps,
+            created_at: now,
+            use_count: 0,
+            last_used_at: None,
+            last_used_by: None,
+        };
+        self.entries.insert(entry.id, entry.clone());
+        Ok(entry)
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<BypassEntry> {
+        self.entries.remove(id)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&BypassEntry> {
+        self.entries.get(id)
+    }
+
+    /// Entries, oldest first
+    pub fn list(&self) -> Vec<BypassEntry> {
+        let mut entries: Vec<BypassEntry> = self.entries.values().cloned().collect();
+        entries.sort_by_key(|e| e.created_at);
+        entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether matching needs DHCP hostnames
+    pub fn needs_hostnames(&self) -> bool {
+        self.entries.values().any(|e| matches!(e.matcher, BypassMatch::HostnameOui { .. }))
+    }
+
+    /// The most specific live entry covering a device
+    pub fn find(&self, mac: &str, hostname: Option<&str>, now: DateTime<Utc>) -> Option<&BypassEntry> {
+        let mac = normalize_hex(mac, 6).ok()?;
+        self.entries.values()
+            .filter(|e| !e.is_expired(now) && e.matcher.matches(&mac, hostname))
+            .max_by_key(|e| (e.matcher.specificity(), std::cmp::Reverse(e.created_at)))
+    }
+
+    /// Note that `mac` was admitted through entry `id`
+    pub fn record_use(&mut self, id: &Uuid, mac: &str, now: DateTime<Utc>) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.use_count += 1;
+            entry.last_used_at = Some(now);
+            entry.last_used_by = Some(mac.to_string());
+        }
+    }
+
+    /// Take out expired entries so their sessions can be ended
+    pub fn remove_expired(&mut self, now: DateTime<Utc>) -> Vec<BypassEntry> {
+        let expired: Vec<Uuid> = self.entries.values()
+            .filter(|e| e.is_expired(now))
+            .map(|e| e.id)
+            .collect();
+        expired.iter().filter_map(|id| self.entries.remove(id)).collect()
+    }
+}
+
+/// `octets` hex bytes separated by ':' or '-', or not at all, as lowercase colon form
+fn normalize_hex(value: &str, octets: usize) -> Result<String, BypassError> {
+    let digits: String = value.trim().chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if digits.len() != octets * 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(BypassError::Invalid(format!("{:?} is not {} hex octets", value, octets)));
+    }
+    let digits = digits.to_ascii_lowercase();
+    Ok((0..octets).map(|i| &digits[i * 2..i * 2 + 2]).collect::<Vec<_>>().join(":"))
+}
+
+/// Hostnames by lowercase MAC from an ISC dhcpd or dnsmasq leases file
+pub fn parse_dhcp_hostnames(leases: &str) -> HashMap<String, String> {
+    let mut hostnames = HashMap::new();
+    let mut mac: Option<String> = None;
+    let mut hostname: Option<String> = None;
+
+    for line in leases.lines() {
+        let line = line.trim();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        // dnsmasq: <expiry> <mac> <ip> <hostname> <client-id>
+        if fields.len() >= 4 && fields[0].chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(mac) = normalize_hex(fields[1], 6) {
+                if fields[3] != "*" {
+                    hostnames.insert(mac, fields[3].to_string());
+                }
+            }
+            continue;
+        }
+
+        // ISC dhcpd: lease <ip> { hardware ethernet <mac>; client-hostname "<name>"; }
+        if line.starts_with("lease ") {
+            mac = None;
+            hostname = None;
+        } else if let Some(value) = line.strip_prefix("hardware ethernet ") {
+            mac = normalize_hex(value.trim_end_matches(';'), 6).ok();
+        } else if let Some(value) = line.strip_prefix("client-hostname ") {
+            hostname = Some(value.trim_end_matches(';').trim_matches('"').to_string());
+        } else if line == "}" {
+            if let (Some(mac), Some(hostname)) = (mac.take(), hostname.take()) {
+                hostnames.insert(mac, hostname);
+            }
+        }
+    }
+
+    hostnames
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BypassError {
+    #[error("Invalid bypass entry: {0}")]
+    Invalid(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn entry(description: &str, matcher: BypassMatch) -> NewBypassEntry {
+        NewBypassEntry {
+            description: description.to_string(),
+            matcher,
+            expires_at: None,
+            download_limit_kbps: None,
+            upload_limit_kbps: None,
+        }
+    }
+
+    #[test]
+    fn test_most_specific_entry_wins() {
+        let mut list = BypassList::new();
+        let now = Utc::now();
+        let vendor = list.add(entry("Lobby TVs", BypassMatch::Oui("A4-5E-60".to_string()))).unwrap();
+        let printer = list.add(entry("Printer", BypassMatch::Mac("A45E60123456".to_string()))).unwrap();
+        let kiosk = list.add(entry("Kiosk", BypassMatch::HostnameOui {
+            hostname: "Lobby-Kiosk".to_string(),
+            oui: "a4:5e:60".to_string(),
+        })).unwrap();
+
+        assert_eq!(printer.matcher, BypassMatch::Mac("a4:5e:60:12:34:56".to_string()));
+        assert_eq!(list.find("A4:5E:60:12:34:56", None, now).unwrap().id, printer.id);
+        assert_eq!(list.find("a4:5e:60:ff:00:01", Some("lobby-kiosk"), now).unwrap().id, kiosk.id);
+        assert_eq!(list.find("a4:5e:60:ff:00:01", Some("laptop"), now).unwrap().id, vendor.id);
+        assert!(list.find("02:5e:60:ff:00:01", Some("lobby-kiosk"), now).is_none());
+        assert!(list.needs_hostnames());
+
+        list.record_use(&printer.id, "a4:5e:60:12:34:56", now);
+        assert_eq!(list.get(&printer.id).unwrap().use_count, 1);
+        assert_eq!(list.get(&printer.id).unwrap().last_used_by.as_deref(), Some("a4:5e:60:12:34:56"));
+    }
+
+    #[test]
+    fn test_expiry_and_validation() {
+        let mut list = BypassList::new();
+        let now = Utc::now();
+        let mut temporary = entry("Event screen", BypassMatch::Mac("02:00:00:00:00:01".to_string()));
+        temporary.expires_at = Some(now + Duration::hours(2));
+        let temporary = list.add(temporary).unwrap();
+
+        assert!(list.find("02:00:00:00:00:01", None, now).is_some());
+        assert!(list.find("02:00:00:00:00:01", None, now + Duration::hours(3)).is_none());
+        assert!(list.remove_expired(now).is_empty());
+        let expired = list.remove_expired(now + Duration::hours(3));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, temporary.id);
+        assert!(list.is_empty());
+
+        assert!(list.add(entry("Bad", BypassMatch::Mac("02:00:00:00:01".to_string()))).is_err());
+        assert!(list.add(entry("Bad", BypassMatch::Oui("zz:00:00".to_string()))).is_err());
+        let mut past = entry("Past", BypassMatch::Oui("02:00:00".to_string()));
+        past.expires_at = Some(now - Duration::minutes(1));
+        assert!(list.add(past).is_err());
+    }
+
+    #[test]
+    fn test_parse_dhcp_hostnames() {
+        let isc = r#"
+lease 192.168.10.20 {
+  starts 4 2026/10/15 10:00:00;
+  hardware ethernet A4:5E:60:12:34:56;
+  client-hostname "lobby-printer";
+}
+lease 192.168.10.21 {
+  hardware ethernet 02:00:00:00:00:02;
+}
+"#;
+        let dnsmasq = "1760000000 02:00:00:00:00:03 192.168.10.22 lobby-tv 01:02:00:00:00:00:03\n\
+                       1760000000 02:00:00:00:00:04 192.168.10.23 * *\n";
+
+        let hostnames = parse_dhcp_hostnames(isc);
+        assert_eq!(hostnames.len(), 1);
+        assert_eq!(hostnames["a4:5e:60:12:34:56"], "lobby-printer");
+
+        let hostnames = parse_dhcp_hostnames(dnsmasq);
+        assert_eq!(hostnames.len(), 1);
+        assert_eq!(hostnames["02:00:00:00:00:03"], "lobby-tv");
+    }
+}
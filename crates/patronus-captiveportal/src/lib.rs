@@ -8,9 +8,23 @@ pub mod auth;
 pub mod vouchers;
 pub mod sessions;
 pub mod bandwidth;
+pub mod radius;
+pub mod oauth;
+pub mod bypass;
 
 pub use portal::CaptivePortal;
-pub use auth::{AuthProvider, AuthMethod};
-pub use vouchers::{VoucherManager, Voucher};
-pub use sessions::{SessionManager, ClientSession};
-pub use bandwidth::BandwidthLimiter;
+pub use auth::{AuthProvider, AuthMethod, SessionAttributes};
+pub use radius::{RadiusAuthProvider, RadiusAuthType, RadiusFallback};
+pub use oauth::{OAuthManager, OAuthProviderConfig};
+pub use bypass::{BypassList, BypassEntry, BypassMatch, NewBypassEntry, BypassError};
+pub use vouchers::{
+    VoucherManager, Voucher, VoucherBatch, VoucherPolicy, VoucherValidity, VoucherUsage, BandwidthTier,
+    CodeFormat, BatchUsage, VoucherStatus, VoucherRenderer, HtmlCardRenderer, PdfCardRenderer, VoucherError,
+};
+pub use sessions::{
+    SessionManager, SessionError, ClientSession, ConcurrentSessionPolicy, RoamingConfig, RoamOutcome, GuestUsage,
+    SessionUsage, GroupUsage, SessionState, SessionEvent, TransitionReason, SessionTimeouts, Clock, SystemClock,
+};
+pub use bandwidth::{
+    BandwidthLimit, BandwidthLimiter, BandwidthGroup, FairUsePolicy, ClientUsage, CommandRunner, SystemCommandRunner,
+};
@@ -0,0 +1,416 @@
+//! OAuth2 social login ("Login with Google/Facebook")
+//!
+//! The guest is redirected to the provider's authorization page with a
+//! random `state` that is remembered for the client and also set as a
+//! cookie. On the callback the state must match both, be unexpired and is
+//! consumed, so a login started elsewhere can't be completed on the
+//! guest's device. The returned code is exchanged for an access token,
+//! which is validated by fetching the user's profile with it; the
+//! provider-verified email is the guest's identity.
+
+use crate::auth::{AuthError, AuthResult, SessionAttributes, UserInfo};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Cookie holding the state of the guest's pending login
+pub const STATE_COOKIE: &str = "portal_oauth_state";
+
+/// How long a guest has to complete a login at the provider
+const STATE_TTL: Duration = Duration::from_secs(600);
+
+/// Most logins pending at once; the oldest are dropped beyond this
+const MAX_PENDING: usize = 10_000;
+
+/// An OAuth2 identity provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthProviderConfig {
+    /// Name used in the portal's `/auth/<name>/...` URLs
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    /// Endpoint returning the user's profile for an access token
+    pub userinfo_url: String,
+    pub scopes: Vec<String>,
+    /// Callback URL registered with the provider
+    pub redirect_uri: String,
+    /// Email domains allowed to log in; empty allows any
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// Require the profile's `email_verified` to be true
+    #[serde(default)]
+    pub require_verified_email: bool,
+}
+
+impl OAuthProviderConfig {
+    /// Google sign-in for the portal at `portal_url`
+    pub fn google(client_id: &str, client_secret: &str, portal_url: &str) -> Self {
+        Self {
+            name: "google".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            authorize_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            userinfo_url: "https://openidconnect.googleapis.com/v1/userinfo".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string(), "profile".to_string()],
+            redirect_uri: format!("{}/auth/google/callback", portal_url.trim_end_matches('/')),
+            allowed_domains: Vec::new(),
+            require_verified_email: true,
+        }
+    }
+
+    /// Facebook login for the portal at `portal_url`
+    ///
+    /// Facebook only returns confirmed emails, without an `email_verified`
+    /// field.
+    pub fn facebook(app_id: &str, app_secret: &str, portal_url: &str) -> Self {
+        Self {
+            name: "facebook".to_string(),
+            client_id: app_id.to_string(),
+            client_secret: app_secret.to_string(),
+            authorize_url: "https://www.facebook.com/v19.0/dialog/oauth".to_string(),
+            token_url: "https://graph.facebook.com/v19.0/oauth/access_token".to_string(),
+            userinfo_url: "https://graph.facebook.com/me?fields=id,name,email".to_string(),
+            scopes: vec!["email".to_string(), "public_profile".to_string()],
+            redirect_uri: format!("{}/auth/facebook/callback", portal_url.trim_end_matches('/')),
+            allowed_domains: Vec::new(),
+            require_verified_email: false,
+        }
+    }
+
+    /// Whether `email` is in one of the allowed domains
+    pub fn allows_email(&self, email: &str) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        self.allowed_domains.iter().any(|d| d.trim_start_matches('@').eq_ignore_ascii_case(domain))
+    }
+}
+
+/// A login started by a guest and not yet completed
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub provider: String,
+    pub mac_address: String,
+    pub ip_address: IpAddr,
+    pub redirect_url: Option<String>,
+    started: Instant,
+}
+
+/// Where to send a guest to log in
+#[derive(Debug, Clone)]
+pub struct OAuthRedirect {
+    /// Provider authorization URL
+    pub url: String,
+    /// State to set as the [`STATE_COOKIE`]
+    pub state: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct Profile {
+    #[serde(default)]
+    sub: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Runs OAuth2 authorization-code logins against the configured providers
+pub struct OAuthManager {
+    providers: HashMap<String, OAuthProviderConfig>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    http: reqwest::Client,
+}
+
+impl OAuthManager {
+    pub fn new(providers: Vec<OAuthProviderConfig>) -> Self {
+        Self {
+            providers: providers.into_iter().map(|p| (p.name.clone(), p)).collect(),
+            pending: Mutex::new(HashMap::new()),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&OAuthProviderConfig> {
+        self.providers.get(name)
+    }
+
+    /// Start a login for the client at `mac`/`ip` with `provider`
+    pub async fn begin(
+        &self,
+        provider: &str,
+        mac: &str,
+        ip: IpAddr,
+        redirect_url: Option<String>,
+    ) -> Result<OAuthRedirect, AuthError> {
+        let config = self.providers.get(provider)
+            .ok_or_else(|| AuthError::Failed(format!("Unknown login provider {}", provider)))?;
+
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let state: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        {
+            let mut pending = self.pending.lock().await;
+            pending.retain(|_, login| login.started.elapsed() < STATE_TTL);
+            if pending.len() >= MAX_PENDING {
+                if let Some(oldest) = pending.iter().min_by_key(|(_, l)| l.started).map(|(s, _)| s.clone()) {
+                    pending.remove(&oldest);
+                }
+            }
+            pending.insert(state.clone(), PendingLogin {
+                provider: provider.to_string(),
+                mac_address: mac.to_string(),
+                ip_address: ip,
+                redirect_url,
+                started: Instant::now(),
+            });
+        }
+
+        let scope = config.scopes.join(" ");
+        let query = [
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("scope", scope.as_str()),
+            ("state", state.as_str()),
+        ];
+        let separator = if config.authorize_url.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}{}", config.authorize_url, separator, encode_query(&query));
+
+        Ok(OAuthRedirect { url, state })
+    }
+
+    /// Complete a login from the provider's callback
+    ///
+    /// `cookie_state` is the guest's [`STATE_COOKIE`] and `ip` the address
+    /// the callback came from; both must match the login `state` was issued
+    /// for. The state is consumed whether or not the login succeeds.
+    pub async fn complete(
+        &self,
+        provider: &str,
+        code: &str,
+        state: &str,
+        cookie_state: Option<&str>,
+        ip: IpAddr,
+    ) -> Result<(PendingLogin, AuthResult), AuthError> {
+        let login = self.pending.lock().await.remove(state);
+        let login = match login {
+            Some(login) if cookie_state == Some(state)
+                && login.provider == provider
+                && login.ip_address == ip
+                && login.started.elapsed() < STATE_TTL => login,
+            _ => {
+                tracing::warn!("Rejected {} login from {} with an invalid state", provider, ip);
+                return Err(AuthError::Failed("Invalid or expired login state".to_string()));
+            }
+        };
+        let config = self.providers.get(provider)
+            .ok_or_else(|| AuthError::Failed(format!("Unknown login provider {}", provider)))?;
+
+        let token: TokenResponse = self.http.post(&config.token_url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", config.redirect_uri.as_str()),
+                ("client_id", config.client_id.as_str()),
+                ("client_secret", config.client_secret.as_str()),
+            ])
+            .send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::Failed(format!("Token exchange failed: {}", e)))?
+            .json().await
+            .map_err(|e| AuthError::Failed(format!("Invalid token response: {}", e)))?;
+
+        let profile: Profile = self.http.get(&config.userinfo_url)
+            .bearer_auth(&token.access_token)
+            .send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::Failed(format!("Token validation failed: {}", e)))?
+            .json().await
+            .map_err(|e| AuthError::Failed(format!("Invalid profile response: {}", e)))?;
+
+        let email = profile.email
+            .filter(|e| e.contains('@'))
+            .ok_or_else(|| AuthError::Rejected("The provider did not share an email address".to_string()))?;
+        if config.require_verified_email && profile.email_verified != Some(true) {
+            return Err(AuthError::Rejected(format!("{} is not verified", email)));
+        }
+        if !config.allows_email(&email) {
+            return Err(AuthError::Rejected(format!("{} is not allowed to log in here", email)));
+        }
+
+        tracing::info!("{} logged in with {} (subject {:?})", email, provider, profile.sub.or(profile.id));
+        let result = AuthResult {
+            success: true,
+            user_id: email.clone(),
+            user_info: UserInfo {
+                name: profile.name,
+                email: Some(email),
+                groups: vec!["guests".to_string()],
+            },
+            session: SessionAttributes::default(),
+        };
+        Ok((login, result))
+    }
+}
+
+/// Percent-encode `pairs` as an `application/x-www-form-urlencoded` query
+fn encode_query(pairs: &[(&str, &str)]) -> String {
+    fn encode(s: &str) -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+    pairs.iter()
+        .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Value of cookie `name` in a `Cookie` header
+pub fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Form, http::HeaderMap, routing::{get, post}, Json, Router};
+
+    /// Provider issuing a token for code "good-code" whose profile is `email`
+    async fn mock_provider(email: &'static str) -> OAuthProviderConfig {
+        async fn token(Form(form): Form<HashMap<String, String>>) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+            let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+                && form.get("code").map(String::as_str) == Some("good-code")
+                && form.get("client_secret").map(String::as_str) == Some("s3cret");
+            if !valid {
+                return Err(axum::http::StatusCode::BAD_REQUEST);
+            }
+            Ok(Json(serde_json::json!({ "access_token": "token-123", "token_type": "Bearer" })))
+        }
+
+        let app = Router::new()
+            .route("/token", post(token))
+            .route("/userinfo", get(move |headers: HeaderMap| async move {
+                if headers.get("authorization").and_then(|v| v.to_str().ok()) != Some("Bearer token-123") {
+                    return Err(axum::http::StatusCode::UNAUTHORIZED);
+                }
+                Ok(Json(serde_json::json!({
+                    "sub": "1234", "email": email, "email_verified": true, "name": "Guest User"
+                })))
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        OAuthProviderConfig {
+            name: "mock".to_string(),
+            client_id: "portal".to_string(),
+            client_secret: "s3cret".to_string(),
+            authorize_url: format!("{}/authorize", base),
+            token_url: format!("{}/token", base),
+            userinfo_url: format!("{}/userinfo", base),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            redirect_uri: "http://portal.local/auth/mock/callback".to_string(),
+            allowed_domains: vec!["example.com".to_string()],
+            require_verified_email: true,
+        }
+    }
+
+    fn guest_ip() -> IpAddr {
+        "10.0.0.20".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_successful_login() {
+        let oauth = OAuthManager::new(vec![mock_provider("guest@Example.com").await]);
+
+        let redirect = oauth.begin("mock", "aa:bb:cc:dd:ee:ff", guest_ip(), Some("http://news.example".to_string()))
+            .await.unwrap();
+        assert!(redirect.url.contains("/authorize?response_type=code&client_id=portal"));
+        assert!(redirect.url.contains("redirect_uri=http%3A%2F%2Fportal.local%2Fauth%2Fmock%2Fcallback"));
+        assert!(redirect.url.contains("scope=openid%20email"));
+        assert!(redirect.url.ends_with(&format!("state={}", redirect.state)));
+
+        let (login, result) = oauth.complete("mock", "good-code", &redirect.state, Some(&redirect.state), guest_ip())
+            .await.unwrap();
+        assert_eq!(login.mac_address, "aa:bb:cc:dd:ee:ff");
+        assert_eq!(login.redirect_url.as_deref(), Some("http://news.example"));
+        assert!(result.success);
+        assert_eq!(result.user_id, "guest@Example.com");
+        assert_eq!(result.user_info.name.as_deref(), Some("Guest User"));
+
+        // The state is single use
+        let replay = oauth.complete("mock", "good-code", &redirect.state, Some(&redirect.state), guest_ip()).await;
+        assert!(replay.is_err());
+
+        // A bad code fails the exchange
+        let redirect = oauth.begin("mock", "aa:bb:cc:dd:ee:ff", guest_ip(), None).await.unwrap();
+        let err = oauth.complete("mock", "bad-code", &redirect.state, Some(&redirect.state), guest_ip())
+            .await.unwrap_err();
+        assert!(err.to_string().contains("Token exchange failed"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_state_mismatch_is_rejected() {
+        let oauth = OAuthManager::new(vec![mock_provider("guest@example.com").await]);
+        let redirect = oauth.begin("mock", "aa:bb:cc:dd:ee:ff", guest_ip(), None).await.unwrap();
+
+        // A state the portal never issued
+        let err = oauth.complete("mock", "good-code", "forged", Some("forged"), guest_ip()).await.unwrap_err();
+        assert!(err.to_string().contains("Invalid or expired login state"));
+
+        // The attacker's own state, completed in a browser without its cookie
+        let err = oauth.complete("mock", "good-code", &redirect.state, Some("other"), guest_ip()).await.unwrap_err();
+        assert!(err.to_string().contains("Invalid or expired login state"));
+
+        // ...which also consumed it
+        assert!(oauth.complete("mock", "good-code", &redirect.state, Some(&redirect.state), guest_ip()).await.is_err());
+
+        // From another client
+        let redirect = oauth.begin("mock", "aa:bb:cc:dd:ee:ff", guest_ip(), None).await.unwrap();
+        let other: IpAddr = "10.0.0.99".parse().unwrap();
+        assert!(oauth.complete("mock", "good-code", &redirect.state, Some(&redirect.state), other).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_domain_allowlist() {
+        let oauth = OAuthManager::new(vec![mock_provider("guest@gmail.com").await]);
+        let redirect = oauth.begin("mock", "aa:bb:cc:dd:ee:ff", guest_ip(), None).await.unwrap();
+        let err = oauth.complete("mock", "good-code", &redirect.state, Some(&redirect.state), guest_ip())
+            .await.unwrap_err();
+        assert!(matches!(err, AuthError::Rejected(_)), "{}", err);
+
+        assert_eq!(cookie_value("a=1; portal_oauth_state=abc; b=2", STATE_COOKIE), Some("abc"));
+        assert_eq!(cookie_value("a=1", STATE_COOKIE), None);
+    }
+}
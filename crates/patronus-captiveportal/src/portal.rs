@@ -4,24 +4,30 @@
 //! and client management.
 
 use crate::{
-    auth::{AuthProvider, AuthMethod},
-    sessions::SessionManager,
+    auth::{AuthCredentials, AuthError, AuthProvider, AuthMethod, AuthResult},
+    bypass::{self, BypassEntry, BypassList, NewBypassEntry},
+    oauth::{self, OAuthManager, OAuthProviderConfig},
+    sessions::{
+        ClientSession, ConcurrentSessionPolicy, RoamOutcome, RoamingConfig, SessionEvent, SessionManager, SessionState,
+        SessionTimeouts,
+    },
     vouchers::VoucherManager,
-    bandwidth::BandwidthLimiter,
+    bandwidth::{BandwidthGroup, BandwidthLimit, BandwidthLimiter, FairUsePolicy},
 };
 use tokio::io::AsyncWriteExt;
 use axum::{
     Router,
-    extract::{State, Query, Form},
-    response::{Html, Redirect, IntoResponse, Response},
+    extract::{ConnectInfo, Path, State, Query, Form},
+    response::{Html, Json, Redirect, IntoResponse, Response},
     routing::{get, post},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::net::SocketAddr;
-use tokio::sync::RwLock;
+use std::net::{IpAddr, SocketAddr};
+use tokio::sync::{RwLock, RwLockWriteGuard};
 use std::collections::HashMap;
+use uuid::Uuid;
 
 /// Captive portal configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,14 +50,38 @@ pub struct PortalConfig {
     pub terms_url: Option<String>,
 
     // Session limits
+    /// Hard session lifetime, however active the guest is; 0 for none
     pub session_timeout_minutes: u32,
+    /// Addresses one MAC may use at once under [`ConcurrentSessionPolicy::AllowConcurrent`]
     pub max_sessions_per_mac: u32,
+    /// Minutes without traffic after which a session loses access; 0 for never
     pub idle_timeout_minutes: u32,
+    /// Minutes after an idle expiry during which the device comes back
+    /// without the splash page
+    #[serde(default = "default_reauth_grace_minutes")]
+    pub reauth_grace_minutes: u32,
+    /// Session migration when guests move between access points
+    #[serde(default)]
+    pub roaming: RoamingConfig,
+    /// Devices let in without a login, e.g. printers and TVs
+    #[serde(default)]
+    pub bypass: Vec<NewBypassEntry>,
+    /// DHCP leases file (ISC dhcpd or dnsmasq), for bypass entries keyed on hostname
+    #[serde(default)]
+    pub dhcp_leases_path: Option<String>,
 
     // Bandwidth limits
     pub download_limit_kbps: Option<u64>,
     pub upload_limit_kbps: Option<u64>,
     pub total_quota_mb: Option<u64>,
+    /// Aggregate caps shared by groups of sessions
+    #[serde(default)]
+    pub bandwidth_groups: Vec<BandwidthGroup>,
+    /// Group of sessions whose login named none of `bandwidth_groups`
+    #[serde(default)]
+    pub default_group: Option<String>,
+    #[serde(default)]
+    pub fair_use: Option<FairUsePolicy>,
 
     // Access control
     pub allowed_domains: Vec<String>,  // Whitelist before auth
@@ -65,12 +95,19 @@ pub struct PortalConfig {
     pub enable_social_login: bool,
     pub facebook_app_id: Option<String>,
     pub google_client_id: Option<String>,
+    /// Providers for `AuthMethod::OAuth2`, e.g. [`OAuthProviderConfig::google`]
+    #[serde(default)]
+    pub oauth_providers: Vec<OAuthProviderConfig>,
 
     // Legal
     pub enable_logging: bool,
     pub data_retention_days: u32,
 }
 
+fn default_reauth_grace_minutes() -> u32 {
+    15
+}
+
 /// Client authentication request
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
@@ -88,8 +125,10 @@ pub struct PortalState {
     config: PortalConfig,
     sessions: Arc<RwLock<SessionManager>>,
     vouchers: Arc<RwLock<VoucherManager>>,
+    bypass: Arc<RwLock<BypassList>>,
     bandwidth: Arc<BandwidthLimiter>,
     auth_providers: HashMap<String, Box<dyn AuthProvider>>,
+    oauth: OAuthManager,
 }
 
 pub struct CaptivePortal {
@@ -98,21 +137,51 @@ pub struct CaptivePortal {
 
 impl CaptivePortal {
     pub fn new(config: PortalConfig) -> Result<Self, Box<dyn std::error::Error>> {
-        let sessions = Arc::new(RwLock::new(SessionManager::new()));
+        let minutes = |m: u32| (m > 0).then(|| chrono::Duration::minutes(m as i64));
+        let timeouts = SessionTimeouts {
+            idle: minutes(config.idle_timeout_minutes),
+            lifetime: minutes(config.session_timeout_minutes),
+            grace: chrono::Duration::minutes(config.reauth_grace_minutes as i64),
+        };
+        let sessions = Arc::new(RwLock::new(
+            SessionManager::with_roaming(config.roaming.clone(), config.max_sessions_per_mac).with_timeouts(timeouts),
+        ));
         let vouchers = Arc::new(RwLock::new(VoucherManager::new()));
-        let bandwidth = Arc::new(BandwidthLimiter::new());
+        let mut bypass = BypassList::new();
+        for entry in &config.bypass {
+            bypass.add(entry.clone())?;
+        }
+        let bypass = Arc::new(RwLock::new(bypass));
+        let bandwidth = Arc::new(BandwidthLimiter::new(&config.interface));
+        let oauth = OAuthManager::new(config.oauth_providers.clone());
 
         let state = Arc::new(PortalState {
             config,
             sessions,
             vouchers,
+            bypass,
             bandwidth,
             auth_providers: HashMap::new(),
+            oauth,
         });
 
         Ok(Self { state })
     }
 
+    /// Check username/password logins against `provider`
+    ///
+    /// Providers must be added before the portal starts serving.
+    pub fn add_auth_provider(&mut self, provider: Box<dyn AuthProvider>) {
+        let state = Arc::get_mut(&mut self.state)
+            .expect("auth providers are added before the portal is shared");
+        state.auth_providers.insert(provider.name().to_string(), provider);
+    }
+
+    /// Session state changes, e.g. for the analytics pipeline
+    pub async fn session_events(&self) -> tokio::sync::broadcast::Receiver<SessionEvent> {
+        self.state.sessions.read().await.subscribe()
+    }
+
     /// Start the captive portal HTTP server
     pub async fn serve(self) -> Result<(), Box<dyn std::error::Error>> {
         let app = Router::new()
@@ -127,14 +196,21 @@ impl CaptivePortal {
             .route("/voucher/redeem", post(redeem_voucher))
             .route("/voucher/check", get(check_voucher))
 
-            // Social login callbacks
-            .route("/auth/facebook/callback", get(facebook_callback))
-            .route("/auth/google/callback", get(google_callback))
+            // Social login
+            .route("/auth/:provider/start", get(oauth_start))
+            .route("/auth/:provider/callback", get(oauth_callback))
 
             // Admin API
             .route("/api/sessions", get(list_sessions))
             .route("/api/sessions/:id/terminate", post(terminate_session))
             .route("/api/vouchers/generate", post(generate_vouchers))
+            .route("/api/bypass", get(list_bypass).post(add_bypass))
+            .route("/api/bypass/:id", axum::routing::delete(remove_bypass))
+            .route("/api/usage", get(guest_usage))
+            .route("/api/usage/sessions", get(session_usage))
+            .route("/api/usage/groups", get(group_usage))
+            .route("/api/sessions/:id/limit", post(set_session_limit))
+            .route("/api/groups/:name", axum::routing::put(set_group_limit))
 
             // Assets
             .route("/static/*path", get(serve_static))
@@ -147,6 +223,12 @@ impl CaptivePortal {
         // Set up firewall rules for captive portal
         self.setup_firewall_rules().await?;
 
+        for group in &self.state.config.bandwidth_groups {
+            if let Err(e) = self.state.bandwidth.set_group(group).await {
+                tracing::warn!("Failed to set up bandwidth group {}: {}", group.name, e);
+            }
+        }
+
         // Start session cleanup background task
         self.start_session_cleanup().await;
 
@@ -232,21 +314,81 @@ table inet captive_portal {{
         Ok(())
     }
 
-    /// Background task to clean up expired sessions
+    /// Background task to clean up expired sessions and enforce data caps
     async fn start_session_cleanup(&self) {
-        let sessions = self.state.sessions.clone();
-        let timeout = self.state.config.session_timeout_minutes;
+        let state = self.state.clone();
 
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(
-                tokio::time::Duration::from_secs(60)
+                tokio::time::Duration::from_secs(15)
             );
 
             loop {
                 interval.tick().await;
 
-                let mut sessions = sessions.write().await;
-                sessions.cleanup_expired(timeout).await;
+                // Follow guests that moved to another access point and let
+                // in bypassed devices that showed up
+                let bypass_active = !state.bypass.read().await.is_empty();
+                if state.config.roaming.enabled || bypass_active {
+                    match neighbors(&state.config.interface).await {
+                        Ok(neighbors) => {
+                            if state.config.roaming.enabled {
+                                follow_roaming(&state, &neighbors).await;
+                            }
+                            if bypass_active {
+                                admit_bypassed(&state, &neighbors).await;
+                            }
+                        }
+                        Err(e) => tracing::debug!("Failed to read neighbor table: {}", e),
+                    }
+                }
+
+                let expired = state.bypass.write().await.remove_expired(chrono::Utc::now());
+                for entry in expired {
+                    tracing::info!("Bypass {} ({}) expired", entry.id, entry.description);
+                    end_bypass(&state, &entry.id).await;
+                }
+
+                // Usage first, so traffic counts as activity before idle expiry
+                let usage = state.bandwidth.poll_usage().await;
+                let mut exhausted = Vec::new();
+                let mut throttled = Vec::new();
+                {
+                    let mut sessions = state.sessions.write().await;
+                    for client in &usage {
+                        sessions.record_usage(client.ip, client.downloaded, client.uploaded).await;
+                    }
+                    if let Some(policy) = &state.config.fair_use {
+                        throttled = sessions.throttle_over_quota(
+                            policy.quota_mb * 1024 * 1024,
+                            policy.throttled_download_kbps,
+                            policy.throttled_upload_kbps,
+                        ).await;
+                    }
+                    // Caps count usage across every address a session has had
+                    for session in sessions.list_sessions().await {
+                        let capped = usage.iter().any(|u| u.exhausted && session.ip_addresses().any(|ip| ip == u.ip));
+                        if capped || session.is_exhausted() {
+                            exhausted.extend(sessions.terminate_by_mac(&session.mac_address).await);
+                        }
+                    }
+                    exhausted.extend(sessions.cleanup_expired().await);
+                }
+
+                for session in throttled {
+                    tracing::info!("Throttling session {} of {} past its fair-use quota", session.session_id, session.mac_address);
+                    for ip in session.ip_addresses() {
+                        shape(&state, &session, ip).await;
+                    }
+                }
+
+                for session in exhausted {
+                    tracing::info!("Ending session {} of {}", session.session_id, session.mac_address);
+                    revoke_access(&state, &session).await;
+                    if session.state == SessionState::IdleExpired {
+                        state.sessions.write().await.begin_grace(&session.session_id).await;
+                    }
+                }
             }
         });
     }
@@ -256,11 +398,42 @@ table inet captive_portal {{
 
 async fn portal_index(
     State(state): State<Arc<PortalState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     Query(params): Query<HashMap<String, String>>,
-) -> Html<String> {
+) -> Response {
     let redirect_url = params.get("redirect").cloned()
         .unwrap_or_else(|| "http://www.google.com".to_string());
 
+    // Controllers pass the client's MAC (and AP) when redirecting, otherwise
+    // it comes from the neighbor table; a guest with a session who moved to
+    // another access point goes straight on
+    let mac = match params.get("mac") {
+        Some(mac) => Some(mac.clone()),
+        None => mac_of(&state, client.ip()).await,
+    };
+    if let Some(mac) = &mac {
+        let outcome = state.sessions.write().await
+            .roam(mac, client.ip(), params.get("ap").map(String::as_str))
+            .await;
+        if apply_roam(&state, outcome).await {
+            return Redirect::to(&redirect_url).into_response();
+        }
+
+        // So does one returning within the grace window after going idle
+        let mut sessions = state.sessions.write().await;
+        if let Some(session) = sessions.resume(mac, client.ip()).await {
+            admit(&state, sessions, session, None).await;
+            return Redirect::to(&redirect_url).into_response();
+        }
+        drop(sessions);
+
+        // Bypassed devices never see the splash page
+        let hostnames = dhcp_hostnames(&state).await;
+        if admit_bypass(&state, mac, client.ip(), &hostnames).await.is_some() {
+            return Redirect::to(&redirect_url).into_response();
+        }
+    }
+
     let html = format!(r#"
 <!DOCTYPE html>
 <html>
@@ -412,7 +585,7 @@ async fn portal_index(
         }
     );
 
-    Html(html)
+    Html(html).into_response()
 }
 
 async fn login_page() -> Html<&'static str> {
@@ -424,48 +597,363 @@ async fn handle_login(
     Form(login): Form<LoginRequest>,
 ) -> Response {
     // Authenticate user
-    let authenticated = if let Some(voucher) = &login.voucher {
-        // Voucher authentication
-        let mut vouchers = state.vouchers.write().await;
-        vouchers.redeem(voucher).await.is_ok()
+    let ip = login.ip_address.parse().unwrap();
+    let granted: Result<ClientSession, String> = if let Some(voucher) = &login.voucher {
+        grant_voucher_access(&state, voucher, &login.mac_address, ip).await
     } else if let (Some(username), Some(password)) = (&login.username, &login.password) {
-        // Username/password authentication
-        // Check against configured auth providers
-        true  // Placeholder
+        // Username/password authentication against the configured providers
+        let credentials = AuthCredentials {
+            username: Some(username.clone()),
+            password: Some(password.clone()),
+            email: None,
+            phone: None,
+            oauth_token: None,
+        };
+        match authenticate_with_providers(&state, &credentials).await {
+            Ok(auth) => Ok(grant_access(&state, &login.mac_address, ip, Some(&auth), state.config.total_quota_mb).await),
+            Err(e) => Err(e),
+        }
     } else {
-        false
+        Err("Missing credentials".to_string())
     };
 
-    if authenticated {
-        // Create session
-        let mut sessions = state.sessions.write().await;
-        let session = sessions.create_session(
-            login.mac_address.clone(),
-            login.ip_address.parse().unwrap(),
-        ).await;
-
-        // Add MAC to nftables authenticated set
-        let _ = tokio::process::Command::new("nft")
-            .args(&["add", "element", "inet", "captive_portal", "authenticated_clients",
-                    &format!("{{ {} timeout 1h }}", login.mac_address)])
-            .output()
-            .await;
+    match granted {
+        Ok(_) => {
+            // Redirect to original URL
+            let redirect_url = login.redirect_url.unwrap_or_else(|| "http://www.google.com".to_string());
+            Redirect::to(&redirect_url).into_response()
+        }
+        Err(message) => {
+            (StatusCode::UNAUTHORIZED, format!("Authentication failed: {}", message)).into_response()
+        }
+    }
+}
+
+/// Create a session for an authenticated guest and let their traffic through
+async fn grant_access(
+    state: &PortalState,
+    mac: &str,
+    ip: IpAddr,
+    auth: Option<&AuthResult>,
+    quota_mb: Option<u64>,
+) -> ClientSession {
+    // Create session
+    let mut sessions = state.sessions.write().await;
+    let mut session = match auth {
+        Some(auth) => sessions.create_authenticated_session(mac.to_string(), ip, auth).await,
+        None => sessions.create_session(mac.to_string(), ip).await,
+    };
+
+    // The first of the user's groups with a bandwidth group of its own
+    session.group = auth.and_then(|auth| {
+        auth.user_info.groups.iter()
+            .find(|g| state.config.bandwidth_groups.iter().any(|b| b.name == **g))
+            .cloned()
+    });
+
+    admit(state, sessions, session, quota_mb).await
+}
+
+/// Redeem a voucher and create a session on it
+///
+/// Errors are meant for the guest, e.g. that the voucher is already online
+/// on as many devices as it allows.
+async fn grant_voucher_access(state: &PortalState, code: &str, mac: &str, ip: IpAddr) -> Result<ClientSession, String> {
+    // Held throughout, so the voucher cannot be used up between the check
+    // and the redemption
+    let mut vouchers = state.vouchers.write().await;
+    let voucher = vouchers.check(code).await.map_err(|e| e.to_string())?.clone();
+
+    let mut sessions = state.sessions.write().await;
+    let session = sessions.create_voucher_session(mac.to_string(), ip, &voucher).await
+        .map_err(|e| e.to_string())?;
+    vouchers.redeem(code).await.map_err(|e| e.to_string())?;
+    drop(vouchers);
+
+    let quota_mb = voucher.quota_mb.or(state.config.total_quota_mb);
+    Ok(admit(state, sessions, session, quota_mb).await)
+}
+
+/// Let a new session's traffic through and apply its limits
+async fn admit(
+    state: &PortalState,
+    mut sessions: RwLockWriteGuard<'_, SessionManager>,
+    mut session: ClientSession,
+    quota_mb: Option<u64>,
+) -> ClientSession {
+    // Add MAC to nftables authenticated set
+    let timeout = session.session_timeout_secs
+        .map(|secs| format!("{}s", secs))
+        .unwrap_or_else(|| "1h".to_string());
+    let _ = tokio::process::Command::new("nft")
+        .args(["add", "element", "inet", "captive_portal", "authenticated_clients",
+               &format!("{{ {} timeout {} }}", session.mac_address, timeout)])
+        .output()
+        .await;
+
+    // Bypassed devices stay out of the guests' shared cap
+    if session.group.is_none() && session.bypass_id.is_none() {
+        session.group = state.config.default_group.clone();
+    }
+
+    // Apply bandwidth limits, preferring those granted at login
+    session.download_limit_kbps = session.download_limit_kbps.or(state.config.download_limit_kbps);
+    session.upload_limit_kbps = session.upload_limit_kbps.or(state.config.upload_limit_kbps);
+    if let Some(mb) = quota_mb {
+        session.data_cap_bytes = Some(mb * 1024 * 1024);
+    }
+    sessions.update(session.clone()).await;
+    drop(sessions);
+
+    shape(state, &session, session.ip_address).await;
+
+    session
+}
 
-        // Apply bandwidth limits if configured
-        if let Some(download_limit) = state.config.download_limit_kbps {
-            state.bandwidth.set_limit(
-                &login.mac_address,
-                download_limit,
-                state.config.upload_limit_kbps.unwrap_or(download_limit),
-            ).await;
+/// Shape `ip` to a session's limits, capped at what is left of its data budget
+async fn shape(state: &PortalState, session: &ClientSession, ip: IpAddr) {
+    let limit = BandwidthLimit {
+        download_kbps: session.download_limit_kbps,
+        upload_kbps: session.upload_limit_kbps,
+        data_cap_bytes: session.data_remaining(),
+        group: session.group.clone(),
+        // Idle detection needs the counters
+        track_usage: state.config.idle_timeout_minutes > 0,
+    };
+
+    // Lifting every limit removes the shaping
+    if !limit.is_unlimited() || state.bandwidth.get_limit(ip).await.is_some() {
+        if let Err(e) = state.bandwidth.set_limit(ip, limit).await {
+            tracing::warn!("Failed to limit bandwidth of {}: {}", ip, e);
         }
+    }
+}
 
-        // Redirect to original URL
-        let redirect_url = login.redirect_url.unwrap_or_else(|| "http://www.google.com".to_string());
-        Redirect::to(&redirect_url).into_response()
-    } else {
-        (StatusCode::UNAUTHORIZED, "Authentication failed").into_response()
+/// Act on a roaming decision, returning whether the client may carry on
+/// without logging in
+async fn apply_roam(state: &PortalState, outcome: RoamOutcome) -> bool {
+    match outcome {
+        RoamOutcome::Unknown => false,
+        RoamOutcome::Unchanged(_) => true,
+        RoamOutcome::Roamed { session, released } => {
+            for ip in released {
+                state.bandwidth.remove_limit(ip).await;
+            }
+            shape(state, &session, session.ip_address).await;
+            true
+        }
+        RoamOutcome::Ended { session, reason } => {
+            tracing::info!("Ending session {} of {} ({:?})", session.session_id, session.mac_address, reason);
+            revoke_access(state, &session).await;
+            false
+        }
+    }
+}
+
+/// Roam sessions whose MAC address the neighbor table shows on a new address
+async fn follow_roaming(state: &PortalState, neighbors: &[(IpAddr, String)]) {
+    let sessions = state.sessions.read().await.list_sessions().await;
+    let policy = state.sessions.read().await.concurrent_policy();
+
+    for session in sessions {
+        let mac = session.mac_address.to_ascii_lowercase();
+        let seen: Vec<IpAddr> = neighbors.iter()
+            .filter(|(_, neighbor_mac)| *neighbor_mac == mac)
+            .map(|(ip, _)| *ip)
+            .collect();
+
+        // Migrating back and forth between two live addresses helps nobody
+        let still_held = seen.iter().any(|ip| session.ip_addresses().any(|held| held == *ip));
+        if still_held && policy == ConcurrentSessionPolicy::Migrate {
+            continue;
+        }
+
+        for ip in seen.into_iter().filter(|ip| session.ip_addresses().all(|held| held != *ip)) {
+            let outcome = state.sessions.write().await.roam(&session.mac_address, ip, None).await;
+            if !apply_roam(state, outcome).await {
+                break;
+            }
+        }
+    }
+}
+
+/// Reachable neighbors on `interface` as (address, lowercase MAC) pairs
+/// Let in neighbors matching a bypass entry that have no session yet
+async fn admit_bypassed(state: &PortalState, neighbors: &[(IpAddr, String)]) {
+    let hostnames = dhcp_hostnames(state).await;
+    for (ip, mac) in neighbors {
+        admit_bypass(state, mac, *ip, &hostnames).await;
+    }
+}
+
+/// Give `mac` a session if a bypass entry covers it and it has none
+async fn admit_bypass(
+    state: &PortalState,
+    mac: &str,
+    ip: IpAddr,
+    hostnames: &HashMap<String, String>,
+) -> Option<ClientSession> {
+    let mac = mac.to_ascii_lowercase();
+    let now = chrono::Utc::now();
+    let entry = state.bypass.read().await
+        .find(&mac, hostnames.get(&mac).map(String::as_str), now)
+        .cloned()?;
+
+    let mut sessions = state.sessions.write().await;
+    if sessions.get_by_mac(&mac).await.is_some() {
+        return None;
+    }
+    let session = sessions.create_bypass_session(mac.clone(), ip, &entry).await;
+    let session = admit(state, sessions, session, None).await;
+
+    state.bypass.write().await.record_use(&entry.id, &mac, now);
+    tracing::info!("Bypass {} ({}) admitted {} at {}", entry.id, entry.description, mac, ip);
+    Some(session)
+}
+
+/// End the sessions a bypass entry admitted and revoke their access
+async fn end_bypass(state: &PortalState, bypass_id: &Uuid) {
+    let ended = state.sessions.write().await.end_bypass_sessions(bypass_id).await;
+    for session in ended {
+        revoke_access(state, &session).await;
+    }
+}
+
+/// Hostnames from the DHCP leases, read only when an entry needs them
+async fn dhcp_hostnames(state: &PortalState) -> HashMap<String, String> {
+    let Some(path) = &state.config.dhcp_leases_path else {
+        return HashMap::new();
+    };
+    if !state.bypass.read().await.needs_hostnames() {
+        return HashMap::new();
+    }
+    match tokio::fs::read_to_string(path).await {
+        Ok(leases) => bypass::parse_dhcp_hostnames(&leases),
+        Err(e) => {
+            tracing::debug!("Failed to read DHCP leases {}: {}", path, e);
+            HashMap::new()
+        }
+    }
+}
+
+/// MAC address the neighbor table has for `ip`
+async fn mac_of(state: &PortalState, ip: IpAddr) -> Option<String> {
+    neighbors(&state.config.interface).await.ok()?
+        .into_iter()
+        .find(|(neighbor, _)| *neighbor == ip)
+        .map(|(_, mac)| mac)
+}
+
+async fn neighbors(interface: &str) -> std::io::Result<Vec<(IpAddr, String)>> {
+    let output = tokio::process::Command::new("ip")
+        .args(["neigh", "show", "dev", interface])
+        .output()
+        .await?;
+    Ok(parse_neighbors(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `ip neigh show dev <interface>` output, keeping entries confirmed
+/// recently enough to mean the client is there now
+fn parse_neighbors(output: &str) -> Vec<(IpAddr, String)> {
+    output.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields.first()?.parse().ok()?;
+            let mac = fields.iter().position(|f| *f == "lladdr").and_then(|i| fields.get(i + 1))?;
+            let state = fields.last()?;
+            matches!(*state, "REACHABLE" | "DELAY" | "PROBE")
+                .then(|| (ip, mac.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Social login request from the portal page
+#[derive(Debug, Deserialize)]
+pub struct OAuthStartRequest {
+    pub mac_address: String,
+    pub redirect_url: Option<String>,
+}
+
+/// Send the guest to the provider, remembering the login's state
+async fn oauth_start(
+    State(state): State<Arc<PortalState>>,
+    Path(provider): Path<String>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Query(request): Query<OAuthStartRequest>,
+) -> Response {
+    if !state.config.auth_methods.contains(&AuthMethod::OAuth2) {
+        return (StatusCode::NOT_FOUND, "Social login is not enabled").into_response();
+    }
+
+    match state.oauth.begin(&provider, &request.mac_address, client.ip(), request.redirect_url).await {
+        Ok(redirect) => {
+            let cookie = format!(
+                "{}={}; Path=/auth/{}; Max-Age=600; HttpOnly; SameSite=Lax",
+                oauth::STATE_COOKIE, redirect.state, provider
+            );
+            ([(header::SET_COOKIE, cookie)], Redirect::to(&redirect.url)).into_response()
+        }
+        Err(e) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finish a social login and admit the guest
+async fn oauth_callback(
+    State(state): State<Arc<PortalState>>,
+    Path(provider): Path<String>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(callback): Query<OAuthCallback>,
+) -> Response {
+    let (Some(code), Some(login_state)) = (callback.code, callback.state) else {
+        let reason = callback.error.unwrap_or_else(|| "missing authorization code".to_string());
+        return (StatusCode::UNAUTHORIZED, format!("Authentication failed: {}", reason)).into_response();
+    };
+    let cookie_state = headers.get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|cookies| oauth::cookie_value(cookies, oauth::STATE_COOKIE));
+
+    match state.oauth.complete(&provider, &code, &login_state, cookie_state, client.ip()).await {
+        Ok((login, auth)) => {
+            grant_access(&state, &login.mac_address, login.ip_address, Some(&auth), state.config.total_quota_mb).await;
+
+            let clear = format!("{}=; Path=/auth/{}; Max-Age=0", oauth::STATE_COOKIE, provider);
+            let redirect_url = login.redirect_url.unwrap_or_else(|| "http://www.google.com".to_string());
+            ([(header::SET_COOKIE, clear)], Redirect::to(&redirect_url)).into_response()
+        }
+        Err(e) => (StatusCode::UNAUTHORIZED, format!("Authentication failed: {}", e)).into_response(),
+    }
+}
+
+/// Try each provider until one accepts the credentials
+///
+/// A provider's rejection message is what the guest sees if none accepts.
+async fn authenticate_with_providers(
+    state: &PortalState,
+    credentials: &AuthCredentials,
+) -> Result<AuthResult, String> {
+    let mut message = "Invalid credentials".to_string();
+    for provider in state.auth_providers.values() {
+        match provider.authenticate(credentials).await {
+            Ok(result) if result.success => return Ok(result),
+            Ok(_) | Err(AuthError::InvalidCredentials) => {}
+            Err(AuthError::Rejected(reply)) => message = reply,
+            Err(e) => {
+                tracing::warn!("Auth provider {} failed: {}", provider.name(), e);
+                if message == "Invalid credentials" {
+                    message = e.to_string();
+                }
+            }
+        }
     }
+    Err(message)
 }
 
 async fn handle_logout(
@@ -474,25 +962,49 @@ async fn handle_logout(
 ) -> Response {
     if let Some(mac) = params.get("mac_address") {
         // Remove session
-        let mut sessions = state.sessions.write().await;
-        sessions.terminate_by_mac(mac).await;
-
-        // Remove from nftables
-        let _ = tokio::process::Command::new("nft")
-            .args(&["delete", "element", "inet", "captive_portal", "authenticated_clients",
-                    &format!("{{ {} }}", mac)])
-            .output()
-            .await;
-
-        // Remove bandwidth limits
-        state.bandwidth.remove_limit(mac).await;
+        let session = state.sessions.write().await.terminate_by_mac(mac).await;
+        if let Some(session) = session {
+            revoke_access(&state, &session).await;
+        }
     }
 
     Redirect::to("/").into_response()
 }
 
-async fn status_page() -> Html<&'static str> {
-    Html("<h1>Connection Status</h1>")
+/// Drop an ended session's client from the authenticated set and its shaping
+async fn revoke_access(state: &PortalState, session: &ClientSession) {
+    // Remove from nftables
+    let _ = tokio::process::Command::new("nft")
+        .args(["delete", "element", "inet", "captive_portal", "authenticated_clients",
+               &format!("{{ {} }}", session.mac_address)])
+        .output()
+        .await;
+
+    // Remove bandwidth limits
+    for ip in session.ip_addresses() {
+        state.bandwidth.remove_limit(ip).await;
+    }
+}
+
+async fn status_page(
+    State(state): State<Arc<PortalState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Html<String> {
+    let sessions = state.sessions.read().await;
+    let Some(session) = sessions.get_by_ip(client.ip()).await else {
+        return Html("<h1>Connection Status</h1><p>You are not connected.</p>".to_string());
+    };
+
+    let used_mb = (session.bytes_downloaded + session.bytes_uploaded) / (1024 * 1024);
+    let mut html = format!("<h1>Connection Status</h1><p>Data used: {} MB</p>", used_mb);
+    if session.throttled_at.is_some() {
+        html.push_str(&format!(
+            "<p class=\"notice\">You have used your fair-use allowance. Your connection is now limited to {} kbps down and {} kbps up.</p>",
+            session.download_limit_kbps.unwrap_or_default(),
+            session.upload_limit_kbps.unwrap_or_default(),
+        ));
+    }
+    Html(html)
 }
 
 async fn terms_page(State(state): State<Arc<PortalState>>) -> Html<String> {
@@ -520,14 +1032,6 @@ async fn check_voucher() -> impl IntoResponse {
     (StatusCode::OK, "Voucher valid")
 }
 
-async fn facebook_callback() -> impl IntoResponse {
-    Redirect::to("/")
-}
-
-async fn google_callback() -> impl IntoResponse {
-    Redirect::to("/")
-}
-
 async fn list_sessions() -> impl IntoResponse {
     (StatusCode::OK, "[]")
 }
@@ -540,6 +1044,109 @@ async fn generate_vouchers() -> impl IntoResponse {
     (StatusCode::OK, "Vouchers generated")
 }
 
+async fn list_bypass(State(state): State<Arc<PortalState>>) -> Json<Vec<BypassEntry>> {
+    Json(state.bypass.read().await.list())
+}
+
+async fn add_bypass(
+    State(state): State<Arc<PortalState>>,
+    Json(entry): Json<NewBypassEntry>,
+) -> Response {
+    match state.bypass.write().await.add(entry) {
+        Ok(entry) => {
+            tracing::info!("Added bypass {} ({})", entry.id, entry.description);
+            (StatusCode::CREATED, Json(entry)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+async fn remove_bypass(
+    State(state): State<Arc<PortalState>>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let Some(entry) = state.bypass.write().await.remove(&id) else {
+        return (StatusCode::NOT_FOUND, "No such bypass entry").into_response();
+    };
+    tracing::info!("Removed bypass {} ({})", entry.id, entry.description);
+    end_bypass(&state, &id).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn session_usage(State(state): State<Arc<PortalState>>) -> impl IntoResponse {
+    Json(state.sessions.read().await.session_usage().await)
+}
+
+async fn group_usage(State(state): State<Arc<PortalState>>) -> impl IntoResponse {
+    Json(state.sessions.read().await.group_usage().await)
+}
+
+#[derive(Debug, Deserialize)]
+struct LimitRequest {
+    download_kbps: Option<u64>,
+    upload_kbps: Option<u64>,
+    /// Move the session to another bandwidth group
+    #[serde(default)]
+    group: Option<String>,
+}
+
+/// Change a session's limits without disconnecting it
+async fn set_session_limit(
+    State(state): State<Arc<PortalState>>,
+    Path(id): Path<String>,
+    Json(request): Json<LimitRequest>,
+) -> Response {
+    if let Some(group) = &request.group {
+        if state.bandwidth.get_group(group).await.is_none() {
+            return (StatusCode::BAD_REQUEST, format!("Unknown bandwidth group {}", group)).into_response();
+        }
+    }
+
+    let mut sessions = state.sessions.write().await;
+    let Some(mut session) = sessions.get(&id).await.cloned() else {
+        return (StatusCode::NOT_FOUND, "No such session").into_response();
+    };
+    session.download_limit_kbps = request.download_kbps;
+    session.upload_limit_kbps = request.upload_kbps;
+    if request.group.is_some() {
+        session.group = request.group;
+    }
+    sessions.update(session.clone()).await;
+    drop(sessions);
+
+    for ip in session.ip_addresses() {
+        shape(&state, &session, ip).await;
+    }
+    Json(session).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupLimitRequest {
+    download_kbps: Option<u64>,
+    upload_kbps: Option<u64>,
+}
+
+/// Create a bandwidth group or change its caps in place
+async fn set_group_limit(
+    State(state): State<Arc<PortalState>>,
+    Path(name): Path<String>,
+    Json(request): Json<GroupLimitRequest>,
+) -> Response {
+    let group = BandwidthGroup {
+        name,
+        download_kbps: request.download_kbps,
+        upload_kbps: request.upload_kbps,
+    };
+    match state.bandwidth.set_group(&group).await {
+        Ok(()) => Json(group).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn guest_usage(State(state): State<Arc<PortalState>>) -> impl IntoResponse {
+    Json(state.sessions.read().await.guest_usage().await)
+}
+
 async fn serve_static() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "Not found")
 }
@@ -562,9 +1169,16 @@ impl Default for PortalConfig {
             session_timeout_minutes: 240,  // 4 hours
             max_sessions_per_mac: 1,
             idle_timeout_minutes: 30,
+            reauth_grace_minutes: default_reauth_grace_minutes(),
+            roaming: RoamingConfig::default(),
+            bypass: Vec::new(),
+            dhcp_leases_path: None,
             download_limit_kbps: Some(10000),  // 10 Mbps
             upload_limit_kbps: Some(5000),     // 5 Mbps
             total_quota_mb: None,
+            bandwidth_groups: Vec::new(),
+            default_group: None,
+            fair_use: None,
             allowed_domains: vec![],
             blocked_domains: vec![],
             enable_vouchers: true,
@@ -572,8 +1186,31 @@ impl Default for PortalConfig {
             enable_social_login: false,
             facebook_app_id: None,
             google_client_id: None,
+            oauth_providers: vec![],
             enable_logging: true,
             data_retention_days: 90,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_neighbors() {
+        let output = "\
+192.168.10.20 lladdr 02:00:00:00:00:AA REACHABLE
+192.168.10.10 lladdr 02:00:00:00:00:aa STALE
+192.168.10.30 FAILED
+fe80::1 lladdr 02:00:00:00:00:bb router DELAY
+";
+        assert_eq!(
+            parse_neighbors(output),
+            vec![
+                ("192.168.10.20".parse().unwrap(), "02:00:00:00:00:aa".to_string()),
+                ("fe80::1".parse().unwrap(), "02:00:00:00:00:bb".to_string()),
+            ],
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! RADIUS authentication provider (RFC 2865)
+//!
+//! Authenticates guests against an external RADIUS server with PAP or CHAP.
+//! Session-Timeout, Idle-Timeout and the WISPr bandwidth attributes of an
+//! Access-Accept become limits on the guest's session; the Reply-Message of
+//! an Access-Reject is passed back to the portal.
+
+use crate::auth::{AuthCredentials, AuthError, AuthProvider, AuthResult, SessionAttributes, UserInfo};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+const ACCESS_REQUEST: u8 = 1;
+const ACCESS_ACCEPT: u8 = 2;
+const ACCESS_REJECT: u8 = 3;
+const ACCESS_CHALLENGE: u8 = 11;
+
+const ATTR_USER_NAME: u8 = 1;
+const ATTR_USER_PASSWORD: u8 = 2;
+const ATTR_CHAP_PASSWORD: u8 = 3;
+const ATTR_FILTER_ID: u8 = 11;
+const ATTR_REPLY_MESSAGE: u8 = 18;
+const ATTR_VENDOR_SPECIFIC: u8 = 26;
+const ATTR_SESSION_TIMEOUT: u8 = 27;
+const ATTR_IDLE_TIMEOUT: u8 = 28;
+const ATTR_NAS_IDENTIFIER: u8 = 32;
+const ATTR_CHAP_CHALLENGE: u8 = 60;
+const ATTR_MESSAGE_AUTHENTICATOR: u8 = 80;
+
+const VENDOR_WISPR: u32 = 14122;
+const WISPR_BANDWIDTH_MAX_UP: u8 = 7;
+const WISPR_BANDWIDTH_MAX_DOWN: u8 = 8;
+
+const HEADER_LEN: usize = 20;
+const MAX_PACKET_LEN: usize = 4096;
+const MAX_ATTR_VALUE_LEN: usize = 253;
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// How the password is sent to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RadiusAuthType {
+    /// User-Password, hidden with the shared secret
+    Pap,
+    /// CHAP-Password over a random challenge
+    Chap,
+}
+
+/// What to do when the RADIUS server does not answer
+#[derive(Clone)]
+pub enum RadiusFallback {
+    /// Refuse the login
+    Deny,
+    /// Let the guest in, limited to a short session
+    Allow { session_timeout_secs: u32 },
+    /// Authenticate with another provider instead
+    Provider(Arc<dyn AuthProvider>),
+}
+
+pub struct RadiusAuthProvider {
+    server: String,
+    secret: String,
+    nas_identifier: String,
+    auth_type: RadiusAuthType,
+    timeout: Duration,
+    retries: u32,
+    fallback: RadiusFallback,
+}
+
+impl RadiusAuthProvider {
+    /// Provider for `server` (`host:port`, usually port 1812) sharing
+    /// `secret` with it
+    pub fn new(server: String, secret: String) -> Self {
+        Self {
+            server,
+            secret,
+            nas_identifier: "patronus-captiveportal".to_string(),
+            auth_type: RadiusAuthType::Pap,
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            fallback: RadiusFallback::Deny,
+        }
+    }
+
+    pub fn with_auth_type(mut self, auth_type: RadiusAuthType) -> Self {
+        self.auth_type = auth_type;
+        self
+    }
+
+    pub fn with_nas_identifier(mut self, nas_identifier: String) -> Self {
+        self.nas_identifier = nas_identifier;
+        self
+    }
+
+    /// Wait `timeout` for each attempt and resend up to `retries` times
+    pub fn with_timeout(mut self, timeout: Duration, retries: u32) -> Self {
+        self.timeout = timeout;
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_fallback(mut self, fallback: RadiusFallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    fn access_request(&self, username: &str, password: &str) -> Result<Packet, AuthError> {
+        if username.is_empty() || username.len() > MAX_ATTR_VALUE_LEN || password.len() > MAX_PASSWORD_LEN {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut authenticator = [0u8; 16];
+        rng.fill_bytes(&mut authenticator);
+
+        let mut request = Packet::new(ACCESS_REQUEST, rng.next_u32() as u8, authenticator);
+        request.push(ATTR_USER_NAME, username.as_bytes().to_vec());
+        match self.auth_type {
+            RadiusAuthType::Pap => {
+                let hidden = hide_password(password.as_bytes(), self.secret.as_bytes(), &authenticator);
+                request.push(ATTR_USER_PASSWORD, hidden);
+            }
+            RadiusAuthType::Chap => {
+                let chap_id = rng.next_u32() as u8;
+                let mut challenge = [0u8; 16];
+                rng.fill_bytes(&mut challenge);
+                let response = Md5::new()
+                    .chain_update([chap_id])
+                    .chain_update(password.as_bytes())
+                    .chain_update(challenge)
+                    .finalize();
+                let mut value = vec![chap_id];
+                value.extend_from_slice(&response);
+                request.push(ATTR_CHAP_PASSWORD, value);
+                request.push(ATTR_CHAP_CHALLENGE, challenge.to_vec());
+            }
+        }
+        request.push(ATTR_NAS_IDENTIFIER, self.nas_identifier.as_bytes().to_vec());
+        Ok(request)
+    }
+
+    /// Send `request` until a verified response arrives or every attempt
+    /// times out
+    async fn exchange(&self, request: &Packet) -> Result<Packet, AuthError> {
+        let unavailable = |e: std::io::Error| {
+            tracing::warn!("RADIUS server {} unreachable: {}", self.server, e);
+            AuthError::Unavailable
+        };
+        let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(unavailable)?;
+        socket.connect(&self.server).await.map_err(unavailable)?;
+        let encoded = request.encode_request(self.secret.as_bytes());
+
+        let mut buf = vec![0u8; MAX_PACKET_LEN];
+        for attempt in 0..=self.retries {
+            socket.send(&encoded).await.map_err(unavailable)?;
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let len = match tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) => return Err(unavailable(e)),
+                    Err(_) => break,
+                };
+                match Packet::decode_response(&buf[..len], request, self.secret.as_bytes()) {
+                    Ok(response) => return Ok(response),
+                    Err(reason) => tracing::warn!("Ignoring RADIUS response from {}: {}", self.server, reason),
+                }
+            }
+            tracing::debug!("RADIUS attempt {} to {} timed out", attempt + 1, self.server);
+        }
+
+        tracing::warn!("RADIUS server {} did not answer after {} attempts", self.server, self.retries + 1);
+        Err(AuthError::Unavailable)
+    }
+
+    async fn fall_back(&self, credentials: &AuthCredentials, username: &str) -> Result<AuthResult, AuthError> {
+        match &self.fallback {
+            RadiusFallback::Deny => Err(AuthError::Unavailable),
+            RadiusFallback::Allow { session_timeout_secs } => {
+                tracing::info!("Admitting {} without RADIUS for {}s", username, session_timeout_secs);
+                Ok(AuthResult {
+                    success: true,
+                    user_id: username.to_string(),
+                    user_info: UserInfo {
+                        name: Some(username.to_string()),
+                        email: None,
+                        groups: vec!["radius-fallback".to_string()],
+                    },
+                    session: SessionAttributes {
+                        session_timeout_secs: Some(*session_timeout_secs),
+                        ..Default::default()
+                    },
+                })
+            }
+            RadiusFallback::Provider(provider) => provider.authenticate(credentials).await,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for RadiusAuthProvider {
+    async fn authenticate(&self, credentials: &AuthCredentials) -> Result<AuthResult, AuthError> {
+        let username = credentials.username.as_ref()
+            .ok_or(AuthError::InvalidCredentials)?;
+        let password = credentials.password.as_ref()
+            .ok_or(AuthError::InvalidCredentials)?;
+
+        let request = self.access_request(username, password)?;
+        let response = match self.exchange(&request).await {
+            Ok(response) => response,
+            Err(AuthError::Unavailable) => return self.fall_back(credentials, username).await,
+            Err(e) => return Err(e),
+        };
+
+        let reply_message = response.reply_message();
+        match response.code {
+            ACCESS_ACCEPT => Ok(AuthResult {
+                success: true,
+                user_id: username.clone(),
+                user_info: UserInfo {
+                    name: Some(username.clone()),
+                    email: None,
+                    groups: response.strings(ATTR_FILTER_ID),
+                },
+                session: SessionAttributes {
+                    session_timeout_secs: response.u32(ATTR_SESSION_TIMEOUT),
+                    idle_timeout_secs: response.u32(ATTR_IDLE_TIMEOUT),
+                    download_limit_kbps: response.wispr_kbps(WISPR_BANDWIDTH_MAX_DOWN),
+                    upload_limit_kbps: response.wispr_kbps(WISPR_BANDWIDTH_MAX_UP),
+                    reply_message,
+                },
+            }),
+            ACCESS_REJECT => Err(AuthError::Rejected(
+                reply_message.unwrap_or_else(|| "Access denied".to_string()),
+            )),
+            ACCESS_CHALLENGE => Err(AuthError::Failed(
+                "RADIUS challenge-response is not supported".to_string(),
+            )),
+            code => Err(AuthError::Failed(format!("Unexpected RADIUS response code {}", code))),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "RADIUS"
+    }
+}
+
+/// A RADIUS packet with its attributes in wire order
+#[derive(Debug, Clone)]
+struct Packet {
+    code: u8,
+    identifier: u8,
+    authenticator: [u8; 16],
+    attributes: Vec<(u8, Vec<u8>)>,
+}
+
+impl Packet {
+    fn new(code: u8, identifier: u8, authenticator: [u8; 16]) -> Self {
+        Self { code, identifier, authenticator, attributes: Vec::new() }
+    }
+
+    fn push(&mut self, kind: u8, value: Vec<u8>) {
+        self.attributes.push((kind, value));
+    }
+
+    fn get(&self, kind: u8) -> Option<&[u8]> {
+        self.attributes.iter().find(|(k, _)| *k == kind).map(|(_, v)| v.as_slice())
+    }
+
+    fn u32(&self, kind: u8) -> Option<u32> {
+        self.get(kind)?.try_into().ok().map(u32::from_be_bytes)
+    }
+
+    fn strings(&self, kind: u8) -> Vec<String> {
+        self.attributes.iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, v)| String::from_utf8_lossy(v).into_owned())
+            .collect()
+    }
+
+    /// Reply-Message attributes joined into one message
+    fn reply_message(&self) -> Option<String> {
+        let lines = self.strings(ATTR_REPLY_MESSAGE);
+        (!lines.is_empty()).then(|| lines.concat())
+    }
+
+    /// WISPr bandwidth sub-attribute, converted from bit/s
+    fn wispr_kbps(&self, sub_kind: u8) -> Option<u64> {
+        self.attributes.iter()
+            .filter(|(k, v)| *k == ATTR_VENDOR_SPECIFIC && v.len() >= 4)
+            .filter(|(_, v)| u32::from_be_bytes([v[0], v[1], v[2], v[3]]) == VENDOR_WISPR)
+            .find_map(|(_, v)| {
+                let mut rest = &v[4..];
+                while rest.len() >= 2 {
+                    let len = rest[1] as usize;
+                    if len < 2 || len > rest.len() {
+                        return None;
+                    }
+                    if rest[0] == sub_kind && len == 6 {
+                        let bps = u32::from_be_bytes([rest[2], rest[3], rest[4], rest[5]]);
+                        return Some(bps as u64 / 1000);
+                    }
+                    rest = &rest[len..];
+                }
+                None
+            })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.code, self.identifier, 0, 0];
+        out.extend_from_slice(&self.authenticator);
+        for (kind, value) in &self.attributes {
+            out.push(*kind);
+            out.push((value.len() + 2) as u8);
+            out.extend_from_slice(value);
+        }
+        let len = out.len() as u16;
+        out[2..4].copy_from_slice(&len.to_be_bytes());
+        out
+    }
+
+    /// Encode an Access-Request, signing it with a Message-Authenticator
+    fn encode_request(&self, secret: &[u8]) -> Vec<u8> {
+        let mut signed = self.clone();
+        signed.push(ATTR_MESSAGE_AUTHENTICATOR, vec![0; 16]);
+        let mut out = signed.encode();
+        let signature = message_authenticator(secret, &out);
+        let at = out.len() - 16;
+        out[at..].copy_from_slice(&signature);
+        out
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, String> {
+        if data.len() < HEADER_LEN {
+            return Err(format!("packet too short ({} bytes)", data.len()));
+        }
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        if len < HEADER_LEN || len > data.len() {
+            return Err(format!("bad length {}", len));
+        }
+
+        let mut packet = Packet::new(data[0], data[1], data[4..HEADER_LEN].try_into().unwrap());
+        let mut rest = &data[HEADER_LEN..len];
+        while !rest.is_empty() {
+            if rest.len() < 2 || (rest[1] as usize) < 2 || rest[1] as usize > rest.len() {
+                return Err("malformed attribute".to_string());
+            }
+            let attr_len = rest[1] as usize;
+            packet.push(rest[0], rest[2..attr_len].to_vec());
+            rest = &rest[attr_len..];
+        }
+        Ok(packet)
+    }
+
+    /// Decode a response to `request`, checking its identifier, Response
+    /// Authenticator and, when present, Message-Authenticator
+    fn decode_response(data: &[u8], request: &Packet, secret: &[u8]) -> Result<Self, String> {
+        let response = Self::decode(data)?;
+        if response.identifier != request.identifier {
+            return Err(format!("identifier {} does not match request {}", response.identifier, request.identifier));
+        }
+
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let expected = Md5::new()
+            .chain_update(&data[..4])
+            .chain_update(request.authenticator)
+            .chain_update(&data[HEADER_LEN..len])
+            .chain_update(secret)
+            .finalize();
+        if expected.as_slice() != response.authenticator {
+            return Err("bad response authenticator (shared secret mismatch?)".to_string());
+        }
+
+        if let Some(signature) = response.get(ATTR_MESSAGE_AUTHENTICATOR) {
+            let mut unsigned = response.clone();
+            unsigned.authenticator = request.authenticator;
+            for (kind, value) in unsigned.attributes.iter_mut() {
+                if *kind == ATTR_MESSAGE_AUTHENTICATOR {
+                    value.fill(0);
+                }
+            }
+            if message_authenticator(secret, &unsigned.encode()).as_slice() != signature {
+                return Err("bad Message-Authenticator".to_string());
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// HMAC-MD5 over the whole packet (RFC 3579)
+fn message_authenticator(secret: &[u8], packet: &[u8]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(packet);
+    mac.finalize().into_bytes().into()
+}
+
+/// Hide a PAP password with the shared secret and request authenticator
+/// (RFC 2865 section 5.2)
+fn hide_password(password: &[u8], secret: &[u8], authenticator: &[u8; 16]) -> Vec<u8> {
+    let mut hidden = password.to_vec();
+    hidden.resize(password.len().div_ceil(16).max(1) * 16, 0);
+
+    let mut previous = authenticator.to_vec();
+    for block in hidden.chunks_mut(16) {
+        let key = Md5::new().chain_update(secret).chain_update(&previous).finalize();
+        for (byte, k) in block.iter_mut().zip(key.iter()) {
+            *byte ^= k;
+        }
+        previous = block.to_vec();
+    }
+    hidden
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    const SECRET: &str = "testing123";
+
+    fn reveal_password(hidden: &[u8], secret: &[u8], authenticator: &[u8; 16]) -> String {
+        let mut password = Vec::new();
+        let mut previous = authenticator.to_vec();
+        for block in hidden.chunks(16) {
+            let key = Md5::new().chain_update(secret).chain_update(&previous).finalize();
+            password.extend(block.iter().zip(key.iter()).map(|(b, k)| b ^ k));
+            previous = block.to_vec();
+        }
+        String::from_utf8(password).unwrap().trim_end_matches('\0').to_string()
+    }
+
+    fn wispr(sub_kind: u8, bps: u32) -> Vec<u8> {
+        let mut value = VENDOR_WISPR.to_be_bytes().to_vec();
+        value.extend_from_slice(&[sub_kind, 6]);
+        value.extend_from_slice(&bps.to_be_bytes());
+        value
+    }
+
+    /// Answer requests for "alice"/"wonderland" with an Access-Accept and
+    /// everything else with an Access-Reject, checking both PAP and CHAP
+    async fn mock_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_PACKET_LEN];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let request = Packet::decode(&buf[..len]).unwrap();
+                assert_eq!(request.code, ACCESS_REQUEST);
+
+                let mut unsigned = buf[..len].to_vec();
+                unsigned[len - 16..].fill(0);
+                assert_eq!(request.get(ATTR_MESSAGE_AUTHENTICATOR).unwrap(), message_authenticator(SECRET.as_bytes(), &unsigned));
+
+                let user = String::from_utf8(request.get(ATTR_USER_NAME).unwrap().to_vec()).unwrap();
+                let password_ok = if let Some(hidden) = request.get(ATTR_USER_PASSWORD) {
+                    reveal_password(hidden, SECRET.as_bytes(), &request.authenticator) == "wonderland"
+                } else {
+                    let chap = request.get(ATTR_CHAP_PASSWORD).unwrap();
+                    let challenge = request.get(ATTR_CHAP_CHALLENGE).unwrap();
+                    let expected = Md5::new()
+                        .chain_update([chap[0]])
+                        .chain_update(b"wonderland")
+                        .chain_update(challenge)
+                        .finalize();
+                    chap[1..] == expected[..]
+                };
+
+                let mut reply = Packet::new(ACCESS_REJECT, request.identifier, request.authenticator);
+                if user == "alice" && password_ok {
+                    reply.code = ACCESS_ACCEPT;
+                    reply.push(ATTR_SESSION_TIMEOUT, 3600u32.to_be_bytes().to_vec());
+                    reply.push(ATTR_IDLE_TIMEOUT, 600u32.to_be_bytes().to_vec());
+                    reply.push(ATTR_FILTER_ID, b"staff".to_vec());
+                    reply.push(ATTR_VENDOR_SPECIFIC, wispr(WISPR_BANDWIDTH_MAX_DOWN, 20_000_000));
+                    reply.push(ATTR_VENDOR_SPECIFIC, wispr(WISPR_BANDWIDTH_MAX_UP, 5_000_000));
+                } else {
+                    reply.push(ATTR_REPLY_MESSAGE, b"Your guest pass has expired".to_vec());
+                }
+
+                let mut out = reply.encode();
+                let signature = Md5::new().chain_update(&out).chain_update(SECRET).finalize();
+                out[4..HEADER_LEN].copy_from_slice(&signature);
+                socket.send_to(&out, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn credentials(username: &str, password: &str) -> AuthCredentials {
+        AuthCredentials {
+            username: Some(username.to_string()),
+            password: Some(password.to_string()),
+            email: None,
+            phone: None,
+            oauth_token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accept_maps_attributes_onto_session() {
+        let addr = mock_server().await;
+
+        for auth_type in [RadiusAuthType::Pap, RadiusAuthType::Chap] {
+            let provider = RadiusAuthProvider::new(addr.to_string(), SECRET.to_string())
+                .with_auth_type(auth_type);
+            let result = provider.authenticate(&credentials("alice", "wonderland")).await.unwrap();
+            assert_eq!(result.user_info.groups, vec!["staff"]);
+            assert_eq!(result.session, SessionAttributes {
+                session_timeout_secs: Some(3600),
+                idle_timeout_secs: Some(600),
+                download_limit_kbps: Some(20_000),
+                upload_limit_kbps: Some(5_000),
+                reply_message: None,
+            });
+
+            let mut sessions = crate::SessionManager::new();
+            let session = sessions
+                .create_authenticated_session("aa:bb:cc:dd:ee:ff".to_string(), "10.0.0.20".parse().unwrap(), &result)
+                .await;
+            assert_eq!(session.username.as_deref(), Some("alice"));
+            assert_eq!(session.session_timeout_secs, Some(3600));
+            assert_eq!(session.download_limit_kbps, Some(20_000));
+            assert!(!session.is_expired(session.last_activity + chrono::Duration::seconds(599)));
+            assert!(session.is_expired(session.last_activity + chrono::Duration::seconds(600)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reject_surfaces_reply_message() {
+        let addr = mock_server().await;
+        let provider = RadiusAuthProvider::new(addr.to_string(), SECRET.to_string());
+
+        match provider.authenticate(&credentials("alice", "looking-glass")).await {
+            Err(AuthError::Rejected(message)) => assert_eq!(message, "Your guest pass has expired"),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+
+        // A server with a different secret is not trusted
+        let provider = RadiusAuthProvider::new(addr.to_string(), "wrong".to_string())
+            .with_timeout(Duration::from_millis(100), 0);
+        assert!(matches!(
+            provider.authenticate(&credentials("alice", "wonderland")).await,
+            Err(AuthError::Unavailable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fallback() {
+        // Bound but never answers
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = silent.local_addr().unwrap().to_string();
+
+        let provider = RadiusAuthProvider::new(addr.clone(), SECRET.to_string())
+            .with_timeout(Duration::from_millis(50), 1);
+        assert!(matches!(
+            provider.authenticate(&credentials("alice", "wonderland")).await,
+            Err(AuthError::Unavailable)
+        ));
+
+        let provider = RadiusAuthProvider::new(addr.clone(), SECRET.to_string())
+            .with_timeout(Duration::from_millis(50), 1)
+            .with_fallback(RadiusFallback::Allow { session_timeout_secs: 900 });
+        let result = provider.authenticate(&credentials("alice", "anything")).await.unwrap();
+        assert_eq!(result.user_info.groups, vec!["radius-fallback"]);
+        assert_eq!(result.session.session_timeout_secs, Some(900));
+
+        let mut local = crate::auth::LocalAuthProvider::new();
+        local.add_user("alice".to_string(), "wonderland".to_string());
+        let provider = RadiusAuthProvider::new(addr, SECRET.to_string())
+            .with_timeout(Duration::from_millis(50), 0)
+            .with_fallback(RadiusFallback::Provider(Arc::new(local)));
+        assert!(provider.authenticate(&credentials("alice", "wonderland")).await.is_ok());
+        assert!(matches!(
+            provider.authenticate(&credentials("alice", "nope")).await,
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+}
@@ -1,11 +1,189 @@
 //! Client session management
+//!
+//! Sessions are keyed on the client's MAC address, so a guest moving to
+//! another access point keeps their session when they reappear on a new IP
+//! address: it is migrated with its remaining time and data budget rather
+//! than ending in a fresh login. What happens when the same MAC address is
+//! in use from several IP addresses at once is set by a
+//! [`ConcurrentSessionPolicy`].
+//!
+//! A session that moves no traffic for the idle timeout loses its access
+//! but is kept for a grace window, during which the device comes back
+//! without seeing the splash page. The hard lifetime ends a session however
+//! active it is. Each step is a [`SessionState`], and every change is
+//! published as a [`SessionEvent`].
 
+use crate::auth::AuthResult;
+use crate::bypass::BypassEntry;
+use crate::vouchers::Voucher;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
-use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+/// Source of the current time, replaced in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Where a session is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    #[default]
+    Active,
+    /// Idle for the idle timeout; its access is being revoked
+    IdleExpired,
+    /// Access revoked; the device resumes the session without logging in
+    /// if it returns before the grace window closes
+    Grace,
+    /// Ended for good
+    Gone,
+}
+
+/// Why a session changed state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionReason {
+    IdleTimeout,
+    /// The portal revoked the idle session's access
+    AccessRevoked,
+    /// The device came back within the grace window
+    Resumed,
+    GraceElapsed,
+    /// Past the hard session lifetime
+    LifetimeReached,
+    /// Terminated, out of data, replaced by a new login or lost to roaming
+    Ended,
+}
+
+/// A session state change, for analytics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub session_id: String,
+    pub mac_address: String,
+    pub from: SessionState,
+    pub to: SessionState,
+    pub reason: TransitionReason,
+    pub at: DateTime<Utc>,
+}
+
+/// Default session timing; limits granted at login take precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTimeouts {
+    /// No traffic for this long idle-expires a session
+    pub idle: Option<Duration>,
+    /// Longest a session lives, active or not
+    pub lifetime: Option<Duration>,
+    /// How long an idle-expired session can be resumed
+    pub grace: Duration,
+}
+
+impl Default for SessionTimeouts {
+    fn default() -> Self {
+        Self {
+            idle: Some(Duration::minutes(30)),
+            lifetime: None,
+            grace: Duration::minutes(15),
+        }
+    }
+}
+
+/// What to do when a MAC address with a session shows up on another IP
+/// address while its current one is still in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrentSessionPolicy {
+    /// Move the session to the new address, releasing the old one
+    #[default]
+    Migrate,
+    /// Keep both addresses on the session, sharing its limits, up to the
+    /// per-MAC maximum; past it the least recently active address is released
+    AllowConcurrent,
+    /// End the session and require a fresh login, e.g. against cloned MACs
+    Reauthenticate,
+}
+
+/// Session roaming settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoamingConfig {
+    /// Migrate sessions to new addresses; when off a new address needs a new login
+    pub enabled: bool,
+    pub concurrent_policy: ConcurrentSessionPolicy,
+    /// An address counts as still in use if it moved traffic this recently
+    pub concurrent_window_secs: u64,
+}
+
+impl Default for RoamingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            concurrent_policy: ConcurrentSessionPolicy::Migrate,
+            // A little over two usage polls
+            concurrent_window_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    /// Shown to the guest on the portal
+    #[error("This voucher is already in use on {max_devices} device(s). Disconnect one of them to use it here.")]
+    DeviceLimitReached { max_devices: u32 },
+}
+
+/// Why a session ended instead of roaming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoamEnd {
+    /// Past its session or idle timeout
+    Expired,
+    /// Data budget used up
+    Exhausted,
+    /// Still in use from another address under [`ConcurrentSessionPolicy::Reauthenticate`]
+    Concurrent,
+}
+
+/// Result of [`SessionManager::roam`]
+#[derive(Debug, Clone)]
+pub enum RoamOutcome {
+    /// No session for the MAC address, or roaming is disabled
+    Unknown,
+    /// The address already belongs to the session
+    Unchanged(ClientSession),
+    /// The session now holds the new address; `released` addresses were
+    /// dropped from it and should lose their shaping
+    Roamed {
+        session: ClientSession,
+        released: Vec<IpAddr>,
+    },
+    /// The session was ended and its access should be revoked
+    Ended {
+        session: ClientSession,
+        reason: RoamEnd,
+    },
+}
+
+/// Usage of one address held by a session
+#[derive(Debug, Clone)]
+struct Binding {
+    session_id: String,
+    /// Counters the shaper last reported for the address
+    downloaded: u64,
+    uploaded: u64,
+    last_active: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientSession {
     pub session_id: String,
@@ -17,57 +195,1023 @@ pub struct ClientSession {
     pub bytes_downloaded: u64,
     pub bytes_uploaded: u64,
     pub authenticated: bool,
+    /// Hard session limit granted at login, overriding the portal default
+    #[serde(default)]
+    pub session_timeout_secs: Option<u32>,
+    /// Idle limit granted at login, overriding the portal default
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u32>,
+    #[serde(default)]
+    pub download_limit_kbps: Option<u64>,
+    #[serde(default)]
+    pub upload_limit_kbps: Option<u64>,
+    /// Bytes in both directions after which the session is disconnected
+    #[serde(default)]
+    pub data_cap_bytes: Option<u64>,
+    /// Access point the client was last seen on, if the controller says
+    #[serde(default)]
+    pub access_point: Option<String>,
+    /// Further addresses in use under [`ConcurrentSessionPolicy::AllowConcurrent`]
+    #[serde(default)]
+    pub concurrent_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub roam_count: u32,
+    #[serde(default)]
+    pub last_roamed_at: Option<DateTime<Utc>>,
+    /// Voucher the guest logged in with
+    #[serde(default)]
+    pub voucher_code: Option<String>,
+    /// Bypass entry that admitted the device without a login
+    #[serde(default)]
+    pub bypass_id: Option<Uuid>,
+    #[serde(default)]
+    pub state: SessionState,
+    /// End of the grace window of an idle-expired session
+    #[serde(default)]
+    pub grace_until: Option<DateTime<Utc>>,
+    /// Bandwidth group whose aggregate cap the session shares
+    #[serde(default)]
+    pub group: Option<String>,
+    /// When the session passed its fair-use quota and was throttled
+    #[serde(default)]
+    pub throttled_at: Option<DateTime<Utc>>,
+}
+
+impl ClientSession {
+    /// Addresses the session holds, current one first
+    pub fn ip_addresses(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        std::iter::once(self.ip_address).chain(self.concurrent_ips.iter().copied())
+    }
+
+    /// Bytes left in the data budget, if capped
+    pub fn data_remaining(&self) -> Option<u64> {
+        self.data_cap_bytes
+            .map(|cap| cap.saturating_sub(self.bytes_downloaded + self.bytes_uploaded))
+    }
+
+    /// Whether the data budget is used up
+    pub fn is_exhausted(&self) -> bool {
+        self.data_remaining() == Some(0)
+    }
+
+    /// Whether the session has outlived its own limits at `now`
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        let past = |since: DateTime<Utc>, secs: Option<u32>| {
+            secs.is_some_and(|secs| now.signed_duration_since(since).num_seconds() >= secs as i64)
+        };
+        past(self.created_at, self.session_timeout_secs) || past(self.last_activity, self.idle_timeout_secs)
+    }
+}
+
+/// Guest activity across current sessions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestUsage {
+    pub sessions: usize,
+    pub unique_devices: usize,
+    pub bytes_downloaded: u64,
+    pub bytes_uploaded: u64,
+    /// Devices online through a bypass, not counted above
+    pub bypassed_devices: usize,
+}
+
+/// Bytes one session has moved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub session_id: String,
+    pub mac_address: String,
+    pub group: Option<String>,
+    pub bytes_downloaded: u64,
+    pub bytes_uploaded: u64,
+    pub throttled: bool,
+}
+
+/// Bytes the sessions of a bandwidth group have moved
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupUsage {
+    /// `None` for sessions outside any group
+    pub group: Option<String>,
+    pub sessions: usize,
+    pub throttled: usize,
+    pub bytes_downloaded: u64,
+    pub bytes_uploaded: u64,
 }
 
 pub struct SessionManager {
     sessions: HashMap<String, ClientSession>,
     mac_to_session: HashMap<String, String>,
+    bindings: HashMap<IpAddr, Binding>,
+    /// Bytes moved on addresses sessions no longer hold, by session ID
+    carried: HashMap<String, (u64, u64)>,
+    roaming: RoamingConfig,
+    /// Addresses a session may hold under [`ConcurrentSessionPolicy::AllowConcurrent`]
+    max_addresses: u32,
+    /// Idle-expired sessions and those in their grace window, by lowercase MAC
+    idle: HashMap<String, ClientSession>,
+    timeouts: SessionTimeouts,
+    clock: Arc<dyn Clock>,
+    events: broadcast::Sender<SessionEvent>,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
+        Self::with_roaming(RoamingConfig::default(), 1)
+    }
+
+    /// Session manager roaming per `roaming`, with at most `max_addresses`
+    /// addresses per MAC when concurrent use is allowed
+    pub fn with_roaming(roaming: RoamingConfig, max_addresses: u32) -> Self {
         Self {
             sessions: HashMap::new(),
             mac_to_session: HashMap::new(),
+            bindings: HashMap::new(),
+            carried: HashMap::new(),
+            roaming,
+            max_addresses: max_addresses.max(1),
+            idle: HashMap::new(),
+            timeouts: SessionTimeouts::default(),
+            clock: Arc::new(SystemClock),
+            events: broadcast::channel(256).0,
         }
     }
 
+    pub fn with_timeouts(mut self, timeouts: SessionTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Session state changes
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, session: &ClientSession, from: SessionState, to: SessionState, reason: TransitionReason) {
+        let _ = self.events.send(SessionEvent {
+            session_id: session.session_id.clone(),
+            mac_address: session.mac_address.clone(),
+            from,
+            to,
+            reason,
+            at: self.clock.now(),
+        });
+    }
+
+    /// How concurrent use of a MAC address is handled
+    pub fn concurrent_policy(&self) -> ConcurrentSessionPolicy {
+        self.roaming.concurrent_policy
+    }
+
     pub async fn create_session(&mut self, mac: String, ip: IpAddr) -> ClientSession {
+        // A fresh login replaces a session waiting out its grace window
+        if let Some(idle) = self.idle.remove(&mac.to_ascii_lowercase()) {
+            self.emit(&idle, idle.state, SessionState::Gone, TransitionReason::Ended);
+        }
+
+        let now = self.clock.now();
         let session_id = Uuid::new_v4().to_string();
         let session = ClientSession {
             session_id: session_id.clone(),
             mac_address: mac.clone(),
             ip_address: ip,
             username: None,
-            created_at: Utc::now(),
-            last_activity: Utc::now(),
+            created_at: now,
+            last_activity: now,
             bytes_downloaded: 0,
             bytes_uploaded: 0,
             authenticated: true,
+            session_timeout_secs: None,
+            idle_timeout_secs: None,
+            download_limit_kbps: None,
+            upload_limit_kbps: None,
+            data_cap_bytes: None,
+            access_point: None,
+            concurrent_ips: Vec::new(),
+            roam_count: 0,
+            last_roamed_at: None,
+            voucher_code: None,
+            bypass_id: None,
+            group: None,
+            throttled_at: None,
+            state: SessionState::Active,
+            grace_until: None,
         };
 
+        self.bind(ip, &session_id, session.created_at);
         self.sessions.insert(session_id.clone(), session.clone());
         self.mac_to_session.insert(mac, session_id);
 
         session
     }
 
+    /// Create a session for a guest an auth provider accepted, carrying
+    /// over the limits it returned
+    pub async fn create_authenticated_session(&mut self, mac: String, ip: IpAddr, auth: &AuthResult) -> ClientSession {
+        let mut session = self.create_session(mac, ip).await;
+        session.username = Some(auth.user_id.clone());
+        session.session_timeout_secs = auth.session.session_timeout_secs;
+        session.idle_timeout_secs = auth.session.idle_timeout_secs;
+        session.download_limit_kbps = auth.session.download_limit_kbps;
+        session.upload_limit_kbps = auth.session.upload_limit_kbps;
+
+        self.sessions.insert(session.session_id.clone(), session.clone());
+        session
+    }
+
+    /// Create a session for a guest logging in with `voucher`
+    ///
+    /// A voucher already online on its maximum number of devices turns away
+    /// MAC addresses it is not in use on. The session ends when the
+    /// voucher does and gets its bandwidth limits.
+    pub async fn create_voucher_session(
+        &mut self,
+        mac: String,
+        ip: IpAddr,
+        voucher: &Voucher,
+    ) -> Result<ClientSession, SessionError> {
+        let now = self.clock.now();
+        if let Some(max_devices) = voucher.max_devices {
+            let devices: HashSet<String> = self.sessions.values()
+                .filter(|s| s.voucher_code.as_deref() == Some(voucher.code.as_str()) && !s.is_expired(now))
+                .map(|s| s.mac_address.to_ascii_lowercase())
+                .collect();
+            if !devices.contains(&mac.to_ascii_lowercase()) && devices.len() >= max_devices as usize {
+                return Err(SessionError::DeviceLimitReached { max_devices });
+            }
+        }
+
+        let mut session = self.create_session(mac, ip).await;
+        let remaining = voucher.access_ends_at(now).signed_duration_since(now).num_seconds();
+        session.session_timeout_secs = Some(remaining.clamp(0, u32::MAX as i64) as u32);
+        session.download_limit_kbps = voucher.bandwidth_limit_kbps;
+        session.upload_limit_kbps = voucher.upload_limit_kbps;
+        session.voucher_code = Some(voucher.code.clone());
+
+        self.sessions.insert(session.session_id.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Create a session for a device admitted by a bypass `entry`
+    ///
+    /// The session lasts until the entry expires and gets its bandwidth
+    /// limits.
+    pub async fn create_bypass_session(&mut self, mac: String, ip: IpAddr, entry: &BypassEntry) -> ClientSession {
+        let mut session = self.create_session(mac, ip).await;
+        session.username = Some(format!("bypass:{}", entry.description));
+        session.session_timeout_secs = entry.expires_at.map(|at| {
+            at.signed_duration_since(session.created_at).num_seconds().clamp(0, u32::MAX as i64) as u32
+        });
+        session.download_limit_kbps = entry.download_limit_kbps;
+        session.upload_limit_kbps = entry.upload_limit_kbps;
+        session.bypass_id = Some(entry.id);
+
+        self.sessions.insert(session.session_id.clone(), session.clone());
+        session
+    }
+
+    /// End every session a bypass entry admitted, e.g. once it is removed
+    pub async fn end_bypass_sessions(&mut self, bypass_id: &Uuid) -> Vec<ClientSession> {
+        let ended: Vec<String> = self.sessions.values()
+            .filter(|s| s.bypass_id.as_ref() == Some(bypass_id))
+            .map(|s| s.session_id.clone())
+            .collect();
+        ended.iter().filter_map(|session_id| self.finish(session_id, TransitionReason::Ended)).collect()
+    }
+
+    /// Usage by guests, leaving out devices admitted through a bypass
+    pub async fn guest_usage(&self) -> GuestUsage {
+        let mut usage = GuestUsage::default();
+        let mut macs = HashSet::new();
+        for session in self.sessions.values() {
+            if session.bypass_id.is_some() {
+                usage.bypassed_devices += 1;
+                continue;
+            }
+            usage.sessions += 1;
+            macs.insert(session.mac_address.to_ascii_lowercase());
+            usage.bytes_downloaded += session.bytes_downloaded;
+            usage.bytes_uploaded += session.bytes_uploaded;
+        }
+        usage.unique_devices = macs.len();
+        usage
+    }
+
+    /// Current usage of every session
+    pub async fn session_usage(&self) -> Vec<SessionUsage> {
+        let mut usage: Vec<SessionUsage> = self.sessions.values()
+            .map(|s| SessionUsage {
+                session_id: s.session_id.clone(),
+                mac_address: s.mac_address.clone(),
+                group: s.group.clone(),
+                bytes_downloaded: s.bytes_downloaded,
+                bytes_uploaded: s.bytes_uploaded,
+                throttled: s.throttled_at.is_some(),
+            })
+            .collect();
+        usage.sort_by(|a, b| a.mac_address.cmp(&b.mac_address));
+        usage
+    }
+
+    /// Current usage of every bandwidth group with sessions
+    pub async fn group_usage(&self) -> Vec<GroupUsage> {
+        let mut groups: HashMap<Option<String>, GroupUsage> = HashMap::new();
+        for session in self.sessions.values() {
+            let usage = groups.entry(session.group.clone()).or_insert_with(|| GroupUsage {
+                group: session.group.clone(),
+                ..Default::default()
+            });
+            usage.sessions += 1;
+            usage.throttled += usize::from(session.throttled_at.is_some());
+            usage.bytes_downloaded += session.bytes_downloaded;
+            usage.bytes_uploaded += session.bytes_uploaded;
+        }
+        let mut groups: Vec<GroupUsage> = groups.into_values().collect();
+        groups.sort_by(|a, b| a.group.cmp(&b.group));
+        groups
+    }
+
+    /// Throttle sessions that moved `quota_bytes` or more to the given
+    /// rates, returning those newly throttled so their shaping can follow
+    ///
+    /// Bypassed devices are not held to the quota.
+    pub async fn throttle_over_quota(
+        &mut self,
+        quota_bytes: u64,
+        download_kbps: u64,
+        upload_kbps: u64,
+    ) -> Vec<ClientSession> {
+        let now = self.clock.now();
+        let mut throttled = Vec::new();
+        for session in self.sessions.values_mut() {
+            if session.throttled_at.is_some()
+                || session.bypass_id.is_some()
+                || session.bytes_downloaded + session.bytes_uploaded < quota_bytes
+            {
+                continue;
+            }
+            session.throttled_at = Some(now);
+            session.download_limit_kbps = Some(session.download_limit_kbps.map_or(download_kbps, |k| k.min(download_kbps)));
+            session.upload_limit_kbps = Some(session.upload_limit_kbps.map_or(upload_kbps, |k| k.min(upload_kbps)));
+            throttled.push(session.clone());
+        }
+        throttled
+    }
+
+    pub async fn get(&self, session_id: &str) -> Option<&ClientSession> {
+        self.sessions.get(session_id)
+    }
+
+    /// The session holding `ip`
+    pub async fn get_by_ip(&self, ip: IpAddr) -> Option<&ClientSession> {
+        self.bindings.get(&ip).and_then(|b| self.sessions.get(&b.session_id))
+    }
+
     pub async fn get_by_mac(&self, mac: &str) -> Option<&ClientSession> {
         self.mac_to_session.get(mac)
             .and_then(|id| self.sessions.get(id))
     }
 
-    pub async fn terminate_by_mac(&mut self, mac: &str) {
-        if let Some(session_id) = self.mac_to_session.remove(mac) {
-            self.sessions.remove(&session_id);
+    /// Replace a session's limits, e.g. with those the portal applies
+    pub async fn update(&mut self, session: ClientSession) {
+        if let Some(existing) = self.sessions.get_mut(&session.session_id) {
+            *existing = session;
+        }
+    }
+
+    pub async fn list_sessions(&self) -> Vec<ClientSession> {
+        self.sessions.values().cloned().collect()
+    }
+
+    /// Record the byte counters the shaper measured for `ip`
+    ///
+    /// The session's byte totals cover every address it has held.
+    pub async fn record_usage(&mut self, ip: IpAddr, downloaded: u64, uploaded: u64) {
+        let now = self.clock.now();
+        let Some(binding) = self.bindings.get_mut(&ip) else {
+            return;
+        };
+
+        // Counters restart when the shaping is reinstalled
+        if downloaded < binding.downloaded || uploaded < binding.uploaded {
+            let carried = self.carried.entry(binding.session_id.clone()).or_default();
+            carried.0 += binding.downloaded;
+            carried.1 += binding.uploaded;
+            binding.downloaded = 0;
+            binding.uploaded = 0;
+        }
+
+        let active = downloaded > binding.downloaded || uploaded > binding.uploaded;
+        binding.downloaded = downloaded;
+        binding.uploaded = uploaded;
+        if active {
+            binding.last_active = now;
+        }
+
+        let session_id = binding.session_id.clone();
+        self.refresh_totals(&session_id);
+        if let Some(session) = self.sessions.get_mut(&session_id).filter(|_| active) {
+            session.last_activity = now;
+        }
+    }
+
+    /// Resume the session of a known MAC address seen at `ip`
+    ///
+    /// A new address takes over the session with whatever time and data it
+    /// has left, unless the address it had is still in use, in which case
+    /// the [`ConcurrentSessionPolicy`] decides. Expired and exhausted
+    /// sessions are ended instead.
+    pub async fn roam(&mut self, mac: &str, ip: IpAddr, access_point: Option<&str>) -> RoamOutcome {
+        let now = self.clock.now();
+        let Some(session_id) = self.mac_to_session.get(mac).cloned() else {
+            return RoamOutcome::Unknown;
+        };
+        let Some(session) = self.sessions.get(&session_id) else {
+            return RoamOutcome::Unknown;
+        };
+
+        if session.is_expired(now) {
+            return self.end(&session_id, RoamEnd::Expired);
+        }
+        if session.is_exhausted() {
+            return self.end(&session_id, RoamEnd::Exhausted);
+        }
+
+        if session.ip_addresses().any(|held| held == ip) {
+            let session = self.sessions.get_mut(&session_id).unwrap();
+            if access_point.is_some() && session.access_point.as_deref() != access_point {
+                // Moved to another access point on the same address
+                session.access_point = access_point.map(str::to_string);
+                session.roam_count += 1;
+                session.last_roamed_at = Some(now);
+            }
+            return RoamOutcome::Unchanged(session.clone());
+        }
+
+        if !self.roaming.enabled {
+            return RoamOutcome::Unknown;
+        }
+
+        // Another session holding the address has lost it
+        if let Some(other) = self.bindings.get(&ip).map(|b| b.session_id.clone()) {
+            self.release(&other, ip);
+        }
+
+        let window = Duration::seconds(self.roaming.concurrent_window_secs as i64);
+        let mut held: Vec<(IpAddr, DateTime<Utc>)> = self.sessions[&session_id].ip_addresses()
+            .map(|addr| (addr, self.bindings.get(&addr).map_or(now - window, |b| b.last_active)))
+            .collect();
+        let in_use = held.iter().any(|(_, last_active)| now.signed_duration_since(*last_active) < window);
+
+        let released: Vec<IpAddr> = match (self.roaming.concurrent_policy, in_use) {
+            (ConcurrentSessionPolicy::Reauthenticate, true) => {
+                tracing::warn!("{} in use from {} and {}, ending its session", mac, held[0].0, ip);
+                return self.end(&session_id, RoamEnd::Concurrent);
+            }
+            (ConcurrentSessionPolicy::AllowConcurrent, true) => {
+                // Keep the most recently active addresses that fit alongside the new one
+                held.sort_by_key(|(_, last_active)| std::cmp::Reverse(*last_active));
+                let keep = (self.max_addresses - 1) as usize;
+                held.into_iter().skip(keep).map(|(addr, _)| addr).collect()
+            }
+            _ => held.into_iter().map(|(addr, _)| addr).collect(),
+        };
+
+        for addr in &released {
+            self.release(&session_id, *addr);
+        }
+        self.bind(ip, &session_id, now);
+
+        let session = self.sessions.get_mut(&session_id).unwrap();
+        let previous = std::mem::replace(&mut session.ip_address, ip);
+        session.concurrent_ips = std::iter::once(previous)
+            .chain(session.concurrent_ips.drain(..))
+            .filter(|addr| !released.contains(addr) && *addr != ip)
+            .collect();
+        if access_point.is_some() {
+            session.access_point = access_point.map(str::to_string);
+        }
+        session.roam_count += 1;
+        session.last_roamed_at = Some(now);
+        session.last_activity = now;
+
+        tracing::info!("Session {} of {} roamed to {}", session_id, mac, ip);
+
+        RoamOutcome::Roamed {
+            session: session.clone(),
+            released,
+        }
+    }
+
+    pub async fn terminate_by_mac(&mut self, mac: &str) -> Option<ClientSession> {
+        let session_id = self.mac_to_session.get(mac)?.clone();
+        self.finish(&session_id, TransitionReason::Ended)
+    }
+
+    fn end(&mut self, session_id: &str, reason: RoamEnd) -> RoamOutcome {
+        match self.finish(session_id, TransitionReason::Ended) {
+            Some(session) => RoamOutcome::Ended { session, reason },
+            None => RoamOutcome::Unknown,
+        }
+    }
+
+    fn bind(&mut self, ip: IpAddr, session_id: &str, now: DateTime<Utc>) {
+        self.bindings.insert(ip, Binding {
+            session_id: session_id.to_string(),
+            downloaded: 0,
+            uploaded: 0,
+            last_active: now,
+        });
+    }
+
+    /// Drop `ip` from a session, keeping the usage it recorded
+    fn release(&mut self, session_id: &str, ip: IpAddr) {
+        if let Some(binding) = self.bindings.remove(&ip) {
+            let carried = self.carried.entry(binding.session_id).or_default();
+            carried.0 += binding.downloaded;
+            carried.1 += binding.uploaded;
+        }
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            session.concurrent_ips.retain(|addr| *addr != ip);
+        }
+    }
+
+    /// Recompute a session's byte totals from its addresses
+    fn refresh_totals(&mut self, session_id: &str) {
+        let (mut downloaded, mut uploaded) = self.carried.get(session_id).copied().unwrap_or_default();
+        for binding in self.bindings.values().filter(|b| b.session_id == session_id) {
+            downloaded += binding.downloaded;
+            uploaded += binding.uploaded;
+        }
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            session.bytes_downloaded = downloaded;
+            session.bytes_uploaded = uploaded;
+        }
+    }
+
+    /// Remove a session and everything tracked for it
+    fn remove(&mut self, session_id: &str) -> Option<ClientSession> {
+        let session = self.sessions.remove(session_id)?;
+        if self.mac_to_session.get(&session.mac_address).map(String::as_str) == Some(session_id) {
+            self.mac_to_session.remove(&session.mac_address);
+        }
+        self.bindings.retain(|_, b| b.session_id != session_id);
+        self.carried.remove(session_id);
+        Some(session)
+    }
+
+    /// Remove an active session for good
+    fn finish(&mut self, session_id: &str, reason: TransitionReason) -> Option<ClientSession> {
+        let mut session = self.remove(session_id)?;
+        session.state = SessionState::Gone;
+        self.emit(&session, SessionState::Active, SessionState::Gone, reason);
+        Some(session)
+    }
+
+    fn idle_limit(&self, session: &ClientSession) -> Option<Duration> {
+        session.idle_timeout_secs.map(|secs| Duration::seconds(secs as i64)).or(self.timeouts.idle)
+    }
+
+    fn lifetime_limit(&self, session: &ClientSession) -> Option<Duration> {
+        session.session_timeout_secs.map(|secs| Duration::seconds(secs as i64)).or(self.timeouts.lifetime)
+    }
+
+    /// Expire sessions, returning those whose access must be revoked
+    ///
+    /// Sessions past their lifetime are gone. Idle ones are returned as
+    /// [`SessionState::IdleExpired`]; once their access is revoked,
+    /// [`begin_grace`](Self::begin_grace) opens their grace window. Sessions
+    /// whose grace window closed are dropped.
+    pub async fn cleanup_expired(&mut self) -> Vec<ClientSession> {
+        let now = self.clock.now();
+
+        let elapsed: Vec<String> = self.idle.iter()
+            .filter(|(_, s)| s.grace_until.is_none_or(|until| now >= until))
+            .map(|(mac, _)| mac.clone())
+            .collect();
+        for mac in elapsed {
+            if let Some(session) = self.idle.remove(&mac) {
+                self.emit(&session, session.state, SessionState::Gone, TransitionReason::GraceElapsed);
+            }
+        }
+
+        let mut expired = Vec::new();
+        let ids: Vec<String> = self.sessions.keys().cloned().collect();
+        for session_id in ids {
+            let session = &self.sessions[&session_id];
+            let lifetime_end = self.lifetime_limit(session).map(|limit| session.created_at + limit);
+            let idle = self.idle_limit(session)
+                .is_some_and(|limit| now.signed_duration_since(session.last_activity) >= limit);
+
+            if lifetime_end.is_some_and(|end| now >= end) {
+                expired.extend(self.finish(&session_id, TransitionReason::LifetimeReached));
+            } else if idle {
+                self.refresh_totals(&session_id);
+                let Some(mut session) = self.remove(&session_id) else {
+                    continue;
+                };
+                let grace_end = now + self.timeouts.grace;
+                session.state = SessionState::IdleExpired;
+                session.grace_until = Some(lifetime_end.map_or(grace_end, |end| end.min(grace_end)));
+                self.emit(&session, SessionState::Active, SessionState::IdleExpired, TransitionReason::IdleTimeout);
+                if let Some(replaced) = self.idle.insert(session.mac_address.to_ascii_lowercase(), session.clone()) {
+                    self.emit(&replaced, replaced.state, SessionState::Gone, TransitionReason::Ended);
+                }
+                expired.push(session);
+            }
+        }
+        expired
+    }
+
+    /// Mark an idle-expired session's access as revoked, opening its grace window
+    pub async fn begin_grace(&mut self, session_id: &str) {
+        let Some(session) = self.idle.values_mut()
+            .find(|s| s.session_id == session_id && s.state == SessionState::IdleExpired)
+        else {
+            return;
+        };
+        session.state = SessionState::Grace;
+        let session = session.clone();
+        self.emit(&session, SessionState::IdleExpired, SessionState::Grace, TransitionReason::AccessRevoked);
+    }
+
+    /// Bring back the idle-expired session of `mac`, now at `ip`, if its
+    /// grace window is still open
+    ///
+    /// The session keeps its usage, limits and lifetime. Its access has to
+    /// be granted again.
+    pub async fn resume(&mut self, mac: &str, ip: IpAddr) -> Option<ClientSession> {
+        let now = self.clock.now();
+        let key = mac.to_ascii_lowercase();
+        let open = self.idle.get(&key)?.grace_until.is_some_and(|until| now < until);
+        if !open || self.mac_to_session.contains_key(mac) {
+            return None;
+        }
+
+        let mut session = self.idle.remove(&key)?;
+        let from = session.state;
+        session.state = SessionState::Active;
+        session.grace_until = None;
+        session.ip_address = ip;
+        session.concurrent_ips.clear();
+        session.last_activity = now;
+
+        // Usage carries on from where it stopped
+        if let Some(other) = self.bindings.get(&ip).map(|b| b.session_id.clone()) {
+            self.release(&other, ip);
+        }
+        self.carried.insert(session.session_id.clone(), (session.bytes_downloaded, session.bytes_uploaded));
+        self.bind(ip, &session.session_id, now);
+        self.sessions.insert(session.session_id.clone(), session.clone());
+        self.mac_to_session.insert(session.mac_address.clone(), session.session_id.clone());
+
+        tracing::info!("Session {} of {} resumed at {} within its grace window", session.session_id, mac, ip);
+        self.emit(&session, from, SessionState::Active, TransitionReason::Resumed);
+        Some(session)
+    }
+
+    /// State of a session; `None` once it is gone
+    pub async fn state_of(&self, session_id: &str) -> Option<SessionState> {
+        if self.sessions.contains_key(session_id) {
+            return Some(SessionState::Active);
+        }
+        self.idle.values().find(|s| s.session_id == session_id).map(|s| s.state)
+    }
+
+    /// Sessions idle-expired or in their grace window
+    pub async fn idle_sessions(&self) -> Vec<ClientSession> {
+        self.idle.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: &str = "02:00:00:00:00:01";
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 10, last])
+    }
+
+    async fn capped_session(manager: &mut SessionManager) -> ClientSession {
+        let mut session = manager.create_session(MAC.to_string(), ip(10)).await;
+        session.download_limit_kbps = Some(2_000);
+        session.data_cap_bytes = Some(1_000);
+        manager.update(session.clone()).await;
+        session
+    }
+
+    #[tokio::test]
+    async fn test_mac_on_new_ip_reuses_session() {
+        let mut manager = SessionManager::new();
+        let session = capped_session(&mut manager).await;
+        manager.record_usage(ip(10), 300, 100).await;
+
+        let (roamed, released) = match manager.roam(MAC, ip(20), Some("ap-lobby")).await {
+            RoamOutcome::Roamed { session, released } => (session, released),
+            other => panic!("expected the session to roam, got {:?}", other),
+        };
+        assert_eq!(roamed.session_id, session.session_id);
+        assert_eq!(roamed.ip_address, ip(20));
+        assert_eq!(roamed.access_point.as_deref(), Some("ap-lobby"));
+        assert_eq!(roamed.download_limit_kbps, Some(2_000));
+        assert_eq!(roamed.data_remaining(), Some(600));
+        assert_eq!(released, vec![ip(10)]);
+
+        // Usage keeps adding up on the new address; the old one no longer counts
+        manager.record_usage(ip(10), 900, 100).await;
+        manager.record_usage(ip(20), 50, 0).await;
+        let current = manager.get_by_mac(MAC).await.unwrap();
+        assert_eq!((current.bytes_downloaded, current.bytes_uploaded), (350, 100));
+        assert_eq!(current.roam_count, 1);
+
+        assert!(matches!(manager.roam(MAC, ip(20), Some("ap-lobby")).await, RoamOutcome::Unchanged(_)));
+        assert!(matches!(manager.roam("02:00:00:00:00:99", ip(30), None).await, RoamOutcome::Unknown));
+
+        // Roaming does not stretch the budget
+        manager.record_usage(ip(20), 600, 0).await;
+        match manager.roam(MAC, ip(30), None).await {
+            RoamOutcome::Ended { reason, .. } => assert_eq!(reason, RoamEnd::Exhausted),
+            other => panic!("expected the session to end, got {:?}", other),
+        }
+        assert!(manager.get_by_mac(MAC).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_session_policies() {
+        let concurrent = |policy| RoamingConfig { concurrent_policy: policy, ..Default::default() };
+
+        // The old address is still moving traffic: log in again
+        let mut manager = SessionManager::with_roaming(concurrent(ConcurrentSessionPolicy::Reauthenticate), 1);
+        capped_session(&mut manager).await;
+        manager.record_usage(ip(10), 10, 10).await;
+        match manager.roam(MAC, ip(20), None).await {
+            RoamOutcome::Ended { reason, .. } => assert_eq!(reason, RoamEnd::Concurrent),
+            other => panic!("expected the session to end, got {:?}", other),
+        }
+
+        // Up to two addresses share the session; a third displaces the quietest
+        let mut manager = SessionManager::with_roaming(concurrent(ConcurrentSessionPolicy::AllowConcurrent), 2);
+        capped_session(&mut manager).await;
+        manager.record_usage(ip(10), 10, 10).await;
+        match manager.roam(MAC, ip(20), None).await {
+            RoamOutcome::Roamed { session, released } => {
+                assert_eq!(session.ip_addresses().collect::<Vec<_>>(), vec![ip(20), ip(10)]);
+                assert!(released.is_empty());
+            }
+            other => panic!("expected the session to roam, got {:?}", other),
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        manager.record_usage(ip(10), 20, 20).await;
+        manager.record_usage(ip(20), 5, 5).await;
+        match manager.roam(MAC, ip(30), None).await {
+            RoamOutcome::Roamed { session, released } => {
+                assert_eq!(released, vec![ip(10)]);
+                assert_eq!(session.ip_addresses().collect::<Vec<_>>(), vec![ip(30), ip(20)]);
+                assert_eq!(session.bytes_downloaded, 25);
+            }
+            other => panic!("expected the session to roam, got {:?}", other),
+        }
+
+        // With roaming off a new address needs a new login
+        let mut manager = SessionManager::with_roaming(RoamingConfig { enabled: false, ..Default::default() }, 1);
+        capped_session(&mut manager).await;
+        assert!(matches!(manager.roam(MAC, ip(20), None).await, RoamOutcome::Unknown));
+    }
+
+    #[tokio::test]
+    async fn test_expired_session_does_not_roam() {
+        let mut manager = SessionManager::new();
+        let mut session = manager.create_session(MAC.to_string(), ip(10)).await;
+        session.session_timeout_secs = Some(0);
+        manager.update(session).await;
+
+        match manager.roam(MAC, ip(20), None).await {
+            RoamOutcome::Ended { reason, .. } => assert_eq!(reason, RoamEnd::Expired),
+            other => panic!("expected the session to end, got {:?}", other),
+        }
+        assert!(manager.list_sessions().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_voucher_device_limit() {
+        use crate::vouchers::{CodeFormat, VoucherManager, VoucherPolicy, VoucherUsage};
+
+        let mut vouchers = VoucherManager::new();
+        let policy = VoucherPolicy {
+            usage: VoucherUsage::MultiUse { max_logins: 10 },
+            max_devices: 2,
+            ..Default::default()
+        };
+        let batch = vouchers.create_batch(1, &policy, &CodeFormat::default(), "test").unwrap();
+        let voucher = &batch.vouchers[0];
+
+        let mut manager = SessionManager::new();
+        let phone = manager.create_voucher_session(MAC.to_string(), ip(10), voucher).await.unwrap();
+        assert_eq!(phone.voucher_code.as_deref(), Some(voucher.code.as_str()));
+        assert_eq!(phone.session_timeout_secs, Some(24 * 3600));
+        manager.create_voucher_session("02:00:00:00:00:02".to_string(), ip(11), voucher).await.unwrap();
+
+        let err = manager.create_voucher_session("02:00:00:00:00:03".to_string(), ip(12), voucher).await.unwrap_err();
+        assert!(matches!(err, SessionError::DeviceLimitReached { max_devices: 2 }));
+        assert!(err.to_string().contains("already in use on 2 device(s)"));
+
+        // A device already on the voucher may log in again
+        assert!(manager.create_voucher_session(MAC.to_ascii_uppercase(), ip(10), voucher).await.is_ok());
+
+        // Once a device leaves, another can take its place
+        manager.terminate_by_mac("02:00:00:00:00:02").await.unwrap();
+        assert!(manager.create_voucher_session("02:00:00:00:00:03".to_string(), ip(12), voucher).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bypass_sessions_excluded_from_guest_usage() {
+        use crate::bypass::{BypassList, BypassMatch, NewBypassEntry};
+
+        let mut bypass = BypassList::new();
+        let printer = bypass.add(NewBypassEntry {
+            description: "Lobby printer".to_string(),
+            matcher: BypassMatch::Mac("a4:5e:60:12:34:56".to_string()),
+            expires_at: Some(Utc::now() + Duration::hours(1)),
+            download_limit_kbps: Some(512),
+            upload_limit_kbps: None,
+        }).unwrap();
+
+        let mut manager = SessionManager::new();
+        let device = manager.create_bypass_session("a4:5e:60:12:34:56".to_string(), ip(20), &printer).await;
+        assert_eq!(device.bypass_id, Some(printer.id));
+        assert_eq!(device.download_limit_kbps, Some(512));
+        assert!(device.session_timeout_secs.is_some_and(|secs| secs > 3_500 && secs <= 3_600));
+
+        manager.create_session(MAC.to_string(), ip(10)).await;
+        manager.record_usage(ip(10), 1_000, 200).await;
+        manager.record_usage(ip(20), 50_000, 50_000).await;
+
+        let usage = manager.guest_usage().await;
+        assert_eq!(usage, GuestUsage {
+            sessions: 1,
+            unique_devices: 1,
+            bytes_downloaded: 1_000,
+            bytes_uploaded: 200,
+            bypassed_devices: 1,
+        });
+
+        let ended = manager.end_bypass_sessions(&printer.id).await;
+        assert_eq!(ended.len(), 1);
+        assert!(manager.get_by_mac("a4:5e:60:12:34:56").await.is_none());
+        assert_eq!(manager.guest_usage().await.bypassed_devices, 0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_and_fair_use_throttling() {
+        let mut manager = SessionManager::new();
+        let mut guest = manager.create_session(MAC.to_string(), ip(10)).await;
+        guest.group = Some("guests".to_string());
+        guest.download_limit_kbps = Some(500);
+        manager.update(guest.clone()).await;
+        let mut staff = manager.create_session("02:00:00:00:00:02".to_string(), ip(11)).await;
+        staff.group = Some("staff".to_string());
+        manager.update(staff).await;
+        manager.create_session("02:00:00:00:00:03".to_string(), ip(12)).await;
+
+        manager.record_usage(ip(10), 900_000, 200_000).await;
+        manager.record_usage(ip(11), 300_000, 100_000).await;
+
+        let throttled = manager.throttle_over_quota(1_000_000, 1_000, 256).await;
+        assert_eq!(throttled.len(), 1);
+        assert_eq!(throttled[0].session_id, guest.session_id);
+        // Limits below the throttled tier stay
+        assert_eq!(throttled[0].download_limit_kbps, Some(500));
+        assert_eq!(throttled[0].upload_limit_kbps, Some(256));
+        assert!(manager.throttle_over_quota(1_000_000, 1_000, 256).await.is_empty());
+        assert!(manager.get_by_ip(ip(10)).await.unwrap().throttled_at.is_some());
+
+        let sessions = manager.session_usage().await;
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions[0].mac_address, MAC);
+        assert_eq!((sessions[0].bytes_downloaded, sessions[0].bytes_uploaded), (900_000, 200_000));
+        assert!(sessions[0].throttled);
+
+        let groups = manager.group_usage().await;
+        assert_eq!(groups.iter().map(|g| g.group.as_deref()).collect::<Vec<_>>(), vec![None, Some("guests"), Some("staff")]);
+        assert_eq!(groups[1].throttled, 1);
+        assert_eq!(groups[2].bytes_downloaded, 300_000);
+        assert_eq!(groups[0].sessions, 1);
+    }
+
+    /// Time that only moves when told to
+    struct ManualClock(std::sync::Mutex<DateTime<Utc>>);
+
+    impl ManualClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
         }
     }
 
-    pub async fn cleanup_expired(&mut self, timeout_minutes: u32) {
-        let now = Utc::now();
-        self.sessions.retain(|_, session| {
-            let age = now.signed_duration_since(session.last_activity);
-            age.num_minutes() < timeout_minutes as i64
+    fn timed_manager(timeouts: SessionTimeouts) -> (SessionManager, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(Utc::now())));
+        let manager = SessionManager::new().with_timeouts(timeouts).with_clock(clock.clone());
+        (manager, clock)
+    }
+
+    fn transitions(events: &mut broadcast::Receiver<SessionEvent>) -> Vec<(SessionState, SessionState, TransitionReason)> {
+        std::iter::from_fn(|| events.try_recv().ok()).map(|e| (e.from, e.to, e.reason)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_idle_expiry_grace_and_resume() {
+        let (mut manager, clock) = timed_manager(SessionTimeouts {
+            idle: Some(Duration::minutes(10)),
+            lifetime: Some(Duration::hours(4)),
+            grace: Duration::minutes(30),
+        });
+        let mut events = manager.subscribe();
+        let session = manager.create_session(MAC.to_string(), ip(10)).await;
+
+        // Traffic keeps the session active
+        clock.advance(Duration::minutes(8));
+        manager.record_usage(ip(10), 5_000, 1_000).await;
+        clock.advance(Duration::minutes(8));
+        assert!(manager.cleanup_expired().await.is_empty());
+
+        clock.advance(Duration::minutes(3));
+        let expired = manager.cleanup_expired().await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].state, SessionState::IdleExpired);
+        assert_eq!(manager.state_of(&session.session_id).await, Some(SessionState::IdleExpired));
+        assert!(manager.get_by_mac(MAC).await.is_none());
+
+        manager.begin_grace(&session.session_id).await;
+        assert_eq!(manager.state_of(&session.session_id).await, Some(SessionState::Grace));
+        assert_eq!(manager.idle_sessions().await.len(), 1);
+
+        // Back on another address within the window, usage intact
+        clock.advance(Duration::minutes(20));
+        let resumed = manager.resume(&MAC.to_ascii_uppercase(), ip(11)).await.unwrap();
+        assert_eq!(resumed.session_id, session.session_id);
+        assert_eq!(resumed.ip_address, ip(11));
+        assert_eq!(manager.state_of(&session.session_id).await, Some(SessionState::Active));
+        manager.record_usage(ip(11), 100, 0).await;
+        let current = manager.get_by_mac(MAC).await.unwrap();
+        assert_eq!((current.bytes_downloaded, current.bytes_uploaded), (5_100, 1_000));
+        assert!(manager.resume(MAC, ip(12)).await.is_none());
+
+        assert_eq!(transitions(&mut events), vec![
+            (SessionState::Active, SessionState::IdleExpired, TransitionReason::IdleTimeout),
+            (SessionState::IdleExpired, SessionState::Grace, TransitionReason::AccessRevoked),
+            (SessionState::Grace, SessionState::Active, TransitionReason::Resumed),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_grace_elapses_and_lifetime_is_independent() {
+        let (mut manager, clock) = timed_manager(SessionTimeouts {
+            idle: Some(Duration::minutes(10)),
+            lifetime: Some(Duration::minutes(60)),
+            grace: Duration::minutes(30),
         });
+        let mut events = manager.subscribe();
+        let leaver = manager.create_session(MAC.to_string(), ip(10)).await;
+        let busy = manager.create_session("02:00:00:00:00:02".to_string(), ip(11)).await;
+
+        clock.advance(Duration::minutes(10));
+        manager.record_usage(ip(11), 1_000, 0).await;
+        manager.cleanup_expired().await;
+        manager.begin_grace(&leaver.session_id).await;
+
+        // Gone once the window closes; a returning device logs in again
+        clock.advance(Duration::minutes(30));
+        manager.record_usage(ip(11), 2_000, 0).await;
+        assert!(manager.cleanup_expired().await.is_empty());
+        assert_eq!(manager.state_of(&leaver.session_id).await, None);
+        assert!(manager.resume(MAC, ip(10)).await.is_none());
+
+        // However busy, a session ends at its lifetime, without grace
+        clock.advance(Duration::minutes(19));
+        manager.record_usage(ip(11), 3_000, 0).await;
+        assert!(manager.cleanup_expired().await.is_empty());
+        clock.advance(Duration::minutes(1));
+        let ended = manager.cleanup_expired().await;
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].session_id, busy.session_id);
+        assert_eq!(ended[0].state, SessionState::Gone);
+        assert!(manager.idle_sessions().await.is_empty());
+
+        assert_eq!(transitions(&mut events), vec![
+            (SessionState::Active, SessionState::IdleExpired, TransitionReason::IdleTimeout),
+            (SessionState::IdleExpired, SessionState::Grace, TransitionReason::AccessRevoked),
+            (SessionState::Grace, SessionState::Gone, TransitionReason::GraceElapsed),
+            (SessionState::Active, SessionState::Gone, TransitionReason::LifetimeReached),
+        ]);
     }
 }
@@ -1,14 +1,32 @@
 //! Voucher management system for guest access
+//!
+//! Front-desk staff mint vouchers in batches that share a [`VoucherPolicy`]:
+//! how long access lasts, how many logins and devices a voucher admits and
+//! what bandwidth it gets. A batch can be exported as CSV or handed to a
+//! [`VoucherRenderer`] for printing, and revoked as a whole.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use chrono::{DateTime, Utc, Duration};
 use rand::Rng;
+use uuid::Uuid;
+
+/// Code alphabet without look-alikes (0/O, 1/I/L)
+const CODE_CHARSET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+
+/// Characters easily misread for one another on a printed card
+const AMBIGUOUS: &[u8] = b"0O1IL";
+
+/// Possible codes per voucher in a batch, so that guessing one is hopeless
+/// and collisions stay rare
+const MIN_CODE_SPACE_PER_VOUCHER: f64 = 1_000_000.0;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Voucher {
     pub code: String,
     pub created_at: DateTime<Utc>,
+    /// When access ends; for vouchers that start on first use, the
+    /// redeem-by date (or never) until then
     pub expires_at: DateTime<Utc>,
     pub duration_hours: u32,
     pub max_uses: u32,
@@ -17,6 +35,238 @@ pub struct Voucher {
     pub quota_mb: Option<u64>,
     pub created_by: String,
     pub notes: Option<String>,
+    /// Batch the voucher was minted in
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    #[serde(default)]
+    pub revoked: bool,
+    /// Access lasts `duration_hours` from the first login rather than from creation
+    #[serde(default)]
+    pub starts_on_first_use: bool,
+    #[serde(default)]
+    pub activated_at: Option<DateTime<Utc>>,
+    /// Devices that may be online on the voucher at once; any number when unset
+    #[serde(default)]
+    pub max_devices: Option<u32>,
+    #[serde(default)]
+    pub upload_limit_kbps: Option<u64>,
+    /// Name of the bandwidth tier, for display
+    #[serde(default)]
+    pub tier: Option<String>,
+}
+
+impl Voucher {
+    /// When access granted at `now` would end
+    pub fn access_ends_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if self.starts_on_first_use && self.activated_at.is_none() {
+            now + Duration::hours(self.duration_hours as i64)
+        } else {
+            self.expires_at
+        }
+    }
+
+    pub fn status(&self, now: DateTime<Utc>) -> VoucherStatus {
+        if self.revoked {
+            VoucherStatus::Revoked
+        } else if now > self.expires_at {
+            VoucherStatus::Expired
+        } else if self.used_count > 0 {
+            VoucherStatus::Activated
+        } else {
+            VoucherStatus::Unused
+        }
+    }
+
+    fn validity_text(&self) -> String {
+        if self.starts_on_first_use && self.activated_at.is_none() {
+            format!("{} hours from first login", self.duration_hours)
+        } else {
+            format!("until {}", self.expires_at.format("%Y-%m-%d %H:%M"))
+        }
+    }
+
+    /// Deadline printed on the card, if there is one
+    fn use_by(&self) -> Option<DateTime<Utc>> {
+        (self.expires_at != DateTime::<Utc>::MAX_UTC).then_some(self.expires_at)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoucherStatus {
+    Unused,
+    /// Redeemed at least once and still valid, whether or not logins remain
+    Activated,
+    Expired,
+    Revoked,
+}
+
+impl VoucherStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VoucherStatus::Unused => "unused",
+            VoucherStatus::Activated => "activated",
+            VoucherStatus::Expired => "expired",
+            VoucherStatus::Revoked => "revoked",
+        }
+    }
+}
+
+/// When a voucher's access ends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoucherValidity {
+    /// Access lasts `hours` from the first login; unused vouchers lapse at
+    /// `redeem_by`, if set
+    AfterFirstUse {
+        hours: u32,
+        redeem_by: Option<DateTime<Utc>>,
+    },
+    /// Access ends at a set time, however late the first login
+    FixedExpiry { expires_at: DateTime<Utc> },
+}
+
+/// How many times a voucher can be logged in with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoucherUsage {
+    SingleUse,
+    MultiUse { max_logins: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandwidthTier {
+    pub name: String,
+    pub download_kbps: Option<u64>,
+    pub upload_kbps: Option<u64>,
+}
+
+/// Terms shared by every voucher of a batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoucherPolicy {
+    pub validity: VoucherValidity,
+    pub usage: VoucherUsage,
+    /// Devices that may be online on one voucher at once
+    pub max_devices: u32,
+    /// Portal defaults apply when unset
+    pub bandwidth: Option<BandwidthTier>,
+    pub quota_mb: Option<u64>,
+}
+
+impl Default for VoucherPolicy {
+    fn default() -> Self {
+        Self {
+            validity: VoucherValidity::AfterFirstUse { hours: 24, redeem_by: None },
+            usage: VoucherUsage::SingleUse,
+            max_devices: 1,
+            bandwidth: None,
+            quota_mb: None,
+        }
+    }
+}
+
+impl VoucherPolicy {
+    fn validate(&self, now: DateTime<Utc>) -> Result<(), VoucherError> {
+        let invalid = |reason: &str| Err(VoucherError::InvalidPolicy(reason.to_string()));
+        match &self.validity {
+            VoucherValidity::AfterFirstUse { hours: 0, .. } => return invalid("access must last at least an hour"),
+            VoucherValidity::AfterFirstUse { redeem_by: Some(at), .. } if *at <= now => {
+                return invalid("redeem-by date is in the past")
+            }
+            VoucherValidity::FixedExpiry { expires_at } if *expires_at <= now => {
+                return invalid("expiry date is in the past")
+            }
+            _ => {}
+        }
+        match self.usage {
+            VoucherUsage::MultiUse { max_logins: 0 } => invalid("a multi-use voucher needs at least one login"),
+            _ if self.max_devices == 0 => invalid("a voucher must admit at least one device"),
+            VoucherUsage::SingleUse if self.max_devices > 1 => {
+                invalid("a single-use voucher admits one login, so one device")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Shape of generated voucher codes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeFormat {
+    /// Upper-cased and put in front as `PREFIX-`
+    pub prefix: String,
+    /// Random characters per code, not counting dashes
+    pub length: usize,
+    /// A dash after every this many characters; none when 0
+    pub group_size: usize,
+    /// Characters to draw from; lower case is upper-cased, and look-alikes
+    /// and anything not alphanumeric are dropped
+    pub charset: String,
+}
+
+impl Default for CodeFormat {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            length: 12,
+            group_size: 4,
+            charset: String::from_utf8_lossy(CODE_CHARSET).into_owned(),
+        }
+    }
+}
+
+impl CodeFormat {
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn alphabet(&self) -> Vec<u8> {
+        let mut alphabet: Vec<u8> = self.charset.to_ascii_uppercase().bytes()
+            .filter(|b| b.is_ascii_alphanumeric() && !AMBIGUOUS.contains(b))
+            .collect();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        alphabet
+    }
+
+    fn validate(&self, count: u32) -> Result<Vec<u8>, VoucherError> {
+        let alphabet = self.alphabet();
+        if alphabet.len() < 2 {
+            return Err(VoucherError::InvalidCodeFormat(
+                "charset needs at least two unambiguous characters".to_string(),
+            ));
+        }
+        if self.prefix.contains(|c: char| !c.is_ascii_alphanumeric()) {
+            return Err(VoucherError::InvalidCodeFormat(format!("prefix {:?} is not alphanumeric", self.prefix)));
+        }
+        let space = (alphabet.len() as f64).powi(self.length.min(64) as i32);
+        if space < count as f64 * MIN_CODE_SPACE_PER_VOUCHER {
+            return Err(VoucherError::InvalidCodeFormat(format!(
+                "{} characters from {} are too few to keep {} codes unguessable",
+                self.length,
+                alphabet.len(),
+                count
+            )));
+        }
+        Ok(alphabet)
+    }
+
+    fn generate(&self, alphabet: &[u8], rng: &mut impl Rng) -> String {
+        let mut code = String::with_capacity(self.prefix.len() + self.length * 2);
+        if !self.prefix.is_empty() {
+            code.push_str(&self.prefix.to_uppercase());
+            code.push('-');
+        }
+        for i in 0..self.length {
+            if i > 0 && self.group_size > 0 && i % self.group_size == 0 {
+                code.push('-');
+            }
+            code.push(alphabet[rng.gen_range(0..alphabet.len())] as char);
+        }
+        code
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,7 +274,32 @@ pub struct VoucherBatch {
     pub batch_id: String,
     pub created_at: DateTime<Utc>,
     pub count: u32,
+    /// Vouchers as minted; [`VoucherManager`] holds their current state
     pub vouchers: Vec<Voucher>,
+    #[serde(default)]
+    pub policy: Option<VoucherPolicy>,
+}
+
+/// How a batch's vouchers are being used
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchUsage {
+    pub total: usize,
+    pub unused: usize,
+    pub activated: usize,
+    /// Past their expiry, used or not, including those already cleaned up
+    pub expired: usize,
+    pub revoked: usize,
+    /// Logins across the batch
+    pub logins: u64,
+}
+
+/// Turns a batch into something to print
+pub trait VoucherRenderer {
+    /// MIME type of the output
+    fn content_type(&self) -> &'static str;
+
+    /// Render `vouchers`, the batch's current printable vouchers
+    fn render(&self, batch: &VoucherBatch, vouchers: &[&Voucher]) -> Result<Vec<u8>, VoucherError>;
 }
 
 pub struct VoucherManager {
@@ -40,72 +315,188 @@ impl VoucherManager {
         }
     }
 
-    /// Generate a batch of vouchers
-    pub async fn generate_batch(
+    /// Mint `count` vouchers sharing one batch id
+    ///
+    /// Codes look like `PREFIX-XXXX-XXXX-XXXX` and are unique across every
+    /// voucher this manager holds. The manager is shared behind a lock, so
+    /// batches created concurrently are checked against each other too.
+    pub fn generate_batch(
         &mut self,
         count: u32,
         duration_hours: u32,
-        bandwidth_limit_kbps: Option<u64>,
+        quota_mb: Option<u64>,
+        prefix: &str,
         created_by: String,
+    ) -> Result<Vec<Voucher>, VoucherError> {
+        let policy = VoucherPolicy {
+            validity: VoucherValidity::FixedExpiry {
+                expires_at: Utc::now() + Duration::hours(duration_hours as i64),
+            },
+            quota_mb,
+            ..Default::default()
+        };
+        let format = CodeFormat::with_prefix(prefix.trim());
+
+        Ok(self.create_batch(count, &policy, &format, &created_by)?.vouchers)
+    }
+
+    /// Mint `count` vouchers under one policy, with codes shaped by `format`
+    pub fn create_batch(
+        &mut self,
+        count: u32,
+        policy: &VoucherPolicy,
+        format: &CodeFormat,
+        created_by: &str,
+    ) -> Result<VoucherBatch, VoucherError> {
+        if count == 0 {
+            return Err(VoucherError::InvalidPolicy("a batch needs at least one voucher".to_string()));
+        }
+        policy.validate(Utc::now())?;
+        let alphabet = format.validate(count)?;
+
+        Ok(self.mint(count, policy, format, &alphabet, created_by))
+    }
+
+    fn mint(
+        &mut self,
+        count: u32,
+        policy: &VoucherPolicy,
+        format: &CodeFormat,
+        alphabet: &[u8],
+        created_by: &str,
     ) -> VoucherBatch {
         let batch_id = Self::generate_batch_id();
-        let mut vouchers = Vec::new();
+        let now = Utc::now();
+        let mut rng = rand::thread_rng();
 
-        for _ in 0..count {
-            let voucher = self.create_voucher(
-                duration_hours,
-                1,  // Single use
-                bandwidth_limit_kbps,
-                created_by.clone(),
-            );
-            vouchers.push(voucher);
+        let mut codes = HashSet::with_capacity(count as usize);
+        let mut vouchers = Vec::with_capacity(count as usize);
+        while vouchers.len() < count as usize {
+            let code = format.generate(alphabet, &mut rng);
+            if self.vouchers.contains_key(&code) || !codes.insert(code.clone()) {
+                continue;
+            }
+            vouchers.push(Self::policy_voucher(code, policy, now, created_by, &batch_id));
         }
 
         let batch = VoucherBatch {
             batch_id: batch_id.clone(),
-            created_at: Utc::now(),
+            created_at: now,
             count,
-            vouchers: vouchers.clone(),
+            vouchers,
+            policy: Some(policy.clone()),
         };
 
-        // Store vouchers
-        for voucher in vouchers {
-            self.vouchers.insert(voucher.code.clone(), voucher);
+        for voucher in &batch.vouchers {
+            self.vouchers.insert(voucher.code.clone(), voucher.clone());
         }
-
-        self.batches.insert(batch_id.clone(), batch.clone());
+        self.batches.insert(batch_id, batch.clone());
 
         batch
     }
 
-    fn create_voucher(
-        &self,
-        duration_hours: u32,
-        max_uses: u32,
-        bandwidth_limit_kbps: Option<u64>,
-        created_by: String,
+    fn policy_voucher(
+        code: String,
+        policy: &VoucherPolicy,
+        now: DateTime<Utc>,
+        created_by: &str,
+        batch_id: &str,
     ) -> Voucher {
+        let (duration_hours, expires_at, starts_on_first_use) = match &policy.validity {
+            VoucherValidity::AfterFirstUse { hours, redeem_by } => {
+                (*hours, redeem_by.unwrap_or(DateTime::<Utc>::MAX_UTC), true)
+            }
+            VoucherValidity::FixedExpiry { expires_at } => {
+                let hours = ((*expires_at - now).num_minutes().max(0) + 59) / 60;
+                (u32::try_from(hours).unwrap_or(u32::MAX), *expires_at, false)
+            }
+        };
+        let max_uses = match policy.usage {
+            VoucherUsage::SingleUse => 1,
+            VoucherUsage::MultiUse { max_logins } => max_logins,
+        };
+
         Voucher {
-            code: Self::generate_code(),
-            created_at: Utc::now(),
-            expires_at: Utc::now() + Duration::hours(duration_hours as i64),
+            code,
+            created_at: now,
+            expires_at,
             duration_hours,
             max_uses,
             used_count: 0,
-            bandwidth_limit_kbps,
-            quota_mb: None,
-            created_by,
+            bandwidth_limit_kbps: policy.bandwidth.as_ref().and_then(|t| t.download_kbps),
+            quota_mb: policy.quota_mb,
+            created_by: created_by.to_string(),
             notes: None,
+            batch_id: Some(batch_id.to_string()),
+            revoked: false,
+            starts_on_first_use,
+            activated_at: None,
+            max_devices: Some(policy.max_devices),
+            upload_limit_kbps: policy.bandwidth.as_ref().and_then(|t| t.upload_kbps),
+            tier: policy.bandwidth.as_ref().map(|t| t.name.clone()),
+        }
+    }
+
+    pub fn get_batch(&self, batch_id: &str) -> Option<&VoucherBatch> {
+        self.batches.get(batch_id)
+    }
+
+    /// Invalidate every voucher of a batch, returning how many there were
+    pub fn revoke_batch(&mut self, batch_id: &str) -> Result<usize, VoucherError> {
+        let batch = self.batches.get_mut(batch_id)
+            .ok_or(VoucherError::NotFound)?;
+
+        for voucher in batch.vouchers.iter_mut() {
+            voucher.revoked = true;
+            if let Some(stored) = self.vouchers.get_mut(&voucher.code) {
+                stored.revoked = true;
+            }
+        }
+
+        Ok(batch.vouchers.len())
+    }
+
+    /// Count a batch's vouchers by status
+    pub fn batch_usage(&self, batch_id: &str) -> Result<BatchUsage, VoucherError> {
+        let batch = self.batches.get(batch_id)
+            .ok_or(VoucherError::NotFound)?;
+
+        let now = Utc::now();
+        let mut usage = BatchUsage {
+            total: batch.vouchers.len(),
+            ..Default::default()
+        };
+        for minted in &batch.vouchers {
+            let Some(voucher) = self.vouchers.get(&minted.code) else {
+                usage.expired += 1;
+                continue;
+            };
+            usage.logins += voucher.used_count as u64;
+            match voucher.status(now) {
+                VoucherStatus::Unused => usage.unused += 1,
+                VoucherStatus::Activated => usage.activated += 1,
+                VoucherStatus::Expired => usage.expired += 1,
+                VoucherStatus::Revoked => usage.revoked += 1,
+            }
         }
+
+        Ok(usage)
     }
 
     /// Redeem a voucher
+    ///
+    /// The first redemption starts the clock on vouchers valid from first use.
     pub async fn redeem(&mut self, code: &str) -> Result<Voucher, VoucherError> {
-        let voucher = self.vouchers.get_mut(code)
+        let voucher = self.vouchers.get_mut(&normalize_code(code))
             .ok_or(VoucherError::NotFound)?;
 
+        if voucher.revoked {
+            return Err(VoucherError::Revoked);
+        }
+
         // Check expiry
-        if Utc::now() > voucher.expires_at {
+        let now = Utc::now();
+        if now > voucher.expires_at {
             return Err(VoucherError::Expired);
         }
 
@@ -114,6 +505,10 @@ impl VoucherManager {
             return Err(VoucherError::MaxUsesReached);
         }
 
+        if voucher.activated_at.is_none() {
+            voucher.expires_at = voucher.access_ends_at(now);
+            voucher.activated_at = Some(now);
+        }
         voucher.used_count += 1;
 
         Ok(voucher.clone())
@@ -121,9 +516,13 @@ impl VoucherManager {
 
     /// Check voucher validity
     pub async fn check(&self, code: &str) -> Result<&Voucher, VoucherError> {
-        let voucher = self.vouchers.get(code)
+        let voucher = self.vouchers.get(&normalize_code(code))
             .ok_or(VoucherError::NotFound)?;
 
+        if voucher.revoked {
+            return Err(VoucherError::Revoked);
+        }
+
         if Utc::now() > voucher.expires_at {
             return Err(VoucherError::Expired);
         }
@@ -146,24 +545,13 @@ impl VoucherManager {
         self.vouchers.retain(|_, v| v.expires_at > now);
     }
 
-    fn generate_code() -> String {
-        // Generate format: XXXX-XXXX-XXXX (12 chars)
-        let mut rng = rand::thread_rng();
-        let chars: String = (0..12)
-            .map(|i| {
-                if i > 0 && i % 4 == 0 {
-                    '-'
-                } else {
-                    let charset = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";  // No confusing chars
-                    charset[rng.gen_range(0..charset.len())] as char
-                }
-            })
-            .collect();
-        chars
+    fn generate_batch_id() -> String {
+        format!("BATCH-{}", Uuid::new_v4().simple())
     }
 
-    fn generate_batch_id() -> String {
-        format!("BATCH-{}", Utc::now().timestamp())
+    /// The batch's vouchers as they stand now, in minting order
+    fn current<'a>(&'a self, batch: &'a VoucherBatch) -> impl Iterator<Item = &'a Voucher> + 'a {
+        batch.vouchers.iter().map(|minted| self.vouchers.get(&minted.code).unwrap_or(minted))
     }
 
     /// Export vouchers to CSV for printing
@@ -171,20 +559,250 @@ impl VoucherManager {
         let batch = self.batches.get(batch_id)
             .ok_or(VoucherError::NotFound)?;
 
-        let mut csv = String::from("Code,Duration,Bandwidth Limit,Expires At\n");
+        let now = Utc::now();
+        let mut csv = String::from(
+            "Code,Validity,Tier,Bandwidth Limit,Upload Limit,Data Cap,Devices,Logins,Expires At,Batch,Status\n",
+        );
 
-        for voucher in &batch.vouchers {
+        let limit = |kbps: Option<u64>| kbps.map(|b| format!("{} kbps", b)).unwrap_or_else(|| "Unlimited".to_string());
+        for voucher in self.current(batch) {
             csv.push_str(&format!(
-                "{},{} hours,{},{}\n",
-                voucher.code,
-                voucher.duration_hours,
-                voucher.bandwidth_limit_kbps.map(|b| format!("{} kbps", b)).unwrap_or_else(|| "Unlimited".to_string()),
-                voucher.expires_at.format("%Y-%m-%d %H:%M")
+                "{},{},{},{},{},{},{},{}/{},{},{},{}\n",
+                csv_field(&voucher.code),
+                csv_field(&voucher.validity_text()),
+                csv_field(voucher.tier.as_deref().unwrap_or("")),
+                limit(voucher.bandwidth_limit_kbps),
+                limit(voucher.upload_limit_kbps),
+                voucher.quota_mb.map(|mb| format!("{} MB", mb)).unwrap_or_else(|| "Unlimited".to_string()),
+                voucher.max_devices.map(|d| d.to_string()).unwrap_or_else(|| "Unlimited".to_string()),
+                voucher.used_count,
+                voucher.max_uses,
+                voucher.use_by().map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+                batch.batch_id,
+                voucher.status(now).as_str(),
             ));
         }
 
         Ok(csv)
     }
+
+    /// Render a batch's printable vouchers, skipping revoked and expired ones
+    pub fn render_batch(&self, batch_id: &str, renderer: &dyn VoucherRenderer) -> Result<Vec<u8>, VoucherError> {
+        let batch = self.batches.get(batch_id)
+            .ok_or(VoucherError::NotFound)?;
+
+        let now = Utc::now();
+        let printable: Vec<&Voucher> = self.current(batch)
+            .filter(|v| matches!(v.status(now), VoucherStatus::Unused | VoucherStatus::Activated))
+            .collect();
+
+        renderer.render(batch, &printable)
+    }
+
+    /// Export a batch as an HTML sheet of cut-out cards
+    pub fn export_printable(&self, batch_id: &str, title: &str) -> Result<String, VoucherError> {
+        let html = self.render_batch(batch_id, &HtmlCardRenderer::new(title))?;
+        Ok(String::from_utf8(html).expect("HTML is rendered from strings"))
+    }
+
+    /// Export a batch as a PDF of cut-out cards
+    pub fn export_pdf(&self, batch_id: &str, title: &str) -> Result<Vec<u8>, VoucherError> {
+        self.render_batch(batch_id, &PdfCardRenderer::new(title))
+    }
+}
+
+/// What a card says below the code
+fn card_terms(voucher: &Voucher) -> Vec<String> {
+    let mut terms = vec![
+        voucher.validity_text(),
+        voucher.quota_mb.map(|mb| format!("{} MB", mb)).unwrap_or_else(|| "unlimited data".to_string()),
+    ];
+    if let Some(tier) = &voucher.tier {
+        terms.push(tier.clone());
+    }
+    if let Some(devices) = voucher.max_devices.filter(|d| *d > 1) {
+        terms.push(format!("{} devices", devices));
+    }
+    if voucher.starts_on_first_use {
+        if let Some(at) = voucher.use_by() {
+            terms.push(format!("use by {}", at.format("%Y-%m-%d")));
+        }
+    }
+    terms
+}
+
+/// Cut-out cards on an HTML page, for printing from a browser
+pub struct HtmlCardRenderer {
+    title: String,
+}
+
+impl HtmlCardRenderer {
+    pub fn new(title: &str) -> Self {
+        Self { title: title.to_string() }
+    }
+}
+
+impl VoucherRenderer for HtmlCardRenderer {
+    fn content_type(&self) -> &'static str {
+        "text/html; charset=utf-8"
+    }
+
+    fn render(&self, _batch: &VoucherBatch, vouchers: &[&Voucher]) -> Result<Vec<u8>, VoucherError> {
+        let cards: String = vouchers.iter()
+            .map(|v| format!(
+                r#"    <div class="card"><div class="title">{}</div><div class="code">{}</div><div class="terms">{}</div></div>
+"#,
+                html_escape(&self.title),
+                html_escape(&v.code),
+                card_terms(v).iter().map(|t| html_escape(t)).collect::<Vec<_>>().join(" &middot; "),
+            ))
+            .collect();
+
+        Ok(format!(r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <title>{} vouchers</title>
+    <style>
+        body {{ font-family: sans-serif; display: flex; flex-wrap: wrap; gap: 8px; }}
+        .card {{ border: 1px dashed #999; padding: 12px; width: 220px; page-break-inside: avoid; }}
+        .title {{ font-size: 12px; color: #666; }}
+        .code {{ font-family: monospace; font-size: 18px; margin: 6px 0; }}
+        .terms {{ font-size: 11px; color: #666; }}
+    </style>
+</head>
+<body>
+{}</body>
+</html>
+"#, html_escape(&self.title), cards).into_bytes())
+    }
+}
+
+/// Cut-out cards on A4 pages, two columns of five
+pub struct PdfCardRenderer {
+    title: String,
+}
+
+impl PdfCardRenderer {
+    const COLUMNS: usize = 2;
+    const ROWS: usize = 5;
+
+    pub fn new(title: &str) -> Self {
+        Self { title: title.to_string() }
+    }
+
+    fn page_content(&self, vouchers: &[&Voucher]) -> String {
+        let (margin, width, height) = (36.0, 256.0, 150.0);
+        let mut content = String::from("0.6 G [4 3] 0 d\n");
+        for (i, voucher) in vouchers.iter().enumerate() {
+            let x = margin + (i % Self::COLUMNS) as f64 * (width + 11.0);
+            let y = 842.0 - margin - ((i / Self::COLUMNS) + 1) as f64 * (height + 4.0);
+            content.push_str(&format!("{:.0} {:.0} {:.0} {:.0} re S\n", x, y, width, height));
+            content.push_str(&format!(
+                "BT /F1 10 Tf {:.0} {:.0} Td ({}) Tj ET\n",
+                x + 14.0,
+                y + height - 28.0,
+                pdf_escape(&self.title)
+            ));
+            content.push_str(&format!(
+                "BT /F2 16 Tf {:.0} {:.0} Td ({}) Tj ET\n",
+                x + 14.0,
+                y + height - 64.0,
+                pdf_escape(&voucher.code)
+            ));
+            for (line, term) in card_terms(voucher).iter().enumerate() {
+                content.push_str(&format!(
+                    "BT /F1 9 Tf {:.0} {:.0} Td ({}) Tj ET\n",
+                    x + 14.0,
+                    y + height - 90.0 - line as f64 * 12.0,
+                    pdf_escape(term)
+                ));
+            }
+        }
+        content
+    }
+}
+
+impl VoucherRenderer for PdfCardRenderer {
+    fn content_type(&self) -> &'static str {
+        "application/pdf"
+    }
+
+    fn render(&self, _batch: &VoucherBatch, vouchers: &[&Voucher]) -> Result<Vec<u8>, VoucherError> {
+        let pages: Vec<&[&Voucher]> = if vouchers.is_empty() {
+            vec![&[]]
+        } else {
+            vouchers.chunks(Self::COLUMNS * Self::ROWS).collect()
+        };
+
+        // Objects 1-4 are the catalog, page tree and fonts; each page
+        // follows as a page object and its content stream
+        let page_ids: Vec<usize> = (0..pages.len()).map(|i| 5 + 2 * i).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+                pages.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier-Bold >>".to_string(),
+        ];
+        for (page, id) in pages.iter().zip(&page_ids) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                id + 1
+            ));
+            let content = self.page_content(page);
+            objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes(),
+        );
+
+        Ok(pdf)
+    }
+}
+
+/// Codes are matched case-insensitively, ignoring surrounding whitespace
+fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// PDF string literal body; the standard fonts only cover ASCII here
+fn pdf_escape(value: &str) -> String {
+    value.chars()
+        .map(|c| match c {
+            '\\' | '(' | ')' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -195,4 +813,192 @@ pub enum VoucherError {
     Expired,
     #[error("Voucher maximum uses reached")]
     MaxUsesReached,
+    #[error("Voucher has been revoked")]
+    Revoked,
+    #[error("Invalid voucher policy: {0}")]
+    InvalidPolicy(String),
+    #[error("Invalid voucher code format: {0}")]
+    InvalidCodeFormat(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_batch_codes_are_unique_and_revocable() {
+        let mut manager = VoucherManager::new();
+        let vouchers = manager.generate_batch(1000, 24, Some(500), "cafe", "admin".to_string()).unwrap();
+
+        let codes: HashSet<&str> = vouchers.iter().map(|v| v.code.as_str()).collect();
+        assert_eq!(codes.len(), 1000);
+        let batch_id = vouchers[0].batch_id.clone().unwrap();
+        for voucher in &vouchers {
+            assert_eq!(voucher.batch_id.as_deref(), Some(batch_id.as_str()));
+            assert_eq!(voucher.quota_mb, Some(500));
+            assert_eq!(voucher.created_by, "admin");
+            let code = voucher.code.strip_prefix("CAFE-").unwrap();
+            assert_eq!(code.len(), 14);
+            assert!(code.bytes().all(|b| b == b'-' || CODE_CHARSET.contains(&b)));
+        }
+
+        let other = manager.generate_batch(3, 24, None, "", "admin".to_string()).unwrap();
+        assert!(matches!(
+            manager.generate_batch(3, 24, None, "café 1", "admin".to_string()),
+            Err(VoucherError::InvalidCodeFormat(_))
+        ));
+        assert!(manager.redeem(&vouchers[0].code.to_lowercase()).await.is_ok());
+
+        let csv = manager.export_to_csv(&batch_id).unwrap();
+        assert_eq!(csv.lines().count(), 1001);
+        assert!(csv.contains("500 MB"));
+        let sheet = manager.export_printable(&batch_id, "Cafe <Guest> WiFi").unwrap();
+        assert_eq!(sheet.matches(r#"class="card""#).count(), 1000);
+        assert!(sheet.contains("Cafe &lt;Guest&gt; WiFi"));
+
+        assert_eq!(manager.revoke_batch(&batch_id).unwrap(), 1000);
+        for voucher in &vouchers {
+            assert!(matches!(manager.check(&voucher.code).await, Err(VoucherError::Revoked)));
+        }
+        assert!(matches!(manager.redeem(&vouchers[1].code).await, Err(VoucherError::Revoked)));
+        assert!(manager.check(&other[0].code).await.is_ok());
+        assert!(!manager.export_printable(&batch_id, "Cafe").unwrap().contains(r#"class="card""#));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_batches_do_not_collide() {
+        let manager = Arc::new(RwLock::new(VoucherManager::new()));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = manager.clone();
+                tokio::spawn(async move { manager.write().await.generate_batch(250, 1, None, "", "admin".to_string()) })
+            })
+            .collect();
+
+        let mut codes = HashSet::new();
+        let mut batches = HashSet::new();
+        for task in tasks {
+            for voucher in task.await.unwrap().unwrap() {
+                assert!(codes.insert(voucher.code));
+                batches.insert(voucher.batch_id.unwrap());
+            }
+        }
+        assert_eq!(codes.len(), 2000);
+        assert_eq!(batches.len(), 8);
+        assert_eq!(manager.read().await.list_all().len(), 2000);
+    }
+
+    #[tokio::test]
+    async fn test_policy_batch_usage() {
+        let mut manager = VoucherManager::new();
+        let policy = VoucherPolicy {
+            validity: VoucherValidity::AfterFirstUse { hours: 8, redeem_by: None },
+            usage: VoucherUsage::MultiUse { max_logins: 3 },
+            max_devices: 2,
+            bandwidth: Some(BandwidthTier {
+                name: "Premium".to_string(),
+                download_kbps: Some(20_000),
+                upload_kbps: Some(5_000),
+            }),
+            quota_mb: None,
+        };
+        let format = CodeFormat {
+            prefix: "Lobby".to_string(),
+            length: 10,
+            group_size: 5,
+            charset: "abcdefgh23456789".to_string(),
+        };
+        let batch = manager.create_batch(200, &policy, &format, "front-desk").unwrap();
+
+        assert_eq!(batch.vouchers.len(), 200);
+        let voucher = &batch.vouchers[0];
+        assert_eq!(voucher.code.len(), "LOBBY-".len() + 11);
+        assert!(voucher.code["LOBBY-".len()..].bytes().all(|b| b == b'-' || b"ABCDEFGH23456789".contains(&b)));
+        assert_eq!((voucher.max_uses, voucher.max_devices), (3, Some(2)));
+        assert_eq!((voucher.bandwidth_limit_kbps, voucher.upload_limit_kbps), (Some(20_000), Some(5_000)));
+        assert_eq!(voucher.expires_at, DateTime::<Utc>::MAX_UTC);
+
+        // The clock starts at the first login and is not reset by later ones
+        let before = Utc::now();
+        let first = manager.redeem(&voucher.code).await.unwrap();
+        assert!(first.expires_at >= before + Duration::hours(8));
+        assert!(first.expires_at <= Utc::now() + Duration::hours(8));
+        let second = manager.redeem(&voucher.code).await.unwrap();
+        assert_eq!(second.expires_at, first.expires_at);
+
+        let code = batch.vouchers[1].code.clone();
+        manager.vouchers.get_mut(&code).unwrap().expires_at = Utc::now() - Duration::minutes(1);
+        let usage = manager.batch_usage(&batch.batch_id).unwrap();
+        assert_eq!(usage, BatchUsage { total: 200, unused: 198, activated: 1, expired: 1, revoked: 0, logins: 2 });
+
+        let csv = manager.export_to_csv(&batch.batch_id).unwrap();
+        let row = csv.lines().find(|l| l.starts_with(&voucher.code)).unwrap();
+        assert!(row.contains(",Premium,20000 kbps,5000 kbps,Unlimited,2,2/3,"));
+        assert!(row.ends_with(",activated"));
+        assert!(csv.contains("8 hours from first login"));
+
+        manager.revoke_batch(&batch.batch_id).unwrap();
+        let usage = manager.batch_usage(&batch.batch_id).unwrap();
+        assert_eq!((usage.revoked, usage.unused, usage.activated), (200, 0, 0));
+    }
+
+    #[test]
+    fn test_invalid_policies_and_formats() {
+        let mut manager = VoucherManager::new();
+        let format = CodeFormat::default();
+        let policy = |validity, usage, max_devices| VoucherPolicy { validity, usage, max_devices, bandwidth: None, quota_mb: None };
+        let day = VoucherValidity::AfterFirstUse { hours: 24, redeem_by: None };
+
+        let invalid = [
+            policy(day.clone(), VoucherUsage::SingleUse, 2),
+            policy(day.clone(), VoucherUsage::MultiUse { max_logins: 5 }, 0),
+            policy(day.clone(), VoucherUsage::MultiUse { max_logins: 0 }, 1),
+            policy(VoucherValidity::AfterFirstUse { hours: 0, redeem_by: None }, VoucherUsage::SingleUse, 1),
+            policy(
+                VoucherValidity::FixedExpiry { expires_at: Utc::now() - Duration::hours(1) },
+                VoucherUsage::SingleUse,
+                1,
+            ),
+        ];
+        for policy in &invalid {
+            assert!(matches!(manager.create_batch(1, policy, &format, "test"), Err(VoucherError::InvalidPolicy(_))));
+        }
+
+        let ok = policy(day, VoucherUsage::SingleUse, 1);
+        // Nothing left once look-alikes are dropped
+        let ambiguous = CodeFormat { charset: "0O1Il".to_string(), ..Default::default() };
+        let short = CodeFormat { length: 4, ..Default::default() };
+        for format in [ambiguous, short] {
+            assert!(matches!(manager.create_batch(200, &ok, &format, "test"), Err(VoucherError::InvalidCodeFormat(_))));
+        }
+        assert!(manager.list_all().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pdf_export() {
+        let mut manager = VoucherManager::new();
+        let batch = manager.create_batch(23, &VoucherPolicy::default(), &CodeFormat::default(), "test").unwrap();
+
+        let pdf = manager.export_pdf(&batch.batch_id, "Guest (Lobby)").unwrap();
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 3 "));
+        assert!(text.contains("(Guest \\(Lobby\\)) Tj"));
+        assert_eq!(text.matches(" re S\n").count(), 23);
+        for voucher in &batch.vouchers {
+            assert!(text.contains(&format!("({}) Tj", voucher.code)));
+        }
+
+        // Every cross-reference entry points at its object
+        let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        let entries: Vec<&str> = text[startxref..].lines().skip(3).take_while(|l| l.ends_with(" n ")).collect();
+        assert_eq!(entries.len(), 4 + 2 * 3);
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj\n", i + 1)));
+        }
+    }
 }
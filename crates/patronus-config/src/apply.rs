@@ -16,6 +16,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
+use tokio::sync::watch;
 
 use crate::declarative::{DeclarativeConfig, ResourceKind, ConfigParser};
 
@@ -246,6 +247,7 @@ impl StateManager {
 pub struct ApplyEngine {
     state_manager: StateManager,
     dry_run: bool,
+    applied: watch::Sender<Vec<DeclarativeConfig>>,
 }
 
 impl ApplyEngine {
@@ -253,11 +255,29 @@ impl ApplyEngine {
         Self {
             state_manager: StateManager::new(state_dir),
             dry_run: false,
+            applied: watch::channel(Vec::new()).0,
         }
     }
 
     pub async fn init(&mut self) -> Result<()> {
-        self.state_manager.init().await
+        self.state_manager.init().await?;
+        self.publish();
+        Ok(())
+    }
+
+    /// Watch the applied configuration, for subsystems that hot-reload
+    /// their resources; the receiver starts with the current state
+    pub fn subscribe(&self) -> watch::Receiver<Vec<DeclarativeConfig>> {
+        self.applied.subscribe()
+    }
+
+    fn publish(&self) {
+        let mut configs: Vec<DeclarativeConfig> = self.state_manager.list()
+            .into_iter()
+            .cloned()
+            .collect();
+        configs.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+        self.applied.send_replace(configs);
     }
 
     pub fn set_dry_run(&mut self, dry_run: bool) {
@@ -378,6 +398,24 @@ impl ApplyEngine {
 
     /// Apply configuration changes
     pub async fn apply(&mut self, desired_configs: Vec<DeclarativeConfig>) -> Result<ApplyResult> {
+        // Reject invalid resources before changing anything, reporting all of them
+        let errors: Vec<String> = desired_configs.iter()
+            .filter_map(|config| {
+                ConfigParser::validate_config(config).err().map(|e| {
+                    format!("Invalid {:?} {}: {}", config.kind, config.metadata.name, e)
+                })
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Ok(ApplyResult {
+                success: false,
+                changes_applied: 0,
+                changes_failed: errors.len(),
+                errors,
+                rollback_performed: false,
+            });
+        }
+
         // Generate diff
         let diff = self.diff(&desired_configs)?;
 
@@ -456,6 +494,7 @@ impl ApplyEngine {
 
         // Save new state
         self.state_manager.save_current_state().await?;
+        self.publish();
 
         tracing::info!("Successfully applied {} changes", changes_applied);
 
@@ -643,6 +682,62 @@ mod tests {
         assert_eq!(diff.deletes, 0);
     }
 
+    fn alert_rule(name: &str, expr: &str) -> DeclarativeConfig {
+        DeclarativeConfig {
+            api_version: API_VERSION.to_string(),
+            kind: ResourceKind::AlertRule,
+            metadata: Metadata {
+                name: name.to_string(),
+                description: None,
+                labels: None,
+                annotations: None,
+            },
+            spec: ResourceSpec::AlertRule(AlertRuleSpec {
+                expr: expr.to_string(),
+                for_duration: None,
+                severity: AlertRuleSeverity::Warning,
+                channels: Vec::new(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_validates_and_publishes() {
+        patronus_core::validation::register_validator(|spec: &AlertRuleSpec| {
+            match spec.expr.find("??") {
+                Some(at) => vec![patronus_core::validation::ValidationError::new(
+                    "expr",
+                    format!("unexpected `?` at column {}", at + 1),
+                )],
+                None => Vec::new(),
+            }
+        });
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut engine = ApplyEngine::new(temp_dir.path().to_path_buf());
+        engine.init().await.unwrap();
+        let mut applied = engine.subscribe();
+        assert!(applied.borrow_and_update().is_empty());
+
+        // Every invalid resource is reported and nothing is applied
+        let result = engine.apply(vec![
+            alert_rule("ok", "up == 0"),
+            alert_rule("bad", "up ?? 0"),
+            alert_rule("empty", ""),
+        ]).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 2);
+        assert!(result.errors[0].contains("bad: Invalid configuration: spec.expr: unexpected `?` at column 4"));
+        assert!(!applied.has_changed().unwrap());
+
+        let result = engine.apply(vec![alert_rule("ok", "up == 0")]).await.unwrap();
+        assert!(result.success);
+        assert!(applied.has_changed().unwrap());
+        let configs = applied.borrow_and_update().clone();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].metadata.name, "ok");
+    }
+
     #[test]
     fn test_format_diff() {
         let diff = DiffResult {
@@ -14,10 +14,15 @@
 //! - Atomic apply with rollback
 
 use patronus_core::{Result, Error};
+use patronus_core::validation::{
+    codes, validate_all, validate_cidr, validate_domain_name, validate_hostname,
+    validate_interface_name, validate_ip_address, validate_ipv4_address, validate_port,
+    validate_port_range, validate_protocol, Validate, ValidationError, ValidationErrors,
+    ValidationResult,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use std::net::IpAddr;
 
 /// API version for configuration schema
 pub const API_VERSION: &str = "patronus.firewall/v1";
@@ -45,6 +50,7 @@ pub enum ResourceKind {
     HaProxyBackend,
     Certificate,
     User,
+    AlertRule,
     SystemSettings,
 }
 
@@ -74,6 +80,7 @@ pub enum ResourceSpec {
     HaProxyBackend(HaProxyBackendSpec),
     Certificate(CertificateSpec),
     User(UserSpec),
+    AlertRule(AlertRuleSpec),
     SystemSettings(SystemSettingsSpec),
 }
 
@@ -339,6 +346,32 @@ pub struct UserSpec {
     pub enabled: bool,
 }
 
+/// Alert rule specification
+///
+/// `expr` is a PromQL-lite expression such as
+/// `rate(interface_errors{name="wan0"}[5m]) > 10`; the monitoring crate
+/// registers a validator that checks its syntax.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRuleSpec {
+    pub expr: String,
+    /// How long the expression must hold before the alert fires, e.g. `5m`
+    #[serde(rename = "for", skip_serializing_if = "Option::is_none")]
+    pub for_duration: Option<String>,
+    pub severity: AlertRuleSeverity,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AlertRuleSeverity {
+    #[serde(rename = "critical")]
+    Critical,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "info")]
+    Info,
+}
+
 /// System settings specification
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemSettingsSpec {
@@ -350,153 +383,252 @@ pub struct SystemSettingsSpec {
     pub timezone: Option<String>,
 }
 
-/// Configuration parser
-pub struct ConfigParser;
+impl Validate for DeclarativeConfig {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
 
-impl ConfigParser {
-    /// Parse YAML configuration file
-    pub fn parse_yaml(content: &str) -> Result<Vec<DeclarativeConfig>> {
-        // Support both single document and multi-document YAML
-        let configs: Vec<DeclarativeConfig> = serde_yaml::from_str(content)
-            .map_err(|e| Error::Config(format!("YAML parse error: {}", e)))?;
-
-        // Validate each config
-        for config in &configs {
-            Self::validate_config(config)?;
+        if self.api_version != API_VERSION {
+            errors.push(ValidationError::new(
+                "apiVersion",
+                format!("Unsupported API version: {} (expected: {})", self.api_version, API_VERSION),
+            ).with_code(codes::UNSUPPORTED).with_value(&self.api_version));
+        }
+        errors.ensure(!self.metadata.name.is_empty(), "metadata.name", codes::REQUIRED, "Resource name cannot be empty");
+
+        let spec: Option<&dyn Validate> = match (&self.kind, &self.spec) {
+            (ResourceKind::FirewallRule, ResourceSpec::FirewallRule(spec)) => Some(spec),
+            (ResourceKind::NatRule, ResourceSpec::NatRule(spec)) => Some(spec),
+            (ResourceKind::VpnConnection, ResourceSpec::VpnConnection(spec)) => Some(spec),
+            (ResourceKind::Interface, ResourceSpec::Interface(spec)) => Some(spec),
+            (ResourceKind::DhcpServer, ResourceSpec::DhcpServer(spec)) => Some(spec),
+            (ResourceKind::AlertRule, ResourceSpec::AlertRule(spec)) => Some(spec),
+            (ResourceKind::SystemSettings, ResourceSpec::SystemSettings(spec)) => Some(spec),
+            _ => None,
+        };
+        match spec {
+            Some(spec) => errors.extend_within("spec", spec.validate()),
+            None => errors.push(ValidationError::new(
+                "kind",
+                format!("Kind {:?} does not match spec", self.kind),
+            ).with_value(format!("{:?}", self.kind))),
         }
 
-        Ok(configs)
+        errors.into_vec()
     }
+}
 
-    /// Parse YAML from file
-    pub async fn parse_yaml_file(path: &Path) -> Result<Vec<DeclarativeConfig>> {
-        let content = tokio::fs::read_to_string(path).await?;
-        Self::parse_yaml(&content)
+impl Validate for FirewallRuleSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        if let Some(interface) = &self.interface {
+            errors.check("interface", validate_interface_name(interface));
+        }
+        errors.nested("source", &self.source);
+        errors.nested("destination", &self.destination);
+        if let Some(protocol) = &self.protocol {
+            errors.check("protocol", validate_protocol(protocol));
+        }
+        errors.into_vec()
     }
+}
 
-    /// Parse TOML configuration file
-    pub fn parse_toml(content: &str) -> Result<DeclarativeConfig> {
-        let config: DeclarativeConfig = toml::from_str(content)
-            .map_err(|e| Error::Config(format!("TOML parse error: {}", e)))?;
-
-        Self::validate_config(&config)?;
+impl Validate for AddressSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        if let Some(address) = &self.address {
+            // Anything that is not CIDR may be a bare IP, `any` or an alias name
+            if address.contains('/') {
+                errors.check("address", validate_cidr(address));
+            }
+        }
+        for (i, port) in self.ports.iter().flatten().enumerate() {
+            errors.check(&format!("ports[{}]", i), validate_port(*port));
+        }
+        for (i, range) in self.port_ranges.iter().flatten().enumerate() {
+            errors.check(&format!("port_ranges[{}]", i), validate_port_range(range.start, range.end));
+        }
+        errors.into_vec()
+    }
+}
 
-        Ok(config)
+impl Validate for NatRuleSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        errors.check("interface", validate_interface_name(&self.interface));
+        errors.nested("source", &self.source);
+        errors.nested("destination", &self.destination);
+        if let Some(translation) = &self.translation {
+            errors.nested("translation", translation);
+        }
+        errors.into_vec()
     }
+}
 
-    /// Validate configuration
-    pub fn validate_config(config: &DeclarativeConfig) -> Result<()> {
-        // Check API version
-        if config.api_version != API_VERSION {
-            return Err(Error::Config(format!(
-                "Unsupported API version: {} (expected: {})",
-                config.api_version, API_VERSION
-            )));
+impl Validate for VpnConnectionSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        // Ensure config exists for the specified type
+        match self.vpn_type {
+            VpnType::WireGuard => errors.ensure(self.wireguard.is_some(), "wireguard", codes::REQUIRED, "WireGuard config required"),
+            VpnType::OpenVpn => errors.ensure(self.openvpn.is_some(), "openvpn", codes::REQUIRED, "OpenVPN config required"),
+            VpnType::Ipsec => errors.ensure(self.ipsec.is_some(), "ipsec", codes::REQUIRED, "IPsec config required"),
         }
+        if let Some(wireguard) = &self.wireguard {
+            errors.each("wireguard.peers", &wireguard.peers);
+        }
+        if let Some(openvpn) = &self.openvpn {
+            errors.check("openvpn.port", validate_port(openvpn.port));
+        }
+        if let Some(ipsec) = &self.ipsec {
+            errors.check("ipsec.remote_address", validate_host(&ipsec.remote_address));
+        }
+        errors.into_vec()
+    }
+}
 
-        // Validate metadata
-        if config.metadata.name.is_empty() {
-            return Err(Error::Config("Resource name cannot be empty".to_string()));
+impl Validate for WireGuardPeer {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        if let Some(endpoint) = &self.endpoint {
+            errors.check("endpoint", validate_endpoint(endpoint));
         }
+        for (i, allowed) in self.allowed_ips.iter().enumerate() {
+            errors.check(&format!("allowed_ips[{}]", i), validate_cidr(allowed));
+        }
+        errors.into_vec()
+    }
+}
 
-        // Validate spec based on kind
-        match (&config.kind, &config.spec) {
-            (ResourceKind::FirewallRule, ResourceSpec::FirewallRule(spec)) => {
-                Self::validate_firewall_rule(spec)?;
-            }
-            (ResourceKind::NatRule, ResourceSpec::NatRule(spec)) => {
-                Self::validate_nat_rule(spec)?;
-            }
-            (ResourceKind::VpnConnection, ResourceSpec::VpnConnection(spec)) => {
-                Self::validate_vpn_connection(spec)?;
+impl Validate for InterfaceSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        errors.check("device", validate_interface_name(&self.device));
+        if let Some(address) = &self.ip_address {
+            if address.contains('/') {
+                errors.check("ip_address", validate_cidr(address));
+            } else {
+                errors.check("ip_address", validate_ip_address(address));
             }
-            _ => {
-                return Err(Error::Config(format!(
-                    "Kind {:?} does not match spec",
-                    config.kind
-                )));
+        }
+        if let Some(mtu) = self.mtu {
+            if !(68..=65535).contains(&mtu) {
+                errors.push(ValidationError::new("mtu", "MTU must be between 68 and 65535")
+                    .with_code(codes::OUT_OF_RANGE)
+                    .with_value(mtu));
             }
         }
-
-        Ok(())
+        errors.into_vec()
     }
+}
 
-    fn validate_firewall_rule(spec: &FirewallRuleSpec) -> Result<()> {
-        // Validate addresses
-        if let Some(addr) = &spec.source.address {
-            Self::validate_address(addr)?;
+impl Validate for DhcpServerSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        errors.check("interface", validate_interface_name(&self.interface));
+        let start = errors.check("range_start", validate_ipv4_address(&self.range_start));
+        let end = errors.check("range_end", validate_ipv4_address(&self.range_end));
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                errors.push(ValidationError::new("range_end", format!("Range end {} is before start {}", end, start))
+                    .with_code(codes::INVALID_RANGE)
+                    .with_value(end));
+            }
         }
-        if let Some(addr) = &spec.destination.address {
-            Self::validate_address(addr)?;
+        if let Some(gateway) = &self.gateway {
+            errors.check("gateway", validate_ip_address(gateway));
         }
-
-        // Validate ports
-        if let Some(ports) = &spec.source.ports {
-            for port in ports {
-                if *port == 0 {
-                    return Err(Error::Config("Port cannot be 0".to_string()));
-                }
-            }
+        for (i, server) in self.dns_servers.iter().flatten().enumerate() {
+            errors.check(&format!("dns_servers[{}]", i), validate_ip_address(server));
         }
+        errors.into_vec()
+    }
+}
 
-        Ok(())
+impl Validate for AlertRuleSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        if self.expr.trim().is_empty() {
+            return vec![ValidationError::new("expr", "Alert rule must specify expr").with_code(codes::REQUIRED)];
+        }
+        // Expression syntax is checked by validators registered for the spec
+        validate_all(self)
     }
+}
 
-    fn validate_nat_rule(spec: &NatRuleSpec) -> Result<()> {
-        // Validate interface exists
-        if spec.interface.is_empty() {
-            return Err(Error::Config("NAT rule must specify interface".to_string()));
+impl Validate for SystemSettingsSpec {
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = ValidationErrors::new();
+        if let Some(hostname) = &self.hostname {
+            errors.check("hostname", validate_hostname(hostname));
         }
+        if let Some(domain) = &self.domain {
+            errors.check("domain", validate_domain_name(domain));
+        }
+        errors.into_vec()
+    }
+}
 
-        Ok(())
+/// An IP address or a hostname
+fn validate_host(host: &str) -> ValidationResult<()> {
+    if validate_ip_address(host).is_ok() {
+        return Ok(());
     }
+    validate_hostname(host)
+}
 
-    fn validate_vpn_connection(spec: &VpnConnectionSpec) -> Result<()> {
-        // Ensure config exists for the specified type
-        match spec.vpn_type {
-            VpnType::WireGuard if spec.wireguard.is_none() => {
-                return Err(Error::Config("WireGuard config required".to_string()));
-            }
-            VpnType::OpenVpn if spec.openvpn.is_none() => {
-                return Err(Error::Config("OpenVPN config required".to_string()));
-            }
-            VpnType::Ipsec if spec.ipsec.is_none() => {
-                return Err(Error::Config("IPsec config required".to_string()));
-            }
-            _ => {}
+/// `host:port`, with IPv6 hosts in brackets
+fn validate_endpoint(endpoint: &str) -> ValidationResult<()> {
+    let invalid = || ValidationError::new("", "Endpoint must be host:port")
+        .with_code(codes::INVALID_FORMAT)
+        .with_value(endpoint);
+
+    let (host, port) = endpoint.rsplit_once(':').ok_or_else(invalid)?;
+    let host = match host.strip_prefix('[') {
+        Some(v6) => v6.strip_suffix(']').ok_or_else(invalid)?,
+        None => host,
+    };
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+
+    validate_host(host).map_err(|e| e.with_value(endpoint))?;
+    validate_port(port).map_err(|e| e.with_value(endpoint))
+}
+
+/// Configuration parser
+pub struct ConfigParser;
+
+impl ConfigParser {
+    /// Parse YAML configuration file
+    pub fn parse_yaml(content: &str) -> Result<Vec<DeclarativeConfig>> {
+        // Support both single document and multi-document YAML
+        let configs: Vec<DeclarativeConfig> = serde_yaml::from_str(content)
+            .map_err(|e| Error::Config(format!("YAML parse error: {}", e)))?;
+
+        // Validate each config
+        for config in &configs {
+            Self::validate_config(config)?;
         }
 
-        Ok(())
+        Ok(configs)
     }
 
-    fn validate_address(addr: &str) -> Result<()> {
-        // Try parsing as IP or CIDR
-        if addr.contains('/') {
-            // CIDR notation
-            let parts: Vec<&str> = addr.split('/').collect();
-            if parts.len() != 2 {
-                return Err(Error::Config(format!("Invalid CIDR: {}", addr)));
-            }
+    /// Parse YAML from file
+    pub async fn parse_yaml_file(path: &Path) -> Result<Vec<DeclarativeConfig>> {
+        let content = tokio::fs::read_to_string(path).await?;
+        Self::parse_yaml(&content)
+    }
 
-            // Validate IP part
-            if parts[0].parse::<IpAddr>().is_err() {
-                return Err(Error::Config(format!("Invalid IP in CIDR: {}", addr)));
-            }
+    /// Parse TOML configuration file
+    pub fn parse_toml(content: &str) -> Result<DeclarativeConfig> {
+        let config: DeclarativeConfig = toml::from_str(content)
+            .map_err(|e| Error::Config(format!("TOML parse error: {}", e)))?;
 
-            // Validate prefix length
-            if let Ok(prefix) = parts[1].parse::<u8>() {
-                if prefix > 128 {
-                    return Err(Error::Config(format!("Invalid prefix length: {}", prefix)));
-                }
-            } else {
-                return Err(Error::Config(format!("Invalid prefix length: {}", parts[1])));
-            }
-        } else if addr != "any" && addr.parse::<IpAddr>().is_err() {
-            // Not a valid IP and not "any"
-            // Could be an alias name, which is OK
-        }
+        Self::validate_config(&config)?;
 
-        Ok(())
+        Ok(config)
+    }
+
+    /// Validate configuration, reporting every problem with its field path
+    pub fn validate_config(config: &DeclarativeConfig) -> Result<()> {
+        config.ensure_valid().map_err(Error::from)
     }
 
     /// Serialize config to YAML
@@ -563,6 +695,133 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_validation_reports_every_field() {
+        let yaml = r#"
+- apiVersion: patronus.firewall/v1
+  kind: FirewallRule
+  metadata:
+    name: bad-rule
+  spec:
+    action: allow
+    interface: "wan0;reboot"
+    source:
+      address: "192.168.1.0/33"
+    destination:
+      address: "10.0.0.1"
+      ports: [443, 0]
+      port_ranges:
+        - start: 9000
+          end: 8000
+    enabled: true
+"#;
+
+        let Err(Error::Validation(errors)) = ConfigParser::parse_yaml(yaml) else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| (e.field_path.as_str(), e.code.as_str())).collect();
+        assert_eq!(fields, [
+            ("spec.interface", codes::INVALID_CHARACTERS),
+            ("spec.source.address", codes::INVALID_PREFIX),
+            ("spec.destination.ports[1]", codes::OUT_OF_RANGE),
+            ("spec.destination.port_ranges[0]", codes::INVALID_RANGE),
+        ]);
+        assert_eq!(errors.iter().nth(1).unwrap().rejected_value.as_deref(), Some("192.168.1.0/33"));
+
+        let yaml = r#"
+apiVersion: patronus.firewall/v0
+kind: VpnConnection
+metadata:
+  name: ""
+spec:
+  vpn_type: wireguard
+  wireguard:
+    private_key: key
+    listen_port: 51820
+    peers:
+      - public_key: peer
+        endpoint: "vpn.example.com:0"
+        allowed_ips: ["10.8.0.0/24", "10.9.0.0"]
+"#;
+        let Err(Error::Validation(errors)) = ConfigParser::parse_toml(&toml::to_string(
+            &serde_yaml::from_str::<DeclarativeConfig>(yaml).unwrap(),
+        ).unwrap()) else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(fields, [
+            "apiVersion",
+            "metadata.name",
+            "spec.wireguard.peers[0].endpoint",
+            "spec.wireguard.peers[0].allowed_ips[1]",
+        ]);
+    }
+
+    #[test]
+    fn test_validate_system_and_dhcp_specs() {
+        let config = |kind, spec| DeclarativeConfig {
+            api_version: API_VERSION.to_string(),
+            kind,
+            metadata: Metadata { name: "x".to_string(), description: None, labels: None, annotations: None },
+            spec,
+        };
+
+        let system = config(ResourceKind::SystemSettings, ResourceSpec::SystemSettings(SystemSettingsSpec {
+            hostname: Some("fw-01".to_string()),
+            domain: Some("1bad.example".to_string()),
+            timezone: None,
+        }));
+        let errors = system.validate();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec.domain");
+
+        let dhcp = config(ResourceKind::DhcpServer, ResourceSpec::DhcpServer(DhcpServerSpec {
+            interface: "lan0".to_string(),
+            range_start: "192.168.1.200".to_string(),
+            range_end: "192.168.1.100".to_string(),
+            gateway: Some("192.168.1.1".to_string()),
+            dns_servers: Some(vec!["1.1.1.1".to_string(), "dns".to_string()]),
+            enabled: true,
+        }));
+        let fields: Vec<_> = dhcp.validate().into_iter().map(|e| e.field_path).collect();
+        assert_eq!(fields, ["spec.range_end", "spec.dns_servers[1]"]);
+    }
+
+    #[test]
+    fn test_parse_alert_rule_yaml() {
+        let yaml = r#"
+- apiVersion: patronus.firewall/v1
+  kind: AlertRule
+  metadata:
+    name: wan-errors
+    description: "WAN interface errors"
+  spec:
+    expr: 'rate(interface_errors{name="wan0"}[5m]) > 10'
+    for: 2m
+    severity: critical
+    channels: [pager]
+- apiVersion: patronus.firewall/v1
+  kind: AlertRule
+  metadata:
+    name: empty-expr
+  spec:
+    expr: ""
+    severity: warning
+"#;
+
+        let configs: Vec<DeclarativeConfig> = serde_yaml::from_str(yaml).unwrap();
+        let ResourceSpec::AlertRule(spec) = &configs[0].spec else {
+            panic!("expected an alert rule spec");
+        };
+        assert_eq!(spec.for_duration.as_deref(), Some("2m"));
+        assert_eq!(spec.severity, AlertRuleSeverity::Critical);
+        assert!(ConfigParser::validate_config(&configs[0]).is_ok());
+        assert!(ConfigParser::validate_config(&configs[1]).is_err());
+
+        let yaml = ConfigParser::to_yaml(&configs[0]).unwrap();
+        assert!(yaml.contains("for: 2m"));
+    }
+
     #[test]
     fn test_serialize_to_yaml() {
         let config = DeclarativeConfig {
@@ -8,11 +8,13 @@ use serde::{Deserialize, Serialize};
 pub mod store;
 pub mod declarative;
 pub mod apply;
+pub mod node;
 
 pub use store::ConfigStore;
 pub use declarative::{
     DeclarativeConfig, ResourceKind, ResourceSpec, Metadata, ConfigParser,
     FirewallRuleSpec, NatRuleSpec, AddressSpec, RuleAction, Direction,
+    AlertRuleSpec, AlertRuleSeverity,
 };
 pub use apply::{
     ApplyEngine, StateManager, ConfigChange, ChangeOp, DiffResult,
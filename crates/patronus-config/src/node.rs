@@ -0,0 +1,190 @@
+//! Node export/import components for configuration state
+//!
+//! Lets [`patronus_core::node`] carry the configuration store and the
+//! declarative state directory to a replacement node.
+
+use crate::store::ConfigStore;
+use async_trait::async_trait;
+use patronus_core::node::{DirectoryComponent, NodeComponent, NodeTransferError, StagedComponent};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Declarative state (`current.yaml` and snapshots) under `state_dir`
+///
+/// The [`crate::StateManager`] reads the directory at startup, so an import
+/// takes effect when it is next initialized.
+pub fn declarative_state(state_dir: impl Into<PathBuf>) -> DirectoryComponent {
+    DirectoryComponent::new("declarative", state_dir)
+}
+
+/// The SQLite configuration store
+///
+/// Exported as a consistent snapshot and imported by replacing every table
+/// in one transaction, so the open store never sees a partial configuration.
+pub struct ConfigStoreComponent {
+    store: Arc<ConfigStore>,
+}
+
+impl ConfigStoreComponent {
+    pub fn new(store: Arc<ConfigStore>) -> Self {
+        Self { store }
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let path = self.store.db_path();
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        path.with_file_name(name)
+    }
+}
+
+fn failed(e: patronus_core::Error) -> NodeTransferError {
+    NodeTransferError::Failed(e.to_string())
+}
+
+async fn remove_file(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl NodeComponent for ConfigStoreComponent {
+    fn name(&self) -> &str {
+        "config-store"
+    }
+
+    async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError> {
+        let snapshot = self.sibling(".export");
+        remove_file(&snapshot).await?;
+        self.store.snapshot_to(&snapshot).await.map_err(failed)?;
+        let content = tokio::fs::read(&snapshot).await;
+        remove_file(&snapshot).await?;
+        Ok(Some(content?))
+    }
+
+    async fn stage(&self, payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError> {
+        let staged = self.sibling(".import");
+        tokio::fs::write(&staged, &payload).await?;
+
+        // Opening the copy brings an older schema up to date before the
+        // tables are copied across
+        let mut check = ConfigStore::new(staged.clone());
+        let result = match check.init().await {
+            Ok(()) => check.integrity_check().await,
+            Err(e) => Err(e),
+        };
+        check.close().await;
+        if let Err(e) = result {
+            remove_file(&staged).await?;
+            return Err(failed(e));
+        }
+
+        Ok(Box::new(StagedConfigStore {
+            store: Arc::clone(&self.store),
+            previous: self.sibling(".pre-import"),
+            staged,
+            committed: false,
+        }))
+    }
+}
+
+struct StagedConfigStore {
+    store: Arc<ConfigStore>,
+    staged: PathBuf,
+    previous: PathBuf,
+    committed: bool,
+}
+
+#[async_trait]
+impl StagedComponent for StagedConfigStore {
+    async fn commit(&mut self) -> Result<(), NodeTransferError> {
+        remove_file(&self.previous).await?;
+        self.store.snapshot_to(&self.previous).await.map_err(failed)?;
+        self.store.replace_from(&self.staged).await.map_err(failed)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    async fn rollback(&mut self) -> Result<(), NodeTransferError> {
+        if self.committed {
+            self.store.replace_from(&self.previous).await.map_err(failed)?;
+            self.committed = false;
+        }
+        remove_file(&self.staged).await?;
+        remove_file(&self.previous).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<(), NodeTransferError> {
+        remove_file(&self.staged).await?;
+        remove_file(&self.previous).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus_core::node::{NodeExporter, NodeImporter};
+
+    async fn store_in(dir: &Path) -> Arc<ConfigStore> {
+        let mut store = ConfigStore::new(dir.join("config.db"));
+        store.init().await.unwrap();
+        Arc::new(store)
+    }
+
+    #[tokio::test]
+    async fn test_config_store_round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        let store = store_in(source.path()).await;
+        store.save_system_config("hostname", "edge-01").await.unwrap();
+
+        let archive = NodeExporter::new()
+            .with_kdf(64, 1)
+            .with_component(Arc::new(ConfigStoreComponent::new(store)))
+            .export("transfer")
+            .await
+            .unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        let store = store_in(target.path()).await;
+        store.save_system_config("hostname", "spare").await.unwrap();
+        store.save_system_config("timezone", "UTC").await.unwrap();
+
+        let report = NodeImporter::new()
+            .with_component(Arc::new(ConfigStoreComponent::new(Arc::clone(&store))))
+            .import(&archive, "transfer")
+            .await
+            .unwrap();
+
+        assert_eq!(report.restored, vec!["config-store"]);
+        assert_eq!(store.load_system_config("hostname").await.unwrap().as_deref(), Some("edge-01"));
+        assert_eq!(store.load_system_config("timezone").await.unwrap(), None);
+        let mut files: Vec<_> = std::fs::read_dir(target.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        files.sort();
+        assert_eq!(files, vec!["config.db"]);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_previous_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(dir.path()).await;
+        store.save_system_config("hostname", "before").await.unwrap();
+
+        let component = ConfigStoreComponent::new(Arc::clone(&store));
+        let payload = component.export().await.unwrap().unwrap();
+        store.save_system_config("hostname", "live").await.unwrap();
+
+        let mut staged = component.stage(payload).await.unwrap();
+        staged.commit().await.unwrap();
+        assert_eq!(store.load_system_config("hostname").await.unwrap().as_deref(), Some("before"));
+
+        staged.rollback().await.unwrap();
+        assert_eq!(store.load_system_config("hostname").await.unwrap().as_deref(), Some("live"));
+
+        assert!(component.stage(b"not a database".to_vec()).await.is_err());
+        assert!(!dir.path().join("config.db.import").exists());
+    }
+}
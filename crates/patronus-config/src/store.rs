@@ -2,7 +2,7 @@
 
 use patronus_core::{Error, Result, types::{FirewallRule, NatRule, ChainType, FirewallAction, Protocol, PortSpec, NatType}};
 use serde::{Deserialize, Serialize};
-use sqlx::{sqlite::SqlitePool, Row};
+use sqlx::{sqlite::{SqliteConnectOptions, SqlitePool}, Row};
 use std::path::{Path, PathBuf};
 use std::net::IpAddr;
 
@@ -32,8 +32,10 @@ impl ConfigStore {
         }
 
         // Connect to SQLite database
-        let db_url = format!("sqlite://{}", self.db_path.display());
-        let pool = SqlitePool::connect(&db_url)
+        let options = SqliteConnectOptions::new()
+            .filename(&self.db_path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
             .await
             .map_err(|e| Error::Config(format!("Failed to connect to database: {}", e)))?;
 
@@ -49,6 +51,110 @@ impl ConfigStore {
         Ok(())
     }
 
+    /// Path of the database file
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Close the database; the store must be initialized again before use
+    pub async fn close(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.close().await;
+        }
+    }
+
+    /// Run SQLite's integrity check over the whole database
+    pub async fn integrity_check(&self) -> Result<()> {
+        let rows = sqlx::query("PRAGMA integrity_check")
+            .fetch_all(self.pool()?)
+            .await
+            .map_err(|e| Error::Config(format!("Integrity check failed: {}", e)))?;
+
+        let problems: Vec<String> = rows.iter().map(|r| r.get::<String, _>(0)).filter(|r| r != "ok").collect();
+        if !problems.is_empty() {
+            return Err(Error::Config(format!("Database is corrupt: {}", problems.join("; "))));
+        }
+        Ok(())
+    }
+
+    /// Write a consistent copy of the database to `path`, which must not exist
+    pub async fn snapshot_to(&self, path: &Path) -> Result<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(self.pool()?)
+            .await
+            .map_err(|e| Error::Config(format!("Failed to snapshot database: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Replace the contents of every table with those of the database at `path`
+    ///
+    /// Runs in one transaction on the open store, so readers see either the
+    /// old or the new configuration. Columns missing from `path` (written by
+    /// an older schema) take their defaults.
+    pub async fn replace_from(&self, path: &Path) -> Result<()> {
+        let err = |e: sqlx::Error| Error::Config(format!("Failed to replace configuration: {}", e));
+        let mut conn = self.pool()?.acquire().await.map_err(err)?;
+
+        sqlx::query("ATTACH DATABASE ? AS import")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(&mut *conn)
+            .await
+            .map_err(err)?;
+
+        let result = async {
+            let tables: Vec<String> = sqlx::query(
+                "SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'"
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|r| r.get(0))
+            .collect();
+
+            sqlx::query("BEGIN IMMEDIATE").execute(&mut *conn).await?;
+            for table in &tables {
+                sqlx::query(&format!("DELETE FROM main.\"{}\"", table)).execute(&mut *conn).await?;
+
+                let columns: Vec<String> = sqlx::query(&format!(
+                    "SELECT i.name FROM pragma_table_info('{0}', 'import') i
+                     JOIN pragma_table_info('{0}', 'main') m ON m.name = i.name",
+                    table
+                ))
+                .fetch_all(&mut *conn)
+                .await?
+                .iter()
+                .map(|r| format!("\"{}\"", r.get::<String, _>(0)))
+                .collect();
+                if columns.is_empty() {
+                    continue;
+                }
+
+                let columns = columns.join(", ");
+                sqlx::query(&format!(
+                    "INSERT INTO main.\"{0}\" ({1}) SELECT {1} FROM import.\"{0}\"",
+                    table, columns
+                ))
+                .execute(&mut *conn)
+                .await?;
+            }
+            sqlx::query("COMMIT").execute(&mut *conn).await?;
+            Ok::<_, sqlx::Error>(())
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+        }
+        let detached = sqlx::query("DETACH DATABASE import").execute(&mut *conn).await;
+        result.map_err(err)?;
+        detached.map_err(err)?;
+
+        tracing::info!("Replaced configuration from {:?}", path);
+        Ok(())
+    }
+
     /// Get database pool
     fn pool(&self) -> Result<&SqlitePool> {
         self.pool
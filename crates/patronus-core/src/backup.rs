@@ -1,634 +0,0 @@
-//! Enterprise Backup and Restore System
-//!
-//! Production-grade configuration backup with:
-//! - Versioning and history
-//! - Encryption (AES-256-GCM)
-//! - Compression (zstd)
-//! - Cloud storage support (S3, Azure, GCS)
-//! - Automated scheduled backups
-//! - Point-in-time recovery
-//! - Configuration diff and rollback
-
-use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use chrono::{DateTime, Utc};
-use tokio::fs;
-use sha2::{Sha256, Digest};
-
-/// Backup configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackupConfig {
-    pub enabled: bool,
-    pub schedule: BackupSchedule,
-    pub retention: RetentionPolicy,
-    pub encryption: EncryptionConfig,
-    pub compression: CompressionConfig,
-    pub storage: StorageBackend,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackupSchedule {
-    pub hourly: bool,
-    pub daily: bool,
-    pub weekly: bool,
-    pub monthly: bool,
-    pub custom_cron: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RetentionPolicy {
-    pub keep_hourly: u32,
-    pub keep_daily: u32,
-    pub keep_weekly: u32,
-    pub keep_monthly: u32,
-    pub keep_yearly: u32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EncryptionConfig {
-    pub enabled: bool,
-    pub algorithm: EncryptionAlgorithm,
-    pub key_derivation: KeyDerivation,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum EncryptionAlgorithm {
-    AES256GCM,
-    ChaCha20Poly1305,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum KeyDerivation {
-    PBKDF2 { iterations: u32 },
-    Argon2id { memory_kb: u32, iterations: u32 },
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CompressionConfig {
-    pub enabled: bool,
-    pub algorithm: CompressionAlgorithm,
-    pub level: u8,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum CompressionAlgorithm {
-    Zstd,
-    Gzip,
-    Bzip2,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum StorageBackend {
-    Local { path: PathBuf },
-    S3 {
-        bucket: String,
-        region: String,
-        access_key: String,
-        secret_key: String,
-        endpoint: Option<String>,  // For S3-compatible services
-    },
-    Azure {
-        account: String,
-        container: String,
-        key: String,
-    },
-    GCS {
-        bucket: String,
-        credentials_file: PathBuf,
-    },
-    SFTP {
-        host: String,
-        port: u16,
-        username: String,
-        key_file: PathBuf,
-        remote_path: PathBuf,
-    },
-}
-
-/// Backup metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackupMetadata {
-    pub backup_id: String,
-    pub created_at: DateTime<Utc>,
-    pub backup_type: BackupType,
-    pub size_bytes: u64,
-    pub compressed_size: u64,
-    pub encrypted: bool,
-    pub checksum: String,
-    pub hostname: String,
-    pub version: String,
-    pub files_included: Vec<String>,
-    pub config_hash: String,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum BackupType {
-    Full,
-    Incremental,
-    Differential,
-}
-
-/// Backup manager
-pub struct BackupManager {
-    config: BackupConfig,
-    backup_dir: PathBuf,
-    config_dirs: Vec<PathBuf>,
-}
-
-impl BackupManager {
-    pub fn new(config: BackupConfig) -> Self {
-        Self {
-            backup_dir: match &config.storage {
-                StorageBackend::Local { path } => path.clone(),
-                _ => PathBuf::from("/var/backups/patronus"),
-            },
-            config_dirs: vec![
-                PathBuf::from("/etc/patronus"),
-                PathBuf::from("/var/lib/patronus"),
-            ],
-            config,
-        }
-    }
-
-    /// Create a full backup
-    pub async fn create_backup(&self, backup_type: BackupType) -> Result<BackupMetadata, BackupError> {
-        let backup_id = Self::generate_backup_id();
-        let timestamp = Utc::now();
-
-        tracing::info!("Creating {:?} backup: {}", backup_type, backup_id);
-
-        // Collect all configuration files
-        let mut files = Vec::new();
-        let mut total_size = 0u64;
-
-        for config_dir in &self.config_dirs {
-            if config_dir.exists() {
-                let dir_files = self.collect_files(config_dir).await?;
-                for file in dir_files {
-                    let size = fs::metadata(&file).await?.len();
-                    total_size += size;
-                    files.push(file);
-                }
-            }
-        }
-
-        tracing::debug!("Collected {} files ({} bytes)", files.len(), total_size);
-
-        // Create tar archive
-        let archive_path = self.backup_dir.join(format!("{}.tar", backup_id));
-        self.create_tar_archive(&files, &archive_path).await?;
-
-        let mut final_path = archive_path.clone();
-        let mut compressed_size = total_size;
-
-        // Compress if enabled
-        if self.config.compression.enabled {
-            let compressed_path = self.compress_archive(&archive_path).await?;
-            compressed_size = fs::metadata(&compressed_path).await?.len();
-            fs::remove_file(&archive_path).await?;
-            final_path = compressed_path;
-        }
-
-        // Encrypt if enabled
-        if self.config.encryption.enabled {
-            let encrypted_path = self.encrypt_archive(&final_path).await?;
-            fs::remove_file(&final_path).await?;
-            final_path = encrypted_path;
-        }
-
-        // Calculate checksum
-        let checksum = self.calculate_checksum(&final_path).await?;
-
-        let metadata = BackupMetadata {
-            backup_id: backup_id.clone(),
-            created_at: timestamp,
-            backup_type,
-            size_bytes: total_size,
-            compressed_size,
-            encrypted: self.config.encryption.enabled,
-            checksum,
-            hostname: hostname::get()?.to_string_lossy().to_string(),
-            version: env!("CARGO_PKG_VERSION").to_string(),
-            files_included: files.iter().map(|p| p.display().to_string()).collect(),
-            config_hash: self.calculate_config_hash(&files).await?,
-        };
-
-        // Save metadata
-        let metadata_path = self.backup_dir.join(format!("{}.json", backup_id));
-        let metadata_json = serde_json::to_string_pretty(&metadata)?;
-        fs::write(&metadata_path, metadata_json).await?;
-
-        // Upload to remote storage if configured
-        self.upload_to_storage(&final_path, &metadata_path).await?;
-
-        tracing::info!("Backup created successfully: {}", backup_id);
-
-        Ok(metadata)
-    }
-
-    /// Restore from backup
-    pub async fn restore_backup(&self, backup_id: &str, target_dir: Option<PathBuf>) -> Result<(), BackupError> {
-        tracing::info!("Restoring backup: {}", backup_id);
-
-        // Download from storage if needed
-        let backup_path = self.download_from_storage(backup_id).await?;
-
-        // Verify checksum
-        let metadata = self.load_metadata(backup_id).await?;
-        let checksum = self.calculate_checksum(&backup_path).await?;
-
-        if checksum != metadata.checksum {
-            return Err(BackupError::ChecksumMismatch);
-        }
-
-        let mut current_path = backup_path;
-
-        // Decrypt if encrypted
-        if metadata.encrypted {
-            let decrypted_path = self.decrypt_archive(&current_path).await?;
-            fs::remove_file(&current_path).await?;
-            current_path = decrypted_path;
-        }
-
-        // Decompress if compressed
-        if current_path.extension().and_then(|s| s.to_str()) == Some("zst")
-            || current_path.extension().and_then(|s| s.to_str()) == Some("gz") {
-            let decompressed_path = self.decompress_archive(&current_path).await?;
-            fs::remove_file(&current_path).await?;
-            current_path = decompressed_path;
-        }
-
-        // Extract tar archive
-        let restore_dir = target_dir.unwrap_or_else(|| PathBuf::from("/"));
-        self.extract_tar_archive(&current_path, &restore_dir).await?;
-
-        tracing::info!("Backup restored successfully to {}", restore_dir.display());
-
-        Ok(())
-    }
-
-    /// List all available backups
-    pub async fn list_backups(&self) -> Result<Vec<BackupMetadata>, BackupError> {
-        let mut backups = Vec::new();
-
-        let mut entries = fs::read_dir(&self.backup_dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(metadata) = self.load_metadata_from_path(&path).await {
-                    backups.push(metadata);
-                }
-            }
-        }
-
-        // Sort by creation time (newest first)
-        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-
-        Ok(backups)
-    }
-
-    /// Delete old backups according to retention policy
-    pub async fn apply_retention_policy(&self) -> Result<(), BackupError> {
-        let backups = self.list_backups().await?;
-
-        // Group backups by type
-        let mut to_keep = std::collections::HashSet::new();
-
-        // Keep recent backups
-        for (idx, backup) in backups.iter().enumerate() {
-            let age_hours = (Utc::now() - backup.created_at).num_hours();
-
-            if age_hours < 24 && idx < self.config.retention.keep_hourly as usize {
-                to_keep.insert(&backup.backup_id);
-            } else if age_hours < 24 * 7 && idx < self.config.retention.keep_daily as usize {
-                to_keep.insert(&backup.backup_id);
-            } else if age_hours < 24 * 30 && idx < self.config.retention.keep_weekly as usize {
-                to_keep.insert(&backup.backup_id);
-            } else if age_hours < 24 * 365 && idx < self.config.retention.keep_monthly as usize {
-                to_keep.insert(&backup.backup_id);
-            } else if idx < self.config.retention.keep_yearly as usize {
-                to_keep.insert(&backup.backup_id);
-            }
-        }
-
-        // Delete backups not in retention
-        for backup in &backups {
-            if !to_keep.contains(&backup.backup_id) {
-                tracing::info!("Deleting old backup: {}", backup.backup_id);
-                self.delete_backup(&backup.backup_id).await?;
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Compare two backups and show differences
-    pub async fn diff_backups(&self, backup_id_a: &str, backup_id_b: &str) -> Result<BackupDiff, BackupError> {
-        let metadata_a = self.load_metadata(backup_id_a).await?;
-        let metadata_b = self.load_metadata(backup_id_b).await?;
-
-        let files_a: std::collections::HashSet<_> = metadata_a.files_included.iter().collect();
-        let files_b: std::collections::HashSet<_> = metadata_b.files_included.iter().collect();
-
-        let added: Vec<String> = files_b.difference(&files_a).map(|s| s.to_string()).collect();
-        let removed: Vec<String> = files_a.difference(&files_b).map(|s| s.to_string()).collect();
-
-        Ok(BackupDiff {
-            backup_a: backup_id_a.to_string(),
-            backup_b: backup_id_b.to_string(),
-            files_added: added,
-            files_removed: removed,
-            config_changed: metadata_a.config_hash != metadata_b.config_hash,
-        })
-    }
-
-    // Helper methods
-
-    async fn collect_files(&self, dir: &Path) -> Result<Vec<PathBuf>, BackupError> {
-        let mut files = Vec::new();
-        let mut stack = vec![dir.to_path_buf()];
-
-        while let Some(current) = stack.pop() {
-            let mut entries = fs::read_dir(&current).await?;
-
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                if path.is_dir() {
-                    stack.push(path);
-                } else {
-                    files.push(path);
-                }
-            }
-        }
-
-        Ok(files)
-    }
-
-    async fn create_tar_archive(&self, files: &[PathBuf], output: &Path) -> Result<(), BackupError> {
-        // Use tar command for production reliability
-        let file_list = files.iter()
-            .map(|p| p.display().to_string())
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let list_file = self.backup_dir.join("files.txt");
-        fs::write(&list_file, file_list).await?;
-
-        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
-        let list_file_str = list_file.to_str().ok_or(BackupError::InvalidPath)?;
-
-        let status = tokio::process::Command::new("tar")
-            .args(&["-czf", output_str, "-T", list_file_str])
-            .status()
-            .await?;
-
-        fs::remove_file(&list_file).await?;
-
-        if !status.success() {
-            return Err(BackupError::ArchiveFailed);
-        }
-
-        Ok(())
-    }
-
-    async fn compress_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
-        let output = path.with_extension("tar.zst");
-
-        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
-        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
-
-        let status = tokio::process::Command::new("zstd")
-            .args(&[
-                &format!("-{}", self.config.compression.level),
-                path_str,
-                "-o", output_str,
-            ])
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(BackupError::CompressionFailed);
-        }
-
-        Ok(output)
-    }
-
-    async fn encrypt_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
-        let ext = path.extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("tar");
-        let output = path.with_extension(format!("{}.enc", ext));
-
-        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
-        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
-
-        // Use age encryption for simplicity and security
-        let status = tokio::process::Command::new("age")
-            .args(&["-e", "-o", output_str, path_str])
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(BackupError::EncryptionFailed);
-        }
-
-        Ok(output)
-    }
-
-    async fn decrypt_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
-        let output = path.with_extension("");
-
-        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
-        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
-
-        let status = tokio::process::Command::new("age")
-            .args(&["-d", "-o", output_str, path_str])
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(BackupError::DecryptionFailed);
-        }
-
-        Ok(output)
-    }
-
-    async fn decompress_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
-        let output = path.with_extension("");
-
-        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
-        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
-
-        let status = tokio::process::Command::new("zstd")
-            .args(&["-d", path_str, "-o", output_str])
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(BackupError::DecompressionFailed);
-        }
-
-        Ok(output)
-    }
-
-    async fn extract_tar_archive(&self, path: &Path, target: &Path) -> Result<(), BackupError> {
-        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
-        let target_str = target.to_str().ok_or(BackupError::InvalidPath)?;
-
-        let status = tokio::process::Command::new("tar")
-            .args(&["-xzf", path_str, "-C", target_str])
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(BackupError::ExtractFailed);
-        }
-
-        Ok(())
-    }
-
-    async fn calculate_checksum(&self, path: &Path) -> Result<String, BackupError> {
-        let content = fs::read(path).await?;
-        let mut hasher = Sha256::new();
-        hasher.update(&content);
-        Ok(format!("{:x}", hasher.finalize()))
-    }
-
-    async fn calculate_config_hash(&self, files: &[PathBuf]) -> Result<String, BackupError> {
-        let mut hasher = Sha256::new();
-        for file in files {
-            let content = fs::read(file).await?;
-            hasher.update(&content);
-        }
-        Ok(format!("{:x}", hasher.finalize()))
-    }
-
-    async fn load_metadata(&self, backup_id: &str) -> Result<BackupMetadata, BackupError> {
-        let path = self.backup_dir.join(format!("{}.json", backup_id));
-        self.load_metadata_from_path(&path).await
-    }
-
-    async fn load_metadata_from_path(&self, path: &Path) -> Result<BackupMetadata, BackupError> {
-        let content = fs::read_to_string(path).await?;
-        Ok(serde_json::from_str(&content)?)
-    }
-
-    async fn delete_backup(&self, backup_id: &str) -> Result<(), BackupError> {
-        // Delete all files associated with this backup
-        let _pattern = format!("{}.*", backup_id);
-
-        let mut entries = fs::read_dir(&self.backup_dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let filename = entry.file_name().to_string_lossy().to_string();
-            if filename.starts_with(backup_id) {
-                fs::remove_file(entry.path()).await?;
-            }
-        }
-
-        Ok(())
-    }
-
-    async fn upload_to_storage(&self, _backup_path: &Path, _metadata_path: &Path) -> Result<(), BackupError> {
-        match &self.config.storage {
-            StorageBackend::Local { .. } => {
-                // Already local
-                Ok(())
-            }
-            StorageBackend::S3 { bucket: _, region: _, access_key: _, secret_key: _, endpoint: _ } => {
-                // Upload to S3 using AWS SDK
-                // Implementation would use aws-sdk-s3
-                Ok(())
-            }
-            StorageBackend::SFTP { host: _, port: _, username: _, key_file: _, remote_path: _ } => {
-                // Upload via SFTP
-                Ok(())
-            }
-            _ => Ok(()),
-        }
-    }
-
-    async fn download_from_storage(&self, backup_id: &str) -> Result<PathBuf, BackupError> {
-        // Implement download from remote storage
-        let local_path = self.backup_dir.join(backup_id);
-        Ok(local_path)
-    }
-
-    fn generate_backup_id() -> String {
-        format!("backup-{}", Utc::now().format("%Y%m%d-%H%M%S"))
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackupDiff {
-    pub backup_a: String,
-    pub backup_b: String,
-    pub files_added: Vec<String>,
-    pub files_removed: Vec<String>,
-    pub config_changed: bool,
-}
-
-#[derive(Debug, thiserror::Error)]
-pub enum BackupError {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-    #[error("Serialization error: {0}")]
-    Serde(#[from] serde_json::Error),
-    #[error("Archive creation failed")]
-    ArchiveFailed,
-    #[error("Compression failed")]
-    CompressionFailed,
-    #[error("Decompression failed")]
-    DecompressionFailed,
-    #[error("Encryption failed")]
-    EncryptionFailed,
-    #[error("Decryption failed")]
-    DecryptionFailed,
-    #[error("Extract failed")]
-    ExtractFailed,
-    #[error("Checksum mismatch")]
-    ChecksumMismatch,
-    #[error("Backup not found")]
-    NotFound,
-    #[error("Invalid path: path contains non-UTF8 characters")]
-    InvalidPath,
-}
-
-impl Default for BackupConfig {
-    fn default() -> Self {
-        Self {
-            enabled: true,
-            schedule: BackupSchedule {
-                hourly: false,
-                daily: true,
-                weekly: true,
-                monthly: true,
-                custom_cron: None,
-            },
-            retention: RetentionPolicy {
-                keep_hourly: 24,
-                keep_daily: 7,
-                keep_weekly: 4,
-                keep_monthly: 12,
-                keep_yearly: 3,
-            },
-            encryption: EncryptionConfig {
-                enabled: true,
-                algorithm: EncryptionAlgorithm::AES256GCM,
-                key_derivation: KeyDerivation::Argon2id {
-                    memory_kb: 65536,
-                    iterations: 3,
-                },
-            },
-            compression: CompressionConfig {
-                enabled: true,
-                algorithm: CompressionAlgorithm::Zstd,
-                level: 3,
-            },
-            storage: StorageBackend::Local {
-                path: PathBuf::from("/var/backups/patronus"),
-            },
-        }
-    }
-}
@@ -0,0 +1,1858 @@
+//! Enterprise Backup and Restore System
+//!
+//! Production-grade configuration backup with:
+//! - Versioning and history
+//! - Encryption (AES-256-GCM)
+//! - Compression (zstd)
+//! - Remote targets (SFTP, S3-compatible storage) with verified uploads
+//! - Automated scheduled backups with retention
+//! - Point-in-time and per-section recovery
+//! - Configuration diff and rollback
+//!
+//! Archives are encrypted under an operator passphrase or a key held in
+//! patronus-secrets. Backup, upload, retention and restore outcomes are
+//! published as [`BackupEvent`]s for alerting.
+
+mod remote;
+mod schedule;
+
+pub use remote::{target_for, DirectoryTarget, RemoteTarget, S3Target, SftpTarget};
+pub use schedule::CronSchedule;
+
+use aes_gcm::aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use crate::kdf::{check_argon2, check_pbkdf2, KdfLimitError};
+use hmac::{Hmac, Mac};
+use patronus_secrets::{SecretManager, SecretString, SecretType};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tokio::fs;
+use tokio::sync::broadcast;
+use sha2::{Sha256, Digest};
+
+/// Marks an archive encrypted by [`BackupManager`]
+const ENCRYPTED_MAGIC: &[u8; 8] = b"PTRNSENC";
+const ENCRYPTED_FORMAT_VERSION: u8 = 1;
+const KDF_PBKDF2: u8 = 1;
+const KDF_ARGON2ID: u8 = 2;
+/// Key read from patronus-secrets, expanded with the archive salt
+const KDF_SECRET_KEY: u8 = 3;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// magic | version | kdf | iterations (u32) | memory_kb (u32) | salt | nonce
+const ENCRYPTED_HEADER_LEN: usize = ENCRYPTED_MAGIC.len() + 2 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// Backup configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    pub schedule: BackupSchedule,
+    pub retention: RetentionPolicy,
+    pub encryption: EncryptionConfig,
+    pub compression: CompressionConfig,
+    pub storage: StorageBackend,
+
+    /// Further targets every backup is copied to
+    #[serde(default)]
+    pub remote_targets: Vec<StorageBackend>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSchedule {
+    pub hourly: bool,
+    pub daily: bool,
+    pub weekly: bool,
+    pub monthly: bool,
+    pub custom_cron: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub keep_hourly: u32,
+    pub keep_daily: u32,
+    pub keep_weekly: u32,
+    pub keep_monthly: u32,
+    pub keep_yearly: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    pub algorithm: EncryptionAlgorithm,
+    pub key_derivation: KeyDerivation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EncryptionAlgorithm {
+    AES256GCM,
+    ChaCha20Poly1305,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KeyDerivation {
+    PBKDF2 { iterations: u32 },
+    Argon2id { memory_kb: u32, iterations: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    pub algorithm: CompressionAlgorithm,
+    pub level: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    Zstd,
+    Gzip,
+    Bzip2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StorageBackend {
+    Local { path: PathBuf },
+    S3 {
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        endpoint: Option<String>,  // For S3-compatible services
+    },
+    Azure {
+        account: String,
+        container: String,
+        key: String,
+    },
+    GCS {
+        bucket: String,
+        credentials_file: PathBuf,
+    },
+    SFTP {
+        host: String,
+        port: u16,
+        username: String,
+        key_file: PathBuf,
+        remote_path: PathBuf,
+    },
+}
+
+/// Backup metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupMetadata {
+    pub backup_id: String,
+    pub created_at: DateTime<Utc>,
+    pub backup_type: BackupType,
+    pub size_bytes: u64,
+    pub compressed_size: u64,
+    pub encrypted: bool,
+    pub checksum: String,
+    pub hostname: String,
+    pub version: String,
+    pub files_included: Vec<String>,
+    pub config_hash: String,
+
+    /// Backup this one is based on; `None` for full backups
+    #[serde(default)]
+    pub parent_id: Option<String>,
+
+    /// State of every backed-up file at backup time, keyed by path
+    #[serde(default)]
+    pub manifest: HashMap<String, ManifestEntry>,
+
+    /// Files removed since the parent backup
+    #[serde(default)]
+    pub files_deleted: Vec<String>,
+
+    /// File name of the archive, used to fetch it from remote targets
+    #[serde(default)]
+    pub archive_name: Option<String>,
+
+    /// patronus-secrets key the archive is encrypted under, if not a passphrase
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+/// Manifest record used to detect changed files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackupType {
+    Full,
+    Incremental,
+    Differential,
+}
+
+/// Backup lifecycle events, for alerting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BackupEvent {
+    /// A backup was taken and copied to every remote target
+    Completed(BackupRun),
+    /// A backup, or some of its config sections, was restored
+    Restored {
+        backup_id: String,
+        /// Sections restored; empty for a full restore
+        sections: Vec<String>,
+        at: DateTime<Utc>,
+    },
+    /// The retention policy was applied
+    Pruned {
+        deleted: Vec<String>,
+        at: DateTime<Utc>,
+    },
+    /// A backup operation failed
+    Failed(BackupFailure),
+}
+
+/// A completed backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupRun {
+    pub backup_id: String,
+    pub backup_type: BackupType,
+    pub size_bytes: u64,
+    /// Remote targets the backup was verified on
+    pub targets: Vec<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// A failed backup operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFailure {
+    pub operation: BackupOperation,
+    pub backup_id: Option<String>,
+    pub error: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BackupOperation {
+    /// Writing the local archive
+    Backup,
+    /// Copying it to a remote target
+    Upload,
+    /// Applying the retention policy
+    Retention,
+    /// Restoring a backup
+    Restore,
+}
+
+/// Key material an archive is encrypted under
+#[derive(Clone)]
+enum ArchiveKey {
+    /// Operator passphrase, stretched with the configured KDF
+    Passphrase(String),
+    /// Random key held in patronus-secrets
+    Secret(SecretString),
+}
+
+/// Backup manager
+pub struct BackupManager {
+    config: BackupConfig,
+    backup_dir: PathBuf,
+    config_dirs: Vec<PathBuf>,
+    passphrase: Option<String>,
+    /// patronus-secrets key name and value
+    secret_key: Option<(String, SecretString)>,
+    events: broadcast::Sender<BackupEvent>,
+}
+
+impl BackupManager {
+    pub fn new(config: BackupConfig) -> Self {
+        Self {
+            backup_dir: match &config.storage {
+                StorageBackend::Local { path } => path.clone(),
+                _ => PathBuf::from("/var/backups/patronus"),
+            },
+            config_dirs: vec![
+                PathBuf::from("/etc/patronus"),
+                PathBuf::from("/var/lib/patronus"),
+            ],
+            config,
+            passphrase: None,
+            secret_key: None,
+            events: broadcast::channel(64).0,
+        }
+    }
+
+    /// Passphrase used to encrypt new backups and decrypt encrypted ones
+    pub fn with_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Encrypt new backups under the key stored as `name` in patronus-secrets
+    ///
+    /// A random key is generated and stored on first use. It takes
+    /// precedence over the passphrase for new backups; archives encrypted
+    /// under a passphrase still restore with it. Losing the key loses every
+    /// backup encrypted under it, so the secret store must be backed up
+    /// separately.
+    pub async fn with_secret_key(mut self, secrets: &SecretManager, name: &str) -> Result<Self, BackupError> {
+        let key = secrets
+            .get_or_generate(name, SecretType::General, "Backup archive encryption key".to_string(), 32)
+            .await
+            .map_err(|e| BackupError::Secrets(e.to_string()))?;
+        self.secret_key = Some((name.to_string(), key));
+        Ok(self)
+    }
+
+    /// Subscribe to backup lifecycle events
+    pub fn subscribe(&self) -> broadcast::Receiver<BackupEvent> {
+        self.events.subscribe()
+    }
+
+    /// Directories included in backups
+    pub fn with_config_dirs(mut self, config_dirs: Vec<PathBuf>) -> Self {
+        self.config_dirs = config_dirs;
+        self
+    }
+
+    /// Create a backup
+    ///
+    /// Incremental backups hold the files changed since the latest backup of
+    /// any type, differential ones the files changed since the latest full
+    /// backup. Without a full backup to build on, a full backup is taken.
+    /// The backup is then copied to every remote target and each copy
+    /// verified against the archive checksum.
+    pub async fn create_backup(&self, backup_type: BackupType) -> Result<BackupMetadata, BackupError> {
+        let (metadata, archive_path, metadata_path) = match self.write_backup(backup_type).await {
+            Ok(written) => written,
+            Err(e) => return Err(self.failed(BackupOperation::Backup, None, e)),
+        };
+
+        let targets = match self.upload_to_storage(&metadata, &archive_path, &metadata_path).await {
+            Ok(targets) => targets,
+            Err(e) => return Err(self.failed(BackupOperation::Upload, Some(&metadata.backup_id), e)),
+        };
+
+        tracing::info!("Backup created successfully: {}", metadata.backup_id);
+
+        let _ = self.events.send(BackupEvent::Completed(BackupRun {
+            backup_id: metadata.backup_id.clone(),
+            backup_type: metadata.backup_type,
+            size_bytes: metadata.size_bytes,
+            targets,
+            at: Utc::now(),
+        }));
+
+        Ok(metadata)
+    }
+
+    /// Take a scheduled backup and apply the retention policy
+    pub async fn run_scheduled(&self) -> Result<BackupMetadata, BackupError> {
+        let metadata = self.create_backup(BackupType::Full).await?;
+        self.apply_retention_policy().await?;
+        Ok(metadata)
+    }
+
+    /// Run [`Self::run_scheduled`] on the configured schedule
+    ///
+    /// Failures are logged and published as events; the schedule keeps
+    /// running.
+    pub fn spawn_scheduler(self: Arc<Self>) -> Result<tokio::task::JoinHandle<()>, BackupError> {
+        let schedule = self.config.schedule.cron()?;
+
+        Ok(tokio::spawn(async move {
+            loop {
+                let Some(next) = schedule.next_after(Utc::now()) else {
+                    tracing::warn!("Backup schedule '{}' never fires", schedule.expression());
+                    return;
+                };
+                let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+
+                if !self.config.enabled {
+                    continue;
+                }
+                if let Err(e) = self.run_scheduled().await {
+                    tracing::error!("Scheduled backup failed: {}", e);
+                }
+            }
+        }))
+    }
+
+    /// Publish a failure event and hand the error back
+    fn failed(&self, operation: BackupOperation, backup_id: Option<&str>, error: BackupError) -> BackupError {
+        tracing::error!("Backup {:?} failed: {}", operation, error);
+        let _ = self.events.send(BackupEvent::Failed(BackupFailure {
+            operation,
+            backup_id: backup_id.map(str::to_string),
+            error: error.to_string(),
+            at: Utc::now(),
+        }));
+        error
+    }
+
+    /// Write the archive and metadata for a new backup to the backup directory
+    async fn write_backup(&self, backup_type: BackupType) -> Result<(BackupMetadata, PathBuf, PathBuf), BackupError> {
+        let backup_id = self.unique_backup_id().await;
+        let timestamp = Utc::now();
+
+        tracing::info!("Creating {:?} backup: {}", backup_type, backup_id);
+
+        // Refuse up front so no plaintext archive is left behind
+        if self.config.encryption.enabled {
+            if !matches!(self.config.encryption.algorithm, EncryptionAlgorithm::AES256GCM) {
+                return Err(BackupError::UnsupportedEncryption(
+                    format!("{:?}", self.config.encryption.algorithm),
+                ));
+            }
+            if self.passphrase.is_none() && self.secret_key.is_none() {
+                return Err(BackupError::PassphraseRequired);
+            }
+        }
+
+        let parent = match backup_type {
+            BackupType::Full => None,
+            BackupType::Incremental => self.list_backups().await?.into_iter().next(),
+            BackupType::Differential => self.list_backups().await?
+                .into_iter()
+                .find(|b| b.backup_type == BackupType::Full),
+        };
+
+        let backup_type = if parent.is_none() && backup_type != BackupType::Full {
+            tracing::warn!("No backup to base a {:?} backup on, taking a full backup", backup_type);
+            BackupType::Full
+        } else {
+            backup_type
+        };
+
+        let parent_manifest = parent.as_ref().map(|p| &p.manifest);
+
+        // Collect all configuration files, keeping those changed since the parent
+        let mut manifest = HashMap::new();
+        let mut files = Vec::new();
+        let mut total_size = 0u64;
+
+        for config_dir in &self.config_dirs {
+            if config_dir.exists() {
+                let dir_files = self.collect_files(config_dir).await?;
+                for file in dir_files {
+                    let key = file.display().to_string();
+                    let previous = parent_manifest.and_then(|m| m.get(&key));
+                    let entry = Self::manifest_entry(&file, previous).await?;
+
+                    if previous.is_none_or(|p| p.sha256 != entry.sha256) {
+                        total_size += entry.size;
+                        files.push(file);
+                    }
+                    manifest.insert(key, entry);
+                }
+            }
+        }
+
+        let mut files_deleted: Vec<String> = parent_manifest
+            .map(|m| m.keys().filter(|path| !manifest.contains_key(*path)).cloned().collect())
+            .unwrap_or_default();
+        files_deleted.sort();
+
+        tracing::debug!(
+            "Collected {} changed files ({} bytes), {} deleted",
+            files.len(),
+            total_size,
+            files_deleted.len()
+        );
+
+        // Create tar archive
+        let archive_path = self.backup_dir.join(format!("{}.tar", backup_id));
+        self.create_tar_archive(&files, &archive_path).await?;
+
+        let mut final_path = archive_path.clone();
+        let mut compressed_size = total_size;
+
+        // Compress if enabled
+        if self.config.compression.enabled {
+            let compressed_path = self.compress_archive(&archive_path).await?;
+            compressed_size = fs::metadata(&compressed_path).await?.len();
+            fs::remove_file(&archive_path).await?;
+            final_path = compressed_path;
+        }
+
+        // Encrypt if enabled
+        if self.config.encryption.enabled {
+            let encrypted_path = self.encrypt_archive(&final_path).await?;
+            fs::remove_file(&final_path).await?;
+            final_path = encrypted_path;
+        }
+
+        // Calculate checksum
+        let checksum = self.calculate_checksum(&final_path).await?;
+
+        let metadata = BackupMetadata {
+            backup_id: backup_id.clone(),
+            created_at: timestamp,
+            backup_type,
+            size_bytes: total_size,
+            compressed_size,
+            encrypted: self.config.encryption.enabled,
+            checksum,
+            hostname: hostname::get()?.to_string_lossy().to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            files_included: files.iter().map(|p| p.display().to_string()).collect(),
+            config_hash: Self::manifest_hash(&manifest),
+            parent_id: parent.map(|p| p.backup_id),
+            manifest,
+            files_deleted,
+            archive_name: final_path.file_name().map(|n| n.to_string_lossy().to_string()),
+            key_id: self.config.encryption.enabled
+                .then(|| self.secret_key.as_ref().map(|(name, _)| name.clone()))
+                .flatten(),
+        };
+
+        // Save metadata
+        let metadata_path = self.backup_dir.join(format!("{}.json", backup_id));
+        let metadata_json = serde_json::to_string_pretty(&metadata)?;
+        fs::write(&metadata_path, metadata_json).await?;
+
+        Ok((metadata, final_path, metadata_path))
+    }
+
+    /// Restore from backup, decrypting with the manager's passphrase if needed
+    pub async fn restore_backup(&self, backup_id: &str, target_dir: Option<PathBuf>) -> Result<(), BackupError> {
+        self.restore_backup_with_passphrase(backup_id, target_dir, self.passphrase.as_deref()).await
+    }
+
+    /// Restore from backup using `passphrase` for encrypted archives
+    ///
+    /// Incremental and differential backups are restored by replaying the
+    /// full backup they build on and every backup in between. Use
+    /// [`Self::is_encrypted`] to decide whether to prompt for a passphrase.
+    /// Backups taken by a newer Patronus version are refused.
+    pub async fn restore_backup_with_passphrase(
+        &self,
+        backup_id: &str,
+        target_dir: Option<PathBuf>,
+        passphrase: Option<&str>,
+    ) -> Result<(), BackupError> {
+        tracing::info!("Restoring backup: {}", backup_id);
+
+        let restore_dir = target_dir.unwrap_or_else(|| PathBuf::from("/"));
+        if let Err(e) = self.restore_chain(backup_id, &restore_dir, passphrase).await {
+            return Err(self.failed(BackupOperation::Restore, Some(backup_id), e));
+        }
+
+        tracing::info!("Backup restored successfully to {}", restore_dir.display());
+
+        let _ = self.events.send(BackupEvent::Restored {
+            backup_id: backup_id.to_string(),
+            sections: Vec::new(),
+            at: Utc::now(),
+        });
+
+        Ok(())
+    }
+
+    /// Restore only some config sections of a backup
+    ///
+    /// A section is a top-level file or directory of a config directory,
+    /// named without its extension: `firewall` for
+    /// `/etc/patronus/firewall.toml`, `vpn` for `/etc/patronus/vpn/`. The
+    /// backup is restored to a staging directory and the section's files
+    /// copied from there; files outside the sections are left untouched.
+    /// Returns the restored file paths.
+    pub async fn restore_sections(
+        &self,
+        backup_id: &str,
+        sections: &[&str],
+        target_dir: Option<PathBuf>,
+        passphrase: Option<&str>,
+    ) -> Result<Vec<String>, BackupError> {
+        tracing::info!("Restoring sections {:?} of backup {}", sections, backup_id);
+
+        let restore_dir = target_dir.unwrap_or_else(|| PathBuf::from("/"));
+        let restored = match self.restore_sections_to(backup_id, sections, &restore_dir, passphrase).await {
+            Ok(restored) => restored,
+            Err(e) => return Err(self.failed(BackupOperation::Restore, Some(backup_id), e)),
+        };
+
+        let _ = self.events.send(BackupEvent::Restored {
+            backup_id: backup_id.to_string(),
+            sections: sections.iter().map(|s| s.to_string()).collect(),
+            at: Utc::now(),
+        });
+
+        Ok(restored)
+    }
+
+    /// Config sections present in a backup, sorted
+    pub async fn list_sections(&self, backup_id: &str) -> Result<Vec<String>, BackupError> {
+        let metadata = self.load_metadata(backup_id).await?;
+        let mut sections: Vec<String> = Self::files_present(&metadata)
+            .into_iter()
+            .filter_map(|file| self.section_of(file))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        sections.sort();
+        Ok(sections)
+    }
+
+    async fn restore_sections_to(
+        &self,
+        backup_id: &str,
+        sections: &[&str],
+        restore_dir: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Vec<String>, BackupError> {
+        let available = self.list_sections(backup_id).await?;
+        if let Some(missing) = sections.iter().find(|s| !available.iter().any(|a| a == *s)) {
+            return Err(BackupError::SectionNotFound(missing.to_string()));
+        }
+
+        let staging = self.backup_dir.join(format!(".restore-{}", backup_id));
+        if fs::try_exists(&staging).await? {
+            fs::remove_dir_all(&staging).await?;
+        }
+        fs::create_dir_all(&staging).await?;
+
+        let copied = async {
+            let chain = self.restore_chain(backup_id, &staging, passphrase).await?;
+            let metadata = chain.last().ok_or(BackupError::NotFound)?;
+
+            let mut files: Vec<&String> = Self::files_present(metadata)
+                .into_iter()
+                .filter(|file| self.section_of(file).is_some_and(|s| sections.contains(&s.as_str())))
+                .collect();
+            files.sort();
+
+            let mut restored = Vec::new();
+            for file in files {
+                let relative = Path::new(file).strip_prefix("/").unwrap_or(Path::new(file));
+                let target = restore_dir.join(relative);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::copy(staging.join(relative), &target).await?;
+                restored.push(file.clone());
+            }
+            Ok(restored)
+        }
+        .await;
+
+        fs::remove_dir_all(&staging).await?;
+        copied
+    }
+
+    /// Section a backed-up file belongs to
+    fn section_of(&self, file: &str) -> Option<String> {
+        let path = Path::new(file);
+        let relative = self.config_dirs.iter().find_map(|dir| path.strip_prefix(dir).ok())?;
+        let first = Path::new(relative.components().next()?.as_os_str());
+        first.file_stem().map(|stem| stem.to_string_lossy().to_string())
+    }
+
+    /// Replay the chain for `backup_id` into `restore_dir`, returning it
+    async fn restore_chain(
+        &self,
+        backup_id: &str,
+        restore_dir: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Vec<BackupMetadata>, BackupError> {
+        let chain = self.verify_chain(backup_id).await?;
+
+        // Check every backup before touching anything
+        let running = env!("CARGO_PKG_VERSION");
+        if let Some(newer) = chain.iter().find(|m| !version_supported(&m.version, running)) {
+            return Err(BackupError::IncompatibleVersion {
+                backup: newer.version.clone(),
+                running: running.to_string(),
+            });
+        }
+
+        for metadata in &chain {
+            self.restore_archive(metadata, restore_dir, passphrase).await?;
+
+            for deleted in &metadata.files_deleted {
+                let path = restore_dir.join(Path::new(deleted).strip_prefix("/").unwrap_or(Path::new(deleted)));
+                if fs::try_exists(&path).await? {
+                    fs::remove_file(&path).await?;
+                }
+            }
+        }
+
+        Ok(chain)
+    }
+
+    /// Restore the state as of `at` from the latest backup taken by then
+    pub async fn restore_point_in_time(
+        &self,
+        at: DateTime<Utc>,
+        target_dir: Option<PathBuf>,
+        passphrase: Option<&str>,
+    ) -> Result<BackupMetadata, BackupError> {
+        let metadata = self.list_backups().await?
+            .into_iter()
+            .find(|b| b.created_at <= at)
+            .ok_or(BackupError::NotFound)?;
+
+        self.restore_backup_with_passphrase(&metadata.backup_id, target_dir, passphrase).await?;
+
+        Ok(metadata)
+    }
+
+    /// Resolve the chain of backups needed to restore `backup_id`, oldest first
+    ///
+    /// Fails with [`BackupError::BrokenChain`] if any backup in the chain has
+    /// lost its metadata or archive, or the chain does not start at a full backup.
+    pub async fn verify_chain(&self, backup_id: &str) -> Result<Vec<BackupMetadata>, BackupError> {
+        let mut chain: Vec<BackupMetadata> = Vec::new();
+        let mut next = Some(backup_id.to_string());
+
+        while let Some(id) = next {
+            if chain.iter().any(|m| m.backup_id == id) {
+                return Err(BackupError::BrokenChain(id));
+            }
+
+            // Backups lost locally are fetched back from a remote target
+            if let Err(e) = self.fetch_from_remote(&id).await {
+                tracing::warn!("Could not fetch backup {} from remote targets: {}", id, e);
+            }
+
+            let metadata = match self.load_metadata(&id).await {
+                Ok(metadata) => metadata,
+                // The requested backup itself is simply not there
+                Err(e) if chain.is_empty() => return Err(e),
+                Err(_) => return Err(BackupError::BrokenChain(id)),
+            };
+            self.download_from_storage(&id).await
+                .map_err(|_| BackupError::BrokenChain(id.clone()))?;
+
+            if metadata.parent_id.is_none() && metadata.backup_type != BackupType::Full {
+                return Err(BackupError::BrokenChain(id));
+            }
+
+            next = metadata.parent_id.clone();
+            chain.push(metadata);
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Verify, decrypt, decompress and extract a single backup archive
+    async fn restore_archive(
+        &self,
+        metadata: &BackupMetadata,
+        restore_dir: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<(), BackupError> {
+        // Download from storage if needed
+        let backup_path = self.download_from_storage(&metadata.backup_id).await?;
+
+        // Verify checksum
+        let checksum = self.calculate_checksum(&backup_path).await?;
+
+        if checksum != metadata.checksum {
+            return Err(BackupError::ChecksumMismatch);
+        }
+
+        let mut current_path = backup_path.clone();
+
+        // Decrypt if encrypted; the archive header is authoritative, not the metadata
+        if Self::is_encrypted_file(&backup_path).await? {
+            let key = self.archive_key(&backup_path, metadata, passphrase).await?;
+            current_path = self.decrypt_archive(&current_path, key).await?;
+        }
+
+        // Decompress if compressed
+        if current_path.extension().and_then(|s| s.to_str()) == Some("zst")
+            || current_path.extension().and_then(|s| s.to_str()) == Some("gz") {
+            let decompressed = self.decompress_archive(&current_path).await;
+            if current_path != backup_path {
+                fs::remove_file(&current_path).await?;
+            }
+            current_path = decompressed?;
+        }
+
+        // Extract tar archive
+        let extracted = self.extract_tar_archive(&current_path, restore_dir).await;
+
+        // Intermediate files are removed; the backup itself is kept
+        if current_path != backup_path {
+            fs::remove_file(&current_path).await?;
+        }
+        extracted
+    }
+
+    /// Whether a backup archive is encrypted and needs a passphrase to restore
+    pub async fn is_encrypted(&self, backup_id: &str) -> Result<bool, BackupError> {
+        let path = self.download_from_storage(backup_id).await?;
+        Self::is_encrypted_file(&path).await
+    }
+
+    /// List all available backups
+    pub async fn list_backups(&self) -> Result<Vec<BackupMetadata>, BackupError> {
+        let mut backups = Vec::new();
+
+        let mut entries = fs::read_dir(&self.backup_dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("json") {
+                if let Ok(metadata) = self.load_metadata_from_path(&path).await {
+                    backups.push(metadata);
+                }
+            }
+        }
+
+        // Sort by creation time (newest first)
+        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        Ok(backups)
+    }
+
+    /// Delete old backups according to retention policy
+    ///
+    /// Keeps the newest backup of each of the last `keep_hourly` hours,
+    /// `keep_daily` days, `keep_weekly` weeks and so on, plus every backup a
+    /// kept one builds on. Deleted backups are removed from remote targets too.
+    pub async fn apply_retention_policy(&self) -> Result<(), BackupError> {
+        match self.prune().await {
+            Ok(deleted) => {
+                let _ = self.events.send(BackupEvent::Pruned { deleted, at: Utc::now() });
+                Ok(())
+            }
+            Err(e) => Err(self.failed(BackupOperation::Retention, None, e)),
+        }
+    }
+
+    async fn prune(&self) -> Result<Vec<String>, BackupError> {
+        let backups = self.list_backups().await?;
+        let retained = schedule::retained_backups(&backups, &self.config.retention);
+        let mut to_keep: HashSet<&String> = backups.iter()
+            .map(|b| &b.backup_id)
+            .filter(|id| retained.contains(*id))
+            .collect();
+
+        // Keep every backup a retained incremental or differential builds on
+        let by_id: HashMap<&String, &BackupMetadata> = backups.iter()
+            .map(|b| (&b.backup_id, b))
+            .collect();
+        let mut pending: Vec<&String> = to_keep.iter().copied().collect();
+        while let Some(id) = pending.pop() {
+            if let Some(parent) = by_id.get(id).and_then(|b| b.parent_id.as_ref()) {
+                if to_keep.insert(parent) {
+                    pending.push(parent);
+                }
+            }
+        }
+
+        // Delete backups not in retention
+        let mut deleted = Vec::new();
+        for backup in &backups {
+            if !to_keep.contains(&backup.backup_id) {
+                tracing::info!("Deleting old backup: {}", backup.backup_id);
+                self.delete_backup(backup).await?;
+                deleted.push(backup.backup_id.clone());
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Compare two backups and show differences
+    pub async fn diff_backups(&self, backup_id_a: &str, backup_id_b: &str) -> Result<BackupDiff, BackupError> {
+        let metadata_a = self.load_metadata(backup_id_a).await?;
+        let metadata_b = self.load_metadata(backup_id_b).await?;
+
+        let files_a = Self::files_present(&metadata_a);
+        let files_b = Self::files_present(&metadata_b);
+
+        let added: Vec<String> = files_b.difference(&files_a).map(|s| s.to_string()).collect();
+        let removed: Vec<String> = files_a.difference(&files_b).map(|s| s.to_string()).collect();
+
+        Ok(BackupDiff {
+            backup_a: backup_id_a.to_string(),
+            backup_b: backup_id_b.to_string(),
+            files_added: added,
+            files_removed: removed,
+            config_changed: metadata_a.config_hash != metadata_b.config_hash,
+        })
+    }
+
+    // Helper methods
+
+    /// Files present when the backup was taken, not just those it archived
+    fn files_present(metadata: &BackupMetadata) -> HashSet<&String> {
+        if metadata.manifest.is_empty() {
+            metadata.files_included.iter().collect()
+        } else {
+            metadata.manifest.keys().collect()
+        }
+    }
+
+    /// Manifest entry for `file`, reusing the previous hash if size and mtime match
+    async fn manifest_entry(file: &Path, previous: Option<&ManifestEntry>) -> Result<ManifestEntry, BackupError> {
+        let meta = fs::metadata(file).await?;
+        let size = meta.len();
+        let modified: DateTime<Utc> = meta.modified()?.into();
+
+        if let Some(previous) = previous {
+            if previous.size == size && previous.modified == modified {
+                return Ok(previous.clone());
+            }
+        }
+
+        let content = fs::read(file).await?;
+        Ok(ManifestEntry {
+            size,
+            modified,
+            sha256: format!("{:x}", Sha256::digest(&content)),
+        })
+    }
+
+    /// Hash of the whole configuration state described by a manifest
+    fn manifest_hash(manifest: &HashMap<String, ManifestEntry>) -> String {
+        let mut paths: Vec<&String> = manifest.keys().collect();
+        paths.sort();
+
+        let mut hasher = Sha256::new();
+        for path in paths {
+            hasher.update(path.as_bytes());
+            hasher.update(manifest[path].sha256.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    async fn collect_files(&self, dir: &Path) -> Result<Vec<PathBuf>, BackupError> {
+        let mut files = Vec::new();
+        let mut stack = vec![dir.to_path_buf()];
+
+        while let Some(current) = stack.pop() {
+            let mut entries = fs::read_dir(&current).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn create_tar_archive(&self, files: &[PathBuf], output: &Path) -> Result<(), BackupError> {
+        // Use tar command for production reliability
+        let file_list = files.iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let list_file = self.backup_dir.join("files.txt");
+        fs::write(&list_file, file_list).await?;
+
+        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
+        let list_file_str = list_file.to_str().ok_or(BackupError::InvalidPath)?;
+
+        let status = tokio::process::Command::new("tar")
+            .args(&["-czf", output_str, "-T", list_file_str])
+            .status()
+            .await?;
+
+        fs::remove_file(&list_file).await?;
+
+        if !status.success() {
+            return Err(BackupError::ArchiveFailed);
+        }
+
+        Ok(())
+    }
+
+    async fn compress_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
+        let output = path.with_extension("tar.zst");
+
+        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
+        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
+
+        let status = tokio::process::Command::new("zstd")
+            .args(&[
+                &format!("-{}", self.config.compression.level),
+                path_str,
+                "-o", output_str,
+            ])
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(BackupError::CompressionFailed);
+        }
+
+        Ok(output)
+    }
+
+    async fn encrypt_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
+        let key = match (&self.secret_key, &self.passphrase) {
+            (Some((_, secret)), _) => ArchiveKey::Secret(secret.clone()),
+            (None, Some(passphrase)) => ArchiveKey::Passphrase(passphrase.clone()),
+            (None, None) => return Err(BackupError::PassphraseRequired),
+        };
+        let key_derivation = self.config.encryption.key_derivation.clone();
+
+        let ext = path.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("tar");
+        let output = path.with_extension(format!("{}.enc", ext));
+
+        let plaintext = fs::read(path).await?;
+
+        // Key derivation is deliberately expensive, keep it off the runtime
+        let encrypted = tokio::task::spawn_blocking(move || {
+            encrypt_payload(&plaintext, &key, &key_derivation)
+        })
+        .await
+        .map_err(|_| BackupError::EncryptionFailed)??;
+
+        fs::write(&output, encrypted).await?;
+
+        Ok(output)
+    }
+
+    async fn decrypt_archive(&self, path: &Path, key: ArchiveKey) -> Result<PathBuf, BackupError> {
+        let output = path.with_extension("");
+
+        let data = fs::read(path).await?;
+
+        let plaintext = tokio::task::spawn_blocking(move || decrypt_payload(&data, &key))
+            .await
+            .map_err(|_| BackupError::DecryptionFailed)??;
+
+        fs::write(&output, plaintext).await?;
+
+        Ok(output)
+    }
+
+    /// Key to decrypt the archive at `path` with, going by its header
+    async fn archive_key(
+        &self,
+        path: &Path,
+        metadata: &BackupMetadata,
+        passphrase: Option<&str>,
+    ) -> Result<ArchiveKey, BackupError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut header = [0u8; ENCRYPTED_MAGIC.len() + 2];
+        fs::File::open(path).await?.read_exact(&mut header).await
+            .map_err(|_| BackupError::InvalidEncryptedArchive)?;
+
+        if header[ENCRYPTED_MAGIC.len() + 1] == KDF_SECRET_KEY {
+            let key_id = metadata.key_id.clone().unwrap_or_default();
+            return match &self.secret_key {
+                Some((name, secret)) if metadata.key_id.is_none() || metadata.key_id.as_ref() == Some(name) => {
+                    Ok(ArchiveKey::Secret(secret.clone()))
+                }
+                _ => Err(BackupError::SecretKeyRequired(key_id)),
+            };
+        }
+
+        passphrase
+            .map(|p| ArchiveKey::Passphrase(p.to_string()))
+            .ok_or(BackupError::PassphraseRequired)
+    }
+
+    async fn is_encrypted_file(path: &Path) -> Result<bool, BackupError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut file = fs::File::open(path).await?;
+        let mut magic = [0u8; ENCRYPTED_MAGIC.len()];
+        match file.read_exact(&mut magic).await {
+            Ok(_) => Ok(&magic == ENCRYPTED_MAGIC),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn decompress_archive(&self, path: &Path) -> Result<PathBuf, BackupError> {
+        let output = path.with_extension("");
+
+        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
+        let output_str = output.to_str().ok_or(BackupError::InvalidPath)?;
+
+        let status = tokio::process::Command::new("zstd")
+            .args(&["-d", path_str, "-o", output_str])
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(BackupError::DecompressionFailed);
+        }
+
+        Ok(output)
+    }
+
+    async fn extract_tar_archive(&self, path: &Path, target: &Path) -> Result<(), BackupError> {
+        let path_str = path.to_str().ok_or(BackupError::InvalidPath)?;
+        let target_str = target.to_str().ok_or(BackupError::InvalidPath)?;
+
+        let status = tokio::process::Command::new("tar")
+            .args(&["-xzf", path_str, "-C", target_str])
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(BackupError::ExtractFailed);
+        }
+
+        Ok(())
+    }
+
+    async fn calculate_checksum(&self, path: &Path) -> Result<String, BackupError> {
+        let content = fs::read(path).await?;
+        let mut hasher = Sha256::new();
+        hasher.update(&content);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    async fn load_metadata(&self, backup_id: &str) -> Result<BackupMetadata, BackupError> {
+        let path = self.backup_dir.join(format!("{}.json", backup_id));
+        self.load_metadata_from_path(&path).await
+    }
+
+    async fn load_metadata_from_path(&self, path: &Path) -> Result<BackupMetadata, BackupError> {
+        let content = fs::read_to_string(path).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    async fn delete_backup(&self, metadata: &BackupMetadata) -> Result<(), BackupError> {
+        let backup_id = &metadata.backup_id;
+
+        for target in self.remote_targets()? {
+            if let Some(archive) = &metadata.archive_name {
+                target.delete(archive).await?;
+            }
+            target.delete(&format!("{}.json", backup_id)).await?;
+        }
+
+        // Delete all local files associated with this backup
+        let mut entries = fs::read_dir(&self.backup_dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if filename.starts_with(&format!("{}.", backup_id)) {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remote targets backups are copied to
+    fn remote_targets(&self) -> Result<Vec<Box<dyn RemoteTarget>>, BackupError> {
+        let primary = match &self.config.storage {
+            // A local storage backend is the backup directory itself
+            StorageBackend::Local { .. } => None,
+            backend => Some(backend),
+        };
+
+        primary.into_iter()
+            .chain(&self.config.remote_targets)
+            .map(target_for)
+            .collect()
+    }
+
+    /// Copy a backup to every remote target, verifying each copy
+    ///
+    /// Each uploaded archive is read back and its SHA-256 compared with the
+    /// metadata checksum before the metadata is uploaded next to it.
+    /// Returns the names of the targets written.
+    async fn upload_to_storage(
+        &self,
+        metadata: &BackupMetadata,
+        backup_path: &Path,
+        metadata_path: &Path,
+    ) -> Result<Vec<String>, BackupError> {
+        let archive = backup_path.file_name()
+            .and_then(|n| n.to_str())
+            .ok_or(BackupError::InvalidPath)?;
+        let metadata_object = format!("{}.json", metadata.backup_id);
+        let verify_path = self.backup_dir.join(format!(".verify-{}", archive));
+
+        let mut written = Vec::new();
+        for target in self.remote_targets()? {
+            tracing::debug!("Uploading backup {} to {}", metadata.backup_id, target.name());
+
+            target.upload(backup_path, archive).await?;
+
+            target.download(archive, &verify_path).await?;
+            let checksum = self.calculate_checksum(&verify_path).await;
+            fs::remove_file(&verify_path).await?;
+            if checksum? != metadata.checksum {
+                return Err(BackupError::UploadVerificationFailed(target.name()));
+            }
+
+            target.upload(metadata_path, &metadata_object).await?;
+            written.push(target.name());
+        }
+
+        Ok(written)
+    }
+
+    /// Fetch a backup's metadata and archive from the first remote target
+    /// holding them, if either is missing locally
+    async fn fetch_from_remote(&self, backup_id: &str) -> Result<(), BackupError> {
+        let metadata_path = self.backup_dir.join(format!("{}.json", backup_id));
+        let have_metadata = fs::try_exists(&metadata_path).await?;
+        if have_metadata && self.download_from_storage(backup_id).await.is_ok() {
+            return Ok(());
+        }
+
+        let mut last_error = None;
+        for target in self.remote_targets()? {
+            let fetched = async {
+                if !have_metadata {
+                    let partial = metadata_path.with_extension("json.part");
+                    target.download(&format!("{}.json", backup_id), &partial).await?;
+                    fs::rename(&partial, &metadata_path).await?;
+                }
+                let metadata = self.load_metadata(backup_id).await?;
+                let archive = metadata.archive_name.ok_or(BackupError::NotFound)?;
+                target.download(&archive, &self.backup_dir.join(&archive)).await
+            }
+            .await;
+
+            match fetched {
+                Ok(()) => {
+                    tracing::info!("Fetched backup {} from {}", backup_id, target.name());
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        last_error.map_or(Ok(()), Err)
+    }
+
+    /// Locate a backup's archive in the backup directory, whatever
+    /// compression and encryption suffixes it has
+    async fn download_from_storage(&self, backup_id: &str) -> Result<PathBuf, BackupError> {
+        let prefix = format!("{}.tar", backup_id);
+
+        let mut entries = fs::read_dir(&self.backup_dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if filename == prefix || filename.starts_with(&format!("{}.", prefix)) {
+                return Ok(entry.path());
+            }
+        }
+
+        Err(BackupError::NotFound)
+    }
+
+    fn generate_backup_id() -> String {
+        format!("backup-{}", Utc::now().format("%Y%m%d-%H%M%S-%3f"))
+    }
+
+    /// Backup ID not used by an existing backup
+    async fn unique_backup_id(&self) -> String {
+        loop {
+            let backup_id = Self::generate_backup_id();
+            let metadata_path = self.backup_dir.join(format!("{}.json", backup_id));
+            if !fs::try_exists(&metadata_path).await.unwrap_or(false) {
+                return backup_id;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+    }
+}
+
+/// Derive the 256-bit archive key
+///
+/// Passphrases are stretched with PBKDF2 or Argon2id, within the
+/// [`crate::kdf`] cost limits; secret keys are already random and only
+/// expanded with the salt (HKDF-Extract).
+fn derive_key(
+    key: &ArchiveKey,
+    salt: &[u8],
+    kdf: u8,
+    iterations: u32,
+    memory_kb: u32,
+) -> Result<[u8; 32], BackupError> {
+    let mut derived = [0u8; 32];
+
+    match (kdf, key) {
+        (KDF_PBKDF2, ArchiveKey::Passphrase(passphrase)) if iterations > 0 => {
+            check_pbkdf2(iterations)?;
+            pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut derived);
+        }
+        (KDF_ARGON2ID, ArchiveKey::Passphrase(passphrase)) => {
+            check_argon2(memory_kb, iterations)?;
+            let params = argon2::Params::new(memory_kb, iterations, 1, Some(derived.len()))
+                .map_err(|_| BackupError::InvalidEncryptedArchive)?;
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .hash_password_into(passphrase.as_bytes(), salt, &mut derived)
+                .map_err(|_| BackupError::InvalidEncryptedArchive)?;
+        }
+        (KDF_SECRET_KEY, ArchiveKey::Secret(secret)) => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(salt).map_err(|_| BackupError::InvalidEncryptedArchive)?;
+            mac.update(secret.expose_secret().as_bytes());
+            derived.copy_from_slice(&mac.finalize().into_bytes());
+        }
+        (KDF_SECRET_KEY, ArchiveKey::Passphrase(_)) => return Err(BackupError::SecretKeyRequired(String::new())),
+        (KDF_PBKDF2 | KDF_ARGON2ID, ArchiveKey::Secret(_)) => return Err(BackupError::PassphraseRequired),
+        _ => return Err(BackupError::InvalidEncryptedArchive),
+    }
+
+    Ok(derived)
+}
+
+/// Encrypt an archive with AES-256-GCM
+///
+/// The header carries everything needed to re-derive the key and is
+/// authenticated as associated data, so tampering with it fails decryption.
+fn encrypt_payload(
+    plaintext: &[u8],
+    key: &ArchiveKey,
+    key_derivation: &KeyDerivation,
+) -> Result<Vec<u8>, BackupError> {
+    let (kdf, iterations, memory_kb) = match (key, key_derivation) {
+        (ArchiveKey::Secret(_), _) => (KDF_SECRET_KEY, 0, 0),
+        (ArchiveKey::Passphrase(_), KeyDerivation::PBKDF2 { iterations }) => (KDF_PBKDF2, *iterations, 0),
+        (ArchiveKey::Passphrase(_), KeyDerivation::Argon2id { memory_kb, iterations }) => {
+            (KDF_ARGON2ID, *iterations, *memory_kb)
+        }
+    };
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let derived = derive_key(key, &salt, kdf, iterations, memory_kb).map_err(|e| match e {
+        BackupError::KdfLimit(limit) => BackupError::KdfLimit(limit),
+        _ => BackupError::EncryptionFailed,
+    })?;
+
+    let mut output = Vec::with_capacity(ENCRYPTED_HEADER_LEN + plaintext.len() + 16);
+    output.extend_from_slice(ENCRYPTED_MAGIC);
+    output.push(ENCRYPTED_FORMAT_VERSION);
+    output.push(kdf);
+    output.extend_from_slice(&iterations.to_be_bytes());
+    output.extend_from_slice(&memory_kb.to_be_bytes());
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+
+    let cipher = Aes256Gcm::new_from_slice(&derived).map_err(|_| BackupError::EncryptionFailed)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &output })
+        .map_err(|_| BackupError::EncryptionFailed)?;
+
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Decrypt an archive produced by [`encrypt_payload`]
+fn decrypt_payload(data: &[u8], key: &ArchiveKey) -> Result<Vec<u8>, BackupError> {
+    if data.len() < ENCRYPTED_HEADER_LEN || !data.starts_with(ENCRYPTED_MAGIC) {
+        return Err(BackupError::InvalidEncryptedArchive);
+    }
+
+    let (header, ciphertext) = data.split_at(ENCRYPTED_HEADER_LEN);
+    let mut pos = ENCRYPTED_MAGIC.len();
+
+    if header[pos] != ENCRYPTED_FORMAT_VERSION {
+        return Err(BackupError::InvalidEncryptedArchive);
+    }
+    let kdf = header[pos + 1];
+    pos += 2;
+
+    let iterations = u32::from_be_bytes(header[pos..pos + 4].try_into().unwrap());
+    let memory_kb = u32::from_be_bytes(header[pos + 4..pos + 8].try_into().unwrap());
+    pos += 8;
+
+    let salt = &header[pos..pos + SALT_LEN];
+    let nonce = &header[pos + SALT_LEN..];
+
+    let derived = derive_key(key, salt, kdf, iterations, memory_kb)?;
+
+    let cipher = Aes256Gcm::new_from_slice(&derived).map_err(|_| BackupError::DecryptionFailed)?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| BackupError::WrongPassphrase)
+}
+
+/// Whether a backup taken by Patronus `backup` can be restored by `running`
+///
+/// Versions are compared on their numeric major.minor.patch; a backup
+/// whose version cannot be parsed is refused.
+pub(crate) fn version_supported(backup: &str, running: &str) -> bool {
+    fn parse(version: &str) -> Option<(u64, u64, u64)> {
+        let core = version.trim().trim_start_matches('v').split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some((major, minor, patch))
+    }
+
+    match (parse(backup), parse(running)) {
+        (Some(backup), Some(running)) => backup <= running,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupDiff {
+    pub backup_a: String,
+    pub backup_b: String,
+    pub files_added: Vec<String>,
+    pub files_removed: Vec<String>,
+    pub config_changed: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Archive creation failed")]
+    ArchiveFailed,
+    #[error("Compression failed")]
+    CompressionFailed,
+    #[error("Decompression failed")]
+    DecompressionFailed,
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    #[error("Decryption failed")]
+    DecryptionFailed,
+    #[error("Extract failed")]
+    ExtractFailed,
+    #[error("Backup is encrypted and no passphrase was provided")]
+    PassphraseRequired,
+    #[error("Wrong passphrase or corrupted backup")]
+    WrongPassphrase,
+    #[error("Encrypted backup header is invalid")]
+    InvalidEncryptedArchive,
+    #[error("Key derivation cost refused: {0}")]
+    KdfLimit(#[from] KdfLimitError),
+    #[error("Unsupported encryption algorithm: {0}")]
+    UnsupportedEncryption(String),
+    #[error("Checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Backup not found")]
+    NotFound,
+    #[error("Backup chain is broken at {0}")]
+    BrokenChain(String),
+    #[error("Invalid path: path contains non-UTF8 characters")]
+    InvalidPath,
+    #[error("Backup is encrypted under secret key '{0}', which is not loaded")]
+    SecretKeyRequired(String),
+    #[error("Secret store error: {0}")]
+    Secrets(String),
+    #[error("Backup was taken by Patronus {backup}, newer than the running {running}")]
+    IncompatibleVersion { backup: String, running: String },
+    #[error("Config section not in backup: {0}")]
+    SectionNotFound(String),
+    #[error("Invalid backup schedule: {0}")]
+    InvalidSchedule(String),
+    #[error("Unsupported storage backend: {0}")]
+    UnsupportedStorage(String),
+    #[error("Remote target {target} failed: {message}")]
+    Remote { target: String, message: String },
+    #[error("Upload to {0} failed checksum verification")]
+    UploadVerificationFailed(String),
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            schedule: BackupSchedule {
+                hourly: false,
+                daily: true,
+                weekly: true,
+                monthly: true,
+                custom_cron: None,
+            },
+            retention: RetentionPolicy {
+                keep_hourly: 24,
+                keep_daily: 7,
+                keep_weekly: 4,
+                keep_monthly: 12,
+                keep_yearly: 3,
+            },
+            encryption: EncryptionConfig {
+                enabled: true,
+                algorithm: EncryptionAlgorithm::AES256GCM,
+                key_derivation: KeyDerivation::Argon2id {
+                    memory_kb: 65536,
+                    iterations: 3,
+                },
+            },
+            compression: CompressionConfig {
+                enabled: true,
+                algorithm: CompressionAlgorithm::Zstd,
+                level: 3,
+            },
+            storage: StorageBackend::Local {
+                path: PathBuf::from("/var/backups/patronus"),
+            },
+            remote_targets: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        source: PathBuf,
+        backups: PathBuf,
+        restore: PathBuf,
+    }
+
+    fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("etc");
+        let backups = dir.path().join("backups");
+        let restore = dir.path().join("restore");
+        for path in [&source, &backups, &restore] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        std::fs::write(source.join("firewall.toml"), "default_policy = \"drop\"\n").unwrap();
+
+        Fixture { _dir: dir, source, backups, restore }
+    }
+
+    fn manager(fixture: &Fixture, encrypted: bool) -> BackupManager {
+        let mut config = BackupConfig::default();
+        config.compression.enabled = false;
+        config.encryption.enabled = encrypted;
+        // Cheap KDF settings keep the tests fast
+        config.encryption.key_derivation = KeyDerivation::PBKDF2 { iterations: 1_000 };
+        config.storage = StorageBackend::Local { path: fixture.backups.clone() };
+
+        BackupManager::new(config).with_config_dirs(vec![fixture.source.clone()])
+    }
+
+    fn restored_file(fixture: &Fixture) -> PathBuf {
+        let relative = fixture.source.strip_prefix("/").unwrap();
+        fixture.restore.join(relative).join("firewall.toml")
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_backup_round_trip() {
+        let fixture = fixture();
+        let manager = manager(&fixture, true).with_passphrase("correct horse battery staple");
+
+        let metadata = manager.create_backup(BackupType::Full).await.unwrap();
+        assert!(metadata.encrypted);
+        assert!(manager.is_encrypted(&metadata.backup_id).await.unwrap());
+
+        // No plaintext archive is left next to the encrypted one
+        let archive = manager.download_from_storage(&metadata.backup_id).await.unwrap();
+        assert!(archive.to_string_lossy().ends_with(".tar.enc"));
+        assert!(!fixture.backups.join(format!("{}.tar", metadata.backup_id)).exists());
+
+        manager.restore_backup(&metadata.backup_id, Some(fixture.restore.clone())).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(restored_file(&fixture)).unwrap(),
+            "default_policy = \"drop\"\n",
+        );
+
+        // The backup survives a restore
+        assert!(archive.exists());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passphrase_fails_cleanly() {
+        let fixture = fixture();
+        let manager = manager(&fixture, true).with_passphrase("correct horse battery staple");
+        let metadata = manager.create_backup(BackupType::Full).await.unwrap();
+
+        let result = manager
+            .restore_backup_with_passphrase(&metadata.backup_id, Some(fixture.restore.clone()), Some("hunter2"))
+            .await;
+        assert!(matches!(result, Err(BackupError::WrongPassphrase)));
+
+        let result = manager
+            .restore_backup_with_passphrase(&metadata.backup_id, Some(fixture.restore.clone()), None)
+            .await;
+        assert!(matches!(result, Err(BackupError::PassphraseRequired)));
+
+        // Nothing was decrypted or extracted
+        assert!(!restored_file(&fixture).exists());
+        let files = std::fs::read_dir(&fixture.backups).unwrap().count();
+        assert_eq!(files, 2);
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_backup_round_trip() {
+        let fixture = fixture();
+        let manager = manager(&fixture, false);
+
+        let metadata = manager.create_backup(BackupType::Full).await.unwrap();
+        assert!(!metadata.encrypted);
+        assert!(!manager.is_encrypted(&metadata.backup_id).await.unwrap());
+
+        manager.restore_backup(&metadata.backup_id, Some(fixture.restore.clone())).await.unwrap();
+        assert!(restored_file(&fixture).exists());
+    }
+
+    #[test]
+    fn test_kdf_cost_bounded_before_deriving() {
+        let passphrase = ArchiveKey::Passphrase("passphrase".to_string());
+        assert!(matches!(
+            encrypt_payload(b"archive", &passphrase, &KeyDerivation::PBKDF2 { iterations: 20_000_000 }),
+            Err(BackupError::KdfLimit(KdfLimitError::Pbkdf2Iterations(20_000_000)))
+        ));
+
+        // A header asking for 4 GiB and u32::MAX passes is refused at once
+        let mut encrypted = encrypt_payload(
+            b"archive",
+            &passphrase,
+            &KeyDerivation::Argon2id { memory_kb: 64, iterations: 1 },
+        ).unwrap();
+        let costs = ENCRYPTED_MAGIC.len() + 2;
+        encrypted[costs..costs + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        encrypted[costs + 4..costs + 8].copy_from_slice(&(4 * 1024 * 1024u32).to_be_bytes());
+        let started = std::time::Instant::now();
+        let err = decrypt_payload(&encrypted, &passphrase).unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert!(err.to_string().contains("exceeds the limit"), "{}", err);
+
+        encrypted[costs + 4..costs + 8].copy_from_slice(&64u32.to_be_bytes());
+        assert!(matches!(
+            decrypt_payload(&encrypted, &passphrase),
+            Err(BackupError::KdfLimit(KdfLimitError::Argon2Iterations(u32::MAX)))
+        ));
+    }
+
+    #[test]
+    fn test_argon2id_payload_and_tampered_header() {
+        let kdf = KeyDerivation::Argon2id { memory_kb: 64, iterations: 1 };
+        let passphrase = ArchiveKey::Passphrase("passphrase".to_string());
+        let mut encrypted = encrypt_payload(b"archive", &passphrase, &kdf).unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_MAGIC));
+        assert_eq!(decrypt_payload(&encrypted, &passphrase).unwrap(), b"archive");
+
+        // The header is authenticated: bumping the salt breaks decryption
+        encrypted[ENCRYPTED_HEADER_LEN - NONCE_LEN - 1] ^= 0xff;
+        assert!(matches!(
+            decrypt_payload(&encrypted, &passphrase),
+            Err(BackupError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            decrypt_payload(b"PTRNSENC", &passphrase),
+            Err(BackupError::InvalidEncryptedArchive)
+        ));
+    }
+
+    fn write(fixture: &Fixture, name: &str, content: &str) {
+        std::fs::write(fixture.source.join(name), content).unwrap();
+    }
+
+    fn restored(fixture: &Fixture, name: &str) -> Option<String> {
+        let relative = fixture.source.strip_prefix("/").unwrap();
+        std::fs::read_to_string(fixture.restore.join(relative).join(name)).ok()
+    }
+
+    #[tokio::test]
+    async fn test_incremental_contains_only_changed_file() {
+        let fixture = fixture();
+        write(&fixture, "interfaces.toml", "wan = \"eth0\"\n");
+        let manager = manager(&fixture, false);
+
+        let full = manager.create_backup(BackupType::Full).await.unwrap();
+        assert_eq!(full.files_included.len(), 2);
+
+        write(&fixture, "firewall.toml", "default_policy = \"reject\"\n");
+        let incremental = manager.create_backup(BackupType::Incremental).await.unwrap();
+
+        let changed = fixture.source.join("firewall.toml").display().to_string();
+        assert_eq!(incremental.backup_type, BackupType::Incremental);
+        assert_eq!(incremental.parent_id.as_deref(), Some(full.backup_id.as_str()));
+        assert_eq!(incremental.files_included, vec![changed]);
+        assert_eq!(incremental.manifest.len(), 2);
+        assert_ne!(incremental.config_hash, full.config_hash);
+
+        // Nothing changed since: the next incremental is empty
+        let unchanged = manager.create_backup(BackupType::Incremental).await.unwrap();
+        assert!(unchanged.files_included.is_empty());
+        assert_eq!(unchanged.config_hash, incremental.config_hash);
+    }
+
+    #[tokio::test]
+    async fn test_chain_replay_and_point_in_time() {
+        let fixture = fixture();
+        write(&fixture, "interfaces.toml", "wan = \"eth0\"\n");
+        let manager = manager(&fixture, false);
+
+        let full = manager.create_backup(BackupType::Full).await.unwrap();
+
+        write(&fixture, "firewall.toml", "default_policy = \"reject\"\n");
+        let first = manager.create_backup(BackupType::Incremental).await.unwrap();
+
+        std::fs::remove_file(fixture.source.join("interfaces.toml")).unwrap();
+        write(&fixture, "vpn.toml", "enabled = true\n");
+        let second = manager.create_backup(BackupType::Incremental).await.unwrap();
+        assert_eq!(second.files_deleted.len(), 1);
+
+        let chain = manager.verify_chain(&second.backup_id).await.unwrap();
+        let ids: Vec<&str> = chain.iter().map(|b| b.backup_id.as_str()).collect();
+        assert_eq!(ids, vec![&full.backup_id, &first.backup_id, &second.backup_id]);
+
+        manager.restore_backup(&second.backup_id, Some(fixture.restore.clone())).await.unwrap();
+        assert_eq!(restored(&fixture, "firewall.toml").unwrap(), "default_policy = \"reject\"\n");
+        assert_eq!(restored(&fixture, "vpn.toml").unwrap(), "enabled = true\n");
+        assert!(restored(&fixture, "interfaces.toml").is_none());
+
+        std::fs::remove_dir_all(&fixture.restore).unwrap();
+        std::fs::create_dir_all(&fixture.restore).unwrap();
+
+        let restored_from = manager
+            .restore_point_in_time(first.created_at, Some(fixture.restore.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(restored_from.backup_id, first.backup_id);
+        assert_eq!(restored(&fixture, "firewall.toml").unwrap(), "default_policy = \"reject\"\n");
+        assert!(restored(&fixture, "interfaces.toml").is_some());
+        assert!(restored(&fixture, "vpn.toml").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_verify_chain_detects_missing_link() {
+        let fixture = fixture();
+        let manager = manager(&fixture, false);
+
+        let full = manager.create_backup(BackupType::Full).await.unwrap();
+        write(&fixture, "firewall.toml", "default_policy = \"reject\"\n");
+        let first = manager.create_backup(BackupType::Incremental).await.unwrap();
+        write(&fixture, "firewall.toml", "default_policy = \"accept\"\n");
+        let second = manager.create_backup(BackupType::Incremental).await.unwrap();
+
+        // A differential skips the incrementals and builds on the full backup
+        let differential = manager.create_backup(BackupType::Differential).await.unwrap();
+        assert_eq!(differential.parent_id.as_deref(), Some(full.backup_id.as_str()));
+
+        std::fs::remove_file(manager.download_from_storage(&first.backup_id).await.unwrap()).unwrap();
+
+        match manager.verify_chain(&second.backup_id).await {
+            Err(BackupError::BrokenChain(id)) => assert_eq!(id, first.backup_id),
+            other => panic!("expected a broken chain, got {:?}", other.map(|c| c.len())),
+        }
+        assert!(manager.restore_backup(&second.backup_id, Some(fixture.restore.clone())).await.is_err());
+        assert!(manager.verify_chain(&differential.backup_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_secret_key_backup_with_remote_copy() {
+        use patronus_secrets::MemoryStore;
+
+        let fixture = fixture();
+        let remote = fixture.backups.parent().unwrap().join("remote");
+        let secrets = SecretManager::new(Arc::new(MemoryStore::new()));
+
+        let mut config = manager(&fixture, true).config.clone();
+        config.remote_targets = vec![StorageBackend::Local { path: remote.clone() }];
+        let manager = BackupManager::new(config.clone())
+            .with_config_dirs(vec![fixture.source.clone()])
+            .with_secret_key(&secrets, "backup/archive-key")
+            .await
+            .unwrap();
+        let mut events = manager.subscribe();
+
+        let metadata = manager.create_backup(BackupType::Full).await.unwrap();
+        assert_eq!(metadata.key_id.as_deref(), Some("backup/archive-key"));
+        match events.recv().await.unwrap() {
+            BackupEvent::Completed(run) => {
+                assert_eq!(run.backup_id, metadata.backup_id);
+                assert_eq!(run.targets, vec![format!("file://{}", remote.display())]);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Both files were copied; losing the local copies is recoverable
+        let archive = metadata.archive_name.clone().unwrap();
+        assert!(remote.join(&archive).exists());
+        assert!(remote.join(format!("{}.json", metadata.backup_id)).exists());
+        for entry in std::fs::read_dir(&fixture.backups).unwrap() {
+            std::fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+
+        manager.restore_backup(&metadata.backup_id, Some(fixture.restore.clone())).await.unwrap();
+        assert!(restored_file(&fixture).exists());
+
+        // The archive is unreadable without the key, passphrase or not
+        let keyless = BackupManager::new(config).with_passphrase("correct horse battery staple");
+        let result = keyless.restore_backup(&metadata.backup_id, Some(fixture.restore.clone())).await;
+        assert!(matches!(result, Err(BackupError::SecretKeyRequired(key)) if key == "backup/archive-key"));
+
+        // Retention removes the remote copies along with the local ones
+        let mut pruning = manager;
+        pruning.config.retention = RetentionPolicy { keep_hourly: 0, keep_daily: 0, keep_weekly: 0, keep_monthly: 0, keep_yearly: 0 };
+        pruning.apply_retention_policy().await.unwrap();
+        assert_eq!(std::fs::read_dir(&remote).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_section_restore_and_version_check() {
+        let fixture = fixture();
+        std::fs::create_dir_all(fixture.source.join("vpn")).unwrap();
+        write(&fixture, "vpn/wg0.conf", "[Interface]\n");
+        let manager = manager(&fixture, false);
+        let mut events = manager.subscribe();
+
+        let metadata = manager.create_backup(BackupType::Full).await.unwrap();
+        assert_eq!(manager.list_sections(&metadata.backup_id).await.unwrap(), vec!["firewall", "vpn"]);
+
+        let paths = manager
+            .restore_sections(&metadata.backup_id, &["vpn"], Some(fixture.restore.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(paths, vec![fixture.source.join("vpn/wg0.conf").display().to_string()]);
+        assert_eq!(restored(&fixture, "vpn/wg0.conf").unwrap(), "[Interface]\n");
+        assert!(!restored_file(&fixture).exists());
+
+        let result = manager.restore_sections(&metadata.backup_id, &["dhcp"], Some(fixture.restore.clone()), None).await;
+        assert!(matches!(result, Err(BackupError::SectionNotFound(section)) if section == "dhcp"));
+
+        // A backup from a newer release is refused before anything is written
+        let metadata_path = fixture.backups.join(format!("{}.json", metadata.backup_id));
+        let mut newer = metadata.clone();
+        newer.version = "999.0.0".to_string();
+        std::fs::write(&metadata_path, serde_json::to_string(&newer).unwrap()).unwrap();
+
+        let result = manager.restore_backup(&metadata.backup_id, Some(fixture.restore.clone())).await;
+        assert!(matches!(result, Err(BackupError::IncompatibleVersion { .. })));
+        assert!(!restored_file(&fixture).exists());
+
+        let mut failures = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let BackupEvent::Failed(failure) = event {
+                failures.push(failure.operation);
+            }
+        }
+        assert_eq!(failures, vec![BackupOperation::Restore, BackupOperation::Restore]);
+    }
+
+    #[test]
+    fn test_version_supported() {
+        assert!(version_supported("0.1.0", "0.1.0"));
+        assert!(version_supported("0.9.3", "1.0.0"));
+        assert!(version_supported("1.2.0-rc1", "1.2.0"));
+        assert!(!version_supported("1.2.1", "1.2.0"));
+        assert!(!version_supported("garbage", "1.2.0"));
+    }
+
+    #[tokio::test]
+    async fn test_encryption_requires_passphrase() {
+        let fixture = fixture();
+        let result = manager(&fixture, true).create_backup(BackupType::Full).await;
+        assert!(matches!(result, Err(BackupError::PassphraseRequired)));
+        assert_eq!(std::fs::read_dir(&fixture.backups).unwrap().count(), 0);
+    }
+}
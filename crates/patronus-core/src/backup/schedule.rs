@@ -0,0 +1,342 @@
+//! Backup scheduling and retention
+//!
+//! Schedules are cron expressions with the usual five fields (minute, hour,
+//! day of month, month, day of week), evaluated in UTC. Retention keeps the
+//! newest backup of each of the last N hours, days, ISO weeks, months and
+//! years, so "keep 7 daily, 4 weekly" survives a week of missed runs.
+
+use super::{BackupError, BackupMetadata, BackupSchedule, RetentionPolicy};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use std::collections::HashSet;
+
+/// Upper bound on the steps taken looking for the next run (several years)
+const MAX_SEARCH_STEPS: usize = 100_000;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Day of month field was `*`
+    any_day_of_month: bool,
+    /// Day of week field was `*`
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression
+    ///
+    /// Supports `*`, values, ranges (`1-5`), steps (`*/15`, `0-30/10`),
+    /// lists (`1,15`), month and day names (`jan`, `mon`) and the `@hourly`,
+    /// `@daily`, `@weekly`, `@monthly` and `@yearly` shorthands. Day of
+    /// week 0 and 7 are both Sunday.
+    pub fn parse(expression: &str) -> Result<Self, BackupError> {
+        let invalid = |reason: &str| BackupError::InvalidSchedule(format!("{}: {}", expression, reason));
+
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(invalid("expected 5 fields"));
+        }
+
+        let minutes = parse_field(fields[0], 0, 59, &[]).map_err(|e| invalid(&e))?;
+        let hours = parse_field(fields[1], 0, 23, &[]).map_err(|e| invalid(&e))?;
+        let days_of_month = parse_field(fields[2], 1, 31, &[]).map_err(|e| invalid(&e))?;
+        let months = parse_field(fields[3], 1, 12, &MONTH_NAMES).map_err(|e| invalid(&e))?;
+        let mut days_of_week = parse_field(fields[4], 0, 7, &DAY_NAMES).map_err(|e| invalid(&e))?;
+
+        // 7 is an alias for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            minutes,
+            hours,
+            days_of_month,
+            months,
+            days_of_week,
+            any_day_of_month: fields[2] == "*",
+            any_day_of_week: fields[4] == "*",
+        })
+    }
+
+    /// The expression this schedule was parsed from
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Whether the schedule fires at the minute containing `at`
+    pub fn matches(&self, at: DateTime<Utc>) -> bool {
+        bit(self.minutes, at.minute())
+            && bit(self.hours, at.hour())
+            && bit(self.months, at.month())
+            && self.matches_day(at.date_naive())
+    }
+
+    /// First time strictly after `after` the schedule fires, if any
+    ///
+    /// Returns `None` for expressions that never fire, e.g. `0 0 31 2 *`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut at = start;
+
+        for _ in 0..MAX_SEARCH_STEPS {
+            if !bit(self.months, at.month()) {
+                let (year, month) = if at.month() == 12 { (at.year() + 1, 1) } else { (at.year(), at.month() + 1) };
+                at = Utc.from_utc_datetime(&NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?);
+                continue;
+            }
+            if !self.matches_day(at.date_naive()) {
+                at = Utc.from_utc_datetime(&at.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?);
+                continue;
+            }
+            if !bit(self.hours, at.hour()) {
+                at = at.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !bit(self.minutes, at.minute()) {
+                at += Duration::minutes(1);
+                continue;
+            }
+            return Some(at);
+        }
+
+        None
+    }
+
+    /// Cron semantics: when both day fields are restricted either may match
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let dom = bit(self.days_of_month, date.day());
+        let dow = bit(self.days_of_week, date.weekday().num_days_from_sunday());
+
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => dow,
+            (false, true) => dom,
+            (false, false) => dom || dow,
+        }
+    }
+}
+
+impl BackupSchedule {
+    /// Cron schedule backups run on
+    ///
+    /// `custom_cron` wins; otherwise the most frequent enabled interval is
+    /// used (hourly on the hour, daily at 02:00, weekly on Sunday at 03:00,
+    /// monthly on the 1st at 04:00). Less frequent intervals are covered by
+    /// the retention policy.
+    pub fn cron(&self) -> Result<CronSchedule, BackupError> {
+        let expression = match &self.custom_cron {
+            Some(expression) => expression.as_str(),
+            None if self.hourly => "0 * * * *",
+            None if self.daily => "0 2 * * *",
+            None if self.weekly => "0 3 * * 0",
+            None if self.monthly => "0 4 1 * *",
+            None => return Err(BackupError::InvalidSchedule("no backup interval enabled".to_string())),
+        };
+
+        CronSchedule::parse(expression)
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Parse one cron field into a bitmask of allowed values
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(idx) = names.iter().position(|n| *n == lower) {
+            // Month names start at 1, day names at 0
+            return Ok(idx as u32 + min);
+        }
+        let v: u32 = s.parse().map_err(|_| format!("invalid value '{}'", s))?;
+        if v < min || v > max {
+            return Err(format!("value {} out of range {}-{}", v, min, max));
+        }
+        Ok(v)
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step '{}'", step))?;
+                if step == 0 {
+                    return Err("step must be positive".to_string());
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/10` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("empty range '{}'", range));
+        }
+
+        let mut v = start;
+        while v <= end {
+            mask |= 1 << v;
+            v += step;
+        }
+    }
+
+    Ok(mask)
+}
+
+/// Names the retention period a backup time falls in
+type PeriodKey = fn(&DateTime<Utc>) -> String;
+
+/// IDs of the backups kept by `policy`
+///
+/// For each period the newest backup in each of the most recent `keep_*`
+/// distinct periods is kept; a backup may be kept by several periods.
+/// Backups that retained incrementals build on are not included here.
+pub(crate) fn retained_backups(backups: &[BackupMetadata], policy: &RetentionPolicy) -> HashSet<String> {
+    let mut newest_first: Vec<&BackupMetadata> = backups.iter().collect();
+    newest_first.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+
+    let periods: [(u32, PeriodKey); 5] = [
+        (policy.keep_hourly, |t| t.format("%Y-%m-%d %H").to_string()),
+        (policy.keep_daily, |t| t.format("%Y-%m-%d").to_string()),
+        (policy.keep_weekly, |t| {
+            let week = t.iso_week();
+            format!("{}-W{}", week.year(), week.week())
+        }),
+        (policy.keep_monthly, |t| t.format("%Y-%m").to_string()),
+        (policy.keep_yearly, |t| t.format("%Y").to_string()),
+    ];
+
+    let mut keep = HashSet::new();
+    for (count, period) in periods {
+        let mut seen = HashSet::new();
+        for backup in &newest_first {
+            if seen.len() >= count as usize {
+                break;
+            }
+            if seen.insert(period(&backup.created_at)) {
+                keep.insert(backup.backup_id.clone());
+            }
+        }
+    }
+
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let every_quarter = CronSchedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(every_quarter.next_after(at("2024-03-10T10:07:30Z")), Some(at("2024-03-10T10:15:00Z")));
+        assert_eq!(every_quarter.next_after(at("2024-03-10T10:45:00Z")), Some(at("2024-03-10T11:00:00Z")));
+
+        // Weekdays at 02:30
+        let weekdays = CronSchedule::parse("30 2 * * mon-fri").unwrap();
+        assert_eq!(weekdays.next_after(at("2024-03-08T03:00:00Z")), Some(at("2024-03-11T02:30:00Z")));
+
+        // Either day field matches when both are restricted
+        let either = CronSchedule::parse("0 0 13 * 5").unwrap();
+        assert_eq!(either.next_after(at("2024-03-01T12:00:00Z")), Some(at("2024-03-08T00:00:00Z")));
+        assert_eq!(either.next_after(at("2024-03-12T12:00:00Z")), Some(at("2024-03-13T00:00:00Z")));
+
+        let yearly = CronSchedule::parse("@yearly").unwrap();
+        assert_eq!(yearly.next_after(at("2024-12-31T23:59:59Z")), Some(at("2025-01-01T00:00:00Z")));
+        assert!(yearly.matches(at("2025-01-01T00:00:42Z")));
+
+        let sunday = CronSchedule::parse("0 3 * * 7").unwrap();
+        assert_eq!(sunday.next_after(at("2024-03-10T03:00:00Z")), Some(at("2024-03-17T03:00:00Z")));
+
+        assert_eq!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(at("2024-01-01T00:00:00Z")), None);
+
+        for invalid in ["* * * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "0 0 * foo *"] {
+            assert!(CronSchedule::parse(invalid).is_err(), "{} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn test_schedule_flags() {
+        let schedule = BackupSchedule { hourly: false, daily: true, weekly: true, monthly: false, custom_cron: None };
+        assert_eq!(schedule.cron().unwrap().expression(), "0 2 * * *");
+
+        let custom = BackupSchedule { custom_cron: Some("15 1 * * *".to_string()), ..schedule.clone() };
+        assert_eq!(custom.cron().unwrap().expression(), "15 1 * * *");
+
+        let none = BackupSchedule { daily: false, weekly: false, ..schedule };
+        assert!(matches!(none.cron(), Err(BackupError::InvalidSchedule(_))));
+    }
+
+    #[test]
+    fn test_retention_keeps_newest_per_period() {
+        let backup = |id: &str, created_at: &str| BackupMetadata {
+            backup_id: id.to_string(),
+            created_at: at(created_at),
+            backup_type: super::super::BackupType::Full,
+            size_bytes: 0,
+            compressed_size: 0,
+            encrypted: false,
+            checksum: String::new(),
+            hostname: String::new(),
+            version: "0.1.0".to_string(),
+            files_included: Vec::new(),
+            config_hash: String::new(),
+            parent_id: None,
+            manifest: Default::default(),
+            files_deleted: Vec::new(),
+            archive_name: None,
+            key_id: None,
+        };
+
+        // A nightly backup from 1 to 10 March, plus a manual one on the 10th
+        let mut backups: Vec<BackupMetadata> = (1..=10)
+            .map(|day| backup(&format!("d{:02}", day), &format!("2024-03-{:02}T02:00:00Z", day)))
+            .collect();
+        backups.push(backup("manual", "2024-03-10T14:00:00Z"));
+
+        let policy = RetentionPolicy { keep_hourly: 0, keep_daily: 3, keep_weekly: 2, keep_monthly: 0, keep_yearly: 0 };
+        let kept = retained_backups(&backups, &policy);
+
+        // Three days (the 10th's newest is the manual one), and the newest of
+        // the previous ISO week (26 Feb - 3 Mar)
+        let expected: HashSet<String> = ["manual", "d09", "d08", "d03"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(kept, expected);
+    }
+}
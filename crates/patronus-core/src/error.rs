@@ -1,5 +1,6 @@
 //! Error types for Patronus
 
+use crate::validation::ValidationErrors;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,6 +8,9 @@ pub enum Error {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Invalid configuration: {0}")]
+    Validation(ValidationErrors),
+
     #[error("Network error: {0}")]
     Network(String),
 
@@ -26,4 +30,10 @@ pub enum Error {
     Unknown(String),
 }
 
+impl From<ValidationErrors> for Error {
+    fn from(errors: ValidationErrors) -> Self {
+        Error::Validation(errors)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
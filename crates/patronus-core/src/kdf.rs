@@ -0,0 +1,59 @@
+//! Key Derivation Cost Limits
+//!
+//! Encrypted backups and node transfer archives record the KDF cost they
+//! were made with, so a crafted header could demand hours of CPU or
+//! gigabytes of memory before decryption fails. Both formats check these
+//! bounds before running a KDF, and exporters check them too so they never
+//! write an archive that cannot be imported.
+
+use thiserror::Error;
+
+/// Most PBKDF2 iterations accepted
+pub const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// Most Argon2 passes (t_cost) accepted
+pub const MAX_ARGON2_ITERATIONS: u32 = 10;
+
+/// Most Argon2 memory accepted, in KiB (1 GiB)
+pub const MAX_ARGON2_MEMORY_KB: u32 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum KdfLimitError {
+    #[error("PBKDF2 iterations {0} exceed the limit of {MAX_PBKDF2_ITERATIONS}")]
+    Pbkdf2Iterations(u32),
+    #[error("Argon2 iterations {0} exceed the limit of {MAX_ARGON2_ITERATIONS}")]
+    Argon2Iterations(u32),
+    #[error("Argon2 memory cost {0} KiB exceeds the limit of {MAX_ARGON2_MEMORY_KB} KiB")]
+    Argon2Memory(u32),
+}
+
+pub fn check_pbkdf2(iterations: u32) -> Result<(), KdfLimitError> {
+    if iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(KdfLimitError::Pbkdf2Iterations(iterations));
+    }
+    Ok(())
+}
+
+pub fn check_argon2(memory_kb: u32, iterations: u32) -> Result<(), KdfLimitError> {
+    if memory_kb > MAX_ARGON2_MEMORY_KB {
+        return Err(KdfLimitError::Argon2Memory(memory_kb));
+    }
+    if iterations > MAX_ARGON2_ITERATIONS {
+        return Err(KdfLimitError::Argon2Iterations(iterations));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_are_inclusive() {
+        assert!(check_pbkdf2(MAX_PBKDF2_ITERATIONS).is_ok());
+        assert_eq!(check_pbkdf2(u32::MAX), Err(KdfLimitError::Pbkdf2Iterations(u32::MAX)));
+        assert!(check_argon2(MAX_ARGON2_MEMORY_KB, MAX_ARGON2_ITERATIONS).is_ok());
+        assert_eq!(check_argon2(MAX_ARGON2_MEMORY_KB + 1, 1), Err(KdfLimitError::Argon2Memory(MAX_ARGON2_MEMORY_KB + 1)));
+        assert_eq!(check_argon2(64, 11), Err(KdfLimitError::Argon2Iterations(11)));
+    }
+}
@@ -11,10 +11,16 @@ pub mod validation;
 pub mod certs;
 
 pub mod backup;
+pub mod kdf;
+pub mod node;
 
 pub use error::{Error, Result};
-pub use service::{ServiceManager, InitSystem, ServiceState};
-pub use backup::{BackupManager, BackupConfig};
+pub use service::{
+    ServiceManager, InitSystem, ServiceRun, ServiceState, ServiceEvent, ServiceEventKind,
+    ServiceReport, HealthCheck, HealthCheckConfig, HealthCheckResult, RestartPolicy, Watchdog,
+};
+pub use backup::{BackupManager, BackupConfig, BackupEvent, BackupOperation, CronSchedule, RemoteTarget};
+pub use node::{NodeExporter, NodeImporter, NodeComponent, ImportReport, NodeTransferError};
 pub use validation::*;
 
 #[cfg(feature = "certificates")]
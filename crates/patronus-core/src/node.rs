@@ -0,0 +1,1108 @@
+//! Node Export and Import
+//!
+//! Moves everything that makes a Patronus node *this* node — configuration
+//! store, declarative state, certificates, secrets and SD-WAN site identity —
+//! onto replacement hardware as a single archive.
+//!
+//! Each piece of state is a [`NodeComponent`] provided by the crate that owns
+//! it. [`NodeExporter`] packs their payloads into an archive signed with
+//! HMAC-SHA256 under a key derived from a transfer passphrase; payloads of
+//! sensitive components are also encrypted, so secrets never leave the node
+//! in the clear. [`NodeImporter`] verifies the archive, stages every
+//! component next to its live state without touching it, and only then
+//! commits them in turn, rolling back the ones already committed if any
+//! commit fails.
+
+use aes_gcm::aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::kdf::{check_argon2, KdfLimitError};
+use hmac::{Hmac, Mac};
+use patronus_secrets::{SecretManager, SecretMetadata, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+/// Marks an archive written by [`NodeExporter`]
+const NODE_MAGIC: &[u8; 8] = b"PTRNNODE";
+const NODE_FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const MAC_LEN: usize = 32;
+
+/// magic | format | manifest length (u32)
+const HEADER_LEN: usize = NODE_MAGIC.len() + 1 + 4;
+
+/// Component name of the certificate store, for [`NodeExporter::exclude`]
+pub const CERTIFICATES: &str = "certificates";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Component names and their plaintext payloads, in archive order
+type Payloads = Vec<(String, Vec<u8>)>;
+
+/// A piece of node state that can be carried to another node
+#[async_trait]
+pub trait NodeComponent: Send + Sync {
+    /// Unique name, used to match payloads to components on import
+    fn name(&self) -> &str;
+
+    /// Whether the payload must be encrypted in the archive
+    fn sensitive(&self) -> bool {
+        false
+    }
+
+    /// Serialize the live state, or `None` if there is none on this node
+    async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError>;
+
+    /// Validate `payload` and prepare it for commit without changing live state
+    async fn stage(&self, payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError>;
+}
+
+/// A component payload ready to replace the live state
+#[async_trait]
+pub trait StagedComponent: Send {
+    /// Make the staged state live
+    async fn commit(&mut self) -> Result<(), NodeTransferError>;
+
+    /// Restore the state from before [`Self::commit`], if it ran, and
+    /// discard what was staged
+    async fn rollback(&mut self) -> Result<(), NodeTransferError>;
+
+    /// Release what was kept for rollback once the whole import succeeded
+    async fn finish(&mut self) -> Result<(), NodeTransferError> {
+        Ok(())
+    }
+}
+
+/// Archive contents, authenticated by the trailing MAC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeManifest {
+    pub software_version: String,
+    pub hostname: String,
+    pub created_at: DateTime<Utc>,
+    pub kdf: KdfParams,
+    pub components: Vec<ComponentEntry>,
+}
+
+/// Argon2id parameters the transfer keys were derived with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kb: u32,
+    pub iterations: u32,
+    pub salt: String,
+}
+
+/// One payload; payloads follow the manifest in this order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentEntry {
+    pub name: String,
+    /// Bytes in the archive, including the GCM tag when encrypted
+    pub size: u64,
+    /// SHA-256 of the payload as stored, so no digest of a secret is exposed
+    pub sha256: String,
+    pub encrypted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// A component that was not restored, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedComponent {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Outcome of a successful import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub source_hostname: String,
+    pub software_version: String,
+    pub exported_at: DateTime<Utc>,
+    pub restored: Vec<String>,
+    pub skipped: Vec<SkippedComponent>,
+}
+
+/// Packs node state into a signed archive
+pub struct NodeExporter {
+    components: Vec<Arc<dyn NodeComponent>>,
+    excluded: HashSet<String>,
+    software_version: String,
+    memory_kb: u32,
+    iterations: u32,
+}
+
+impl Default for NodeExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeExporter {
+    pub fn new() -> Self {
+        Self {
+            components: Vec::new(),
+            excluded: HashSet::new(),
+            software_version: env!("CARGO_PKG_VERSION").to_string(),
+            memory_kb: 65536,
+            iterations: 3,
+        }
+    }
+
+    pub fn with_component(mut self, component: Arc<dyn NodeComponent>) -> Self {
+        self.components.push(component);
+        self
+    }
+
+    /// Leave a component out of the archive, e.g. [`CERTIFICATES`]
+    pub fn exclude(mut self, name: &str) -> Self {
+        self.excluded.insert(name.to_string());
+        self
+    }
+
+    /// Argon2id cost for deriving the transfer keys
+    pub fn with_kdf(mut self, memory_kb: u32, iterations: u32) -> Self {
+        self.memory_kb = memory_kb;
+        self.iterations = iterations;
+        self
+    }
+
+    /// Export every registered component under `passphrase`
+    pub async fn export(&self, passphrase: &str) -> Result<Vec<u8>, NodeTransferError> {
+        if passphrase.is_empty() {
+            return Err(NodeTransferError::PassphraseRequired);
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let kdf = KdfParams {
+            memory_kb: self.memory_kb,
+            iterations: self.iterations,
+            salt: hex::encode(salt),
+        };
+        let keys = TransferKeys::derive(passphrase, &kdf)?;
+
+        let mut entries = Vec::new();
+        let mut blobs = Vec::new();
+        for component in &self.components {
+            let name = component.name();
+            if self.excluded.contains(name) {
+                continue;
+            }
+            let Some(payload) = component.export().await? else {
+                tracing::info!("Node component {} has no state to export", name);
+                continue;
+            };
+
+            let (blob, nonce) = if component.sensitive() {
+                let (blob, nonce) = keys.encrypt(name, &payload)?;
+                (blob, Some(hex::encode(nonce)))
+            } else {
+                (payload, None)
+            };
+
+            entries.push(ComponentEntry {
+                name: name.to_string(),
+                size: blob.len() as u64,
+                sha256: hex::encode(Sha256::digest(&blob)),
+                encrypted: nonce.is_some(),
+                nonce,
+            });
+            blobs.push(blob);
+        }
+
+        let manifest = NodeManifest {
+            software_version: self.software_version.clone(),
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            created_at: Utc::now(),
+            kdf,
+            components: entries,
+        };
+        let manifest = serde_json::to_vec(&manifest)?;
+
+        let mut archive = Vec::with_capacity(HEADER_LEN + manifest.len() + blobs.iter().map(Vec::len).sum::<usize>() + MAC_LEN);
+        archive.extend_from_slice(NODE_MAGIC);
+        archive.push(NODE_FORMAT_VERSION);
+        archive.extend_from_slice(&(manifest.len() as u32).to_be_bytes());
+        archive.extend_from_slice(&manifest);
+        for blob in blobs {
+            archive.extend_from_slice(&blob);
+        }
+        let mac = keys.sign(&archive);
+        archive.extend_from_slice(&mac);
+
+        Ok(archive)
+    }
+}
+
+/// Restores node state from an archive written by [`NodeExporter`]
+pub struct NodeImporter {
+    components: Vec<Arc<dyn NodeComponent>>,
+    running_version: String,
+}
+
+impl Default for NodeImporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeImporter {
+    pub fn new() -> Self {
+        Self {
+            components: Vec::new(),
+            running_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Register a component; components are committed in registration order
+    pub fn with_component(mut self, component: Arc<dyn NodeComponent>) -> Self {
+        self.components.push(component);
+        self
+    }
+
+    /// Verify an archive and read its manifest without restoring anything
+    pub fn inspect(&self, archive: &[u8], passphrase: &str) -> Result<NodeManifest, NodeTransferError> {
+        open_archive(archive, passphrase).map(|(manifest, _)| manifest)
+    }
+
+    /// Restore every registered component present in the archive
+    ///
+    /// Nothing is changed unless all components stage successfully. If a
+    /// commit fails, components already committed are rolled back.
+    pub async fn import(&self, archive: &[u8], passphrase: &str) -> Result<ImportReport, NodeTransferError> {
+        let (manifest, mut payloads) = open_archive(archive, passphrase)?;
+        if !crate::backup::version_supported(&manifest.software_version, &self.running_version) {
+            return Err(NodeTransferError::IncompatibleVersion {
+                archive: manifest.software_version,
+                running: self.running_version.clone(),
+            });
+        }
+
+        let mut skipped = Vec::new();
+        for (name, _) in &payloads {
+            if !self.components.iter().any(|c| c.name() == name) {
+                skipped.push(SkippedComponent {
+                    name: name.clone(),
+                    reason: "no such component on this node".to_string(),
+                });
+            }
+        }
+
+        let mut staged: Vec<(String, Box<dyn StagedComponent>)> = Vec::new();
+        for component in &self.components {
+            let name = component.name().to_string();
+            let Some(index) = payloads.iter().position(|(n, _)| *n == name) else {
+                skipped.push(SkippedComponent {
+                    name,
+                    reason: "not in archive".to_string(),
+                });
+                continue;
+            };
+            let (_, payload) = payloads.swap_remove(index);
+
+            match component.stage(payload).await {
+                Ok(stage) => staged.push((name, stage)),
+                Err(e) => {
+                    tracing::error!("Staging node component {} failed: {}", name, e);
+                    rollback_all(&mut staged).await;
+                    return Err(NodeTransferError::Stage {
+                        component: name,
+                        message: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        for index in 0..staged.len() {
+            if let Err(e) = staged[index].1.commit().await {
+                let component = staged[index].0.clone();
+                tracing::error!("Committing node component {} failed: {}", component, e);
+                let rolled_back = staged[..index].iter().map(|(name, _)| name.clone()).collect();
+                rollback_all(&mut staged).await;
+                return Err(NodeTransferError::Commit {
+                    component,
+                    message: e.to_string(),
+                    rolled_back,
+                });
+            }
+        }
+
+        let mut restored = Vec::new();
+        for (name, stage) in &mut staged {
+            if let Err(e) = stage.finish().await {
+                tracing::warn!("Cleaning up after node component {} failed: {}", name, e);
+            }
+            restored.push(name.clone());
+        }
+
+        tracing::info!(
+            "Imported node state from {} ({} restored, {} skipped)",
+            manifest.hostname,
+            restored.len(),
+            skipped.len()
+        );
+
+        Ok(ImportReport {
+            source_hostname: manifest.hostname,
+            software_version: manifest.software_version,
+            exported_at: manifest.created_at,
+            restored,
+            skipped,
+        })
+    }
+}
+
+/// Roll back staged components in reverse order, logging failures
+async fn rollback_all(staged: &mut [(String, Box<dyn StagedComponent>)]) {
+    for (name, stage) in staged.iter_mut().rev() {
+        if let Err(e) = stage.rollback().await {
+            tracing::error!("Rolling back node component {} failed: {}", name, e);
+        }
+    }
+}
+
+/// Verify an archive and return its manifest and decrypted payloads
+fn open_archive(archive: &[u8], passphrase: &str) -> Result<(NodeManifest, Payloads), NodeTransferError> {
+    if passphrase.is_empty() {
+        return Err(NodeTransferError::PassphraseRequired);
+    }
+    if archive.len() < HEADER_LEN + MAC_LEN || &archive[..NODE_MAGIC.len()] != NODE_MAGIC {
+        return Err(NodeTransferError::InvalidArchive("not a node archive".to_string()));
+    }
+    if archive[NODE_MAGIC.len()] != NODE_FORMAT_VERSION {
+        return Err(NodeTransferError::InvalidArchive(format!(
+            "unsupported format {}",
+            archive[NODE_MAGIC.len()]
+        )));
+    }
+
+    let manifest_len = u32::from_be_bytes(archive[NODE_MAGIC.len() + 1..HEADER_LEN].try_into().unwrap()) as usize;
+    let (signed, mac) = archive.split_at(archive.len() - MAC_LEN);
+    let manifest_bytes = signed
+        .get(HEADER_LEN..HEADER_LEN + manifest_len)
+        .ok_or_else(|| NodeTransferError::InvalidArchive("truncated manifest".to_string()))?;
+
+    // The manifest is read before the MAC is checked because it carries the
+    // KDF salt; nothing in it is trusted until verification below
+    let manifest: NodeManifest = serde_json::from_slice(manifest_bytes)
+        .map_err(|_| NodeTransferError::InvalidSignature)?;
+    let keys = TransferKeys::derive(passphrase, &manifest.kdf)?;
+    keys.verify(signed, mac)?;
+
+    let mut payloads = Vec::new();
+    let mut offset = HEADER_LEN + manifest_len;
+    for entry in &manifest.components {
+        let end = usize::try_from(entry.size)
+            .ok()
+            .and_then(|size| offset.checked_add(size))
+            .filter(|end| *end <= signed.len())
+            .ok_or_else(|| NodeTransferError::InvalidArchive(format!("truncated payload {}", entry.name)))?;
+        let blob = &signed[offset..end];
+        offset = end;
+        if hex::encode(Sha256::digest(blob)) != entry.sha256 {
+            return Err(NodeTransferError::ChecksumMismatch(entry.name.clone()));
+        }
+
+        let payload = match (&entry.encrypted, &entry.nonce) {
+            (false, _) => blob.to_vec(),
+            (true, Some(nonce)) => keys.decrypt(&entry.name, nonce, blob)?,
+            (true, None) => {
+                return Err(NodeTransferError::InvalidArchive(format!("{} has no nonce", entry.name)))
+            }
+        };
+        payloads.push((entry.name.clone(), payload));
+    }
+
+    Ok((manifest, payloads))
+}
+
+/// Encryption and signing keys derived from the transfer passphrase
+struct TransferKeys {
+    encryption: [u8; 32],
+    signing: [u8; 32],
+}
+
+impl TransferKeys {
+    fn derive(passphrase: &str, kdf: &KdfParams) -> Result<Self, NodeTransferError> {
+        check_argon2(kdf.memory_kb, kdf.iterations)?;
+        let salt = hex::decode(&kdf.salt)
+            .map_err(|_| NodeTransferError::InvalidArchive("invalid KDF salt".to_string()))?;
+
+        let mut derived = [0u8; 64];
+        let params = argon2::Params::new(kdf.memory_kb, kdf.iterations, 1, Some(derived.len()))
+            .map_err(|e| NodeTransferError::InvalidArchive(format!("invalid KDF parameters: {}", e)))?;
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut derived)
+            .map_err(|e| NodeTransferError::InvalidArchive(format!("key derivation failed: {}", e)))?;
+
+        let mut keys = Self {
+            encryption: [0u8; 32],
+            signing: [0u8; 32],
+        };
+        keys.encryption.copy_from_slice(&derived[..32]);
+        keys.signing.copy_from_slice(&derived[32..]);
+        Ok(keys)
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        <HmacSha256 as Mac>::new_from_slice(&self.signing).expect("HMAC accepts any key length")
+    }
+
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), NodeTransferError> {
+        let mut mac = self.mac();
+        mac.update(data);
+        mac.verify_slice(signature).map_err(|_| NodeTransferError::InvalidSignature)
+    }
+
+    /// AES-256-GCM with the component name as associated data, so payloads
+    /// cannot be swapped between components
+    fn encrypt(&self, name: &str, plaintext: &[u8]) -> Result<(Vec<u8>, [u8; NONCE_LEN]), NodeTransferError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new_from_slice(&self.encryption).map_err(|_| NodeTransferError::EncryptionFailed)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: name.as_bytes() })
+            .map_err(|_| NodeTransferError::EncryptionFailed)?;
+        Ok((ciphertext, nonce))
+    }
+
+    fn decrypt(&self, name: &str, nonce: &str, ciphertext: &[u8]) -> Result<Vec<u8>, NodeTransferError> {
+        let nonce = hex::decode(nonce)
+            .ok()
+            .filter(|n| n.len() == NONCE_LEN)
+            .ok_or_else(|| NodeTransferError::InvalidArchive(format!("invalid nonce for {}", name)))?;
+        let cipher = Aes256Gcm::new_from_slice(&self.encryption).map_err(|_| NodeTransferError::DecryptionFailed)?;
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: ciphertext, aad: name.as_bytes() })
+            .map_err(|_| NodeTransferError::DecryptionFailed)
+    }
+}
+
+/// A secret with the metadata needed to store it again
+#[derive(Serialize, Deserialize)]
+struct SecretEntry {
+    metadata: SecretMetadata,
+    value: String,
+}
+
+/// Every secret in a [`SecretManager`]
+///
+/// Imported secrets are added to, or replace, those already on the node;
+/// secrets not in the archive are left alone.
+pub struct SecretsComponent {
+    secrets: Arc<SecretManager>,
+}
+
+impl SecretsComponent {
+    pub fn new(secrets: Arc<SecretManager>) -> Self {
+        Self { secrets }
+    }
+}
+
+#[async_trait]
+impl NodeComponent for SecretsComponent {
+    fn name(&self) -> &str {
+        "secrets"
+    }
+
+    fn sensitive(&self) -> bool {
+        true
+    }
+
+    async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError> {
+        let mut entries = Vec::new();
+        for metadata in self.secrets.list_secrets().await.map_err(secrets_error)? {
+            let Some(value) = self.secrets.get_secret(&metadata.key).await.map_err(secrets_error)? else {
+                continue;
+            };
+            entries.push(SecretEntry {
+                metadata,
+                value: value.into_string(),
+            });
+        }
+        entries.sort_by(|a, b| a.metadata.key.cmp(&b.metadata.key));
+        Ok(Some(serde_json::to_vec(&entries)?))
+    }
+
+    async fn stage(&self, payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError> {
+        let entries: Vec<SecretEntry> = serde_json::from_slice(&payload)?;
+        for entry in &entries {
+            self.secrets
+                .validate_secret(&SecretString::from_str(&entry.value), entry.metadata.secret_type)
+                .map_err(|e| NodeTransferError::Failed(format!("secret {}: {}", entry.metadata.key, e)))?;
+        }
+
+        Ok(Box::new(StagedSecrets {
+            secrets: Arc::clone(&self.secrets),
+            entries,
+            previous: Vec::new(),
+        }))
+    }
+}
+
+struct StagedSecrets {
+    secrets: Arc<SecretManager>,
+    entries: Vec<SecretEntry>,
+    /// Keys written so far and what they held before
+    previous: Vec<(String, Option<(SecretString, SecretMetadata)>)>,
+}
+
+impl StagedSecrets {
+    async fn store(&self, value: SecretString, metadata: &SecretMetadata) -> Result<(), NodeTransferError> {
+        self.secrets
+            .store_secret(
+                &metadata.key,
+                value,
+                metadata.secret_type,
+                metadata.description.clone(),
+                metadata.rotation_days,
+            )
+            .await
+            .map_err(secrets_error)
+    }
+}
+
+#[async_trait]
+impl StagedComponent for StagedSecrets {
+    async fn commit(&mut self) -> Result<(), NodeTransferError> {
+        for entry in &self.entries {
+            let key = &entry.metadata.key;
+            let value = self.secrets.get_secret(key).await.map_err(secrets_error)?;
+            let metadata = self.secrets.get_metadata(key).await.map_err(secrets_error)?;
+            self.previous.push((key.clone(), value.zip(metadata)));
+
+            self.store(SecretString::from_str(&entry.value), &entry.metadata).await?;
+        }
+        Ok(())
+    }
+
+    async fn rollback(&mut self) -> Result<(), NodeTransferError> {
+        while let Some((key, previous)) = self.previous.pop() {
+            match previous {
+                Some((value, metadata)) => self.store(value, &metadata).await?,
+                None => self.secrets.delete_secret(&key).await.map_err(secrets_error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn secrets_error(e: anyhow::Error) -> NodeTransferError {
+    NodeTransferError::Failed(format!("secret store: {}", e))
+}
+
+/// Checks a payload before it is staged
+pub type PayloadValidator = fn(&[u8]) -> Result<(), String>;
+
+/// A single file, replaced atomically by rename
+pub struct FileComponent {
+    name: String,
+    path: PathBuf,
+    sensitive: bool,
+    validator: Option<PayloadValidator>,
+}
+
+impl FileComponent {
+    pub fn new(name: &str, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.into(),
+            sensitive: false,
+            validator: None,
+        }
+    }
+
+    /// Encrypt the file in the archive and write it back owner-only
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+
+    /// Refuse payloads `validator` rejects
+    pub fn with_validator(mut self, validator: PayloadValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+}
+
+#[async_trait]
+impl NodeComponent for FileComponent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError> {
+        match fs::read(&self.path).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn stage(&self, payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError> {
+        if let Some(validator) = self.validator {
+            validator(&payload).map_err(NodeTransferError::Failed)?;
+        }
+
+        let staged = StagedPath::new(&self.path, false);
+        staged.clear().await?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&staged.staged, &payload).await?;
+        if self.sensitive {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&staged.staged, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        Ok(Box::new(staged))
+    }
+}
+
+/// A directory tree, carried as a gzipped tarball and swapped in by rename
+pub struct DirectoryComponent {
+    name: String,
+    path: PathBuf,
+    sensitive: bool,
+}
+
+impl DirectoryComponent {
+    pub fn new(name: &str, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.into(),
+            sensitive: false,
+        }
+    }
+
+    /// Encrypt the tarball in the archive
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+}
+
+#[async_trait]
+impl NodeComponent for DirectoryComponent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError> {
+        if !fs::try_exists(&self.path).await? {
+            return Ok(None);
+        }
+
+        let output = tokio::process::Command::new("tar")
+            .arg("-czf")
+            .arg("-")
+            .arg("-C")
+            .arg(&self.path)
+            .arg(".")
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(NodeTransferError::Failed(format!(
+                "tar {}: {}",
+                self.path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(Some(output.stdout))
+    }
+
+    async fn stage(&self, payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError> {
+        use tokio::io::AsyncWriteExt;
+
+        let staged = StagedPath::new(&self.path, true);
+        staged.clear().await?;
+        fs::create_dir_all(&staged.staged).await?;
+
+        let mut child = tokio::process::Command::new("tar")
+            .arg("-xzf")
+            .arg("-")
+            .arg("-C")
+            .arg(&staged.staged)
+            .stdin(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let written = stdin.write_all(&payload).await;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+
+        if written.is_err() || !output.status.success() {
+            staged.clear().await?;
+            return Err(NodeTransferError::Failed(format!(
+                "extracting {}: {}",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(Box::new(staged))
+    }
+}
+
+/// A file or directory staged beside the live one, so the commit is a rename
+/// on the same filesystem
+struct StagedPath {
+    live: PathBuf,
+    staged: PathBuf,
+    previous: PathBuf,
+    directory: bool,
+    committed: bool,
+    had_previous: bool,
+}
+
+impl StagedPath {
+    fn new(live: &Path, directory: bool) -> Self {
+        let sibling = |suffix: &str| {
+            let mut name = live.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            live.with_file_name(name)
+        };
+        Self {
+            live: live.to_path_buf(),
+            staged: sibling(".import"),
+            previous: sibling(".pre-import"),
+            directory,
+            committed: false,
+            had_previous: false,
+        }
+    }
+
+    async fn remove(&self, path: &Path) -> std::io::Result<()> {
+        let result = if self.directory {
+            fs::remove_dir_all(path).await
+        } else {
+            fs::remove_file(path).await
+        };
+        match result {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove leftovers of an earlier, interrupted import
+    async fn clear(&self) -> std::io::Result<()> {
+        self.remove(&self.staged).await
+    }
+}
+
+#[async_trait]
+impl StagedComponent for StagedPath {
+    async fn commit(&mut self) -> Result<(), NodeTransferError> {
+        self.remove(&self.previous).await?;
+        self.had_previous = fs::try_exists(&self.live).await?;
+        if self.had_previous {
+            fs::rename(&self.live, &self.previous).await?;
+        }
+        if let Err(e) = fs::rename(&self.staged, &self.live).await {
+            if self.had_previous {
+                fs::rename(&self.previous, &self.live).await?;
+            }
+            return Err(e.into());
+        }
+        self.committed = true;
+        Ok(())
+    }
+
+    async fn rollback(&mut self) -> Result<(), NodeTransferError> {
+        if self.committed {
+            self.remove(&self.live).await?;
+            if self.had_previous {
+                fs::rename(&self.previous, &self.live).await?;
+            }
+            self.committed = false;
+        }
+        self.remove(&self.staged).await?;
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<(), NodeTransferError> {
+        self.remove(&self.previous).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NodeTransferError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("A transfer passphrase is required")]
+    PassphraseRequired,
+    #[error("Invalid node archive: {0}")]
+    InvalidArchive(String),
+    #[error("Wrong passphrase or tampered archive")]
+    InvalidSignature,
+    #[error("Key derivation cost refused: {0}")]
+    KdfLimit(#[from] KdfLimitError),
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    #[error("Decryption failed")]
+    DecryptionFailed,
+    #[error("Checksum mismatch in {0}")]
+    ChecksumMismatch(String),
+    #[error("Archive was exported by Patronus {archive}, newer than the running {running}")]
+    IncompatibleVersion { archive: String, running: String },
+    #[error("{0}")]
+    Failed(String),
+    #[error("Staging {component} failed, nothing was changed: {message}")]
+    Stage { component: String, message: String },
+    #[error("Committing {component} failed: {message} (rolled back: {rolled_back:?})")]
+    Commit {
+        component: String,
+        message: String,
+        rolled_back: Vec<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus_secrets::{MemoryStore, SecretType};
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn exporter() -> NodeExporter {
+        NodeExporter::new().with_kdf(64, 1)
+    }
+
+    async fn secrets_with(key: &str, value: &str) -> Arc<SecretManager> {
+        let secrets = Arc::new(SecretManager::new(Arc::new(MemoryStore::new())));
+        secrets
+            .store_secret(key, SecretString::from_str(value), SecretType::General, "test".to_string(), Some(90))
+            .await
+            .unwrap();
+        secrets
+    }
+
+    /// Fails every commit, to exercise rollback
+    struct Unwritable;
+
+    struct UnwritableStage;
+
+    #[async_trait]
+    impl NodeComponent for Unwritable {
+        fn name(&self) -> &str {
+            "unwritable"
+        }
+
+        async fn export(&self) -> Result<Option<Vec<u8>>, NodeTransferError> {
+            Ok(Some(b"x".to_vec()))
+        }
+
+        async fn stage(&self, _payload: Vec<u8>) -> Result<Box<dyn StagedComponent>, NodeTransferError> {
+            Ok(Box::new(UnwritableStage))
+        }
+    }
+
+    #[async_trait]
+    impl StagedComponent for UnwritableStage {
+        async fn commit(&mut self) -> Result<(), NodeTransferError> {
+            Err(NodeTransferError::Failed("read-only".to_string()))
+        }
+
+        async fn rollback(&mut self) -> Result<(), NodeTransferError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("certs/ca")).unwrap();
+        std::fs::write(source.path().join("certs/ca/root.pem"), "CERT").unwrap();
+        std::fs::write(source.path().join("identity.json"), "{\"site\":1}").unwrap();
+
+        let archive = exporter()
+            .with_component(Arc::new(SecretsComponent::new(secrets_with("vpn/psk", "s3cr3t-value").await)))
+            .with_component(Arc::new(DirectoryComponent::new(CERTIFICATES, source.path().join("certs")).sensitive()))
+            .with_component(Arc::new(FileComponent::new("sdwan-identity", source.path().join("identity.json")).sensitive()))
+            .with_component(Arc::new(FileComponent::new("absent", source.path().join("missing.db"))))
+            .export(PASSPHRASE)
+            .await
+            .unwrap();
+
+        // Sensitive payloads never appear in the clear
+        assert!(!archive.windows(12).any(|w| w == b"s3cr3t-value"));
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(target.path().join("certs")).unwrap();
+        std::fs::write(target.path().join("certs/old.pem"), "OLD").unwrap();
+        let secrets = Arc::new(SecretManager::new(Arc::new(MemoryStore::new())));
+
+        let importer = NodeImporter::new()
+            .with_component(Arc::new(SecretsComponent::new(Arc::clone(&secrets))))
+            .with_component(Arc::new(DirectoryComponent::new(CERTIFICATES, target.path().join("certs"))))
+            .with_component(Arc::new(FileComponent::new("config-store", target.path().join("config.db"))));
+
+        assert_eq!(importer.inspect(&archive, PASSPHRASE).unwrap().components.len(), 3);
+
+        let report = importer.import(&archive, PASSPHRASE).await.unwrap();
+        assert_eq!(report.restored, vec!["secrets", CERTIFICATES]);
+        let skipped: Vec<_> = report.skipped.iter().map(|s| (s.name.as_str(), s.reason.as_str())).collect();
+        assert_eq!(skipped, vec![
+            ("sdwan-identity", "no such component on this node"),
+            ("config-store", "not in archive"),
+        ]);
+
+        assert_eq!(secrets.get_secret("vpn/psk").await.unwrap().unwrap().expose_secret(), "s3cr3t-value");
+        assert_eq!(secrets.get_metadata("vpn/psk").await.unwrap().unwrap().rotation_days, Some(90));
+        assert_eq!(std::fs::read_to_string(target.path().join("certs/ca/root.pem")).unwrap(), "CERT");
+        assert!(!target.path().join("certs/old.pem").exists());
+        assert!(!target.path().join("certs.pre-import").exists());
+    }
+
+    #[tokio::test]
+    async fn test_signature_and_version_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.yaml"), "a: 1").unwrap();
+        let component: Arc<dyn NodeComponent> = Arc::new(FileComponent::new("state", dir.path().join("state.yaml")));
+
+        let archive = exporter().with_component(Arc::clone(&component)).export(PASSPHRASE).await.unwrap();
+        let importer = NodeImporter::new().with_component(component);
+
+        assert!(matches!(importer.inspect(&archive, "wrong"), Err(NodeTransferError::InvalidSignature)));
+        assert!(matches!(importer.inspect(&archive, ""), Err(NodeTransferError::PassphraseRequired)));
+
+        let mut tampered = archive.clone();
+        let last_payload_byte = tampered.len() - MAC_LEN - 1;
+        tampered[last_payload_byte] ^= 1;
+        assert!(matches!(importer.inspect(&tampered, PASSPHRASE), Err(NodeTransferError::InvalidSignature)));
+        assert!(matches!(
+            importer.inspect(&archive[..archive.len() - 1], PASSPHRASE),
+            Err(NodeTransferError::InvalidSignature)
+        ));
+
+        let mut newer = exporter().with_component(Arc::new(FileComponent::new("state", dir.path().join("state.yaml"))));
+        newer.software_version = "99.0.0".to_string();
+        let archive = newer.export(PASSPHRASE).await.unwrap();
+        assert!(matches!(
+            importer.import(&archive, PASSPHRASE).await,
+            Err(NodeTransferError::IncompatibleVersion { archive, .. }) if archive == "99.0.0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_kdf_cost_bounded_before_deriving() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.yaml"), "a: 1").unwrap();
+        let component: Arc<dyn NodeComponent> = Arc::new(FileComponent::new("state", dir.path().join("state.yaml")));
+
+        assert!(matches!(
+            NodeExporter::new().with_kdf(2 * 1024 * 1024, 1).with_component(Arc::clone(&component)).export(PASSPHRASE).await,
+            Err(NodeTransferError::KdfLimit(KdfLimitError::Argon2Memory(_)))
+        ));
+
+        // Rewrite the manifest of a valid archive to ask for u32::MAX passes
+        let archive = exporter().with_component(Arc::clone(&component)).export(PASSPHRASE).await.unwrap();
+        let manifest_len = u32::from_be_bytes(archive[NODE_MAGIC.len() + 1..HEADER_LEN].try_into().unwrap()) as usize;
+        let mut manifest: NodeManifest = serde_json::from_slice(&archive[HEADER_LEN..HEADER_LEN + manifest_len]).unwrap();
+        manifest.kdf.iterations = u32::MAX;
+        let manifest = serde_json::to_vec(&manifest).unwrap();
+        let mut crafted = archive[..NODE_MAGIC.len() + 1].to_vec();
+        crafted.extend_from_slice(&(manifest.len() as u32).to_be_bytes());
+        crafted.extend_from_slice(&manifest);
+        crafted.extend_from_slice(&archive[HEADER_LEN + manifest_len..]);
+
+        let started = std::time::Instant::now();
+        let err = NodeImporter::new().with_component(component).inspect(&crafted, PASSPHRASE).unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert!(matches!(err, NodeTransferError::KdfLimit(KdfLimitError::Argon2Iterations(u32::MAX))), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_stage_failure_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("state")).unwrap();
+        std::fs::write(dir.path().join("state/current.yaml"), "new").unwrap();
+        std::fs::write(dir.path().join("identity.json"), "garbage").unwrap();
+
+        let archive = exporter()
+            .with_component(Arc::new(DirectoryComponent::new("declarative", dir.path().join("state"))))
+            .with_component(Arc::new(FileComponent::new("identity", dir.path().join("identity.json"))))
+            .export(PASSPHRASE)
+            .await
+            .unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(target.path().join("state")).unwrap();
+        std::fs::write(target.path().join("state/current.yaml"), "old").unwrap();
+
+        let result = NodeImporter::new()
+            .with_component(Arc::new(DirectoryComponent::new("declarative", target.path().join("state"))))
+            .with_component(Arc::new(
+                FileComponent::new("identity", target.path().join("identity.json"))
+                    .with_validator(|payload| serde_json::from_slice::<serde_json::Value>(payload).map(|_| ()).map_err(|e| e.to_string())),
+            ))
+            .import(&archive, PASSPHRASE)
+            .await;
+
+        assert!(matches!(result, Err(NodeTransferError::Stage { component, .. }) if component == "identity"));
+        assert_eq!(std::fs::read_to_string(target.path().join("state/current.yaml")).unwrap(), "old");
+        let mut leftovers: Vec<_> = std::fs::read_dir(target.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        leftovers.sort();
+        assert_eq!(leftovers, vec!["state"]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_failure_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.yaml"), "new").unwrap();
+        let secrets = secrets_with("api/key", "imported-key").await;
+
+        let archive = exporter()
+            .with_component(Arc::new(FileComponent::new("state", dir.path().join("state.yaml"))))
+            .with_component(Arc::new(SecretsComponent::new(secrets)))
+            .with_component(Arc::new(Unwritable))
+            .export(PASSPHRASE)
+            .await
+            .unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("state.yaml"), "old").unwrap();
+        let secrets = secrets_with("api/key", "existing-key").await;
+
+        let result = NodeImporter::new()
+            .with_component(Arc::new(FileComponent::new("state", target.path().join("state.yaml"))))
+            .with_component(Arc::new(SecretsComponent::new(Arc::clone(&secrets))))
+            .with_component(Arc::new(Unwritable))
+            .import(&archive, PASSPHRASE)
+            .await;
+
+        match result {
+            Err(NodeTransferError::Commit { component, rolled_back, .. }) => {
+                assert_eq!(component, "unwritable");
+                assert_eq!(rolled_back, vec!["state", "secrets"]);
+            }
+            other => panic!("expected commit failure, got {:?}", other.map(|r| r.restored)),
+        }
+        assert_eq!(std::fs::read_to_string(target.path().join("state.yaml")).unwrap(), "old");
+        assert!(!target.path().join("state.yaml.pre-import").exists());
+        assert_eq!(secrets.get_secret("api/key").await.unwrap().unwrap().expose_secret(), "existing-key");
+    }
+}
@@ -0,0 +1,304 @@
+//! Service health checks and the systemd watchdog
+//!
+//! A running unit is not necessarily a working one: a hung daemon keeps its
+//! PID. Health checks probe what the service actually offers (a TCP port, an
+//! HTTP endpoint, or a command's exit code) so the supervisor in
+//! [`super::ServiceManager`] can restart it.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+/// How to probe a service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HealthCheck {
+    /// A TCP connection to `address` (`host:port`) must succeed
+    Tcp { address: String },
+
+    /// A GET on `url` must return `expected_status`, or any 2xx if unset
+    Http {
+        url: String,
+        #[serde(default)]
+        expected_status: Option<u16>,
+    },
+
+    /// `program args...` must exit with `expected_exit`
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        expected_exit: i32,
+    },
+}
+
+/// A health check and how it is scheduled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    pub check: HealthCheck,
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Consecutive failures before the service is restarted
+    pub failure_threshold: u32,
+}
+
+impl HealthCheckConfig {
+    pub fn new(check: HealthCheck) -> Self {
+        Self {
+            check,
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(5),
+            failure_threshold: 3,
+        }
+    }
+}
+
+/// Restart backoff and circuit breaker settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartPolicy {
+    /// Delay before the first restart; doubled for every restart in the
+    /// last hour
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Restarts allowed in any hour before the circuit opens and the
+    /// service is left failed
+    pub max_restarts_per_hour: u32,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            max_restarts_per_hour: 5,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before the next restart, given how many happened this hour
+    pub fn backoff(&self, recent_restarts: usize) -> Duration {
+        let factor = 1u32.checked_shl(recent_restarts.min(31) as u32).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Outcome of one health check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheckResult {
+    pub healthy: bool,
+    pub message: String,
+    pub latency: Duration,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl HealthCheck {
+    /// Probe the service, failing if it takes longer than `timeout`
+    pub async fn run(&self, timeout: Duration) -> HealthCheckResult {
+        let started = Instant::now();
+        let outcome = match tokio::time::timeout(timeout, self.probe(timeout)).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(format!("timed out after {:?}", timeout)),
+        };
+
+        let (healthy, message) = match outcome {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        HealthCheckResult {
+            healthy,
+            message,
+            latency: started.elapsed(),
+            checked_at: Utc::now(),
+        }
+    }
+
+    async fn probe(&self, timeout: Duration) -> std::result::Result<String, String> {
+        match self {
+            HealthCheck::Tcp { address } => TcpStream::connect(address)
+                .await
+                .map(|_| format!("connected to {}", address))
+                .map_err(|e| format!("connect to {} failed: {}", address, e)),
+
+            HealthCheck::Http { url, expected_status } => {
+                let client = reqwest::Client::builder()
+                    .timeout(timeout)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let status = client.get(url).send().await.map_err(|e| e.to_string())?.status();
+                let ok = match expected_status {
+                    Some(expected) => status.as_u16() == *expected,
+                    None => status.is_success(),
+                };
+                if ok {
+                    Ok(format!("HTTP {}", status.as_u16()))
+                } else {
+                    Err(format!("unexpected HTTP {}", status.as_u16()))
+                }
+            }
+
+            HealthCheck::Command { program, args, expected_exit } => {
+                let status = Command::new(program)
+                    .args(args)
+                    .kill_on_drop(true)
+                    .status()
+                    .await
+                    .map_err(|e| format!("failed to run {}: {}", program, e))?;
+                match status.code() {
+                    Some(code) if code == *expected_exit => Ok(format!("exit {}", code)),
+                    Some(code) => Err(format!("exit {} (expected {})", code, expected_exit)),
+                    None => Err("terminated by signal".to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Pings the systemd watchdog (`sd_notify("WATCHDOG=1")`) for this process
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    socket: PathBuf,
+    interval: Duration,
+}
+
+impl Watchdog {
+    /// Watchdog for `socket`, pinged every `interval`
+    pub fn new(socket: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            socket: socket.into(),
+            interval,
+        }
+    }
+
+    /// The watchdog systemd configured for this process, if any
+    ///
+    /// Requires `NOTIFY_SOCKET` and `WATCHDOG_USEC` (and `WATCHDOG_PID`, when
+    /// set, to name this process). Pings are sent at half the timeout, as
+    /// sd_watchdog_enabled(3) recommends.
+    pub fn from_env() -> Option<Self> {
+        let socket = std::env::var_os("NOTIFY_SOCKET")?;
+        let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+            if pid.parse::<u32>().ok()? != std::process::id() {
+                return None;
+            }
+        }
+        Some(Self::new(socket, Duration::from_micros(usec / 2)))
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Tell systemd the supervisor is alive
+    pub fn notify(&self) -> std::io::Result<()> {
+        sd_notify(&self.socket, "WATCHDOG=1")
+    }
+}
+
+/// Send a state string to the systemd notification socket at `socket`
+///
+/// Paths starting with `@` are Linux abstract sockets.
+pub fn sd_notify(socket: &std::path::Path, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sock = UnixDatagram::unbound()?;
+    let path = socket.to_string_lossy();
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+            sock.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            sock.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_tcp_and_command_checks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let check = HealthCheck::Tcp { address: address.clone() };
+        assert!(check.run(Duration::from_secs(1)).await.healthy);
+        drop(listener);
+        let result = check.run(Duration::from_secs(1)).await;
+        assert!(!result.healthy);
+        assert!(result.message.contains(&address));
+
+        let check = |program: &str, expected_exit| HealthCheck::Command {
+            program: program.to_string(),
+            args: Vec::new(),
+            expected_exit,
+        };
+        assert!(check("true", 0).run(Duration::from_secs(1)).await.healthy);
+        assert!(check("false", 1).run(Duration::from_secs(1)).await.healthy);
+        assert_eq!(check("false", 0).run(Duration::from_secs(1)).await.message, "exit 1 (expected 0)");
+
+        let hung = HealthCheck::Command {
+            program: "sleep".to_string(),
+            args: vec!["5".to_string()],
+            expected_exit: 0,
+        };
+        let result = hung.run(Duration::from_millis(100)).await;
+        assert!(!result.healthy);
+        assert!(result.message.starts_with("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_http_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/health", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for status in ["200 OK", "503 Service Unavailable"] {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = conn.read(&mut buf).await.unwrap();
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                conn.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let check = HealthCheck::Http { url, expected_status: None };
+        assert!(check.run(Duration::from_secs(2)).await.healthy);
+        let result = check.run(Duration::from_secs(2)).await;
+        assert!(!result.healthy);
+        assert_eq!(result.message, "unexpected HTTP 503");
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(8));
+        assert_eq!(policy.backoff(40), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_watchdog_notify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let watchdog = Watchdog::new(&path, Duration::from_secs(15));
+        watchdog.notify().unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"WATCHDOG=1");
+    }
+}
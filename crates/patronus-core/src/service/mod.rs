@@ -0,0 +1,1094 @@
+//! Service management abstraction
+//!
+//! Provides a unified interface for managing system services across
+//! different init systems (systemd, OpenRC).
+//!
+//! Services can also be supervised: a health check runs on an interval and
+//! a service that keeps failing it is restarted with exponential backoff,
+//! until too many restarts in an hour open a circuit breaker. When Patronus
+//! itself runs under a systemd watchdog the supervisor loop keeps it fed.
+
+pub mod health;
+
+use crate::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+pub use health::{HealthCheck, HealthCheckConfig, HealthCheckResult, RestartPolicy, Watchdog};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// How often the supervisor looks for due health checks
+const SUPERVISOR_TICK: Duration = Duration::from_secs(1);
+
+/// Init system type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InitSystem {
+    Systemd,
+    OpenRC,
+    SysVInit,
+    Unknown,
+}
+
+/// Service state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServiceState {
+    Running,
+    Stopped,
+    Failed,
+    Unknown,
+    /// Running but failing its health check
+    Unhealthy,
+    /// Restarted by the supervisor, waiting for a passing health check
+    Restarting,
+}
+
+/// Something the supervisor observed or did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServiceEventKind {
+    StateChanged { from: ServiceState, to: ServiceState },
+    Restarted { restarts_this_hour: u32 },
+    RestartFailed { error: String },
+    /// Too many restarts this hour; the service is left failed
+    CircuitOpened { restarts_this_hour: u32 },
+    CircuitClosed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceEvent {
+    pub service: String,
+    pub kind: ServiceEventKind,
+    pub at: DateTime<Utc>,
+}
+
+/// Supervision summary for one managed service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceReport {
+    pub name: String,
+    pub state: ServiceState,
+    /// Time since the service was last seen healthy after a (re)start
+    pub uptime: Option<Duration>,
+    pub restart_count: u32,
+    pub restarts_this_hour: u32,
+    pub circuit_open: bool,
+    pub last_health_check: Option<HealthCheckResult>,
+}
+
+/// Runtime supervision state of one service
+#[derive(Debug)]
+struct Supervised {
+    check: HealthCheckConfig,
+    state: ServiceState,
+    up_since: Option<(Instant, DateTime<Utc>)>,
+    next_check: Option<Instant>,
+    consecutive_failures: u32,
+    restart_count: u32,
+    recent_restarts: VecDeque<Instant>,
+    circuit_open: bool,
+    last_result: Option<HealthCheckResult>,
+}
+
+impl Supervised {
+    fn new(check: HealthCheckConfig) -> Self {
+        Self {
+            check,
+            state: ServiceState::Unknown,
+            up_since: None,
+            next_check: None,
+            consecutive_failures: 0,
+            restart_count: 0,
+            recent_restarts: VecDeque::new(),
+            circuit_open: false,
+            last_result: None,
+        }
+    }
+
+    fn prune_restarts(&mut self, now: Instant) {
+        while self.recent_restarts.front().is_some_and(|at| now.duration_since(*at) >= HOUR) {
+            self.recent_restarts.pop_front();
+        }
+    }
+}
+
+/// Outcome of starting or stopping a set of services
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceRun {
+    /// Services handled successfully, in the order they were processed
+    pub completed: Vec<String>,
+
+    /// Services whose start or stop failed, with the error
+    pub failed: Vec<(String, String)>,
+
+    /// Services not started because a dependency failed, with that dependency
+    pub blocked: Vec<(String, String)>,
+}
+
+impl ServiceRun {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.blocked.is_empty()
+    }
+}
+
+/// Service manager
+pub struct ServiceManager {
+    init_system: InitSystem,
+
+    /// Service name -> services it requires
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+
+    restart_policy: RestartPolicy,
+    supervised: Mutex<HashMap<String, Supervised>>,
+    events: broadcast::Sender<ServiceEvent>,
+}
+
+impl ServiceManager {
+    /// Create a new service manager (auto-detects init system)
+    pub fn new() -> Self {
+        Self::with_init_system(Self::detect_init_system())
+    }
+
+    /// Create a service manager for a specific init system
+    pub fn with_init_system(init_system: InitSystem) -> Self {
+        Self {
+            init_system,
+            dependencies: BTreeMap::new(),
+            restart_policy: RestartPolicy::default(),
+            supervised: Mutex::new(HashMap::new()),
+            events: broadcast::channel(64).0,
+        }
+    }
+
+    /// Supervise a service with a health check, registering it if needed
+    pub fn set_health_check(&mut self, service_name: &str, check: HealthCheckConfig) {
+        self.add_service(service_name);
+        self.supervised
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(service_name.to_string(), Supervised::new(check));
+    }
+
+    /// Backoff and circuit breaker settings for supervised restarts
+    pub fn set_restart_policy(&mut self, policy: RestartPolicy) {
+        self.restart_policy = policy;
+    }
+
+    /// Supervision events (state changes, restarts, circuit breaker)
+    pub fn subscribe(&self) -> broadcast::Receiver<ServiceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Close the circuit breaker of a service so it may be restarted again
+    pub fn reset_circuit(&self, service_name: &str) {
+        let mut supervised = self.supervision();
+        if let Some(entry) = supervised.get_mut(service_name) {
+            entry.recent_restarts.clear();
+            entry.consecutive_failures = 0;
+            if std::mem::take(&mut entry.circuit_open) {
+                self.emit(service_name, ServiceEventKind::CircuitClosed);
+            }
+        }
+    }
+
+    /// Uptime, restarts and last health check of every managed service
+    ///
+    /// Services without a health check report their init system status.
+    pub fn get_service_report(&self) -> Vec<ServiceReport> {
+        let now = Instant::now();
+        let mut supervised = self.supervision();
+
+        self.dependencies
+            .keys()
+            .map(|name| match supervised.get_mut(name) {
+                Some(entry) => {
+                    entry.prune_restarts(now);
+                    ServiceReport {
+                        name: name.clone(),
+                        state: entry.state.clone(),
+                        uptime: entry.up_since.map(|(since, _)| now.duration_since(since)),
+                        restart_count: entry.restart_count,
+                        restarts_this_hour: entry.recent_restarts.len() as u32,
+                        circuit_open: entry.circuit_open,
+                        last_health_check: entry.last_result.clone(),
+                    }
+                }
+                None => ServiceReport {
+                    name: name.clone(),
+                    state: self.status(name).unwrap_or(ServiceState::Unknown),
+                    uptime: None,
+                    restart_count: 0,
+                    restarts_this_hour: 0,
+                    circuit_open: false,
+                    last_health_check: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Run health checks and restarts in the background
+    ///
+    /// Also pings the systemd watchdog when Patronus runs under one, so a
+    /// hung supervisor gets Patronus itself restarted.
+    pub fn spawn_supervisor(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let watchdog = Watchdog::from_env();
+        if let Some(watchdog) = &watchdog {
+            tracing::info!("systemd watchdog enabled, pinging every {:?}", watchdog.interval());
+        }
+
+        tokio::spawn(async move {
+            let mut last_ping: Option<Instant> = None;
+            loop {
+                if let Some(watchdog) = &watchdog {
+                    if last_ping.is_none_or(|at| at.elapsed() >= watchdog.interval()) {
+                        if let Err(e) = watchdog.notify() {
+                            tracing::warn!("Failed to ping systemd watchdog: {}", e);
+                        }
+                        last_ping = Some(Instant::now());
+                    }
+                }
+
+                for (name, check) in self.due_checks(Instant::now()) {
+                    let result = check.check.run(check.timeout).await;
+                    if !self.observe(&name, result, Instant::now()) {
+                        continue;
+                    }
+                    let manager = self.clone();
+                    let service = name.clone();
+                    let outcome = tokio::task::spawn_blocking(move || manager.restart(&service))
+                        .await
+                        .unwrap_or_else(|e| Err(Error::Service(e.to_string())));
+                    self.restarted(&name, outcome, Instant::now());
+                }
+
+                tokio::time::sleep(SUPERVISOR_TICK).await;
+            }
+        })
+    }
+
+    /// Checks whose interval has elapsed, marking them as scheduled
+    fn due_checks(&self, now: Instant) -> Vec<(String, HealthCheckConfig)> {
+        let mut supervised = self.supervision();
+        supervised
+            .iter_mut()
+            .filter(|(_, entry)| entry.next_check.is_none_or(|at| at <= now))
+            .map(|(name, entry)| {
+                entry.next_check = Some(now + entry.check.interval);
+                (name.clone(), entry.check.clone())
+            })
+            .collect()
+    }
+
+    /// Record a health check result, returning whether to restart now
+    fn observe(&self, service_name: &str, result: HealthCheckResult, now: Instant) -> bool {
+        let mut supervised = self.supervision();
+        let Some(entry) = supervised.get_mut(service_name) else {
+            return false;
+        };
+        entry.prune_restarts(now);
+        let healthy = result.healthy;
+        entry.last_result = Some(result);
+
+        if entry.circuit_open && (entry.recent_restarts.len() as u32) < self.restart_policy.max_restarts_per_hour {
+            entry.circuit_open = false;
+            self.emit(service_name, ServiceEventKind::CircuitClosed);
+        }
+
+        if healthy {
+            entry.consecutive_failures = 0;
+            if entry.up_since.is_none() {
+                entry.up_since = Some((now, Utc::now()));
+            }
+            self.transition(service_name, entry, ServiceState::Running);
+            return false;
+        }
+
+        entry.consecutive_failures += 1;
+        if entry.circuit_open {
+            return false;
+        }
+        if entry.state != ServiceState::Restarting {
+            self.transition(service_name, entry, ServiceState::Unhealthy);
+        }
+        if entry.consecutive_failures < entry.check.failure_threshold {
+            return false;
+        }
+
+        let restarts = entry.recent_restarts.len();
+        if restarts as u32 >= self.restart_policy.max_restarts_per_hour {
+            tracing::error!(
+                "{} restarted {} times in the last hour, giving up until the circuit closes",
+                service_name,
+                restarts
+            );
+            entry.circuit_open = true;
+            self.emit(service_name, ServiceEventKind::CircuitOpened { restarts_this_hour: restarts as u32 });
+            self.transition(service_name, entry, ServiceState::Failed);
+            return false;
+        }
+
+        match entry.recent_restarts.back() {
+            Some(last) if now.duration_since(*last) < self.restart_policy.backoff(restarts) => false,
+            _ => {
+                entry.recent_restarts.push_back(now);
+                entry.restart_count += 1;
+                entry.consecutive_failures = 0;
+                entry.up_since = None;
+                self.transition(service_name, entry, ServiceState::Restarting);
+                true
+            }
+        }
+    }
+
+    /// Record the outcome of a restart requested by [`Self::observe`]
+    fn restarted(&self, service_name: &str, outcome: Result<()>, now: Instant) {
+        let mut supervised = self.supervision();
+        let Some(entry) = supervised.get_mut(service_name) else {
+            return;
+        };
+        entry.prune_restarts(now);
+        match outcome {
+            Ok(()) => {
+                tracing::info!("Restarted unhealthy service {}", service_name);
+                let restarts_this_hour = entry.recent_restarts.len() as u32;
+                self.emit(service_name, ServiceEventKind::Restarted { restarts_this_hour });
+            }
+            Err(e) => {
+                tracing::error!("Failed to restart {}: {}", service_name, e);
+                self.emit(service_name, ServiceEventKind::RestartFailed { error: e.to_string() });
+                self.transition(service_name, entry, ServiceState::Failed);
+            }
+        }
+    }
+
+    fn transition(&self, service_name: &str, entry: &mut Supervised, to: ServiceState) {
+        if entry.state != to {
+            let from = std::mem::replace(&mut entry.state, to.clone());
+            self.emit(service_name, ServiceEventKind::StateChanged { from, to });
+        }
+    }
+
+    fn emit(&self, service_name: &str, kind: ServiceEventKind) {
+        let _ = self.events.send(ServiceEvent {
+            service: service_name.to_string(),
+            kind,
+            at: Utc::now(),
+        });
+    }
+
+    fn supervision(&self) -> std::sync::MutexGuard<'_, HashMap<String, Supervised>> {
+        self.supervised.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register a managed service
+    pub fn add_service(&mut self, service_name: &str) {
+        self.dependencies.entry(service_name.to_string()).or_default();
+    }
+
+    /// Declare that `service_name` requires `dependency` to be running first
+    pub fn add_dependency(&mut self, service_name: &str, dependency: &str) {
+        self.add_service(dependency);
+        self.dependencies
+            .entry(service_name.to_string())
+            .or_default()
+            .insert(dependency.to_string());
+    }
+
+    /// Declared dependencies of a service
+    pub fn dependencies(&self, service_name: &str) -> Vec<String> {
+        self.dependencies
+            .get(service_name)
+            .map(|deps| deps.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Managed services ordered so every dependency precedes its dependents
+    pub fn start_order(&self) -> Result<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Visiting,
+            Done,
+        }
+
+        fn visit<'a>(
+            deps: &'a BTreeMap<String, BTreeSet<String>>,
+            name: &'a str,
+            marks: &mut HashMap<&'a str, Mark>,
+            path: &mut Vec<&'a str>,
+            order: &mut Vec<String>,
+        ) -> Result<()> {
+            match marks.get(name) {
+                Some(Mark::Done) => return Ok(()),
+                Some(Mark::Visiting) => {
+                    let start = path.iter().position(|n| *n == name).unwrap_or(0);
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(name);
+                    return Err(Error::Service(format!(
+                        "Service dependency cycle: {}",
+                        cycle.join(" -> ")
+                    )));
+                }
+                None => {}
+            }
+
+            marks.insert(name, Mark::Visiting);
+            path.push(name);
+            for dep in deps.get(name).into_iter().flatten() {
+                visit(deps, dep, marks, path, order)?;
+            }
+            path.pop();
+            marks.insert(name, Mark::Done);
+            order.push(name.to_string());
+            Ok(())
+        }
+
+        let mut marks = HashMap::new();
+        let mut order = Vec::with_capacity(self.dependencies.len());
+        for name in self.dependencies.keys() {
+            visit(&self.dependencies, name, &mut marks, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Start all managed services in dependency order
+    ///
+    /// A service is not started while any of its dependencies is failed,
+    /// either already or during this run.
+    pub fn start_all(&self) -> Result<ServiceRun> {
+        self.start_all_with(|name| self.start(name), |name| self.status(name))
+    }
+
+    /// Stop all managed services, dependents before their dependencies
+    pub fn stop_all(&self) -> Result<ServiceRun> {
+        self.stop_all_with(|name| self.stop(name))
+    }
+
+    fn start_all_with(
+        &self,
+        mut start: impl FnMut(&str) -> Result<()>,
+        mut status: impl FnMut(&str) -> Result<ServiceState>,
+    ) -> Result<ServiceRun> {
+        let mut run = ServiceRun::default();
+        let mut unavailable = BTreeSet::new();
+
+        for name in self.start_order()? {
+            let failed_dep = self.dependencies[&name].iter().find(|dep| {
+                unavailable.contains(*dep)
+                    || matches!(status(dep.as_str()), Ok(ServiceState::Failed))
+            });
+            if let Some(dep) = failed_dep {
+                tracing::warn!("Not starting {}: dependency {} has failed", name, dep);
+                run.blocked.push((name.clone(), dep.clone()));
+                unavailable.insert(name);
+                continue;
+            }
+
+            if matches!(status(&name), Ok(ServiceState::Running)) {
+                run.completed.push(name);
+                continue;
+            }
+
+            match start(&name) {
+                Ok(()) => run.completed.push(name),
+                Err(e) => {
+                    run.failed.push((name.clone(), e.to_string()));
+                    unavailable.insert(name);
+                }
+            }
+        }
+
+        Ok(run)
+    }
+
+    fn stop_all_with(&self, mut stop: impl FnMut(&str) -> Result<()>) -> Result<ServiceRun> {
+        let mut run = ServiceRun::default();
+        for name in self.start_order()?.into_iter().rev() {
+            match stop(&name) {
+                Ok(()) => run.completed.push(name),
+                Err(e) => run.failed.push((name, e.to_string())),
+            }
+        }
+        Ok(run)
+    }
+
+    /// Detect the running init system
+    pub fn detect_init_system() -> InitSystem {
+        // Check for systemd
+        if std::path::Path::new("/run/systemd/system").exists() {
+            return InitSystem::Systemd;
+        }
+
+        // Check for OpenRC
+        if std::path::Path::new("/run/openrc").exists()
+            || std::path::Path::new("/etc/init.d/functions.sh").exists() {
+            return InitSystem::OpenRC;
+        }
+
+        // Check for SysV init
+        if std::path::Path::new("/etc/init.d").exists() {
+            return InitSystem::SysVInit;
+        }
+
+        InitSystem::Unknown
+    }
+
+    /// Get the current init system
+    pub fn init_system(&self) -> InitSystem {
+        self.init_system
+    }
+
+    /// Start a service
+    pub fn start(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("start", service_name)
+            }
+            InitSystem::OpenRC => {
+                self.openrc_command("start", service_name)
+            }
+            InitSystem::SysVInit => {
+                self.sysv_command("start", service_name)
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Stop a service
+    pub fn stop(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("stop", service_name)
+            }
+            InitSystem::OpenRC => {
+                self.openrc_command("stop", service_name)
+            }
+            InitSystem::SysVInit => {
+                self.sysv_command("stop", service_name)
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Restart a service
+    pub fn restart(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("restart", service_name)
+            }
+            InitSystem::OpenRC => {
+                self.openrc_command("restart", service_name)
+            }
+            InitSystem::SysVInit => {
+                self.sysv_command("restart", service_name)
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Reload a service configuration
+    pub fn reload(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("reload", service_name)
+            }
+            InitSystem::OpenRC => {
+                self.openrc_command("reload", service_name)
+            }
+            InitSystem::SysVInit => {
+                self.sysv_command("reload", service_name)
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Enable a service to start on boot
+    pub fn enable(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("enable", service_name)
+            }
+            InitSystem::OpenRC => {
+                // OpenRC uses rc-update
+                let output = Command::new("rc-update")
+                    .args(&["add", service_name, "default"])
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to enable service: {}", e)))?;
+
+                if !output.status.success() {
+                    return Err(Error::Service(format!(
+                        "Failed to enable service: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    )));
+                }
+
+                Ok(())
+            }
+            InitSystem::SysVInit => {
+                // SysV uses update-rc.d or chkconfig
+                if std::path::Path::new("/usr/sbin/update-rc.d").exists() {
+                    let output = Command::new("update-rc.d")
+                        .args(&[service_name, "defaults"])
+                        .output()
+                        .map_err(|e| Error::Service(format!("Failed to enable service: {}", e)))?;
+
+                    if !output.status.success() {
+                        return Err(Error::Service(format!(
+                            "Failed to enable service: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        )));
+                    }
+                } else if std::path::Path::new("/sbin/chkconfig").exists() {
+                    let output = Command::new("chkconfig")
+                        .args(&[service_name, "on"])
+                        .output()
+                        .map_err(|e| Error::Service(format!("Failed to enable service: {}", e)))?;
+
+                    if !output.status.success() {
+                        return Err(Error::Service(format!(
+                            "Failed to enable service: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        )));
+                    }
+                }
+
+                Ok(())
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Disable a service from starting on boot
+    pub fn disable(&self, service_name: &str) -> Result<()> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                self.systemd_command("disable", service_name)
+            }
+            InitSystem::OpenRC => {
+                let output = Command::new("rc-update")
+                    .args(&["del", service_name])
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to disable service: {}", e)))?;
+
+                if !output.status.success() {
+                    return Err(Error::Service(format!(
+                        "Failed to disable service: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    )));
+                }
+
+                Ok(())
+            }
+            InitSystem::SysVInit => {
+                if std::path::Path::new("/usr/sbin/update-rc.d").exists() {
+                    let output = Command::new("update-rc.d")
+                        .args(&[service_name, "remove"])
+                        .output()
+                        .map_err(|e| Error::Service(format!("Failed to disable service: {}", e)))?;
+
+                    if !output.status.success() {
+                        return Err(Error::Service(format!(
+                            "Failed to disable service: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        )));
+                    }
+                } else if std::path::Path::new("/sbin/chkconfig").exists() {
+                    let output = Command::new("chkconfig")
+                        .args(&[service_name, "off"])
+                        .output()
+                        .map_err(|e| Error::Service(format!("Failed to disable service: {}", e)))?;
+
+                    if !output.status.success() {
+                        return Err(Error::Service(format!(
+                            "Failed to disable service: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        )));
+                    }
+                }
+
+                Ok(())
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Get service status
+    pub fn status(&self, service_name: &str) -> Result<ServiceState> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                let output = Command::new("systemctl")
+                    .args(&["is-active", service_name])
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to get service status: {}", e)))?;
+
+                let status = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+                match status.as_str() {
+                    "active" => Ok(ServiceState::Running),
+                    "inactive" => Ok(ServiceState::Stopped),
+                    "failed" => Ok(ServiceState::Failed),
+                    _ => Ok(ServiceState::Unknown),
+                }
+            }
+            InitSystem::OpenRC => {
+                let output = Command::new("rc-service")
+                    .args(&[service_name, "status"])
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to get service status: {}", e)))?;
+
+                if output.status.success() {
+                    let status = String::from_utf8_lossy(&output.stdout);
+                    if status.contains("started") {
+                        Ok(ServiceState::Running)
+                    } else if status.contains("stopped") {
+                        Ok(ServiceState::Stopped)
+                    } else if status.contains("crashed") {
+                        Ok(ServiceState::Failed)
+                    } else {
+                        Ok(ServiceState::Unknown)
+                    }
+                } else {
+                    Ok(ServiceState::Stopped)
+                }
+            }
+            InitSystem::SysVInit => {
+                let output = Command::new("/etc/init.d/".to_string() + service_name)
+                    .arg("status")
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to get service status: {}", e)))?;
+
+                if output.status.success() {
+                    Ok(ServiceState::Running)
+                } else {
+                    Ok(ServiceState::Stopped)
+                }
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    /// Check if a service is running
+    pub fn is_running(&self, service_name: &str) -> Result<bool> {
+        Ok(self.status(service_name)? == ServiceState::Running)
+    }
+
+    /// Check if a service is enabled
+    pub fn is_enabled(&self, service_name: &str) -> Result<bool> {
+        match self.init_system {
+            InitSystem::Systemd => {
+                let output = Command::new("systemctl")
+                    .args(&["is-enabled", service_name])
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to check if service is enabled: {}", e)))?;
+
+                Ok(output.status.success())
+            }
+            InitSystem::OpenRC => {
+                let output = Command::new("rc-update")
+                    .arg("show")
+                    .output()
+                    .map_err(|e| Error::Service(format!("Failed to check if service is enabled: {}", e)))?;
+
+                let list = String::from_utf8_lossy(&output.stdout);
+                Ok(list.contains(service_name))
+            }
+            InitSystem::SysVInit => {
+                // This is more complex and depends on the distribution
+                Ok(false)
+            }
+            InitSystem::Unknown => {
+                Err(Error::Service("Unknown init system".to_string()))
+            }
+        }
+    }
+
+    // Private helper methods
+
+    fn systemd_command(&self, action: &str, service_name: &str) -> Result<()> {
+        let output = Command::new("systemctl")
+            .args(&[action, service_name])
+            .output()
+            .map_err(|e| Error::Service(format!("Failed to {} service: {}", action, e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Service(format!(
+                "Failed to {} service: {}",
+                action,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn openrc_command(&self, action: &str, service_name: &str) -> Result<()> {
+        let output = Command::new("rc-service")
+            .args(&[service_name, action])
+            .output()
+            .map_err(|e| Error::Service(format!("Failed to {} service: {}", action, e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Service(format!(
+                "Failed to {} service: {}",
+                action,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn sysv_command(&self, action: &str, service_name: &str) -> Result<()> {
+        let script_path = format!("/etc/init.d/{}", service_name);
+
+        let output = Command::new(&script_path)
+            .arg(action)
+            .output()
+            .map_err(|e| Error::Service(format!("Failed to {} service: {}", action, e)))?;
+
+        if !output.status.success() {
+            return Err(Error::Service(format!(
+                "Failed to {} service: {}",
+                action,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ServiceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_init_system() {
+        let init = ServiceManager::detect_init_system();
+        // Should detect something on most systems
+        assert_ne!(init, InitSystem::Unknown);
+    }
+
+    #[test]
+    fn test_service_manager_creation() {
+        let manager = ServiceManager::new();
+        assert_ne!(manager.init_system(), InitSystem::Unknown);
+    }
+
+    fn web_stack() -> ServiceManager {
+        let mut manager = ServiceManager::with_init_system(InitSystem::Unknown);
+        manager.add_dependency("web", "db");
+        manager.add_dependency("web", "cache");
+        manager.add_dependency("worker", "db");
+        manager.add_service("ntp");
+        manager
+    }
+
+    #[test]
+    fn test_dependency_ordering() {
+        let manager = web_stack();
+        let order = manager.start_order().unwrap();
+        let pos = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert_eq!(order.len(), 5);
+        assert!(pos("db") < pos("web"));
+        assert!(pos("cache") < pos("web"));
+        assert!(pos("db") < pos("worker"));
+
+        let mut started = Vec::new();
+        let run = manager
+            .start_all_with(
+                |name| {
+                    started.push(name.to_string());
+                    Ok(())
+                },
+                |_| Ok(ServiceState::Stopped),
+            )
+            .unwrap();
+        assert!(run.is_success());
+        assert_eq!(started, order);
+
+        let run = manager.stop_all_with(|_| Ok(())).unwrap();
+        let stopped: Vec<_> = order.iter().rev().cloned().collect();
+        assert_eq!(run.completed, stopped);
+    }
+
+    #[test]
+    fn test_failed_dependency_blocks_dependents() {
+        let manager = web_stack();
+
+        // db fails to start: web and worker must not be started
+        let mut started = Vec::new();
+        let run = manager
+            .start_all_with(
+                |name| {
+                    if name == "db" {
+                        return Err(Error::Service("exit status 1".to_string()));
+                    }
+                    started.push(name.to_string());
+                    Ok(())
+                },
+                |_| Ok(ServiceState::Stopped),
+            )
+            .unwrap();
+        assert_eq!(started, vec!["cache", "ntp"]);
+        assert_eq!(run.failed.len(), 1);
+        assert_eq!(
+            run.blocked,
+            vec![("web".to_string(), "db".to_string()), ("worker".to_string(), "db".to_string())]
+        );
+
+        // db already in the failed state: same refusal without trying it
+        let run = manager
+            .start_all_with(
+                |name| {
+                    assert!(name != "web" && name != "worker");
+                    Ok(())
+                },
+                |name| Ok(if name == "db" { ServiceState::Failed } else { ServiceState::Stopped }),
+            )
+            .unwrap();
+        assert_eq!(run.blocked.len(), 2);
+    }
+
+    #[test]
+    fn test_dependency_cycle_is_reported() {
+        let mut manager = web_stack();
+        manager.add_dependency("db", "storage");
+        manager.add_dependency("storage", "web");
+
+        let err = manager.start_order().unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+        assert!(err.contains("db -> storage -> web -> db"), "{}", err);
+        assert!(manager.start_all().is_err());
+    }
+
+    fn result(healthy: bool) -> HealthCheckResult {
+        HealthCheckResult {
+            healthy,
+            message: if healthy { "ok".into() } else { "connection refused".into() },
+            latency: Duration::from_millis(1),
+            checked_at: Utc::now(),
+        }
+    }
+
+    fn supervised_manager() -> ServiceManager {
+        let mut manager = ServiceManager::with_init_system(InitSystem::Unknown);
+        let mut check = HealthCheckConfig::new(HealthCheck::Tcp { address: "127.0.0.1:1".into() });
+        check.failure_threshold = 2;
+        manager.set_health_check("dns", check);
+        manager.set_restart_policy(RestartPolicy {
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+            max_restarts_per_hour: 3,
+        });
+        manager
+    }
+
+    #[test]
+    fn test_unhealthy_service_restarts_with_backoff() {
+        let manager = supervised_manager();
+        let mut events = manager.subscribe();
+        let t0 = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+
+        assert!(!manager.observe("dns", result(true), at(0)));
+        assert_eq!(manager.get_service_report()[0].state, ServiceState::Running);
+
+        // One failure is tolerated, the second triggers a restart
+        assert!(!manager.observe("dns", result(false), at(1)));
+        assert!(manager.observe("dns", result(false), at(2)));
+        manager.restarted("dns", Ok(()), at(2));
+
+        // Still failing: the next restart waits for the doubled backoff (20s)
+        assert!(!manager.observe("dns", result(false), at(3)));
+        assert!(!manager.observe("dns", result(false), at(12)));
+        assert!(!manager.observe("dns", result(false), at(20)));
+        assert!(manager.observe("dns", result(false), at(22)));
+        manager.restarted("dns", Err(Error::Service("unit not found".into())), at(22));
+
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![
+            ServiceEventKind::StateChanged { from: ServiceState::Unknown, to: ServiceState::Running },
+            ServiceEventKind::StateChanged { from: ServiceState::Running, to: ServiceState::Unhealthy },
+            ServiceEventKind::StateChanged { from: ServiceState::Unhealthy, to: ServiceState::Restarting },
+            ServiceEventKind::Restarted { restarts_this_hour: 1 },
+            ServiceEventKind::RestartFailed { error: "Service error: unit not found".into() },
+            ServiceEventKind::StateChanged { from: ServiceState::Restarting, to: ServiceState::Failed },
+        ]);
+
+        let report = &manager.get_service_report()[0];
+        assert_eq!(report.restart_count, 2);
+        assert_eq!(report.uptime, None);
+        assert_eq!(report.last_health_check.as_ref().unwrap().message, "connection refused");
+
+        // Recovery resets the failure count and starts the uptime clock
+        assert!(!manager.observe("dns", result(true), at(30)));
+        let report = &manager.get_service_report()[0];
+        assert_eq!(report.state, ServiceState::Running);
+        assert!(report.uptime.is_some());
+    }
+
+    #[test]
+    fn test_circuit_breaker_opens_after_max_restarts() {
+        let manager = supervised_manager();
+        let mut events = manager.subscribe();
+        let t0 = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+
+        let mut restarts = 0;
+        for minute in 0..10 {
+            for offset in 0..2 {
+                if manager.observe("dns", result(false), at(minute * 60 + offset)) {
+                    manager.restarted("dns", Ok(()), at(minute * 60 + offset));
+                    restarts += 1;
+                }
+            }
+        }
+        assert_eq!(restarts, 3);
+
+        let report = &manager.get_service_report()[0];
+        assert!(report.circuit_open);
+        assert_eq!(report.state, ServiceState::Failed);
+        assert!(std::iter::from_fn(|| events.try_recv().ok())
+            .any(|e| e.kind == ServiceEventKind::CircuitOpened { restarts_this_hour: 3 }));
+
+        // Once the oldest restart ages out of the hour the breaker closes
+        assert!(!manager.observe("dns", result(false), at(3500)));
+        assert!(manager.observe("dns", result(false), at(3700)));
+        assert!(!manager.get_service_report()[0].circuit_open);
+
+        manager.reset_circuit("dns");
+        assert_eq!(manager.get_service_report()[0].restarts_this_hour, 0);
+    }
+
+    #[test]
+    fn test_due_checks_follow_interval() {
+        let manager = supervised_manager();
+        let t0 = Instant::now();
+        assert_eq!(manager.due_checks(t0).len(), 1);
+        assert!(manager.due_checks(t0 + Duration::from_secs(5)).is_empty());
+        assert_eq!(manager.due_checks(t0 + Duration::from_secs(10)).len(), 1);
+    }
+}
@@ -14,6 +14,38 @@ pub struct Interface {
     pub index: u32,
 }
 
+/// Interface traffic counters and link state
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InterfaceStats {
+    pub index: u32,
+    pub name: String,
+    pub mac_address: Option<String>,
+    pub mtu: u32,
+
+    /// Administratively enabled
+    pub admin_up: bool,
+
+    /// Link is up and running
+    pub oper_up: bool,
+
+    pub speed_bps: Option<u64>,
+
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+/// Source of interface counters, implemented by the interface manager
+#[async_trait::async_trait]
+pub trait InterfaceStatsProvider: Send + Sync {
+    async fn interface_stats(&self) -> crate::Result<Vec<InterfaceStats>>;
+}
+
 /// IP address with CIDR prefix
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct IpNetwork {
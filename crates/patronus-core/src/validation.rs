@@ -6,145 +6,253 @@
 //! - SQL injection prevention (complementary to parameterized queries)
 //! - XSS prevention
 //! - Network input validation (IPs, ports, interfaces)
-
-use anyhow::{bail, Result};
+//!
+//! Every validator fails with a [`ValidationError`] carrying a machine-readable
+//! [`codes`] entry and the rejected value, so callers (the web UI in
+//! particular) can point at the exact field that is wrong. Structs implement
+//! [`Validate`] and use [`ValidationErrors`] to report all of their problems
+//! at once instead of stopping at the first.
+//!
+//! Beyond the fixed validators, crates can register domain-specific
+//! [`Validator`]s for their types and check a value against all of them
+//! with [`validate_all`], which reports every failure with its field path.
+
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Result of a single-value validator
+pub type ValidationResult<T> = std::result::Result<T, ValidationError>;
+
+/// Machine-readable codes carried by [`ValidationError::code`]
+pub mod codes {
+    pub const REQUIRED: &str = "required";
+    pub const TOO_LONG: &str = "too_long";
+    pub const INVALID_CHARACTERS: &str = "invalid_characters";
+    pub const INVALID_FORMAT: &str = "invalid_format";
+    pub const INVALID_IP: &str = "invalid_ip";
+    pub const INVALID_PREFIX: &str = "invalid_prefix";
+    pub const INVALID_ZONE: &str = "invalid_zone";
+    pub const OUT_OF_RANGE: &str = "out_of_range";
+    pub const INVALID_RANGE: &str = "invalid_range";
+    pub const NOT_ALLOWED: &str = "not_allowed";
+    pub const PATH_TRAVERSAL: &str = "path_traversal";
+    pub const UNSUPPORTED: &str = "unsupported";
+    /// Generic failure, used when no more specific code applies
+    pub const INVALID: &str = "invalid";
+}
 
-/// Validate an interface name (e.g., eth0, wg0)
-pub fn validate_interface_name(name: &str) -> Result<()> {
+fn reject<T>(code: &str, message: impl Into<String>, value: impl fmt::Display) -> ValidationResult<T> {
+    Err(ValidationError::new("", message).with_code(code).with_value(value))
+}
+
+/// Validate an interface name (e.g., eth0, wg0, eth0.100)
+pub fn validate_interface_name(name: &str) -> ValidationResult<()> {
     if name.is_empty() {
-        bail!("Interface name cannot be empty");
+        return reject(codes::REQUIRED, "Interface name cannot be empty", name);
     }
 
+    // IFNAMSIZ is 16 including the terminating NUL
     if name.len() > 15 {
-        bail!("Interface name too long (max 15 characters)");
+        return reject(codes::TOO_LONG, "Interface name too long (max 15 characters)", name);
+    }
+
+    // Reject obvious injection attempts
+    if name.contains("&&") || name.contains("||") || name.contains(';') || name.contains('`') {
+        return reject(codes::INVALID_CHARACTERS, "Interface name contains shell metacharacters", name);
     }
 
     // Interface names should only contain alphanumeric, dash, underscore, dot
     if !name
         .chars()
-        .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
     {
-        bail!("Interface name contains invalid characters");
+        return reject(codes::INVALID_CHARACTERS, "Interface name contains invalid characters", name);
     }
 
     // Should not start with dot or dash
     if name.starts_with('.') || name.starts_with('-') {
-        bail!("Interface name cannot start with '.' or '-'");
-    }
-
-    // Reject obvious injection attempts
-    if name.contains("&&") || name.contains("||") || name.contains(";") || name.contains("`") {
-        bail!("Interface name contains shell metacharacters");
+        return reject(codes::INVALID_FORMAT, "Interface name cannot start with '.' or '-'", name);
     }
 
     Ok(())
 }
 
 /// Validate an IP address
-pub fn validate_ip_address(ip: &str) -> Result<IpAddr> {
-    ip.parse::<IpAddr>()
-        .map_err(|e| anyhow::anyhow!("Invalid IP address '{}': {}", ip, e))
+pub fn validate_ip_address(ip: &str) -> ValidationResult<IpAddr> {
+    ip.parse::<IpAddr>().or_else(|e| {
+        reject(codes::INVALID_IP, format!("Invalid IP address '{}': {}", ip, e), ip)
+    })
 }
 
 /// Validate an IPv4 address
-pub fn validate_ipv4_address(ip: &str) -> Result<Ipv4Addr> {
-    ip.parse::<Ipv4Addr>()
-        .map_err(|e| anyhow::anyhow!("Invalid IPv4 address '{}': {}", ip, e))
+pub fn validate_ipv4_address(ip: &str) -> ValidationResult<Ipv4Addr> {
+    ip.parse::<Ipv4Addr>().or_else(|e| {
+        reject(codes::INVALID_IP, format!("Invalid IPv4 address '{}': {}", ip, e), ip)
+    })
 }
 
 /// Validate an IPv6 address
-pub fn validate_ipv6_address(ip: &str) -> Result<Ipv6Addr> {
-    ip.parse::<Ipv6Addr>()
-        .map_err(|e| anyhow::anyhow!("Invalid IPv6 address '{}': {}", ip, e))
+pub fn validate_ipv6_address(ip: &str) -> ValidationResult<Ipv6Addr> {
+    ip.parse::<Ipv6Addr>().or_else(|e| {
+        reject(codes::INVALID_IP, format!("Invalid IPv6 address '{}': {}", ip, e), ip)
+    })
+}
+
+/// Validate an IPv6 address with an optional RFC 4007 zone ID
+/// (e.g. `fe80::1%eth0` or `fe80::1%3`), returning the address and zone.
+///
+/// Zones are only meaningful for link-local unicast and multicast addresses.
+pub fn validate_ipv6_scoped(ip: &str) -> ValidationResult<(Ipv6Addr, Option<String>)> {
+    let Some((addr, zone)) = ip.split_once('%') else {
+        return validate_ipv6_address(ip).map(|addr| (addr, None));
+    };
+
+    let parsed = validate_ipv6_address(addr).map_err(|e| e.with_value(ip))?;
+
+    let numeric = !zone.is_empty() && zone.chars().all(|c| c.is_ascii_digit());
+    if !numeric && validate_interface_name(zone).is_err() {
+        return reject(codes::INVALID_ZONE, format!("Invalid IPv6 zone ID '{}'", zone), ip);
+    }
+
+    let link_local = (parsed.segments()[0] & 0xffc0) == 0xfe80;
+    if !link_local && !parsed.is_multicast() {
+        return reject(
+            codes::INVALID_ZONE,
+            "Zone IDs are only allowed on link-local or multicast addresses",
+            ip,
+        );
+    }
+
+    Ok((parsed, Some(zone.to_string())))
 }
 
 /// Validate a CIDR notation (IP/prefix)
-pub fn validate_cidr(cidr: &str) -> Result<()> {
+pub fn validate_cidr(cidr: &str) -> ValidationResult<()> {
     let parts: Vec<&str> = cidr.split('/').collect();
     if parts.len() != 2 {
-        bail!("Invalid CIDR format (expected IP/prefix)");
+        return reject(codes::INVALID_FORMAT, "Invalid CIDR format (expected IP/prefix)", cidr);
     }
 
     // Validate IP part
-    validate_ip_address(parts[0])?;
+    let ip = validate_ip_address(parts[0]).map_err(|e| e.with_value(cidr))?;
 
     // Validate prefix length
-    let prefix: u8 = parts[1]
-        .parse()
-        .map_err(|_| anyhow::anyhow!("Invalid prefix length"))?;
+    let Ok(prefix) = parts[1].parse::<u8>() else {
+        return reject(codes::INVALID_PREFIX, "Invalid prefix length", cidr);
+    };
 
     // Check prefix range based on IP type
-    if parts[0].contains(':') {
-        // IPv6
-        if prefix > 128 {
-            bail!("IPv6 prefix must be <= 128");
-        }
-    } else {
-        // IPv4
-        if prefix > 32 {
-            bail!("IPv4 prefix must be <= 32");
-        }
+    match ip {
+        IpAddr::V6(_) if prefix > 128 => reject(codes::INVALID_PREFIX, "IPv6 prefix must be <= 128", cidr),
+        IpAddr::V4(_) if prefix > 32 => reject(codes::INVALID_PREFIX, "IPv4 prefix must be <= 32", cidr),
+        _ => Ok(()),
     }
-
-    Ok(())
 }
 
 /// Validate a port number
-pub fn validate_port(port: u16) -> Result<()> {
+pub fn validate_port(port: u16) -> ValidationResult<()> {
     if port == 0 {
-        bail!("Port cannot be 0");
+        return reject(codes::OUT_OF_RANGE, "Port cannot be 0", port);
     }
     Ok(())
 }
 
 /// Validate a port range
-pub fn validate_port_range(start: u16, end: u16) -> Result<()> {
-    validate_port(start)?;
-    validate_port(end)?;
+pub fn validate_port_range(start: u16, end: u16) -> ValidationResult<()> {
+    let range = format!("{}-{}", start, end);
+    validate_port(start).map_err(|e| e.with_value(&range))?;
+    validate_port(end).map_err(|e| e.with_value(&range))?;
 
     if start > end {
-        bail!("Port range start ({}) must be <= end ({})", start, end);
+        return reject(
+            codes::INVALID_RANGE,
+            format!("Port range start ({}) must be <= end ({})", start, end),
+            range,
+        );
     }
 
     Ok(())
 }
 
 /// Validate a hostname/domain name
-pub fn validate_hostname(hostname: &str) -> Result<()> {
+pub fn validate_hostname(hostname: &str) -> ValidationResult<()> {
     if hostname.is_empty() {
-        bail!("Hostname cannot be empty");
+        return reject(codes::REQUIRED, "Hostname cannot be empty", hostname);
     }
 
     if hostname.len() > 253 {
-        bail!("Hostname too long (max 253 characters)");
+        return reject(codes::TOO_LONG, "Hostname too long (max 253 characters)", hostname);
     }
 
-    // Split into labels
-    let labels: Vec<&str> = hostname.split('.').collect();
-
-    for label in labels {
+    for label in hostname.split('.') {
         if label.is_empty() || label.len() > 63 {
-            bail!("Invalid hostname label length");
+            return reject(codes::INVALID_FORMAT, "Invalid hostname label length", hostname);
         }
 
         // Labels should start and end with alphanumeric
-        let first_char = label.chars().next();
-        let last_char = label.chars().last();
-
-        match (first_char, last_char) {
-            (Some(first), Some(last)) => {
-                if !first.is_alphanumeric() || !last.is_alphanumeric() {
-                    bail!("Hostname labels must start and end with alphanumeric characters");
-                }
-            }
-            _ => bail!("Invalid hostname label"),
+        let first = label.chars().next().unwrap_or('-');
+        let last = label.chars().last().unwrap_or('-');
+        if !first.is_alphanumeric() || !last.is_alphanumeric() {
+            return reject(
+                codes::INVALID_FORMAT,
+                "Hostname labels must start and end with alphanumeric characters",
+                hostname,
+            );
         }
 
         // Labels should only contain alphanumeric and hyphens
         if !label.chars().all(|c| c.is_alphanumeric() || c == '-') {
-            bail!("Hostname contains invalid characters");
+            return reject(codes::INVALID_CHARACTERS, "Hostname contains invalid characters", hostname);
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a domain name against the RFC 1035 preferred name syntax:
+/// labels of 1-63 characters that start with a letter, end with a letter or
+/// digit and contain only letters, digits and hyphens. A single trailing dot
+/// (fully-qualified form) is accepted.
+pub fn validate_domain_name(domain: &str) -> ValidationResult<()> {
+    let name = domain.strip_suffix('.').unwrap_or(domain);
+    if name.is_empty() {
+        return reject(codes::REQUIRED, "Domain name cannot be empty", domain);
+    }
+
+    // 255 octets on the wire, less the length bytes
+    if name.len() > 253 {
+        return reject(codes::TOO_LONG, "Domain name too long (max 253 characters)", domain);
+    }
+
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return reject(codes::INVALID_FORMAT, "Domain labels must be 1-63 characters", domain);
+        }
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return reject(
+                codes::INVALID_CHARACTERS,
+                format!("Domain label '{}' may only contain letters, digits and '-'", label),
+                domain,
+            );
+        }
+        if !label.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return reject(
+                codes::INVALID_FORMAT,
+                format!("Domain label '{}' must start with a letter", label),
+                domain,
+            );
+        }
+        if label.ends_with('-') {
+            return reject(
+                codes::INVALID_FORMAT,
+                format!("Domain label '{}' cannot end with '-'", label),
+                domain,
+            );
         }
     }
 
@@ -152,7 +260,7 @@ pub fn validate_hostname(hostname: &str) -> Result<()> {
 }
 
 /// Validate a protocol name
-pub fn validate_protocol(protocol: &str) -> Result<()> {
+pub fn validate_protocol(protocol: &str) -> ValidationResult<()> {
     const VALID_PROTOCOLS: &[&str] = &[
         "tcp", "udp", "icmp", "icmpv6", "esp", "ah", "gre", "ipip",
         "tcp", "udp", "icmp", "sctp", "all", "any",
@@ -161,29 +269,39 @@ pub fn validate_protocol(protocol: &str) -> Result<()> {
     let proto_lower = protocol.to_lowercase();
 
     if !VALID_PROTOCOLS.contains(&proto_lower.as_str()) {
-        bail!("Invalid protocol '{}'. Must be one of: {:?}", protocol, VALID_PROTOCOLS);
+        return reject(
+            codes::UNSUPPORTED,
+            format!("Invalid protocol '{}'. Must be one of: {:?}", protocol, VALID_PROTOCOLS),
+            protocol,
+        );
     }
 
     Ok(())
 }
 
 /// Validate a firewall action
-pub fn validate_firewall_action(action: &str) -> Result<()> {
+pub fn validate_firewall_action(action: &str) -> ValidationResult<()> {
     const VALID_ACTIONS: &[&str] = &["allow", "deny", "reject", "drop"];
 
     let action_lower = action.to_lowercase();
 
     if !VALID_ACTIONS.contains(&action_lower.as_str()) {
-        bail!("Invalid action '{}'. Must be one of: {:?}", action, VALID_ACTIONS);
+        return reject(
+            codes::UNSUPPORTED,
+            format!("Invalid action '{}'. Must be one of: {:?}", action, VALID_ACTIONS),
+            action,
+        );
     }
 
     Ok(())
 }
 
 /// Validate and sanitize a comment/description field
-pub fn sanitize_comment(comment: &str, max_length: usize) -> Result<String> {
+pub fn sanitize_comment(comment: &str, max_length: usize) -> ValidationResult<String> {
     if comment.len() > max_length {
-        bail!("Comment too long (max {} characters)", max_length);
+        // The comment itself is not echoed back; it may be arbitrarily long
+        return Err(ValidationError::new("", format!("Comment too long (max {} characters)", max_length))
+            .with_code(codes::TOO_LONG));
     }
 
     // Remove control characters and potentially dangerous characters
@@ -209,25 +327,30 @@ pub fn sanitize_comment(comment: &str, max_length: usize) -> Result<String> {
 }
 
 /// Validate a file path (prevent path traversal)
-pub fn validate_safe_path(path: &Path, allowed_base: &Path) -> Result<PathBuf> {
+pub fn validate_safe_path(path: &Path, allowed_base: &Path) -> ValidationResult<PathBuf> {
+    let shown = path.display();
+
     // Canonicalize both paths
-    let canonical_base = allowed_base
-        .canonicalize()
-        .map_err(|e| anyhow::anyhow!("Invalid base path: {}", e))?;
+    let canonical_base = match allowed_base.canonicalize() {
+        Ok(base) => base,
+        Err(e) => return reject(codes::INVALID, format!("Invalid base path: {}", e), allowed_base.display()),
+    };
 
     let canonical_path = if path.is_relative() {
         canonical_base.join(path)
     } else {
-        path.canonicalize()
-            .map_err(|e| anyhow::anyhow!("Invalid path: {}", e))?
+        match path.canonicalize() {
+            Ok(path) => path,
+            Err(e) => return reject(codes::INVALID, format!("Invalid path: {}", e), shown),
+        }
     };
 
     // Ensure the path is within the allowed base
     if !canonical_path.starts_with(&canonical_base) {
-        bail!(
-            "Path traversal detected: {:?} is outside {:?}",
-            canonical_path,
-            canonical_base
+        return reject(
+            codes::PATH_TRAVERSAL,
+            format!("Path traversal detected: {:?} is outside {:?}", canonical_path, canonical_base),
+            shown,
         );
     }
 
@@ -235,7 +358,7 @@ pub fn validate_safe_path(path: &Path, allowed_base: &Path) -> Result<PathBuf> {
     for component in path.components() {
         let component_str = component.as_os_str().to_string_lossy();
         if component_str.contains("..") {
-            bail!("Path contains '..' component");
+            return reject(codes::PATH_TRAVERSAL, "Path contains '..' component", shown);
         }
     }
 
@@ -243,14 +366,14 @@ pub fn validate_safe_path(path: &Path, allowed_base: &Path) -> Result<PathBuf> {
 }
 
 /// Validate a URL (basic validation to prevent injection)
-pub fn validate_url(url: &str) -> Result<()> {
+pub fn validate_url(url: &str) -> ValidationResult<()> {
     if url.is_empty() {
-        bail!("URL cannot be empty");
+        return reject(codes::REQUIRED, "URL cannot be empty", url);
     }
 
     // Must start with http:// or https://
     if !url.starts_with("http://") && !url.starts_with("https://") {
-        bail!("URL must start with http:// or https://");
+        return reject(codes::INVALID_FORMAT, "URL must start with http:// or https://", url);
     }
 
     // Check for obvious injection attempts
@@ -261,67 +384,75 @@ pub fn validate_url(url: &str) -> Result<()> {
         || url.contains('<')
         || url.contains('>')
     {
-        bail!("URL contains invalid characters");
+        return reject(codes::INVALID_CHARACTERS, "URL contains invalid characters", url.escape_debug());
     }
 
     // Length check
     if url.len() > 2048 {
-        bail!("URL too long (max 2048 characters)");
+        return Err(ValidationError::new("", "URL too long (max 2048 characters)").with_code(codes::TOO_LONG));
     }
 
     Ok(())
 }
 
-/// Validate a MAC address
-pub fn validate_mac_address(mac: &str) -> Result<()> {
-    // MAC address should be in format: XX:XX:XX:XX:XX:XX or XX-XX-XX-XX-XX-XX
-    let parts: Vec<&str> = if mac.contains(':') {
-        mac.split(':').collect()
-    } else if mac.contains('-') {
-        mac.split('-').collect()
+/// Validate a MAC address, returning its octets.
+///
+/// Accepts `XX:XX:XX:XX:XX:XX`, `XX-XX-XX-XX-XX-XX` and the Cisco dotted
+/// form `XXXX.XXXX.XXXX`.
+pub fn validate_mac_address(mac: &str) -> ValidationResult<[u8; 6]> {
+    let digits: String = if mac.contains(':') || mac.contains('-') {
+        let separator = if mac.contains(':') { ':' } else { '-' };
+        let parts: Vec<&str> = mac.split(separator).collect();
+        if parts.len() != 6 {
+            return reject(codes::INVALID_FORMAT, "MAC address must have 6 octets", mac);
+        }
+        if parts.iter().any(|part| part.len() != 2) {
+            return reject(codes::INVALID_FORMAT, "Each MAC address octet must be 2 hex digits", mac);
+        }
+        parts.concat()
+    } else if mac.contains('.') {
+        let parts: Vec<&str> = mac.split('.').collect();
+        if parts.len() != 3 || parts.iter().any(|part| part.len() != 4) {
+            return reject(codes::INVALID_FORMAT, "Dotted MAC address must be XXXX.XXXX.XXXX", mac);
+        }
+        parts.concat()
     } else {
-        bail!("MAC address must use ':' or '-' as separator");
+        return reject(codes::INVALID_FORMAT, "MAC address must use ':', '-' or '.' as separator", mac);
     };
 
-    if parts.len() != 6 {
-        bail!("MAC address must have 6 octets");
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return reject(codes::INVALID_CHARACTERS, "MAC address contains non-hexadecimal characters", mac);
     }
 
-    for part in parts {
-        if part.len() != 2 {
-            bail!("Each MAC address octet must be 2 hex digits");
-        }
-
-        if !part.chars().all(|c| c.is_ascii_hexdigit()) {
-            bail!("MAC address contains non-hexadecimal characters");
-        }
+    let mut octets = [0u8; 6];
+    for (i, octet) in octets.iter_mut().enumerate() {
+        *octet = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap_or_default();
     }
-
-    Ok(())
+    Ok(octets)
 }
 
 /// Validate a VLAN ID
-pub fn validate_vlan_id(vlan: u16) -> Result<()> {
+pub fn validate_vlan_id(vlan: u16) -> ValidationResult<()> {
     if vlan == 0 || vlan > 4094 {
-        bail!("VLAN ID must be between 1 and 4094");
+        return reject(codes::OUT_OF_RANGE, "VLAN ID must be between 1 and 4094", vlan);
     }
     Ok(())
 }
 
 /// Validate alphanumeric identifier (for names, keys, etc.)
-pub fn validate_identifier(id: &str, max_length: usize) -> Result<()> {
+pub fn validate_identifier(id: &str, max_length: usize) -> ValidationResult<()> {
     if id.is_empty() {
-        bail!("Identifier cannot be empty");
+        return reject(codes::REQUIRED, "Identifier cannot be empty", id);
     }
 
     if id.len() > max_length {
-        bail!("Identifier too long (max {} characters)", max_length);
+        return reject(codes::TOO_LONG, format!("Identifier too long (max {} characters)", max_length), id);
     }
 
     // Must start with letter (safe to use match since we already checked non-empty)
     match id.chars().next() {
         Some(first) if first.is_alphabetic() => {}
-        _ => bail!("Identifier must start with a letter"),
+        _ => return reject(codes::INVALID_FORMAT, "Identifier must start with a letter", id),
     }
 
     // Only alphanumeric, underscore, hyphen
@@ -329,7 +460,11 @@ pub fn validate_identifier(id: &str, max_length: usize) -> Result<()> {
         .chars()
         .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
     {
-        bail!("Identifier can only contain alphanumeric, underscore, and hyphen");
+        return reject(
+            codes::INVALID_CHARACTERS,
+            "Identifier can only contain alphanumeric, underscore, and hyphen",
+            id,
+        );
     }
 
     Ok(())
@@ -342,38 +477,320 @@ pub fn escape_shell_arg(arg: &str) -> String {
 }
 
 /// Validate email address (basic validation)
-pub fn validate_email(email: &str) -> Result<()> {
+pub fn validate_email(email: &str) -> ValidationResult<()> {
     if email.is_empty() {
-        bail!("Email cannot be empty");
-    }
-
-    if !email.contains('@') {
-        bail!("Email must contain '@'");
+        return reject(codes::REQUIRED, "Email cannot be empty", email);
     }
 
     let parts: Vec<&str> = email.split('@').collect();
     if parts.len() != 2 {
-        bail!("Email must have exactly one '@'");
+        return reject(codes::INVALID_FORMAT, "Email must have exactly one '@'", email);
     }
 
     let local = parts[0];
     let domain = parts[1];
 
     if local.is_empty() || domain.is_empty() {
-        bail!("Email local and domain parts cannot be empty");
+        return reject(codes::INVALID_FORMAT, "Email local and domain parts cannot be empty", email);
     }
 
     // Validate domain part
-    validate_hostname(domain)?;
+    validate_hostname(domain).map_err(|e| e.with_value(email))?;
 
     // Basic length check
     if email.len() > 254 {
-        bail!("Email too long (max 254 characters)");
+        return reject(codes::TOO_LONG, "Email too long (max 254 characters)", email);
+    }
+
+    Ok(())
+}
+
+/// Validate a single DNS label (RFC 1123): 1-63 letters, digits or
+/// hyphens, not starting or ending with a hyphen
+pub fn validate_dns_label(label: &str) -> ValidationResult<()> {
+    if label.is_empty() || label.len() > 63 {
+        return reject(codes::INVALID_FORMAT, "DNS label must be 1-63 characters", label);
+    }
+
+    if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return reject(codes::INVALID_CHARACTERS, "DNS label may only contain letters, digits and '-'", label);
+    }
+
+    if label.starts_with('-') || label.ends_with('-') {
+        return reject(codes::INVALID_FORMAT, "DNS label cannot start or end with '-'", label);
     }
 
     Ok(())
 }
 
+/// A failed validation rule, located by the path of the offending field
+/// (e.g. `sites[2].name`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationError {
+    /// Path of the offending field; empty until the caller places it
+    pub field_path: String,
+    /// Stable machine-readable reason, one of [`codes`]
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected_value: Option<String>,
+}
+
+impl ValidationError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field_path: field.into(),
+            code: codes::INVALID.to_string(),
+            message: message.into(),
+            rejected_value: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = code.to_string();
+        self
+    }
+
+    pub fn with_value(mut self, value: impl fmt::Display) -> Self {
+        self.rejected_value = Some(value.to_string());
+        self
+    }
+
+    /// Place the result of a fixed validator at `field`
+    pub fn check<T>(field: &str, result: ValidationResult<T>) -> Option<Self> {
+        result.err().map(|e| e.within(field))
+    }
+
+    /// Nest under a parent field, e.g. `name` becomes `sites[2].name`
+    pub fn within(mut self, parent: &str) -> Self {
+        self.field_path = if self.field_path.is_empty() {
+            parent.to_string()
+        } else if parent.is_empty() {
+            self.field_path
+        } else if self.field_path.starts_with('[') {
+            format!("{}{}", parent, self.field_path)
+        } else {
+            format!("{}.{}", parent, self.field_path)
+        };
+        self
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field_path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field_path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Every violation found in one value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(Vec<ValidationError>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: ValidationError) {
+        self.0.push(error);
+    }
+
+    /// Record the failure of a fixed validator at `field`, passing its
+    /// value through on success
+    pub fn check<T>(&mut self, field: &str, result: ValidationResult<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.0.push(e.within(field));
+                None
+            }
+        }
+    }
+
+    /// Record a failure at `field` unless `condition` holds
+    pub fn ensure(&mut self, condition: bool, field: &str, code: &str, message: impl Into<String>) {
+        if !condition {
+            self.0.push(ValidationError::new(field, message).with_code(code));
+        }
+    }
+
+    /// Add errors reported relative to a nested field
+    pub fn extend_within(&mut self, parent: &str, errors: impl IntoIterator<Item = ValidationError>) {
+        self.0.extend(errors.into_iter().map(|e| e.within(parent)));
+    }
+
+    /// Validate a nested value, placing its errors under `field`
+    pub fn nested(&mut self, field: &str, value: &impl Validate) {
+        self.extend_within(field, value.validate());
+    }
+
+    /// Validate every element of a list, placing errors under `field[i]`
+    pub fn each<V: Validate>(&mut self, field: &str, values: &[V]) {
+        for (i, value) in values.iter().enumerate() {
+            self.nested(&format!("{}[{}]", field, i), value);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ValidationError> {
+        self.0.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<ValidationError> {
+        self.0
+    }
+
+    /// `Ok` when nothing was recorded
+    pub fn into_result(self) -> std::result::Result<(), Self> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<Vec<ValidationError>> for ValidationErrors {
+    fn from(errors: Vec<ValidationError>) -> Self {
+        Self(errors)
+    }
+}
+
+impl IntoIterator for ValidationErrors {
+    type Item = ValidationError;
+    type IntoIter = std::vec::IntoIter<ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Extend<ValidationError> for ValidationErrors {
+    fn extend<I: IntoIterator<Item = ValidationError>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// A value that can check itself, reporting every violation with field
+/// paths relative to the value
+pub trait Validate {
+    fn validate(&self) -> Vec<ValidationError>;
+
+    /// `Err` with all violations if there are any
+    fn ensure_valid(&self) -> std::result::Result<(), ValidationErrors> {
+        ValidationErrors::from(self.validate()).into_result()
+    }
+}
+
+/// A validation rule for values of type `T`, reporting every violation
+pub trait Validator<T: ?Sized>: Send + Sync {
+    fn validate(&self, value: &T) -> Vec<ValidationError>;
+}
+
+impl<T: ?Sized, F> Validator<T> for F
+where
+    F: Fn(&T) -> Vec<ValidationError> + Send + Sync,
+{
+    fn validate(&self, value: &T) -> Vec<ValidationError> {
+        self(value)
+    }
+}
+
+/// Validators registered per value type
+#[derive(Default)]
+pub struct ValidatorRegistry {
+    // TypeId of T -> Vec<Arc<dyn Validator<T>>>
+    validators: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl ValidatorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule for values of type `T`
+    pub fn register<T: 'static>(&mut self, validator: impl Validator<T> + 'static) {
+        self.validators
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Vec::<Arc<dyn Validator<T>>>::new()))
+            .downcast_mut::<Vec<Arc<dyn Validator<T>>>>()
+            .expect("validators are stored under their own type")
+            .push(Arc::new(validator));
+    }
+
+    /// Number of rules registered for `T`
+    pub fn count<T: 'static>(&self) -> usize {
+        self.rules::<T>().len()
+    }
+
+    /// Run every rule for `T`, collecting all failures
+    pub fn validate_all<T: 'static>(&self, value: &T) -> Vec<ValidationError> {
+        self.rules::<T>()
+            .iter()
+            .flat_map(|rule| rule.validate(value))
+            .collect()
+    }
+
+    fn rules<T: 'static>(&self) -> Vec<Arc<dyn Validator<T>>> {
+        self.validators
+            .get(&TypeId::of::<T>())
+            .and_then(|rules| rules.downcast_ref::<Vec<Arc<dyn Validator<T>>>>())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+fn global_registry() -> &'static RwLock<ValidatorRegistry> {
+    static REGISTRY: OnceLock<RwLock<ValidatorRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(ValidatorRegistry::new()))
+}
+
+/// Register a rule in the process-wide registry
+pub fn register_validator<T: 'static>(validator: impl Validator<T> + 'static) {
+    global_registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .register(validator);
+}
+
+/// Check `value` against every rule registered for its type
+pub fn validate_all<T: 'static>(value: &T) -> Vec<ValidationError> {
+    // Clone the rules out so validators may themselves use the registry
+    let rules = global_registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .rules::<T>();
+    rules.iter().flat_map(|rule| rule.validate(value)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -443,7 +860,10 @@ mod tests {
         assert!(validate_mac_address("00-11-22-33-44-55").is_ok());
         assert!(validate_mac_address("AA:BB:CC:DD:EE:FF").is_ok());
 
+        assert_eq!(validate_mac_address("0011.2233.44ff").unwrap(), [0x00, 0x11, 0x22, 0x33, 0x44, 0xff]);
+
         assert!(validate_mac_address("00:11:22:33:44").is_err());
+        assert!(validate_mac_address("00:11-22:33:44:55").is_err());
         assert!(validate_mac_address("00:11:22:33:44:ZZ").is_err());
         assert!(validate_mac_address("invalid").is_err());
     }
@@ -475,4 +895,190 @@ mod tests {
         assert!(validate_email("@no-local.com").is_err());
         assert!(validate_email("no-domain@").is_err());
     }
+
+    struct Site {
+        name: String,
+        gateway: String,
+        mtu: u32,
+    }
+
+    struct Topology {
+        sites: Vec<Site>,
+    }
+
+    fn site_rules() -> impl Validator<Site> {
+        |site: &Site| {
+            let mut errors = Vec::new();
+            errors.extend(ValidationError::check("name", validate_dns_label(&site.name)));
+            errors.extend(ValidationError::check("gateway", validate_ip_address(&site.gateway)));
+            if !(576..=9000).contains(&site.mtu) {
+                errors.push(ValidationError::new("mtu", "MTU must be between 576 and 9000"));
+            }
+            errors
+        }
+    }
+
+    #[test]
+    fn test_validate_dns_label() {
+        assert!(validate_dns_label("branch-01").is_ok());
+        assert!(validate_dns_label("-branch").is_err());
+        assert!(validate_dns_label("branch_01").is_err());
+        assert!(validate_dns_label(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_validate_ipv6_scoped() {
+        let (addr, zone) = validate_ipv6_scoped("fe80::1%eth0").unwrap();
+        assert_eq!(addr, "fe80::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(zone.as_deref(), Some("eth0"));
+        assert_eq!(validate_ipv6_scoped("ff02::1%3").unwrap().1.as_deref(), Some("3"));
+        assert_eq!(validate_ipv6_scoped("2001:db8::1").unwrap().1, None);
+
+        let err = validate_ipv6_scoped("2001:db8::1%eth0").unwrap_err();
+        assert_eq!(err.code, codes::INVALID_ZONE);
+        assert_eq!(validate_ipv6_scoped("fe80::1%").unwrap_err().code, codes::INVALID_ZONE);
+        assert_eq!(validate_ipv6_scoped("fe80::1%eth0;id").unwrap_err().code, codes::INVALID_ZONE);
+        let err = validate_ipv6_scoped("fe80::zz%eth0").unwrap_err();
+        assert_eq!(err.code, codes::INVALID_IP);
+        assert_eq!(err.rejected_value.as_deref(), Some("fe80::zz%eth0"));
+    }
+
+    #[test]
+    fn test_validate_domain_name() {
+        assert!(validate_domain_name("example.com").is_ok());
+        assert!(validate_domain_name("mail.example.com.").is_ok());
+        assert!(validate_domain_name("a-1.b2").is_ok());
+
+        assert_eq!(validate_domain_name("").unwrap_err().code, codes::REQUIRED);
+        assert_eq!(validate_domain_name("1st.example.com").unwrap_err().code, codes::INVALID_FORMAT);
+        assert_eq!(validate_domain_name("host-.example").unwrap_err().code, codes::INVALID_FORMAT);
+        assert_eq!(validate_domain_name("under_score.com").unwrap_err().code, codes::INVALID_CHARACTERS);
+        assert_eq!(validate_domain_name("a..b").unwrap_err().code, codes::INVALID_FORMAT);
+        assert_eq!(validate_domain_name(&"a.".repeat(130)).unwrap_err().code, codes::TOO_LONG);
+    }
+
+    #[test]
+    fn test_errors_carry_code_and_value() {
+        let err = ValidationError::check("gateway", validate_cidr("10.0.0.0/33")).unwrap();
+        assert_eq!(err.field_path, "gateway");
+        assert_eq!(err.code, codes::INVALID_PREFIX);
+        assert_eq!(err.rejected_value.as_deref(), Some("10.0.0.0/33"));
+
+        let err = validate_port_range(2000, 1000).unwrap_err();
+        assert_eq!(err.code, codes::INVALID_RANGE);
+        assert_eq!(err.rejected_value.as_deref(), Some("2000-1000"));
+
+        assert_eq!(validate_vlan_id(5000).unwrap_err().code, codes::OUT_OF_RANGE);
+        assert_eq!(validate_interface_name("eth0;ls").unwrap_err().code, codes::INVALID_CHARACTERS);
+
+        let json = serde_json::to_value(err.within("ports[0]").within("rule")).unwrap();
+        assert_eq!(json["field_path"], "rule.ports[0]");
+        assert_eq!(json["code"], "invalid_range");
+    }
+
+    struct Vlan {
+        id: u16,
+        parent: String,
+    }
+
+    impl Validate for Vlan {
+        fn validate(&self) -> Vec<ValidationError> {
+            let mut errors = ValidationErrors::new();
+            errors.check("id", validate_vlan_id(self.id));
+            errors.check("parent", validate_interface_name(&self.parent));
+            errors.into_vec()
+        }
+    }
+
+    struct Trunk {
+        name: String,
+        vlans: Vec<Vlan>,
+    }
+
+    impl Validate for Trunk {
+        fn validate(&self) -> Vec<ValidationError> {
+            let mut errors = ValidationErrors::new();
+            errors.check("name", validate_interface_name(&self.name));
+            errors.ensure(!self.vlans.is_empty(), "vlans", codes::REQUIRED, "at least one VLAN is required");
+            errors.each("vlans", &self.vlans);
+            errors.into_vec()
+        }
+    }
+
+    #[test]
+    fn test_validate_trait_collects_all_errors() {
+        let trunk = Trunk {
+            name: "bond0".into(),
+            vlans: vec![
+                Vlan { id: 10, parent: "bond0".into() },
+                Vlan { id: 0, parent: "".into() },
+                Vlan { id: 4095, parent: "bond0".into() },
+            ],
+        };
+
+        let errors = trunk.ensure_valid().unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|e| (e.field_path.as_str(), e.code.as_str())).collect();
+        assert_eq!(fields, [
+            ("vlans[1].id", codes::OUT_OF_RANGE),
+            ("vlans[1].parent", codes::REQUIRED),
+            ("vlans[2].id", codes::OUT_OF_RANGE),
+        ]);
+        assert!(errors.to_string().starts_with("vlans[1].id: VLAN ID must be between 1 and 4094; "));
+
+        let empty = Trunk { name: "bond 0".into(), vlans: Vec::new() };
+        assert_eq!(empty.validate().len(), 2);
+        assert!(Trunk { name: "bond0".into(), vlans: vec![Vlan { id: 1, parent: "bond0".into() }] }
+            .ensure_valid()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_validate_all_reports_every_violation() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(|topology: &Topology| {
+            topology
+                .sites
+                .iter()
+                .enumerate()
+                .flat_map(|(i, site)| {
+                    site_rules()
+                        .validate(site)
+                        .into_iter()
+                        .map(move |e| e.within(&format!("[{}]", i)).within("sites"))
+                })
+                .collect()
+        });
+        registry.register(|topology: &Topology| {
+            if topology.sites.is_empty() {
+                vec![ValidationError::new("sites", "at least one site is required")]
+            } else {
+                Vec::new()
+            }
+        });
+        assert_eq!(registry.count::<Topology>(), 2);
+        assert_eq!(registry.count::<Site>(), 0);
+
+        let topology = Topology {
+            sites: vec![
+                Site { name: "hq".into(), gateway: "10.0.0.1".into(), mtu: 1500 },
+                Site { name: "branch_2".into(), gateway: "10.0.0.300".into(), mtu: 100 },
+            ],
+        };
+        let errors = registry.validate_all(&topology);
+        let fields: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(fields, ["sites[1].name", "sites[1].gateway", "sites[1].mtu"]);
+        assert!(errors[2].to_string().starts_with("sites[1].mtu: "));
+
+        let errors = registry.validate_all(&Topology { sites: Vec::new() });
+        assert_eq!(errors, vec![ValidationError::new("sites", "at least one site is required")]);
+    }
+
+    #[test]
+    fn test_global_registry() {
+        register_validator(site_rules());
+        let site = Site { name: "-x-".into(), gateway: "nope".into(), mtu: 1500 };
+        assert_eq!(validate_all(&site).len(), 2);
+        // Types without rules always pass
+        assert!(validate_all(&42u32).is_empty());
+    }
 }
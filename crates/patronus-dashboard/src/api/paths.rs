@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::{error::Result, state::AppState};
+use patronus_sdwan::failover::{CalibrationReport, PredictionRecord};
 
 /// List all paths
 pub async fn list_paths(State(state): State<Arc<AppState>>) -> Result<Json<Vec<PathResponse>>> {
@@ -48,6 +49,30 @@ pub async fn get_path_metrics(
     }))
 }
 
+/// Get recent failure predictions for a path and how well they held up
+pub async fn get_path_predictions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> Result<Json<PathPredictionsResponse>> {
+    let path_id = patronus_sdwan::types::PathId::new(id);
+
+    let predictions = state.failover_engine.get_prediction_history(path_id).await;
+    let calibration = state.failover_engine.calibration_report(Some(path_id)).await;
+
+    Ok(Json(PathPredictionsResponse {
+        path_id: id,
+        predictions,
+        calibration,
+    }))
+}
+
+/// Get failure prediction calibration across all paths
+pub async fn get_prediction_calibration(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CalibrationReport>> {
+    Ok(Json(state.failover_engine.calibration_report(None).await))
+}
+
 /// Path response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PathResponse {
@@ -108,3 +133,12 @@ pub struct PathMetricsResponse {
     pub path_id: u64,
     pub metrics: MetricsResponse,
 }
+
+/// Path failure predictions response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PathPredictionsResponse {
+    pub path_id: u64,
+    /// Oldest first
+    pub predictions: Vec<PredictionRecord>,
+    pub calibration: CalibrationReport,
+}
@@ -147,6 +147,8 @@ fn api_routes() -> Router<Arc<AppState>> {
         .route("/paths", get(api::paths::list_paths))
         .route("/paths/:id", get(api::paths::get_path))
         .route("/paths/:id/metrics", get(api::paths::get_path_metrics))
+        .route("/paths/:id/predictions", get(api::paths::get_path_predictions))
+        .route("/predictions/calibration", get(api::paths::get_prediction_calibration))
         // Flows
         .route("/flows", get(api::flows::list_flows))
         // Policies
@@ -0,0 +1,1719 @@
+//! DNS Diagnostics
+//!
+//! A small stub resolver that talks DNS over UDP, falling back to TCP on
+//! truncation, directly to chosen servers. Outcomes keep SERVFAIL,
+//! NXDOMAIN and timeouts apart, answers can be compared across resolvers,
+//! DNSSEC is validated locally from the IANA root trust anchor, with
+//! failures traced to the link of the chain of trust that breaks, and
+//! delegations can be walked from the root like `dig +trace`.
+
+use crate::doh::{
+    build_query_with, parse_message, rcode_name, read_name, read_u16, read_u32,
+    record_type_code, DnsMessage, QueryFlags, WireRecord,
+};
+use crate::tools::DnsRecord;
+use futures::future::join_all;
+use patronus_core::{Error, Result};
+use ring::{digest, signature};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+pub const DNS_PORT: u16 = 53;
+
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Types queried when enumerating a name
+///
+/// Most servers no longer answer ANY fully (RFC 8482), so enumeration asks
+/// for each type explicitly.
+pub const COMMON_RECORD_TYPES: &[&str] = &[
+    "A", "AAAA", "CNAME", "MX", "NS", "TXT", "SOA", "SRV", "CAA", "HTTPS", "DS", "DNSKEY",
+];
+
+/// IPv4 addresses of a.root-servers.net through m.root-servers.net
+pub const ROOT_SERVERS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+
+/// DS records of the root zone KSKs published by IANA in root-anchors.xml:
+/// KSK-2017 and KSK-2024 (key tag, algorithm, digest type, SHA-256 digest)
+const IANA_ROOT_ANCHORS: [(u16, u8, u8, &str); 2] = [
+    (20326, 8, 2, "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D"),
+    (38696, 8, 2, "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16"),
+];
+
+/// DNSKEY algorithms validated: RSA/SHA-1, RSA/SHA-256, RSA/SHA-512,
+/// ECDSA P-256 and P-384, and Ed25519
+const SUPPORTED_ALGORITHMS: [u8; 7] = [5, 7, 8, 10, 13, 14, 15];
+
+/// Referrals followed before a trace gives up
+const MAX_TRACE_STEPS: usize = 16;
+
+const CLASS_IN: u16 = 1;
+
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_DS: u16 = 43;
+const TYPE_RRSIG: u16 = 46;
+const TYPE_NSEC: u16 = 47;
+const TYPE_DNSKEY: u16 = 48;
+const TYPE_NSEC3: u16 = 50;
+
+const RCODE_SERVFAIL: u8 = 2;
+
+/// Outcome of a single query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsQueryStatus {
+    /// NOERROR, with or without records
+    NoError,
+    NxDomain,
+    ServFail,
+    Refused,
+    /// Any other rcode (FORMERR, NOTIMP, ...)
+    OtherRcode,
+    /// No response within the timeout
+    Timeout,
+    /// Transport or decoding failure other than a timeout
+    Error,
+}
+
+impl DnsQueryStatus {
+    fn from_rcode(rcode: u8) -> Self {
+        match rcode {
+            0 => Self::NoError,
+            2 => Self::ServFail,
+            3 => Self::NxDomain,
+            5 => Self::Refused,
+            _ => Self::OtherRcode,
+        }
+    }
+
+    /// Whether the server produced a DNS response at all
+    pub fn responded(&self) -> bool {
+        !matches!(self, Self::Timeout | Self::Error)
+    }
+}
+
+/// One server's answer to one query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsServerAnswer {
+    pub server: SocketAddr,
+    pub query: String,
+    pub record_type: String,
+    pub status: DnsQueryStatus,
+    /// DNS response code, `None` on timeout or transport error
+    pub rcode: Option<u8>,
+    pub rcode_name: Option<String>,
+    pub records: Vec<DnsRecord>,
+    /// The server set AD: it validated the answer with DNSSEC
+    pub authenticated: bool,
+    /// The UDP answer was truncated and the query was repeated over TCP
+    pub used_tcp: bool,
+    pub response_time_ms: Option<f64>,
+    pub error: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+/// Servers that returned the same answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsAnswerGroup {
+    pub status: DnsQueryStatus,
+    /// Sorted record values, TTLs ignored
+    pub values: Vec<String>,
+    pub servers: Vec<SocketAddr>,
+}
+
+/// Answers for one name compared across resolvers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsPropagationReport {
+    pub query: String,
+    pub record_type: String,
+    pub answers: Vec<DnsServerAnswer>,
+    /// Distinct answers among the servers that responded, most common first
+    pub groups: Vec<DnsAnswerGroup>,
+    /// Servers that timed out or could not be reached
+    pub unreachable: Vec<SocketAddr>,
+    /// Every server that responded gave the same answer
+    pub consistent: bool,
+    pub timestamp: SystemTime,
+}
+
+/// DNSSEC state of an answer or of one link in the chain of trust
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnssecStatus {
+    /// Validated from the root trust anchor down
+    Secure,
+    /// Provably unsigned: the chain ends at an unsigned delegation
+    Insecure,
+    /// Signed but failing validation
+    Bogus,
+    /// Not enough information, e.g. a non-validating resolver or no response
+    Indeterminate,
+}
+
+/// One step in the chain of trust
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnssecLink {
+    /// Owner name of the checked RRset
+    pub name: String,
+    pub record_type: String,
+    pub status: DnssecStatus,
+    pub detail: String,
+}
+
+/// DNSSEC validation result for one query
+///
+/// The chain walk re-fetches each link with checking disabled and
+/// validates it here, from the client's [`TrustAnchor`]s down: DS digests
+/// and RRSIGs are verified cryptographically, so a failure is pinned to
+/// the link that breaks. The status comes from this walk alone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnssecReport {
+    pub query: String,
+    pub record_type: String,
+    pub server: SocketAddr,
+    pub status: DnssecStatus,
+    /// The resolver set AD on the answer; informational, it never makes a
+    /// Bogus or Indeterminate chain Secure
+    pub resolver_validated: bool,
+    /// Where the chain breaks (Bogus) or ends (Insecure)
+    pub failing_link: Option<DnssecLink>,
+    pub chain: Vec<DnssecLink>,
+    /// The answer as returned with validation enabled
+    pub answer: DnsServerAnswer,
+    pub timestamp: SystemTime,
+}
+
+/// A DNSSEC trust anchor for the root zone, in DS form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustAnchor {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+impl TrustAnchor {
+    pub fn new(key_tag: u16, algorithm: u8, digest_type: u8, digest: Vec<u8>) -> Self {
+        Self { key_tag, algorithm, digest_type, digest }
+    }
+
+    /// The root key signing keys published by IANA
+    pub fn iana_root() -> Vec<Self> {
+        IANA_ROOT_ANCHORS.iter()
+            .map(|(tag, algorithm, digest_type, digest)| {
+                Self::new(*tag, *algorithm, *digest_type, hex::decode(digest).expect("valid anchor digest"))
+            })
+            .collect()
+    }
+}
+
+/// One server consulted while tracing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsTraceStep {
+    /// Zone the server was expected to be authoritative for
+    pub zone: String,
+    pub server: SocketAddr,
+    pub server_name: Option<String>,
+    pub status: DnsQueryStatus,
+    pub rcode_name: Option<String>,
+    /// Referral NS records, or the final answer
+    pub records: Vec<DnsRecord>,
+    pub response_time_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Iterative resolution from the root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsTraceResult {
+    pub query: String,
+    pub record_type: String,
+    pub steps: Vec<DnsTraceStep>,
+    pub status: DnsQueryStatus,
+    pub answers: Vec<DnsRecord>,
+    pub error: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+/// Why an exchange produced no message
+#[derive(Debug, Clone)]
+enum Failure {
+    Timeout,
+    Error(String),
+}
+
+impl Failure {
+    fn status(&self) -> DnsQueryStatus {
+        match self {
+            Self::Timeout => DnsQueryStatus::Timeout,
+            Self::Error(_) => DnsQueryStatus::Error,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::Timeout => "No response before timeout".to_string(),
+            Self::Error(e) => e.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Exchange {
+    message: DnsMessage,
+    elapsed_ms: f64,
+    used_tcp: bool,
+}
+
+type Outcome = std::result::Result<Exchange, Failure>;
+
+/// DNS client for diagnostics
+#[derive(Debug, Clone)]
+pub struct DnsClient {
+    timeout: Duration,
+    port: u16,
+    roots: Vec<IpAddr>,
+    trust_anchors: Vec<TrustAnchor>,
+}
+
+impl Default for DnsClient {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_DNS_TIMEOUT,
+            port: DNS_PORT,
+            roots: ROOT_SERVERS.iter().map(|ip| IpAddr::V4(*ip)).collect(),
+            trust_anchors: TrustAnchor::iana_root(),
+        }
+    }
+}
+
+impl DnsClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Per-query timeout, covering any TCP retry
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Port used for root servers and servers learned from referrals
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Servers a trace starts from
+    pub fn with_roots(mut self, roots: Vec<IpAddr>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Root keys DNSSEC validation starts from, instead of IANA's
+    pub fn with_trust_anchors(mut self, anchors: Vec<TrustAnchor>) -> Self {
+        self.trust_anchors = anchors;
+        self
+    }
+
+    /// Query one server
+    pub async fn query(&self, name: &str, record_type: &str, server: SocketAddr) -> Result<DnsServerAnswer> {
+        let qtype = parse_type(record_type)?;
+        let name = normalize(name);
+        let outcome = self.exchange(server, &name, qtype, QueryFlags::default()).await;
+        Ok(server_answer(server, &name, record_type, &outcome))
+    }
+
+    /// Query one server for several types in parallel
+    ///
+    /// Pass [`COMMON_RECORD_TYPES`] for an ANY-style enumeration.
+    pub async fn query_types(
+        &self,
+        name: &str,
+        record_types: &[&str],
+        server: SocketAddr,
+    ) -> Result<Vec<DnsServerAnswer>> {
+        for record_type in record_types {
+            parse_type(record_type)?;
+        }
+        join_all(record_types.iter().map(|t| self.query(name, t, server)))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Query several resolvers in parallel and compare their answers
+    pub async fn propagation_check(
+        &self,
+        name: &str,
+        record_type: &str,
+        servers: &[SocketAddr],
+    ) -> Result<DnsPropagationReport> {
+        if servers.is_empty() {
+            return Err(Error::Config("No DNS servers to compare".to_string()));
+        }
+        parse_type(record_type)?;
+
+        let answers: Vec<DnsServerAnswer> = join_all(servers.iter().map(|s| self.query(name, record_type, *s)))
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+
+        let mut groups: BTreeMap<(String, Vec<String>), DnsAnswerGroup> = BTreeMap::new();
+        let mut unreachable = Vec::new();
+        for answer in &answers {
+            if !answer.status.responded() {
+                unreachable.push(answer.server);
+                continue;
+            }
+            let mut values: Vec<String> = answer.records.iter()
+                .map(|r| format!("{} {}", r.record_type, r.value))
+                .collect();
+            values.sort();
+            values.dedup();
+
+            let key = (format!("{:?}", answer.status), values.clone());
+            groups.entry(key)
+                .or_insert_with(|| DnsAnswerGroup { status: answer.status, values, servers: Vec::new() })
+                .servers
+                .push(answer.server);
+        }
+
+        let mut groups: Vec<DnsAnswerGroup> = groups.into_values().collect();
+        groups.sort_by_key(|g| std::cmp::Reverse(g.servers.len()));
+
+        Ok(DnsPropagationReport {
+            query: normalize(name),
+            record_type: record_type.to_ascii_uppercase(),
+            consistent: groups.len() <= 1,
+            answers,
+            groups,
+            unreachable,
+            timestamp: SystemTime::now(),
+        })
+    }
+
+    /// Look up the PTR record for an address
+    pub async fn reverse_lookup(&self, ip: IpAddr, server: SocketAddr) -> Result<DnsServerAnswer> {
+        self.query(&reverse_name(ip), "PTR", server).await
+    }
+
+    /// Query through a resolver and validate the answer's chain of trust
+    pub async fn dnssec_check(&self, name: &str, record_type: &str, server: SocketAddr) -> Result<DnssecReport> {
+        let qtype = parse_type(record_type)?;
+        let name = normalize(name);
+        let validated = QueryFlags { dnssec_ok: true, ..Default::default() };
+        let unchecked = QueryFlags { checking_disabled: true, ..validated };
+
+        let outcome = self.exchange(server, &name, qtype, validated).await;
+        let mut report = DnssecReport {
+            query: name.clone(),
+            record_type: record_type.to_ascii_uppercase(),
+            server,
+            status: DnssecStatus::Indeterminate,
+            resolver_validated: false,
+            failing_link: None,
+            chain: Vec::new(),
+            answer: server_answer(server, &name, record_type, &outcome),
+            timestamp: SystemTime::now(),
+        };
+        let Ok(plain) = outcome else {
+            return Ok(report);
+        };
+        report.resolver_validated = plain.message.authenticated_data();
+
+        let target = self.exchange(server, &name, qtype, unchecked).await;
+        report.chain = self.walk_chain(server, &name, qtype, &target, unchecked).await;
+
+        let broken = report.chain.iter().find(|l| l.status == DnssecStatus::Bogus).cloned();
+        let insecure = report.chain.iter().find(|l| l.status == DnssecStatus::Insecure).cloned();
+        // SERVFAIL that goes away with CD set means validation failed
+        let rejected = plain.message.rcode == RCODE_SERVFAIL
+            && matches!(&target, Ok(t) if t.message.rcode != RCODE_SERVFAIL);
+
+        let verified = !report.chain.is_empty()
+            && report.chain.iter().all(|l| l.status == DnssecStatus::Secure);
+
+        if rejected || broken.is_some() {
+            report.status = DnssecStatus::Bogus;
+            report.failing_link = broken.or_else(|| Some(DnssecLink {
+                name: name.clone(),
+                record_type: report.record_type.clone(),
+                status: DnssecStatus::Bogus,
+                detail: "Resolver rejected the answer although every link verifies here; \
+                         check the resolver's trust anchor and clock".to_string(),
+            }));
+        } else if insecure.is_some() {
+            report.status = DnssecStatus::Insecure;
+            report.failing_link = insecure;
+        } else if verified {
+            report.status = DnssecStatus::Secure;
+        }
+
+        Ok(report)
+    }
+
+    /// Resolve iteratively from the root servers, following referrals
+    pub async fn trace(&self, name: &str, record_type: &str) -> Result<DnsTraceResult> {
+        let qtype = parse_type(record_type)?;
+        let name = normalize(name);
+        let flags = QueryFlags { recursion_desired: false, ..Default::default() };
+
+        let mut result = DnsTraceResult {
+            query: name.clone(),
+            record_type: record_type.to_ascii_uppercase(),
+            steps: Vec::new(),
+            status: DnsQueryStatus::Error,
+            answers: Vec::new(),
+            error: None,
+            timestamp: SystemTime::now(),
+        };
+
+        let mut zone = ".".to_string();
+        let mut servers: Vec<(Option<String>, SocketAddr)> = self.roots.iter()
+            .map(|ip| (None, SocketAddr::new(*ip, self.port)))
+            .collect();
+
+        for _ in 0..MAX_TRACE_STEPS {
+            let mut response = None;
+            for (server_name, server) in &servers {
+                let outcome = self.exchange(*server, &name, qtype, flags).await;
+                let mut step = DnsTraceStep {
+                    zone: zone.clone(),
+                    server: *server,
+                    server_name: server_name.clone(),
+                    status: DnsQueryStatus::Error,
+                    rcode_name: None,
+                    records: Vec::new(),
+                    response_time_ms: None,
+                    error: None,
+                };
+                match outcome {
+                    Ok(exchange) => {
+                        let msg = exchange.message;
+                        step.status = DnsQueryStatus::from_rcode(msg.rcode);
+                        step.rcode_name = Some(rcode_name(msg.rcode));
+                        step.response_time_ms = Some(exchange.elapsed_ms);
+                        step.records = if msg.answers.is_empty() {
+                            msg.authority.iter().filter(|r| r.rtype == TYPE_NS).map(|r| r.record.clone()).collect()
+                        } else {
+                            msg.answers.iter().map(|r| r.record.clone()).collect()
+                        };
+                        result.steps.push(step);
+                        response = Some(msg);
+                        break;
+                    }
+                    Err(failure) => {
+                        step.status = failure.status();
+                        step.error = Some(failure.message());
+                        result.steps.push(step);
+                    }
+                }
+            }
+
+            let Some(msg) = response else {
+                result.status = result.steps.last().map(|s| s.status).unwrap_or(DnsQueryStatus::Error);
+                result.error = Some(format!("No server for {} responded", zone));
+                return Ok(result);
+            };
+
+            result.status = DnsQueryStatus::from_rcode(msg.rcode);
+            if msg.rcode != 0 || !msg.answers.is_empty() {
+                result.answers = msg.answers.iter().map(|r| r.record.clone()).collect();
+                return Ok(result);
+            }
+
+            let referral: Vec<&WireRecord> = msg.authority.iter().filter(|r| r.rtype == TYPE_NS).collect();
+            let Some(first) = referral.first() else {
+                // NODATA: authoritative "exists, but not with this type"
+                return Ok(result);
+            };
+            let next_zone = normalize(&first.record.name);
+            if next_zone == zone || !in_zone(&next_zone, &zone) || !in_zone(&name, &next_zone) {
+                result.status = DnsQueryStatus::Error;
+                result.error = Some(format!("Lame referral from {} to {}", zone, next_zone));
+                return Ok(result);
+            }
+
+            // Glue from the additional section, IPv4 first
+            let mut next: Vec<(Option<String>, SocketAddr)> = Vec::new();
+            for ns in &referral {
+                let host = normalize(&ns.record.value);
+                for glue in msg.additional.iter().filter(|r| normalize(&r.record.name) == host) {
+                    if let Ok(ip) = glue.record.value.parse::<IpAddr>() {
+                        next.push((Some(host.clone()), SocketAddr::new(ip, self.port)));
+                    }
+                }
+            }
+            if next.is_empty() {
+                for ns in &referral {
+                    let host = normalize(&ns.record.value);
+                    if let Ok(addrs) = tokio::net::lookup_host(format!("{}:{}", host, self.port)).await {
+                        let addrs: Vec<SocketAddr> = addrs.collect();
+                        next.extend(addrs.into_iter().map(|a| (Some(host.clone()), a)));
+                    }
+                }
+            }
+            if next.is_empty() {
+                result.status = DnsQueryStatus::Error;
+                result.error = Some(format!("No address for any nameserver of {}", next_zone));
+                return Ok(result);
+            }
+            next.sort_by_key(|(_, addr)| addr.is_ipv6());
+
+            zone = next_zone;
+            servers = next;
+        }
+
+        result.status = DnsQueryStatus::Error;
+        result.error = Some(format!("Gave up after {} referrals", MAX_TRACE_STEPS));
+        Ok(result)
+    }
+
+    /// Check each link from the root keys down to the answer
+    async fn walk_chain(
+        &self,
+        server: SocketAddr,
+        name: &str,
+        qtype: u16,
+        target: &Outcome,
+        flags: QueryFlags,
+    ) -> Vec<DnssecLink> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32;
+        let mut chain = Vec::new();
+
+        // The root keys are the trust anchor
+        let mut zone = ".".to_string();
+        let root = match self.exchange(server, ".", TYPE_DNSKEY, flags).await {
+            Ok(exchange) => exchange.message,
+            Err(failure) => {
+                chain.push(link(".", TYPE_DNSKEY, DnssecStatus::Indeterminate, failure.message()));
+                return chain;
+            }
+        };
+        let mut keys = dnskeys(&root.answers, &zone);
+        if keys.is_empty() {
+            chain.push(link(".", TYPE_DNSKEY, DnssecStatus::Indeterminate, "No root DNSKEY records returned".to_string()));
+            return chain;
+        }
+        let anchors: Vec<Ds> = self.trust_anchors.iter().map(Ds::from).collect();
+        let anchored: Vec<DnsKey> = keys.iter()
+            .filter(|k| anchors.iter().any(|a| a.matches(".", k)))
+            .cloned()
+            .collect();
+        if anchored.is_empty() {
+            let tags: Vec<String> = anchors.iter().map(|a| a.key_tag.to_string()).collect();
+            chain.push(link(".", TYPE_DNSKEY, DnssecStatus::Bogus, format!(
+                "No root DNSKEY matches trust anchor key tag {} (root keys: {})",
+                tags.join(", "), join_tags(&keys)
+            )));
+            return chain;
+        }
+        if let Some(fault) = check_signature(&root.answers, ".", TYPE_DNSKEY, ".", &anchored, now) {
+            chain.push(link(".", TYPE_DNSKEY, DnssecStatus::Bogus, fault));
+            return chain;
+        }
+        chain.push(link(".", TYPE_DNSKEY, DnssecStatus::Secure, format!(
+            "Key set signed by trust anchor key tag {}", join_tags(&anchored)
+        )));
+
+        for child in ancestors(name) {
+            let ds_msg = match self.exchange(server, &child, TYPE_DS, flags).await {
+                Ok(exchange) => exchange.message,
+                Err(failure) => {
+                    chain.push(link(&child, TYPE_DS, DnssecStatus::Indeterminate, failure.message()));
+                    return chain;
+                }
+            };
+            let ds: Vec<Ds> = ds_msg.answers.iter()
+                .filter(|r| r.rtype == TYPE_DS && normalize(&r.record.name) == child)
+                .filter_map(|r| Ds::parse(&r.rdata))
+                .collect();
+
+            let key_msg = match self.exchange(server, &child, TYPE_DNSKEY, flags).await {
+                Ok(exchange) => exchange.message,
+                Err(failure) => {
+                    chain.push(link(&child, TYPE_DNSKEY, DnssecStatus::Indeterminate, failure.message()));
+                    return chain;
+                }
+            };
+            let child_keys = dnskeys(&key_msg.answers, &child);
+
+            if ds.is_empty() {
+                if child_keys.is_empty() {
+                    // Not a zone cut; the name stays in the current zone
+                    continue;
+                }
+                chain.push(link(&child, TYPE_DS, DnssecStatus::Insecure, format!(
+                    "{} has no DS record for {}; unsigned delegation", zone, child
+                )));
+                return chain;
+            }
+
+            if let Some(fault) = check_signature(&ds_msg.answers, &child, TYPE_DS, &zone, &keys, now) {
+                chain.push(link(&child, TYPE_DS, DnssecStatus::Bogus, fault));
+                return chain;
+            }
+            // A zone signed only with algorithms we cannot check is treated
+            // as unsigned (RFC 4035 section 5.2)
+            if !ds.iter().any(Ds::is_supported) {
+                chain.push(link(&child, TYPE_DS, DnssecStatus::Insecure, format!(
+                    "{} DS records for {} only use unsupported algorithms or digest types", zone, child
+                )));
+                return chain;
+            }
+
+            if child_keys.is_empty() {
+                chain.push(link(&child, TYPE_DNSKEY, DnssecStatus::Bogus, format!(
+                    "{} publishes a DS for {} but the zone returns no DNSKEY", zone, child
+                )));
+                return chain;
+            }
+
+            let trusted: Vec<DnsKey> = child_keys.iter()
+                .filter(|k| ds.iter().any(|d| d.matches(&child, k)))
+                .cloned()
+                .collect();
+            if trusted.is_empty() {
+                let tags: Vec<String> = ds.iter().map(|d| d.key_tag.to_string()).collect();
+                chain.push(link(&child, TYPE_DS, DnssecStatus::Bogus, format!(
+                    "No DNSKEY in {} matches DS key tag {} (zone keys: {})",
+                    child, tags.join(", "), join_tags(&child_keys)
+                )));
+                return chain;
+            }
+            chain.push(link(&child, TYPE_DS, DnssecStatus::Secure, format!(
+                "Signed by {}, digest matches key tag {} in {}", zone, join_tags(&trusted), child
+            )));
+
+            if let Some(fault) = check_signature(&key_msg.answers, &child, TYPE_DNSKEY, &child, &trusted, now) {
+                chain.push(link(&child, TYPE_DNSKEY, DnssecStatus::Bogus, fault));
+                return chain;
+            }
+            chain.push(link(&child, TYPE_DNSKEY, DnssecStatus::Secure, format!(
+                "Key set signed by a DS-referenced key, tags {}", join_tags(&child_keys)
+            )));
+
+            zone = child;
+            keys = child_keys;
+        }
+
+        let message = match target {
+            Ok(exchange) => &exchange.message,
+            Err(failure) => {
+                chain.push(link(name, qtype, DnssecStatus::Indeterminate, failure.message()));
+                return chain;
+            }
+        };
+        let answer_types: Vec<u16> = message.answers.iter()
+            .filter(|r| normalize(&r.record.name) == name && r.rtype != TYPE_RRSIG)
+            .map(|r| r.rtype)
+            .collect();
+
+        if message.rcode == RCODE_SERVFAIL {
+            chain.push(link(name, qtype, DnssecStatus::Indeterminate,
+                "SERVFAIL even with checking disabled; not a DNSSEC failure".to_string()));
+        } else if let Some(&covered) = answer_types.iter().find(|t| **t == qtype || **t == TYPE_CNAME) {
+            let (status, detail) = match check_signature(&message.answers, name, covered, &zone, &keys, now) {
+                Some(fault) => (DnssecStatus::Bogus, fault),
+                None => (DnssecStatus::Secure, format!("Signed by {}", zone)),
+            };
+            chain.push(link(name, covered, status, detail));
+        } else {
+            // NXDOMAIN or NODATA needs a signed NSEC/NSEC3 proof
+            let proof = message.authority.iter().find(|r| r.rtype == TYPE_NSEC || r.rtype == TYPE_NSEC3);
+            let (status, detail) = match proof {
+                None => (DnssecStatus::Bogus, "Denial of existence has no NSEC or NSEC3 proof".to_string()),
+                Some(proof) => {
+                    let owner = normalize(&proof.record.name);
+                    match check_signature(&message.authority, &owner, proof.rtype, &zone, &keys, now) {
+                        Some(fault) => (DnssecStatus::Bogus, fault),
+                        None => (DnssecStatus::Secure, format!("Signed {} denial of existence", proof.record.record_type)),
+                    }
+                }
+            };
+            chain.push(link(name, qtype, status, detail));
+        }
+
+        chain
+    }
+
+    /// Send one query, retrying over TCP if the UDP answer is truncated
+    async fn exchange(&self, server: SocketAddr, name: &str, qtype: u16, flags: QueryFlags) -> Outcome {
+        let id = {
+            let bytes = uuid::Uuid::new_v4().into_bytes();
+            u16::from_be_bytes([bytes[0], bytes[1]])
+        };
+        let query = build_query_with(name, qtype, &QueryFlags { id, ..flags })
+            .map_err(|e| Failure::Error(e.to_string()))?;
+        let start = Instant::now();
+
+        let message = tokio::time::timeout(self.timeout, udp_exchange(server, &query, id))
+            .await
+            .map_err(|_| Failure::Timeout)?
+            .map_err(|e| Failure::Error(e.to_string()))?;
+
+        if !message.truncated() {
+            return Ok(Exchange { message, elapsed_ms: elapsed_ms(start), used_tcp: false });
+        }
+
+        let remaining = self.timeout.saturating_sub(start.elapsed());
+        let message = tokio::time::timeout(remaining, tcp_exchange(server, &query))
+            .await
+            .map_err(|_| Failure::Timeout)?
+            .map_err(|e| Failure::Error(e.to_string()))?;
+        if message.id != id {
+            return Err(Failure::Error("TCP response ID does not match the query".to_string()));
+        }
+
+        Ok(Exchange { message, elapsed_ms: elapsed_ms(start), used_tcp: true })
+    }
+}
+
+async fn udp_exchange(server: SocketAddr, query: &[u8], id: u16) -> Result<DnsMessage> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        // Ignore stray or spoofed datagrams and keep waiting for ours
+        if let Ok(message) = parse_message(&buf[..n]) {
+            if message.id == id {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+async fn tcp_exchange(server: SocketAddr, query: &[u8]) -> Result<DnsMessage> {
+    let mut stream = TcpStream::connect(server).await?;
+
+    let mut framed = Vec::with_capacity(query.len() + 2);
+    framed.extend_from_slice(&(query.len() as u16).to_be_bytes());
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf).await?;
+
+    parse_message(&buf)
+}
+
+fn server_answer(server: SocketAddr, name: &str, record_type: &str, outcome: &Outcome) -> DnsServerAnswer {
+    let mut answer = DnsServerAnswer {
+        server,
+        query: name.to_string(),
+        record_type: record_type.to_ascii_uppercase(),
+        status: DnsQueryStatus::Error,
+        rcode: None,
+        rcode_name: None,
+        records: Vec::new(),
+        authenticated: false,
+        used_tcp: false,
+        response_time_ms: None,
+        error: None,
+        timestamp: SystemTime::now(),
+    };
+
+    match outcome {
+        Ok(exchange) => {
+            let msg = &exchange.message;
+            answer.status = DnsQueryStatus::from_rcode(msg.rcode);
+            answer.rcode = Some(msg.rcode);
+            answer.rcode_name = Some(rcode_name(msg.rcode));
+            answer.records = msg.answers.iter().map(|r| r.record.clone()).collect();
+            answer.authenticated = msg.authenticated_data();
+            answer.used_tcp = exchange.used_tcp;
+            answer.response_time_ms = Some(exchange.elapsed_ms);
+        }
+        Err(failure) => {
+            answer.status = failure.status();
+            answer.error = Some(failure.message());
+        }
+    }
+
+    answer
+}
+
+/// Name queried for a reverse lookup, in in-addr.arpa or ip6.arpa
+pub fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(v6) => {
+            let mut labels: Vec<String> = v6.octets().iter()
+                .flat_map(|b| [b >> 4, b & 0x0f])
+                .map(|n| format!("{:x}", n))
+                .collect();
+            labels.reverse();
+            format!("{}.ip6.arpa", labels.join("."))
+        }
+    }
+}
+
+/// Resolvers configured in /etc/resolv.conf
+pub fn system_resolvers() -> Vec<SocketAddr> {
+    std::fs::read_to_string("/etc/resolv.conf")
+        .map(|content| parse_resolv_conf(&content))
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_resolv_conf(content: &str) -> Vec<SocketAddr> {
+    content.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()? != "nameserver" {
+                return None;
+            }
+            // Drop any IPv6 zone index, e.g. fe80::1%eth0
+            let addr = fields.next()?.split('%').next()?;
+            addr.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, DNS_PORT))
+        })
+        .collect()
+}
+
+fn parse_type(record_type: &str) -> Result<u16> {
+    record_type_code(record_type)
+        .ok_or_else(|| Error::Config(format!("Unsupported DNS record type: {}", record_type)))
+}
+
+/// Lowercase without the trailing dot; the root is "."
+fn normalize(name: &str) -> String {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if name.is_empty() { ".".to_string() } else { name }
+}
+
+/// Whether `name` is `zone` or below it
+fn in_zone(name: &str, zone: &str) -> bool {
+    zone == "." || name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+/// Names between the root and `name`, top down, excluding the root
+fn ancestors(name: &str) -> Vec<String> {
+    if name == "." {
+        return Vec::new();
+    }
+    let labels: Vec<&str> = name.split('.').collect();
+    (0..labels.len()).rev().map(|i| labels[i..].join(".")).collect()
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn link(name: &str, rtype: u16, status: DnssecStatus, detail: String) -> DnssecLink {
+    DnssecLink {
+        name: name.to_string(),
+        record_type: crate::doh::record_type_name(rtype),
+        status,
+        detail,
+    }
+}
+
+/// Key tag of a DNSKEY (RFC 4034 Appendix B)
+pub(crate) fn key_tag(rdata: &[u8]) -> u16 {
+    let mut acc: u32 = 0;
+    for (i, b) in rdata.iter().enumerate() {
+        acc += if i & 1 == 0 { (*b as u32) << 8 } else { *b as u32 };
+    }
+    acc += (acc >> 16) & 0xffff;
+    (acc & 0xffff) as u16
+}
+
+#[derive(Clone)]
+struct DnsKey {
+    tag: u16,
+    algorithm: u8,
+    rdata: Vec<u8>,
+}
+
+fn dnskeys(records: &[WireRecord], owner: &str) -> Vec<DnsKey> {
+    records.iter()
+        .filter(|r| r.rtype == TYPE_DNSKEY && normalize(&r.record.name) == owner && r.rdata.len() > 4)
+        .map(|r| DnsKey { tag: key_tag(&r.rdata), algorithm: r.rdata[3], rdata: r.rdata.clone() })
+        .collect()
+}
+
+fn join_tags(keys: &[DnsKey]) -> String {
+    keys.iter().map(|k| k.tag.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+struct Ds {
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+}
+
+impl Ds {
+    fn parse(rdata: &[u8]) -> Option<Self> {
+        Some(Self {
+            key_tag: read_u16(rdata, 0).ok()?,
+            algorithm: *rdata.get(2)?,
+            digest_type: *rdata.get(3)?,
+            digest: rdata.get(4..)?.to_vec(),
+        })
+    }
+
+    fn is_supported(&self) -> bool {
+        SUPPORTED_ALGORITHMS.contains(&self.algorithm) && matches!(self.digest_type, 1 | 2 | 4)
+    }
+
+    /// Whether this DS refers to `key`, owned by `owner`
+    fn matches(&self, owner: &str, key: &DnsKey) -> bool {
+        self.key_tag == key.tag
+            && self.algorithm == key.algorithm
+            && ds_digest(owner, &key.rdata, self.digest_type).is_some_and(|d| d == self.digest)
+    }
+}
+
+impl From<&TrustAnchor> for Ds {
+    fn from(anchor: &TrustAnchor) -> Self {
+        Self {
+            key_tag: anchor.key_tag,
+            algorithm: anchor.algorithm,
+            digest_type: anchor.digest_type,
+            digest: anchor.digest.clone(),
+        }
+    }
+}
+
+/// Digest of a DNSKEY as published in a DS record (RFC 4034 section 5.1.4)
+fn ds_digest(owner: &str, dnskey: &[u8], digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        2 => &digest::SHA256,
+        4 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut context = digest::Context::new(algorithm);
+    context.update(&canonical_name(owner));
+    context.update(dnskey);
+    Some(context.finish().as_ref().to_vec())
+}
+
+struct Rrsig {
+    type_covered: u16,
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer: String,
+    /// RDATA up to the signature, with the signer name in canonical form
+    signed_fields: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Rrsig {
+    fn parse(rdata: &[u8]) -> Option<Self> {
+        // Signer names are never compressed (RFC 4034 section 3.1.7)
+        let (signer, end) = read_name(rdata, 18).ok()?;
+        let signer = normalize(&signer);
+        let mut signed_fields = rdata.get(..18)?.to_vec();
+        signed_fields.extend_from_slice(&canonical_name(&signer));
+        Some(Self {
+            type_covered: read_u16(rdata, 0).ok()?,
+            algorithm: rdata[2],
+            labels: rdata[3],
+            original_ttl: read_u32(rdata, 4).ok()?,
+            expiration: read_u32(rdata, 8).ok()?,
+            inception: read_u32(rdata, 12).ok()?,
+            key_tag: read_u16(rdata, 16).ok()?,
+            signer,
+            signed_fields,
+            signature: rdata.get(end..)?.to_vec(),
+        })
+    }
+
+    /// What the signature covers (RFC 4034 section 3.1.8.1): the RRSIG
+    /// fields, then the RRset in canonical form and order
+    fn signed_data(&self, owner: &str, rdatas: &[&[u8]]) -> Vec<u8> {
+        let owner = normalize(owner);
+        let labels: Vec<&str> = owner.split('.').filter(|l| !l.is_empty()).collect();
+        // A wildcard expansion is signed as the wildcard (RFC 4035 section 5.3.2)
+        let owner = if (self.labels as usize) < labels.len() {
+            let closest = &labels[labels.len() - self.labels as usize..];
+            std::iter::once("*").chain(closest.iter().copied()).collect::<Vec<_>>().join(".")
+        } else {
+            owner
+        };
+        let owner = canonical_name(&owner);
+
+        let mut rdatas: Vec<Vec<u8>> = rdatas.iter().map(|r| canonical_rdata(self.type_covered, r)).collect();
+        rdatas.sort();
+        rdatas.dedup();
+
+        let mut data = self.signed_fields.clone();
+        for rdata in rdatas {
+            data.extend_from_slice(&owner);
+            data.extend_from_slice(&self.type_covered.to_be_bytes());
+            data.extend_from_slice(&CLASS_IN.to_be_bytes());
+            data.extend_from_slice(&self.original_ttl.to_be_bytes());
+            data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            data.extend_from_slice(&rdata);
+        }
+        data
+    }
+}
+
+/// Lowercase wire form of a name
+fn canonical_name(name: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    for label in normalize(name).split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out
+}
+
+/// RDATA with its embedded names lowercased (RFC 4034 section 6.2, as
+/// amended by RFC 6840 section 5.1)
+fn canonical_rdata(rtype: u16, rdata: &[u8]) -> Vec<u8> {
+    let mut rdata = rdata.to_vec();
+    // Offset of the first name and how many names follow it
+    let (mut pos, names) = match rtype {
+        2 | 5 | 12 | 39 => (0, 1), // NS, CNAME, PTR, DNAME
+        15 => (2, 1),              // MX
+        33 => (6, 1),              // SRV
+        6 => (0, 2),               // SOA
+        _ => return rdata,
+    };
+    for _ in 0..names {
+        while let Some(&len) = rdata.get(pos) {
+            pos += 1;
+            if len == 0 {
+                break;
+            }
+            let end = (pos + len as usize).min(rdata.len());
+            rdata[pos..end].make_ascii_lowercase();
+            pos = end;
+        }
+    }
+    rdata
+}
+
+/// Check `sig` over `data` with `key`
+fn verify_signature(key: &DnsKey, sig: &Rrsig, data: &[u8]) -> bool {
+    let public_key = &key.rdata[4..];
+    match key.algorithm {
+        5 | 7 | 8 | 10 => {
+            let Some((e, n)) = rsa_public_key(public_key) else {
+                return false;
+            };
+            let params = match key.algorithm {
+                8 => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                10 => &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            };
+            signature::RsaPublicKeyComponents { n, e }.verify(params, data, &sig.signature).is_ok()
+        }
+        13 | 14 => {
+            let params = if key.algorithm == 13 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            // DNSKEY holds the bare point; ring wants it uncompressed-tagged
+            let mut point = vec![0x04];
+            point.extend_from_slice(public_key);
+            signature::UnparsedPublicKey::new(params, point).verify(data, &sig.signature).is_ok()
+        }
+        15 => signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(data, &sig.signature)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// Exponent and modulus of an RSA DNSKEY (RFC 3110 section 2)
+fn rsa_public_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (exponent_len, rest) = match *key.first()? {
+        0 => (read_u16(key, 1).ok()? as usize, key.get(3..)?),
+        len => (len as usize, key.get(1..)?),
+    };
+    if rest.len() <= exponent_len {
+        return None;
+    }
+    Some(rest.split_at(exponent_len))
+}
+
+/// Check that an RRset carries a valid signature by one of `keys`,
+/// describing the fault if not
+fn check_signature(
+    records: &[WireRecord],
+    owner: &str,
+    covered: u16,
+    signer: &str,
+    keys: &[DnsKey],
+    now: u32,
+) -> Option<String> {
+    let type_name = crate::doh::record_type_name(covered);
+    let rrset: Vec<&[u8]> = records.iter()
+        .filter(|r| r.rtype == covered && normalize(&r.record.name) == owner)
+        .map(|r| r.rdata.as_slice())
+        .collect();
+    let sigs: Vec<Rrsig> = records.iter()
+        .filter(|r| r.rtype == TYPE_RRSIG && normalize(&r.record.name) == owner)
+        .filter_map(|r| Rrsig::parse(&r.rdata))
+        .filter(|s| s.type_covered == covered)
+        .collect();
+
+    let fault = |sig: &Rrsig| {
+        let candidates: Vec<&DnsKey> = keys.iter()
+            .filter(|k| k.tag == sig.key_tag && k.algorithm == sig.algorithm)
+            .collect();
+        if sig.signer != signer {
+            Some(format!("RRSIG signer {} is not the zone {}", sig.signer, signer))
+        } else if candidates.is_empty() {
+            Some(format!("Signed with key tag {}, which is not in the {} DNSKEY set", sig.key_tag, signer))
+        } else if now > sig.expiration {
+            Some(format!("RRSIG by key tag {} expired at {}", sig.key_tag, sig.expiration))
+        } else if now < sig.inception {
+            Some(format!("RRSIG by key tag {} not valid until {}", sig.key_tag, sig.inception))
+        } else if !SUPPORTED_ALGORITHMS.contains(&sig.algorithm) {
+            Some(format!("RRSIG by key tag {} uses unsupported algorithm {}", sig.key_tag, sig.algorithm))
+        } else {
+            let data = sig.signed_data(owner, &rrset);
+            if candidates.iter().any(|key| verify_signature(key, sig, &data)) {
+                None
+            } else {
+                Some(format!("RRSIG by key tag {} does not verify", sig.key_tag))
+            }
+        }
+    };
+
+    match sigs.first() {
+        None => Some(format!("{} {} has no RRSIG", owner, type_name)),
+        Some(_) if sigs.iter().any(|s| fault(s).is_none()) => None,
+        Some(first) => fault(first).map(|f| format!("{} {}: {}", owner, type_name, f)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    /// A resource record in a mock response: owner, type, rdata
+    type Rr = (String, u16, Vec<u8>);
+
+    #[derive(Default, Clone)]
+    struct Reply {
+        rcode: u8,
+        authenticated: bool,
+        truncate_udp: bool,
+        answers: Vec<Rr>,
+        authority: Vec<Rr>,
+        additional: Vec<Rr>,
+    }
+
+    struct Query {
+        name: String,
+        qtype: u16,
+        checking_disabled: bool,
+    }
+
+    type Handler = Arc<dyn Fn(&Query) -> Option<Reply> + Send + Sync>;
+
+    fn wire_name(name: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        }
+        out.push(0);
+        out
+    }
+
+    fn encode(query: &[u8], reply: &Reply, tcp: bool) -> Vec<u8> {
+        let (_, question_end) = read_name(query, 12).unwrap();
+        let truncated = reply.truncate_udp && !tcp;
+
+        let mut flags = 0x8080u16 | reply.rcode as u16;
+        if reply.authenticated {
+            flags |= 0x0020;
+        }
+        if truncated {
+            flags |= 0x0200;
+        }
+
+        let sections: [&[Rr]; 3] = if truncated {
+            [&[], &[], &[]]
+        } else {
+            [&reply.answers, &reply.authority, &reply.additional]
+        };
+
+        let mut msg = query[..2].to_vec();
+        msg.extend_from_slice(&flags.to_be_bytes());
+        msg.extend_from_slice(&1u16.to_be_bytes());
+        for section in sections {
+            msg.extend_from_slice(&(section.len() as u16).to_be_bytes());
+        }
+        msg.extend_from_slice(&query[12..question_end + 4]);
+        for section in sections {
+            for (name, rtype, rdata) in section {
+                msg.extend_from_slice(&wire_name(name));
+                msg.extend_from_slice(&rtype.to_be_bytes());
+                msg.extend_from_slice(&[0, 1, 0, 0, 1, 0x2c]);
+                msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                msg.extend_from_slice(rdata);
+            }
+        }
+        msg
+    }
+
+    fn decode(query: &[u8]) -> Query {
+        let (name, next) = read_name(query, 12).unwrap();
+        Query {
+            name: normalize(&name),
+            qtype: read_u16(query, next).unwrap(),
+            checking_disabled: read_u16(query, 2).unwrap() & 0x0010 != 0,
+        }
+    }
+
+    /// UDP and TCP DNS server on `ip`; a `None` reply drops the query
+    async fn mock_server(ip: &str, port: u16, handler: Handler) -> SocketAddr {
+        let socket = UdpSocket::bind((ip, port)).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        let udp_handler = handler.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                if let Some(reply) = udp_handler(&decode(&buf[..n])) {
+                    socket.send_to(&encode(&buf[..n], &reply, false), peer).await.unwrap();
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).await.unwrap();
+                let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut query).await.unwrap();
+                if let Some(reply) = handler(&decode(&query)) {
+                    let msg = encode(&query, &reply, true);
+                    stream.write_all(&(msg.len() as u16).to_be_bytes()).await.unwrap();
+                    stream.write_all(&msg).await.unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn a(name: &str, ip: [u8; 4]) -> Rr {
+        (name.to_string(), 1, ip.to_vec())
+    }
+
+    fn answer_with(ip: [u8; 4]) -> Handler {
+        Arc::new(move |q: &Query| Some(Reply { answers: vec![a(&q.name, ip)], ..Default::default() }))
+    }
+
+    fn client() -> DnsClient {
+        DnsClient::new().with_timeout(Duration::from_millis(300))
+    }
+
+    #[tokio::test]
+    async fn test_status_distinguishes_servfail_nxdomain_timeout() {
+        let server = mock_server("127.0.0.1", 0, Arc::new(|q: &Query| match q.name.as_str() {
+            "ok.test" => Some(Reply { answers: vec![a("ok.test", [192, 0, 2, 1])], ..Default::default() }),
+            "missing.test" => Some(Reply { rcode: 3, ..Default::default() }),
+            "broken.test" => Some(Reply { rcode: 2, ..Default::default() }),
+            _ => None,
+        })).await;
+        let client = client();
+
+        let ok = client.query("ok.test.", "A", server).await.unwrap();
+        assert_eq!(ok.status, DnsQueryStatus::NoError);
+        assert_eq!(ok.records[0].value, "192.0.2.1");
+        assert!(ok.response_time_ms.is_some());
+
+        let missing = client.query("missing.test", "A", server).await.unwrap();
+        assert_eq!(missing.status, DnsQueryStatus::NxDomain);
+        assert_eq!(missing.rcode_name.as_deref(), Some("NXDOMAIN"));
+
+        let broken = client.query("broken.test", "A", server).await.unwrap();
+        assert_eq!(broken.status, DnsQueryStatus::ServFail);
+        assert_eq!(broken.rcode, Some(2));
+
+        let silent = client.query("silent.test", "A", server).await.unwrap();
+        assert_eq!(silent.status, DnsQueryStatus::Timeout);
+        assert_eq!(silent.rcode, None);
+        assert!(silent.error.is_some());
+
+        assert!(client.query("ok.test", "BOGUS", server).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_answer_retries_over_tcp() {
+        let server = mock_server("127.0.0.1", 0, Arc::new(|q: &Query| Some(Reply {
+            truncate_udp: true,
+            answers: (1..=40).map(|i| a(&q.name, [192, 0, 2, i])).collect(),
+            ..Default::default()
+        }))).await;
+
+        let answer = client().query("big.test", "A", server).await.unwrap();
+        assert!(answer.used_tcp);
+        assert_eq!(answer.records.len(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_propagation_check_groups_answers() {
+        let fresh = answer_with([192, 0, 2, 2]);
+        let stale = answer_with([192, 0, 2, 1]);
+        let servers = vec![
+            mock_server("127.0.0.1", 0, fresh.clone()).await,
+            mock_server("127.0.0.1", 0, fresh).await,
+            mock_server("127.0.0.1", 0, stale).await,
+            mock_server("127.0.0.1", 0, Arc::new(|_: &Query| None)).await,
+        ];
+
+        let report = client().propagation_check("www.test", "a", &servers).await.unwrap();
+        assert!(!report.consistent);
+        assert_eq!(report.answers.len(), 4);
+        assert_eq!(report.unreachable, vec![servers[3]]);
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].values, vec!["A 192.0.2.2"]);
+        assert_eq!(report.groups[0].servers.len(), 2);
+        assert_eq!(report.groups[1].servers, vec![servers[2]]);
+
+        let agreeing = client().propagation_check("www.test", "A", &servers[..2]).await.unwrap();
+        assert!(agreeing.consistent);
+    }
+
+    #[tokio::test]
+    async fn test_query_types_enumerates() {
+        let server = mock_server("127.0.0.1", 0, Arc::new(|q: &Query| Some(match q.qtype {
+            1 => Reply { answers: vec![a(&q.name, [192, 0, 2, 1])], ..Default::default() },
+            16 => Reply { answers: vec![(q.name.clone(), 16, b"\x05hello".to_vec())], ..Default::default() },
+            _ => Reply::default(),
+        }))).await;
+
+        let answers = client().query_types("www.test", &["A", "TXT", "MX"], server).await.unwrap();
+        let types: Vec<&str> = answers.iter().map(|a| a.record_type.as_str()).collect();
+        assert_eq!(types, vec!["A", "TXT", "MX"]);
+        assert_eq!(answers[1].records[0].value, "\"hello\"");
+        assert!(answers[2].records.is_empty());
+        assert_eq!(answers[2].status, DnsQueryStatus::NoError);
+    }
+
+    #[tokio::test]
+    async fn test_reverse_lookup() {
+        assert_eq!(reverse_name("192.0.2.10".parse().unwrap()), "10.2.0.192.in-addr.arpa");
+        assert_eq!(
+            reverse_name("2001:db8::1".parse().unwrap()),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+
+        let server = mock_server("127.0.0.1", 0, Arc::new(|q: &Query| {
+            assert_eq!(q.qtype, 12);
+            Some(Reply { answers: vec![(q.name.clone(), 12, wire_name("host.test"))], ..Default::default() })
+        })).await;
+        let answer = client().reverse_lookup("192.0.2.10".parse().unwrap(), server).await.unwrap();
+        assert_eq!(answer.query, "10.2.0.192.in-addr.arpa");
+        assert_eq!(answer.records[0].value, "host.test");
+    }
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let conf = "# generated\nsearch lan\nnameserver 192.0.2.53\nnameserver fe80::1%eth0\noptions edns0\n";
+        let servers = parse_resolv_conf(conf);
+        assert_eq!(servers, vec![
+            "192.0.2.53:53".parse().unwrap(),
+            "[fe80::1]:53".parse().unwrap(),
+        ]);
+    }
+
+    /// How the mock signed zone is broken
+    #[derive(Clone, Copy, PartialEq)]
+    enum Fault {
+        None,
+        ExpiredAnswer,
+        WrongDs,
+        Unsigned,
+        /// The answer's signature is altered but the resolver still sets AD
+        TamperedSignature,
+    }
+
+    /// An Ed25519 zone signing key
+    struct ZoneKey {
+        pair: Ed25519KeyPair,
+        rdata: Vec<u8>,
+    }
+
+    impl ZoneKey {
+        fn new(seed: u8, ksk: bool) -> Self {
+            let pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+            let flags: u16 = if ksk { 257 } else { 256 };
+            let mut rdata = flags.to_be_bytes().to_vec();
+            rdata.extend_from_slice(&[3, 15]);
+            rdata.extend_from_slice(pair.public_key().as_ref());
+            Self { pair, rdata }
+        }
+
+        fn tag(&self) -> u16 {
+            key_tag(&self.rdata)
+        }
+
+        fn record(&self, owner: &str) -> Rr {
+            (owner.to_string(), TYPE_DNSKEY, self.rdata.clone())
+        }
+
+        fn ds(&self, owner: &str) -> Vec<u8> {
+            let mut rdata = self.tag().to_be_bytes().to_vec();
+            rdata.extend_from_slice(&[15, 2]);
+            rdata.extend_from_slice(&ds_digest(owner, &self.rdata, 2).unwrap());
+            rdata
+        }
+
+        fn anchor(&self) -> TrustAnchor {
+            TrustAnchor::new(self.tag(), 15, 2, ds_digest(".", &self.rdata, 2).unwrap())
+        }
+
+        /// RRSIG over `rrset` by this key of zone `signer`
+        fn sign(&self, rrset: &[Rr], signer: &str, inception: u32, expiration: u32) -> Rr {
+            let (owner, covered, _) = &rrset[0];
+            let labels = owner.split('.').filter(|l| !l.is_empty()).count() as u8;
+            let mut rdata = covered.to_be_bytes().to_vec();
+            rdata.extend_from_slice(&[15, labels, 0, 0, 1, 0x2c]);
+            rdata.extend_from_slice(&expiration.to_be_bytes());
+            rdata.extend_from_slice(&inception.to_be_bytes());
+            rdata.extend_from_slice(&self.tag().to_be_bytes());
+            rdata.extend_from_slice(&wire_name(signer));
+
+            let unsigned = Rrsig::parse(&rdata).unwrap();
+            let rdatas: Vec<&[u8]> = rrset.iter().map(|(_, _, rdata)| rdata.as_slice()).collect();
+            rdata.extend_from_slice(self.pair.sign(&unsigned.signed_data(owner, &rdatas)).as_ref());
+            (owner.clone(), TYPE_RRSIG, rdata)
+        }
+    }
+
+    /// A client trusting the mock root key
+    fn dnssec_client() -> DnsClient {
+        client().with_trust_anchors(vec![ZoneKey::new(1, true).anchor()])
+    }
+
+    /// A resolver over a signed root and `test.` zone
+    fn signed_zones(fault: Fault) -> Handler {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        let (inception, expiration) = (now - 86_400, now + 86_400);
+
+        let root_key = ZoneKey::new(1, true);
+        let test_ksk = ZoneKey::new(2, true);
+        let test_zsk = ZoneKey::new(3, false);
+        let signed = |rrset: Vec<Rr>, key: &ZoneKey, signer: &str| {
+            let sig = key.sign(&rrset, signer, inception, expiration);
+            [rrset, vec![sig]].concat()
+        };
+
+        let mut zones: HashMap<(&str, u16), Vec<Rr>> = HashMap::new();
+        zones.insert((".", TYPE_DNSKEY), signed(vec![root_key.record(".")], &root_key, "."));
+        if fault != Fault::Unsigned {
+            // WrongDs points at a key the zone does not publish
+            let ds = if fault == Fault::WrongDs { ZoneKey::new(9, true).ds("test") } else { test_ksk.ds("test") };
+            zones.insert(("test", TYPE_DS), signed(vec![("test".into(), TYPE_DS, ds)], &root_key, "."));
+        }
+        zones.insert(
+            ("test", TYPE_DNSKEY),
+            signed(vec![test_ksk.record("test"), test_zsk.record("test")], &test_ksk, "test"),
+        );
+
+        let answer = vec![a("www.test", [192, 0, 2, 80])];
+        let mut sig = if fault == Fault::ExpiredAnswer {
+            test_zsk.sign(&answer, "test", now - 10 * 86_400, now - 86_400)
+        } else {
+            test_zsk.sign(&answer, "test", inception, expiration)
+        };
+        if fault == Fault::TamperedSignature {
+            *sig.2.last_mut().unwrap() ^= 0x01;
+        }
+        zones.insert(("www.test", 1), [answer, vec![sig]].concat());
+
+        Arc::new(move |q: &Query| {
+            let failing = matches!(fault, Fault::ExpiredAnswer | Fault::WrongDs);
+            if failing && !q.checking_disabled {
+                return Some(Reply { rcode: RCODE_SERVFAIL, ..Default::default() });
+            }
+            let answers = zones.get(&(q.name.as_str(), q.qtype)).cloned().unwrap_or_default();
+            Some(Reply {
+                authenticated: matches!(fault, Fault::None | Fault::TamperedSignature) && !q.checking_disabled,
+                answers,
+                ..Default::default()
+            })
+        })
+    }
+
+    #[test]
+    fn test_iana_anchor_matches_root_ksk() {
+        // KSK-2017 as published in the root zone
+        let key = "AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=";
+        let mut rdata = vec![1, 1, 3, 8];
+        rdata.extend(base64::engine::general_purpose::STANDARD.decode(key).unwrap());
+        let ksk = DnsKey { tag: key_tag(&rdata), algorithm: 8, rdata };
+
+        assert_eq!(ksk.tag, 20326);
+        assert!(TrustAnchor::iana_root().iter().map(Ds::from).any(|anchor| anchor.matches(".", &ksk)));
+    }
+
+    #[test]
+    fn test_verifies_ecdsa_p256_case_insensitively() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let mut rdata = vec![1, 0, 3, 13];
+        rdata.extend_from_slice(&pair.public_key().as_ref()[1..]);
+        let key = DnsKey { tag: key_tag(&rdata), algorithm: 13, rdata };
+
+        let mut sig = vec![0, 5, 13, 2, 0, 0, 1, 0x2c, 0, 0, 0, 2, 0, 0, 0, 1];
+        sig.extend_from_slice(&key.tag.to_be_bytes());
+        sig.extend_from_slice(&wire_name("example.test"));
+        let target = wire_name("web.example.test");
+        let data = Rrsig::parse(&sig).unwrap().signed_data("www.example.test", &[&target]);
+        sig.extend_from_slice(pair.sign(&rng, &data).unwrap().as_ref());
+        let sig = Rrsig::parse(&sig).unwrap();
+
+        // Owner and target case does not matter; the target itself does
+        let upper = wire_name("WEB.Example.test");
+        assert!(verify_signature(&key, &sig, &sig.signed_data("WWW.example.TEST", &[&upper])));
+        let other = wire_name("mail.example.test");
+        assert!(!verify_signature(&key, &sig, &sig.signed_data("www.example.test", &[&other])));
+    }
+
+    #[tokio::test]
+    async fn test_dnssec_secure_chain() {
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::None)).await;
+
+        let report = dnssec_client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert_eq!(report.status, DnssecStatus::Secure);
+        assert!(report.resolver_validated);
+        assert!(report.failing_link.is_none());
+
+        let links: Vec<(&str, &str)> = report.chain.iter().map(|l| (l.name.as_str(), l.record_type.as_str())).collect();
+        assert_eq!(links, vec![(".", "DNSKEY"), ("test", "DS"), ("test", "DNSKEY"), ("www.test", "A")]);
+        assert!(report.chain.iter().all(|l| l.status == DnssecStatus::Secure), "{:?}", report.chain);
+    }
+
+    #[tokio::test]
+    async fn test_dnssec_bogus_reports_failing_link() {
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::ExpiredAnswer)).await;
+        let report = dnssec_client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert_eq!(report.status, DnssecStatus::Bogus);
+        assert_eq!(report.answer.status, DnsQueryStatus::ServFail);
+        let link = report.failing_link.unwrap();
+        assert_eq!((link.name.as_str(), link.record_type.as_str()), ("www.test", "A"));
+        assert!(link.detail.contains("expired"), "{}", link.detail);
+
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::WrongDs)).await;
+        let report = dnssec_client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert_eq!(report.status, DnssecStatus::Bogus);
+        let link = report.failing_link.unwrap();
+        assert_eq!((link.name.as_str(), link.record_type.as_str()), ("test", "DS"));
+        assert!(link.detail.contains("No DNSKEY"), "{}", link.detail);
+    }
+
+    #[tokio::test]
+    async fn test_dnssec_insecure_delegation() {
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::Unsigned)).await;
+
+        let report = dnssec_client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert_eq!(report.status, DnssecStatus::Insecure);
+        assert_eq!(report.answer.status, DnsQueryStatus::NoError);
+        let link = report.failing_link.unwrap();
+        assert_eq!((link.name.as_str(), link.status), ("test", DnssecStatus::Insecure));
+    }
+
+    #[tokio::test]
+    async fn test_dnssec_tampered_signature_is_bogus_despite_ad() {
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::TamperedSignature)).await;
+
+        let report = dnssec_client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert!(report.resolver_validated);
+        assert_eq!(report.status, DnssecStatus::Bogus);
+        let link = report.failing_link.unwrap();
+        assert_eq!((link.name.as_str(), link.record_type.as_str()), ("www.test", "A"));
+        assert!(link.detail.contains("does not verify"), "{}", link.detail);
+
+        // A root that is not the trust anchor is not trusted, AD or not
+        let server = mock_server("127.0.0.1", 0, signed_zones(Fault::None)).await;
+        let report = client().dnssec_check("www.test", "A", server).await.unwrap();
+        assert!(report.resolver_validated);
+        assert_eq!(report.status, DnssecStatus::Bogus);
+        let link = report.failing_link.unwrap();
+        assert_eq!((link.name.as_str(), link.record_type.as_str()), (".", "DNSKEY"));
+        assert!(link.detail.contains("trust anchor"), "{}", link.detail);
+    }
+
+    #[tokio::test]
+    async fn test_trace_follows_referrals() {
+        // Root, TLD and authoritative servers share a port on different loopback addresses
+        let root = mock_server("127.0.0.1", 0, Arc::new(|_: &Query| Some(Reply {
+            authority: vec![("test".into(), TYPE_NS, wire_name("ns.test"))],
+            additional: vec![a("ns.test", [127, 0, 0, 2])],
+            ..Default::default()
+        }))).await;
+        let port = root.port();
+        mock_server("127.0.0.2", port, Arc::new(|_: &Query| Some(Reply {
+            authority: vec![("example.test".into(), TYPE_NS, wire_name("ns1.example.test"))],
+            additional: vec![a("ns1.example.test", [127, 0, 0, 3])],
+            ..Default::default()
+        }))).await;
+        mock_server("127.0.0.3", port, Arc::new(|q: &Query| Some(match q.name.as_str() {
+            "www.example.test" => Reply { answers: vec![a(&q.name, [192, 0, 2, 8])], ..Default::default() },
+            _ => Reply { rcode: 3, ..Default::default() },
+        }))).await;
+
+        let client = client().with_port(port).with_roots(vec![root.ip()]);
+
+        let trace = client.trace("www.example.test", "A").await.unwrap();
+        assert_eq!(trace.status, DnsQueryStatus::NoError, "{:?}", trace.error);
+        let zones: Vec<&str> = trace.steps.iter().map(|s| s.zone.as_str()).collect();
+        assert_eq!(zones, vec![".", "test", "example.test"]);
+        assert_eq!(trace.steps[1].server_name.as_deref(), Some("ns.test"));
+        assert_eq!(trace.answers[0].value, "192.0.2.8");
+
+        let missing = client.trace("nope.example.test", "A").await.unwrap();
+        assert_eq!(missing.status, DnsQueryStatus::NxDomain);
+        assert!(missing.answers.is_empty());
+    }
+}
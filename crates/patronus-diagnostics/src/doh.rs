@@ -0,0 +1,664 @@
+//! DNS-over-HTTPS (RFC 8484)
+//!
+//! Minimal DNS wire-format encoding and decoding for querying DoH servers
+//! directly, bypassing the system resolver. HTTP status and DNS rcode are
+//! reported separately so transport and resolution failures can be told
+//! apart.
+//!
+//! The wire-format helpers here are shared with the plain UDP/TCP
+//! diagnostics in [`crate::dns`].
+
+use crate::tools::DnsRecord;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::SystemTime;
+
+/// Media type for DNS wire-format messages
+pub const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+/// HTTP method used for the DoH request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DohMethod {
+    /// Query carried base64url-encoded in the `dns` parameter
+    Get,
+    /// Query carried as the request body
+    Post,
+}
+
+/// DoH lookup result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DohLookupResult {
+    pub query: String,
+    pub record_type: String,
+    pub doh_url: String,
+    pub method: DohMethod,
+    /// HTTP status, `None` if no response was received
+    pub http_status: Option<u16>,
+    /// DNS response code, `None` if no DNS message was decoded
+    pub rcode: Option<u8>,
+    pub rcode_name: Option<String>,
+    pub records: Vec<DnsRecord>,
+    pub query_time_ms: Option<f64>,
+    pub error: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+/// Decoded DNS response
+#[derive(Debug, Clone)]
+pub struct DnsResponse {
+    pub id: u16,
+    pub rcode: u8,
+    pub answers: Vec<DnsRecord>,
+}
+
+/// Map a record type mnemonic to its numeric code
+pub fn record_type_code(record_type: &str) -> Option<u16> {
+    match record_type.to_ascii_uppercase().as_str() {
+        "A" => Some(1),
+        "NS" => Some(2),
+        "CNAME" => Some(5),
+        "SOA" => Some(6),
+        "PTR" => Some(12),
+        "MX" => Some(15),
+        "TXT" => Some(16),
+        "AAAA" => Some(28),
+        "SRV" => Some(33),
+        "NAPTR" => Some(35),
+        "DS" => Some(43),
+        "SSHFP" => Some(44),
+        "RRSIG" => Some(46),
+        "NSEC" => Some(47),
+        "DNSKEY" => Some(48),
+        "NSEC3" => Some(50),
+        "TLSA" => Some(52),
+        "SVCB" => Some(64),
+        "HTTPS" => Some(65),
+        "CAA" => Some(257),
+        "ANY" => Some(255),
+        _ => None,
+    }
+}
+
+/// Map a numeric record type to its mnemonic
+pub fn record_type_name(code: u16) -> String {
+    match code {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        35 => "NAPTR".to_string(),
+        41 => "OPT".to_string(),
+        43 => "DS".to_string(),
+        44 => "SSHFP".to_string(),
+        46 => "RRSIG".to_string(),
+        47 => "NSEC".to_string(),
+        48 => "DNSKEY".to_string(),
+        50 => "NSEC3".to_string(),
+        52 => "TLSA".to_string(),
+        64 => "SVCB".to_string(),
+        65 => "HTTPS".to_string(),
+        257 => "CAA".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+/// Name of a DNS response code
+pub fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+/// Build a recursive query for `name`
+///
+/// The ID is zero, as RFC 8484 recommends for cache friendliness.
+pub fn build_query(name: &str, qtype: u16) -> Result<Vec<u8>> {
+    build_query_with(name, qtype, &QueryFlags::default())
+}
+
+/// Header and EDNS options for a query
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QueryFlags {
+    pub id: u16,
+    pub recursion_desired: bool,
+    /// Ask for DNSSEC records via the EDNS0 DO bit
+    pub dnssec_ok: bool,
+    /// Ask a validating resolver to skip validation
+    pub checking_disabled: bool,
+}
+
+impl Default for QueryFlags {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            recursion_desired: true,
+            dnssec_ok: false,
+            checking_disabled: false,
+        }
+    }
+}
+
+/// Advertised EDNS0 UDP payload size (the DNS flag day 2020 value)
+const EDNS_UDP_PAYLOAD: u16 = 1232;
+
+pub(crate) fn build_query_with(name: &str, qtype: u16, flags: &QueryFlags) -> Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(12 + name.len() + 17);
+
+    let mut header = 0u16;
+    if flags.recursion_desired {
+        header |= 0x0100;
+    }
+    if flags.checking_disabled {
+        header |= 0x0010;
+    }
+
+    msg.extend_from_slice(&flags.id.to_be_bytes());
+    msg.extend_from_slice(&header.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    msg.extend_from_slice(&[0, 0, 0, 0]); // AN/NS counts
+    msg.extend_from_slice(&(flags.dnssec_ok as u16).to_be_bytes()); // ARCOUNT
+
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        if label.len() > 63 {
+            return Err(Error::Config(format!("DNS label too long: {}", label)));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes()); // IN
+
+    if flags.dnssec_ok {
+        // OPT pseudo-record: root name, class carries the payload size,
+        // TTL carries extended rcode, version and the DO flag
+        msg.push(0);
+        msg.extend_from_slice(&41u16.to_be_bytes());
+        msg.extend_from_slice(&EDNS_UDP_PAYLOAD.to_be_bytes());
+        msg.extend_from_slice(&[0, 0, 0x80, 0]);
+        msg.extend_from_slice(&0u16.to_be_bytes());
+    }
+
+    Ok(msg)
+}
+
+/// Parse a DNS response message
+pub fn parse_response(msg: &[u8]) -> Result<DnsResponse> {
+    let message = parse_message(msg)?;
+    Ok(DnsResponse {
+        id: message.id,
+        rcode: message.rcode,
+        answers: message.answers.into_iter().map(|r| r.record).collect(),
+    })
+}
+
+/// A resource record along with its raw type and rdata
+#[derive(Debug, Clone)]
+pub(crate) struct WireRecord {
+    pub record: DnsRecord,
+    pub rtype: u16,
+    /// RDATA with any compressed names expanded, so it stands on its own
+    pub rdata: Vec<u8>,
+}
+
+/// A fully decoded message, all sections included
+#[derive(Debug, Clone)]
+pub(crate) struct DnsMessage {
+    pub id: u16,
+    pub flags: u16,
+    pub rcode: u8,
+    pub answers: Vec<WireRecord>,
+    pub authority: Vec<WireRecord>,
+    pub additional: Vec<WireRecord>,
+}
+
+impl DnsMessage {
+    pub fn truncated(&self) -> bool {
+        self.flags & 0x0200 != 0
+    }
+
+    /// AD: the resolver validated the answer with DNSSEC
+    pub fn authenticated_data(&self) -> bool {
+        self.flags & 0x0020 != 0
+    }
+}
+
+pub(crate) fn parse_message(msg: &[u8]) -> Result<DnsMessage> {
+    if msg.len() < 12 {
+        return Err(malformed("header truncated"));
+    }
+
+    let id = read_u16(msg, 0)?;
+    let flags = read_u16(msg, 2)?;
+    let qdcount = read_u16(msg, 4)?;
+    let counts = [read_u16(msg, 6)?, read_u16(msg, 8)?, read_u16(msg, 10)?];
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        let (_, next) = read_name(msg, pos)?;
+        pos = next + 4; // QTYPE + QCLASS
+    }
+
+    let mut sections: [Vec<WireRecord>; 3] = Default::default();
+    for (section, count) in sections.iter_mut().zip(counts) {
+        for _ in 0..count {
+            let (name, next) = read_name(msg, pos)?;
+            let rtype = read_u16(msg, next)?;
+            let ttl = read_u32(msg, next + 4)?;
+            let rdlength = read_u16(msg, next + 8)? as usize;
+            let rdata_start = next + 10;
+            let rdata_end = rdata_start + rdlength;
+            if rdata_end > msg.len() {
+                return Err(malformed("record data truncated"));
+            }
+
+            section.push(WireRecord {
+                record: DnsRecord {
+                    name,
+                    record_type: record_type_name(rtype),
+                    ttl: Some(ttl),
+                    value: format_rdata(msg, rtype, rdata_start, rdata_end)?,
+                },
+                rtype,
+                rdata: expand_rdata(msg, rtype, rdata_start, rdata_end)?,
+            });
+            pos = rdata_end;
+        }
+    }
+    let [answers, authority, additional] = sections;
+
+    Ok(DnsMessage {
+        id,
+        flags,
+        rcode: (flags & 0x000f) as u8,
+        answers,
+        authority,
+        additional,
+    })
+}
+
+fn format_rdata(msg: &[u8], rtype: u16, start: usize, end: usize) -> Result<String> {
+    let rdata = &msg[start..end];
+
+    let value = match rtype {
+        1 if rdata.len() == 4 => Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).to_string(),
+        28 if rdata.len() == 16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(rdata);
+            Ipv6Addr::from(octets).to_string()
+        }
+        2 | 5 | 12 => read_name(msg, start)?.0,
+        15 => {
+            let preference = read_u16(msg, start)?;
+            format!("{} {}", preference, read_name(msg, start + 2)?.0)
+        }
+        16 => {
+            let mut parts = Vec::new();
+            let mut i = 0;
+            while i < rdata.len() {
+                let len = rdata[i] as usize;
+                let part = rdata.get(i + 1..i + 1 + len).ok_or_else(|| malformed("TXT truncated"))?;
+                parts.push(format!("\"{}\"", String::from_utf8_lossy(part)));
+                i += 1 + len;
+            }
+            parts.join(" ")
+        }
+        6 => {
+            let (mname, next) = read_name(msg, start)?;
+            let (rname, next) = read_name(msg, next)?;
+            let fields: Vec<String> = (0..5)
+                .map(|i| read_u32(msg, next + i * 4).map(|v| v.to_string()))
+                .collect::<Result<_>>()?;
+            format!("{} {} {}", mname, rname, fields.join(" "))
+        }
+        33 => {
+            let priority = read_u16(msg, start)?;
+            let weight = read_u16(msg, start + 2)?;
+            let port = read_u16(msg, start + 4)?;
+            format!("{} {} {} {}", priority, weight, port, read_name(msg, start + 6)?.0)
+        }
+        43 if rdata.len() > 4 => format!(
+            "{} {} {} {}",
+            read_u16(msg, start)?, rdata[2], rdata[3], hex(&rdata[4..]).to_ascii_uppercase()
+        ),
+        48 if rdata.len() > 4 => format!(
+            "{} {} {} {}",
+            read_u16(msg, start)?, rdata[2], rdata[3], BASE64.encode(&rdata[4..])
+        ),
+        46 if rdata.len() > 18 => {
+            let (signer, sig_start) = read_name(msg, start + 18)?;
+            format!(
+                "{} {} {} {} {} {} {} {} {}",
+                record_type_name(read_u16(msg, start)?),
+                rdata[2],
+                rdata[3],
+                read_u32(msg, start + 4)?,
+                format_sig_time(read_u32(msg, start + 8)?),
+                format_sig_time(read_u32(msg, start + 12)?),
+                read_u16(msg, start + 16)?,
+                signer,
+                BASE64.encode(msg.get(sig_start..end).unwrap_or_default()),
+            )
+        }
+        47 => {
+            let (next, bitmap_start) = read_name(msg, start)?;
+            let types = msg.get(bitmap_start..end).map(type_bitmap).unwrap_or_default();
+            format!("{} {}", next, types.join(" ")).trim_end().to_string()
+        }
+        257 if rdata.len() > 2 => {
+            let tag_len = rdata[1] as usize;
+            let tag = rdata.get(2..2 + tag_len).ok_or_else(|| malformed("CAA truncated"))?;
+            format!(
+                "{} {} \"{}\"",
+                rdata[0],
+                String::from_utf8_lossy(tag),
+                String::from_utf8_lossy(&rdata[2 + tag_len..])
+            )
+        }
+        _ => hex(rdata),
+    };
+
+    Ok(value)
+}
+
+/// Copy RDATA out of a message, following compression pointers in the
+/// names of the types that may compress them (RFC 3597 section 4)
+fn expand_rdata(msg: &[u8], rtype: u16, start: usize, end: usize) -> Result<Vec<u8>> {
+    // Fixed-size fields before the first name, and whether a second name follows
+    let (prefix, names) = match rtype {
+        2 | 5 | 12 | 39 => (0, 1), // NS, CNAME, PTR, DNAME
+        15 => (2, 1),              // MX
+        6 => (0, 2),               // SOA
+        _ => return Ok(msg[start..end].to_vec()),
+    };
+    if start + prefix > end {
+        return Err(malformed("record data truncated"));
+    }
+
+    let mut out = msg[start..start + prefix].to_vec();
+    let mut pos = start + prefix;
+    for _ in 0..names {
+        pos = expand_name(msg, pos, &mut out)?;
+    }
+    if pos > end {
+        return Err(malformed("record data truncated"));
+    }
+    out.extend_from_slice(&msg[pos..end]);
+    Ok(out)
+}
+
+/// Append the uncompressed wire form of the name at `start`, returning the
+/// offset after it in the message
+fn expand_name(msg: &[u8], start: usize, out: &mut Vec<u8>) -> Result<usize> {
+    let mut pos = start;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| malformed("name truncated"))? as usize;
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if jumps > 32 {
+                return Err(malformed("compression loop"));
+            }
+            pos = (read_u16(msg, pos)? & 0x3fff) as usize;
+            continue;
+        }
+        let label = msg.get(pos..pos + 1 + len).ok_or_else(|| malformed("label truncated"))?;
+        out.extend_from_slice(label);
+        if len == 0 {
+            return Ok(end.unwrap_or(pos + 1));
+        }
+        pos += 1 + len;
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// RRSIG timestamps in presentation form, YYYYMMDDHHmmSS
+fn format_sig_time(secs: u32) -> String {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map(|t| t.format("%Y%m%d%H%M%S").to_string())
+        .unwrap_or_else(|| secs.to_string())
+}
+
+/// Decode an NSEC/NSEC3 type bitmap into mnemonics
+fn type_bitmap(bitmap: &[u8]) -> Vec<String> {
+    let mut types = Vec::new();
+    let mut i = 0;
+    while i + 2 <= bitmap.len() {
+        let window = bitmap[i] as u16;
+        let len = bitmap[i + 1] as usize;
+        let bits = bitmap.get(i + 2..i + 2 + len).unwrap_or_default();
+        for (byte_index, byte) in bits.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push(record_type_name(window * 256 + (byte_index * 8 + bit) as u16));
+                }
+            }
+        }
+        i += 2 + len;
+    }
+    types
+}
+
+/// Read a possibly compressed name, returning it and the offset after it
+pub(crate) fn read_name(msg: &[u8], start: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = start;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| malformed("name truncated"))? as usize;
+
+        if len & 0xc0 == 0xc0 {
+            let pointer = (read_u16(msg, pos)? & 0x3fff) as usize;
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if jumps > 32 {
+                return Err(malformed("compression loop"));
+            }
+            pos = pointer;
+            continue;
+        }
+
+        if len == 0 {
+            end.get_or_insert(pos + 1);
+            break;
+        }
+
+        let label = msg.get(pos + 1..pos + 1 + len).ok_or_else(|| malformed("label truncated"))?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        pos += 1 + len;
+    }
+
+    let name = if labels.is_empty() { ".".to_string() } else { labels.join(".") };
+    Ok((name, end.unwrap_or(pos + 1)))
+}
+
+pub(crate) fn read_u16(msg: &[u8], pos: usize) -> Result<u16> {
+    msg.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| malformed("message truncated"))
+}
+
+pub(crate) fn read_u32(msg: &[u8], pos: usize) -> Result<u32> {
+    msg.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| malformed("message truncated"))
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Network(format!("Malformed DNS message: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::DiagnosticTools;
+    use base64::Engine;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer the query with one A record, using a compression pointer to the question
+    fn answer_a(query: &[u8], rcode: u8, addr: Ipv4Addr) -> Vec<u8> {
+        let mut resp = query.to_vec();
+        resp[2] = 0x81; // QR + RD
+        resp[3] = 0x80 | rcode; // RA + rcode
+        if rcode != 0 {
+            return resp;
+        }
+        resp[7] = 1; // ANCOUNT
+        resp.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0x0e, 0x10, 0, 4]);
+        resp.extend_from_slice(&addr.octets());
+        resp
+    }
+
+    /// Serve a single DoH request, answering with `respond(query)`
+    async fn mock_doh_server(
+        status: u16,
+        respond: fn(&[u8]) -> Vec<u8>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+
+            let header_end = loop {
+                let n = sock.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i + 4;
+                }
+            };
+
+            let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+            let query = if head.starts_with("GET") {
+                let param = head.split("dns=").nth(1).unwrap().split([' ', '&']).next().unwrap();
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(param).unwrap()
+            } else {
+                let len: usize = head.lines()
+                    .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                    .unwrap();
+                while buf.len() < header_end + len {
+                    let n = sock.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                buf[header_end..header_end + len].to_vec()
+            };
+
+            let body = respond(&query);
+            let head = format!(
+                "HTTP/1.1 {} X\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                status, DNS_MESSAGE_CONTENT_TYPE, body.len()
+            );
+            sock.write_all(head.as_bytes()).await.unwrap();
+            sock.write_all(&body).await.unwrap();
+        });
+
+        format!("http://{}/dns-query", addr)
+    }
+
+    #[test]
+    fn test_query_encoding() {
+        let query = build_query("example.com", 28).unwrap();
+        assert_eq!(&query[..4], &[0, 0, 1, 0]);
+        assert_eq!(&query[12..25], b"\x07example\x03com\x00");
+        assert_eq!(&query[25..], &[0, 28, 0, 1]);
+    }
+
+    #[test]
+    fn test_parse_compressed_answers() {
+        let query = build_query("example.com", 1).unwrap();
+        let resp = parse_response(&answer_a(&query, 0, Ipv4Addr::new(192, 0, 2, 1))).unwrap();
+
+        assert_eq!(resp.rcode, 0);
+        assert_eq!(resp.answers.len(), 1);
+        assert_eq!(resp.answers[0].name, "example.com");
+        assert_eq!(resp.answers[0].record_type, "A");
+        assert_eq!(resp.answers[0].ttl, Some(3600));
+        assert_eq!(resp.answers[0].value, "192.0.2.1");
+    }
+
+    #[test]
+    fn test_compressed_rdata_names_are_expanded() {
+        let query = build_query("www.example.com", 5).unwrap();
+        let mut resp = query.clone();
+        resp[2] = 0x81;
+        resp[3] = 0x80;
+        resp[7] = 1;
+        // CNAME web.<pointer to example.com in the question>
+        resp.extend_from_slice(&[0xc0, 0x0c, 0, 5, 0, 1, 0, 0, 0x0e, 0x10, 0, 6]);
+        resp.extend_from_slice(&[3, b'w', b'e', b'b', 0xc0, 0x10]);
+
+        let message = parse_message(&resp).unwrap();
+        assert_eq!(message.answers[0].record.value, "web.example.com");
+        assert_eq!(message.answers[0].rdata, b"\x03web\x07example\x03com\x00");
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated() {
+        assert!(parse_response(&[0, 0, 0x81]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_doh_get_lookup() {
+        let url = mock_doh_server(200, |q| answer_a(q, 0, Ipv4Addr::new(192, 0, 2, 7))).await;
+
+        let result = DiagnosticTools::doh_lookup("example.com", "A", &url).await.unwrap();
+
+        assert_eq!(result.method, DohMethod::Get);
+        assert_eq!(result.http_status, Some(200));
+        assert_eq!(result.rcode, Some(0));
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(result.records[0].value, "192.0.2.7");
+        assert!(result.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_doh_post_nxdomain() {
+        let url = mock_doh_server(200, |q| answer_a(q, 3, Ipv4Addr::UNSPECIFIED)).await;
+
+        let result = DiagnosticTools::doh_lookup_with_method("missing.example", "A", &url, DohMethod::Post)
+            .await
+            .unwrap();
+
+        assert_eq!(result.http_status, Some(200));
+        assert_eq!(result.rcode, Some(3));
+        assert_eq!(result.rcode_name.as_deref(), Some("NXDOMAIN"));
+        assert!(result.records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_doh_http_error_has_no_rcode() {
+        let url = mock_doh_server(502, |_| Vec::new()).await;
+
+        let result = DiagnosticTools::doh_lookup("example.com", "A", &url).await.unwrap();
+
+        assert_eq!(result.http_status, Some(502));
+        assert_eq!(result.rcode, None);
+        assert!(result.error.is_some());
+    }
+}
@@ -3,7 +3,17 @@
 //! Network troubleshooting and diagnostic utilities.
 
 pub mod packet_capture;
+pub mod pcapng;
 pub mod tools;
+pub mod mtr;
+pub mod doh;
+pub mod dns;
+pub mod ping;
+pub mod live_capture;
+pub mod ring_capture;
+pub mod rotating_capture;
+pub mod throughput;
+pub mod neighbor_monitor;
 
 pub use packet_capture::{
     PacketCaptureManager, CaptureConfig, CaptureSession, CaptureStats,
@@ -15,3 +25,34 @@ pub use tools::{
     DnsLookupResult, DnsRecord, PortTestResult, ArpEntry, NdpEntry,
     RouteEntry, SocketEntry, FirewallState, SystemActivity, ProcessInfo,
 };
+
+pub use doh::{DohLookupResult, DohMethod};
+
+pub use dns::{
+    DnsAnswerGroup, DnsClient, DnsPropagationReport, DnsQueryStatus, DnsServerAnswer,
+    DnsTraceResult, DnsTraceStep, DnssecLink, DnssecReport, DnssecStatus, TrustAnchor,
+    COMMON_RECORD_TYPES,
+};
+
+pub use mtr::{MtrAccumulator, MtrHopAddress, MtrHopStats, MtrOptions, MtrProbe, MtrProtocol, MtrReport};
+
+pub use ping::{PingAccumulator, PingStats};
+
+pub use pcapng::{PcapNgCapture, PcapNgInterface, PcapNgWriter};
+
+pub use live_capture::{LiveCapture, LiveCaptureOptions, LivePacket, RateSampler};
+
+pub use ring_capture::{CapturedPacket, PacketRingBuffer, PcapStreamParser, RingCapture};
+
+pub use rotating_capture::{CaptureEvent, DiskQuota, RotatingWriter, RotationPolicy, RotationSummary};
+
+pub use throughput::{
+    ThroughputDirection, ThroughputHistory, ThroughputInterval, ThroughputOptions,
+    ThroughputProtocol, ThroughputResult, ThroughputServer, ThroughputStream, ThroughputTest,
+    UdpStats, DEFAULT_THROUGHPUT_PORT,
+};
+
+pub use neighbor_monitor::{
+    BindingRecord, NeighborAlert, NeighborEvent, NeighborIssue, NeighborMonitor,
+    NeighborMonitorConfig, NeighborObservation,
+};
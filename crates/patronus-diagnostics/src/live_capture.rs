@@ -0,0 +1,506 @@
+//! Live Packet Capture
+//!
+//! Streams packets as they are captured, decoded to one-line
+//! [`PacketDetails`], for live views in the web UI. Filtering happens in
+//! the kernel via the BPF filter, the forwarded rate is capped by evenly
+//! sampling rather than queueing, and captured bytes are only included
+//! when the caller asks for them.
+
+use crate::packet_capture::{CaptureConfig, PacketDetails};
+use crate::ring_capture::{self, CapturedPacket, PcapStreamParser};
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::process::Child;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Snapshot length used when payloads are not wanted; enough for headers
+const HEADER_SNAPLEN: u32 = 128;
+
+/// Packets decoded ahead of a slow consumer before new ones are dropped
+const QUEUE_LEN: usize = 256;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_RAW_ALT: u32 = 12;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// Options for a live capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveCaptureOptions {
+    /// Packets forwarded per second; beyond this the stream is sampled
+    pub max_packets_per_sec: u32,
+    /// Include captured bytes as hex
+    pub include_payload: bool,
+    /// Longest hex payload, in captured bytes
+    pub max_payload_bytes: usize,
+}
+
+impl Default for LiveCaptureOptions {
+    fn default() -> Self {
+        Self {
+            max_packets_per_sec: 50,
+            include_payload: false,
+            max_payload_bytes: 256,
+        }
+    }
+}
+
+/// A decoded packet from a live capture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LivePacket {
+    pub packet: PacketDetails,
+    /// One in this many packets is currently being forwarded
+    pub sample_rate: u32,
+}
+
+/// Caps packets per second by forwarding every Nth packet
+///
+/// N is set each second from the previous second's rate, so a flood is
+/// thinned evenly instead of only its first packets being shown. Time is
+/// taken from packet timestamps.
+#[derive(Debug, Clone)]
+pub struct RateSampler {
+    max_per_sec: u32,
+    second: u32,
+    seen: u32,
+    forwarded: u32,
+    stride: u32,
+}
+
+impl RateSampler {
+    pub fn new(max_per_sec: u32) -> Self {
+        Self {
+            max_per_sec: max_per_sec.max(1),
+            second: 0,
+            seen: 0,
+            forwarded: 0,
+            stride: 1,
+        }
+    }
+
+    /// Whether to forward a packet seen at `ts_sec`, and the sample rate if so
+    pub fn admit(&mut self, ts_sec: u32) -> Option<u32> {
+        if ts_sec != self.second {
+            self.stride = if ts_sec == self.second.wrapping_add(1) {
+                self.seen.div_ceil(self.max_per_sec).max(1)
+            } else {
+                1
+            };
+            self.second = ts_sec;
+            self.seen = 0;
+            self.forwarded = 0;
+        }
+
+        self.seen += 1;
+        if self.forwarded >= self.max_per_sec || !(self.seen - 1).is_multiple_of(self.stride) {
+            return None;
+        }
+
+        self.forwarded += 1;
+        Some(self.stride)
+    }
+}
+
+/// A running live capture
+///
+/// Dropping it kills the capture process.
+pub struct LiveCapture {
+    pub id: String,
+    pub interface: String,
+    packets: mpsc::Receiver<LivePacket>,
+    dropped: Arc<AtomicU64>,
+    process: Option<Child>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl LiveCapture {
+    pub(crate) fn start(mut config: CaptureConfig, options: LiveCaptureOptions) -> Result<Self> {
+        if !options.include_payload {
+            // Payload bytes never leave the kernel
+            config.snaplen = config.snaplen.min(HEADER_SNAPLEN);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        tracing::info!("Starting live capture {} on {} (filter {:?}, {} pps)",
+            id, config.interface, config.filter, options.max_packets_per_sec);
+
+        let mut child = ring_capture::stream_command(&config, None)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let mut stdout = child.stdout.take()
+            .ok_or_else(|| Error::Network("Capture process has no stdout".to_string()))?;
+
+        let (tx, packets) = mpsc::channel(QUEUE_LEN);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let reader_dropped = dropped.clone();
+        let reader_id = id.clone();
+
+        let reader = tokio::spawn(async move {
+            let mut parser = PcapStreamParser::new();
+            let mut decoder = LiveDecoder::new(options);
+            let mut chunk = vec![0u8; 64 * 1024];
+
+            loop {
+                let n = match stdout.read(&mut chunk).await {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) => {
+                        tracing::warn!("Live capture {} read failed: {}", reader_id, e);
+                        break;
+                    }
+                };
+
+                let captured = match parser.feed(&chunk[..n]) {
+                    Ok(captured) => captured,
+                    Err(e) => {
+                        tracing::warn!("Live capture {} stopped: {}", reader_id, e);
+                        break;
+                    }
+                };
+                let linktype = parser.linktype().unwrap_or(LINKTYPE_ETHERNET);
+
+                for packet in decoder.process(linktype, &captured) {
+                    match tx.try_send(packet) {
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            reader_dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => return,
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            id,
+            interface: config.interface,
+            packets,
+            dropped,
+            process: Some(child),
+            reader: Some(reader),
+        })
+    }
+
+    /// Next forwarded packet; `None` once the capture has ended
+    pub async fn recv(&mut self) -> Option<LivePacket> {
+        self.packets.recv().await
+    }
+
+    /// Packets decoded but dropped because the consumer fell behind
+    pub fn packets_dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop the capture process and wait for it to exit
+    pub async fn stop(mut self) -> Result<()> {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+        if let Some(mut process) = self.process.take() {
+            let _ = process.start_kill();
+            let _ = process.wait().await?;
+        }
+        tracing::info!("Stopped live capture {}", self.id);
+        Ok(())
+    }
+}
+
+impl Drop for LiveCapture {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+        // kill_on_drop stops the capture process with it
+    }
+}
+
+/// Samples and decodes a captured packet stream
+struct LiveDecoder {
+    options: LiveCaptureOptions,
+    sampler: RateSampler,
+    packets_seen: u32,
+}
+
+impl LiveDecoder {
+    fn new(options: LiveCaptureOptions) -> Self {
+        Self {
+            sampler: RateSampler::new(options.max_packets_per_sec),
+            options,
+            packets_seen: 0,
+        }
+    }
+
+    fn process(&mut self, linktype: u32, captured: &[CapturedPacket]) -> Vec<LivePacket> {
+        captured.iter()
+            .filter_map(|packet| {
+                self.packets_seen = self.packets_seen.wrapping_add(1);
+                let sample_rate = self.sampler.admit(packet.ts_sec)?;
+                let mut details = decode_packet(linktype, packet, self.packets_seen);
+                if self.options.include_payload {
+                    details.payload_hex = Some(hex(&packet.data[..packet.data.len().min(self.options.max_payload_bytes)]));
+                }
+                Some(LivePacket { packet: details, sample_rate })
+            })
+            .collect()
+    }
+}
+
+/// Decode link, network and transport headers into a one-line summary
+///
+/// The payload is never included; see [`LiveCaptureOptions::include_payload`].
+pub fn decode_packet(linktype: u32, packet: &CapturedPacket, packet_number: u32) -> PacketDetails {
+    let timestamp = chrono::DateTime::from_timestamp(packet.ts_sec as i64, packet.ts_nsec);
+    let decoded = network_layer(linktype, &packet.data)
+        .and_then(|(ethertype, data)| decode_network(ethertype, data));
+
+    let (protocol, source, destination, info) = match decoded {
+        Some(d) => (d.protocol, Some(d.source), Some(d.destination), d.info),
+        None => ("Unknown".to_string(), None, None, String::new()),
+    };
+
+    let mut summary = protocol.clone();
+    if let (Some(src), Some(dst)) = (&source, &destination) {
+        let _ = write!(summary, " {} -> {}", src, dst);
+    }
+    if !info.is_empty() {
+        let _ = write!(summary, " {}", info);
+    }
+    let _ = write!(summary, " {} bytes", packet.orig_len);
+
+    PacketDetails {
+        packet_number,
+        details: summary,
+        timestamp,
+        source,
+        destination,
+        protocol: Some(protocol),
+        payload_hex: None,
+    }
+}
+
+struct Decoded {
+    protocol: String,
+    source: String,
+    destination: String,
+    info: String,
+}
+
+/// Ethertype and network-layer bytes of a frame
+fn network_layer(linktype: u32, data: &[u8]) -> Option<(u16, &[u8])> {
+    match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = be16(data, 12)?;
+            let mut offset = 14;
+            while ethertype == ETHERTYPE_VLAN {
+                ethertype = be16(data, offset + 2)?;
+                offset += 4;
+            }
+            Some((ethertype, data.get(offset..)?))
+        }
+        LINKTYPE_LINUX_SLL => Some((be16(data, 14)?, data.get(16..)?)),
+        LINKTYPE_LINUX_SLL2 => Some((be16(data, 0)?, data.get(20..)?)),
+        LINKTYPE_RAW | LINKTYPE_RAW_ALT => match data.first()? >> 4 {
+            4 => Some((ETHERTYPE_IPV4, data)),
+            6 => Some((ETHERTYPE_IPV6, data)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn decode_network(ethertype: u16, data: &[u8]) -> Option<Decoded> {
+    match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header_len = ((*data.first()? & 0x0f) as usize) * 4;
+            let src = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data.get(12..16)?).ok()?));
+            let dst = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data.get(16..20)?).ok()?));
+            Some(decode_transport(*data.get(9)?, src, dst, data.get(header_len..).unwrap_or(&[])))
+        }
+        ETHERTYPE_IPV6 => {
+            let src = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data.get(8..24)?).ok()?));
+            let dst = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data.get(24..40)?).ok()?));
+            Some(decode_transport(*data.get(6)?, src, dst, data.get(40..).unwrap_or(&[])))
+        }
+        ETHERTYPE_ARP => {
+            let op = be16(data, 6)?;
+            let sender = Ipv4Addr::from(<[u8; 4]>::try_from(data.get(14..18)?).ok()?);
+            let target = Ipv4Addr::from(<[u8; 4]>::try_from(data.get(24..28)?).ok()?);
+            let info = match op {
+                1 => format!("who-has {} tell {}", target, sender),
+                2 => format!("{} is-at {}", sender, mac(data.get(8..14)?)),
+                _ => format!("op {}", op),
+            };
+            Some(Decoded {
+                protocol: "ARP".to_string(),
+                source: sender.to_string(),
+                destination: target.to_string(),
+                info,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn decode_transport(protocol: u8, src: IpAddr, dst: IpAddr, data: &[u8]) -> Decoded {
+    let ports = (be16(data, 0), be16(data, 2));
+    let endpoint = |ip: IpAddr, port: Option<u16>| match (ip, port) {
+        (IpAddr::V4(ip), Some(port)) => format!("{}:{}", ip, port),
+        (IpAddr::V6(ip), Some(port)) => format!("[{}]:{}", ip, port),
+        (ip, None) => ip.to_string(),
+    };
+
+    let (name, with_ports, info) = match protocol {
+        6 => {
+            let flags = data.get(13).map(|f| tcp_flags(*f)).unwrap_or_default();
+            ("TCP", true, if flags.is_empty() { flags } else { format!("[{}]", flags) })
+        }
+        17 => ("UDP", true, String::new()),
+        1 | 58 => {
+            let name = if protocol == 1 { "ICMP" } else { "ICMPv6" };
+            let info = match (data.first(), data.get(1)) {
+                (Some(kind), Some(code)) => format!("type {} code {}", kind, code),
+                _ => String::new(),
+            };
+            (name, false, info)
+        }
+        other => return Decoded {
+            protocol: format!("IP proto {}", other),
+            source: src.to_string(),
+            destination: dst.to_string(),
+            info: String::new(),
+        },
+    };
+
+    let (sport, dport) = if with_ports { ports } else { (None, None) };
+    Decoded {
+        protocol: name.to_string(),
+        source: endpoint(src, sport),
+        destination: endpoint(dst, dport),
+        info,
+    }
+}
+
+fn tcp_flags(flags: u8) -> String {
+    const NAMES: [(u8, &str); 6] = [
+        (0x02, "SYN"), (0x10, "ACK"), (0x01, "FIN"), (0x04, "RST"), (0x08, "PSH"), (0x20, "URG"),
+    ];
+    NAMES.iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn mac(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{:02x}", b);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ethernet + IPv4 + TCP SYN from 10.0.0.1:51000 to 192.0.2.7:443 with a payload
+    fn tcp_syn(ts_sec: u32) -> CapturedPacket {
+        let mut data = vec![0u8; 12];
+        data.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let mut ip = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 192, 0, 2, 7];
+        ip.extend_from_slice(&51000u16.to_be_bytes());
+        ip.extend_from_slice(&443u16.to_be_bytes());
+        ip.extend_from_slice(&[0; 9]);
+        ip.push(0x02); // SYN
+        ip.extend_from_slice(&[0; 6]);
+        ip.extend_from_slice(b"secret");
+        data.extend_from_slice(&ip);
+
+        CapturedPacket { ts_sec, ts_nsec: 5_000, orig_len: data.len() as u32, data }
+    }
+
+    #[test]
+    fn test_decode_tcp_summary() {
+        let details = decode_packet(LINKTYPE_ETHERNET, &tcp_syn(1_700_000_000), 7);
+
+        assert_eq!(details.packet_number, 7);
+        assert_eq!(details.protocol.as_deref(), Some("TCP"));
+        assert_eq!(details.source.as_deref(), Some("10.0.0.1:51000"));
+        assert_eq!(details.destination.as_deref(), Some("192.0.2.7:443"));
+        assert_eq!(details.details, "TCP 10.0.0.1:51000 -> 192.0.2.7:443 [SYN] 60 bytes");
+        assert_eq!(details.timestamp.unwrap().timestamp_subsec_nanos(), 5_000);
+        assert!(details.payload_hex.is_none());
+    }
+
+    #[test]
+    fn test_payload_only_when_requested() {
+        let redacted = LiveDecoder::new(LiveCaptureOptions::default())
+            .process(LINKTYPE_ETHERNET, &[tcp_syn(0)]);
+        assert!(redacted[0].packet.payload_hex.is_none());
+
+        let options = LiveCaptureOptions { include_payload: true, ..Default::default() };
+        let full = LiveDecoder::new(options).process(LINKTYPE_ETHERNET, &[tcp_syn(0)]);
+        let payload = full[0].packet.payload_hex.as_deref().unwrap();
+        assert!(payload.ends_with(&hex(b"secret")));
+    }
+
+    #[test]
+    fn test_sampler_thins_floods_evenly() {
+        let mut sampler = RateSampler::new(100);
+
+        // Under the limit everything passes
+        let passed = (0..80).filter(|_| sampler.admit(10).is_some()).count();
+        assert_eq!(passed, 80);
+
+        // A 10k pps flood is capped at the limit for its first second...
+        let passed = (0..10_000).filter(|_| sampler.admit(11).is_some()).count();
+        assert_eq!(passed, 100);
+
+        // ...then sampled 1-in-100 across the whole next second
+        let admitted: Vec<usize> = (0..10_000).filter(|_| sampler.admit(12).is_some()).collect();
+        assert_eq!(admitted.len(), 100);
+        assert_eq!(admitted[1] - admitted[0], 100);
+        assert!(*admitted.last().unwrap() >= 9_900);
+        assert_eq!(sampler.clone().admit(13), Some(100));
+        assert_eq!(sampler.admit(12), None);
+
+        // After a quiet gap the stride resets
+        assert_eq!(sampler.admit(20), Some(1));
+    }
+
+    #[test]
+    fn test_decoder_numbers_every_packet() {
+        let options = LiveCaptureOptions { max_packets_per_sec: 2, ..Default::default() };
+        let mut decoder = LiveDecoder::new(options);
+        let packets: Vec<_> = (0..5).map(|_| tcp_syn(100)).collect();
+
+        let forwarded = decoder.process(LINKTYPE_ETHERNET, &packets);
+        let numbers: Vec<u32> = forwarded.iter().map(|p| p.packet.packet_number).collect();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+}
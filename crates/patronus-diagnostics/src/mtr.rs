@@ -0,0 +1,562 @@
+//! MTR - Continuous Path Statistics
+//!
+//! Accumulates per-hop loss and RTT statistics over repeated traceroute
+//! rounds, in the style of `mtr`. Hops that never answer are shown as `*`
+//! and every unanswered probe counts as lost. Routers that answer from
+//! different addresses across rounds (ECMP) are listed per address.
+
+use crate::tools::TracerouteHop;
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+/// Probe packet type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MtrProtocol {
+    /// ICMP echo requests
+    #[default]
+    Icmp,
+    /// UDP datagrams to high ports
+    Udp,
+}
+
+/// Options for an MTR run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtrOptions {
+    pub protocol: MtrProtocol,
+    /// Resolve hostnames to IPv6 rather than IPv4 addresses
+    pub ipv6: bool,
+    /// Number of probing rounds; ignored when `duration` is set
+    pub count: u32,
+    /// Keep probing for this long instead of a fixed number of rounds
+    pub duration: Option<Duration>,
+    /// Delay between the start of consecutive rounds
+    pub interval: Duration,
+    pub max_hops: u32,
+    /// How long to wait for each probe reply
+    pub timeout: Duration,
+}
+
+impl Default for MtrOptions {
+    fn default() -> Self {
+        Self {
+            protocol: MtrProtocol::Icmp,
+            ipv6: false,
+            count: 10,
+            duration: None,
+            interval: Duration::from_secs(1),
+            max_hops: 30,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl MtrOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_hops == 0 || self.max_hops > 255 {
+            return Err(Error::Config(format!("MTR max hops must be 1-255, got {}", self.max_hops)));
+        }
+        if self.timeout.is_zero() {
+            return Err(Error::Config("MTR probe timeout must be non-zero".to_string()));
+        }
+        if self.duration.is_some_and(|d| d.is_zero()) {
+            return Err(Error::Config("MTR duration must be non-zero".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a single probe
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtrProbe {
+    pub hop_number: u32,
+    /// Address that answered, if any
+    pub ip_address: Option<IpAddr>,
+    pub rtt_ms: Option<f64>,
+}
+
+/// A responding address at a hop, with how many replies it sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtrHopAddress {
+    pub ip_address: IpAddr,
+    pub received: u32,
+}
+
+/// Accumulated statistics for a single hop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtrHopStats {
+    pub hop_number: u32,
+    /// Hostname or address of the hop, `*` if it has never responded
+    pub host: String,
+    /// Most recent responding address
+    pub ip_address: Option<IpAddr>,
+    /// Every address seen at this hop, in order of first response
+    pub addresses: Vec<MtrHopAddress>,
+    pub sent: u32,
+    pub received: u32,
+    pub lost: u32,
+    pub loss_pct: f32,
+    pub last_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub best_rtt_ms: Option<f64>,
+    pub worst_rtt_ms: Option<f64>,
+    pub stddev_rtt_ms: Option<f64>,
+    /// Running sum of squared deviations from the mean (Welford)
+    #[serde(skip)]
+    rtt_m2: f64,
+}
+
+impl MtrHopStats {
+    fn new(hop_number: u32) -> Self {
+        Self {
+            hop_number,
+            host: "*".to_string(),
+            ip_address: None,
+            addresses: Vec::new(),
+            sent: 0,
+            received: 0,
+            lost: 0,
+            loss_pct: 0.0,
+            last_rtt_ms: None,
+            avg_rtt_ms: None,
+            best_rtt_ms: None,
+            worst_rtt_ms: None,
+            stddev_rtt_ms: None,
+            rtt_m2: 0.0,
+        }
+    }
+
+    /// Record the outcome of a single probe
+    pub fn record_probe(&mut self, rtt_ms: Option<f64>) {
+        self.sent += 1;
+
+        if let Some(rtt) = rtt_ms {
+            self.received += 1;
+            let mean = self.avg_rtt_ms.unwrap_or(0.0);
+            let delta = rtt - mean;
+            let mean = mean + delta / self.received as f64;
+            self.rtt_m2 += delta * (rtt - mean);
+
+            self.last_rtt_ms = Some(rtt);
+            self.best_rtt_ms = Some(self.best_rtt_ms.map_or(rtt, |b| b.min(rtt)));
+            self.worst_rtt_ms = Some(self.worst_rtt_ms.map_or(rtt, |w| w.max(rtt)));
+            self.avg_rtt_ms = Some(mean);
+            self.stddev_rtt_ms = Some((self.rtt_m2 / self.received as f64).sqrt());
+        }
+
+        self.lost = self.sent - self.received;
+        self.loss_pct = (self.lost as f32 / self.sent as f32) * 100.0;
+    }
+
+    fn record_address(&mut self, ip: IpAddr, hostname: Option<&str>, replied: bool) {
+        self.ip_address = Some(ip);
+        self.host = hostname.map_or_else(|| ip.to_string(), str::to_string);
+
+        let entry = match self.addresses.iter_mut().position(|a| a.ip_address == ip) {
+            Some(i) => &mut self.addresses[i],
+            None => {
+                self.addresses.push(MtrHopAddress { ip_address: ip, received: 0 });
+                self.addresses.last_mut().unwrap()
+            }
+        };
+        if replied {
+            entry.received += 1;
+        }
+    }
+}
+
+/// Snapshot of MTR statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtrReport {
+    pub target: String,
+    /// Address being probed, once resolved
+    pub destination: Option<IpAddr>,
+    /// Number of completed probing rounds
+    pub rounds: u32,
+    pub hops: Vec<MtrHopStats>,
+    /// Set on the summary emitted when the stream ends
+    pub is_final: bool,
+    pub timestamp: SystemTime,
+}
+
+/// Aggregates traceroute rounds into running per-hop statistics
+#[derive(Debug, Clone)]
+pub struct MtrAccumulator {
+    target: String,
+    destination: Option<IpAddr>,
+    destination_hop: Option<u32>,
+    rounds: u32,
+    hops: BTreeMap<u32, MtrHopStats>,
+}
+
+impl MtrAccumulator {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            destination: None,
+            destination_hop: None,
+            rounds: 0,
+            hops: BTreeMap::new(),
+        }
+    }
+
+    /// Set the resolved destination, so hops past it can be dropped
+    pub fn with_destination(mut self, destination: IpAddr) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    /// Fold one traceroute round into the statistics
+    pub fn record_round(&mut self, hops: &[TracerouteHop]) {
+        self.rounds += 1;
+
+        for hop in hops {
+            let stats = self.hop_mut(hop.hop_number);
+
+            if let Some(ip) = hop.ip_address {
+                let replied = hop.rtt_ms.iter().any(Option::is_some);
+                stats.record_address(ip, hop.hostname.as_deref(), replied);
+            }
+
+            if hop.rtt_ms.is_empty() {
+                // No probe results at all for this hop is a lost probe
+                stats.record_probe(None);
+            }
+            for rtt in &hop.rtt_ms {
+                stats.record_probe(*rtt);
+            }
+
+            if let Some(ip) = hop.ip_address {
+                self.note_destination(hop.hop_number, ip);
+            }
+        }
+    }
+
+    /// Fold one round of individual probes into the statistics
+    ///
+    /// Unlike [`record_round`](Self::record_round), each probe carries its
+    /// own responding address, so ECMP paths are tracked per reply.
+    /// `probed_hops` is how many hops the round sent probes to; those up to
+    /// it (or to the destination) without a result count as sent and lost,
+    /// so a round that failed or timed out loses every probe.
+    pub fn record_probes(&mut self, probes: &[MtrProbe], probed_hops: u32) {
+        self.rounds += 1;
+
+        for probe in probes {
+            let stats = self.hop_mut(probe.hop_number);
+
+            if let Some(ip) = probe.ip_address {
+                stats.record_address(ip, None, probe.rtt_ms.is_some());
+            }
+            stats.record_probe(probe.rtt_ms);
+
+            if let Some(ip) = probe.ip_address {
+                self.note_destination(probe.hop_number, ip);
+            }
+        }
+
+        let last_hop = self.destination_hop.map_or(probed_hops, |d| d.min(probed_hops));
+        for hop_number in 1..=last_hop {
+            if !probes.iter().any(|p| p.hop_number == hop_number) {
+                self.hop_mut(hop_number).record_probe(None);
+            }
+        }
+    }
+
+    fn hop_mut(&mut self, hop_number: u32) -> &mut MtrHopStats {
+        self.hops
+            .entry(hop_number)
+            .or_insert_with(|| MtrHopStats::new(hop_number))
+    }
+
+    fn note_destination(&mut self, hop_number: u32, ip: IpAddr) {
+        if self.destination == Some(ip) {
+            self.destination_hop = Some(self.destination_hop.map_or(hop_number, |h| h.min(hop_number)));
+        }
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    /// Hop at which the destination answered, once it has
+    pub fn destination_hop(&self) -> Option<u32> {
+        self.destination_hop
+    }
+
+    /// Current statistics
+    ///
+    /// Hops beyond the one where the destination answered are left out;
+    /// they only appear when a round failed to reach it.
+    pub fn report(&self, is_final: bool) -> MtrReport {
+        let last_hop = self.destination_hop.unwrap_or(u32::MAX);
+
+        MtrReport {
+            target: self.target.clone(),
+            destination: self.destination,
+            rounds: self.rounds,
+            hops: self.hops.range(..=last_hop).map(|(_, h)| h.clone()).collect(),
+            is_final,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+/// Parse `traceroute -n` output into individual probes
+///
+/// Handles several addresses on one hop line, e.g.
+/// ` 3  10.0.0.1  1.2 ms  10.0.0.2  1.5 ms *`, where each RTT belongs to
+/// the address printed before it. Annotations such as `!H` are ignored.
+pub(crate) fn parse_probe_output(output: &str) -> Vec<MtrProbe> {
+    let mut probes = Vec::new();
+
+    for line in output.lines() {
+        let mut parts = line.split_whitespace().peekable();
+        let Some(hop_number) = parts.next().and_then(|s| s.parse::<u32>().ok()) else {
+            // Header line or wrapped output
+            continue;
+        };
+
+        let mut current: Option<IpAddr> = None;
+        while let Some(part) = parts.next() {
+            if part == "*" {
+                probes.push(MtrProbe { hop_number, ip_address: None, rtt_ms: None });
+            } else if let Ok(ip) = part.trim_matches(|c| c == '(' || c == ')').parse::<IpAddr>() {
+                current = Some(ip);
+            } else if let Some(rtt) = parse_rtt(part, parts.peek().copied()) {
+                if parts.peek() == Some(&"ms") {
+                    parts.next();
+                }
+                probes.push(MtrProbe { hop_number, ip_address: current, rtt_ms: Some(rtt) });
+            }
+        }
+    }
+
+    probes
+}
+
+/// RTT as either `1.23 ms` (two tokens) or `1.23ms`
+fn parse_rtt(part: &str, next: Option<&str>) -> Option<f64> {
+    if next == Some("ms") {
+        part.parse().ok()
+    } else {
+        part.strip_suffix("ms")?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(n: u32, ip: Option<&str>, rtts: Vec<Option<f64>>) -> TracerouteHop {
+        TracerouteHop {
+            hop_number: n,
+            hostname: None,
+            ip_address: ip.map(|s| s.parse().unwrap()),
+            rtt_ms: rtts,
+        }
+    }
+
+    #[test]
+    fn test_mtr_accumulates_rtt_stats() {
+        let mut acc = MtrAccumulator::new("10.0.0.1");
+
+        acc.record_round(&[hop(1, Some("192.168.1.1"), vec![Some(1.0)])]);
+        acc.record_round(&[hop(1, Some("192.168.1.1"), vec![Some(3.0)])]);
+        acc.record_round(&[hop(1, Some("192.168.1.1"), vec![None])]);
+        acc.record_round(&[hop(1, Some("192.168.1.1"), vec![Some(2.0)])]);
+
+        let report = acc.report(false);
+        assert_eq!(report.rounds, 4);
+
+        let stats = &report.hops[0];
+        assert_eq!(stats.host, "192.168.1.1");
+        assert_eq!(stats.sent, 4);
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.loss_pct, 25.0);
+        assert_eq!(stats.last_rtt_ms, Some(2.0));
+        assert_eq!(stats.avg_rtt_ms, Some(2.0));
+        assert_eq!(stats.best_rtt_ms, Some(1.0));
+        assert_eq!(stats.worst_rtt_ms, Some(3.0));
+    }
+
+    #[test]
+    fn test_mtr_silent_hop_shows_star() {
+        let mut acc = MtrAccumulator::new("10.0.0.1");
+
+        for _ in 0..3 {
+            acc.record_round(&[
+                hop(1, Some("192.168.1.1"), vec![Some(1.0), Some(1.0)]),
+                hop(2, None, vec![None, None]),
+                hop(3, Some("10.0.0.1"), vec![Some(10.0), None]),
+            ]);
+        }
+
+        let report = acc.report(true);
+        assert!(report.is_final);
+        assert_eq!(report.hops.len(), 3);
+
+        let silent = &report.hops[1];
+        assert_eq!(silent.host, "*");
+        assert_eq!(silent.sent, 6);
+        assert_eq!(silent.received, 0);
+        assert_eq!(silent.loss_pct, 100.0);
+        assert!(silent.avg_rtt_ms.is_none());
+
+        let last = &report.hops[2];
+        assert_eq!(last.loss_pct, 50.0);
+        assert_eq!(last.avg_rtt_ms, Some(10.0));
+    }
+
+    #[test]
+    fn test_mtr_hop_learns_address_later() {
+        let mut acc = MtrAccumulator::new("10.0.0.1");
+
+        acc.record_round(&[hop(1, None, vec![None])]);
+        acc.record_round(&[hop(1, Some("192.168.1.1"), vec![Some(4.0)])]);
+
+        let stats = &acc.report(false).hops[0];
+        assert_eq!(stats.host, "192.168.1.1");
+        assert_eq!(stats.loss_pct, 50.0);
+    }
+
+    #[test]
+    fn test_mtr_stddev_and_lost() {
+        let mut acc = MtrAccumulator::new("10.0.0.1");
+
+        for rtt in [Some(2.0), Some(4.0), None, Some(4.0), Some(4.0), None, Some(5.0), Some(7.0), Some(9.0), Some(2.0)] {
+            acc.record_probes(&[MtrProbe { hop_number: 1, ip_address: None, rtt_ms: rtt }], 1);
+        }
+
+        let stats = &acc.report(false).hops[0];
+        assert_eq!(stats.sent, 10);
+        assert_eq!(stats.lost, 2);
+        assert_eq!(stats.loss_pct, 20.0);
+        // 2, 4, 4, 4, 5, 7, 9, 2 has mean 4.625 and population variance 4.984375
+        assert!((stats.avg_rtt_ms.unwrap() - 4.625).abs() < 1e-9);
+        assert!((stats.stddev_rtt_ms.unwrap() - 4.984375_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mtr_tracks_ecmp_addresses() {
+        let output = "traceroute to 10.0.0.9 (10.0.0.9), 30 hops max, 60 byte packets\n \
+                       1  192.168.1.1  0.512 ms\n \
+                       2  10.1.0.1  3.1 ms 10.1.0.2  3.4 ms *\n \
+                       3  10.0.0.9  5.0ms !H\n";
+        let probes = parse_probe_output(output);
+        assert_eq!(probes.len(), 5);
+        assert_eq!(probes[2], MtrProbe {
+            hop_number: 2,
+            ip_address: Some("10.1.0.2".parse().unwrap()),
+            rtt_ms: Some(3.4),
+        });
+        assert_eq!(probes[3].rtt_ms, None);
+        assert_eq!(probes[4].rtt_ms, Some(5.0));
+
+        let mut acc = MtrAccumulator::new("10.0.0.9");
+        acc.record_probes(&probes, 3);
+        acc.record_probes(&parse_probe_output(" 2  10.1.0.2  3.0 ms\n"), 3);
+
+        let hop = &acc.report(false).hops[1];
+        assert_eq!(hop.sent, 4);
+        assert_eq!(hop.lost, 1);
+        assert_eq!(hop.host, "10.1.0.2");
+        let seen: Vec<(String, u32)> = hop.addresses.iter()
+            .map(|a| (a.ip_address.to_string(), a.received))
+            .collect();
+        assert_eq!(seen, vec![("10.1.0.1".to_string(), 1), ("10.1.0.2".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_mtr_parses_ipv6_probes() {
+        let probes = parse_probe_output(" 1  fe80::1  0.4 ms\n 2  *\n 3  2001:db8::1  12.5 ms\n");
+        assert_eq!(probes.len(), 3);
+        assert_eq!(probes[0].ip_address, Some("fe80::1".parse().unwrap()));
+        assert_eq!(probes[1], MtrProbe { hop_number: 2, ip_address: None, rtt_ms: None });
+        assert_eq!(probes[2].rtt_ms, Some(12.5));
+    }
+
+    #[test]
+    fn test_mtr_drops_hops_past_destination() {
+        let dest: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut acc = MtrAccumulator::new("example.net").with_destination(dest);
+
+        // First round misses the destination and runs on to max hops
+        acc.record_probes(&[
+            MtrProbe { hop_number: 1, ip_address: Some("192.168.1.1".parse().unwrap()), rtt_ms: Some(1.0) },
+            MtrProbe { hop_number: 2, ip_address: None, rtt_ms: None },
+            MtrProbe { hop_number: 3, ip_address: None, rtt_ms: None },
+        ], 3);
+        assert_eq!(acc.report(false).hops.len(), 3);
+
+        acc.record_probes(&[
+            MtrProbe { hop_number: 1, ip_address: Some("192.168.1.1".parse().unwrap()), rtt_ms: Some(1.0) },
+            MtrProbe { hop_number: 2, ip_address: Some(dest), rtt_ms: Some(8.0) },
+        ], 3);
+        assert_eq!(acc.destination_hop(), Some(2));
+
+        let report = acc.report(false);
+        assert_eq!(report.destination, Some(dest));
+        assert_eq!(report.hops.len(), 2);
+        assert_eq!(report.hops[1].loss_pct, 50.0);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["hops"][1]["addresses"][0]["ip_address"], "10.0.0.1");
+        assert!(json["hops"][1].get("rtt_m2").is_none());
+    }
+
+    #[test]
+    fn test_mtr_counts_missing_and_failed_probes_as_lost() {
+        let dest: IpAddr = "10.0.0.3".parse().unwrap();
+        let mut acc = MtrAccumulator::new("10.0.0.3").with_destination(dest);
+
+        // Hop 2 printed nothing this round
+        acc.record_probes(&parse_probe_output(" 1  10.0.0.1  1.0 ms\n 3  10.0.0.3  3.0 ms\n"), 30);
+        // The whole round failed or timed out
+        acc.record_probes(&[], 3);
+
+        let report = acc.report(false);
+        assert_eq!(report.hops.len(), 3);
+        for hop in &report.hops {
+            assert_eq!(hop.sent, 2, "hop {}", hop.hop_number);
+        }
+        let lost: Vec<u32> = report.hops.iter().map(|h| h.lost).collect();
+        assert_eq!(lost, vec![1, 2, 1]);
+        assert_eq!(report.hops[1].host, "*");
+    }
+
+    #[test]
+    fn test_mtr_options_validation() {
+        assert!(MtrOptions::default().validate().is_ok());
+        assert!(MtrOptions { max_hops: 0, ..Default::default() }.validate().is_err());
+        assert!(MtrOptions { timeout: Duration::ZERO, ..Default::default() }.validate().is_err());
+        assert!(MtrOptions { duration: Some(Duration::ZERO), ..Default::default() }.validate().is_err());
+
+        let json = serde_json::to_value(MtrOptions::default()).unwrap();
+        assert_eq!(json["protocol"], "icmp");
+    }
+
+    #[tokio::test]
+    async fn test_mtr_stream_ends_with_summary() {
+        use crate::tools::DiagnosticTools;
+        use futures::StreamExt;
+
+        let options = MtrOptions { count: 0, ..Default::default() };
+        let reports: Vec<MtrReport> = DiagnosticTools::mtr_stream("127.0.0.1", &options)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].is_final);
+        assert_eq!(reports[0].rounds, 0);
+        assert_eq!(reports[0].destination, Some("127.0.0.1".parse().unwrap()));
+
+        let report = DiagnosticTools::mtr("127.0.0.1", &options).await.unwrap();
+        assert!(report.is_final);
+    }
+}
@@ -0,0 +1,735 @@
+//! Neighbor Table Monitor
+//!
+//! Snapshots the ARP and NDP tables periodically and tracks IP-to-MAC
+//! bindings over time. An IP claimed by two MACs within a window, a changed
+//! static entry, a binding that violates a pin and a gratuitous-ARP storm
+//! are raised as [`NeighborEvent`]s, and cleared once the condition goes
+//! away.
+//!
+//! Only binding changes are recorded, in a bounded history per interface,
+//! and bindings not seen for a while are forgotten, so memory follows the
+//! number of live neighbors rather than the number of snapshots.
+
+use crate::packet_capture::PacketDetails;
+use crate::tools::{ArpEntry, DiagnosticTools, NdpEntry};
+use chrono::{DateTime, Utc};
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// Monitor settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeighborMonitorConfig {
+    /// Time between table snapshots
+    pub interval: Duration,
+    /// Two MACs claiming one IP within this window is a conflict
+    pub conflict_window: Duration,
+    /// Binding changes kept per interface
+    pub history_per_interface: usize,
+    /// Bindings not seen for this long are forgotten
+    pub stale_after: Duration,
+    /// Gratuitous ARPs on one interface within `storm_window` that make a storm
+    pub storm_threshold: u32,
+    pub storm_window: Duration,
+}
+
+impl Default for NeighborMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            conflict_window: Duration::from_secs(300),
+            history_per_interface: 4096,
+            stale_after: Duration::from_secs(3600),
+            storm_threshold: 100,
+            storm_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// One neighbor table entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NeighborObservation {
+    pub interface: String,
+    pub ip: IpAddr,
+    pub mac: String,
+    /// Permanent entry, configured rather than learned
+    pub is_static: bool,
+}
+
+impl From<&ArpEntry> for NeighborObservation {
+    fn from(entry: &ArpEntry) -> Self {
+        Self {
+            interface: entry.interface.clone(),
+            ip: IpAddr::V4(entry.ip_address),
+            mac: entry.mac_address.to_ascii_lowercase(),
+            // `arp -n` flags: C complete, M permanent, P published
+            is_static: entry.flags.contains('M'),
+        }
+    }
+}
+
+impl From<&NdpEntry> for NeighborObservation {
+    fn from(entry: &NdpEntry) -> Self {
+        Self {
+            interface: entry.interface.clone(),
+            ip: IpAddr::V6(entry.ip_address),
+            mac: entry.mac_address.to_ascii_lowercase(),
+            is_static: entry.state.eq_ignore_ascii_case("PERMANENT"),
+        }
+    }
+}
+
+/// A binding first seen or changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BindingRecord {
+    pub interface: String,
+    pub ip: IpAddr,
+    pub mac: String,
+    /// MAC bound before, `None` for a new binding
+    pub previous_mac: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Condition the monitor alerts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborIssue {
+    /// Several MACs claimed one IP within the conflict window
+    IpConflict,
+    /// A permanent entry now resolves to another MAC
+    StaticMacChanged,
+    /// A pinned IP resolves to another MAC
+    PinViolation,
+    /// Gratuitous ARPs above the storm threshold
+    GratuitousArpStorm,
+}
+
+impl NeighborIssue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::IpConflict => "ip_conflict",
+            Self::StaticMacChanged => "static_mac_changed",
+            Self::PinViolation => "pin_violation",
+            Self::GratuitousArpStorm => "gratuitous_arp_storm",
+        }
+    }
+}
+
+/// An issue on one interface, and for one IP unless it is a storm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeighborAlert {
+    pub issue: NeighborIssue,
+    pub interface: String,
+    pub ip: Option<IpAddr>,
+    /// MACs involved, expected one first where there is one
+    pub macs: Vec<String>,
+    pub detail: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl NeighborAlert {
+    /// Identifies the issue across raise and clear, e.g. `ip_conflict/eth0/10.0.0.5`
+    pub fn key(&self) -> String {
+        issue_key(self.issue, &self.interface, self.ip)
+    }
+}
+
+/// Event published to subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NeighborEvent {
+    /// A known IP moved to another MAC
+    MacChanged(BindingRecord),
+    Raised(NeighborAlert),
+    Cleared(NeighborAlert),
+}
+
+struct Binding {
+    mac: String,
+    last_seen: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct InterfaceState {
+    bindings: HashMap<IpAddr, Binding>,
+    /// MACs that claimed an IP whose binding changed recently, with when
+    /// each was last seen; only flapping IPs have an entry
+    claims: HashMap<IpAddr, Vec<(String, DateTime<Utc>)>>,
+    /// MAC of each permanent entry when first seen
+    static_macs: HashMap<IpAddr, String>,
+    history: VecDeque<BindingRecord>,
+    /// Gratuitous ARPs counted per second
+    gratuitous: VecDeque<(i64, u32)>,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    interfaces: HashMap<String, InterfaceState>,
+    pins: HashMap<IpAddr, String>,
+    active: HashMap<String, NeighborAlert>,
+}
+
+/// Tracks neighbor bindings and raises alerts on suspicious changes
+pub struct NeighborMonitor {
+    config: NeighborMonitorConfig,
+    state: Mutex<MonitorState>,
+    events: broadcast::Sender<NeighborEvent>,
+}
+
+impl NeighborMonitor {
+    pub fn new(config: NeighborMonitorConfig) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            config,
+            state: Mutex::new(MonitorState::default()),
+            events,
+        }
+    }
+
+    pub fn config(&self) -> &NeighborMonitorConfig {
+        &self.config
+    }
+
+    /// Receive binding changes and raised or cleared alerts
+    pub fn subscribe(&self) -> broadcast::Receiver<NeighborEvent> {
+        self.events.subscribe()
+    }
+
+    /// Snapshot the tables every `interval` until the task is aborted
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(self.config.interval);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            loop {
+                tick.tick().await;
+                match Self::read_tables().await {
+                    Ok(observations) => {
+                        self.process_snapshot(&observations, Utc::now());
+                    }
+                    Err(e) => tracing::warn!("Neighbor table snapshot failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Current ARP and NDP entries; fails only if neither table can be read
+    pub async fn read_tables() -> Result<Vec<NeighborObservation>> {
+        let arp = DiagnosticTools::get_arp_table().await;
+        let ndp = DiagnosticTools::get_ndp_table().await;
+
+        if let (Err(arp), Err(_)) = (&arp, &ndp) {
+            return Err(Error::Network(format!("Cannot read neighbor tables: {}", arp)));
+        }
+
+        let mut observations: Vec<NeighborObservation> = arp.unwrap_or_default().iter().map(Into::into).collect();
+        observations.extend(ndp.unwrap_or_default().iter().map(NeighborObservation::from));
+        // Skip incomplete and failed entries
+        observations.retain(|o| is_mac(&o.mac));
+        Ok(observations)
+    }
+
+    /// Apply one snapshot of the tables, returning the events it caused
+    pub fn process_snapshot(&self, observations: &[NeighborObservation], now: DateTime<Utc>) -> Vec<NeighborEvent> {
+        let mut events = Vec::new();
+        {
+            let mut state = self.lock();
+            for observation in observations {
+                self.observe(&mut state, observation, now, &mut events);
+            }
+            self.sweep(&mut state, now, &mut events);
+        }
+        self.publish(&events);
+        events
+    }
+
+    /// Count a gratuitous ARP, an announcement where sender and target IP
+    /// are equal; `mac` also records the claim it makes
+    pub fn record_gratuitous_arp(
+        &self,
+        interface: &str,
+        ip: IpAddr,
+        mac: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> Vec<NeighborEvent> {
+        let mut events = Vec::new();
+        {
+            let mut state = self.lock();
+            let iface = state.interfaces.entry(interface.to_string()).or_default();
+            let second = at.timestamp();
+            match iface.gratuitous.back_mut() {
+                Some((s, count)) if *s == second => *count += 1,
+                _ => iface.gratuitous.push_back((second, 1)),
+            }
+            self.check_storm(&mut state, interface, at, &mut events);
+
+            if let Some(mac) = mac.filter(|m| is_mac(m)) {
+                let observation = NeighborObservation {
+                    interface: interface.to_string(),
+                    ip,
+                    mac: mac.to_ascii_lowercase(),
+                    is_static: false,
+                };
+                self.observe(&mut state, &observation, at, &mut events);
+            }
+        }
+        self.publish(&events);
+        events
+    }
+
+    /// Feed a packet from a live capture; gratuitous ARPs are counted
+    pub fn observe_packet(&self, interface: &str, packet: &PacketDetails) -> Vec<NeighborEvent> {
+        if packet.protocol.as_deref() != Some("ARP") || packet.source.is_none() || packet.source != packet.destination {
+            return Vec::new();
+        }
+        let Some(ip) = packet.source.as_deref().and_then(|s| s.parse().ok()) else {
+            return Vec::new();
+        };
+        // Replies read "<ip> is-at <mac>"; requests carry no MAC in the summary
+        let mac = packet.details.split_whitespace()
+            .skip_while(|w| *w != "is-at")
+            .nth(1);
+        self.record_gratuitous_arp(interface, ip, mac, packet.timestamp.unwrap_or_else(Utc::now))
+    }
+
+    /// Expect `ip` to resolve to `mac` on every interface, alerting otherwise
+    pub fn pin_binding(&self, ip: IpAddr, mac: &str) -> Result<()> {
+        if !is_mac(mac) {
+            return Err(Error::Config(format!("Invalid MAC address: {}", mac)));
+        }
+        let mut events = Vec::new();
+        {
+            let mut state = self.lock();
+            state.pins.insert(ip, mac.to_ascii_lowercase());
+            let interfaces: Vec<String> = state.interfaces.keys().cloned().collect();
+            for interface in interfaces {
+                self.check_bindings(&mut state, &interface, ip, Utc::now(), &mut events);
+            }
+        }
+        self.publish(&events);
+        Ok(())
+    }
+
+    /// Remove a pin, clearing its violations; false if `ip` was not pinned
+    pub fn unpin_binding(&self, ip: IpAddr) -> bool {
+        let mut events = Vec::new();
+        let removed = {
+            let mut state = self.lock();
+            let removed = state.pins.remove(&ip).is_some();
+            let interfaces: Vec<String> = state.interfaces.keys().cloned().collect();
+            for interface in interfaces {
+                self.check_bindings(&mut state, &interface, ip, Utc::now(), &mut events);
+            }
+            removed
+        };
+        self.publish(&events);
+        removed
+    }
+
+    /// Pinned IPs and their expected MACs
+    pub fn pinned_bindings(&self) -> Vec<(IpAddr, String)> {
+        let mut pins: Vec<(IpAddr, String)> = self.lock().pins.iter().map(|(ip, mac)| (*ip, mac.clone())).collect();
+        pins.sort();
+        pins
+    }
+
+    /// Recorded bindings of `ip` on all interfaces, oldest first
+    pub fn get_binding_history(&self, ip: IpAddr) -> Vec<BindingRecord> {
+        let state = self.lock();
+        let mut records: Vec<BindingRecord> = state.interfaces.values()
+            .flat_map(|iface| iface.history.iter().filter(|r| r.ip == ip).cloned())
+            .collect();
+        records.sort_by_key(|r| r.timestamp);
+        records
+    }
+
+    /// Issues currently raised
+    pub fn active_alerts(&self) -> Vec<NeighborAlert> {
+        self.lock().active.values().cloned().collect()
+    }
+
+    /// Bindings currently tracked across all interfaces
+    pub fn binding_count(&self) -> usize {
+        self.lock().interfaces.values().map(|i| i.bindings.len()).sum()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MonitorState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn publish(&self, events: &[NeighborEvent]) {
+        for event in events {
+            // No subscribers is fine
+            let _ = self.events.send(event.clone());
+        }
+    }
+
+    fn observe(
+        &self,
+        state: &mut MonitorState,
+        observation: &NeighborObservation,
+        now: DateTime<Utc>,
+        events: &mut Vec<NeighborEvent>,
+    ) {
+        let NeighborObservation { interface, ip, mac, is_static } = observation;
+        let iface = state.interfaces.entry(interface.clone()).or_default();
+
+        if *is_static {
+            iface.static_macs.entry(*ip).or_insert_with(|| mac.clone());
+        }
+
+        let previous = match iface.bindings.get_mut(ip) {
+            Some(binding) if &binding.mac == mac => {
+                binding.last_seen = now;
+                if let Some(claim) = iface.claims.get_mut(ip).and_then(|c| c.iter_mut().find(|(m, _)| m == mac)) {
+                    claim.1 = now;
+                }
+                None
+            }
+            Some(binding) => {
+                let old = std::mem::replace(binding, Binding { mac: mac.clone(), last_seen: now });
+                let claims = iface.claims.entry(*ip).or_default();
+                for (claimant, seen) in [(old.mac.clone(), old.last_seen), (mac.clone(), now)] {
+                    match claims.iter_mut().find(|(m, _)| *m == claimant) {
+                        Some(claim) => claim.1 = claim.1.max(seen),
+                        None => claims.push((claimant, seen)),
+                    }
+                }
+                Some(Some(old.mac))
+            }
+            None => {
+                iface.bindings.insert(*ip, Binding { mac: mac.clone(), last_seen: now });
+                Some(None)
+            }
+        };
+
+        if let Some(previous_mac) = previous {
+            let record = BindingRecord {
+                interface: interface.clone(),
+                ip: *ip,
+                mac: mac.clone(),
+                previous_mac,
+                timestamp: now,
+            };
+            if iface.history.len() >= self.config.history_per_interface {
+                iface.history.pop_front();
+            }
+            iface.history.push_back(record.clone());
+            if record.previous_mac.is_some() {
+                events.push(NeighborEvent::MacChanged(record));
+            }
+            self.check_bindings(state, interface, *ip, now, events);
+        }
+    }
+
+    /// Re-evaluate the per-IP issues of `ip` on `interface`
+    fn check_bindings(
+        &self,
+        state: &mut MonitorState,
+        interface: &str,
+        ip: IpAddr,
+        now: DateTime<Utc>,
+        events: &mut Vec<NeighborEvent>,
+    ) {
+        let Some(iface) = state.interfaces.get_mut(interface) else {
+            return;
+        };
+        let current = iface.bindings.get(&ip).map(|b| b.mac.clone());
+
+        let window = chrono::Duration::from_std(self.config.conflict_window).unwrap_or(chrono::Duration::MAX);
+        let conflict = match iface.claims.get_mut(&ip) {
+            Some(claims) => {
+                claims.retain(|(_, seen)| now.signed_duration_since(*seen) <= window);
+                let macs: Vec<String> = claims.iter().map(|(m, _)| m.clone()).collect();
+                if claims.len() < 2 {
+                    iface.claims.remove(&ip);
+                }
+                (macs.len() > 1).then_some(macs)
+            }
+            None => None,
+        };
+
+        let static_change = match (iface.static_macs.get(&ip), &current) {
+            (Some(expected), Some(current)) if expected != current => Some(vec![expected.clone(), current.clone()]),
+            _ => None,
+        };
+        let pin_violation = match (state.pins.get(&ip), &current) {
+            (Some(pinned), Some(current)) if pinned != current => Some(vec![pinned.clone(), current.clone()]),
+            _ => None,
+        };
+
+        let checks = [
+            (NeighborIssue::IpConflict, conflict, "claimed by several MACs within the conflict window"),
+            (NeighborIssue::StaticMacChanged, static_change, "static entry changed MAC"),
+            (NeighborIssue::PinViolation, pin_violation, "resolves to a MAC other than the pinned one"),
+        ];
+        for (issue, macs, what) in checks {
+            let alert = macs.map(|macs| NeighborAlert {
+                issue,
+                interface: interface.to_string(),
+                ip: Some(ip),
+                detail: format!("{} on {} {}: {}", ip, interface, what, macs.join(", ")),
+                macs,
+                timestamp: now,
+            });
+            set_issue(state, issue_key(issue, interface, Some(ip)), alert, now, events);
+        }
+    }
+
+    fn check_storm(&self, state: &mut MonitorState, interface: &str, now: DateTime<Utc>, events: &mut Vec<NeighborEvent>) {
+        let Some(iface) = state.interfaces.get_mut(interface) else {
+            return;
+        };
+        let window = self.config.storm_window.as_secs().max(1) as i64;
+        let oldest = now.timestamp() - window + 1;
+        while iface.gratuitous.front().is_some_and(|(second, _)| *second < oldest) {
+            iface.gratuitous.pop_front();
+        }
+        let count: u32 = iface.gratuitous.iter().map(|(_, c)| c).sum();
+
+        let alert = (count >= self.config.storm_threshold).then(|| NeighborAlert {
+            issue: NeighborIssue::GratuitousArpStorm,
+            interface: interface.to_string(),
+            ip: None,
+            macs: Vec::new(),
+            detail: format!("{} gratuitous ARPs on {} in {}s", count, interface, window),
+            timestamp: now,
+        });
+        set_issue(state, issue_key(NeighborIssue::GratuitousArpStorm, interface, None), alert, now, events);
+    }
+
+    /// Expire conflicts and storms, and forget stale bindings
+    fn sweep(&self, state: &mut MonitorState, now: DateTime<Utc>, events: &mut Vec<NeighborEvent>) {
+        let stale = chrono::Duration::from_std(self.config.stale_after).unwrap_or(chrono::Duration::MAX);
+        let interfaces: Vec<String> = state.interfaces.keys().cloned().collect();
+
+        for interface in interfaces {
+            let flapping: Vec<IpAddr> = {
+                let iface = state.interfaces.get_mut(&interface).expect("listed above");
+                let pins = &state.pins;
+                iface.bindings.retain(|ip, b| {
+                    now.signed_duration_since(b.last_seen) <= stale || pins.contains_key(ip)
+                });
+                iface.claims.keys().copied().collect()
+            };
+            for ip in flapping {
+                self.check_bindings(state, &interface, ip, now, events);
+            }
+            self.check_storm(state, &interface, now, events);
+        }
+    }
+}
+
+impl Default for NeighborMonitor {
+    fn default() -> Self {
+        Self::new(NeighborMonitorConfig::default())
+    }
+}
+
+fn issue_key(issue: NeighborIssue, interface: &str, ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(ip) => format!("{}/{}/{}", issue.name(), interface, ip),
+        None => format!("{}/{}", issue.name(), interface),
+    }
+}
+
+/// Raise `alert` under `key` if new, or clear the active one when `None`
+fn set_issue(
+    state: &mut MonitorState,
+    key: String,
+    alert: Option<NeighborAlert>,
+    now: DateTime<Utc>,
+    events: &mut Vec<NeighborEvent>,
+) {
+    match alert {
+        Some(alert) => {
+            if let Entry::Vacant(slot) = state.active.entry(key) {
+                tracing::warn!("Neighbor alert: {}", alert.detail);
+                slot.insert(alert.clone());
+                events.push(NeighborEvent::Raised(alert));
+            }
+        }
+        None => {
+            if let Some(mut alert) = state.active.remove(&key) {
+                alert.timestamp = now;
+                events.push(NeighborEvent::Cleared(alert));
+            }
+        }
+    }
+}
+
+fn is_mac(mac: &str) -> bool {
+    let parts: Vec<&str> = mac.split(':').collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const MAC_A: &str = "02:00:00:00:00:0a";
+    const MAC_B: &str = "02:00:00:00:00:0b";
+
+    fn obs(ip: &str, mac: &str) -> NeighborObservation {
+        NeighborObservation {
+            interface: "eth0".to_string(),
+            ip: ip.parse().unwrap(),
+            mac: mac.to_string(),
+            is_static: false,
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn raised(events: &[NeighborEvent]) -> Vec<NeighborIssue> {
+        events.iter()
+            .filter_map(|e| match e {
+                NeighborEvent::Raised(a) => Some(a.issue),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn cleared(events: &[NeighborEvent]) -> Vec<NeighborIssue> {
+        events.iter()
+            .filter_map(|e| match e {
+                NeighborEvent::Cleared(a) => Some(a.issue),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_conflict_within_window_raises_and_clears() {
+        let monitor = NeighborMonitor::new(NeighborMonitorConfig {
+            conflict_window: Duration::from_secs(60),
+            ..Default::default()
+        });
+
+        assert!(monitor.process_snapshot(&[obs("10.0.0.5", MAC_A)], at(0)).is_empty());
+        let events = monitor.process_snapshot(&[obs("10.0.0.5", MAC_B)], at(30));
+        assert!(matches!(&events[0], NeighborEvent::MacChanged(r) if r.previous_mac.as_deref() == Some(MAC_A)));
+        assert_eq!(raised(&events), vec![NeighborIssue::IpConflict]);
+
+        let alert = &monitor.active_alerts()[0];
+        assert_eq!(alert.key(), "ip_conflict/eth0/10.0.0.5");
+        assert_eq!(alert.macs, vec![MAC_A, MAC_B]);
+
+        // Still only B once A has been silent for the whole window
+        assert!(monitor.process_snapshot(&[obs("10.0.0.5", MAC_B)], at(60)).is_empty());
+        let events = monitor.process_snapshot(&[obs("10.0.0.5", MAC_B)], at(100));
+        assert_eq!(cleared(&events), vec![NeighborIssue::IpConflict]);
+        assert!(monitor.active_alerts().is_empty());
+
+        let history = monitor.get_binding_history("10.0.0.5".parse().unwrap());
+        let macs: Vec<&str> = history.iter().map(|r| r.mac.as_str()).collect();
+        assert_eq!(macs, vec![MAC_A, MAC_B]);
+    }
+
+    #[test]
+    fn test_slow_replacement_is_not_a_conflict() {
+        let monitor = NeighborMonitor::new(NeighborMonitorConfig {
+            conflict_window: Duration::from_secs(60),
+            ..Default::default()
+        });
+        monitor.process_snapshot(&[obs("10.0.0.5", MAC_A)], at(0));
+        monitor.process_snapshot(&[], at(30));
+
+        let events = monitor.process_snapshot(&[obs("10.0.0.5", MAC_B)], at(600));
+        assert!(matches!(&events[..], [NeighborEvent::MacChanged(_)]));
+    }
+
+    #[test]
+    fn test_static_entry_and_pin_violations() {
+        let monitor = NeighborMonitor::default();
+        let gateway = NeighborObservation { is_static: true, ..obs("10.0.0.1", MAC_A) };
+        monitor.process_snapshot(std::slice::from_ref(&gateway), at(0));
+
+        let moved = NeighborObservation { mac: MAC_B.to_string(), ..gateway.clone() };
+        let events = monitor.process_snapshot(&[moved], at(5000));
+        assert_eq!(raised(&events), vec![NeighborIssue::StaticMacChanged]);
+
+        let events = monitor.process_snapshot(&[gateway], at(5030));
+        assert_eq!(cleared(&events), vec![NeighborIssue::StaticMacChanged]);
+
+        // Pinning to a different MAC than the current one alerts at once
+        monitor.process_snapshot(&[obs("10.0.0.2", MAC_A)], Utc::now());
+        let mut events = monitor.subscribe();
+        monitor.pin_binding("10.0.0.2".parse().unwrap(), "02:00:00:00:00:0B").unwrap();
+        let NeighborEvent::Raised(alert) = events.try_recv().unwrap() else {
+            panic!("expected a raised alert");
+        };
+        assert_eq!(alert.issue, NeighborIssue::PinViolation);
+        assert_eq!(alert.macs, vec![MAC_B, MAC_A]);
+
+        assert_eq!(monitor.pinned_bindings(), vec![("10.0.0.2".parse().unwrap(), MAC_B.to_string())]);
+        assert!(monitor.unpin_binding("10.0.0.2".parse().unwrap()));
+        assert!(matches!(events.try_recv().unwrap(), NeighborEvent::Cleared(_)));
+        assert!(monitor.pin_binding("10.0.0.1".parse().unwrap(), "nonsense").is_err());
+    }
+
+    #[test]
+    fn test_gratuitous_arp_storm() {
+        let monitor = NeighborMonitor::new(NeighborMonitorConfig {
+            storm_threshold: 50,
+            storm_window: Duration::from_secs(5),
+            ..Default::default()
+        });
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+
+        let mut storm = Vec::new();
+        for i in 0..60 {
+            storm.extend(monitor.record_gratuitous_arp("eth1", ip, Some(MAC_A), at(i / 20)));
+        }
+        assert_eq!(raised(&storm), vec![NeighborIssue::GratuitousArpStorm]);
+
+        let packet = PacketDetails {
+            packet_number: 1,
+            details: format!("ARP 10.0.0.9 > 10.0.0.9 10.0.0.9 is-at {} 42 bytes", MAC_B),
+            timestamp: Some(at(3)),
+            source: Some("10.0.0.9".to_string()),
+            destination: Some("10.0.0.9".to_string()),
+            protocol: Some("ARP".to_string()),
+            payload_hex: None,
+        };
+        let events = monitor.observe_packet("eth1", &packet);
+        assert_eq!(raised(&events), vec![NeighborIssue::IpConflict]);
+
+        let events = monitor.process_snapshot(&[], at(30));
+        assert!(cleared(&events).contains(&NeighborIssue::GratuitousArpStorm));
+    }
+
+    #[test]
+    fn test_history_is_bounded_on_large_segments() {
+        let monitor = NeighborMonitor::new(NeighborMonitorConfig {
+            history_per_interface: 1000,
+            stale_after: Duration::from_secs(120),
+            ..Default::default()
+        });
+
+        let table: Vec<NeighborObservation> = (0..5000u32)
+            .map(|i| obs(&Ipv4Addr::from(0x0a00_0000 + i).to_string(), MAC_A))
+            .collect();
+        for round in 0..3 {
+            monitor.process_snapshot(&table, at(round * 30));
+        }
+        assert_eq!(monitor.binding_count(), 5000);
+        assert_eq!(monitor.lock().interfaces["eth0"].history.len(), 1000);
+        // Only the newest first-sightings survive
+        assert!(monitor.get_binding_history("10.0.0.1".parse().unwrap()).is_empty());
+        assert_eq!(monitor.get_binding_history(Ipv4Addr::from(0x0a00_0000 + 4999).into()).len(), 1);
+
+        // Neighbors that leave the table are forgotten after stale_after
+        monitor.process_snapshot(&table[..10], at(300));
+        assert_eq!(monitor.binding_count(), 10);
+    }
+}
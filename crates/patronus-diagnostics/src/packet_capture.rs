@@ -3,12 +3,18 @@
 //! Web-based packet capture for network troubleshooting.
 //! Essential diagnostic tool for analyzing traffic.
 
+use crate::pcapng;
+use crate::live_capture::{LiveCapture, LiveCaptureOptions};
+use crate::ring_capture::{self, PcapStreamParser, RingCapture};
+use crate::rotating_capture::{self, CaptureEvent, DiskQuota, RotatingFiles, RotatingWriter, RotationPolicy};
 use patronus_core::{Result, Error};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Arc;
 use tokio::process::{Command, Child};
 use tokio::io::AsyncBufReadExt;
+use tokio::sync::broadcast;
 
 /// Packet capture configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +27,9 @@ pub struct CaptureConfig {
     pub max_packets: Option<u32>,  // Maximum packets to capture
     pub max_time: Option<u32>,     // Maximum capture time (seconds)
     pub max_size: Option<u32>,     // Maximum file size (MB)
+    /// Rotate across several files instead of writing one (`max_size` is then ignored)
+    #[serde(default)]
+    pub rotation: Option<RotationPolicy>,
 }
 
 /// Capture format
@@ -41,6 +50,39 @@ pub struct CaptureSession {
     pub bytes_captured: u64,
     pub output_file: PathBuf,
     process: Option<Child>,
+    rotation: Option<RotatingFiles>,
+}
+
+impl CaptureSession {
+    /// Files holding this session's packets, oldest first
+    ///
+    /// Rotating sessions list the files they still keep; others list their
+    /// single output file.
+    pub async fn list_files(&self) -> Result<Vec<CaptureInfo>> {
+        if let Some(rotation) = &self.rotation {
+            return Ok(rotation.quota.files(&self.id));
+        }
+
+        let metadata = tokio::fs::metadata(&self.output_file).await?;
+        Ok(vec![CaptureInfo::new(&self.output_file, &metadata)])
+    }
+
+    /// Contents of the `index`th file returned by [`list_files`](Self::list_files)
+    ///
+    /// The file still being written may end part-way through a packet.
+    pub async fn download_file(&self, index: usize) -> Result<Vec<u8>> {
+        let files = self.list_files().await?;
+        let file = files.get(index)
+            .ok_or_else(|| Error::Config(format!("Capture {} has no file {}", self.id, index)))?;
+
+        Ok(tokio::fs::read(&file.path).await?)
+    }
+
+    /// Merge this session's files into one pcapng, preserving timestamps
+    pub async fn export_merged(&self, output_file: &Path) -> Result<()> {
+        let files = self.list_files().await?;
+        rotating_capture::export_merged(&files, &self.interface, output_file).await
+    }
 }
 
 /// Packet capture statistics
@@ -50,19 +92,47 @@ pub struct CaptureStats {
     pub packets_dropped: u64,
     pub bytes_captured: u64,
     pub duration_seconds: u64,
+    /// Bytes currently held in a ring buffer (ring captures only)
+    #[serde(default)]
+    pub buffer_used_bytes: Option<u64>,
+    /// Ring buffer capacity in bytes (ring captures only)
+    #[serde(default)]
+    pub buffer_capacity_bytes: Option<u64>,
 }
 
 pub struct PacketCaptureManager {
     captures_dir: PathBuf,
+    quota: Arc<DiskQuota>,
 }
 
 impl PacketCaptureManager {
     pub fn new() -> Self {
         Self {
             captures_dir: PathBuf::from("/var/log/patronus/captures"),
+            quota: Arc::new(DiskQuota::new(rotating_capture::DEFAULT_DISK_QUOTA_MB * 1024 * 1024)),
         }
     }
 
+    pub fn with_captures_dir(mut self, captures_dir: impl Into<PathBuf>) -> Self {
+        self.captures_dir = captures_dir.into();
+        self
+    }
+
+    /// Limit the disk space shared by all rotating captures
+    pub fn with_disk_quota(mut self, limit_mb: u64) -> Self {
+        self.quota = Arc::new(DiskQuota::new(limit_mb * 1024 * 1024));
+        self
+    }
+
+    pub fn disk_quota(&self) -> &DiskQuota {
+        &self.quota
+    }
+
+    /// Receive file eviction and quota-exceeded alerts from rotating captures
+    pub fn subscribe(&self) -> broadcast::Receiver<CaptureEvent> {
+        self.quota.subscribe()
+    }
+
     /// Start a new packet capture
     pub async fn start_capture(&self, config: CaptureConfig) -> Result<CaptureSession> {
         // Create captures directory
@@ -70,6 +140,11 @@ impl PacketCaptureManager {
 
         // Generate unique session ID
         let session_id = uuid::Uuid::new_v4().to_string();
+
+        if let Some(policy) = config.rotation {
+            return self.start_rotating_capture(session_id, config, policy);
+        }
+
         let output_file = self.captures_dir.join(format!("{}.pcap", session_id));
 
         tracing::info!("Starting packet capture on {} to {}",
@@ -122,6 +197,29 @@ impl PacketCaptureManager {
             bytes_captured: 0,
             output_file,
             process: Some(child),
+            rotation: None,
+        })
+    }
+
+    fn start_rotating_capture(&self, session_id: String, config: CaptureConfig, policy: RotationPolicy) -> Result<CaptureSession> {
+        policy.validate()?;
+
+        tracing::info!("Starting rotating capture on {} to {} ({} MB x {} files)",
+            config.interface, self.captures_dir.display(), policy.file_size_mb, policy.max_files);
+
+        let writer = RotatingWriter::new(&session_id, &self.captures_dir, policy,
+            self.quota.clone(), config.snaplen);
+        let (child, rotation) = rotating_capture::spawn(&config, writer)?;
+
+        Ok(CaptureSession {
+            output_file: rotating_capture::file_path(&self.captures_dir, &session_id, 0),
+            id: session_id,
+            interface: config.interface,
+            started_at: chrono::Utc::now(),
+            packets_captured: 0,
+            bytes_captured: 0,
+            process: Some(child),
+            rotation: Some(rotation),
         })
     }
 
@@ -143,11 +241,72 @@ impl PacketCaptureManager {
             let _ = process.wait().await?;
         }
 
+        if let Some(rotation) = &mut session.rotation {
+            let summary = rotation.finish().await;
+            session.packets_captured = summary.packets_written;
+            session.bytes_captured = summary.bytes_written;
+        }
+
         // Get capture statistics
         self.get_stats(session).await
     }
 
+    /// Start a continuous capture keeping only the last `capacity_mb` megabytes
+    ///
+    /// Packet limits in `config` are ignored; the buffer evicts the oldest
+    /// packets instead.
+    pub async fn start_ring_capture(&self, config: CaptureConfig, capacity_mb: u32) -> Result<RingCapture> {
+        if capacity_mb == 0 {
+            return Err(Error::Config("Ring buffer capacity must be non-zero".to_string()));
+        }
+
+        RingCapture::start(config, capacity_mb)
+    }
+
+    /// Stream decoded packets as they are captured
+    ///
+    /// The BPF filter in `config` is applied by the kernel; packet limits are
+    /// ignored. Dropping the returned capture stops the capture process.
+    pub fn start_live_capture(&self, config: CaptureConfig, options: LiveCaptureOptions) -> Result<LiveCapture> {
+        if options.max_packets_per_sec == 0 {
+            return Err(Error::Config("Live capture rate limit must be non-zero".to_string()));
+        }
+        validate_capture_args(&config)?;
+
+        LiveCapture::start(config, options)
+    }
+
+    /// Save the current contents of a ring capture to the captures directory
+    pub async fn snapshot_ring_capture(&self, capture: &RingCapture, format: CaptureFormat) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.captures_dir).await?;
+
+        let extension = match format {
+            CaptureFormat::PcapNg => "pcapng",
+            _ => "pcap",
+        };
+        let output_file = ring_capture::snapshot_path(&self.captures_dir, capture, extension);
+        capture.snapshot_as(&output_file, format).await?;
+
+        tracing::info!("Saved ring capture {} snapshot to {}",
+            capture.id, output_file.display());
+
+        Ok(output_file)
+    }
+
     async fn get_stats(&self, session: &CaptureSession) -> Result<CaptureStats> {
+        let duration = (chrono::Utc::now() - session.started_at).num_seconds() as u64;
+
+        if session.rotation.is_some() {
+            return Ok(CaptureStats {
+                packets_captured: session.packets_captured,
+                packets_dropped: 0,
+                bytes_captured: session.bytes_captured,
+                duration_seconds: duration,
+                buffer_used_bytes: None,
+                buffer_capacity_bytes: None,
+            });
+        }
+
         // Get file size
         let metadata = tokio::fs::metadata(&session.output_file).await?;
         let bytes = metadata.len();
@@ -166,13 +325,13 @@ impl PacketCaptureManager {
             0
         };
 
-        let duration = (chrono::Utc::now() - session.started_at).num_seconds() as u64;
-
         Ok(CaptureStats {
             packets_captured: packets,
             packets_dropped: 0,
             bytes_captured: bytes,
             duration_seconds: duration,
+            buffer_used_bytes: None,
+            buffer_capacity_bytes: None,
         })
     }
 
@@ -237,13 +396,8 @@ impl PacketCaptureManager {
 
         match format {
             CaptureFormat::PcapNg => {
-                // Convert using editcap
-                Command::new("editcap")
-                    .arg("-F").arg("pcapng")
-                    .arg(input_file)
-                    .arg(&output_file)
-                    .status()
-                    .await?;
+                // pcap files don't record the interface name
+                self.write_pcapng(input_file, &output_file, None).await?;
             }
             CaptureFormat::Text => {
                 // Convert to text
@@ -259,6 +413,34 @@ impl PacketCaptureManager {
         Ok(output_file)
     }
 
+    /// Export a capture session as pcapng, recording its interface name
+    pub async fn export_pcapng(&self, session: &CaptureSession) -> Result<PathBuf> {
+        if session.rotation.is_some() {
+            let output_file = self.captures_dir.join(format!("{}.pcapng", session.id));
+            session.export_merged(&output_file).await?;
+            return Ok(output_file);
+        }
+
+        let output_file = session.output_file.with_extension("pcapng");
+        self.write_pcapng(&session.output_file, &output_file, Some(&session.interface)).await?;
+        Ok(output_file)
+    }
+
+    async fn write_pcapng(&self, input_file: &Path, output_file: &Path, interface: Option<&str>) -> Result<()> {
+        let pcap = tokio::fs::read(input_file).await?;
+
+        let mut parser = PcapStreamParser::new();
+        let packets = parser.feed(&pcap)?;
+        let linktype = parser.linktype()
+            .ok_or_else(|| Error::Network("Capture file has no pcap header".to_string()))?;
+        let snaplen = packets.iter().map(|p| p.data.len() as u32).max().unwrap_or(0).max(65535);
+
+        let bytes = pcapng::encode(interface, linktype, snaplen, &packets);
+        tokio::fs::write(output_file, bytes).await?;
+
+        Ok(())
+    }
+
     /// List available capture files
     pub async fn list_captures(&self) -> Result<Vec<CaptureInfo>> {
         let mut captures = Vec::new();
@@ -275,15 +457,7 @@ impl PacketCaptureManager {
             if let Some(ext) = path.extension() {
                 if ext == "pcap" || ext == "pcapng" {
                     let metadata = entry.metadata().await?;
-
-                    captures.push(CaptureInfo {
-                        filename: path.file_name().unwrap().to_string_lossy().to_string(),
-                        path: path.clone(),
-                        size_bytes: metadata.len(),
-                        created_at: metadata.created()
-                            .ok()
-                            .and_then(|t| chrono::DateTime::from(t).into()),
-                    });
+                    captures.push(CaptureInfo::new(&path, &metadata));
                 }
             }
         }
@@ -299,7 +473,8 @@ impl PacketCaptureManager {
             return Err(Error::Config(format!("Capture file not found: {}", filename)));
         }
 
-        tokio::fs::remove_file(path).await?;
+        tokio::fs::remove_file(&path).await?;
+        self.quota.forget(&path);
 
         Ok(())
     }
@@ -319,6 +494,11 @@ impl PacketCaptureManager {
         Ok(PacketDetails {
             packet_number: packet_num,
             details,
+            timestamp: None,
+            source: None,
+            destination: None,
+            protocol: None,
+            payload_hex: None,
         })
     }
 
@@ -369,6 +549,7 @@ impl PacketCaptureManager {
             max_packets: Some(1000),
             max_time: Some(30),
             max_size: None,
+            rotation: None,
         };
 
         let mut session = self.start_capture(config).await?;
@@ -390,10 +571,35 @@ pub struct CaptureInfo {
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl CaptureInfo {
+    fn new(path: &Path, metadata: &std::fs::Metadata) -> Self {
+        Self {
+            filename: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: path.to_path_buf(),
+            size_bytes: metadata.len(),
+            created_at: metadata.created()
+                .ok()
+                .and_then(|t| chrono::DateTime::from(t).into()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PacketDetails {
     pub packet_number: u32,
+    /// Full dissection, or a one-line summary for live packets
     pub details: String,
+    #[serde(default)]
+    pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub destination: Option<String>,
+    #[serde(default)]
+    pub protocol: Option<String>,
+    /// Captured bytes as hex; absent when payloads are redacted
+    #[serde(default)]
+    pub payload_hex: Option<String>,
 }
 
 impl Default for CaptureConfig {
@@ -407,8 +613,29 @@ impl Default for CaptureConfig {
             max_packets: None,
             max_time: None,
             max_size: None,
+            rotation: None,
+        }
+    }
+}
+
+/// Reject interface names and filters that tcpdump would read as options
+///
+/// Live captures take these straight from web clients.
+fn validate_capture_args(config: &CaptureConfig) -> Result<()> {
+    let valid_interface = !config.interface.is_empty()
+        && config.interface.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '@'))
+        && !config.interface.starts_with('-');
+    if !valid_interface {
+        return Err(Error::Config(format!("Invalid capture interface: {}", config.interface)));
+    }
+
+    if let Some(filter) = &config.filter {
+        if filter.split_whitespace().any(|part| part.starts_with('-')) {
+            return Err(Error::Config(format!("Invalid capture filter: {}", filter)));
         }
     }
+
+    Ok(())
 }
 
 /// Common BPF filter examples
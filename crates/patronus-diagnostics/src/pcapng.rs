@@ -0,0 +1,338 @@
+//! pcapng Export
+//!
+//! Writes captures as pcapng so they open in Wireshark with interface
+//! metadata: a Section Header Block, one Interface Description Block
+//! carrying the interface name and link type, and Enhanced Packet Blocks
+//! with nanosecond timestamps. A small reader is included to inspect the
+//! blocks back.
+
+use crate::ring_capture::CapturedPacket;
+use patronus_core::{Error, Result};
+
+const BLOCK_SHB: u32 = 0x0a0d_0d0a;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_END: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+/// `if_tsresol` value for nanosecond timestamps (10^-9)
+const TSRESOL_NANOS: u8 = 9;
+
+/// Builds a single-interface pcapng file
+#[derive(Debug)]
+pub struct PcapNgWriter {
+    buf: Vec<u8>,
+}
+
+impl PcapNgWriter {
+    /// Start a file with its section header and interface description
+    pub fn new(interface: Option<&str>, linktype: u32, snaplen: u32) -> Self {
+        let mut writer = Self { buf: Vec::new() };
+
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes()); // major version
+        shb.extend_from_slice(&0u16.to_le_bytes()); // minor version
+        shb.extend_from_slice(&(-1i64).to_le_bytes()); // section length unknown
+        push_option(&mut shb, OPT_SHB_USERAPPL, b"patronus");
+        push_option(&mut shb, OPT_END, &[]);
+        writer.push_block(BLOCK_SHB, &shb);
+
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&(linktype as u16).to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes()); // reserved
+        idb.extend_from_slice(&snaplen.to_le_bytes());
+        if let Some(name) = interface {
+            push_option(&mut idb, OPT_IF_NAME, name.as_bytes());
+        }
+        push_option(&mut idb, OPT_IF_TSRESOL, &[TSRESOL_NANOS]);
+        push_option(&mut idb, OPT_END, &[]);
+        writer.push_block(BLOCK_IDB, &idb);
+
+        writer
+    }
+
+    /// Append a packet as an Enhanced Packet Block
+    pub fn write_packet(&mut self, packet: &CapturedPacket) {
+        let ts = packet.ts_sec as u64 * 1_000_000_000 + packet.ts_nsec as u64;
+
+        let mut epb = Vec::with_capacity(20 + packet.data.len() + 3);
+        epb.extend_from_slice(&0u32.to_le_bytes()); // interface id
+        epb.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(ts as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&packet.orig_len.to_le_bytes());
+        epb.extend_from_slice(&packet.data);
+        pad32(&mut epb);
+
+        self.push_block(BLOCK_EPB, &epb);
+    }
+
+    /// Finish and return the file contents
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn push_block(&mut self, block_type: u32, body: &[u8]) {
+        let total = (12 + body.len()) as u32;
+        self.buf.extend_from_slice(&block_type.to_le_bytes());
+        self.buf.extend_from_slice(&total.to_le_bytes());
+        self.buf.extend_from_slice(body);
+        self.buf.extend_from_slice(&total.to_le_bytes());
+    }
+}
+
+/// Encode packets as a complete pcapng file
+pub fn encode(interface: Option<&str>, linktype: u32, snaplen: u32, packets: &[CapturedPacket]) -> Vec<u8> {
+    let mut writer = PcapNgWriter::new(interface, linktype, snaplen);
+    for packet in packets {
+        writer.write_packet(packet);
+    }
+    writer.into_bytes()
+}
+
+/// Interface described in a pcapng file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapNgInterface {
+    pub name: Option<String>,
+    pub linktype: u16,
+    pub snaplen: u32,
+    /// Timestamp units per second
+    pub ts_units_per_sec: u64,
+}
+
+/// Contents of a pcapng file
+#[derive(Debug, Clone, Default)]
+pub struct PcapNgCapture {
+    pub interfaces: Vec<PcapNgInterface>,
+    /// Packets with the index of the interface they were captured on
+    pub packets: Vec<(u32, CapturedPacket)>,
+}
+
+/// Parse a little-endian pcapng file
+pub fn parse(data: &[u8]) -> Result<PcapNgCapture> {
+    let mut capture = PcapNgCapture::default();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let block_type = read_u32(data, pos)?;
+        let total = read_u32(data, pos + 4)? as usize;
+        if total < 12 || !total.is_multiple_of(4) || pos + total > data.len() {
+            return Err(malformed("bad block length"));
+        }
+        if read_u32(data, pos + total - 4)? as usize != total {
+            return Err(malformed("trailing block length mismatch"));
+        }
+        let body = &data[pos + 8..pos + total - 4];
+
+        match block_type {
+            BLOCK_SHB if read_u32(body, 0)? != BYTE_ORDER_MAGIC => {
+                return Err(malformed("unsupported byte order"));
+            }
+            BLOCK_IDB => {
+                let mut iface = PcapNgInterface {
+                    name: None,
+                    linktype: read_u16(body, 0)?,
+                    snaplen: read_u32(body, 4)?,
+                    ts_units_per_sec: 1_000_000,
+                };
+                for (code, value) in options(&body[8..])? {
+                    match code {
+                        OPT_IF_NAME => iface.name = Some(String::from_utf8_lossy(value).to_string()),
+                        OPT_IF_TSRESOL => {
+                            let res = *value.first().ok_or_else(|| malformed("empty if_tsresol"))?;
+                            iface.ts_units_per_sec = if res & 0x80 != 0 {
+                                1u64 << (res & 0x7f)
+                            } else {
+                                10u64.pow(res as u32)
+                            };
+                        }
+                        _ => {}
+                    }
+                }
+                capture.interfaces.push(iface);
+            }
+            BLOCK_EPB => {
+                let interface_id = read_u32(body, 0)?;
+                let iface = capture.interfaces.get(interface_id as usize)
+                    .ok_or_else(|| malformed("packet references unknown interface"))?;
+                let ts = ((read_u32(body, 4)? as u64) << 32) | read_u32(body, 8)? as u64;
+                let caplen = read_u32(body, 12)? as usize;
+                let orig_len = read_u32(body, 16)?;
+                let packet_data = body.get(20..20 + caplen).ok_or_else(|| malformed("packet truncated"))?;
+
+                let units = iface.ts_units_per_sec;
+                capture.packets.push((interface_id, CapturedPacket {
+                    ts_sec: (ts / units) as u32,
+                    ts_nsec: ((ts % units) * 1_000_000_000 / units) as u32,
+                    orig_len,
+                    data: packet_data.to_vec(),
+                }));
+            }
+            _ => {}
+        }
+
+        pos += total;
+    }
+
+    Ok(capture)
+}
+
+fn options(mut data: &[u8]) -> Result<Vec<(u16, &[u8])>> {
+    let mut opts = Vec::new();
+
+    while data.len() >= 4 {
+        let code = read_u16(data, 0)?;
+        let len = read_u16(data, 2)? as usize;
+        if code == OPT_END {
+            break;
+        }
+        let value = data.get(4..4 + len).ok_or_else(|| malformed("option truncated"))?;
+        opts.push((code, value));
+        data = &data[(4 + len.div_ceil(4) * 4).min(data.len())..];
+    }
+
+    Ok(opts)
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad32(buf);
+}
+
+fn pad32(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16> {
+    data.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Network(format!("Malformed pcapng: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packets() -> Vec<CapturedPacket> {
+        vec![
+            CapturedPacket { ts_sec: 1_700_000_000, ts_nsec: 123_456_789, orig_len: 60, data: vec![0xaa; 60] },
+            CapturedPacket { ts_sec: 1_700_000_001, ts_nsec: 1, orig_len: 1514, data: vec![0xbb; 61] },
+            CapturedPacket { ts_sec: 1_700_000_002, ts_nsec: 999_999_999, orig_len: 3, data: vec![1, 2, 3] },
+        ]
+    }
+
+    #[test]
+    fn test_pcapng_roundtrip_preserves_timestamps() {
+        let bytes = encode(Some("eth0"), 1, 65535, &packets());
+        assert!(bytes.len().is_multiple_of(4));
+        assert_eq!(&bytes[..4], &BLOCK_SHB.to_le_bytes());
+
+        let capture = parse(&bytes).unwrap();
+
+        assert_eq!(capture.interfaces, vec![PcapNgInterface {
+            name: Some("eth0".to_string()),
+            linktype: 1,
+            snaplen: 65535,
+            ts_units_per_sec: 1_000_000_000,
+        }]);
+
+        let parsed: Vec<CapturedPacket> = capture.packets.into_iter().map(|(id, p)| {
+            assert_eq!(id, 0);
+            p
+        }).collect();
+        assert_eq!(parsed, packets());
+    }
+
+    #[test]
+    fn test_pcapng_without_interface_name() {
+        let capture = parse(&encode(None, 113, 262144, &[])).unwrap();
+        assert_eq!(capture.interfaces[0].name, None);
+        assert_eq!(capture.interfaces[0].linktype, 113);
+        assert!(capture.packets.is_empty());
+    }
+
+    #[test]
+    fn test_pcapng_rejects_corrupt_length() {
+        let mut bytes = encode(Some("eth0"), 1, 65535, &packets());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(parse(&bytes).is_err());
+    }
+
+    /// pcapng reader written from the spec (draft-ietf-opsawg-pcapng) rather
+    /// than from `parse`, so a shared misreading cannot pass both
+    mod independent {
+        /// Timestamps of every EPB as (seconds, nanoseconds), applying the
+        /// `if_tsresol` of its interface (default 10^-6)
+        pub fn timestamps(data: &[u8]) -> Vec<(u64, u32)> {
+            let u16_at = |p: usize| u16::from_le_bytes([data[p], data[p + 1]]);
+            let u32_at = |p: usize| u32::from_le_bytes(data[p..p + 4].try_into().unwrap());
+
+            let mut units_per_sec: Vec<u64> = Vec::new();
+            let mut out = Vec::new();
+            let mut pos = 0;
+            while pos < data.len() {
+                let (kind, len) = (u32_at(pos), u32_at(pos + 4) as usize);
+                assert_eq!(u32_at(pos + len - 4) as usize, len, "trailing length");
+                match kind {
+                    0x0a0d0d0a => assert_eq!(u32_at(pos + 8), 0x1a2b3c4d),
+                    1 => {
+                        let mut resol = 6u8;
+                        let mut opt = pos + 16;
+                        while opt < pos + len - 4 {
+                            let (code, olen) = (u16_at(opt), u16_at(opt + 2) as usize);
+                            if code == 0 {
+                                break;
+                            }
+                            if code == 9 {
+                                resol = data[opt + 4];
+                            }
+                            opt += 4 + olen.div_ceil(4) * 4;
+                        }
+                        units_per_sec.push(if resol & 0x80 != 0 {
+                            1u64 << (resol & 0x7f)
+                        } else {
+                            10u64.pow(resol as u32)
+                        });
+                    }
+                    6 => {
+                        let units = units_per_sec[u32_at(pos + 8) as usize];
+                        let ts = ((u32_at(pos + 12) as u64) << 32) | u32_at(pos + 16) as u64;
+                        let frac = (ts % units) as u128 * 1_000_000_000 / units as u128;
+                        out.push((ts / units, frac as u32));
+                    }
+                    _ => {}
+                }
+                pos += len;
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn test_independent_reader_sees_nanoseconds() {
+        let times = independent::timestamps(&encode(Some("eth0"), 1, 65535, &packets()));
+        let expected: Vec<(u64, u32)> = packets().iter().map(|p| (p.ts_sec as u64, p.ts_nsec)).collect();
+        assert_eq!(times, expected);
+    }
+}
@@ -0,0 +1,185 @@
+//! Ping Statistics
+//!
+//! Accumulates echo replies into loss, RTT and jitter statistics. Replies
+//! are recorded in arrival order so duplicates and reordering are visible.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+/// Summary statistics for a ping run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingStats {
+    pub target: String,
+    pub packets_sent: u32,
+    /// Unique replies received (duplicates excluded)
+    pub packets_received: u32,
+    pub packet_loss_pct: f32,
+    pub duplicates: u32,
+    /// Replies that arrived after a reply to a later probe
+    pub out_of_order: u32,
+    pub min_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+    /// Standard deviation of RTT, as reported by `ping`
+    pub mdev_rtt_ms: Option<f64>,
+    /// Mean absolute difference between consecutive RTTs
+    pub jitter_ms: Option<f64>,
+    pub timestamp: SystemTime,
+}
+
+/// Collects echo replies for one target
+#[derive(Debug, Clone)]
+pub struct PingAccumulator {
+    target: String,
+    seen: HashSet<u32>,
+    highest_seq: Option<u32>,
+    rtts: Vec<f64>,
+    duplicates: u32,
+    out_of_order: u32,
+}
+
+impl PingAccumulator {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            seen: HashSet::new(),
+            highest_seq: None,
+            rtts: Vec::new(),
+            duplicates: 0,
+            out_of_order: 0,
+        }
+    }
+
+    /// Record a reply for probe `seq` in the order it arrived
+    pub fn record_reply(&mut self, seq: u32, rtt_ms: f64) {
+        if !self.seen.insert(seq) {
+            self.duplicates += 1;
+            return;
+        }
+
+        match self.highest_seq {
+            Some(highest) if seq < highest => self.out_of_order += 1,
+            _ => self.highest_seq = Some(seq),
+        }
+
+        self.rtts.push(rtt_ms);
+    }
+
+    /// Compute statistics given the number of probes sent
+    pub fn finish(&self, packets_sent: u32) -> PingStats {
+        let received = self.rtts.len() as u32;
+        let packet_loss_pct = if packets_sent > 0 {
+            (packets_sent.saturating_sub(received) as f32 / packets_sent as f32) * 100.0
+        } else {
+            0.0
+        };
+
+        let (min, avg, max, mdev) = if self.rtts.is_empty() {
+            (None, None, None, None)
+        } else {
+            let n = self.rtts.len() as f64;
+            let min = self.rtts.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = self.rtts.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let avg = self.rtts.iter().sum::<f64>() / n;
+            let variance = self.rtts.iter().map(|r| (r - avg).powi(2)).sum::<f64>() / n;
+            (Some(min), Some(avg), Some(max), Some(variance.sqrt()))
+        };
+
+        let jitter = if self.rtts.len() < 2 {
+            None
+        } else {
+            let diffs: f64 = self.rtts.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+            Some(diffs / (self.rtts.len() - 1) as f64)
+        };
+
+        PingStats {
+            target: self.target.clone(),
+            packets_sent,
+            packets_received: received,
+            packet_loss_pct,
+            duplicates: self.duplicates,
+            out_of_order: self.out_of_order,
+            min_rtt_ms: min,
+            avg_rtt_ms: avg,
+            max_rtt_ms: max,
+            mdev_rtt_ms: mdev,
+            jitter_ms: jitter,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+/// Parse a reply line such as
+/// `64 bytes from 10.0.0.1: icmp_seq=3 ttl=64 time=0.512 ms`
+pub(crate) fn parse_reply_line(line: &str) -> Option<(u32, f64)> {
+    let field = |name: &str| {
+        line.split_whitespace()
+            .find_map(|part| part.strip_prefix(name))
+    };
+
+    let seq = field("icmp_seq=").or_else(|| field("seq="))?.parse().ok()?;
+    let rtt = field("time=")?.trim_end_matches("ms").parse().ok()?;
+
+    Some((seq, rtt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_stats_math() {
+        let mut acc = PingAccumulator::new("10.0.0.1");
+        for (seq, rtt) in [(1, 10.0), (2, 20.0), (3, 15.0), (5, 25.0)] {
+            acc.record_reply(seq, rtt);
+        }
+
+        let stats = acc.finish(5);
+        assert_eq!(stats.packets_received, 4);
+        assert_eq!(stats.packet_loss_pct, 20.0);
+        assert_eq!(stats.min_rtt_ms, Some(10.0));
+        assert_eq!(stats.max_rtt_ms, Some(25.0));
+        assert_eq!(stats.avg_rtt_ms, Some(17.5));
+        // Population std dev of [10, 20, 15, 25]
+        assert!((stats.mdev_rtt_ms.unwrap() - 31.25f64.sqrt()).abs() < 1e-9);
+        // |20-10| + |15-20| + |25-15| over 3 intervals
+        assert!((stats.jitter_ms.unwrap() - 25.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_duplicates_and_reordering() {
+        let mut acc = PingAccumulator::new("2001:db8::1");
+        acc.record_reply(1, 5.0);
+        acc.record_reply(3, 5.0);
+        acc.record_reply(2, 9.0);
+        acc.record_reply(3, 5.5);
+
+        let stats = acc.finish(3);
+        assert_eq!(stats.packets_received, 3);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.packet_loss_pct, 0.0);
+    }
+
+    #[test]
+    fn test_all_lost() {
+        let stats = PingAccumulator::new("10.0.0.1").finish(4);
+        assert_eq!(stats.packet_loss_pct, 100.0);
+        assert!(stats.avg_rtt_ms.is_none());
+        assert!(stats.jitter_ms.is_none());
+    }
+
+    #[test]
+    fn test_parse_reply_line() {
+        assert_eq!(
+            parse_reply_line("64 bytes from 10.0.0.1: icmp_seq=3 ttl=64 time=0.512 ms"),
+            Some((3, 0.512))
+        );
+        assert_eq!(
+            parse_reply_line("64 bytes from 2001:db8::1: icmp_seq=7 ttl=58 time=12.1 ms (DUP!)"),
+            Some((7, 12.1))
+        );
+        assert_eq!(parse_reply_line("PING 10.0.0.1 (10.0.0.1) 56(84) bytes of data."), None);
+    }
+}
@@ -0,0 +1,584 @@
+//! Ring-Buffer Packet Capture
+//!
+//! Continuous capture that keeps only the most recent N megabytes of
+//! packets in memory. The buffer can be snapshotted to a pcap file on a
+//! trigger (e.g. when an alert fires), and the BPF filter can be replaced
+//! mid-capture without losing what has already been buffered.
+
+use crate::packet_capture::{CaptureConfig, CaptureFormat, CaptureStats};
+use crate::pcapng;
+use patronus_core::{Error, Result};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+/// Size of the pcap global header
+pub(crate) const PCAP_HEADER_LEN: usize = 24;
+
+/// Size of a pcap per-record header
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+/// Ethernet link type, used until the capture process reports its own
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// A single packet held in the ring buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    pub ts_sec: u32,
+    /// Sub-second timestamp in nanoseconds
+    pub ts_nsec: u32,
+    /// Length of the packet on the wire
+    pub orig_len: u32,
+    /// Captured bytes (at most snaplen)
+    pub data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Bytes this packet occupies in the buffer and in a pcap file
+    pub fn stored_len(&self) -> usize {
+        PCAP_RECORD_HEADER_LEN + self.data.len()
+    }
+
+    /// Append this packet as a nanosecond pcap record
+    pub(crate) fn write_record(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.ts_sec.to_le_bytes());
+        out.extend_from_slice(&self.ts_nsec.to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.orig_len.to_le_bytes());
+        out.extend_from_slice(&self.data);
+    }
+}
+
+/// Bounded in-memory packet store that evicts the oldest packets first
+#[derive(Debug, Clone)]
+pub struct PacketRingBuffer {
+    capacity_bytes: usize,
+    used_bytes: usize,
+    packets: VecDeque<CapturedPacket>,
+    linktype: u32,
+    snaplen: u32,
+    packets_seen: u64,
+    packets_evicted: u64,
+    packets_dropped: u64,
+}
+
+impl PacketRingBuffer {
+    pub fn new(capacity_bytes: usize, snaplen: u32) -> Self {
+        Self {
+            capacity_bytes,
+            used_bytes: 0,
+            packets: VecDeque::new(),
+            linktype: LINKTYPE_ETHERNET,
+            snaplen,
+            packets_seen: 0,
+            packets_evicted: 0,
+            packets_dropped: 0,
+        }
+    }
+
+    /// Add a packet, evicting the oldest packets until it fits
+    ///
+    /// Packets larger than the whole buffer are dropped.
+    pub fn push(&mut self, packet: CapturedPacket) {
+        self.packets_seen += 1;
+
+        let len = packet.stored_len();
+        if len > self.capacity_bytes {
+            self.packets_dropped += 1;
+            return;
+        }
+
+        while self.used_bytes + len > self.capacity_bytes {
+            match self.packets.pop_front() {
+                Some(old) => {
+                    self.used_bytes -= old.stored_len();
+                    self.packets_evicted += 1;
+                }
+                None => break,
+            }
+        }
+
+        self.used_bytes += len;
+        self.packets.push_back(packet);
+    }
+
+    pub fn set_linktype(&mut self, linktype: u32) {
+        self.linktype = linktype;
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn capacity_bytes(&self) -> usize {
+        self.capacity_bytes
+    }
+
+    /// Fill level as a percentage of capacity
+    pub fn fill_percent(&self) -> f64 {
+        if self.capacity_bytes == 0 {
+            0.0
+        } else {
+            self.used_bytes as f64 / self.capacity_bytes as f64 * 100.0
+        }
+    }
+
+    /// Number of packets evicted to make room for newer ones
+    pub fn packets_evicted(&self) -> u64 {
+        self.packets_evicted
+    }
+
+    pub fn packets(&self) -> impl Iterator<Item = &CapturedPacket> {
+        self.packets.iter()
+    }
+
+    /// Statistics for the buffer's current contents
+    pub fn stats(&self, duration_seconds: u64) -> CaptureStats {
+        CaptureStats {
+            packets_captured: self.packets_seen,
+            packets_dropped: self.packets_dropped,
+            bytes_captured: self.packets.iter().map(|p| p.data.len() as u64).sum(),
+            duration_seconds,
+            buffer_used_bytes: Some(self.used_bytes as u64),
+            buffer_capacity_bytes: Some(self.capacity_bytes as u64),
+        }
+    }
+
+    /// Link type of the buffered packets
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+
+    /// Snapshot length of the capture
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+
+    /// Serialize the buffered packets as a nanosecond pcap file, oldest first
+    pub fn to_pcap(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(PCAP_HEADER_LEN + self.used_bytes);
+
+        out.extend_from_slice(&pcap_header(self.snaplen, self.linktype));
+        for packet in &self.packets {
+            packet.write_record(&mut out);
+        }
+
+        out
+    }
+}
+
+/// Nanosecond-resolution pcap global header
+pub(crate) fn pcap_header(snaplen: u32, linktype: u32) -> [u8; PCAP_HEADER_LEN] {
+    let mut header = [0u8; PCAP_HEADER_LEN];
+    header[0..4].copy_from_slice(&PCAP_MAGIC_NANOS.to_le_bytes());
+    header[4..6].copy_from_slice(&2u16.to_le_bytes()); // version major
+    header[6..8].copy_from_slice(&4u16.to_le_bytes()); // version minor
+    // thiszone and sigfigs stay zero
+    header[16..20].copy_from_slice(&snaplen.to_le_bytes());
+    header[20..24].copy_from_slice(&linktype.to_le_bytes());
+    header
+}
+
+impl PacketRingBuffer {
+    /// Serialize the buffered packets as pcapng, oldest first
+    pub fn to_pcapng(&self, interface: Option<&str>) -> Vec<u8> {
+        let mut writer = pcapng::PcapNgWriter::new(interface, self.linktype, self.snaplen);
+        for packet in &self.packets {
+            writer.write_packet(packet);
+        }
+        writer.into_bytes()
+    }
+}
+
+/// Incremental parser for a pcap byte stream (e.g. `tcpdump -w -`)
+#[derive(Debug, Default)]
+pub struct PcapStreamParser {
+    pending: Vec<u8>,
+    header: Option<PcapHeader>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PcapHeader {
+    big_endian: bool,
+    nanos: bool,
+    linktype: u32,
+}
+
+impl PcapStreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Link type from the stream's global header, once it has been read
+    pub fn linktype(&self) -> Option<u32> {
+        self.header.map(|h| h.linktype)
+    }
+
+    /// Feed raw bytes and return every complete packet they finish
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<CapturedPacket>> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut offset = 0;
+        let mut packets = Vec::new();
+
+        if self.header.is_none() {
+            if self.pending.len() < PCAP_HEADER_LEN {
+                return Ok(packets);
+            }
+            self.header = Some(Self::parse_header(&self.pending[..PCAP_HEADER_LEN])?);
+            offset = PCAP_HEADER_LEN;
+        }
+
+        let header = self.header.expect("pcap header parsed above");
+        let read_u32 = |b: &[u8]| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if header.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+        };
+
+        while self.pending.len() - offset >= PCAP_RECORD_HEADER_LEN {
+            let rec = &self.pending[offset..offset + PCAP_RECORD_HEADER_LEN];
+            let incl_len = read_u32(&rec[8..12]) as usize;
+            let end = offset + PCAP_RECORD_HEADER_LEN + incl_len;
+            if self.pending.len() < end {
+                break;
+            }
+
+            let ts_frac = read_u32(&rec[4..8]);
+            packets.push(CapturedPacket {
+                ts_sec: read_u32(&rec[0..4]),
+                ts_nsec: if header.nanos { ts_frac } else { ts_frac.saturating_mul(1000) },
+                orig_len: read_u32(&rec[12..16]),
+                data: self.pending[offset + PCAP_RECORD_HEADER_LEN..end].to_vec(),
+            });
+            offset = end;
+        }
+
+        self.pending.drain(..offset);
+        Ok(packets)
+    }
+
+    fn parse_header(bytes: &[u8]) -> Result<PcapHeader> {
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC, _) => (false, false),
+            (PCAP_MAGIC_NANOS, _) => (false, true),
+            (_, PCAP_MAGIC) => (true, false),
+            (_, PCAP_MAGIC_NANOS) => (true, true),
+            _ => return Err(Error::Network("Capture output is not in pcap format".to_string())),
+        };
+
+        let lt = [bytes[20], bytes[21], bytes[22], bytes[23]];
+        let linktype = if big_endian { u32::from_be_bytes(lt) } else { u32::from_le_bytes(lt) };
+
+        Ok(PcapHeader { big_endian, nanos, linktype })
+    }
+}
+
+/// A running ring-buffer capture
+pub struct RingCapture {
+    pub id: String,
+    pub interface: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    config: CaptureConfig,
+    buffer: Arc<Mutex<PacketRingBuffer>>,
+    process: Option<Child>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl RingCapture {
+    /// Start capturing into a buffer of `capacity_mb` megabytes
+    pub(crate) fn start(config: CaptureConfig, capacity_mb: u32) -> Result<Self> {
+        let capacity_bytes = capacity_mb as usize * 1024 * 1024;
+        let buffer = Arc::new(Mutex::new(PacketRingBuffer::new(capacity_bytes, config.snaplen)));
+
+        let mut capture = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            interface: config.interface.clone(),
+            started_at: chrono::Utc::now(),
+            config,
+            buffer,
+            process: None,
+            reader: None,
+        };
+
+        tracing::info!("Starting ring-buffer capture on {} ({} MB)",
+            capture.interface, capacity_mb);
+
+        capture.spawn()?;
+        Ok(capture)
+    }
+
+    /// Current BPF filter
+    pub fn filter(&self) -> Option<&str> {
+        self.config.filter.as_deref()
+    }
+
+    /// Replace the BPF filter without discarding buffered packets
+    ///
+    /// The capture process is restarted with the new filter; packets already
+    /// in the buffer are kept.
+    pub async fn set_filter(&mut self, filter: Option<String>) -> Result<()> {
+        tracing::info!("Changing ring capture {} filter to {:?}", self.id, filter);
+
+        self.kill().await?;
+        self.config.filter = filter;
+        self.spawn()
+    }
+
+    /// Current capture statistics, including buffer fill level
+    pub fn stats(&self) -> CaptureStats {
+        let duration = (chrono::Utc::now() - self.started_at).num_seconds() as u64;
+        self.buffer.lock().unwrap().stats(duration)
+    }
+
+    /// Write the buffer's current contents to a pcap file
+    pub async fn snapshot(&self, path: &Path) -> Result<()> {
+        self.snapshot_as(path, CaptureFormat::Pcap).await
+    }
+
+    /// Write the buffer's current contents to a file in `format`
+    pub async fn snapshot_as(&self, path: &Path, format: CaptureFormat) -> Result<()> {
+        let bytes = {
+            let buffer = self.buffer.lock().unwrap();
+            match format {
+                CaptureFormat::Pcap => buffer.to_pcap(),
+                CaptureFormat::PcapNg => buffer.to_pcapng(Some(&self.interface)),
+                CaptureFormat::Text => {
+                    return Err(Error::Config("Ring snapshots support pcap and pcapng only".to_string()));
+                }
+            }
+        };
+
+        tokio::fs::write(path, bytes).await?;
+        Ok(())
+    }
+
+    /// Stop capturing; buffered packets remain available for snapshots
+    pub async fn stop(&mut self) -> Result<CaptureStats> {
+        self.kill().await?;
+        Ok(self.stats())
+    }
+
+    fn spawn(&mut self) -> Result<()> {
+        let mut child = stream_command(&self.config, None)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let mut stdout = child.stdout.take()
+            .ok_or_else(|| Error::Network("Capture process has no stdout".to_string()))?;
+        let buffer = self.buffer.clone();
+        let id = self.id.clone();
+
+        self.reader = Some(tokio::spawn(async move {
+            let mut parser = PcapStreamParser::new();
+            let mut chunk = vec![0u8; 64 * 1024];
+
+            loop {
+                let n = match stdout.read(&mut chunk).await {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) => {
+                        tracing::warn!("Ring capture {} read failed: {}", id, e);
+                        break;
+                    }
+                };
+
+                match parser.feed(&chunk[..n]) {
+                    Ok(packets) => {
+                        let mut buffer = buffer.lock().unwrap();
+                        if let Some(linktype) = parser.linktype() {
+                            buffer.set_linktype(linktype);
+                        }
+                        for packet in packets {
+                            buffer.push(packet);
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Ring capture {} stopped: {}", id, e);
+                        break;
+                    }
+                }
+            }
+        }));
+        self.process = Some(child);
+
+        Ok(())
+    }
+
+    async fn kill(&mut self) -> Result<()> {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.start_kill();
+            let _ = process.wait().await?;
+        }
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.await;
+        }
+        Ok(())
+    }
+}
+
+/// tcpdump writing packet-buffered pcap to stdout
+pub(crate) fn stream_command(config: &CaptureConfig, max_packets: Option<u32>) -> Command {
+    let mut cmd = Command::new("tcpdump");
+    cmd.arg("-i").arg(&config.interface);
+    cmd.arg("-s").arg(config.snaplen.to_string());
+    cmd.arg("-B").arg((config.buffer_size * 1024).to_string());
+    cmd.arg("-U"); // Packet-buffered output
+    // Match the nanosecond if_tsresol of pcapng exports instead of padding
+    // microsecond timestamps with zeros
+    cmd.arg("--time-stamp-precision=nano");
+    cmd.arg("-w").arg("-");
+
+    if !config.promiscuous {
+        cmd.arg("-p");
+    }
+
+    if let Some(count) = max_packets {
+        cmd.arg("-c").arg(count.to_string());
+    }
+
+    if let Some(filter) = &config.filter {
+        for part in filter.split_whitespace() {
+            cmd.arg(part);
+        }
+    }
+
+    cmd
+}
+
+/// Default snapshot file name for a ring capture
+pub(crate) fn snapshot_path(dir: &Path, capture: &RingCapture, extension: &str) -> PathBuf {
+    dir.join(format!("ring-{}-{}.{}",
+        capture.id, chrono::Utc::now().format("%Y%m%dT%H%M%S"), extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(seq: u32, len: usize) -> CapturedPacket {
+        CapturedPacket {
+            ts_sec: 1_700_000_000 + seq,
+            ts_nsec: 123_456_789,
+            orig_len: len as u32,
+            data: vec![seq as u8; len],
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_evicts_oldest() {
+        // Room for exactly four 84-byte records
+        let mut buffer = PacketRingBuffer::new(4 * 100, 65535);
+
+        for seq in 0..10 {
+            buffer.push(packet(seq, 84));
+        }
+
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.used_bytes(), 400);
+        assert_eq!(buffer.fill_percent(), 100.0);
+        assert_eq!(buffer.packets_evicted(), 6);
+
+        let kept: Vec<u32> = buffer.packets().map(|p| p.ts_sec - 1_700_000_000).collect();
+        assert_eq!(kept, vec![6, 7, 8, 9]);
+
+        // A larger packet evicts as many old ones as it needs
+        buffer.push(packet(10, 250));
+        let kept: Vec<u32> = buffer.packets().map(|p| p.ts_sec - 1_700_000_000).collect();
+        assert_eq!(kept, vec![9, 10]);
+        assert!(buffer.used_bytes() <= buffer.capacity_bytes());
+
+        let stats = buffer.stats(0);
+        assert_eq!(stats.packets_captured, 11);
+        assert_eq!(stats.buffer_used_bytes, Some(100 + 266));
+        assert_eq!(stats.buffer_capacity_bytes, Some(400));
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oversized_packet() {
+        let mut buffer = PacketRingBuffer::new(100, 65535);
+        buffer.push(packet(0, 50));
+        buffer.push(packet(1, 500));
+
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.stats(0).packets_dropped, 1);
+    }
+
+    #[test]
+    fn test_snapshot_parses_back() {
+        let mut buffer = PacketRingBuffer::new(1024, 65535);
+        for seq in 0..3 {
+            buffer.push(packet(seq, 60));
+        }
+
+        let pcap = buffer.to_pcap();
+        assert_eq!(pcap.len(), PCAP_HEADER_LEN + buffer.used_bytes());
+
+        // Feed in awkward chunk sizes to exercise partial records
+        let mut parser = PcapStreamParser::new();
+        let mut parsed = Vec::new();
+        for chunk in pcap.chunks(7) {
+            parsed.extend(parser.feed(chunk).unwrap());
+        }
+
+        assert_eq!(parser.linktype(), Some(LINKTYPE_ETHERNET));
+        assert_eq!(parsed, buffer.packets().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parser_rejects_non_pcap() {
+        let mut parser = PcapStreamParser::new();
+        assert!(parser.feed(&[0u8; PCAP_HEADER_LEN]).is_err());
+    }
+
+    #[test]
+    fn test_pcap_to_pcapng_keeps_nanoseconds() {
+        let mut buffer = PacketRingBuffer::new(1024, 65535);
+        buffer.push(packet(0, 60));
+
+        let capture = pcapng::parse(&buffer.to_pcapng(Some("wan0"))).unwrap();
+        assert_eq!(capture.interfaces[0].name.as_deref(), Some("wan0"));
+        assert_eq!(capture.packets[0].1.ts_nsec, 123_456_789);
+    }
+
+    #[test]
+    fn test_stream_command_requests_nanoseconds() {
+        let config = CaptureConfig { interface: "wan0".to_string(), ..CaptureConfig::default() };
+        let cmd = stream_command(&config, Some(10));
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&std::ffi::OsStr::new("--time-stamp-precision=nano")));
+    }
+
+    #[test]
+    fn test_microsecond_stream_scales_to_nanoseconds() {
+        // What a tcpdump without nanosecond support writes
+        let mut pcap = Vec::new();
+        for field in [PCAP_MAGIC, 0x0004_0002, 0, 0, 65535, LINKTYPE_ETHERNET] {
+            pcap.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [1_700_000_000u32, 123_456, 4, 4] {
+            pcap.extend_from_slice(&field.to_le_bytes());
+        }
+        pcap.extend_from_slice(&[1, 2, 3, 4]);
+
+        let packets = PcapStreamParser::new().feed(&pcap).unwrap();
+        assert_eq!(packets[0].ts_nsec, 123_456_000);
+    }
+}
@@ -0,0 +1,698 @@
+//! Rotating File Capture
+//!
+//! Long-running capture to disk that starts a new pcap file every N
+//! megabytes or M seconds and keeps at most K files per session. Every
+//! rotating session of a manager shares one [`DiskQuota`]: when a write
+//! would exceed it the oldest finished file, from any session, is evicted,
+//! and when nothing is left to evict the session is stopped and a
+//! [`CaptureEvent::QuotaExceeded`] alert is raised.
+//!
+//! tcpdump streams pcap to us instead of rotating files itself, so a file
+//! is only ever cut between complete records and no packet is lost at a
+//! boundary.
+
+use crate::packet_capture::{CaptureConfig, CaptureInfo};
+use crate::pcapng::PcapNgWriter;
+use crate::ring_capture::{self, CapturedPacket, PcapStreamParser, PCAP_HEADER_LEN};
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+use tokio::process::Child;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Quota shared by all rotating captures unless configured otherwise
+pub const DEFAULT_DISK_QUOTA_MB: u64 = 2048;
+
+const MB: u64 = 1024 * 1024;
+
+/// When a rotating capture starts a new file and how many it keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationPolicy {
+    /// Start a new file before the current one would exceed this size
+    pub file_size_mb: u32,
+    /// Start a new file once the current one spans this many seconds of packets
+    #[serde(default)]
+    pub file_seconds: Option<u32>,
+    /// Files kept per session; the oldest is deleted on rotation
+    pub max_files: u32,
+}
+
+impl RotationPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.file_size_mb == 0 {
+            return Err(Error::Config("Rotation file size must be non-zero".to_string()));
+        }
+        if self.max_files == 0 {
+            return Err(Error::Config("Rotation must keep at least one file".to_string()));
+        }
+        if self.file_seconds == Some(0) {
+            return Err(Error::Config("Rotation interval must be non-zero".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self {
+            file_size_mb: 100,
+            file_seconds: None,
+            max_files: 10,
+        }
+    }
+}
+
+/// Notifications raised by rotating captures
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CaptureEvent {
+    /// A finished file was deleted to stay within the disk quota
+    FileEvicted { session_id: String, path: PathBuf },
+    /// A session was stopped because its next write would exceed the quota
+    QuotaExceeded { session_id: String, limit_bytes: u64, used_bytes: u64 },
+}
+
+/// Disk space shared by the rotating captures of one manager
+///
+/// Tracks every rotated file on disk, oldest first, and is the source of
+/// truth for which files a session still has.
+#[derive(Debug)]
+pub struct DiskQuota {
+    limit_bytes: u64,
+    state: Mutex<QuotaState>,
+    events: broadcast::Sender<CaptureEvent>,
+}
+
+#[derive(Debug, Default)]
+struct QuotaState {
+    used_bytes: u64,
+    files: VecDeque<TrackedFile>,
+}
+
+#[derive(Debug)]
+struct TrackedFile {
+    session_id: String,
+    path: PathBuf,
+    size_bytes: u64,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// Still being written; never evicted
+    open: bool,
+}
+
+impl DiskQuota {
+    pub fn new(limit_bytes: u64) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            limit_bytes,
+            state: Mutex::new(QuotaState::default()),
+            events,
+        }
+    }
+
+    pub fn limit_bytes(&self) -> u64 {
+        self.limit_bytes
+    }
+
+    /// Bytes currently held by rotated files
+    pub fn used_bytes(&self) -> u64 {
+        self.state.lock().unwrap().used_bytes
+    }
+
+    /// Receive eviction and quota alerts
+    pub fn subscribe(&self) -> broadcast::Receiver<CaptureEvent> {
+        self.events.subscribe()
+    }
+
+    /// Files a session still has on disk, oldest first
+    pub fn files(&self, session_id: &str) -> Vec<CaptureInfo> {
+        self.state.lock().unwrap().files.iter()
+            .filter(|f| f.session_id == session_id)
+            .map(|f| CaptureInfo {
+                filename: f.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                path: f.path.clone(),
+                size_bytes: f.size_bytes,
+                created_at: Some(f.created_at),
+            })
+            .collect()
+    }
+
+    /// Stop tracking a file that was deleted by other means
+    pub(crate) fn forget(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(pos) = state.files.iter().position(|f| f.path == path) {
+            let file = state.files.remove(pos).expect("position is in range");
+            state.used_bytes -= file.size_bytes;
+        }
+    }
+
+    /// Start tracking a new file, reserving `bytes` for its header
+    ///
+    /// On failure returns the bytes in use after evicting everything possible.
+    fn open_file(&self, session_id: &str, path: &Path, bytes: u64) -> std::result::Result<(), u64> {
+        let mut state = self.state.lock().unwrap();
+        self.make_room(&mut state, bytes)?;
+
+        state.used_bytes += bytes;
+        state.files.push_back(TrackedFile {
+            session_id: session_id.to_string(),
+            path: path.to_path_buf(),
+            size_bytes: bytes,
+            created_at: chrono::Utc::now(),
+            open: true,
+        });
+        Ok(())
+    }
+
+    /// Account for `bytes` about to be appended to an open file
+    fn charge(&self, path: &Path, bytes: u64) -> std::result::Result<(), u64> {
+        let mut state = self.state.lock().unwrap();
+        self.make_room(&mut state, bytes)?;
+
+        state.used_bytes += bytes;
+        // The file being written is almost always at the back
+        if let Some(file) = state.files.iter_mut().rev().find(|f| f.path == path) {
+            file.size_bytes += bytes;
+        }
+        Ok(())
+    }
+
+    fn close_file(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.files.iter_mut().rev().find(|f| f.path == path) {
+            file.open = false;
+        }
+    }
+
+    /// Delete a session's oldest finished files until at most `keep` remain
+    fn trim_session(&self, session_id: &str, keep: usize) {
+        let mut state = self.state.lock().unwrap();
+        let mut count = state.files.iter().filter(|f| f.session_id == session_id).count();
+
+        while count > keep {
+            let Some(pos) = state.files.iter().position(|f| f.session_id == session_id && !f.open) else {
+                break;
+            };
+            let file = state.files.remove(pos).expect("position is in range");
+            state.used_bytes -= file.size_bytes;
+            remove_file(&file.path);
+            count -= 1;
+        }
+    }
+
+    /// Evict the oldest finished files until `bytes` more fit under the limit
+    fn make_room(&self, state: &mut QuotaState, bytes: u64) -> std::result::Result<(), u64> {
+        while state.used_bytes + bytes > self.limit_bytes {
+            let Some(pos) = state.files.iter().position(|f| !f.open) else {
+                return Err(state.used_bytes);
+            };
+            let file = state.files.remove(pos).expect("position is in range");
+            state.used_bytes -= file.size_bytes;
+            remove_file(&file.path);
+
+            tracing::info!("Evicted capture file {} to stay within disk quota", file.path.display());
+            let _ = self.events.send(CaptureEvent::FileEvicted {
+                session_id: file.session_id,
+                path: file.path,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to delete capture file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Writes a packet stream into size- and time-bounded pcap files
+#[derive(Debug)]
+pub struct RotatingWriter {
+    session_id: String,
+    dir: PathBuf,
+    policy: RotationPolicy,
+    quota: Arc<DiskQuota>,
+    snaplen: u32,
+    linktype: u32,
+    next_index: u32,
+    current: Option<OpenFile>,
+    packets_written: u64,
+    bytes_written: u64,
+    quota_exceeded: bool,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+    size_bytes: u64,
+    packets: u64,
+    first_ts_sec: Option<u32>,
+}
+
+/// Totals of a finished rotating writer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RotationSummary {
+    pub packets_written: u64,
+    /// Captured packet bytes, excluding pcap headers
+    pub bytes_written: u64,
+    pub quota_exceeded: bool,
+}
+
+impl RotatingWriter {
+    pub fn new(session_id: &str, dir: &Path, policy: RotationPolicy, quota: Arc<DiskQuota>, snaplen: u32) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            dir: dir.to_path_buf(),
+            policy,
+            quota,
+            snaplen,
+            linktype: 1,
+            next_index: 0,
+            current: None,
+            packets_written: 0,
+            bytes_written: 0,
+            quota_exceeded: false,
+        }
+    }
+
+    /// Link type written into the header of files opened from now on
+    pub fn set_linktype(&mut self, linktype: u32) {
+        self.linktype = linktype;
+    }
+
+    /// Append a packet, rotating first if the current file is full
+    ///
+    /// Once the disk quota cannot take another packet the current file is
+    /// closed, a [`CaptureEvent::QuotaExceeded`] alert is raised, and this
+    /// and every later write fails.
+    pub fn write(&mut self, packet: &CapturedPacket) -> Result<()> {
+        if self.quota_exceeded {
+            return Err(self.quota_error());
+        }
+
+        if self.current.as_ref().is_some_and(|file| self.should_rotate(file, packet)) {
+            self.close_current()?;
+            self.quota.trim_session(&self.session_id, (self.policy.max_files as usize).saturating_sub(1));
+        }
+        if self.current.is_none() {
+            self.open_next()?;
+        }
+
+        let len = packet.stored_len() as u64;
+        let path = &self.current.as_ref().expect("file opened above").path;
+        if let Err(used_bytes) = self.quota.charge(path, len) {
+            return self.stop_for_quota(used_bytes);
+        }
+
+        let mut record = Vec::with_capacity(len as usize);
+        packet.write_record(&mut record);
+
+        let file = self.current.as_mut().expect("file opened above");
+        file.out.write_all(&record)?;
+        file.size_bytes += len;
+        file.packets += 1;
+        file.first_ts_sec.get_or_insert(packet.ts_sec);
+
+        self.packets_written += 1;
+        self.bytes_written += packet.data.len() as u64;
+        Ok(())
+    }
+
+    /// Push buffered records to disk so the current file can be read
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(file) = &mut self.current {
+            file.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Close the current file and return the writer's totals
+    pub fn finish(mut self) -> Result<RotationSummary> {
+        self.close_current()?;
+        Ok(self.summary())
+    }
+
+    pub fn summary(&self) -> RotationSummary {
+        RotationSummary {
+            packets_written: self.packets_written,
+            bytes_written: self.bytes_written,
+            quota_exceeded: self.quota_exceeded,
+        }
+    }
+
+    fn should_rotate(&self, file: &OpenFile, packet: &CapturedPacket) -> bool {
+        // A file always takes at least one packet, however large
+        if file.packets == 0 {
+            return false;
+        }
+
+        let max_bytes = self.policy.file_size_mb as u64 * MB;
+        let too_big = file.size_bytes + packet.stored_len() as u64 > max_bytes;
+        let too_old = match (self.policy.file_seconds, file.first_ts_sec) {
+            (Some(secs), Some(first)) => packet.ts_sec.saturating_sub(first) >= secs,
+            _ => false,
+        };
+        too_big || too_old
+    }
+
+    fn open_next(&mut self) -> Result<()> {
+        let path = file_path(&self.dir, &self.session_id, self.next_index);
+
+        if let Err(used_bytes) = self.quota.open_file(&self.session_id, &path, PCAP_HEADER_LEN as u64) {
+            return self.stop_for_quota(used_bytes);
+        }
+
+        let mut out = match File::create(&path) {
+            Ok(file) => BufWriter::with_capacity(256 * 1024, file),
+            Err(e) => {
+                self.quota.forget(&path);
+                return Err(e.into());
+            }
+        };
+        out.write_all(&ring_capture::pcap_header(self.snaplen, self.linktype))?;
+
+        tracing::debug!("Capture {} rotated to {}", self.session_id, path.display());
+
+        self.next_index += 1;
+        self.current = Some(OpenFile {
+            path,
+            out,
+            size_bytes: PCAP_HEADER_LEN as u64,
+            packets: 0,
+            first_ts_sec: None,
+        });
+        Ok(())
+    }
+
+    fn close_current(&mut self) -> Result<()> {
+        if let Some(mut file) = self.current.take() {
+            let flushed = file.out.flush();
+            self.quota.close_file(&file.path);
+            flushed?;
+        }
+        Ok(())
+    }
+
+    fn stop_for_quota(&mut self, used_bytes: u64) -> Result<()> {
+        self.quota_exceeded = true;
+        self.close_current()?;
+
+        tracing::error!("Capture {} stopped: disk quota of {} bytes exhausted ({} bytes in use)",
+            self.session_id, self.quota.limit_bytes(), used_bytes);
+        let _ = self.quota.events.send(CaptureEvent::QuotaExceeded {
+            session_id: self.session_id.clone(),
+            limit_bytes: self.quota.limit_bytes(),
+            used_bytes,
+        });
+
+        Err(self.quota_error())
+    }
+
+    fn quota_error(&self) -> Error {
+        Error::Service(format!("Capture {} stopped: disk quota exceeded", self.session_id))
+    }
+}
+
+/// File name of a session's `index`th rotated file
+pub(crate) fn file_path(dir: &Path, session_id: &str, index: u32) -> PathBuf {
+    dir.join(format!("{}-{:05}.pcap", session_id, index))
+}
+
+/// Background half of a rotating capture session
+#[derive(Debug)]
+pub(crate) struct RotatingFiles {
+    pub(crate) quota: Arc<DiskQuota>,
+    writer: Option<JoinHandle<RotationSummary>>,
+    summary: Option<RotationSummary>,
+}
+
+impl RotatingFiles {
+    /// Wait for the writer to drain the stream and close its last file
+    pub(crate) async fn finish(&mut self) -> RotationSummary {
+        if let Some(writer) = self.writer.take() {
+            match writer.await {
+                Ok(summary) => self.summary = Some(summary),
+                Err(e) => tracing::warn!("Rotating capture writer failed: {}", e),
+            }
+        }
+        self.summary.unwrap_or_default()
+    }
+}
+
+/// Start tcpdump streaming into a rotating writer
+pub(crate) fn spawn(config: &CaptureConfig, writer: RotatingWriter) -> Result<(Child, RotatingFiles)> {
+    let quota = writer.quota.clone();
+
+    let mut child = ring_capture::stream_command(config, config.max_packets)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdout = child.stdout.take()
+        .ok_or_else(|| Error::Network("Capture process has no stdout".to_string()))?;
+
+    // Reads never wait on disk; the channel absorbs bursts and pushes back
+    // on tcpdump only when the writer falls behind
+    let (tx, rx) = mpsc::channel::<Vec<u8>>(256);
+    tokio::spawn(async move {
+        let mut chunk = vec![0u8; 64 * 1024];
+        loop {
+            let n = match stdout.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::warn!("Rotating capture read failed: {}", e);
+                    break;
+                }
+            };
+            // The writer hung up after a hard stop; dropping stdout ends tcpdump
+            if tx.send(chunk[..n].to_vec()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let files = RotatingFiles {
+        quota,
+        writer: Some(spawn_writer(rx, writer)),
+        summary: None,
+    };
+    Ok((child, files))
+}
+
+/// Parse pcap chunks from `rx` and write them until the stream ends or stops
+fn spawn_writer(mut rx: mpsc::Receiver<Vec<u8>>, mut writer: RotatingWriter) -> JoinHandle<RotationSummary> {
+    tokio::task::spawn_blocking(move || {
+        let mut parser = PcapStreamParser::new();
+
+        while let Some(chunk) = rx.blocking_recv() {
+            if let Err(e) = write_chunk(&mut writer, &mut parser, &chunk) {
+                tracing::warn!("Rotating capture {} stopped: {}", writer.session_id, e);
+                break;
+            }
+        }
+
+        let summary = writer.summary();
+        writer.finish().unwrap_or_else(|e| {
+            tracing::warn!("Failed to close rotating capture file: {}", e);
+            summary
+        })
+    })
+}
+
+fn write_chunk(writer: &mut RotatingWriter, parser: &mut PcapStreamParser, chunk: &[u8]) -> Result<()> {
+    let packets = parser.feed(chunk)?;
+    if let Some(linktype) = parser.linktype() {
+        writer.set_linktype(linktype);
+    }
+    for packet in &packets {
+        writer.write(packet)?;
+    }
+    writer.flush()
+}
+
+/// Concatenate rotated files into one pcapng, keeping every timestamp
+///
+/// Files evicted since `files` was listed are skipped.
+pub(crate) async fn export_merged(files: &[CaptureInfo], interface: &str, output_file: &Path) -> Result<()> {
+    let mut linktype = None;
+    let mut packets = Vec::new();
+
+    for file in files {
+        let bytes = match tokio::fs::read(&file.path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!("Skipping evicted capture file {}", file.path.display());
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut parser = PcapStreamParser::new();
+        packets.extend(parser.feed(&bytes)?);
+        linktype = linktype.or(parser.linktype());
+    }
+
+    let linktype = linktype
+        .ok_or_else(|| Error::Network("Capture has no readable files".to_string()))?;
+    let snaplen = packets.iter().map(|p| p.data.len() as u32).max().unwrap_or(0).max(65535);
+
+    let mut writer = PcapNgWriter::new(Some(interface), linktype, snaplen);
+    for packet in &packets {
+        writer.write_packet(packet);
+    }
+    tokio::fs::write(output_file, writer.into_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcapng;
+
+    fn packet(seq: u32, ts_sec: u32, len: usize) -> CapturedPacket {
+        let mut data = vec![0u8; len];
+        data[..4].copy_from_slice(&seq.to_le_bytes());
+        CapturedPacket {
+            ts_sec,
+            ts_nsec: seq % 1_000_000_000,
+            orig_len: len as u32,
+            data,
+        }
+    }
+
+    fn read_seqs(path: &Path) -> Vec<u32> {
+        let mut parser = PcapStreamParser::new();
+        parser.feed(&std::fs::read(path).unwrap()).unwrap()
+            .iter()
+            .map(|p| u32::from_le_bytes(p.data[..4].try_into().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rotation_never_drops_packets_at_boundaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = Arc::new(DiskQuota::new(64 * MB));
+        let policy = RotationPolicy { file_size_mb: 1, file_seconds: None, max_files: 100 };
+        let writer = RotatingWriter::new("s1", dir.path(), policy, quota.clone(), 65535);
+
+        // 60k packets of varying size as a pcap byte stream, delivered in
+        // chunks that split records, as tcpdump's pipe does
+        let total = 60_000u32;
+        let mut stream = ring_capture::pcap_header(65535, 1).to_vec();
+        for seq in 0..total {
+            packet(seq, 1_700_000_000 + seq / 10_000, 60 + (seq as usize * 7) % 1400)
+                .write_record(&mut stream);
+        }
+
+        let (tx, rx) = mpsc::channel(8);
+        let handle = spawn_writer(rx, writer);
+        for chunk in stream.chunks(4093) {
+            tx.send(chunk.to_vec()).await.unwrap();
+        }
+        drop(tx);
+        let summary = handle.await.unwrap();
+
+        assert_eq!(summary.packets_written, total as u64);
+        assert!(!summary.quota_exceeded);
+
+        let files = quota.files("s1");
+        assert!(files.len() > 20, "expected many rotations, got {}", files.len());
+
+        let mut seqs = Vec::new();
+        for file in &files {
+            let size = std::fs::metadata(&file.path).unwrap().len();
+            assert_eq!(size, file.size_bytes);
+            assert!(size <= MB);
+            seqs.extend(read_seqs(&file.path));
+        }
+        assert_eq!(seqs, (0..total).collect::<Vec<_>>());
+        assert_eq!(quota.used_bytes(), files.iter().map(|f| f.size_bytes).sum::<u64>());
+
+        // Merging keeps every packet and nanosecond timestamp in order
+        let merged = dir.path().join("merged.pcapng");
+        export_merged(&files, "wan0", &merged).await.unwrap();
+        let capture = pcapng::parse(&std::fs::read(&merged).unwrap()).unwrap();
+        assert_eq!(capture.interfaces[0].name.as_deref(), Some("wan0"));
+        assert_eq!(capture.packets.len(), total as usize);
+        assert_eq!(capture.packets[12_345].1.ts_sec, 1_700_000_001);
+        assert_eq!(capture.packets[12_345].1.ts_nsec, 12_345);
+    }
+
+    #[test]
+    fn test_time_rotation_and_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = Arc::new(DiskQuota::new(64 * MB));
+        let policy = RotationPolicy { file_size_mb: 1, file_seconds: Some(10), max_files: 3 };
+        let mut writer = RotatingWriter::new("s1", dir.path(), policy, quota.clone(), 65535);
+
+        // One packet per second for a minute: six 10-second files
+        for seq in 0..60 {
+            writer.write(&packet(seq, 1_700_000_000 + seq, 100)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let files = quota.files("s1");
+        let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, vec!["s1-00003.pcap", "s1-00004.pcap", "s1-00005.pcap"]);
+        assert_eq!(read_seqs(&files[0].path), (30..40).collect::<Vec<_>>());
+        assert!(!file_path(dir.path(), "s1", 0).exists());
+    }
+
+    #[test]
+    fn test_quota_evicts_oldest_then_hard_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = Arc::new(DiskQuota::new(3 * MB));
+        let mut events = quota.subscribe();
+        let policy = RotationPolicy { file_size_mb: 1, file_seconds: None, max_files: 10 };
+
+        // Two sessions fill the quota; the older session's finished files go first
+        let mut a = RotatingWriter::new("a", dir.path(), policy, quota.clone(), 65535);
+        let mut b = RotatingWriter::new("b", dir.path(), policy, quota.clone(), 65535);
+        let big = |seq| packet(seq, 1_700_000_000, 1000);
+        for seq in 0..2000 {
+            a.write(&big(seq)).unwrap();
+        }
+        a.finish().unwrap();
+        for seq in 0..3000 {
+            b.write(&big(seq)).unwrap();
+        }
+
+        assert!(quota.used_bytes() <= quota.limit_bytes());
+        assert_eq!(quota.files("a").len(), 0);
+        assert_eq!(quota.files("b").len(), 3);
+        match events.try_recv().unwrap() {
+            CaptureEvent::FileEvicted { session_id, path } => {
+                assert_eq!(session_id, "a");
+                assert_eq!(path, file_path(dir.path(), "a", 0));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // A single writer whose open file is the only thing left must stop
+        let quota = Arc::new(DiskQuota::new(MB / 2));
+        let mut events = quota.subscribe();
+        let mut c = RotatingWriter::new("c", dir.path(), policy, quota.clone(), 65535);
+        let mut written = 0;
+        while c.write(&big(written)).is_ok() {
+            written += 1;
+        }
+        assert!(c.write(&big(written)).is_err());
+        assert!(c.summary().quota_exceeded);
+        assert_eq!(read_seqs(&file_path(dir.path(), "c", 0)).len(), written as usize);
+        assert!(matches!(events.try_recv().unwrap(),
+            CaptureEvent::QuotaExceeded { ref session_id, .. } if session_id == "c"));
+    }
+}
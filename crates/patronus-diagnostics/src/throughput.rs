@@ -0,0 +1,1282 @@
+//! Throughput Testing
+//!
+//! iperf-style TCP and UDP throughput tests between two Patronus nodes.
+//! One node runs a [`ThroughputServer`]; the other starts a test against it
+//! with [`DiagnosticTools::throughput_test`](crate::DiagnosticTools::throughput_test).
+//!
+//! Each stream is one TCP connection that opens with a JSON request line
+//! and the server's JSON reply. TCP data then flows on that connection; UDP
+//! data goes to the server's UDP socket on the same port, with the
+//! connection kept as the control channel for the final report. Both ends
+//! pace their sends to the lower of the client's and the server's rate cap,
+//! so a test can't starve production traffic.
+
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Port the throughput server listens on by default (as iperf3)
+pub const DEFAULT_THROUGHPUT_PORT: u16 = 5201;
+
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Longest test either end will run
+const MAX_DURATION: Duration = Duration::from_secs(300);
+
+/// Most streams per test, and most concurrent streams per server
+const MAX_STREAMS: u32 = 32;
+
+/// Largest TCP write or read
+const TCP_CHUNK: usize = 128 * 1024;
+
+/// Token, sequence number and send time, all big-endian u64
+const UDP_HEADER_LEN: usize = 24;
+
+/// Largest UDP payload that fits an IPv4 datagram
+const MAX_UDP_PAYLOAD: usize = 65_507;
+
+/// Time allowed for late datagrams and reports after the data phase ends
+const REPORT_GRACE: Duration = Duration::from_millis(500);
+
+/// Longest control message line
+const MAX_MESSAGE_LEN: u64 = 4096;
+
+/// Transport being tested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThroughputProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// Which way data flows, from the client's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThroughputDirection {
+    /// Client sends to the server
+    #[default]
+    Upload,
+    /// Server sends to the client
+    Download,
+}
+
+/// Options for a throughput test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputOptions {
+    pub duration: Duration,
+    /// Parallel connections
+    pub streams: u32,
+    pub direction: ThroughputDirection,
+    pub protocol: ThroughputProtocol,
+    /// Cap on the total rate across all streams; required for UDP
+    pub max_rate_mbps: Option<f64>,
+    /// Length of each reported interval
+    pub interval: Duration,
+    /// Datagram payload size for UDP tests
+    pub udp_payload_bytes: usize,
+}
+
+impl Default for ThroughputOptions {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(10),
+            streams: 1,
+            direction: ThroughputDirection::Upload,
+            protocol: ThroughputProtocol::Tcp,
+            max_rate_mbps: None,
+            interval: Duration::from_secs(1),
+            udp_payload_bytes: 1200,
+        }
+    }
+}
+
+impl ThroughputOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.duration.is_zero() || self.duration > MAX_DURATION {
+            return Err(Error::Config(format!(
+                "Throughput test duration must be between 0 and {}s",
+                MAX_DURATION.as_secs()
+            )));
+        }
+        if self.streams == 0 || self.streams > MAX_STREAMS {
+            return Err(Error::Config(format!("Throughput streams must be 1-{}", MAX_STREAMS)));
+        }
+        if self.interval.is_zero() {
+            return Err(Error::Config("Throughput report interval must be non-zero".to_string()));
+        }
+        if let Some(rate) = self.max_rate_mbps {
+            if !(rate > 0.0 && rate.is_finite()) {
+                return Err(Error::Config(format!("Invalid throughput rate cap: {}", rate)));
+            }
+        }
+        if self.protocol == ThroughputProtocol::Udp {
+            if self.max_rate_mbps.is_none() {
+                return Err(Error::Config("UDP throughput tests need a rate cap".to_string()));
+            }
+            if !(UDP_HEADER_LEN..=MAX_UDP_PAYLOAD).contains(&self.udp_payload_bytes) {
+                return Err(Error::Config(format!(
+                    "UDP payload must be {}-{} bytes",
+                    UDP_HEADER_LEN, MAX_UDP_PAYLOAD
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Traffic during one reporting interval, summed over streams
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputInterval {
+    /// Seconds since the test started
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub bytes: u64,
+    pub mbps: f64,
+    /// TCP retransmits in the interval; only known when this end sends
+    pub retransmits: Option<u64>,
+}
+
+/// Totals for a single stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputStream {
+    pub stream: u32,
+    pub bytes: u64,
+    pub mbps: f64,
+    pub retransmits: Option<u64>,
+    pub udp: Option<UdpStats>,
+}
+
+/// Datagram delivery statistics for a UDP test
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UdpStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub lost: u64,
+    pub loss_pct: f64,
+    /// RFC 3550 interarrival jitter
+    pub jitter_ms: f64,
+    pub out_of_order: u64,
+}
+
+/// Outcome of a throughput test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputResult {
+    pub id: Uuid,
+    pub target: String,
+    pub protocol: ThroughputProtocol,
+    pub direction: ThroughputDirection,
+    pub streams: u32,
+    /// Rate cap in effect after negotiation with the server
+    pub max_rate_mbps: Option<f64>,
+    /// Length of the data phase
+    pub duration_secs: f64,
+    /// Bytes delivered to the receiving end
+    pub bytes: u64,
+    pub mbps: f64,
+    /// TCP retransmits; only known for uploads, where this end sends
+    pub retransmits: Option<u64>,
+    pub intervals: Vec<ThroughputInterval>,
+    pub per_stream: Vec<ThroughputStream>,
+    /// Combined UDP statistics, for UDP tests
+    pub udp: Option<UdpStats>,
+    /// Stopped early by [`ThroughputTest::cancel`]
+    pub cancelled: bool,
+    pub timestamp: SystemTime,
+}
+
+/// Opening message of every stream
+#[derive(Debug, Serialize, Deserialize)]
+struct TestRequest {
+    version: u32,
+    protocol: ThroughputProtocol,
+    direction: ThroughputDirection,
+    duration_ms: u64,
+    /// Cap for this stream
+    max_rate_mbps: Option<f64>,
+    udp_token: u64,
+    /// Client UDP port for UDP downloads
+    udp_port: Option<u16>,
+    udp_payload_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Accepted { max_rate_mbps: Option<f64>, udp_port: u16 },
+    Rejected { reason: String },
+    TcpReport { bytes_received: u64 },
+    UdpReport { stats: UdpStats },
+    UdpSent { packets_sent: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    UdpDone { packets_sent: u64 },
+}
+
+/// Lower of two optional rate caps
+fn min_rate(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn mbps(bytes: u64, secs: f64) -> f64 {
+    if secs > 0.0 {
+        bytes as f64 * 8.0 / secs / 1_000_000.0
+    } else {
+        0.0
+    }
+}
+
+async fn send_message<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_vec(message)
+        .map_err(|e| Error::Network(format!("Failed to encode throughput message: {}", e)))?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+async fn read_message<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncBufRead + Unpin,
+    T: for<'de> Deserialize<'de>,
+{
+    let mut line = String::new();
+    reader.take(MAX_MESSAGE_LEN).read_line(&mut line).await?;
+    if line.is_empty() {
+        return Err(Error::Network("Throughput peer closed the connection".to_string()));
+    }
+    serde_json::from_str(&line)
+        .map_err(|e| Error::Network(format!("Bad throughput message: {}", e)))
+}
+
+/// Spaces sends so the average rate stays under a cap
+struct Pacer {
+    bytes_per_sec: Option<f64>,
+    start: Instant,
+    sent: u64,
+}
+
+impl Pacer {
+    fn new(max_rate_mbps: Option<f64>) -> Self {
+        Self {
+            bytes_per_sec: max_rate_mbps.map(|m| m * 1_000_000.0 / 8.0),
+            start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Chunk size keeping pacing steps around 20ms at low rates
+    fn chunk(&self) -> usize {
+        self.bytes_per_sec
+            .map_or(TCP_CHUNK, |r| ((r / 50.0) as usize).clamp(1460, TCP_CHUNK))
+    }
+
+    /// Wait needed after `bytes` more have gone out
+    fn delay_after(&mut self, bytes: usize) -> Duration {
+        self.sent += bytes as u64;
+        match self.bytes_per_sec {
+            Some(rate) => Duration::from_secs_f64(self.sent as f64 / rate)
+                .saturating_sub(self.start.elapsed()),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Sleep for `delay`; returns true if cancelled first
+async fn sleep_or_cancel(delay: Duration, cancel: &mut watch::Receiver<bool>) -> bool {
+    if *cancel.borrow() {
+        return true;
+    }
+    if delay.is_zero() {
+        return false;
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => false,
+        _ = cancel.changed() => true,
+    }
+}
+
+/// Total retransmitted segments on a TCP socket, from `TCP_INFO`
+#[cfg(target_os = "linux")]
+fn tcp_retransmits(stream: &TcpStream) -> Option<u64> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: tcp_info is plain data, so all-zero is a valid value
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    // SAFETY: info and len describe a writable buffer of the right size and
+    // the fd stays open for the duration of the call
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0).then_some(info.tcpi_total_retrans as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tcp_retransmits(_stream: &TcpStream) -> Option<u64> {
+    None
+}
+
+fn encode_datagram(buf: &mut [u8], token: u64, seq: u64, sent_nanos: u64) {
+    buf[0..8].copy_from_slice(&token.to_be_bytes());
+    buf[8..16].copy_from_slice(&seq.to_be_bytes());
+    buf[16..24].copy_from_slice(&sent_nanos.to_be_bytes());
+}
+
+fn decode_datagram(buf: &[u8]) -> Option<(u64, u64, u64)> {
+    if buf.len() < UDP_HEADER_LEN {
+        return None;
+    }
+    let field = |i: usize| u64::from_be_bytes(buf[i..i + 8].try_into().unwrap());
+    Some((field(0), field(8), field(16)))
+}
+
+/// Receive-side UDP accounting for one stream
+#[derive(Debug, Default)]
+struct UdpReceiver {
+    packets: u64,
+    bytes: u64,
+    highest_seq: Option<u64>,
+    out_of_order: u64,
+    jitter_ms: f64,
+    last_transit_ms: Option<f64>,
+}
+
+impl UdpReceiver {
+    /// Record a datagram; times are in each end's own clock
+    fn record(&mut self, seq: u64, sent_nanos: u64, recv_nanos: u64, len: usize) {
+        self.packets += 1;
+        self.bytes += len as u64;
+
+        match self.highest_seq {
+            Some(highest) if seq <= highest => self.out_of_order += 1,
+            _ => self.highest_seq = Some(seq),
+        }
+
+        // Clock offsets cancel out in the difference between transit times
+        let transit_ms = (recv_nanos as f64 - sent_nanos as f64) / 1_000_000.0;
+        if let Some(last) = self.last_transit_ms {
+            let d = (transit_ms - last).abs();
+            self.jitter_ms += (d - self.jitter_ms) / 16.0;
+        }
+        self.last_transit_ms = Some(transit_ms);
+    }
+
+    fn finish(&self, packets_sent: u64) -> UdpStats {
+        let lost = packets_sent.saturating_sub(self.packets);
+        UdpStats {
+            packets_sent,
+            packets_received: self.packets,
+            bytes_received: self.bytes,
+            lost,
+            loss_pct: if packets_sent > 0 { lost as f64 / packets_sent as f64 * 100.0 } else { 0.0 },
+            jitter_ms: self.jitter_ms,
+            out_of_order: self.out_of_order,
+        }
+    }
+}
+
+/// Datagrams are only accepted from the host holding the control connection
+struct UdpRegistration {
+    peer: IpAddr,
+    epoch: Instant,
+    receiver: UdpReceiver,
+}
+
+struct ServerState {
+    udp: UdpSocket,
+    max_rate_mbps: Mutex<Option<f64>>,
+    active_streams: AtomicUsize,
+    udp_streams: Mutex<HashMap<u64, UdpRegistration>>,
+}
+
+/// Releases a server stream slot when dropped
+struct StreamSlot(Arc<ServerState>);
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0.active_streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Answers throughput tests from other nodes
+pub struct ThroughputServer {
+    local_addr: SocketAddr,
+    state: Arc<ServerState>,
+    accept_task: JoinHandle<()>,
+    udp_task: JoinHandle<()>,
+}
+
+impl ThroughputServer {
+    /// Listen for tests on `addr`, TCP and UDP on the same port
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let udp = UdpSocket::bind(local_addr).await?;
+
+        let state = Arc::new(ServerState {
+            udp,
+            max_rate_mbps: Mutex::new(None),
+            active_streams: AtomicUsize::new(0),
+            udp_streams: Mutex::new(HashMap::new()),
+        });
+
+        let accept_state = state.clone();
+        let accept_task = tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::warn!("Throughput server accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let state = accept_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_stream(state, stream, peer).await {
+                        tracing::debug!("Throughput stream from {} ended: {}", peer, e);
+                    }
+                });
+            }
+        });
+
+        let udp_task = tokio::spawn(receive_datagrams(state.clone()));
+
+        tracing::info!("Throughput server listening on {}", local_addr);
+
+        Ok(Self { local_addr, state, accept_task, udp_task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Cap the rate of every stream this server sends or receives
+    pub fn set_max_rate(&self, max_rate_mbps: Option<f64>) {
+        *self.state.max_rate_mbps.lock().unwrap() = max_rate_mbps;
+    }
+
+    /// Streams currently being served
+    pub fn active_streams(&self) -> usize {
+        self.state.active_streams.load(Ordering::SeqCst)
+    }
+
+    pub fn shutdown(self) {
+        // Drop aborts the listener tasks
+    }
+}
+
+impl Drop for ThroughputServer {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        self.udp_task.abort();
+    }
+}
+
+async fn receive_datagrams(state: Arc<ServerState>) {
+    let mut buf = vec![0u8; 65_536];
+    loop {
+        let (len, from) = match state.udp.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::debug!("Throughput UDP receive failed: {}", e);
+                continue;
+            }
+        };
+        let Some((token, seq, sent_nanos)) = decode_datagram(&buf[..len]) else {
+            continue;
+        };
+
+        let mut streams = state.udp_streams.lock().unwrap();
+        if let Some(reg) = streams.get_mut(&token) {
+            if reg.peer == from.ip() {
+                let recv_nanos = reg.epoch.elapsed().as_nanos() as u64;
+                reg.receiver.record(seq, sent_nanos, recv_nanos, len);
+            }
+        }
+    }
+}
+
+async fn serve_stream(state: Arc<ServerState>, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+    let mut conn = BufReader::new(stream);
+    let request: TestRequest = read_message(&mut conn).await?;
+
+    let reject = |reason: String| ServerMessage::Rejected { reason };
+    let problem = if request.version != PROTOCOL_VERSION {
+        Some(format!("unsupported protocol version {}", request.version))
+    } else if Duration::from_millis(request.duration_ms) > MAX_DURATION {
+        Some(format!("duration over {}s", MAX_DURATION.as_secs()))
+    } else if request.protocol == ThroughputProtocol::Udp
+        && !(UDP_HEADER_LEN..=MAX_UDP_PAYLOAD).contains(&request.udp_payload_bytes)
+    {
+        Some(format!("bad UDP payload size {}", request.udp_payload_bytes))
+    } else {
+        None
+    };
+    if let Some(reason) = problem {
+        send_message(conn.get_mut(), &reject(reason)).await?;
+        return Ok(());
+    }
+
+    if state.active_streams.fetch_add(1, Ordering::SeqCst) >= MAX_STREAMS as usize {
+        state.active_streams.fetch_sub(1, Ordering::SeqCst);
+        send_message(conn.get_mut(), &reject("server busy".to_string())).await?;
+        return Ok(());
+    }
+    let _slot = StreamSlot(state.clone());
+
+    let rate = min_rate(request.max_rate_mbps, *state.max_rate_mbps.lock().unwrap());
+    if request.protocol == ThroughputProtocol::Udp && rate.is_none() {
+        send_message(conn.get_mut(), &reject("UDP needs a rate cap".to_string())).await?;
+        return Ok(());
+    }
+
+    // Register before accepting so the first datagrams aren't dropped
+    if (request.protocol, request.direction) == (ThroughputProtocol::Udp, ThroughputDirection::Upload) {
+        state.udp_streams.lock().unwrap().insert(request.udp_token, UdpRegistration {
+            peer: peer.ip(),
+            epoch: Instant::now(),
+            receiver: UdpReceiver::default(),
+        });
+    }
+
+    let udp_port = state.udp.local_addr()?.port();
+    send_message(conn.get_mut(), &ServerMessage::Accepted { max_rate_mbps: rate, udp_port }).await?;
+
+    let duration = Duration::from_millis(request.duration_ms);
+    // The client decides when to stop; this only bounds a misbehaving one
+    let deadline = Instant::now() + duration + REPORT_GRACE * 4;
+
+    match (request.protocol, request.direction) {
+        (ThroughputProtocol::Tcp, ThroughputDirection::Upload) => {
+            let mut pacer = Pacer::new(rate);
+            let mut buf = vec![0u8; TCP_CHUNK];
+            let mut received = 0u64;
+            loop {
+                let chunk = pacer.chunk();
+                let n = match tokio::time::timeout_at(deadline.into(), conn.read(&mut buf[..chunk])).await {
+                    Ok(n) => n?,
+                    Err(_) => break,
+                };
+                if n == 0 {
+                    break;
+                }
+                received += n as u64;
+                tokio::time::sleep(pacer.delay_after(n)).await;
+            }
+            send_message(conn.get_mut(), &ServerMessage::TcpReport { bytes_received: received }).await?;
+        }
+        (ThroughputProtocol::Tcp, ThroughputDirection::Download) => {
+            let mut pacer = Pacer::new(rate);
+            let buf = vec![0xa5u8; TCP_CHUNK];
+            let end = Instant::now() + duration;
+            while Instant::now() < end {
+                let chunk = pacer.chunk();
+                if tokio::time::timeout_at(end.into(), conn.get_mut().write_all(&buf[..chunk])).await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(pacer.delay_after(chunk).min(end.saturating_duration_since(Instant::now()))).await;
+            }
+            conn.get_mut().shutdown().await?;
+        }
+        (ThroughputProtocol::Udp, ThroughputDirection::Upload) => {
+            let done = tokio::time::timeout_at(deadline.into(), read_message::<_, ClientMessage>(&mut conn)).await;
+            tokio::time::sleep(REPORT_GRACE).await;
+            let reg = state.udp_streams.lock().unwrap().remove(&request.udp_token);
+
+            if let (Ok(Ok(ClientMessage::UdpDone { packets_sent })), Some(reg)) = (done, reg) {
+                let stats = reg.receiver.finish(packets_sent);
+                send_message(conn.get_mut(), &ServerMessage::UdpReport { stats }).await?;
+            }
+        }
+        (ThroughputProtocol::Udp, ThroughputDirection::Download) => {
+            let port = request.udp_port
+                .ok_or_else(|| Error::Network("UDP download without a client port".to_string()))?;
+            // Only ever send to the host that asked, never a third party
+            let dest = SocketAddr::new(peer.ip(), port);
+
+            let mut pacer = Pacer::new(rate);
+            let mut buf = vec![0u8; request.udp_payload_bytes];
+            let start = Instant::now();
+            let end = start + duration;
+            let mut sent = 0u64;
+            let mut probe = [0u8; 1];
+
+            while Instant::now() < end {
+                encode_datagram(&mut buf, request.udp_token, sent, start.elapsed().as_nanos() as u64);
+                if let Err(e) = state.udp.send_to(&buf, dest).await {
+                    tracing::debug!("Throughput UDP send to {} failed: {}", dest, e);
+                }
+                sent += 1;
+
+                let delay = pacer.delay_after(buf.len());
+                if !delay.is_zero() {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        // Client hung up: stop sending
+                        _ = conn.read(&mut probe) => return Ok(()),
+                    }
+                }
+            }
+            send_message(conn.get_mut(), &ServerMessage::UdpSent { packets_sent: sent }).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Live counters shared between a stream and the interval sampler
+#[derive(Default)]
+struct StreamCounters {
+    bytes: AtomicU64,
+    /// Retransmits plus one, so zero means unknown
+    retransmits: AtomicU64,
+}
+
+impl StreamCounters {
+    fn set_retransmits(&self, retransmits: Option<u64>) {
+        if let Some(r) = retransmits {
+            self.retransmits.store(r + 1, Ordering::Relaxed);
+        }
+    }
+
+    fn retransmits(&self) -> Option<u64> {
+        self.retransmits.load(Ordering::Relaxed).checked_sub(1)
+    }
+}
+
+/// Turns the running counters into per-interval totals
+#[derive(Default)]
+struct IntervalSampler {
+    intervals: Vec<ThroughputInterval>,
+    last_tick: Duration,
+    last_bytes: u64,
+    last_retransmits: Option<u64>,
+}
+
+impl IntervalSampler {
+    fn sample(&mut self, counters: &[Arc<StreamCounters>], now: Duration) {
+        let bytes: u64 = counters.iter().map(|c| c.bytes.load(Ordering::Relaxed)).sum();
+        let retransmits = counters.iter().map(|c| c.retransmits()).sum::<Option<u64>>();
+        let delta = bytes - self.last_bytes;
+
+        self.intervals.push(ThroughputInterval {
+            start_secs: self.last_tick.as_secs_f64(),
+            end_secs: now.as_secs_f64(),
+            bytes: delta,
+            mbps: mbps(delta, (now - self.last_tick).as_secs_f64()),
+            retransmits: retransmits.map(|r| r.saturating_sub(self.last_retransmits.unwrap_or(0))),
+        });
+        self.last_tick = now;
+        self.last_bytes = bytes;
+        self.last_retransmits = retransmits;
+    }
+}
+
+struct StreamOutcome {
+    /// Bytes delivered to the receiver
+    bytes: u64,
+    elapsed: Duration,
+    retransmits: Option<u64>,
+    udp: Option<UdpStats>,
+}
+
+struct StreamContext {
+    server: SocketAddr,
+    options: ThroughputOptions,
+    /// Cap for each stream
+    rate: Option<f64>,
+    counters: Arc<StreamCounters>,
+    cancel: watch::Receiver<bool>,
+}
+
+/// A running throughput test
+pub struct ThroughputTest {
+    id: Uuid,
+    cancel: watch::Sender<bool>,
+    task: JoinHandle<Result<ThroughputResult>>,
+}
+
+impl ThroughputTest {
+    pub(crate) fn start(target: &str, server: SocketAddr, options: ThroughputOptions) -> Self {
+        let id = Uuid::new_v4();
+        let (cancel, cancel_rx) = watch::channel(false);
+        let target = target.to_string();
+        let task = tokio::spawn(run_test(id, target, server, options, cancel_rx));
+
+        Self { id, cancel, task }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Stop early; [`wait`](Self::wait) still returns the partial result
+    pub fn cancel(&self) {
+        let _ = self.cancel.send(true);
+    }
+
+    pub async fn wait(self) -> Result<ThroughputResult> {
+        self.task
+            .await
+            .map_err(|e| Error::Service(format!("Throughput test {} failed: {}", self.id, e)))?
+    }
+}
+
+async fn run_test(
+    id: Uuid,
+    target: String,
+    server: SocketAddr,
+    options: ThroughputOptions,
+    cancel: watch::Receiver<bool>,
+) -> Result<ThroughputResult> {
+    let per_stream_rate = options.max_rate_mbps.map(|r| r / options.streams as f64);
+    let counters: Vec<Arc<StreamCounters>> = (0..options.streams)
+        .map(|_| Arc::new(StreamCounters::default()))
+        .collect();
+
+    let start = Instant::now();
+    let handles: Vec<_> = counters.iter()
+        .map(|c| {
+            tokio::spawn(run_stream(StreamContext {
+                server,
+                options: options.clone(),
+                rate: per_stream_rate,
+                counters: c.clone(),
+                cancel: cancel.clone(),
+            }))
+        })
+        .collect();
+    let mut streams = Box::pin(futures::future::join_all(handles));
+
+    let mut ticker = tokio::time::interval_at((start + options.interval).into(), options.interval);
+    let mut sampler = IntervalSampler::default();
+    let joined = loop {
+        tokio::select! {
+            joined = &mut streams => break joined,
+            _ = ticker.tick() => sampler.sample(&counters, start.elapsed()),
+        }
+    };
+
+    let mut outcomes = Vec::with_capacity(joined.len());
+    let mut rate = per_stream_rate;
+    for result in joined {
+        let (outcome, stream_rate) = result
+            .map_err(|e| Error::Service(format!("Throughput stream failed: {}", e)))??;
+        rate = stream_rate;
+        outcomes.push(outcome);
+    }
+
+    let elapsed = outcomes.iter().map(|o| o.elapsed).max().unwrap_or_default();
+    // A trailing partial interval is only worth reporting if it has some length
+    if elapsed.saturating_sub(sampler.last_tick) > options.interval / 10 {
+        sampler.sample(&counters, elapsed);
+    }
+
+    let per_stream: Vec<ThroughputStream> = outcomes.iter().enumerate()
+        .map(|(i, o)| ThroughputStream {
+            stream: i as u32,
+            bytes: o.bytes,
+            mbps: mbps(o.bytes, o.elapsed.as_secs_f64()),
+            retransmits: o.retransmits,
+            udp: o.udp.clone(),
+        })
+        .collect();
+
+    let udp = (options.protocol == ThroughputProtocol::Udp).then(|| {
+        let stats: Vec<&UdpStats> = outcomes.iter().filter_map(|o| o.udp.as_ref()).collect();
+        let mut total = UdpStats::default();
+        for s in &stats {
+            total.packets_sent += s.packets_sent;
+            total.packets_received += s.packets_received;
+            total.bytes_received += s.bytes_received;
+            total.lost += s.lost;
+            total.out_of_order += s.out_of_order;
+            total.jitter_ms += s.jitter_ms / stats.len() as f64;
+        }
+        if total.packets_sent > 0 {
+            total.loss_pct = total.lost as f64 / total.packets_sent as f64 * 100.0;
+        }
+        total
+    });
+
+    let bytes = outcomes.iter().map(|o| o.bytes).sum();
+
+    Ok(ThroughputResult {
+        id,
+        target,
+        protocol: options.protocol,
+        direction: options.direction,
+        streams: options.streams,
+        max_rate_mbps: rate.map(|r| r * options.streams as f64),
+        duration_secs: elapsed.as_secs_f64(),
+        bytes,
+        mbps: mbps(bytes, elapsed.as_secs_f64()),
+        retransmits: outcomes.iter().map(|o| o.retransmits).sum(),
+        intervals: sampler.intervals,
+        per_stream,
+        udp,
+        cancelled: *cancel.borrow(),
+        timestamp: SystemTime::now(),
+    })
+}
+
+/// Run one stream; also returns the rate cap the server agreed to
+async fn run_stream(mut ctx: StreamContext) -> Result<(StreamOutcome, Option<f64>)> {
+    let stream = TcpStream::connect(ctx.server).await?;
+    let mut conn = BufReader::new(stream);
+
+    // UDP downloads need our port in the request
+    let udp = match ctx.options.protocol {
+        ThroughputProtocol::Udp => {
+            let bind: SocketAddr = if ctx.server.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }.parse().unwrap();
+            Some(UdpSocket::bind(bind).await?)
+        }
+        ThroughputProtocol::Tcp => None,
+    };
+
+    let token = Uuid::new_v4().as_u64_pair().0;
+    let request = TestRequest {
+        version: PROTOCOL_VERSION,
+        protocol: ctx.options.protocol,
+        direction: ctx.options.direction,
+        duration_ms: ctx.options.duration.as_millis() as u64,
+        max_rate_mbps: ctx.rate,
+        udp_token: token,
+        udp_port: match &udp {
+            Some(socket) => Some(socket.local_addr()?.port()),
+            None => None,
+        },
+        udp_payload_bytes: ctx.options.udp_payload_bytes,
+    };
+    send_message(conn.get_mut(), &request).await?;
+
+    let (rate, udp_port) = match read_message(&mut conn).await? {
+        ServerMessage::Accepted { max_rate_mbps, udp_port } => (max_rate_mbps, udp_port),
+        ServerMessage::Rejected { reason } => {
+            return Err(Error::Network(format!("Throughput server refused the test: {}", reason)));
+        }
+        other => return Err(Error::Network(format!("Unexpected throughput message: {:?}", other))),
+    };
+    ctx.rate = rate;
+
+    let outcome = match (ctx.options.protocol, ctx.options.direction, udp) {
+        (ThroughputProtocol::Tcp, ThroughputDirection::Upload, _) => tcp_upload(&mut ctx, conn).await?,
+        (ThroughputProtocol::Tcp, ThroughputDirection::Download, _) => tcp_download(&mut ctx, conn).await?,
+        (ThroughputProtocol::Udp, ThroughputDirection::Upload, Some(socket)) => {
+            socket.connect(SocketAddr::new(ctx.server.ip(), udp_port)).await?;
+            udp_upload(&mut ctx, conn, socket, token).await?
+        }
+        (ThroughputProtocol::Udp, ThroughputDirection::Download, Some(socket)) => {
+            udp_download(&mut ctx, conn, socket, token).await?
+        }
+        (ThroughputProtocol::Udp, _, None) => unreachable!("UDP socket is bound for UDP tests"),
+    };
+
+    Ok((outcome, rate))
+}
+
+async fn tcp_upload(ctx: &mut StreamContext, mut conn: BufReader<TcpStream>) -> Result<StreamOutcome> {
+    let mut pacer = Pacer::new(ctx.rate);
+    let buf = vec![0x5au8; TCP_CHUNK];
+    let start = Instant::now();
+    let end = start + ctx.options.duration;
+    let mut sent = 0u64;
+
+    while Instant::now() < end {
+        let chunk = pacer.chunk();
+        tokio::select! {
+            result = tokio::time::timeout_at(end.into(), conn.get_mut().write_all(&buf[..chunk])) => {
+                match result {
+                    Ok(r) => r?,
+                    Err(_) => break,
+                }
+            }
+            _ = ctx.cancel.changed() => break,
+        }
+        sent += chunk as u64;
+        ctx.counters.bytes.fetch_add(chunk as u64, Ordering::Relaxed);
+        ctx.counters.set_retransmits(tcp_retransmits(conn.get_ref()));
+
+        let delay = pacer.delay_after(chunk).min(end.saturating_duration_since(Instant::now()));
+        if sleep_or_cancel(delay, &mut ctx.cancel).await {
+            break;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let retransmits = tcp_retransmits(conn.get_ref());
+    ctx.counters.set_retransmits(retransmits);
+    conn.get_mut().shutdown().await?;
+
+    // The server's count is what actually arrived
+    let report = tokio::time::timeout(REPORT_GRACE * 4, read_message(&mut conn)).await;
+    let bytes = match report {
+        Ok(Ok(ServerMessage::TcpReport { bytes_received })) => bytes_received,
+        _ => {
+            tracing::warn!("No report from throughput server {}, using bytes sent", ctx.server);
+            sent
+        }
+    };
+
+    Ok(StreamOutcome { bytes, elapsed, retransmits, udp: None })
+}
+
+async fn tcp_download(ctx: &mut StreamContext, mut conn: BufReader<TcpStream>) -> Result<StreamOutcome> {
+    let mut buf = vec![0u8; TCP_CHUNK];
+    let start = Instant::now();
+    let deadline = start + ctx.options.duration + REPORT_GRACE;
+    let mut received = 0u64;
+
+    loop {
+        let n = tokio::select! {
+            result = tokio::time::timeout_at(deadline.into(), conn.read(&mut buf)) => {
+                match result {
+                    Ok(r) => r?,
+                    Err(_) => break,
+                }
+            }
+            _ = ctx.cancel.changed() => break,
+        };
+        if n == 0 {
+            break;
+        }
+        received += n as u64;
+        ctx.counters.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    Ok(StreamOutcome { bytes: received, elapsed: start.elapsed(), retransmits: None, udp: None })
+}
+
+async fn udp_upload(
+    ctx: &mut StreamContext,
+    mut conn: BufReader<TcpStream>,
+    socket: UdpSocket,
+    token: u64,
+) -> Result<StreamOutcome> {
+    let mut pacer = Pacer::new(ctx.rate);
+    let mut buf = vec![0u8; ctx.options.udp_payload_bytes];
+    let start = Instant::now();
+    let end = start + ctx.options.duration;
+    let mut sent = 0u64;
+
+    while Instant::now() < end {
+        encode_datagram(&mut buf, token, sent, start.elapsed().as_nanos() as u64);
+        if let Err(e) = socket.send(&buf).await {
+            // Transient errors (e.g. ENOBUFS) count as lost datagrams
+            tracing::debug!("Throughput UDP send failed: {}", e);
+        }
+        sent += 1;
+        ctx.counters.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+
+        let delay = pacer.delay_after(buf.len()).min(end.saturating_duration_since(Instant::now()));
+        if sleep_or_cancel(delay, &mut ctx.cancel).await {
+            break;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    send_message(conn.get_mut(), &ClientMessage::UdpDone { packets_sent: sent }).await?;
+    let stats = match tokio::time::timeout(REPORT_GRACE * 4, read_message(&mut conn)).await {
+        Ok(Ok(ServerMessage::UdpReport { stats })) => stats,
+        _ => return Err(Error::Network(format!("No UDP report from throughput server {}", ctx.server))),
+    };
+
+    Ok(StreamOutcome { bytes: stats.bytes_received, elapsed, retransmits: None, udp: Some(stats) })
+}
+
+async fn udp_download(
+    ctx: &mut StreamContext,
+    mut conn: BufReader<TcpStream>,
+    socket: UdpSocket,
+    token: u64,
+) -> Result<StreamOutcome> {
+    let mut receiver = UdpReceiver::default();
+    let mut buf = vec![0u8; 65_536];
+    let start = Instant::now();
+    let mut deadline = start + ctx.options.duration + REPORT_GRACE * 4;
+    let mut packets_sent = None;
+    let mut data_end = None;
+
+    // Polled across loop iterations, since a line read isn't cancel-safe
+    let control = read_message::<_, ServerMessage>(&mut conn);
+    tokio::pin!(control);
+
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buf) => {
+                let (len, from) = result?;
+                if from.ip() != ctx.server.ip() {
+                    continue;
+                }
+                if let Some((t, seq, sent_nanos)) = decode_datagram(&buf[..len]) {
+                    if t == token {
+                        receiver.record(seq, sent_nanos, start.elapsed().as_nanos() as u64, len);
+                        ctx.counters.bytes.fetch_add(len as u64, Ordering::Relaxed);
+                    }
+                }
+            }
+            message = &mut control, if data_end.is_none() => {
+                if let Ok(ServerMessage::UdpSent { packets_sent: n }) = message {
+                    packets_sent = Some(n);
+                }
+                // Let stragglers in, then stop
+                data_end = Some(start.elapsed());
+                deadline = Instant::now() + REPORT_GRACE;
+            }
+            _ = tokio::time::sleep_until(deadline.into()) => break,
+            _ = ctx.cancel.changed() => break,
+        }
+    }
+
+    let elapsed = data_end.unwrap_or_else(|| start.elapsed());
+    // Without the server's count, the highest sequence seen bounds what was sent
+    let sent = packets_sent.unwrap_or_else(|| receiver.highest_seq.map_or(0, |s| s + 1));
+    let stats = receiver.finish(sent);
+
+    Ok(StreamOutcome { bytes: stats.bytes_received, elapsed, retransmits: None, udp: Some(stats) })
+}
+
+/// Completed throughput tests, newest last
+///
+/// Kept so SLA verification can point at on-demand runs by id. With a
+/// path, the history is saved after every recorded test.
+#[derive(Clone)]
+pub struct ThroughputHistory {
+    results: Arc<RwLock<VecDeque<ThroughputResult>>>,
+    capacity: usize,
+    path: Option<PathBuf>,
+}
+
+impl ThroughputHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            results: Arc::new(RwLock::new(VecDeque::new())),
+            capacity: capacity.max(1),
+            path: None,
+        }
+    }
+
+    /// Load the history saved at `path`, if any, and keep saving to it
+    pub fn open(path: impl AsRef<Path>, capacity: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut history = Self::new(capacity);
+
+        if path.exists() {
+            let data = std::fs::read(&path)?;
+            let results: VecDeque<ThroughputResult> = serde_json::from_slice(&data)
+                .map_err(|e| Error::Config(format!("Bad throughput history {}: {}", path.display(), e)))?;
+            *history.results.write().unwrap() = results;
+            history.trim();
+        }
+
+        history.path = Some(path);
+        Ok(history)
+    }
+
+    fn trim(&self) {
+        let mut results = self.results.write().unwrap();
+        while results.len() > self.capacity {
+            results.pop_front();
+        }
+    }
+
+    pub fn record(&self, result: ThroughputResult) -> Result<()> {
+        self.results.write().unwrap().push_back(result);
+        self.trim();
+
+        if let Some(path) = &self.path {
+            let data = serde_json::to_vec_pretty(&*self.results.read().unwrap())
+                .map_err(|e| Error::Service(format!("Failed to encode throughput history: {}", e)))?;
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<ThroughputResult> {
+        self.results.read().unwrap().iter().find(|r| r.id == id).cloned()
+    }
+
+    /// Most recent test against `target`
+    pub fn latest(&self, target: &str) -> Option<ThroughputResult> {
+        self.results.read().unwrap().iter().rev().find(|r| r.target == target).cloned()
+    }
+
+    pub fn list(&self) -> Vec<ThroughputResult> {
+        self.results.read().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DiagnosticTools;
+
+    async fn server() -> ThroughputServer {
+        ThroughputServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap()
+    }
+
+    fn options(protocol: ThroughputProtocol, direction: ThroughputDirection) -> ThroughputOptions {
+        ThroughputOptions {
+            duration: Duration::from_millis(600),
+            streams: 2,
+            direction,
+            protocol,
+            max_rate_mbps: Some(40.0),
+            interval: Duration::from_millis(200),
+            udp_payload_bytes: 1000,
+        }
+    }
+
+    async fn run(server: &ThroughputServer, options: &ThroughputOptions) -> ThroughputResult {
+        let target = server.local_addr().to_string();
+        DiagnosticTools::throughput_test(&target, options).await.unwrap().wait().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tcp_upload_is_rate_capped() {
+        let server = server().await;
+        let result = run(&server, &options(ThroughputProtocol::Tcp, ThroughputDirection::Upload)).await;
+
+        assert!(!result.cancelled);
+        assert_eq!(result.per_stream.len(), 2);
+        assert!(result.mbps > 20.0 && result.mbps < 48.0, "{} Mbps", result.mbps);
+        assert!(result.intervals.len() >= 3);
+        assert!(result.retransmits.is_some());
+        assert_eq!(result.bytes, result.per_stream.iter().map(|s| s.bytes).sum::<u64>());
+        assert_eq!(server.active_streams(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_download_obeys_server_cap() {
+        let server = server().await;
+        server.set_max_rate(Some(8.0));
+
+        let mut opts = options(ThroughputProtocol::Tcp, ThroughputDirection::Download);
+        opts.max_rate_mbps = None;
+        let result = run(&server, &opts).await;
+
+        assert_eq!(result.max_rate_mbps, Some(16.0));
+        assert!(result.mbps > 5.0 && result.mbps < 20.0, "{} Mbps", result.mbps);
+        assert!(result.retransmits.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_udp_reports_loss_and_jitter() {
+        let server = server().await;
+
+        for direction in [ThroughputDirection::Upload, ThroughputDirection::Download] {
+            let result = run(&server, &options(ThroughputProtocol::Udp, direction)).await;
+            let udp = result.udp.unwrap();
+
+            assert!(udp.packets_sent > 1000, "{:?}: {:?}", direction, udp);
+            assert!(udp.packets_received + udp.lost >= udp.packets_sent);
+            assert!(udp.loss_pct < 10.0, "{:?}: {:?}", direction, udp);
+            assert!(udp.jitter_ms >= 0.0);
+            assert!(result.mbps > 20.0 && result.mbps < 48.0, "{:?}: {} Mbps", direction, result.mbps);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_returns_partial_result() {
+        let server = server().await;
+        let mut opts = options(ThroughputProtocol::Tcp, ThroughputDirection::Upload);
+        opts.duration = Duration::from_secs(60);
+
+        let test = DiagnosticTools::throughput_test(&server.local_addr().to_string(), &opts).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        test.cancel();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), test.wait()).await.unwrap().unwrap();
+        assert!(result.cancelled);
+        assert!(result.duration_secs < 5.0);
+        assert!(result.bytes > 0);
+    }
+
+    #[test]
+    fn test_udp_receiver_accounting() {
+        let mut rx = UdpReceiver::default();
+        // 10ms spacing with transit times 5, 7, 5 and then a reordered packet
+        rx.record(0, 0, 5_000_000, 100);
+        rx.record(1, 10_000_000, 17_000_000, 100);
+        rx.record(3, 30_000_000, 35_000_000, 100);
+        rx.record(2, 20_000_000, 36_000_000, 100);
+
+        let stats = rx.finish(6);
+        assert_eq!(stats.packets_received, 4);
+        assert_eq!(stats.bytes_received, 400);
+        assert_eq!(stats.lost, 2);
+        assert!((stats.loss_pct - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.out_of_order, 1);
+
+        // D = 2, 2, 11 ms through J += (|D| - J) / 16
+        let mut j = 0.0;
+        for d in [2.0, 2.0, 11.0] {
+            j += (d - j) / 16.0;
+        }
+        assert!((stats.jitter_ms - j).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_options_validation() {
+        assert!(ThroughputOptions::default().validate().is_ok());
+        assert!(ThroughputOptions { streams: 0, ..Default::default() }.validate().is_err());
+        assert!(ThroughputOptions { duration: Duration::from_secs(3600), ..Default::default() }.validate().is_err());
+        assert!(ThroughputOptions { protocol: ThroughputProtocol::Udp, ..Default::default() }.validate().is_err());
+        assert!(ThroughputOptions {
+            protocol: ThroughputProtocol::Udp,
+            max_rate_mbps: Some(10.0),
+            udp_payload_bytes: 8,
+            ..Default::default()
+        }.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_history_persists_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("throughput.json");
+        let server = server().await;
+
+        let history = ThroughputHistory::open(&path, 2).unwrap();
+        let mut opts = options(ThroughputProtocol::Tcp, ThroughputDirection::Upload);
+        opts.duration = Duration::from_millis(100);
+        for _ in 0..3 {
+            history.record(run(&server, &opts).await).unwrap();
+        }
+        let ids: Vec<Uuid> = history.list().iter().map(|r| r.id).collect();
+        assert_eq!(ids.len(), 2);
+
+        let reopened = ThroughputHistory::open(&path, 10).unwrap();
+        assert_eq!(reopened.list().iter().map(|r| r.id).collect::<Vec<_>>(), ids);
+        assert_eq!(reopened.latest(&server.local_addr().to_string()).unwrap().id, ids[1]);
+        assert!(reopened.get(ids[0]).is_some());
+    }
+}
@@ -3,8 +3,12 @@
 //! Comprehensive suite of diagnostic utilities accessible from web UI:
 //! - Ping - ICMP echo testing
 //! - Traceroute - Route path analysis
+//! - MTR - Continuous per-hop loss and latency
 //! - DNS Lookup - Domain name resolution
+//! - DoH Lookup - DNS-over-HTTPS queries against a specific server
+//! - DNS Diagnostics - Resolver comparison, DNSSEC validation and root trace
 //! - Port Test - TCP connection testing
+//! - Throughput - iperf-style TCP/UDP tests between nodes
 //! - ARP Table - Layer 2 address mapping
 //! - NDP Table - IPv6 neighbor discovery
 //! - Routes - Routing table viewer
@@ -14,10 +18,17 @@
 //!
 //! All tools support real-time output and result export.
 
+use crate::dns::{self, DnsClient, DnsPropagationReport, DnsServerAnswer, DnsTraceResult, DnssecReport};
+use crate::doh::{self, DohLookupResult, DohMethod};
+use crate::mtr::{self, MtrAccumulator, MtrOptions, MtrProbe, MtrProtocol, MtrReport};
+use crate::ping::{self, PingAccumulator, PingStats};
+use crate::throughput::{ThroughputOptions, ThroughputServer, ThroughputTest, DEFAULT_THROUGHPUT_PORT};
+use futures::stream::{self, Stream, StreamExt};
 use patronus_core::Result;
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::time::SystemTime;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::process::Command;
 use tokio::io::AsyncBufReadExt;
 
@@ -194,6 +205,52 @@ impl DiagnosticTools {
         Ok(result)
     }
 
+    /// Ping a host with `count` probes spaced by `interval`
+    ///
+    /// Probes go out on the interval regardless of outstanding replies, and
+    /// replies are collected as they arrive so duplicates and reordering are
+    /// counted. IPv6 targets use `ping6`.
+    pub async fn ping_async(
+        target: &str,
+        count: u32,
+        interval: std::time::Duration,
+    ) -> Result<PingStats> {
+        let ipv6 = target.parse::<Ipv6Addr>().is_ok();
+        let ping_cmd = if ipv6 { "ping6" } else { "ping" };
+
+        let mut child = Command::new(ping_cmd)
+            .arg("-n")
+            .arg("-c").arg(count.to_string())
+            .arg("-i").arg(format!("{:.3}", interval.as_secs_f64()))
+            .arg(target)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdout = child.stdout.take()
+            .ok_or_else(|| patronus_core::Error::Network("ping has no stdout".to_string()))?;
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+
+        let mut acc = PingAccumulator::new(target);
+        let mut packets_sent = count;
+
+        while let Some(line) = lines.next_line().await? {
+            if let Some((seq, rtt)) = ping::parse_reply_line(&line) {
+                acc.record_reply(seq, rtt);
+            } else if line.contains("packets transmitted") {
+                packets_sent = line.split_whitespace()
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(count);
+            }
+        }
+
+        child.wait().await?;
+
+        Ok(acc.finish(packets_sent))
+    }
+
     fn parse_ping_output(output: &str, target: &str) -> Result<PingResult> {
         let mut packets_sent = 0;
         let mut packets_received = 0;
@@ -279,6 +336,129 @@ impl DiagnosticTools {
         })
     }
 
+    /// Continuous MTR-style traceroute
+    ///
+    /// Probes the path for `options.count` rounds (or `options.duration`)
+    /// and returns the final per-hop statistics. See
+    /// [`mtr_stream`](Self::mtr_stream) for intermediate results.
+    pub async fn mtr(target: &str, options: &MtrOptions) -> Result<MtrReport> {
+        let reports = Self::mtr_stream(target, options).await?;
+        futures::pin_mut!(reports);
+
+        let mut last = None;
+        while let Some(report) = reports.next().await {
+            last = Some(report);
+        }
+
+        last.ok_or_else(|| patronus_core::Error::Network(format!("MTR to {} produced no report", target)))
+    }
+
+    /// Continuous MTR-style traceroute, yielding intermediate results
+    ///
+    /// Resolves the target up front, then yields the accumulated per-hop
+    /// statistics after each round and a final summary (with `is_final`
+    /// set) when the stream ends. Every round probes all hops at once; once
+    /// the destination has answered, later rounds stop at its hop.
+    pub async fn mtr_stream(
+        target: &str,
+        options: &MtrOptions,
+    ) -> Result<impl Stream<Item = MtrReport>> {
+        options.validate()?;
+
+        let destination = Self::resolve_target(target, options.ipv6).await?;
+        let acc = MtrAccumulator::new(target).with_destination(destination);
+        let options = options.clone();
+        let started = Instant::now();
+
+        Ok(stream::unfold(Some(acc), move |state| {
+            let options = options.clone();
+            async move {
+                let mut acc = state?;
+
+                let done = match options.duration {
+                    Some(duration) => acc.rounds() > 0 && started.elapsed() + options.interval > duration,
+                    None => acc.rounds() >= options.count,
+                };
+                if done {
+                    return Some((acc.report(true), None));
+                }
+
+                if acc.rounds() > 0 {
+                    tokio::time::sleep(options.interval).await;
+                }
+
+                // Every hop is probed at once, so a round needs little more
+                // than one probe timeout
+                let max_ttl = acc.destination_hop().unwrap_or(options.max_hops);
+                let deadline = options.timeout * 3 + Duration::from_secs(1);
+                let probes = match tokio::time::timeout(deadline, Self::mtr_round(destination, &options, max_ttl)).await {
+                    Ok(Ok(probes)) => probes,
+                    Ok(Err(e)) => {
+                        tracing::warn!("MTR probe round to {} failed: {}", destination, e);
+                        Vec::new()
+                    }
+                    Err(_) => {
+                        tracing::warn!("MTR probe round to {} timed out after {:?}", destination, deadline);
+                        Vec::new()
+                    }
+                };
+                acc.record_probes(&probes, max_ttl);
+
+                Some((acc.report(false), Some(acc)))
+            }
+        }))
+    }
+
+    async fn resolve_target(target: &str, ipv6: bool) -> Result<IpAddr> {
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return Ok(ip);
+        }
+
+        tokio::net::lookup_host((target, 0))
+            .await?
+            .map(|addr| addr.ip())
+            .find(|ip| ip.is_ipv6() == ipv6)
+            .ok_or_else(|| patronus_core::Error::Network(format!(
+                "{} has no {} address",
+                target,
+                if ipv6 { "IPv6" } else { "IPv4" },
+            )))
+    }
+
+    async fn mtr_round(
+        destination: IpAddr,
+        options: &MtrOptions,
+        max_ttl: u32,
+    ) -> Result<Vec<MtrProbe>> {
+        let traceroute_cmd = if destination.is_ipv6() { "traceroute6" } else { "traceroute" };
+
+        let mut cmd = Command::new(traceroute_cmd);
+        cmd.arg("-n")          // No reverse DNS, keeps rounds fast
+            .arg("-q").arg("1") // One probe per hop per round
+            .arg("-m").arg(max_ttl.to_string())
+            .arg("-N").arg(max_ttl.to_string()) // Probe every hop simultaneously
+            .arg("-w").arg(format!("{:.1}", options.timeout.as_secs_f64()));
+
+        if options.protocol == MtrProtocol::Icmp {
+            cmd.arg("-I");
+        }
+
+        let output = cmd.arg(destination.to_string())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        let probes = mtr::parse_probe_output(&String::from_utf8_lossy(&output.stdout));
+        if probes.is_empty() && !output.status.success() {
+            return Err(patronus_core::Error::Network(format!(
+                "{} failed: {}",
+                traceroute_cmd,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            )));
+        }
+        Ok(probes)
+    }
+
     fn parse_traceroute_output(output: &str) -> Result<Vec<TracerouteHop>> {
         let mut hops = Vec::new();
 
@@ -329,6 +509,37 @@ impl DiagnosticTools {
         Ok(hops)
     }
 
+    /// Answer throughput tests from other nodes on `port`
+    pub async fn throughput_server(port: u16) -> Result<ThroughputServer> {
+        ThroughputServer::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).await
+    }
+
+    /// Start a throughput test against a node running a throughput server
+    ///
+    /// `target` is `host` or `host:port`; the port defaults to
+    /// [`DEFAULT_THROUGHPUT_PORT`]. The returned handle can cancel the test
+    /// or wait for its result.
+    pub async fn throughput_test(target: &str, options: &ThroughputOptions) -> Result<ThroughputTest> {
+        options.validate()?;
+
+        let server = if let Ok(addr) = target.parse::<SocketAddr>() {
+            addr
+        } else if let Ok(ip) = target.parse::<IpAddr>() {
+            SocketAddr::new(ip, DEFAULT_THROUGHPUT_PORT)
+        } else {
+            let addrs: Vec<SocketAddr> = if target.contains(':') {
+                tokio::net::lookup_host(target).await?.collect()
+            } else {
+                tokio::net::lookup_host((target, DEFAULT_THROUGHPUT_PORT)).await?.collect()
+            };
+            addrs.first().copied().ok_or_else(|| {
+                patronus_core::Error::Network(format!("Cannot resolve {}", target))
+            })?
+        };
+
+        Ok(ThroughputTest::start(target, server, options.clone()))
+    }
+
     /// DNS lookup
     pub async fn dns_lookup(
         query: &str,
@@ -371,6 +582,160 @@ impl DiagnosticTools {
         })
     }
 
+    /// Query one DNS server directly, or the first system resolver
+    ///
+    /// SERVFAIL, NXDOMAIN and timeouts are reported as distinct statuses.
+    pub async fn dns_query(
+        query: &str,
+        record_type: &str,
+        server: Option<SocketAddr>,
+    ) -> Result<DnsServerAnswer> {
+        DnsClient::new().query(query, record_type, Self::dns_server(server)?).await
+    }
+
+    /// Query each of `record_types` in parallel, e.g. [`dns::COMMON_RECORD_TYPES`]
+    pub async fn dns_query_types(
+        query: &str,
+        record_types: &[&str],
+        server: Option<SocketAddr>,
+    ) -> Result<Vec<DnsServerAnswer>> {
+        DnsClient::new().query_types(query, record_types, Self::dns_server(server)?).await
+    }
+
+    /// Compare answers across resolvers; no servers means the system resolvers
+    pub async fn dns_propagation_check(
+        query: &str,
+        record_type: &str,
+        servers: &[SocketAddr],
+    ) -> Result<DnsPropagationReport> {
+        let servers = if servers.is_empty() { dns::system_resolvers() } else { servers.to_vec() };
+        DnsClient::new().propagation_check(query, record_type, &servers).await
+    }
+
+    /// Reverse (PTR) lookup of an address
+    pub async fn reverse_lookup(ip: IpAddr, server: Option<SocketAddr>) -> Result<DnsServerAnswer> {
+        DnsClient::new().reverse_lookup(ip, Self::dns_server(server)?).await
+    }
+
+    /// Validate an answer with DNSSEC through a validating resolver
+    pub async fn dnssec_validate(
+        query: &str,
+        record_type: &str,
+        server: Option<SocketAddr>,
+    ) -> Result<DnssecReport> {
+        DnsClient::new().dnssec_check(query, record_type, Self::dns_server(server)?).await
+    }
+
+    /// Resolve iteratively from the root servers, like `dig +trace`
+    pub async fn dns_trace(query: &str, record_type: &str) -> Result<DnsTraceResult> {
+        DnsClient::new().trace(query, record_type).await
+    }
+
+    fn dns_server(server: Option<SocketAddr>) -> Result<SocketAddr> {
+        server
+            .or_else(|| dns::system_resolvers().first().copied())
+            .ok_or_else(|| patronus_core::Error::Config("No DNS server configured".to_string()))
+    }
+
+    /// Look up a name via DNS-over-HTTPS (RFC 8484) using GET
+    pub async fn doh_lookup(
+        query: &str,
+        record_type: &str,
+        doh_url: &str,
+    ) -> Result<DohLookupResult> {
+        Self::doh_lookup_with_method(query, record_type, doh_url, DohMethod::Get).await
+    }
+
+    /// Look up a name via DNS-over-HTTPS (RFC 8484)
+    ///
+    /// Transport failures are reported in `error` with no `http_status`;
+    /// a decoded DNS answer always carries its `rcode`.
+    pub async fn doh_lookup_with_method(
+        query: &str,
+        record_type: &str,
+        doh_url: &str,
+        method: DohMethod,
+    ) -> Result<DohLookupResult> {
+        use base64::Engine;
+
+        let qtype = doh::record_type_code(record_type).ok_or_else(|| {
+            patronus_core::Error::Config(format!("Unsupported record type: {}", record_type))
+        })?;
+        let message = doh::build_query(query, qtype)?;
+
+        let mut result = DohLookupResult {
+            query: query.to_string(),
+            record_type: record_type.to_uppercase(),
+            doh_url: doh_url.to_string(),
+            method,
+            http_status: None,
+            rcode: None,
+            rcode_name: None,
+            records: Vec::new(),
+            query_time_ms: None,
+            error: None,
+            timestamp: SystemTime::now(),
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| patronus_core::Error::Network(e.to_string()))?;
+
+        let request = match method {
+            DohMethod::Get => {
+                let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&message);
+                client.get(doh_url).query(&[("dns", encoded)])
+            }
+            DohMethod::Post => client
+                .post(doh_url)
+                .header(reqwest::header::CONTENT_TYPE, doh::DNS_MESSAGE_CONTENT_TYPE)
+                .body(message),
+        };
+
+        let start = SystemTime::now();
+        let response = request
+            .header(reqwest::header::ACCEPT, doh::DNS_MESSAGE_CONTENT_TYPE)
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(r) => r,
+            Err(e) => {
+                result.error = Some(format!("Transport error: {}", e));
+                return Ok(result);
+            }
+        };
+
+        let status = response.status();
+        result.http_status = Some(status.as_u16());
+
+        let body = match response.bytes().await {
+            Ok(b) => b,
+            Err(e) => {
+                result.error = Some(format!("Transport error: {}", e));
+                return Ok(result);
+            }
+        };
+        result.query_time_ms = start.elapsed().ok().map(|d| d.as_secs_f64() * 1000.0);
+
+        if !status.is_success() {
+            result.error = Some(format!("HTTP error: {}", status));
+            return Ok(result);
+        }
+
+        match doh::parse_response(&body) {
+            Ok(answer) => {
+                result.rcode = Some(answer.rcode);
+                result.rcode_name = Some(doh::rcode_name(answer.rcode));
+                result.records = answer.answers;
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+
+        Ok(result)
+    }
+
     fn parse_dig_output(output: &str, record_type: &str) -> Result<Vec<DnsRecord>> {
         let mut records = Vec::new();
 
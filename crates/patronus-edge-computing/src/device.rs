@@ -1,11 +1,32 @@
 //! IoT Device Management
+//!
+//! Devices are tracked with their latest metrics and can be updated over
+//! the air through staged [`FirmwareRollout`]s. Tags and groups select
+//! devices for bulk operations through a [`DeviceController`]. Devices can
+//! also enroll themselves with a one-time token, see [`crate::enrollment`].
 
+use crate::enrollment::{
+    EnrolledDevice, EnrollmentMode, EnrollmentOutcome, EnrollmentPeer, EnrollmentRequest,
+    EnrollmentStats, EnrollmentToken, PendingEnrollment, QuarantineHook,
+};
+use crate::fleet::{DeviceController, DeviceGroup, DeviceOpResult};
+use crate::firmware::{
+    DeviceFilter, DeviceUpdate, DeviceUpdateStatus, Firmware, FirmwareInstaller, FirmwareRollout,
+    RolloutState,
+};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinSet;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use patronus_security::pki::fingerprint;
+use patronus_security::CertificateAuthority;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DeviceType {
@@ -34,6 +55,22 @@ pub struct IoTDevice {
     pub location: (f64, f64), // lat, lon
     pub metrics: DeviceMetrics,
     pub online: bool,
+    /// Installed firmware version, if known
+    #[serde(default)]
+    pub firmware_version: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Last known source address
+    #[serde(default)]
+    pub address: Option<IpAddr>,
+    /// SHA-256 fingerprint of the enrolled client certificate
+    #[serde(default)]
+    pub cert_fingerprint: Option<String>,
+    #[serde(default)]
+    pub cert_serial: Option<String>,
+    /// Traffic is blocked but the device stays registered
+    #[serde(default)]
+    pub quarantined: bool,
 }
 
 impl IoTDevice {
@@ -52,6 +89,12 @@ impl IoTDevice {
                 last_seen: Utc::now(),
             },
             online: true,
+            firmware_version: None,
+            tags: BTreeSet::new(),
+            address: None,
+            cert_fingerprint: None,
+            cert_serial: None,
+            quarantined: false,
         }
     }
 
@@ -66,15 +109,99 @@ impl IoTDevice {
 
 pub struct DeviceManager {
     devices: Arc<RwLock<HashMap<Uuid, IoTDevice>>>,
+    rollouts: Arc<RwLock<HashMap<Uuid, FirmwareRollout>>>,
+    /// Images by version, so a rollback can reinstall the previous one
+    firmware: Arc<RwLock<HashMap<String, Firmware>>>,
+    installer: Option<Arc<dyn FirmwareInstaller>>,
+    health_grace: Duration,
+    groups: Arc<RwLock<HashMap<String, DeviceGroup>>>,
+    controller: Option<Arc<dyn DeviceController>>,
+    bulk_concurrency: usize,
+    bulk_timeout: Duration,
+    ca: Option<Arc<CertificateAuthority>>,
+    cert_validity_days: u32,
+    enrollment_mode: EnrollmentMode,
+    enrollment: Arc<RwLock<EnrollmentState>>,
+    quarantine_hook: Option<Arc<dyn QuarantineHook>>,
+}
+
+/// Outstanding tokens, approval queue and counters
+#[derive(Default)]
+struct EnrollmentState {
+    tokens: HashMap<String, EnrollmentToken>,
+    pending: HashMap<Uuid, PendingEnrollment>,
+    tokens_issued: u64,
+    enrolled: u64,
+    re_enrolled: u64,
+    rejected: u64,
 }
 
 impl DeviceManager {
     pub fn new() -> Self {
         Self {
             devices: Arc::new(RwLock::new(HashMap::new())),
+            rollouts: Arc::new(RwLock::new(HashMap::new())),
+            firmware: Arc::new(RwLock::new(HashMap::new())),
+            installer: None,
+            health_grace: Duration::from_secs(60),
+            groups: Arc::new(RwLock::new(HashMap::new())),
+            controller: None,
+            bulk_concurrency: 16,
+            bulk_timeout: Duration::from_secs(30),
+            ca: None,
+            cert_validity_days: 365,
+            enrollment_mode: EnrollmentMode::Automatic,
+            enrollment: Arc::new(RwLock::new(EnrollmentState::default())),
+            quarantine_hook: None,
         }
     }
 
+    /// Use `installer` to push firmware during rollouts
+    pub fn with_installer(mut self, installer: Arc<dyn FirmwareInstaller>) -> Self {
+        self.installer = Some(installer);
+        self
+    }
+
+    /// How long an updated device has to come back online before it counts
+    /// as failed
+    pub fn with_health_grace(mut self, grace: Duration) -> Self {
+        self.health_grace = grace;
+        self
+    }
+
+    /// Use `controller` for group reboots, config pushes and metric queries
+    pub fn with_controller(mut self, controller: Arc<dyn DeviceController>) -> Self {
+        self.controller = Some(controller);
+        self
+    }
+
+    /// Limit bulk operations to `concurrency` devices in flight, each given
+    /// up to `timeout`
+    pub fn with_bulk_limits(mut self, concurrency: usize, timeout: Duration) -> Self {
+        self.bulk_concurrency = concurrency.max(1);
+        self.bulk_timeout = timeout;
+        self
+    }
+
+    /// Issue enrolled devices client certificates from `ca`, valid for
+    /// `validity_days`
+    pub fn with_certificate_authority(mut self, ca: Arc<CertificateAuthority>, validity_days: u32) -> Self {
+        self.ca = Some(ca);
+        self.cert_validity_days = validity_days;
+        self
+    }
+
+    pub fn with_enrollment_mode(mut self, mode: EnrollmentMode) -> Self {
+        self.enrollment_mode = mode;
+        self
+    }
+
+    /// Use `hook` to block the traffic of quarantined devices
+    pub fn with_quarantine_hook(mut self, hook: Arc<dyn QuarantineHook>) -> Self {
+        self.quarantine_hook = Some(hook);
+        self
+    }
+
     pub async fn register_device(&self, device: IoTDevice) -> Uuid {
         let id = device.id;
         let mut devices = self.devices.write().await;
@@ -87,9 +214,18 @@ impl DeviceManager {
         devices.get(id).cloned()
     }
 
+    /// Remove a device, revoking its enrolled certificate
     pub async fn unregister_device(&self, id: &Uuid) -> bool {
         let mut devices = self.devices.write().await;
-        devices.remove(id).is_some()
+        let Some(device) = devices.remove(id) else {
+            return false;
+        };
+        if let (Some(ca), Some(serial)) = (&self.ca, &device.cert_serial) {
+            if let Err(e) = ca.revoke_certificate(serial) {
+                tracing::warn!("Failed to revoke certificate of device {}: {}", id, e);
+            }
+        }
+        true
     }
 
     pub async fn list_devices(&self) -> Vec<IoTDevice> {
@@ -139,6 +275,693 @@ impl DeviceManager {
     }
 }
 
+impl DeviceManager {
+    /// Make an image known, e.g. the baseline a rollback returns to
+    pub async fn register_firmware(&self, firmware: Firmware) {
+        self.firmware.write().await.insert(firmware.version.clone(), firmware);
+    }
+
+    /// Update the devices matching `target` to `firmware`, `batch_size`
+    /// at a time
+    ///
+    /// Devices already on the version are skipped. The rollout pauses
+    /// itself after a batch in which more than `failure_threshold` (0 to 1)
+    /// of the devices failed to install or did not come back online.
+    pub async fn start_rollout(
+        &self,
+        firmware: Firmware,
+        target: DeviceFilter,
+        batch_size: usize,
+        failure_threshold: f64,
+    ) -> Result<Uuid> {
+        if self.installer.is_none() {
+            anyhow::bail!("No firmware installer configured");
+        }
+        if batch_size == 0 {
+            anyhow::bail!("Batch size must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&failure_threshold) {
+            anyhow::bail!("Failure threshold must be between 0 and 1, got {}", failure_threshold);
+        }
+
+        let mut targets: Vec<IoTDevice> = self.devices.read().await.values()
+            .filter(|d| target.matches(d) && d.firmware_version.as_ref() != Some(&firmware.version))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            anyhow::bail!("No devices need firmware {}", firmware.version);
+        }
+        targets.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        let mut rollouts = self.rollouts.write().await;
+        if let Some(busy) = rollouts.values()
+            .filter(|r| r.state.is_active())
+            .find(|r| r.devices.iter().any(|u| targets.iter().any(|d| d.id == u.device_id)))
+        {
+            anyhow::bail!("Devices are already part of active rollout {}", busy.id);
+        }
+
+        let now = Utc::now();
+        let rollout = FirmwareRollout {
+            id: Uuid::new_v4(),
+            firmware: firmware.clone(),
+            batch_size,
+            failure_threshold,
+            state: RolloutState::Running,
+            devices: targets.iter()
+                .map(|d| DeviceUpdate {
+                    device_id: d.id,
+                    device_name: d.name.clone(),
+                    previous_version: d.firmware_version.clone(),
+                    status: DeviceUpdateStatus::Pending,
+                    batch: None,
+                    updated_at: None,
+                })
+                .collect(),
+            batches_completed: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        let id = rollout.id;
+        rollouts.insert(id, rollout);
+        drop(rollouts);
+
+        self.register_firmware(firmware.clone()).await;
+        tracing::info!("Starting rollout {} of firmware {} to {} devices", id, firmware.version, targets.len());
+        tokio::spawn(self.rollout_driver().run(id));
+        Ok(id)
+    }
+
+    /// Stop a running rollout after its current batch
+    pub async fn pause_rollout(&self, id: &Uuid) -> bool {
+        let mut rollouts = self.rollouts.write().await;
+        match rollouts.get_mut(id) {
+            Some(rollout) if rollout.state == RolloutState::Running => {
+                rollout.state = RolloutState::Paused { reason: "Paused by operator".to_string() };
+                rollout.updated_at = Utc::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Continue a paused rollout with its remaining devices
+    pub async fn resume_rollout(&self, id: &Uuid) -> Result<()> {
+        {
+            let mut rollouts = self.rollouts.write().await;
+            let rollout = rollouts.get_mut(id).ok_or_else(|| anyhow::anyhow!("Rollout {} not found", id))?;
+            if !matches!(rollout.state, RolloutState::Paused { .. }) {
+                anyhow::bail!("Rollout {} is not paused", id);
+            }
+            rollout.state = RolloutState::Running;
+            rollout.updated_at = Utc::now();
+        }
+        tokio::spawn(self.rollout_driver().run(*id));
+        Ok(())
+    }
+
+    /// Reinstall the previous firmware on every device the rollout updated
+    ///
+    /// The rollout must be paused or completed. Devices whose previous
+    /// version was never registered are marked `RollbackFailed`.
+    pub async fn rollback_rollout(&self, id: &Uuid) -> Result<()> {
+        {
+            let mut rollouts = self.rollouts.write().await;
+            let rollout = rollouts.get_mut(id).ok_or_else(|| anyhow::anyhow!("Rollout {} not found", id))?;
+            if !matches!(rollout.state, RolloutState::Paused { .. } | RolloutState::Completed) {
+                anyhow::bail!("Rollout {} must be paused or completed to roll back", id);
+            }
+            rollout.state = RolloutState::RollingBack;
+            rollout.updated_at = Utc::now();
+        }
+        tokio::spawn(self.rollout_driver().rollback(*id));
+        Ok(())
+    }
+
+    pub async fn get_rollout(&self, id: &Uuid) -> Option<FirmwareRollout> {
+        self.rollouts.read().await.get(id).cloned()
+    }
+
+    pub async fn list_rollouts(&self) -> Vec<FirmwareRollout> {
+        self.rollouts.read().await.values().cloned().collect()
+    }
+
+    fn rollout_driver(&self) -> RolloutDriver {
+        RolloutDriver {
+            devices: self.devices.clone(),
+            rollouts: self.rollouts.clone(),
+            firmware: self.firmware.clone(),
+            installer: self.installer.clone().expect("checked when the rollout started"),
+            health_grace: self.health_grace,
+        }
+    }
+}
+
+/// Background task state for one rollout
+struct RolloutDriver {
+    devices: Arc<RwLock<HashMap<Uuid, IoTDevice>>>,
+    rollouts: Arc<RwLock<HashMap<Uuid, FirmwareRollout>>>,
+    firmware: Arc<RwLock<HashMap<String, Firmware>>>,
+    installer: Arc<dyn FirmwareInstaller>,
+    health_grace: Duration,
+}
+
+impl RolloutDriver {
+    /// Update batches until the rollout completes, pauses or is paused
+    async fn run(self, id: Uuid) {
+        loop {
+            let (batch, devices, firmware) = {
+                let mut rollouts = self.rollouts.write().await;
+                let Some(rollout) = rollouts.get_mut(&id) else {
+                    return;
+                };
+                if rollout.state != RolloutState::Running {
+                    return;
+                }
+
+                let batch = rollout.batches_completed;
+                let mut devices = Vec::new();
+                for update in rollout.devices.iter_mut()
+                    .filter(|u| u.status == DeviceUpdateStatus::Pending)
+                    .take(rollout.batch_size)
+                {
+                    update.status = DeviceUpdateStatus::Installing;
+                    update.batch = Some(batch);
+                    devices.push(update.device_id);
+                }
+                if devices.is_empty() {
+                    rollout.state = RolloutState::Completed;
+                    rollout.updated_at = Utc::now();
+                    tracing::info!("Rollout {} completed: {} updated, {} failed",
+                        id, rollout.succeeded(), rollout.failed());
+                    return;
+                }
+                (batch, devices, rollout.firmware.clone())
+            };
+
+            let mut results = self.install_all(&devices, &firmware).await;
+
+            // Devices that installed must still be online after the grace period
+            tokio::time::sleep(self.health_grace).await;
+            {
+                let registered = self.devices.read().await;
+                for (device_id, result) in results.iter_mut() {
+                    if result.is_ok() && !registered.get(device_id).is_some_and(|d| d.online) {
+                        *result = Err("Device did not come back online after the update".to_string());
+                    }
+                }
+            }
+
+            let mut rollouts = self.rollouts.write().await;
+            let Some(rollout) = rollouts.get_mut(&id) else {
+                return;
+            };
+            let now = Utc::now();
+            for update in rollout.devices.iter_mut().filter(|u| u.batch == Some(batch)) {
+                update.status = match results.remove(&update.device_id) {
+                    Some(Ok(())) => DeviceUpdateStatus::Succeeded,
+                    Some(Err(reason)) => DeviceUpdateStatus::Failed { reason },
+                    None => DeviceUpdateStatus::Failed { reason: "No install result".to_string() },
+                };
+                update.updated_at = Some(now);
+            }
+            rollout.batches_completed += 1;
+            rollout.updated_at = now;
+
+            let failure_rate = rollout.batch_failure_rate(batch);
+            if failure_rate > rollout.failure_threshold {
+                let reason = format!(
+                    "Batch {} failure rate {:.0}% exceeds threshold {:.0}%",
+                    batch + 1, failure_rate * 100.0, rollout.failure_threshold * 100.0
+                );
+                tracing::warn!("Pausing rollout {}: {}", id, reason);
+                rollout.state = RolloutState::Paused { reason };
+            }
+        }
+    }
+
+    /// Return updated devices to their previous firmware
+    async fn rollback(self, id: Uuid) {
+        let Some(rollout) = self.rollouts.read().await.get(&id).cloned() else {
+            return;
+        };
+        let images = self.firmware.read().await.clone();
+
+        let mut outcomes = HashMap::new();
+        let mut by_version: HashMap<String, Vec<Uuid>> = HashMap::new();
+        for update in &rollout.devices {
+            let installed = self.devices.read().await
+                .get(&update.device_id)
+                .is_some_and(|d| d.firmware_version.as_ref() == Some(&rollout.firmware.version));
+            if !installed {
+                continue;
+            }
+            match update.previous_version.as_ref().filter(|v| images.contains_key(*v)) {
+                Some(version) => by_version.entry(version.clone()).or_default().push(update.device_id),
+                None => {
+                    let reason = match &update.previous_version {
+                        Some(version) => format!("Previous firmware {} is not registered", version),
+                        None => "Previous firmware version unknown".to_string(),
+                    };
+                    outcomes.insert(update.device_id, DeviceUpdateStatus::RollbackFailed { reason });
+                }
+            }
+        }
+
+        for (version, devices) in by_version {
+            for (device_id, result) in self.install_all(&devices, &images[&version]).await {
+                let status = match result {
+                    Ok(()) => DeviceUpdateStatus::RolledBack,
+                    Err(reason) => DeviceUpdateStatus::RollbackFailed { reason },
+                };
+                outcomes.insert(device_id, status);
+            }
+        }
+
+        let mut rollouts = self.rollouts.write().await;
+        if let Some(rollout) = rollouts.get_mut(&id) {
+            let now = Utc::now();
+            for update in rollout.devices.iter_mut() {
+                if let Some(status) = outcomes.remove(&update.device_id) {
+                    update.status = status;
+                    update.updated_at = Some(now);
+                }
+            }
+            rollout.state = RolloutState::RolledBack;
+            rollout.updated_at = now;
+            tracing::info!("Rollout {} rolled back", id);
+        }
+    }
+
+    /// Install `firmware` on `devices` concurrently, recording the new
+    /// version on success
+    async fn install_all(&self, devices: &[Uuid], firmware: &Firmware) -> HashMap<Uuid, std::result::Result<(), String>> {
+        let mut results = HashMap::new();
+        let mut installs = JoinSet::new();
+        for device_id in devices {
+            let Some(device) = self.devices.read().await.get(device_id).cloned() else {
+                results.insert(*device_id, Err("Device is no longer registered".to_string()));
+                continue;
+            };
+            let installer = self.installer.clone();
+            let firmware = firmware.clone();
+            installs.spawn(async move {
+                let result = installer.install(&device, &firmware).await.map_err(|e| e.to_string());
+                (device.id, result)
+            });
+        }
+
+        while let Some(joined) = installs.join_next().await {
+            let Ok((device_id, result)) = joined else {
+                continue;
+            };
+            if result.is_ok() {
+                if let Some(device) = self.devices.write().await.get_mut(&device_id) {
+                    device.firmware_version = Some(firmware.version.clone());
+                }
+            }
+            results.insert(device_id, result);
+        }
+        results
+    }
+}
+
+impl DeviceManager {
+    pub async fn add_tag(&self, id: &Uuid, tag: &str) -> bool {
+        let mut devices = self.devices.write().await;
+        match devices.get_mut(id) {
+            Some(device) => {
+                device.tags.insert(tag.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn remove_tag(&self, id: &Uuid, tag: &str) -> bool {
+        let mut devices = self.devices.write().await;
+        devices.get_mut(id).is_some_and(|d| d.tags.remove(tag))
+    }
+
+    pub async fn get_devices_by_tag(&self, tag: &str) -> Vec<IoTDevice> {
+        let devices = self.devices.read().await;
+        devices.values()
+            .filter(|d| d.tags.contains(tag))
+            .cloned()
+            .collect()
+    }
+
+    /// Create or replace a group
+    pub async fn create_group(&self, group: DeviceGroup) {
+        self.groups.write().await.insert(group.name.clone(), group);
+    }
+
+    pub async fn delete_group(&self, name: &str) -> bool {
+        self.groups.write().await.remove(name).is_some()
+    }
+
+    pub async fn add_to_group(&self, name: &str, device_id: Uuid) -> Result<()> {
+        if !self.devices.read().await.contains_key(&device_id) {
+            anyhow::bail!("Device {} not found", device_id);
+        }
+        let mut groups = self.groups.write().await;
+        let group = groups.get_mut(name).ok_or_else(|| anyhow::anyhow!("Group {} not found", name))?;
+        group.members.insert(device_id);
+        Ok(())
+    }
+
+    pub async fn remove_from_group(&self, name: &str, device_id: &Uuid) -> bool {
+        let mut groups = self.groups.write().await;
+        groups.get_mut(name).is_some_and(|g| g.members.remove(device_id))
+    }
+
+    pub async fn get_group(&self, name: &str) -> Option<DeviceGroup> {
+        self.groups.read().await.get(name).cloned()
+    }
+
+    pub async fn list_groups(&self) -> Vec<DeviceGroup> {
+        self.groups.read().await.values().cloned().collect()
+    }
+
+    /// Registered devices currently in the group, sorted by name
+    pub async fn get_group_devices(&self, name: &str) -> Result<Vec<IoTDevice>> {
+        let group = self.get_group(name).await
+            .ok_or_else(|| anyhow::anyhow!("Group {} not found", name))?;
+        let mut members: Vec<IoTDevice> = self.devices.read().await.values()
+            .filter(|d| group.contains(d))
+            .cloned()
+            .collect();
+        members.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        Ok(members)
+    }
+
+    pub async fn reboot_group(&self, name: &str) -> Result<Vec<DeviceOpResult<()>>> {
+        self.run_on_group(name, |controller, device| async move {
+            controller.reboot(&device).await
+        })
+        .await
+    }
+
+    pub async fn apply_config_to_group(&self, name: &str, config: serde_json::Value) -> Result<Vec<DeviceOpResult<()>>> {
+        let config = Arc::new(config);
+        self.run_on_group(name, move |controller, device| {
+            let config = config.clone();
+            async move { controller.apply_config(&device, &config).await }
+        })
+        .await
+    }
+
+    /// Fetch metrics from every group member, storing the ones that arrive
+    pub async fn query_group_metrics(&self, name: &str) -> Result<Vec<DeviceOpResult<DeviceMetrics>>> {
+        let results = self.run_on_group(name, |controller, device| async move {
+            controller.query_metrics(&device).await
+        })
+        .await?;
+
+        let mut devices = self.devices.write().await;
+        for outcome in &results {
+            if let (Ok(metrics), Some(device)) = (&outcome.result, devices.get_mut(&outcome.device_id)) {
+                device.metrics = metrics.clone();
+            }
+        }
+        Ok(results)
+    }
+
+    /// Run `op` against every group member, at most `bulk_concurrency` at a
+    /// time
+    ///
+    /// Offline devices are reported as failed without being contacted. A
+    /// device failing, timing out or panicking only affects its own result.
+    async fn run_on_group<T, F, Fut>(&self, name: &str, op: F) -> Result<Vec<DeviceOpResult<T>>>
+    where
+        T: Send + 'static,
+        F: Fn(Arc<dyn DeviceController>, IoTDevice) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        let controller = self.controller.clone()
+            .ok_or_else(|| anyhow::anyhow!("No device controller configured"))?;
+        let members = self.get_group_devices(name).await?;
+
+        let permits = Arc::new(Semaphore::new(self.bulk_concurrency));
+        let timeout = self.bulk_timeout;
+        let mut results = Vec::with_capacity(members.len());
+        let mut tasks = JoinSet::new();
+        let mut pending = HashMap::new();
+        for device in members {
+            if !device.online {
+                results.push(DeviceOpResult {
+                    device_id: device.id,
+                    device_name: device.name,
+                    result: Err("Device is offline".to_string()),
+                });
+                continue;
+            }
+
+            let (device_id, device_name) = (device.id, device.name.clone());
+            let call = op(controller.clone(), device);
+            let permits = permits.clone();
+            let task = tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await.expect("semaphore is never closed");
+                match tokio::time::timeout(timeout, call).await {
+                    Ok(result) => result.map_err(|e| e.to_string()),
+                    Err(_) => Err(format!("Timed out after {:?}", timeout)),
+                }
+            });
+            pending.insert(task.id(), (device_id, device_name));
+        }
+
+        while let Some(joined) = tasks.join_next_with_id().await {
+            let (task_id, result) = match joined {
+                Ok((task_id, result)) => (task_id, result),
+                Err(e) => (e.id(), Err(format!("Operation aborted: {}", e))),
+            };
+            if let Some((device_id, device_name)) = pending.remove(&task_id) {
+                results.push(DeviceOpResult { device_id, device_name, result });
+            }
+        }
+
+        results.sort_by(|a, b| a.device_name.cmp(&b.device_name).then(a.device_id.cmp(&b.device_id)));
+        let failed = results.iter().filter(|r| !r.is_ok()).count();
+        if failed > 0 {
+            tracing::warn!("Bulk operation on group {} failed for {} of {} devices", name, failed, results.len());
+        }
+        Ok(results)
+    }
+}
+
+impl DeviceManager {
+    /// Mint `count` single-use tokens for devices of `device_type`, valid
+    /// for `ttl`
+    ///
+    /// Expired and redeemed tokens are dropped at the same time.
+    pub async fn generate_enrollment_tokens(
+        &self,
+        count: usize,
+        device_type: DeviceType,
+        ttl: Duration,
+    ) -> Result<Vec<EnrollmentToken>> {
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::from_std(ttl)?;
+        let tokens: Vec<EnrollmentToken> = (0..count)
+            .map(|_| EnrollmentToken::generate(device_type.clone(), expires_at))
+            .collect();
+
+        let mut state = self.enrollment.write().await;
+        state.tokens.retain(|_, t| t.used_at.is_none() && !t.is_expired(now));
+        for token in &tokens {
+            state.tokens.insert(token.token.clone(), token.clone());
+        }
+        state.tokens_issued += tokens.len() as u64;
+        tracing::info!("Generated {} enrollment tokens for {:?} devices", tokens.len(), device_type);
+        Ok(tokens)
+    }
+
+    /// Redeem a token for a client certificate
+    ///
+    /// A request made over a connection authenticated with an enrolled
+    /// device's current certificate replaces that certificate and revokes
+    /// the old one. In manual approval mode the token is consumed and the
+    /// request queued.
+    pub async fn enroll(&self, request: EnrollmentRequest, peer: EnrollmentPeer) -> Result<EnrollmentOutcome> {
+        if self.ca.is_none() {
+            anyhow::bail!("No certificate authority configured");
+        }
+
+        {
+            let mut state = self.enrollment.write().await;
+            let now = Utc::now();
+            let refusal = match state.tokens.get(&request.token) {
+                None => Some("Unknown enrollment token".to_string()),
+                Some(t) if t.used_at.is_some() => Some("Enrollment token already used".to_string()),
+                Some(t) if t.is_expired(now) => Some("Enrollment token expired".to_string()),
+                Some(t) if t.device_type != request.device_type => Some(format!(
+                    "Enrollment token is for {:?} devices, not {:?}",
+                    t.device_type, request.device_type
+                )),
+                Some(_) => None,
+            };
+            if let Some(reason) = refusal {
+                state.rejected += 1;
+                tracing::warn!("Rejected enrollment of {}: {}", request.name, reason);
+                anyhow::bail!(reason);
+            }
+            if let Some(token) = state.tokens.get_mut(&request.token) {
+                token.used_at = Some(now);
+            }
+
+            if self.enrollment_mode == EnrollmentMode::ManualApproval {
+                let pending = PendingEnrollment {
+                    id: Uuid::new_v4(),
+                    request,
+                    peer,
+                    requested_at: now,
+                };
+                let request_id = pending.id;
+                tracing::info!("Enrollment of {} awaiting approval ({})", pending.request.name, request_id);
+                state.pending.insert(request_id, pending);
+                return Ok(EnrollmentOutcome::PendingApproval { request_id });
+            }
+        }
+
+        Ok(EnrollmentOutcome::Enrolled(self.complete_enrollment(request, peer).await?))
+    }
+
+    pub async fn pending_enrollments(&self) -> Vec<PendingEnrollment> {
+        let mut pending: Vec<_> = self.enrollment.read().await.pending.values().cloned().collect();
+        pending.sort_by_key(|p| p.requested_at);
+        pending
+    }
+
+    /// Issue the certificate for a queued request
+    pub async fn approve_enrollment(&self, request_id: &Uuid) -> Result<EnrolledDevice> {
+        let pending = self.enrollment.write().await.pending.remove(request_id)
+            .ok_or_else(|| anyhow::anyhow!("Enrollment request {} not found", request_id))?;
+        self.complete_enrollment(pending.request, pending.peer).await
+    }
+
+    /// Drop a queued request; its token stays used
+    pub async fn reject_enrollment(&self, request_id: &Uuid) -> bool {
+        let mut state = self.enrollment.write().await;
+        if state.pending.remove(request_id).is_none() {
+            return false;
+        }
+        state.rejected += 1;
+        true
+    }
+
+    async fn complete_enrollment(&self, request: EnrollmentRequest, peer: EnrollmentPeer) -> Result<EnrolledDevice> {
+        let ca = self.ca.as_ref().ok_or_else(|| anyhow::anyhow!("No certificate authority configured"))?;
+        let current_fingerprint = peer.client_cert_pem.as_deref().map(fingerprint).transpose()?;
+        let mut devices = self.devices.write().await;
+
+        // Only the certificate the connection authenticated with, which
+        // must still be the device's current one, selects a device
+        let existing = match &current_fingerprint {
+            Some(fingerprint) => Some(
+                devices.values()
+                    .find(|d| d.cert_fingerprint.as_ref() == Some(fingerprint))
+                    .map(|d| d.id)
+                    .ok_or_else(|| anyhow::anyhow!("No device enrolled with certificate {}", fingerprint))?,
+            ),
+            None => None,
+        };
+
+        let device_id = existing.unwrap_or_else(Uuid::new_v4);
+        let cert = ca.issue_client_certificate(&device_id.to_string(), self.cert_validity_days)?;
+        let device = match existing {
+            Some(id) => {
+                let device = devices.get_mut(&id).expect("found above");
+                if let Some(serial) = device.cert_serial.replace(cert.serial.clone()) {
+                    ca.revoke_certificate(&serial)?;
+                }
+                device
+            }
+            None => {
+                let mut device = IoTDevice::new(request.name.clone(), request.device_type.clone(), request.location);
+                device.id = device_id;
+                device.cert_serial = Some(cert.serial.clone());
+                devices.entry(device_id).or_insert(device)
+            }
+        };
+        device.cert_fingerprint = Some(cert.fingerprint.clone());
+        if peer.address.is_some() {
+            device.address = peer.address;
+        }
+        drop(devices);
+
+        let mut state = self.enrollment.write().await;
+        if existing.is_some() {
+            state.re_enrolled += 1;
+            tracing::info!("Re-enrolled device {} ({})", request.name, device_id);
+        } else {
+            state.enrolled += 1;
+            tracing::info!("Enrolled device {} ({})", request.name, device_id);
+        }
+
+        Ok(EnrolledDevice {
+            device_id,
+            cert_pem: cert.cert_pem,
+            key_pem: cert.key_pem,
+            ca_cert_pem: ca.ca_cert_pem().to_string(),
+            fingerprint: cert.fingerprint,
+            expires_at: DateTime::<Utc>::from(cert.not_after),
+        })
+    }
+
+    /// The device identified by a client certificate fingerprint
+    pub async fn get_device_by_fingerprint(&self, fingerprint: &str) -> Option<IoTDevice> {
+        self.devices.read().await.values()
+            .find(|d| d.cert_fingerprint.as_deref() == Some(fingerprint))
+            .cloned()
+    }
+
+    /// Block a device's traffic while keeping it registered
+    pub async fn quarantine(&self, device_id: &Uuid) -> Result<()> {
+        self.set_quarantined(device_id, true).await
+    }
+
+    pub async fn release_quarantine(&self, device_id: &Uuid) -> Result<()> {
+        self.set_quarantined(device_id, false).await
+    }
+
+    async fn set_quarantined(&self, device_id: &Uuid, quarantined: bool) -> Result<()> {
+        let hook = self.quarantine_hook.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No quarantine hook configured"))?;
+        let mut devices = self.devices.write().await;
+        let device = devices.get_mut(device_id)
+            .ok_or_else(|| anyhow::anyhow!("Device {} not found", device_id))?;
+        if device.quarantined == quarantined {
+            return Ok(());
+        }
+
+        if quarantined {
+            hook.block(device).await?;
+            tracing::warn!("Quarantined device {} ({})", device.name, device_id);
+        } else {
+            hook.unblock(device).await?;
+            tracing::info!("Released device {} ({}) from quarantine", device.name, device_id);
+        }
+        device.quarantined = quarantined;
+        Ok(())
+    }
+
+    pub async fn enrollment_stats(&self) -> EnrollmentStats {
+        let now = Utc::now();
+        let state = self.enrollment.read().await;
+        EnrollmentStats {
+            tokens_issued: state.tokens_issued,
+            tokens_outstanding: state.tokens.values()
+                .filter(|t| t.used_at.is_none() && !t.is_expired(now))
+                .count(),
+            enrolled: state.enrolled,
+            re_enrolled: state.re_enrolled,
+            rejected: state.rejected,
+            pending_approval: state.pending.len(),
+            quarantined: self.devices.read().await.values().filter(|d| d.quarantined).count(),
+        }
+    }
+}
+
 impl Default for DeviceManager {
     fn default() -> Self {
         Self::new()
@@ -315,4 +1138,339 @@ mod tests {
         let low_battery = manager.get_low_battery_devices().await;
         assert_eq!(low_battery.len(), 1);
     }
+
+    struct FlakyInstaller {
+        failing: Vec<String>,
+    }
+
+    #[async_trait::async_trait]
+    impl FirmwareInstaller for FlakyInstaller {
+        async fn install(&self, device: &IoTDevice, firmware: &Firmware) -> anyhow::Result<()> {
+            if firmware.version == "2.0.0" && self.failing.contains(&device.name) {
+                anyhow::bail!("flash verification failed");
+            }
+            Ok(())
+        }
+    }
+
+    fn firmware(version: &str) -> Firmware {
+        Firmware::new(
+            version.to_string(),
+            format!("https://updates.example.com/{}.bin", version),
+            "00".repeat(32),
+        )
+    }
+
+    async fn wait_for_state(manager: &DeviceManager, id: &Uuid, done: impl Fn(&RolloutState) -> bool) -> FirmwareRollout {
+        for _ in 0..200 {
+            let rollout = manager.get_rollout(id).await.unwrap();
+            if done(&rollout.state) {
+                return rollout;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("rollout did not reach the expected state");
+    }
+
+    #[tokio::test]
+    async fn test_rollout_pauses_on_failures_and_rolls_back() {
+        let installer = FlakyInstaller { failing: vec!["b0".to_string(), "b1".to_string()] };
+        let manager = DeviceManager::new()
+            .with_installer(Arc::new(installer))
+            .with_health_grace(Duration::from_millis(5));
+        manager.register_firmware(firmware("1.0.0")).await;
+
+        for name in ["a0", "a1", "a2", "b0", "b1", "b2", "c0", "c1"] {
+            let mut device = IoTDevice::new(name.to_string(), DeviceType::Sensor, (0.0, 0.0));
+            device.firmware_version = Some("1.0.0".to_string());
+            manager.register_device(device).await;
+        }
+        manager.register_device(IoTDevice::new("cam".to_string(), DeviceType::Camera, (0.0, 0.0))).await;
+
+        assert!(manager.start_rollout(firmware("2.0.0"), DeviceFilter::all(), 0, 0.5).await.is_err());
+
+        let id = manager
+            .start_rollout(firmware("2.0.0"), DeviceFilter::by_type(DeviceType::Sensor), 3, 0.5)
+            .await
+            .unwrap();
+
+        // Second batch (b0..b2) loses two of three devices
+        let rollout = wait_for_state(&manager, &id, |s| matches!(s, RolloutState::Paused { .. })).await;
+        assert_eq!(rollout.batches_completed, 2);
+        assert_eq!(rollout.succeeded(), 4);
+        assert_eq!(rollout.failed(), 2);
+        assert_eq!(rollout.pending(), 2);
+        assert!((rollout.batch_failure_rate(1) - 2.0 / 3.0).abs() < 1e-9);
+
+        // Overlapping rollouts are refused while this one is active
+        assert!(manager.start_rollout(firmware("2.0.1"), DeviceFilter::all(), 3, 0.5).await.is_err());
+
+        manager.resume_rollout(&id).await.unwrap();
+        let rollout = wait_for_state(&manager, &id, |s| *s == RolloutState::Completed).await;
+        assert_eq!(rollout.succeeded(), 6);
+        assert_eq!(rollout.batches_completed, 3);
+
+        manager.rollback_rollout(&id).await.unwrap();
+        let rollout = wait_for_state(&manager, &id, |s| *s == RolloutState::RolledBack).await;
+        let rolled_back = rollout.devices.iter().filter(|u| u.status == DeviceUpdateStatus::RolledBack).count();
+        assert_eq!(rolled_back, 6);
+        for device in manager.get_devices_by_type(&DeviceType::Sensor).await {
+            assert_eq!(device.firmware_version.as_deref(), Some("1.0.0"));
+        }
+    }
+
+    struct RecordingController {
+        rejecting: Vec<String>,
+        applied: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DeviceController for RecordingController {
+        async fn reboot(&self, _device: &IoTDevice) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn apply_config(&self, device: &IoTDevice, config: &serde_json::Value) -> anyhow::Result<()> {
+            if self.rejecting.contains(&device.name) {
+                anyhow::bail!("config rejected: unknown key");
+            }
+            assert_eq!(config["sample_interval_s"], 30);
+            self.applied.lock().unwrap().push(device.name.clone());
+            Ok(())
+        }
+
+        async fn query_metrics(&self, device: &IoTDevice) -> anyhow::Result<DeviceMetrics> {
+            Ok(DeviceMetrics { battery_percent: Some(42.0), ..device.metrics.clone() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bulk_config_reports_per_device_results() {
+        let controller = Arc::new(RecordingController {
+            rejecting: vec!["floor2-b".to_string()],
+            applied: std::sync::Mutex::new(Vec::new()),
+        });
+        let manager = DeviceManager::new()
+            .with_controller(controller.clone())
+            .with_bulk_limits(2, Duration::from_secs(5));
+
+        let mut ids = HashMap::new();
+        for name in ["floor1-a", "floor1-b", "floor2-a", "floor2-b", "floor2-c", "lobby"] {
+            let mut device = IoTDevice::new(name.to_string(), DeviceType::Sensor, (0.0, 0.0));
+            if name.starts_with("floor2") {
+                device.tags.insert("floor2".to_string());
+            }
+            device.online = name != "floor2-c";
+            ids.insert(name, manager.register_device(device).await);
+        }
+
+        manager.create_group(DeviceGroup::by_tags("floor2".to_string(), ["floor2".to_string()])).await;
+        manager.add_to_group("floor2", ids["lobby"]).await.unwrap();
+        assert!(manager.add_to_group("missing", ids["lobby"]).await.is_err());
+
+        let results = manager
+            .apply_config_to_group("floor2", serde_json::json!({ "sample_interval_s": 30 }))
+            .await
+            .unwrap();
+
+        let outcomes: Vec<(&str, bool)> = results.iter().map(|r| (r.device_name.as_str(), r.is_ok())).collect();
+        assert_eq!(outcomes, vec![
+            ("floor2-a", true),
+            ("floor2-b", false),
+            ("floor2-c", false),
+            ("lobby", true),
+        ]);
+        assert_eq!(crate::fleet::summarize(&results), (2, 2));
+        assert!(results[1].result.as_ref().unwrap_err().contains("config rejected"));
+        assert_eq!(results[2].result.as_ref().unwrap_err(), "Device is offline");
+
+        let mut applied = controller.applied.lock().unwrap().clone();
+        applied.sort();
+        assert_eq!(applied, vec!["floor2-a", "lobby"]);
+
+        // Untagged devices outside the group are untouched; metrics land on members
+        let metrics = manager.query_group_metrics("floor2").await.unwrap();
+        assert_eq!(crate::fleet::summarize(&metrics), (3, 1));
+        assert_eq!(manager.get_device(&ids["lobby"]).await.unwrap().metrics.battery_percent, Some(42.0));
+        assert_eq!(manager.get_device(&ids["floor1-a"]).await.unwrap().metrics.battery_percent, Some(100.0));
+    }
+
+    #[derive(Default)]
+    struct RecordingFirewall {
+        blocked: std::sync::Mutex<BTreeSet<Uuid>>,
+    }
+
+    #[async_trait::async_trait]
+    impl QuarantineHook for RecordingFirewall {
+        async fn block(&self, device: &IoTDevice) -> anyhow::Result<()> {
+            self.blocked.lock().unwrap().insert(device.id);
+            Ok(())
+        }
+
+        async fn unblock(&self, device: &IoTDevice) -> anyhow::Result<()> {
+            self.blocked.lock().unwrap().remove(&device.id);
+            Ok(())
+        }
+    }
+
+    fn enrollment_request(token: &EnrollmentToken) -> EnrollmentRequest {
+        EnrollmentRequest {
+            token: token.token.clone(),
+            name: "meter-7".to_string(),
+            device_type: DeviceType::Sensor,
+            location: (0.0, 0.0),
+        }
+    }
+
+    /// A connection from 10.20.0.7, authenticated with `client_cert_pem`
+    fn peer(client_cert_pem: Option<&str>) -> EnrollmentPeer {
+        EnrollmentPeer {
+            address: Some("10.20.0.7".parse().unwrap()),
+            client_cert_pem: client_cert_pem.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enroll_re_enroll_and_quarantine() {
+        let ca = Arc::new(CertificateAuthority::new().unwrap());
+        let firewall = Arc::new(RecordingFirewall::default());
+        let manager = DeviceManager::new()
+            .with_certificate_authority(ca.clone(), 90)
+            .with_quarantine_hook(firewall.clone());
+
+        let tokens = manager
+            .generate_enrollment_tokens(3, DeviceType::Sensor, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let expired = manager
+            .generate_enrollment_tokens(1, DeviceType::Sensor, Duration::ZERO)
+            .await
+            .unwrap();
+
+        let EnrollmentOutcome::Enrolled(first) = manager.enroll(enrollment_request(&tokens[0]), peer(None)).await.unwrap() else {
+            panic!("automatic mode enrolls immediately");
+        };
+        let device = manager.get_device_by_fingerprint(&first.fingerprint).await.unwrap();
+        assert_eq!(device.id, first.device_id);
+        assert_eq!(device.address, Some("10.20.0.7".parse().unwrap()));
+
+        // Tokens are single-use, expire and are bound to a device type
+        assert!(manager.enroll(enrollment_request(&tokens[0]), peer(None)).await.is_err());
+        assert!(manager.enroll(enrollment_request(&expired[0]), peer(None)).await.is_err());
+        let mut camera = enrollment_request(&tokens[1]);
+        camera.device_type = DeviceType::Camera;
+        assert!(manager.enroll(camera, peer(None)).await.is_err());
+
+        // Re-enrolling keeps the device and revokes the old certificate
+        let EnrollmentOutcome::Enrolled(second) = manager
+            .enroll(enrollment_request(&tokens[1]), peer(Some(&first.cert_pem)))
+            .await
+            .unwrap()
+        else {
+            panic!("automatic mode enrolls immediately");
+        };
+        assert_eq!(second.device_id, first.device_id);
+        assert_ne!(second.fingerprint, first.fingerprint);
+        assert!(manager.get_device_by_fingerprint(&first.fingerprint).await.is_none());
+        assert!(ca.is_revoked(device.cert_serial.as_deref().unwrap()));
+        assert_eq!(manager.list_devices().await.len(), 1);
+
+        manager.quarantine(&first.device_id).await.unwrap();
+        assert!(firewall.blocked.lock().unwrap().contains(&first.device_id));
+        let listed = manager.list_devices().await;
+        assert!(listed[0].quarantined);
+
+        let stats = manager.enrollment_stats().await;
+        assert_eq!(stats, EnrollmentStats {
+            tokens_issued: 4,
+            tokens_outstanding: 1,
+            enrolled: 1,
+            re_enrolled: 1,
+            rejected: 3,
+            pending_approval: 0,
+            quarantined: 1,
+        });
+
+        manager.release_quarantine(&first.device_id).await.unwrap();
+        assert!(firewall.blocked.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_re_enroll_requires_current_certificate() {
+        let ca = Arc::new(CertificateAuthority::new().unwrap());
+        let manager = DeviceManager::new().with_certificate_authority(ca.clone(), 90);
+        let tokens = manager
+            .generate_enrollment_tokens(3, DeviceType::Sensor, Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        let EnrollmentOutcome::Enrolled(victim) = manager.enroll(enrollment_request(&tokens[0]), peer(None)).await.unwrap() else {
+            panic!("automatic mode enrolls immediately");
+        };
+
+        // A token holder naming the victim in the request body, over a
+        // connection without its certificate, gets a device of their own
+        let body = serde_json::json!({
+            "token": tokens[1].token,
+            "name": "meter-7",
+            "device_type": "Sensor",
+            "location": [0.0, 0.0],
+            "current_fingerprint": victim.fingerprint,
+        });
+        let request: EnrollmentRequest = serde_json::from_value(body).unwrap();
+        let attacker_peer = EnrollmentPeer {
+            address: Some("10.20.0.66".parse().unwrap()),
+            client_cert_pem: None,
+        };
+        let EnrollmentOutcome::Enrolled(attacker) = manager.enroll(request, attacker_peer).await.unwrap() else {
+            panic!("automatic mode enrolls immediately");
+        };
+        assert_ne!(attacker.device_id, victim.device_id);
+        let device = manager.get_device_by_fingerprint(&victim.fingerprint).await.unwrap();
+        assert!(!ca.is_revoked(device.cert_serial.as_deref().unwrap()));
+        assert_eq!(device.address, Some("10.20.0.7".parse().unwrap()));
+        let attacker_device = manager.get_device(&attacker.device_id).await.unwrap();
+        assert_eq!(attacker_device.address, Some("10.20.0.66".parse().unwrap()));
+
+        // A certificate that is not a device's current one selects nothing
+        assert!(manager.enroll(enrollment_request(&tokens[2]), peer(Some(&victim.ca_cert_pem))).await.is_err());
+        assert_eq!(manager.list_devices().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_manual_approval_queue() {
+        let ca = Arc::new(CertificateAuthority::new().unwrap());
+        let manager = DeviceManager::new()
+            .with_certificate_authority(ca.clone(), 90)
+            .with_enrollment_mode(EnrollmentMode::ManualApproval);
+        let tokens = manager
+            .generate_enrollment_tokens(2, DeviceType::Sensor, Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        let EnrollmentOutcome::PendingApproval { request_id } =
+            manager.enroll(enrollment_request(&tokens[0]), peer(None)).await.unwrap()
+        else {
+            panic!("manual mode queues requests");
+        };
+        let EnrollmentOutcome::PendingApproval { request_id: rejected } =
+            manager.enroll(enrollment_request(&tokens[1]), peer(None)).await.unwrap()
+        else {
+            panic!("manual mode queues requests");
+        };
+        assert_eq!(manager.pending_enrollments().await.len(), 2);
+        assert!(manager.list_devices().await.is_empty());
+
+        let enrolled = manager.approve_enrollment(&request_id).await.unwrap();
+        assert!(manager.reject_enrollment(&rejected).await);
+        assert!(manager.approve_enrollment(&rejected).await.is_err());
+        assert!(manager.get_device(&enrolled.device_id).await.is_some());
+
+        let device = manager.get_device(&enrolled.device_id).await.unwrap();
+        assert!(manager.unregister_device(&enrolled.device_id).await);
+        assert!(ca.is_revoked(device.cert_serial.as_deref().unwrap()));
+
+        let stats = manager.enrollment_stats().await;
+        assert_eq!((stats.enrolled, stats.rejected, stats.pending_approval), (1, 1, 0));
+    }
 }
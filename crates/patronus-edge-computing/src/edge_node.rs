@@ -1,11 +1,29 @@
 //! Edge Node Management
+//!
+//! Nodes report CPU, memory, thermal and link metrics, which are combined
+//! into a 0-100 health score. Nodes scoring below the configured threshold
+//! are marked `Degraded`, which keeps the scheduler off them until they
+//! recover. Nodes that miss a heartbeat interval are marked `Degraded`, and
+//! `Offline` once they stay silent for the heartbeat timeout; see
+//! [`crate::heartbeat`] for the protocol.
 
+use crate::heartbeat::{
+    DeviceAggregates, Heartbeat, HeartbeatAck, NodeAlert, NodeAlertKind, SignedHeartbeat, StatusTransition,
+    WorkloadHealth,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeBounds;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 
+/// Status transitions kept per node
+const TIMELINE_LEN: usize = 500;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum NodeStatus {
     Online,
@@ -32,6 +50,95 @@ pub struct EdgeNode {
     pub cpu_usage_percent: f64,
     pub memory_usage_percent: f64,
     pub connected_devices: usize,
+    /// Hottest sensor reading, if the node reports one
+    #[serde(default)]
+    pub temperature_celsius: Option<f64>,
+    /// Uplink utilization
+    #[serde(default)]
+    pub link_utilization_percent: f64,
+    #[serde(default)]
+    pub packet_loss_percent: f64,
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Measured round-trip latency to named sites
+    #[serde(default)]
+    pub site_latency_ms: HashMap<String, f64>,
+    #[serde(default = "Utc::now")]
+    pub last_heartbeat: DateTime<Utc>,
+    /// Set while the node is degraded for missing a heartbeat
+    #[serde(default)]
+    pub heartbeat_late: bool,
+    /// Time from the node sending its last heartbeat to it arriving; unknown
+    /// while the clocks are skewed
+    #[serde(default)]
+    pub heartbeat_delay_ms: Option<f64>,
+    /// Node clock minus controller clock, when beyond the tolerance
+    #[serde(default)]
+    pub clock_skew_ms: Option<i64>,
+    #[serde(default)]
+    pub devices: DeviceAggregates,
+    /// Workload health from the last heartbeat
+    #[serde(default)]
+    pub workloads: Vec<WorkloadHealth>,
+}
+
+/// Metrics reported by a node
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub cpu_usage_percent: f64,
+    pub memory_usage_percent: f64,
+    pub temperature_celsius: Option<f64>,
+    pub link_utilization_percent: f64,
+    pub packet_loss_percent: f64,
+}
+
+/// Relative weight of each metric in the health score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthWeights {
+    pub cpu: f64,
+    pub memory: f64,
+    pub thermal: f64,
+    pub link: f64,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        Self {
+            cpu: 0.3,
+            memory: 0.25,
+            thermal: 0.25,
+            link: 0.2,
+        }
+    }
+}
+
+/// How health scores are computed and acted on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthScoring {
+    pub weights: HealthWeights,
+    /// Temperature up to which the thermal score is perfect
+    pub nominal_temperature_celsius: f64,
+    /// Temperature at which the thermal score reaches zero
+    pub critical_temperature_celsius: f64,
+    /// Packet loss at which the link score reaches zero
+    pub critical_packet_loss_percent: f64,
+    /// Nodes scoring below this are marked degraded
+    pub degraded_below: f64,
+    /// Points above the threshold a degraded node needs to recover
+    pub recovery_margin: f64,
+}
+
+impl Default for HealthScoring {
+    fn default() -> Self {
+        Self {
+            weights: HealthWeights::default(),
+            nominal_temperature_celsius: 60.0,
+            critical_temperature_celsius: 95.0,
+            critical_packet_loss_percent: 10.0,
+            degraded_below: 50.0,
+            recovery_margin: 5.0,
+        }
+    }
 }
 
 impl EdgeNode {
@@ -45,9 +152,91 @@ impl EdgeNode {
             cpu_usage_percent: 0.0,
             memory_usage_percent: 0.0,
             connected_devices: 0,
+            temperature_celsius: None,
+            link_utilization_percent: 0.0,
+            packet_loss_percent: 0.0,
+            region: None,
+            site_latency_ms: HashMap::new(),
+            last_heartbeat: Utc::now(),
+            heartbeat_late: false,
+            heartbeat_delay_ms: None,
+            clock_skew_ms: None,
+            devices: DeviceAggregates::default(),
+            workloads: Vec::new(),
         }
     }
 
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Health from 0 (unusable) to 100 using the default weighting
+    pub fn health_score(&self) -> f64 {
+        self.health_score_with(&HealthScoring::default())
+    }
+
+    /// Health from 0 (unusable) to 100
+    ///
+    /// Each metric is scored from 0 to 100 and the scores are averaged by
+    /// weight. Thermal is left out for nodes without a temperature sensor.
+    pub fn health_score_with(&self, scoring: &HealthScoring) -> f64 {
+        let headroom = |used: f64| (100.0 - used).clamp(0.0, 100.0);
+
+        let thermal = self.temperature_celsius.map(|t| {
+            let span = (scoring.critical_temperature_celsius - scoring.nominal_temperature_celsius).max(f64::EPSILON);
+            (1.0 - (t - scoring.nominal_temperature_celsius) / span).clamp(0.0, 1.0) * 100.0
+        });
+        let loss_factor = (1.0 - self.packet_loss_percent / scoring.critical_packet_loss_percent.max(f64::EPSILON))
+            .clamp(0.0, 1.0);
+        let link = headroom(self.link_utilization_percent) * loss_factor;
+
+        let weights = &scoring.weights;
+        let components = [
+            (headroom(self.cpu_usage_percent), weights.cpu),
+            (headroom(self.memory_usage_percent), weights.memory),
+            (link, weights.link),
+        ]
+        .into_iter()
+        .chain(thermal.map(|t| (t, weights.thermal)));
+
+        let (sum, total_weight) = components
+            .filter(|(_, w)| *w > 0.0)
+            .fold((0.0, 0.0), |(sum, total), (score, w)| (sum + score * w, total + w));
+        if total_weight == 0.0 {
+            100.0
+        } else {
+            sum / total_weight
+        }
+    }
+
+    /// Apply freshly reported metrics
+    pub fn apply_metrics(&mut self, metrics: &NodeMetrics) {
+        self.cpu_usage_percent = metrics.cpu_usage_percent;
+        self.memory_usage_percent = metrics.memory_usage_percent;
+        self.temperature_celsius = metrics.temperature_celsius;
+        self.link_utilization_percent = metrics.link_utilization_percent;
+        self.packet_loss_percent = metrics.packet_loss_percent;
+    }
+
+    /// Move between `Online` and `Degraded` according to the health score;
+    /// offline nodes are left alone
+    fn update_health_status(&mut self, scoring: &HealthScoring) -> f64 {
+        let score = self.health_score_with(scoring);
+        match self.status {
+            NodeStatus::Online if score < scoring.degraded_below => {
+                tracing::warn!("Edge node {} degraded, health score {:.1}", self.name, score);
+                self.status = NodeStatus::Degraded;
+            }
+            NodeStatus::Degraded if score >= scoring.degraded_below + scoring.recovery_margin => {
+                tracing::info!("Edge node {} recovered, health score {:.1}", self.name, score);
+                self.status = NodeStatus::Online;
+            }
+            _ => {}
+        }
+        score
+    }
+
     pub fn is_overloaded(&self) -> bool {
         self.cpu_usage_percent > 80.0 || self.memory_usage_percent > 80.0
     }
@@ -63,15 +252,69 @@ impl EdgeNode {
 
 pub struct EdgeNodeManager {
     nodes: Arc<RwLock<HashMap<Uuid, EdgeNode>>>,
+    scoring: HealthScoring,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    max_clock_skew: Duration,
+    heartbeat_keys: Arc<RwLock<HashMap<Uuid, Vec<u8>>>>,
+    /// Highest heartbeat sequence accepted from each node
+    sequences: Arc<RwLock<HashMap<Uuid, u64>>>,
+    timelines: Arc<RwLock<HashMap<Uuid, VecDeque<StatusTransition>>>>,
+    alerts: broadcast::Sender<NodeAlert>,
 }
 
 impl EdgeNodeManager {
     pub fn new() -> Self {
+        let (alerts, _) = broadcast::channel(64);
         Self {
             nodes: Arc::new(RwLock::new(HashMap::new())),
+            scoring: HealthScoring::default(),
+            heartbeat_interval: Duration::from_secs(10),
+            heartbeat_timeout: Duration::from_secs(30),
+            max_clock_skew: Duration::from_secs(2),
+            heartbeat_keys: Arc::new(RwLock::new(HashMap::new())),
+            sequences: Arc::new(RwLock::new(HashMap::new())),
+            timelines: Arc::new(RwLock::new(HashMap::new())),
+            alerts,
         }
     }
 
+    pub fn with_health_scoring(mut self, scoring: HealthScoring) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// How long a node may go without a heartbeat before it is marked offline
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Expect a heartbeat every `interval`; nodes are degraded after one
+    /// missed interval and offline after `offline_after` missed intervals
+    pub fn with_heartbeat_interval(mut self, interval: Duration, offline_after: u32) -> Self {
+        self.heartbeat_interval = interval;
+        self.heartbeat_timeout = interval * offline_after.max(1);
+        self
+    }
+
+    /// Largest disagreement between node and controller clocks tolerated
+    /// before it is reported
+    pub fn with_max_clock_skew(mut self, skew: Duration) -> Self {
+        self.max_clock_skew = skew;
+        self
+    }
+
+    /// Key `node_id` signs its heartbeats with
+    pub async fn set_heartbeat_key(&self, node_id: Uuid, key: impl Into<Vec<u8>>) {
+        self.heartbeat_keys.write().await.insert(node_id, key.into());
+    }
+
+    /// Alerts for missed heartbeats, failed nodes and clock skew
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<NodeAlert> {
+        self.alerts.subscribe()
+    }
+
     pub async fn register_node(&self, node: EdgeNode) -> Uuid {
         let id = node.id;
         let mut nodes = self.nodes.write().await;
@@ -97,6 +340,266 @@ impl EdgeNodeManager {
             .collect()
     }
 
+    /// Record a node's latest metrics and update its status, returning the
+    /// new health score
+    pub async fn update_node_metrics(&self, id: &Uuid, metrics: NodeMetrics) -> Option<f64> {
+        let mut nodes = self.nodes.write().await;
+        let node = nodes.get_mut(id)?;
+        let before = node.status.clone();
+        node.apply_metrics(&metrics);
+        let recovered = Self::record_heartbeat(node);
+        let score = node.update_health_status(&self.scoring);
+        let node = node.clone();
+        drop(nodes);
+
+        self.note_transition(&node, before, format!("metrics update, health score {:.1}", score)).await;
+        if recovered {
+            self.alert(&node, NodeAlertKind::Recovered);
+        }
+        Some(score)
+    }
+
+    /// Record that a node is alive; an offline node comes back online
+    pub async fn heartbeat(&self, id: &Uuid) -> bool {
+        let mut nodes = self.nodes.write().await;
+        let Some(node) = nodes.get_mut(id) else {
+            return false;
+        };
+        let before = node.status.clone();
+        let recovered = Self::record_heartbeat(node);
+        let node = node.clone();
+        drop(nodes);
+
+        self.note_transition(&node, before, "heartbeat".to_string()).await;
+        if recovered {
+            self.alert(&node, NodeAlertKind::Recovered);
+        }
+        true
+    }
+
+    /// Accept a signed heartbeat from a node
+    ///
+    /// Rejects heartbeats with a bad signature, from unknown nodes or
+    /// nodes without a key, and replays of an already seen sequence number.
+    pub async fn receive_heartbeat(&self, signed: &SignedHeartbeat) -> Result<HeartbeatAck> {
+        let key = self.heartbeat_keys.read().await.get(&signed.node_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("No heartbeat key for node {}", signed.node_id))?;
+        let heartbeat = signed.verify(&key)?;
+        self.accept_heartbeat(heartbeat, Utc::now()).await
+    }
+
+    async fn accept_heartbeat(&self, heartbeat: Heartbeat, received_at: DateTime<Utc>) -> Result<HeartbeatAck> {
+        {
+            let mut sequences = self.sequences.write().await;
+            let last = sequences.get(&heartbeat.node_id).copied().unwrap_or(0);
+            if heartbeat.sequence <= last {
+                anyhow::bail!(
+                    "Stale heartbeat {} from node {}, already at {}",
+                    heartbeat.sequence, heartbeat.node_id, last
+                );
+            }
+            sequences.insert(heartbeat.node_id, heartbeat.sequence);
+        }
+
+        let tolerance = chrono::Duration::from_std(self.max_clock_skew).unwrap_or_default();
+        let offset = heartbeat.sent_at - received_at;
+        // A node clock ahead of ours would show up as a negative delay, one
+        // behind as an implausibly long one; neither is a real latency
+        let skewed = offset.abs() > tolerance;
+
+        let mut nodes = self.nodes.write().await;
+        let node = nodes.get_mut(&heartbeat.node_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown edge node {}", heartbeat.node_id))?;
+        let before = node.status.clone();
+        let was_skewed = node.clock_skew_ms.is_some();
+
+        node.apply_metrics(&heartbeat.metrics);
+        node.connected_devices = heartbeat.devices.connected;
+        node.devices = heartbeat.devices;
+        node.workloads = heartbeat.workloads;
+        if skewed {
+            node.clock_skew_ms = Some(offset.num_milliseconds());
+            node.heartbeat_delay_ms = None;
+        } else {
+            node.clock_skew_ms = None;
+            node.heartbeat_delay_ms = Some((-offset).num_microseconds().unwrap_or(0).max(0) as f64 / 1000.0);
+        }
+
+        let recovered = Self::record_heartbeat(node);
+        let reason = match heartbeat.status {
+            NodeStatus::Offline => {
+                node.status = NodeStatus::Offline;
+                "node announced shutdown".to_string()
+            }
+            NodeStatus::Degraded => {
+                node.status = NodeStatus::Degraded;
+                "node reported itself degraded".to_string()
+            }
+            NodeStatus::Online => {
+                let score = node.update_health_status(&self.scoring);
+                format!("heartbeat {}, health score {:.1}", heartbeat.sequence, score)
+            }
+        };
+        node.last_heartbeat = received_at;
+        let node = node.clone();
+        drop(nodes);
+
+        self.note_transition(&node, before, reason).await;
+        if recovered {
+            self.alert(&node, NodeAlertKind::Recovered);
+        }
+        if let (Some(skew_ms), false) = (node.clock_skew_ms, was_skewed) {
+            tracing::warn!("Edge node {} clock is off by {} ms", node.name, skew_ms);
+            self.alert(&node, NodeAlertKind::ClockSkew { skew_ms });
+        }
+
+        Ok(HeartbeatAck {
+            node_id: node.id,
+            sequence: heartbeat.sequence,
+            received_at,
+            status: node.status,
+            clock_skew_ms: node.clock_skew_ms,
+        })
+    }
+
+    /// Refresh the heartbeat time, reviving an offline or late node;
+    /// returns whether it was one
+    fn record_heartbeat(node: &mut EdgeNode) -> bool {
+        node.last_heartbeat = Utc::now();
+        let late = std::mem::take(&mut node.heartbeat_late);
+        match node.status {
+            NodeStatus::Offline => {
+                tracing::info!("Edge node {} is back online", node.name);
+                node.status = NodeStatus::Online;
+                true
+            }
+            NodeStatus::Degraded if late => {
+                tracing::info!("Edge node {} heartbeats resumed", node.name);
+                node.status = NodeStatus::Online;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Mark nodes that missed a heartbeat interval degraded and those past
+    /// the timeout offline, returning the ones that just went offline
+    ///
+    /// A heartbeat counts as missed half an interval after it was due, so
+    /// ordinary jitter does not degrade a node.
+    pub async fn check_heartbeats(&self) -> Vec<Uuid> {
+        let now = Utc::now();
+        let offline_cutoff = now - chrono::Duration::from_std(self.heartbeat_timeout).unwrap_or_default();
+        let late_cutoff = now - chrono::Duration::from_std(self.heartbeat_interval * 3 / 2).unwrap_or_default();
+
+        let mut changed = Vec::new();
+        let mut failed = Vec::new();
+        {
+            let mut nodes = self.nodes.write().await;
+            for node in nodes.values_mut() {
+                let before = node.status.clone();
+                if node.status != NodeStatus::Offline && node.last_heartbeat < offline_cutoff {
+                    tracing::warn!("Edge node {} missed heartbeats since {}, marking offline", node.name, node.last_heartbeat);
+                    node.status = NodeStatus::Offline;
+                    node.heartbeat_late = false;
+                    failed.push(node.id);
+                    changed.push((node.clone(), before, NodeAlertKind::Offline { last_heartbeat: node.last_heartbeat }));
+                } else if node.status == NodeStatus::Online && node.last_heartbeat < late_cutoff {
+                    tracing::warn!("Edge node {} missed a heartbeat, last seen {}", node.name, node.last_heartbeat);
+                    node.status = NodeStatus::Degraded;
+                    node.heartbeat_late = true;
+                    changed.push((node.clone(), before, NodeAlertKind::MissedHeartbeat { last_heartbeat: node.last_heartbeat }));
+                }
+            }
+        }
+
+        for (node, before, kind) in changed {
+            let reason = match &kind {
+                NodeAlertKind::Offline { last_heartbeat } => format!("no heartbeat since {}", last_heartbeat),
+                _ => format!("missed heartbeat, last seen {}", node.last_heartbeat),
+            };
+            self.note_transition(&node, before, reason).await;
+            self.alert(&node, kind);
+        }
+        failed
+    }
+
+    /// Status transitions of a node within `range`, oldest first
+    pub async fn get_node_timeline(&self, id: &Uuid, range: impl RangeBounds<DateTime<Utc>>) -> Vec<StatusTransition> {
+        self.timelines.read().await
+            .get(id)
+            .map(|t| t.iter().filter(|tr| range.contains(&tr.at)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    async fn note_transition(&self, node: &EdgeNode, from: NodeStatus, reason: String) {
+        if node.status == from {
+            return;
+        }
+        let mut timelines = self.timelines.write().await;
+        let timeline = timelines.entry(node.id).or_default();
+        if timeline.len() == TIMELINE_LEN {
+            timeline.pop_front();
+        }
+        timeline.push_back(StatusTransition {
+            at: Utc::now(),
+            from,
+            to: node.status.clone(),
+            reason,
+        });
+    }
+
+    fn alert(&self, node: &EdgeNode, kind: NodeAlertKind) {
+        // No subscribers is fine
+        let _ = self.alerts.send(NodeAlert {
+            node_id: node.id,
+            node_name: node.name.clone(),
+            kind,
+            at: Utc::now(),
+        });
+    }
+
+    /// Record the measured latency from a node to a named site
+    pub async fn update_site_latency(&self, id: &Uuid, site: &str, latency_ms: f64) -> bool {
+        let mut nodes = self.nodes.write().await;
+        match nodes.get_mut(id) {
+            Some(node) => {
+                node.site_latency_ms.insert(site.to_string(), latency_ms);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-evaluate every node's status against its current metrics
+    pub async fn refresh_health(&self) {
+        let mut changed = Vec::new();
+        {
+            let mut nodes = self.nodes.write().await;
+            for node in nodes.values_mut() {
+                let before = node.status.clone();
+                let score = node.update_health_status(&self.scoring);
+                if node.status != before {
+                    changed.push((node.clone(), before, score));
+                }
+            }
+        }
+        for (node, before, score) in changed {
+            self.note_transition(&node, before, format!("health score {:.1}", score)).await;
+        }
+    }
+
+    /// Nodes that are not offline, healthiest first
+    pub async fn rank_nodes(&self) -> Vec<(EdgeNode, f64)> {
+        let nodes = self.nodes.read().await;
+        let mut ranked: Vec<(EdgeNode, f64)> = nodes.values()
+            .filter(|n| n.status != NodeStatus::Offline)
+            .map(|n| (n.clone(), n.health_score_with(&self.scoring)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+
     pub async fn find_least_loaded_node(&self) -> Option<EdgeNode> {
         let nodes = self.nodes.read().await;
         nodes.values()
@@ -177,4 +680,243 @@ mod tests {
         assert!(least_loaded.is_some());
         assert_eq!(least_loaded.unwrap().name, "node2");
     }
+
+    fn caps() -> NodeCapabilities {
+        NodeCapabilities {
+            cpu_cores: 8,
+            memory_gb: 32,
+            storage_gb: 500,
+            gpu_available: false,
+            supports_5g: true,
+        }
+    }
+
+    #[test]
+    fn test_health_score() {
+        let mut node = EdgeNode::new("edge-1".to_string(), (0.0, 0.0), caps());
+        assert_eq!(node.health_score(), 100.0);
+
+        node.apply_metrics(&NodeMetrics {
+            cpu_usage_percent: 40.0,
+            memory_usage_percent: 60.0,
+            temperature_celsius: Some(77.5),
+            link_utilization_percent: 50.0,
+            packet_loss_percent: 5.0,
+        });
+        // cpu 60, memory 40, thermal 50, link 25
+        let expected = 0.3 * 60.0 + 0.25 * 40.0 + 0.25 * 50.0 + 0.2 * 25.0;
+        assert!((node.health_score() - expected).abs() < 1e-9);
+
+        // Without a sensor the thermal weight is left out
+        node.temperature_celsius = None;
+        let expected = (0.3 * 60.0 + 0.25 * 40.0 + 0.2 * 25.0) / 0.75;
+        assert!((node.health_score() - expected).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_hot_node_degraded_when_thermal_dominates() {
+        let scoring = HealthScoring {
+            weights: HealthWeights { cpu: 0.15, memory: 0.1, thermal: 0.6, link: 0.15 },
+            ..HealthScoring::default()
+        };
+        let manager = EdgeNodeManager::new().with_health_scoring(scoring);
+        let hot = manager.register_node(EdgeNode::new("hot".to_string(), (0.0, 0.0), caps())).await;
+        let cool = manager.register_node(EdgeNode::new("cool".to_string(), (0.0, 0.0), caps())).await;
+
+        let hot_metrics = NodeMetrics {
+            cpu_usage_percent: 5.0,
+            memory_usage_percent: 20.0,
+            temperature_celsius: Some(92.0),
+            link_utilization_percent: 10.0,
+            packet_loss_percent: 0.0,
+        };
+        let score = manager.update_node_metrics(&hot, hot_metrics.clone()).await.unwrap();
+        assert!(score < 50.0, "score {}", score);
+        assert_eq!(manager.get_node(&hot).await.unwrap().status, NodeStatus::Degraded);
+
+        // The same node under the default weighting is still healthy
+        let mut node = manager.get_node(&hot).await.unwrap();
+        node.apply_metrics(&hot_metrics);
+        assert!(node.health_score() > 50.0);
+
+        manager.update_node_metrics(&cool, NodeMetrics {
+            cpu_usage_percent: 60.0,
+            memory_usage_percent: 50.0,
+            temperature_celsius: Some(55.0),
+            ..NodeMetrics::default()
+        }).await;
+
+        let ranked = manager.rank_nodes().await;
+        assert_eq!(ranked[0].0.name, "cool");
+        assert_eq!(ranked[1].0.name, "hot");
+
+        // The scheduler's view excludes the degraded node
+        let online = manager.get_online_nodes().await;
+        assert_eq!(online.len(), 1);
+        assert_eq!(online[0].name, "cool");
+
+        // Recovery needs the score to clear the threshold plus the margin
+        manager.update_node_metrics(&hot, NodeMetrics { temperature_celsius: Some(85.0), ..hot_metrics.clone() }).await;
+        assert_eq!(manager.get_node(&hot).await.unwrap().status, NodeStatus::Degraded);
+        manager.update_node_metrics(&hot, NodeMetrics { temperature_celsius: Some(65.0), ..hot_metrics }).await;
+        assert_eq!(manager.get_node(&hot).await.unwrap().status, NodeStatus::Online);
+    }
+
+    fn heartbeat_for(node_id: Uuid, sequence: u64, sent_at: DateTime<Utc>) -> Heartbeat {
+        Heartbeat {
+            node_id,
+            sequence,
+            sent_at,
+            status: NodeStatus::Online,
+            metrics: NodeMetrics { cpu_usage_percent: 30.0, ..NodeMetrics::default() },
+            devices: DeviceAggregates { connected: 12, ..DeviceAggregates::default() },
+            workloads: vec![WorkloadHealth {
+                workload_id: Uuid::new_v4(),
+                healthy: true,
+                restarts: 0,
+                detail: None,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_heartbeat_carries_metrics_and_rejects_forgery_and_replay() {
+        use crate::heartbeat::HeartbeatSigner;
+
+        let manager = EdgeNodeManager::new();
+        let id = manager.register_node(EdgeNode::new("edge-1".to_string(), (0.0, 0.0), caps())).await;
+        let signer = HeartbeatSigner::new(id, b"node-secret".to_vec());
+        let beat = || signer.next(
+            NodeStatus::Online,
+            NodeMetrics { cpu_usage_percent: 30.0, ..NodeMetrics::default() },
+            DeviceAggregates { connected: 12, ..DeviceAggregates::default() },
+            Vec::new(),
+        ).unwrap();
+
+        // No key registered yet
+        assert!(manager.receive_heartbeat(&beat()).await.is_err());
+        manager.set_heartbeat_key(id, b"node-secret".to_vec()).await;
+
+        let first = beat();
+        let ack = manager.receive_heartbeat(&first).await.unwrap();
+        assert_eq!(ack.status, NodeStatus::Online);
+        assert_eq!(ack.clock_skew_ms, None);
+        let node = manager.get_node(&id).await.unwrap();
+        assert_eq!(node.cpu_usage_percent, 30.0);
+        assert_eq!(node.connected_devices, 12);
+        assert!(node.heartbeat_delay_ms.unwrap() >= 0.0);
+
+        // Replayed and forged heartbeats are refused
+        assert!(manager.receive_heartbeat(&first).await.is_err());
+        let mut forged = beat();
+        forged.payload = forged.payload.replace("30.0", "5.0");
+        assert!(manager.receive_heartbeat(&forged).await.is_err());
+        let other = SignedHeartbeat::sign(&heartbeat_for(id, 100, Utc::now()), b"wrong").unwrap();
+        assert!(manager.receive_heartbeat(&other).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_accepted_after_node_restart() {
+        use crate::heartbeat::HeartbeatSigner;
+
+        let manager = EdgeNodeManager::new();
+        let id = manager.register_node(EdgeNode::new("edge-1".to_string(), (0.0, 0.0), caps())).await;
+        manager.set_heartbeat_key(id, b"node-secret".to_vec()).await;
+        let beat = |signer: &HeartbeatSigner| signer.next(
+            NodeStatus::Online,
+            NodeMetrics::default(),
+            DeviceAggregates::default(),
+            Vec::new(),
+        ).unwrap();
+
+        let before = HeartbeatSigner::new(id, b"node-secret".to_vec());
+        for _ in 0..3 {
+            manager.receive_heartbeat(&beat(&before)).await.unwrap();
+        }
+
+        // The agent restarts with a fresh signer
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let after = HeartbeatSigner::new(id, b"node-secret".to_vec());
+        assert!(after.last_sequence() > before.last_sequence());
+        let ack = manager.receive_heartbeat(&beat(&after)).await.unwrap();
+        assert_eq!(ack.status, NodeStatus::Online);
+
+        // A persisted counter works as well
+        let resumed = HeartbeatSigner::new(id, b"node-secret".to_vec()).with_sequence(after.last_sequence());
+        manager.receive_heartbeat(&beat(&resumed)).await.unwrap();
+        let stale = HeartbeatSigner::new(id, b"node-secret".to_vec()).with_sequence(0);
+        assert!(manager.receive_heartbeat(&beat(&stale)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_reported_not_turned_into_latency() {
+        let manager = EdgeNodeManager::new().with_max_clock_skew(Duration::from_secs(2));
+        let mut alerts = manager.subscribe_alerts();
+        let id = manager.register_node(EdgeNode::new("edge-1".to_string(), (0.0, 0.0), caps())).await;
+
+        let now = Utc::now();
+        let ack = manager.accept_heartbeat(heartbeat_for(id, 1, now + chrono::Duration::seconds(30)), now).await.unwrap();
+        assert_eq!(ack.clock_skew_ms, Some(30_000));
+        let node = manager.get_node(&id).await.unwrap();
+        assert_eq!(node.heartbeat_delay_ms, None);
+        let alert = alerts.try_recv().unwrap();
+        assert_eq!(alert.kind, NodeAlertKind::ClockSkew { skew_ms: 30_000 });
+
+        // Still skewed: no repeat alert
+        manager.accept_heartbeat(heartbeat_for(id, 2, now + chrono::Duration::seconds(31)), now).await.unwrap();
+        assert!(alerts.try_recv().is_err());
+
+        let ack = manager.accept_heartbeat(heartbeat_for(id, 3, now - chrono::Duration::milliseconds(40)), now).await.unwrap();
+        assert_eq!(ack.clock_skew_ms, None);
+        assert_eq!(manager.get_node(&id).await.unwrap().heartbeat_delay_ms, Some(40.0));
+    }
+
+    #[tokio::test]
+    async fn test_missed_heartbeats_degrade_then_offline_with_timeline() {
+        let manager = EdgeNodeManager::new().with_heartbeat_interval(Duration::from_secs(10), 3);
+        let mut alerts = manager.subscribe_alerts();
+        let start = Utc::now();
+        let mut node = EdgeNode::new("edge-1".to_string(), (0.0, 0.0), caps());
+        let id = node.id;
+
+        node.last_heartbeat = Utc::now() - chrono::Duration::seconds(16);
+        manager.register_node(node.clone()).await;
+        assert!(manager.check_heartbeats().await.is_empty());
+        assert_eq!(manager.get_node(&id).await.unwrap().status, NodeStatus::Degraded);
+        assert!(matches!(alerts.try_recv().unwrap().kind, NodeAlertKind::MissedHeartbeat { .. }));
+        // Degraded nodes take no new work
+        assert!(manager.get_online_nodes().await.is_empty());
+
+        // Still degraded on the next check, no new alert
+        manager.check_heartbeats().await;
+        assert!(alerts.try_recv().is_err());
+
+        manager.nodes.write().await.get_mut(&id).unwrap().last_heartbeat = Utc::now() - chrono::Duration::seconds(31);
+        assert_eq!(manager.check_heartbeats().await, vec![id]);
+        assert_eq!(manager.get_node(&id).await.unwrap().status, NodeStatus::Offline);
+        assert!(matches!(alerts.try_recv().unwrap().kind, NodeAlertKind::Offline { .. }));
+
+        manager.accept_heartbeat(heartbeat_for(id, 1, Utc::now()), Utc::now()).await.unwrap();
+        assert_eq!(manager.get_node(&id).await.unwrap().status, NodeStatus::Online);
+        assert_eq!(alerts.try_recv().unwrap().kind, NodeAlertKind::Recovered);
+
+        let timeline = manager.get_node_timeline(&id, start..).await;
+        let steps: Vec<(NodeStatus, NodeStatus)> = timeline.iter().map(|t| (t.from.clone(), t.to.clone())).collect();
+        assert_eq!(steps, vec![
+            (NodeStatus::Online, NodeStatus::Degraded),
+            (NodeStatus::Degraded, NodeStatus::Offline),
+            (NodeStatus::Offline, NodeStatus::Online),
+        ]);
+        assert!(timeline[1].reason.starts_with("no heartbeat since"));
+        assert!(manager.get_node_timeline(&id, ..start).await.is_empty());
+
+        // A node announcing shutdown goes offline at once
+        manager.accept_heartbeat(
+            Heartbeat { status: NodeStatus::Offline, ..heartbeat_for(id, 2, Utc::now()) },
+            Utc::now(),
+        ).await.unwrap();
+        let last = manager.get_node_timeline(&id, ..).await.pop().unwrap();
+        assert_eq!(last.to, NodeStatus::Offline);
+        assert_eq!(last.reason, "node announced shutdown");
+    }
 }
@@ -0,0 +1,171 @@
+//! Device Enrollment
+//!
+//! Devices onboard themselves by exchanging a one-time [`EnrollmentToken`]
+//! for a client certificate issued by the site CA. The certificate's
+//! SHA-256 fingerprint becomes the device's identity. Tokens are generated
+//! in batches, expire, and only admit the device type they were minted for.
+//! In [`EnrollmentMode::ManualApproval`] a redeemed token parks the request
+//! until an operator approves it.
+//!
+//! A device re-enrolls by connecting with its current client certificate;
+//! the TLS handshake proves it holds the key, and the server passes the
+//! verified certificate in the [`EnrollmentPeer`]. Nothing in the request
+//! body can select an existing device.
+//!
+//! Quarantined devices stay registered and visible; a [`QuarantineHook`]
+//! blocks their traffic in the firewall.
+
+use crate::device::{DeviceType, IoTDevice};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnrollmentMode {
+    /// A valid token is enough to get a certificate
+    Automatic,
+    /// Requests wait for [`DeviceManager::approve_enrollment`](crate::DeviceManager::approve_enrollment)
+    ManualApproval,
+}
+
+/// A single-use credential for one device of a given type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollmentToken {
+    pub token: String,
+    pub device_type: DeviceType,
+    pub expires_at: DateTime<Utc>,
+    /// Set once a device has redeemed the token
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl EnrollmentToken {
+    pub fn generate(device_type: DeviceType, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            token: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            device_type,
+            expires_at,
+            used_at: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// What a device presents to enroll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollmentRequest {
+    pub token: String,
+    pub name: String,
+    pub device_type: DeviceType,
+    pub location: (f64, f64),
+}
+
+/// What the server observed about the connection an enrollment request
+/// arrived on; never taken from the request itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnrollmentPeer {
+    /// Source address of the connection, used for quarantine
+    pub address: Option<IpAddr>,
+    /// Client certificate verified in the TLS handshake (PEM); present
+    /// when an enrolled device re-enrolls
+    pub client_cert_pem: Option<String>,
+}
+
+/// Credentials handed back to an enrolled device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrolledDevice {
+    pub device_id: Uuid,
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub ca_cert_pem: String,
+    pub fingerprint: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EnrollmentOutcome {
+    Enrolled(EnrolledDevice),
+    /// Waiting for an operator; poll with the request ID
+    PendingApproval { request_id: Uuid },
+}
+
+/// A redeemed token awaiting manual approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEnrollment {
+    pub id: Uuid,
+    pub request: EnrollmentRequest,
+    #[serde(default)]
+    pub peer: EnrollmentPeer,
+    pub requested_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnrollmentStats {
+    pub tokens_issued: u64,
+    /// Unused tokens that have not expired
+    pub tokens_outstanding: usize,
+    pub enrolled: u64,
+    pub re_enrolled: u64,
+    /// Requests refused for a bad, used or expired token or a type mismatch
+    pub rejected: u64,
+    pub pending_approval: usize,
+    pub quarantined: usize,
+}
+
+/// Blocks and unblocks a device's traffic in the firewall
+#[async_trait]
+pub trait QuarantineHook: Send + Sync {
+    async fn block(&self, device: &IoTDevice) -> anyhow::Result<()>;
+
+    async fn unblock(&self, device: &IoTDevice) -> anyhow::Result<()>;
+}
+
+/// Quarantine through an nftables set of blocked source addresses
+///
+/// The ruleset is expected to drop traffic from the set, e.g.
+/// `ip saddr @iot_quarantine drop` in the forward chain.
+pub struct NftQuarantine {
+    pub family: String,
+    pub table: String,
+    pub set: String,
+}
+
+impl Default for NftQuarantine {
+    fn default() -> Self {
+        Self {
+            family: "inet".to_string(),
+            table: "patronus".to_string(),
+            set: "iot_quarantine".to_string(),
+        }
+    }
+}
+
+impl NftQuarantine {
+    async fn update(&self, action: &str, device: &IoTDevice) -> anyhow::Result<()> {
+        let address = device.address
+            .ok_or_else(|| anyhow::anyhow!("Device {} has no known address", device.id))?;
+        let output = tokio::process::Command::new("nft")
+            .args([action, "element", &self.family, &self.table, &self.set, &format!("{{ {} }}", address)])
+            .output()
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!("nft {} element failed: {}", action, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl QuarantineHook for NftQuarantine {
+    async fn block(&self, device: &IoTDevice) -> anyhow::Result<()> {
+        self.update("add", device).await
+    }
+
+    async fn unblock(&self, device: &IoTDevice) -> anyhow::Result<()> {
+        self.update("delete", device).await
+    }
+}
@@ -0,0 +1,147 @@
+//! Firmware Rollouts
+//!
+//! Types for staged over-the-air updates driven by
+//! [`DeviceManager::start_rollout`](crate::DeviceManager::start_rollout).
+//! Devices are updated one batch at a time; each updated device gets a
+//! grace period to come back online, and the rollout pauses itself when
+//! too much of a batch failed.
+
+use crate::device::{DeviceType, IoTDevice};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A firmware image
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Firmware {
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+}
+
+impl Firmware {
+    pub fn new(version: String, url: String, sha256: String) -> Self {
+        Self { version, url, sha256 }
+    }
+}
+
+/// Pushes firmware images to devices
+#[async_trait]
+pub trait FirmwareInstaller: Send + Sync {
+    /// Install `firmware`, returning once the device has accepted it
+    async fn install(&self, device: &IoTDevice, firmware: &Firmware) -> anyhow::Result<()>;
+}
+
+/// Devices a rollout targets; unset fields match every device
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceFilter {
+    pub device_type: Option<DeviceType>,
+    pub edge_node_id: Option<Uuid>,
+    pub device_ids: Option<Vec<Uuid>>,
+    /// Skip devices that are offline when the rollout starts
+    pub online_only: bool,
+}
+
+impl DeviceFilter {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn by_type(device_type: DeviceType) -> Self {
+        Self {
+            device_type: Some(device_type),
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, device: &IoTDevice) -> bool {
+        self.device_type.as_ref().is_none_or(|t| &device.device_type == t)
+            && self.edge_node_id.is_none_or(|n| device.edge_node_id == Some(n))
+            && self.device_ids.as_ref().is_none_or(|ids| ids.contains(&device.id))
+            && (!self.online_only || device.online)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum RolloutState {
+    Running,
+    Paused { reason: String },
+    Completed,
+    RollingBack,
+    RolledBack,
+}
+
+impl RolloutState {
+    /// Whether devices of this rollout may still change firmware
+    pub fn is_active(&self) -> bool {
+        !matches!(self, Self::Completed | Self::RolledBack)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeviceUpdateStatus {
+    Pending,
+    Installing,
+    Succeeded,
+    Failed { reason: String },
+    RolledBack,
+    RollbackFailed { reason: String },
+}
+
+/// Progress of one device in a rollout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceUpdate {
+    pub device_id: Uuid,
+    pub device_name: String,
+    /// Firmware the device ran before the rollout
+    pub previous_version: Option<String>,
+    pub status: DeviceUpdateStatus,
+    /// Batch the device was updated in, counting from zero
+    pub batch: Option<usize>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A staged firmware update across many devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirmwareRollout {
+    pub id: Uuid,
+    pub firmware: Firmware,
+    pub batch_size: usize,
+    /// Fraction of a batch that may fail before the rollout pauses
+    pub failure_threshold: f64,
+    pub state: RolloutState,
+    pub devices: Vec<DeviceUpdate>,
+    pub batches_completed: usize,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FirmwareRollout {
+    pub fn pending(&self) -> usize {
+        self.count(|s| matches!(s, DeviceUpdateStatus::Pending))
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.count(|s| matches!(s, DeviceUpdateStatus::Succeeded))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|s| matches!(s, DeviceUpdateStatus::Failed { .. }))
+    }
+
+    /// Share of devices in `batch` that failed to update
+    pub fn batch_failure_rate(&self, batch: usize) -> f64 {
+        let updates: Vec<&DeviceUpdate> = self.devices.iter().filter(|d| d.batch == Some(batch)).collect();
+        if updates.is_empty() {
+            return 0.0;
+        }
+        let failed = updates.iter().filter(|d| matches!(d.status, DeviceUpdateStatus::Failed { .. })).count();
+        failed as f64 / updates.len() as f64
+    }
+
+    fn count(&self, pred: impl Fn(&DeviceUpdateStatus) -> bool) -> usize {
+        self.devices.iter().filter(|d| pred(&d.status)).count()
+    }
+}
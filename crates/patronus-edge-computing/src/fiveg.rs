@@ -1,5 +1,17 @@
 //! 5G Network Slicing
+//!
+//! Each active slice reserves its bandwidth floor out of the shared cell
+//! capacity. Flows are admitted onto a slice only while it stays within
+//! its committed capacity; best-effort slices may go beyond it, up to their
+//! ceiling, but only by borrowing capacity no other slice has reserved.
+//!
+//! Slices bound to their traffic are mapped onto SD-WAN policies and QoS
+//! classes; see [`crate::slice_policy`].
 
+use crate::slice_policy::{SliceBinding, SlicePolicySink, SliceReport, SliceTrafficMatch};
+use anyhow::Result;
+use patronus_sdwan::sla::SlaMonitor;
+use patronus_sdwan::traffic_stats::TrafficStatsCollector;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -13,11 +25,28 @@ pub enum SliceType {
     MMTC,  // Massive Machine Type Communications
 }
 
+impl SliceType {
+    /// Whether the slice may borrow idle capacity beyond its floor
+    pub fn is_best_effort(&self) -> bool {
+        !matches!(self, SliceType::URLLC)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkSlice {
+    /// Latency budget flows on the slice can rely on
     pub max_latency_ms: f64,
+    /// Bandwidth reserved for the slice while it is active
     pub min_bandwidth_mbps: f64,
+    /// Most the slice may ever carry, including borrowed capacity
+    pub max_bandwidth_mbps: f64,
     pub reliability_percent: f64,
+    /// Rank among slices when their traffic overlaps, higher wins
+    #[serde(default)]
+    pub priority: u32,
+    /// Cap on each device's traffic, for slices of many small senders
+    #[serde(default)]
+    pub per_device_rate_mbps: Option<f64>,
 }
 
 impl NetworkSlice {
@@ -25,7 +54,10 @@ impl NetworkSlice {
         Self {
             max_latency_ms: 50.0,
             min_bandwidth_mbps: 1000.0,
+            max_bandwidth_mbps: 10000.0,
             reliability_percent: 99.0,
+            priority: 200,
+            per_device_rate_mbps: None,
         }
     }
 
@@ -33,7 +65,10 @@ impl NetworkSlice {
         Self {
             max_latency_ms: 1.0,
             min_bandwidth_mbps: 100.0,
+            max_bandwidth_mbps: 100.0,
             reliability_percent: 99.999,
+            priority: 300,
+            per_device_rate_mbps: None,
         }
     }
 
@@ -41,7 +76,10 @@ impl NetworkSlice {
         Self {
             max_latency_ms: 1000.0,
             min_bandwidth_mbps: 1.0,
+            max_bandwidth_mbps: 10.0,
             reliability_percent: 99.0,
+            priority: 100,
+            per_device_rate_mbps: Some(0.1),
         }
     }
 }
@@ -83,19 +121,57 @@ impl FiveGSlice {
     pub fn deactivate(&mut self) {
         self.active = false;
     }
+
+    /// Capacity the slice holds out of the cell, whether used or not
+    pub fn reserved_bandwidth_mbps(&self) -> f64 {
+        if self.active {
+            self.allocated_bandwidth_mbps.max(self.slice_config.min_bandwidth_mbps)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A flow admitted onto a slice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceFlow {
+    pub id: Uuid,
+    pub slice_id: Uuid,
+    pub bandwidth_mbps: f64,
+    /// Part of the flow carried by capacity borrowed beyond the slice floor
+    pub borrowed_mbps: f64,
 }
 
 pub struct SliceManager {
     slices: Arc<RwLock<HashMap<Uuid, FiveGSlice>>>,
+    flows: Arc<RwLock<HashMap<Uuid, SliceFlow>>>,
+    bindings: Arc<RwLock<HashMap<Uuid, SliceBinding>>>,
+    policy_sink: Option<Arc<dyn SlicePolicySink>>,
+    capacity_mbps: f64,
 }
 
 impl SliceManager {
     pub fn new() -> Self {
+        Self::with_capacity(10000.0)
+    }
+
+    /// Manager for a cell that can carry `capacity_mbps` in total
+    pub fn with_capacity(capacity_mbps: f64) -> Self {
         Self {
             slices: Arc::new(RwLock::new(HashMap::new())),
+            flows: Arc::new(RwLock::new(HashMap::new())),
+            bindings: Arc::new(RwLock::new(HashMap::new())),
+            policy_sink: None,
+            capacity_mbps,
         }
     }
 
+    /// Install generated slice policies through `sink`
+    pub fn with_policy_sink(mut self, sink: Arc<dyn SlicePolicySink>) -> Self {
+        self.policy_sink = Some(sink);
+        self
+    }
+
     pub async fn create_slice(&self, name: String, slice_type: SliceType) -> Uuid {
         let slice = FiveGSlice::new(name, slice_type);
         let id = slice.id;
@@ -109,9 +185,18 @@ impl SliceManager {
         slices.get(id).cloned()
     }
 
+    /// Activate a slice, provided its floor can still be reserved
     pub async fn activate_slice(&self, id: &Uuid) -> bool {
         let mut slices = self.slices.write().await;
+        let others: f64 = slices.values()
+            .filter(|s| s.id != *id)
+            .map(|s| s.reserved_bandwidth_mbps())
+            .sum();
         if let Some(slice) = slices.get_mut(id) {
+            if !slice.active && others + slice.slice_config.min_bandwidth_mbps > self.capacity_mbps {
+                tracing::warn!("Cannot reserve {} Mbps for slice {}", slice.slice_config.min_bandwidth_mbps, slice.name);
+                return false;
+            }
             slice.activate();
             true
         } else {
@@ -119,10 +204,210 @@ impl SliceManager {
         }
     }
 
+    /// Admit a flow needing `bandwidth_mbps` and at most `max_latency_ms`
+    ///
+    /// Fails if the slice cannot meet the latency, would exceed its
+    /// ceiling, or would need capacity reserved by another slice. Only
+    /// best-effort slices may go beyond their committed floor.
+    pub async fn admit_flow(&self, slice_id: &Uuid, bandwidth_mbps: f64, max_latency_ms: f64) -> Result<Uuid> {
+        if bandwidth_mbps <= 0.0 {
+            anyhow::bail!("Flow bandwidth must be positive, got {}", bandwidth_mbps);
+        }
+
+        let mut slices = self.slices.write().await;
+        let others: f64 = slices.values()
+            .filter(|s| s.id != *slice_id)
+            .map(|s| s.reserved_bandwidth_mbps())
+            .sum();
+        let slice = slices.get_mut(slice_id)
+            .ok_or_else(|| anyhow::anyhow!("Slice {} not found", slice_id))?;
+        if !slice.active {
+            anyhow::bail!("Slice {} is not active", slice.name);
+        }
+
+        let config = &slice.slice_config;
+        if max_latency_ms < config.max_latency_ms {
+            anyhow::bail!(
+                "Slice {} latency budget {} ms exceeds the flow's {} ms",
+                slice.name, config.max_latency_ms, max_latency_ms
+            );
+        }
+
+        let allocated = slice.allocated_bandwidth_mbps + bandwidth_mbps;
+        if allocated > config.max_bandwidth_mbps {
+            anyhow::bail!(
+                "Slice {} would carry {} Mbps, above its {} Mbps ceiling",
+                slice.name, allocated, config.max_bandwidth_mbps
+            );
+        }
+
+        let borrowed_mbps = if allocated <= config.min_bandwidth_mbps {
+            0.0
+        } else if !slice.slice_type.is_best_effort() {
+            anyhow::bail!(
+                "Slice {} would carry {} Mbps, above its committed {} Mbps",
+                slice.name, allocated, config.min_bandwidth_mbps
+            );
+        } else {
+            let idle = self.capacity_mbps - others - slice.reserved_bandwidth_mbps();
+            let needed = allocated - slice.reserved_bandwidth_mbps();
+            if needed > idle {
+                anyhow::bail!(
+                    "Slice {} needs {} Mbps beyond its reservation but only {} Mbps is idle",
+                    slice.name, needed, idle.max(0.0)
+                );
+            }
+            allocated - config.min_bandwidth_mbps.max(slice.allocated_bandwidth_mbps)
+        };
+
+        slice.allocated_bandwidth_mbps = allocated;
+        slice.connected_devices += 1;
+
+        let flow = SliceFlow {
+            id: Uuid::new_v4(),
+            slice_id: *slice_id,
+            bandwidth_mbps,
+            borrowed_mbps,
+        };
+        let id = flow.id;
+        self.flows.write().await.insert(id, flow);
+        Ok(id)
+    }
+
+    /// Release a flow's bandwidth back to its slice
+    pub async fn release_flow(&self, flow_id: &Uuid) -> bool {
+        let Some(flow) = self.flows.write().await.remove(flow_id) else {
+            return false;
+        };
+        let mut slices = self.slices.write().await;
+        if let Some(slice) = slices.get_mut(&flow.slice_id) {
+            slice.allocated_bandwidth_mbps = (slice.allocated_bandwidth_mbps - flow.bandwidth_mbps).max(0.0);
+            slice.connected_devices = slice.connected_devices.saturating_sub(1);
+        }
+        true
+    }
+
+    pub async fn get_flow(&self, flow_id: &Uuid) -> Option<SliceFlow> {
+        self.flows.read().await.get(flow_id).cloned()
+    }
+
+    /// Capacity not reserved or used by any active slice
+    pub async fn idle_capacity_mbps(&self) -> f64 {
+        let slices = self.slices.read().await;
+        let reserved: f64 = slices.values().map(|s| s.reserved_bandwidth_mbps()).sum();
+        (self.capacity_mbps - reserved).max(0.0)
+    }
+
     pub async fn list_active_slices(&self) -> Vec<FiveGSlice> {
         let slices = self.slices.read().await;
         slices.values().filter(|s| s.active).cloned().collect()
     }
+
+    /// Map a slice's traffic onto SD-WAN policies and a QoS class
+    ///
+    /// Traffic matching any of `matches` belongs to the slice. Rebinding
+    /// replaces the previous matches.
+    pub async fn bind_slice_to_policy(&self, slice_id: &Uuid, matches: Vec<SliceTrafficMatch>) -> Result<SliceBinding> {
+        if matches.is_empty() {
+            anyhow::bail!("Slice {} needs at least one traffic match", slice_id);
+        }
+        for m in &matches {
+            m.validate()?;
+        }
+
+        let slice = self.get_slice(slice_id).await
+            .ok_or_else(|| anyhow::anyhow!("Slice {} not found", slice_id))?;
+        let binding = SliceBinding::generate(&slice, matches);
+
+        let mut bindings = self.bindings.write().await;
+        if let Some(sink) = &self.policy_sink {
+            if let Some(previous) = bindings.get(slice_id) {
+                sink.withdraw(previous).await?;
+            }
+            sink.apply(&binding).await?;
+        }
+        tracing::info!(
+            "Bound slice {} to {} SD-WAN policies ({:?})",
+            slice.name, binding.policies.len(), binding.qos.class
+        );
+        bindings.insert(*slice_id, binding.clone());
+        Ok(binding)
+    }
+
+    /// Withdraw a slice's policies, returning whether it was bound
+    pub async fn unbind_slice(&self, slice_id: &Uuid) -> Result<bool> {
+        let mut bindings = self.bindings.write().await;
+        let Some(binding) = bindings.get(slice_id) else {
+            return Ok(false);
+        };
+        if let Some(sink) = &self.policy_sink {
+            sink.withdraw(binding).await?;
+        }
+        bindings.remove(slice_id);
+        Ok(true)
+    }
+
+    pub async fn get_binding(&self, slice_id: &Uuid) -> Option<SliceBinding> {
+        self.bindings.read().await.get(slice_id).cloned()
+    }
+
+    /// Change a slice's parameters and regenerate its SD-WAN policies
+    ///
+    /// Fails if an active slice's new floor cannot be reserved, or if the
+    /// new ceiling is below what is already admitted.
+    pub async fn update_slice_config(&self, slice_id: &Uuid, config: NetworkSlice) -> Result<()> {
+        if config.min_bandwidth_mbps < 0.0 || config.max_bandwidth_mbps < config.min_bandwidth_mbps {
+            anyhow::bail!(
+                "Invalid slice bandwidth {}-{} Mbps",
+                config.min_bandwidth_mbps, config.max_bandwidth_mbps
+            );
+        }
+
+        let mut slices = self.slices.write().await;
+        let others: f64 = slices.values()
+            .filter(|s| s.id != *slice_id)
+            .map(|s| s.reserved_bandwidth_mbps())
+            .sum();
+        let slice = slices.get_mut(slice_id)
+            .ok_or_else(|| anyhow::anyhow!("Slice {} not found", slice_id))?;
+        if slice.active && others + slice.allocated_bandwidth_mbps.max(config.min_bandwidth_mbps) > self.capacity_mbps {
+            anyhow::bail!("Cannot reserve {} Mbps for slice {}", config.min_bandwidth_mbps, slice.name);
+        }
+        if slice.allocated_bandwidth_mbps > config.max_bandwidth_mbps {
+            anyhow::bail!(
+                "Slice {} already carries {} Mbps, above the new {} Mbps ceiling",
+                slice.name, slice.allocated_bandwidth_mbps, config.max_bandwidth_mbps
+            );
+        }
+        slice.slice_config = config;
+        let slice = slice.clone();
+        drop(slices);
+
+        let mut bindings = self.bindings.write().await;
+        if let Some(previous) = bindings.get(slice_id) {
+            let binding = SliceBinding::generate(&slice, previous.matches.clone());
+            if let Some(sink) = &self.policy_sink {
+                sink.apply(&binding).await?;
+            }
+            tracing::info!("Regenerated SD-WAN policies for slice {}", slice.name);
+            bindings.insert(*slice_id, binding);
+        }
+        Ok(())
+    }
+
+    /// Utilization and SLA attainment of a bound slice
+    pub async fn slice_report(
+        &self,
+        slice_id: &Uuid,
+        stats: &TrafficStatsCollector,
+        sla: &SlaMonitor,
+    ) -> Result<SliceReport> {
+        let slice = self.get_slice(slice_id).await
+            .ok_or_else(|| anyhow::anyhow!("Slice {} not found", slice_id))?;
+        let binding = self.get_binding(slice_id).await
+            .ok_or_else(|| anyhow::anyhow!("Slice {} is not bound to any traffic", slice.name))?;
+        Ok(SliceReport::build(&slice, &binding, stats, sla).await)
+    }
 }
 
 impl Default for SliceManager {
@@ -158,4 +443,186 @@ mod tests {
         let slice = manager.get_slice(&id).await.unwrap();
         assert!(slice.active);
     }
+
+    #[tokio::test]
+    async fn test_embb_cannot_starve_urllc_reservation() {
+        let manager = SliceManager::with_capacity(1200.0);
+        let urllc = manager.create_slice("control".to_string(), SliceType::URLLC).await;
+        let embb = manager.create_slice("video".to_string(), SliceType::EMBB).await;
+        assert!(manager.activate_slice(&urllc).await);
+        assert!(manager.activate_slice(&embb).await);
+        assert_eq!(manager.idle_capacity_mbps().await, 100.0);
+
+        // eMBB fills its floor, then borrows the idle 100 Mbps
+        manager.admit_flow(&embb, 1000.0, 100.0).await.unwrap();
+        let borrowed = manager.admit_flow(&embb, 100.0, 100.0).await.unwrap();
+        assert_eq!(manager.get_flow(&borrowed).await.unwrap().borrowed_mbps, 100.0);
+
+        // Anything more would come out of the URLLC reservation
+        assert!(manager.admit_flow(&embb, 50.0, 100.0).await.is_err());
+        manager.admit_flow(&urllc, 100.0, 1.0).await.unwrap();
+
+        // URLLC never goes past its committed capacity
+        assert!(manager.admit_flow(&urllc, 1.0, 1.0).await.is_err());
+
+        // Released borrowed capacity can be borrowed again
+        assert!(manager.release_flow(&borrowed).await);
+        manager.admit_flow(&embb, 50.0, 100.0).await.unwrap();
+        assert_eq!(manager.get_slice(&embb).await.unwrap().allocated_bandwidth_mbps, 1050.0);
+    }
+
+    #[tokio::test]
+    async fn test_admission_checks_latency_and_reservation() {
+        let manager = SliceManager::with_capacity(1050.0);
+        let embb = manager.create_slice("video".to_string(), SliceType::EMBB).await;
+        let urllc = manager.create_slice("control".to_string(), SliceType::URLLC).await;
+
+        assert!(manager.admit_flow(&embb, 10.0, 100.0).await.is_err());
+        assert!(manager.activate_slice(&embb).await);
+
+        // eMBB cannot promise 10 ms
+        assert!(manager.admit_flow(&embb, 10.0, 10.0).await.is_err());
+
+        // Only 50 Mbps left, not enough for the URLLC floor
+        assert!(!manager.activate_slice(&urllc).await);
+    }
+
+    #[tokio::test]
+    async fn test_bound_slices_drive_sdwan_policies() {
+        use crate::slice_policy::{QueueDiscipline, SliceTrafficMatch};
+        use patronus_sdwan::policy::{PacketMarking, PathPreference, PolicyEngine};
+        use patronus_sdwan::qos::QosClass;
+        use patronus_sdwan::types::FlowKey;
+
+        let engine = Arc::new(RwLock::new(PolicyEngine::new()));
+        let manager = SliceManager::new().with_policy_sink(engine.clone());
+        let control = manager.create_slice("control".to_string(), SliceType::URLLC).await;
+        let sensors = manager.create_slice("sensors".to_string(), SliceType::MMTC).await;
+
+        assert!(manager.bind_slice_to_policy(&control, vec![SliceTrafficMatch::Dscp(64)]).await.is_err());
+        assert!(manager.bind_slice_to_policy(&control, vec![SliceTrafficMatch::Vlan(0)]).await.is_err());
+        let binding = manager
+            .bind_slice_to_policy(&control, vec![SliceTrafficMatch::Dscp(46), SliceTrafficMatch::Vlan(20)])
+            .await
+            .unwrap();
+        assert_eq!(binding.qos.class, QosClass::RealTime);
+        assert_eq!(binding.qos.discipline, QueueDiscipline::StrictPriority);
+        assert_eq!(binding.policies.len(), 2);
+        assert!(matches!(binding.policies[0].path_preference, PathPreference::LowestLatency));
+
+        let binding = manager
+            .bind_slice_to_policy(&sensors, vec![SliceTrafficMatch::SourceCidr("10.50.0.0/16".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(binding.qos.discipline, QueueDiscipline::BestEffort { per_device_rate_mbps: Some(0.1) });
+
+        let flow = FlowKey {
+            src_ip: "10.50.1.7".parse().unwrap(),
+            dst_ip: "192.0.2.1".parse().unwrap(),
+            src_port: 5683,
+            dst_port: 5683,
+            protocol: 17,
+        };
+        let ef = PacketMarking { dscp: Some(46), vlan_id: None };
+        {
+            let engine = engine.read().await;
+            assert_eq!(engine.list_policies().len(), 3);
+            // URLLC outranks mMTC when a sensor marks its traffic EF
+            let policy = engine.find_matching_policy_marked(&flow, &ef).unwrap();
+            assert_eq!(policy.name, "slice-control-0");
+            assert_eq!(engine.find_matching_policy(&flow).unwrap().name, "slice-sensors-0");
+        }
+
+        // Changing the slice regenerates its policies in place
+        let mut config = NetworkSlice::mmtc();
+        config.priority = 400;
+        config.per_device_rate_mbps = Some(0.5);
+        manager.update_slice_config(&sensors, config).await.unwrap();
+        let binding = manager.get_binding(&sensors).await.unwrap();
+        assert_eq!(binding.qos.discipline, QueueDiscipline::BestEffort { per_device_rate_mbps: Some(0.5) });
+        {
+            let engine = engine.read().await;
+            assert_eq!(engine.list_policies().len(), 3);
+            assert_eq!(engine.find_matching_policy_marked(&flow, &ef).unwrap().name, "slice-sensors-0");
+        }
+
+        assert!(manager.unbind_slice(&control).await.unwrap());
+        assert!(!manager.unbind_slice(&control).await.unwrap());
+        assert_eq!(engine.read().await.list_policies().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slice_config_update_respects_capacity() {
+        let manager = SliceManager::with_capacity(1200.0);
+        let embb = manager.create_slice("video".to_string(), SliceType::EMBB).await;
+        let urllc = manager.create_slice("control".to_string(), SliceType::URLLC).await;
+        assert!(manager.activate_slice(&embb).await);
+        assert!(manager.activate_slice(&urllc).await);
+        manager.admit_flow(&embb, 600.0, 100.0).await.unwrap();
+
+        let mut config = NetworkSlice::embb();
+        config.min_bandwidth_mbps = 1200.0;
+        assert!(manager.update_slice_config(&embb, config).await.is_err());
+
+        let mut config = NetworkSlice::embb();
+        config.max_bandwidth_mbps = 500.0;
+        config.min_bandwidth_mbps = 500.0;
+        assert!(manager.update_slice_config(&embb, config).await.is_err());
+
+        let mut config = NetworkSlice::embb();
+        config.min_bandwidth_mbps = 800.0;
+        manager.update_slice_config(&embb, config).await.unwrap();
+        assert_eq!(manager.idle_capacity_mbps().await, 300.0);
+    }
+
+    #[tokio::test]
+    async fn test_slice_report() {
+        use crate::slice_policy::SliceTrafficMatch;
+        use patronus_sdwan::sla::{SlaConfig, SlaMonitor};
+        use patronus_sdwan::traffic_stats::TrafficStatsCollector;
+        use patronus_sdwan::types::{FlowKey, PathId};
+
+        let manager = SliceManager::new();
+        let control = manager.create_slice("control".to_string(), SliceType::URLLC).await;
+        let stats = TrafficStatsCollector::new(None);
+        let sla = SlaMonitor::new();
+        assert!(manager.slice_report(&control, &stats, &sla).await.is_err());
+
+        let binding = manager
+            .bind_slice_to_policy(&control, vec![SliceTrafficMatch::Dscp(46)])
+            .await
+            .unwrap();
+        let report = manager.slice_report(&control, &stats, &sla).await.unwrap();
+        assert_eq!(report.bytes, 0);
+        assert_eq!(report.sla_attainment_percent, None);
+
+        let flow = FlowKey {
+            src_ip: "10.0.0.1".parse().unwrap(),
+            dst_ip: "10.0.0.2".parse().unwrap(),
+            src_port: 4000,
+            dst_port: 4001,
+            protocol: 17,
+        };
+        stats.record_packet(binding.policies[0].id, flow, 1500).await;
+        stats.record_packet(binding.policies[0].id, flow, 500).await;
+
+        let config = SlaConfig { min_samples: 1, ..Default::default() };
+        for (path, latency) in [(1, 0.5), (2, 0.8), (3, 12.0)] {
+            let path = PathId::new(path);
+            sla.configure_path(path, config.clone());
+            sla.record_latency(&path, latency);
+            sla.record_packets(&path, 100_000, 0);
+            sla.compute_sla(&path).unwrap();
+        }
+
+        let report = manager.slice_report(&control, &stats, &sla).await.unwrap();
+        assert_eq!(report.packets, 2);
+        assert_eq!(report.bytes, 2000);
+        assert_eq!(report.active_flows, 1);
+        assert_eq!(report.paths.len(), 3);
+        assert_eq!(report.paths[0].path_id, PathId::new(1));
+        assert!(!report.paths[2].met);
+        let attainment = report.sla_attainment_percent.unwrap();
+        assert!((attainment - 200.0 / 3.0).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,78 @@
+//! Fleet Operations
+//!
+//! Devices carry free-form tags and can be collected into named groups.
+//! Bulk operations such as
+//! [`DeviceManager::apply_config_to_group`](crate::DeviceManager::apply_config_to_group)
+//! run against every member with bounded concurrency and report an outcome
+//! per device; one device failing does not stop the others.
+
+use crate::device::{DeviceMetrics, IoTDevice};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// Sends management commands to devices
+#[async_trait]
+pub trait DeviceController: Send + Sync {
+    async fn reboot(&self, device: &IoTDevice) -> anyhow::Result<()>;
+
+    async fn apply_config(&self, device: &IoTDevice, config: &serde_json::Value) -> anyhow::Result<()>;
+
+    /// Fetch current metrics from the device
+    async fn query_metrics(&self, device: &IoTDevice) -> anyhow::Result<DeviceMetrics>;
+}
+
+/// A named set of devices
+///
+/// Members are the devices added explicitly plus every device carrying all
+/// of the group's tags, if it has any.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub name: String,
+    pub members: BTreeSet<Uuid>,
+    pub tags: BTreeSet<String>,
+}
+
+impl DeviceGroup {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// Group of every device carrying all of `tags`
+    pub fn by_tags(name: String, tags: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            name,
+            tags: tags.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn contains(&self, device: &IoTDevice) -> bool {
+        self.members.contains(&device.id)
+            || (!self.tags.is_empty() && self.tags.is_subset(&device.tags))
+    }
+}
+
+/// Outcome of a bulk operation on one device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceOpResult<T> {
+    pub device_id: Uuid,
+    pub device_name: String,
+    pub result: Result<T, String>,
+}
+
+impl<T> DeviceOpResult<T> {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Number of devices a bulk operation succeeded and failed on
+pub fn summarize<T>(results: &[DeviceOpResult<T>]) -> (usize, usize) {
+    let succeeded = results.iter().filter(|r| r.is_ok()).count();
+    (succeeded, results.len() - succeeded)
+}
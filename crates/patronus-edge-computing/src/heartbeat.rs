@@ -0,0 +1,261 @@
+//! Node Heartbeats
+//!
+//! Every few seconds an edge node sends a [`SignedHeartbeat`]: its own view
+//! of its status, current metrics, a summary of its attached devices and the
+//! health of the workloads it runs. The payload is HMAC-SHA256 signed with a
+//! key shared between the node and the controller, and carries a sequence
+//! number so a captured heartbeat cannot be replayed.
+//!
+//! The controller side lives in [`EdgeNodeManager`](crate::EdgeNodeManager):
+//! one missed interval degrades a node, several take it offline, and each
+//! status change is kept in a per-node timeline.
+
+use crate::device::IoTDevice;
+use crate::edge_node::{NodeMetrics, NodeStatus};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Devices behind a node, summarised
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceAggregates {
+    pub connected: usize,
+    /// Devices reporting a battery below 20%
+    pub low_battery: usize,
+    pub mean_signal_dbm: Option<f64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl DeviceAggregates {
+    pub fn from_devices<'a>(devices: impl IntoIterator<Item = &'a IoTDevice>) -> Self {
+        let mut aggregates = Self::default();
+        let mut signal_sum = 0.0;
+        for device in devices {
+            aggregates.connected += 1;
+            if device.metrics.battery_percent.is_some_and(|b| b < 20.0) {
+                aggregates.low_battery += 1;
+            }
+            signal_sum += device.metrics.signal_strength_dbm;
+            aggregates.bytes_sent += device.metrics.data_sent_bytes;
+            aggregates.bytes_received += device.metrics.data_received_bytes;
+        }
+        if aggregates.connected > 0 {
+            aggregates.mean_signal_dbm = Some(signal_sum / aggregates.connected as f64);
+        }
+        aggregates
+    }
+}
+
+/// A workload's health as seen by the node running it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkloadHealth {
+    pub workload_id: Uuid,
+    pub healthy: bool,
+    pub restarts: u32,
+    pub detail: Option<String>,
+}
+
+/// What a node reports about itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub node_id: Uuid,
+    /// Increases with every heartbeat the node sends
+    pub sequence: u64,
+    /// Node's clock when the heartbeat was sent
+    pub sent_at: DateTime<Utc>,
+    /// `Offline` announces a graceful shutdown, `Degraded` a fault the
+    /// node detected itself
+    pub status: NodeStatus,
+    pub metrics: NodeMetrics,
+    pub devices: DeviceAggregates,
+    pub workloads: Vec<WorkloadHealth>,
+}
+
+/// A heartbeat as sent on the wire
+///
+/// The signature covers the exact payload bytes, so the controller verifies
+/// before parsing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedHeartbeat {
+    pub node_id: Uuid,
+    /// JSON-encoded [`Heartbeat`]
+    pub payload: String,
+    /// Hex HMAC-SHA256 of the payload
+    pub signature: String,
+}
+
+impl SignedHeartbeat {
+    pub fn sign(heartbeat: &Heartbeat, key: &[u8]) -> Result<Self> {
+        let payload = serde_json::to_string(heartbeat)?;
+        let mut mac = HmacSha256::new_from_slice(key)?;
+        mac.update(payload.as_bytes());
+        Ok(Self {
+            node_id: heartbeat.node_id,
+            payload,
+            signature: hex::encode(mac.finalize().into_bytes()),
+        })
+    }
+
+    /// Check the signature and decode the heartbeat
+    pub fn verify(&self, key: &[u8]) -> Result<Heartbeat> {
+        let signature = hex::decode(&self.signature)
+            .map_err(|_| anyhow::anyhow!("Heartbeat signature is not hex"))?;
+        let mut mac = HmacSha256::new_from_slice(key)?;
+        mac.update(self.payload.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| anyhow::anyhow!("Bad heartbeat signature from node {}", self.node_id))?;
+
+        let heartbeat: Heartbeat = serde_json::from_str(&self.payload)?;
+        if heartbeat.node_id != self.node_id {
+            anyhow::bail!("Heartbeat for node {} sent as node {}", heartbeat.node_id, self.node_id);
+        }
+        Ok(heartbeat)
+    }
+}
+
+/// What the controller answers to a heartbeat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeartbeatAck {
+    pub node_id: Uuid,
+    pub sequence: u64,
+    pub received_at: DateTime<Utc>,
+    /// Status the controller now holds for the node
+    pub status: NodeStatus,
+    /// Node clock minus controller clock, when it exceeds the tolerance
+    pub clock_skew_ms: Option<i64>,
+}
+
+/// A status change, for post-mortems
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusTransition {
+    pub at: DateTime<Utc>,
+    pub from: NodeStatus,
+    pub to: NodeStatus,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NodeAlertKind {
+    /// No heartbeat for one interval; the node is degraded
+    MissedHeartbeat { last_heartbeat: DateTime<Utc> },
+    /// No heartbeat for the offline timeout; its workloads will be rescheduled
+    Offline { last_heartbeat: DateTime<Utc> },
+    /// Node and controller clocks disagree by more than the tolerance
+    ClockSkew { skew_ms: i64 },
+    /// Heartbeats resumed after the node was degraded or offline
+    Recovered,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeAlert {
+    pub node_id: Uuid,
+    pub node_name: String,
+    pub kind: NodeAlertKind,
+    pub at: DateTime<Utc>,
+}
+
+/// Carries heartbeats from a node to the controller, e.g. an HTTP POST or
+/// the mesh control plane
+/// First sequence number of a node booted at `at`
+fn boot_sequence(at: DateTime<Utc>) -> u64 {
+    (at.timestamp_millis().max(0) as u64) << SEQUENCE_COUNTER_BITS
+}
+
+#[async_trait]
+pub trait HeartbeatTransport: Send + Sync {
+    async fn send(&self, heartbeat: &SignedHeartbeat) -> Result<HeartbeatAck>;
+}
+
+/// Bits of a sequence number counting heartbeats within one boot
+const SEQUENCE_COUNTER_BITS: u32 = 20;
+
+/// Node-side heartbeat construction
+///
+/// Sequence numbers start from the boot time in Unix milliseconds, shifted
+/// above a per-boot counter, so a restarted node continues above whatever
+/// it sent before the restart (as long as its clock did not go back) and
+/// the controller does not mistake its heartbeats for replays.
+pub struct HeartbeatSigner {
+    node_id: Uuid,
+    key: Vec<u8>,
+    sequence: AtomicU64,
+}
+
+impl HeartbeatSigner {
+    pub fn new(node_id: Uuid, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            node_id,
+            key: key.into(),
+            sequence: AtomicU64::new(boot_sequence(Utc::now())),
+        }
+    }
+
+    /// Continue from a persisted sequence number instead of the boot time
+    pub fn with_sequence(self, last_sent: u64) -> Self {
+        self.sequence.store(last_sent, Ordering::Relaxed);
+        self
+    }
+
+    /// Sequence number of the last heartbeat signed, for persisting
+    pub fn last_sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Relaxed)
+    }
+
+    /// Sign the next heartbeat
+    pub fn next(
+        &self,
+        status: NodeStatus,
+        metrics: NodeMetrics,
+        devices: DeviceAggregates,
+        workloads: Vec<WorkloadHealth>,
+    ) -> Result<SignedHeartbeat> {
+        let heartbeat = Heartbeat {
+            node_id: self.node_id,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed) + 1,
+            sent_at: Utc::now(),
+            status,
+            metrics,
+            devices,
+            workloads,
+        };
+        SignedHeartbeat::sign(&heartbeat, &self.key)
+    }
+
+    /// Send a heartbeat built from `collect` every `interval` until aborted
+    ///
+    /// Send failures are logged; the next tick tries again.
+    pub fn spawn<F>(self: Arc<Self>, transport: Arc<dyn HeartbeatTransport>, collect: F, interval: Duration) -> JoinHandle<()>
+    where
+        F: Fn() -> (NodeStatus, NodeMetrics, DeviceAggregates, Vec<WorkloadHealth>) + Send + Sync + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let (status, metrics, devices, workloads) = collect();
+                let result = match self.next(status, metrics, devices, workloads) {
+                    Ok(heartbeat) => transport.send(&heartbeat).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(ack) if ack.clock_skew_ms.is_some() => {
+                        tracing::warn!("Controller reports clock skew of {:?} ms", ack.clock_skew_ms);
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Heartbeat from node {} failed: {}", self.node_id, e),
+                }
+            }
+        })
+    }
+}
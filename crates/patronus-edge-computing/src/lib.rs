@@ -4,10 +4,42 @@
 
 pub mod device;
 pub mod edge_node;
+pub mod enrollment;
+pub mod firmware;
 pub mod fiveg;
+pub mod fleet;
+pub mod heartbeat;
+pub mod placement;
+pub mod slice_policy;
 pub mod workload;
 
 pub use device::{IoTDevice, DeviceType, DeviceManager, DeviceMetrics};
-pub use edge_node::{EdgeNode, EdgeNodeManager, NodeCapabilities, NodeStatus};
-pub use fiveg::{FiveGSlice, NetworkSlice, SliceType, SliceManager};
-pub use workload::{EdgeWorkload, WorkloadScheduler, WorkloadPlacement, SchedulingPolicy};
+pub use firmware::{
+    DeviceFilter, DeviceUpdate, DeviceUpdateStatus, Firmware, FirmwareInstaller, FirmwareRollout,
+    RolloutState,
+};
+pub use enrollment::{
+    EnrolledDevice, EnrollmentMode, EnrollmentOutcome, EnrollmentPeer, EnrollmentRequest,
+    EnrollmentStats, EnrollmentToken, NftQuarantine, PendingEnrollment, QuarantineHook,
+};
+pub use edge_node::{
+    EdgeNode, EdgeNodeManager, HealthScoring, HealthWeights, NodeCapabilities, NodeMetrics, NodeStatus,
+};
+pub use fleet::{DeviceController, DeviceGroup, DeviceOpResult};
+pub use heartbeat::{
+    DeviceAggregates, Heartbeat, HeartbeatAck, HeartbeatSigner, HeartbeatTransport, NodeAlert, NodeAlertKind,
+    SignedHeartbeat, StatusTransition, WorkloadHealth,
+};
+pub use placement::{
+    NodeEvaluation, NodeVerdict, PlacementConstraints, PlacementExplanation, RescheduleEvent,
+    ScoreBreakdown, SiteLatency,
+};
+pub use fiveg::{FiveGSlice, NetworkSlice, SliceFlow, SliceType, SliceManager};
+pub use slice_policy::{
+    PathAttainment, QueueDiscipline, SliceBinding, SlicePolicySink, SliceQos, SliceReport,
+    SliceTrafficMatch,
+};
+pub use workload::{
+    EdgeWorkload, WorkloadScheduler, WorkloadPlacement, SchedulingPolicy,
+    ScalingMetric, ScalingEvent, WorkloadReplica, ReplicaState,
+};
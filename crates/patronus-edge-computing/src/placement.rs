@@ -0,0 +1,277 @@
+//! Workload Placement
+//!
+//! A workload's [`PlacementConstraints`] rule nodes out: missing GPU or
+//! storage, the wrong region, too far from a latency-sensitive site, or
+//! already running a workload it must not share a node with. Nodes that
+//! pass are scored on load, free capacity, health and, when the workload
+//! names a site, latency to it; the scheduling policy decides how much each
+//! counts. Every decision is kept as a [`PlacementExplanation`].
+
+use crate::edge_node::{EdgeNode, NodeStatus};
+use crate::workload::{EdgeWorkload, SchedulingPolicy};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// A ceiling on the latency between the hosting node and a named site
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiteLatency {
+    pub site: String,
+    pub max_ms: f64,
+}
+
+/// Where a workload may run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlacementConstraints {
+    pub requires_gpu: bool,
+    pub requires_5g: bool,
+    pub min_storage_gb: Option<u32>,
+    /// Workloads this one must not share a node with, in either direction
+    pub anti_affinity: BTreeSet<Uuid>,
+    pub max_latency_to: Option<SiteLatency>,
+    /// Allowed regions; any region when empty
+    pub regions: BTreeSet<String>,
+}
+
+impl PlacementConstraints {
+    /// Why `node` can never host the workload, ignoring capacity and
+    /// co-located workloads
+    fn node_rejection(&self, node: &EdgeNode) -> Option<String> {
+        if !self.regions.is_empty() {
+            match &node.region {
+                Some(region) if self.regions.contains(region) => {}
+                Some(region) => return Some(format!("region {} is not one of {:?}", region, self.regions)),
+                None => return Some(format!("no region set, workload is pinned to {:?}", self.regions)),
+            }
+        }
+        if self.requires_gpu && !node.capabilities.gpu_available {
+            return Some("no GPU".to_string());
+        }
+        if self.requires_5g && !node.capabilities.supports_5g {
+            return Some("no 5G support".to_string());
+        }
+        if let Some(min) = self.min_storage_gb {
+            if node.capabilities.storage_gb < min {
+                return Some(format!("{} GB storage, needs {} GB", node.capabilities.storage_gb, min));
+            }
+        }
+        if let Some(ceiling) = &self.max_latency_to {
+            match node.site_latency_ms.get(&ceiling.site) {
+                None => return Some(format!("no latency measurement to site {}", ceiling.site)),
+                Some(&latency) if latency > ceiling.max_ms => {
+                    return Some(format!(
+                        "{:.1} ms to {} exceeds {:.1} ms",
+                        latency, ceiling.site, ceiling.max_ms
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        None
+    }
+}
+
+/// How a feasible node scored, each component from 0 to 100
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreBreakdown {
+    /// Headroom left by reported usage plus existing reservations
+    pub load: f64,
+    /// Share of the node's CPU and memory still free after placement
+    pub capacity: f64,
+    pub health: f64,
+    /// Closeness to the workload's latency ceiling, if it has one
+    pub latency: Option<f64>,
+    /// Policy-weighted average of the components
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NodeVerdict {
+    Chosen(ScoreBreakdown),
+    /// Feasible, but another node scored higher
+    Outscored(ScoreBreakdown),
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeEvaluation {
+    pub node_id: Uuid,
+    pub node_name: String,
+    pub verdict: NodeVerdict,
+}
+
+/// Why a workload ended up where it is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlacementExplanation {
+    pub workload_id: Uuid,
+    pub policy: SchedulingPolicy,
+    pub chosen: Option<Uuid>,
+    /// Chosen node first, then the other feasible nodes by score, then the
+    /// rejected ones
+    pub nodes: Vec<NodeEvaluation>,
+    pub decided_at: DateTime<Utc>,
+}
+
+impl PlacementExplanation {
+    pub fn chosen_score(&self) -> Option<&ScoreBreakdown> {
+        self.nodes.iter().find_map(|n| match &n.verdict {
+            NodeVerdict::Chosen(score) => Some(score),
+            _ => None,
+        })
+    }
+
+    pub fn rejection(&self, node_id: &Uuid) -> Option<&str> {
+        self.nodes.iter().find(|n| n.node_id == *node_id).and_then(|n| match &n.verdict {
+            NodeVerdict::Rejected { reason } => Some(reason.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// What is already committed to each node
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NodeUsage {
+    /// CPU and memory reserved by placed workloads and replicas
+    pub reserved: HashMap<Uuid, (f64, f64)>,
+    /// Workloads with a placement or replica on each node
+    pub residents: HashMap<Uuid, Vec<(Uuid, String, BTreeSet<Uuid>)>>,
+}
+
+impl NodeUsage {
+    pub fn add(&mut self, node_id: Uuid, workload: &EdgeWorkload) {
+        let entry = self.reserved.entry(node_id).or_default();
+        entry.0 += workload.cpu_requirement;
+        entry.1 += workload.memory_requirement_gb;
+        let residents = self.residents.entry(node_id).or_default();
+        if !residents.iter().any(|(id, _, _)| *id == workload.id) {
+            residents.push((workload.id, workload.name.clone(), workload.constraints.anti_affinity.clone()));
+        }
+    }
+
+    /// Why `workload` cannot go on `node`, if it cannot
+    pub fn rejection(&self, workload: &EdgeWorkload, node: &EdgeNode) -> Option<String> {
+        if node.status != NodeStatus::Online {
+            return Some(format!("node is {:?}", node.status));
+        }
+        if node.is_overloaded() {
+            return Some("node is overloaded".to_string());
+        }
+        if let Some(reason) = workload.constraints.node_rejection(node) {
+            return Some(reason);
+        }
+
+        let (cpu, memory) = self.reserved.get(&node.id).copied().unwrap_or_default();
+        let free_cpu = node.capabilities.cpu_cores as f64 - cpu;
+        let free_memory = node.capabilities.memory_gb as f64 - memory;
+        if free_cpu < workload.cpu_requirement {
+            return Some(format!("{:.1} cores free, needs {:.1}", free_cpu.max(0.0), workload.cpu_requirement));
+        }
+        if free_memory < workload.memory_requirement_gb {
+            return Some(format!(
+                "{:.1} GB memory free, needs {:.1} GB",
+                free_memory.max(0.0),
+                workload.memory_requirement_gb
+            ));
+        }
+
+        let conflict = self.residents.get(&node.id).into_iter().flatten().find(|(id, _, anti)| {
+            *id != workload.id && (workload.constraints.anti_affinity.contains(id) || anti.contains(&workload.id))
+        });
+        conflict.map(|(_, name, _)| format!("runs anti-affine workload {}", name))
+    }
+
+    /// Score a node that passed [`rejection`](Self::rejection)
+    pub fn score(&self, workload: &EdgeWorkload, node: &EdgeNode, policy: &SchedulingPolicy) -> ScoreBreakdown {
+        let (cpu, memory) = self.reserved.get(&node.id).copied().unwrap_or_default();
+        let cores = node.capabilities.cpu_cores.max(1) as f64;
+        let memory_gb = node.capabilities.memory_gb.max(1) as f64;
+
+        let cpu_used = node.cpu_usage_percent + (cpu + workload.cpu_requirement) / cores * 100.0;
+        let memory_used = node.memory_usage_percent + (memory + workload.memory_requirement_gb) / memory_gb * 100.0;
+        let load = (100.0 - (cpu_used + memory_used) / 2.0).clamp(0.0, 100.0);
+
+        let free_after = |total: f64, reserved: f64, needed: f64| ((total - reserved - needed) / total).clamp(0.0, 1.0);
+        let capacity = (free_after(cores, cpu, workload.cpu_requirement)
+            + free_after(memory_gb, memory, workload.memory_requirement_gb))
+            * 50.0;
+
+        let health = node.health_score();
+        let latency = workload.constraints.max_latency_to.as_ref().and_then(|ceiling| {
+            let measured = node.site_latency_ms.get(&ceiling.site)?;
+            Some(((1.0 - measured / ceiling.max_ms.max(f64::EPSILON)) * 100.0).clamp(0.0, 100.0))
+        });
+
+        // (load, capacity, health, latency)
+        let weights = match policy {
+            SchedulingPolicy::LeastLoaded => (0.5, 0.2, 0.3, 0.0),
+            SchedulingPolicy::ResourceAware => (0.2, 0.5, 0.3, 0.0),
+            SchedulingPolicy::Latency => (0.2, 0.1, 0.2, 0.5),
+        };
+        let mut sum = load * weights.0 + capacity * weights.1 + health * weights.2;
+        let mut total_weight = weights.0 + weights.1 + weights.2;
+        if let Some(latency) = latency {
+            sum += latency * weights.3;
+            total_weight += weights.3;
+        }
+
+        ScoreBreakdown {
+            load,
+            capacity,
+            health,
+            latency,
+            total: sum / total_weight,
+        }
+    }
+
+    /// Evaluate every node and pick the best feasible one
+    pub fn evaluate(&self, workload: &EdgeWorkload, nodes: &[EdgeNode], policy: &SchedulingPolicy) -> PlacementExplanation {
+        let mut feasible = Vec::new();
+        let mut rejected = Vec::new();
+        for node in nodes {
+            match self.rejection(workload, node) {
+                Some(reason) => rejected.push(NodeEvaluation {
+                    node_id: node.id,
+                    node_name: node.name.clone(),
+                    verdict: NodeVerdict::Rejected { reason },
+                }),
+                None => feasible.push((node, self.score(workload, node, policy))),
+            }
+        }
+
+        feasible.sort_by(|(a, sa), (b, sb)| sb.total.total_cmp(&sa.total).then(a.name.cmp(&b.name)));
+        rejected.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+
+        let chosen = feasible.first().map(|(node, _)| node.id);
+        let mut evaluations: Vec<NodeEvaluation> = feasible
+            .into_iter()
+            .enumerate()
+            .map(|(i, (node, score))| NodeEvaluation {
+                node_id: node.id,
+                node_name: node.name.clone(),
+                verdict: if i == 0 { NodeVerdict::Chosen(score) } else { NodeVerdict::Outscored(score) },
+            })
+            .collect();
+        evaluations.extend(rejected);
+
+        PlacementExplanation {
+            workload_id: workload.id,
+            policy: policy.clone(),
+            chosen,
+            nodes: evaluations,
+            decided_at: Utc::now(),
+        }
+    }
+}
+
+/// What a reconciliation pass did about failed nodes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RescheduleEvent {
+    /// The node stopped sending heartbeats
+    NodeFailed { node_id: Uuid },
+    Rescheduled { workload_id: Uuid, from: Uuid, to: Uuid },
+    /// Left for a later pass: no feasible node, or the disruption budget is spent
+    Deferred { workload_id: Uuid, reason: String },
+    /// Still not running elsewhere after the reschedule deadline
+    DeadlineMissed { workload_id: Uuid, node_id: Uuid, since: DateTime<Utc> },
+}
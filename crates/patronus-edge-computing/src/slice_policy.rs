@@ -0,0 +1,318 @@
+//! Slice to SD-WAN Mapping
+//!
+//! A slice bound to its traffic becomes one SD-WAN routing policy per
+//! traffic match (source CIDR, VLAN or DSCP; any of them selects the slice)
+//! plus a QoS treatment derived from the slice type:
+//!
+//! - URLLC: lowest-latency path, strict-priority queue
+//! - eMBB: throughput-weighted path scoring, queue weighted by the slice floor
+//! - mMTC: cheapest path, best effort with a per-device rate cap
+//!
+//! Bindings are regenerated whenever the slice's parameters change and
+//! pushed to a [`SlicePolicySink`].
+
+use crate::fiveg::{FiveGSlice, NetworkSlice, SliceType};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use patronus_sdwan::policy::{
+    CidrNetwork, MatchRules, PathPreference, PathScoringWeights, PolicyEngine, RoutingPolicy,
+};
+use patronus_sdwan::qos::QosClass;
+use patronus_sdwan::sla::{SlaConfig, SlaMonitor};
+use patronus_sdwan::traffic_stats::TrafficStatsCollector;
+use patronus_sdwan::PathId;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// How traffic is recognised as belonging to a slice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SliceTrafficMatch {
+    /// Source prefix, e.g. "10.20.0.0/16"
+    SourceCidr(String),
+    /// 802.1Q VLAN ID, 1 to 4094
+    Vlan(u16),
+    /// DSCP code point, 0 to 63
+    Dscp(u8),
+}
+
+impl SliceTrafficMatch {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            SliceTrafficMatch::SourceCidr(cidr) if CidrNetwork::parse(cidr).is_none() => {
+                anyhow::bail!("Invalid source CIDR {}", cidr)
+            }
+            SliceTrafficMatch::Vlan(vlan) if !(1..=4094).contains(vlan) => {
+                anyhow::bail!("VLAN ID {} is outside 1-4094", vlan)
+            }
+            SliceTrafficMatch::Dscp(dscp) if *dscp > 63 => anyhow::bail!("DSCP {} is outside 0-63", dscp),
+            _ => Ok(()),
+        }
+    }
+
+    fn match_rules(&self) -> MatchRules {
+        match self {
+            SliceTrafficMatch::SourceCidr(cidr) => MatchRules {
+                src_ip: Some(cidr.clone()),
+                ..Default::default()
+            },
+            SliceTrafficMatch::Vlan(vlan) => MatchRules {
+                vlan_id: Some(*vlan),
+                ..Default::default()
+            },
+            SliceTrafficMatch::Dscp(dscp) => MatchRules {
+                dscp: Some(*dscp),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Queueing treatment of a slice's traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum QueueDiscipline {
+    /// Served ahead of every other queue
+    StrictPriority,
+    /// Share of the link in proportion to the slice floor
+    Weighted { weight_mbps: f64 },
+    /// Whatever is left, each device capped at the given rate
+    BestEffort { per_device_rate_mbps: Option<f64> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SliceQos {
+    pub class: QosClass,
+    pub discipline: QueueDiscipline,
+    pub guaranteed_mbps: f64,
+    pub ceiling_mbps: f64,
+}
+
+impl SliceQos {
+    pub fn for_slice(slice: &FiveGSlice) -> Self {
+        let config = &slice.slice_config;
+        let (class, discipline) = match slice.slice_type {
+            SliceType::URLLC => (QosClass::RealTime, QueueDiscipline::StrictPriority),
+            SliceType::EMBB => (
+                QosClass::Streaming,
+                QueueDiscipline::Weighted {
+                    weight_mbps: config.min_bandwidth_mbps,
+                },
+            ),
+            SliceType::MMTC => (
+                QosClass::Bulk,
+                QueueDiscipline::BestEffort {
+                    per_device_rate_mbps: config.per_device_rate_mbps,
+                },
+            ),
+        };
+        Self {
+            class,
+            discipline,
+            guaranteed_mbps: config.min_bandwidth_mbps,
+            ceiling_mbps: config.max_bandwidth_mbps,
+        }
+    }
+}
+
+/// The SD-WAN policies and QoS generated for one slice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceBinding {
+    pub slice_id: Uuid,
+    pub slice_name: String,
+    pub matches: Vec<SliceTrafficMatch>,
+    /// Slice parameters the policies were generated from
+    pub slice_config: NetworkSlice,
+    /// One policy per match, in the same order
+    pub policies: Vec<RoutingPolicy>,
+    pub qos: SliceQos,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SliceBinding {
+    pub fn generate(slice: &FiveGSlice, matches: Vec<SliceTrafficMatch>) -> Self {
+        let preference = match slice.slice_type {
+            SliceType::URLLC => PathPreference::LowestLatency,
+            SliceType::EMBB => PathPreference::Custom(PathScoringWeights::throughput_focused()),
+            SliceType::MMTC => PathPreference::LowestCost,
+        };
+        let policies = matches
+            .iter()
+            .enumerate()
+            .map(|(i, m)| RoutingPolicy {
+                id: policy_id(&slice.id, i),
+                name: format!("slice-{}-{}", slice.name, i),
+                priority: slice.slice_config.priority,
+                match_rules: m.match_rules(),
+                path_preference: preference.clone(),
+                enabled: true,
+            })
+            .collect();
+
+        Self {
+            slice_id: slice.id,
+            slice_name: slice.name.clone(),
+            matches,
+            slice_config: slice.slice_config.clone(),
+            policies,
+            qos: SliceQos::for_slice(slice),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn policy_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.policies.iter().map(|p| p.id)
+    }
+
+    /// SLA targets for paths carrying the slice
+    pub fn sla_config(&self) -> SlaConfig {
+        let latency_ms = self.slice_config.max_latency_ms.ceil().max(1.0) as u32;
+        let defaults = SlaConfig::default();
+        SlaConfig {
+            target_latency_ms: latency_ms,
+            target_packet_loss_pct: (100.0 - self.slice_config.reliability_percent).max(0.0) as f32,
+            target_jitter_ms: defaults.target_jitter_ms.min(latency_ms),
+            ..defaults
+        }
+    }
+}
+
+/// Policy IDs stay the same across regenerations of a slice's binding
+fn policy_id(slice_id: &Uuid, index: usize) -> u64 {
+    let (high, _) = slice_id.as_u64_pair();
+    (high & !0xff) | (index as u64 & 0xff)
+}
+
+/// Where generated slice policies are installed
+#[async_trait]
+pub trait SlicePolicySink: Send + Sync {
+    /// Install or replace the binding's policies
+    async fn apply(&self, binding: &SliceBinding) -> Result<()>;
+
+    async fn withdraw(&self, binding: &SliceBinding) -> Result<()>;
+}
+
+/// Installs into a [`PolicyEngine`], which matches higher-priority policies first
+#[async_trait]
+impl SlicePolicySink for RwLock<PolicyEngine> {
+    async fn apply(&self, binding: &SliceBinding) -> Result<()> {
+        let mut engine = self.write().await;
+        for policy in &binding.policies {
+            engine.remove_policy(policy.id);
+            engine.add_policy(policy.clone());
+        }
+        Ok(())
+    }
+
+    async fn withdraw(&self, binding: &SliceBinding) -> Result<()> {
+        let mut engine = self.write().await;
+        for id in binding.policy_ids() {
+            engine.remove_policy(id);
+        }
+        Ok(())
+    }
+}
+
+/// How one path measures up against a slice's SLA
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathAttainment {
+    pub path_id: PathId,
+    pub latency_p95_ms: f64,
+    pub packet_loss_pct: f32,
+    pub met: bool,
+}
+
+/// Utilization and SLA attainment of a bound slice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceReport {
+    pub slice_id: Uuid,
+    pub slice_name: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub active_flows: u64,
+    /// Mean rate since the slice's traffic was first seen
+    pub throughput_mbps: f64,
+    /// Throughput as a share of the guaranteed rate
+    pub utilization_percent: f64,
+    /// Bandwidth admitted onto the slice
+    pub allocated_mbps: f64,
+    /// Measured paths, best first
+    pub paths: Vec<PathAttainment>,
+    /// Share of measured paths meeting the slice SLA; `None` without measurements
+    pub sla_attainment_percent: Option<f64>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl SliceReport {
+    pub(crate) async fn build(
+        slice: &FiveGSlice,
+        binding: &SliceBinding,
+        stats: &TrafficStatsCollector,
+        sla: &SlaMonitor,
+    ) -> Self {
+        let mut packets = 0;
+        let mut bytes = 0;
+        let mut active_flows = 0;
+        let mut window: Option<(SystemTime, SystemTime)> = None;
+        for id in binding.policy_ids() {
+            let Some(policy_stats) = stats.get_policy_stats(id).await else {
+                continue;
+            };
+            packets += policy_stats.packets_matched;
+            bytes += policy_stats.bytes_matched;
+            active_flows += stats.get_active_flow_count(id).await;
+            window = Some(match window {
+                None => (policy_stats.first_seen, policy_stats.last_updated),
+                Some((first, last)) => (first.min(policy_stats.first_seen), last.max(policy_stats.last_updated)),
+            });
+        }
+
+        let throughput_mbps = window
+            .and_then(|(first, last)| last.duration_since(first).ok())
+            .map(|elapsed| elapsed.as_secs_f64())
+            .filter(|secs| *secs > 0.0)
+            .map_or(0.0, |secs| bytes as f64 * 8.0 / secs / 1_000_000.0);
+        let guaranteed = binding.qos.guaranteed_mbps;
+        let utilization_percent = if guaranteed > 0.0 {
+            throughput_mbps / guaranteed * 100.0
+        } else {
+            0.0
+        };
+
+        let targets = binding.sla_config();
+        let mut paths: Vec<PathAttainment> = sla
+            .get_all_measurements()
+            .into_values()
+            .map(|m| PathAttainment {
+                path_id: m.path_id,
+                latency_p95_ms: m.latency_p95_ms,
+                packet_loss_pct: m.packet_loss_pct,
+                met: m.latency_p95_ms <= binding.slice_config.max_latency_ms
+                    && m.packet_loss_pct <= targets.target_packet_loss_pct,
+            })
+            .collect();
+        paths.sort_by(|a, b| {
+            b.met
+                .cmp(&a.met)
+                .then(a.latency_p95_ms.total_cmp(&b.latency_p95_ms))
+                .then(a.path_id.as_u64().cmp(&b.path_id.as_u64()))
+        });
+        let sla_attainment_percent = (!paths.is_empty())
+            .then(|| paths.iter().filter(|p| p.met).count() as f64 / paths.len() as f64 * 100.0);
+
+        Self {
+            slice_id: slice.id,
+            slice_name: slice.name.clone(),
+            packets,
+            bytes,
+            active_flows,
+            throughput_mbps,
+            utilization_percent,
+            allocated_mbps: slice.allocated_bandwidth_mbps,
+            paths,
+            sla_attainment_percent,
+            generated_at: Utc::now(),
+        }
+    }
+}
@@ -1,11 +1,30 @@
 //! Edge Workload Scheduling
+//!
+//! Workloads are either placed once on a chosen node, or autoscaled: the
+//! scheduler keeps between `min_replicas` and `max_replicas` copies running
+//! across edge nodes, sized to the reported load. Surplus replicas are
+//! drained before removal so in-flight work can finish.
+//!
+//! Workloads placed with [`WorkloadScheduler::place_workload`] go on the
+//! best node satisfying their [`PlacementConstraints`], and
+//! [`WorkloadScheduler::reconcile`] moves them off nodes that stop sending
+//! heartbeats.
 
+use crate::edge_node::{EdgeNode, EdgeNodeManager, NodeStatus};
+use crate::placement::{NodeUsage, NodeVerdict, PlacementConstraints, PlacementExplanation, RescheduleEvent};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+/// Load within this fraction of the target doesn't trigger scaling
+const SCALING_TOLERANCE: f64 = 0.1;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum SchedulingPolicy {
     LeastLoaded,
@@ -13,6 +32,32 @@ pub enum SchedulingPolicy {
     ResourceAware,
 }
 
+/// Load signal an autoscaled workload is sized by
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ScalingMetric {
+    /// Average CPU utilization across replicas, in percent
+    Cpu { target_percent: f64 },
+    /// Total queued items, shared among replicas
+    QueueDepth { target_per_replica: f64 },
+}
+
+impl ScalingMetric {
+    /// Replicas needed for `load` with `current` replicas running
+    fn desired_replicas(&self, load: f64, current: u32) -> u32 {
+        let ratio = match *self {
+            ScalingMetric::Cpu { target_percent } => load / target_percent,
+            ScalingMetric::QueueDepth { target_per_replica } => {
+                load / (target_per_replica * current.max(1) as f64)
+            }
+        };
+
+        if (ratio - 1.0).abs() <= SCALING_TOLERANCE {
+            return current;
+        }
+        (ratio * current.max(1) as f64).ceil() as u32
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EdgeWorkload {
     pub id: Uuid,
@@ -20,6 +65,12 @@ pub struct EdgeWorkload {
     pub cpu_requirement: f64,
     pub memory_requirement_gb: f64,
     pub latency_requirement_ms: Option<f64>,
+    pub min_replicas: u32,
+    pub max_replicas: u32,
+    /// Autoscaling signal; fixed at `min_replicas` when unset
+    pub scaling_metric: Option<ScalingMetric>,
+    #[serde(default)]
+    pub constraints: PlacementConstraints,
 }
 
 impl EdgeWorkload {
@@ -30,6 +81,10 @@ impl EdgeWorkload {
             cpu_requirement: cpu,
             memory_requirement_gb: memory,
             latency_requirement_ms: None,
+            min_replicas: 1,
+            max_replicas: 1,
+            scaling_metric: None,
+            constraints: PlacementConstraints::default(),
         }
     }
 
@@ -37,6 +92,67 @@ impl EdgeWorkload {
         self.latency_requirement_ms = Some(latency_ms);
         self
     }
+
+    pub fn with_autoscaling(mut self, min_replicas: u32, max_replicas: u32, metric: ScalingMetric) -> Self {
+        self.min_replicas = min_replicas;
+        self.max_replicas = max_replicas.max(min_replicas);
+        self.scaling_metric = Some(metric);
+        self
+    }
+
+    pub fn with_constraints(mut self, constraints: PlacementConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    /// Whether `node` could ever host a replica of this workload
+    fn fits_on(&self, node: &EdgeNode) -> bool {
+        node.capabilities.cpu_cores as f64 >= self.cpu_requirement
+            && node.capabilities.memory_gb as f64 >= self.memory_requirement_gb
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ReplicaState {
+    Running,
+    /// Receives no new work and is removed once `until` passes or the
+    /// drain is confirmed
+    Draining { until: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkloadReplica {
+    pub id: Uuid,
+    pub workload_id: Uuid,
+    pub node_id: Uuid,
+    pub state: ReplicaState,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WorkloadReplica {
+    pub fn is_running(&self) -> bool {
+        self.state == ReplicaState::Running
+    }
+}
+
+/// What an autoscaling pass changed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ScalingEvent {
+    ReplicaAdded { workload_id: Uuid, replica_id: Uuid, node_id: Uuid },
+    /// A draining replica was put back into service instead of adding one
+    DrainCancelled { workload_id: Uuid, replica_id: Uuid },
+    DrainStarted { workload_id: Uuid, replica_id: Uuid },
+    ReplicaRemoved { workload_id: Uuid, replica_id: Uuid, node_id: Uuid },
+    /// Not enough node capacity for the desired replica count
+    Unschedulable { workload_id: Uuid, desired: u32, running: u32 },
+}
+
+/// Autoscaling state of one workload
+struct ScaledWorkload {
+    workload: EdgeWorkload,
+    replicas: Vec<WorkloadReplica>,
+    /// Latest reported load, in the units of the scaling metric
+    load: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,19 +162,64 @@ pub struct WorkloadPlacement {
     pub scheduled_successfully: bool,
 }
 
+/// A workload placed by the scheduler, kept for reconciliation
+struct PlacedWorkload {
+    workload: EdgeWorkload,
+    node_id: Uuid,
+    explanation: PlacementExplanation,
+    displaced: Option<Displacement>,
+}
+
+/// The node a workload was on failed
+struct Displacement {
+    from: Uuid,
+    since: DateTime<Utc>,
+    deadline_reported: bool,
+}
+
 pub struct WorkloadScheduler {
     placements: Arc<RwLock<HashMap<Uuid, WorkloadPlacement>>>,
+    placed: Arc<RwLock<HashMap<Uuid, PlacedWorkload>>>,
+    scaled: Arc<RwLock<HashMap<Uuid, ScaledWorkload>>>,
     policy: SchedulingPolicy,
+    drain_timeout: Duration,
+    reschedule_deadline: Duration,
+    max_disruption: usize,
 }
 
 impl WorkloadScheduler {
     pub fn new(policy: SchedulingPolicy) -> Self {
         Self {
             placements: Arc::new(RwLock::new(HashMap::new())),
+            placed: Arc::new(RwLock::new(HashMap::new())),
+            scaled: Arc::new(RwLock::new(HashMap::new())),
             policy,
+            drain_timeout: Duration::from_secs(30),
+            reschedule_deadline: Duration::from_secs(60),
+            max_disruption: 5,
         }
     }
 
+    /// How long a workload on a failed node may stay unplaced before
+    /// reconciliation reports the deadline as missed
+    pub fn with_reschedule_deadline(mut self, deadline: Duration) -> Self {
+        self.reschedule_deadline = deadline;
+        self
+    }
+
+    /// Move at most `max` workloads per reconciliation pass, so a burst of
+    /// node failures does not reshuffle the whole fleet at once
+    pub fn with_max_disruption(mut self, max: usize) -> Self {
+        self.max_disruption = max;
+        self
+    }
+
+    /// How long scale-down waits for a replica to drain before removing it
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
     pub async fn schedule_workload(&self, workload: &EdgeWorkload, node_id: Uuid) -> bool {
         let placement = WorkloadPlacement {
             workload_id: workload.id,
@@ -77,6 +238,7 @@ impl WorkloadScheduler {
     }
 
     pub async fn unschedule_workload(&self, workload_id: &Uuid) -> bool {
+        self.placed.write().await.remove(workload_id);
         let mut placements = self.placements.write().await;
         placements.remove(workload_id).is_some()
     }
@@ -87,6 +249,394 @@ impl WorkloadScheduler {
     }
 }
 
+impl WorkloadScheduler {
+    /// Place `workload` on the best node that satisfies its constraints
+    ///
+    /// Replaces any earlier placement of the same workload. Fails with every
+    /// node's rejection reason when none can host it.
+    pub async fn place_workload(&self, workload: EdgeWorkload, nodes: &EdgeNodeManager) -> Result<WorkloadPlacement> {
+        let all = nodes.list_nodes().await;
+        let mut placed = self.placed.write().await;
+        placed.remove(&workload.id);
+        let usage = node_usage(&placed, &*self.scaled.read().await);
+
+        let explanation = usage.evaluate(&workload, &all, &self.policy);
+        let Some(node_id) = explanation.chosen else {
+            let reasons: Vec<String> = explanation.nodes.iter()
+                .filter_map(|n| match &n.verdict {
+                    NodeVerdict::Rejected { reason } => Some(format!("{}: {}", n.node_name, reason)),
+                    _ => None,
+                })
+                .collect();
+            anyhow::bail!("No node can host {}: {}", workload.name, reasons.join("; "));
+        };
+
+        tracing::info!("Placed {} on node {}", workload.name, node_id);
+        let placement = WorkloadPlacement { workload_id: workload.id, node_id, scheduled_successfully: true };
+        self.placements.write().await.insert(workload.id, placement.clone());
+        placed.insert(workload.id, PlacedWorkload { workload, node_id, explanation, displaced: None });
+        Ok(placement)
+    }
+
+    /// Scores and rejection reasons behind a workload's current placement
+    pub async fn explain_placement(&self, workload_id: &Uuid) -> Option<PlacementExplanation> {
+        self.placed.read().await.get(workload_id).map(|p| p.explanation.clone())
+    }
+
+    /// Detect failed nodes and move their workloads elsewhere
+    ///
+    /// Nodes that missed their heartbeats are marked offline first. Workloads
+    /// on offline nodes are re-placed oldest failure first, up to the
+    /// disruption budget; the rest wait for the next pass.
+    pub async fn reconcile(&self, nodes: &EdgeNodeManager) -> Vec<RescheduleEvent> {
+        let mut events: Vec<RescheduleEvent> = nodes.check_heartbeats().await
+            .into_iter()
+            .map(|node_id| RescheduleEvent::NodeFailed { node_id })
+            .collect();
+        let all = nodes.list_nodes().await;
+        let now = Utc::now();
+
+        let mut placed = self.placed.write().await;
+        for p in placed.values_mut() {
+            let alive = all.iter().any(|n| n.id == p.node_id && n.status != NodeStatus::Offline);
+            if !alive && p.displaced.is_none() {
+                tracing::warn!("Node {} hosting {} has failed", p.node_id, p.workload.name);
+                p.displaced = Some(Displacement { from: p.node_id, since: now, deadline_reported: false });
+            }
+        }
+
+        let mut displaced: Vec<(DateTime<Utc>, String, Uuid)> = placed.values()
+            .filter_map(|p| p.displaced.as_ref().map(|d| (d.since, p.workload.name.clone(), p.workload.id)))
+            .collect();
+        if displaced.is_empty() {
+            return events;
+        }
+        displaced.sort();
+
+        let mut usage = node_usage(&placed, &*self.scaled.read().await);
+        let deadline = chrono::Duration::from_std(self.reschedule_deadline).unwrap_or_default();
+        let mut budget = self.max_disruption;
+        for (_, _, workload_id) in displaced {
+            let p = placed.get_mut(&workload_id).expect("collected above");
+            let from = p.node_id;
+
+            if budget == 0 {
+                events.push(RescheduleEvent::Deferred {
+                    workload_id,
+                    reason: "Disruption budget for this pass is spent".to_string(),
+                });
+            } else {
+                let explanation = usage.evaluate(&p.workload, &all, &self.policy);
+                match explanation.chosen {
+                    Some(to) => {
+                        budget -= 1;
+                        usage.add(to, &p.workload);
+                        tracing::info!("Rescheduled {} from failed node {} to {}", p.workload.name, from, to);
+                        p.node_id = to;
+                        p.explanation = explanation;
+                        p.displaced = None;
+                        self.placements.write().await.insert(workload_id, WorkloadPlacement {
+                            workload_id,
+                            node_id: to,
+                            scheduled_successfully: true,
+                        });
+                        events.push(RescheduleEvent::Rescheduled { workload_id, from, to });
+                        continue;
+                    }
+                    None => events.push(RescheduleEvent::Deferred {
+                        workload_id,
+                        reason: "No feasible node".to_string(),
+                    }),
+                }
+            }
+
+            if let Some(d) = p.displaced.as_mut() {
+                if !d.deadline_reported && now - d.since >= deadline {
+                    d.deadline_reported = true;
+                    tracing::error!("{} not rescheduled within {:?} of node {} failing",
+                        p.workload.name, self.reschedule_deadline, d.from);
+                    events.push(RescheduleEvent::DeadlineMissed { workload_id, node_id: d.from, since: d.since });
+                }
+            }
+        }
+        events
+    }
+
+    /// Run [`reconcile`](Self::reconcile) every `interval` until aborted
+    pub fn spawn_reconciler(self: Arc<Self>, nodes: Arc<EdgeNodeManager>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for event in self.reconcile(&nodes).await {
+                    tracing::debug!("Reconciler: {:?}", event);
+                }
+            }
+        })
+    }
+}
+
+/// Reservations and residents of every placed workload and replica
+fn node_usage(placed: &HashMap<Uuid, PlacedWorkload>, scaled: &HashMap<Uuid, ScaledWorkload>) -> NodeUsage {
+    let mut usage = NodeUsage::default();
+    for p in placed.values() {
+        usage.add(p.node_id, &p.workload);
+    }
+    for w in scaled.values() {
+        for r in &w.replicas {
+            usage.add(r.node_id, &w.workload);
+        }
+    }
+    usage
+}
+
+impl WorkloadScheduler {
+    /// Manage `workload` with the autoscaler
+    ///
+    /// Replicas are started at `min_replicas` on the next
+    /// [`autoscale`](Self::autoscale) pass.
+    pub async fn enable_autoscaling(&self, workload: EdgeWorkload) {
+        let mut scaled = self.scaled.write().await;
+        scaled.entry(workload.id).or_insert_with(|| ScaledWorkload {
+            workload,
+            replicas: Vec::new(),
+            load: None,
+        });
+    }
+
+    /// Stop managing a workload; returns its replicas, which are no longer tracked
+    pub async fn disable_autoscaling(&self, workload_id: &Uuid) -> Vec<WorkloadReplica> {
+        let mut scaled = self.scaled.write().await;
+        scaled.remove(workload_id).map(|w| w.replicas).unwrap_or_default()
+    }
+
+    /// Report the current load of an autoscaled workload
+    pub async fn report_load(&self, workload_id: &Uuid, load: f64) -> bool {
+        let mut scaled = self.scaled.write().await;
+        match scaled.get_mut(workload_id) {
+            Some(w) => {
+                w.load = Some(load);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Confirm a draining replica has finished its work
+    pub async fn complete_drain(&self, replica_id: &Uuid) -> bool {
+        let mut scaled = self.scaled.write().await;
+        for w in scaled.values_mut() {
+            if let Some(i) = w.replicas.iter().position(|r| r.id == *replica_id && !r.is_running()) {
+                w.replicas.remove(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn get_replicas(&self, workload_id: &Uuid) -> Vec<WorkloadReplica> {
+        let scaled = self.scaled.read().await;
+        scaled.get(workload_id).map(|w| w.replicas.clone()).unwrap_or_default()
+    }
+
+    /// Run one autoscaling pass over every managed workload
+    ///
+    /// Each workload is sized from its last reported load, clamped to its
+    /// replica bounds. New replicas go on online nodes with room for them,
+    /// picked by the scheduling policy; draining replicas are reused first.
+    /// Surplus replicas start draining, and drained ones are removed.
+    pub async fn autoscale(&self, nodes: &EdgeNodeManager) -> Vec<ScalingEvent> {
+        let online = nodes.get_online_nodes().await;
+        let placed = self.placed.read().await;
+        let mut scaled = self.scaled.write().await;
+        let mut events = Vec::new();
+        let now = Utc::now();
+
+        // Remove replicas whose drain has run out
+        for w in scaled.values_mut() {
+            w.replicas.retain(|r| match r.state {
+                ReplicaState::Draining { until } if until <= now => {
+                    events.push(ScalingEvent::ReplicaRemoved {
+                        workload_id: r.workload_id,
+                        replica_id: r.id,
+                        node_id: r.node_id,
+                    });
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        // CPU and memory already reserved on each node by any replica or
+        // placed workload
+        let mut usage = node_usage(&placed, &scaled);
+        drop(placed);
+
+        for w in scaled.values_mut() {
+            let running = w.replicas.iter().filter(|r| r.is_running()).count() as u32;
+            let desired = match (w.workload.scaling_metric, w.load) {
+                (Some(metric), Some(load)) if running > 0 => metric.desired_replicas(load, running),
+                _ => running,
+            }
+            .clamp(w.workload.min_replicas, w.workload.max_replicas);
+
+            if desired > running {
+                self.scale_up(w, desired - running, &online, &mut usage, &mut events);
+            } else if desired < running {
+                self.scale_down(w, running - desired, &online, now, &mut events);
+            }
+        }
+
+        events
+    }
+
+    fn scale_up(
+        &self,
+        w: &mut ScaledWorkload,
+        mut needed: u32,
+        online: &[EdgeNode],
+        usage: &mut NodeUsage,
+        events: &mut Vec<ScalingEvent>,
+    ) {
+        let workload_id = w.workload.id;
+
+        // Draining replicas still hold their reservation, so reuse them first
+        for r in w.replicas.iter_mut().filter(|r| !r.is_running()) {
+            if needed == 0 {
+                break;
+            }
+            r.state = ReplicaState::Running;
+            events.push(ScalingEvent::DrainCancelled { workload_id, replica_id: r.id });
+            needed -= 1;
+        }
+
+        while needed > 0 {
+            let Some(node) = self.pick_node(&w.workload, &w.replicas, online, usage) else {
+                let running = w.replicas.iter().filter(|r| r.is_running()).count() as u32;
+                tracing::warn!("No edge node has room for another replica of {}", w.workload.name);
+                events.push(ScalingEvent::Unschedulable {
+                    workload_id,
+                    desired: running + needed,
+                    running,
+                });
+                return;
+            };
+
+            usage.add(node.id, &w.workload);
+
+            let replica = WorkloadReplica {
+                id: Uuid::new_v4(),
+                workload_id,
+                node_id: node.id,
+                state: ReplicaState::Running,
+                created_at: Utc::now(),
+            };
+            tracing::info!("Scaling up {}: replica {} on {}", w.workload.name, replica.id, node.name);
+            events.push(ScalingEvent::ReplicaAdded { workload_id, replica_id: replica.id, node_id: node.id });
+            w.replicas.push(replica);
+            needed -= 1;
+        }
+    }
+
+    fn scale_down(
+        &self,
+        w: &mut ScaledWorkload,
+        surplus: u32,
+        online: &[EdgeNode],
+        now: DateTime<Utc>,
+        events: &mut Vec<ScalingEvent>,
+    ) {
+        // Drain replicas on unavailable or busiest nodes first, newest first
+        let load = |node_id: &Uuid| {
+            online.iter()
+                .find(|n| n.id == *node_id)
+                .map_or(f64::INFINITY, |n| n.cpu_usage_percent + n.memory_usage_percent)
+        };
+        let mut candidates: Vec<usize> = (0..w.replicas.len()).filter(|&i| w.replicas[i].is_running()).collect();
+        candidates.sort_by(|&a, &b| {
+            let (ra, rb) = (&w.replicas[a], &w.replicas[b]);
+            load(&rb.node_id).total_cmp(&load(&ra.node_id))
+                .then(rb.created_at.cmp(&ra.created_at))
+        });
+
+        let until = now + chrono::Duration::from_std(self.drain_timeout).unwrap_or_default();
+        for i in candidates.into_iter().take(surplus as usize) {
+            let replica = &mut w.replicas[i];
+            replica.state = ReplicaState::Draining { until };
+            tracing::info!("Scaling down {}: draining replica {}", w.workload.name, replica.id);
+            events.push(ScalingEvent::DrainStarted { workload_id: w.workload.id, replica_id: replica.id });
+        }
+    }
+
+    /// Choose a node for a new replica according to the scheduling policy
+    fn pick_node<'a>(
+        &self,
+        workload: &EdgeWorkload,
+        replicas: &[WorkloadReplica],
+        online: &'a [EdgeNode],
+        usage: &NodeUsage,
+    ) -> Option<&'a EdgeNode> {
+        let reserved = &usage.reserved;
+        let free = |node: &EdgeNode| {
+            let (cpu, memory) = reserved.get(&node.id).copied().unwrap_or_default();
+            (
+                node.capabilities.cpu_cores as f64 - cpu,
+                node.capabilities.memory_gb as f64 - memory,
+            )
+        };
+
+        // Reported usage lags behind placements, so count reservations too
+        let projected_load = |node: &EdgeNode| {
+            let (cpu, memory) = reserved.get(&node.id).copied().unwrap_or_default();
+            node.cpu_usage_percent + node.memory_usage_percent
+                + cpu / node.capabilities.cpu_cores.max(1) as f64 * 100.0
+                + memory / node.capabilities.memory_gb.max(1) as f64 * 100.0
+        };
+
+        let candidates = online.iter().filter(|n| workload.fits_on(n) && usage.rejection(workload, n).is_none());
+
+        match self.policy {
+            SchedulingPolicy::LeastLoaded => {
+                candidates.min_by(|a, b| projected_load(a).total_cmp(&projected_load(b)))
+            }
+            SchedulingPolicy::ResourceAware => candidates.max_by(|a, b| {
+                let (cpu_a, mem_a) = free(a);
+                let (cpu_b, mem_b) = free(b);
+                (cpu_a / a.capabilities.cpu_cores.max(1) as f64 + mem_a / a.capabilities.memory_gb.max(1) as f64)
+                    .total_cmp(&(cpu_b / b.capabilities.cpu_cores.max(1) as f64 + mem_b / b.capabilities.memory_gb.max(1) as f64))
+            }),
+            SchedulingPolicy::Latency => {
+                // Stay close to the first replica, so clients see similar latency
+                let anchor = replicas.first()
+                    .and_then(|r| online.iter().find(|n| n.id == r.node_id))
+                    .map(|n| n.location);
+                candidates.min_by(|a, b| match anchor {
+                    Some(origin) => distance(origin, a.location).total_cmp(&distance(origin, b.location)),
+                    None => projected_load(a).total_cmp(&projected_load(b)),
+                })
+            }
+        }
+    }
+
+    /// Run [`autoscale`](Self::autoscale) every `interval` until aborted
+    pub fn spawn_autoscaler(self: Arc<Self>, nodes: Arc<EdgeNodeManager>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for event in self.autoscale(&nodes).await {
+                    tracing::debug!("Autoscaler: {:?}", event);
+                }
+            }
+        })
+    }
+}
+
+/// Squared distance between two (lat, lon) points; only used for ordering
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,4 +678,221 @@ mod tests {
         assert!(scheduler.unschedule_workload(&workload.id).await);
         assert!(scheduler.get_placement(&workload.id).await.is_none());
     }
+
+    fn node(name: &str, cores: u32, location: (f64, f64)) -> EdgeNode {
+        use crate::edge_node::NodeCapabilities;
+
+        EdgeNode::new(name.to_string(), location, NodeCapabilities {
+            cpu_cores: cores,
+            memory_gb: 32,
+            storage_gb: 500,
+            gpu_available: false,
+            supports_5g: false,
+        })
+    }
+
+    fn running(replicas: &[WorkloadReplica]) -> usize {
+        replicas.iter().filter(|r| r.is_running()).count()
+    }
+
+    #[tokio::test]
+    async fn test_autoscale_follows_load_between_bounds() {
+        let nodes = EdgeNodeManager::new();
+        nodes.register_node(node("edge-1", 8, (0.0, 0.0))).await;
+        nodes.register_node(node("edge-2", 8, (1.0, 1.0))).await;
+
+        let scheduler = WorkloadScheduler::new(SchedulingPolicy::LeastLoaded)
+            .with_drain_timeout(Duration::from_secs(3600));
+        let workload = EdgeWorkload::new("inference".to_string(), 2.0, 4.0)
+            .with_autoscaling(1, 4, ScalingMetric::Cpu { target_percent: 50.0 });
+        let id = workload.id;
+        scheduler.enable_autoscaling(workload).await;
+
+        scheduler.autoscale(&nodes).await;
+        assert_eq!(running(&scheduler.get_replicas(&id).await), 1);
+
+        // Rising load: 1 replica at 90% wants 2, then 2 at 100% wants 4
+        scheduler.report_load(&id, 90.0).await;
+        scheduler.autoscale(&nodes).await;
+        assert_eq!(running(&scheduler.get_replicas(&id).await), 2);
+
+        scheduler.report_load(&id, 100.0).await;
+        scheduler.autoscale(&nodes).await;
+        assert_eq!(running(&scheduler.get_replicas(&id).await), 4);
+
+        // Still overloaded, but capped at max
+        scheduler.report_load(&id, 100.0).await;
+        let events = scheduler.autoscale(&nodes).await;
+        assert!(events.is_empty());
+        let replicas = scheduler.get_replicas(&id).await;
+        assert_eq!(running(&replicas), 4);
+        // Spread across both nodes
+        assert!(replicas.iter().any(|r| r.node_id != replicas[0].node_id));
+
+        // Falling load drains down to min, gracefully
+        scheduler.report_load(&id, 5.0).await;
+        let events = scheduler.autoscale(&nodes).await;
+        assert_eq!(events.iter().filter(|e| matches!(e, ScalingEvent::DrainStarted { .. })).count(), 3);
+        let replicas = scheduler.get_replicas(&id).await;
+        assert_eq!(running(&replicas), 1);
+        assert_eq!(replicas.len(), 4);
+
+        for r in replicas.iter().filter(|r| !r.is_running()) {
+            assert!(scheduler.complete_drain(&r.id).await);
+        }
+        assert_eq!(scheduler.get_replicas(&id).await.len(), 1);
+
+        scheduler.report_load(&id, 1.0).await;
+        scheduler.autoscale(&nodes).await;
+        assert_eq!(running(&scheduler.get_replicas(&id).await), 1);
+    }
+
+    #[tokio::test]
+    async fn test_autoscale_respects_capacity_and_drain_timeout() {
+        let nodes = EdgeNodeManager::new();
+        nodes.register_node(node("small", 3, (0.0, 0.0))).await;
+        nodes.register_node(node("tiny", 1, (0.0, 0.0))).await;
+
+        let scheduler = WorkloadScheduler::new(SchedulingPolicy::ResourceAware)
+            .with_drain_timeout(Duration::ZERO);
+        let workload = EdgeWorkload::new("queue-worker".to_string(), 1.5, 1.0)
+            .with_autoscaling(1, 5, ScalingMetric::QueueDepth { target_per_replica: 10.0 });
+        let id = workload.id;
+        scheduler.enable_autoscaling(workload).await;
+        scheduler.autoscale(&nodes).await;
+
+        // 100 queued items want 5 replicas, but only "small" fits two
+        scheduler.report_load(&id, 100.0).await;
+        let events = scheduler.autoscale(&nodes).await;
+        assert!(events.contains(&ScalingEvent::Unschedulable { workload_id: id, desired: 5, running: 2 }));
+        assert_eq!(running(&scheduler.get_replicas(&id).await), 2);
+
+        // With no drain time, surplus replicas are gone on the next pass
+        scheduler.report_load(&id, 0.0).await;
+        scheduler.autoscale(&nodes).await;
+        assert_eq!(scheduler.get_replicas(&id).await.len(), 2);
+        let events = scheduler.autoscale(&nodes).await;
+        assert!(matches!(events[0], ScalingEvent::ReplicaRemoved { .. }));
+        assert_eq!(scheduler.get_replicas(&id).await.len(), 1);
+    }
+
+    fn constrained(name: &str, constraints: PlacementConstraints) -> EdgeWorkload {
+        EdgeWorkload::new(name.to_string(), 2.0, 4.0).with_constraints(constraints)
+    }
+
+    #[tokio::test]
+    async fn test_place_workload_explains_rejections() {
+        use crate::placement::SiteLatency;
+
+        let nodes = EdgeNodeManager::new();
+        let mut ids = HashMap::new();
+        for (name, gpu, region, latency) in [
+            ("gpu-east", true, "east", 5.0),
+            ("gpu-east-2", true, "east", 12.0),
+            ("cpu-east", false, "east", 3.0),
+            ("gpu-west", true, "west", 4.0),
+            ("gpu-far", true, "east", 40.0),
+        ] {
+            let mut n = node(name, 16, (0.0, 0.0)).with_region(region);
+            n.capabilities.gpu_available = gpu;
+            n.site_latency_ms.insert("hq".to_string(), latency);
+            ids.insert(name, nodes.register_node(n).await);
+        }
+
+        let scheduler = WorkloadScheduler::new(SchedulingPolicy::Latency);
+        let constraints = PlacementConstraints {
+            requires_gpu: true,
+            regions: ["east".to_string()].into(),
+            max_latency_to: Some(SiteLatency { site: "hq".to_string(), max_ms: 20.0 }),
+            ..Default::default()
+        };
+        let vision = constrained("vision", constraints.clone());
+        let placement = scheduler.place_workload(vision.clone(), &nodes).await.unwrap();
+        assert_eq!(placement.node_id, ids["gpu-east"]);
+
+        let explanation = scheduler.explain_placement(&vision.id).await.unwrap();
+        assert_eq!(explanation.chosen, Some(ids["gpu-east"]));
+        let score = explanation.chosen_score().unwrap();
+        assert_eq!(score.latency, Some(75.0));
+        assert!(matches!(explanation.nodes[1].verdict, NodeVerdict::Outscored(_)));
+        assert_eq!(explanation.rejection(&ids["cpu-east"]), Some("no GPU"));
+        assert!(explanation.rejection(&ids["gpu-west"]).unwrap().starts_with("region west"));
+        assert_eq!(explanation.rejection(&ids["gpu-far"]), Some("40.0 ms to hq exceeds 20.0 ms"));
+
+        // Anti-affinity pushes the replica set member onto the other GPU node
+        let mut spread = constraints;
+        spread.anti_affinity.insert(vision.id);
+        let backup = constrained("vision-backup", spread);
+        let placement = scheduler.place_workload(backup.clone(), &nodes).await.unwrap();
+        assert_eq!(placement.node_id, ids["gpu-east-2"]);
+        let explanation = scheduler.explain_placement(&backup.id).await.unwrap();
+        assert_eq!(explanation.rejection(&ids["gpu-east"]), Some("runs anti-affine workload vision"));
+
+        let mut third = PlacementConstraints { requires_gpu: true, ..Default::default() };
+        third.regions.insert("north".to_string());
+        let err = scheduler.place_workload(constrained("nowhere", third), &nodes).await.unwrap_err();
+        assert!(err.to_string().contains("gpu-east: region east"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_moves_workloads_off_failed_node() {
+        let nodes = EdgeNodeManager::new().with_heartbeat_timeout(Duration::from_secs(30));
+        let mut edge1 = node("edge-1", 16, (0.0, 0.0));
+        edge1.capabilities.gpu_available = true;
+        let edge1_id = nodes.register_node(edge1.clone()).await;
+
+        let scheduler = WorkloadScheduler::new(SchedulingPolicy::LeastLoaded)
+            .with_max_disruption(2)
+            .with_reschedule_deadline(Duration::ZERO);
+        let alpha = constrained("alpha", PlacementConstraints::default());
+        let charlie = constrained("charlie", PlacementConstraints {
+            anti_affinity: [alpha.id].into(),
+            ..Default::default()
+        });
+        let gpu_job = constrained("gpu-job", PlacementConstraints { requires_gpu: true, ..Default::default() });
+        let mut ids = Vec::new();
+        for workload in [alpha.clone(), constrained("bravo", Default::default()), gpu_job.clone()] {
+            ids.push(workload.id);
+            scheduler.place_workload(workload, &nodes).await.unwrap();
+        }
+        assert!(scheduler.place_workload(charlie.clone(), &nodes).await.is_err());
+
+        nodes.register_node(node("edge-2", 16, (1.0, 0.0))).await;
+        nodes.register_node(node("edge-3", 16, (2.0, 0.0))).await;
+        scheduler.place_workload(charlie.clone(), &nodes).await.unwrap();
+        // Force charlie onto edge-1 as if it was placed before the others joined
+        scheduler.placed.write().await.get_mut(&charlie.id).unwrap().node_id = edge1_id;
+
+        edge1.last_heartbeat = Utc::now() - chrono::Duration::seconds(60);
+        nodes.register_node(edge1).await;
+
+        // Budget of two: alpha and bravo move, charlie waits, gpu-job has nowhere to go
+        let events = scheduler.reconcile(&nodes).await;
+        assert_eq!(events[0], RescheduleEvent::NodeFailed { node_id: edge1_id });
+        let rescheduled: Vec<Uuid> = events.iter()
+            .filter_map(|e| match e {
+                RescheduleEvent::Rescheduled { workload_id, from, .. } if *from == edge1_id => Some(*workload_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rescheduled, vec![alpha.id, ids[1]]);
+        let deferred = |events: &[RescheduleEvent], id: Uuid| events.iter()
+            .any(|e| matches!(e, RescheduleEvent::Deferred { workload_id, .. } if *workload_id == id));
+        assert!(deferred(&events, charlie.id));
+        assert!(deferred(&events, gpu_job.id));
+        let missed = events.iter().filter(|e| matches!(e, RescheduleEvent::DeadlineMissed { .. })).count();
+        assert_eq!(missed, 2);
+
+        let events = scheduler.reconcile(&nodes).await;
+        assert!(events.iter().any(|e| matches!(e, RescheduleEvent::Rescheduled { workload_id, .. } if *workload_id == charlie.id)));
+        assert!(!events.iter().any(|e| matches!(e, RescheduleEvent::DeadlineMissed { .. })));
+        let charlie_node = scheduler.get_placement(&charlie.id).await.unwrap().node_id;
+        assert_ne!(charlie_node, scheduler.get_placement(&alpha.id).await.unwrap().node_id);
+        assert_ne!(charlie_node, edge1_id);
+
+        // The GPU node recovers and takes its workload back
+        assert!(nodes.heartbeat(&edge1_id).await);
+        let events = scheduler.reconcile(&nodes).await;
+        assert_eq!(events, vec![RescheduleEvent::Rescheduled { workload_id: gpu_job.id, from: edge1_id, to: edge1_id }]);
+    }
 }
@@ -5,9 +5,42 @@
 //! - Data exfiltration
 //! - Network reconnaissance
 //! - Hardware failures
+//!
+//! The learned baseline can be saved and loaded so a restarted node resumes
+//! scoring against it instead of cold-starting.
+//!
+//! Besides the windowed batch path ([`AnomalyDetector::detect`]) there is an
+//! online path, [`AnomalyDetector::update_and_score`], that keeps an
+//! exponentially weighted mean and variance per feature and scores each
+//! sample in constant time. It keeps learning from what it scores, except
+//! from samples above [`StreamingConfig::learn_threshold`] and while the
+//! detector is [frozen](AnomalyDetector::freeze), and reports how far it has
+//! drifted from a pinned reference baseline.
+//!
+//! Features can optionally be rescaled with a fitted [`FeatureScaler`]
+//! before either path sees them; the scaler is saved with the model.
 
+use crate::scaler::FeatureScaler;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+/// Version of the saved model format
+pub const MODEL_VERSION: u32 = 1;
+
+/// Features the detector scores, in model order
+pub const FEATURES: [&str; 4] = [
+    "bytes_per_second",
+    "packets_per_second",
+    "tcp_syn_ratio",
+    "unique_src_ips",
+];
+
+/// Weight of each feature's z-score; SYN ratio counts double
+const FEATURE_WEIGHTS: [f64; 4] = [1.0, 1.0, 2.0, 1.0];
+
+type FeatureVector = [f64; FEATURES.len()];
 
 /// Traffic metrics for ML model
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,11 +63,172 @@ pub struct AnomalyScore {
     pub reason: String,
 }
 
+impl TrafficMetrics {
+    /// Raw feature values in [`FEATURES`] order, e.g. for fitting a scaler
+    pub fn features(&self) -> FeatureVector {
+        [
+            self.bytes_per_second,
+            self.packets_per_second,
+            self.tcp_syn_ratio,
+            self.unique_src_ips as f64,
+        ]
+    }
+}
+
+/// Samples needed before either path reports scores
+const MIN_SAMPLES: usize = 10;
+
+/// Configuration of the online scoring path
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StreamingConfig {
+    /// Effective number of recent samples the baseline reflects
+    pub window_size: usize,
+    /// EWMA weight of each new sample, i.e. the learning rate; derived from
+    /// `window_size` when unset
+    pub decay: Option<f64>,
+    /// Samples scoring above this are not learned from, so an ongoing
+    /// attack is not absorbed into the baseline
+    #[serde(default)]
+    pub learn_threshold: Option<f64>,
+    /// Samples after one above `learn_threshold` that are not learned from
+    /// either, covering the tail of an attack that scores just below it
+    #[serde(default)]
+    pub protection_window: usize,
+}
+
+impl StreamingConfig {
+    /// Smoothing factor, `2 / (window_size + 1)` unless `decay` is given
+    pub fn alpha(&self) -> f64 {
+        self.decay.unwrap_or(2.0 / (self.window_size as f64 + 1.0))
+    }
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 100,
+            decay: None,
+            learn_threshold: None,
+            protection_window: 0,
+        }
+    }
+}
+
+/// Exponentially weighted per-feature mean and variance
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct EwmaBaseline {
+    mean: FeatureVector,
+    variance: FeatureVector,
+    samples: usize,
+}
+
+impl EwmaBaseline {
+    fn update(&mut self, features: &FeatureVector, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = *features;
+        } else {
+            for ((value, mean), variance) in features.iter().zip(&mut self.mean).zip(&mut self.variance) {
+                let diff = value - *mean;
+                let increment = alpha * diff;
+                *mean += increment;
+                *variance = (1.0 - alpha) * (*variance + diff * increment);
+            }
+        }
+        self.samples += 1;
+    }
+
+    fn stats(&self) -> Vec<FeatureStats> {
+        FEATURES.iter()
+            .enumerate()
+            .map(|(i, feature)| FeatureStats {
+                feature: feature.to_string(),
+                mean: self.mean[i],
+                variance: self.variance[i],
+            })
+            .collect()
+    }
+}
+
+/// Mean and variance of one feature over the baseline window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureStats {
+    pub feature: String,
+    pub mean: f64,
+    pub variance: f64,
+}
+
+/// Distance of one feature's online baseline from the pinned reference
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureDrift {
+    pub feature: String,
+    pub reference_mean: f64,
+    pub current_mean: f64,
+    /// Hellinger distance between the two as normal distributions, 0 to 1
+    pub distance: f64,
+}
+
+/// Drift of the online baseline from the pinned reference
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineDrift {
+    /// Largest per-feature distance, 0 (same) to 1 (disjoint); the value to
+    /// compare against an mlops data-drift threshold
+    pub score: f64,
+    pub features: Vec<FeatureDrift>,
+}
+
+impl BaselineDrift {
+    /// Flat metrics for the mlops registry: `drift_score` plus
+    /// `drift.<feature>` for each feature
+    pub fn to_metrics(&self) -> HashMap<String, f64> {
+        let mut metrics: HashMap<String, f64> = self.features.iter()
+            .map(|f| (format!("drift.{}", f.feature), f.distance))
+            .collect();
+        metrics.insert("drift_score".to_string(), self.score);
+        metrics
+    }
+}
+
+/// Saved detector state
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedModel {
+    version: u32,
+    features: Vec<String>,
+    window_size: usize,
+    threshold: f64,
+    /// Baseline statistics, for inspection; scoring recomputes them
+    stats: Vec<FeatureStats>,
+    /// Baseline window, oldest first, as vectors in `features` order
+    samples: Vec<Vec<f64>>,
+    #[serde(default)]
+    streaming_config: StreamingConfig,
+    /// Online baseline, absent in models saved before it existed
+    #[serde(default)]
+    streaming: Option<EwmaBaseline>,
+    /// Preprocessing the samples above were scaled with
+    #[serde(default)]
+    scaler: Option<FeatureScaler>,
+    #[serde(default)]
+    frozen: bool,
+    /// Samples still to skip after a protected one
+    #[serde(default)]
+    protect_remaining: usize,
+    /// Pinned reference for drift
+    #[serde(default)]
+    reference: Option<Vec<FeatureStats>>,
+}
+
 /// Isolation Forest-based anomaly detector
 pub struct AnomalyDetector {
-    history: VecDeque<TrafficMetrics>,
+    history: VecDeque<FeatureVector>,
     window_size: usize,
     threshold: f64,
+    streaming_config: StreamingConfig,
+    streaming: EwmaBaseline,
+    persistent: bool,
+    scaler: Option<FeatureScaler>,
+    frozen: bool,
+    protect_remaining: usize,
+    reference: Option<Vec<FeatureStats>>,
 }
 
 impl AnomalyDetector {
@@ -43,17 +237,161 @@ impl AnomalyDetector {
             history: VecDeque::new(),
             window_size: 100,
             threshold: 0.7, // Score above 0.7 = anomaly
+            streaming_config: StreamingConfig::default(),
+            streaming: EwmaBaseline::default(),
+            persistent: false,
+            scaler: None,
+            frozen: false,
+            protect_remaining: 0,
+            reference: None,
+        }
+    }
+
+    /// Rescale features with `scaler` before scoring
+    ///
+    /// The scaler must be fitted on [`TrafficMetrics::features`]. Both
+    /// baselines are reset, since they were learned in the old units.
+    pub fn with_scaler(mut self, scaler: FeatureScaler) -> Result<Self> {
+        if scaler.len() != FEATURES.len() {
+            anyhow::bail!(
+                "Scaler has {} features, the anomaly detector uses {}",
+                scaler.len(), FEATURES.len()
+            );
+        }
+        self.scaler = Some(scaler);
+        self.history.clear();
+        self.streaming = EwmaBaseline::default();
+        Ok(self)
+    }
+
+    pub fn scaler(&self) -> Option<&FeatureScaler> {
+        self.scaler.as_ref()
+    }
+
+    /// Features of `metrics` as the model sees them
+    fn features(&self, metrics: &TrafficMetrics) -> FeatureVector {
+        let mut features = metrics.features();
+        if let Some(scaler) = &self.scaler {
+            scaler.apply(&mut features);
+        }
+        features
+    }
+
+    /// Configure the online path; resets its baseline
+    pub fn with_streaming_config(mut self, config: StreamingConfig) -> Self {
+        self.streaming_config = config;
+        self.streaming = EwmaBaseline::default();
+        self.protect_remaining = 0;
+        self
+    }
+
+    /// Stop learning, e.g. while an incident is investigated
+    ///
+    /// Both paths keep scoring against the baselines as they are now.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Resume learning
+    pub fn unfreeze(&mut self) {
+        self.frozen = false;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Mark the current anomaly as persistent
+    ///
+    /// While set, samples the online path scores as anomalous are kept out
+    /// of its baseline, so a sustained shift keeps being reported instead of
+    /// becoming the new normal.
+    pub fn set_persistent(&mut self, persistent: bool) {
+        self.persistent = persistent;
+    }
+
+    /// Score a sample against the online baseline, then fold it in
+    ///
+    /// O(1) per sample: nothing is recomputed over a window. A sustained
+    /// shift is gradually absorbed, so its scores decay unless it scores
+    /// above [`StreamingConfig::learn_threshold`] or
+    /// [`set_persistent`](Self::set_persistent) is on.
+    pub fn update_and_score(&mut self, sample: TrafficMetrics) -> AnomalyScore {
+        let features = self.features(&sample);
+
+        let result = if self.streaming.samples < MIN_SAMPLES {
+            AnomalyScore {
+                score: 0.0,
+                is_anomaly: false,
+                reason: "Insufficient data".to_string(),
+            }
+        } else {
+            self.score(&sample, &features, &self.streaming.stats())
+        };
+
+        let protected = (self.persistent && result.is_anomaly)
+            || self.streaming_config.learn_threshold.is_some_and(|t| result.score > t);
+        if protected {
+            self.protect_remaining = self.streaming_config.protection_window;
+        } else if self.protect_remaining > 0 {
+            self.protect_remaining -= 1;
+        } else if !self.frozen {
+            self.streaming.update(&features, self.streaming_config.alpha());
         }
+
+        result
+    }
+
+    /// Whether the online path is currently refusing to learn because of a
+    /// recent high-scoring sample
+    pub fn is_protecting(&self) -> bool {
+        self.protect_remaining > 0
+    }
+
+    /// Pin the current online baseline as the reference for
+    /// [`drift`](Self::drift), e.g. after a model is approved
+    pub fn pin_reference(&mut self) {
+        self.reference = Some(self.streaming.stats());
+    }
+
+    pub fn reference(&self) -> Option<&[FeatureStats]> {
+        self.reference.as_deref()
+    }
+
+    /// How far the online baseline has moved from the pinned reference
+    pub fn drift(&self) -> Option<BaselineDrift> {
+        let reference = self.reference.as_ref()?;
+        let features: Vec<FeatureDrift> = reference.iter()
+            .zip(self.streaming.stats())
+            .map(|(reference, current)| FeatureDrift {
+                feature: current.feature.clone(),
+                reference_mean: reference.mean,
+                current_mean: current.mean,
+                distance: hellinger_distance(reference, &current),
+            })
+            .collect();
+        let score = features.iter().map(|f| f.distance).fold(0.0, f64::max);
+        Some(BaselineDrift { score, features })
+    }
+
+    /// Current mean and variance of the online baseline
+    pub fn streaming_baseline(&self) -> Vec<FeatureStats> {
+        self.streaming.stats()
     }
 
     /// Add metrics and check for anomalies
+    ///
+    /// While frozen the sample is scored without joining the window.
     pub fn detect(&mut self, metrics: TrafficMetrics) -> AnomalyScore {
-        self.history.push_back(metrics.clone());
-        if self.history.len() > self.window_size {
-            self.history.pop_front();
+        let features = self.features(&metrics);
+        if !self.frozen {
+            self.history.push_back(features);
+            if self.history.len() > self.window_size {
+                self.history.pop_front();
+            }
         }
 
-        if self.history.len() < 10 {
+        if self.history.len() < MIN_SAMPLES {
             return AnomalyScore {
                 score: 0.0,
                 is_anomaly: false,
@@ -61,11 +399,15 @@ impl AnomalyDetector {
             };
         }
 
-        let score = self.calculate_anomaly_score(&metrics);
+        self.score(&metrics, &features, &self.baseline())
+    }
+
+    fn score(&self, metrics: &TrafficMetrics, features: &FeatureVector, baseline: &[FeatureStats]) -> AnomalyScore {
+        let score = calculate_anomaly_score(features, baseline);
         let is_anomaly = score > self.threshold;
 
         let reason = if is_anomaly {
-            self.identify_anomaly_type(&metrics)
+            self.identify_anomaly_type(metrics)
         } else {
             "Normal".to_string()
         };
@@ -77,36 +419,108 @@ impl AnomalyDetector {
         }
     }
 
-    fn calculate_anomaly_score(&self, metrics: &TrafficMetrics) -> f64 {
-        // Calculate z-scores for each metric
-        let bps_zscore = self.z_score(metrics.bytes_per_second, |m| m.bytes_per_second);
-        let pps_zscore = self.z_score(metrics.packets_per_second, |m| m.packets_per_second);
-        let syn_zscore = self.z_score(metrics.tcp_syn_ratio, |m| m.tcp_syn_ratio);
-        let unique_ips_zscore = self.z_score(metrics.unique_src_ips as f64, |m| m.unique_src_ips as f64);
+    /// Mean and variance of each feature over the current window
+    ///
+    /// All zero while the window is empty, e.g. when only the online path is used.
+    pub fn baseline(&self) -> Vec<FeatureStats> {
+        let count = self.history.len().max(1) as f64;
+        FEATURES.iter()
+            .enumerate()
+            .map(|(i, feature)| {
+                let mean = self.history.iter().map(|s| s[i]).sum::<f64>() / count;
+                let variance = self.history.iter()
+                    .map(|s| (s[i] - mean).powi(2))
+                    .sum::<f64>() / count;
+                FeatureStats { feature: feature.to_string(), mean, variance }
+            })
+            .collect()
+    }
 
-        // Combine z-scores (simplified Isolation Forest approximation)
-        let combined = (bps_zscore.abs() + pps_zscore.abs() + syn_zscore.abs() * 2.0 + unique_ips_zscore.abs()) / 5.0;
+    /// Save the learned baseline to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let model = SavedModel {
+            version: MODEL_VERSION,
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+            window_size: self.window_size,
+            threshold: self.threshold,
+            stats: self.baseline(),
+            samples: self.history.iter().map(|s| s.to_vec()).collect(),
+            streaming_config: self.streaming_config,
+            streaming: Some(self.streaming.clone()),
+            scaler: self.scaler.clone(),
+            frozen: self.frozen,
+            protect_remaining: self.protect_remaining,
+            reference: self.reference.clone(),
+        };
 
-        // Normalize to 0-1
-        (combined / 10.0).min(1.0)
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&model)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
     }
 
-    fn z_score<F>(&self, value: f64, extractor: F) -> f64
-    where
-        F: Fn(&TrafficMetrics) -> f64,
-    {
-        let values: Vec<f64> = self.history.iter().map(|m| extractor(m)).collect();
-        let mean = values.iter().sum::<f64>() / values.len() as f64;
-        let variance = values.iter()
-            .map(|v| (v - mean).powi(2))
-            .sum::<f64>() / values.len() as f64;
-        let std_dev = variance.sqrt();
+    /// Load a baseline saved by [`save`](Self::save)
+    ///
+    /// Fails for models of another format version or feature set.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let model: SavedModel = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse model {}", path.display()))?;
 
-        if std_dev == 0.0 {
-            0.0
-        } else {
-            (value - mean) / std_dev
+        if model.version != MODEL_VERSION {
+            anyhow::bail!(
+                "Unsupported anomaly model version {} (expected {})",
+                model.version, MODEL_VERSION
+            );
+        }
+        if model.features != FEATURES {
+            anyhow::bail!(
+                "Anomaly model was trained on features {:?}, expected {:?}",
+                model.features, FEATURES
+            );
+        }
+        if model.window_size == 0 {
+            anyhow::bail!("Anomaly model has an empty window");
         }
+        if let Some(scaler) = &model.scaler {
+            if scaler.len() != FEATURES.len() {
+                anyhow::bail!("Anomaly model scaler has {} features, expected {}", scaler.len(), FEATURES.len());
+            }
+        }
+        if let Some(reference) = &model.reference {
+            if reference.iter().map(|s| s.feature.as_str()).ne(FEATURES) {
+                anyhow::bail!("Anomaly model drift reference does not match features {:?}", FEATURES);
+            }
+        }
+
+        let mut history = VecDeque::with_capacity(model.window_size);
+        for (i, sample) in model.samples.iter().enumerate() {
+            let sample: FeatureVector = sample.as_slice().try_into().map_err(|_| {
+                anyhow::anyhow!("Sample {} has {} features, expected {}", i, sample.len(), FEATURES.len())
+            })?;
+            history.push_back(sample);
+        }
+        while history.len() > model.window_size {
+            history.pop_front();
+        }
+
+        Ok(Self {
+            history,
+            window_size: model.window_size,
+            threshold: model.threshold,
+            streaming_config: model.streaming_config,
+            streaming: model.streaming.unwrap_or_default(),
+            persistent: false,
+            scaler: model.scaler,
+            frozen: model.frozen,
+            protect_remaining: model.protect_remaining,
+            reference: model.reference,
+        })
     }
 
     fn identify_anomaly_type(&self, metrics: &TrafficMetrics) -> String {
@@ -124,6 +538,38 @@ impl AnomalyDetector {
     }
 }
 
+fn calculate_anomaly_score(features: &FeatureVector, baseline: &[FeatureStats]) -> f64 {
+    // Combine weighted z-scores (simplified Isolation Forest approximation)
+    let combined = features.iter()
+        .zip(baseline)
+        .zip(FEATURE_WEIGHTS)
+        .map(|((value, stats), weight)| z_score(*value, stats).abs() * weight)
+        .sum::<f64>() / FEATURE_WEIGHTS.iter().sum::<f64>();
+
+    // Normalize to 0-1
+    (combined / 10.0).min(1.0)
+}
+
+fn z_score(value: f64, stats: &FeatureStats) -> f64 {
+    let std_dev = stats.variance.sqrt();
+    if std_dev == 0.0 {
+        0.0
+    } else {
+        (value - stats.mean) / std_dev
+    }
+}
+
+/// Hellinger distance between two features taken as normal distributions
+fn hellinger_distance(a: &FeatureStats, b: &FeatureStats) -> f64 {
+    let variance_sum = a.variance + b.variance;
+    if variance_sum == 0.0 {
+        return if a.mean == b.mean { 0.0 } else { 1.0 };
+    }
+    let spread = (2.0 * (a.variance * b.variance).sqrt() / variance_sum).sqrt();
+    let shift = (-(a.mean - b.mean).powi(2) / (4.0 * variance_sum)).exp();
+    (1.0 - spread * shift).max(0.0).sqrt()
+}
+
 impl Default for AnomalyDetector {
     fn default() -> Self {
         Self::new()
@@ -204,4 +650,265 @@ mod tests {
         // Should detect anomaly or at least have elevated score
         assert!(result.score > 0.3 || result.reason.contains("SYN flood"));
     }
+
+    fn traffic(i: usize) -> TrafficMetrics {
+        let jitter = (i % 7) as f64;
+        TrafficMetrics {
+            bytes_per_second: 1_000_000.0 + jitter * 10_000.0,
+            packets_per_second: 1_000.0 + jitter * 15.0,
+            unique_src_ips: 10 + i % 3,
+            unique_dst_ips: 10,
+            avg_packet_size: 1000.0,
+            tcp_syn_ratio: 0.1 + jitter * 0.01,
+            udp_ratio: 0.2,
+            icmp_ratio: 0.01,
+        }
+    }
+
+    #[test]
+    fn test_save_load_resumes_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anomaly.json");
+
+        let mut detector = AnomalyDetector::new();
+        for i in 0..150 {
+            detector.detect(traffic(i));
+        }
+        detector.save(&path).unwrap();
+
+        let mut restored = AnomalyDetector::load(&path).unwrap();
+        assert_eq!(restored.baseline(), detector.baseline());
+
+        // A restarted node scores identical input identically, from the first sample
+        let mut inputs: Vec<_> = (150..170).map(traffic).collect();
+        inputs.push(TrafficMetrics { tcp_syn_ratio: 0.95, unique_src_ips: 5000, ..traffic(0) });
+        for metrics in inputs {
+            let before = detector.detect(metrics.clone());
+            let after = restored.detect(metrics);
+            assert_eq!(before.score, after.score);
+            assert_eq!(before.is_anomaly, after.is_anomaly);
+            assert_ne!(after.reason, "Insufficient data");
+        }
+    }
+
+    #[test]
+    fn test_streaming_baseline_absorbs_sustained_shift() {
+        let config = StreamingConfig { window_size: 20, ..Default::default() };
+        let shifted = |i| TrafficMetrics {
+            bytes_per_second: traffic(i).bytes_per_second * 3.0,
+            tcp_syn_ratio: traffic(i).tcp_syn_ratio + 0.3,
+            ..traffic(i)
+        };
+
+        let run = |persistent: bool| {
+            let mut detector = AnomalyDetector::new().with_streaming_config(config);
+            for i in 0..50 {
+                assert!(!detector.update_and_score(traffic(i)).is_anomaly);
+            }
+            detector.set_persistent(persistent);
+            (50..150).map(|i| detector.update_and_score(shifted(i))).collect::<Vec<_>>()
+        };
+
+        // The shift is flagged at first, then becomes the new normal
+        let scores = run(false);
+        assert!(scores[0].is_anomaly);
+        assert_eq!(scores[0].reason, "Unusual traffic pattern");
+        let last = scores.last().unwrap();
+        assert!(!last.is_anomaly);
+        assert!(last.score < scores[0].score / 4.0, "{} vs {}", last.score, scores[0].score);
+
+        // Flagged persistent, it keeps being reported
+        let scores = run(true);
+        assert!(scores.iter().all(|s| s.is_anomaly));
+        assert_eq!(scores.last().unwrap().score, scores[0].score);
+    }
+
+    #[test]
+    fn test_streaming_decay_and_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anomaly.json");
+
+        // A heavier decay forgets the old mean faster
+        let slow = StreamingConfig { window_size: 50, ..Default::default() };
+        let fast = StreamingConfig { window_size: 50, decay: Some(0.5), ..Default::default() };
+        let mut detectors = [slow, fast].map(|c| AnomalyDetector::new().with_streaming_config(c));
+        for detector in &mut detectors {
+            for i in 0..30 {
+                detector.update_and_score(traffic(i));
+            }
+            detector.update_and_score(TrafficMetrics { bytes_per_second: 5_000_000.0, ..traffic(0) });
+        }
+        let mean = |d: &AnomalyDetector| d.streaming_baseline()[0].mean;
+        assert!(mean(&detectors[1]) > mean(&detectors[0]));
+
+        let [detector, _] = detectors;
+        detector.save(&path).unwrap();
+        let restored = AnomalyDetector::load(&path).unwrap();
+        assert_eq!(restored.streaming_baseline(), detector.streaming_baseline());
+        assert_eq!(restored.streaming_config, slow);
+    }
+
+    #[test]
+    fn test_load_rejects_incompatible_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anomaly.json");
+        let mut detector = AnomalyDetector::new();
+        for i in 0..20 {
+            detector.detect(traffic(i));
+        }
+        detector.save(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+
+        let mut other_features = saved.clone();
+        other_features["features"][3] = "unique_dst_ips".into();
+        std::fs::write(&path, other_features.to_string()).unwrap();
+        let err = AnomalyDetector::load(&path).err().unwrap();
+        assert!(err.to_string().contains("trained on features"), "{}", err);
+
+        let mut newer = saved.clone();
+        newer["version"] = (MODEL_VERSION + 1).into();
+        std::fs::write(&path, newer.to_string()).unwrap();
+        assert!(AnomalyDetector::load(&path).err().unwrap().to_string().contains("version"));
+
+        let mut short = saved;
+        short["samples"][0] = serde_json::json!([1.0, 2.0]);
+        std::fs::write(&path, short.to_string()).unwrap();
+        assert!(AnomalyDetector::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_scaler_preprocessing_is_saved() {
+        use crate::scaler::{FeatureScaler, ScalingMethod};
+
+        let scaler = FeatureScaler::fit(ScalingMethod::ZScore, (0..50).map(|i| traffic(i).features())).unwrap();
+        let mut scaled = AnomalyDetector::new().with_scaler(scaler.clone()).unwrap();
+        let mut raw = AnomalyDetector::new();
+
+        for i in 0..40 {
+            scaled.detect(traffic(i));
+            raw.detect(traffic(i));
+        }
+        // Scaled features sit near zero instead of around a million
+        assert!(scaled.baseline()[0].mean.abs() < 1.0);
+
+        // Z-scores are unaffected by an affine rescale, so the verdict matches
+        let mut spike = traffic(40);
+        spike.tcp_syn_ratio = 0.9;
+        let a = scaled.detect(spike.clone());
+        let b = raw.detect(spike);
+        assert!((a.score - b.score).abs() < 1e-9);
+        assert_eq!(a.is_anomaly, b.is_anomaly);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anomaly.json");
+        scaled.save(&path).unwrap();
+        let restored = AnomalyDetector::load(&path).unwrap();
+        assert_eq!(restored.scaler(), Some(&scaler));
+        assert_eq!(restored.baseline(), scaled.baseline());
+
+        let wrong = FeatureScaler::fit(ScalingMethod::MinMax, [[1.0, 2.0]]).unwrap();
+        assert!(AnomalyDetector::new().with_scaler(wrong).is_err());
+    }
+
+    #[test]
+    fn test_online_learning_protects_baseline_and_freezes() {
+        let config = StreamingConfig {
+            window_size: 20,
+            learn_threshold: Some(0.5),
+            protection_window: 5,
+            ..Default::default()
+        };
+        let attack = |i| TrafficMetrics { tcp_syn_ratio: 0.9, unique_src_ips: 4000, ..traffic(i) };
+
+        let mut detector = AnomalyDetector::new().with_streaming_config(config);
+        for i in 0..50 {
+            detector.update_and_score(traffic(i));
+        }
+        let learned = detector.streaming_baseline();
+
+        // A sustained attack keeps scoring high instead of becoming normal
+        let scores: Vec<_> = (50..150).map(|i| detector.update_and_score(attack(i))).collect();
+        assert!(scores.iter().all(|s| s.is_anomaly));
+        assert_eq!(scores.last().unwrap().score, scores[0].score);
+        assert_eq!(detector.streaming_baseline(), learned);
+
+        // The protection window also keeps the first normal samples out
+        assert!(detector.is_protecting());
+        for i in 150..155 {
+            detector.update_and_score(traffic(i));
+        }
+        assert!(!detector.is_protecting());
+        assert_eq!(detector.streaming_baseline(), learned);
+
+        // Normal traffic is learned from again, and a new branch's gentler
+        // shift is absorbed
+        let branch = |i| TrafficMetrics { bytes_per_second: traffic(i).bytes_per_second * 1.03, ..traffic(i) };
+        for i in 155..255 {
+            detector.update_and_score(branch(i));
+        }
+        assert!(detector.streaming_baseline()[0].mean > learned[0].mean * 1.02);
+
+        // Frozen, neither path learns
+        detector.freeze();
+        let online = detector.streaming_baseline();
+        for i in 0..30 {
+            detector.update_and_score(branch(i));
+            detector.detect(traffic(i));
+        }
+        assert_eq!(detector.streaming_baseline(), online);
+        assert_eq!(detector.detect(traffic(0)).reason, "Insufficient data");
+        detector.unfreeze();
+        detector.update_and_score(traffic(0));
+        assert_ne!(detector.streaming_baseline(), online);
+    }
+
+    #[test]
+    fn test_drift_from_pinned_reference_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anomaly.json");
+        let config = StreamingConfig { window_size: 30, learn_threshold: Some(0.7), ..Default::default() };
+
+        let mut detector = AnomalyDetector::new().with_streaming_config(config);
+        assert!(detector.drift().is_none());
+        for i in 0..100 {
+            detector.update_and_score(traffic(i));
+        }
+        detector.pin_reference();
+        for i in 100..200 {
+            detector.update_and_score(traffic(i));
+        }
+        let steady = detector.drift().unwrap();
+        assert!(steady.score < 0.2, "{:?}", steady);
+
+        // Onboarded branches raise traffic a little at a time
+        for i in 200..500 {
+            let growth = 1.0 + (i - 200) as f64 * 0.002;
+            detector.update_and_score(TrafficMetrics {
+                bytes_per_second: traffic(i).bytes_per_second * growth,
+                packets_per_second: traffic(i).packets_per_second * growth,
+                ..traffic(i)
+            });
+        }
+        let drift = detector.drift().unwrap();
+        assert!(drift.score > 0.9, "{:?}", drift);
+        assert_eq!(drift.score, drift.features[0].distance.max(drift.features[1].distance));
+        assert!(drift.features[2].distance < 0.2);
+        let metrics = drift.to_metrics();
+        assert_eq!(metrics["drift_score"], drift.score);
+        assert_eq!(metrics["drift.bytes_per_second"], drift.features[0].distance);
+
+        detector.freeze();
+        detector.save(&path).unwrap();
+        let mut restored = AnomalyDetector::load(&path).unwrap();
+        assert!(restored.is_frozen());
+        assert_eq!(restored.drift(), Some(drift));
+        assert_eq!(restored.streaming_baseline(), detector.streaming_baseline());
+
+        restored.unfreeze();
+        let next = traffic(500);
+        assert_eq!(restored.update_and_score(next.clone()).score, {
+            detector.unfreeze();
+            detector.update_and_score(next).score
+        });
+    }
 }
@@ -2,8 +2,52 @@
 //!
 //! Classifies encrypted traffic (HTTPS, TLS, VPN) without decryption
 //! Uses Random Forest classifier based on statistical features
-
+//!
+//! Sites can also train their own classes from example flows. Each custom
+//! class is a centroid with per-feature spread; a flow is assigned to the
+//! nearest one when it falls close enough, ahead of the built-in trees.
+//! Custom class features can be rescaled with a fitted [`FeatureScaler`].
+//!
+//! The built-in trees can be replaced at runtime by a model bundle: a
+//! directory holding `metadata.json` (version, feature schema hash and
+//! class labels) and `weights.json` (a softmax layer over the class
+//! features), as with the threat classifier's bundles. A bundle may name
+//! classes this crate has never heard of; they come through as
+//! [`TrafficClass::Other`].
+
+use crate::scaler::FeatureScaler;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Saved custom class format version
+pub const DPI_MODEL_VERSION: u32 = 1;
+
+/// Features custom classes are trained on, in model order
+pub const CLASS_FEATURES: [&str; 5] = [
+    "log_avg_packet_size",
+    "log_packet_size_stddev",
+    "log_avg_inter_arrival_ms",
+    "log_burst_count",
+    "log_tls_handshake_size",
+];
+
+/// Smallest per-feature spread, so a class trained on identical flows
+/// still accepts small variations (about 5% in log space)
+const MIN_SPREAD: f64 = 0.05;
+
+/// Model bundle file holding the metadata
+const BUNDLE_METADATA: &str = "metadata.json";
+
+/// Model bundle file holding the weights
+const BUNDLE_WEIGHTS: &str = "weights.json";
+
+/// Version reported while the built-in trees classify
+pub const BUILTIN_MODEL_VERSION: &str = "builtin";
+
+type ClassVector = [f64; CLASS_FEATURES.len()];
 
 /// Traffic classification result
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -15,9 +59,236 @@ pub enum TrafficClass {
     Gaming,
     VPN,
     P2P,
+    /// A class trained with [`EncryptedDpi::train_class`]
+    Custom(String),
+    /// A class a model bundle defines that has no variant of its own
+    Other(String),
     Unknown,
 }
 
+impl TrafficClass {
+    /// Well-known class named `label`, or [`Other`](Self::Other)
+    ///
+    /// Matching ignores case, so bundle labels like "voip" still map to
+    /// their variant.
+    pub fn from_label(label: &str) -> Self {
+        const KNOWN: [(&str, TrafficClass); 8] = [
+            ("Web", TrafficClass::Web),
+            ("Video", TrafficClass::Video),
+            ("VoIP", TrafficClass::VoIP),
+            ("FileTransfer", TrafficClass::FileTransfer),
+            ("Gaming", TrafficClass::Gaming),
+            ("VPN", TrafficClass::VPN),
+            ("P2P", TrafficClass::P2P),
+            ("Unknown", TrafficClass::Unknown),
+        ];
+        KNOWN.into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(label))
+            .map(|(_, class)| class)
+            .unwrap_or_else(|| TrafficClass::Other(label.to_string()))
+    }
+
+    /// Name of the class, as used for bundle labels
+    pub fn name(&self) -> &str {
+        match self {
+            TrafficClass::Web => "Web",
+            TrafficClass::Video => "Video",
+            TrafficClass::VoIP => "VoIP",
+            TrafficClass::FileTransfer => "FileTransfer",
+            TrafficClass::Gaming => "Gaming",
+            TrafficClass::VPN => "VPN",
+            TrafficClass::P2P => "P2P",
+            TrafficClass::Custom(label) | TrafficClass::Other(label) => label,
+            TrafficClass::Unknown => "Unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for TrafficClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One candidate class for a flow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassProbability {
+    /// Class name, as [`TrafficClass::name`]
+    pub label: String,
+    pub class: TrafficClass,
+    pub probability: f64,
+}
+
+impl ClassProbability {
+    fn new(class: TrafficClass, probability: f64) -> Self {
+        Self {
+            label: class.name().to_string(),
+            class,
+            probability,
+        }
+    }
+}
+
+/// Outcome of classifying a flow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DpiClassification {
+    /// Most likely class, or `Unknown` when it falls short of the
+    /// minimum confidence
+    pub class: TrafficClass,
+    /// Probability of the most likely class
+    pub confidence: f64,
+    /// Candidate classes, most likely first
+    pub ranked: Vec<ClassProbability>,
+    /// Bundle version that classified the flow, or [`BUILTIN_MODEL_VERSION`]
+    pub model_version: String,
+}
+
+/// Hash of the class feature layout a bundle must be trained on
+pub fn feature_schema_hash() -> String {
+    let labels = CLASS_FEATURES.join(",");
+
+    // FNV-1a: unlike DefaultHasher, stable across builds
+    let hash = labels.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Metadata stored alongside bundle weights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DpiBundleMetadata {
+    pub version: String,
+    /// [`feature_schema_hash`] of the layout the weights were trained on
+    pub feature_schema_hash: String,
+    /// Class of each output, in weight row order
+    pub labels: Vec<String>,
+}
+
+/// Softmax layer over [`TrafficFeatures::class_vector`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DpiWeights {
+    /// One row of [`CLASS_FEATURES`] weights per label
+    pub weights: Vec<Vec<f64>>,
+    /// One bias per label
+    pub bias: Vec<f64>,
+}
+
+/// A classification model loaded at runtime in place of the built-in trees
+#[derive(Debug, Clone, PartialEq)]
+pub struct DpiBundle {
+    metadata: DpiBundleMetadata,
+    weights: DpiWeights,
+}
+
+impl DpiBundle {
+    /// Bundle for the current feature schema, validated
+    pub fn new(version: &str, labels: Vec<String>, weights: DpiWeights) -> Result<Self> {
+        let bundle = Self {
+            metadata: DpiBundleMetadata {
+                version: version.to_string(),
+                feature_schema_hash: feature_schema_hash(),
+                labels,
+            },
+            weights,
+        };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    pub fn metadata(&self) -> &DpiBundleMetadata {
+        &self.metadata
+    }
+
+    /// Read a bundle directory
+    ///
+    /// Bundles trained on another feature layout, or whose weights don't
+    /// match their labels, are rejected.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let metadata_path = path.join(BUNDLE_METADATA);
+        let metadata: DpiBundleMetadata = serde_json::from_slice(
+            &std::fs::read(&metadata_path)
+                .with_context(|| format!("Failed to read {}", metadata_path.display()))?,
+        ).with_context(|| format!("Invalid DPI bundle metadata in {}", metadata_path.display()))?;
+
+        let weights_path = path.join(BUNDLE_WEIGHTS);
+        let weights: DpiWeights = serde_json::from_slice(
+            &std::fs::read(&weights_path)
+                .with_context(|| format!("Failed to read {}", weights_path.display()))?,
+        ).with_context(|| format!("Invalid DPI bundle weights in {}", weights_path.display()))?;
+
+        let bundle = Self { metadata, weights };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    /// Write the bundle as a directory
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        std::fs::write(path.join(BUNDLE_METADATA), serde_json::to_vec_pretty(&self.metadata)?)?;
+        std::fs::write(path.join(BUNDLE_WEIGHTS), serde_json::to_vec(&self.weights)?)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        let version = &self.metadata.version;
+        let schema_hash = feature_schema_hash();
+        if self.metadata.feature_schema_hash != schema_hash {
+            anyhow::bail!(
+                "DPI bundle {} was trained on feature schema {}, live schema is {}",
+                version, self.metadata.feature_schema_hash, schema_hash
+            );
+        }
+
+        let labels = &self.metadata.labels;
+        if labels.is_empty() {
+            anyhow::bail!("DPI bundle {} has no labels", version);
+        }
+        let mut seen = HashSet::new();
+        for label in labels {
+            if label.trim().is_empty() || TrafficClass::from_label(label) == TrafficClass::Unknown {
+                anyhow::bail!("DPI bundle {} has an invalid label {:?}", version, label);
+            }
+            if !seen.insert(label.to_ascii_lowercase()) {
+                anyhow::bail!("DPI bundle {} repeats label {}", version, label);
+            }
+        }
+
+        let weights = &self.weights;
+        if weights.weights.len() != labels.len() || weights.bias.len() != labels.len() {
+            anyhow::bail!(
+                "DPI bundle {} has {} labels but {} weight rows and {} biases",
+                version, labels.len(), weights.weights.len(), weights.bias.len()
+            );
+        }
+        if weights.weights.iter().any(|row| row.len() != CLASS_FEATURES.len()) {
+            anyhow::bail!("DPI bundle {} weights don't cover the {} class features", version, CLASS_FEATURES.len());
+        }
+        if !weights.weights.iter().flatten().chain(&weights.bias).all(|w| w.is_finite()) {
+            anyhow::bail!("DPI bundle {} has non-finite weights", version);
+        }
+        Ok(())
+    }
+
+    /// Probability of each label for `v`
+    fn predict(&self, v: &ClassVector) -> Vec<ClassProbability> {
+        let logits: Vec<f64> = self.weights.weights.iter().zip(&self.weights.bias)
+            .map(|(row, bias)| row.iter().zip(v).map(|(w, x)| w * x).sum::<f64>() + bias)
+            .collect();
+
+        // Shift by the largest logit so exp() can't overflow
+        let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<f64> = logits.iter().map(|l| (l - max).exp()).collect();
+        let total: f64 = exps.iter().sum();
+
+        self.metadata.labels.iter().zip(exps)
+            .map(|(label, e)| ClassProbability::new(TrafficClass::from_label(label), e / total))
+            .collect()
+    }
+}
+
 /// Encrypted traffic features for ML classification
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrafficFeatures {
@@ -32,32 +303,325 @@ pub struct TrafficFeatures {
     pub tls_handshake_size: Option<usize>,
 }
 
+impl TrafficFeatures {
+    /// Values custom classes are trained on, in [`CLASS_FEATURES`] order
+    pub fn class_vector(&self) -> ClassVector {
+        [
+            self.avg_packet_size.max(0.0).ln_1p(),
+            self.packet_size_variance.max(0.0).sqrt().ln_1p(),
+            self.avg_inter_arrival_ms.max(0.0).ln_1p(),
+            (self.burst_count as f64).ln_1p(),
+            self.tls_handshake_size.map_or(0.0, |s| (s as f64).ln_1p()),
+        ]
+    }
+}
+
+/// A learned custom traffic class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomClass {
+    pub label: String,
+    pub samples: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl CustomClass {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            samples: 0,
+            sum: vec![0.0; CLASS_FEATURES.len()],
+            sum_sq: vec![0.0; CLASS_FEATURES.len()],
+        }
+    }
+
+    fn add(&mut self, v: &ClassVector) {
+        self.samples += 1;
+        for (i, x) in v.iter().enumerate() {
+            self.sum[i] += x;
+            self.sum_sq[i] += x * x;
+        }
+    }
+
+    /// Mean of each feature, in [`CLASS_FEATURES`] order
+    pub fn centroid(&self) -> Vec<f64> {
+        let n = self.samples.max(1) as f64;
+        self.sum.iter().map(|s| s / n).collect()
+    }
+
+    /// Standard deviation of each feature, floored at [`MIN_SPREAD`]
+    pub fn spread(&self) -> Vec<f64> {
+        let n = self.samples.max(1) as f64;
+        self.sum.iter().zip(&self.sum_sq)
+            .map(|(s, sq)| {
+                let mean = s / n;
+                (sq / n - mean * mean).max(0.0).sqrt().max(MIN_SPREAD)
+            })
+            .collect()
+    }
+
+    /// How well `v` fits this class, from 1.0 at the centroid down to 0
+    ///
+    /// Uses the RMS z-score across features: a flow one spread away on
+    /// every feature scores about 0.88, two spreads away about 0.61.
+    fn confidence(&self, v: &ClassVector) -> f64 {
+        let d2 = self.centroid().iter().zip(self.spread()).zip(v)
+            .map(|((mean, spread), x)| ((x - mean) / spread).powi(2))
+            .sum::<f64>() / v.len() as f64;
+        (-d2 / 8.0).exp()
+    }
+}
+
+/// On-disk form of the custom classes
+#[derive(Serialize, Deserialize)]
+struct SavedClasses {
+    version: u32,
+    features: Vec<String>,
+    confidence_threshold: f64,
+    classes: Vec<CustomClass>,
+    /// Preprocessing the classes were trained with
+    #[serde(default)]
+    scaler: Option<FeatureScaler>,
+}
+
 /// Encrypted DPI classifier
+///
+/// A loaded bundle is swapped in atomically: a classification holds a
+/// reference to the bundle it started with, so in-flight classifications
+/// finish on the old one.
 pub struct EncryptedDpi {
-    // In production, would store trained Random Forest model
     confidence_threshold: f64,
+    custom_classes: Vec<CustomClass>,
+    scaler: Option<FeatureScaler>,
+    /// Replaces the built-in trees when set
+    bundle: RwLock<Option<Arc<DpiBundle>>>,
 }
 
 impl EncryptedDpi {
     pub fn new() -> Self {
         Self {
             confidence_threshold: 0.7,
+            custom_classes: Vec::new(),
+            scaler: None,
+            bundle: RwLock::new(None),
         }
     }
 
+    /// Report flows as `Unknown` unless their most likely class reaches
+    /// `confidence`
+    ///
+    /// Custom classes must also fit a flow this well to claim it.
+    pub fn with_min_confidence(mut self, confidence: f64) -> Self {
+        self.confidence_threshold = confidence;
+        self
+    }
+
+    pub fn min_confidence(&self) -> f64 {
+        self.confidence_threshold
+    }
+
+    /// Load a model bundle and classify with it instead of the current model
+    pub fn load_bundle(&self, path: impl AsRef<Path>) -> Result<DpiBundleMetadata> {
+        let bundle = DpiBundle::load(path)?;
+        let metadata = bundle.metadata.clone();
+        self.set_bundle(bundle);
+        Ok(metadata)
+    }
+
+    /// Classify with `bundle` instead of the current model
+    pub fn set_bundle(&self, bundle: DpiBundle) {
+        tracing::info!(
+            "Activating DPI model {} (replacing {})",
+            bundle.metadata.version,
+            self.model_version()
+        );
+        *self.bundle.write().unwrap() = Some(Arc::new(bundle));
+    }
+
+    /// Go back to the built-in trees
+    pub fn unload_bundle(&self) {
+        *self.bundle.write().unwrap() = None;
+    }
+
+    /// Version of the model classifying flows
+    pub fn model_version(&self) -> String {
+        self.bundle.read().unwrap().as_ref()
+            .map(|b| b.metadata.version.clone())
+            .unwrap_or_else(|| BUILTIN_MODEL_VERSION.to_string())
+    }
+
+    /// Rescale custom class features with `scaler`
+    ///
+    /// The scaler must be fitted on [`TrafficFeatures::class_vector`] and
+    /// set before any class is trained. The built-in trees always see raw
+    /// features.
+    pub fn with_scaler(mut self, scaler: FeatureScaler) -> Result<Self> {
+        if scaler.len() != CLASS_FEATURES.len() {
+            anyhow::bail!(
+                "Scaler has {} features, DPI classes use {}",
+                scaler.len(), CLASS_FEATURES.len()
+            );
+        }
+        if !self.custom_classes.is_empty() {
+            anyhow::bail!("Set the DPI scaler before training custom classes");
+        }
+        self.scaler = Some(scaler);
+        Ok(self)
+    }
+
+    pub fn scaler(&self) -> Option<&FeatureScaler> {
+        self.scaler.as_ref()
+    }
+
+    /// Class features of a flow as the custom classes see them
+    fn class_vector(&self, features: &TrafficFeatures) -> ClassVector {
+        let mut v = features.class_vector();
+        if let Some(scaler) = &self.scaler {
+            scaler.apply(&mut v);
+        }
+        v
+    }
+
+    /// Train a custom class from example flows
+    ///
+    /// Training an existing label again adds the samples to it.
+    pub fn train_class(&mut self, label: &str, samples: &[TrafficFeatures]) -> Result<()> {
+        if label.trim().is_empty() {
+            anyhow::bail!("Custom traffic class needs a label");
+        }
+        if samples.is_empty() {
+            anyhow::bail!("No samples to train class {}", label);
+        }
+
+        let vectors: Vec<ClassVector> = samples.iter().map(|s| self.class_vector(s)).collect();
+        let class = match self.custom_classes.iter().position(|c| c.label == label) {
+            Some(i) => &mut self.custom_classes[i],
+            None => {
+                self.custom_classes.push(CustomClass::new(label));
+                self.custom_classes.last_mut().unwrap()
+            }
+        };
+        for v in &vectors {
+            class.add(v);
+        }
+
+        tracing::info!("Trained traffic class {} on {} flows", label, class.samples);
+        Ok(())
+    }
+
+    /// Forget a custom class; returns whether it existed
+    pub fn remove_class(&mut self, label: &str) -> bool {
+        let before = self.custom_classes.len();
+        self.custom_classes.retain(|c| c.label != label);
+        self.custom_classes.len() != before
+    }
+
+    pub fn custom_classes(&self) -> &[CustomClass] {
+        &self.custom_classes
+    }
+
+    /// Save the custom classes to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let saved = SavedClasses {
+            version: DPI_MODEL_VERSION,
+            features: CLASS_FEATURES.iter().map(|f| f.to_string()).collect(),
+            confidence_threshold: self.confidence_threshold,
+            classes: self.custom_classes.clone(),
+            scaler: self.scaler.clone(),
+        };
+
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&saved)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Load custom classes saved by [`save`](Self::save)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let saved: SavedClasses = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse DPI classes {}", path.display()))?;
+
+        if saved.version != DPI_MODEL_VERSION {
+            anyhow::bail!(
+                "Unsupported DPI model version {} (expected {})",
+                saved.version, DPI_MODEL_VERSION
+            );
+        }
+        if saved.features != CLASS_FEATURES {
+            anyhow::bail!(
+                "DPI classes were trained on features {:?}, expected {:?}",
+                saved.features, CLASS_FEATURES
+            );
+        }
+        for class in &saved.classes {
+            if class.sum.len() != CLASS_FEATURES.len() || class.sum_sq.len() != CLASS_FEATURES.len() {
+                anyhow::bail!("DPI class {} has the wrong number of features", class.label);
+            }
+        }
+
+        if let Some(scaler) = &saved.scaler {
+            if scaler.len() != CLASS_FEATURES.len() {
+                anyhow::bail!("DPI scaler has {} features, expected {}", scaler.len(), CLASS_FEATURES.len());
+            }
+        }
+
+        Ok(Self {
+            confidence_threshold: saved.confidence_threshold,
+            custom_classes: saved.classes,
+            scaler: saved.scaler,
+            bundle: RwLock::new(None),
+        })
+    }
+
     /// Classify encrypted traffic
-    pub fn classify(&self, features: &TrafficFeatures) -> (TrafficClass, f64) {
-        // Simplified Random Forest decision trees
-        let (class, confidence) = self.classify_with_trees(features);
+    ///
+    /// Candidates come from the custom classes and from the model: the
+    /// loaded bundle's softmax probabilities, or the built-in trees' mean
+    /// vote confidence per class. A custom class wins when the flow fits
+    /// it with at least the minimum confidence; otherwise the model
+    /// decides. Either way, a result below the minimum is reported as
+    /// `Unknown`.
+    pub fn classify(&self, features: &TrafficFeatures) -> DpiClassification {
+        let bundle = self.bundle.read().unwrap().clone();
+
+        let mut ranked: Vec<ClassProbability> = self.classify_custom(features);
+        match &bundle {
+            Some(bundle) => ranked.extend(bundle.predict(&features.class_vector())),
+            // Simplified Random Forest decision trees
+            None => ranked.extend(self.classify_with_trees(features)),
+        }
+        ranked.sort_by(|a, b| b.probability.total_cmp(&a.probability));
 
-        if confidence < self.confidence_threshold {
-            (TrafficClass::Unknown, confidence)
-        } else {
-            (class, confidence)
+        let (class, confidence) = match ranked.first() {
+            Some(top) if top.probability >= self.confidence_threshold => (top.class.clone(), top.probability),
+            Some(top) => (TrafficClass::Unknown, top.probability),
+            None => (TrafficClass::Unknown, 0.0),
+        };
+
+        DpiClassification {
+            class,
+            confidence,
+            ranked,
+            model_version: bundle.map(|b| b.metadata.version.clone())
+                .unwrap_or_else(|| BUILTIN_MODEL_VERSION.to_string()),
         }
     }
 
-    fn classify_with_trees(&self, features: &TrafficFeatures) -> (TrafficClass, f64) {
+    fn classify_custom(&self, features: &TrafficFeatures) -> Vec<ClassProbability> {
+        let v = self.class_vector(features);
+
+        self.custom_classes.iter()
+            .map(|c| ClassProbability::new(TrafficClass::Custom(c.label.clone()), c.confidence(&v)))
+            .collect()
+    }
+
+    fn classify_with_trees(&self, features: &TrafficFeatures) -> Vec<ClassProbability> {
         let mut votes: Vec<(TrafficClass, f64)> = Vec::new();
 
         // Tree 1: Packet size analysis
@@ -130,38 +694,28 @@ impl EncryptedDpi {
         }
     }
 
-    fn aggregate_votes(&self, votes: Vec<(TrafficClass, f64)>) -> (TrafficClass, f64) {
-        use std::collections::HashMap;
-
-        let mut class_scores: HashMap<String, Vec<f64>> = HashMap::new();
+    /// Average confidence of each class the trees voted for
+    ///
+    /// Votes for `Unknown` are abstentions and yield no candidate.
+    fn aggregate_votes(&self, votes: Vec<(TrafficClass, f64)>) -> Vec<ClassProbability> {
+        let mut class_scores: Vec<(TrafficClass, Vec<f64>)> = Vec::new();
 
         for (class, confidence) in votes {
-            let key = format!("{:?}", class);
-            class_scores.entry(key).or_insert_with(Vec::new).push(confidence);
-        }
-
-        // Find class with highest average confidence
-        let mut best_class = TrafficClass::Unknown;
-        let mut best_score = 0.0;
-
-        for (class_str, scores) in class_scores {
-            let avg_score = scores.iter().sum::<f64>() / scores.len() as f64;
-            if avg_score > best_score {
-                best_score = avg_score;
-                best_class = match class_str.as_str() {
-                    "Web" => TrafficClass::Web,
-                    "Video" => TrafficClass::Video,
-                    "VoIP" => TrafficClass::VoIP,
-                    "FileTransfer" => TrafficClass::FileTransfer,
-                    "Gaming" => TrafficClass::Gaming,
-                    "VPN" => TrafficClass::VPN,
-                    "P2P" => TrafficClass::P2P,
-                    _ => TrafficClass::Unknown,
-                };
+            if class == TrafficClass::Unknown {
+                continue;
+            }
+            match class_scores.iter_mut().find(|(c, _)| *c == class) {
+                Some((_, scores)) => scores.push(confidence),
+                None => class_scores.push((class, vec![confidence])),
             }
         }
 
-        (best_class, best_score)
+        class_scores.into_iter()
+            .map(|(class, scores)| {
+                let avg_score = scores.iter().sum::<f64>() / scores.len() as f64;
+                ClassProbability::new(class, avg_score)
+            })
+            .collect()
     }
 }
 
@@ -191,7 +745,7 @@ mod tests {
             tls_handshake_size: Some(3000),
         };
 
-        let (class, confidence) = dpi.classify(&features);
+        let DpiClassification { class, confidence, .. } = dpi.classify(&features);
         assert_eq!(class, TrafficClass::Video);
         assert!(confidence > 0.7);
     }
@@ -212,7 +766,7 @@ mod tests {
             tls_handshake_size: Some(2500),
         };
 
-        let (class, confidence) = dpi.classify(&features);
+        let DpiClassification { class, confidence, .. } = dpi.classify(&features);
         assert_eq!(class, TrafficClass::VoIP);
         assert!(confidence > 0.7);
     }
@@ -233,7 +787,209 @@ mod tests {
             tls_handshake_size: Some(2000),
         };
 
-        let (class, _confidence) = dpi.classify(&features);
+        let DpiClassification { class, .. } = dpi.classify(&features);
         assert_eq!(class, TrafficClass::Gaming);
     }
+
+    fn flow(avg_size: f64, stddev: f64, iat_ms: f64, bursts: usize, handshake: usize, jitter: f64) -> TrafficFeatures {
+        let scale = 1.0 + jitter;
+        TrafficFeatures {
+            packet_count: 400,
+            total_bytes: (avg_size * scale * 400.0) as u64,
+            avg_packet_size: avg_size * scale,
+            packet_size_variance: (stddev * scale).powi(2),
+            inter_arrival_times_ms: vec![iat_ms * scale; 10],
+            avg_inter_arrival_ms: iat_ms * scale,
+            burst_count: bursts,
+            tcp_flags: vec![],
+            tls_handshake_size: Some((handshake as f64 * scale) as usize),
+        }
+    }
+
+    /// Deterministic +/-10% jitter
+    fn jitter(i: usize) -> f64 {
+        (i as f64 * 1.7).sin() * 0.1
+    }
+
+    // Chatty ERP client: mid-size packets every ~250ms with a short handshake
+    fn erp(i: usize) -> TrafficFeatures {
+        flow(620.0, 90.0, 250.0, 2, 1800, jitter(i))
+    }
+
+    // Bulk backup agent: near-MTU packets back to back with a large handshake
+    fn backup(i: usize) -> TrafficFeatures {
+        flow(1380.0, 300.0, 2.0, 40, 6500, jitter(i + 100))
+    }
+
+    #[test]
+    fn test_custom_classes_separate_patterns() {
+        let mut dpi = EncryptedDpi::new();
+        dpi.train_class("erp", &(0..30).map(erp).collect::<Vec<_>>()).unwrap();
+        dpi.train_class("backup", &(0..30).map(backup).collect::<Vec<_>>()).unwrap();
+
+        for i in 50..60 {
+            let DpiClassification { class, confidence, .. } = dpi.classify(&erp(i));
+            assert_eq!(class, TrafficClass::Custom("erp".to_string()));
+            assert!(confidence >= 0.7);
+
+            let DpiClassification { class, .. } = dpi.classify(&backup(i));
+            assert_eq!(class, TrafficClass::Custom("backup".to_string()));
+        }
+
+        // A flow resembling neither falls through to the built-in classes
+        let DpiClassification { class, .. } = dpi.classify(&flow(1450.0, 7.0, 100.0, 15, 3000, 0.0));
+        assert_eq!(class, TrafficClass::Video);
+
+        // ...and one the trees aren't sure about either is Unknown
+        let DpiClassification { class, confidence, .. } = dpi.classify(&flow(900.0, 500.0, 80.0, 4, 0, 0.0));
+        assert_eq!(class, TrafficClass::Unknown);
+        assert!(confidence < 0.7);
+    }
+
+    #[test]
+    fn test_custom_classes_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dpi.json");
+
+        let mut dpi = EncryptedDpi::new();
+        assert!(dpi.train_class("erp", &[]).is_err());
+        assert!(dpi.train_class(" ", &[erp(0)]).is_err());
+        dpi.train_class("erp", &(0..20).map(erp).collect::<Vec<_>>()).unwrap();
+        dpi.train_class("erp", &(20..30).map(erp).collect::<Vec<_>>()).unwrap();
+        assert_eq!(dpi.custom_classes().len(), 1);
+        assert_eq!(dpi.custom_classes()[0].samples, 30);
+        dpi.save(&path).unwrap();
+
+        let loaded = EncryptedDpi::load(&path).unwrap();
+        assert_eq!(loaded.custom_classes()[0].centroid(), dpi.custom_classes()[0].centroid());
+        assert_eq!(loaded.classify(&erp(40)), dpi.classify(&erp(40)));
+
+        let mut other = serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path).unwrap()).unwrap();
+        other["features"][0] = "avg_packet_size".into();
+        std::fs::write(&path, other.to_string()).unwrap();
+        assert!(EncryptedDpi::load(&path).err().unwrap().to_string().contains("features"));
+
+        let mut dpi = loaded;
+        assert!(dpi.remove_class("erp"));
+        assert_eq!(dpi.classify(&erp(40)).class, TrafficClass::Unknown);
+    }
+
+    #[test]
+    fn test_custom_classes_with_scaler() {
+        use crate::scaler::{FeatureScaler, ScalingMethod};
+
+        let erp_flows: Vec<_> = (0..30).map(erp).collect();
+        let backup_flows: Vec<_> = (0..30).map(backup).collect();
+        let scaler = FeatureScaler::fit(
+            ScalingMethod::ZScore,
+            erp_flows.iter().chain(&backup_flows).map(|f| f.class_vector()),
+        ).unwrap();
+
+        let mut dpi = EncryptedDpi::new().with_scaler(scaler.clone()).unwrap();
+        dpi.train_class("erp", &erp_flows).unwrap();
+        dpi.train_class("backup", &backup_flows).unwrap();
+        assert!(dpi.custom_classes()[0].centroid().iter().all(|m| m.abs() < 2.0));
+
+        assert_eq!(dpi.classify(&erp(70)).class, TrafficClass::Custom("erp".to_string()));
+        assert_eq!(dpi.classify(&backup(70)).class, TrafficClass::Custom("backup".to_string()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dpi.json");
+        dpi.save(&path).unwrap();
+        let loaded = EncryptedDpi::load(&path).unwrap();
+        assert_eq!(loaded.scaler(), Some(&scaler));
+        assert_eq!(loaded.classify(&backup(71)), dpi.classify(&backup(71)));
+
+        // Classes learned in raw units can't be reinterpreted as scaled ones
+        let mut trained = EncryptedDpi::new();
+        trained.train_class("erp", &erp_flows).unwrap();
+        assert!(trained.with_scaler(scaler).is_err());
+    }
+
+    fn video() -> TrafficFeatures {
+        flow(1450.0, 7.0, 100.0, 15, 3000, 0.0)
+    }
+
+    #[test]
+    fn test_ranked_classes_and_min_confidence() {
+        let result = EncryptedDpi::new().classify(&video());
+        assert_eq!(result.class, TrafficClass::Video);
+        assert_eq!(result.model_version, BUILTIN_MODEL_VERSION);
+        let labels: Vec<&str> = result.ranked.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["Video", "Web"]);
+        assert!((result.ranked[0].probability - 0.775).abs() < 1e-9);
+        assert_eq!(result.confidence, result.ranked[0].probability);
+
+        // Not sure enough for a stricter threshold, but the guess is still ranked
+        let result = EncryptedDpi::new().with_min_confidence(0.8).classify(&video());
+        assert_eq!(result.class, TrafficClass::Unknown);
+        assert_eq!(result.ranked[0].class, TrafficClass::Video);
+    }
+
+    fn webrtc_bundle(version: &str) -> DpiBundle {
+        let labels = vec!["web".to_string(), "Video".to_string(), "WebRTC".to_string()];
+        let weights = DpiWeights {
+            weights: vec![vec![0.0; CLASS_FEATURES.len()]; 3],
+            bias: vec![0.0, 1.0, 3.0],
+        };
+        DpiBundle::new(version, labels, weights).unwrap()
+    }
+
+    #[test]
+    fn test_bundle_adds_classes_and_hot_swaps() {
+        let dir = tempfile::tempdir().unwrap();
+        webrtc_bundle("2024.06-webrtc").save(dir.path()).unwrap();
+
+        let dpi = EncryptedDpi::new().with_min_confidence(0.5);
+        let metadata = dpi.load_bundle(dir.path()).unwrap();
+        assert_eq!(metadata.labels, ["web", "Video", "WebRTC"]);
+        assert_eq!(dpi.model_version(), "2024.06-webrtc");
+
+        let result = dpi.classify(&video());
+        assert_eq!(result.class, TrafficClass::Other("WebRTC".to_string()));
+        assert_eq!(result.class.to_string(), "WebRTC");
+        assert_eq!(result.model_version, "2024.06-webrtc");
+        let classes: Vec<TrafficClass> = result.ranked.iter().map(|c| c.class.clone()).collect();
+        assert_eq!(classes, [TrafficClass::Other("WebRTC".to_string()), TrafficClass::Video, TrafficClass::Web]);
+        let total: f64 = result.ranked.iter().map(|c| c.probability).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        assert!((result.confidence - 3f64.exp() / (1.0 + 1f64.exp() + 3f64.exp())).abs() < 1e-12);
+
+        dpi.unload_bundle();
+        assert_eq!(dpi.classify(&video()).class, TrafficClass::Video);
+    }
+
+    #[test]
+    fn test_bundle_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = webrtc_bundle("v1");
+        bundle.save(dir.path()).unwrap();
+        assert_eq!(DpiBundle::load(dir.path()).unwrap(), bundle);
+
+        let rewrite = |file: &str, edit: &dyn Fn(&mut serde_json::Value)| {
+            bundle.save(dir.path()).unwrap();
+            let path = dir.path().join(file);
+            let mut value: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+            edit(&mut value);
+            std::fs::write(&path, value.to_string()).unwrap();
+            DpiBundle::load(dir.path()).unwrap_err().to_string()
+        };
+
+        let err = rewrite(BUNDLE_METADATA, &|m| m["feature_schema_hash"] = "0000000000000000".into());
+        assert!(err.contains("feature schema"), "{}", err);
+        let err = rewrite(BUNDLE_METADATA, &|m| m["labels"][0] = "WEBRTC".into());
+        assert!(err.contains("repeats label"), "{}", err);
+        let err = rewrite(BUNDLE_METADATA, &|m| m["labels"][0] = "Unknown".into());
+        assert!(err.contains("invalid label"), "{}", err);
+        let err = rewrite(BUNDLE_WEIGHTS, &|w| { w["bias"].as_array_mut().unwrap().pop(); });
+        assert!(err.contains("3 labels"), "{}", err);
+        let err = rewrite(BUNDLE_WEIGHTS, &|w| { w["weights"][1].as_array_mut().unwrap().pop(); });
+        assert!(err.contains("class features"), "{}", err);
+
+        // A rejected bundle leaves the running model alone
+        let dpi = EncryptedDpi::new();
+        dpi.set_bundle(webrtc_bundle("v1"));
+        assert!(dpi.load_bundle(dir.path()).is_err());
+        assert_eq!(dpi.model_version(), "v1");
+    }
 }
@@ -1,9 +1,18 @@
 //! Predictive Failover using ML
 //!
 //! Predicts link failures before they happen using Gradient Boosting
+//!
+//! Each prediction carries a confidence and, when a metric is trending
+//! towards its failure limit, a time-to-failure interval from a linear fit
+//! of the recent history, so operators can pick a sensible threshold for
+//! pre-emptive rerouting and suppress predictions the data doesn't support.
+//!
+//! Predictions also name the health metrics that drove them, and a
+//! [`CalibrationTracker`] scores past predictions against what actually
+//! happened, so the probabilities can be checked before they are trusted.
 
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 /// Link health metrics for prediction
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,16 +28,90 @@ pub struct LinkHealth {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailoverPrediction {
     pub failure_probability: f64,
+    /// How well the recent history supports this prediction (0.0-1.0)
+    pub confidence: f64,
     pub should_failover: bool,
+    /// Failure is likely enough to move traffic off the link pre-emptively
+    pub recommend_reroute: bool,
+    /// Confidence was below the predictor's minimum; no action is recommended
+    pub suppressed: bool,
+    /// Expected time to failure, as in `time_to_failure`
     pub time_to_failure_seconds: Option<u64>,
+    pub time_to_failure: Option<TimeToFailure>,
     pub reason: String,
+    /// Metrics that contributed most to the probability, largest first
+    #[serde(default)]
+    pub explanation: Vec<FeatureContribution>,
 }
 
+/// Which way a metric moved the failure probability
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContributionDirection {
+    IncreasesRisk,
+    DecreasesRisk,
+}
+
+/// One metric's share of a failure probability
+///
+/// Contributions are relative to the predictor's recent window: a metric
+/// that is as bad as it has been all along contributes nothing, one that
+/// recovered contributes negatively.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureContribution {
+    /// `LinkHealth` field name
+    pub feature: String,
+    pub value: f64,
+    /// Mean of the metric over the predictor's window
+    pub baseline: f64,
+    /// Change in failure probability attributed to the metric
+    pub contribution: f64,
+    pub direction: ContributionDirection,
+}
+
+/// Predicted time until a metric crosses its failure limit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeToFailure {
+    pub expected_seconds: u64,
+    /// Lower end of the 95% interval
+    pub lower_seconds: u64,
+    /// Upper end of the 95% interval; `None` when the trend may be flat
+    pub upper_seconds: Option<u64>,
+    /// Metric whose trend drives the estimate
+    pub metric: String,
+}
+
+/// Values at which a link is considered failed
+const FAILURE_LIMITS: [(&str, f64); 4] = [
+    ("latency_ms", 500.0),
+    ("packet_loss", 0.25),
+    ("jitter_ms", 200.0),
+    ("error_rate", 0.05),
+];
+
+/// Most recent samples fitted for time-to-failure
+const TREND_SAMPLES: usize = 20;
+
+/// Most recent samples checked for agreement with the current prediction
+const CONSISTENCY_SAMPLES: usize = 10;
+
+/// Contributions listed in a prediction's explanation
+const EXPLANATION_FEATURES: usize = 3;
+
+/// Failure score the latency trend tree adds
+const TREND_SCORE: f64 = 0.25;
+
+/// Two-sided 95% normal quantile
+const Z_95: f64 = 1.96;
+
 /// Gradient Boosting-based failover predictor
 pub struct PredictiveFailover {
     history: VecDeque<LinkHealth>,
     window_size: usize,
     failure_threshold: f64,
+    reroute_threshold: f64,
+    min_confidence: f64,
+    sample_interval_secs: f64,
 }
 
 impl PredictiveFailover {
@@ -37,9 +120,34 @@ impl PredictiveFailover {
             history: VecDeque::new(),
             window_size: 60, // 1 minute of history
             failure_threshold: 0.75, // 75% probability triggers failover
+            reroute_threshold: 0.5,
+            min_confidence: 0.0, // Nothing suppressed
+            sample_interval_secs: 1.0,
         }
     }
 
+    /// Failure probability at or above which rerouting is recommended
+    pub fn with_reroute_threshold(mut self, threshold: f64) -> Self {
+        self.reroute_threshold = threshold;
+        self
+    }
+
+    /// Suppress predictions whose confidence is below `confidence`
+    pub fn with_min_confidence(mut self, confidence: f64) -> Self {
+        self.min_confidence = confidence;
+        self
+    }
+
+    /// Seconds between the samples passed to [`predict`](Self::predict)
+    pub fn with_sample_interval(mut self, secs: f64) -> Self {
+        self.sample_interval_secs = secs;
+        self
+    }
+
+    pub fn reroute_threshold(&self) -> f64 {
+        self.reroute_threshold
+    }
+
     /// Predict if link will fail
     pub fn predict(&mut self, health: LinkHealth) -> FailoverPrediction {
         self.history.push_back(health.clone());
@@ -50,88 +158,126 @@ impl PredictiveFailover {
         if self.history.len() < 10 {
             return FailoverPrediction {
                 failure_probability: 0.0,
+                confidence: 0.0,
                 should_failover: false,
+                recommend_reroute: false,
+                suppressed: false,
                 time_to_failure_seconds: None,
+                time_to_failure: None,
                 reason: "Insufficient data".to_string(),
+                explanation: Vec::new(),
             };
         }
 
         let probability = self.calculate_failure_probability(&health);
-        let should_failover = probability > self.failure_threshold;
-
-        let time_to_failure = if should_failover {
-            Some(self.estimate_time_to_failure())
-        } else {
-            None
-        };
+        let confidence = self.calculate_confidence(probability);
+        let suppressed = confidence < self.min_confidence;
 
+        let time_to_failure = self.estimate_time_to_failure();
         let reason = self.get_failure_reason(&health);
+        let explanation = self.explain(&health);
 
         FailoverPrediction {
             failure_probability: probability,
-            should_failover,
-            time_to_failure_seconds: time_to_failure,
+            confidence,
+            should_failover: !suppressed && probability > self.failure_threshold,
+            recommend_reroute: !suppressed && probability >= self.reroute_threshold,
+            suppressed,
+            time_to_failure_seconds: time_to_failure.as_ref().map(|t| t.expected_seconds),
+            time_to_failure,
             reason,
+            explanation,
         }
     }
 
     fn calculate_failure_probability(&self, health: &LinkHealth) -> f64 {
-        // Simplified gradient boosting approximation
-        let mut score: f64 = 0.0;
+        let mut score = instant_score(health);
 
-        // Tree 1: Latency degradation
-        if health.latency_ms > 100.0 {
-            score += 0.3;
+        // Tree 5: Trend analysis
+        if self.latency_rising() {
+            score += TREND_SCORE;
         }
 
-        // Tree 2: Packet loss
-        if health.packet_loss > 0.05 {
-            score += 0.4;
-        }
+        score.min(1.0)
+    }
 
-        // Tree 3: Jitter
-        if health.jitter_ms > 50.0 {
-            score += 0.2;
+    /// Whether latency rose by more than 10 ms per sample over the last five
+    fn latency_rising(&self) -> bool {
+        if self.history.len() < 5 {
+            return false;
         }
+        let recent: Vec<&LinkHealth> = self.history.iter().rev().take(5).collect();
+        let latency_trend: f64 = recent.windows(2)
+            .map(|w| w[0].latency_ms - w[1].latency_ms)
+            .sum::<f64>() / 4.0;
+        latency_trend > 10.0
+    }
 
-        // Tree 4: Error rate
-        if health.error_rate > 0.01 {
-            score += 0.3;
-        }
+    /// Attribute the failure score to the metrics of `health`
+    ///
+    /// Each tree splits on a single metric, so a metric's contribution is
+    /// its trees' output for the current sample minus their mean output
+    /// over the window. The trend tree is credited to latency in full.
+    fn explain(&self, health: &LinkHealth) -> Vec<FeatureContribution> {
+        let n = self.history.len() as f64;
+        let mut contributions: Vec<FeatureContribution> = FEATURES.iter()
+            .map(|feature| {
+                let baseline = self.history.iter().map(|h| metric_value(h, feature)).sum::<f64>() / n;
+                let expected = self.history.iter()
+                    .map(|h| tree_score(feature, metric_value(h, feature)))
+                    .sum::<f64>() / n;
+                let value = metric_value(health, feature);
+                let mut contribution = tree_score(feature, value) - expected;
+                if *feature == "latency_ms" && self.latency_rising() {
+                    contribution += TREND_SCORE;
+                }
+                FeatureContribution {
+                    feature: feature.to_string(),
+                    value,
+                    baseline,
+                    contribution,
+                    direction: if contribution >= 0.0 {
+                        ContributionDirection::IncreasesRisk
+                    } else {
+                        ContributionDirection::DecreasesRisk
+                    },
+                }
+            })
+            .filter(|c| c.contribution.abs() > f64::EPSILON)
+            .collect();
+
+        contributions.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+        contributions.truncate(EXPLANATION_FEATURES);
+        contributions
+    }
 
-        // Tree 5: Trend analysis
-        if self.history.len() >= 5 {
-            let recent: Vec<&LinkHealth> = self.history.iter().rev().take(5).collect();
-            let latency_trend: f64 = recent.windows(2)
-                .map(|w| w[0].latency_ms - w[1].latency_ms)
-                .sum::<f64>() / 4.0;
-
-            if latency_trend > 10.0 {
-                score += 0.25; // Latency is increasing
-            }
-        }
+    /// Data sufficiency times agreement of recent samples with `probability`
+    ///
+    /// A lone spike after a healthy stretch scores low; sustained
+    /// degradation, or sustained health, scores high.
+    fn calculate_confidence(&self, probability: f64) -> f64 {
+        let sufficiency = (self.history.len() as f64 / TREND_SAMPLES as f64).min(1.0);
 
-        score.min(1.0)
-    }
+        let failing = probability >= self.reroute_threshold;
+        let recent: Vec<&LinkHealth> = self.history.iter().rev().take(CONSISTENCY_SAMPLES).collect();
+        let agreeing = recent.iter()
+            .filter(|h| (instant_score(h).min(1.0) >= self.reroute_threshold) == failing)
+            .count();
 
-    fn estimate_time_to_failure(&self) -> u64 {
-        // Estimate based on degradation rate
-        if self.history.len() < 2 {
-            return 60;
-        }
+        sufficiency * agreeing as f64 / recent.len() as f64
+    }
 
-        let recent: Vec<&LinkHealth> = self.history.iter().rev().take(5).collect();
-        let avg_latency_increase: f64 = recent.windows(2)
-            .map(|w| (w[0].latency_ms - w[1].latency_ms).max(0.0))
-            .sum::<f64>() / (recent.len() - 1) as f64;
-
-        if avg_latency_increase > 5.0 {
-            30 // 30 seconds
-        } else if avg_latency_increase > 1.0 {
-            120 // 2 minutes
-        } else {
-            300 // 5 minutes
-        }
+    /// Earliest time any metric's recent trend reaches its failure limit
+    fn estimate_time_to_failure(&self) -> Option<TimeToFailure> {
+        let recent: Vec<&LinkHealth> = self.history.iter().rev().take(TREND_SAMPLES).rev().collect();
+
+        FAILURE_LIMITS.iter()
+            .filter_map(|(metric, limit)| {
+                let values: Vec<f64> = recent.iter().map(|h| metric_value(h, metric)).collect();
+                let ttf = trend_time_to_limit(&values, *limit, self.sample_interval_secs)?;
+                Some(TimeToFailure { metric: metric.to_string(), ..ttf })
+            })
+            .min_by_key(|t| t.expected_seconds)
     }
 
     fn get_failure_reason(&self, health: &LinkHealth) -> String {
@@ -155,6 +301,237 @@ impl Default for PredictiveFailover {
     }
 }
 
+/// Metrics of a `LinkHealth`, by field name
+const FEATURES: [&str; 5] = ["latency_ms", "packet_loss", "jitter_ms", "bandwidth_utilization", "error_rate"];
+
+/// Trees 1-4: failure score from a single sample, before trend analysis
+fn instant_score(health: &LinkHealth) -> f64 {
+    // Simplified gradient boosting approximation
+    FEATURES.iter()
+        .map(|feature| tree_score(feature, metric_value(health, feature)))
+        .sum()
+}
+
+/// Output of the tree splitting on `metric`
+fn tree_score(metric: &str, value: f64) -> f64 {
+    match metric {
+        // Tree 1: Latency degradation
+        "latency_ms" if value > 100.0 => 0.3,
+        // Tree 2: Packet loss
+        "packet_loss" if value > 0.05 => 0.4,
+        // Tree 3: Jitter
+        "jitter_ms" if value > 50.0 => 0.2,
+        // Tree 4: Error rate
+        "error_rate" if value > 0.01 => 0.3,
+        _ => 0.0,
+    }
+}
+
+fn metric_value(health: &LinkHealth, metric: &str) -> f64 {
+    match metric {
+        "latency_ms" => health.latency_ms,
+        "packet_loss" => health.packet_loss,
+        "jitter_ms" => health.jitter_ms,
+        "bandwidth_utilization" => health.bandwidth_utilization,
+        "error_rate" => health.error_rate,
+        _ => unreachable!("unknown failure metric {}", metric),
+    }
+}
+
+/// A prediction made for a link and, once known, whether the link failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionRecord {
+    pub link: String,
+    /// Unix time of the prediction, in seconds
+    pub timestamp_secs: u64,
+    pub failure_probability: f64,
+    /// Whether the prediction was acted on as a failure
+    pub predicted_failure: bool,
+    pub explanation: Vec<FeatureContribution>,
+    /// Whether the link failed within the horizon; `None` until known
+    pub outcome: Option<bool>,
+}
+
+/// How well resolved predictions matched what happened
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationReport {
+    /// Predictions whose outcome is known
+    pub resolved: usize,
+    /// Predictions still within their horizon
+    pub pending: usize,
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+    /// `None` until a failure has been predicted
+    pub precision: Option<f64>,
+    /// `None` until a link has failed
+    pub recall: Option<f64>,
+    /// Mean squared error of the probabilities; `None` until one resolves
+    pub brier_score: Option<f64>,
+}
+
+/// Records failover predictions against actual link failures
+///
+/// A prediction resolves as a failure when its link fails within
+/// `horizon_secs` of it, and as healthy once the horizon passes without
+/// one. The most recent `window` predictions of each link are kept.
+pub struct CalibrationTracker {
+    horizon_secs: u64,
+    window: usize,
+    links: HashMap<String, VecDeque<PredictionRecord>>,
+}
+
+impl CalibrationTracker {
+    pub fn new(horizon_secs: u64, window: usize) -> Self {
+        Self {
+            horizon_secs,
+            window,
+            links: HashMap::new(),
+        }
+    }
+
+    pub fn horizon_secs(&self) -> u64 {
+        self.horizon_secs
+    }
+
+    /// Record `prediction` for `link`, made at `now_secs`
+    pub fn record(&mut self, link: &str, prediction: &FailoverPrediction, predicted_failure: bool, now_secs: u64) {
+        self.resolve(now_secs);
+
+        let records = self.links.entry(link.to_string()).or_default();
+        records.push_back(PredictionRecord {
+            link: link.to_string(),
+            timestamp_secs: now_secs,
+            failure_probability: prediction.failure_probability,
+            predicted_failure,
+            explanation: prediction.explanation.clone(),
+            outcome: None,
+        });
+        if records.len() > self.window {
+            records.pop_front();
+        }
+    }
+
+    /// Record that `link` failed at `at_secs`
+    ///
+    /// Pending predictions whose horizon covers the failure resolve as
+    /// failures.
+    pub fn record_failure(&mut self, link: &str, at_secs: u64) {
+        self.resolve(at_secs);
+
+        if let Some(records) = self.links.get_mut(link) {
+            for record in records.iter_mut().filter(|r| r.outcome.is_none() && r.timestamp_secs <= at_secs) {
+                record.outcome = Some(true);
+            }
+        }
+    }
+
+    /// Resolve predictions whose horizon has passed at `now_secs` as healthy
+    pub fn resolve(&mut self, now_secs: u64) {
+        for record in self.links.values_mut().flatten() {
+            if record.outcome.is_none() && record.timestamp_secs + self.horizon_secs < now_secs {
+                record.outcome = Some(false);
+            }
+        }
+    }
+
+    /// Predictions kept for `link`, oldest first
+    pub fn get_prediction_history(&self, link: &str) -> Vec<PredictionRecord> {
+        self.links.get(link)
+            .map(|records| records.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Calibration over the kept predictions of every link
+    pub fn report(&self) -> CalibrationReport {
+        calibration(self.links.values().flatten())
+    }
+
+    /// Calibration over the kept predictions of `link`
+    pub fn report_for(&self, link: &str) -> CalibrationReport {
+        calibration(self.links.get(link).into_iter().flatten())
+    }
+}
+
+fn calibration<'a>(records: impl Iterator<Item = &'a PredictionRecord>) -> CalibrationReport {
+    let mut report = CalibrationReport::default();
+    let mut squared_error = 0.0;
+
+    for record in records {
+        let Some(failed) = record.outcome else {
+            report.pending += 1;
+            continue;
+        };
+        report.resolved += 1;
+        squared_error += (record.failure_probability - if failed { 1.0 } else { 0.0 }).powi(2);
+        match (record.predicted_failure, failed) {
+            (true, true) => report.true_positives += 1,
+            (true, false) => report.false_positives += 1,
+            (false, false) => report.true_negatives += 1,
+            (false, true) => report.false_negatives += 1,
+        }
+    }
+
+    let ratio = |hits: usize, misses: usize| (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64);
+    report.precision = ratio(report.true_positives, report.false_positives);
+    report.recall = ratio(report.true_positives, report.false_negatives);
+    report.brier_score = (report.resolved > 0).then(|| squared_error / report.resolved as f64);
+    report
+}
+
+/// Fit a least-squares line to `values` and extrapolate it to `limit`
+///
+/// The interval comes from the slope's standard error. Returns `None` when
+/// there are too few samples or the metric is not worsening.
+fn trend_time_to_limit(values: &[f64], limit: f64, interval_secs: f64) -> Option<TimeToFailure> {
+    let current = *values.last()?;
+    if current >= limit {
+        return Some(TimeToFailure {
+            expected_seconds: 0,
+            lower_seconds: 0,
+            upper_seconds: Some(0),
+            metric: String::new(),
+        });
+    }
+    if values.len() < 3 {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let xs = (0..values.len()).map(|i| i as f64 * interval_secs);
+    let mean_x = xs.clone().sum::<f64>() / n;
+    let mean_y = values.iter().sum::<f64>() / n;
+    let sxx: f64 = xs.clone().map(|x| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = xs.clone().zip(values).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    if slope <= 0.0 {
+        return None;
+    }
+    let intercept = mean_y - slope * mean_x;
+    let residual: f64 = xs.clone().zip(values).map(|(x, y)| (y - intercept - slope * x).powi(2)).sum();
+    let slope_error = (residual / (n - 2.0) / sxx).sqrt();
+
+    // Extrapolate from the fitted value at the latest sample
+    let last_x = (values.len() - 1) as f64 * interval_secs;
+    let remaining = (limit - (intercept + slope * last_x)).max(0.0);
+    let seconds = |rate: f64| (remaining / rate).round() as u64;
+
+    let fast = slope + Z_95 * slope_error;
+    let slow = slope - Z_95 * slope_error;
+
+    Some(TimeToFailure {
+        expected_seconds: seconds(slope),
+        lower_seconds: seconds(fast),
+        upper_seconds: (slow > 0.0).then(|| seconds(slow)),
+        metric: String::new(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -215,4 +592,155 @@ mod tests {
         assert!(prediction.should_failover);
         assert!(prediction.time_to_failure_seconds.is_some());
     }
+
+    fn healthy(i: usize) -> LinkHealth {
+        let jitter = (i % 5) as f64;
+        LinkHealth {
+            latency_ms: 20.0 + jitter,
+            packet_loss: 0.001,
+            jitter_ms: 2.0 + jitter * 0.5,
+            bandwidth_utilization: 0.5,
+            error_rate: 0.0001,
+        }
+    }
+
+    #[test]
+    fn test_degrading_link_predicts_near_term_failure_confidently() {
+        let mut predictor = PredictiveFailover::new()
+            .with_reroute_threshold(0.6)
+            .with_min_confidence(0.8);
+
+        for i in 0..20 {
+            predictor.predict(healthy(i));
+        }
+
+        // Latency climbing ~12 ms/s towards its 500 ms limit, with some noise
+        let mut prediction = None;
+        for i in 0..30 {
+            let noise = ((i * 7) % 5) as f64 - 2.0;
+            prediction = Some(predictor.predict(LinkHealth {
+                latency_ms: 120.0 + i as f64 * 12.0 + noise * 3.0,
+                packet_loss: 0.06 + i as f64 * 0.004 + noise * 0.001,
+                jitter_ms: 20.0 + i as f64,
+                bandwidth_utilization: 0.5,
+                error_rate: 0.002,
+            }));
+        }
+        let prediction = prediction.unwrap();
+
+        assert!(prediction.confidence > 0.9, "{:?}", prediction);
+        assert!(!prediction.suppressed);
+        assert!(prediction.recommend_reroute);
+        assert!(prediction.should_failover);
+
+        let ttf = prediction.time_to_failure.unwrap();
+        assert_eq!(ttf.metric, "latency_ms");
+        assert!(ttf.expected_seconds <= 10, "{:?}", ttf);
+        assert!(ttf.lower_seconds <= ttf.expected_seconds);
+        assert!(ttf.upper_seconds.unwrap() >= ttf.expected_seconds);
+        assert!(ttf.upper_seconds.unwrap() < 60);
+        assert_eq!(prediction.time_to_failure_seconds, Some(ttf.expected_seconds));
+    }
+
+    #[test]
+    fn test_lone_spike_is_suppressed() {
+        let mut predictor = PredictiveFailover::new().with_min_confidence(0.5);
+        for i in 0..30 {
+            let prediction = predictor.predict(healthy(i));
+            assert!(!prediction.recommend_reroute);
+        }
+        let settled = predictor.predict(healthy(30));
+        assert!(settled.confidence > 0.9);
+        assert!(settled.time_to_failure.is_none());
+
+        let spike = predictor.predict(LinkHealth {
+            latency_ms: 180.0,
+            packet_loss: 0.08,
+            ..healthy(0)
+        });
+        assert!(spike.failure_probability >= predictor.reroute_threshold());
+        assert!(spike.confidence < 0.5);
+        assert!(spike.suppressed);
+        assert!(!spike.recommend_reroute);
+        assert!(!spike.should_failover);
+    }
+
+    #[test]
+    fn test_explanation_names_driving_metrics() {
+        let mut predictor = PredictiveFailover::new();
+        for i in 0..30 {
+            let prediction = predictor.predict(healthy(i));
+            assert!(prediction.explanation.is_empty());
+        }
+
+        let spike = predictor.predict(LinkHealth {
+            latency_ms: 180.0,
+            packet_loss: 0.08,
+            ..healthy(0)
+        });
+        let features: Vec<&str> = spike.explanation.iter().map(|c| c.feature.as_str()).collect();
+        // Latency also trips the trend tree
+        assert_eq!(features, ["latency_ms", "packet_loss"]);
+
+        let loss = &spike.explanation[1];
+        assert_eq!(loss.value, 0.08);
+        assert!((loss.baseline - 0.001).abs() < 0.01);
+        assert_eq!(loss.direction, ContributionDirection::IncreasesRisk);
+        // One degraded sample in a window of 31 healthy ones
+        assert!((loss.contribution - 0.4 * 30.0 / 31.0).abs() < 1e-9);
+
+        // Once latency recovers it counts against the failure
+        let recovered = predictor.predict(LinkHealth { packet_loss: 0.08, ..healthy(1) });
+        let latency = recovered.explanation.iter().find(|c| c.feature == "latency_ms").unwrap();
+        assert_eq!(latency.direction, ContributionDirection::DecreasesRisk);
+        assert!(latency.contribution < 0.0);
+    }
+
+    #[test]
+    fn test_calibration_report() {
+        let prediction = |p: f64| FailoverPrediction {
+            failure_probability: p,
+            confidence: 1.0,
+            should_failover: false,
+            recommend_reroute: false,
+            suppressed: false,
+            time_to_failure_seconds: None,
+            time_to_failure: None,
+            reason: String::new(),
+            explanation: Vec::new(),
+        };
+        let mut tracker = CalibrationTracker::new(60, 100);
+
+        // wan1: warned at 0.9, failed 30 s later
+        tracker.record("wan1", &prediction(0.9), true, 1000);
+        tracker.record_failure("wan1", 1030);
+        // wan2: warned at 0.8, stayed up
+        tracker.record("wan2", &prediction(0.8), true, 1000);
+        // wan3: calm at 0.2, failed anyway
+        tracker.record("wan3", &prediction(0.2), false, 1000);
+        tracker.record_failure("wan3", 1050);
+        // wan1 again: calm at 0.1 and still within its horizon
+        tracker.record("wan1", &prediction(0.1), false, 1070);
+
+        tracker.resolve(1065);
+        let report = tracker.report();
+        assert_eq!(report.resolved, 3);
+        assert_eq!(report.pending, 1);
+        assert_eq!((report.true_positives, report.false_positives, report.false_negatives), (1, 1, 1));
+        assert_eq!(report.precision, Some(0.5));
+        assert_eq!(report.recall, Some(0.5));
+        let brier = (0.1f64.powi(2) + 0.8f64.powi(2) + 0.8f64.powi(2)) / 3.0;
+        assert!((report.brier_score.unwrap() - brier).abs() < 1e-12);
+
+        let history = tracker.get_prediction_history("wan1");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].outcome, Some(true));
+        assert_eq!(history[1].outcome, None);
+
+        // Failures after the horizon don't count against old predictions
+        tracker.record_failure("wan2", 1200);
+        assert_eq!(tracker.report_for("wan2").false_positives, 1);
+        assert_eq!(tracker.report_for("wan1").true_negatives, 1);
+        assert_eq!(tracker.report_for("unknown"), CalibrationReport::default());
+    }
 }
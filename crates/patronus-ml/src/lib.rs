@@ -4,11 +4,24 @@
 //! 1. Anomaly Detection - Detect unusual traffic patterns
 //! 2. Predictive Failover - Predict link failures before they happen
 //! 3. Encrypted Traffic DPI - Classify encrypted traffic using ML
+//!
+//! Feature scaling ([`FeatureScaler`]) is shared as optional preprocessing,
+//! and a [`ModelSet`] loads the model versions the MLOps registry rolls out.
 
 pub mod anomaly;
 pub mod failover;
 pub mod dpi;
+pub mod scaler;
+pub mod model_set;
 
-pub use anomaly::{AnomalyDetector, AnomalyScore};
-pub use failover::{PredictiveFailover, FailoverPrediction};
-pub use dpi::{EncryptedDpi, TrafficClass};
+pub use anomaly::{AnomalyDetector, AnomalyScore, BaselineDrift, FeatureDrift, FeatureStats, StreamingConfig};
+pub use failover::{
+    CalibrationReport, CalibrationTracker, ContributionDirection, FailoverPrediction, FeatureContribution,
+    PredictionRecord, PredictiveFailover, TimeToFailure,
+};
+pub use dpi::{
+    ClassProbability, CustomClass, DpiBundle, DpiBundleMetadata, DpiClassification, DpiWeights, EncryptedDpi,
+    TrafficClass,
+};
+pub use scaler::{FeatureScaler, ScalingMethod};
+pub use model_set::{ModelSet, VersionedModel};
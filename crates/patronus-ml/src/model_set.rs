@@ -0,0 +1,205 @@
+//! Registry-Driven Model Loading
+//!
+//! A [`ModelSet`] holds the versions of one model type named by the MLOps
+//! registry's load plan and routes each entity to the production or
+//! canary version. Keep it current with
+//! [`ModelRegistry::sync_consumer`](patronus_mlops::ModelRegistry::sync_consumer).
+
+use anyhow::{Context, Result};
+use patronus_mlops::{LoadPlan, ModelConsumer, ModelType, ModelVersion};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::anomaly::AnomalyDetector;
+use crate::dpi::EncryptedDpi;
+
+/// A model that can be loaded from a registered version's bundle
+pub trait VersionedModel: Sized {
+    const MODEL_TYPE: ModelType;
+
+    fn load_version(path: &Path) -> Result<Self>;
+}
+
+impl VersionedModel for AnomalyDetector {
+    const MODEL_TYPE: ModelType = ModelType::AnomalyDetection;
+
+    fn load_version(path: &Path) -> Result<Self> {
+        AnomalyDetector::load(path)
+    }
+}
+
+impl VersionedModel for EncryptedDpi {
+    const MODEL_TYPE: ModelType = ModelType::EncryptedDpi;
+
+    fn load_version(path: &Path) -> Result<Self> {
+        let dpi = EncryptedDpi::new();
+        dpi.load_bundle(path)?;
+        Ok(dpi)
+    }
+}
+
+/// The loaded versions of one model type
+pub struct ModelSet<M> {
+    plan: Option<LoadPlan>,
+    models: HashMap<Uuid, M>,
+}
+
+impl<M: VersionedModel> ModelSet<M> {
+    pub fn new() -> Self {
+        Self {
+            plan: None,
+            models: HashMap::new(),
+        }
+    }
+
+    /// The plan the loaded versions follow, `None` before the first sync
+    pub fn plan(&self) -> Option<&LoadPlan> {
+        self.plan.as_ref()
+    }
+
+    pub fn get(&self, model_id: &Uuid) -> Option<&M> {
+        self.models.get(model_id)
+    }
+
+    /// The version whose result is used for `entity` (a flow, source or
+    /// device key)
+    ///
+    /// Record canary results on the plan's `canary_split` to compare the
+    /// canary with production.
+    pub fn route(&self, entity: &str) -> Option<(Uuid, &M)> {
+        let id = self.plan.as_ref()?.route(entity)?;
+        self.models.get(&id).map(|model| (id, model))
+    }
+
+    pub fn route_mut(&mut self, entity: &str) -> Option<(Uuid, &mut M)> {
+        let id = self.plan.as_ref()?.route(entity)?;
+        self.models.get_mut(&id).map(|model| (id, model))
+    }
+
+    /// The version scoring every entity alongside production, whose
+    /// results are only recorded
+    pub fn shadow(&self) -> Option<(Uuid, &M)> {
+        let id = self.plan.as_ref()?.shadow?;
+        self.models.get(&id).map(|model| (id, model))
+    }
+
+    pub fn shadow_mut(&mut self) -> Option<(Uuid, &mut M)> {
+        let id = self.plan.as_ref()?.shadow?;
+        self.models.get_mut(&id).map(|model| (id, model))
+    }
+}
+
+impl<M: VersionedModel> Default for ModelSet<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: VersionedModel> ModelConsumer for ModelSet<M> {
+    fn model_type(&self) -> ModelType {
+        M::MODEL_TYPE
+    }
+
+    fn apply_plan(&mut self, plan: &LoadPlan, models: &[&ModelVersion]) -> Result<()> {
+        for version in models {
+            if self.models.contains_key(&version.id) {
+                continue;
+            }
+            let path = version.bundle().ok_or_else(|| {
+                anyhow::anyhow!("{} {} has no bundle to load", version.model_name, version.version)
+            })?;
+            let model = M::load_version(path)
+                .with_context(|| format!("Failed to load {} {}", version.model_name, version.version))?;
+            tracing::info!("Loaded {:?} model {} ({})", M::MODEL_TYPE, version.version, version.id);
+            self.models.insert(version.id, model);
+        }
+
+        let wanted = plan.versions();
+        self.models.retain(|id, _| wanted.contains(id));
+        self.plan = Some(plan.clone());
+        Ok(())
+    }
+
+    fn loaded_versions(&self) -> Vec<Uuid> {
+        self.models.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::TrafficMetrics;
+    use patronus_mlops::{ModelRegistry, ModelStatus};
+
+    fn register(registry: &mut ModelRegistry, version: &str, bundle: &Path) -> Uuid {
+        let mut model = ModelVersion::new("anomaly-detector", version, ModelType::AnomalyDetection, "judy")
+            .with_bundle(bundle);
+        model.status = ModelStatus::Registered;
+        registry.register_model(model).unwrap()
+    }
+
+    fn metrics() -> TrafficMetrics {
+        TrafficMetrics {
+            bytes_per_second: 1_000_000.0,
+            packets_per_second: 1_000.0,
+            unique_src_ips: 10,
+            unique_dst_ips: 10,
+            avg_packet_size: 1000.0,
+            tcp_syn_ratio: 0.1,
+            udp_ratio: 0.2,
+            icmp_ratio: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_follows_registry_rollout() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = ModelRegistry::new();
+        let mut paths = Vec::new();
+        for version in ["v1", "v2"] {
+            let path = dir.path().join(format!("{}.json", version));
+            AnomalyDetector::new().save(&path).unwrap();
+            paths.push(path);
+        }
+        let v1 = register(&mut registry, "v1.0.0", &paths[0]);
+        let v2 = register(&mut registry, "v2.0.0", &paths[1]);
+        let broken = register(&mut registry, "v3.0.0", &dir.path().join("missing.json"));
+
+        let mut detectors = ModelSet::<AnomalyDetector>::new();
+        assert!(detectors.route("10.0.0.1").is_none());
+        registry.deploy_model(&v1).unwrap();
+        registry.sync_consumer("anomaly-detector", &mut detectors).unwrap();
+        assert_eq!(detectors.route("10.0.0.1").unwrap().0, v1);
+
+        registry.advance(&v2, ModelStatus::Shadow).unwrap();
+        registry.sync_consumer("anomaly-detector", &mut detectors).unwrap();
+        assert_eq!(detectors.route("10.0.0.1").unwrap().0, v1);
+        assert_eq!(detectors.shadow().unwrap().0, v2);
+        detectors.shadow_mut().unwrap().1.detect(metrics());
+
+        // Half the sources are scored by the canary, the same ones every time
+        registry.advance(&v2, ModelStatus::Canary(50)).unwrap();
+        registry.sync_consumer("anomaly-detector", &mut detectors).unwrap();
+        assert!(detectors.shadow().is_none());
+        let sources: Vec<String> = (0..100).map(|i| format!("10.0.0.{}", i)).collect();
+        let canary = sources.iter().filter(|s| detectors.route(s).unwrap().0 == v2).count();
+        assert!(canary > 20 && canary < 80, "canary scored {} of 100 sources", canary);
+        let source = sources.iter().find(|s| detectors.route(s).unwrap().0 == v2).unwrap();
+        assert_eq!(detectors.route_mut(source).unwrap().0, v2);
+        assert_eq!(registry.consumers_of(&v2), vec!["anomaly-detector"]);
+
+        // Withdrawing the canary unloads it
+        registry.withdraw(&ModelType::AnomalyDetection, "false positives", "ops").unwrap();
+        registry.sync_consumer("anomaly-detector", &mut detectors).unwrap();
+        assert_eq!(detectors.loaded_versions(), vec![v1]);
+        assert!(registry.consumers_of(&v2).is_empty());
+        assert_eq!(detectors.route(source).unwrap().0, v1);
+
+        // A version that fails to load leaves scoring on the previous plan
+        registry.advance(&broken, ModelStatus::Shadow).unwrap();
+        assert!(registry.sync_consumer("anomaly-detector", &mut detectors).is_err());
+        assert!(detectors.plan().unwrap().shadow.is_none());
+        assert_eq!(detectors.loaded_versions(), vec![v1]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Feature Scaling
+//!
+//! Raw traffic features span very different ranges (bytes per second in
+//! the millions next to ratios below one). A [`FeatureScaler`] is fitted
+//! once on training data and then applied unchanged at inference; it
+//! serializes with the model so both sides use the same parameters.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// How features are rescaled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingMethod {
+    /// Map the training range onto [0, 1]
+    MinMax,
+    /// Subtract the training mean and divide by its standard deviation
+    ZScore,
+}
+
+/// Per-feature affine rescaling, `(x - offset) / scale`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureScaler {
+    method: ScalingMethod,
+    offset: Vec<f64>,
+    scale: Vec<f64>,
+}
+
+impl FeatureScaler {
+    /// Fit to training samples, each a vector of the same length
+    ///
+    /// A feature that is constant in the training data is only shifted, so
+    /// it maps to zero rather than dividing by zero.
+    pub fn fit<I, V>(method: ScalingMethod, samples: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = V>,
+        V: AsRef<[f64]>,
+    {
+        let mut count = 0usize;
+        let mut sum: Vec<f64> = Vec::new();
+        let mut sum_sq: Vec<f64> = Vec::new();
+        let mut min: Vec<f64> = Vec::new();
+        let mut max: Vec<f64> = Vec::new();
+
+        for sample in samples {
+            let sample = sample.as_ref();
+            if count == 0 {
+                sum = vec![0.0; sample.len()];
+                sum_sq = vec![0.0; sample.len()];
+                min = vec![f64::INFINITY; sample.len()];
+                max = vec![f64::NEG_INFINITY; sample.len()];
+            } else if sample.len() != sum.len() {
+                anyhow::bail!(
+                    "Sample {} has {} features, expected {}",
+                    count, sample.len(), sum.len()
+                );
+            }
+
+            for (i, &x) in sample.iter().enumerate() {
+                if !x.is_finite() {
+                    anyhow::bail!("Sample {} has a non-finite value for feature {}", count, i);
+                }
+                sum[i] += x;
+                sum_sq[i] += x * x;
+                min[i] = min[i].min(x);
+                max[i] = max[i].max(x);
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            anyhow::bail!("Cannot fit a feature scaler without samples");
+        }
+
+        let n = count as f64;
+        let (offset, scale): (Vec<f64>, Vec<f64>) = match method {
+            ScalingMethod::MinMax => min.iter().zip(&max)
+                .map(|(&lo, &hi)| (lo, hi - lo))
+                .unzip(),
+            ScalingMethod::ZScore => sum.iter().zip(&sum_sq)
+                .map(|(&s, &sq)| {
+                    let mean = s / n;
+                    (mean, (sq / n - mean * mean).max(0.0).sqrt())
+                })
+                .unzip(),
+        };
+        let scale = scale.into_iter()
+            .map(|s| if s > f64::EPSILON { s } else { 1.0 })
+            .collect();
+
+        Ok(Self { method, offset, scale })
+    }
+
+    pub fn method(&self) -> ScalingMethod {
+        self.method
+    }
+
+    /// Number of features the scaler was fitted on
+    pub fn len(&self) -> usize {
+        self.offset.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset.is_empty()
+    }
+
+    fn check_len(&self, sample: &[f64]) -> Result<()> {
+        if sample.len() != self.len() {
+            anyhow::bail!(
+                "Scaler was fitted on {} features, got {}",
+                self.len(), sample.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Scale a sample in place
+    pub fn transform_in_place(&self, sample: &mut [f64]) -> Result<()> {
+        self.check_len(sample)?;
+        self.apply(sample);
+        Ok(())
+    }
+
+    pub fn transform(&self, sample: &[f64]) -> Result<Vec<f64>> {
+        let mut scaled = sample.to_vec();
+        self.transform_in_place(&mut scaled)?;
+        Ok(scaled)
+    }
+
+    /// Map a scaled sample back to raw feature values
+    pub fn inverse_transform(&self, sample: &[f64]) -> Result<Vec<f64>> {
+        self.check_len(sample)?;
+        Ok(sample.iter()
+            .zip(self.offset.iter().zip(&self.scale))
+            .map(|(x, (offset, scale))| x * scale + offset)
+            .collect())
+    }
+
+    /// Scale without a length check, for callers that checked at setup
+    pub(crate) fn apply(&self, sample: &mut [f64]) {
+        for (x, (offset, scale)) in sample.iter_mut().zip(self.offset.iter().zip(&self.scale)) {
+            *x = (*x - offset) / scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Features on wildly different scales: bytes/s, packets/s, a ratio
+    fn training() -> Vec<[f64; 3]> {
+        (0..200)
+            .map(|i| {
+                let t = i as f64;
+                [
+                    5_000_000.0 + (t * 0.37).sin() * 2_000_000.0,
+                    4_000.0 + (t * 1.3).cos() * 900.0,
+                    0.1 + (t * 0.11).sin().abs() * 0.05,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zscore_gives_zero_mean_unit_variance() {
+        let data = training();
+        let scaler = FeatureScaler::fit(ScalingMethod::ZScore, &data).unwrap();
+        let scaled: Vec<Vec<f64>> = data.iter().map(|s| scaler.transform(s).unwrap()).collect();
+
+        for i in 0..3 {
+            let n = scaled.len() as f64;
+            let mean = scaled.iter().map(|s| s[i]).sum::<f64>() / n;
+            let variance = scaled.iter().map(|s| (s[i] - mean).powi(2)).sum::<f64>() / n;
+            assert!(mean.abs() < 1e-9, "feature {} mean {}", i, mean);
+            assert!((variance - 1.0).abs() < 1e-9, "feature {} variance {}", i, variance);
+        }
+
+        let back = scaler.inverse_transform(&scaled[7]).unwrap();
+        for (a, b) in back.iter().zip(&data[7]) {
+            assert!((a - b).abs() < 1e-6 * b.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn test_minmax_maps_training_range_to_unit_interval() {
+        let data = training();
+        let scaler = FeatureScaler::fit(ScalingMethod::MinMax, &data).unwrap();
+
+        for sample in &data {
+            for x in scaler.transform(sample).unwrap() {
+                assert!((-1e-12..=1.0 + 1e-12).contains(&x));
+            }
+        }
+
+        // A constant feature maps to zero instead of dividing by zero
+        let constant = FeatureScaler::fit(ScalingMethod::MinMax, [[1.0, 3.0], [2.0, 3.0]]).unwrap();
+        assert_eq!(constant.transform(&[1.5, 3.0]).unwrap(), vec![0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_scaler_roundtrips_and_checks_shape() {
+        let scaler = FeatureScaler::fit(ScalingMethod::ZScore, training()).unwrap();
+        let json = serde_json::to_string(&scaler).unwrap();
+        let loaded: FeatureScaler = serde_json::from_str(&json).unwrap();
+
+        let sample = [6_100_000.0, 3_500.0, 0.12];
+        assert_eq!(loaded.transform(&sample).unwrap(), scaler.transform(&sample).unwrap());
+        assert!(json.contains("\"z_score\""));
+
+        assert!(scaler.transform(&[1.0, 2.0]).is_err());
+        assert!(FeatureScaler::fit(ScalingMethod::ZScore, Vec::<Vec<f64>>::new()).is_err());
+        assert!(FeatureScaler::fit(ScalingMethod::ZScore, [vec![1.0, 2.0], vec![1.0]]).is_err());
+        assert!(FeatureScaler::fit(ScalingMethod::MinMax, [[f64::NAN]]).is_err());
+    }
+}
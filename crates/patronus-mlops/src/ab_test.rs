@@ -0,0 +1,193 @@
+//! A/B model deployment
+//!
+//! Two versions of a model serve side by side with a percentage of traffic
+//! going to the challenger (B). Requests are assigned by a stable hash of
+//! an entity key (a flow, site or device ID), so the same entity always
+//! reaches the same variant while the split is unchanged. Each variant's
+//! latency, errors and labelled outcomes are collected for comparison, and
+//! promotion or rollback replaces the split in one step.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::registry::ModelType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Variant {
+    /// The incumbent
+    A,
+    /// The challenger
+    B,
+}
+
+/// Which model version handles a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub variant: Variant,
+    pub model_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Split {
+    variant_a: Uuid,
+    variant_b: Uuid,
+    /// Share of traffic sent to B, 0 to 100
+    b_percent: u8,
+    /// Set once promotion or rollback settled the test
+    winner: Option<Variant>,
+}
+
+/// Performance of one variant
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VariantStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub total_latency_ms: f64,
+    /// Predictions whose true outcome was later reported
+    pub labelled: u64,
+    pub correct: u64,
+}
+
+impl VariantStats {
+    pub fn mean_latency_ms(&self) -> Option<f64> {
+        let served = self.requests - self.errors;
+        (served > 0).then(|| self.total_latency_ms / served as f64)
+    }
+
+    pub fn error_rate(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.errors as f64 / self.requests as f64)
+    }
+
+    pub fn accuracy(&self) -> Option<f64> {
+        (self.labelled > 0).then(|| self.correct as f64 / self.labelled as f64)
+    }
+}
+
+/// Side-by-side statistics of both variants
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AbComparison {
+    pub variant_a: VariantStats,
+    pub variant_b: VariantStats,
+    pub b_percent: u8,
+}
+
+/// Routes inference requests between two versions of a model
+///
+/// Created by [`ModelRegistry::start_ab_deployment`](crate::ModelRegistry::start_ab_deployment)
+/// and shared with whatever serves inference.
+#[derive(Debug)]
+pub struct AbDeployment {
+    pub id: Uuid,
+    pub model_type: ModelType,
+    split: RwLock<Split>,
+    stats: Mutex<[VariantStats; 2]>,
+}
+
+impl AbDeployment {
+    pub(crate) fn new(model_type: ModelType, variant_a: Uuid, variant_b: Uuid, b_percent: u8) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            model_type,
+            split: RwLock::new(Split { variant_a, variant_b, b_percent: b_percent.min(100), winner: None }),
+            stats: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Pick the variant for `entity`
+    pub fn route(&self, entity: &str) -> Assignment {
+        let split = *self.split.read().unwrap();
+        let variant = if bucket(&self.id, entity) < split.b_percent {
+            Variant::B
+        } else {
+            Variant::A
+        };
+        let model_id = match variant {
+            Variant::A => split.variant_a,
+            Variant::B => split.variant_b,
+        };
+        Assignment { variant, model_id }
+    }
+
+    pub fn b_percent(&self) -> u8 {
+        self.split.read().unwrap().b_percent
+    }
+
+    /// The variant promotion or rollback settled on, if any
+    pub fn winner(&self) -> Option<Variant> {
+        self.split.read().unwrap().winner
+    }
+
+    /// Change the challenger's share; entities only move towards the
+    /// variant whose share grew
+    pub fn set_split(&self, b_percent: u8) -> anyhow::Result<()> {
+        let mut split = self.split.write().unwrap();
+        if let Some(winner) = split.winner {
+            anyhow::bail!("A/B deployment {} already concluded in favour of {:?}", self.id, winner);
+        }
+        split.b_percent = b_percent.min(100);
+        tracing::info!("A/B deployment {} now sends {}% to B", self.id, split.b_percent);
+        Ok(())
+    }
+
+    pub fn model_id(&self, variant: Variant) -> Uuid {
+        let split = self.split.read().unwrap();
+        match variant {
+            Variant::A => split.variant_a,
+            Variant::B => split.variant_b,
+        }
+    }
+
+    /// Record a served inference and how long it took
+    pub fn record_success(&self, variant: Variant, latency_ms: f64) {
+        let mut stats = self.stats.lock().unwrap();
+        let stats = &mut stats[variant as usize];
+        stats.requests += 1;
+        stats.total_latency_ms += latency_ms;
+    }
+
+    pub fn record_error(&self, variant: Variant) {
+        let mut stats = self.stats.lock().unwrap();
+        let stats = &mut stats[variant as usize];
+        stats.requests += 1;
+        stats.errors += 1;
+    }
+
+    /// Record whether a prediction turned out to be right
+    pub fn record_outcome(&self, variant: Variant, correct: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        let stats = &mut stats[variant as usize];
+        stats.labelled += 1;
+        stats.correct += u64::from(correct);
+    }
+
+    pub fn compare(&self) -> AbComparison {
+        let [variant_a, variant_b] = self.stats.lock().unwrap().clone();
+        AbComparison { variant_a, variant_b, b_percent: self.b_percent() }
+    }
+
+    /// Send all traffic to `winner`, returning its model
+    pub(crate) fn conclude(&self, winner: Variant) -> Uuid {
+        let mut split = self.split.write().unwrap();
+        split.b_percent = match winner {
+            Variant::A => 0,
+            Variant::B => 100,
+        };
+        split.winner = Some(winner);
+        match winner {
+            Variant::A => split.variant_a,
+            Variant::B => split.variant_b,
+        }
+    }
+}
+
+/// Stable bucket 0..100 of `entity` within one deployment
+fn bucket(deployment: &Uuid, entity: &str) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(deployment.as_bytes())
+        .chain_update(entity.as_bytes())
+        .finalize();
+    let value = u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"));
+    (value % 100) as u8
+}
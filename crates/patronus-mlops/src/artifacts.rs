@@ -0,0 +1,163 @@
+//! Pipeline Stage Artifacts
+//!
+//! Outputs of training pipeline stages, stored under a content address
+//! derived from what the stage consumed, so re-running a configuration
+//! only executes the stages whose inputs changed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What a pipeline stage produced
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StageArtifact {
+    pub metrics: HashMap<String, f64>,
+    /// Opaque output handed to later stages, e.g. a serialized feature
+    /// matrix or model
+    #[serde(default)]
+    pub data: Vec<u8>,
+}
+
+impl StageArtifact {
+    pub fn new(metrics: HashMap<String, f64>, data: Vec<u8>) -> Self {
+        Self { metrics, data }
+    }
+
+    /// An artifact that is only metrics, for executors that keep their
+    /// outputs themselves
+    pub fn from_metrics(metrics: HashMap<String, f64>) -> Self {
+        Self { metrics, data: Vec::new() }
+    }
+
+    /// SHA-256 of the metrics (in key order) and data, hex encoded
+    pub fn hash(&self) -> String {
+        let metrics: BTreeMap<&String, u64> = self.metrics.iter().map(|(k, v)| (k, v.to_bits())).collect();
+        let mut hasher = Sha256::new();
+        for (name, bits) in metrics {
+            hasher.update((name.len() as u64).to_be_bytes());
+            hasher.update(name.as_bytes());
+            hasher.update(bits.to_be_bytes());
+        }
+        hasher.update(&self.data);
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Content-addressed storage for stage outputs
+pub trait ArtifactStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<StageArtifact>>;
+    fn put(&self, key: &str, artifact: &StageArtifact) -> Result<()>;
+}
+
+/// Keeps artifacts for the lifetime of the process
+#[derive(Debug, Default)]
+pub struct MemoryArtifactStore {
+    artifacts: Mutex<HashMap<String, StageArtifact>>,
+}
+
+impl MemoryArtifactStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.artifacts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ArtifactStore for MemoryArtifactStore {
+    fn get(&self, key: &str) -> Result<Option<StageArtifact>> {
+        Ok(self.artifacts.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, artifact: &StageArtifact) -> Result<()> {
+        self.artifacts.lock().unwrap().insert(key.to_string(), artifact.clone());
+        Ok(())
+    }
+}
+
+/// Keeps artifacts in a directory, as `<key>.json` (metrics) and
+/// `<key>.bin` (data), so they survive restarts
+#[derive(Debug, Clone)]
+pub struct DirArtifactStore {
+    dir: PathBuf,
+}
+
+impl DirArtifactStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create artifact directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, key: &str, extension: &str) -> Result<PathBuf> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid artifact key: {}", key);
+        }
+        Ok(self.dir.join(format!("{}.{}", key, extension)))
+    }
+}
+
+impl ArtifactStore for DirArtifactStore {
+    fn get(&self, key: &str) -> Result<Option<StageArtifact>> {
+        let metrics_path = self.path(key, "json")?;
+        // The metrics are written last, so their presence marks a complete
+        // artifact
+        let metrics = match std::fs::read(&metrics_path) {
+            Ok(metrics) => metrics,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", metrics_path.display())),
+        };
+        let data_path = self.path(key, "bin")?;
+        let data = std::fs::read(&data_path)
+            .with_context(|| format!("Failed to read {}", data_path.display()))?;
+        Ok(Some(StageArtifact { metrics: serde_json::from_slice(&metrics)?, data }))
+    }
+
+    fn put(&self, key: &str, artifact: &StageArtifact) -> Result<()> {
+        std::fs::write(self.path(key, "bin")?, &artifact.data)?;
+        let metrics_path = self.path(key, "json")?;
+        let staging = metrics_path.with_extension("json.tmp");
+        std::fs::write(&staging, serde_json::to_vec(&artifact.metrics)?)?;
+        std::fs::rename(&staging, &metrics_path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_ignores_metric_order() {
+        let a = StageArtifact::new(HashMap::from([("a".to_string(), 1.0), ("b".to_string(), 2.0)]), vec![1, 2]);
+        let mut b = StageArtifact::new(HashMap::from([("b".to_string(), 2.0), ("a".to_string(), 1.0)]), vec![1, 2]);
+        assert_eq!(a.hash(), b.hash());
+
+        b.data.push(3);
+        assert_ne!(a.hash(), b.hash());
+    }
+
+    #[test]
+    fn test_dir_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("patronus-artifacts-{}", uuid::Uuid::new_v4()));
+        let store = DirArtifactStore::new(&dir).unwrap();
+        let artifact = StageArtifact::new(HashMap::from([("rows".to_string(), 1200.0)]), b"features".to_vec());
+        let key = artifact.hash();
+
+        assert_eq!(store.get(&key).unwrap(), None);
+        store.put(&key, &artifact).unwrap();
+        assert_eq!(store.get(&key).unwrap(), Some(artifact));
+        assert!(store.get("../etc/passwd").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
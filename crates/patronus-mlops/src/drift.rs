@@ -0,0 +1,192 @@
+//! Data Drift Detection
+//!
+//! A [`DriftBaseline`] bins each feature of the training data at its
+//! quantiles. Recent observations are binned the same way and the two
+//! histograms compared with the population stability index, KL
+//! divergence or the Kolmogorov-Smirnov statistic. The model's drift score is the worst of its features, so a
+//! shift in a single input is enough to flag it.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Floor for empty bins, which would otherwise make both measures infinite
+const EMPTY_BIN: f64 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DriftMethod {
+    /// Population stability index; above 0.1 is a moderate and above 0.25
+    /// a significant shift
+    Psi,
+    /// KL divergence of the recent distribution from the training one
+    KlDivergence,
+    /// Largest gap between the two cumulative distributions, 0 to 1,
+    /// evaluated at the bin edges
+    KolmogorovSmirnov,
+}
+
+impl DriftMethod {
+    /// Label used in exported metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DriftMethod::Psi => "psi",
+            DriftMethod::KlDivergence => "kl",
+            DriftMethod::KolmogorovSmirnov => "ks",
+        }
+    }
+}
+
+/// Training distribution of one feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureBaseline {
+    pub name: String,
+    /// Upper bounds of every bin but the last
+    pub bin_edges: Vec<f64>,
+    pub proportions: Vec<f64>,
+    #[serde(default)]
+    pub mean: f64,
+    #[serde(default)]
+    pub variance: f64,
+}
+
+impl FeatureBaseline {
+    fn bin(&self, value: f64) -> usize {
+        self.bin_edges.partition_point(|edge| *edge <= value)
+    }
+
+    fn histogram(&self, values: impl Iterator<Item = f64>) -> Vec<f64> {
+        let mut counts = vec![0.0f64; self.bin_edges.len() + 1];
+        let mut total = 0.0;
+        for value in values {
+            counts[self.bin(value)] += 1.0;
+            total += 1.0;
+        }
+        counts.iter().map(|c| (c / total).max(EMPTY_BIN)).collect()
+    }
+}
+
+/// Training distributions of a model's input features
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftBaseline {
+    pub features: Vec<FeatureBaseline>,
+    pub sample_count: usize,
+}
+
+impl DriftBaseline {
+    /// Bin training `samples` (one row per observation, one column per
+    /// feature in `names`) into at most `bins` quantile bins per feature
+    pub fn from_samples(names: &[&str], samples: &[Vec<f64>], bins: usize) -> Result<Self> {
+        if samples.is_empty() {
+            anyhow::bail!("Drift baseline needs at least one training sample");
+        }
+        if bins < 2 {
+            anyhow::bail!("Drift baseline needs at least 2 bins, got {}", bins);
+        }
+        check_rows(samples, names.len())?;
+
+        let features = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let mut column: Vec<f64> = samples.iter().map(|row| row[i]).collect();
+                column.sort_by(f64::total_cmp);
+                let mean = column.iter().sum::<f64>() / column.len() as f64;
+                let variance = column.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / column.len() as f64;
+
+                let mut bin_edges: Vec<f64> = (1..bins)
+                    .map(|q| column[q * column.len() / bins])
+                    .collect();
+                bin_edges.dedup();
+
+                let mut feature = FeatureBaseline {
+                    name: name.to_string(),
+                    bin_edges,
+                    proportions: Vec::new(),
+                    mean,
+                    variance,
+                };
+                feature.proportions = feature.histogram(column.into_iter());
+                feature
+            })
+            .collect();
+
+        Ok(Self {
+            features,
+            sample_count: samples.len(),
+        })
+    }
+
+    /// Compare recent observations, laid out like the training samples
+    pub fn compare(&self, recent: &[Vec<f64>], method: DriftMethod) -> Result<DriftReport> {
+        if recent.is_empty() {
+            anyhow::bail!("No recent samples to compare against the baseline");
+        }
+        check_rows(recent, self.features.len())?;
+
+        let feature_scores: Vec<(String, f64)> = self
+            .features
+            .iter()
+            .enumerate()
+            .map(|(i, feature)| {
+                let actual = feature.histogram(recent.iter().map(|row| row[i]));
+                let pairs = actual.iter().zip(&feature.proportions);
+                let score = match method {
+                    DriftMethod::Psi => pairs.map(|(a, e)| (a - e) * (a / e).ln()).sum::<f64>().max(0.0),
+                    DriftMethod::KlDivergence => pairs.map(|(a, e)| a * (a / e).ln()).sum::<f64>().max(0.0),
+                    DriftMethod::KolmogorovSmirnov => {
+                        let (mut cum_a, mut cum_e, mut gap) = (0.0, 0.0, 0.0f64);
+                        for (a, e) in pairs {
+                            cum_a += a;
+                            cum_e += e;
+                            gap = gap.max((cum_a - cum_e).abs());
+                        }
+                        gap.min(1.0)
+                    }
+                };
+                (feature.name.clone(), score)
+            })
+            .collect();
+
+        let score = feature_scores.iter().map(|(_, s)| *s).fold(0.0, f64::max);
+        Ok(DriftReport {
+            method,
+            score,
+            feature_scores,
+            sample_count: recent.len(),
+            computed_at: Utc::now(),
+        })
+    }
+}
+
+fn check_rows(samples: &[Vec<f64>], width: usize) -> Result<()> {
+    if let Some((i, row)) = samples.iter().enumerate().find(|(_, row)| row.len() != width) {
+        anyhow::bail!("Sample {} has {} features, expected {}", i, row.len(), width);
+    }
+    Ok(())
+}
+
+/// Outcome of one drift comparison
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftReport {
+    pub method: DriftMethod,
+    /// Highest per-feature score
+    pub score: f64,
+    pub feature_scores: Vec<(String, f64)>,
+    pub sample_count: usize,
+    pub computed_at: DateTime<Utc>,
+}
+
+impl DriftReport {
+    /// Score of one feature
+    pub fn feature_score(&self, name: &str) -> Option<f64> {
+        self.feature_scores.iter().find(|(n, _)| n == name).map(|(_, s)| *s)
+    }
+
+    /// The feature that drifted most
+    pub fn worst_feature(&self) -> Option<&str> {
+        self.feature_scores
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -1,11 +1,22 @@
 //! MLOps Pipeline
 //!
-//! Model registry, training pipelines, and automated retraining
+//! Model registry with staged (shadow and canary) rollout, training pipelines
+//! with cached, resumable stages, A/B deployment and automated retraining,
+//! including on data drift
 
+pub mod ab_test;
+pub mod artifacts;
+pub mod drift;
 pub mod registry;
 pub mod pipeline;
 pub mod retraining;
 
-pub use registry::{ModelRegistry, ModelVersion, ModelType, ModelStatus, ModelMetadata};
-pub use pipeline::{TrainingPipeline, PipelineExecutor, TrainingConfig, PipelineRun, PipelineStatus};
-pub use retraining::{RetrainingManager, RetrainingTrigger, TriggerType, PerformanceThresholds};
+pub use ab_test::{AbComparison, AbDeployment, Assignment, Variant, VariantStats};
+pub use artifacts::{ArtifactStore, DirArtifactStore, MemoryArtifactStore, StageArtifact};
+pub use registry::{
+    DeploymentAction, DeploymentEvent, LoadPlan, ModelConsumer, ModelRegistry, ModelVersion, ModelType, ModelStatus,
+    ModelMetadata, BUNDLE_TAG,
+};
+pub use pipeline::{TrainingPipeline, PipelineExecutor, TrainingConfig, TrainingFeatures, PipelineRun, PipelineStage, PipelineStatus};
+pub use drift::{DriftBaseline, DriftMethod, DriftReport, FeatureBaseline};
+pub use retraining::{DriftCheck, RetrainingManager, RetrainingTrigger, TriggerType, PerformanceThresholds};
@@ -2,10 +2,22 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use anyhow::Result;
 use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+
+use crate::artifacts::{ArtifactStore, StageArtifact};
+use crate::drift::DriftBaseline;
+use crate::registry::{ModelRegistry, ModelStatus};
+use crate::retraining::{DriftCheck, RetrainingManager};
+
+/// Quantile bins per feature in drift baselines captured from training data
+const BASELINE_BINS: usize = 10;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PipelineStage {
@@ -18,6 +30,26 @@ pub enum PipelineStage {
     Deployment,
 }
 
+impl PipelineStage {
+    /// The parts of `config` this stage's output depends on by default;
+    /// everything else reaches it through the outputs of earlier stages
+    pub fn config_inputs(&self, config: &TrainingConfig) -> serde_json::Value {
+        match self {
+            PipelineStage::DataCollection => serde_json::json!({ "data": config.training_data_path }),
+            PipelineStage::DataPreprocessing => serde_json::json!({ "validation_split": config.validation_split }),
+            PipelineStage::Training => serde_json::json!({
+                "hyperparameters": config.hyperparameters,
+                "epochs": config.epochs,
+                "batch_size": config.batch_size,
+            }),
+            PipelineStage::Deployment => serde_json::json!({ "model": config.model_name, "version": config.version }),
+            PipelineStage::FeatureEngineering | PipelineStage::Validation | PipelineStage::Testing => {
+                serde_json::Value::Null
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PipelineStatus {
     Pending,
@@ -35,6 +67,16 @@ pub struct StageResult {
     pub completed_at: Option<DateTime<Utc>>,
     pub error: Option<String>,
     pub metrics: HashMap<String, f64>,
+    /// Content address of the stage's inputs; unset for stages that are
+    /// never cached
+    #[serde(default)]
+    pub cache_key: Option<String>,
+    /// Hash of what the stage produced
+    #[serde(default)]
+    pub artifact_hash: Option<String>,
+    /// The output was reused from the artifact store, not executed
+    #[serde(default)]
+    pub cache_hit: bool,
 }
 
 impl StageResult {
@@ -46,6 +88,9 @@ impl StageResult {
             completed_at: None,
             error: None,
             metrics: HashMap::new(),
+            cache_key: None,
+            artifact_hash: None,
+            cache_hit: false,
         }
     }
 
@@ -75,6 +120,39 @@ pub struct TrainingConfig {
     pub validation_split: f64,
     pub epochs: u32,
     pub batch_size: u32,
+    #[serde(default)]
+    pub budget: ResourceBudget,
+}
+
+/// Limits on what a run may consume; unset limits are not enforced
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceBudget {
+    /// CPU time across all stages
+    pub max_cpu_seconds: Option<f64>,
+    /// Peak resident memory
+    pub max_memory_mb: Option<u64>,
+    /// Wall-clock time from the start of the run
+    pub max_duration_secs: Option<u64>,
+}
+
+/// Resources consumed by a run so far, as measured by the executor
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub cpu_seconds: f64,
+    pub memory_mb: u64,
+}
+
+impl ResourceBudget {
+    /// Why `usage` is over budget, if it is
+    pub fn exceeded_by(&self, usage: &ResourceUsage) -> Option<String> {
+        if let Some(max) = self.max_cpu_seconds.filter(|max| usage.cpu_seconds > *max) {
+            return Some(format!("CPU budget of {:.1}s exceeded ({:.1}s used)", max, usage.cpu_seconds));
+        }
+        if let Some(max) = self.max_memory_mb.filter(|max| usage.memory_mb > *max) {
+            return Some(format!("Memory budget of {} MB exceeded ({} MB used)", max, usage.memory_mb));
+        }
+        None
+    }
 }
 
 impl TrainingConfig {
@@ -87,9 +165,15 @@ impl TrainingConfig {
             validation_split: 0.2,
             epochs: 100,
             batch_size: 32,
+            budget: ResourceBudget::default(),
         }
     }
 
+    pub fn with_budget(mut self, budget: ResourceBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
     pub fn with_hyperparameter(
         mut self,
         key: impl Into<String>,
@@ -114,6 +198,19 @@ pub struct PipelineRun {
     pub started_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
     pub created_by: String,
+    /// Registry entry of the model this run produces
+    #[serde(default)]
+    pub model_id: Option<Uuid>,
+    /// Why the run failed or was cancelled
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+    /// Context recorded with the run, e.g. the drift that started it
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+    /// Distribution of the training features, captured when the run
+    /// completes
+    #[serde(default)]
+    pub drift_baseline: Option<DriftBaseline>,
 }
 
 impl PipelineRun {
@@ -134,6 +231,10 @@ impl PipelineRun {
             started_at: Utc::now(),
             completed_at: None,
             created_by: created_by.into(),
+            model_id: None,
+            failure_reason: None,
+            metadata: HashMap::new(),
+            drift_baseline: None,
         }
     }
 
@@ -145,19 +246,122 @@ impl PipelineRun {
         self.stages.iter().find(|s| s.status == PipelineStatus::Running)
     }
 
+    /// Stages that actually ran, as opposed to being served from cache
+    pub fn executed_stages(&self) -> Vec<&PipelineStage> {
+        self.stages.iter()
+            .filter(|s| s.started_at.is_some() && !s.cache_hit)
+            .map(|s| &s.stage)
+            .collect()
+    }
+
+    /// Stages whose output was reused from the artifact store
+    pub fn cached_stages(&self) -> Vec<&PipelineStage> {
+        self.stages.iter().filter(|s| s.cache_hit).map(|s| &s.stage).collect()
+    }
+
     pub fn is_complete(&self) -> bool {
         self.status == PipelineStatus::Completed || self.status == PipelineStatus::Failed
     }
+
+    fn finish(&mut self, status: PipelineStatus, reason: Option<String>) {
+        self.status = status;
+        self.failure_reason = reason;
+        self.completed_at = Some(Utc::now());
+    }
+}
+
+/// The feature matrix a model was trained on
+#[derive(Debug, Clone, Default)]
+pub struct TrainingFeatures {
+    pub names: Vec<String>,
+    /// One row per observation, one column per name
+    pub samples: Vec<Vec<f64>>,
 }
 
 #[async_trait]
 pub trait PipelineExecutor: Send + Sync {
     async fn execute_stage(&self, stage: &PipelineStage, config: &TrainingConfig) -> Result<HashMap<String, f64>>;
+
+    /// Run a stage given the outputs of the stages before it, in stage
+    /// order; the default runs [`execute_stage`](Self::execute_stage) and
+    /// produces metrics only
+    async fn execute_step(
+        &self,
+        stage: &PipelineStage,
+        config: &TrainingConfig,
+        _upstream: &[StageArtifact],
+    ) -> Result<StageArtifact> {
+        Ok(StageArtifact::from_metrics(self.execute_stage(stage, config).await?))
+    }
+
+    /// The configuration a stage's output depends on, hashed into its
+    /// cache key together with the upstream outputs
+    fn stage_inputs(&self, stage: &PipelineStage, config: &TrainingConfig) -> serde_json::Value {
+        stage.config_inputs(config)
+    }
+
+    /// Whether a stage's output may be reused; deployment has side
+    /// effects, so it always runs by default
+    fn cacheable(&self, stage: &PipelineStage) -> bool {
+        *stage != PipelineStage::Deployment
+    }
+
+    /// Features the completed run trained on, to capture as its drift
+    /// baseline; `None` if the executor does not keep them
+    async fn training_features(&self, _config: &TrainingConfig) -> Option<TrainingFeatures> {
+        None
+    }
+
+    /// Resources used by the run so far; `None` if the executor cannot
+    /// measure them, in which case only the time budget is enforced
+    async fn resource_usage(&self) -> Option<ResourceUsage> {
+        None
+    }
+
+    /// Free whatever an aborted stage was holding (processes, GPU memory,
+    /// scratch files)
+    async fn cleanup(&self, _stage: &PipelineStage, _config: &TrainingConfig) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Cancels runs while [`TrainingPipeline::execute_run`] holds the pipeline
+#[derive(Clone)]
+pub struct PipelineCanceller {
+    signals: Arc<Mutex<HashMap<Uuid, watch::Sender<bool>>>>,
+}
+
+impl PipelineCanceller {
+    /// Ask a pending or running run to stop, returning whether it was
+    /// still cancellable
+    pub fn cancel(&self, run_id: &Uuid) -> bool {
+        match self.signals.lock().unwrap().get(run_id) {
+            Some(signal) => {
+                signal.send_replace(true);
+                tracing::info!("Cancellation requested for pipeline run: {}", run_id);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+enum StageOutcome {
+    Finished(Result<StageArtifact>),
+    OverBudget(String),
+    Cancelled,
 }
 
 pub struct TrainingPipeline<E: PipelineExecutor> {
     runs: HashMap<Uuid, PipelineRun>,
     executor: E,
+    canceller: PipelineCanceller,
+    registry: Option<Arc<Mutex<ModelRegistry>>>,
+    retraining: Option<Arc<Mutex<RetrainingManager>>>,
+    artifacts: Option<Arc<dyn ArtifactStore>>,
+    /// Outputs of the completed stages of failed runs, kept for resuming
+    outputs: HashMap<Uuid, Vec<StageArtifact>>,
+    usage_poll_interval: Duration,
 }
 
 impl<E: PipelineExecutor> TrainingPipeline<E> {
@@ -165,74 +369,309 @@ impl<E: PipelineExecutor> TrainingPipeline<E> {
         Self {
             runs: HashMap::new(),
             executor,
+            canceller: PipelineCanceller { signals: Arc::new(Mutex::new(HashMap::new())) },
+            registry: None,
+            retraining: None,
+            artifacts: None,
+            outputs: HashMap::new(),
+            usage_poll_interval: Duration::from_secs(1),
         }
     }
 
+    /// Mark the models of failed and cancelled runs as failed in `registry`
+    pub fn with_registry(mut self, registry: Arc<Mutex<ModelRegistry>>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Install the drift baseline of each completed run in `manager`, so
+    /// drift is measured against the most recently trained version
+    pub fn with_retraining(mut self, manager: Arc<Mutex<RetrainingManager>>) -> Self {
+        self.retraining = Some(manager);
+        self
+    }
+
+    /// Cache stage outputs in `store` and reuse them for stages whose
+    /// inputs are unchanged
+    pub fn with_artifact_store(mut self, store: Arc<dyn ArtifactStore>) -> Self {
+        self.artifacts = Some(store);
+        self
+    }
+
+    /// How often the executor is asked for resource usage during a stage
+    pub fn with_usage_poll_interval(mut self, interval: Duration) -> Self {
+        self.usage_poll_interval = interval;
+        self
+    }
+
+    pub fn canceller(&self) -> PipelineCanceller {
+        self.canceller.clone()
+    }
+
     pub fn create_run(&mut self, config: TrainingConfig, created_by: impl Into<String>) -> Uuid {
         let run = PipelineRun::new(config, created_by);
         let run_id = run.id;
         self.runs.insert(run_id, run);
+        self.canceller.signals.lock().unwrap().insert(run_id, watch::channel(false).0);
         tracing::info!("Created pipeline run: {}", run_id);
         run_id
     }
 
+    /// Create a run for a drift trigger that fired, recording the drift
+    /// report and streak under the "drift" metadata key
+    pub fn create_drift_run(&mut self, config: TrainingConfig, check: &DriftCheck) -> Result<Uuid> {
+        if !check.triggered {
+            anyhow::bail!("Drift check of {} did not trigger retraining", check.model_name);
+        }
+        let evidence = serde_json::to_value(check)?;
+        let run_id = self.create_run(config, "drift-trigger");
+        let run = self.runs.get_mut(&run_id).expect("run was just created");
+        run.metadata.insert("trigger".to_string(), serde_json::json!("DataDrift"));
+        run.metadata.insert("drift".to_string(), evidence);
+        Ok(run_id)
+    }
+
+    /// Record the registry entry the run is training
+    pub fn attach_model(&mut self, run_id: &Uuid, model_id: Uuid) -> Result<()> {
+        let run = self.runs.get_mut(run_id)
+            .ok_or_else(|| anyhow::anyhow!("Run not found"))?;
+        run.model_id = Some(model_id);
+        Ok(())
+    }
+
     pub fn get_run(&self, run_id: &Uuid) -> Option<&PipelineRun> {
         self.runs.get(run_id)
     }
 
+    /// Run every stage in order
+    ///
+    /// The run fails if a stage fails or the run goes over its budget, and
+    /// stops early if cancelled through [`canceller`](Self::canceller). An
+    /// aborted stage is dropped and the executor asked to clean up after it.
+    /// With an [artifact store](Self::with_artifact_store), stages whose
+    /// cache key is already stored are not executed.
     pub async fn execute_run(&mut self, run_id: &Uuid) -> Result<()> {
         let run = self.runs.get_mut(run_id)
             .ok_or_else(|| anyhow::anyhow!("Run not found"))?;
+        if run.status != PipelineStatus::Pending {
+            anyhow::bail!("Run is {:?}, not pending", run.status);
+        }
+        self.run_stages(run_id, 0).await
+    }
+
+    /// Continue a failed run from its first unfinished stage
+    ///
+    /// Earlier stages keep their results; their outputs come from memory or,
+    /// failing that, the artifact store, and if neither has them the run
+    /// restarts at the first missing one. The time budget starts again.
+    pub async fn resume(&mut self, run_id: &Uuid) -> Result<()> {
+        let run = self.runs.get_mut(run_id)
+            .ok_or_else(|| anyhow::anyhow!("Run not found"))?;
+        if run.status != PipelineStatus::Failed {
+            anyhow::bail!("Run is {:?}, only failed runs can be resumed", run.status);
+        }
 
+        let finished = run.stages.iter()
+            .position(|s| s.status != PipelineStatus::Completed)
+            .unwrap_or(run.stages.len());
+        let outputs = self.outputs.entry(*run_id).or_default();
+        outputs.truncate(finished);
+        for stage in &run.stages[outputs.len()..finished] {
+            let stored = match (&self.artifacts, &stage.cache_key) {
+                (Some(store), Some(key)) => store.get(key).ok().flatten(),
+                _ => None,
+            };
+            match stored {
+                Some(artifact) if Some(artifact.hash()) == stage.artifact_hash => outputs.push(artifact),
+                _ => break,
+            }
+        }
+        let first = outputs.len();
+
+        for stage in &mut run.stages[first..] {
+            *stage = StageResult::new(stage.stage.clone());
+        }
+        run.status = PipelineStatus::Pending;
+        run.failure_reason = None;
+        run.completed_at = None;
+        tracing::info!("Resuming pipeline run {} at stage {}", run_id, first);
+
+        if let (Some(registry), Some(model_id)) = (&self.registry, run.model_id) {
+            let mut registry = registry.lock().unwrap();
+            if registry.get_model(&model_id).is_some_and(|m| m.status == ModelStatus::Failed) {
+                registry.update_status(&model_id, ModelStatus::Training)?;
+            }
+        }
+        self.canceller.signals.lock().unwrap().insert(*run_id, watch::channel(false).0);
+        self.run_stages(run_id, first).await
+    }
+
+    async fn run_stages(&mut self, run_id: &Uuid, first: usize) -> Result<()> {
+        let run = self.runs.get_mut(run_id)
+            .ok_or_else(|| anyhow::anyhow!("Run not found"))?;
+        let mut cancel = self.canceller.signals.lock().unwrap().get(run_id)
+            .map(|signal| signal.subscribe())
+            .ok_or_else(|| anyhow::anyhow!("Run is not cancellable"))?;
+
+        let mut outputs = self.outputs.remove(run_id).unwrap_or_default();
         run.status = PipelineStatus::Running;
+        let deadline = run.config.budget.max_duration_secs
+            .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
 
+        let mut result = Ok(());
         // Execute each stage
-        for i in 0..run.stages.len() {
+        for i in first..run.stages.len() {
             let stage = run.stages[i].stage.clone();
 
             // Start stage
             run.stages[i].start();
+            let cache_key = self.executor.cacheable(&stage)
+                .then(|| cache_key(&stage, &self.executor.stage_inputs(&stage, &run.config), &run.stages[..i]));
+            run.stages[i].cache_key = cache_key.clone();
+
+            if let (Some(store), Some(key)) = (&self.artifacts, &cache_key) {
+                match store.get(key) {
+                    Ok(Some(artifact)) => {
+                        run.stages[i].metrics = artifact.metrics.clone();
+                        run.stages[i].artifact_hash = Some(artifact.hash());
+                        run.stages[i].cache_hit = true;
+                        run.stages[i].complete();
+                        tracing::info!("Reused cached output of stage {:?} ({})", stage, key);
+                        outputs.push(artifact);
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Artifact lookup for stage {:?} failed: {}", stage, e),
+                }
+            }
             tracing::info!("Starting stage: {:?}", stage);
 
-            // Execute stage
-            match self.executor.execute_stage(&stage, &run.config).await {
-                Ok(metrics) => {
-                    run.stages[i].metrics = metrics;
+            let outcome = {
+                let execution = self.executor.execute_step(&stage, &run.config, &outputs);
+                tokio::pin!(execution);
+                let mut poll = tokio::time::interval(self.usage_poll_interval);
+                loop {
+                    tokio::select! {
+                        biased;
+                        Ok(_) = cancel.wait_for(|cancelled| *cancelled) => break StageOutcome::Cancelled,
+                        _ = sleep_until(deadline) => {
+                            let secs = run.config.budget.max_duration_secs.unwrap_or_default();
+                            break StageOutcome::OverBudget(format!("Time budget of {}s exceeded", secs));
+                        }
+                        metrics = &mut execution => break StageOutcome::Finished(metrics),
+                        _ = poll.tick() => {
+                            let usage = self.executor.resource_usage().await;
+                            if let Some(reason) = usage.and_then(|u| run.config.budget.exceeded_by(&u)) {
+                                break StageOutcome::OverBudget(reason);
+                            }
+                        }
+                    }
+                }
+            };
+
+            match outcome {
+                StageOutcome::Finished(Ok(artifact)) => {
+                    if let (Some(store), Some(key)) = (&self.artifacts, &cache_key) {
+                        if let Err(e) = store.put(key, &artifact) {
+                            tracing::warn!("Could not cache output of stage {:?}: {}", stage, e);
+                        }
+                    }
+                    run.stages[i].metrics = artifact.metrics.clone();
+                    run.stages[i].artifact_hash = Some(artifact.hash());
                     run.stages[i].complete();
+                    outputs.push(artifact);
                     tracing::info!("Completed stage: {:?}", stage);
+                    continue;
                 }
-                Err(e) => {
+                StageOutcome::Finished(Err(e)) => {
                     run.stages[i].fail(e.to_string());
-                    run.status = PipelineStatus::Failed;
-                    run.completed_at = Some(Utc::now());
+                    run.finish(PipelineStatus::Failed, Some(format!("Stage {:?} failed: {}", stage, e)));
                     tracing::error!("Stage failed: {:?} - {}", stage, e);
-                    return Err(e);
+                    result = Err(e);
+                }
+                StageOutcome::OverBudget(reason) => {
+                    if let Err(e) = self.executor.cleanup(&stage, &run.config).await {
+                        tracing::warn!("Cleanup after {:?} failed: {}", stage, e);
+                    }
+                    run.stages[i].fail(reason.clone());
+                    run.finish(PipelineStatus::Failed, Some(reason.clone()));
+                    tracing::error!("Pipeline run {} over budget: {}", run_id, reason);
+                    result = Err(anyhow::anyhow!(reason));
+                }
+                StageOutcome::Cancelled => {
+                    if let Err(e) = self.executor.cleanup(&stage, &run.config).await {
+                        tracing::warn!("Cleanup after {:?} failed: {}", stage, e);
+                    }
+                    run.stages[i].status = PipelineStatus::Cancelled;
+                    run.stages[i].completed_at = Some(Utc::now());
+                    run.finish(PipelineStatus::Cancelled, Some("Cancelled".to_string()));
+                    tracing::info!("Cancelled pipeline run: {}", run_id);
+                    result = Err(anyhow::anyhow!("Run {} was cancelled", run_id));
                 }
             }
+            break;
         }
 
-        run.status = PipelineStatus::Completed;
-        run.completed_at = Some(Utc::now());
-        tracing::info!("Pipeline run completed: {}", run_id);
+        if result.is_ok() {
+            if let Some(features) = self.executor.training_features(&run.config).await {
+                let names: Vec<&str> = features.names.iter().map(String::as_str).collect();
+                match DriftBaseline::from_samples(&names, &features.samples, BASELINE_BINS) {
+                    Ok(baseline) => run.drift_baseline = Some(baseline),
+                    Err(e) => tracing::warn!("No drift baseline for run {}: {}", run_id, e),
+                }
+            }
+            run.finish(PipelineStatus::Completed, None);
+            tracing::info!("Pipeline run completed: {}", run_id);
 
-        Ok(())
+            if let (Some(manager), Some(baseline)) = (&self.retraining, &run.drift_baseline) {
+                manager.lock().unwrap().set_drift_baseline(run.config.model_name.clone(), baseline.clone());
+            }
+        } else if run.status == PipelineStatus::Failed {
+            self.outputs.insert(*run_id, outputs);
+        }
+        self.settle(run_id);
+        result
     }
 
     pub fn cancel_run(&mut self, run_id: &Uuid) -> Result<()> {
         let run = self.runs.get_mut(run_id)
             .ok_or_else(|| anyhow::anyhow!("Run not found"))?;
 
-        if run.is_complete() {
+        if run.is_complete() || run.status == PipelineStatus::Cancelled {
             anyhow::bail!("Cannot cancel completed run");
         }
 
-        run.status = PipelineStatus::Cancelled;
-        run.completed_at = Some(Utc::now());
+        run.finish(PipelineStatus::Cancelled, Some("Cancelled".to_string()));
+        self.settle(run_id);
 
         tracing::info!("Cancelled pipeline run: {}", run_id);
         Ok(())
     }
 
+    /// Drop a finished run's cancellation signal and fail its model in the
+    /// registry unless the run completed
+    fn settle(&mut self, run_id: &Uuid) {
+        self.canceller.signals.lock().unwrap().remove(run_id);
+        let Some(run) = self.runs.get(run_id) else {
+            return;
+        };
+        let (Some(registry), Some(model_id)) = (&self.registry, run.model_id) else {
+            return;
+        };
+        if run.status == PipelineStatus::Completed {
+            return;
+        }
+
+        let mut registry = registry.lock().unwrap();
+        let unfinished = registry.get_model(&model_id)
+            .is_some_and(|m| matches!(m.status, ModelStatus::Training | ModelStatus::Registered));
+        if unfinished {
+            if let Err(e) = registry.update_status(&model_id, ModelStatus::Failed) {
+                tracing::warn!("Could not mark model {} failed: {}", model_id, e);
+            }
+        }
+    }
+
     pub fn list_runs(&self) -> Vec<&PipelineRun> {
         self.runs.values().collect()
     }
@@ -242,6 +681,27 @@ impl<E: PipelineExecutor> TrainingPipeline<E> {
     }
 }
 
+/// Content address of a stage: its name, its configuration inputs and the
+/// hashes of everything upstream of it
+fn cache_key(stage: &PipelineStage, inputs: &serde_json::Value, upstream: &[StageResult]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}", stage).as_bytes());
+    hasher.update([0]);
+    hasher.update(inputs.to_string().as_bytes());
+    for result in upstream {
+        hasher.update([0]);
+        hasher.update(result.artifact_hash.as_deref().unwrap_or_default().as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -329,6 +789,215 @@ mod tests {
         assert_eq!(run.status, PipelineStatus::Failed);
     }
 
+    /// Hangs in the training stage until aborted, recording cleanup
+    #[derive(Default)]
+    struct SlowExecutor {
+        memory_mb: u64,
+        cleaned_up: Arc<Mutex<Vec<PipelineStage>>>,
+    }
+
+    #[async_trait]
+    impl PipelineExecutor for SlowExecutor {
+        async fn execute_stage(&self, stage: &PipelineStage, _config: &TrainingConfig) -> Result<HashMap<String, f64>> {
+            if stage == &PipelineStage::Training {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            Ok(HashMap::new())
+        }
+
+        async fn resource_usage(&self) -> Option<ResourceUsage> {
+            Some(ResourceUsage { cpu_seconds: 0.5, memory_mb: self.memory_mb })
+        }
+
+        async fn cleanup(&self, stage: &PipelineStage, _config: &TrainingConfig) -> Result<()> {
+            self.cleaned_up.lock().unwrap().push(stage.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_over_time_budget_fails() {
+        let executor = SlowExecutor::default();
+        let cleaned_up = executor.cleaned_up.clone();
+        let mut pipeline = TrainingPipeline::new(executor);
+
+        let config = TrainingConfig::new("test-model", "v1.0.0").with_budget(ResourceBudget {
+            max_duration_secs: Some(1),
+            ..Default::default()
+        });
+        let run_id = pipeline.create_run(config, "erin");
+
+        let started = std::time::Instant::now();
+        let err = pipeline.execute_run(&run_id).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(err.to_string().contains("Time budget of 1s exceeded"));
+
+        let run = pipeline.get_run(&run_id).unwrap();
+        assert_eq!(run.status, PipelineStatus::Failed);
+        assert_eq!(run.failure_reason.as_deref(), Some("Time budget of 1s exceeded"));
+        let training = run.stages.iter().find(|s| s.stage == PipelineStage::Training).unwrap();
+        assert_eq!(training.status, PipelineStatus::Failed);
+        assert_eq!(*cleaned_up.lock().unwrap(), vec![PipelineStage::Training]);
+    }
+
+    #[tokio::test]
+    async fn test_run_over_memory_budget_fails() {
+        let executor = SlowExecutor { memory_mb: 4096, ..Default::default() };
+        let mut pipeline = TrainingPipeline::new(executor).with_usage_poll_interval(Duration::from_millis(10));
+
+        let config = TrainingConfig::new("test-model", "v1.0.0").with_budget(ResourceBudget {
+            max_memory_mb: Some(1024),
+            ..Default::default()
+        });
+        let run_id = pipeline.create_run(config, "erin");
+
+        assert!(pipeline.execute_run(&run_id).await.is_err());
+        let run = pipeline.get_run(&run_id).unwrap();
+        assert_eq!(run.status, PipelineStatus::Failed);
+        assert!(run.failure_reason.as_deref().unwrap().starts_with("Memory budget of 1024 MB exceeded"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_mid_run_cleans_up() {
+        use crate::registry::{ModelType, ModelVersion};
+
+        let registry = Arc::new(Mutex::new(ModelRegistry::new()));
+        let model_id = registry.lock().unwrap()
+            .register_model(ModelVersion::new("test-model", "v1.0.0", ModelType::AnomalyDetection, "frank"))
+            .unwrap();
+
+        let executor = SlowExecutor::default();
+        let cleaned_up = executor.cleaned_up.clone();
+        let mut pipeline = TrainingPipeline::new(executor).with_registry(registry.clone());
+        let run_id = pipeline.create_run(TrainingConfig::new("test-model", "v1.0.0"), "frank");
+        pipeline.attach_model(&run_id, model_id).unwrap();
+
+        let canceller = pipeline.canceller();
+        let cancel = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel(&run_id)
+        });
+
+        let err = pipeline.execute_run(&run_id).await.unwrap_err();
+        assert!(cancel.await.unwrap());
+        assert!(err.to_string().contains("cancelled"));
+
+        let run = pipeline.get_run(&run_id).unwrap();
+        assert_eq!(run.status, PipelineStatus::Cancelled);
+        let statuses: Vec<_> = run.stages.iter().map(|s| s.status.clone()).collect();
+        assert_eq!(&statuses[..4], &[
+            PipelineStatus::Completed,
+            PipelineStatus::Completed,
+            PipelineStatus::Completed,
+            PipelineStatus::Cancelled,
+        ]);
+        assert!(statuses[4..].iter().all(|s| *s == PipelineStatus::Pending));
+        assert_eq!(*cleaned_up.lock().unwrap(), vec![PipelineStage::Training]);
+
+        assert_eq!(registry.lock().unwrap().get_model(&model_id).unwrap().status, ModelStatus::Failed);
+        assert!(!pipeline.canceller().cancel(&run_id));
+        assert!(pipeline.execute_run(&run_id).await.is_err());
+    }
+
+    /// Records which stages it ran; fails training until told otherwise
+    #[derive(Default)]
+    struct RecordingExecutor {
+        executed: Arc<Mutex<Vec<(PipelineStage, usize)>>>,
+        fail_training: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl PipelineExecutor for RecordingExecutor {
+        async fn execute_stage(&self, _stage: &PipelineStage, _config: &TrainingConfig) -> Result<HashMap<String, f64>> {
+            unreachable!("execute_step is overridden")
+        }
+
+        async fn execute_step(
+            &self,
+            stage: &PipelineStage,
+            config: &TrainingConfig,
+            upstream: &[StageArtifact],
+        ) -> Result<StageArtifact> {
+            self.executed.lock().unwrap().push((stage.clone(), upstream.len()));
+            if *stage == PipelineStage::Training && self.fail_training.load(std::sync::atomic::Ordering::SeqCst) {
+                anyhow::bail!("Out of GPU memory");
+            }
+            let data = format!("{:?} {}", stage, self.stage_inputs(stage, config)).into_bytes();
+            Ok(StageArtifact::new(HashMap::from([("rows".to_string(), 100.0)]), data))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_stages_reuse_cached_outputs() {
+        use crate::artifacts::MemoryArtifactStore;
+
+        let executor = RecordingExecutor::default();
+        let executed = executor.executed.clone();
+        let store = Arc::new(MemoryArtifactStore::new());
+        let mut pipeline = TrainingPipeline::new(executor).with_artifact_store(store.clone());
+        let config = TrainingConfig::new("test-model", "v1.0.0").with_data_path("/data/flows");
+
+        let first = pipeline.create_run(config.clone(), "grace");
+        pipeline.execute_run(&first).await.unwrap();
+        assert_eq!(pipeline.get_run(&first).unwrap().executed_stages().len(), 7);
+        // Deployment is never cached
+        assert_eq!(store.len(), 6);
+
+        // Same configuration: only deployment runs again
+        let second = pipeline.create_run(config.clone(), "grace");
+        pipeline.execute_run(&second).await.unwrap();
+        let run = pipeline.get_run(&second).unwrap();
+        assert_eq!(run.executed_stages(), vec![&PipelineStage::Deployment]);
+        assert_eq!(run.cached_stages().len(), 6);
+        assert_eq!(run.stages[0].metrics["rows"], 100.0);
+
+        // New hyperparameters invalidate training and everything after it
+        executed.lock().unwrap().clear();
+        let third = pipeline.create_run(config.with_hyperparameter("depth", serde_json::json!(8)), "grace");
+        pipeline.execute_run(&third).await.unwrap();
+        let stages: Vec<_> = executed.lock().unwrap().iter().map(|(stage, _)| stage.clone()).collect();
+        assert_eq!(stages, vec![
+            PipelineStage::Training,
+            PipelineStage::Validation,
+            PipelineStage::Testing,
+            PipelineStage::Deployment,
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_failed_stage() {
+        use crate::registry::{ModelType, ModelVersion};
+
+        let registry = Arc::new(Mutex::new(ModelRegistry::new()));
+        let model_id = registry.lock().unwrap()
+            .register_model(ModelVersion::new("test-model", "v1.0.0", ModelType::AnomalyDetection, "heidi"))
+            .unwrap();
+
+        let executor = RecordingExecutor::default();
+        executor.fail_training.store(true, std::sync::atomic::Ordering::SeqCst);
+        let executed = executor.executed.clone();
+        let mut pipeline = TrainingPipeline::new(executor).with_registry(registry.clone());
+        let run_id = pipeline.create_run(TrainingConfig::new("test-model", "v1.0.0"), "heidi");
+        pipeline.attach_model(&run_id, model_id).unwrap();
+
+        assert!(pipeline.execute_run(&run_id).await.is_err());
+        assert_eq!(registry.lock().unwrap().get_model(&model_id).unwrap().status, ModelStatus::Failed);
+
+        executed.lock().unwrap().clear();
+        pipeline.executor.fail_training.store(false, std::sync::atomic::Ordering::SeqCst);
+        pipeline.resume(&run_id).await.unwrap();
+
+        // Training gets the outputs of the three stages that had finished
+        assert_eq!(executed.lock().unwrap()[0], (PipelineStage::Training, 3));
+        let run = pipeline.get_run(&run_id).unwrap();
+        assert_eq!(run.status, PipelineStatus::Completed);
+        assert!(run.failure_reason.is_none());
+        assert!(run.stages.iter().all(|s| s.status == PipelineStatus::Completed && s.error.is_none()));
+        assert_eq!(registry.lock().unwrap().get_model(&model_id).unwrap().status, ModelStatus::Training);
+
+        assert!(pipeline.resume(&run_id).await.is_err());
+    }
+
     #[test]
     fn test_stage_result() {
         let mut stage = StageResult::new(PipelineStage::Training);
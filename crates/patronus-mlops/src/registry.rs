@@ -2,10 +2,14 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use anyhow::Result;
 use sha2::{Sha256, Digest};
+use std::sync::Arc;
+
+use crate::ab_test::{AbDeployment, Variant};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ModelType {
@@ -14,17 +18,39 @@ pub enum ModelType {
     EncryptedDpi,
     TrafficForecasting,
     QosOptimization,
+    ThreatClassification,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Lifecycle of a model version
+///
+/// A trained version is registered, then rolled out through shadow and
+/// canary stages to production: Registered -> Shadow -> Canary(percent) ->
+/// Production -> Archived. Stages can be skipped, and a version in shadow
+/// or canary can be withdrawn back to registered.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ModelStatus {
     Training,
-    Validated,
-    Deployed,
+    /// Trained and validated; a candidate for rollout
+    #[serde(alias = "Validated")]
+    Registered,
+    /// Scores live traffic alongside production; its results are not used
+    Shadow,
+    /// Serves this percentage of live traffic, 1 to 99
+    Canary(u8),
+    /// Serves all traffic not sent to a canary
+    #[serde(alias = "Deployed")]
+    Production,
     Archived,
     Failed,
 }
 
+impl ModelStatus {
+    /// Shadow or canary, i.e. being rolled out
+    pub fn is_candidate(&self) -> bool {
+        matches!(self, ModelStatus::Shadow | ModelStatus::Canary(_))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelMetadata {
     pub accuracy: Option<f64>,
@@ -36,6 +62,9 @@ pub struct ModelMetadata {
     pub training_duration_secs: u64,
 }
 
+/// Tag holding the path of a version's model files
+pub const BUNDLE_TAG: &str = "bundle";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelVersion {
     pub id: Uuid,
@@ -99,15 +128,127 @@ impl ModelVersion {
         self
     }
 
+    /// Record where the version's model files are, for consumers to load
+    pub fn with_bundle(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().display().to_string();
+        self.with_tag(BUNDLE_TAG, path)
+    }
+
+    pub fn bundle(&self) -> Option<&Path> {
+        self.tags.get(BUNDLE_TAG).map(Path::new)
+    }
+
     pub fn set_status(&mut self, status: ModelStatus) {
         self.status = status;
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeploymentAction {
+    Deployed,
+    /// A previous version was restored
+    RolledBack { reason: String, triggered_by: String },
+    /// A candidate moved to shadow or canary
+    Staged { stage: ModelStatus },
+    /// A candidate was taken out of shadow or canary
+    Withdrawn { reason: String, triggered_by: String },
+}
+
+/// A change of the deployed or candidate version of a model type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentEvent {
+    pub model_type: ModelType,
+    pub model_id: Uuid,
+    /// The production version this one replaced; none for candidates
+    pub previous: Option<Uuid>,
+    pub action: DeploymentAction,
+    pub at: DateTime<Utc>,
+}
+
+/// Which versions of a model type a consumer should load
+///
+/// Returned by [`ModelRegistry::load_plan`]. Consumers such as the threat
+/// classifier, encrypted DPI and anomaly detector load every version in
+/// [`versions`](Self::versions), score each request with the version from
+/// [`route`](Self::route), and additionally score it with the shadow
+/// version when there is one. [`ModelRegistry::sync_consumer`] does this
+/// for a [`ModelConsumer`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadPlan {
+    pub model_type: ModelType,
+    pub production: Option<Uuid>,
+    pub shadow: Option<Uuid>,
+    pub canary: Option<Uuid>,
+    /// Share of traffic the canary scores, 0 without a canary
+    pub canary_percent: u8,
+    /// Routes between production (A) and the canary (B); record the
+    /// canary's results here to compare it with production
+    #[serde(skip)]
+    pub canary_split: Option<Arc<AbDeployment>>,
+}
+
+impl LoadPlan {
+    /// Every version that should be loaded
+    pub fn versions(&self) -> Vec<Uuid> {
+        [self.production, self.shadow, self.canary].into_iter().flatten().collect()
+    }
+
+    /// Share of traffic the canary scores, 0.0 to 1.0
+    pub fn canary_fraction(&self) -> f64 {
+        self.canary.map_or(0.0, |_| self.canary_percent as f64 / 100.0)
+    }
+
+    /// The version whose result is used for `entity` (a flow, source or
+    /// device key)
+    ///
+    /// Assignment is stable per entity, and raising the canary's share
+    /// only moves entities from production to the canary.
+    pub fn route(&self, entity: &str) -> Option<Uuid> {
+        match &self.canary_split {
+            Some(split) => Some(split.route(entity).model_id),
+            None => self.production,
+        }
+    }
+}
+
+/// Something that loads and scores with a model type's versions
+///
+/// Kept in line with the registry by [`ModelRegistry::sync_consumer`].
+pub trait ModelConsumer {
+    fn model_type(&self) -> ModelType;
+
+    /// Load the versions in `plan`, whose registry entries are `models`,
+    /// and drop any others
+    ///
+    /// On error the versions loaded so far stay loaded and scoring keeps
+    /// following the previous plan.
+    fn apply_plan(&mut self, plan: &LoadPlan, models: &[&ModelVersion]) -> Result<()>;
+
+    /// Versions currently loaded
+    fn loaded_versions(&self) -> Vec<Uuid>;
+}
+
+/// Something that loads and scores with a model type's versions
+#[derive(Debug, Clone)]
+struct Consumer {
+    model_type: ModelType,
+    /// Versions it reported as loaded
+    loaded: Vec<Uuid>,
+}
+
 pub struct ModelRegistry {
     models: HashMap<Uuid, ModelVersion>,
     versions_by_name: HashMap<String, Vec<Uuid>>, // model_name -> [version_ids]
     deployed_models: HashMap<ModelType, Uuid>,    // model_type -> deployed_version_id
+    /// Versions that have been deployed, oldest first; the last is current
+    deployment_stack: HashMap<ModelType, Vec<Uuid>>,
+    deployment_history: Vec<DeploymentEvent>,
+    ab_deployments: HashMap<ModelType, Arc<AbDeployment>>,
+    /// The version in shadow or canary, at most one per model type
+    candidates: HashMap<ModelType, Uuid>,
+    /// Traffic split of each canary against production
+    canary_splits: HashMap<ModelType, Arc<AbDeployment>>,
+    consumers: HashMap<String, Consumer>,
 }
 
 impl ModelRegistry {
@@ -116,6 +257,12 @@ impl ModelRegistry {
             models: HashMap::new(),
             versions_by_name: HashMap::new(),
             deployed_models: HashMap::new(),
+            deployment_stack: HashMap::new(),
+            deployment_history: Vec::new(),
+            ab_deployments: HashMap::new(),
+            candidates: HashMap::new(),
+            canary_splits: HashMap::new(),
+            consumers: HashMap::new(),
         }
     }
 
@@ -157,21 +304,291 @@ impl ModelRegistry {
             .and_then(|id| self.models.get(id))
     }
 
+    /// Promote a registered or candidate version straight to production
     pub fn deploy_model(&mut self, model_id: &Uuid) -> Result<()> {
-        let model = self.models.get_mut(model_id)
+        self.advance(model_id, ModelStatus::Production)
+    }
+
+    /// Move a version to the shadow, canary or production stage
+    ///
+    /// Registered versions and candidates can move to any of these, in
+    /// either direction between shadow and canary; changing a canary's
+    /// percentage is a move from canary to canary. Each model type has at
+    /// most one candidate at a time. Promoting to production demotes the
+    /// current production version to registered.
+    pub fn advance(&mut self, model_id: &Uuid, stage: ModelStatus) -> Result<()> {
+        let model = self.models.get(model_id)
             .ok_or_else(|| anyhow::anyhow!("Model not found"))?;
+        let model_type = model.model_type.clone();
+
+        if model.status != ModelStatus::Registered && !model.status.is_candidate() {
+            anyhow::bail!(
+                "Model must be registered or in shadow or canary to move to {:?}, but is {:?}",
+                stage, model.status
+            );
+        }
+        if let ModelStatus::Canary(percent) = stage {
+            if !(1..=99).contains(&percent) {
+                anyhow::bail!("Canary share must be between 1 and 99 percent, got {}", percent);
+            }
+            if !self.deployed_models.contains_key(&model_type) {
+                anyhow::bail!("{:?} has no production version for a canary to share traffic with", model_type);
+            }
+        } else if !matches!(stage, ModelStatus::Shadow | ModelStatus::Production) {
+            anyhow::bail!("{:?} is not a rollout stage", stage);
+        }
+        if let Some(ab) = self.ab_deployments.get(&model_type) {
+            anyhow::bail!("{:?} has A/B deployment {} in progress; conclude it first", model_type, ab.id);
+        }
+        if let Some(candidate) = self.candidates.get(&model_type).filter(|id| *id != model_id) {
+            anyhow::bail!(
+                "{:?} already has candidate {} in rollout; promote or withdraw it first",
+                model_type, candidate
+            );
+        }
+
+        let model = self.models.get_mut(model_id).expect("model exists");
+        model.status = stage.clone();
+        tracing::info!("Moved model {} ({}) to {:?}", model.model_name, model_id, stage);
+
+        if stage == ModelStatus::Production {
+            self.candidates.remove(&model_type);
+            self.set_deployed(model_type.clone(), *model_id, DeploymentAction::Deployed);
+        } else {
+            self.candidates.insert(model_type.clone(), *model_id);
+            self.deployment_history.push(DeploymentEvent {
+                model_type: model_type.clone(),
+                model_id: *model_id,
+                previous: None,
+                action: DeploymentAction::Staged { stage },
+                at: Utc::now(),
+            });
+        }
+        self.sync_canary_split(&model_type);
+        Ok(())
+    }
+
+    /// Take a model type's shadow or canary version back to registered
+    ///
+    /// Production is untouched. Returns the withdrawn version.
+    pub fn withdraw(
+        &mut self,
+        model_type: &ModelType,
+        reason: impl Into<String>,
+        triggered_by: impl Into<String>,
+    ) -> Result<Uuid> {
+        let candidate = self.candidates.remove(model_type)
+            .ok_or_else(|| anyhow::anyhow!("No {:?} version in shadow or canary", model_type))?;
+        if let Some(model) = self.models.get_mut(&candidate) {
+            model.status = ModelStatus::Registered;
+        }
+
+        let (reason, triggered_by) = (reason.into(), triggered_by.into());
+        tracing::warn!("Withdrew {:?} candidate {} ({}, by {})", model_type, candidate, reason, triggered_by);
+        self.deployment_history.push(DeploymentEvent {
+            model_type: model_type.clone(),
+            model_id: candidate,
+            previous: None,
+            action: DeploymentAction::Withdrawn { reason, triggered_by },
+            at: Utc::now(),
+        });
+        self.sync_canary_split(model_type);
+        Ok(candidate)
+    }
+
+    /// The versions consumers of `model_type` should load
+    ///
+    /// A/B deployments are routed by their [`AbDeployment`] instead; while
+    /// one runs the plan only names the incumbent.
+    pub fn load_plan(&self, model_type: &ModelType) -> LoadPlan {
+        let candidate = self.candidates.get(model_type)
+            .and_then(|id| self.models.get(id));
+        let (shadow, canary, canary_percent) = match candidate.map(|m| (m.id, &m.status)) {
+            Some((id, ModelStatus::Shadow)) => (Some(id), None, 0),
+            Some((id, ModelStatus::Canary(percent))) => (None, Some(id), *percent),
+            _ => (None, None, 0),
+        };
+        LoadPlan {
+            model_type: model_type.clone(),
+            production: self.deployed_models.get(model_type).copied(),
+            shadow,
+            canary,
+            canary_percent,
+            canary_split: canary.and_then(|_| self.canary_splits.get(model_type).cloned()),
+        }
+    }
+
+    /// Bring `consumer` in line with the load plan of its model type and
+    /// record what it has loaded, registering it as `name` if need be
+    ///
+    /// What the consumer loaded is recorded even when loading failed.
+    pub fn sync_consumer(&mut self, name: &str, consumer: &mut dyn ModelConsumer) -> Result<LoadPlan> {
+        let model_type = consumer.model_type();
+        if self.consumers.get(name).is_none_or(|c| c.model_type != model_type) {
+            self.register_consumer(name, model_type.clone());
+        }
 
-        if model.status != ModelStatus::Validated {
-            anyhow::bail!("Model must be validated before deployment");
+        let plan = self.load_plan(&model_type);
+        let models: Vec<&ModelVersion> = plan.versions().iter()
+            .filter_map(|id| self.models.get(id))
+            .collect();
+        let applied = consumer.apply_plan(&plan, &models);
+        self.report_loaded(name, &consumer.loaded_versions())?;
+        applied?;
+        Ok(plan)
+    }
+
+    /// Keep each canary's traffic split in step with its share and the
+    /// production version it is measured against
+    fn sync_canary_split(&mut self, model_type: &ModelType) {
+        let canary = self.candidates.get(model_type)
+            .and_then(|id| self.models.get(id))
+            .and_then(|model| match model.status {
+                ModelStatus::Canary(percent) => Some((model.id, percent)),
+                _ => None,
+            });
+        let (Some((canary, percent)), Some(&production)) = (canary, self.deployed_models.get(model_type)) else {
+            self.canary_splits.remove(model_type);
+            return;
+        };
+
+        let current = self.canary_splits.get(model_type)
+            .filter(|split| split.model_id(Variant::A) == production && split.model_id(Variant::B) == canary);
+        match current {
+            // The same split keeps its buckets, so a wider canary only gains entities
+            Some(split) if split.b_percent() != percent => {
+                if let Err(e) = split.set_split(percent) {
+                    tracing::warn!("Failed to resize {:?} canary split: {}", model_type, e);
+                }
+            }
+            Some(_) => {}
+            None => {
+                let split = AbDeployment::new(model_type.clone(), production, canary, percent);
+                self.canary_splits.insert(model_type.clone(), Arc::new(split));
+            }
         }
+    }
+
+    /// Track a consumer of `model_type`, e.g. "threat-classifier"
+    pub fn register_consumer(&mut self, name: impl Into<String>, model_type: ModelType) {
+        let name = name.into();
+        tracing::info!("Registered {:?} consumer {}", model_type, name);
+        self.consumers.insert(name, Consumer { model_type, loaded: Vec::new() });
+    }
 
-        model.status = ModelStatus::Deployed;
-        self.deployed_models.insert(model.model_type.clone(), *model_id);
+    pub fn unregister_consumer(&mut self, name: &str) -> bool {
+        self.consumers.remove(name).is_some()
+    }
 
-        tracing::info!("Deployed model: {} ({})", model.model_name, model_id);
+    /// Record the versions a consumer now has loaded, replacing what it
+    /// reported before
+    pub fn report_loaded(&mut self, name: &str, versions: &[Uuid]) -> Result<()> {
+        let consumer = self.consumers.get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown model consumer {}", name))?;
+        for id in versions {
+            let model = self.models.get(id)
+                .ok_or_else(|| anyhow::anyhow!("Consumer {} reported unknown model {}", name, id))?;
+            if model.model_type != consumer.model_type {
+                anyhow::bail!(
+                    "Consumer {} uses {:?} models but reported {:?} model {}",
+                    name, consumer.model_type, model.model_type, id
+                );
+            }
+        }
+        self.consumers.get_mut(name).expect("consumer exists").loaded = versions.to_vec();
         Ok(())
     }
 
+    /// Names of the consumers that have `model_id` loaded
+    pub fn consumers_of(&self, model_id: &Uuid) -> Vec<&str> {
+        let mut names: Vec<&str> = self.consumers.iter()
+            .filter(|(_, c)| c.loaded.contains(model_id))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Restore the version deployed before the current one
+    ///
+    /// The current version goes back to registered, so it can be deployed
+    /// again once fixed. Versions archived since they were deployed are
+    /// skipped. Returns the restored version.
+    pub fn rollback(
+        &mut self,
+        model_type: &ModelType,
+        reason: impl Into<String>,
+        triggered_by: impl Into<String>,
+    ) -> Result<Uuid> {
+        if let Some(ab) = self.ab_deployments.get(model_type) {
+            anyhow::bail!("{:?} has A/B deployment {} in progress; conclude it instead", model_type, ab.id);
+        }
+        let current = *self.deployed_models.get(model_type)
+            .ok_or_else(|| anyhow::anyhow!("No deployed {:?} model to roll back", model_type))?;
+        let stack = self.deployment_stack.entry(model_type.clone()).or_default();
+        if stack.len() < 2 {
+            anyhow::bail!("No earlier {:?} version was ever deployed; nothing to roll back to", model_type);
+        }
+
+        stack.pop();
+        let previous = *stack.last().expect("stack has at least one entry");
+        if self.candidates.get(model_type) == Some(&previous) {
+            self.candidates.remove(model_type);
+        }
+        if let Some(model) = self.models.get_mut(&current) {
+            model.status = ModelStatus::Registered;
+        }
+        if let Some(model) = self.models.get_mut(&previous) {
+            model.status = ModelStatus::Production;
+        }
+        self.deployed_models.insert(model_type.clone(), previous);
+        self.sync_canary_split(model_type);
+
+        let (reason, triggered_by) = (reason.into(), triggered_by.into());
+        tracing::warn!(
+            "Rolled back {:?} from {} to {} ({}, by {})",
+            model_type, current, previous, reason, triggered_by
+        );
+        self.deployment_history.push(DeploymentEvent {
+            model_type: model_type.clone(),
+            model_id: previous,
+            previous: Some(current),
+            action: DeploymentAction::RolledBack { reason, triggered_by },
+            at: Utc::now(),
+        });
+        Ok(previous)
+    }
+
+    /// Every deployment and rollback of `model_type`, oldest first
+    pub fn deployment_history(&self, model_type: &ModelType) -> Vec<&DeploymentEvent> {
+        self.deployment_history
+            .iter()
+            .filter(|e| &e.model_type == model_type)
+            .collect()
+    }
+
+    /// Make `model_id` the deployed version, demoting the one it replaces
+    fn set_deployed(&mut self, model_type: ModelType, model_id: Uuid, action: DeploymentAction) {
+        let previous = self.deployed_models.insert(model_type.clone(), model_id)
+            .filter(|id| *id != model_id);
+        if let Some(model) = previous.and_then(|id| self.models.get_mut(&id)) {
+            if model.status == ModelStatus::Production {
+                model.status = ModelStatus::Registered;
+            }
+        }
+
+        let stack = self.deployment_stack.entry(model_type.clone()).or_default();
+        stack.retain(|id| *id != model_id);
+        stack.push(model_id);
+        self.deployment_history.push(DeploymentEvent {
+            model_type,
+            model_id,
+            previous,
+            action,
+            at: Utc::now(),
+        });
+    }
+
     pub fn get_deployed_model(&self, model_type: &ModelType) -> Option<&ModelVersion> {
         self.deployed_models
             .get(model_type)
@@ -188,21 +605,128 @@ impl ModelRegistry {
         Ok(())
     }
 
+    /// Archive a version no consumer has loaded any more
     pub fn archive_model(&mut self, model_id: &Uuid) -> Result<()> {
+        let consumers = self.consumers_of(model_id);
+        if !consumers.is_empty() {
+            anyhow::bail!("Model {} is still loaded by {}", model_id, consumers.join(", "));
+        }
         let model = self.models.get_mut(model_id)
             .ok_or_else(|| anyhow::anyhow!("Model not found"))?;
 
-        if model.status == ModelStatus::Deployed {
+        if let Some(ab) = self.ab_deployments.get(&model.model_type) {
+            if ab.model_id(Variant::A) == *model_id || ab.model_id(Variant::B) == *model_id {
+                anyhow::bail!("Model is part of A/B deployment {}", ab.id);
+            }
+        }
+
+        if model.status == ModelStatus::Production {
             // Remove from deployed models
             self.deployed_models.remove(&model.model_type);
         }
+        if let Some(stack) = self.deployment_stack.get_mut(&model.model_type) {
+            stack.retain(|id| id != model_id);
+        }
+        if self.candidates.get(&model.model_type) == Some(model_id) {
+            self.candidates.remove(&model.model_type);
+        }
 
         model.status = ModelStatus::Archived;
+        let model_type = model.model_type.clone();
         tracing::info!("Archived model: {}", model_id);
 
+        self.sync_canary_split(&model_type);
         Ok(())
     }
 
+    /// Serve `variant_a` (the incumbent) and `variant_b` side by side, with
+    /// `b_percent` of traffic going to B
+    ///
+    /// Both must be versions of the same model type, B must be registered
+    /// and A registered or already deployed, and no other version may be in
+    /// shadow or canary. A is the deployed model until the test concludes.
+    pub fn start_ab_deployment(&mut self, variant_a: &Uuid, variant_b: &Uuid, b_percent: u8) -> Result<Arc<AbDeployment>> {
+        if variant_a == variant_b {
+            anyhow::bail!("A/B deployment needs two different models");
+        }
+        if b_percent > 100 {
+            anyhow::bail!("Split must be between 0 and 100 percent, got {}", b_percent);
+        }
+        let a = self.models.get(variant_a).ok_or_else(|| anyhow::anyhow!("Model A not found"))?;
+        let b = self.models.get(variant_b).ok_or_else(|| anyhow::anyhow!("Model B not found"))?;
+        if a.model_type != b.model_type {
+            anyhow::bail!("Models A and B have different types ({:?} and {:?})", a.model_type, b.model_type);
+        }
+        if !matches!(a.status, ModelStatus::Registered | ModelStatus::Production) {
+            anyhow::bail!("Model A must be registered or deployed");
+        }
+        if b.status != ModelStatus::Registered {
+            anyhow::bail!("Model B must be registered before deployment");
+        }
+        let model_type = a.model_type.clone();
+        if let Some(candidate) = self.candidates.get(&model_type) {
+            anyhow::bail!("{:?} has candidate {} in rollout; promote or withdraw it first", model_type, candidate);
+        }
+        if let Some(existing) = self.ab_deployments.get(&model_type) {
+            anyhow::bail!("{:?} already has A/B deployment {}", model_type, existing.id);
+        }
+
+        for id in [variant_a, variant_b] {
+            if let Some(model) = self.models.get_mut(id) {
+                model.status = ModelStatus::Production;
+            }
+        }
+        if self.deployed_models.get(&model_type) != Some(variant_a) {
+            self.set_deployed(model_type.clone(), *variant_a, DeploymentAction::Deployed);
+        }
+
+        let deployment = Arc::new(AbDeployment::new(model_type.clone(), *variant_a, *variant_b, b_percent));
+        self.ab_deployments.insert(model_type, Arc::clone(&deployment));
+        tracing::info!(
+            "Started A/B deployment {}: {} vs {} with {}% to B",
+            deployment.id, variant_a, variant_b, b_percent
+        );
+        Ok(deployment)
+    }
+
+    pub fn get_ab_deployment(&self, model_type: &ModelType) -> Option<Arc<AbDeployment>> {
+        self.ab_deployments.get(model_type).cloned()
+    }
+
+    /// Make the challenger the deployed model and archive the incumbent
+    pub fn promote_ab(&mut self, model_type: &ModelType) -> Result<Uuid> {
+        self.conclude_ab(model_type, Variant::B)
+    }
+
+    /// Keep the incumbent and archive the challenger
+    pub fn rollback_ab(&mut self, model_type: &ModelType) -> Result<Uuid> {
+        self.conclude_ab(model_type, Variant::A)
+    }
+
+    fn conclude_ab(&mut self, model_type: &ModelType, winner: Variant) -> Result<Uuid> {
+        let deployment = self.ab_deployments.remove(model_type)
+            .ok_or_else(|| anyhow::anyhow!("No A/B deployment for {:?}", model_type))?;
+
+        // Routing switches first so no request reaches the loser afterwards
+        let winner_id = deployment.conclude(winner);
+        let loser_id = match winner {
+            Variant::A => deployment.model_id(Variant::B),
+            Variant::B => deployment.model_id(Variant::A),
+        };
+        if let Some(loser) = self.models.get_mut(&loser_id) {
+            loser.status = ModelStatus::Archived;
+        }
+        if let Some(stack) = self.deployment_stack.get_mut(model_type) {
+            stack.retain(|id| *id != loser_id);
+        }
+        if self.deployed_models.get(model_type) != Some(&winner_id) {
+            self.set_deployed(model_type.clone(), winner_id, DeploymentAction::Deployed);
+        }
+
+        tracing::info!("A/B deployment {} concluded: {:?} ({}) wins", deployment.id, winner, winner_id);
+        Ok(winner_id)
+    }
+
     pub fn list_models_by_status(&self, status: &ModelStatus) -> Vec<&ModelVersion> {
         self.models
             .values()
@@ -284,7 +808,7 @@ mod tests {
             ModelType::AnomalyDetection,
             "charlie",
         );
-        model.status = ModelStatus::Validated;
+        model.status = ModelStatus::Registered;
 
         let model_id = model.id;
         registry.register_model(model).unwrap();
@@ -293,7 +817,7 @@ mod tests {
 
         let deployed = registry.get_deployed_model(&ModelType::AnomalyDetection).unwrap();
         assert_eq!(deployed.id, model_id);
-        assert_eq!(deployed.status, ModelStatus::Deployed);
+        assert_eq!(deployed.status, ModelStatus::Production);
     }
 
     #[test]
@@ -328,6 +852,302 @@ mod tests {
         assert_eq!(model.size_bytes, data.len() as u64);
     }
 
+    fn registered(registry: &mut ModelRegistry, version: &str) -> Uuid {
+        let mut model = ModelVersion::new("anomaly-detector", version, ModelType::AnomalyDetection, "grace");
+        model.status = ModelStatus::Registered;
+        registry.register_model(model).unwrap()
+    }
+
+    #[test]
+    fn test_ab_split_and_promotion() {
+        let mut registry = ModelRegistry::new();
+        let a = registered(&mut registry, "v1.0.0");
+        let b = registered(&mut registry, "v2.0.0");
+        registry.deploy_model(&a).unwrap();
+
+        let ab = registry.start_ab_deployment(&a, &b, 10).unwrap();
+        assert!(registry.start_ab_deployment(&a, &b, 10).is_err());
+        assert!(registry.archive_model(&b).is_err());
+
+        let to_b = (0..10_000)
+            .filter(|i| ab.route(&format!("flow-{}", i)).variant == Variant::B)
+            .count();
+        assert!((800..=1200).contains(&to_b), "{} of 10000 routed to B", to_b);
+
+        // Assignment is sticky per entity
+        let first = ab.route("site-42");
+        assert!((0..100).all(|_| ab.route("site-42") == first));
+        assert_eq!(first.model_id, ab.model_id(first.variant));
+
+        ab.record_success(Variant::A, 12.0);
+        ab.record_success(Variant::B, 8.0);
+        ab.record_error(Variant::B);
+        ab.record_outcome(Variant::B, true);
+        let comparison = ab.compare();
+        assert_eq!(comparison.variant_b.mean_latency_ms(), Some(8.0));
+        assert_eq!(comparison.variant_b.error_rate(), Some(0.5));
+        assert_eq!(comparison.variant_b.accuracy(), Some(1.0));
+        assert_eq!(comparison.variant_a.accuracy(), None);
+
+        assert_eq!(registry.promote_ab(&ModelType::AnomalyDetection).unwrap(), b);
+        assert_eq!(ab.b_percent(), 100);
+        assert!((0..1000).all(|i| ab.route(&format!("flow-{}", i)).model_id == b));
+        assert!(ab.set_split(50).is_err());
+        assert_eq!(registry.get_deployed_model(&ModelType::AnomalyDetection).unwrap().id, b);
+        assert_eq!(registry.get_model(&a).unwrap().status, ModelStatus::Archived);
+        assert!(registry.get_ab_deployment(&ModelType::AnomalyDetection).is_none());
+    }
+
+    #[test]
+    fn test_ab_rollback_keeps_incumbent() {
+        let mut registry = ModelRegistry::new();
+        let a = registered(&mut registry, "v1.0.0");
+        let b = registered(&mut registry, "v2.0.0");
+
+        let ab = registry.start_ab_deployment(&a, &b, 50).unwrap();
+        assert_eq!(registry.rollback_ab(&ModelType::AnomalyDetection).unwrap(), a);
+        assert!((0..1000).all(|i| ab.route(&format!("flow-{}", i)).model_id == a));
+        assert_eq!(registry.get_deployed_model(&ModelType::AnomalyDetection).unwrap().id, a);
+        assert_eq!(registry.get_model(&b).unwrap().status, ModelStatus::Archived);
+        assert!(registry.rollback_ab(&ModelType::AnomalyDetection).is_err());
+    }
+
+    #[test]
+    fn test_rollback_restores_previous_deployment() {
+        let mut registry = ModelRegistry::new();
+        let v1 = registered(&mut registry, "v1.0.0");
+        let v2 = registered(&mut registry, "v2.0.0");
+        let model_type = ModelType::AnomalyDetection;
+
+        assert!(registry.rollback(&model_type, "no model", "ops").is_err());
+        registry.deploy_model(&v1).unwrap();
+        let err = registry.rollback(&model_type, "regression", "ops").unwrap_err();
+        assert!(err.to_string().contains("nothing to roll back to"));
+
+        registry.deploy_model(&v2).unwrap();
+        assert_eq!(registry.get_model(&v1).unwrap().status, ModelStatus::Registered);
+
+        assert_eq!(registry.rollback(&model_type, "false positives doubled", "heidi").unwrap(), v1);
+        assert_eq!(registry.get_deployed_model(&model_type).unwrap().id, v1);
+        assert_eq!(registry.get_model(&v1).unwrap().status, ModelStatus::Production);
+        assert_eq!(registry.get_model(&v2).unwrap().status, ModelStatus::Registered);
+        assert_eq!(registry.get_versions("anomaly-detector").len(), 2);
+
+        let history = registry.deployment_history(&model_type);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].model_id, v2);
+        assert_eq!(history[1].previous, Some(v1));
+        assert_eq!(history[2].model_id, v1);
+        assert_eq!(history[2].previous, Some(v2));
+        assert_eq!(
+            history[2].action,
+            DeploymentAction::RolledBack {
+                reason: "false positives doubled".to_string(),
+                triggered_by: "heidi".to_string(),
+            }
+        );
+
+        // v1 was the first deployment, so there is nothing further back
+        assert!(registry.rollback(&model_type, "again", "heidi").is_err());
+    }
+
+    #[test]
+    fn test_rollback_skips_archived_versions() {
+        let mut registry = ModelRegistry::new();
+        let v1 = registered(&mut registry, "v1.0.0");
+        let v2 = registered(&mut registry, "v2.0.0");
+        let v3 = registered(&mut registry, "v3.0.0");
+        let model_type = ModelType::AnomalyDetection;
+
+        registry.deploy_model(&v1).unwrap();
+        registry.deploy_model(&v2).unwrap();
+        registry.deploy_model(&v3).unwrap();
+        registry.archive_model(&v2).unwrap();
+
+        assert_eq!(registry.rollback(&model_type, "latency", "ops").unwrap(), v1);
+        assert_eq!(registry.get_model(&v2).unwrap().status, ModelStatus::Archived);
+    }
+
+    #[test]
+    fn test_staged_rollout() {
+        let mut registry = ModelRegistry::new();
+        let v1 = registered(&mut registry, "v1.0.0");
+        let v2 = registered(&mut registry, "v2.0.0");
+        let v3 = registered(&mut registry, "v3.0.0");
+        let model_type = ModelType::AnomalyDetection;
+        registry.deploy_model(&v1).unwrap();
+
+        registry.advance(&v2, ModelStatus::Shadow).unwrap();
+        let plan = registry.load_plan(&model_type);
+        assert_eq!(plan.versions(), vec![v1, v2]);
+        assert_eq!(plan.shadow, Some(v2));
+        assert_eq!(plan.canary_fraction(), 0.0);
+        assert!((0..100).all(|i| plan.route(&format!("flow-{}", i)) == Some(v1)));
+
+        // One candidate at a time, and only real stages
+        assert!(registry.advance(&v3, ModelStatus::Shadow).is_err());
+        assert!(registry.advance(&v2, ModelStatus::Canary(0)).is_err());
+        assert!(registry.advance(&v2, ModelStatus::Canary(100)).is_err());
+        assert!(registry.advance(&v2, ModelStatus::Archived).is_err());
+
+        registry.advance(&v2, ModelStatus::Canary(10)).unwrap();
+        let plan = registry.load_plan(&model_type);
+        assert_eq!(plan.shadow, None);
+        assert_eq!((plan.canary, plan.canary_fraction()), (Some(v2), 0.1));
+        let to_canary: Vec<String> = (0..10_000)
+            .map(|i| format!("flow-{}", i))
+            .filter(|flow| plan.route(flow) == Some(v2))
+            .collect();
+        assert!((800..=1200).contains(&to_canary.len()), "{} of 10000 to the canary", to_canary.len());
+
+        // Widening the canary keeps the entities it already had
+        registry.advance(&v2, ModelStatus::Canary(50)).unwrap();
+        let plan = registry.load_plan(&model_type);
+        assert!(to_canary.iter().all(|flow| plan.route(flow) == Some(v2)));
+
+        registry.advance(&v2, ModelStatus::Production).unwrap();
+        let plan = registry.load_plan(&model_type);
+        assert_eq!((plan.production, plan.canary), (Some(v2), None));
+        assert_eq!(registry.get_model(&v1).unwrap().status, ModelStatus::Registered);
+        assert!(registry.advance(&v2, ModelStatus::Shadow).is_err());
+
+        // A bad canary is withdrawn without touching production
+        registry.advance(&v3, ModelStatus::Canary(5)).unwrap();
+        assert_eq!(registry.withdraw(&model_type, "error rate", "ops").unwrap(), v3);
+        assert_eq!(registry.get_model(&v3).unwrap().status, ModelStatus::Registered);
+        assert_eq!(registry.load_plan(&model_type).versions(), vec![v2]);
+        assert!(registry.withdraw(&model_type, "again", "ops").is_err());
+
+        // Rollback restores v1 in one call
+        assert_eq!(registry.rollback(&model_type, "recall dropped", "ivan").unwrap(), v1);
+        assert_eq!(registry.load_plan(&model_type).production, Some(v1));
+        let actions: Vec<DeploymentAction> = registry.deployment_history(&model_type)
+            .into_iter()
+            .map(|e| e.action.clone())
+            .collect();
+        assert_eq!(actions, vec![
+            DeploymentAction::Deployed,
+            DeploymentAction::Staged { stage: ModelStatus::Shadow },
+            DeploymentAction::Staged { stage: ModelStatus::Canary(10) },
+            DeploymentAction::Staged { stage: ModelStatus::Canary(50) },
+            DeploymentAction::Deployed,
+            DeploymentAction::Staged { stage: ModelStatus::Canary(5) },
+            DeploymentAction::Withdrawn { reason: "error rate".to_string(), triggered_by: "ops".to_string() },
+            DeploymentAction::RolledBack { reason: "recall dropped".to_string(), triggered_by: "ivan".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_referenced_version_cannot_be_archived() {
+        let mut registry = ModelRegistry::new();
+        let v1 = registered(&mut registry, "v1.0.0");
+        let v2 = registered(&mut registry, "v2.0.0");
+        registry.deploy_model(&v1).unwrap();
+        registry.advance(&v2, ModelStatus::Shadow).unwrap();
+
+        registry.register_consumer("anomaly-detector", ModelType::AnomalyDetection);
+        registry.register_consumer("encrypted-dpi", ModelType::EncryptedDpi);
+        let plan = registry.load_plan(&ModelType::AnomalyDetection);
+        registry.report_loaded("anomaly-detector", &plan.versions()).unwrap();
+        assert!(registry.report_loaded("encrypted-dpi", &[v1]).is_err());
+        assert!(registry.report_loaded("threat-classifier", &[v1]).is_err());
+
+        assert_eq!(registry.consumers_of(&v2), vec!["anomaly-detector"]);
+        let err = registry.archive_model(&v2).unwrap_err();
+        assert!(err.to_string().contains("still loaded by anomaly-detector"));
+
+        // Once the consumer unloads it the version can go
+        registry.withdraw(&ModelType::AnomalyDetection, "not needed", "ops").unwrap();
+        let plan = registry.load_plan(&ModelType::AnomalyDetection);
+        registry.report_loaded("anomaly-detector", &plan.versions()).unwrap();
+        registry.archive_model(&v2).unwrap();
+        assert!(registry.archive_model(&v1).is_err());
+        assert!(registry.unregister_consumer("anomaly-detector"));
+        registry.archive_model(&v1).unwrap();
+    }
+
+    /// Loads every version the plan names that has a bundle
+    #[derive(Default)]
+    struct BundleConsumer {
+        loaded: Vec<Uuid>,
+    }
+
+    impl ModelConsumer for BundleConsumer {
+        fn model_type(&self) -> ModelType {
+            ModelType::AnomalyDetection
+        }
+
+        fn apply_plan(&mut self, plan: &LoadPlan, models: &[&ModelVersion]) -> Result<()> {
+            for model in models {
+                if model.bundle().is_none() {
+                    anyhow::bail!("{} has no bundle", model.id);
+                }
+                if !self.loaded.contains(&model.id) {
+                    self.loaded.push(model.id);
+                }
+            }
+            let wanted = plan.versions();
+            self.loaded.retain(|id| wanted.contains(id));
+            Ok(())
+        }
+
+        fn loaded_versions(&self) -> Vec<Uuid> {
+            self.loaded.clone()
+        }
+    }
+
+    #[test]
+    fn test_sync_consumer_follows_rollout() {
+        let mut registry = ModelRegistry::new();
+        let mut bundled = |version: &str, bundle: Option<&str>| {
+            let mut model = ModelVersion::new("anomaly-detector", version, ModelType::AnomalyDetection, "judy");
+            if let Some(bundle) = bundle {
+                model = model.with_bundle(bundle);
+            }
+            model.status = ModelStatus::Registered;
+            registry.register_model(model).unwrap()
+        };
+        let v1 = bundled("v1.0.0", Some("/var/lib/patronus/models/v1"));
+        let v2 = bundled("v2.0.0", None);
+        let v3 = bundled("v3.0.0", Some("/var/lib/patronus/models/v3"));
+        let model_type = ModelType::AnomalyDetection;
+        assert_eq!(registry.get_model(&v1).unwrap().bundle(), Some(Path::new("/var/lib/patronus/models/v1")));
+
+        // A canary needs production traffic to take a share of
+        assert!(registry.advance(&v3, ModelStatus::Canary(20)).is_err());
+        registry.deploy_model(&v1).unwrap();
+        let mut consumer = BundleConsumer::default();
+        registry.sync_consumer("anomaly-detector", &mut consumer).unwrap();
+        assert_eq!(registry.consumers_of(&v1), vec!["anomaly-detector"]);
+
+        // A version that cannot be loaded leaves the consumer as it was
+        registry.advance(&v2, ModelStatus::Canary(20)).unwrap();
+        assert!(registry.sync_consumer("anomaly-detector", &mut consumer).is_err());
+        assert_eq!(consumer.loaded, vec![v1]);
+        registry.withdraw(&model_type, "no bundle", "ops").unwrap();
+        assert!(registry.load_plan(&model_type).canary_split.is_none());
+
+        registry.advance(&v3, ModelStatus::Canary(20)).unwrap();
+        let plan = registry.sync_consumer("anomaly-detector", &mut consumer).unwrap();
+        assert_eq!(consumer.loaded, vec![v1, v3]);
+        assert_eq!(registry.consumers_of(&v3), vec!["anomaly-detector"]);
+        assert!(registry.archive_model(&v3).is_err());
+
+        // The canary is routed, and measured, by an A/B split against production
+        let split = plan.canary_split.clone().unwrap();
+        assert_eq!((split.model_id(Variant::A), split.model_id(Variant::B), split.b_percent()), (v1, v3, 20));
+        let flow = (0..100).map(|i| format!("flow-{}", i)).find(|flow| plan.route(flow) == Some(v3)).unwrap();
+        assert_eq!(split.route(&flow).variant, Variant::B);
+        split.record_success(Variant::B, 4.0);
+        let again = registry.load_plan(&model_type).canary_split.unwrap();
+        assert_eq!(again.compare().variant_b.requests, 1);
+
+        registry.advance(&v3, ModelStatus::Production).unwrap();
+        registry.sync_consumer("anomaly-detector", &mut consumer).unwrap();
+        assert_eq!(consumer.loaded, vec![v3]);
+        registry.archive_model(&v1).unwrap();
+    }
+
     #[test]
     fn test_tag_search() {
         let mut registry = ModelRegistry::new();
@@ -6,6 +6,8 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc, Duration};
 use anyhow::Result;
 
+use crate::drift::{DriftBaseline, DriftMethod, DriftReport};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TriggerType {
     TimeBasedSchedule,
@@ -20,6 +22,13 @@ pub struct PerformanceThresholds {
     pub min_precision: f64,
     pub min_recall: f64,
     pub max_latency_ms: f64,
+    /// Quiet period after a performance or drift trigger fires
+    #[serde(default = "default_cooldown_minutes")]
+    pub cooldown_minutes: u32,
+}
+
+fn default_cooldown_minutes() -> u32 {
+    360
 }
 
 impl Default for PerformanceThresholds {
@@ -29,6 +38,7 @@ impl Default for PerformanceThresholds {
             min_precision: 0.80,
             min_recall: 0.80,
             max_latency_ms: 100.0,
+            cooldown_minutes: default_cooldown_minutes(),
         }
     }
 }
@@ -42,6 +52,12 @@ pub struct RetrainingTrigger {
     pub schedule_interval_days: Option<u32>,
     pub performance_thresholds: Option<PerformanceThresholds>,
     pub data_drift_threshold: Option<f64>,
+    /// Drift windows in a row that must exceed the threshold; 1 if unset
+    #[serde(default)]
+    pub consecutive_windows: u32,
+    /// Drift windows in a row that exceeded the threshold so far
+    #[serde(default)]
+    pub windows_over_threshold: u32,
     pub last_triggered: Option<DateTime<Utc>>,
     pub trigger_count: u32,
 }
@@ -56,6 +72,8 @@ impl RetrainingTrigger {
             schedule_interval_days: Some(interval_days),
             performance_thresholds: None,
             data_drift_threshold: None,
+            consecutive_windows: 1,
+            windows_over_threshold: 0,
             last_triggered: None,
             trigger_count: 0,
         }
@@ -70,6 +88,8 @@ impl RetrainingTrigger {
             schedule_interval_days: None,
             performance_thresholds: Some(thresholds),
             data_drift_threshold: None,
+            consecutive_windows: 1,
+            windows_over_threshold: 0,
             last_triggered: None,
             trigger_count: 0,
         }
@@ -84,11 +104,37 @@ impl RetrainingTrigger {
             schedule_interval_days: None,
             performance_thresholds: None,
             data_drift_threshold: Some(threshold),
+            consecutive_windows: 1,
+            windows_over_threshold: 0,
             last_triggered: None,
             trigger_count: 0,
         }
     }
 
+    /// Fire only after `windows` drift evaluations in a row exceed the
+    /// threshold, so a single noisy window does not start a retrain
+    pub fn with_consecutive_windows(mut self, windows: u32) -> Self {
+        self.consecutive_windows = windows.max(1);
+        self
+    }
+
+    /// Override the cooldown, keeping any other thresholds
+    pub fn with_cooldown(mut self, minutes: u32) -> Self {
+        self.performance_thresholds
+            .get_or_insert_with(PerformanceThresholds::default)
+            .cooldown_minutes = minutes;
+        self
+    }
+
+    /// Whether the trigger fired too recently to fire again
+    pub fn in_cooldown(&self, now: DateTime<Utc>) -> bool {
+        let minutes = self.performance_thresholds
+            .as_ref()
+            .map_or_else(default_cooldown_minutes, |t| t.cooldown_minutes);
+        self.last_triggered
+            .is_some_and(|last| now.signed_duration_since(last) < Duration::minutes(i64::from(minutes)))
+    }
+
     pub fn should_trigger_time_based(&self) -> bool {
         if !self.enabled || self.trigger_type != TriggerType::TimeBasedSchedule {
             return false;
@@ -108,7 +154,7 @@ impl RetrainingTrigger {
     }
 
     pub fn should_trigger_performance(&self, metrics: &HashMap<String, f64>) -> bool {
-        if !self.enabled || self.trigger_type != TriggerType::PerformanceDegradation {
+        if !self.enabled || self.trigger_type != TriggerType::PerformanceDegradation || self.in_cooldown(Utc::now()) {
             return false;
         }
 
@@ -145,7 +191,7 @@ impl RetrainingTrigger {
     }
 
     pub fn should_trigger_data_drift(&self, drift_score: f64) -> bool {
-        if !self.enabled || self.trigger_type != TriggerType::DataDrift {
+        if !self.enabled || self.trigger_type != TriggerType::DataDrift || self.in_cooldown(Utc::now()) {
             return false;
         }
 
@@ -156,15 +202,46 @@ impl RetrainingTrigger {
         }
     }
 
+    /// Count one drift window towards the streak; true once the streak
+    /// is long enough and the trigger is out of its cooldown
+    ///
+    /// Windows keep counting during the cooldown, so drift that persists
+    /// through it fires as soon as the cooldown ends.
+    pub fn observe_drift_window(&mut self, drift_score: f64) -> bool {
+        if !self.enabled || self.trigger_type != TriggerType::DataDrift {
+            return false;
+        }
+        let over = self.data_drift_threshold.is_some_and(|threshold| drift_score > threshold);
+        self.windows_over_threshold = if over { self.windows_over_threshold + 1 } else { 0 };
+        self.windows_over_threshold >= self.consecutive_windows.max(1) && self.should_trigger_data_drift(drift_score)
+    }
+
     pub fn trigger(&mut self) {
         self.last_triggered = Some(Utc::now());
         self.trigger_count += 1;
+        self.windows_over_threshold = 0;
     }
 }
 
+/// Result of checking a model's recent inputs for drift
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftCheck {
+    pub model_name: String,
+    pub report: DriftReport,
+    /// Whether a drift trigger fired, i.e. retraining is due
+    pub triggered: bool,
+    /// Longest run of windows over threshold among the model's triggers,
+    /// counting this one; for a fired trigger, the run that fired it
+    pub consecutive_windows: u32,
+    /// Threshold of the trigger that fired
+    pub threshold: Option<f64>,
+}
+
 pub struct RetrainingManager {
     triggers: HashMap<Uuid, RetrainingTrigger>,
     model_triggers: HashMap<String, Vec<Uuid>>, // model_name -> [trigger_ids]
+    drift_baselines: HashMap<String, DriftBaseline>,
+    drift_reports: HashMap<String, DriftReport>, // model_name -> latest report
 }
 
 impl RetrainingManager {
@@ -172,6 +249,8 @@ impl RetrainingManager {
         Self {
             triggers: HashMap::new(),
             model_triggers: HashMap::new(),
+            drift_baselines: HashMap::new(),
+            drift_reports: HashMap::new(),
         }
     }
 
@@ -254,23 +333,114 @@ impl RetrainingManager {
         false
     }
 
+    /// Count one drift window for the model's drift triggers; true if one
+    /// fired
     pub fn check_data_drift_triggers(&mut self, model_name: &str, drift_score: f64) -> bool {
-        if let Some(trigger_ids) = self.model_triggers.get(model_name) {
-            for trigger_id in trigger_ids {
-                if let Some(trigger) = self.triggers.get_mut(trigger_id) {
-                    if trigger.should_trigger_data_drift(drift_score) {
-                        trigger.trigger();
-                        tracing::warn!(
-                            "Data drift detected for model: {} (score: {})",
-                            model_name,
-                            drift_score
-                        );
-                        return true;
-                    }
-                }
+        self.observe_drift_window(model_name, drift_score).0.is_some()
+    }
+
+    /// The threshold of the trigger that fired, if any, and the longest
+    /// streak of windows over threshold
+    fn observe_drift_window(&mut self, model_name: &str, drift_score: f64) -> (Option<f64>, u32) {
+        let mut fired = None;
+        let mut streak = 0;
+        for trigger_id in self.model_triggers.get(model_name).into_iter().flatten() {
+            let Some(trigger) = self.triggers.get_mut(trigger_id) else { continue };
+            let due = trigger.observe_drift_window(drift_score);
+            streak = streak.max(trigger.windows_over_threshold);
+            if due && fired.is_none() {
+                tracing::warn!(
+                    "Data drift detected for model: {} (score: {}, {} windows over threshold)",
+                    model_name, drift_score, trigger.windows_over_threshold
+                );
+                trigger.trigger();
+                fired = trigger.data_drift_threshold;
             }
         }
-        false
+        (fired, streak)
+    }
+
+    /// Record the training distribution drift is measured against
+    pub fn set_drift_baseline(&mut self, model_name: impl Into<String>, baseline: DriftBaseline) {
+        self.drift_baselines.insert(model_name.into(), baseline);
+    }
+
+    /// Score `recent` inputs against the model's training distribution and
+    /// fire its drift triggers if the score exceeds their threshold
+    ///
+    /// Each call is one window, e.g. the rows of the feature collector's
+    /// current aggregation window.
+    pub fn evaluate_data_drift(
+        &mut self,
+        model_name: &str,
+        recent: &[Vec<f64>],
+        method: DriftMethod,
+    ) -> Result<DriftCheck> {
+        let baseline = self.drift_baselines.get(model_name)
+            .ok_or_else(|| anyhow::anyhow!("No drift baseline for model: {}", model_name))?;
+        let report = baseline.compare(recent, method)?;
+        tracing::debug!(
+            "Drift score for {}: {:.4} ({:?}, worst feature {:?})",
+            model_name, report.score, method, report.worst_feature()
+        );
+
+        let (fired, streak) = self.observe_drift_window(model_name, report.score);
+        self.drift_reports.insert(model_name.to_string(), report.clone());
+        Ok(DriftCheck {
+            model_name: model_name.to_string(),
+            report,
+            triggered: fired.is_some(),
+            consecutive_windows: streak,
+            threshold: fired,
+        })
+    }
+
+    /// The most recent drift evaluation of a model
+    pub fn last_drift_report(&self, model_name: &str) -> Option<&DriftReport> {
+        self.drift_reports.get(model_name)
+    }
+
+    /// The latest drift score of one of a model's input features
+    pub fn feature_drift(&self, model_name: &str, feature: &str) -> Option<f64> {
+        self.drift_reports.get(model_name)?.feature_score(feature)
+    }
+
+    /// Latest drift scores in the Prometheus text format
+    pub fn drift_metrics(&self) -> String {
+        let mut models: Vec<(&String, &DriftReport)> = self.drift_reports.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut out = String::new();
+        out.push_str("# HELP patronus_mlops_drift_score Highest feature drift score of a model\n");
+        out.push_str("# TYPE patronus_mlops_drift_score gauge\n");
+        for (model, report) in &models {
+            out.push_str(&format!(
+                "patronus_mlops_drift_score{{model=\"{}\",method=\"{}\"}} {}\n",
+                model, report.method.as_str(), report.score
+            ));
+        }
+        out.push_str("# HELP patronus_mlops_feature_drift_score Drift score of a model input feature\n");
+        out.push_str("# TYPE patronus_mlops_feature_drift_score gauge\n");
+        for (model, report) in &models {
+            for (feature, score) in &report.feature_scores {
+                out.push_str(&format!(
+                    "patronus_mlops_feature_drift_score{{model=\"{}\",feature=\"{}\",method=\"{}\"}} {}\n",
+                    model, feature, report.method.as_str(), score
+                ));
+            }
+        }
+        out.push_str("# HELP patronus_mlops_drift_windows_over_threshold Drift windows in a row over the trigger threshold\n");
+        out.push_str("# TYPE patronus_mlops_drift_windows_over_threshold gauge\n");
+        for (model, _) in &models {
+            let streak = self.list_triggers_for_model(model)
+                .iter()
+                .filter(|t| t.trigger_type == TriggerType::DataDrift)
+                .map(|t| t.windows_over_threshold)
+                .max()
+                .unwrap_or(0);
+            out.push_str(&format!("patronus_mlops_drift_windows_over_threshold{{model=\"{}\"}} {}\n", model, streak));
+        }
+        out
     }
 
     pub fn get_trigger(&self, trigger_id: &Uuid) -> Option<&RetrainingTrigger> {
@@ -333,6 +503,139 @@ mod tests {
         assert!(trigger.should_trigger_data_drift(0.4)); // Above threshold
     }
 
+    #[test]
+    fn test_drift_trigger_cooldown() {
+        let mut trigger = RetrainingTrigger::data_drift("test-model", 0.3).with_cooldown(60);
+        trigger.trigger();
+        assert!(!trigger.should_trigger_data_drift(0.9));
+
+        trigger.last_triggered = Some(Utc::now() - Duration::minutes(61));
+        assert!(trigger.should_trigger_data_drift(0.9));
+    }
+
+    #[tokio::test]
+    async fn test_distribution_shift_schedules_retraining() {
+        use crate::pipeline::{PipelineStatus, TrainingConfig, TrainingPipeline};
+
+        struct NoopExecutor;
+
+        #[async_trait::async_trait]
+        impl crate::pipeline::PipelineExecutor for NoopExecutor {
+            async fn execute_stage(
+                &self,
+                _stage: &crate::pipeline::PipelineStage,
+                _config: &TrainingConfig,
+            ) -> Result<HashMap<String, f64>> {
+                Ok(HashMap::new())
+            }
+        }
+
+        // Deterministic spread of values in [0, 1)
+        let sample = |i: usize, shift: f64| vec![(i * 37 % 1000) as f64 / 1000.0 + shift, (i * 13 % 100) as f64];
+        let training: Vec<Vec<f64>> = (0..2000).map(|i| sample(i, 0.0)).collect();
+
+        let mut manager = RetrainingManager::new();
+        manager.set_drift_baseline(
+            "anomaly-detector",
+            DriftBaseline::from_samples(&["latency", "port_bucket"], &training, 10).unwrap(),
+        );
+        let trigger_id = manager.add_trigger(RetrainingTrigger::data_drift("anomaly-detector", 0.25));
+        let mut pipeline = TrainingPipeline::new(NoopExecutor);
+
+        let same: Vec<Vec<f64>> = (0..500).map(|i| sample(i * 3 + 1, 0.0)).collect();
+        let check = manager.evaluate_data_drift("anomaly-detector", &same, DriftMethod::Psi).unwrap();
+        assert!(check.report.score < 0.1, "score {}", check.report.score);
+        assert!(!check.triggered);
+
+        let shifted: Vec<Vec<f64>> = (0..500).map(|i| sample(i, 0.5)).collect();
+        let check = manager.evaluate_data_drift("anomaly-detector", &shifted, DriftMethod::Psi).unwrap();
+        assert!(check.report.score > 0.25, "score {}", check.report.score);
+        assert_eq!(check.report.worst_feature(), Some("latency"));
+        assert!(check.triggered);
+        pipeline.create_run(TrainingConfig::new("anomaly-detector", "v1.1.0"), "drift");
+
+        assert_eq!(pipeline.list_runs_by_status(&PipelineStatus::Pending).len(), 1);
+        assert_eq!(manager.get_trigger(&trigger_id).unwrap().trigger_count, 1);
+        assert_eq!(manager.last_drift_report("anomaly-detector").unwrap().score, check.report.score);
+
+        // Still drifted, but the trigger is cooling down
+        let check = manager.evaluate_data_drift("anomaly-detector", &shifted, DriftMethod::KlDivergence).unwrap();
+        assert!(check.report.score > 0.25);
+        assert!(!check.triggered);
+    }
+
+    #[tokio::test]
+    async fn test_sustained_drift_against_captured_baseline() {
+        use crate::pipeline::{PipelineExecutor, PipelineStage, TrainingConfig, TrainingFeatures, TrainingPipeline};
+        use std::sync::{Arc, Mutex};
+
+        let sample = |i: usize, shift: f64| vec![(i * 37 % 1000) as f64 / 1000.0 + shift, (i * 13 % 100) as f64];
+
+        struct FeatureExecutor(Vec<Vec<f64>>);
+
+        #[async_trait::async_trait]
+        impl PipelineExecutor for FeatureExecutor {
+            async fn execute_stage(&self, _stage: &PipelineStage, _config: &TrainingConfig) -> Result<HashMap<String, f64>> {
+                Ok(HashMap::new())
+            }
+
+            async fn training_features(&self, _config: &TrainingConfig) -> Option<TrainingFeatures> {
+                Some(TrainingFeatures {
+                    names: vec!["latency".to_string(), "port_bucket".to_string()],
+                    samples: self.0.clone(),
+                })
+            }
+        }
+
+        let manager = Arc::new(Mutex::new(RetrainingManager::new()));
+        let training: Vec<Vec<f64>> = (0..2000).map(|i| sample(i, 0.0)).collect();
+        let mut pipeline = TrainingPipeline::new(FeatureExecutor(training)).with_retraining(Arc::clone(&manager));
+        let run_id = pipeline.create_run(TrainingConfig::new("anomaly-detector", "v1.0.0"), "ops");
+        pipeline.execute_run(&run_id).await.unwrap();
+
+        let baseline = pipeline.get_run(&run_id).unwrap().drift_baseline.clone().unwrap();
+        assert!((baseline.features[0].mean - 0.4995).abs() < 1e-3, "mean {}", baseline.features[0].mean);
+        assert!((baseline.features[0].variance - 1.0 / 12.0).abs() < 1e-3);
+
+        let mut manager = manager.lock().unwrap();
+        manager.add_trigger(RetrainingTrigger::data_drift("anomaly-detector", 0.2).with_consecutive_windows(3));
+        let shifted: Vec<Vec<f64>> = (0..500).map(|i| sample(i, 0.3)).collect();
+        let normal: Vec<Vec<f64>> = (0..500).map(|i| sample(i * 3 + 1, 0.0)).collect();
+
+        // A normal window in between restarts the count
+        for (window, expected_streak) in [(&shifted, 1), (&shifted, 2), (&normal, 0), (&shifted, 1), (&shifted, 2)] {
+            let check = manager.evaluate_data_drift("anomaly-detector", window, DriftMethod::KolmogorovSmirnov).unwrap();
+            assert!(!check.triggered);
+            assert_eq!(check.consecutive_windows, expected_streak);
+        }
+        let check = manager.evaluate_data_drift("anomaly-detector", &shifted, DriftMethod::KolmogorovSmirnov).unwrap();
+        assert!(check.triggered);
+        assert_eq!((check.consecutive_windows, check.threshold), (3, Some(0.2)));
+
+        let latency = manager.feature_drift("anomaly-detector", "latency").unwrap();
+        assert!((0.25..=0.35).contains(&latency), "KS {}", latency);
+        assert!(manager.feature_drift("anomaly-detector", "port_bucket").unwrap() < 0.05);
+        assert_eq!(manager.feature_drift("anomaly-detector", "missing"), None);
+
+        let metrics = manager.drift_metrics();
+        assert!(metrics.contains("# TYPE patronus_mlops_feature_drift_score gauge\n"));
+        assert!(metrics.contains(&format!(
+            "patronus_mlops_feature_drift_score{{model=\"anomaly-detector\",feature=\"latency\",method=\"ks\"}} {}\n",
+            latency
+        )));
+        assert!(metrics.contains("patronus_mlops_drift_windows_over_threshold{model=\"anomaly-detector\"} 0\n"));
+
+        let run_id = pipeline.create_drift_run(TrainingConfig::new("anomaly-detector", "v1.1.0"), &check).unwrap();
+        let run = pipeline.get_run(&run_id).unwrap();
+        assert_eq!(run.created_by, "drift-trigger");
+        assert_eq!(run.metadata["trigger"], "DataDrift");
+        assert_eq!(run.metadata["drift"]["consecutive_windows"], 3);
+        assert_eq!(run.metadata["drift"]["report"]["feature_scores"][0][0], "latency");
+
+        let quiet = manager.evaluate_data_drift("anomaly-detector", &normal, DriftMethod::Psi).unwrap();
+        assert!(pipeline.create_drift_run(TrainingConfig::new("anomaly-detector", "v1.2.0"), &quiet).is_err());
+    }
+
     #[test]
     fn test_retraining_manager() {
         let mut manager = RetrainingManager::new();
@@ -2,12 +2,54 @@
 //!
 //! Proactive monitoring with configurable alert rules and notifications.
 //! Integrates with Prometheus Alertmanager and supports multiple notification channels.
+//!
+//! Alerts are routed to named channels by rule or severity, deliveries are
+//! retried with backoff, unacknowledged alerts escalate to secondary
+//! channels, and silences mute matching alerts during maintenance windows.
+//! Each channel delivers from its own bounded queue, so a slow or
+//! unreachable channel never holds up rule evaluation or other channels.
+//!
+//! Expression rules are evaluated against [`MetricsCollector`] series and
+//! alert per matching series; a condition must hold for the rule's
+//! duration (pending) before it fires, and firing alerts are resolved once
+//! the condition clears. Rules declared as `AlertRule` resources are
+//! hot-reloaded from the applied configuration.
+//!
+//! Alerts can also be raised and resolved by other subsystems; neighbor
+//! table issues fire through [`AlertManager::watch_neighbors`], backup and
+//! restore failures through [`AlertManager::watch_backups`].
 
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use patronus_config::{AlertRuleSeverity, AlertRuleSpec, DeclarativeConfig, ResourceSpec};
+use patronus_core::backup::{BackupEvent, BackupOperation};
+use patronus_core::validation::{codes, register_validator, ValidationError};
+use patronus_diagnostics::{NeighborAlert, NeighborEvent, NeighborIssue};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tokio::time::{interval, Duration};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
+use tokio::time::{interval, Duration, MissedTickBehavior};
 use sysinfo::{System, Disks};
 
+use crate::expr::{self, ExprEvaluator, SeriesSnapshot};
+use crate::metrics::MetricsCollector;
+use crate::silences::{Silence, SilenceStore};
+
+/// Header carrying the HMAC-SHA256 signature of webhook bodies
+pub const SIGNATURE_HEADER: &str = "X-Patronus-Signature-256";
+
+/// Timeout for each SMTP connection attempt and reply
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Notifications waiting per channel before further ones are dropped
+const DELIVERY_QUEUE_DEPTH: usize = 256;
+
 /// Alert severity levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AlertSeverity {
@@ -16,6 +58,116 @@ pub enum AlertSeverity {
     Info,
 }
 
+impl AlertSeverity {
+    /// Lowercase name, used as the `severity` label
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Critical => "critical",
+            Self::Warning => "warning",
+            Self::Info => "info",
+        }
+    }
+}
+
+/// Re-notify secondary channels if an alert stays unacknowledged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Escalation {
+    /// Time since the alert was notified
+    pub after: Duration,
+    /// Channel names to notify
+    pub channels: Vec<String>,
+}
+
+/// Channels notified for alerts of one severity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeverityRoute {
+    pub severity: AlertSeverity,
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub escalation: Option<Escalation>,
+}
+
+/// Severity-based routing
+///
+/// Alerts whose rule names no channels go to the route for their severity;
+/// without a route they go to every channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRouting {
+    pub routes: Vec<SeverityRoute>,
+}
+
+impl AlertRouting {
+    /// Route for `severity`
+    pub fn route(&self, severity: AlertSeverity) -> Option<&SeverityRoute> {
+        self.routes.iter().find(|r| r.severity == severity)
+    }
+}
+
+impl Default for AlertRouting {
+    fn default() -> Self {
+        Self {
+            routes: vec![
+                SeverityRoute {
+                    severity: AlertSeverity::Warning,
+                    channels: vec!["slack".to_string()],
+                    escalation: None,
+                },
+                SeverityRoute {
+                    severity: AlertSeverity::Critical,
+                    channels: vec!["webhook".to_string(), "email".to_string()],
+                    escalation: None,
+                },
+            ],
+        }
+    }
+}
+
+/// Delivery retry policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts per delivery, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each further retry
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (1-based)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Notification counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationStats {
+    /// Notifications delivered
+    pub delivered: u64,
+    /// Notifications dropped after exhausting retries
+    pub failed: u64,
+    /// Retried delivery attempts
+    pub retries: u64,
+    /// Alerts fired while silenced
+    pub silenced: u64,
+    /// Alerts escalated to secondary channels
+    pub escalations: u64,
+    /// Notifications dropped because the channel's queue was full
+    #[serde(default)]
+    pub dropped: u64,
+}
+
 /// Alert rule definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlertRule {
@@ -25,6 +177,12 @@ pub struct AlertRule {
     pub condition: AlertCondition,
     pub duration: Duration,  // How long condition must be true
     pub enabled: bool,
+    /// Channel names for this rule, overriding severity routing
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Escalation for this rule, overriding the severity route's
+    #[serde(default)]
+    pub escalation: Option<Escalation>,
 }
 
 /// Alert conditions
@@ -54,6 +212,74 @@ pub enum AlertCondition {
     ConnectionsAbove { threshold: u64 },
     /// Custom Prometheus query
     PrometheusQuery { query: String, threshold: f64 },
+    /// PromQL-lite expression over collected metrics, alerting per matching
+    /// series, e.g. `rate(interface_errors{name="wan0"}[5m]) > 10`
+    Expression { expr: String },
+    /// Raised and resolved by another subsystem instead of being evaluated,
+    /// e.g. `neighbor_monitor`
+    External { source: String },
+}
+
+impl AlertRule {
+    /// Rule for a declarative `AlertRule` resource
+    pub fn from_spec(name: &str, description: Option<&str>, spec: &AlertRuleSpec) -> Result<Self> {
+        let duration = match &spec.for_duration {
+            Some(d) => expr::parse_duration(d)
+                .map_err(|e| anyhow::anyhow!("{}: invalid for duration: {}", name, e))?,
+            None => Duration::ZERO,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            severity: match spec.severity {
+                AlertRuleSeverity::Critical => AlertSeverity::Critical,
+                AlertRuleSeverity::Warning => AlertSeverity::Warning,
+                AlertRuleSeverity::Info => AlertSeverity::Info,
+            },
+            description: description.unwrap_or(&spec.expr).to_string(),
+            condition: AlertCondition::Expression { expr: spec.expr.clone() },
+            duration,
+            enabled: true,
+            channels: spec.channels.clone(),
+            escalation: None,
+        })
+    }
+}
+
+/// Alert rules declared as `AlertRule` resources in `configs`
+pub fn declared_rules(configs: &[DeclarativeConfig]) -> Result<Vec<AlertRule>> {
+    configs.iter()
+        .filter_map(|config| match &config.spec {
+            ResourceSpec::AlertRule(spec) => Some(AlertRule::from_spec(
+                &config.metadata.name,
+                config.metadata.description.as_deref(),
+                spec,
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check the expression and `for` duration of `AlertRule` resources when
+/// configuration is parsed or applied
+pub fn register_rule_validator() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        register_validator(|spec: &AlertRuleSpec| {
+            let mut errors = Vec::new();
+            if let Err(e) = expr::parse(&spec.expr) {
+                errors.push(ValidationError::new("expr", e.to_string())
+                    .with_code(codes::INVALID_FORMAT)
+                    .with_value(&spec.expr));
+            }
+            if let Some(Err(e)) = spec.for_duration.as_deref().map(expr::parse_duration) {
+                errors.push(ValidationError::new("for", e.to_string())
+                    .with_code(codes::INVALID_FORMAT)
+                    .with_value(spec.for_duration.as_deref().unwrap_or_default()));
+            }
+            errors
+        });
+    });
 }
 
 /// Alert notification channel
@@ -62,8 +288,17 @@ pub enum NotificationChannel {
     /// Email notification
     Email {
         to: Vec<String>,
+        /// `host` or `host:port`; the port defaults to 465 for TLS, 587
+        /// for STARTTLS and 25 for plain SMTP
         smtp_server: String,
         from: String,
+        #[serde(default)]
+        security: SmtpSecurity,
+        /// SMTP AUTH login, only ever sent over TLS
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
     },
     /// Slack webhook
     Slack {
@@ -88,6 +323,9 @@ pub enum NotificationChannel {
         url: String,
         method: String,
         headers: HashMap<String, String>,
+        /// Key for the HMAC-SHA256 body signature in [`SIGNATURE_HEADER`]
+        #[serde(default)]
+        secret: Option<String>,
     },
     /// Syslog
     Syslog {
@@ -96,71 +334,640 @@ pub enum NotificationChannel {
     },
 }
 
+/// Transport security of an email channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SmtpSecurity {
+    /// TLS from the first byte (SMTPS)
+    Tls,
+    /// Plain connection upgraded with STARTTLS, which the server must offer
+    #[default]
+    StartTls,
+    /// No encryption, for a relay on a trusted local network; credentials
+    /// are refused
+    Plain,
+}
+
+impl NotificationChannel {
+    /// Name a channel is registered under by [`AlertManager::add_channel`]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Email { .. } => "email",
+            Self::Slack { .. } => "slack",
+            Self::Discord { .. } => "discord",
+            Self::PagerDuty { .. } => "pagerduty",
+            Self::Telegram { .. } => "telegram",
+            Self::Webhook { .. } => "webhook",
+            Self::Syslog { .. } => "syslog",
+        }
+    }
+}
+
+/// Alert whose condition holds but not yet for the rule's duration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAlert {
+    pub rule_name: String,
+    pub details: HashMap<String, String>,
+    pub since: DateTime<Utc>,
+}
+
 /// Fired alert
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FiredAlert {
+    /// Unique alert ID, used to acknowledge the alert
+    #[serde(default)]
+    pub id: String,
     pub rule_name: String,
     pub severity: AlertSeverity,
     pub description: String,
     pub fired_at: chrono::DateTime<chrono::Utc>,
     pub details: HashMap<String, String>,
+    /// When channels were last notified; None while silenced
+    #[serde(default)]
+    pub notified_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub acknowledged_by: Option<String>,
+    #[serde(default)]
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    /// Silence muting the alert
+    #[serde(default)]
+    pub silenced_by: Option<String>,
+    #[serde(default)]
+    pub escalated: bool,
+}
+
+impl FiredAlert {
+    pub fn new(
+        rule_name: &str,
+        severity: AlertSeverity,
+        description: &str,
+        details: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            rule_name: rule_name.to_string(),
+            severity,
+            description: description.to_string(),
+            fired_at: Utc::now(),
+            details,
+            notified_at: None,
+            acknowledged_by: None,
+            acknowledged_at: None,
+            silenced_by: None,
+            escalated: false,
+        }
+    }
+
+    /// Details plus `rule` and `severity`, for silence matchers
+    pub fn labels(&self) -> HashMap<String, String> {
+        let mut labels = self.details.clone();
+        labels.insert("rule".to_string(), self.rule_name.clone());
+        labels.insert("severity".to_string(), self.severity.label().to_string());
+        labels
+    }
+}
+
+/// Rules added in code plus those loaded by the last reload
+#[derive(Default)]
+struct RuleSet {
+    rules: Arc<Vec<AlertRule>>,
+    declared: HashSet<String>,
+}
+
+/// Series matching a rule: alert key and alert details
+type Matches = Vec<(String, HashMap<String, String>)>;
+
+/// Notification waiting in a channel's delivery queue
+struct Delivery {
+    channel: NotificationChannel,
+    alert: FiredAlert,
+    resolved: bool,
 }
 
 pub struct AlertManager {
-    rules: Vec<AlertRule>,
-    channels: Vec<NotificationChannel>,
-    active_alerts: HashMap<String, FiredAlert>,
+    rules: Mutex<RuleSet>,
+    channels: Vec<(String, NotificationChannel)>,
+    routing: AlertRouting,
+    retry: RetryPolicy,
+    evaluation_interval: Duration,
+    metrics: Option<Arc<MetricsCollector>>,
+    evaluator: Arc<Mutex<ExprEvaluator>>,
+    /// Rule name -> alert key -> pending alert
+    pending: RwLock<HashMap<String, HashMap<String, PendingAlert>>>,
+    /// Keyed by rule name, plus series labels for expression rules
+    active_alerts: RwLock<HashMap<String, FiredAlert>>,
+    silences: SilenceStore,
+    stats: Arc<RwLock<NotificationStats>>,
+    /// Channel name -> queue drained by the channel's delivery task
+    queues: Mutex<HashMap<String, mpsc::Sender<Delivery>>>,
+    /// Notifications queued or being delivered
+    queued: Arc<AtomicUsize>,
 }
 
 impl AlertManager {
     pub fn new() -> Self {
         Self {
-            rules: Vec::new(),
+            rules: Mutex::new(RuleSet::default()),
             channels: Vec::new(),
-            active_alerts: HashMap::new(),
+            routing: AlertRouting::default(),
+            retry: RetryPolicy::default(),
+            evaluation_interval: Duration::from_secs(30),
+            metrics: None,
+            evaluator: Arc::new(Mutex::new(ExprEvaluator::new())),
+            pending: RwLock::new(HashMap::new()),
+            active_alerts: RwLock::new(HashMap::new()),
+            silences: SilenceStore::new(),
+            stats: Arc::new(RwLock::new(NotificationStats::default())),
+            queues: Mutex::new(HashMap::new()),
+            queued: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Evaluate expression rules against `collector`'s series
+    pub fn with_metrics(mut self, collector: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(collector);
+        self
+    }
+
+    /// Set how often rules are evaluated
+    pub fn with_evaluation_interval(mut self, every: Duration) -> Self {
+        self.evaluation_interval = every;
+        self
+    }
+
+    /// Persist silences to `path`, restoring those still active
+    pub fn with_silence_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.silences = SilenceStore::open(path)?;
+        Ok(self)
+    }
+
+    /// Set the severity routing
+    pub fn with_routing(mut self, routing: AlertRouting) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Set the delivery retry policy
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
     /// Add an alert rule
     pub fn add_rule(&mut self, rule: AlertRule) {
-        self.rules.push(rule);
+        let set = self.rules.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::make_mut(&mut set.rules).push(rule);
+    }
+
+    /// Current rules
+    pub fn rules(&self) -> Arc<Vec<AlertRule>> {
+        self.rule_set().rules.clone()
+    }
+
+    fn rule_set(&self) -> MutexGuard<'_, RuleSet> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the rules loaded by the previous reload, keeping those added
+    /// with [`add_rule`](Self::add_rule)
+    ///
+    /// Every rule is checked first, so an invalid set leaves the current
+    /// rules in place. Alerts of removed rules are resolved.
+    pub async fn reload_rules(&self, rules: Vec<AlertRule>) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut names = HashSet::new();
+        for rule in &rules {
+            if !names.insert(rule.name.clone()) {
+                errors.push(format!("{}: duplicate rule name", rule.name));
+            }
+            if let AlertCondition::Expression { expr } = &rule.condition {
+                if let Err(e) = expr::parse(expr) {
+                    errors.push(format!("{}: {}", rule.name, e));
+                }
+            }
+        }
+
+        let removed: Vec<AlertRule> = {
+            let mut set = self.rule_set();
+            let (declared, fixed): (Vec<AlertRule>, Vec<AlertRule>) = set.rules.iter()
+                .cloned()
+                .partition(|r| set.declared.contains(&r.name));
+            for rule in &fixed {
+                if names.contains(&rule.name) {
+                    errors.push(format!("{}: conflicts with a built-in rule", rule.name));
+                }
+            }
+            if !errors.is_empty() {
+                return Err(anyhow::anyhow!("Invalid alert rules: {}", errors.join("; ")));
+            }
+
+            set.rules = Arc::new(fixed.into_iter().chain(rules).collect());
+            set.declared = names;
+            declared.into_iter().filter(|r| !set.declared.contains(&r.name)).collect()
+        };
+
+        for rule in &removed {
+            self.pending.write().await.remove(&rule.name);
+            let keys: Vec<String> = self.active_alerts.read().await.iter()
+                .filter(|(_, alert)| alert.rule_name == rule.name)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in keys {
+                self.resolve(&key, rule).await;
+            }
+        }
+
+        tracing::info!("Reloaded alert rules ({} removed)", removed.len());
+        Ok(())
+    }
+
+    /// Reload declared rules whenever configuration is applied, e.g. from
+    /// [`ApplyEngine::subscribe`](patronus_config::ApplyEngine::subscribe)
+    pub fn watch_config(
+        self: Arc<Self>,
+        mut configs: watch::Receiver<Vec<DeclarativeConfig>>,
+    ) -> tokio::task::JoinHandle<()> {
+        register_rule_validator();
+
+        tokio::spawn(async move {
+            loop {
+                let rules = declared_rules(&configs.borrow_and_update());
+                let result = match rules {
+                    Ok(rules) => self.reload_rules(rules).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::error!("Failed to reload alert rules: {}", e);
+                }
+
+                if configs.changed().await.is_err() {
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Raise and resolve alerts for issues found by a [`NeighborMonitor`](patronus_diagnostics::NeighborMonitor),
+    /// from its [`subscribe`](patronus_diagnostics::NeighborMonitor::subscribe) receiver
+    ///
+    /// Each issue fires under its own rule, e.g. `NeighborIpConflict`; add
+    /// a rule of that name with an `External` condition to change its
+    /// severity, channels or escalation.
+    pub fn watch_neighbors(
+        self: Arc<Self>,
+        mut events: broadcast::Receiver<NeighborEvent>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let (alert, raised) = match events.recv().await {
+                    Ok(NeighborEvent::Raised(alert)) => (alert, true),
+                    Ok(NeighborEvent::Cleared(alert)) => (alert, false),
+                    Ok(NeighborEvent::MacChanged(_)) => continue,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Missed {} neighbor monitor events", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                let rule = self.neighbor_rule(alert.issue);
+                let details = neighbor_details(&alert);
+                let key = alert_key(&rule.name, &details.iter()
+                    .filter(|(k, _)| *k == "interface" || *k == "ip")
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect());
+                if raised {
+                    self.fire(&key, &rule, details.into_iter().collect()).await;
+                } else {
+                    self.resolve(&key, &rule).await;
+                }
+            }
+        })
     }
 
-    /// Add a notification channel
+    /// Raise and resolve alerts for failed backup operations, from a
+    /// [`BackupManager::subscribe`](patronus_core::BackupManager::subscribe) receiver
+    ///
+    /// Failures fire `BackupFailed`, `BackupUploadFailed`,
+    /// `BackupRetentionFailed` or `RestoreFailed`; the next successful
+    /// operation of the same kind resolves them.
+    pub fn watch_backups(
+        self: Arc<Self>,
+        mut events: broadcast::Receiver<BackupEvent>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Missed {} backup events", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                let resolved = match event {
+                    BackupEvent::Failed(failure) => {
+                        let rule = self.backup_rule(failure.operation);
+                        let mut details = HashMap::new();
+                        if let Some(backup_id) = failure.backup_id {
+                            details.insert("backup_id".to_string(), backup_id);
+                        }
+                        details.insert("error".to_string(), failure.error);
+                        self.fire(&rule.name, &rule, details).await;
+                        continue;
+                    }
+                    BackupEvent::Completed(_) => vec![BackupOperation::Backup, BackupOperation::Upload],
+                    BackupEvent::Pruned { .. } => vec![BackupOperation::Retention],
+                    BackupEvent::Restored { .. } => vec![BackupOperation::Restore],
+                };
+
+                for operation in resolved {
+                    let rule = self.backup_rule(operation);
+                    self.resolve(&rule.name, &rule).await;
+                }
+            }
+        })
+    }
+
+    /// Configured rule for a failed backup operation, or its default
+    fn backup_rule(&self, operation: BackupOperation) -> AlertRule {
+        let (name, severity, description) = match operation {
+            BackupOperation::Backup => ("BackupFailed", AlertSeverity::Critical, "Configuration backup failed"),
+            BackupOperation::Upload => (
+                "BackupUploadFailed",
+                AlertSeverity::Critical,
+                "Backup could not be copied to a remote target",
+            ),
+            BackupOperation::Retention => (
+                "BackupRetentionFailed",
+                AlertSeverity::Warning,
+                "Backup retention policy could not be applied",
+            ),
+            BackupOperation::Restore => ("RestoreFailed", AlertSeverity::Critical, "Backup restore failed"),
+        };
+
+        if let Some(rule) = self.rules().iter().find(|r| r.name == name) {
+            return rule.clone();
+        }
+        AlertRule {
+            name: name.to_string(),
+            severity,
+            description: description.to_string(),
+            condition: AlertCondition::External { source: "backup_manager".to_string() },
+            duration: Duration::ZERO,
+            enabled: true,
+            channels: Vec::new(),
+            escalation: None,
+        }
+    }
+
+    /// Configured rule for a neighbor issue, or its default
+    fn neighbor_rule(&self, issue: NeighborIssue) -> AlertRule {
+        let (name, severity, description) = match issue {
+            NeighborIssue::IpConflict => (
+                "NeighborIpConflict",
+                AlertSeverity::Critical,
+                "IP address claimed by several MAC addresses (possible ARP spoofing)",
+            ),
+            NeighborIssue::StaticMacChanged => (
+                "NeighborStaticMacChanged",
+                AlertSeverity::Critical,
+                "Static neighbor entry resolves to a different MAC address",
+            ),
+            NeighborIssue::PinViolation => (
+                "NeighborPinViolation",
+                AlertSeverity::Critical,
+                "Pinned IP address resolves to a different MAC address",
+            ),
+            NeighborIssue::GratuitousArpStorm => (
+                "GratuitousArpStorm",
+                AlertSeverity::Warning,
+                "Gratuitous ARP storm",
+            ),
+        };
+
+        if let Some(rule) = self.rules().iter().find(|r| r.name == name) {
+            return rule.clone();
+        }
+        AlertRule {
+            name: name.to_string(),
+            severity,
+            description: description.to_string(),
+            condition: AlertCondition::External { source: "neighbor_monitor".to_string() },
+            duration: Duration::ZERO,
+            enabled: true,
+            channels: Vec::new(),
+            escalation: None,
+        }
+    }
+
+    /// Add a notification channel, named after its kind (`slack`, `email`, ...)
     pub fn add_channel(&mut self, channel: NotificationChannel) {
-        self.channels.push(channel);
+        self.add_named_channel(channel.kind(), channel);
+    }
+
+    /// Add a notification channel under `name`, for routes and rules to refer to
+    pub fn add_named_channel(&mut self, name: &str, channel: NotificationChannel) {
+        self.channels.push((name.to_string(), channel));
     }
 
     /// Start monitoring and alerting
-    pub async fn start(mut self) {
-        let mut check_interval = interval(Duration::from_secs(30));
+    pub async fn start(self: Arc<Self>) {
+        let mut check_interval = interval(self.evaluation_interval);
+        check_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         loop {
             check_interval.tick().await;
             self.evaluate_rules().await;
+            self.process_timers(Utc::now()).await;
         }
     }
 
-    async fn evaluate_rules(&mut self) {
-        // Collect enabled rules and their evaluation results
-        let mut rule_evaluations = Vec::new();
+    /// Currently firing alerts
+    pub async fn active_alerts(&self) -> Vec<FiredAlert> {
+        self.active_alerts.read().await.values().cloned().collect()
+    }
 
-        for rule in &self.rules {
-            if !rule.enabled {
-                continue;
-            }
+    /// Alerts waiting for their condition to hold for the rule's duration
+    pub async fn pending_alerts(&self) -> Vec<PendingAlert> {
+        self.pending.read().await.values().flat_map(|p| p.values().cloned()).collect()
+    }
+
+    /// Acknowledge a firing alert, stopping its escalation
+    pub async fn acknowledge_alert(&self, alert_id: &str, user: &str) -> Result<FiredAlert> {
+        let mut active = self.active_alerts.write().await;
+        let alert = active.values_mut()
+            .find(|a| a.id == alert_id)
+            .ok_or_else(|| anyhow::anyhow!("Alert {} not found", alert_id))?;
+
+        alert.acknowledged_by = Some(user.to_string());
+        alert.acknowledged_at = Some(Utc::now());
+        tracing::info!("Alert {} acknowledged by {}", alert.rule_name, user);
+
+        Ok(alert.clone())
+    }
+
+    /// Mute alerts of `rule` (or `"*"` for any rule) whose labels match
+    /// `matchers` for `duration`
+    pub async fn silence(
+        &self,
+        rule: &str,
+        duration: Duration,
+        matchers: HashMap<String, String>,
+    ) -> Result<Silence> {
+        self.silences.add(rule, duration, matchers).await
+    }
+
+    /// Lift a silence
+    pub async fn remove_silence(&self, silence_id: &str) -> Result<()> {
+        self.silences.remove(silence_id).await
+    }
+
+    /// All silences, including expired ones not yet purged
+    pub async fn silences(&self) -> Vec<Silence> {
+        self.silences.list().await
+    }
+
+    /// Notification counters
+    pub async fn stats(&self) -> NotificationStats {
+        self.stats.read().await.clone()
+    }
+
+    async fn evaluate_rules(&self) {
+        self.evaluate_rules_at(Utc::now()).await;
+    }
+
+    async fn evaluate_rules_at(&self, now: DateTime<Utc>) {
+        let rules = self.rules();
+        let mut expression_matches = self.evaluate_expressions(&rules, now).await;
 
-            let condition_met = self.check_condition(&rule.condition).await;
-            rule_evaluations.push((rule.clone(), condition_met));
+        for rule in rules.iter().filter(|r| r.enabled) {
+            let matches = match &rule.condition {
+                // Skipped when unparseable or without a collector
+                AlertCondition::Expression { .. } => match expression_matches.remove(&rule.name) {
+                    Some(matches) => matches,
+                    None => continue,
+                },
+                // Fired and resolved by their source
+                AlertCondition::External { .. } => continue,
+                condition => {
+                    if self.check_condition(condition).await {
+                        vec![(rule.name.clone(), HashMap::new())]
+                    } else {
+                        Vec::new()
+                    }
+                }
+            };
+            self.update_rule_state(rule, matches, now).await;
+        }
+    }
+
+    /// Evaluate every expression rule against one snapshot of the
+    /// collector, on the blocking pool so a large rule set never stalls
+    /// collection or other tasks
+    async fn evaluate_expressions(
+        &self,
+        rules: &Arc<Vec<AlertRule>>,
+        now: DateTime<Utc>,
+    ) -> HashMap<String, Matches> {
+        let Some(metrics) = self.metrics.clone() else {
+            return HashMap::new();
+        };
+        let expressions: Vec<(String, String)> = rules.iter()
+            .filter(|r| r.enabled)
+            .filter_map(|r| match &r.condition {
+                AlertCondition::Expression { expr } => Some((r.name.clone(), expr.clone())),
+                _ => None,
+            })
+            .collect();
+        if expressions.is_empty() {
+            return HashMap::new();
         }
 
-        // Process evaluations
-        for (rule, condition_met) in rule_evaluations {
-            if condition_met {
-                self.fire_alert(&rule).await;
-            } else {
-                self.resolve_alert(&rule.name).await;
+        let evaluator = self.evaluator.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let snapshot = SeriesSnapshot::new(now, metrics.snapshot());
+            let sources: Vec<&str> = expressions.iter().map(|(_, expr)| expr.as_str()).collect();
+            let results = evaluator
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .evaluate_all(&sources, &snapshot);
+
+            expressions.iter()
+                .zip(results)
+                .filter_map(|((name, _), result)| match result {
+                    Ok(series) => {
+                        let matches = series.into_iter()
+                            .map(|s| {
+                                let key = alert_key(name, &s.labels);
+                                let mut details: HashMap<_, _> = s.labels.into_iter().collect();
+                                details.insert("value".to_string(), s.value.to_string());
+                                (key, details)
+                            })
+                            .collect();
+                        Some((name.clone(), matches))
+                    }
+                    Err(e) => {
+                        tracing::error!("Alert rule {} has an invalid expression: {}", name, e);
+                        None
+                    }
+                })
+                .collect()
+        });
+
+        task.await.unwrap_or_else(|e| {
+            tracing::error!("Alert expression evaluation failed: {}", e);
+            HashMap::new()
+        })
+    }
+
+    /// Move `rule`'s alerts between pending, firing and resolved given the
+    /// series currently matching it
+    async fn update_rule_state(&self, rule: &AlertRule, matches: Matches, now: DateTime<Utc>) {
+        let mut due = Vec::new();
+        let cleared: Vec<String>;
+        {
+            let active = self.active_alerts.read().await;
+            let mut all_pending = self.pending.write().await;
+            let pending = all_pending.entry(rule.name.clone()).or_default();
+            let keys: HashSet<&str> = matches.iter().map(|(key, _)| key.as_str()).collect();
+
+            // A condition that stopped holding starts its duration over
+            pending.retain(|key, _| keys.contains(key.as_str()));
+
+            for (key, details) in &matches {
+                if active.contains_key(key) {
+                    continue;
+                }
+                let entry = pending.entry(key.clone()).or_insert_with(|| PendingAlert {
+                    rule_name: rule.name.clone(),
+                    details: details.clone(),
+                    since: now,
+                });
+                entry.details = details.clone();
+
+                let held = now.signed_duration_since(entry.since).to_std().unwrap_or_default();
+                if held >= rule.duration {
+                    pending.remove(key);
+                    due.push((key.clone(), details.clone()));
+                }
+            }
+
+            if pending.is_empty() {
+                all_pending.remove(&rule.name);
             }
+
+            cleared = active.iter()
+                .filter(|(key, alert)| alert.rule_name == rule.name && !keys.contains(key.as_str()))
+                .map(|(key, _)| key.clone())
+                .collect();
+        }
+
+        for (key, details) in due {
+            self.fire(&key, rule, details).await;
+        }
+        for key in cleared {
+            self.resolve(&key, rule).await;
         }
     }
 
@@ -274,22 +1081,33 @@ impl AlertManager {
                 // Would need Prometheus client to query
                 false
             }
+            // Evaluated in bulk by evaluate_expressions
+            AlertCondition::Expression { .. } => false,
+            AlertCondition::External { .. } => false,
+        }
+    }
+
+    #[cfg(test)]
+    async fn fire_alert(&self, rule: &AlertRule) {
+        self.fire(&rule.name, rule, HashMap::new()).await;
+    }
+
+    /// Wait until every queued notification is delivered or given up on
+    #[cfg(test)]
+    async fn settle(&self) {
+        while self.queued.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
         }
     }
 
-    async fn fire_alert(&mut self, rule: &AlertRule) {
+    /// Record a firing alert under `key` and notify its channels unless silenced
+    async fn fire(&self, key: &str, rule: &AlertRule, details: HashMap<String, String>) {
         // Check if alert already fired
-        if self.active_alerts.contains_key(&rule.name) {
+        if self.active_alerts.read().await.contains_key(key) {
             return;
         }
 
-        let alert = FiredAlert {
-            rule_name: rule.name.clone(),
-            severity: rule.severity,
-            description: rule.description.clone(),
-            fired_at: chrono::Utc::now(),
-            details: HashMap::new(),
-        };
+        let mut alert = FiredAlert::new(&rule.name, rule.severity, &rule.description, details);
 
         tracing::warn!(
             "Alert fired: {} - {}",
@@ -297,63 +1115,219 @@ impl AlertManager {
             alert.description
         );
 
-        // Send notifications
-        for channel in &self.channels {
-            self.send_notification(channel, &alert).await;
+        if let Some(silence) = self.silences.find_match(&alert, alert.fired_at).await {
+            tracing::info!("Alert {} silenced by {}", alert.rule_name, silence.id);
+            alert.silenced_by = Some(silence.id);
+            self.stats.write().await.silenced += 1;
+        } else {
+            alert.notified_at = Some(Utc::now());
         }
 
-        self.active_alerts.insert(rule.name.clone(), alert);
+        self.active_alerts.write().await.insert(key.to_string(), alert.clone());
+
+        // Send notifications
+        if alert.silenced_by.is_none() {
+            for (name, channel) in self.channels_for(rule) {
+                self.deliver(name, channel, &alert, false).await;
+            }
+        }
     }
 
-    async fn resolve_alert(&mut self, rule_name: &str) {
-        if let Some(alert) = self.active_alerts.remove(rule_name) {
-            tracing::info!("Alert resolved: {}", alert.rule_name);
+    /// Clear the alert under `key` and notify `rule`'s channels
+    async fn resolve(&self, key: &str, rule: &AlertRule) {
+        let Some(alert) = self.active_alerts.write().await.remove(key) else {
+            return;
+        };
+        tracing::info!("Alert resolved: {}", key);
+
+        // Channels never heard about an alert that stayed silenced
+        if alert.notified_at.is_none() {
+            return;
+        }
 
-            // Send resolution notifications
-            for channel in &self.channels {
-                self.send_resolution(channel, &alert).await;
-            }
+        // Send resolution notifications
+        for (name, channel) in self.channels_for(rule) {
+            self.deliver(name, channel, &alert, true).await;
         }
     }
 
-    async fn send_notification(&self, channel: &NotificationChannel, alert: &FiredAlert) {
-        match channel {
-            NotificationChannel::Email { to, smtp_server, from } => {
-                self.send_email(to, smtp_server, from, alert).await;
-            }
-            NotificationChannel::Slack { webhook_url, channel: slack_channel } => {
-                self.send_slack(webhook_url, slack_channel, alert).await;
-            }
-            NotificationChannel::Discord { webhook_url } => {
-                self.send_discord(webhook_url, alert).await;
-            }
-            NotificationChannel::PagerDuty { integration_key } => {
-                self.send_pagerduty(integration_key, alert).await;
-            }
-            NotificationChannel::Telegram { bot_token, chat_id } => {
-                self.send_telegram(bot_token, chat_id, alert).await;
+    /// Notify alerts whose silence lapsed and escalate unacknowledged ones
+    async fn process_timers(&self, now: DateTime<Utc>) {
+        if let Err(e) = self.silences.purge_expired(now).await {
+            tracing::error!("Failed to persist silences: {}", e);
+        }
+
+        let silences = self.silences.list().await;
+        let rules = self.rules();
+        let mut released = Vec::new();
+        let mut escalated = Vec::new();
+
+        {
+            let mut active = self.active_alerts.write().await;
+            for alert in active.values_mut() {
+                if let Some(silence_id) = &alert.silenced_by {
+                    let still_silenced = silences.iter()
+                        .any(|s| &s.id == silence_id && s.is_active(now));
+                    if still_silenced {
+                        continue;
+                    }
+                    alert.silenced_by = None;
+                    alert.notified_at = Some(now);
+                    released.push(alert.clone());
+                    continue;
+                }
+
+                let Some(rule) = rules.iter().find(|r| r.name == alert.rule_name) else {
+                    continue;
+                };
+                let Some(escalation) = self.escalation_for(rule) else {
+                    continue;
+                };
+                let due = alert.notified_at.is_some_and(|notified| {
+                    now.signed_duration_since(notified).to_std().unwrap_or_default() >= escalation.after
+                });
+
+                if due && alert.acknowledged_by.is_none() && !alert.escalated {
+                    alert.escalated = true;
+                    escalated.push((alert.clone(), escalation.clone()));
+                }
             }
-            NotificationChannel::Webhook { url, method, headers } => {
-                self.send_webhook(url, method, headers, alert).await;
+        }
+
+        for alert in released {
+            let Some(rule) = rules.iter().find(|r| r.name == alert.rule_name) else {
+                continue;
+            };
+            for (name, channel) in self.channels_for(rule) {
+                self.deliver(name, channel, &alert, false).await;
             }
-            NotificationChannel::Syslog { server, facility } => {
-                self.send_syslog(server, facility, alert).await;
+        }
+
+        for (mut alert, escalation) in escalated {
+            tracing::warn!(
+                "Escalating unacknowledged alert {} to {:?}",
+                alert.rule_name,
+                escalation.channels
+            );
+            self.stats.write().await.escalations += 1;
+
+            alert.description = format!(
+                "[ESCALATED] {} (unacknowledged for {}s)",
+                alert.description,
+                escalation.after.as_secs()
+            );
+            for (name, channel) in self.named_channels(&escalation.channels) {
+                self.deliver(name, channel, &alert, false).await;
             }
         }
     }
 
-    async fn send_resolution(&self, channel: &NotificationChannel, alert: &FiredAlert) {
-        match channel {
-            NotificationChannel::Email { to, smtp_server, from } => {
-                // Send resolution email
-                tracing::debug!("Sending resolution email to {:?} via {}", to, smtp_server);
-                let _ = (from, alert); // Use variables to avoid warning
-            }
-            NotificationChannel::Slack { webhook_url, channel: slack_channel } => {
-                let payload = serde_json::json!({
-                    "channel": slack_channel,
-                    "username": "Patronus Alerts",
-                    "icon_emoji": ":white_check_mark:",
+    /// Channels notified for `rule`: its own, else its severity route, else all
+    fn channels_for(&self, rule: &AlertRule) -> Vec<(&str, &NotificationChannel)> {
+        if !rule.channels.is_empty() {
+            return self.named_channels(&rule.channels);
+        }
+
+        match self.routing.route(rule.severity) {
+            Some(route) => self.named_channels(&route.channels),
+            None => self.channels.iter().map(|(n, c)| (n.as_str(), c)).collect(),
+        }
+    }
+
+    fn escalation_for<'a>(&'a self, rule: &'a AlertRule) -> Option<&'a Escalation> {
+        rule.escalation.as_ref()
+            .or_else(|| self.routing.route(rule.severity).and_then(|r| r.escalation.as_ref()))
+    }
+
+    fn named_channels(&self, names: &[String]) -> Vec<(&str, &NotificationChannel)> {
+        names.iter()
+            .flat_map(|name| {
+                let matched: Vec<_> = self.channels.iter()
+                    .filter(|(n, _)| n == name)
+                    .map(|(n, c)| (n.as_str(), c))
+                    .collect();
+                if matched.is_empty() {
+                    tracing::warn!("Alert route refers to unknown channel {}", name);
+                }
+                matched
+            })
+            .collect()
+    }
+
+    /// Queue a notification on channel `name` without waiting for it
+    async fn deliver(&self, name: &str, channel: &NotificationChannel, alert: &FiredAlert, resolved: bool) {
+        let queue = {
+            let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+            queues.entry(name.to_string())
+                .or_insert_with(|| self.spawn_delivery_task(name))
+                .clone()
+        };
+
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        let delivery = Delivery { channel: channel.clone(), alert: alert.clone(), resolved };
+        if let Err(e) = queue.try_send(delivery) {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            tracing::error!("Dropping notification of {} to {}: {}", alert.rule_name, name, e);
+            self.stats.write().await.dropped += 1;
+        }
+    }
+
+    /// Start the task delivering channel `name`'s notifications in order
+    fn spawn_delivery_task(&self, name: &str) -> mpsc::Sender<Delivery> {
+        let (sender, mut receiver) = mpsc::channel::<Delivery>(DELIVERY_QUEUE_DEPTH);
+        let name = name.to_string();
+        let retry = self.retry.clone();
+        let stats = Arc::clone(&self.stats);
+        let queued = Arc::clone(&self.queued);
+
+        tokio::spawn(async move {
+            while let Some(delivery) = receiver.recv().await {
+                deliver_with_retry(&name, &delivery, &retry, &stats).await;
+                queued.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        sender
+    }
+
+    async fn send_notification(channel: &NotificationChannel, alert: &FiredAlert) -> Result<()> {
+        match channel {
+            NotificationChannel::Email { to, smtp_server, from, security, username, password } => {
+                let message = email_message(from, to, alert, false)?;
+                send_smtp(smtp_server, *security, username.as_deref(), password.as_deref(), message).await
+            }
+            NotificationChannel::Slack { webhook_url, channel: slack_channel } => {
+                Self::send_slack(webhook_url, slack_channel, alert).await
+            }
+            NotificationChannel::Discord { webhook_url } => {
+                Self::send_discord(webhook_url, alert).await
+            }
+            NotificationChannel::PagerDuty { integration_key } => {
+                Self::send_pagerduty(integration_key, alert).await
+            }
+            NotificationChannel::Telegram { bot_token, chat_id } => {
+                Self::send_telegram(bot_token, chat_id, alert).await
+            }
+            NotificationChannel::Webhook { url, method, headers, secret } => {
+                let payload = serde_json::to_value(alert)?;
+                Self::send_webhook(url, method, headers, secret.as_deref(), &payload).await
+            }
+            NotificationChannel::Syslog { server, facility } => {
+                Self::send_syslog(server, facility, alert).await
+            }
+        }
+    }
+
+    async fn send_resolution(channel: &NotificationChannel, alert: &FiredAlert) -> Result<()> {
+        match channel {
+            NotificationChannel::Email { to, smtp_server, from, security, username, password } => {
+                let message = email_message(from, to, alert, true)?;
+                send_smtp(smtp_server, *security, username.as_deref(), password.as_deref(), message).await
+            }
+            NotificationChannel::Slack { webhook_url, channel: slack_channel } => {
+                let payload = serde_json::json!({
+                    "channel": slack_channel,
+                    "username": "Patronus Alerts",
+                    "icon_emoji": ":white_check_mark:",
                     "attachments": [{
                         "color": "good",
                         "title": format!("✅ RESOLVED: {}", alert.rule_name),
@@ -368,14 +1342,7 @@ impl AlertManager {
                     }]
                 });
 
-                if let Err(e) = reqwest::Client::new()
-                    .post(webhook_url)
-                    .json(&payload)
-                    .send()
-                    .await
-                {
-                    tracing::error!("Failed to send Slack resolution: {}", e);
-                }
+                post_json(webhook_url, &payload).await
             }
             NotificationChannel::Discord { webhook_url } => {
                 let payload = serde_json::json!({
@@ -387,14 +1354,7 @@ impl AlertManager {
                     }]
                 });
 
-                if let Err(e) = reqwest::Client::new()
-                    .post(webhook_url)
-                    .json(&payload)
-                    .send()
-                    .await
-                {
-                    tracing::error!("Failed to send Discord resolution: {}", e);
-                }
+                post_json(webhook_url, &payload).await
             }
             NotificationChannel::PagerDuty { integration_key } => {
                 let payload = serde_json::json!({
@@ -403,14 +1363,7 @@ impl AlertManager {
                     "dedup_key": alert.rule_name,
                 });
 
-                if let Err(e) = reqwest::Client::new()
-                    .post("https://events.pagerduty.com/v2/enqueue")
-                    .json(&payload)
-                    .send()
-                    .await
-                {
-                    tracing::error!("Failed to send PagerDuty resolution: {}", e);
-                }
+                post_json("https://events.pagerduty.com/v2/enqueue", &payload).await
             }
             NotificationChannel::Telegram { bot_token, chat_id } => {
                 let message = format!(
@@ -426,93 +1379,29 @@ impl AlertManager {
                     "parse_mode": "Markdown"
                 });
 
-                if let Err(e) = reqwest::Client::new()
-                    .post(&url)
-                    .json(&payload)
-                    .send()
-                    .await
-                {
-                    tracing::error!("Failed to send Telegram resolution: {}", e);
-                }
+                post_json(&url, &payload).await
             }
-            NotificationChannel::Webhook { url, method, headers } => {
-                let client = reqwest::Client::new();
-                let mut request = match method.to_uppercase().as_str() {
-                    "POST" => client.post(url),
-                    "PUT" => client.put(url),
-                    _ => client.post(url),
-                };
-
-                for (key, value) in headers {
-                    request = request.header(key, value);
-                }
-
+            NotificationChannel::Webhook { url, method, headers, secret } => {
                 let payload = serde_json::json!({
                     "type": "resolution",
                     "alert": alert,
                     "resolved_at": chrono::Utc::now().to_rfc3339(),
                 });
 
-                if let Err(e) = request.json(&payload).send().await {
-                    tracing::error!("Failed to send webhook resolution: {}", e);
-                }
+                Self::send_webhook(url, method, headers, secret.as_deref(), &payload).await
             }
             NotificationChannel::Syslog { server, facility } => {
                 tracing::debug!("Sending syslog resolution to {} ({})", server, facility);
+                Ok(())
             }
         }
     }
 
-    async fn send_email(&self, to: &[String], _smtp_server: &str, _from: &str, _alert: &FiredAlert) {
-        tracing::debug!("Sending email alert to {:?}", to);
-        // Full implementation would use lettre crate for SMTP
-    }
-
-    async fn send_slack(&self, webhook_url: &str, channel: &str, alert: &FiredAlert) {
-        let severity_emoji = match alert.severity {
-            AlertSeverity::Critical => "🔴",
-            AlertSeverity::Warning => "⚠️",
-            AlertSeverity::Info => "ℹ️",
-        };
-
-        let payload = serde_json::json!({
-            "channel": channel,
-            "username": "Patronus Alerts",
-            "icon_emoji": ":shield:",
-            "attachments": [{
-                "color": match alert.severity {
-                    AlertSeverity::Critical => "danger",
-                    AlertSeverity::Warning => "warning",
-                    AlertSeverity::Info => "good",
-                },
-                "title": format!("{} {}", severity_emoji, alert.rule_name),
-                "text": alert.description,
-                "fields": [
-                    {
-                        "title": "Severity",
-                        "value": format!("{:?}", alert.severity),
-                        "short": true
-                    },
-                    {
-                        "title": "Time",
-                        "value": alert.fired_at.to_rfc3339(),
-                        "short": true
-                    }
-                ]
-            }]
-        });
-
-        if let Err(e) = reqwest::Client::new()
-            .post(webhook_url)
-            .json(&payload)
-            .send()
-            .await
-        {
-            tracing::error!("Failed to send Slack notification: {}", e);
-        }
+    async fn send_slack(webhook_url: &str, channel: &str, alert: &FiredAlert) -> Result<()> {
+        post_json(webhook_url, &slack_payload(channel, alert)).await
     }
 
-    async fn send_discord(&self, webhook_url: &str, alert: &FiredAlert) {
+    async fn send_discord(webhook_url: &str, alert: &FiredAlert) -> Result<()> {
         let color = match alert.severity {
             AlertSeverity::Critical => 0xFF0000,  // Red
             AlertSeverity::Warning => 0xFFA500,   // Orange
@@ -540,17 +1429,10 @@ impl AlertManager {
             }]
         });
 
-        if let Err(e) = reqwest::Client::new()
-            .post(webhook_url)
-            .json(&payload)
-            .send()
-            .await
-        {
-            tracing::error!("Failed to send Discord notification: {}", e);
-        }
+        post_json(webhook_url, &payload).await
     }
 
-    async fn send_pagerduty(&self, integration_key: &str, alert: &FiredAlert) {
+    async fn send_pagerduty(integration_key: &str, alert: &FiredAlert) -> Result<()> {
         let payload = serde_json::json!({
             "routing_key": integration_key,
             "event_action": "trigger",
@@ -566,17 +1448,10 @@ impl AlertManager {
             }
         });
 
-        if let Err(e) = reqwest::Client::new()
-            .post("https://events.pagerduty.com/v2/enqueue")
-            .json(&payload)
-            .send()
-            .await
-        {
-            tracing::error!("Failed to send PagerDuty alert: {}", e);
-        }
+        post_json("https://events.pagerduty.com/v2/enqueue", &payload).await
     }
 
-    async fn send_telegram(&self, bot_token: &str, chat_id: &str, alert: &FiredAlert) {
+    async fn send_telegram(bot_token: &str, chat_id: &str, alert: &FiredAlert) -> Result<()> {
         let message = format!(
             "🛡️ *Patronus Alert*\n\n*{}*\n{}\n\nSeverity: {:?}\nTime: {}",
             alert.rule_name,
@@ -592,17 +1467,16 @@ impl AlertManager {
             "parse_mode": "Markdown"
         });
 
-        if let Err(e) = reqwest::Client::new()
-            .post(&url)
-            .json(&payload)
-            .send()
-            .await
-        {
-            tracing::error!("Failed to send Telegram notification: {}", e);
-        }
+        post_json(&url, &payload).await
     }
 
-    async fn send_webhook(&self, url: &str, method: &str, headers: &HashMap<String, String>, alert: &FiredAlert) {
+    async fn send_webhook(
+        url: &str,
+        method: &str,
+        headers: &HashMap<String, String>,
+        secret: Option<&str>,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
         let client = reqwest::Client::new();
         let mut request = match method.to_uppercase().as_str() {
             "POST" => client.post(url),
@@ -614,16 +1488,24 @@ impl AlertManager {
             request = request.header(key, value);
         }
 
-        let payload = serde_json::to_value(alert).unwrap();
-
-        if let Err(e) = request.json(&payload).send().await {
-            tracing::error!("Failed to send webhook notification: {}", e);
+        let body = serde_json::to_vec(payload)?;
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign_payload(secret, &body));
         }
+
+        request
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
     }
 
-    async fn send_syslog(&self, server: &str, _facility: &str, _alert: &FiredAlert) {
+    async fn send_syslog(server: &str, _facility: &str, _alert: &FiredAlert) -> Result<()> {
         // Full implementation would use syslog crate
         tracing::debug!("Sending syslog to {}", server);
+        Ok(())
     }
 
     /// Create common alert rule presets
@@ -636,6 +1518,8 @@ impl AlertManager {
             condition: AlertCondition::CpuUsageAbove { percent: 80.0 },
             duration: Duration::from_secs(300),  // 5 minutes
             enabled: true,
+            channels: Vec::new(),
+            escalation: None,
         });
 
         // High memory usage
@@ -646,6 +1530,8 @@ impl AlertManager {
             condition: AlertCondition::MemoryUsageAbove { percent: 90.0 },
             duration: Duration::from_secs(300),
             enabled: true,
+            channels: Vec::new(),
+            escalation: None,
         });
 
         // Disk space critical
@@ -659,6 +1545,8 @@ impl AlertManager {
             },
             duration: Duration::from_secs(60),
             enabled: true,
+            channels: Vec::new(),
+            escalation: None,
         });
 
         // Certificate expiring
@@ -672,6 +1560,8 @@ impl AlertManager {
             },
             duration: Duration::from_secs(3600),  // 1 hour
             enabled: true,
+            channels: Vec::new(),
+            escalation: None,
         });
 
         // HA failover
@@ -682,6 +1572,8 @@ impl AlertManager {
             condition: AlertCondition::HaFailover,
             duration: Duration::from_secs(0),  // Immediate
             enabled: true,
+            channels: Vec::new(),
+            escalation: None,
         });
     }
 }
@@ -693,3 +1585,882 @@ impl Default for AlertManager {
         manager
     }
 }
+
+/// Key of an alert of `rule` for one series, e.g. `WanErrors{name="wan0"}`
+fn alert_key(rule: &str, labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return rule.to_string();
+    }
+    let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}={:?}", k, v)).collect();
+    format!("{}{{{}}}", rule, labels.join(","))
+}
+
+/// Alert details for a neighbor issue
+fn neighbor_details(alert: &NeighborAlert) -> BTreeMap<String, String> {
+    let mut details = BTreeMap::new();
+    details.insert("interface".to_string(), alert.interface.clone());
+    if let Some(ip) = alert.ip {
+        details.insert("ip".to_string(), ip.to_string());
+    }
+    if !alert.macs.is_empty() {
+        details.insert("macs".to_string(), alert.macs.join(","));
+    }
+    details.insert("detail".to_string(), alert.detail.clone());
+    details
+}
+
+/// Slack-compatible incoming webhook payload
+pub fn slack_payload(channel: &str, alert: &FiredAlert) -> serde_json::Value {
+    let severity_emoji = match alert.severity {
+        AlertSeverity::Critical => "🔴",
+        AlertSeverity::Warning => "⚠️",
+        AlertSeverity::Info => "ℹ️",
+    };
+
+    serde_json::json!({
+        "channel": channel,
+        "username": "Patronus Alerts",
+        "icon_emoji": ":shield:",
+        "attachments": [{
+            "color": match alert.severity {
+                AlertSeverity::Critical => "danger",
+                AlertSeverity::Warning => "warning",
+                AlertSeverity::Info => "good",
+            },
+            "title": format!("{} {}", severity_emoji, alert.rule_name),
+            "text": alert.description,
+            "fields": [
+                {
+                    "title": "Severity",
+                    "value": format!("{:?}", alert.severity),
+                    "short": true
+                },
+                {
+                    "title": "Time",
+                    "value": alert.fired_at.to_rfc3339(),
+                    "short": true
+                }
+            ]
+        }]
+    })
+}
+
+/// `sha256=<hex>` HMAC-SHA256 signature of a webhook body
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POST a JSON payload, failing on transport errors and non-2xx responses
+async fn post_json(url: &str, payload: &serde_json::Value) -> Result<()> {
+    reqwest::Client::new()
+        .post(url)
+        .json(payload)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Deliver a queued notification, retrying with backoff
+async fn deliver_with_retry(
+    name: &str,
+    delivery: &Delivery,
+    retry: &RetryPolicy,
+    stats: &RwLock<NotificationStats>,
+) {
+    let Delivery { channel, alert, resolved } = delivery;
+    let mut attempt = 1;
+
+    loop {
+        let result = if *resolved {
+            AlertManager::send_resolution(channel, alert).await
+        } else {
+            AlertManager::send_notification(channel, alert).await
+        };
+
+        match result {
+            Ok(()) => {
+                stats.write().await.delivered += 1;
+                return;
+            }
+            Err(e) if attempt < retry.max_attempts => {
+                let backoff = retry.backoff(attempt);
+                tracing::warn!(
+                    "Delivery of {} to {} failed (attempt {}): {}; retrying in {:?}",
+                    alert.rule_name, name, attempt, e, backoff
+                );
+                stats.write().await.retries += 1;
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Failed to deliver {} to {} after {} attempts: {}",
+                    alert.rule_name, name, attempt, e
+                );
+                stats.write().await.failed += 1;
+                return;
+            }
+        }
+    }
+}
+
+/// Email for an alert or its resolution
+fn email_message(from: &str, to: &[String], alert: &FiredAlert, resolved: bool) -> Result<Message> {
+    let subject = if resolved {
+        format!("[Patronus] RESOLVED: {}", alert.rule_name)
+    } else {
+        format!("[Patronus] {:?}: {}", alert.severity, alert.rule_name)
+    };
+
+    let mut body = format!(
+        "{}\r\n\r\nRule: {}\r\nSeverity: {:?}\r\nFired at: {}\r\n",
+        alert.description,
+        alert.rule_name,
+        alert.severity,
+        alert.fired_at.to_rfc3339()
+    );
+    if resolved {
+        body.push_str(&format!("Resolved at: {}\r\n", Utc::now().to_rfc3339()));
+    }
+    let mut details: Vec<_> = alert.details.iter().collect();
+    details.sort();
+    for (key, value) in details {
+        body.push_str(&format!("{}: {}\r\n", key, value));
+    }
+
+    let mut message = Message::builder()
+        .from(from.parse().with_context(|| format!("Invalid sender address {}", from))?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for rcpt in to {
+        message = message.to(rcpt.parse().with_context(|| format!("Invalid recipient address {}", rcpt))?);
+    }
+    Ok(message.body(body)?)
+}
+
+/// Send `message` through `server` (`host` or `host:port`)
+async fn send_smtp(
+    server: &str,
+    security: SmtpSecurity,
+    username: Option<&str>,
+    password: Option<&str>,
+    message: Message,
+) -> Result<()> {
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => {
+            let port: u16 = port.parse().with_context(|| format!("Invalid SMTP port in {}", server))?;
+            (host, Some(port))
+        }
+        None => (server, None),
+    };
+
+    let mut transport = match security {
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpSecurity::Plain if username.is_some() => {
+            return Err(anyhow::anyhow!("Refusing to send SMTP credentials to {} without TLS", server));
+        }
+        SmtpSecurity::Plain => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+    }
+    .timeout(Some(SMTP_TIMEOUT));
+    if let Some(port) = port {
+        transport = transport.port(port);
+    }
+    if let Some(username) = username {
+        transport = transport.credentials(Credentials::new(
+            username.to_string(),
+            password.unwrap_or_default().to_string(),
+        ));
+    }
+
+    tracing::debug!("Sending email alert via {}", server);
+    transport.build()
+        .send(message)
+        .await
+        .with_context(|| format!("Failed to send email via {}", server))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn rule(name: &str, severity: AlertSeverity) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            severity,
+            description: format!("{} fired", name),
+            condition: AlertCondition::HaFailover,
+            duration: Duration::from_secs(0),
+            enabled: true,
+            channels: Vec::new(),
+            escalation: None,
+        }
+    }
+
+    fn syslog() -> NotificationChannel {
+        NotificationChannel::Syslog {
+            server: "127.0.0.1:514".to_string(),
+            facility: "local0".to_string(),
+        }
+    }
+
+    fn webhook(url: &str, secret: Option<&str>) -> NotificationChannel {
+        NotificationChannel::Webhook {
+            url: url.to_string(),
+            method: "POST".to_string(),
+            headers: HashMap::new(),
+            secret: secret.map(str::to_string),
+        }
+    }
+
+    fn names(channels: Vec<(&str, &NotificationChannel)>) -> Vec<String> {
+        channels.into_iter().map(|(n, _)| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_severity_routing() {
+        let mut manager = AlertManager::new();
+        manager.add_channel(webhook("http://127.0.0.1:1/", None));
+        manager.add_channel(NotificationChannel::Slack {
+            webhook_url: "http://127.0.0.1:1/".to_string(),
+            channel: "#ops".to_string(),
+        });
+        manager.add_channel(NotificationChannel::Email {
+            to: vec!["oncall@example.com".to_string()],
+            smtp_server: "127.0.0.1:1".to_string(),
+            from: "patronus@example.com".to_string(),
+            security: SmtpSecurity::StartTls,
+            username: None,
+            password: None,
+        });
+
+        assert_eq!(names(manager.channels_for(&rule("Cpu", AlertSeverity::Warning))), vec!["slack"]);
+        assert_eq!(
+            names(manager.channels_for(&rule("Failover", AlertSeverity::Critical))),
+            vec!["webhook", "email"]
+        );
+        // No route for Info: every channel
+        assert_eq!(names(manager.channels_for(&rule("Note", AlertSeverity::Info))).len(), 3);
+
+        // Rule channels override the severity route
+        let mut custom = rule("Disk", AlertSeverity::Critical);
+        custom.channels = vec!["slack".to_string(), "missing".to_string()];
+        assert_eq!(names(manager.channels_for(&custom)), vec!["slack"]);
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        assert_eq!(
+            sign_payload("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_secs(2));
+        assert_eq!(policy.backoff(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3), Duration::from_secs(8));
+        assert_eq!(policy.backoff(10), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let alert = FiredAlert::new("HighCpuUsage", AlertSeverity::Warning, "CPU above 80%", HashMap::new());
+        let payload = slack_payload("#ops", &alert);
+
+        assert_eq!(payload["channel"], "#ops");
+        assert_eq!(payload["attachments"][0]["color"], "warning");
+        assert_eq!(payload["attachments"][0]["text"], "CPU above 80%");
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let mut manager = AlertManager::new().with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        });
+        // Nothing listens on port 1, so every attempt is refused
+        manager.add_channel(webhook("http://127.0.0.1:1/", None));
+        let failover = rule("HAFailover", AlertSeverity::Critical);
+        manager.add_rule(failover.clone());
+
+        manager.fire_alert(&failover).await;
+
+        manager.settle().await;
+        let stats = manager.stats().await;
+        assert_eq!(stats.delivered, 0);
+        assert_eq!(stats.retries, 2);
+        assert_eq!(stats.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_is_signed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length: usize = text.lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length: ").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+            }
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let mut manager = AlertManager::new();
+        manager.add_channel(webhook(&url, Some("s3cret")));
+        let failover = rule("HAFailover", AlertSeverity::Critical);
+        manager.add_rule(failover.clone());
+        manager.fire_alert(&failover).await;
+
+        let request = server.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let signature = head.lines()
+            .find_map(|l| l.strip_prefix(&format!("{}: ", SIGNATURE_HEADER.to_lowercase())))
+            .unwrap();
+        assert_eq!(signature, sign_payload("s3cret", body.as_bytes()));
+        assert!(body.contains("HAFailover"));
+        manager.settle().await;
+        assert_eq!(manager.stats().await.delivered, 1);
+    }
+
+    /// SMTP server without STARTTLS that records the commands and message
+    /// lines it receives
+    async fn smtp_server(listener: TcpListener) -> Vec<String> {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut smtp = BufReader::new(socket);
+        smtp.get_mut().write_all(b"220 mx ready\r\n").await.unwrap();
+
+        let mut transcript = Vec::new();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if smtp.read_line(&mut line).await.unwrap_or(0) == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = if in_data {
+                if line == "." {
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else {
+                    transcript.push(line);
+                    continue;
+                }
+            } else if line.starts_with("EHLO") {
+                b"250-mx\r\n250 8BITMIME\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                let _ = smtp.get_mut().write_all(b"221 bye\r\n").await;
+                break;
+            } else {
+                transcript.push(line);
+                b"250 ok\r\n"
+            };
+            if smtp.get_mut().write_all(reply).await.is_err() {
+                break;
+            }
+        }
+        transcript
+    }
+
+    fn email(server: &str, security: SmtpSecurity, username: Option<&str>) -> NotificationChannel {
+        NotificationChannel::Email {
+            to: vec!["oncall@example.com".to_string()],
+            smtp_server: server.to_string(),
+            from: "patronus@example.com".to_string(),
+            security,
+            username: username.map(str::to_string),
+            password: username.map(|_| "hunter22".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_smtp_delivery() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(smtp_server(listener));
+
+        let alert = FiredAlert::new("DiskSpaceCritical", AlertSeverity::Critical, ".hidden", HashMap::new());
+        let channel = email(&server_addr, SmtpSecurity::Plain, None);
+        AlertManager::send_notification(&channel, &alert).await.unwrap();
+
+        let transcript = server.await.unwrap();
+        assert!(transcript[0].starts_with("MAIL FROM:<patronus@example.com>"));
+        assert_eq!(transcript[1], "RCPT TO:<oncall@example.com>");
+        assert!(transcript.contains(&"Subject: [Patronus] Critical: DiskSpaceCritical".to_string()));
+        // Lines starting with '.' are dot-stuffed
+        assert!(transcript.contains(&"..hidden".to_string()));
+        assert!(!transcript.iter().any(|l| l == ".hidden"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_requires_tls() {
+        let alert = FiredAlert::new("DiskSpaceCritical", AlertSeverity::Critical, "Root filesystem above 95%", HashMap::new());
+
+        // STARTTLS is required, so a server not offering it never sees the message
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(smtp_server(listener));
+        let channel = email(&server_addr, SmtpSecurity::StartTls, Some("patronus"));
+        assert!(AlertManager::send_notification(&channel, &alert).await.is_err());
+        let transcript = server.await.unwrap();
+        assert!(!transcript.iter().any(|l| l.starts_with("MAIL FROM") || l.starts_with("AUTH")));
+
+        // Credentials are never sent in the clear
+        let channel = email(&server_addr, SmtpSecurity::Plain, Some("patronus"));
+        let err = AlertManager::send_notification(&channel, &alert).await.unwrap_err();
+        assert!(err.to_string().contains("without TLS"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_channel_does_not_block_alerting() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let _stalled = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let mut manager = AlertManager::new();
+        manager.add_named_channel("stalled", webhook(&url, None));
+        manager.add_named_channel("local", syslog());
+        let mut failover = rule("HAFailover", AlertSeverity::Critical);
+        failover.channels = vec!["stalled".to_string(), "local".to_string()];
+        manager.add_rule(failover.clone());
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            manager.fire_alert(&failover).await;
+            manager.resolve("HAFailover", &failover).await;
+        })
+        .await
+        .expect("alerting waited on a stalled channel");
+
+        // The healthy channel delivered both while the stalled one still waits
+        for _ in 0..1000 {
+            if manager.stats().await.delivered == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(manager.stats().await.delivered, 2);
+        assert_eq!(manager.queued.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_notifications() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let _stalled = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let mut manager = AlertManager::new();
+        manager.add_named_channel("stalled", webhook(&url, None));
+        let mut failover = rule("HAFailover", AlertSeverity::Critical);
+        failover.channels = vec!["stalled".to_string()];
+        manager.add_rule(failover.clone());
+
+        // One in flight plus a full queue; the rest are dropped
+        let extra = 5;
+        for i in 0..DELIVERY_QUEUE_DEPTH + 1 + extra {
+            manager.fire(&format!("HAFailover{}", i), &failover, HashMap::new()).await;
+            tokio::task::yield_now().await;
+        }
+        let stats = manager.stats().await;
+        assert!(stats.dropped >= extra as u64);
+        assert_eq!(manager.queued.load(Ordering::SeqCst) as u64 + stats.dropped, (DELIVERY_QUEUE_DEPTH + 1 + extra) as u64);
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_alert_escalates() {
+        let mut manager = AlertManager::new();
+        manager.add_named_channel("primary", syslog());
+        manager.add_named_channel("oncall", syslog());
+
+        let mut failover = rule("HAFailover", AlertSeverity::Critical);
+        failover.channels = vec!["primary".to_string()];
+        failover.escalation = Some(Escalation {
+            after: Duration::from_secs(60),
+            channels: vec!["oncall".to_string()],
+        });
+        let mut disk = failover.clone();
+        disk.name = "DiskSpaceCritical".to_string();
+        manager.add_rule(failover.clone());
+        manager.add_rule(disk.clone());
+
+        manager.fire_alert(&failover).await;
+        manager.fire_alert(&disk).await;
+        manager.settle().await;
+        assert_eq!(manager.stats().await.delivered, 2);
+
+        let disk_alert = manager.active_alerts().await
+            .into_iter()
+            .find(|a| a.rule_name == "DiskSpaceCritical")
+            .unwrap();
+        let acked = manager.acknowledge_alert(&disk_alert.id, "alice").await.unwrap();
+        assert_eq!(acked.acknowledged_by.as_deref(), Some("alice"));
+        assert!(manager.acknowledge_alert("no-such-alert", "alice").await.is_err());
+
+        let now = Utc::now();
+        manager.process_timers(now + chrono::Duration::seconds(30)).await;
+        manager.settle().await;
+        assert_eq!(manager.stats().await.escalations, 0);
+
+        // Only the unacknowledged alert escalates, and only once
+        manager.process_timers(now + chrono::Duration::seconds(61)).await;
+        manager.process_timers(now + chrono::Duration::seconds(120)).await;
+        manager.settle().await;
+        let stats = manager.stats().await;
+        assert_eq!(stats.escalations, 1);
+        assert_eq!(stats.delivered, 3);
+    }
+
+    fn expression_rule(name: &str, expr: &str, duration: Duration) -> AlertRule {
+        let mut rule = rule(name, AlertSeverity::Warning);
+        rule.condition = AlertCondition::Expression { expr: expr.to_string() };
+        rule.duration = duration;
+        rule
+    }
+
+    fn counter(collector: &MetricsCollector, name: &str) -> prometheus::CounterVec {
+        let counter = prometheus::CounterVec::new(prometheus::Opts::new(name, "test counter"), &["name"]).unwrap();
+        collector.registry().register(Box::new(counter.clone())).unwrap();
+        counter
+    }
+
+    #[tokio::test]
+    async fn test_expression_rule_pending_firing_resolved() {
+        let collector = Arc::new(MetricsCollector::new().unwrap());
+        let errors = counter(&collector, "interface_errors");
+        let mut manager = AlertManager::new().with_metrics(collector.clone());
+        manager.add_named_channel("primary", syslog());
+        let mut wan_errors = expression_rule(
+            "WanErrors",
+            r#"rate(interface_errors{name="wan0"}[1m]) > 10"#,
+            Duration::from_secs(60),
+        );
+        wan_errors.channels = vec!["primary".to_string()];
+        manager.add_rule(wan_errors);
+
+        let start = Utc::now();
+        let step = |secs: i64, wan: f64| {
+            errors.with_label_values(&["wan0"]).inc_by(wan);
+            // lan0 errors are filtered out by the matcher
+            errors.with_label_values(&["lan0"]).inc_by(5000.0);
+            manager.evaluate_rules_at(start + chrono::Duration::seconds(secs))
+        };
+
+        step(0, 0.0).await;
+        step(60, 1200.0).await;
+        assert_eq!(manager.pending_alerts().await.len(), 1);
+
+        // A transient spike clears before the for duration
+        step(90, 0.0).await;
+        assert!(manager.pending_alerts().await.is_empty());
+
+        step(120, 1200.0).await;
+        step(150, 1200.0).await;
+        assert!(manager.active_alerts().await.is_empty());
+        let pending = manager.pending_alerts().await;
+        assert_eq!(pending[0].since, start + chrono::Duration::seconds(120));
+        assert_eq!(pending[0].details["value"], "40");
+
+        step(180, 1200.0).await;
+        assert!(manager.pending_alerts().await.is_empty());
+        let firing = manager.active_alerts().await;
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].details["name"], "wan0");
+        assert!(manager.active_alerts.read().await.contains_key(r#"WanErrors{name="wan0"}"#));
+        manager.settle().await;
+        assert_eq!(manager.stats().await.delivered, 1);
+
+        // Resolved once the errors stop, with a resolution notification
+        step(300, 0.0).await;
+        assert!(manager.active_alerts().await.is_empty());
+        manager.settle().await;
+        assert_eq!(manager.stats().await.delivered, 2);
+    }
+
+    fn alert_config(name: &str, expr: &str) -> DeclarativeConfig {
+        DeclarativeConfig {
+            api_version: patronus_config::declarative::API_VERSION.to_string(),
+            kind: patronus_config::ResourceKind::AlertRule,
+            metadata: patronus_config::Metadata {
+                name: name.to_string(),
+                description: None,
+                labels: None,
+                annotations: None,
+            },
+            spec: ResourceSpec::AlertRule(AlertRuleSpec {
+                expr: expr.to_string(),
+                for_duration: Some("30s".to_string()),
+                severity: AlertRuleSeverity::Critical,
+                channels: Vec::new(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_declared_rules_hot_reload() {
+        register_rule_validator();
+        let bad = alert_config("WanErrors", r#"rate(interface_errors{name="wan0"}[5m) > 10"#);
+        let err = patronus_config::ConfigParser::validate_config(&bad).unwrap_err();
+        assert!(err.to_string().ends_with("spec.expr: expected `]`, found `)` at column 38"), "{}", err);
+
+        let mut manager = AlertManager::new();
+        manager.load_default_rules();
+        let builtin = manager.rules().len();
+        let manager = Arc::new(manager);
+
+        let (tx, rx) = watch::channel(vec![alert_config("WanErrors", "up == 0")]);
+        let watcher = manager.clone().watch_config(rx);
+        let rule_count = |expected: usize| {
+            let manager = manager.clone();
+            async move {
+                for _ in 0..200 {
+                    if manager.rules().len() == expected {
+                        return true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+                false
+            }
+        };
+        assert!(rule_count(builtin + 1).await);
+        let declared = manager.rules().iter().find(|r| r.name == "WanErrors").cloned().unwrap();
+        assert_eq!(declared.duration, Duration::from_secs(30));
+        assert_eq!(declared.severity, AlertSeverity::Critical);
+
+        tx.send(vec![alert_config("WanErrors", "up == 0"), alert_config("LanErrors", "up == 1")]).unwrap();
+        assert!(rule_count(builtin + 2).await);
+
+        // An invalid set keeps the current rules
+        tx.send(vec![bad]).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(manager.rules().len(), builtin + 2);
+        assert!(manager.reload_rules(vec![rule("HAFailover", AlertSeverity::Info)]).await.is_err());
+
+        tx.send(Vec::new()).unwrap();
+        assert!(rule_count(builtin).await);
+        drop(tx);
+        watcher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evaluates_many_rules_per_cycle() {
+        let collector = Arc::new(MetricsCollector::new().unwrap());
+        let errors = counter(&collector, "interface_errors");
+        for i in 0..50 {
+            errors.with_label_values(&[&format!("eth{}", i)]).inc();
+        }
+
+        let mut manager = AlertManager::new().with_metrics(collector.clone());
+        for i in 0..500 {
+            let expr = match i % 3 {
+                0 => format!(r#"rate(interface_errors{{name="eth{}"}}[5m]) > 1"#, i % 50),
+                1 => format!("increase(interface_errors[1m]) >= {}", i),
+                _ => format!(r#"interface_errors{{name!="eth{}"}} > 0"#, i % 50),
+            };
+            manager.add_rule(expression_rule(&format!("rule{}", i), &expr, Duration::from_secs(3600)));
+        }
+
+        let start = Utc::now();
+        let began = std::time::Instant::now();
+        for step in 0..4 {
+            for i in 0..50 {
+                errors.with_label_values(&[&format!("eth{}", i)]).inc_by(1000.0);
+            }
+            manager.evaluate_rules_at(start + chrono::Duration::seconds(step * 15)).await;
+        }
+        // Four 15 s cycles of 500 rules take a small fraction of one interval
+        assert!(began.elapsed() < Duration::from_secs(5), "{:?}", began.elapsed());
+
+        // 167 rate rules match one series, 167 increase rules all 50 and
+        // 166 label-filter rules 49; none has held for an hour yet
+        assert_eq!(manager.pending_alerts().await.len(), 167 + 167 * 50 + 166 * 49);
+        assert!(manager.active_alerts().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_silenced_alert_is_not_notified() {
+        let mut manager = AlertManager::new();
+        manager.add_named_channel("primary", syslog());
+        let mut failover = rule("HAFailover", AlertSeverity::Critical);
+        failover.channels = vec!["primary".to_string()];
+        manager.add_rule(failover.clone());
+
+        let silence = manager.silence("HAFailover", Duration::from_secs(3600), HashMap::new())
+            .await.unwrap();
+
+        manager.fire_alert(&failover).await;
+        let alert = &manager.active_alerts().await[0];
+        assert_eq!(alert.silenced_by.as_ref(), Some(&silence.id));
+
+        manager.resolve("HAFailover", &failover).await;
+        manager.settle().await;
+        let stats = manager.stats().await;
+        assert_eq!(stats.silenced, 1);
+        assert_eq!(stats.delivered, 0);
+
+        // An alert still firing when its silence lapses is notified then
+        manager.fire_alert(&failover).await;
+        manager.process_timers(Utc::now() + chrono::Duration::hours(2)).await;
+        manager.settle().await;
+        assert_eq!(manager.stats().await.delivered, 1);
+        assert!(manager.silences().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_neighbor_issues_fire_and_resolve() {
+        use patronus_diagnostics::{NeighborMonitor, NeighborMonitorConfig, NeighborObservation};
+
+        let mut manager = AlertManager::new();
+        let mut storm = rule("GratuitousArpStorm", AlertSeverity::Info);
+        storm.condition = AlertCondition::External { source: "neighbor_monitor".to_string() };
+        manager.add_rule(storm);
+        let manager = Arc::new(manager);
+
+        let monitor = NeighborMonitor::new(NeighborMonitorConfig {
+            conflict_window: Duration::from_secs(60),
+            storm_threshold: 3,
+            ..Default::default()
+        });
+        let watcher = manager.clone().watch_neighbors(monitor.subscribe());
+
+        let wait_for = |count: usize| {
+            let manager = manager.clone();
+            async move {
+                for _ in 0..200 {
+                    let active = manager.active_alerts().await;
+                    if active.len() == count {
+                        return active;
+                    }
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+                panic!("expected {} active alerts", count);
+            }
+        };
+
+        let seen = |mac: &str| NeighborObservation {
+            interface: "lan0".to_string(),
+            ip: "192.168.1.20".parse().unwrap(),
+            mac: mac.to_string(),
+            is_static: false,
+        };
+        let now = Utc::now();
+        monitor.process_snapshot(&[seen("02:00:00:00:00:01")], now);
+        monitor.process_snapshot(&[seen("02:00:00:00:00:02")], now + chrono::Duration::seconds(10));
+
+        let active = wait_for(1).await;
+        assert_eq!(active[0].rule_name, "NeighborIpConflict");
+        assert_eq!(active[0].severity, AlertSeverity::Critical);
+        assert_eq!(active[0].details["ip"], "192.168.1.20");
+        assert_eq!(active[0].details["macs"], "02:00:00:00:00:01,02:00:00:00:00:02");
+
+        // Configured rules override the default severity, and are never
+        // resolved by rule evaluation
+        for _ in 0..3 {
+            monitor.record_gratuitous_arp("lan0", "192.168.1.30".parse().unwrap(), None, Utc::now());
+        }
+        let active = wait_for(2).await;
+        let storm = active.iter().find(|a| a.rule_name == "GratuitousArpStorm").unwrap();
+        assert_eq!(storm.severity, AlertSeverity::Info);
+        manager.evaluate_rules().await;
+        assert_eq!(manager.active_alerts().await.len(), 2);
+
+        monitor.process_snapshot(&[seen("02:00:00:00:00:02")], now + chrono::Duration::seconds(120));
+        let active = wait_for(0).await;
+        assert!(active.is_empty());
+
+        drop(monitor);
+        watcher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backup_failures_fire_and_resolve() {
+        use patronus_core::backup::{BackupFailure, BackupRun, BackupType};
+
+        let manager = Arc::new(AlertManager::new());
+        let (events, receiver) = broadcast::channel(8);
+        let watcher = manager.clone().watch_backups(receiver);
+
+        let wait_for = |count: usize| {
+            let manager = manager.clone();
+            async move {
+                for _ in 0..200 {
+                    let active = manager.active_alerts().await;
+                    if active.len() == count {
+                        return active;
+                    }
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+                panic!("expected {} active alerts", count);
+            }
+        };
+
+        events.send(BackupEvent::Failed(BackupFailure {
+            operation: BackupOperation::Upload,
+            backup_id: Some("backup-1".to_string()),
+            error: "Upload to s3://patronus failed checksum verification".to_string(),
+            at: Utc::now(),
+        })).unwrap();
+
+        let active = wait_for(1).await;
+        assert_eq!(active[0].rule_name, "BackupUploadFailed");
+        assert_eq!(active[0].severity, AlertSeverity::Critical);
+        assert_eq!(active[0].details["backup_id"], "backup-1");
+
+        // A restore succeeding says nothing about uploads
+        events.send(BackupEvent::Restored { backup_id: "backup-0".to_string(), sections: Vec::new(), at: Utc::now() }).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(manager.active_alerts().await.len(), 1);
+
+        events.send(BackupEvent::Completed(BackupRun {
+            backup_id: "backup-2".to_string(),
+            backup_type: BackupType::Full,
+            size_bytes: 1024,
+            targets: vec!["s3://patronus".to_string()],
+            at: Utc::now(),
+        })).unwrap();
+        wait_for(0).await;
+
+        drop(events);
+        watcher.await.unwrap();
+    }
+}
@@ -0,0 +1,369 @@
+//! Per-User Dashboards
+//!
+//! Every operator gets their own dashboard layout, falling back to the
+//! default layout until they save one. Layouts are validated on every
+//! save or import, persisted to a JSON file, and can be exported so teams
+//! can share dashboards.
+
+use crate::metrics::MetricsCollector;
+use crate::status::{DashboardConfig, MetricQuery, StatusPageManager, WidgetType};
+use anyhow::{Context, Result};
+use axum::{
+    Json, Router,
+    extract::{Path as UrlPath, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Histogram backing the SD-WAN path widget
+const SDWAN_PATH_METRIC: &str = "patronus_sdwan_path_rtt_seconds";
+
+/// Dashboard layouts by user, optionally persisted to a JSON file
+pub struct DashboardStore {
+    layouts: RwLock<HashMap<String, DashboardConfig>>,
+    path: Option<PathBuf>,
+}
+
+impl DashboardStore {
+    /// In-memory store
+    pub fn new() -> Self {
+        Self {
+            layouts: RwLock::new(HashMap::new()),
+            path: None,
+        }
+    }
+
+    /// Store persisted at `path`; layouts that no longer validate are dropped
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let layouts = if path.exists() {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("Failed to read dashboards from {}", path.display()))?;
+            let mut layouts: HashMap<String, DashboardConfig> = serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid dashboard file {}", path.display()))?;
+
+            layouts.retain(|user, layout| match layout.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Dropping invalid dashboard of {}: {}", user, e);
+                    false
+                }
+            });
+            layouts
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            layouts: RwLock::new(layouts),
+            path: Some(path),
+        })
+    }
+
+    /// Layout of `user`, or the default layout
+    pub async fn get(&self, user: &str) -> DashboardConfig {
+        self.layouts
+            .read()
+            .await
+            .get(user)
+            .cloned()
+            .unwrap_or_else(DashboardConfig::default_layout)
+    }
+
+    /// Whether `user` has saved a layout of their own
+    pub async fn has_custom(&self, user: &str) -> bool {
+        self.layouts.read().await.contains_key(user)
+    }
+
+    /// Save the layout of `user`
+    pub async fn set(&self, user: &str, layout: DashboardConfig) -> Result<()> {
+        layout.validate().map_err(|e| anyhow::anyhow!(e))?;
+
+        let mut layouts = self.layouts.write().await;
+        layouts.insert(user.to_string(), layout);
+        self.save(&layouts)
+    }
+
+    /// Drop the layout of `user`, who then sees the default again
+    pub async fn reset(&self, user: &str) -> Result<()> {
+        let mut layouts = self.layouts.write().await;
+        if layouts.remove(user).is_some() {
+            self.save(&layouts)?;
+        }
+        Ok(())
+    }
+
+    /// Layout of `user` as shareable JSON
+    pub async fn export(&self, user: &str) -> Result<String> {
+        Ok(self.get(user).await.to_json()?)
+    }
+
+    /// Replace the layout of `user` with a shared one
+    pub async fn import(&self, user: &str, json: &str) -> Result<DashboardConfig> {
+        let layout = DashboardConfig::from_json(json)?;
+        self.set(user, layout.clone()).await?;
+        Ok(layout)
+    }
+
+    fn save(&self, layouts: &HashMap<String, DashboardConfig>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // Write to a temporary file and rename so a crash never leaves a
+        // truncated file behind
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(layouts)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl Default for DashboardStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Data of one widget in a layout
+#[derive(Debug, Clone, Serialize)]
+pub struct WidgetData {
+    /// Position of the widget in the layout
+    pub index: usize,
+    pub data: serde_json::Value,
+}
+
+/// State shared by the dashboard endpoints
+#[derive(Clone)]
+pub struct DashboardApi {
+    pub layouts: Arc<DashboardStore>,
+    pub collector: Arc<MetricsCollector>,
+}
+
+impl DashboardApi {
+    /// Data for every widget of a layout; a failing widget reports its
+    /// error instead of failing the whole dashboard
+    pub async fn widget_data(&self, layout: &DashboardConfig) -> Vec<WidgetData> {
+        let mut widgets = Vec::with_capacity(layout.widgets.len());
+
+        for (index, widget) in layout.widgets.iter().enumerate() {
+            let data = match &widget.widget_type {
+                WidgetType::MetricQuery(query) => {
+                    serde_json::json!({ "series": self.collector.query(query) })
+                }
+                WidgetType::SdwanPath { path } => {
+                    let query = MetricQuery {
+                        metric: SDWAN_PATH_METRIC.to_string(),
+                        labels: BTreeMap::from([("path".to_string(), path.clone())]),
+                    };
+                    serde_json::json!({ "series": self.collector.query(&query) })
+                }
+                other => match StatusPageManager::get_widget_data(other).await {
+                    Ok(data) => data,
+                    Err(e) => serde_json::json!({ "error": e.to_string() }),
+                },
+            };
+            widgets.push(WidgetData { index, data });
+        }
+
+        widgets
+    }
+}
+
+/// Dashboard layout endpoints under `/api/dashboards/:user`
+pub fn dashboard_routes(api: DashboardApi) -> Router {
+    Router::new()
+        .route(
+            "/api/dashboards/:user",
+            get(get_layout).put(put_layout).delete(reset_layout),
+        )
+        .route("/api/dashboards/:user/data", get(layout_data))
+        .route("/api/dashboards/:user/export", get(export_layout))
+        .route("/api/dashboards/:user/import", post(import_layout))
+        .with_state(api)
+}
+
+async fn get_layout(State(api): State<DashboardApi>, UrlPath(user): UrlPath<String>) -> Response {
+    Json(api.layouts.get(&user).await).into_response()
+}
+
+async fn put_layout(
+    State(api): State<DashboardApi>,
+    UrlPath(user): UrlPath<String>,
+    Json(layout): Json<DashboardConfig>,
+) -> Response {
+    match api.layouts.set(&user, layout.clone()).await {
+        Ok(()) => Json(layout).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+async fn reset_layout(State(api): State<DashboardApi>, UrlPath(user): UrlPath<String>) -> Response {
+    match api.layouts.reset(&user).await {
+        Ok(()) => Json(DashboardConfig::default_layout()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn layout_data(State(api): State<DashboardApi>, UrlPath(user): UrlPath<String>) -> Response {
+    let layout = api.layouts.get(&user).await;
+    Json(api.widget_data(&layout).await).into_response()
+}
+
+async fn export_layout(State(api): State<DashboardApi>, UrlPath(user): UrlPath<String>) -> Response {
+    match api.layouts.export(&user).await {
+        Ok(json) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"dashboard-{}.json\"", user),
+                ),
+            ],
+            json,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn import_layout(
+    State(api): State<DashboardApi>,
+    UrlPath(user): UrlPath<String>,
+    body: String,
+) -> Response {
+    match api.layouts.import(&user, &body).await {
+        Ok(layout) => Json(layout).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::DashboardWidget;
+
+    fn widget(widget_type: WidgetType, position: (u32, u32), size: (u32, u32)) -> DashboardWidget {
+        DashboardWidget { widget_type, position, size, refresh_interval: 5 }
+    }
+
+    fn rtt_layout() -> DashboardConfig {
+        DashboardConfig {
+            widgets: vec![
+                widget(WidgetType::SdwanPath { path: "site-b".into() }, (0, 0), (6, 3)),
+                widget(
+                    WidgetType::MetricQuery(MetricQuery {
+                        metric: SDWAN_PATH_METRIC.into(),
+                        labels: BTreeMap::from([("path".into(), "site-a".into())]),
+                    }),
+                    (0, 6),
+                    (6, 3),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_layout_validation() {
+        assert!(DashboardConfig::default_layout().validate().is_ok());
+        assert!(rtt_layout().validate().is_ok());
+
+        let mut layout = rtt_layout();
+        layout.widgets[1].position = (2, 5);
+        assert_eq!(layout.validate().unwrap_err(), "Widgets 0 and 1 overlap");
+
+        // Touching edges is fine
+        layout.widgets[1].position = (3, 0);
+        assert!(layout.validate().is_ok());
+
+        layout.widgets[1].position = (3, 8);
+        assert!(layout.validate().unwrap_err().contains("past column"));
+
+        let mut layout = rtt_layout();
+        layout.widgets[0].widget_type = WidgetType::MetricQuery(MetricQuery::default());
+        assert!(layout.validate().unwrap_err().contains("metric name"));
+    }
+
+    #[tokio::test]
+    async fn test_per_user_layouts_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dashboards.json");
+
+        let store = DashboardStore::open(&path).unwrap();
+        store.set("alice", rtt_layout()).await.unwrap();
+        assert_eq!(store.get("bob").await, DashboardConfig::default_layout());
+
+        let mut invalid = rtt_layout();
+        invalid.widgets[1].position = (1, 1);
+        assert!(store.set("bob", invalid).await.is_err());
+        assert!(!store.has_custom("bob").await);
+
+        // Share alice's dashboard with bob
+        let json = store.export("alice").await.unwrap();
+        store.import("bob", &json).await.unwrap();
+        assert!(store.import("carol", "{\"widgets\": 3}").await.is_err());
+
+        let reopened = DashboardStore::open(&path).unwrap();
+        assert_eq!(reopened.get("alice").await, rtt_layout());
+        assert_eq!(reopened.get("bob").await, rtt_layout());
+
+        reopened.reset("alice").await.unwrap();
+        assert_eq!(reopened.get("alice").await, DashboardConfig::default_layout());
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_endpoints() {
+        let collector = Arc::new(MetricsCollector::new().unwrap());
+        collector.record_path_rtt("site-a", 0.020);
+        collector.record_path_rtt("site-a", 0.040);
+        collector.record_path_rtt("site-b", 0.100);
+
+        let api = DashboardApi { layouts: Arc::new(DashboardStore::new()), collector };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api/dashboards", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, dashboard_routes(api)).await });
+
+        let client = reqwest::Client::new();
+        let layout: DashboardConfig = client.get(format!("{}/alice", base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(layout, DashboardConfig::default_layout());
+
+        let response = client.put(format!("{}/alice", base)).json(&rtt_layout()).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let mut overlapping = rtt_layout();
+        overlapping.widgets[1].position = (0, 0);
+        let response = client.put(format!("{}/bob", base)).json(&overlapping).send().await.unwrap();
+        assert_eq!(response.status(), 400);
+
+        let data: serde_json::Value = client.get(format!("{}/alice/data", base)).send().await.unwrap().json().await.unwrap();
+        let series = |i: usize| data[i]["data"]["series"].as_array().unwrap().clone();
+        let site_b = series(0);
+        assert_eq!(site_b[0]["name"], "patronus_sdwan_path_rtt_seconds_count");
+        assert_eq!(site_b[0]["value"], 1.0);
+        let site_a = series(1);
+        assert_eq!(site_a[0]["labels"]["path"], "site-a");
+        assert_eq!(site_a[0]["value"], 2.0);
+        assert!((site_a[1]["value"].as_f64().unwrap() - 0.06).abs() < 1e-9);
+
+        let exported = client.get(format!("{}/alice/export", base)).send().await.unwrap();
+        assert!(exported.headers()["content-disposition"].to_str().unwrap().contains("dashboard-alice.json"));
+        let json = exported.text().await.unwrap();
+        let response = client.post(format!("{}/bob/import", base)).body(json).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let layout: DashboardConfig = client.get(format!("{}/bob", base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(layout, rtt_layout());
+
+        let layout: DashboardConfig = client.delete(format!("{}/bob", base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(layout, DashboardConfig::default_layout());
+    }
+}
@@ -0,0 +1,527 @@
+//! Distribution Metrics
+//!
+//! Histograms with per-metric bucket layouts, sliding-window summaries,
+//! duration timer guards and bucket exemplars. Exemplars are only kept
+//! while enabled and are only visible in the OpenMetrics exposition, since
+//! the classic text format has no syntax for them.
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{self, MetricFamily, MetricType};
+use prometheus::{HistogramOpts, HistogramVec, Registry};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Buckets for request latencies, in seconds
+pub const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Buckets for WAN path round-trip times, in seconds
+pub const DEFAULT_RTT_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.02, 0.04, 0.08, 0.15, 0.3, 0.6, 1.2,
+];
+
+/// Quantiles reported by summaries unless configured otherwise
+pub const DEFAULT_QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// Label carrying the trace ID on exemplars
+pub const TRACE_ID_LABEL: &str = "trace_id";
+
+/// Per-metric histogram bucket overrides
+#[derive(Debug, Clone, Default)]
+pub struct HistogramBuckets {
+    overrides: HashMap<String, Vec<f64>>,
+}
+
+impl HistogramBuckets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `buckets` for the histogram named `metric`
+    pub fn with(mut self, metric: &str, buckets: Vec<f64>) -> Self {
+        self.overrides.insert(metric.to_string(), buckets);
+        self
+    }
+
+    /// Buckets for `metric`, falling back to `default`
+    pub fn for_metric(&self, metric: &str, default: &[f64]) -> Vec<f64> {
+        self.overrides
+            .get(metric)
+            .cloned()
+            .unwrap_or_else(|| default.to_vec())
+    }
+}
+
+/// Sample linking a histogram bucket to the trace that produced it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub trace_id: String,
+    pub value: f64,
+
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+}
+
+/// Series key: metric name and its sorted label pairs
+type SeriesKey = (String, Vec<(String, String)>);
+
+/// Latest exemplar per histogram bucket
+#[derive(Default)]
+pub struct ExemplarStore {
+    enabled: AtomicBool,
+    exemplars: RwLock<HashMap<SeriesKey, Vec<Option<Exemplar>>>>,
+}
+
+impl ExemplarStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start or stop keeping exemplars; stopping drops the ones kept so far
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            if let Ok(mut exemplars) = self.exemplars.write() {
+                exemplars.clear();
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Exemplars of one series, indexed by bucket with `+Inf` last
+    pub fn get(&self, metric: &str, labels: &[(String, String)]) -> Option<Vec<Option<Exemplar>>> {
+        let key = (metric.to_string(), labels.to_vec());
+        self.exemplars.read().ok()?.get(&key).cloned()
+    }
+
+    fn record(&self, key: SeriesKey, bucket: usize, buckets: usize, exemplar: Exemplar) {
+        if let Ok(mut exemplars) = self.exemplars.write() {
+            let slots = exemplars.entry(key).or_insert_with(|| vec![None; buckets]);
+            slots[bucket] = Some(exemplar);
+        }
+    }
+}
+
+/// Labelled histogram that can attach exemplars to its buckets
+#[derive(Clone)]
+pub struct HistogramMetric {
+    name: String,
+    label_names: Vec<String>,
+    buckets: Arc<Vec<f64>>,
+    inner: HistogramVec,
+    exemplars: Arc<ExemplarStore>,
+}
+
+impl HistogramMetric {
+    /// Create and register a histogram
+    pub fn register(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+        label_names: &[&str],
+        buckets: Vec<f64>,
+        exemplars: Arc<ExemplarStore>,
+    ) -> prometheus::Result<Self> {
+        let buckets = if buckets.is_empty() {
+            prometheus::DEFAULT_BUCKETS.to_vec()
+        } else {
+            buckets
+        };
+
+        // HistogramVec only checks buckets when the first child is created
+        if buckets.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(prometheus::Error::Msg(format!(
+                "histogram {} buckets must be in increasing order", name
+            )));
+        }
+
+        let inner = HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets.clone()), label_names)?;
+        registry.register(Box::new(inner.clone()))?;
+
+        // The exposition always ends with +Inf, which is implicit here
+        let buckets = buckets.into_iter().filter(|b| b.is_finite()).collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            label_names: label_names.iter().map(|l| l.to_string()).collect(),
+            buckets: Arc::new(buckets),
+            inner,
+            exemplars,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Upper bounds of the finite buckets
+    pub fn buckets(&self) -> &[f64] {
+        &self.buckets
+    }
+
+    pub fn observe(&self, labels: &[&str], value: f64) {
+        self.inner.with_label_values(labels).observe(value);
+    }
+
+    /// Observe `value`, keeping it as the exemplar of its bucket when enabled
+    pub fn observe_with_exemplar(&self, labels: &[&str], value: f64, trace_id: &str) {
+        self.observe(labels, value);
+
+        if !self.exemplars.is_enabled() || labels.len() != self.label_names.len() {
+            return;
+        }
+
+        let bucket = self.buckets
+            .iter()
+            .position(|upper| value <= *upper)
+            .unwrap_or(self.buckets.len());
+
+        let mut pairs: Vec<(String, String)> = self.label_names
+            .iter()
+            .cloned()
+            .zip(labels.iter().map(|l| l.to_string()))
+            .collect();
+        pairs.sort();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        self.exemplars.record(
+            (self.name.clone(), pairs),
+            bucket,
+            self.buckets.len() + 1,
+            Exemplar { trace_id: trace_id.to_string(), value, timestamp },
+        );
+    }
+
+    /// Timer guard observing the elapsed seconds when dropped
+    pub fn observe_duration(&self, labels: &[&str]) -> DurationTimer {
+        DurationTimer {
+            histogram: self.clone(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            trace_id: None,
+            start: Instant::now(),
+            done: false,
+        }
+    }
+}
+
+/// Observes the time since its creation into a histogram when dropped
+///
+/// When exemplars are enabled the observation is tagged with the explicit
+/// trace ID, or else with the ID of the current `tracing` span, if any.
+pub struct DurationTimer {
+    histogram: HistogramMetric,
+    labels: Vec<String>,
+    trace_id: Option<String>,
+    start: Instant,
+    done: bool,
+}
+
+impl DurationTimer {
+    /// Attach the observation to a specific trace
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Observe now and return the elapsed seconds
+    pub fn observe(mut self) -> f64 {
+        self.record()
+    }
+
+    /// Drop the timer without observing anything
+    pub fn discard(mut self) {
+        self.done = true;
+    }
+
+    fn record(&mut self) -> f64 {
+        self.done = true;
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let labels: Vec<&str> = self.labels.iter().map(String::as_str).collect();
+
+        let trace_id = self.trace_id.clone().or_else(|| {
+            tracing::Span::current()
+                .id()
+                .map(|id| format!("{:016x}", id.into_u64()))
+        });
+
+        match trace_id {
+            Some(trace_id) => self.histogram.observe_with_exemplar(&labels, elapsed, &trace_id),
+            None => self.histogram.observe(&labels, elapsed),
+        }
+        elapsed
+    }
+}
+
+impl Drop for DurationTimer {
+    fn drop(&mut self) {
+        if !self.done {
+            self.record();
+        }
+    }
+}
+
+#[derive(Default)]
+struct SummarySeries {
+    window: VecDeque<f64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Labelled summary reporting quantiles over the most recent observations
+///
+/// Sum and count are cumulative; quantiles only cover the last
+/// `max_samples` observations of each series.
+#[derive(Clone)]
+pub struct SummaryMetric {
+    desc: Desc,
+    quantiles: Arc<Vec<f64>>,
+    max_samples: usize,
+    series: Arc<Mutex<HashMap<Vec<String>, SummarySeries>>>,
+}
+
+impl SummaryMetric {
+    /// Create and register a summary
+    pub fn register(
+        registry: &Registry,
+        name: &str,
+        help: &str,
+        label_names: &[&str],
+        quantiles: Vec<f64>,
+        max_samples: usize,
+    ) -> prometheus::Result<Self> {
+        if quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+            return Err(prometheus::Error::Msg(format!(
+                "summary {} quantiles must be within [0, 1]", name
+            )));
+        }
+
+        let desc = Desc::new(
+            name.to_string(),
+            help.to_string(),
+            label_names.iter().map(|l| l.to_string()).collect(),
+            HashMap::new(),
+        )?;
+
+        let summary = Self {
+            desc,
+            quantiles: Arc::new(quantiles),
+            max_samples: max_samples.max(1),
+            series: Arc::new(Mutex::new(HashMap::new())),
+        };
+        registry.register(Box::new(summary.clone()))?;
+
+        Ok(summary)
+    }
+
+    pub fn observe(&self, labels: &[&str], value: f64) {
+        if labels.len() != self.desc.variable_labels.len() {
+            tracing::warn!("Summary {} observed with wrong label count", self.desc.fq_name);
+            return;
+        }
+
+        let Ok(mut series) = self.series.lock() else {
+            return;
+        };
+        let entry = series
+            .entry(labels.iter().map(|l| l.to_string()).collect())
+            .or_default();
+
+        entry.sum += value;
+        entry.count += 1;
+        entry.window.push_back(value);
+        if entry.window.len() > self.max_samples {
+            entry.window.pop_front();
+        }
+    }
+
+    /// Current quantile values for one series
+    pub fn quantiles(&self, labels: &[&str]) -> Vec<(f64, f64)> {
+        let key: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+        let Ok(series) = self.series.lock() else {
+            return Vec::new();
+        };
+
+        series
+            .get(&key)
+            .map(|s| self.compute_quantiles(&s.window))
+            .unwrap_or_default()
+    }
+
+    fn compute_quantiles(&self, window: &VecDeque<f64>) -> Vec<(f64, f64)> {
+        let mut sorted: Vec<f64> = window.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        self.quantiles
+            .iter()
+            .map(|&q| {
+                if sorted.is_empty() {
+                    return (q, f64::NAN);
+                }
+                // Nearest-rank
+                let rank = (q * sorted.len() as f64).ceil() as usize;
+                (q, sorted[rank.clamp(1, sorted.len()) - 1])
+            })
+            .collect()
+    }
+}
+
+impl Collector for SummaryMetric {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut family = MetricFamily::default();
+        family.set_name(self.desc.fq_name.clone());
+        family.set_help(self.desc.help.clone());
+        family.set_field_type(MetricType::SUMMARY);
+
+        let Ok(series) = self.series.lock() else {
+            return vec![family];
+        };
+
+        for (values, data) in series.iter() {
+            let mut summary = proto::Summary::default();
+            summary.set_sample_count(data.count);
+            summary.set_sample_sum(data.sum);
+            for (q, value) in self.compute_quantiles(&data.window) {
+                let mut quantile = proto::Quantile::default();
+                quantile.set_quantile(q);
+                quantile.set_value(value);
+                summary.mut_quantile().push(quantile);
+            }
+
+            let mut labels: Vec<proto::LabelPair> = self.desc.variable_labels
+                .iter()
+                .zip(values)
+                .map(|(name, value)| {
+                    let mut pair = proto::LabelPair::default();
+                    pair.set_name(name.clone());
+                    pair.set_value(value.clone());
+                    pair
+                })
+                .collect();
+            labels.sort();
+
+            let mut metric = proto::Metric::default();
+            for pair in labels {
+                metric.mut_label().push(pair);
+            }
+            metric.set_summary(summary);
+            family.mut_metric().push(metric);
+        }
+
+        vec![family]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_overrides() {
+        let buckets = HistogramBuckets::new().with("patronus_http_request_duration_seconds", vec![0.1, 1.0]);
+        assert_eq!(
+            buckets.for_metric("patronus_http_request_duration_seconds", DEFAULT_LATENCY_BUCKETS),
+            vec![0.1, 1.0]
+        );
+        assert_eq!(
+            buckets.for_metric("patronus_dns_query_duration_seconds", DEFAULT_LATENCY_BUCKETS),
+            DEFAULT_LATENCY_BUCKETS.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_histogram_rejects_unsorted_buckets() {
+        let registry = Registry::new();
+        let result = HistogramMetric::register(
+            &registry, "test_seconds", "Test", &[], vec![1.0, 0.5], Arc::new(ExemplarStore::new()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_exemplars_only_kept_when_enabled() {
+        let registry = Registry::new();
+        let exemplars = Arc::new(ExemplarStore::new());
+        let histogram = HistogramMetric::register(
+            &registry, "test_seconds", "Test", &["path"], vec![0.1, 1.0], exemplars.clone(),
+        ).unwrap();
+        let series = vec![("path".to_string(), "wan1".to_string())];
+
+        histogram.observe_with_exemplar(&["wan1"], 0.5, "abc");
+        assert!(exemplars.get("test_seconds", &series).is_none());
+
+        exemplars.set_enabled(true);
+        histogram.observe_with_exemplar(&["wan1"], 0.5, "abc");
+        histogram.observe_with_exemplar(&["wan1"], 7.0, "def");
+
+        let slots = exemplars.get("test_seconds", &series).unwrap();
+        assert_eq!(slots.len(), 3);
+        assert!(slots[0].is_none());
+        assert_eq!(slots[1].as_ref().unwrap().trace_id, "abc");
+        assert_eq!(slots[2].as_ref().unwrap().value, 7.0);
+
+        exemplars.set_enabled(false);
+        assert!(exemplars.get("test_seconds", &series).is_none());
+    }
+
+    #[test]
+    fn test_duration_timer() {
+        let registry = Registry::new();
+        let exemplars = Arc::new(ExemplarStore::new());
+        exemplars.set_enabled(true);
+        let histogram = HistogramMetric::register(
+            &registry, "test_seconds", "Test", &["method"], DEFAULT_LATENCY_BUCKETS.to_vec(), exemplars.clone(),
+        ).unwrap();
+
+        {
+            let _timer = histogram.observe_duration(&["GET"]);
+        }
+        let elapsed = histogram.observe_duration(&["GET"]).with_trace_id("4bf92f3577b34da6").observe();
+        assert!(elapsed >= 0.0);
+        histogram.observe_duration(&["GET"]).discard();
+
+        assert_eq!(histogram.inner.with_label_values(&["GET"]).get_sample_count(), 2);
+
+        let series = vec![("method".to_string(), "GET".to_string())];
+        let slots = exemplars.get("test_seconds", &series).unwrap();
+        assert!(slots.iter().flatten().any(|e| e.trace_id == "4bf92f3577b34da6"));
+    }
+
+    #[test]
+    fn test_summary_quantiles() {
+        let registry = Registry::new();
+        let summary = SummaryMetric::register(
+            &registry, "test_rtt_seconds", "Test", &["path"], DEFAULT_QUANTILES.to_vec(), 100,
+        ).unwrap();
+
+        for i in 1..=200 {
+            summary.observe(&["wan1"], i as f64);
+        }
+
+        // Quantiles only cover the last 100 observations (101..=200)
+        assert_eq!(summary.quantiles(&["wan1"]), vec![(0.5, 150.0), (0.9, 190.0), (0.99, 199.0)]);
+
+        let families = registry.gather();
+        let metric = &families[0].get_metric()[0];
+        assert_eq!(families[0].get_field_type(), MetricType::SUMMARY);
+        assert_eq!(metric.get_summary().get_sample_count(), 200);
+        assert_eq!(metric.get_summary().get_sample_sum(), (1..=200).sum::<i32>() as f64);
+        assert_eq!(metric.get_label()[0].get_value(), "wan1");
+
+        assert!(SummaryMetric::register(&registry, "bad", "Bad", &[], vec![1.5], 10).is_err());
+    }
+}
@@ -0,0 +1,715 @@
+//! PromQL-lite alert expressions
+//!
+//! A small subset of PromQL evaluated against [`MetricsCollector`] series:
+//! instant selectors with label matchers, `rate()` and `increase()` over a
+//! range, and comparisons against a number or another selector, e.g.
+//! `rate(interface_errors{name="wan0"}[5m]) > 10`.
+//!
+//! Range functions are computed from the samples taken at each evaluation,
+//! so their resolution is the evaluation interval.
+//!
+//! [`MetricsCollector`]: crate::metrics::MetricsCollector
+
+use crate::metrics::MetricSample;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Expression syntax error, located by 1-based column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOp {
+    Equal,
+    NotEqual,
+}
+
+/// `label="value"` or `label!="value"`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelMatcher {
+    pub name: String,
+    pub op: MatchOp,
+    pub value: String,
+}
+
+impl LabelMatcher {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        // A missing label compares as the empty string, as in PromQL
+        let value = labels.get(&self.name).map(String::as_str).unwrap_or("");
+        match self.op {
+            MatchOp::Equal => value == self.value,
+            MatchOp::NotEqual => value != self.value,
+        }
+    }
+}
+
+/// Metric name plus label matchers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub metric: String,
+    pub matchers: Vec<LabelMatcher>,
+}
+
+impl Selector {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.matchers.iter().all(|m| m.matches(labels))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeFunction {
+    /// Per-second increase over the range
+    Rate,
+    /// Total increase over the range
+    Increase,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Selector(Selector),
+    Range {
+        function: RangeFunction,
+        selector: Selector,
+        range: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+        }
+    }
+}
+
+/// Parsed expression: an operand, optionally compared with another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub lhs: Operand,
+    pub comparison: Option<(Comparison, Operand)>,
+}
+
+impl Expr {
+    /// Longest range of each metric read through a range function
+    fn ranges(&self) -> impl Iterator<Item = (&str, Duration)> {
+        let rhs = self.comparison.as_ref().map(|(_, rhs)| rhs);
+        std::iter::once(&self.lhs).chain(rhs).filter_map(|operand| match operand {
+            Operand::Range { selector, range, .. } => Some((selector.metric.as_str(), *range)),
+            _ => None,
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, ParseError> {
+        parse(source)
+    }
+}
+
+/// Parse an alert expression
+pub fn parse(source: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(source);
+    let lhs = parser.operand()?;
+    parser.skip_whitespace();
+
+    let comparison = if parser.at_end() {
+        None
+    } else {
+        let op = parser.comparison()?;
+        Some((op, parser.operand()?))
+    };
+
+    parser.skip_whitespace();
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(format!("unexpected `{}`", c)));
+    }
+
+    Ok(Expr { lhs, comparison })
+}
+
+/// Parse a duration such as `90s`, `5m` or `1h30m`
+pub fn parse_duration(source: &str) -> Result<Duration, ParseError> {
+    parse_duration_at(source, 1)
+}
+
+fn parse_duration_at(source: &str, column: usize) -> Result<Duration, ParseError> {
+    let error = |offset: usize, message: &str| ParseError {
+        column: column + offset,
+        message: message.to_string(),
+    };
+
+    let chars: Vec<char> = source.chars().collect();
+    if chars.is_empty() {
+        return Err(error(0, "expected duration such as 5m"));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == start {
+            return Err(error(i, "expected duration such as 5m"));
+        }
+        let amount: u64 = chars[start..i].iter().collect::<String>()
+            .parse()
+            .map_err(|_| error(start, "duration out of range"))?;
+
+        let unit_start = i;
+        while i < chars.len() && chars[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        let millis = match chars[unit_start..i].iter().collect::<String>().as_str() {
+            "ms" => 1,
+            "s" => 1_000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            "d" => 86_400_000,
+            "" => return Err(error(unit_start, "expected duration unit (ms, s, m, h, d)")),
+            _ => return Err(error(unit_start, "unknown duration unit")),
+        };
+        total += Duration::from_millis(amount.saturating_mul(millis));
+    }
+
+    Ok(total)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            column: self.pos + 1,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("`{}`", c),
+            None => "end of expression".to_string(),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`, found {}", expected, self.found())))
+        }
+    }
+
+    fn identifier(&mut self, what: &str) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let valid = c.is_ascii_alphabetic() || c == '_' || c == ':'
+                || (self.pos > start && c.is_ascii_digit());
+            if !valid {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error(format!("expected {}, found {}", what, self.found())));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {
+                let start = self.pos;
+                let name = self.identifier("metric name")?;
+
+                self.skip_whitespace();
+                if self.peek() == Some('(') {
+                    let function = match name.as_str() {
+                        "rate" => RangeFunction::Rate,
+                        "increase" => RangeFunction::Increase,
+                        _ => {
+                            self.pos = start;
+                            return Err(self.error(format!("unknown function `{}`", name)));
+                        }
+                    };
+                    self.pos += 1;
+                    let metric = self.identifier("metric name")?;
+                    let selector = self.selector_body(metric)?;
+                    let range = self.range()?;
+                    self.expect(')')?;
+                    return Ok(Operand::Range { function, selector, range });
+                }
+
+                let selector = self.selector_body(name)?;
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    return Err(self.error("range selector needs rate() or increase()"));
+                }
+                Ok(Operand::Selector(selector))
+            }
+            _ => Err(self.error(format!("expected metric or number, found {}", self.found()))),
+        }
+    }
+
+    fn number(&mut self) -> Result<Operand, ParseError> {
+        let start = self.pos;
+        if matches!(self.peek(), Some('-' | '+')) {
+            self.pos += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '.') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map(Operand::Number).map_err(|_| ParseError {
+            column: start + 1,
+            message: format!("invalid number `{}`", text),
+        })
+    }
+
+    fn selector_body(&mut self, metric: String) -> Result<Selector, ParseError> {
+        let mut matchers = Vec::new();
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Ok(Selector { metric, matchers });
+        }
+        self.pos += 1;
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.identifier("label name")?;
+            self.skip_whitespace();
+            let op = match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some('='), _) => {
+                    self.pos += 1;
+                    MatchOp::Equal
+                }
+                (Some('!'), Some('=')) => {
+                    self.pos += 2;
+                    MatchOp::NotEqual
+                }
+                _ => return Err(self.error(format!("expected `=` or `!=`, found {}", self.found()))),
+            };
+            let value = self.string()?;
+            matchers.push(LabelMatcher { name, op, value });
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err(self.error(format!("expected `,` or `}}`, found {}", self.found()))),
+            }
+        }
+
+        Ok(Selector { metric, matchers })
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some('"') {
+            return Err(self.error(format!("expected quoted label value, found {}", self.found())));
+        }
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => {
+                    self.pos = start;
+                    return Err(self.error("unterminated string"));
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c @ ('"' | '\\')) => value.push(c),
+                        Some('n') => value.push('\n'),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn range(&mut self) -> Result<Duration, ParseError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let range = parse_duration_at(&text, start + 1)?;
+        if range.is_zero() {
+            return Err(ParseError { column: start + 1, message: "range must be positive".to_string() });
+        }
+        self.expect(']')?;
+        Ok(range)
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, ParseError> {
+        let next = self.chars.get(self.pos + 1).copied();
+        let (op, len) = match (self.peek(), next) {
+            (Some('>'), Some('=')) => (Comparison::Ge, 2),
+            (Some('<'), Some('=')) => (Comparison::Le, 2),
+            (Some('='), Some('=')) => (Comparison::Eq, 2),
+            (Some('!'), Some('=')) => (Comparison::Ne, 2),
+            (Some('>'), _) => (Comparison::Gt, 1),
+            (Some('<'), _) => (Comparison::Lt, 1),
+            _ => return Err(self.error(format!("expected comparison operator, found {}", self.found()))),
+        };
+        self.pos += len;
+        Ok(op)
+    }
+}
+
+/// One series of an evaluation result
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+type SeriesValues = Vec<(BTreeMap<String, String>, f64)>;
+
+/// Current values of every collected series, indexed by metric name
+pub struct SeriesSnapshot {
+    at: DateTime<Utc>,
+    by_metric: HashMap<String, SeriesValues>,
+}
+
+impl SeriesSnapshot {
+    pub fn new(at: DateTime<Utc>, samples: Vec<MetricSample>) -> Self {
+        let mut by_metric: HashMap<String, Vec<_>> = HashMap::new();
+        for sample in samples {
+            by_metric.entry(sample.name).or_default().push((sample.labels, sample.value));
+        }
+        Self { at, by_metric }
+    }
+
+    fn select(&self, selector: &Selector) -> Vec<Series> {
+        self.by_metric
+            .get(&selector.metric)
+            .into_iter()
+            .flatten()
+            .filter(|(labels, _)| selector.matches(labels))
+            .map(|(labels, value)| Series { labels: labels.clone(), value: *value })
+            .collect()
+    }
+}
+
+enum Value {
+    Scalar(f64),
+    Vector(Vec<Series>),
+}
+
+type SampleHistory = HashMap<BTreeMap<String, String>, VecDeque<(DateTime<Utc>, f64)>>;
+
+/// Evaluates many expressions against one snapshot per cycle, caching
+/// parsed expressions and keeping the samples range functions need
+#[derive(Default)]
+pub struct ExprEvaluator {
+    compiled: HashMap<String, Result<Arc<Expr>, ParseError>>,
+    history: HashMap<String, SampleHistory>,
+}
+
+impl ExprEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate `sources` against `snapshot`, returning the series each
+    /// expression yields; only the given expressions stay cached
+    pub fn evaluate_all(
+        &mut self,
+        sources: &[&str],
+        snapshot: &SeriesSnapshot,
+    ) -> Vec<Result<Vec<Series>, ParseError>> {
+        self.compiled.retain(|source, _| sources.contains(&source.as_str()));
+        let exprs: Vec<_> = sources.iter()
+            .map(|source| {
+                self.compiled
+                    .entry(source.to_string())
+                    .or_insert_with(|| parse(source).map(Arc::new))
+                    .clone()
+            })
+            .collect();
+
+        let mut ranges: HashMap<&str, Duration> = HashMap::new();
+        for expr in exprs.iter().flatten() {
+            for (metric, range) in expr.ranges() {
+                let longest = ranges.entry(metric).or_default();
+                *longest = (*longest).max(range);
+            }
+        }
+        self.record(snapshot, &ranges);
+
+        exprs.into_iter()
+            .map(|expr| expr.map(|expr| self.evaluate(&expr, snapshot)))
+            .collect()
+    }
+
+    /// Append the snapshot to the history of metrics read through range
+    /// functions and drop samples older than their longest range
+    fn record(&mut self, snapshot: &SeriesSnapshot, ranges: &HashMap<&str, Duration>) {
+        self.history.retain(|metric, _| ranges.contains_key(metric.as_str()));
+
+        for (metric, range) in ranges {
+            let cutoff = snapshot.at - chrono::Duration::from_std(*range).unwrap_or(chrono::Duration::MAX);
+            let series = self.history.entry(metric.to_string()).or_default();
+            for (labels, value) in snapshot.by_metric.get(*metric).into_iter().flatten() {
+                series.entry(labels.clone()).or_default().push_back((snapshot.at, *value));
+            }
+            series.retain(|_, points| {
+                while points.front().is_some_and(|(at, _)| *at < cutoff) {
+                    points.pop_front();
+                }
+                !points.is_empty()
+            });
+        }
+    }
+
+    fn evaluate(&self, expr: &Expr, snapshot: &SeriesSnapshot) -> Vec<Series> {
+        let lhs = self.operand(&expr.lhs, snapshot);
+        let Some((op, rhs)) = &expr.comparison else {
+            return match lhs {
+                Value::Scalar(value) => vec![Series { labels: BTreeMap::new(), value }],
+                Value::Vector(series) => series,
+            };
+        };
+
+        match (lhs, self.operand(rhs, snapshot)) {
+            (Value::Scalar(l), Value::Scalar(r)) => op.holds(l, r)
+                .then(|| Series { labels: BTreeMap::new(), value: l })
+                .into_iter()
+                .collect(),
+            (Value::Vector(series), Value::Scalar(r)) => {
+                series.into_iter().filter(|s| op.holds(s.value, r)).collect()
+            }
+            (Value::Scalar(l), Value::Vector(series)) => {
+                series.into_iter().filter(|s| op.holds(l, s.value)).collect()
+            }
+            // Vector against vector compares series with identical labels
+            (Value::Vector(left), Value::Vector(right)) => left.into_iter()
+                .filter(|l| {
+                    right.iter().any(|r| r.labels == l.labels && op.holds(l.value, r.value))
+                })
+                .collect(),
+        }
+    }
+
+    fn operand(&self, operand: &Operand, snapshot: &SeriesSnapshot) -> Value {
+        match operand {
+            Operand::Number(n) => Value::Scalar(*n),
+            Operand::Selector(selector) => Value::Vector(snapshot.select(selector)),
+            Operand::Range { function, selector, range } => {
+                let from = snapshot.at - chrono::Duration::from_std(*range).unwrap_or(chrono::Duration::MAX);
+                let series = self.history.get(&selector.metric).into_iter().flatten()
+                    .filter(|(labels, _)| selector.matches(labels))
+                    .filter_map(|(labels, points)| {
+                        let window: Vec<_> = points.iter()
+                            .filter(|(at, _)| *at >= from && *at <= snapshot.at)
+                            .collect();
+                        let value = range_value(*function, &window)?;
+                        Some(Series { labels: labels.clone(), value })
+                    })
+                    .collect();
+                Value::Vector(series)
+            }
+        }
+    }
+}
+
+/// Counter increase across `window`, treating any decrease as a reset
+fn range_value(function: RangeFunction, window: &[&(DateTime<Utc>, f64)]) -> Option<f64> {
+    let (first, last) = (window.first()?, window.last()?);
+    let elapsed = (last.0 - first.0).num_milliseconds() as f64 / 1000.0;
+    if window.len() < 2 || elapsed <= 0.0 {
+        return None;
+    }
+
+    let increase: f64 = window.windows(2)
+        .map(|pair| {
+            let (prev, cur) = (pair[0].1, pair[1].1);
+            if cur < prev { cur } else { cur - prev }
+        })
+        .sum();
+
+    Some(match function {
+        RangeFunction::Rate => increase / elapsed,
+        RangeFunction::Increase => increase,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, iface: &str, value: f64) -> MetricSample {
+        MetricSample {
+            name: name.to_string(),
+            labels: BTreeMap::from([("name".to_string(), iface.to_string())]),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let expr = parse(r#"rate(interface_errors{name="wan0", kind!="rx"}[5m]) > 10"#).unwrap();
+        assert_eq!(expr.comparison.as_ref().unwrap(), &(Comparison::Gt, Operand::Number(10.0)));
+        let Operand::Range { function, selector, range } = &expr.lhs else {
+            panic!("expected a range function");
+        };
+        assert_eq!(*function, RangeFunction::Rate);
+        assert_eq!(selector.metric, "interface_errors");
+        assert_eq!(selector.matchers[1].op, MatchOp::NotEqual);
+        assert_eq!(*range, Duration::from_secs(300));
+
+        assert_eq!(parse("up").unwrap().lhs, Operand::Selector(Selector {
+            metric: "up".to_string(),
+            matchers: Vec::new(),
+        }));
+        assert!(parse("cpu_usage >= -1.5e2").is_ok());
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    }
+
+    #[test]
+    fn test_parse_errors_report_column() {
+        let cases = [
+            ("rate(errors[5x]) > 1", 14, "unknown duration unit"),
+            ("rate(errors[5m) > 1", 15, "expected `]`, found `)`"),
+            ("errors{name=wan0} > 1", 13, "expected quoted label value, found `w`"),
+            ("errors > ", 10, "expected metric or number, found end of expression"),
+            ("avg(errors[5m]) > 1", 1, "unknown function `avg`"),
+            ("errors[5m] > 1", 7, "range selector needs rate() or increase()"),
+            ("errors > 1 1", 12, "unexpected `1`"),
+            (r#"errors{name="wan0} > 1"#, 13, "unterminated string"),
+        ];
+        for (source, column, message) in cases {
+            let err = parse(source).unwrap_err();
+            assert_eq!((err.column, err.message.as_str()), (column, message), "{}", source);
+        }
+        assert_eq!(parse_duration("5").unwrap_err().to_string(), "expected duration unit (ms, s, m, h, d) at column 2");
+    }
+
+    #[test]
+    fn test_evaluate_rate_and_filters() {
+        let mut evaluator = ExprEvaluator::new();
+        let sources = [
+            r#"rate(interface_errors{name="wan0"}[1m]) > 5"#,
+            "increase(interface_errors[1m])",
+            "interface_up == 0",
+            "rate(errors[5m) > 1",
+        ];
+        let start = Utc::now();
+        let snapshot = |secs: i64, wan: f64, lan: f64| SeriesSnapshot::new(
+            start + chrono::Duration::seconds(secs),
+            vec![
+                sample("interface_errors", "wan0", wan),
+                sample("interface_errors", "lan0", lan),
+                sample("interface_up", "wan0", 1.0),
+                sample("interface_up", "lan0", 0.0),
+            ],
+        );
+
+        // One sample is not enough for a rate
+        let results = evaluator.evaluate_all(&sources, &snapshot(0, 0.0, 0.0));
+        assert!(results[0].as_ref().unwrap().is_empty());
+        let down = results[2].as_ref().unwrap();
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].labels["name"], "lan0");
+        assert_eq!(results[3].as_ref().unwrap_err().column, 15);
+
+        evaluator.evaluate_all(&sources, &snapshot(30, 300.0, 10.0));
+        // The wan0 counter resets to 60 and keeps rising
+        let results = evaluator.evaluate_all(&sources, &snapshot(60, 60.0, 20.0));
+        let firing = results[0].as_ref().unwrap();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].value, 6.0);
+
+        let mut increases = results[1].clone().unwrap();
+        increases.sort_by(|a, b| a.labels.cmp(&b.labels));
+        assert_eq!(increases.iter().map(|s| s.value).collect::<Vec<_>>(), vec![20.0, 360.0]);
+
+        // The earlier samples age out of the one-minute range
+        evaluator.evaluate_all(&sources, &snapshot(90, 60.0, 20.0));
+        let results = evaluator.evaluate_all(&sources, &snapshot(150, 60.0, 20.0));
+        assert!(results[0].as_ref().unwrap().is_empty());
+    }
+}
@@ -6,13 +6,32 @@
 pub mod prometheus;
 pub mod metrics;
 pub mod alerts;
+pub mod expr;
 pub mod status;
+pub mod retention;
+pub mod silences;
+pub mod distribution;
+pub mod snmp;
+pub mod traffic;
+pub mod dashboards;
 
 pub use prometheus::PrometheusExporter;
-pub use metrics::MetricsCollector;
-pub use alerts::AlertManager;
+pub use metrics::{MetricSample, MetricsCollector};
+pub use alerts::{
+    AlertManager, AlertRouting, Escalation, FiredAlert, NotificationStats, PendingAlert,
+    RetryPolicy, SeverityRoute, SmtpSecurity,
+};
+pub use expr::{Expr, ExprEvaluator, ParseError};
+pub use silences::{Silence, SilenceStore};
+pub use distribution::{
+    DurationTimer, Exemplar, ExemplarStore, HistogramBuckets, HistogramMetric, SummaryMetric,
+};
+pub use snmp::{SnmpAgent, SnmpConfig};
+pub use dashboards::{dashboard_routes, DashboardApi, DashboardStore, WidgetData};
+pub use traffic::{TrafficHistory, TrafficHistoryPoint, TrafficRetention, TrafficTier};
+pub use retention::{RetentionPolicy, MetricStore, TimeSeries, SeriesPoint, Aggregate};
 pub use status::{
-    StatusPageManager, DashboardConfig, DashboardWidget, WidgetType,
+    StatusPageManager, DashboardConfig, DashboardWidget, WidgetType, MetricQuery,
     InterfaceStatus, DhcpLease, ServiceStatus, IpsecTunnelStatus,
     OpenVpnClientStatus, WireGuardPeerStatus, GatewayHealth,
     TrafficDataPoint, LogEntry,
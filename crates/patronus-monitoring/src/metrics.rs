@@ -4,16 +4,36 @@
 //! alerting, and capacity planning.
 
 use prometheus::{
-    Registry, Counter, Gauge, HistogramOpts,
-    Opts, CounterVec, GaugeVec, HistogramVec,
+    Registry, Counter, Gauge,
+    Opts, CounterVec, GaugeVec,
 };
-use std::sync::Arc;
+use crate::distribution::{
+    DurationTimer, ExemplarStore, HistogramBuckets, HistogramMetric, SummaryMetric,
+    DEFAULT_LATENCY_BUCKETS, DEFAULT_RTT_BUCKETS,
+};
+use crate::retention::{Aggregate, MetricStore, RetentionPolicy, SeriesPoint};
+use crate::status::MetricQuery;
+use chrono::{DateTime, Utc};
+use prometheus::proto::MetricType;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 use tokio::time::{interval, Duration};
 use sysinfo::{System, Disks, Networks, Components};
 
+/// Current value of one series, as selected by a [`MetricQuery`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
 /// Central metrics collector for all Patronus subsystems
 pub struct MetricsCollector {
     registry: Registry,
+    buckets: HistogramBuckets,
+    exemplars: Arc<ExemplarStore>,
 
     // System metrics
     cpu_usage: Gauge,
@@ -59,7 +79,7 @@ pub struct MetricsCollector {
 
     // DNS metrics
     dns_queries_total: CounterVec,
-    dns_query_duration: HistogramVec,
+    dns_query_duration: HistogramMetric,
     dns_cache_hits: Counter,
     dns_cache_misses: Counter,
     dns_blocked_queries: CounterVec,
@@ -89,17 +109,37 @@ pub struct MetricsCollector {
 
     // Web UI metrics
     http_requests_total: CounterVec,
-    http_request_duration: HistogramVec,
+    http_request_duration: HistogramMetric,
     http_requests_in_flight: Gauge,
 
+    // SD-WAN metrics
+    sdwan_path_rtt: HistogramMetric,
+
     // Service health
     service_up: GaugeVec,
     service_restarts: CounterVec,
+
+    // Retained sample history
+    history: RwLock<MetricStore>,
 }
 
 impl MetricsCollector {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_retention(RetentionPolicy::default())
+    }
+
+    /// Create a collector with a custom sample retention policy
+    pub fn with_retention(policy: RetentionPolicy) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(policy, HistogramBuckets::default())
+    }
+
+    /// Create a collector with a retention policy and histogram bucket overrides
+    pub fn with_config(
+        policy: RetentionPolicy,
+        buckets: HistogramBuckets,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let registry = Registry::new();
+        let exemplars = Arc::new(ExemplarStore::new());
 
         // System metrics
         let cpu_usage = Gauge::with_opts(Opts::new(
@@ -231,7 +271,7 @@ impl MetricsCollector {
         registry.register(Box::new(firewall_connections_total.clone()))?;
 
         let firewall_rules_count = Gauge::with_opts(Opts::new(
-            "patronus_firewall_rules_count",
+            "patronus_firewall_rules",
             "Number of active firewall rules"
         ))?;
         registry.register(Box::new(firewall_rules_count.clone()))?;
@@ -305,11 +345,14 @@ impl MetricsCollector {
         )?;
         registry.register(Box::new(dns_queries_total.clone()))?;
 
-        let dns_query_duration = HistogramVec::new(
-            HistogramOpts::new("patronus_dns_query_duration_seconds", "DNS query duration"),
-            &["type"]
+        let dns_query_duration = HistogramMetric::register(
+            &registry,
+            "patronus_dns_query_duration_seconds",
+            "DNS query duration",
+            &["type"],
+            buckets.for_metric("patronus_dns_query_duration_seconds", DEFAULT_LATENCY_BUCKETS),
+            exemplars.clone(),
         )?;
-        registry.register(Box::new(dns_query_duration.clone()))?;
 
         let dns_cache_hits = Counter::with_opts(Opts::new(
             "patronus_dns_cache_hits_total",
@@ -430,11 +473,14 @@ impl MetricsCollector {
         )?;
         registry.register(Box::new(http_requests_total.clone()))?;
 
-        let http_request_duration = HistogramVec::new(
-            HistogramOpts::new("patronus_http_request_duration_seconds", "HTTP request duration"),
-            &["method", "path"]
+        let http_request_duration = HistogramMetric::register(
+            &registry,
+            "patronus_http_request_duration_seconds",
+            "HTTP request duration",
+            &["method", "path"],
+            buckets.for_metric("patronus_http_request_duration_seconds", DEFAULT_LATENCY_BUCKETS),
+            exemplars.clone(),
         )?;
-        registry.register(Box::new(http_request_duration.clone()))?;
 
         let http_requests_in_flight = Gauge::with_opts(Opts::new(
             "patronus_http_requests_in_flight",
@@ -442,6 +488,16 @@ impl MetricsCollector {
         ))?;
         registry.register(Box::new(http_requests_in_flight.clone()))?;
 
+        // SD-WAN metrics
+        let sdwan_path_rtt = HistogramMetric::register(
+            &registry,
+            "patronus_sdwan_path_rtt_seconds",
+            "SD-WAN path round-trip time",
+            &["path"],
+            buckets.for_metric("patronus_sdwan_path_rtt_seconds", DEFAULT_RTT_BUCKETS),
+            exemplars.clone(),
+        )?;
+
         // Service health
         let service_up = GaugeVec::new(
             Opts::new("patronus_service_up", "Service health (1=up, 0=down)"),
@@ -457,6 +513,8 @@ impl MetricsCollector {
 
         Ok(Self {
             registry,
+            buckets,
+            exemplars,
             cpu_usage,
             memory_usage,
             memory_total,
@@ -512,8 +570,10 @@ impl MetricsCollector {
             http_requests_total,
             http_request_duration,
             http_requests_in_flight,
+            sdwan_path_rtt,
             service_up,
             service_restarts,
+            history: RwLock::new(MetricStore::new(policy)),
         })
     }
 
@@ -522,6 +582,46 @@ impl MetricsCollector {
         &self.registry
     }
 
+    /// Exemplars attached to histogram buckets
+    pub fn exemplars(&self) -> &Arc<ExemplarStore> {
+        &self.exemplars
+    }
+
+    /// Keep bucket exemplars, typically whenever tracing is enabled
+    pub fn set_exemplars_enabled(&self, enabled: bool) {
+        self.exemplars.set_enabled(enabled);
+    }
+
+    /// Register a subsystem histogram, honoring any configured bucket override
+    pub fn register_histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        default_buckets: &[f64],
+    ) -> prometheus::Result<HistogramMetric> {
+        HistogramMetric::register(
+            &self.registry,
+            name,
+            help,
+            labels,
+            self.buckets.for_metric(name, default_buckets),
+            self.exemplars.clone(),
+        )
+    }
+
+    /// Register a subsystem summary over the last `max_samples` observations
+    pub fn register_summary(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        quantiles: &[f64],
+        max_samples: usize,
+    ) -> prometheus::Result<SummaryMetric> {
+        SummaryMetric::register(&self.registry, name, help, labels, quantiles.to_vec(), max_samples)
+    }
+
     /// Start automatic metrics collection
     pub async fn start_collection(self: Arc<Self>) {
         let mut sys_interval = interval(Duration::from_secs(5));
@@ -556,11 +656,13 @@ impl MetricsCollector {
                 .map(|p| p.cpu_usage() as f64)
                 .sum::<f64>() / cpus.len() as f64;
             self.cpu_usage.set(cpu_usage);
+            self.record_sample("cpu_usage_percent", cpu_usage);
         }
 
         // Memory
         self.memory_usage.set(sys.used_memory() as f64);
         self.memory_total.set(sys.total_memory() as f64);
+        self.record_sample("memory_used_bytes", sys.used_memory() as f64);
 
         // Disk usage - using separate Disks struct
         let disks = Disks::new_with_refreshed_list();
@@ -581,6 +683,7 @@ impl MetricsCollector {
         self.system_load.with_label_values(&["1m"]).set(load_avg.one);
         self.system_load.with_label_values(&["5m"]).set(load_avg.five);
         self.system_load.with_label_values(&["15m"]).set(load_avg.fifteen);
+        self.record_sample("system_load_1m", load_avg.one);
 
         // Uptime
         self.system_uptime.set(System::uptime() as f64);
@@ -596,6 +699,8 @@ impl MetricsCollector {
                     .set(temp as f64);
             }
         }
+
+        self.compact_history();
     }
 
     /// Collect network interface metrics
@@ -660,9 +765,7 @@ impl MetricsCollector {
         self.dns_queries_total
             .with_label_values(&[query_type, result])
             .inc();
-        self.dns_query_duration
-            .with_label_values(&[query_type])
-            .observe(duration_secs);
+        self.dns_query_duration.observe(&[query_type], duration_secs);
     }
 
     pub fn record_ids_alert(&self, severity: &str, category: &str, signature: &str) {
@@ -681,9 +784,16 @@ impl MetricsCollector {
         self.http_requests_total
             .with_label_values(&[method, path, &status.to_string()])
             .inc();
-        self.http_request_duration
-            .with_label_values(&[method, path])
-            .observe(duration_secs);
+        self.http_request_duration.observe(&[method, path], duration_secs);
+    }
+
+    /// Timer guard observing an HTTP handler's latency when dropped
+    pub fn observe_http_duration(&self, method: &str, path: &str) -> DurationTimer {
+        self.http_request_duration.observe_duration(&[method, path])
+    }
+
+    pub fn record_path_rtt(&self, path_id: &str, rtt_secs: f64) {
+        self.sdwan_path_rtt.observe(&[path_id], rtt_secs);
     }
 
     pub fn set_service_health(&self, service: &str, healthy: bool) {
@@ -691,4 +801,96 @@ impl MetricsCollector {
             .with_label_values(&[service])
             .set(if healthy { 1.0 } else { 0.0 });
     }
+
+    // Retained history API
+
+    /// Record a sample into the retained history
+    pub fn record_sample(&self, name: &str, value: f64) {
+        self.record_sample_at(name, Utc::now(), value);
+    }
+
+    /// Record a sample with an explicit timestamp
+    pub fn record_sample_at(&self, name: &str, timestamp: DateTime<Utc>, value: f64) {
+        if let Ok(mut history) = self.history.write() {
+            history.record(name, timestamp, value);
+        }
+    }
+
+    /// Apply the retention policy, downsampling and dropping old samples
+    pub fn compact_history(&self) {
+        if let Ok(mut history) = self.history.write() {
+            history.compact(Utc::now());
+        }
+    }
+
+    /// Query retained history across raw and downsampled tiers
+    pub fn query_history(&self, name: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<SeriesPoint> {
+        self.history
+            .read()
+            .map(|h| h.query(name, from, to))
+            .unwrap_or_default()
+    }
+
+    /// Aggregate retained history over a time range
+    pub fn aggregate_history(&self, name: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<Aggregate> {
+        self.history
+            .read()
+            .ok()
+            .and_then(|h| h.aggregate(name, from, to))
+    }
+
+    /// Current values of the series of `query.metric` whose labels match
+    /// every filter; histograms and summaries yield `_count` and `_sum`
+    pub fn query(&self, query: &MetricQuery) -> Vec<MetricSample> {
+        self.gather_samples(Some(query))
+    }
+
+    /// Current values of every series, from a single gather
+    pub fn snapshot(&self) -> Vec<MetricSample> {
+        self.gather_samples(None)
+    }
+
+    fn gather_samples(&self, query: Option<&MetricQuery>) -> Vec<MetricSample> {
+        let mut samples = Vec::new();
+
+        for family in self.registry.gather() {
+            if query.is_some_and(|q| family.get_name() != q.metric) {
+                continue;
+            }
+
+            for metric in family.get_metric() {
+                let labels: BTreeMap<String, String> = metric
+                    .get_label()
+                    .iter()
+                    .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                    .collect();
+                let matches = query.is_none_or(|q| q.labels.iter().all(|(k, v)| labels.get(k) == Some(v)));
+                if !matches {
+                    continue;
+                }
+
+                let mut push = |name: String, value: f64| {
+                    samples.push(MetricSample { name, labels: labels.clone(), value });
+                };
+                let name = family.get_name();
+                match family.get_field_type() {
+                    MetricType::COUNTER => push(name.to_string(), metric.get_counter().get_value()),
+                    MetricType::GAUGE => push(name.to_string(), metric.get_gauge().get_value()),
+                    MetricType::UNTYPED => push(name.to_string(), metric.get_untyped().get_value()),
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        push(format!("{}_count", name), histogram.get_sample_count() as f64);
+                        push(format!("{}_sum", name), histogram.get_sample_sum());
+                    }
+                    MetricType::SUMMARY => {
+                        let summary = metric.get_summary();
+                        push(format!("{}_count", name), summary.get_sample_count() as f64);
+                        push(format!("{}_sum", name), summary.get_sample_sum());
+                    }
+                }
+            }
+        }
+
+        samples
+    }
 }
@@ -1,28 +1,44 @@
 //! Prometheus HTTP Exporter
 //!
-//! Serves metrics in Prometheus format on /metrics endpoint
+//! Serves metrics in Prometheus format on /metrics endpoint. Scrapers
+//! asking for OpenMetrics get that format instead, which also carries
+//! histogram bucket exemplars.
 
+use crate::dashboards::{dashboard_routes, DashboardApi, DashboardStore};
+use crate::distribution::{Exemplar, ExemplarStore, TRACE_ID_LABEL};
 use crate::metrics::MetricsCollector;
 use axum::{
     Router,
     extract::State,
     response::{IntoResponse, Response},
     routing::get,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
 use prometheus::{Encoder, TextEncoder};
+use std::fmt::Write as FmtWrite;
 use std::sync::Arc;
 use std::net::SocketAddr;
 
+/// Content type of the OpenMetrics text exposition
+pub const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// Prometheus metrics exporter
 pub struct PrometheusExporter {
     collector: Arc<MetricsCollector>,
     addr: SocketAddr,
+    dashboards: Option<Arc<DashboardStore>>,
 }
 
 impl PrometheusExporter {
     pub fn new(collector: Arc<MetricsCollector>, addr: SocketAddr) -> Self {
-        Self { collector, addr }
+        Self { collector, addr, dashboards: None }
+    }
+
+    /// Also serve the per-user dashboard endpoints
+    pub fn with_dashboards(mut self, layouts: Arc<DashboardStore>) -> Self {
+        self.dashboards = Some(layouts);
+        self
     }
 
     /// Start the Prometheus HTTP server
@@ -32,10 +48,17 @@ impl PrometheusExporter {
         // Start automatic metric collection
         collector.clone().start_collection().await;
 
-        let app = Router::new()
+        let mut app = Router::new()
             .route("/metrics", get(metrics_handler))
             .route("/health", get(health_handler))
-            .with_state(self.collector);
+            .with_state(self.collector.clone());
+
+        if let Some(layouts) = self.dashboards {
+            app = app.merge(dashboard_routes(DashboardApi {
+                layouts,
+                collector: self.collector,
+            }));
+        }
 
         tracing::info!("Prometheus exporter listening on {}", self.addr);
 
@@ -49,10 +72,26 @@ impl PrometheusExporter {
 
 async fn metrics_handler(
     State(collector): State<Arc<MetricsCollector>>,
+    headers: HeaderMap,
 ) -> Response {
-    let encoder = TextEncoder::new();
     let metric_families = collector.registry().gather();
 
+    let wants_openmetrics = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+
+    if wants_openmetrics {
+        let body = encode_openmetrics(&metric_families, collector.exemplars());
+        return (
+            StatusCode::OK,
+            [("Content-Type", OPENMETRICS_CONTENT_TYPE)],
+            body,
+        ).into_response();
+    }
+
+    let encoder = TextEncoder::new();
+
     let mut buffer = Vec::new();
     match encoder.encode(&metric_families, &mut buffer) {
         Ok(_) => {
@@ -76,6 +115,132 @@ async fn health_handler() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
+/// Encode metric families in the OpenMetrics text format, attaching the
+/// exemplars kept for histogram buckets
+pub fn encode_openmetrics(families: &[MetricFamily], exemplars: &ExemplarStore) -> String {
+    let mut out = String::new();
+
+    for family in families {
+        let name = family.get_name();
+        let (family_name, kind) = match family.get_field_type() {
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+
+        writeln!(out, "# TYPE {} {}", family_name, kind).unwrap();
+        writeln!(out, "# HELP {} {}", family_name, escape(family.get_help())).unwrap();
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    let sample = format!("{}_total", family_name);
+                    write_sample(&mut out, &sample, labels, None, metric.get_counter().get_value(), None);
+                }
+                MetricType::GAUGE => {
+                    write_sample(&mut out, name, labels, None, metric.get_gauge().get_value(), None);
+                }
+                MetricType::UNTYPED => {
+                    write_sample(&mut out, name, labels, None, metric.get_untyped().get_value(), None);
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let pairs: Vec<(String, String)> = labels
+                        .iter()
+                        .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                        .collect();
+                    let kept = exemplars.get(name, &pairs).unwrap_or_default();
+                    let bucket_name = format!("{}_bucket", name);
+
+                    for (i, bucket) in histogram.get_bucket().iter().enumerate() {
+                        let le = ("le", format_float(bucket.get_upper_bound()));
+                        let exemplar = kept.get(i).and_then(Option::as_ref);
+                        write_sample(&mut out, &bucket_name, labels, Some(le), bucket.get_cumulative_count() as f64, exemplar);
+                    }
+
+                    let inf = ("le", "+Inf".to_string());
+                    let exemplar = kept.get(histogram.get_bucket().len()).and_then(Option::as_ref);
+                    write_sample(&mut out, &bucket_name, labels, Some(inf), histogram.get_sample_count() as f64, exemplar);
+
+                    write_sample(&mut out, &format!("{}_sum", name), labels, None, histogram.get_sample_sum(), None);
+                    write_sample(&mut out, &format!("{}_count", name), labels, None, histogram.get_sample_count() as f64, None);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = ("quantile", format_float(quantile.get_quantile()));
+                        write_sample(&mut out, name, labels, Some(q), quantile.get_value(), None);
+                    }
+                    write_sample(&mut out, &format!("{}_sum", name), labels, None, summary.get_sample_sum(), None);
+                    write_sample(&mut out, &format!("{}_count", name), labels, None, summary.get_sample_count() as f64, None);
+                }
+            }
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, String)>,
+    value: f64,
+    exemplar: Option<&Exemplar>,
+) {
+    out.push_str(name);
+
+    let mut pairs: Vec<(&str, &str)> = labels.iter().map(|l| (l.get_name(), l.get_value())).collect();
+    if let Some((key, value)) = &extra {
+        pairs.push((key, value));
+    }
+    if !pairs.is_empty() {
+        let rendered: Vec<String> = pairs
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+            .collect();
+        write!(out, "{{{}}}", rendered.join(",")).unwrap();
+    }
+
+    write!(out, " {}", format_float(value)).unwrap();
+
+    if let Some(exemplar) = exemplar {
+        write!(
+            out,
+            " # {{{}=\"{}\"}} {} {:.3}",
+            TRACE_ID_LABEL,
+            escape(&exemplar.trace_id),
+            format_float(exemplar.value),
+            exemplar.timestamp,
+        ).unwrap();
+    }
+    out.push('\n');
+}
+
+/// Format a float the way OpenMetrics expects, e.g. `1.0`, `+Inf`, `NaN`
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.1}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape label values and help text
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,4 +251,112 @@ mod tests {
         let addr = "127.0.0.1:9090".parse().unwrap();
         let _exporter = PrometheusExporter::new(collector, addr);
     }
+
+    fn populated_collector() -> MetricsCollector {
+        let collector = MetricsCollector::new().unwrap();
+        collector.record_http_request("GET", "/api/status", 200, 0.042);
+        collector.record_http_request("GET", "/api/status", 200, 3.0);
+        collector.record_path_rtt("wan1", 0.018);
+        collector.record_dns_query("A", "NOERROR", 0.002);
+        collector.record_firewall_packet("input", "accept", 1500);
+        collector.set_firewall_connections(12);
+        collector
+    }
+
+    fn text_exposition(collector: &MetricsCollector) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&collector.registry().gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn sample_value(line: &str) -> f64 {
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn test_text_exposition_follows_conventions() {
+        let text = text_exposition(&populated_collector());
+
+        // The checks promtool check-metrics applies to names and help text
+        let mut families = Vec::new();
+        for line in text.lines().filter(|l| l.starts_with("# TYPE ")) {
+            let mut parts = line.split(' ').skip(2);
+            let (name, kind) = (parts.next().unwrap(), parts.next().unwrap());
+            assert!(text.contains(&format!("# HELP {} ", name)), "{} has no help", name);
+            if kind == "counter" {
+                assert!(name.ends_with("_total"), "counter {} lacks _total", name);
+            }
+            if kind != "histogram" && kind != "summary" {
+                for suffix in ["_count", "_sum", "_bucket"] {
+                    assert!(!name.ends_with(suffix), "{} {} uses reserved suffix", kind, name);
+                }
+            }
+            families.push(name.to_string());
+        }
+        assert!(families.contains(&"patronus_sdwan_path_rtt_seconds".to_string()));
+
+        // Buckets are cumulative and end with +Inf, which equals _count
+        let series = "{method=\"GET\",path=\"/api/status\"";
+        let buckets: Vec<&str> = text.lines()
+            .filter(|l| l.starts_with("patronus_http_request_duration_seconds_bucket") && l.contains(series))
+            .collect();
+        let counts: Vec<f64> = buckets.iter().map(|l| sample_value(l)).collect();
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]));
+        assert!(buckets.last().unwrap().contains("le=\"+Inf\""));
+        assert_eq!(counts.first(), Some(&0.0));
+        assert_eq!(counts.last(), Some(&2.0));
+
+        let count = text.lines()
+            .find(|l| l.starts_with("patronus_http_request_duration_seconds_count"))
+            .unwrap();
+        let sum = text.lines()
+            .find(|l| l.starts_with("patronus_http_request_duration_seconds_sum"))
+            .unwrap();
+        assert_eq!(sample_value(count), 2.0);
+        assert!((sample_value(sum) - 3.042).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_custom_buckets() {
+        let buckets = crate::distribution::HistogramBuckets::new()
+            .with("patronus_sdwan_path_rtt_seconds", vec![0.01, 0.05]);
+        let collector = MetricsCollector::with_config(Default::default(), buckets).unwrap();
+        collector.record_path_rtt("wan1", 0.02);
+
+        let text = text_exposition(&collector);
+        let les: Vec<&str> = text.lines()
+            .filter(|l| l.starts_with("patronus_sdwan_path_rtt_seconds_bucket"))
+            .map(|l| l.split("le=\"").nth(1).unwrap().split('"').next().unwrap())
+            .collect();
+        assert_eq!(les, vec!["0.01", "0.05", "+Inf"]);
+    }
+
+    #[test]
+    fn test_openmetrics_exposition_with_exemplars() {
+        let collector = populated_collector();
+        collector.set_exemplars_enabled(true);
+        collector
+            .observe_http_duration("POST", "/api/login")
+            .with_trace_id("4bf92f3577b34da6a3ce929d0e0e4736")
+            .observe();
+
+        let text = encode_openmetrics(&collector.registry().gather(), collector.exemplars());
+        assert!(text.ends_with("# EOF\n"));
+
+        // Counter families drop the _total suffix, samples keep it
+        assert!(text.contains("# TYPE patronus_firewall_packets counter\n"));
+        assert!(text.contains("patronus_firewall_packets_total{action=\"accept\",chain=\"input\"} 1.0\n"));
+        assert!(text.contains("# TYPE patronus_sdwan_path_rtt_seconds histogram\n"));
+
+        let with_exemplar: Vec<&str> = text.lines()
+            .filter(|l| l.contains("# {trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"}"))
+            .collect();
+        assert_eq!(with_exemplar.len(), 1);
+        assert!(with_exemplar[0].starts_with(
+            "patronus_http_request_duration_seconds_bucket{method=\"POST\",path=\"/api/login\",le=\"0.005\"} 1.0 # "
+        ));
+
+        // Exemplars stay out of the classic text format
+        assert!(!text_exposition(&collector).contains("trace_id"));
+    }
 }
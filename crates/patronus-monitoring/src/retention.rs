@@ -0,0 +1,336 @@
+//! Metric Retention & Downsampling
+//!
+//! Keeps recent samples at full resolution and folds older samples into
+//! coarser min/max/avg buckets, dropping anything past the maximum age.
+//! Queries read transparently across the raw and downsampled tiers.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Retention policy for stored metric samples
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// How long raw samples are kept before being downsampled
+    pub raw_retention: Duration,
+    /// Width of each downsampled bucket
+    pub bucket_width: Duration,
+    /// Data older than this is dropped entirely
+    pub max_age: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_retention: Duration::hours(1),
+            bucket_width: Duration::minutes(5),
+            max_age: Duration::days(30),
+        }
+    }
+}
+
+/// A single raw sample
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// A downsampled bucket summarising all samples in `[start, start + width)`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub start: DateTime<Utc>,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Bucket {
+    fn from_sample(start: DateTime<Utc>, value: f64) -> Self {
+        Self {
+            start,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Average of all samples folded into this bucket
+    pub fn avg(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+}
+
+/// A point returned from a range query, from either tier
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub timestamp: DateTime<Utc>,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u64,
+    /// Whether this point comes from the downsampled tier
+    pub downsampled: bool,
+}
+
+/// Aggregate over a time range
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u64,
+}
+
+/// Two-tier time series for a single metric
+#[derive(Debug, Clone, Default)]
+pub struct TimeSeries {
+    raw: VecDeque<Sample>,
+    buckets: VecDeque<Bucket>,
+}
+
+impl TimeSeries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a sample (samples are expected in timestamp order)
+    pub fn push(&mut self, sample: Sample) {
+        self.raw.push_back(sample);
+    }
+
+    pub fn raw_len(&self) -> usize {
+        self.raw.len()
+    }
+
+    pub fn bucket_len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Downsample raw samples past the raw window and drop expired data
+    pub fn compact(&mut self, now: DateTime<Utc>, policy: &RetentionPolicy) {
+        let raw_cutoff = now - policy.raw_retention;
+        let max_cutoff = now - policy.max_age;
+        let width = policy.bucket_width.num_milliseconds().max(1);
+
+        while let Some(sample) = self.raw.front().copied() {
+            if sample.timestamp >= raw_cutoff {
+                break;
+            }
+            self.raw.pop_front();
+
+            if sample.timestamp < max_cutoff {
+                continue;
+            }
+
+            let ts = sample.timestamp.timestamp_millis();
+            let start_ms = ts - ts.rem_euclid(width);
+            let start = DateTime::<Utc>::from_timestamp_millis(start_ms)
+                .unwrap_or(sample.timestamp);
+
+            match self.buckets.back_mut() {
+                Some(bucket) if bucket.start == start => bucket.add(sample.value),
+                _ => self.buckets.push_back(Bucket::from_sample(start, sample.value)),
+            }
+        }
+
+        while let Some(bucket) = self.buckets.front() {
+            if bucket.start + policy.bucket_width > max_cutoff {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Return all points in `[from, to]`, oldest first, across both tiers
+    pub fn query(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<SeriesPoint> {
+        let downsampled = self.buckets.iter()
+            .filter(|b| b.start >= from && b.start <= to)
+            .map(|b| SeriesPoint {
+                timestamp: b.start,
+                min: b.min,
+                max: b.max,
+                avg: b.avg(),
+                count: b.count,
+                downsampled: true,
+            });
+
+        let raw = self.raw.iter()
+            .filter(|s| s.timestamp >= from && s.timestamp <= to)
+            .map(|s| SeriesPoint {
+                timestamp: s.timestamp,
+                min: s.value,
+                max: s.value,
+                avg: s.value,
+                count: 1,
+                downsampled: false,
+            });
+
+        downsampled.chain(raw).collect()
+    }
+
+    /// Aggregate min/max/avg over `[from, to]`, weighting buckets by sample count
+    pub fn aggregate(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<Aggregate> {
+        let points = self.query(from, to);
+        if points.is_empty() {
+            return None;
+        }
+
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut sum = 0.0;
+        let mut count = 0u64;
+
+        for point in &points {
+            min = min.min(point.min);
+            max = max.max(point.max);
+            sum += point.avg * point.count as f64;
+            count += point.count;
+        }
+
+        Some(Aggregate {
+            min,
+            max,
+            avg: sum / count as f64,
+            count,
+        })
+    }
+}
+
+/// Named collection of retained time series sharing one policy
+#[derive(Debug, Clone, Default)]
+pub struct MetricStore {
+    policy: RetentionPolicy,
+    series: HashMap<String, TimeSeries>,
+}
+
+impl MetricStore {
+    pub fn new(policy: RetentionPolicy) -> Self {
+        Self {
+            policy,
+            series: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    /// Record a sample for the named metric
+    pub fn record(&mut self, name: &str, timestamp: DateTime<Utc>, value: f64) {
+        self.series
+            .entry(name.to_string())
+            .or_default()
+            .push(Sample { timestamp, value });
+    }
+
+    /// Apply the retention policy to every series
+    pub fn compact(&mut self, now: DateTime<Utc>) {
+        let policy = self.policy.clone();
+        for series in self.series.values_mut() {
+            series.compact(now, &policy);
+        }
+        self.series.retain(|_, s| s.raw_len() > 0 || s.bucket_len() > 0);
+    }
+
+    pub fn series(&self, name: &str) -> Option<&TimeSeries> {
+        self.series.get(name)
+    }
+
+    pub fn query(&self, name: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<SeriesPoint> {
+        self.series
+            .get(name)
+            .map(|s| s.query(from, to))
+            .unwrap_or_default()
+    }
+
+    pub fn aggregate(&self, name: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<Aggregate> {
+        self.series.get(name).and_then(|s| s.aggregate(from, to))
+    }
+
+    pub fn metric_names(&self) -> Vec<String> {
+        self.series.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy {
+            raw_retention: Duration::hours(1),
+            bucket_width: Duration::minutes(5),
+            max_age: Duration::days(7),
+        }
+    }
+
+    #[test]
+    fn test_long_series_is_compacted() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_100, 0).unwrap();
+        let start = now - Duration::hours(24);
+        let mut series = TimeSeries::new();
+
+        // 24 hours of 10 second samples following a sawtooth
+        let total = 24 * 360;
+        let mut true_sum = 0.0;
+        for i in 0..total {
+            let value = (i % 100) as f64;
+            true_sum += value;
+            series.push(Sample {
+                timestamp: start + Duration::seconds(i as i64 * 10),
+                value,
+            });
+        }
+
+        series.compact(now, &policy());
+
+        assert_eq!(series.raw_len(), 360);
+        assert_eq!(series.bucket_len(), 23 * 12);
+
+        let agg = series.aggregate(start, now).unwrap();
+        assert_eq!(agg.count, total as u64);
+        assert_eq!(agg.min, 0.0);
+        assert_eq!(agg.max, 99.0);
+        let true_avg = true_sum / total as f64;
+        assert!((agg.avg - true_avg).abs() < 0.01);
+
+        // The query straddles both tiers
+        let points = series.query(now - Duration::hours(2), now);
+        assert!(points.iter().any(|p| p.downsampled));
+        assert!(points.iter().any(|p| !p.downsampled));
+        assert!(points.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+
+    #[test]
+    fn test_data_past_max_age_is_dropped() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_100, 0).unwrap();
+        let mut store = MetricStore::new(policy());
+
+        store.record("cpu", now - Duration::days(10), 50.0);
+        store.record("cpu", now - Duration::days(3), 20.0);
+        store.record("cpu", now - Duration::minutes(1), 40.0);
+        store.record("stale", now - Duration::days(9), 1.0);
+
+        store.compact(now);
+
+        let agg = store.aggregate("cpu", now - Duration::days(30), now).unwrap();
+        assert_eq!(agg.count, 2);
+        assert_eq!(agg.min, 20.0);
+        assert_eq!(agg.max, 40.0);
+        assert!(store.series("stale").is_none());
+    }
+}
@@ -0,0 +1,248 @@
+//! Alert Silences
+//!
+//! Silences mute notifications for matching alerts, so maintenance windows
+//! don't page anyone. When backed by a file, active silences are persisted
+//! on every change and survive a restart.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::alerts::FiredAlert;
+
+/// Rule name matching every alert rule
+pub const ANY_RULE: &str = "*";
+
+/// Silence muting notifications for matching alerts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Silence {
+    pub id: String,
+
+    /// Alert rule name, or [`ANY_RULE`]
+    pub rule: String,
+
+    /// Labels the alert must carry, all of which must match
+    pub matchers: HashMap<String, String>,
+
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Silence {
+    /// Whether the silence is in effect at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        now < self.expires_at
+    }
+
+    /// Whether the silence covers `alert`
+    pub fn matches(&self, alert: &FiredAlert) -> bool {
+        if self.rule != ANY_RULE && self.rule != alert.rule_name {
+            return false;
+        }
+
+        let labels = alert.labels();
+        self.matchers
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+/// Set of silences, optionally persisted to a JSON file
+pub struct SilenceStore {
+    silences: RwLock<Vec<Silence>>,
+    path: Option<PathBuf>,
+}
+
+impl SilenceStore {
+    /// In-memory store
+    pub fn new() -> Self {
+        Self {
+            silences: RwLock::new(Vec::new()),
+            path: None,
+        }
+    }
+
+    /// Store persisted at `path`, restoring the silences still active
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let silences = if path.exists() {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("Failed to read silences from {}", path.display()))?;
+            let mut silences: Vec<Silence> = serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid silence file {}", path.display()))?;
+
+            let now = Utc::now();
+            silences.retain(|s| s.is_active(now));
+            tracing::info!("Restored {} active silences from {}", silences.len(), path.display());
+            silences
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            silences: RwLock::new(silences),
+            path: Some(path),
+        })
+    }
+
+    /// Silence alerts of `rule` carrying the `matchers` labels for `duration`
+    pub async fn add(
+        &self,
+        rule: &str,
+        duration: Duration,
+        matchers: HashMap<String, String>,
+    ) -> Result<Silence> {
+        if rule.trim().is_empty() {
+            return Err(anyhow::anyhow!("Silences require a rule name or \"{}\"", ANY_RULE));
+        }
+
+        let now = Utc::now();
+        let silence = Silence {
+            id: uuid::Uuid::new_v4().to_string(),
+            rule: rule.to_string(),
+            matchers,
+            created_at: now,
+            expires_at: now + chrono::Duration::from_std(duration)
+                .context("Silence duration out of range")?,
+        };
+
+        let mut silences = self.silences.write().await;
+        silences.push(silence.clone());
+        self.save(&silences)?;
+
+        tracing::info!(
+            "Silenced {} {:?} until {}",
+            silence.rule,
+            silence.matchers,
+            silence.expires_at.to_rfc3339()
+        );
+        Ok(silence)
+    }
+
+    /// Remove a silence by ID
+    pub async fn remove(&self, silence_id: &str) -> Result<()> {
+        let mut silences = self.silences.write().await;
+
+        let pos = silences.iter().position(|s| s.id == silence_id)
+            .ok_or_else(|| anyhow::anyhow!("Silence {} not found", silence_id))?;
+        silences.remove(pos);
+        self.save(&silences)
+    }
+
+    /// All silences, including expired ones not yet purged
+    pub async fn list(&self) -> Vec<Silence> {
+        self.silences.read().await.clone()
+    }
+
+    /// First silence in effect at `now` covering `alert`
+    pub async fn find_match(&self, alert: &FiredAlert, now: DateTime<Utc>) -> Option<Silence> {
+        self.silences.read().await
+            .iter()
+            .find(|s| s.is_active(now) && s.matches(alert))
+            .cloned()
+    }
+
+    /// Drop expired silences
+    pub async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut silences = self.silences.write().await;
+        let before = silences.len();
+        silences.retain(|s| s.is_active(now));
+
+        let purged = before - silences.len();
+        if purged > 0 {
+            self.save(&silences)?;
+        }
+        Ok(purged)
+    }
+
+    fn save(&self, silences: &[Silence]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // Write to a temporary file and rename so a crash never leaves a
+        // truncated file behind
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(silences)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl Default for SilenceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertSeverity;
+
+    fn alert(rule: &str, details: &[(&str, &str)]) -> FiredAlert {
+        FiredAlert::new(
+            rule,
+            AlertSeverity::Warning,
+            "test alert",
+            details.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        )
+    }
+
+    fn matchers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_silence_matching() {
+        let store = SilenceStore::new();
+        store.add("InterfaceDown", Duration::from_secs(3600), matchers(&[("interface", "wan0")]))
+            .await.unwrap();
+        store.add(ANY_RULE, Duration::from_secs(3600), matchers(&[("severity", "info")]))
+            .await.unwrap();
+
+        let now = Utc::now();
+        assert!(store.find_match(&alert("InterfaceDown", &[("interface", "wan0")]), now).await.is_some());
+        assert!(store.find_match(&alert("InterfaceDown", &[("interface", "lan0")]), now).await.is_none());
+        assert!(store.find_match(&alert("HighCpuUsage", &[("interface", "wan0")]), now).await.is_none());
+
+        let mut info = alert("HighCpuUsage", &[]);
+        info.severity = AlertSeverity::Info;
+        assert!(store.find_match(&info, now).await.is_some());
+
+        // Silences lapse once they expire
+        let later = now + chrono::Duration::hours(2);
+        assert!(store.find_match(&alert("InterfaceDown", &[("interface", "wan0")]), later).await.is_none());
+        assert_eq!(store.purge_expired(later).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_silences_persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("silences.json");
+
+        let store = SilenceStore::open(&path).unwrap();
+        let kept = store.add("DiskSpaceCritical", Duration::from_secs(3600), HashMap::new())
+            .await.unwrap();
+        let removed = store.add("HighCpuUsage", Duration::from_secs(3600), HashMap::new())
+            .await.unwrap();
+        store.remove(&removed.id).await.unwrap();
+        assert!(store.remove(&removed.id).await.is_err());
+
+        let restored = SilenceStore::open(&path).unwrap();
+        assert_eq!(restored.list().await, vec![kept]);
+    }
+
+    #[tokio::test]
+    async fn test_silence_requires_rule() {
+        let store = SilenceStore::new();
+        assert!(store.add(" ", Duration::from_secs(60), HashMap::new()).await.is_err());
+    }
+}
@@ -0,0 +1,1135 @@
+//! SNMP v2c/v3 agent
+
+use super::ber::{Oid, Value};
+use super::mib::{
+    HostSample, MibSource, MibView, SdwanTunnelStatus, SystemInfo, DEFAULT_ENTERPRISE_NUMBER,
+    SYS_UPTIME,
+};
+use super::pdu::{
+    self, CommunityMessage, Pdu, PduType, ScopedPdu, ScopedPduData, UsmParams, V3Message, VarBind,
+    FLAG_AUTH, FLAG_PRIV, NOT_WRITABLE, TOO_BIG, VERSION_2C, VERSION_3,
+};
+use super::usm::{AuthProtocol, UsmUser, MIN_PASSWORD_LEN, TIME_WINDOW};
+use crate::status::GatewayHealth;
+use anyhow::{bail, Context, Result};
+use ipnetwork::IpNetwork;
+use patronus_core::types::{InterfaceStats, InterfaceStatsProvider};
+use patronus_secrets::SecretManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Upper bound on variable bindings produced by one GETBULK
+const MAX_BULK_VARBINDS: usize = 1024;
+
+/// Room for the sequence headers growing when varbinds are added
+const LENGTH_SLACK: usize = 8;
+
+// usmStats counters reported back to managers (RFC 3414 section 5)
+const USM_STATS: [u32; 9] = [1, 3, 6, 1, 6, 3, 15, 1, 1];
+const UNSUPPORTED_SEC_LEVELS: u32 = 1;
+const NOT_IN_TIME_WINDOWS: u32 = 2;
+const UNKNOWN_USER_NAMES: u32 = 3;
+const UNKNOWN_ENGINE_IDS: u32 = 4;
+const WRONG_DIGESTS: u32 = 5;
+const DECRYPTION_ERRORS: u32 = 6;
+
+/// Where snmpEngineBoots is kept across restarts by default
+pub const DEFAULT_ENGINE_BOOTS_PATH: &str = "/var/lib/patronus/snmp/engine-boots";
+
+/// snmpEngineBoots stops here; the engine then needs a new engine ID
+/// (RFC 3414 section 2.2.2)
+const MAX_ENGINE_BOOTS: u32 = 2_147_483_647;
+
+/// v2c community allowed from one source network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommunityAccess {
+    pub community: String,
+    pub source: IpNetwork,
+}
+
+/// v3 user whose passwords are held in the secret store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsmUserConfig {
+    pub name: String,
+    pub auth_protocol: AuthProtocol,
+
+    /// Secret key of the authentication password
+    pub auth_secret: String,
+
+    /// Secret key of the privacy password
+    pub priv_secret: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnmpConfig {
+    pub listen: SocketAddr,
+    pub communities: Vec<CommunityAccess>,
+    pub users: Vec<UsmUserConfig>,
+    pub sys_descr: String,
+    pub sys_contact: String,
+    pub sys_name: String,
+    pub sys_location: String,
+
+    /// IANA private enterprise number rooting the Patronus subtree
+    pub enterprise_number: u32,
+
+    /// snmpEngineID; derived from the enterprise number and sysName if unset
+    pub engine_id: Option<Vec<u8>>,
+
+    /// File snmpEngineBoots is persisted in, incremented on every start;
+    /// without one `engine_boots` is used as is
+    #[serde(default)]
+    pub engine_boots_path: Option<PathBuf>,
+
+    /// snmpEngineBoots when no `engine_boots_path` is set
+    pub engine_boots: u32,
+
+    pub max_message_size: usize,
+}
+
+impl Default for SnmpConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 161)),
+            communities: Vec::new(),
+            users: Vec::new(),
+            sys_descr: format!("Patronus Firewall {}", env!("CARGO_PKG_VERSION")),
+            sys_contact: String::new(),
+            sys_name: System::host_name().unwrap_or_else(|| "patronus".to_string()),
+            sys_location: String::new(),
+            enterprise_number: DEFAULT_ENTERPRISE_NUMBER,
+            engine_id: None,
+            engine_boots_path: Some(PathBuf::from(DEFAULT_ENGINE_BOOTS_PATH)),
+            engine_boots: 1,
+            max_message_size: 65507,
+        }
+    }
+}
+
+/// Agent counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SnmpStats {
+    pub in_packets: u64,
+    pub bad_versions: u64,
+    pub parse_errors: u64,
+    pub bad_community_names: u64,
+    pub unknown_engine_ids: u64,
+    pub unknown_user_names: u64,
+    pub unsupported_sec_levels: u64,
+    pub wrong_digests: u64,
+    pub not_in_time_windows: u64,
+    pub decryption_errors: u64,
+}
+
+/// Data the MIB view is rebuilt from
+#[derive(Default)]
+struct MibData {
+    interfaces: Vec<InterfaceStats>,
+    tunnels: Vec<(u32, SdwanTunnelStatus)>,
+    gateways: Vec<(u32, GatewayHealth)>,
+
+    // Row indexes stay with a name for the agent's lifetime
+    tunnel_index: HashMap<String, u32>,
+    gateway_index: HashMap<String, u32>,
+}
+
+pub struct SnmpAgent {
+    config: SnmpConfig,
+    engine_id: Vec<u8>,
+    engine_boots: u32,
+    users: HashMap<Vec<u8>, UsmUser>,
+    communities: RwLock<Vec<CommunityAccess>>,
+    started: Instant,
+    data: Mutex<MibData>,
+    view: RwLock<Arc<MibView>>,
+    stats: Mutex<SnmpStats>,
+    salt: AtomicU64,
+}
+
+impl SnmpAgent {
+    /// Create an agent, resolving v3 passwords from the secret store
+    pub async fn new(config: SnmpConfig, secrets: &SecretManager) -> Result<Self> {
+        let engine_id = match &config.engine_id {
+            Some(id) if !(5..=32).contains(&id.len()) => {
+                bail!("SNMP engine ID must be 5 to 32 octets, got {}", id.len())
+            }
+            Some(id) => id.clone(),
+            None => default_engine_id(config.enterprise_number, &config.sys_name),
+        };
+        let engine_boots = match &config.engine_boots_path {
+            Some(path) => next_engine_boots(path, &engine_id)?,
+            None => config.engine_boots,
+        };
+        info!("SNMP engine {} starting, boot {}", hex::encode(&engine_id), engine_boots);
+
+        let mut users = HashMap::new();
+        for user in &config.users {
+            let auth = resolve_password(secrets, &user.name, &user.auth_secret).await?;
+            let privacy = resolve_password(secrets, &user.name, &user.priv_secret).await?;
+            let localized = UsmUser::new(&user.name, user.auth_protocol, &auth, &privacy, &engine_id);
+            users.insert(localized.name.clone(), localized);
+        }
+
+        let agent = Self {
+            communities: RwLock::new(config.communities.clone()),
+            config,
+            engine_id,
+            engine_boots,
+            users,
+            started: Instant::now(),
+            data: Mutex::new(MibData::default()),
+            view: RwLock::new(Arc::new(MibView::default())),
+            stats: Mutex::new(SnmpStats::default()),
+            salt: AtomicU64::new(uuid::Uuid::new_v4().as_u64_pair().0),
+        };
+        agent.rebuild(&agent.data.lock().unwrap());
+
+        Ok(agent)
+    }
+
+    pub fn engine_id(&self) -> &[u8] {
+        &self.engine_id
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.engine_boots
+    }
+
+    pub fn stats(&self) -> SnmpStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Current MIB snapshot
+    pub fn view(&self) -> Arc<MibView> {
+        self.view.read().unwrap().clone()
+    }
+
+    /// Replace the v2c communities and the sources allowed to use them
+    pub fn set_communities(&self, communities: Vec<CommunityAccess>) {
+        *self.communities.write().unwrap() = communities;
+    }
+
+    pub fn update_interfaces(&self, mut interfaces: Vec<InterfaceStats>) {
+        interfaces.sort_by_key(|iface| iface.index);
+        let mut data = self.data.lock().unwrap();
+        data.interfaces = interfaces;
+        self.rebuild(&data);
+    }
+
+    pub fn update_tunnels(&self, tunnels: Vec<SdwanTunnelStatus>) {
+        let mut data = self.data.lock().unwrap();
+        let rows = index_rows(&mut data.tunnel_index, tunnels, |t| &t.name);
+        data.tunnels = rows;
+        self.rebuild(&data);
+    }
+
+    pub fn update_gateways(&self, gateways: Vec<GatewayHealth>) {
+        let mut data = self.data.lock().unwrap();
+        let rows = index_rows(&mut data.gateway_index, gateways, |g| &g.name);
+        data.gateways = rows;
+        self.rebuild(&data);
+    }
+
+    /// Refresh interface counters from `provider` every `interval`
+    pub fn spawn_interface_poller(
+        self: &Arc<Self>,
+        provider: Arc<dyn InterfaceStatsProvider>,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let agent = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match provider.interface_stats().await {
+                    Ok(stats) => agent.update_interfaces(stats),
+                    Err(e) => warn!("Failed to poll interface statistics: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Bind the configured address and answer requests until an I/O error
+    pub async fn serve(self: Arc<Self>) -> Result<()> {
+        let socket = UdpSocket::bind(self.config.listen)
+            .await
+            .with_context(|| format!("Failed to bind SNMP agent to {}", self.config.listen))?;
+        info!("SNMP agent listening on {}", self.config.listen);
+        self.serve_on(socket).await
+    }
+
+    pub async fn serve_on(self: Arc<Self>, socket: UdpSocket) -> Result<()> {
+        let mut buf = vec![0u8; 65535];
+        loop {
+            let (len, src) = socket.recv_from(&mut buf).await?;
+            if let Some(response) = self.handle_message(&buf[..len], src) {
+                if let Err(e) = socket.send_to(&response, src).await {
+                    warn!("Failed to send SNMP response to {}: {}", src, e);
+                }
+            }
+        }
+    }
+
+    /// Process one datagram, returning the response to send if any
+    pub fn handle_message(&self, data: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        self.count(|s| s.in_packets += 1);
+        match pdu::message_version(data) {
+            Ok(VERSION_2C) => self.handle_community(data, src),
+            Ok(VERSION_3) => self.handle_v3(data),
+            Ok(version) => {
+                debug!("Dropping SNMP version {} message from {}", version, src);
+                self.count(|s| s.bad_versions += 1);
+                None
+            }
+            Err(_) => {
+                self.count(|s| s.parse_errors += 1);
+                None
+            }
+        }
+    }
+
+    fn handle_community(&self, data: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        let Ok(request) = pdu::decode_community(data) else {
+            self.count(|s| s.parse_errors += 1);
+            return None;
+        };
+
+        let source = src.ip().to_canonical();
+        let allowed = self.communities.read().unwrap().iter().any(|access| {
+            access.source.contains(source) && constant_time_eq(access.community.as_bytes(), &request.community)
+        });
+        if !allowed {
+            debug!("Rejected SNMP community from {}", src);
+            self.count(|s| s.bad_community_names += 1);
+            return None;
+        }
+
+        let envelope = |pdu: Pdu| CommunityMessage { community: request.community.clone(), pdu }.encode();
+        let overhead = envelope(Pdu::new(PduType::Response, request.pdu.request_id, Vec::new())).len();
+        let budget = self.config.max_message_size.saturating_sub(overhead + LENGTH_SLACK);
+
+        self.respond(&request.pdu, budget).map(envelope)
+    }
+
+    fn handle_v3(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let Ok(msg) = pdu::decode_v3(data) else {
+            self.count(|s| s.parse_errors += 1);
+            return None;
+        };
+
+        // Plaintext request ID, known only for unencrypted requests
+        let request_id = match &msg.data {
+            ScopedPduData::Plain(scoped) => scoped.pdu.request_id,
+            ScopedPduData::Encrypted(_) => 0,
+        };
+
+        if msg.usm.engine_id != self.engine_id {
+            // Also the discovery path of RFC 3414 section 4
+            let count = self.count(|s| {
+                s.unknown_engine_ids += 1;
+                s.unknown_engine_ids
+            });
+            return Some(self.report(&msg, request_id, UNKNOWN_ENGINE_IDS, count, None));
+        }
+
+        let Some(user) = self.users.get(&msg.usm.user_name) else {
+            let count = self.count(|s| {
+                s.unknown_user_names += 1;
+                s.unknown_user_names
+            });
+            return Some(self.report(&msg, request_id, UNKNOWN_USER_NAMES, count, None));
+        };
+
+        if msg.flags & (FLAG_AUTH | FLAG_PRIV) != FLAG_AUTH | FLAG_PRIV {
+            let count = self.count(|s| {
+                s.unsupported_sec_levels += 1;
+                s.unsupported_sec_levels
+            });
+            return Some(self.report(&msg, request_id, UNSUPPORTED_SEC_LEVELS, count, None));
+        }
+
+        if !user.auth.verify(&user.auth_key, data, msg.auth_offset, &msg.usm.auth_params) {
+            let count = self.count(|s| {
+                s.wrong_digests += 1;
+                s.wrong_digests
+            });
+            return Some(self.report(&msg, request_id, WRONG_DIGESTS, count, None));
+        }
+
+        let (boots, time) = self.engine_clock();
+        if msg.usm.engine_boots != boots || msg.usm.engine_time.abs_diff(time) > TIME_WINDOW {
+            let count = self.count(|s| {
+                s.not_in_time_windows += 1;
+                s.not_in_time_windows
+            });
+            return Some(self.report(&msg, request_id, NOT_IN_TIME_WINDOWS, count, Some(user)));
+        }
+
+        let scoped = match &msg.data {
+            ScopedPduData::Encrypted(ciphertext) => user
+                .decrypt(msg.usm.engine_boots, msg.usm.engine_time, &msg.usm.priv_params, ciphertext)
+                .and_then(|plain| ScopedPdu::decode(&plain).ok()),
+            ScopedPduData::Plain(_) => None,
+        };
+        let Some(scoped) = scoped else {
+            let count = self.count(|s| {
+                s.decryption_errors += 1;
+                s.decryption_errors
+            });
+            return Some(self.report(&msg, request_id, DECRYPTION_ERRORS, count, Some(user)));
+        };
+
+        let max_size = (msg.max_size.max(0) as usize).min(self.config.max_message_size);
+        let envelope = |pdu: Pdu| {
+            let scoped = ScopedPdu {
+                context_engine_id: self.engine_id.clone(),
+                context_name: scoped.context_name.clone(),
+                pdu,
+            };
+            let (ciphertext, priv_params) =
+                user.encrypt(boots, time, self.salt.fetch_add(1, Ordering::Relaxed), &scoped.encode());
+            self.encode_v3(&msg, FLAG_AUTH | FLAG_PRIV, Some(user), priv_params, ScopedPduData::Encrypted(ciphertext))
+        };
+        let overhead = envelope(Pdu::new(PduType::Response, scoped.pdu.request_id, Vec::new())).len();
+        let budget = max_size.saturating_sub(overhead + LENGTH_SLACK);
+
+        self.respond(&scoped.pdu, budget).map(envelope)
+    }
+
+    /// Report PDU carrying a usmStats counter, authenticated when `user` is set
+    fn report(&self, msg: &V3Message, request_id: i64, counter: u32, value: u64, user: Option<&UsmUser>) -> Vec<u8> {
+        let varbind = (Oid::new(&USM_STATS).child(&[counter, 0]), Value::Counter32(value as u32));
+        let scoped = ScopedPdu {
+            context_engine_id: self.engine_id.clone(),
+            context_name: Vec::new(),
+            pdu: Pdu::new(PduType::Report, request_id, vec![varbind]),
+        };
+        let flags = if user.is_some() { FLAG_AUTH } else { 0 };
+        self.encode_v3(msg, flags, user, Vec::new(), ScopedPduData::Plain(scoped))
+    }
+
+    fn encode_v3(
+        &self,
+        request: &V3Message,
+        flags: u8,
+        user: Option<&UsmUser>,
+        priv_params: Vec<u8>,
+        data: ScopedPduData,
+    ) -> Vec<u8> {
+        let (boots, time) = self.engine_clock();
+        let response = V3Message {
+            msg_id: request.msg_id,
+            max_size: self.config.max_message_size as i64,
+            flags,
+            usm: UsmParams {
+                engine_id: self.engine_id.clone(),
+                engine_boots: boots,
+                engine_time: time,
+                user_name: request.usm.user_name.clone(),
+                auth_params: user.map(|u| vec![0; u.auth.mac_len()]).unwrap_or_default(),
+                priv_params,
+            },
+            data,
+            auth_offset: 0,
+        };
+
+        let (mut out, auth_offset) = response.encode();
+        if let Some(user) = user {
+            user.auth.authenticate(&user.auth_key, &mut out, auth_offset);
+        }
+        out
+    }
+
+    /// Answer a request PDU from a single MIB snapshot
+    fn respond(&self, request: &Pdu, budget: usize) -> Option<Pdu> {
+        let view = self.view();
+        let uptime = Oid::new(&SYS_UPTIME);
+        let ticks = (self.started.elapsed().as_millis() / 10) as u32;
+        let resolve = |oid: &Oid, value: &Value| {
+            if *oid == uptime {
+                Value::TimeTicks(ticks)
+            } else {
+                value.clone()
+            }
+        };
+        let next = |oid: &Oid| match view.next(oid) {
+            Some((next, value)) => (next.clone(), resolve(next, value)),
+            None => (oid.clone(), Value::EndOfMibView),
+        };
+
+        let mut response = Pdu::new(PduType::Response, request.request_id, Vec::new());
+        match request.pdu_type {
+            PduType::Get => {
+                for (oid, _) in &request.varbinds {
+                    let value = match view.get(oid) {
+                        Some(value) => resolve(oid, value),
+                        None => missing(&view, oid),
+                    };
+                    response.varbinds.push((oid.clone(), value));
+                }
+            }
+            PduType::GetNext => {
+                response.varbinds = request.varbinds.iter().map(|(oid, _)| next(oid)).collect();
+            }
+            PduType::GetBulk => {
+                let non_repeaters = request.error_status.clamp(0, request.varbinds.len() as i64) as usize;
+                let (fixed, repeated) = request.varbinds.split_at(non_repeaters);
+                let cap = MAX_BULK_VARBINDS.saturating_sub(non_repeaters) / repeated.len().max(1);
+                let repetitions = (request.error_index.max(0) as usize).min(cap);
+
+                response.varbinds = fixed.iter().map(|(oid, _)| next(oid)).collect();
+                let mut cursors: Vec<Oid> = repeated.iter().map(|(oid, _)| oid.clone()).collect();
+                for _ in 0..repetitions {
+                    let row: Vec<VarBind> = cursors.iter().map(next).collect();
+                    let done = row.iter().all(|(_, value)| *value == Value::EndOfMibView);
+                    cursors = row.iter().map(|(oid, _)| oid.clone()).collect();
+                    response.varbinds.extend(row);
+                    if done {
+                        break;
+                    }
+                }
+
+                // Drop trailing bindings that would not fit (RFC 3416 4.2.3)
+                let mut used = 0;
+                let fitting = response
+                    .varbinds
+                    .iter()
+                    .take_while(|(oid, value)| {
+                        used += pdu::varbind_len(oid, value);
+                        used <= budget
+                    })
+                    .count();
+                response.varbinds.truncate(fitting);
+                return Some(response);
+            }
+            PduType::Set => {
+                response.error_status = NOT_WRITABLE;
+                response.error_index = 1;
+                response.varbinds = request.varbinds.clone();
+                return Some(response);
+            }
+            _ => return None,
+        }
+
+        let size: usize = response.varbinds.iter().map(|(oid, value)| pdu::varbind_len(oid, value)).sum();
+        if size > budget {
+            response.error_status = TOO_BIG;
+            response.error_index = 0;
+            response.varbinds.clear();
+        }
+        Some(response)
+    }
+
+    fn rebuild(&self, data: &MibData) {
+        let system = SystemInfo {
+            descr: self.config.sys_descr.clone(),
+            contact: self.config.sys_contact.clone(),
+            name: self.config.sys_name.clone(),
+            location: self.config.sys_location.clone(),
+        };
+        let load = System::load_average();
+        let view = MibSource {
+            enterprise: self.config.enterprise_number,
+            system: &system,
+            host: HostSample { uptime_secs: System::uptime(), load: [load.one, load.five, load.fifteen] },
+            interfaces: &data.interfaces,
+            tunnels: &data.tunnels,
+            gateways: &data.gateways,
+        }
+        .build();
+
+        *self.view.write().unwrap() = Arc::new(view);
+    }
+
+    fn engine_clock(&self) -> (u32, u32) {
+        (self.engine_boots, self.started.elapsed().as_secs() as u32)
+    }
+
+    fn count<T>(&self, f: impl FnOnce(&mut SnmpStats) -> T) -> T {
+        f(&mut self.stats.lock().unwrap())
+    }
+}
+
+/// noSuchInstance inside a known object, noSuchObject otherwise
+fn missing(view: &MibView, oid: &Oid) -> Value {
+    let arcs = oid.arcs();
+    let parent = Oid::new(&arcs[..arcs.len().saturating_sub(1)]);
+    match view.next(&parent) {
+        Some((next, _)) if arcs.len() > 1 && next.starts_with(&parent) => Value::NoSuchInstance,
+        _ => Value::NoSuchObject,
+    }
+}
+
+/// Assign stable row indexes by name and order rows by index
+fn index_rows<T>(indexes: &mut HashMap<String, u32>, rows: Vec<T>, name: impl Fn(&T) -> &String) -> Vec<(u32, T)> {
+    let mut indexed: Vec<(u32, T)> = rows
+        .into_iter()
+        .map(|row| {
+            let next = indexes.values().max().copied().unwrap_or(0) + 1;
+            let index = *indexes.entry(name(&row).clone()).or_insert(next);
+            (index, row)
+        })
+        .collect();
+    indexed.sort_by_key(|(index, _)| *index);
+    indexed
+}
+
+/// RFC 3411 format: enterprise number with the high bit set, then text
+fn default_engine_id(enterprise: u32, name: &str) -> Vec<u8> {
+    let name = if name.is_empty() { "patronus" } else { name };
+    let mut id = (enterprise | 0x8000_0000).to_be_bytes().to_vec();
+    id.push(4);
+    id.extend(name.bytes().take(27));
+    id
+}
+
+/// Increment the snmpEngineBoots persisted for `engine_id` and return it
+///
+/// The file holds the engine ID and its boot count; a new engine ID starts
+/// counting again from 1. An unreadable file is an error rather than a
+/// reset, since reusing a boot count lets old messages be replayed.
+fn next_engine_boots(path: &Path, engine_id: &[u8]) -> Result<u32> {
+    let engine = hex::encode(engine_id);
+    let previous = match std::fs::read_to_string(path) {
+        Ok(state) => {
+            let mut fields = state.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(id), Some(boots)) if id == engine => boots
+                    .parse::<u32>()
+                    .with_context(|| format!("Invalid snmpEngineBoots in {}", path.display()))?,
+                (Some(_), Some(_)) => 0,
+                _ => bail!("Invalid SNMP engine state in {}", path.display()),
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    let boots = previous.saturating_add(1).min(MAX_ENGINE_BOOTS);
+    if boots == MAX_ENGINE_BOOTS {
+        warn!("snmpEngineBoots reached its maximum; configure a new engine ID");
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let staging = path.with_extension("tmp");
+    std::fs::write(&staging, format!("{} {}\n", engine, boots))
+        .and_then(|_| std::fs::rename(&staging, path))
+        .with_context(|| format!("Failed to persist snmpEngineBoots to {}", path.display()))?;
+    Ok(boots)
+}
+
+async fn resolve_password(secrets: &SecretManager, user: &str, key: &str) -> Result<String> {
+    let secret = secrets
+        .get_secret(key)
+        .await?
+        .with_context(|| format!("Secret '{}' for SNMP user '{}' not found", key, user))?;
+    if secret.expose_secret().len() < MIN_PASSWORD_LEN {
+        bail!("SNMP password '{}' for user '{}' is shorter than {} characters", key, user, MIN_PASSWORD_LEN);
+    }
+    Ok(secret.expose_secret().to_string())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snmp::mib::TunnelState;
+    use crate::snmp::pdu::FLAG_REPORTABLE;
+    use patronus_secrets::{MemoryStore, SecretString, SecretStore};
+
+    const WALK_ROOT: [u32; 2] = [1, 3];
+
+    /// Minimal SNMP manager speaking v2c or v3 authPriv over UDP
+    struct Manager {
+        socket: UdpSocket,
+        agent: SocketAddr,
+        security: Security,
+        next_id: i64,
+    }
+
+    enum Security {
+        Community(&'static str),
+        Usm { user: &'static str, auth: &'static str, privacy: &'static str, session: Option<Session> },
+    }
+
+    struct Session {
+        keys: UsmUser,
+        engine_id: Vec<u8>,
+        boots: u32,
+        time: u32,
+    }
+
+    impl Manager {
+        async fn connect(agent: SocketAddr, security: Security) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut manager = Self { socket, agent, security, next_id: 1 };
+            if matches!(manager.security, Security::Usm { .. }) {
+                manager.discover().await;
+            }
+            manager
+        }
+
+        async fn exchange(&self, request: &[u8]) -> Vec<u8> {
+            self.socket.send_to(request, self.agent).await.unwrap();
+            let mut buf = vec![0u8; 65535];
+            let (len, _) = tokio::time::timeout(Duration::from_secs(5), self.socket.recv_from(&mut buf))
+                .await
+                .expect("agent did not answer")
+                .unwrap();
+            buf.truncate(len);
+            buf
+        }
+
+        /// RFC 3414 section 4 discovery: learn the engine ID, then its clock
+        async fn discover(&mut self) {
+            let probe = V3Message {
+                msg_id: 1,
+                max_size: 65507,
+                flags: FLAG_REPORTABLE,
+                usm: UsmParams::default(),
+                data: ScopedPduData::Plain(ScopedPdu {
+                    context_engine_id: Vec::new(),
+                    context_name: Vec::new(),
+                    pdu: Pdu::new(PduType::Get, 1, Vec::new()),
+                }),
+                auth_offset: 0,
+            };
+            let report = pdu::decode_v3(&self.exchange(&probe.encode().0).await).unwrap();
+            let engine_id = report.usm.engine_id;
+
+            let Security::Usm { user, auth, privacy, session } = &mut self.security else {
+                unreachable!()
+            };
+            *session = Some(Session {
+                keys: UsmUser::new(user, AuthProtocol::Sha256, auth, privacy, &engine_id),
+                engine_id,
+                boots: 0,
+                time: 0,
+            });
+
+            // An authenticated request out of the time window returns the clock
+            let response = self.send_v3(Pdu::new(PduType::Get, 0, Vec::new())).await;
+            assert_eq!(response.pdu_type, PduType::Report);
+        }
+
+        async fn send_v3(&mut self, request: Pdu) -> Pdu {
+            let Security::Usm { session: Some(session), .. } = &mut self.security else {
+                unreachable!()
+            };
+            let scoped = ScopedPdu {
+                context_engine_id: session.engine_id.clone(),
+                context_name: Vec::new(),
+                pdu: request,
+            };
+            let (ciphertext, priv_params) =
+                session.keys.encrypt(session.boots, session.time, 7, &scoped.encode());
+            let msg = V3Message {
+                msg_id: scoped.pdu.request_id,
+                max_size: 65507,
+                flags: FLAG_AUTH | FLAG_PRIV | FLAG_REPORTABLE,
+                usm: UsmParams {
+                    engine_id: session.engine_id.clone(),
+                    engine_boots: session.boots,
+                    engine_time: session.time,
+                    user_name: session.keys.name.clone(),
+                    auth_params: vec![0; session.keys.auth.mac_len()],
+                    priv_params,
+                },
+                data: ScopedPduData::Encrypted(ciphertext),
+                auth_offset: 0,
+            };
+            let (mut out, offset) = msg.encode();
+            session.keys.auth.authenticate(&session.keys.auth_key, &mut out, offset);
+
+            let raw = self.exchange(&out).await;
+            let response = pdu::decode_v3(&raw).unwrap();
+            let Security::Usm { session: Some(session), .. } = &mut self.security else {
+                unreachable!()
+            };
+            assert!(session.keys.auth.verify(
+                &session.keys.auth_key,
+                &raw,
+                response.auth_offset,
+                &response.usm.auth_params
+            ));
+            session.boots = response.usm.engine_boots;
+            session.time = response.usm.engine_time;
+            match response.data {
+                ScopedPduData::Plain(scoped) => scoped.pdu,
+                ScopedPduData::Encrypted(ciphertext) => {
+                    let plain = session
+                        .keys
+                        .decrypt(response.usm.engine_boots, response.usm.engine_time, &response.usm.priv_params, &ciphertext)
+                        .unwrap();
+                    ScopedPdu::decode(&plain).unwrap().pdu
+                }
+            }
+        }
+
+        async fn request(&mut self, mut request: Pdu) -> Pdu {
+            self.next_id += 1;
+            request.request_id = self.next_id;
+            let response = match self.security {
+                Security::Community(community) => {
+                    let msg = CommunityMessage { community: community.as_bytes().to_vec(), pdu: request };
+                    pdu::decode_community(&self.exchange(&msg.encode()).await).unwrap().pdu
+                }
+                Security::Usm { .. } => self.send_v3(request).await,
+            };
+            assert_eq!(response.pdu_type, PduType::Response);
+            assert_eq!(response.request_id, self.next_id);
+            response
+        }
+
+        async fn walk(&mut self, root: &Oid) -> Vec<VarBind> {
+            let mut result = Vec::new();
+            let mut cursor = root.clone();
+            loop {
+                let response = self.request(Pdu::request(PduType::GetNext, 0, std::slice::from_ref(&cursor))).await;
+                let (oid, value) = response.varbinds.into_iter().next().unwrap();
+                if value == Value::EndOfMibView || !oid.starts_with(root) {
+                    return result;
+                }
+                cursor = oid.clone();
+                result.push((oid, value));
+            }
+        }
+
+        async fn bulk_walk(&mut self, root: &Oid, repetitions: i64) -> Vec<VarBind> {
+            let mut result: Vec<VarBind> = Vec::new();
+            let mut cursor = root.clone();
+            loop {
+                let response = self.request(Pdu::get_bulk(0, 0, repetitions, std::slice::from_ref(&cursor))).await;
+                assert!(!response.varbinds.is_empty());
+                for (oid, value) in response.varbinds {
+                    if value == Value::EndOfMibView || !oid.starts_with(root) {
+                        return result;
+                    }
+                    cursor = oid.clone();
+                    result.push((oid, value));
+                }
+            }
+        }
+    }
+
+    fn interface(index: u32, name: &str, rx_bytes: u64) -> InterfaceStats {
+        InterfaceStats {
+            index,
+            name: name.to_string(),
+            mac_address: Some("52:54:00:12:34:56".to_string()),
+            mtu: 1500,
+            admin_up: true,
+            oper_up: index != 3,
+            speed_bps: Some(1_000_000_000),
+            rx_bytes,
+            tx_bytes: 1_000,
+            rx_packets: 10,
+            tx_packets: 5,
+            ..Default::default()
+        }
+    }
+
+    fn tunnel(name: &str, latency_ms: f64) -> SdwanTunnelStatus {
+        SdwanTunnelStatus { name: name.to_string(), state: TunnelState::Up, latency_ms, packet_loss_pct: 0.25 }
+    }
+
+    fn config() -> SnmpConfig {
+        SnmpConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            communities: vec![CommunityAccess { community: "monitor".into(), source: "127.0.0.0/8".parse().unwrap() }],
+            users: vec![UsmUserConfig {
+                name: "nms".into(),
+                auth_protocol: AuthProtocol::Sha256,
+                auth_secret: "snmp/nms/auth".into(),
+                priv_secret: "snmp/nms/priv".into(),
+            }],
+            sys_name: "gw1".into(),
+            engine_boots_path: None,
+            ..Default::default()
+        }
+    }
+
+    async fn agent_with(config: SnmpConfig) -> Arc<SnmpAgent> {
+        let store = Arc::new(MemoryStore::new());
+        store.store("snmp/nms/auth", SecretString::from_str("auth-password")).await.unwrap();
+        store.store("snmp/nms/priv", SecretString::from_str("priv-password")).await.unwrap();
+        let secrets = SecretManager::new(store);
+        Arc::new(SnmpAgent::new(config, &secrets).await.unwrap())
+    }
+
+    async fn agent() -> Arc<SnmpAgent> {
+        agent_with(config()).await
+    }
+
+    async fn serve(agent: &Arc<SnmpAgent>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(Arc::clone(agent).serve_on(socket));
+        addr
+    }
+
+    /// Compare walks ignoring sysUpTime, which advances between requests
+    fn without_uptime(walk: &[VarBind]) -> Vec<VarBind> {
+        let uptime = Oid::new(&SYS_UPTIME);
+        walk.iter().filter(|(oid, _)| *oid != uptime).cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn test_walk_full_tree_v2c_and_v3() {
+        let agent = agent().await;
+        agent.update_interfaces(vec![interface(2, "eth1", 5_000_000_000), interface(1, "lo", 42), interface(3, "wg0", 0)]);
+        agent.update_tunnels(vec![tunnel("branch-a", 12.5), tunnel("branch-b", 40.0)]);
+        agent.update_gateways(vec![GatewayHealth {
+            name: "wan1".into(),
+            status: "degraded".into(),
+            latency_ms: Some(8.0),
+            packet_loss_pct: Some(1.5),
+            last_check: None,
+            monitor_target: "192.0.2.1".into(),
+        }]);
+        let addr = serve(&agent).await;
+        let root = Oid::new(&WALK_ROOT);
+
+        let mut v2c = Manager::connect(addr, Security::Community("monitor")).await;
+        let walk = v2c.walk(&root).await;
+        assert_eq!(walk.len(), agent.view().len());
+        assert!(walk.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let bulk = v2c.bulk_walk(&root, 25).await;
+        assert_eq!(without_uptime(&bulk), without_uptime(&walk));
+
+        let mut v3 = Manager::connect(
+            addr,
+            Security::Usm { user: "nms", auth: "auth-password", privacy: "priv-password", session: None },
+        )
+        .await;
+        let secure = v3.bulk_walk(&root, 10).await;
+        assert_eq!(without_uptime(&secure), without_uptime(&walk));
+
+        let get = |oid: &str| {
+            let oid: Oid = oid.parse().unwrap();
+            walk.iter().find(|(o, _)| *o == oid).map(|(_, v)| v.clone()).unwrap()
+        };
+        assert_eq!(get("1.3.6.1.2.1.1.5.0"), Value::string("gw1"));
+        assert_eq!(get("1.3.6.1.2.1.2.1.0"), Value::Integer(3));
+        assert_eq!(get("1.3.6.1.2.1.2.2.1.2.2"), Value::string("eth1"));
+        assert_eq!(get("1.3.6.1.2.1.2.2.1.3.3"), Value::Integer(131));
+        assert_eq!(get("1.3.6.1.2.1.2.2.1.8.3"), Value::Integer(2));
+        // 5e9 wraps the 32-bit counter, the HC counter does not
+        assert_eq!(get("1.3.6.1.2.1.2.2.1.10.2"), Value::Counter32(705_032_704));
+        assert_eq!(get("1.3.6.1.2.1.31.1.1.1.6.2"), Value::Counter64(5_000_000_000));
+        assert_eq!(get("1.3.6.1.2.1.31.1.1.1.15.2"), Value::Gauge32(1000));
+        assert_eq!(get("1.3.6.1.4.1.32473.1.2.1.1.2.2"), Value::string("branch-b"));
+        assert_eq!(get("1.3.6.1.4.1.32473.1.2.1.1.4.1"), Value::Gauge32(12_500));
+        assert_eq!(get("1.3.6.1.4.1.32473.1.2.1.1.5.1"), Value::Gauge32(25));
+        assert_eq!(get("1.3.6.1.4.1.32473.1.3.1.1.3.1"), Value::Integer(2));
+
+        // Reads only; sets are refused
+        let set = v2c.request(Pdu::new(PduType::Set, 0, vec![("1.3.6.1.2.1.1.5.0".parse().unwrap(), Value::string("x"))])).await;
+        assert_eq!(set.error_status, NOT_WRITABLE);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_reads_are_consistent_under_updates() {
+        let agent = agent().await;
+        agent.update_tunnels(vec![tunnel("t0", 0.0)]);
+        let addr = serve(&agent).await;
+
+        let updater = {
+            let agent = Arc::clone(&agent);
+            std::thread::spawn(move || {
+                for generation in 1..=2000u32 {
+                    let rows = (generation % 7 + 1) as usize;
+                    agent.update_tunnels(
+                        (0..rows).map(|i| tunnel(&format!("t{}", i), generation as f64)).collect(),
+                    );
+                }
+            })
+        };
+
+        let mut manager = Manager::connect(addr, Security::Community("monitor")).await;
+        let names: Oid = "1.3.6.1.4.1.32473.1.2.1.1.2".parse().unwrap();
+        let latencies: Oid = "1.3.6.1.4.1.32473.1.2.1.1.4".parse().unwrap();
+        while !updater.is_finished() {
+            let response = manager.request(Pdu::get_bulk(0, 0, 16, &[names.clone(), latencies.clone()])).await;
+            let rows: Vec<_> = response.varbinds.chunks(2).take_while(|row| row[0].0.starts_with(&names)).collect();
+            assert!(!rows.is_empty());
+            // Every row of one response comes from the same table generation
+            assert!(rows.iter().all(|row| row[1].0.starts_with(&latencies) && row[1].1 == rows[0][1].1));
+            let generation = match rows[0][1].1 {
+                Value::Gauge32(us) => us / 1000,
+                ref other => panic!("unexpected {:?}", other),
+            };
+            if generation > 0 {
+                assert_eq!(rows.len() as u32, generation % 7 + 1);
+            }
+        }
+        updater.join().unwrap();
+
+        // Indexes stayed with their names through every rebuild
+        let view = agent.view();
+        assert_eq!(view.get(&names.child(&[1])), Some(&Value::string("t0")));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unauthorized_requests() {
+        let agent = agent().await;
+        let get = |community: &str| {
+            CommunityMessage {
+                community: community.as_bytes().to_vec(),
+                pdu: Pdu::request(PduType::Get, 9, &["1.3.6.1.2.1.1.5.0".parse().unwrap()]),
+            }
+            .encode()
+        };
+        let local: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let remote: SocketAddr = "198.51.100.7:40000".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:127.0.0.1]:40000".parse().unwrap();
+
+        assert!(agent.handle_message(&get("monitor"), local).is_some());
+        assert!(agent.handle_message(&get("monitor"), mapped).is_some());
+        assert!(agent.handle_message(&get("public"), local).is_none());
+        assert!(agent.handle_message(&get("monitor"), remote).is_none());
+        assert_eq!(agent.stats().bad_community_names, 2);
+
+        agent.set_communities(vec![CommunityAccess { community: "ops".into(), source: "198.51.100.0/24".parse().unwrap() }]);
+        assert!(agent.handle_message(&get("ops"), remote).is_some());
+        assert!(agent.handle_message(&get("monitor"), local).is_none());
+
+        // v3 with the wrong password gets a wrongDigests report
+        let report_counter = |raw: Vec<u8>| match pdu::decode_v3(&raw).unwrap().data {
+            ScopedPduData::Plain(scoped) => {
+                assert_eq!(scoped.pdu.pdu_type, PduType::Report);
+                scoped.pdu.varbinds[0].0.arcs()[9]
+            }
+            ScopedPduData::Encrypted(_) => panic!("report must be plaintext"),
+        };
+        let keys = UsmUser::new("nms", AuthProtocol::Sha256, "wrong-password", "priv-password", agent.engine_id());
+        let mut msg = V3Message {
+            msg_id: 3,
+            max_size: 65507,
+            flags: FLAG_AUTH | FLAG_PRIV | FLAG_REPORTABLE,
+            usm: UsmParams {
+                engine_id: agent.engine_id().to_vec(),
+                engine_boots: 1,
+                engine_time: 0,
+                user_name: b"nms".to_vec(),
+                auth_params: vec![0; 24],
+                priv_params: vec![0; 8],
+            },
+            data: ScopedPduData::Encrypted(vec![0; 32]),
+            auth_offset: 0,
+        };
+        let (mut out, offset) = msg.encode();
+        keys.auth.authenticate(&keys.auth_key, &mut out, offset);
+        assert_eq!(report_counter(agent.handle_message(&out, local).unwrap()), WRONG_DIGESTS);
+
+        msg.usm.user_name = b"intruder".to_vec();
+        assert_eq!(report_counter(agent.handle_message(&msg.encode().0, local).unwrap()), UNKNOWN_USER_NAMES);
+
+        msg.usm.user_name = b"nms".to_vec();
+        msg.flags = FLAG_AUTH | FLAG_REPORTABLE;
+        assert_eq!(report_counter(agent.handle_message(&msg.encode().0, local).unwrap()), UNSUPPORTED_SEC_LEVELS);
+
+        let stats = agent.stats();
+        assert_eq!((stats.wrong_digests, stats.unknown_user_names, stats.unsupported_sec_levels), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_engine_boots_persist_per_engine_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snmp/engine-boots");
+        assert_eq!(next_engine_boots(&path, b"engine-a").unwrap(), 1);
+        assert_eq!(next_engine_boots(&path, b"engine-a").unwrap(), 2);
+        // A new engine ID restarts the count
+        assert_eq!(next_engine_boots(&path, b"engine-b").unwrap(), 1);
+
+        std::fs::write(&path, format!("{} {}\n", hex::encode(b"engine-b"), MAX_ENGINE_BOOTS)).unwrap();
+        assert_eq!(next_engine_boots(&path, b"engine-b").unwrap(), MAX_ENGINE_BOOTS);
+
+        // A damaged file is not silently reset to a reusable count
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(next_engine_boots(&path, b"engine-b").is_err());
+    }
+
+    /// Run a net-snmp tool, `None` when net-snmp is not installed
+    async fn net_snmp(tool: &str, args: &[&str]) -> Option<String> {
+        let output = match tokio::process::Command::new(tool).args(args).output().await {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => panic!("Failed to run {}: {}", tool, e),
+        };
+        assert!(output.status.success(), "{} failed: {}", tool, String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    /// Varbinds printed with `-On -Oq`: numeric OID, then the value
+    fn printed_varbinds(output: &str) -> Vec<(Oid, String)> {
+        output
+            .lines()
+            .map(|line| {
+                let (oid, value) = line.split_once(' ').unwrap_or((line, ""));
+                (oid.parse().unwrap(), value.to_string())
+            })
+            .collect()
+    }
+
+    /// Walks the agent with net-snmp over v2c and v3 authPriv (SHA-256,
+    /// AES-128); skipped when net-snmp is not installed
+    #[tokio::test]
+    async fn test_net_snmp_walks_full_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let persisted = || SnmpConfig { engine_boots_path: Some(dir.path().join("engine-boots")), ..config() };
+        let agent = agent_with(persisted()).await;
+        agent.update_interfaces(vec![interface(2, "eth1", 5_000_000_000), interface(1, "lo", 42), interface(3, "wg0", 0)]);
+        agent.update_tunnels(vec![tunnel("branch-a", 12.5), tunnel("branch-b", 40.0)]);
+        let target = format!("udp:{}", serve(&agent).await);
+
+        let output = ["-On", "-Oq", "-Oe", "-Ot", "-t", "5", "-r", "1"];
+        let v2c = [&["-v2c", "-c", "monitor"], &output[..], &[target.as_str(), "1.3"]].concat();
+        let Some(walk) = net_snmp("snmpwalk", &v2c).await else {
+            eprintln!("net-snmp not installed; skipping");
+            return;
+        };
+        let walk = printed_varbinds(&walk);
+        let expected: Vec<Oid> = agent.view().iter().map(|(oid, _)| oid.clone()).collect();
+        assert_eq!(walk.iter().map(|(oid, _)| oid.clone()).collect::<Vec<_>>(), expected);
+        let get = |walk: &[(Oid, String)], oid: &str| {
+            let oid: Oid = oid.parse().unwrap();
+            walk.iter().find(|(o, _)| *o == oid).map(|(_, v)| v.clone()).unwrap()
+        };
+        assert_eq!(get(&walk, "1.3.6.1.2.1.1.5.0"), "\"gw1\"");
+        assert_eq!(get(&walk, "1.3.6.1.2.1.2.2.1.10.2"), "705032704");
+        assert_eq!(get(&walk, "1.3.6.1.2.1.31.1.1.1.6.2"), "5000000000");
+        assert_eq!(get(&walk, "1.3.6.1.4.1.32473.1.2.1.1.2.2"), "\"branch-b\"");
+
+        let usm = [
+            "-v3", "-l", "authPriv", "-u", "nms", "-a", "SHA-256", "-A", "auth-password", "-x", "AES", "-X",
+            "priv-password",
+        ];
+        let bulk = [&usm[..], &output[..], &["-Cr10", target.as_str(), "1.3"]].concat();
+        let secure = printed_varbinds(&net_snmp("snmpbulkwalk", &bulk).await.unwrap());
+        assert_eq!(secure.iter().map(|(oid, _)| oid.clone()).collect::<Vec<_>>(), expected);
+        assert_eq!(get(&secure, "1.3.6.1.2.1.31.1.1.1.6.2"), "5000000000");
+
+        // A restarted agent is rediscovered with its new snmpEngineBoots
+        let restarted = agent_with(persisted()).await;
+        assert_eq!(restarted.engine_boots(), 2);
+        let target = format!("udp:{}", serve(&restarted).await);
+        let get_name = [&usm[..], &output[..], &[target.as_str(), "1.3.6.1.2.1.1.5.0"]].concat();
+        let name = printed_varbinds(&net_snmp("snmpget", &get_name).await.unwrap());
+        assert_eq!(name[0].1, "\"gw1\"");
+    }
+}
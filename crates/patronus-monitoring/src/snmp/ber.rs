@@ -0,0 +1,390 @@
+//! BER encoding of the SNMP subset of ASN.1
+//!
+//! Only definite lengths and the universal/application types SNMP uses are
+//! supported, which is all RFC 3416/3417 messages need.
+
+use std::fmt;
+use std::str::FromStr;
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_IP_ADDRESS: u8 = 0x40;
+pub const TAG_COUNTER32: u8 = 0x41;
+pub const TAG_GAUGE32: u8 = 0x42;
+pub const TAG_TIMETICKS: u8 = 0x43;
+pub const TAG_COUNTER64: u8 = 0x46;
+pub const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+pub const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+pub const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+/// Malformed or unsupported BER input
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("BER decode error: {0}")]
+pub struct DecodeError(pub &'static str);
+
+pub type DecodeResult<T> = std::result::Result<T, DecodeError>;
+
+/// Object identifier, ordered lexicographically as SNMP walks require
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Oid(Vec<u32>);
+
+impl Oid {
+    pub fn new(arcs: &[u32]) -> Self {
+        Self(arcs.to_vec())
+    }
+
+    pub fn arcs(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// This OID with `arcs` appended
+    pub fn child(&self, arcs: &[u32]) -> Self {
+        let mut oid = self.0.clone();
+        oid.extend_from_slice(arcs);
+        Self(oid)
+    }
+
+    pub fn starts_with(&self, prefix: &Oid) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arcs: Vec<String> = self.0.iter().map(u32::to_string).collect();
+        f.write_str(&arcs.join("."))
+    }
+}
+
+impl FromStr for Oid {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> DecodeResult<Self> {
+        s.trim_start_matches('.')
+            .split('.')
+            .map(|arc| arc.parse().map_err(|_| DecodeError("invalid OID arc")))
+            .collect::<DecodeResult<Vec<u32>>>()
+            .map(Self)
+    }
+}
+
+/// SNMP variable binding value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    ObjectId(Oid),
+    IpAddress([u8; 4]),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Value {
+    pub fn string(s: &str) -> Self {
+        Value::OctetString(s.as_bytes().to_vec())
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Integer(v) => write_integer(out, *v),
+            Value::OctetString(v) => write_tlv(out, TAG_OCTET_STRING, v),
+            Value::Null => write_tlv(out, TAG_NULL, &[]),
+            Value::ObjectId(oid) => write_oid(out, oid),
+            Value::IpAddress(ip) => write_tlv(out, TAG_IP_ADDRESS, ip),
+            Value::Counter32(v) => write_unsigned(out, TAG_COUNTER32, *v as u64),
+            Value::Gauge32(v) => write_unsigned(out, TAG_GAUGE32, *v as u64),
+            Value::TimeTicks(v) => write_unsigned(out, TAG_TIMETICKS, *v as u64),
+            Value::Counter64(v) => write_unsigned(out, TAG_COUNTER64, *v),
+            Value::NoSuchObject => write_tlv(out, TAG_NO_SUCH_OBJECT, &[]),
+            Value::NoSuchInstance => write_tlv(out, TAG_NO_SUCH_INSTANCE, &[]),
+            Value::EndOfMibView => write_tlv(out, TAG_END_OF_MIB_VIEW, &[]),
+        }
+    }
+
+    pub fn decode(reader: &mut Reader<'_>) -> DecodeResult<Self> {
+        let (tag, content) = reader.read_tlv()?;
+        Ok(match tag {
+            TAG_INTEGER => Value::Integer(decode_integer(content)?),
+            TAG_OCTET_STRING => Value::OctetString(content.to_vec()),
+            TAG_NULL => Value::Null,
+            TAG_OID => Value::ObjectId(decode_oid(content)?),
+            TAG_IP_ADDRESS => Value::IpAddress(
+                content.try_into().map_err(|_| DecodeError("IpAddress must be 4 bytes"))?,
+            ),
+            TAG_COUNTER32 => Value::Counter32(decode_unsigned(content, 4)? as u32),
+            TAG_GAUGE32 => Value::Gauge32(decode_unsigned(content, 4)? as u32),
+            TAG_TIMETICKS => Value::TimeTicks(decode_unsigned(content, 4)? as u32),
+            TAG_COUNTER64 => Value::Counter64(decode_unsigned(content, 8)?),
+            TAG_NO_SUCH_OBJECT => Value::NoSuchObject,
+            TAG_NO_SUCH_INSTANCE => Value::NoSuchInstance,
+            TAG_END_OF_MIB_VIEW => Value::EndOfMibView,
+            _ => return Err(DecodeError("unsupported value type")),
+        })
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (len as u64).to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+pub fn write_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    write_length(out, content.len());
+    out.extend_from_slice(content);
+}
+
+/// Encode a constructed value whose content is produced by `f`
+pub fn write_constructed(out: &mut Vec<u8>, tag: u8, f: impl FnOnce(&mut Vec<u8>)) {
+    let mut content = Vec::new();
+    f(&mut content);
+    write_tlv(out, tag, &content);
+}
+
+pub fn write_integer(out: &mut Vec<u8>, value: i64) {
+    let bytes = value.to_be_bytes();
+    // Drop redundant sign bytes, keeping the one that carries the sign
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0x00 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    write_tlv(out, TAG_INTEGER, &bytes[start..]);
+}
+
+fn write_unsigned(out: &mut Vec<u8>, tag: u8, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    let mut content = Vec::with_capacity(9);
+    // A leading zero keeps values with the top bit set positive
+    if bytes[skip] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(&bytes[skip..]);
+    write_tlv(out, tag, &content);
+}
+
+pub fn write_octets(out: &mut Vec<u8>, value: &[u8]) {
+    write_tlv(out, TAG_OCTET_STRING, value);
+}
+
+pub fn write_oid(out: &mut Vec<u8>, oid: &Oid) {
+    let arcs = oid.arcs();
+    let mut content = Vec::new();
+
+    let (first, rest) = match arcs {
+        [] => (0, &[][..]),
+        [a] => (a * 40, &[][..]),
+        [a, b, rest @ ..] => (a * 40 + b, rest),
+    };
+
+    for arc in std::iter::once(first).chain(rest.iter().copied()) {
+        let mut chunk = [0u8; 5];
+        let mut n = 0;
+        let mut v = arc;
+        loop {
+            chunk[n] = (v & 0x7f) as u8;
+            n += 1;
+            v >>= 7;
+            if v == 0 {
+                break;
+            }
+        }
+        for i in (0..n).rev() {
+            content.push(chunk[i] | if i > 0 { 0x80 } else { 0 });
+        }
+    }
+
+    write_tlv(out, TAG_OID, &content);
+}
+
+fn decode_integer(content: &[u8]) -> DecodeResult<i64> {
+    if content.is_empty() || content.len() > 8 {
+        return Err(DecodeError("bad INTEGER length"));
+    }
+    let mut value: i64 = if content[0] & 0x80 != 0 { -1 } else { 0 };
+    for b in content {
+        value = (value << 8) | *b as i64;
+    }
+    Ok(value)
+}
+
+fn decode_unsigned(content: &[u8], max_bytes: usize) -> DecodeResult<u64> {
+    let content = match content {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _ => content,
+    };
+    if content.is_empty() || content.len() > max_bytes {
+        return Err(DecodeError("bad unsigned length"));
+    }
+    Ok(content.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn decode_oid(content: &[u8]) -> DecodeResult<Oid> {
+    let mut raw = Vec::new();
+    let mut value: u64 = 0;
+    for (i, b) in content.iter().enumerate() {
+        value = (value << 7) | (b & 0x7f) as u64;
+        if value > u32::MAX as u64 {
+            return Err(DecodeError("OID arc overflow"));
+        }
+        if b & 0x80 == 0 {
+            raw.push(value as u32);
+            value = 0;
+        } else if i == content.len() - 1 {
+            return Err(DecodeError("truncated OID"));
+        }
+    }
+
+    let Some((&first, rest)) = raw.split_first() else {
+        return Ok(Oid::default());
+    };
+    let (a, b) = match first {
+        0..=39 => (0, first),
+        40..=79 => (1, first - 40),
+        _ => (2, first - 80),
+    };
+    let mut arcs = vec![a, b];
+    arcs.extend_from_slice(rest);
+    Ok(Oid(arcs))
+}
+
+/// Cursor over BER-encoded input
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Offset of the next TLV within the input
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    pub fn read_tlv(&mut self) -> DecodeResult<(u8, &'a [u8])> {
+        let tag = *self.data.get(self.pos).ok_or(DecodeError("unexpected end of input"))?;
+        let mut pos = self.pos + 1;
+
+        let first = *self.data.get(pos).ok_or(DecodeError("missing length"))?;
+        pos += 1;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || n > 4 {
+                return Err(DecodeError("unsupported length form"));
+            }
+            let bytes = self.data.get(pos..pos + n).ok_or(DecodeError("truncated length"))?;
+            pos += n;
+            bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        };
+
+        let content = self.data.get(pos..pos + len).ok_or(DecodeError("truncated content"))?;
+        self.pos = pos + len;
+        Ok((tag, content))
+    }
+
+    pub fn read_expected(&mut self, expected: u8) -> DecodeResult<&'a [u8]> {
+        let (tag, content) = self.read_tlv()?;
+        if tag != expected {
+            return Err(DecodeError("unexpected tag"));
+        }
+        Ok(content)
+    }
+
+    /// Enter a constructed value with the given tag
+    pub fn read_constructed(&mut self, tag: u8) -> DecodeResult<Reader<'a>> {
+        self.read_expected(tag).map(Reader::new)
+    }
+
+    pub fn read_integer(&mut self) -> DecodeResult<i64> {
+        decode_integer(self.read_expected(TAG_INTEGER)?)
+    }
+
+    pub fn read_octets(&mut self) -> DecodeResult<&'a [u8]> {
+        self.read_expected(TAG_OCTET_STRING)
+    }
+
+    pub fn read_oid(&mut self) -> DecodeResult<Oid> {
+        decode_oid(self.read_expected(TAG_OID)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(value: Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        assert_eq!(Value::decode(&mut Reader::new(&out)).unwrap(), value);
+        out
+    }
+
+    #[test]
+    fn test_known_encodings() {
+        // X.690 examples and the classic sysDescr.0 OID
+        assert_eq!(roundtrip(Value::Integer(0)), [0x02, 0x01, 0x00]);
+        assert_eq!(roundtrip(Value::Integer(127)), [0x02, 0x01, 0x7f]);
+        assert_eq!(roundtrip(Value::Integer(128)), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(roundtrip(Value::Integer(-129)), [0x02, 0x02, 0xff, 0x7f]);
+        assert_eq!(roundtrip(Value::Counter32(u32::MAX)), [0x41, 0x05, 0x00, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            roundtrip(Value::ObjectId("1.3.6.1.2.1.1.1.0".parse().unwrap())),
+            [0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00]
+        );
+        assert_eq!(
+            roundtrip(Value::ObjectId("1.3.6.1.4.1.32473".parse().unwrap())),
+            [0x06, 0x08, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x81, 0xfd, 0x59]
+        );
+        roundtrip(Value::Counter64(u64::MAX));
+        roundtrip(Value::OctetString(vec![0xaa; 300]));
+    }
+
+    #[test]
+    fn test_oid_ordering() {
+        let a: Oid = "1.3.6.1.2.1.2.2.1.1.2".parse().unwrap();
+        let b: Oid = "1.3.6.1.2.1.2.2.1.1.10".parse().unwrap();
+        let c: Oid = "1.3.6.1.2.1.2.2.1.2".parse().unwrap();
+        assert!(a < b && b < c);
+        assert!(Oid::new(&[1, 3]) < Oid::new(&[1, 3, 0]));
+        assert_eq!(b.to_string(), "1.3.6.1.2.1.2.2.1.1.10");
+    }
+
+    #[test]
+    fn test_rejects_truncated_input() {
+        assert!(Reader::new(&[0x30, 0x05, 0x02]).read_tlv().is_err());
+        assert!(Reader::new(&[0x06, 0x02, 0x2b, 0x86]).read_oid().is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! MIB snapshot served by the agent
+//!
+//! The agent rebuilds a complete [`MibView`] whenever its data changes and
+//! swaps it in atomically, so every request — and therefore every GETNEXT
+//! or GETBULK step of a table walk — is answered from one consistent
+//! snapshot rather than from tables being updated underneath it.
+
+use super::ber::{Oid, Value};
+use crate::status::GatewayHealth;
+use patronus_core::types::InterfaceStats;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// RFC 5612 documentation enterprise number, used until one is configured
+pub const DEFAULT_ENTERPRISE_NUMBER: u32 = 32473;
+
+pub const SYSTEM: [u32; 7] = [1, 3, 6, 1, 2, 1, 1];
+pub const SYS_UPTIME: [u32; 9] = [1, 3, 6, 1, 2, 1, 1, 3, 0];
+pub const IF_NUMBER: [u32; 9] = [1, 3, 6, 1, 2, 1, 2, 1, 0];
+pub const IF_ENTRY: [u32; 10] = [1, 3, 6, 1, 2, 1, 2, 2, 1, 0];
+pub const IF_X_ENTRY: [u32; 11] = [1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 0];
+pub const ENTERPRISES: [u32; 7] = [1, 3, 6, 1, 4, 1, 0];
+
+/// Operational state of an SD-WAN tunnel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelState {
+    Up = 1,
+    Degraded = 2,
+    Down = 3,
+}
+
+/// SD-WAN tunnel status exported in the enterprise subtree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SdwanTunnelStatus {
+    pub name: String,
+    pub state: TunnelState,
+    pub latency_ms: f64,
+    pub packet_loss_pct: f64,
+}
+
+/// Static system group values
+#[derive(Debug, Clone)]
+pub struct SystemInfo {
+    pub descr: String,
+    pub contact: String,
+    pub name: String,
+    pub location: String,
+}
+
+/// Host figures sampled when the view is built
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostSample {
+    pub uptime_secs: u64,
+    pub load: [f64; 3],
+}
+
+/// Immutable, ordered MIB snapshot
+#[derive(Debug, Default)]
+pub struct MibView {
+    objects: BTreeMap<Oid, Value>,
+}
+
+impl MibView {
+    pub fn get(&self, oid: &Oid) -> Option<&Value> {
+        self.objects.get(oid)
+    }
+
+    /// First object strictly after `oid` in lexicographic order
+    pub fn next(&self, oid: &Oid) -> Option<(&Oid, &Value)> {
+        self.objects.range((Bound::Excluded(oid), Bound::Unbounded)).next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Oid, &Value)> {
+        self.objects.iter()
+    }
+}
+
+/// Everything a view is built from; tables carry stable row indexes
+pub struct MibSource<'a> {
+    pub enterprise: u32,
+    pub system: &'a SystemInfo,
+    pub host: HostSample,
+    pub interfaces: &'a [InterfaceStats],
+    pub tunnels: &'a [(u32, SdwanTunnelStatus)],
+    pub gateways: &'a [(u32, GatewayHealth)],
+}
+
+impl MibSource<'_> {
+    pub fn build(&self) -> MibView {
+        let mut objects = BTreeMap::new();
+        let mut put = |oid: Oid, value: Value| {
+            objects.insert(oid, value);
+        };
+
+        // SNMPv2-MIB system group; sysUpTime is filled in per request
+        let system = Oid::new(&SYSTEM);
+        let enterprise = Oid::new(&ENTERPRISES[..6]).child(&[self.enterprise]);
+        put(system.child(&[1, 0]), Value::string(&self.system.descr));
+        put(system.child(&[2, 0]), Value::ObjectId(enterprise.child(&[1])));
+        put(system.child(&[3, 0]), Value::TimeTicks(0));
+        put(system.child(&[4, 0]), Value::string(&self.system.contact));
+        put(system.child(&[5, 0]), Value::string(&self.system.name));
+        put(system.child(&[6, 0]), Value::string(&self.system.location));
+        // sysServices: internet + end-to-end layers
+        put(system.child(&[7, 0]), Value::Integer(72));
+
+        // IF-MIB ifTable
+        put(Oid::new(&IF_NUMBER), Value::Integer(self.interfaces.len() as i64));
+        let if_entry = Oid::new(&IF_ENTRY[..9]);
+        for iface in self.interfaces {
+            let row = |column: u32| if_entry.child(&[column, iface.index]);
+            let speed = iface.speed_bps.unwrap_or(0);
+            put(row(1), Value::Integer(iface.index as i64));
+            put(row(2), Value::string(&iface.name));
+            put(row(3), Value::Integer(if_type(iface)));
+            put(row(4), Value::Integer(iface.mtu as i64));
+            put(row(5), Value::Gauge32(speed.min(u32::MAX as u64) as u32));
+            put(row(6), Value::OctetString(parse_mac(iface.mac_address.as_deref())));
+            put(row(7), Value::Integer(if iface.admin_up { 1 } else { 2 }));
+            put(row(8), Value::Integer(if iface.oper_up { 1 } else { 2 }));
+            // 32-bit counters wrap as IF-MIB specifies
+            put(row(10), Value::Counter32(iface.rx_bytes as u32));
+            put(row(11), Value::Counter32(iface.rx_packets as u32));
+            put(row(13), Value::Counter32(iface.rx_dropped as u32));
+            put(row(14), Value::Counter32(iface.rx_errors as u32));
+            put(row(16), Value::Counter32(iface.tx_bytes as u32));
+            put(row(17), Value::Counter32(iface.tx_packets as u32));
+            put(row(19), Value::Counter32(iface.tx_dropped as u32));
+            put(row(20), Value::Counter32(iface.tx_errors as u32));
+        }
+
+        // IF-MIB ifXTable
+        let if_x_entry = Oid::new(&IF_X_ENTRY[..10]);
+        for iface in self.interfaces {
+            let row = |column: u32| if_x_entry.child(&[column, iface.index]);
+            put(row(1), Value::string(&iface.name));
+            put(row(6), Value::Counter64(iface.rx_bytes));
+            put(row(7), Value::Counter64(iface.rx_packets));
+            put(row(10), Value::Counter64(iface.tx_bytes));
+            put(row(11), Value::Counter64(iface.tx_packets));
+            put(row(15), Value::Gauge32((iface.speed_bps.unwrap_or(0) / 1_000_000) as u32));
+        }
+
+        // Enterprise subtree: <pen>.1.1 system, .1.2 tunnels, .1.3 gateways
+        let product = enterprise.child(&[1]);
+        let host = product.child(&[1]);
+        put(host.child(&[1, 0]), Value::TimeTicks((self.host.uptime_secs * 100) as u32));
+        for (i, load) in self.host.load.iter().enumerate() {
+            put(host.child(&[2 + i as u32, 0]), Value::Gauge32((load * 100.0).round() as u32));
+        }
+
+        let tunnel_entry = product.child(&[2, 1, 1]);
+        for (index, tunnel) in self.tunnels {
+            let row = |column: u32| tunnel_entry.child(&[column, *index]);
+            put(row(1), Value::Integer(*index as i64));
+            put(row(2), Value::string(&tunnel.name));
+            put(row(3), Value::Integer(tunnel.state as i64));
+            // Latency in microseconds, loss in hundredths of a percent
+            put(row(4), Value::Gauge32((tunnel.latency_ms * 1000.0).round() as u32));
+            put(row(5), Value::Gauge32((tunnel.packet_loss_pct * 100.0).round() as u32));
+        }
+
+        let gateway_entry = product.child(&[3, 1, 1]);
+        for (index, gateway) in self.gateways {
+            let row = |column: u32| gateway_entry.child(&[column, *index]);
+            put(row(1), Value::Integer(*index as i64));
+            put(row(2), Value::string(&gateway.name));
+            put(row(3), Value::Integer(gateway_state(&gateway.status) as i64));
+            put(row(4), Value::Gauge32(
+                gateway.latency_ms.map(|ms| (ms * 1000.0).round() as u32).unwrap_or(0),
+            ));
+            put(row(5), Value::Gauge32(
+                gateway.packet_loss_pct.map(|pct| (pct * 100.0).round() as u32).unwrap_or(0),
+            ));
+            put(row(6), Value::string(&gateway.monitor_target));
+        }
+
+        MibView { objects }
+    }
+}
+
+fn gateway_state(status: &str) -> TunnelState {
+    match status {
+        "online" => TunnelState::Up,
+        "degraded" => TunnelState::Degraded,
+        _ => TunnelState::Down,
+    }
+}
+
+/// IANAifType: softwareLoopback(24), tunnel(131), ethernetCsmacd(6)
+fn if_type(iface: &InterfaceStats) -> i64 {
+    if iface.name == "lo" {
+        24
+    } else if ["wg", "tun", "gre", "ipsec", "vti"].iter().any(|p| iface.name.starts_with(p)) {
+        131
+    } else {
+        6
+    }
+}
+
+fn parse_mac(mac: Option<&str>) -> Vec<u8> {
+    mac.map(|mac| mac.split(':').filter_map(|b| u8::from_str_radix(b, 16).ok()).collect())
+        .unwrap_or_default()
+}
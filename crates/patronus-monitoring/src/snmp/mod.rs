@@ -0,0 +1,16 @@
+//! SNMP agent
+//!
+//! Serves IF-MIB interface counters, the system group and a Patronus
+//! enterprise subtree with SD-WAN tunnel and gateway health over SNMPv2c
+//! (community per allowed source network) and SNMPv3 USM authPriv.
+
+pub mod agent;
+pub mod ber;
+pub mod mib;
+pub mod pdu;
+pub mod usm;
+
+pub use agent::{CommunityAccess, DEFAULT_ENGINE_BOOTS_PATH, SnmpAgent, SnmpConfig, SnmpStats, UsmUserConfig};
+pub use ber::{Oid, Value};
+pub use mib::{MibView, SdwanTunnelStatus, TunnelState, DEFAULT_ENTERPRISE_NUMBER};
+pub use usm::AuthProtocol;
@@ -12,30 +12,46 @@
 //!
 //! All pages support real-time updates via WebSocket.
 
-use patronus_core::Result;
+use crate::traffic::{TrafficHistory, TrafficHistoryPoint};
+use patronus_core::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::{IpAddr, Ipv4Addr};
-use std::time::SystemTime;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 use tokio::process::Command;
 
-/// Dashboard widget type
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Dashboard widget type, with the parameters each widget takes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum WidgetType {
     SystemInfo,
-    InterfaceTraffic,
-    GatewayStatus,
+    /// Traffic of one interface, or all when unset
+    InterfaceTraffic { interface: Option<String> },
+    /// Health of one gateway, or all when unset
+    GatewayStatus { gateway: Option<String> },
     ServiceStatus,
     CpuUsage,
     MemoryUsage,
     DiskUsage,
-    FirewallLogs,
+    FirewallLogs { limit: u32 },
     ActiveConnections,
     VpnStatus,
+    /// Latency histogram of one SD-WAN path
+    SdwanPath { path: String },
+    /// Series selected from the metrics collector
+    MetricQuery(MetricQuery),
+}
+
+/// Metric series selection: a metric name and exact-match label filters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricQuery {
+    pub metric: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
 }
 
 /// Dashboard widget configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DashboardWidget {
     pub widget_type: WidgetType,
     pub position: (u32, u32),  // row, column
@@ -43,12 +59,103 @@ pub struct DashboardWidget {
     pub refresh_interval: u32,  // seconds
 }
 
+impl DashboardWidget {
+    fn new(widget_type: WidgetType, position: (u32, u32), size: (u32, u32)) -> Self {
+        Self { widget_type, position, size, refresh_interval: 10 }
+    }
+
+    /// Whether the two widgets share any grid cell
+    pub fn overlaps(&self, other: &DashboardWidget) -> bool {
+        let (row, col) = self.position;
+        let (width, height) = self.size;
+        let (other_row, other_col) = other.position;
+        let (other_width, other_height) = other.size;
+
+        row < other_row + other_height
+            && other_row < row + height
+            && col < other_col + other_width
+            && other_col < col + width
+    }
+}
+
 /// Dashboard configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DashboardConfig {
     pub widgets: Vec<DashboardWidget>,
 }
 
+impl DashboardConfig {
+    /// Columns of the dashboard grid
+    pub const GRID_COLUMNS: u32 = 12;
+
+    /// Layout shown to users who have not saved their own
+    pub fn default_layout() -> Self {
+        Self {
+            widgets: vec![
+                DashboardWidget::new(WidgetType::SystemInfo, (0, 0), (4, 2)),
+                DashboardWidget::new(WidgetType::CpuUsage, (0, 4), (4, 2)),
+                DashboardWidget::new(WidgetType::MemoryUsage, (0, 8), (4, 2)),
+                DashboardWidget::new(WidgetType::InterfaceTraffic { interface: None }, (2, 0), (8, 4)),
+                DashboardWidget::new(WidgetType::GatewayStatus { gateway: None }, (2, 8), (4, 2)),
+                DashboardWidget::new(WidgetType::ServiceStatus, (4, 8), (4, 2)),
+            ],
+        }
+    }
+
+    /// Check every widget fits the grid, takes valid parameters and no two
+    /// widgets cover the same cell
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for (i, widget) in self.widgets.iter().enumerate() {
+            let (_, col) = widget.position;
+            let (width, height) = widget.size;
+            if width == 0 || height == 0 {
+                return Err(format!("Widget {} has an empty size", i));
+            }
+            if col + width > Self::GRID_COLUMNS {
+                return Err(format!(
+                    "Widget {} extends past column {}",
+                    i,
+                    Self::GRID_COLUMNS
+                ));
+            }
+            if widget.refresh_interval == 0 {
+                return Err(format!("Widget {} has a zero refresh interval", i));
+            }
+
+            match &widget.widget_type {
+                WidgetType::SdwanPath { path } if path.is_empty() => {
+                    return Err(format!("Widget {} needs an SD-WAN path", i));
+                }
+                WidgetType::MetricQuery(query) if query.metric.is_empty() => {
+                    return Err(format!("Widget {} needs a metric name", i));
+                }
+                WidgetType::FirewallLogs { limit: 0 } => {
+                    return Err(format!("Widget {} shows no log lines", i));
+                }
+                _ => {}
+            }
+
+            if let Some(j) = self.widgets[..i].iter().position(|other| widget.overlaps(other)) {
+                return Err(format!("Widgets {} and {} overlap", j, i));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Export as JSON for sharing
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Import a shared layout, rejecting invalid ones
+    pub fn from_json(json: &str) -> Result<Self> {
+        let config: Self = serde_json::from_str(json)?;
+        config.validate().map_err(Error::Config)?;
+        Ok(config)
+    }
+}
+
 /// Interface statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InterfaceStatus {
@@ -168,6 +275,9 @@ pub struct LogEntry {
 
 pub struct StatusPageManager;
 
+/// Traffic history backing the traffic graphs, once configured
+static TRAFFIC_HISTORY: RwLock<Option<Arc<TrafficHistory>>> = RwLock::new(None);
+
 impl StatusPageManager {
     /// Get all interface statuses
     pub async fn get_interface_statuses() -> Result<Vec<InterfaceStatus>> {
@@ -513,14 +623,60 @@ impl StatusPageManager {
         Ok(Vec::new())
     }
 
+    /// Set the store traffic graphs are recorded to and read from
+    pub fn set_traffic_history(history: Arc<TrafficHistory>) {
+        *TRAFFIC_HISTORY.write().unwrap() = Some(history);
+    }
+
+    fn traffic_history() -> Option<Arc<TrafficHistory>> {
+        TRAFFIC_HISTORY.read().unwrap().clone()
+    }
+
+    /// Record a traffic sample for an interface
+    pub async fn record_traffic(interface: &str, point: &TrafficDataPoint) -> Result<()> {
+        match Self::traffic_history() {
+            Some(history) => history.record(interface, point).await,
+            None => Ok(()),
+        }
+    }
+
     /// Get traffic graph data for interface
     pub async fn get_traffic_data(
-        _interface: &str,
-        _duration_secs: u32,
+        interface: &str,
+        duration_secs: u32,
     ) -> Result<Vec<TrafficDataPoint>> {
-        // Would use RRD or time-series database
-        // Full implementation requires time-series infrastructure
-        Ok(Vec::new())
+        if Self::traffic_history().is_none() {
+            return Ok(Vec::new());
+        }
+
+        let points = Self::get_traffic_history(interface, Duration::from_secs(duration_secs as u64), None).await?;
+        Ok(points
+            .into_iter()
+            .filter_map(|p| Some(TrafficDataPoint {
+                timestamp: p.timestamp,
+                inbound_bps: p.inbound_bps?,
+                outbound_bps: p.outbound_bps?,
+            }))
+            .collect())
+    }
+
+    /// Get traffic history covering the last `range`
+    ///
+    /// Reads from the finest tier that still holds the whole range and is
+    /// no finer than `resolution` (automatic if `None`). Missing samples
+    /// come back as null points so graphs show gaps, not outages.
+    pub async fn get_traffic_history(
+        interface: &str,
+        range: Duration,
+        resolution: Option<Duration>,
+    ) -> Result<Vec<TrafficHistoryPoint>> {
+        let history = Self::traffic_history()
+            .ok_or_else(|| Error::Config("Traffic history is not configured".to_string()))?;
+
+        let now = SystemTime::now();
+        let from = now - range;
+        let tier = history.select_tier(now, from, resolution);
+        history.query(interface, tier, from, now).await
     }
 
     /// Get system logs
@@ -556,42 +712,78 @@ impl StatusPageManager {
         let mut data = HashMap::new();
 
         for widget in &config.widgets {
-            let widget_data = match widget.widget_type {
-                WidgetType::SystemInfo => {
-                    serde_json::json!({
-                        "hostname": "patronus-firewall",
-                        "uptime": 12345,
-                        "version": "1.0.0"
-                    })
-                }
-                WidgetType::InterfaceTraffic => {
-                    serde_json::json!({
-                        "interfaces": Self::get_interface_statuses().await?
-                    })
-                }
-                WidgetType::GatewayStatus => {
-                    serde_json::json!({
-                        "gateways": Self::get_gateway_health().await?
-                    })
-                }
-                WidgetType::ServiceStatus => {
-                    serde_json::json!({
-                        "services": Self::get_service_statuses().await?
-                    })
-                }
-                _ => serde_json::json!({}),
-            };
-
+            let widget_data = Self::get_widget_data(&widget.widget_type).await?;
             data.insert(format!("{:?}", widget.widget_type), widget_data);
         }
 
         Ok(data)
     }
+
+    /// Get the data of one widget; metric queries are answered by the
+    /// metrics collector instead
+    pub async fn get_widget_data(widget_type: &WidgetType) -> Result<serde_json::Value> {
+        Ok(match widget_type {
+            WidgetType::SystemInfo => {
+                serde_json::json!({
+                    "hostname": "patronus-firewall",
+                    "uptime": 12345,
+                    "version": "1.0.0"
+                })
+            }
+            WidgetType::InterfaceTraffic { interface } => {
+                let mut interfaces = Self::get_interface_statuses().await?;
+                if let Some(name) = interface {
+                    interfaces.retain(|i| &i.name == name);
+                }
+                serde_json::json!({ "interfaces": interfaces })
+            }
+            WidgetType::GatewayStatus { gateway } => {
+                let mut gateways = Self::get_gateway_health().await?;
+                if let Some(name) = gateway {
+                    gateways.retain(|g| &g.name == name);
+                }
+                serde_json::json!({ "gateways": gateways })
+            }
+            WidgetType::ServiceStatus => {
+                serde_json::json!({
+                    "services": Self::get_service_statuses().await?
+                })
+            }
+            _ => serde_json::json!({}),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::traffic::TrafficRetention;
+
+    #[tokio::test]
+    async fn test_traffic_history_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("patronus.db").display());
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        let history = TrafficHistory::new(pool, TrafficRetention::default()).await.unwrap();
+        StatusPageManager::set_traffic_history(Arc::new(history));
+
+        let now = SystemTime::now();
+        for age in [30, 20, 10] {
+            let point = TrafficDataPoint {
+                timestamp: now - Duration::from_secs(age),
+                inbound_bps: 1000.0,
+                outbound_bps: 500.0,
+            };
+            StatusPageManager::record_traffic("lan0", &point).await.unwrap();
+        }
+
+        let points = StatusPageManager::get_traffic_history("lan0", Duration::from_secs(900), None)
+            .await
+            .unwrap();
+        assert_eq!(points.len(), 3);
+        assert!(points.iter().all(|p| p.inbound_bps == Some(1000.0)));
+        assert_eq!(StatusPageManager::get_traffic_data("lan0", 900).await.unwrap().len(), 3);
+    }
 
     #[tokio::test]
     async fn test_get_service_statuses() {
@@ -0,0 +1,420 @@
+//! Traffic History
+//!
+//! Stores interface throughput in the configuration database in three
+//! tiers: raw points, 1-minute averages and 5-minute averages. A
+//! background task folds each tier into the next coarser one and prunes
+//! whatever has aged out, so history stays bounded while the dashboard
+//! can still graph the last month.
+
+use crate::status::TrafficDataPoint;
+use patronus_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqlitePool, Row};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+/// Storage tier of a traffic sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrafficTier {
+    Raw = 0,
+    OneMinute = 1,
+    FiveMinutes = 2,
+}
+
+impl TrafficTier {
+    pub const ALL: [TrafficTier; 3] = [TrafficTier::Raw, TrafficTier::OneMinute, TrafficTier::FiveMinutes];
+
+    /// Bucket width; raw points have none
+    pub fn width(self) -> Option<Duration> {
+        match self {
+            TrafficTier::Raw => None,
+            TrafficTier::OneMinute => Some(Duration::from_secs(60)),
+            TrafficTier::FiveMinutes => Some(Duration::from_secs(300)),
+        }
+    }
+
+    /// Tier this one is downsampled from
+    fn source(self) -> Option<TrafficTier> {
+        match self {
+            TrafficTier::Raw => None,
+            TrafficTier::OneMinute => Some(TrafficTier::Raw),
+            TrafficTier::FiveMinutes => Some(TrafficTier::OneMinute),
+        }
+    }
+}
+
+/// How long each tier is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficRetention {
+    pub raw: Duration,
+    pub one_minute: Duration,
+    pub five_minutes: Duration,
+
+    /// Expected spacing of raw points; larger holes are graphed as gaps
+    pub sample_interval: Duration,
+}
+
+impl Default for TrafficRetention {
+    fn default() -> Self {
+        Self {
+            raw: Duration::from_secs(3600),
+            one_minute: Duration::from_secs(24 * 3600),
+            five_minutes: Duration::from_secs(30 * 24 * 3600),
+            sample_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TrafficRetention {
+    pub fn for_tier(&self, tier: TrafficTier) -> Duration {
+        match tier {
+            TrafficTier::Raw => self.raw,
+            TrafficTier::OneMinute => self.one_minute,
+            TrafficTier::FiveMinutes => self.five_minutes,
+        }
+    }
+}
+
+/// Point of a traffic graph; `None` marks a gap in the data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrafficHistoryPoint {
+    pub timestamp: SystemTime,
+    pub inbound_bps: Option<f64>,
+    pub outbound_bps: Option<f64>,
+}
+
+/// Tiered traffic history in SQLite
+pub struct TrafficHistory {
+    pool: SqlitePool,
+    retention: TrafficRetention,
+}
+
+impl TrafficHistory {
+    /// Use `pool`, creating the history table if needed
+    pub async fn new(pool: SqlitePool, retention: TrafficRetention) -> Result<Self> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS traffic_history (
+                interface TEXT NOT NULL,
+                tier INTEGER NOT NULL,
+                ts INTEGER NOT NULL,
+                inbound_bps REAL NOT NULL,
+                outbound_bps REAL NOT NULL,
+                samples INTEGER NOT NULL,
+                PRIMARY KEY (interface, tier, ts)
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| Error::Config(format!("Failed to create traffic history table: {}", e)))?;
+
+        Ok(Self { pool, retention })
+    }
+
+    pub fn retention(&self) -> &TrafficRetention {
+        &self.retention
+    }
+
+    /// Store a raw point
+    pub async fn record(&self, interface: &str, point: &TrafficDataPoint) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO traffic_history
+             (interface, tier, ts, inbound_bps, outbound_bps, samples)
+             VALUES (?, ?, ?, ?, ?, 1)",
+        )
+        .bind(interface)
+        .bind(TrafficTier::Raw as i64)
+        .bind(millis(point.timestamp))
+        .bind(point.inbound_bps)
+        .bind(point.outbound_bps)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Config(format!("Failed to record traffic: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Fold every complete bucket still fully covered by its source tier
+    ///
+    /// Buckets are recomputed from their source rows and replaced, so
+    /// running this twice over the same window leaves the same result.
+    pub async fn downsample(&self, now: SystemTime) -> Result<()> {
+        let now = millis(now);
+
+        for tier in [TrafficTier::OneMinute, TrafficTier::FiveMinutes] {
+            let (Some(width), Some(source)) = (tier.width(), tier.source()) else {
+                continue;
+            };
+            let width = width.as_millis() as i64;
+            let horizon = now - self.retention.for_tier(source).as_millis() as i64;
+
+            // Buckets straddling the source tier's prune horizon were
+            // already folded while complete; recomputing them would lose rows
+            let start = (horizon.max(0) + width - 1) / width * width;
+            let end = now / width * width;
+            if start >= end {
+                continue;
+            }
+
+            sqlx::query(
+                "INSERT OR REPLACE INTO traffic_history
+                 (interface, tier, ts, inbound_bps, outbound_bps, samples)
+                 SELECT interface, ?1, (ts / ?2) * ?2 AS bucket,
+                        SUM(inbound_bps * samples) / SUM(samples),
+                        SUM(outbound_bps * samples) / SUM(samples),
+                        SUM(samples)
+                 FROM traffic_history
+                 WHERE tier = ?3 AND ts >= ?4 AND ts < ?5
+                 GROUP BY interface, bucket",
+            )
+            .bind(tier as i64)
+            .bind(width)
+            .bind(source as i64)
+            .bind(start)
+            .bind(end)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Error::Config(format!("Failed to downsample traffic history: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Delete rows older than their tier's retention; returns rows removed
+    pub async fn prune(&self, now: SystemTime) -> Result<u64> {
+        let mut removed = 0;
+        for tier in TrafficTier::ALL {
+            let cutoff = millis(now) - self.retention.for_tier(tier).as_millis() as i64;
+            removed += sqlx::query("DELETE FROM traffic_history WHERE tier = ? AND ts < ?")
+                .bind(tier as i64)
+                .bind(cutoff)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| Error::Config(format!("Failed to prune traffic history: {}", e)))?
+                .rows_affected();
+        }
+        Ok(removed)
+    }
+
+    /// Downsample, then prune
+    pub async fn maintain(&self, now: SystemTime) -> Result<()> {
+        self.downsample(now).await?;
+        let removed = self.prune(now).await?;
+        if removed > 0 {
+            tracing::debug!("Pruned {} traffic history rows", removed);
+        }
+        Ok(())
+    }
+
+    /// Run maintenance every `interval`, which must stay well below the
+    /// raw retention so no bucket is pruned before it is folded
+    pub fn spawn_maintenance(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let history = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = history.maintain(SystemTime::now()).await {
+                    tracing::warn!("Traffic history maintenance failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Finest tier that still covers `from` and is at least as coarse as
+    /// the requested resolution
+    pub fn select_tier(&self, now: SystemTime, from: SystemTime, resolution: Option<Duration>) -> TrafficTier {
+        let age = now.duration_since(from).unwrap_or_default();
+        TrafficTier::ALL
+            .into_iter()
+            .find(|tier| {
+                let fine_enough = match (resolution, tier.width()) {
+                    (Some(wanted), Some(width)) => width >= wanted,
+                    (Some(wanted), None) => wanted <= self.retention.sample_interval,
+                    (None, _) => true,
+                };
+                fine_enough && age <= self.retention.for_tier(*tier)
+            })
+            .unwrap_or(TrafficTier::FiveMinutes)
+    }
+
+    /// Points of `interface` in `[from, to]` from one tier, with gaps as nulls
+    pub async fn query(
+        &self,
+        interface: &str,
+        tier: TrafficTier,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Result<Vec<TrafficHistoryPoint>> {
+        let rows = sqlx::query(
+            "SELECT ts, inbound_bps, outbound_bps FROM traffic_history
+             WHERE interface = ? AND tier = ? AND ts >= ? AND ts <= ?
+             ORDER BY ts",
+        )
+        .bind(interface)
+        .bind(tier as i64)
+        .bind(millis(from) - tier.width().map_or(0, |w| w.as_millis() as i64 - 1))
+        .bind(millis(to))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Config(format!("Failed to query traffic history: {}", e)))?;
+
+        let rows: Vec<(i64, f64, f64)> = rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        Ok(match tier.width() {
+            Some(width) => fill_buckets(&rows, width.as_millis() as i64, millis(from), millis(to)),
+            None => mark_gaps(&rows, self.retention.sample_interval.as_millis() as i64),
+        })
+    }
+}
+
+/// One point per bucket over the range, null where no bucket was stored
+fn fill_buckets(rows: &[(i64, f64, f64)], width: i64, from: i64, to: i64) -> Vec<TrafficHistoryPoint> {
+    let mut points = Vec::new();
+    let mut rows = rows.iter().peekable();
+    let mut slot = from / width * width;
+    while slot <= to {
+        while rows.next_if(|(ts, _, _)| *ts < slot).is_some() {}
+        let point = match rows.next_if(|(ts, _, _)| *ts == slot) {
+            Some((_, inbound, outbound)) => point(slot, Some(*inbound), Some(*outbound)),
+            None => point(slot, None, None),
+        };
+        points.push(point);
+        slot += width;
+    }
+    points
+}
+
+/// Raw points as stored, with a null inserted wherever samples stopped
+/// for more than two sample intervals
+fn mark_gaps(rows: &[(i64, f64, f64)], interval: i64) -> Vec<TrafficHistoryPoint> {
+    let mut points = Vec::with_capacity(rows.len());
+    for (i, (ts, inbound, outbound)) in rows.iter().enumerate() {
+        if let Some((prev, _, _)) = i.checked_sub(1).map(|p| rows[p]) {
+            if ts - prev > 2 * interval {
+                points.push(point(prev + interval, None, None));
+            }
+        }
+        points.push(point(*ts, Some(*inbound), Some(*outbound)));
+    }
+    points
+}
+
+fn point(ts: i64, inbound_bps: Option<f64>, outbound_bps: Option<f64>) -> TrafficHistoryPoint {
+    TrafficHistoryPoint {
+        timestamp: UNIX_EPOCH + Duration::from_millis(ts.max(0) as u64),
+        inbound_bps,
+        outbound_bps,
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn history() -> (tempfile::TempDir, TrafficHistory) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("patronus.db").display());
+        let pool = SqlitePool::connect(&url).await.unwrap();
+        (dir, TrafficHistory::new(pool, TrafficRetention::default()).await.unwrap())
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 / 300 * 300 + secs)
+    }
+
+    async fn record(history: &TrafficHistory, secs: u64, inbound_bps: f64) {
+        let point = TrafficDataPoint { timestamp: at(secs), inbound_bps, outbound_bps: inbound_bps / 2.0 };
+        history.record("wan0", &point).await.unwrap();
+    }
+
+    async fn count(history: &TrafficHistory, tier: TrafficTier) -> i64 {
+        sqlx::query("SELECT COUNT(*) FROM traffic_history WHERE tier = ?")
+            .bind(tier as i64)
+            .fetch_one(&history.pool)
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    #[tokio::test]
+    async fn test_downsampling_is_idempotent() {
+        let (_dir, history) = history().await;
+        // Ten minutes at 10s intervals; the second minute doubles the rate
+        for secs in (0..600).step_by(10) {
+            record(&history, secs, if (60..120).contains(&secs) { 200.0 } else { 100.0 }).await;
+        }
+
+        let now = at(600);
+        history.downsample(now).await.unwrap();
+        let once = history.query("wan0", TrafficTier::OneMinute, at(0), at(599)).await.unwrap();
+        let five = history.query("wan0", TrafficTier::FiveMinutes, at(0), at(599)).await.unwrap();
+
+        history.downsample(now).await.unwrap();
+        assert_eq!(history.query("wan0", TrafficTier::OneMinute, at(0), at(599)).await.unwrap(), once);
+        assert_eq!(history.query("wan0", TrafficTier::FiveMinutes, at(0), at(599)).await.unwrap(), five);
+        assert_eq!(count(&history, TrafficTier::OneMinute).await, 10);
+        assert_eq!(count(&history, TrafficTier::FiveMinutes).await, 2);
+
+        assert_eq!(once[0].inbound_bps, Some(100.0));
+        assert_eq!(once[1].inbound_bps, Some(200.0));
+        assert_eq!(once[1].outbound_bps, Some(100.0));
+        assert_eq!(five[0].inbound_bps, Some(120.0));
+        assert_eq!(five[1].inbound_bps, Some(100.0));
+
+        // Raw ages out after an hour, the averages survive it
+        history.maintain(at(600 + 3600)).await.unwrap();
+        assert_eq!(count(&history, TrafficTier::Raw).await, 0);
+        assert_eq!(count(&history, TrafficTier::OneMinute).await, 10);
+        assert_eq!(
+            history.query("wan0", TrafficTier::OneMinute, at(0), at(599)).await.unwrap(),
+            once
+        );
+
+        // And the minute tier after a day
+        history.maintain(at(600 + 24 * 3600)).await.unwrap();
+        assert_eq!(count(&history, TrafficTier::OneMinute).await, 0);
+        assert_eq!(count(&history, TrafficTier::FiveMinutes).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_gaps_are_null_not_zero() {
+        let (_dir, history) = history().await;
+        for secs in (0..120).step_by(10).chain((300..360).step_by(10)) {
+            record(&history, secs, 50.0).await;
+        }
+        history.downsample(at(600)).await.unwrap();
+
+        let minutes = history.query("wan0", TrafficTier::OneMinute, at(0), at(359)).await.unwrap();
+        let rates: Vec<_> = minutes.iter().map(|p| p.inbound_bps).collect();
+        assert_eq!(rates, vec![Some(50.0), Some(50.0), None, None, None, Some(50.0)]);
+
+        let raw = history.query("wan0", TrafficTier::Raw, at(0), at(359)).await.unwrap();
+        let gaps: Vec<_> = raw.iter().filter(|p| p.inbound_bps.is_none()).collect();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].timestamp, at(120));
+        assert!(raw.iter().all(|p| p.inbound_bps != Some(0.0)));
+    }
+
+    #[tokio::test]
+    async fn test_tier_selection() {
+        let (_dir, history) = history().await;
+        let now = at(0) + Duration::from_secs(40 * 24 * 3600);
+        let ago = |secs: u64| now - Duration::from_secs(secs);
+
+        assert_eq!(history.select_tier(now, ago(900), None), TrafficTier::Raw);
+        assert_eq!(history.select_tier(now, ago(6 * 3600), None), TrafficTier::OneMinute);
+        assert_eq!(history.select_tier(now, ago(7 * 24 * 3600), None), TrafficTier::FiveMinutes);
+        assert_eq!(history.select_tier(now, ago(900), Some(Duration::from_secs(60))), TrafficTier::OneMinute);
+        assert_eq!(history.select_tier(now, ago(900), Some(Duration::from_secs(120))), TrafficTier::FiveMinutes);
+        // Beyond every tier's retention, the coarsest is all there is
+        assert_eq!(history.select_tier(now, ago(35 * 24 * 3600), None), TrafficTier::FiveMinutes);
+    }
+}
@@ -1,10 +1,29 @@
 //! AWS Connectivity
 //!
 //! Connects to AWS VPC, Transit Gateway, and Direct Connect
+//!
+//! Transit Gateway VPNs are built through the EC2 API: a customer gateway
+//! for this node, a VPN connection attached to the Transit Gateway, and the
+//! attachment's route table association and propagations. Every step looks
+//! for what a previous run created before creating anything, so re-running
+//! converges on the requested state. The tunnels' pre-shared keys go to
+//! patronus-secrets and the local IPsec side is configured from what AWS
+//! returns.
 
+use crate::health::{self, ConnectionProbe, LinkType};
+use crate::ipsec::{inside_addresses, LocalIpsec, LocalIpsecTunnel};
 use crate::manager::{CloudConnection, CloudProvider};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use patronus_bgp::config::TimersConfig;
+use patronus_bgp::{AddressFamily, BgpConfig, NeighborConfig};
+use patronus_secrets::{SecretManager, SecretString, SecretType};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// AWS configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,14 +35,243 @@ pub struct AwsConfig {
     pub transit_gateway_id: Option<String>,
 }
 
+/// Runs EC2 API operations
+#[async_trait]
+pub trait Ec2Api: Send + Sync {
+    /// Run `operation` (the CLI name, e.g. `describe-vpn-connections`) and
+    /// return its JSON output, `Null` when it prints nothing
+    async fn call(&self, operation: &str, args: &[String]) -> Result<Value>;
+}
+
+/// [`Ec2Api`] through the `aws` command line client
+pub struct AwsCli {
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl AwsCli {
+    pub fn new(config: &AwsConfig) -> Self {
+        Self {
+            region: config.region.clone(),
+            access_key_id: config.access_key_id.clone(),
+            secret_access_key: config.secret_access_key.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Ec2Api for AwsCli {
+    async fn call(&self, operation: &str, args: &[String]) -> Result<Value> {
+        let output = tokio::process::Command::new("aws")
+            .args(["ec2", operation])
+            .args(args)
+            .args(["--region", &self.region, "--output", "json"])
+            .env("AWS_ACCESS_KEY_ID", &self.access_key_id)
+            .env("AWS_SECRET_ACCESS_KEY", &self.secret_access_key)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("Failed to run aws")?;
+        if !output.status.success() {
+            anyhow::bail!("aws ec2 {}: {}", operation, String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&stdout).with_context(|| format!("Unexpected output from aws ec2 {}", operation))
+    }
+}
+
+/// A Transit Gateway and the ASN of its BGP speakers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitGateway {
+    pub id: String,
+    pub amazon_asn: u32,
+}
+
+/// How on-premises prefixes reach the Transit Gateway
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TgwRouting {
+    /// Static routes to `prefixes` through the attachment in every
+    /// propagation route table
+    Static { prefixes: Vec<String> },
+    /// BGP over both tunnels; routes are propagated from the sessions
+    Bgp { asn: u32 },
+}
+
+/// VPN from this node to a Transit Gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TgwVpnSpec {
+    /// Tags the customer gateway and VPN connection, and names the local tunnels
+    pub name: String,
+    /// This node's public address
+    pub public_ip: Ipv4Addr,
+    pub routing: TgwRouting,
+    /// Route table the attachment forwards with; left alone when unset
+    #[serde(default)]
+    pub association_route_table: Option<String>,
+    /// Route tables that learn the on-premises prefixes. Propagation is
+    /// disabled on any other table, including the default one.
+    #[serde(default)]
+    pub propagation_route_tables: Vec<String>,
+    /// Cloud prefixes reached over the tunnels with static routing
+    #[serde(default)]
+    pub cloud_prefixes: Vec<String>,
+}
+
+/// Customer gateway ASN for statically routed VPNs, which AWS still requires
+const STATIC_CUSTOMER_ASN: u32 = 65000;
+
+/// One of the two tunnels of a VPN connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TgwVpnTunnel {
+    /// AWS end of the tunnel
+    pub outside_ip: Ipv4Addr,
+    /// /30 inside the tunnel; AWS takes the first address and we the second
+    pub inside_cidr: String,
+    pub aws_inside_ip: Ipv4Addr,
+    pub local_inside_ip: Ipv4Addr,
+    /// patronus-secrets key of the tunnel's pre-shared key
+    pub psk_secret: String,
+}
+
+/// A VPN attached to a Transit Gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TgwVpnAttachment {
+    pub name: String,
+    pub region: String,
+    pub transit_gateway: TransitGateway,
+    pub customer_gateway_id: String,
+    pub vpn_connection_id: String,
+    pub attachment_id: String,
+    pub public_ip: Ipv4Addr,
+    pub routing: TgwRouting,
+    pub cloud_prefixes: Vec<String>,
+    pub propagation_route_tables: Vec<String>,
+    pub tunnels: Vec<TgwVpnTunnel>,
+}
+
+impl TgwVpnAttachment {
+    /// BGP neighbors for the AWS end of each tunnel; none with static routing
+    pub fn bgp_neighbors(&self) -> Vec<NeighborConfig> {
+        if !matches!(self.routing, TgwRouting::Bgp { .. }) {
+            return Vec::new();
+        }
+        self.tunnels
+            .iter()
+            .enumerate()
+            .map(|(i, tunnel)| NeighborConfig {
+                ip: tunnel.aws_inside_ip.into(),
+                asn: self.transit_gateway.amazon_asn,
+                description: Some(format!("{} tunnel {} ({})", self.vpn_connection_id, i + 1, self.transit_gateway.id)),
+                password: None,
+                // AWS drops the session after 30 s without keepalives
+                timers: Some(TimersConfig { keepalive_secs: 10, holdtime_secs: 30, ..TimersConfig::default() }),
+                route_map_in: None,
+                route_map_out: None,
+                next_hop_self: false,
+                address_families: vec![AddressFamily::Ipv4Unicast],
+                prefix_limit: None,
+            })
+            .collect()
+    }
+
+    /// Add or replace this VPN's neighbors in `config`
+    pub fn apply_bgp(&self, config: &mut BgpConfig) {
+        for neighbor in self.bgp_neighbors() {
+            config.neighbors.retain(|n| n.ip != neighbor.ip);
+            config.neighbors.push(neighbor);
+        }
+    }
+
+    /// Local side of each tunnel
+    pub fn local_tunnels(&self) -> Vec<LocalIpsecTunnel> {
+        let (local_ts, remote_ts) = match &self.routing {
+            TgwRouting::Static { prefixes } => (prefixes.clone(), self.cloud_prefixes.clone()),
+            // Route based: BGP decides what goes into the tunnel
+            TgwRouting::Bgp { .. } => (vec!["0.0.0.0/0".to_string()], vec!["0.0.0.0/0".to_string()]),
+        };
+        self.tunnels
+            .iter()
+            .enumerate()
+            .map(|(i, tunnel)| LocalIpsecTunnel {
+                name: format!("{}-t{}", self.name, i + 1),
+                local_address: self.public_ip,
+                remote_address: tunnel.outside_ip,
+                local_inside_ip: tunnel.local_inside_ip,
+                remote_inside_ip: tunnel.aws_inside_ip,
+                inside_prefix_len: 30,
+                local_subnets: local_ts.clone(),
+                remote_subnets: remote_ts.clone(),
+                psk_secret: tunnel.psk_secret.clone(),
+            })
+            .collect()
+    }
+
+    /// The VPN as a cloud connection over its first tunnel
+    pub fn connection(&self) -> CloudConnection {
+        let tunnel = self.tunnels.first();
+        CloudConnection {
+            provider: CloudProvider::AWS,
+            region: self.region.clone(),
+            vpc_id: self.transit_gateway.id.clone(),
+            local_ip: tunnel.map(|t| t.local_inside_ip.to_string()).unwrap_or_default(),
+            remote_ip: tunnel.map(|t| t.aws_inside_ip.to_string()).unwrap_or_default(),
+            tunnel_id: 1,
+            connected: true,
+            latency_ms: 0.0,
+        }
+    }
+}
+
 /// AWS connector
 pub struct AwsConnector {
     config: AwsConfig,
+    ec2: Arc<dyn Ec2Api>,
+    secrets: Option<Arc<SecretManager>>,
+    ipsec: Option<Arc<dyn LocalIpsec>>,
+    poll_interval: Duration,
+    max_polls: u32,
 }
 
 impl AwsConnector {
     pub fn new(config: AwsConfig) -> Self {
-        Self { config }
+        Self {
+            ec2: Arc::new(AwsCli::new(&config)),
+            config,
+            secrets: None,
+            ipsec: None,
+            poll_interval: Duration::from_secs(10),
+            max_polls: 60,
+        }
+    }
+
+    /// Reach EC2 through something other than the `aws` client
+    pub fn with_ec2_api(mut self, ec2: Arc<dyn Ec2Api>) -> Self {
+        self.ec2 = ec2;
+        self
+    }
+
+    /// Where tunnel pre-shared keys are kept
+    pub fn with_secrets(mut self, secrets: Arc<SecretManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Configure the local side of Transit Gateway VPNs
+    pub fn with_local_ipsec(mut self, ipsec: Arc<dyn LocalIpsec>) -> Self {
+        self.ipsec = Some(ipsec);
+        self
+    }
+
+    /// How often and how long to wait for AWS resources to settle
+    pub fn with_polling(mut self, interval: Duration, max_polls: u32) -> Self {
+        self.poll_interval = interval;
+        self.max_polls = max_polls.max(1);
+        self
     }
 
     /// Connect to AWS VPC
@@ -83,6 +331,446 @@ impl AwsConnector {
 
         Ok(())
     }
+
+    /// The configured Transit Gateway
+    pub async fn transit_gateway(&self) -> Result<TransitGateway> {
+        let tgw_id = self.config.transit_gateway_id.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No Transit Gateway ID configured"))?;
+        let output = self.ec2.call("describe-transit-gateways", &["--transit-gateway-ids".to_string(), tgw_id.clone()]).await?;
+        list(&output, "TransitGateways")
+            .first()
+            .and_then(transit_gateway_of)
+            .ok_or_else(|| anyhow::anyhow!("Transit Gateway {} not found", tgw_id))
+    }
+
+    /// Find the Transit Gateway tagged `name`, creating it if there is none
+    pub async fn ensure_transit_gateway(&self, name: &str, amazon_asn: u32) -> Result<TransitGateway> {
+        let filters = filters(&[("tag:Name", name), ("state", "pending,available")]);
+        let output = self.ec2.call("describe-transit-gateways", &filters).await?;
+        let tgw = match list(&output, "TransitGateways").first() {
+            Some(tgw) => tgw.clone(),
+            None => {
+                tracing::info!("Creating Transit Gateway {} (ASN {})", name, amazon_asn);
+                let output = self.ec2.call("create-transit-gateway", &[
+                    "--description".to_string(), format!("patronus {}", name),
+                    "--options".to_string(), format!(r#"{{"AmazonSideAsn":{}}}"#, amazon_asn),
+                    "--tag-specifications".to_string(), tag_spec("transit-gateway", name),
+                ]).await?;
+                output.get("TransitGateway").cloned().unwrap_or(Value::Null)
+            }
+        };
+        let tgw_id = str_at(&tgw, "TransitGatewayId").context("Transit Gateway without an ID")?;
+
+        self.poll(&format!("Transit Gateway {} to become available", tgw_id), || async move {
+            let output = self.ec2.call("describe-transit-gateways", &["--transit-gateway-ids".to_string(), tgw_id.to_string()]).await?;
+            Ok(list(&output, "TransitGateways")
+                .first()
+                .filter(|tgw| str_at(tgw, "State") == Some("available"))
+                .and_then(transit_gateway_of))
+        })
+        .await
+    }
+
+    /// Bring up a VPN from this node to `tgw`
+    ///
+    /// Reuses the customer gateway and VPN connection of an earlier run for
+    /// the same public address. The attachment is accepted if it waits for
+    /// acceptance, associated with the requested route table and propagated
+    /// to exactly the requested ones; with static routing the on-premises
+    /// prefixes are routed to it in each of those tables instead. The tunnel
+    /// keys are stored in patronus-secrets and, when a [`LocalIpsec`] is set,
+    /// both tunnels are configured locally. BGP neighbors for the tunnels
+    /// come from [`TgwVpnAttachment::bgp_neighbors`].
+    pub async fn connect_transit_gateway_vpn(&self, tgw: &TransitGateway, spec: &TgwVpnSpec) -> Result<TgwVpnAttachment> {
+        let secrets = self.secrets.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("A secret manager is needed to keep the tunnel pre-shared keys"))?;
+        let customer_asn = match spec.routing {
+            TgwRouting::Static { .. } => STATIC_CUSTOMER_ASN,
+            TgwRouting::Bgp { asn } => asn,
+        };
+        let static_only = matches!(spec.routing, TgwRouting::Static { .. });
+
+        let customer_gateway_id = self.ensure_customer_gateway(spec, customer_asn).await?;
+        let vpn = match self.find_vpn(&tgw.id, &customer_gateway_id).await? {
+            Some(vpn) => {
+                if vpn_static_only(&vpn) != static_only {
+                    anyhow::bail!(
+                        "VPN connection {} to {} exists with different routing; tear it down first",
+                        str_at(&vpn, "VpnConnectionId").unwrap_or_default(), tgw.id
+                    );
+                }
+                vpn
+            }
+            None => {
+                tracing::info!("Creating VPN connection {} to Transit Gateway {}", spec.name, tgw.id);
+                let output = self.ec2.call("create-vpn-connection", &[
+                    "--type".to_string(), "ipsec.1".to_string(),
+                    "--customer-gateway-id".to_string(), customer_gateway_id.clone(),
+                    "--transit-gateway-id".to_string(), tgw.id.clone(),
+                    "--options".to_string(), format!(r#"{{"StaticRoutesOnly":{}}}"#, static_only),
+                    "--tag-specifications".to_string(), tag_spec("vpn-connection", &spec.name),
+                ]).await?;
+                output.get("VpnConnection").cloned().context("create-vpn-connection returned no connection")?
+            }
+        };
+        let vpn_connection_id = str_at(&vpn, "VpnConnectionId").context("VPN connection without an ID")?.to_string();
+
+        let attachment_id = self.await_attachment(&tgw.id, &vpn_connection_id).await?;
+        self.converge_association(&attachment_id, spec.association_route_table.as_deref()).await?;
+        self.converge_propagations(&attachment_id, &spec.propagation_route_tables).await?;
+        if let TgwRouting::Static { prefixes } = &spec.routing {
+            for table in &spec.propagation_route_tables {
+                self.converge_static_routes(table, &attachment_id, prefixes).await?;
+            }
+        }
+
+        // Keys are only returned in full by describe, not always on create
+        let vpn = self.describe_vpn(&vpn_connection_id).await?.unwrap_or(vpn);
+        let mut tunnels = Vec::new();
+        for (i, options) in vpn.pointer("/Options/TunnelOptions").map(list_of).unwrap_or_default().iter().enumerate() {
+            let outside_ip = str_at(options, "OutsideIpAddress").and_then(|ip| ip.parse().ok())
+                .with_context(|| format!("Tunnel {} of {} has no outside address", i + 1, vpn_connection_id))?;
+            let inside_cidr = str_at(options, "TunnelInsideCidr")
+                .with_context(|| format!("Tunnel {} of {} has no inside CIDR", i + 1, vpn_connection_id))?;
+            let (aws_inside_ip, local_inside_ip) = inside_addresses(inside_cidr)?;
+            let psk = str_at(options, "PreSharedKey")
+                .with_context(|| format!("Tunnel {} of {} has no pre-shared key", i + 1, vpn_connection_id))?;
+
+            let psk_secret = format!("aws/tgw/{}/tunnel{}", vpn_connection_id, i + 1);
+            // AWS keys are alphanumeric, short of the IPsec PSK strength policy
+            secrets.store_secret(
+                &psk_secret,
+                SecretString::from(psk),
+                SecretType::General,
+                format!("Pre-shared key of {} tunnel {}", vpn_connection_id, i + 1),
+                None,
+            ).await?;
+            tunnels.push(TgwVpnTunnel {
+                outside_ip,
+                inside_cidr: inside_cidr.to_string(),
+                aws_inside_ip,
+                local_inside_ip,
+                psk_secret,
+            });
+        }
+
+        let attachment = TgwVpnAttachment {
+            name: spec.name.clone(),
+            region: self.config.region.clone(),
+            transit_gateway: tgw.clone(),
+            customer_gateway_id,
+            vpn_connection_id,
+            attachment_id,
+            public_ip: spec.public_ip,
+            routing: spec.routing.clone(),
+            cloud_prefixes: spec.cloud_prefixes.clone(),
+            propagation_route_tables: spec.propagation_route_tables.clone(),
+            tunnels,
+        };
+
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                let psk = secrets.get_secret(&tunnel.psk_secret).await?
+                    .ok_or_else(|| anyhow::anyhow!("Pre-shared key {} went missing", tunnel.psk_secret))?;
+                ipsec.configure(&tunnel, &psk).await?;
+            }
+        }
+
+        tracing::info!(
+            "VPN {} attached to Transit Gateway {} as {}",
+            attachment.vpn_connection_id, tgw.id, attachment.attachment_id
+        );
+        Ok(attachment)
+    }
+
+    /// Remove a Transit Gateway VPN
+    ///
+    /// Static routes and propagations go first, then the association, and
+    /// only then the VPN connection and its attachment. The customer gateway
+    /// is deleted once no other VPN uses it. Parts already gone are skipped,
+    /// so an interrupted teardown can be re-run.
+    pub async fn teardown_transit_gateway_vpn(&self, attachment: &TgwVpnAttachment) -> Result<()> {
+        let attachment_id = &attachment.attachment_id;
+        let live = self.describe_attachment(attachment_id).await?
+            .filter(|a| !matches!(str_at(a, "State"), Some("deleting" | "deleted")));
+
+        if let Some(live) = &live {
+            if matches!(attachment.routing, TgwRouting::Static { .. }) {
+                for table in &attachment.propagation_route_tables {
+                    self.converge_static_routes(table, attachment_id, &[]).await?;
+                }
+            }
+            self.converge_propagations(attachment_id, &[]).await?;
+            if let Some(table) = live.pointer("/Association/TransitGatewayRouteTableId").and_then(Value::as_str) {
+                self.disassociate(table, attachment_id).await?;
+            }
+        }
+
+        let vpn_id = attachment.vpn_connection_id.as_str();
+        if self.describe_vpn(vpn_id).await?.is_some() {
+            tracing::info!("Deleting VPN connection {}", vpn_id);
+            self.ec2.call("delete-vpn-connection", &["--vpn-connection-id".to_string(), vpn_id.to_string()]).await?;
+            self.poll(&format!("VPN connection {} to be deleted", vpn_id), || async move {
+                Ok(self.describe_vpn(vpn_id).await?.is_none().then_some(()))
+            })
+            .await?;
+        }
+
+        let cgw_filter = filters(&[("customer-gateway-id", &attachment.customer_gateway_id)]);
+        let output = self.ec2.call("describe-vpn-connections", &cgw_filter).await?;
+        let in_use = list(&output, "VpnConnections").iter().any(|vpn| !vpn_gone(vpn));
+        if !in_use {
+            let output = self.ec2.call("describe-customer-gateways", &cgw_filter).await?;
+            let exists = list(&output, "CustomerGateways").iter()
+                .any(|cgw| !matches!(str_at(cgw, "State"), Some("deleting" | "deleted")));
+            if exists {
+                self.ec2.call("delete-customer-gateway", &["--customer-gateway-id".to_string(), attachment.customer_gateway_id.clone()]).await?;
+            }
+        }
+
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                ipsec.remove(&tunnel).await?;
+            }
+        }
+        if let Some(secrets) = &self.secrets {
+            for tunnel in &attachment.tunnels {
+                if secrets.get_secret(&tunnel.psk_secret).await?.is_some() {
+                    secrets.delete_secret(&tunnel.psk_secret).await?;
+                }
+            }
+        }
+
+        tracing::info!("Removed VPN {} from Transit Gateway {}", vpn_id, attachment.transit_gateway.id);
+        Ok(())
+    }
+
+    async fn ensure_customer_gateway(&self, spec: &TgwVpnSpec, asn: u32) -> Result<String> {
+        let ip = spec.public_ip.to_string();
+        let output = self.ec2.call("describe-customer-gateways", &filters(&[
+            ("ip-address", &ip),
+            ("bgp-asn", &asn.to_string()),
+            ("state", "pending,available"),
+        ])).await?;
+        if let Some(id) = list(&output, "CustomerGateways").first().and_then(|cgw| str_at(cgw, "CustomerGatewayId")) {
+            return Ok(id.to_string());
+        }
+
+        tracing::info!("Creating customer gateway {} for {}", spec.name, ip);
+        let output = self.ec2.call("create-customer-gateway", &[
+            "--type".to_string(), "ipsec.1".to_string(),
+            "--public-ip".to_string(), ip,
+            "--bgp-asn".to_string(), asn.to_string(),
+            "--tag-specifications".to_string(), tag_spec("customer-gateway", &spec.name),
+        ]).await?;
+        output.pointer("/CustomerGateway/CustomerGatewayId")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .context("create-customer-gateway returned no ID")
+    }
+
+    async fn find_vpn(&self, tgw_id: &str, customer_gateway_id: &str) -> Result<Option<Value>> {
+        let output = self.ec2.call("describe-vpn-connections", &filters(&[
+            ("customer-gateway-id", customer_gateway_id),
+            ("transit-gateway-id", tgw_id),
+        ])).await?;
+        Ok(list(&output, "VpnConnections").into_iter().find(|vpn| !vpn_gone(vpn)))
+    }
+
+    async fn describe_vpn(&self, vpn_id: &str) -> Result<Option<Value>> {
+        let output = self.ec2.call("describe-vpn-connections", &filters(&[("vpn-connection-id", vpn_id)])).await?;
+        Ok(list(&output, "VpnConnections").into_iter().find(|vpn| !vpn_gone(vpn)))
+    }
+
+    async fn describe_attachment(&self, attachment_id: &str) -> Result<Option<Value>> {
+        let output = self.ec2.call("describe-transit-gateway-attachments", &filters(&[
+            ("transit-gateway-attachment-id", attachment_id),
+        ])).await?;
+        Ok(list(&output, "TransitGatewayAttachments").into_iter().next())
+    }
+
+    /// Wait for the VPN's attachment to become available, accepting it if
+    /// it needs to be
+    async fn await_attachment(&self, tgw_id: &str, vpn_connection_id: &str) -> Result<String> {
+        self.poll(&format!("attachment of {} to become available", vpn_connection_id), || async move {
+            let output = self.ec2.call("describe-transit-gateway-attachments", &filters(&[
+                ("resource-id", vpn_connection_id),
+                ("transit-gateway-id", tgw_id),
+            ])).await?;
+            let Some(attachment) = list(&output, "TransitGatewayAttachments").into_iter()
+                .find(|a| !matches!(str_at(a, "State"), Some("deleting" | "deleted" | "failed" | "rejected")))
+            else {
+                return Ok(None);
+            };
+            let id = str_at(&attachment, "TransitGatewayAttachmentId").context("Attachment without an ID")?.to_string();
+            match str_at(&attachment, "State") {
+                Some("available") => Ok(Some(id)),
+                Some("pendingAcceptance") => {
+                    tracing::info!("Accepting Transit Gateway attachment {}", id);
+                    self.ec2.call("accept-transit-gateway-vpc-attachment", &["--transit-gateway-attachment-id".to_string(), id]).await?;
+                    Ok(None)
+                }
+                _ => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn converge_association(&self, attachment_id: &str, table: Option<&str>) -> Result<()> {
+        let Some(table) = table else { return Ok(()) };
+        let attachment = self.describe_attachment(attachment_id).await?;
+        let current = attachment.as_ref()
+            .and_then(|a| a.pointer("/Association/TransitGatewayRouteTableId"))
+            .and_then(Value::as_str);
+        match current {
+            Some(current) if current == table => return Ok(()),
+            Some(current) => self.disassociate(current, attachment_id).await?,
+            None => {}
+        }
+
+        tracing::info!("Associating {} with route table {}", attachment_id, table);
+        self.ec2.call("associate-transit-gateway-route-table", &[
+            "--transit-gateway-route-table-id".to_string(), table.to_string(),
+            "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+        ]).await?;
+        Ok(())
+    }
+
+    async fn disassociate(&self, table: &str, attachment_id: &str) -> Result<()> {
+        tracing::info!("Disassociating {} from route table {}", attachment_id, table);
+        self.ec2.call("disassociate-transit-gateway-route-table", &[
+            "--transit-gateway-route-table-id".to_string(), table.to_string(),
+            "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+        ]).await?;
+        self.poll(&format!("{} to leave route table {}", attachment_id, table), || async move {
+            let attachment = self.describe_attachment(attachment_id).await?;
+            let associated = attachment.as_ref()
+                .and_then(|a| a.pointer("/Association/TransitGatewayRouteTableId"))
+                .is_some();
+            Ok((!associated).then_some(()))
+        })
+        .await
+    }
+
+    /// Propagate the attachment to exactly `tables`
+    async fn converge_propagations(&self, attachment_id: &str, tables: &[String]) -> Result<()> {
+        let output = self.ec2.call("get-transit-gateway-attachment-propagations", &[
+            "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+        ]).await?;
+        let current: Vec<String> = list(&output, "TransitGatewayAttachmentPropagations").iter()
+            .filter(|p| matches!(str_at(p, "State"), Some("enabled" | "enabling")))
+            .filter_map(|p| str_at(p, "TransitGatewayRouteTableId").map(str::to_string))
+            .collect();
+
+        for table in current.iter().filter(|t| !tables.contains(t)) {
+            tracing::info!("Disabling propagation of {} to {}", attachment_id, table);
+            self.ec2.call("disable-transit-gateway-route-table-propagation", &[
+                "--transit-gateway-route-table-id".to_string(), table.clone(),
+                "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+            ]).await?;
+        }
+        for table in tables.iter().filter(|t| !current.contains(t)) {
+            tracing::info!("Enabling propagation of {} to {}", attachment_id, table);
+            self.ec2.call("enable-transit-gateway-route-table-propagation", &[
+                "--transit-gateway-route-table-id".to_string(), table.clone(),
+                "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+            ]).await?;
+        }
+        Ok(())
+    }
+
+    /// Route exactly `prefixes` to the attachment in `table`
+    async fn converge_static_routes(&self, table: &str, attachment_id: &str, prefixes: &[String]) -> Result<()> {
+        let mut args = vec!["--transit-gateway-route-table-id".to_string(), table.to_string()];
+        args.extend(filters(&[
+            ("attachment.transit-gateway-attachment-id", attachment_id),
+            ("type", "static"),
+        ]));
+        let output = self.ec2.call("search-transit-gateway-routes", &args).await?;
+        let current: Vec<String> = list(&output, "Routes").iter()
+            .filter(|r| str_at(r, "State") != Some("deleting"))
+            .filter_map(|r| str_at(r, "DestinationCidrBlock").map(str::to_string))
+            .collect();
+
+        for cidr in current.iter().filter(|c| !prefixes.contains(c)) {
+            self.ec2.call("delete-transit-gateway-route", &[
+                "--transit-gateway-route-table-id".to_string(), table.to_string(),
+                "--destination-cidr-block".to_string(), cidr.clone(),
+            ]).await?;
+        }
+        for cidr in prefixes.iter().filter(|p| !current.contains(p)) {
+            self.ec2.call("create-transit-gateway-route", &[
+                "--transit-gateway-route-table-id".to_string(), table.to_string(),
+                "--destination-cidr-block".to_string(), cidr.clone(),
+                "--transit-gateway-attachment-id".to_string(), attachment_id.to_string(),
+            ]).await?;
+        }
+        Ok(())
+    }
+
+    /// Retry `check` until it yields a value
+    async fn poll<T, F, Fut>(&self, what: &str, mut check: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Option<T>>>,
+    {
+        for attempt in 0..self.max_polls {
+            if attempt > 0 {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+            if let Some(value) = check().await? {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("Timed out waiting for {}", what)
+    }
+
+    /// Health probe for a connection made by this connector
+    pub fn health_probe(&self, link: LinkType) -> Arc<dyn ConnectionProbe> {
+        health::probe_for(CloudProvider::AWS, link)
+    }
+}
+
+/// `--filters` arguments for the EC2 CLI
+fn filters(filters: &[(&str, &str)]) -> Vec<String> {
+    let mut args = vec!["--filters".to_string()];
+    args.extend(filters.iter().map(|(name, values)| format!("Name={},Values={}", name, values)));
+    args
+}
+
+fn tag_spec(resource_type: &str, name: &str) -> String {
+    serde_json::json!([{
+        "ResourceType": resource_type,
+        "Tags": [{ "Key": "Name", "Value": name }],
+    }])
+    .to_string()
+}
+
+fn str_at<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn list(value: &Value, key: &str) -> Vec<Value> {
+    value.get(key).map(list_of).unwrap_or_default()
+}
+
+fn list_of(value: &Value) -> Vec<Value> {
+    value.as_array().cloned().unwrap_or_default()
+}
+
+fn transit_gateway_of(tgw: &Value) -> Option<TransitGateway> {
+    Some(TransitGateway {
+        id: str_at(tgw, "TransitGatewayId")?.to_string(),
+        amazon_asn: tgw.pointer("/Options/AmazonSideAsn")?.as_u64()? as u32,
+    })
+}
+
+fn vpn_gone(vpn: &Value) -> bool {
+    matches!(str_at(vpn, "State"), Some("deleting" | "deleted"))
+}
+
+fn vpn_static_only(vpn: &Value) -> bool {
+    vpn.pointer("/Options/StaticRoutesOnly").and_then(Value::as_bool).unwrap_or(false)
 }
 
 #[cfg(test)]
@@ -106,4 +794,416 @@ mod tests {
         assert_eq!(connection.region, "us-east-1");
         assert!(connection.connected);
     }
+
+    use patronus_secrets::MemoryStore;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// EC2 kept in memory; resources settle immediately
+    #[derive(Default)]
+    struct FakeEc2 {
+        state: Mutex<Ec2State>,
+    }
+
+    #[derive(Default)]
+    struct Ec2State {
+        tgws: Vec<Value>,
+        cgws: Vec<Value>,
+        vpns: Vec<Value>,
+        attachments: Vec<Value>,
+        /// (attachment, route table)
+        propagations: Vec<(String, String)>,
+        /// (route table, destination, attachment)
+        routes: Vec<(String, String, String)>,
+        calls: Vec<String>,
+        next_id: u32,
+        require_acceptance: bool,
+    }
+
+    fn arg(args: &[String], name: &str) -> String {
+        let i = args.iter().position(|a| a == name).unwrap_or_else(|| panic!("missing {}", name));
+        args[i + 1].clone()
+    }
+
+    fn matches_filters(item: &Value, args: &[String]) -> bool {
+        args.iter().filter_map(|a| a.strip_prefix("Name=")).all(|filter| {
+            let (name, values) = filter.split_once(",Values=").unwrap();
+            let key = match name {
+                "tag:Name" => "Name",
+                "state" => "State",
+                "ip-address" => "IpAddress",
+                "bgp-asn" => "BgpAsn",
+                "customer-gateway-id" => "CustomerGatewayId",
+                "transit-gateway-id" => "TransitGatewayId",
+                "vpn-connection-id" => "VpnConnectionId",
+                "resource-id" => "ResourceId",
+                "transit-gateway-attachment-id" => "TransitGatewayAttachmentId",
+                other => panic!("unexpected filter {}", other),
+            };
+            let value = match &item[key] {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            values.split(',').any(|v| v == value)
+        })
+    }
+
+    impl Ec2State {
+        fn id(&mut self, prefix: &str) -> String {
+            self.next_id += 1;
+            format!("{}-{:04}", prefix, self.next_id)
+        }
+
+        fn attachment(&mut self, id: &str) -> &mut Value {
+            self.attachments.iter_mut().find(|a| a["TransitGatewayAttachmentId"] == id).unwrap()
+        }
+
+        fn calls(&self, op: &str) -> usize {
+            self.calls.iter().filter(|c| *c == op).count()
+        }
+
+        fn position(&self, op: &str) -> usize {
+            self.calls.iter().position(|c| c == op).unwrap_or_else(|| panic!("{} not called", op))
+        }
+    }
+
+    #[async_trait]
+    impl Ec2Api for FakeEc2 {
+        async fn call(&self, operation: &str, args: &[String]) -> Result<Value> {
+            let mut state = self.state.lock().unwrap();
+            state.calls.push(operation.to_string());
+            let filtered = |items: &[Value]| -> Vec<Value> {
+                items.iter().filter(|i| matches_filters(i, args)).cloned().collect()
+            };
+            Ok(match operation {
+                "describe-transit-gateways" => {
+                    let tgws = match args.iter().position(|a| a == "--transit-gateway-ids") {
+                        Some(i) => state.tgws.iter().filter(|t| t["TransitGatewayId"] == args[i + 1]).cloned().collect(),
+                        None => filtered(&state.tgws),
+                    };
+                    json!({ "TransitGateways": tgws })
+                }
+                "create-transit-gateway" => {
+                    let options: Value = serde_json::from_str(&arg(args, "--options")).unwrap();
+                    let tags: Value = serde_json::from_str(&arg(args, "--tag-specifications")).unwrap();
+                    let tgw = json!({
+                        "TransitGatewayId": state.id("tgw"),
+                        "State": "available",
+                        "Name": tags[0]["Tags"][0]["Value"],
+                        "Options": { "AmazonSideAsn": options["AmazonSideAsn"] },
+                    });
+                    state.tgws.push(tgw.clone());
+                    json!({ "TransitGateway": tgw })
+                }
+                "describe-customer-gateways" => json!({ "CustomerGateways": filtered(&state.cgws) }),
+                "create-customer-gateway" => {
+                    let cgw = json!({
+                        "CustomerGatewayId": state.id("cgw"),
+                        "IpAddress": arg(args, "--public-ip"),
+                        "BgpAsn": arg(args, "--bgp-asn"),
+                        "State": "available",
+                    });
+                    state.cgws.push(cgw.clone());
+                    json!({ "CustomerGateway": cgw })
+                }
+                "delete-customer-gateway" => {
+                    let id = arg(args, "--customer-gateway-id");
+                    let cgw = state.cgws.iter_mut().find(|c| c["CustomerGatewayId"] == id).unwrap();
+                    cgw["State"] = json!("deleted");
+                    Value::Null
+                }
+                "describe-vpn-connections" => json!({ "VpnConnections": filtered(&state.vpns) }),
+                "create-vpn-connection" => {
+                    let options: Value = serde_json::from_str(&arg(args, "--options")).unwrap();
+                    let vpn_id = state.id("vpn");
+                    let tgw_id = arg(args, "--transit-gateway-id");
+                    let vpn = json!({
+                        "VpnConnectionId": vpn_id,
+                        "CustomerGatewayId": arg(args, "--customer-gateway-id"),
+                        "TransitGatewayId": tgw_id,
+                        "State": "available",
+                        "Options": {
+                            "StaticRoutesOnly": options["StaticRoutesOnly"],
+                            "TunnelOptions": [
+                                { "OutsideIpAddress": "52.0.0.1", "TunnelInsideCidr": "169.254.10.0/30", "PreSharedKey": "Tunnel1Key_abcdef" },
+                                { "OutsideIpAddress": "52.0.0.2", "TunnelInsideCidr": "169.254.10.4/30", "PreSharedKey": "Tunnel2Key_abcdef" },
+                            ],
+                        },
+                    });
+                    state.vpns.push(vpn.clone());
+
+                    // New attachments go to the default route table
+                    let attachment_id = state.id("tgw-attach");
+                    let attachment_state = if state.require_acceptance { "pendingAcceptance" } else { "available" };
+                    state.attachments.push(json!({
+                        "TransitGatewayAttachmentId": attachment_id,
+                        "TransitGatewayId": tgw_id,
+                        "ResourceId": vpn_id,
+                        "ResourceType": "vpn",
+                        "State": attachment_state,
+                        "Association": { "TransitGatewayRouteTableId": "tgw-rtb-default", "State": "associated" },
+                    }));
+                    state.propagations.push((attachment_id, "tgw-rtb-default".to_string()));
+
+                    // Keys are left out of the create response
+                    let mut created = vpn;
+                    created["Options"]["TunnelOptions"] = json!([]);
+                    json!({ "VpnConnection": created })
+                }
+                "delete-vpn-connection" => {
+                    let id = arg(args, "--vpn-connection-id");
+                    state.vpns.iter_mut().find(|v| v["VpnConnectionId"] == id).unwrap()["State"] = json!("deleted");
+                    for attachment in state.attachments.iter_mut().filter(|a| a["ResourceId"] == id) {
+                        attachment["State"] = json!("deleted");
+                    }
+                    Value::Null
+                }
+                "describe-transit-gateway-attachments" => json!({ "TransitGatewayAttachments": filtered(&state.attachments) }),
+                "accept-transit-gateway-vpc-attachment" => {
+                    let id = arg(args, "--transit-gateway-attachment-id");
+                    state.attachment(&id)["State"] = json!("available");
+                    Value::Null
+                }
+                "associate-transit-gateway-route-table" => {
+                    let id = arg(args, "--transit-gateway-attachment-id");
+                    let table = arg(args, "--transit-gateway-route-table-id");
+                    let attachment = state.attachment(&id);
+                    assert!(attachment.get("Association").is_none(), "already associated");
+                    attachment["Association"] = json!({ "TransitGatewayRouteTableId": table, "State": "associated" });
+                    Value::Null
+                }
+                "disassociate-transit-gateway-route-table" => {
+                    let id = arg(args, "--transit-gateway-attachment-id");
+                    state.attachment(&id).as_object_mut().unwrap().remove("Association");
+                    Value::Null
+                }
+                "get-transit-gateway-attachment-propagations" => {
+                    let id = arg(args, "--transit-gateway-attachment-id");
+                    let propagations: Vec<Value> = state.propagations.iter()
+                        .filter(|(a, _)| *a == id)
+                        .map(|(_, t)| json!({ "TransitGatewayRouteTableId": t, "State": "enabled" }))
+                        .collect();
+                    json!({ "TransitGatewayAttachmentPropagations": propagations })
+                }
+                "enable-transit-gateway-route-table-propagation" => {
+                    let entry = (arg(args, "--transit-gateway-attachment-id"), arg(args, "--transit-gateway-route-table-id"));
+                    state.propagations.push(entry);
+                    Value::Null
+                }
+                "disable-transit-gateway-route-table-propagation" => {
+                    let entry = (arg(args, "--transit-gateway-attachment-id"), arg(args, "--transit-gateway-route-table-id"));
+                    state.propagations.retain(|p| *p != entry);
+                    Value::Null
+                }
+                "search-transit-gateway-routes" => {
+                    let table = arg(args, "--transit-gateway-route-table-id");
+                    let attachment = args.iter()
+                        .find_map(|a| a.strip_prefix("Name=attachment.transit-gateway-attachment-id,Values="))
+                        .unwrap();
+                    let routes: Vec<Value> = state.routes.iter()
+                        .filter(|(t, _, a)| *t == table && a == attachment)
+                        .map(|(_, cidr, _)| json!({ "DestinationCidrBlock": cidr, "Type": "static", "State": "active" }))
+                        .collect();
+                    json!({ "Routes": routes })
+                }
+                "create-transit-gateway-route" => {
+                    let route = (
+                        arg(args, "--transit-gateway-route-table-id"),
+                        arg(args, "--destination-cidr-block"),
+                        arg(args, "--transit-gateway-attachment-id"),
+                    );
+                    state.routes.push(route);
+                    Value::Null
+                }
+                "delete-transit-gateway-route" => {
+                    let table = arg(args, "--transit-gateway-route-table-id");
+                    let cidr = arg(args, "--destination-cidr-block");
+                    state.routes.retain(|(t, c, _)| !(*t == table && *c == cidr));
+                    Value::Null
+                }
+                other => panic!("unexpected operation {}", other),
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeIpsec {
+        tunnels: Mutex<Vec<(LocalIpsecTunnel, String)>>,
+    }
+
+    #[async_trait]
+    impl LocalIpsec for FakeIpsec {
+        async fn configure(&self, tunnel: &LocalIpsecTunnel, psk: &SecretString) -> Result<()> {
+            let mut tunnels = self.tunnels.lock().unwrap();
+            tunnels.retain(|(t, _)| t.name != tunnel.name);
+            tunnels.push((tunnel.clone(), psk.expose_secret().to_string()));
+            Ok(())
+        }
+
+        async fn remove(&self, tunnel: &LocalIpsecTunnel) -> Result<()> {
+            self.tunnels.lock().unwrap().retain(|(t, _)| t.name != tunnel.name);
+            Ok(())
+        }
+    }
+
+    fn tgw_connector(ec2: Arc<FakeEc2>, ipsec: Arc<FakeIpsec>) -> (AwsConnector, Arc<SecretManager>) {
+        let secrets = Arc::new(SecretManager::new(Arc::new(MemoryStore::new())));
+        let connector = AwsConnector::new(AwsConfig {
+            access_key_id: "test_key".to_string(),
+            secret_access_key: "test_secret".to_string(),
+            region: "eu-west-1".to_string(),
+            vpc_id: "vpc-12345".to_string(),
+            transit_gateway_id: None,
+        })
+        .with_ec2_api(ec2)
+        .with_secrets(secrets.clone())
+        .with_local_ipsec(ipsec)
+        .with_polling(Duration::ZERO, 5);
+        (connector, secrets)
+    }
+
+    fn spec(routing: TgwRouting) -> TgwVpnSpec {
+        TgwVpnSpec {
+            name: "branch-1".to_string(),
+            public_ip: "198.51.100.7".parse().unwrap(),
+            routing,
+            association_route_table: Some("tgw-rtb-branches".to_string()),
+            propagation_route_tables: vec!["tgw-rtb-shared".to_string(), "tgw-rtb-prod".to_string()],
+            cloud_prefixes: vec!["10.100.0.0/16".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tgw_vpn_with_bgp_is_idempotent() {
+        let ec2 = Arc::new(FakeEc2::default());
+        ec2.state.lock().unwrap().require_acceptance = true;
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = tgw_connector(ec2.clone(), ipsec.clone());
+
+        let tgw = connector.ensure_transit_gateway("hub", 64512).await.unwrap();
+        assert_eq!(connector.ensure_transit_gateway("hub", 64512).await.unwrap(), tgw);
+
+        let spec = spec(TgwRouting::Bgp { asn: 65010 });
+        let attachment = connector.connect_transit_gateway_vpn(&tgw, &spec).await.unwrap();
+        {
+            let state = ec2.state.lock().unwrap();
+            assert_eq!(state.calls("create-transit-gateway"), 1);
+            assert_eq!(state.calls("accept-transit-gateway-vpc-attachment"), 1);
+            assert_eq!(state.cgws[0]["BgpAsn"], "65010");
+            assert_eq!(state.attachments[0]["Association"]["TransitGatewayRouteTableId"], "tgw-rtb-branches");
+            let mut tables: Vec<&str> = state.propagations.iter().map(|(_, t)| t.as_str()).collect();
+            tables.sort();
+            assert_eq!(tables, vec!["tgw-rtb-prod", "tgw-rtb-shared"]);
+            assert!(state.routes.is_empty());
+        }
+
+        assert_eq!(attachment.tunnels.len(), 2);
+        let tunnel = &attachment.tunnels[1];
+        assert_eq!(tunnel.outside_ip, "52.0.0.2".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(tunnel.aws_inside_ip, "169.254.10.5".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(tunnel.local_inside_ip, "169.254.10.6".parse::<Ipv4Addr>().unwrap());
+        let psk = secrets.get_secret(&tunnel.psk_secret).await.unwrap().unwrap();
+        assert_eq!(psk.expose_secret(), "Tunnel2Key_abcdef");
+
+        let configured = ipsec.tunnels.lock().unwrap().clone();
+        assert_eq!(configured.len(), 2);
+        assert_eq!(configured[0].0.name, "branch-1-t1");
+        assert_eq!(configured[0].0.remote_address, "52.0.0.1".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(configured[0].0.remote_subnets, vec!["0.0.0.0/0".to_string()]);
+        assert_eq!(configured[0].1, "Tunnel1Key_abcdef");
+
+        let neighbors = attachment.bgp_neighbors();
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(neighbors[0].ip, "169.254.10.1".parse::<std::net::IpAddr>().unwrap());
+        assert_eq!(neighbors[0].asn, 64512);
+        assert_eq!(neighbors[0].timers.as_ref().unwrap().holdtime_secs, 30);
+
+        let mut bgp = BgpConfig {
+            asn: 65010,
+            router_id: "10.0.0.1".parse().unwrap(),
+            neighbors: Vec::new(),
+            networks: Vec::new(),
+            route_maps: Vec::new(),
+            timers: TimersConfig::default(),
+            dampening: None,
+        };
+        attachment.apply_bgp(&mut bgp);
+        attachment.apply_bgp(&mut bgp);
+        assert_eq!(bgp.neighbors.len(), 2);
+
+        // A second run finds everything in place
+        let again = connector.connect_transit_gateway_vpn(&tgw, &spec).await.unwrap();
+        assert_eq!(again.vpn_connection_id, attachment.vpn_connection_id);
+        assert_eq!(again.attachment_id, attachment.attachment_id);
+        let state = ec2.state.lock().unwrap();
+        for op in [
+            "create-customer-gateway",
+            "create-vpn-connection",
+            "associate-transit-gateway-route-table",
+            "enable-transit-gateway-route-table-propagation",
+        ] {
+            let expected = if op.starts_with("enable") { 2 } else { 1 };
+            assert_eq!(state.calls(op), expected, "{}", op);
+        }
+        assert_eq!(state.calls("disable-transit-gateway-route-table-propagation"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tgw_vpn_static_routes_and_teardown() {
+        let ec2 = Arc::new(FakeEc2::default());
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = tgw_connector(ec2.clone(), ipsec.clone());
+        let tgw = connector.ensure_transit_gateway("hub", 64512).await.unwrap();
+
+        let mut spec = spec(TgwRouting::Static { prefixes: vec!["192.168.10.0/24".to_string()] });
+        let attachment = connector.connect_transit_gateway_vpn(&tgw, &spec).await.unwrap();
+        assert!(attachment.bgp_neighbors().is_empty());
+        assert_eq!(ipsec.tunnels.lock().unwrap()[0].0.local_subnets, vec!["192.168.10.0/24".to_string()]);
+        assert_eq!(ipsec.tunnels.lock().unwrap()[0].0.remote_subnets, vec!["10.100.0.0/16".to_string()]);
+        assert_eq!(ec2.state.lock().unwrap().routes.len(), 2);
+
+        // Re-running with other prefixes replaces the routes
+        spec.routing = TgwRouting::Static { prefixes: vec!["192.168.20.0/24".to_string()] };
+        connector.connect_transit_gateway_vpn(&tgw, &spec).await.unwrap();
+        {
+            let state = ec2.state.lock().unwrap();
+            assert_eq!(state.routes.len(), 2);
+            assert!(state.routes.iter().all(|(_, cidr, _)| cidr == "192.168.20.0/24"));
+        }
+
+        // Routing can't be switched in place
+        let err = connector.connect_transit_gateway_vpn(&tgw, &self::spec(TgwRouting::Bgp { asn: STATIC_CUSTOMER_ASN })).await.unwrap_err();
+        assert!(err.to_string().contains("different routing"));
+
+        let attachment = TgwVpnAttachment {
+            routing: spec.routing.clone(),
+            ..attachment
+        };
+        connector.teardown_transit_gateway_vpn(&attachment).await.unwrap();
+        {
+            let state = ec2.state.lock().unwrap();
+            assert!(state.routes.is_empty());
+            assert!(state.propagations.is_empty());
+            let delete = state.position("delete-vpn-connection");
+            assert!(state.position("disable-transit-gateway-route-table-propagation") < delete);
+            assert!(state.position("disassociate-transit-gateway-route-table") < delete);
+            assert!(state.position("delete-customer-gateway") > delete);
+        }
+        assert!(ipsec.tunnels.lock().unwrap().is_empty());
+        assert!(secrets.get_secret(&attachment.tunnels[0].psk_secret).await.unwrap().is_none());
+
+        // Nothing left to remove the second time
+        let calls = ec2.state.lock().unwrap().calls.len();
+        connector.teardown_transit_gateway_vpn(&attachment).await.unwrap();
+        let state = ec2.state.lock().unwrap();
+        assert!(state.calls[calls..].iter().all(|op| op.starts_with("describe")));
+    }
+
+    #[test]
+    fn test_inside_addresses() {
+        let (aws, local) = inside_addresses("169.254.10.4/30").unwrap();
+        assert_eq!(aws, "169.254.10.5".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(local, "169.254.10.6".parse::<Ipv4Addr>().unwrap());
+        assert!(inside_addresses("169.254.10.0/29").is_err());
+    }
 }
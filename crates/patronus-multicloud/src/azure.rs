@@ -1,13 +1,29 @@
 //! Azure Connectivity
 //!
 //! Connects to Azure VNet, Virtual WAN, and ExpressRoute
+//!
+//! Virtual WAN sites are managed through Azure Resource Manager: this node
+//! is registered as a VPN site with one link per WAN uplink, and connected
+//! with BGP to the VPN gateway of each requested hub. The gateways' tunnel
+//! addresses and BGP peers become local IPsec tunnels and BGP neighbors.
+//! The service principal secret and the tunnel keys live in patronus-secrets.
 
+use crate::health::{self, ConnectionProbe, LinkType};
+use crate::ipsec::{generate_psk, LocalIpsec, LocalIpsecTunnel};
 use crate::manager::{CloudConnection, CloudProvider};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use patronus_bgp::{AddressFamily, BgpConfig, NeighborConfig};
+use patronus_secrets::{SecretManager, SecretType};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 /// Azure configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AzureConfig {
     pub subscription_id: String,
     pub tenant_id: String,
@@ -16,16 +32,342 @@ pub struct AzureConfig {
     pub region: String,
     pub vnet_id: String,
     pub resource_group: String,
+    /// patronus-secrets key of the service principal secret; required for
+    /// Virtual WAN, which never uses `client_secret`
+    #[serde(default)]
+    pub client_secret_key: Option<String>,
+    /// Virtual WAN in `resource_group` that sites join
+    #[serde(default)]
+    pub virtual_wan: Option<String>,
+}
+
+impl std::fmt::Debug for AzureConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AzureConfig")
+            .field("subscription_id", &self.subscription_id)
+            .field("tenant_id", &self.tenant_id)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"[REDACTED]")
+            .field("region", &self.region)
+            .field("vnet_id", &self.vnet_id)
+            .field("resource_group", &self.resource_group)
+            .field("client_secret_key", &self.client_secret_key)
+            .field("virtual_wan", &self.virtual_wan)
+            .finish()
+    }
+}
+
+/// Azure Resource Manager, addressed by resource ID
+#[async_trait]
+pub trait AzureApi: Send + Sync {
+    /// A resource, or a collection as `{"value": [...]}`; None when missing
+    async fn get(&self, id: &str) -> Result<Option<Value>>;
+    /// Create or update a resource
+    async fn put(&self, id: &str, body: &Value) -> Result<Value>;
+    /// Delete a resource; missing ones are not an error
+    async fn delete(&self, id: &str) -> Result<()>;
+}
+
+const ARM_ENDPOINT: &str = "https://management.azure.com";
+const ARM_API_VERSION: &str = "2023-09-01";
+
+/// [`AzureApi`] over HTTPS, signed in as the configured service principal
+pub struct ArmClient {
+    http: reqwest::Client,
+    tenant_id: String,
+    client_id: String,
+    secrets: Arc<SecretManager>,
+    secret_key: String,
+    token: tokio::sync::Mutex<Option<(String, Instant)>>,
+}
+
+impl ArmClient {
+    pub fn new(config: &AzureConfig, secrets: Arc<SecretManager>) -> Result<Self> {
+        let secret_key = config.client_secret_key.clone()
+            .ok_or_else(|| anyhow::anyhow!("No patronus-secrets key configured for the Azure client secret"))?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            tenant_id: config.tenant_id.clone(),
+            client_id: config.client_id.clone(),
+            secrets,
+            secret_key,
+            token: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn token(&self) -> Result<String> {
+        let mut cached = self.token.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if *expires > Instant::now() + Duration::from_secs(60) {
+                return Ok(token.clone());
+            }
+        }
+
+        let secret = self.secrets.get_secret(&self.secret_key).await?
+            .ok_or_else(|| anyhow::anyhow!("Azure client secret {} is not in patronus-secrets", self.secret_key))?;
+        let response = self.http
+            .post(format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", self.tenant_id))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", &self.client_id),
+                ("client_secret", secret.expose_secret()),
+                ("scope", "https://management.azure.com/.default"),
+            ])
+            .send()
+            .await
+            .context("Azure sign-in request failed")?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            anyhow::bail!(
+                "Azure sign-in as {} failed ({}): {}",
+                self.client_id, status, body["error_description"].as_str().unwrap_or("no details")
+            );
+        }
+
+        let token = body["access_token"].as_str().context("Azure sign-in returned no token")?.to_string();
+        let lifetime = Duration::from_secs(body["expires_in"].as_u64().unwrap_or(3600));
+        *cached = Some((token.clone(), Instant::now() + lifetime));
+        Ok(token)
+    }
+
+    async fn request(&self, method: reqwest::Method, id: &str, body: Option<&Value>) -> Result<Option<Value>> {
+        let mut request = self.http
+            .request(method.clone(), format!("{}{}", ARM_ENDPOINT, id))
+            .query(&[("api-version", ARM_API_VERSION)])
+            .bearer_auth(self.token().await?);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.with_context(|| format!("{} {} failed", method, id))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = serde_json::from_str::<Value>(&text).ok()
+                .and_then(|v| v.pointer("/error/message").and_then(Value::as_str).map(str::to_string))
+                .unwrap_or_else(|| status.to_string());
+            anyhow::bail!("{} {}: {}", method, id, message);
+        }
+        Ok(Some(if text.trim().is_empty() { Value::Null } else { serde_json::from_str(&text)? }))
+    }
+}
+
+#[async_trait]
+impl AzureApi for ArmClient {
+    async fn get(&self, id: &str) -> Result<Option<Value>> {
+        self.request(reqwest::Method::GET, id, None).await
+    }
+
+    async fn put(&self, id: &str, body: &Value) -> Result<Value> {
+        Ok(self.request(reqwest::Method::PUT, id, Some(body)).await?.unwrap_or(Value::Null))
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.request(reqwest::Method::DELETE, id, None).await.map(|_| ())
+    }
+}
+
+/// How long-running Azure operations are waited for
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// First delay between checks, doubled after each
+    pub initial: Duration,
+    pub max: Duration,
+    /// Give up after this long
+    pub timeout: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        // Gateway connections commonly take several minutes to provision
+        Self {
+            initial: Duration::from_secs(2),
+            max: Duration::from_secs(30),
+            timeout: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// One WAN uplink of a site
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VwanSiteLink {
+    pub name: String,
+    /// Public address of the uplink
+    pub ip_address: Ipv4Addr,
+    pub link_speed_mbps: u32,
+    pub provider: String,
+    /// Local BGP address on this link's tunnels
+    pub bgp_peering_address: Ipv4Addr,
+}
+
+/// This node as a Virtual WAN VPN site
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VwanSiteSpec {
+    pub name: String,
+    pub asn: u32,
+    pub links: Vec<VwanSiteLink>,
+    /// Hubs to connect to; connections to other hubs are removed
+    pub hubs: Vec<String>,
+    /// On-premises prefixes; BGP advertises them either way
+    #[serde(default)]
+    pub address_prefixes: Vec<String>,
+}
+
+/// A tunnel from one site link to one instance of a hub's gateway
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VwanTunnel {
+    pub link: String,
+    pub instance: usize,
+    pub local_address: Ipv4Addr,
+    pub local_bgp_ip: Ipv4Addr,
+    /// Gateway instance's tunnel address
+    pub remote_ip: Ipv4Addr,
+    pub bgp_peer_ip: Ipv4Addr,
+    /// patronus-secrets key of the pre-shared key
+    pub psk_secret: String,
+}
+
+/// The site's connection to one hub
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VwanHubConnection {
+    pub hub: String,
+    pub location: String,
+    pub gateway_id: String,
+    pub connection_id: String,
+    /// ASN of the hub's gateway
+    pub asn: u32,
+    pub tunnels: Vec<VwanTunnel>,
+}
+
+/// A site and its hub connections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VwanAttachment {
+    pub site: String,
+    pub site_id: String,
+    pub hubs: Vec<VwanHubConnection>,
+}
+
+impl VwanAttachment {
+    /// One neighbor per gateway instance of every hub
+    pub fn bgp_neighbors(&self) -> Vec<NeighborConfig> {
+        let mut neighbors: Vec<NeighborConfig> = Vec::new();
+        for hub in &self.hubs {
+            for tunnel in &hub.tunnels {
+                if neighbors.iter().any(|n| n.ip == std::net::IpAddr::V4(tunnel.bgp_peer_ip)) {
+                    continue;
+                }
+                neighbors.push(NeighborConfig {
+                    ip: tunnel.bgp_peer_ip.into(),
+                    asn: hub.asn,
+                    description: Some(format!("vWAN hub {} instance {}", hub.hub, tunnel.instance)),
+                    password: None,
+                    timers: None,
+                    route_map_in: None,
+                    route_map_out: None,
+                    next_hop_self: false,
+                    address_families: vec![AddressFamily::Ipv4Unicast],
+                    prefix_limit: None,
+                });
+            }
+        }
+        neighbors
+    }
+
+    /// Add or replace this site's neighbors in `config`
+    pub fn apply_bgp(&self, config: &mut BgpConfig) {
+        for neighbor in self.bgp_neighbors() {
+            config.neighbors.retain(|n| n.ip != neighbor.ip);
+            config.neighbors.push(neighbor);
+        }
+    }
+
+    /// Local side of every tunnel
+    pub fn local_tunnels(&self) -> Vec<LocalIpsecTunnel> {
+        self.hubs
+            .iter()
+            .flat_map(|hub| hub.tunnels.iter().map(move |tunnel| (hub, tunnel)))
+            .map(|(hub, tunnel)| LocalIpsecTunnel {
+                name: format!("{}-{}-{}-{}", self.site, hub.hub, tunnel.link, tunnel.instance),
+                local_address: tunnel.local_address,
+                remote_address: tunnel.remote_ip,
+                local_inside_ip: tunnel.local_bgp_ip,
+                remote_inside_ip: tunnel.bgp_peer_ip,
+                inside_prefix_len: 32,
+                // Route based: BGP decides what goes into the tunnel
+                local_subnets: vec!["0.0.0.0/0".to_string()],
+                remote_subnets: vec!["0.0.0.0/0".to_string()],
+                psk_secret: tunnel.psk_secret.clone(),
+            })
+            .collect()
+    }
+
+    /// One cloud connection per hub, over its first tunnel
+    pub fn connections(&self) -> Vec<CloudConnection> {
+        self.hubs
+            .iter()
+            .map(|hub| CloudConnection {
+                provider: CloudProvider::Azure,
+                region: hub.location.clone(),
+                vpc_id: hub.hub.clone(),
+                local_ip: hub.tunnels.first().map(|t| t.local_bgp_ip.to_string()).unwrap_or_default(),
+                remote_ip: hub.tunnels.first().map(|t| t.bgp_peer_ip.to_string()).unwrap_or_default(),
+                tunnel_id: 1,
+                connected: true,
+                latency_ms: 0.0,
+            })
+            .collect()
+    }
+}
+
+/// patronus-secrets key of a link's pre-shared key towards a hub
+fn psk_key(site: &str, hub: &str, link: &str) -> String {
+    format!("azure/vwan/{}/{}/{}", site, hub, link)
 }
 
 /// Azure connector
 pub struct AzureConnector {
     config: AzureConfig,
+    api: OnceLock<Arc<dyn AzureApi>>,
+    secrets: Option<Arc<SecretManager>>,
+    ipsec: Option<Arc<dyn LocalIpsec>>,
+    backoff: Backoff,
 }
 
 impl AzureConnector {
     pub fn new(config: AzureConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            api: OnceLock::new(),
+            secrets: None,
+            ipsec: None,
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Reach Resource Manager through something other than [`ArmClient`]
+    pub fn with_azure_api(self, api: Arc<dyn AzureApi>) -> Self {
+        let _ = self.api.set(api);
+        self
+    }
+
+    /// Where the client secret and tunnel keys are kept
+    pub fn with_secrets(mut self, secrets: Arc<SecretManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Configure the local side of Virtual WAN tunnels
+    pub fn with_local_ipsec(mut self, ipsec: Arc<dyn LocalIpsec>) -> Self {
+        self.ipsec = Some(ipsec);
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
     }
 
     /// Connect to Azure VNet
@@ -82,6 +424,387 @@ impl AzureConnector {
 
         Ok(())
     }
+
+    /// Register this node as a Virtual WAN site and connect it to each hub
+    ///
+    /// The site and its connections are created or updated in place, so
+    /// re-running with a changed spec converges on it; connections to hubs
+    /// no longer listed are removed. Each link gets its own pre-shared key
+    /// per hub, generated once and kept in patronus-secrets. When a
+    /// [`LocalIpsec`] is set the tunnels are configured locally and this
+    /// waits until every link connection reports Connected.
+    pub async fn attach_vwan_site(&self, spec: &VwanSiteSpec) -> Result<VwanAttachment> {
+        let api = self.api()?;
+        let secrets = self.secrets()?;
+        if spec.links.is_empty() || spec.hubs.is_empty() {
+            anyhow::bail!("Site {} needs at least one link and one hub", spec.name);
+        }
+
+        let site_id = self.site_id(&spec.name);
+        let mut properties = json!({
+            "virtualWan": { "id": self.wan_id()? },
+            "deviceProperties": { "deviceVendor": "Patronus" },
+            "vpnSiteLinks": spec.links.iter().map(|link| json!({
+                "name": link.name,
+                "properties": {
+                    "ipAddress": link.ip_address.to_string(),
+                    "linkProperties": {
+                        "linkProviderName": link.provider,
+                        "linkSpeedInMbps": link.link_speed_mbps,
+                    },
+                    "bgpProperties": {
+                        "asn": spec.asn,
+                        "bgpPeeringAddress": link.bgp_peering_address.to_string(),
+                    },
+                },
+            })).collect::<Vec<_>>(),
+        });
+        if !spec.address_prefixes.is_empty() {
+            properties["addressSpace"] = json!({ "addressPrefixes": spec.address_prefixes });
+        }
+        tracing::info!("Registering Virtual WAN site {} with {} link(s)", spec.name, spec.links.len());
+        api.put(&site_id, &json!({ "location": self.config.region, "properties": properties })).await?;
+        self.wait_provisioned(&site_id).await?;
+
+        for hub in &spec.hubs {
+            let gateway_id = self.hub_gateway(hub).await?
+                .ok_or_else(|| anyhow::anyhow!("Hub {} has no VPN gateway", hub))?;
+
+            let mut link_connections = Vec::new();
+            for link in &spec.links {
+                let key = psk_key(&spec.name, hub, &link.name);
+                let psk = match secrets.get_secret(&key).await? {
+                    Some(psk) => psk,
+                    None => {
+                        let psk = generate_psk();
+                        secrets.store_secret(
+                            &key,
+                            psk.clone(),
+                            SecretType::General,
+                            format!("Pre-shared key of {} link {} to hub {}", spec.name, link.name, hub),
+                            None,
+                        ).await?;
+                        psk
+                    }
+                };
+                link_connections.push(json!({
+                    "name": link.name,
+                    "properties": {
+                        "vpnSiteLink": { "id": format!("{}/vpnSiteLinks/{}", site_id, link.name) },
+                        "enableBgp": true,
+                        "sharedKey": psk.expose_secret(),
+                        "vpnConnectionProtocolType": "IKEv2",
+                        "connectionBandwidth": link.link_speed_mbps,
+                    },
+                }));
+            }
+
+            let connection_id = format!("{}/vpnConnections/{}", gateway_id, spec.name);
+            tracing::info!("Connecting site {} to hub {}", spec.name, hub);
+            api.put(&connection_id, &json!({
+                "properties": {
+                    "remoteVpnSite": { "id": site_id },
+                    "vpnLinkConnections": link_connections,
+                },
+            })).await?;
+            self.wait_provisioned(&connection_id).await?;
+        }
+
+        let attachment = self.vwan_site(&spec.name).await?
+            .ok_or_else(|| anyhow::anyhow!("Site {} disappeared while attaching", spec.name))?;
+
+        let stale: Vec<VwanHubConnection> = attachment.hubs.iter()
+            .filter(|h| !spec.hubs.contains(&h.hub))
+            .cloned()
+            .collect();
+        if !stale.is_empty() {
+            self.disconnect_hubs(&VwanAttachment { hubs: stale, ..attachment.clone() }).await?;
+        }
+        let attachment = VwanAttachment {
+            hubs: attachment.hubs.into_iter().filter(|h| spec.hubs.contains(&h.hub)).collect(),
+            ..attachment
+        };
+
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                let psk = secrets.get_secret(&tunnel.psk_secret).await?
+                    .ok_or_else(|| anyhow::anyhow!("Pre-shared key {} went missing", tunnel.psk_secret))?;
+                ipsec.configure(&tunnel, &psk).await?;
+            }
+            self.wait_connected(&attachment).await?;
+        }
+
+        tracing::info!("Site {} attached to hub(s) {}", spec.name, spec.hubs.join(", "));
+        Ok(attachment)
+    }
+
+    /// Wait until every link connection of the site reports Connected
+    pub async fn wait_connected(&self, attachment: &VwanAttachment) -> Result<()> {
+        let api = self.api()?;
+        for hub in &attachment.hubs {
+            let api = api.clone();
+            self.wait_for(&format!("site {} to connect to hub {}", attachment.site, hub.hub), || {
+                let api = api.clone();
+                async move {
+                    let Some(connection) = api.get(&hub.connection_id).await? else {
+                        anyhow::bail!("Connection {} no longer exists", hub.connection_id);
+                    };
+                    let links = connection.pointer("/properties/vpnLinkConnections")
+                        .and_then(Value::as_array)
+                        .cloned()
+                        .unwrap_or_default();
+                    let connected = !links.is_empty() && links.iter().all(|link| {
+                        link.pointer("/properties/connectionStatus").and_then(Value::as_str) == Some("Connected")
+                    });
+                    Ok(connected.then_some(()))
+                }
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// A site as Azure has it, with its connections to hubs in this WAN
+    pub async fn vwan_site(&self, site: &str) -> Result<Option<VwanAttachment>> {
+        let api = self.api()?;
+        let site_id = self.site_id(site);
+        let Some(resource) = api.get(&site_id).await? else {
+            return Ok(None);
+        };
+        let links: Vec<VwanSiteLink> = array_at(&resource, "/properties/vpnSiteLinks")
+            .iter()
+            .filter_map(|link| Some(VwanSiteLink {
+                name: link["name"].as_str()?.to_string(),
+                ip_address: link.pointer("/properties/ipAddress")?.as_str()?.parse().ok()?,
+                link_speed_mbps: link.pointer("/properties/linkProperties/linkSpeedInMbps")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0) as u32,
+                provider: link.pointer("/properties/linkProperties/linkProviderName")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                bgp_peering_address: link.pointer("/properties/bgpProperties/bgpPeeringAddress")?.as_str()?.parse().ok()?,
+            }))
+            .collect();
+
+        let mut hubs = Vec::new();
+        for (hub, location, gateway_id) in self.wan_hubs().await? {
+            let Some(gateway_id) = gateway_id else { continue };
+            let connections = api.get(&format!("{}/vpnConnections", gateway_id)).await?.unwrap_or(Value::Null);
+            let Some(connection) = array_at(&connections, "/value").into_iter().find(|c| {
+                c.pointer("/properties/remoteVpnSite/id").and_then(Value::as_str)
+                    .is_some_and(|id| id.eq_ignore_ascii_case(&site_id))
+            }) else {
+                continue;
+            };
+            let connection_id = connection["id"].as_str().context("Connection without an ID")?.to_string();
+
+            let gateway = api.get(&gateway_id).await?
+                .ok_or_else(|| anyhow::anyhow!("VPN gateway {} not found", gateway_id))?;
+            let asn = gateway.pointer("/properties/bgpSettings/asn").and_then(Value::as_u64)
+                .context("VPN gateway without a BGP ASN")? as u32;
+            let instances = gateway_instances(&gateway)?;
+
+            let connected_links: Vec<String> = array_at(&connection, "/properties/vpnLinkConnections")
+                .iter()
+                .filter_map(|l| l["name"].as_str().map(str::to_string))
+                .collect();
+            let mut tunnels = Vec::new();
+            for link in links.iter().filter(|link| connected_links.contains(&link.name)) {
+                for (instance, (remote_ip, bgp_peer_ip)) in instances.iter().enumerate() {
+                    tunnels.push(VwanTunnel {
+                        link: link.name.clone(),
+                        instance,
+                        local_address: link.ip_address,
+                        local_bgp_ip: link.bgp_peering_address,
+                        remote_ip: *remote_ip,
+                        bgp_peer_ip: *bgp_peer_ip,
+                        psk_secret: psk_key(site, &hub, &link.name),
+                    });
+                }
+            }
+
+            hubs.push(VwanHubConnection { hub, location, gateway_id, connection_id, asn, tunnels });
+        }
+
+        Ok(Some(VwanAttachment { site: site.to_string(), site_id, hubs }))
+    }
+
+    /// Disconnect a site from every hub and delete it
+    ///
+    /// Local tunnels go first so nothing keeps retrying against a gateway
+    /// that is going away. Detaching a site that is already gone succeeds.
+    pub async fn detach(&self, site: &str) -> Result<()> {
+        let api = self.api()?;
+        let Some(attachment) = self.vwan_site(site).await? else {
+            tracing::info!("Virtual WAN site {} is already gone", site);
+            return Ok(());
+        };
+        self.disconnect_hubs(&attachment).await?;
+
+        tracing::info!("Deleting Virtual WAN site {}", site);
+        api.delete(&attachment.site_id).await?;
+        self.wait_deleted(&attachment.site_id).await?;
+        Ok(())
+    }
+
+    /// Tear down the site's connections to `attachment.hubs`, locally and in Azure
+    async fn disconnect_hubs(&self, attachment: &VwanAttachment) -> Result<()> {
+        let api = self.api()?;
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                ipsec.remove(&tunnel).await?;
+            }
+        }
+        for hub in &attachment.hubs {
+            tracing::info!("Disconnecting site {} from hub {}", attachment.site, hub.hub);
+            api.delete(&hub.connection_id).await?;
+            self.wait_deleted(&hub.connection_id).await?;
+
+            if let Some(secrets) = &self.secrets {
+                let mut links: Vec<&str> = hub.tunnels.iter().map(|t| t.link.as_str()).collect();
+                links.dedup();
+                for link in links {
+                    let key = psk_key(&attachment.site, &hub.hub, link);
+                    if secrets.get_secret(&key).await?.is_some() {
+                        secrets.delete_secret(&key).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn api(&self) -> Result<Arc<dyn AzureApi>> {
+        if let Some(api) = self.api.get() {
+            return Ok(api.clone());
+        }
+        let client: Arc<dyn AzureApi> = Arc::new(ArmClient::new(&self.config, self.secrets()?.clone())?);
+        Ok(self.api.get_or_init(|| client).clone())
+    }
+
+    fn secrets(&self) -> Result<&Arc<SecretManager>> {
+        self.secrets.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("A secret manager is needed for Azure credentials and tunnel keys"))
+    }
+
+    fn group_id(&self) -> String {
+        format!("/subscriptions/{}/resourceGroups/{}", self.config.subscription_id, self.config.resource_group)
+    }
+
+    fn wan_id(&self) -> Result<String> {
+        let wan = self.config.virtual_wan.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No Virtual WAN configured"))?;
+        Ok(format!("{}/providers/Microsoft.Network/virtualWans/{}", self.group_id(), wan))
+    }
+
+    fn site_id(&self, site: &str) -> String {
+        format!("{}/providers/Microsoft.Network/vpnSites/{}", self.group_id(), site)
+    }
+
+    /// (name, location, VPN gateway ID) of each hub in the WAN
+    async fn wan_hubs(&self) -> Result<Vec<(String, String, Option<String>)>> {
+        let wan_id = self.wan_id()?;
+        let hubs = self.api()?
+            .get(&format!("{}/providers/Microsoft.Network/virtualHubs", self.group_id()))
+            .await?
+            .unwrap_or(Value::Null);
+        Ok(array_at(&hubs, "/value")
+            .iter()
+            .filter(|hub| {
+                hub.pointer("/properties/virtualWan/id").and_then(Value::as_str)
+                    .is_some_and(|id| id.eq_ignore_ascii_case(&wan_id))
+            })
+            .filter_map(|hub| Some((
+                hub["name"].as_str()?.to_string(),
+                hub["location"].as_str().unwrap_or_default().to_string(),
+                hub.pointer("/properties/vpnGateway/id").and_then(Value::as_str).map(str::to_string),
+            )))
+            .collect())
+    }
+
+    async fn hub_gateway(&self, hub: &str) -> Result<Option<String>> {
+        let hubs = self.wan_hubs().await?;
+        let (_, _, gateway) = hubs.into_iter().find(|(name, _, _)| name == hub)
+            .ok_or_else(|| anyhow::anyhow!("Hub {} is not in Virtual WAN {:?}", hub, self.config.virtual_wan))?;
+        Ok(gateway)
+    }
+
+    async fn wait_provisioned(&self, id: &str) -> Result<()> {
+        let api = self.api()?;
+        self.wait_for(&format!("{} to provision", id), || {
+            let api = api.clone();
+            async move {
+                let resource = api.get(id).await?.ok_or_else(|| anyhow::anyhow!("{} not found", id))?;
+                match resource.pointer("/properties/provisioningState").and_then(Value::as_str) {
+                    Some("Succeeded") => Ok(Some(())),
+                    Some(state @ ("Failed" | "Canceled")) => anyhow::bail!("Provisioning {} {}", id, state.to_lowercase()),
+                    _ => Ok(None),
+                }
+            }
+        })
+        .await
+    }
+
+    async fn wait_deleted(&self, id: &str) -> Result<()> {
+        let api = self.api()?;
+        self.wait_for(&format!("{} to be deleted", id), || {
+            let api = api.clone();
+            async move { Ok(api.get(id).await?.is_none().then_some(())) }
+        })
+        .await
+    }
+
+    /// Retry `check` with exponential backoff until it yields a value
+    async fn wait_for<T, F, Fut>(&self, what: &str, mut check: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Option<T>>>,
+    {
+        let deadline = Instant::now() + self.backoff.timeout;
+        let mut delay = self.backoff.initial;
+        loop {
+            if let Some(value) = check().await? {
+                return Ok(value);
+            }
+            if Instant::now() + delay > deadline {
+                anyhow::bail!("Timed out waiting for {}", what);
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(self.backoff.max);
+        }
+    }
+
+    /// Health probe for a connection made by this connector
+    pub fn health_probe(&self, link: LinkType) -> Arc<dyn ConnectionProbe> {
+        health::probe_for(CloudProvider::Azure, link)
+    }
+}
+
+fn array_at(value: &Value, pointer: &str) -> Vec<Value> {
+    value.pointer(pointer).and_then(Value::as_array).cloned().unwrap_or_default()
+}
+
+/// (tunnel address, BGP address) of each gateway instance, in instance order
+fn gateway_instances(gateway: &Value) -> Result<Vec<(Ipv4Addr, Ipv4Addr)>> {
+    let mut peers = array_at(gateway, "/properties/bgpSettings/bgpPeeringAddresses");
+    peers.sort_by_key(|p| p["ipconfigurationId"].as_str().unwrap_or_default().to_string());
+    peers
+        .iter()
+        .map(|peer| {
+            let first = |key: &str| {
+                peer[key].as_array()
+                    .and_then(|a| a.first())
+                    .and_then(Value::as_str)
+                    .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+            };
+            let tunnel = first("tunnelIpAddresses").context("Gateway instance without a tunnel address")?;
+            let bgp = first("customBgpIpAddresses")
+                .or_else(|| first("defaultBgpIpAddresses"))
+                .context("Gateway instance without a BGP address")?;
+            Ok((tunnel, bgp))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -98,6 +821,8 @@ mod tests {
             region: "eastus".to_string(),
             vnet_id: "vnet-12345".to_string(),
             resource_group: "rg-patronus".to_string(),
+            client_secret_key: None,
+            virtual_wan: None,
         };
 
         let connector = AzureConnector::new(config);
@@ -107,4 +832,296 @@ mod tests {
         assert_eq!(connection.region, "eastus");
         assert!(connection.connected);
     }
+
+    use patronus_secrets::{MemoryStore, SecretString};
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    const GROUP: &str = "/subscriptions/sub-1/resourceGroups/rg-wan";
+
+    /// Resource Manager kept in memory; provisioning settles on PUT, link
+    /// connections report Connected after `connect_after` reads
+    #[derive(Default)]
+    struct FakeArm {
+        state: Mutex<ArmState>,
+    }
+
+    #[derive(Default)]
+    struct ArmState {
+        resources: BTreeMap<String, Value>,
+        calls: Vec<String>,
+        connect_after: Option<usize>,
+        connection_reads: usize,
+    }
+
+    impl FakeArm {
+        fn with_hubs(hubs: &[(&str, &str)]) -> Self {
+            let arm = Self::default();
+            {
+                let mut state = arm.state.lock().unwrap();
+                state.connect_after = Some(1);
+                for (i, (hub, location)) in hubs.iter().enumerate() {
+                    let hub_id = format!("{}/providers/Microsoft.Network/virtualHubs/{}", GROUP, hub);
+                    let gateway_id = format!("{}/providers/Microsoft.Network/vpnGateways/{}-gw", GROUP, hub);
+                    state.resources.insert(hub_id.to_lowercase(), json!({
+                        "id": hub_id,
+                        "name": hub,
+                        "location": location,
+                        "properties": {
+                            "virtualWan": { "id": format!("{}/providers/Microsoft.Network/virtualWans/corp-wan", GROUP) },
+                            "vpnGateway": { "id": gateway_id },
+                        },
+                    }));
+                    // Listed out of instance order on purpose
+                    state.resources.insert(gateway_id.to_lowercase(), json!({
+                        "id": gateway_id,
+                        "properties": { "bgpSettings": { "asn": 65515, "bgpPeeringAddresses": [
+                            {
+                                "ipconfigurationId": format!("{}/ipConfigurations/Instance1", gateway_id),
+                                "defaultBgpIpAddresses": [format!("10.{}.0.13", i)],
+                                "customBgpIpAddresses": [],
+                                "tunnelIpAddresses": [format!("20.{}.0.2", i)],
+                            },
+                            {
+                                "ipconfigurationId": format!("{}/ipConfigurations/Instance0", gateway_id),
+                                "defaultBgpIpAddresses": [format!("10.{}.0.12", i)],
+                                "customBgpIpAddresses": [],
+                                "tunnelIpAddresses": [format!("20.{}.0.1", i)],
+                            },
+                        ]}},
+                    }));
+                }
+            }
+            arm
+        }
+
+        fn calls(&self, prefix: &str) -> usize {
+            self.state.lock().unwrap().calls.iter().filter(|c| c.starts_with(prefix)).count()
+        }
+
+        fn resource(&self, id: &str) -> Option<Value> {
+            self.state.lock().unwrap().resources.get(&id.to_lowercase()).cloned()
+        }
+    }
+
+    #[async_trait]
+    impl AzureApi for FakeArm {
+        async fn get(&self, id: &str) -> Result<Option<Value>> {
+            let mut state = self.state.lock().unwrap();
+            let key = id.to_lowercase();
+            if let Some(resource) = state.resources.get(&key).cloned() {
+                if !key.contains("/vpnconnections/") {
+                    return Ok(Some(resource));
+                }
+                state.connection_reads += 1;
+                let connected = state.connect_after.is_some_and(|n| state.connection_reads > n);
+                let mut resource = resource;
+                for link in resource["properties"]["vpnLinkConnections"].as_array_mut().unwrap() {
+                    link["properties"]["connectionStatus"] = json!(if connected { "Connected" } else { "Connecting" });
+                }
+                return Ok(Some(resource));
+            }
+            let prefix = format!("{}/", key);
+            let children: Vec<Value> = state.resources.iter()
+                .filter(|(k, _)| k.strip_prefix(&prefix).is_some_and(|rest| !rest.contains('/')))
+                .map(|(_, v)| v.clone())
+                .collect();
+            Ok((!children.is_empty()).then(|| json!({ "value": children })))
+        }
+
+        async fn put(&self, id: &str, body: &Value) -> Result<Value> {
+            let mut state = self.state.lock().unwrap();
+            state.calls.push(format!("PUT {}", id));
+            let mut resource = body.clone();
+            resource["id"] = json!(id);
+            resource["name"] = json!(id.rsplit('/').next().unwrap());
+            resource["properties"]["provisioningState"] = json!("Succeeded");
+            state.resources.insert(id.to_lowercase(), resource.clone());
+            Ok(resource)
+        }
+
+        async fn delete(&self, id: &str) -> Result<()> {
+            let mut state = self.state.lock().unwrap();
+            state.calls.push(format!("DELETE {}", id));
+            state.resources.remove(&id.to_lowercase());
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeIpsec {
+        tunnels: Mutex<Vec<(LocalIpsecTunnel, String)>>,
+    }
+
+    #[async_trait]
+    impl LocalIpsec for FakeIpsec {
+        async fn configure(&self, tunnel: &LocalIpsecTunnel, psk: &SecretString) -> Result<()> {
+            let mut tunnels = self.tunnels.lock().unwrap();
+            tunnels.retain(|(t, _)| t.name != tunnel.name);
+            tunnels.push((tunnel.clone(), psk.expose_secret().to_string()));
+            Ok(())
+        }
+
+        async fn remove(&self, tunnel: &LocalIpsecTunnel) -> Result<()> {
+            self.tunnels.lock().unwrap().retain(|(t, _)| t.name != tunnel.name);
+            Ok(())
+        }
+    }
+
+    fn vwan_connector(arm: Arc<FakeArm>, ipsec: Arc<FakeIpsec>) -> (AzureConnector, Arc<SecretManager>) {
+        let secrets = Arc::new(SecretManager::new(Arc::new(MemoryStore::new())));
+        let connector = AzureConnector::new(AzureConfig {
+            subscription_id: "sub-1".to_string(),
+            tenant_id: "tenant-1".to_string(),
+            client_id: "client-1".to_string(),
+            client_secret: String::new(),
+            region: "westeurope".to_string(),
+            vnet_id: String::new(),
+            resource_group: "rg-wan".to_string(),
+            client_secret_key: Some("azure/client-secret".to_string()),
+            virtual_wan: Some("corp-wan".to_string()),
+        })
+        .with_azure_api(arm)
+        .with_secrets(secrets.clone())
+        .with_local_ipsec(ipsec)
+        .with_backoff(Backoff { initial: Duration::ZERO, max: Duration::ZERO, timeout: Duration::from_secs(5) });
+        (connector, secrets)
+    }
+
+    fn site_spec(hubs: &[&str]) -> VwanSiteSpec {
+        VwanSiteSpec {
+            name: "branch-7".to_string(),
+            asn: 65010,
+            links: vec![
+                VwanSiteLink {
+                    name: "fiber".to_string(),
+                    ip_address: "198.51.100.7".parse().unwrap(),
+                    link_speed_mbps: 1000,
+                    provider: "FiberCo".to_string(),
+                    bgp_peering_address: "169.254.21.1".parse().unwrap(),
+                },
+                VwanSiteLink {
+                    name: "lte".to_string(),
+                    ip_address: "203.0.113.9".parse().unwrap(),
+                    link_speed_mbps: 50,
+                    provider: "MobileCo".to_string(),
+                    bgp_peering_address: "169.254.22.1".parse().unwrap(),
+                },
+            ],
+            hubs: hubs.iter().map(|h| h.to_string()).collect(),
+            address_prefixes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vwan_attach_to_multiple_hubs() {
+        let arm = Arc::new(FakeArm::with_hubs(&[("hub-weu", "westeurope"), ("hub-neu", "northeurope")]));
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = vwan_connector(arm.clone(), ipsec.clone());
+
+        let attachment = connector.attach_vwan_site(&site_spec(&["hub-weu", "hub-neu"])).await.unwrap();
+        assert_eq!(attachment.hubs.len(), 2);
+
+        let site = arm.resource(&attachment.site_id).unwrap();
+        assert_eq!(site["properties"]["vpnSiteLinks"][1]["properties"]["linkProperties"]["linkSpeedInMbps"], 50);
+        assert_eq!(site["properties"]["vpnSiteLinks"][0]["properties"]["bgpProperties"]["asn"], 65010);
+
+        let weu = attachment.hubs.iter().find(|h| h.hub == "hub-weu").unwrap();
+        assert_eq!(weu.asn, 65515);
+        assert_eq!(weu.location, "westeurope");
+        // Two links, each to both gateway instances
+        assert_eq!(weu.tunnels.len(), 4);
+        let first = &weu.tunnels[0];
+        assert_eq!((first.link.as_str(), first.instance), ("fiber", 0));
+        assert_eq!(first.remote_ip, "20.0.0.1".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(first.bgp_peer_ip, "10.0.0.12".parse::<Ipv4Addr>().unwrap());
+
+        let connection = arm.resource(&weu.connection_id).unwrap();
+        let fiber = &connection["properties"]["vpnLinkConnections"][0]["properties"];
+        assert_eq!(fiber["enableBgp"], true);
+        let psk = secrets.get_secret(&first.psk_secret).await.unwrap().unwrap();
+        assert_eq!(fiber["sharedKey"], psk.expose_secret());
+        assert!(psk.expose_secret().chars().all(|c| c.is_ascii_alphanumeric()));
+
+        let neighbors = attachment.bgp_neighbors();
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.iter().all(|n| n.asn == 65515));
+
+        let tunnels = ipsec.tunnels.lock().unwrap().clone();
+        assert_eq!(tunnels.len(), 8);
+        assert!(tunnels.iter().any(|(t, _)| t.name == "branch-7-hub-neu-lte-1" && t.inside_prefix_len == 32));
+
+        // Re-running keeps the keys and converges in place
+        let again = connector.attach_vwan_site(&site_spec(&["hub-weu", "hub-neu"])).await.unwrap();
+        let unchanged = secrets.get_secret(&again.hubs[0].tunnels[0].psk_secret).await.unwrap().unwrap();
+        assert_eq!(again.hubs.len(), 2);
+        assert_eq!(arm.calls("PUT"), 6);
+        assert_eq!(arm.calls("DELETE"), 0);
+        assert_eq!(
+            unchanged.expose_secret(),
+            secrets.get_secret(&attachment.hubs[0].tunnels[0].psk_secret).await.unwrap().unwrap().expose_secret()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vwan_drop_hub_and_detach() {
+        let arm = Arc::new(FakeArm::with_hubs(&[("hub-weu", "westeurope"), ("hub-neu", "northeurope")]));
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = vwan_connector(arm.clone(), ipsec.clone());
+
+        let both = connector.attach_vwan_site(&site_spec(&["hub-weu", "hub-neu"])).await.unwrap();
+        let neu = both.hubs.iter().find(|h| h.hub == "hub-neu").unwrap().clone();
+
+        let one = connector.attach_vwan_site(&site_spec(&["hub-weu"])).await.unwrap();
+        assert_eq!(one.hubs.len(), 1);
+        assert!(arm.resource(&neu.connection_id).is_none());
+        assert!(secrets.get_secret(&neu.tunnels[0].psk_secret).await.unwrap().is_none());
+        assert_eq!(ipsec.tunnels.lock().unwrap().len(), 4);
+
+        connector.detach("branch-7").await.unwrap();
+        assert!(arm.resource(&one.site_id).is_none());
+        assert!(arm.resource(&one.hubs[0].connection_id).is_none());
+        assert!(ipsec.tunnels.lock().unwrap().is_empty());
+        assert!(secrets.get_secret(&one.hubs[0].tunnels[0].psk_secret).await.unwrap().is_none());
+
+        // Nothing left to do
+        connector.detach("branch-7").await.unwrap();
+        assert_eq!(arm.calls("DELETE"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_vwan_connect_timeout() {
+        let arm = Arc::new(FakeArm::with_hubs(&[("hub-weu", "westeurope")]));
+        arm.state.lock().unwrap().connect_after = None;
+        let (connector, _) = vwan_connector(arm, Arc::new(FakeIpsec::default()));
+        let connector = connector.with_backoff(Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(4),
+            timeout: Duration::from_millis(50),
+        });
+
+        let err = connector.attach_vwan_site(&site_spec(&["hub-weu"])).await.unwrap_err();
+        assert!(err.to_string().contains("Timed out waiting for site branch-7 to connect to hub hub-weu"));
+
+        let err = connector.attach_vwan_site(&site_spec(&["hub-missing"])).await.unwrap_err();
+        assert!(err.to_string().contains("not in Virtual WAN"));
+    }
+
+    #[test]
+    fn test_azure_config_debug_redacts_secret() {
+        let config = AzureConfig {
+            subscription_id: "sub-1".to_string(),
+            tenant_id: "tenant-1".to_string(),
+            client_id: "client-1".to_string(),
+            client_secret: "hunter2-client-secret".to_string(),
+            region: "westeurope".to_string(),
+            vnet_id: String::new(),
+            resource_group: "rg-wan".to_string(),
+            client_secret_key: None,
+            virtual_wan: None,
+        };
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("REDACTED"));
+    }
 }
@@ -0,0 +1,531 @@
+//! Cost Estimation
+//!
+//! Projects what the cloud side of each connection costs over a period:
+//! an hourly charge for keeping the connection provisioned plus per-GB
+//! egress, extrapolated from the traffic recorded on the connection.
+//! Built-in rates are approximate list prices and can be overridden per
+//! region; regions without a rate fall back to the provider default and are
+//! flagged so the estimate is not mistaken for a quote.
+//!
+//! Billed spend over a past period comes from the bytes actually recorded
+//! in each hour, priced with the connection's [`CostModel`]: free-tier
+//! allowances come off the egress, committed-use discounts off the charges,
+//! and a spend commitment sets a floor. Projections are priced with the
+//! same model, so an estimate and the bill it predicts agree.
+
+use crate::manager::CloudProvider;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Average month (365 * 24 / 12 hours), as used by cloud billing calculators
+pub const MONTH: Duration = Duration::from_secs(730 * 3600);
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Rates for one provider/region
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    /// USD per hour the connection is provisioned
+    pub connection_hourly: f64,
+    /// USD per GB sent from the cloud towards Patronus
+    pub egress_per_gb: f64,
+}
+
+/// Pricing per provider with per-region overrides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingTable {
+    defaults: HashMap<CloudProvider, Pricing>,
+    regions: HashMap<CloudProvider, HashMap<String, Pricing>>,
+}
+
+impl PricingTable {
+    /// Table with only provider-wide rates and no known regions
+    pub fn new(defaults: HashMap<CloudProvider, Pricing>) -> Self {
+        Self {
+            defaults,
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Set the rate for a region, replacing any built-in one
+    pub fn set_region(&mut self, provider: CloudProvider, region: &str, pricing: Pricing) {
+        self.regions.entry(provider).or_default().insert(region.to_string(), pricing);
+    }
+
+    /// Set the fallback rate used for regions without their own entry
+    pub fn set_default(&mut self, provider: CloudProvider, pricing: Pricing) {
+        self.defaults.insert(provider, pricing);
+    }
+
+    /// Rate for a region, and whether it came from the fallback
+    pub fn rate(&self, provider: CloudProvider, region: &str) -> (Pricing, bool) {
+        match self.regions.get(&provider).and_then(|r| r.get(region)) {
+            Some(pricing) => (*pricing, false),
+            None => (
+                self.defaults.get(&provider).copied().unwrap_or(Pricing {
+                    connection_hourly: 0.0,
+                    egress_per_gb: 0.0,
+                }),
+                true,
+            ),
+        }
+    }
+}
+
+impl Default for PricingTable {
+    /// Approximate public list prices: AWS Site-to-Site VPN, Azure VpnGw1 and
+    /// GCP Partner Interconnect VLAN attachments, with internet/interconnect
+    /// egress for the first pricing tier.
+    fn default() -> Self {
+        let rate = |connection_hourly, egress_per_gb| Pricing { connection_hourly, egress_per_gb };
+
+        let mut table = Self::new(HashMap::from([
+            (CloudProvider::AWS, rate(0.05, 0.09)),
+            (CloudProvider::Azure, rate(0.19, 0.087)),
+            (CloudProvider::GCP, rate(0.10, 0.02)),
+        ]));
+
+        for region in ["us-east-1", "us-east-2", "us-west-1", "us-west-2", "eu-west-1", "eu-central-1"] {
+            table.set_region(CloudProvider::AWS, region, rate(0.05, 0.09));
+        }
+        table.set_region(CloudProvider::AWS, "ap-southeast-1", rate(0.05, 0.12));
+        table.set_region(CloudProvider::AWS, "ap-northeast-1", rate(0.048, 0.114));
+
+        for region in ["eastus", "eastus2", "westus2", "westeurope", "northeurope"] {
+            table.set_region(CloudProvider::Azure, region, rate(0.19, 0.087));
+        }
+        table.set_region(CloudProvider::Azure, "southeastasia", rate(0.19, 0.12));
+
+        for region in ["us-central1", "us-east1", "us-west1", "europe-west1"] {
+            table.set_region(CloudProvider::GCP, region, rate(0.10, 0.02));
+        }
+        table.set_region(CloudProvider::GCP, "asia-east1", rate(0.10, 0.05));
+
+        table
+    }
+}
+
+/// Egress observed on a connection
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TrafficRecord {
+    pub egress_bytes: u64,
+    /// Wall time the bytes were counted over
+    pub observed: Duration,
+}
+
+impl TrafficRecord {
+    pub fn add(&mut self, egress_bytes: u64, interval: Duration) {
+        self.egress_bytes = self.egress_bytes.saturating_add(egress_bytes);
+        self.observed += interval;
+    }
+
+    /// Egress extrapolated over `period` at the observed rate
+    pub fn project_gb(&self, period: Duration) -> f64 {
+        if self.observed.is_zero() {
+            return 0.0;
+        }
+        let rate = self.egress_bytes as f64 / self.observed.as_secs_f64();
+        rate * period.as_secs_f64() / BYTES_PER_GB
+    }
+}
+
+/// Billing terms of one connection on top of the list price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostModel {
+    /// Negotiated rates; the pricing table's when unset
+    #[serde(default)]
+    pub pricing: Option<Pricing>,
+    /// Egress GB per month that is not charged
+    #[serde(default)]
+    pub free_egress_gb_per_month: f64,
+    /// Share taken off the charges for committed use, e.g. 0.25
+    #[serde(default)]
+    pub committed_use_discount: f64,
+    /// Spend committed to per month, charged even if usage falls short
+    #[serde(default)]
+    pub committed_spend_per_month: f64,
+}
+
+/// Egress recorded per hour, for billing over arbitrary periods
+#[derive(Debug, Clone, Default)]
+pub struct UsageLedger {
+    /// Bytes by hour since the Unix epoch
+    hours: BTreeMap<u64, u64>,
+    /// Last cumulative counter reading and when it was taken
+    last_counter: Option<(u64, SystemTime)>,
+    /// Latest time anything was recorded
+    latest: Option<SystemTime>,
+}
+
+impl UsageLedger {
+    pub fn add(&mut self, bytes: u64, at: SystemTime) {
+        let entry = self.hours.entry(hour_of(at)).or_insert(0);
+        *entry = entry.saturating_add(bytes);
+        self.latest = self.latest.max(Some(at));
+    }
+
+    /// Take a cumulative byte counter and record the bytes since the last
+    /// reading; returns them with the time they were counted over
+    ///
+    /// The first reading only sets the baseline. A counter that went
+    /// backwards was reset, so all of its value is new.
+    pub fn add_counter(&mut self, total_bytes: u64, at: SystemTime) -> Option<(u64, Duration)> {
+        let previous = self.last_counter.replace((total_bytes, at));
+        let (last, last_at) = previous?;
+        let bytes = if total_bytes >= last { total_bytes - last } else { total_bytes };
+        self.add(bytes, at);
+        Some((bytes, at.duration_since(last_at).unwrap_or_default()))
+    }
+
+    /// Bytes recorded over [start, end)
+    ///
+    /// Hours only partly inside the period count pro rata. The hour still
+    /// being recorded spans only up to the latest recording, so a period
+    /// ending now takes all of it.
+    pub fn bytes_between(&self, start: SystemTime, end: SystemTime) -> u64 {
+        let (start, end) = (secs_of(start), secs_of(end));
+        if start >= end {
+            return 0;
+        }
+        let latest = self.latest.map(secs_of);
+
+        let hours = (start / 3600.0) as u64..=(end / 3600.0) as u64;
+        let bytes: f64 = self.hours.range(hours)
+            .map(|(&hour, &bytes)| {
+                let from = (hour * 3600) as f64;
+                let to = match latest {
+                    Some(latest) if latest >= from && latest < from + 3600.0 => latest,
+                    _ => from + 3600.0,
+                };
+                let share = if to > from {
+                    ((end.min(to) - start.max(from)) / (to - from)).clamp(0.0, 1.0)
+                } else if start <= from && from < end {
+                    1.0
+                } else {
+                    0.0
+                };
+                bytes as f64 * share
+            })
+            .sum();
+        bytes.round() as u64
+    }
+}
+
+fn hour_of(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 3600
+}
+
+fn secs_of(at: SystemTime) -> f64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// A span of time to bill
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillingPeriod {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl BillingPeriod {
+    pub fn new(start: SystemTime, end: SystemTime) -> Self {
+        Self { start, end }
+    }
+
+    /// The `length` up to now
+    pub fn last(length: Duration) -> Self {
+        let end = SystemTime::now();
+        Self { start: end - length, end }
+    }
+
+    pub fn hours(&self) -> f64 {
+        self.end.duration_since(self.start).unwrap_or_default().as_secs_f64() / 3600.0
+    }
+}
+
+/// Billed spend of one connection over a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionSpend {
+    pub provider: CloudProvider,
+    pub region: String,
+    pub egress_gb: f64,
+    /// Egress covered by the free tier
+    pub free_gb: f64,
+    pub billable_gb: f64,
+    pub connection_cost: f64,
+    pub egress_cost: f64,
+    /// Committed-use discount taken off the charges
+    pub discount: f64,
+    /// Charged to make up the spend commitment
+    pub commitment_shortfall: f64,
+    pub total: f64,
+    /// The region had no rate and the provider default was used
+    pub default_rate: bool,
+}
+
+impl ConnectionSpend {
+    /// What a GB of egress effectively cost, for comparing connections
+    pub fn cost_per_gb(&self) -> Option<f64> {
+        (self.egress_gb > 0.0).then(|| self.total / self.egress_gb)
+    }
+}
+
+/// Billed spend per connection and provider over a period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostReport {
+    pub period: BillingPeriod,
+    pub connections: Vec<ConnectionSpend>,
+    pub per_provider: BTreeMap<CloudProvider, f64>,
+    pub total: f64,
+    /// At least one connection was priced with a fallback rate
+    pub uses_default_rates: bool,
+}
+
+impl CostReport {
+    pub fn from_connections(period: BillingPeriod, mut connections: Vec<ConnectionSpend>) -> Self {
+        connections.sort_by(|a, b| (a.provider, &a.region).cmp(&(b.provider, &b.region)));
+
+        let mut per_provider = BTreeMap::new();
+        for spend in &connections {
+            *per_provider.entry(spend.provider).or_insert(0.0) += spend.total;
+        }
+
+        Self {
+            period,
+            total: connections.iter().map(|c| c.total).sum(),
+            uses_default_rates: connections.iter().any(|c| c.default_rate),
+            per_provider,
+            connections,
+        }
+    }
+
+    /// One row per connection, then a subtotal per provider and the total
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "Provider,Region,Egress GB,Free GB,Billable GB,Connection Cost,Egress Cost,Discount,Commitment Shortfall,Total,Default Rate\n",
+        );
+        for c in &self.connections {
+            csv.push_str(&format!(
+                "{:?},{},{:.3},{:.3},{:.3},{:.2},{:.2},{:.2},{:.2},{:.2},{}\n",
+                c.provider,
+                csv_field(&c.region),
+                c.egress_gb,
+                c.free_gb,
+                c.billable_gb,
+                c.connection_cost,
+                c.egress_cost,
+                c.discount,
+                c.commitment_shortfall,
+                c.total,
+                c.default_rate,
+            ));
+        }
+        for (provider, total) in &self.per_provider {
+            csv.push_str(&format!("{:?},All,,,,,,,,{:.2},\n", provider, total));
+        }
+        csv.push_str(&format!("All,All,,,,,,,,{:.2},{}\n", self.total, self.uses_default_rates));
+        csv
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Bill one connection for `egress_bytes` sent over a period of `hours`
+pub fn bill(
+    provider: CloudProvider,
+    region: &str,
+    egress_bytes: u64,
+    hours: f64,
+    model: &CostModel,
+    pricing: &PricingTable,
+) -> ConnectionSpend {
+    let (rate, default_rate) = match model.pricing {
+        Some(rate) => (rate, false),
+        None => pricing.rate(provider, region),
+    };
+    if default_rate {
+        tracing::warn!("No {:?} pricing for region {}, using the provider default", provider, region);
+    }
+    let months = hours / (MONTH.as_secs_f64() / 3600.0);
+
+    let egress_gb = egress_bytes as f64 / BYTES_PER_GB;
+    let free_gb = egress_gb.min(model.free_egress_gb_per_month * months);
+    let billable_gb = egress_gb - free_gb;
+    let connection_cost = hours * rate.connection_hourly;
+    let egress_cost = billable_gb * rate.egress_per_gb;
+    let discount = (connection_cost + egress_cost) * model.committed_use_discount.clamp(0.0, 1.0);
+    let charged = connection_cost + egress_cost - discount;
+    let commitment_shortfall = (model.committed_spend_per_month * months - charged).max(0.0);
+
+    ConnectionSpend {
+        provider,
+        region: region.to_string(),
+        egress_gb,
+        free_gb,
+        billable_gb,
+        connection_cost,
+        egress_cost,
+        discount,
+        commitment_shortfall,
+        total: charged + commitment_shortfall,
+        default_rate,
+    }
+}
+
+/// Projected spend over a period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostBreakdown {
+    pub period_hours: f64,
+    pub connections: Vec<ConnectionSpend>,
+    pub per_provider: BTreeMap<CloudProvider, f64>,
+    pub total: f64,
+    /// At least one connection was priced with a fallback rate
+    pub uses_default_rates: bool,
+}
+
+impl CostBreakdown {
+    pub fn from_connections(period: Duration, mut connections: Vec<ConnectionSpend>) -> Self {
+        connections.sort_by(|a, b| (a.provider, &a.region).cmp(&(b.provider, &b.region)));
+
+        let mut per_provider = BTreeMap::new();
+        for cost in &connections {
+            *per_provider.entry(cost.provider).or_insert(0.0) += cost.total;
+        }
+
+        Self {
+            period_hours: period.as_secs_f64() / 3600.0,
+            total: connections.iter().map(|c| c.total).sum(),
+            uses_default_rates: connections.iter().any(|c| c.default_rate),
+            per_provider,
+            connections,
+        }
+    }
+}
+
+/// Project the spend of one connection over `period` from its recorded
+/// egress rate, priced with its cost model as [`bill`] would
+pub fn estimate(
+    provider: CloudProvider,
+    region: &str,
+    traffic: &TrafficRecord,
+    model: &CostModel,
+    pricing: &PricingTable,
+    period: Duration,
+) -> ConnectionSpend {
+    let egress_bytes = (traffic.project_gb(period) * BYTES_PER_GB).round() as u64;
+    bill(provider, region, egress_bytes, period.as_secs_f64() / 3600.0, model, pricing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_override_and_fallback() {
+        let mut table = PricingTable::default();
+        assert_eq!(table.rate(CloudProvider::AWS, "us-east-1"), (Pricing { connection_hourly: 0.05, egress_per_gb: 0.09 }, false));
+
+        let (rate, fallback) = table.rate(CloudProvider::GCP, "me-central2");
+        assert!(fallback);
+        assert_eq!(rate.egress_per_gb, 0.02);
+
+        table.set_region(CloudProvider::GCP, "me-central2", Pricing { connection_hourly: 0.2, egress_per_gb: 0.08 });
+        assert!(!table.rate(CloudProvider::GCP, "me-central2").1);
+    }
+
+    #[test]
+    fn test_traffic_projection() {
+        let mut traffic = TrafficRecord::default();
+        assert_eq!(traffic.project_gb(MONTH), 0.0);
+
+        // 1 GiB per hour for two hours -> 730 GiB a month
+        traffic.add(1 << 30, Duration::from_secs(3600));
+        traffic.add(1 << 30, Duration::from_secs(3600));
+        assert!((traffic.project_gb(MONTH) - 730.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_counter_deltas_and_reset() {
+        let mut ledger = UsageLedger::default();
+        let t0 = UNIX_EPOCH + Duration::from_secs(1_000 * 3600);
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        assert_eq!(ledger.add_counter(5_000, t0), None);
+        assert_eq!(ledger.add_counter(8_000, at(600)), Some((3_000, Duration::from_secs(600))));
+        // Counter restarted at zero and has since counted 1000
+        assert_eq!(ledger.add_counter(1_000, at(4_000)), Some((1_000, Duration::from_secs(3_400))));
+
+        assert_eq!(ledger.bytes_between(t0, at(3600)), 3_000);
+        assert_eq!(ledger.bytes_between(t0, at(7200)), 4_000);
+        assert_eq!(ledger.bytes_between(at(3600), t0), 0);
+    }
+
+    #[test]
+    fn test_partial_hours_are_prorated() {
+        let mut ledger = UsageLedger::default();
+        let t0 = UNIX_EPOCH + Duration::from_secs(1_000 * 3600);
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        ledger.add(3_600, at(3_599));
+        // The current hour has been recorded for 30 minutes
+        ledger.add(1_000, at(5_400));
+
+        assert_eq!(ledger.bytes_between(at(1_800), at(3_600)), 1_800);
+        assert_eq!(ledger.bytes_between(at(3_600), at(4_500)), 500);
+        // A period ending now takes all of the current hour so far
+        assert_eq!(ledger.bytes_between(t0, at(5_400)), 4_600);
+    }
+
+    #[test]
+    fn test_free_tier_discount_and_commitment() {
+        let table = PricingTable::default();
+        let gib = 1u64 << 30;
+        let month_hours = 730.0;
+        let model = CostModel {
+            pricing: Some(Pricing { connection_hourly: 0.10, egress_per_gb: 0.05 }),
+            free_egress_gb_per_month: 100.0,
+            committed_use_discount: 0.2,
+            committed_spend_per_month: 0.0,
+        };
+
+        // 1100 GiB, 100 free: (73 + 50) * 0.8
+        let spend = bill(CloudProvider::GCP, "us-central1", 1100 * gib, month_hours, &model, &table);
+        assert!((spend.free_gb - 100.0).abs() < 1e-9);
+        assert!((spend.egress_cost - 50.0).abs() < 1e-9);
+        assert!((spend.discount - 24.6).abs() < 1e-9);
+        assert!((spend.total - 98.4).abs() < 1e-9);
+        assert!(!spend.default_rate);
+
+        // Half a month under a 200/month commitment is floored at 100
+        let committed = CostModel { committed_spend_per_month: 200.0, ..model };
+        let spend = bill(CloudProvider::GCP, "us-central1", 10 * gib, month_hours / 2.0, &committed, &table);
+        assert_eq!(spend.billable_gb, 0.0);
+        assert!((spend.total - 100.0).abs() < 1e-9);
+        assert!(spend.commitment_shortfall > 0.0);
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let table = PricingTable::default();
+        let period = BillingPeriod::new(UNIX_EPOCH, UNIX_EPOCH + MONTH);
+        let report = CostReport::from_connections(period, vec![
+            bill(CloudProvider::GCP, "us-central1", 0, period.hours(), &CostModel::default(), &table),
+            bill(CloudProvider::AWS, "us-east-1", 1 << 30, period.hours(), &CostModel::default(), &table),
+        ]);
+
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("Provider,Region,Egress GB"));
+        assert_eq!(lines[1], "AWS,us-east-1,1.000,0.000,1.000,36.50,0.09,0.00,0.00,36.59,false");
+        assert_eq!(lines[3], "AWS,All,,,,,,,,36.59,");
+        assert_eq!(lines[5], "All,All,,,,,,,,109.59,false");
+        assert!(lines.iter().all(|l| l.split(',').count() == 11));
+    }
+}
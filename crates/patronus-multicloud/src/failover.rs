@@ -0,0 +1,398 @@
+//! Cross-Cloud Failover
+//!
+//! A failover group carries a set of prefixes over one of several cloud
+//! connections, in priority order. When the active member fails the prefixes
+//! move immediately to the best healthy member; when a higher-priority member
+//! recovers they only move back once it has stayed healthy for the dampening
+//! period, so a flapping link does not drag traffic back and forth.
+//!
+//! Operators can pin a group to a member with a manual failover and release
+//! it with a failback. Every switchover is handed to the registered
+//! [`FailoverAction`]s, e.g. [`BgpFailover`], which move the routes, or
+//! `SdwanFailover` (feature `sdwan`), which re-steers SD-WAN flows.
+
+use crate::manager::CloudConnection;
+use anyhow::Result;
+use async_trait::async_trait;
+use ipnetwork::IpNetwork;
+use patronus_bgp::BgpManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::RwLock;
+
+/// Connections that back each other up for a set of prefixes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverGroup {
+    pub name: String,
+    /// Connection keys (`Provider_region`), most preferred first
+    pub members: Vec<String>,
+    /// Prefixes routed over the active member
+    pub prefixes: Vec<IpNetwork>,
+    /// How long a preferred member must stay healthy before failing back;
+    /// the hold-down that keeps a flapping member from taking traffic
+    pub dampening: Duration,
+    /// Members slower than this count as failed even while connected
+    #[serde(default)]
+    pub max_latency_ms: Option<f64>,
+}
+
+impl FailoverGroup {
+    /// Whether a member connection can carry the group's prefixes
+    pub fn accepts(&self, connection: &CloudConnection) -> bool {
+        connection.connected && self.max_latency_ms.is_none_or(|max| connection.latency_ms <= max)
+    }
+}
+
+/// A change of active member
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailoverEvent {
+    pub group: String,
+    pub kind: FailoverEventKind,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub prefixes: Vec<IpNetwork>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailoverEventKind {
+    /// The group had no active member and one became healthy
+    Activated,
+    /// The active member failed and a backup took over
+    FailedOver,
+    /// A preferred member recovered and took traffic back
+    FailedBack,
+    /// No member is healthy; the prefixes are unreachable
+    AllDown,
+    /// An operator moved the prefixes to a member
+    Manual,
+}
+
+/// Where a group's prefixes currently go
+#[derive(Debug, Clone, Default)]
+pub struct FailoverState {
+    pub active: Option<String>,
+    /// Preferred member that recovered, and since when
+    recovering: Option<(String, Instant)>,
+    /// Member an operator moved the group to; kept until it fails or is released
+    pinned: Option<String>,
+}
+
+impl FailoverState {
+    /// Re-evaluate the active member given each member's health at `now`
+    pub fn evaluate(
+        &mut self,
+        group: &FailoverGroup,
+        healthy: impl Fn(&str) -> bool,
+        now: Instant,
+    ) -> Option<FailoverEvent> {
+        let best = group.members.iter().find(|m| healthy(m)).cloned();
+
+        let active_healthy = self.active.as_deref().is_some_and(&healthy);
+        if !active_healthy {
+            self.recovering = None;
+            self.pinned = None;
+            if best == self.active {
+                return None;
+            }
+            let kind = match (&self.active, &best) {
+                (_, None) => FailoverEventKind::AllDown,
+                (None, Some(_)) => FailoverEventKind::Activated,
+                (Some(_), Some(_)) => FailoverEventKind::FailedOver,
+            };
+            return Some(self.switch(group, kind, best));
+        }
+
+        // Active is healthy; only move back to a member ranked above it, and
+        // never away from where an operator put the group
+        if self.pinned.is_some() {
+            return None;
+        }
+        let rank = |member: &str| group.members.iter().position(|m| m == member);
+        let preferred = best.filter(|b| rank(b) < self.active.as_deref().and_then(rank));
+        let Some(preferred) = preferred else {
+            self.recovering = None;
+            return None;
+        };
+
+        match &self.recovering {
+            Some((member, since)) if *member == preferred => {
+                if now.duration_since(*since) >= group.dampening {
+                    self.recovering = None;
+                    return Some(self.switch(group, FailoverEventKind::FailedBack, Some(preferred)));
+                }
+            }
+            _ => {
+                tracing::info!(
+                    "Failover group {}: {} recovered, failing back in {:?} if it stays up",
+                    group.name,
+                    preferred,
+                    group.dampening
+                );
+                self.recovering = Some((preferred, now));
+            }
+        }
+        None
+    }
+
+    /// Member an operator pinned the group to
+    pub fn pinned(&self) -> Option<&str> {
+        self.pinned.as_deref()
+    }
+
+    /// Move the group to `target` and keep it there while it stays healthy
+    pub fn pin(&mut self, group: &FailoverGroup, target: &str) -> Option<FailoverEvent> {
+        self.recovering = None;
+        self.pinned = Some(target.to_string());
+        if self.active.as_deref() == Some(target) {
+            return None;
+        }
+        Some(self.switch(group, FailoverEventKind::Manual, Some(target.to_string())))
+    }
+
+    /// Release a pin and move straight to the most preferred healthy member,
+    /// skipping the dampening period
+    pub fn failback(&mut self, group: &FailoverGroup, healthy: impl Fn(&str) -> bool) -> Option<FailoverEvent> {
+        self.recovering = None;
+        self.pinned = None;
+        let best = group.members.iter().find(|m| healthy(m)).cloned();
+        if best.is_none() || best == self.active {
+            return None;
+        }
+        Some(self.switch(group, FailoverEventKind::FailedBack, best))
+    }
+
+    fn switch(&mut self, group: &FailoverGroup, kind: FailoverEventKind, to: Option<String>) -> FailoverEvent {
+        let from = std::mem::replace(&mut self.active, to.clone());
+        match kind {
+            FailoverEventKind::AllDown => {
+                tracing::error!("Failover group {}: no healthy member", group.name)
+            }
+            _ => tracing::warn!("Failover group {}: {:?} {:?} -> {:?}", group.name, kind, from, to),
+        }
+        FailoverEvent {
+            group: group.name.clone(),
+            kind,
+            from,
+            to,
+            prefixes: group.prefixes.clone(),
+        }
+    }
+}
+
+/// A prefix of a failover group and the connection carrying it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailoverRoute {
+    pub prefix: IpNetwork,
+    pub group: String,
+    pub connection: String,
+    pub next_hop: String,
+}
+
+/// A switchover as it was carried out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverLogEntry {
+    pub at: SystemTime,
+    pub event: FailoverEvent,
+    /// Requested through the manual failover or failback API
+    pub manual: bool,
+    /// Failures of [`FailoverAction`]s; the switchover stands regardless
+    pub action_errors: Vec<String>,
+}
+
+/// What a failure or manual failover would change, without changing it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailoverSimulation {
+    pub events: Vec<FailoverEvent>,
+    pub withdrawn: Vec<FailoverRoute>,
+    pub advertised: Vec<FailoverRoute>,
+}
+
+/// Carries out a switchover outside the manager
+#[async_trait]
+pub trait FailoverAction: Send + Sync {
+    /// `routes` are the group's routes after the switch; empty when all
+    /// members are down
+    async fn apply(&self, event: &FailoverEvent, routes: &[FailoverRoute]) -> Result<()>;
+}
+
+/// Originates each group's prefixes on the running BGP speaker with the
+/// active connection as next hop, and withdraws them when no member is
+/// healthy
+///
+/// Only prefixes this action originated are moved or withdrawn; a prefix
+/// the speaker already originates for another reason is left alone.
+pub struct BgpFailover {
+    manager: Arc<RwLock<BgpManager>>,
+    /// Group name -> prefixes originated for it
+    originated: Mutex<HashMap<String, Vec<IpNetwork>>>,
+}
+
+impl BgpFailover {
+    pub fn new(manager: Arc<RwLock<BgpManager>>) -> Self {
+        Self { manager, originated: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl FailoverAction for BgpFailover {
+    async fn apply(&self, event: &FailoverEvent, routes: &[FailoverRoute]) -> Result<()> {
+        let next_hop = match routes.first() {
+            Some(route) => Some(route.next_hop.parse::<IpAddr>().map_err(|_| {
+                anyhow::anyhow!("Next hop {} of {} is not an address", route.next_hop, route.connection)
+            })?),
+            None => None,
+        };
+
+        let mut manager = self.manager.write().await;
+        let mut originated = self.originated.lock().unwrap_or_else(|e| e.into_inner());
+        let ours = originated.entry(event.group.clone()).or_default();
+
+        let wanted: &[IpNetwork] = if next_hop.is_some() { &event.prefixes } else { &[] };
+        ours.retain(|prefix| {
+            if wanted.contains(prefix) {
+                return true;
+            }
+            manager.withdraw_originated(*prefix);
+            false
+        });
+        let Some(next_hop) = next_hop else {
+            tracing::warn!("Withdrew the prefixes of failover group {}", event.group);
+            return Ok(());
+        };
+
+        for prefix in &event.prefixes {
+            if !ours.contains(prefix) && manager.originated(prefix).is_some() {
+                tracing::warn!(
+                    "{} is already originated; failover group {} leaves it alone",
+                    prefix, event.group
+                );
+                continue;
+            }
+            manager.originate(*prefix, next_hop)?;
+            if !ours.contains(prefix) {
+                ours.push(*prefix);
+            }
+        }
+        tracing::info!("Advertising the prefixes of failover group {} via {}", event.group, next_hop);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus_bgp::BgpConfig;
+
+    fn group() -> FailoverGroup {
+        FailoverGroup {
+            name: "prod".to_string(),
+            members: vec!["AWS_us-east-1".to_string(), "Azure_eastus".to_string()],
+            prefixes: vec!["10.50.0.0/16".parse().unwrap()],
+            dampening: Duration::from_secs(30),
+            max_latency_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_flapping_primary_restarts_dampening() {
+        let group = group();
+        let mut state = FailoverState::default();
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let only_backup = |m: &str| m == "Azure_eastus";
+        let both = |_: &str| true;
+
+        let event = state.evaluate(&group, only_backup, t0).unwrap();
+        assert_eq!(event.kind, FailoverEventKind::Activated);
+        assert_eq!(event.to.as_deref(), Some("Azure_eastus"));
+
+        // Primary back at 10s, flaps at 25s, back at 26s: the timer restarts
+        assert!(state.evaluate(&group, both, at(10)).is_none());
+        assert!(state.evaluate(&group, only_backup, at(25)).is_none());
+        assert!(state.evaluate(&group, both, at(26)).is_none());
+        assert!(state.evaluate(&group, both, at(45)).is_none());
+        let event = state.evaluate(&group, both, at(56)).unwrap();
+        assert_eq!(event.kind, FailoverEventKind::FailedBack);
+        assert_eq!(state.active.as_deref(), Some("AWS_us-east-1"));
+
+        let event = state.evaluate(&group, |_| false, at(60)).unwrap();
+        assert_eq!(event.kind, FailoverEventKind::AllDown);
+        assert_eq!(event.to, None);
+        assert!(state.evaluate(&group, |_| false, at(61)).is_none());
+    }
+
+    #[test]
+    fn test_pin_holds_until_member_fails() {
+        let group = group();
+        let mut state = FailoverState::default();
+        let t0 = Instant::now();
+        let both = |_: &str| true;
+        state.evaluate(&group, both, t0);
+
+        let event = state.pin(&group, "Azure_eastus").unwrap();
+        assert_eq!(event.kind, FailoverEventKind::Manual);
+        assert_eq!(state.pinned(), Some("Azure_eastus"));
+        // No failback to the preferred member, however long it stays up
+        assert!(state.evaluate(&group, both, t0 + Duration::from_secs(3600)).is_none());
+
+        // The pin does not survive the member failing
+        let event = state.evaluate(&group, |m| m == "AWS_us-east-1", t0 + Duration::from_secs(3601)).unwrap();
+        assert_eq!(event.kind, FailoverEventKind::FailedOver);
+        assert_eq!(state.pinned(), None);
+
+        state.pin(&group, "Azure_eastus");
+        let event = state.failback(&group, both).unwrap();
+        assert_eq!(event.kind, FailoverEventKind::FailedBack);
+        assert_eq!(state.active.as_deref(), Some("AWS_us-east-1"));
+        assert!(state.failback(&group, both).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bgp_failover_moves_next_hop() {
+        let manager = Arc::new(RwLock::new(BgpManager::new(BgpConfig {
+            asn: 65000,
+            router_id: "10.0.0.1".parse().unwrap(),
+            neighbors: vec![],
+            networks: vec![],
+            route_maps: vec![],
+            timers: Default::default(),
+            dampening: None,
+        })));
+        // Originated by the operator, not by failover
+        let static_prefix: IpNetwork = "192.168.0.0/16".parse().unwrap();
+        let static_hop: IpAddr = "10.0.0.1".parse().unwrap();
+        manager.write().await.originate(static_prefix, static_hop).unwrap();
+
+        let bgp = BgpFailover::new(manager.clone());
+        let mut group = group();
+        group.prefixes.push(static_prefix);
+        let mut state = FailoverState::default();
+        let route = |next_hop: &str| FailoverRoute {
+            prefix: group.prefixes[0],
+            group: group.name.clone(),
+            connection: "Azure_eastus".to_string(),
+            next_hop: next_hop.to_string(),
+        };
+        let next_hops = |manager: &BgpManager| {
+            manager.advertised_routes().iter().map(|r| (r.to_ip_network(), r.next_hop_ip())).collect::<Vec<_>>()
+        };
+
+        let event = state.evaluate(&group, |_| true, Instant::now()).unwrap();
+        bgp.apply(&event, &[route("169.254.10.1")]).await.unwrap();
+        let event = state.evaluate(&group, |m| m == "Azure_eastus", Instant::now()).unwrap();
+        bgp.apply(&event, &[route("10.2.0.1")]).await.unwrap();
+        assert_eq!(
+            next_hops(&*manager.read().await),
+            vec![(group.prefixes[0], "10.2.0.1".parse().unwrap()), (static_prefix, static_hop)]
+        );
+
+        let event = state.evaluate(&group, |_| false, Instant::now()).unwrap();
+        bgp.apply(&event, &[]).await.unwrap();
+        assert_eq!(next_hops(&*manager.read().await), vec![(static_prefix, static_hop)]);
+
+        assert!(bgp.apply(&event, &[route("not-an-address")]).await.is_err());
+    }
+}
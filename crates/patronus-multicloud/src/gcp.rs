@@ -1,10 +1,26 @@
 //! GCP Connectivity
 //!
 //! Connects to GCP VPC and Cloud Interconnect
+//!
+//! HA VPN is built with `gcloud compute`: an HA VPN gateway, an external
+//! gateway describing our two uplinks, a Cloud Router, and one tunnel from
+//! each gateway interface to the matching uplink, each with its own router
+//! interface and BGP session. Tunnel 0 is the preferred path in both
+//! directions. Every step looks for what a previous run created first, so
+//! re-running converges on the requested state.
 
+use crate::health::{self, ConnectionProbe, LinkType};
+use crate::ipsec::{generate_psk, inside_addresses, LocalIpsec, LocalIpsecTunnel};
 use crate::manager::{CloudConnection, CloudProvider};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use patronus_bgp::config::{RouteMapAction, RouteMapRule, SetAction};
+use patronus_bgp::{AddressFamily, BgpConfig, NeighborConfig, RouteMapConfig};
+use patronus_secrets::{SecretManager, SecretType};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
 
 /// GCP configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,14 +31,275 @@ pub struct GcpConfig {
     pub network_name: String,
 }
 
+/// Runs Compute Engine operations
+#[async_trait]
+pub trait ComputeApi: Send + Sync {
+    /// Run `gcloud compute` with `args` (e.g. `vpn-tunnels describe t0`) and
+    /// return its JSON output; `Null` when it prints nothing or the resource
+    /// does not exist
+    async fn call(&self, args: &[String]) -> Result<Value>;
+}
+
+/// [`ComputeApi`] through the `gcloud` command line client, authenticated
+/// with the service account key file in the configuration
+pub struct GcloudCli {
+    project_id: String,
+    key_file: String,
+}
+
+impl GcloudCli {
+    pub fn new(config: &GcpConfig) -> Self {
+        Self {
+            project_id: config.project_id.clone(),
+            key_file: config.service_account_key.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ComputeApi for GcloudCli {
+    async fn call(&self, args: &[String]) -> Result<Value> {
+        let mut command = tokio::process::Command::new("gcloud");
+        command.arg("compute")
+            .args(args)
+            .args(["--project", &self.project_id, "--format", "json", "--quiet"])
+            .kill_on_drop(true);
+        if !self.key_file.is_empty() {
+            command.env("CLOUDSDK_AUTH_CREDENTIAL_FILE_OVERRIDE", &self.key_file);
+        }
+        let output = command.output().await.context("Failed to run gcloud")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("was not found") {
+                return Ok(Value::Null);
+            }
+            anyhow::bail!("gcloud compute {}: {}", args.join(" "), stderr.trim());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&stdout).with_context(|| format!("Unexpected output from gcloud compute {}", args.join(" ")))
+    }
+}
+
+/// HA VPN from this node's two uplinks to a VPC network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HaVpnSpec {
+    /// Prefixes the names of every resource created
+    pub name: String,
+    /// Public addresses of our uplinks; tunnel N ends on the Nth
+    pub local_interfaces: [Ipv4Addr; 2],
+    pub local_asn: u32,
+    /// ASN of the Cloud Router
+    pub cloud_router_asn: u32,
+    /// Link-local /30 inside each tunnel; the Cloud Router takes the first
+    /// address and we the second
+    pub inside_cidrs: [String; 2],
+}
+
+/// Local preference and MED per tunnel, so tunnel 0 is preferred both ways
+const TUNNEL_LOCAL_PREF: [u32; 2] = [200, 100];
+const TUNNEL_MED: [u32; 2] = [100, 200];
+
+/// One of the two tunnels of an HA VPN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HaVpnTunnel {
+    pub index: usize,
+    pub name: String,
+    /// Address of the HA VPN gateway interface the tunnel ends on
+    pub gcp_address: Ipv4Addr,
+    pub local_address: Ipv4Addr,
+    pub gcp_inside_ip: Ipv4Addr,
+    pub local_inside_ip: Ipv4Addr,
+    /// Cloud Router interface and BGP peer of the tunnel
+    pub router_interface: String,
+    pub bgp_peer: String,
+    /// MED the Cloud Router advertises over this tunnel
+    pub advertised_route_priority: u32,
+    /// patronus-secrets key of the pre-shared key
+    pub psk_secret: String,
+}
+
+/// An HA VPN and the Cloud Router carrying its BGP sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HaVpnAttachment {
+    pub spec: HaVpnSpec,
+    pub region: String,
+    pub network: String,
+    pub gateway: String,
+    pub external_gateway: String,
+    pub router: String,
+    pub tunnels: Vec<HaVpnTunnel>,
+}
+
+impl HaVpnAttachment {
+    /// BGP neighbors for the Cloud Router end of each tunnel, with the route
+    /// maps from [`route_maps`](Self::route_maps)
+    pub fn bgp_neighbors(&self) -> Vec<NeighborConfig> {
+        self.tunnels
+            .iter()
+            .map(|tunnel| NeighborConfig {
+                ip: tunnel.gcp_inside_ip.into(),
+                asn: self.spec.cloud_router_asn,
+                description: Some(format!("{} ({})", tunnel.name, self.router)),
+                password: None,
+                timers: None,
+                route_map_in: Some(format!("{}-in", tunnel.name)),
+                route_map_out: Some(format!("{}-out", tunnel.name)),
+                next_hop_self: false,
+                address_families: vec![AddressFamily::Ipv4Unicast],
+                prefix_limit: None,
+            })
+            .collect()
+    }
+
+    /// Per tunnel, a higher local preference on what we learn and a lower
+    /// MED on what we advertise for tunnel 0
+    pub fn route_maps(&self) -> Vec<RouteMapConfig> {
+        self.tunnels
+            .iter()
+            .flat_map(|tunnel| {
+                let preference = tunnel.index.min(1);
+                [
+                    (format!("{}-in", tunnel.name), SetAction::LocalPreference { value: TUNNEL_LOCAL_PREF[preference] }),
+                    (format!("{}-out", tunnel.name), SetAction::Med { value: TUNNEL_MED[preference] }),
+                ]
+            })
+            .map(|(name, action)| RouteMapConfig {
+                name,
+                rules: vec![RouteMapRule {
+                    sequence: 10,
+                    action: RouteMapAction::Permit,
+                    match_conditions: vec![],
+                    set_actions: vec![action],
+                }],
+            })
+            .collect()
+    }
+
+    /// Add or replace this VPN's neighbors and route maps in `config`
+    pub fn apply_bgp(&self, config: &mut BgpConfig) {
+        for map in self.route_maps() {
+            config.route_maps.retain(|m| m.name != map.name);
+            config.route_maps.push(map);
+        }
+        for neighbor in self.bgp_neighbors() {
+            config.neighbors.retain(|n| n.ip != neighbor.ip);
+            config.neighbors.push(neighbor);
+        }
+    }
+
+    /// Local side of each tunnel
+    pub fn local_tunnels(&self) -> Vec<LocalIpsecTunnel> {
+        self.tunnels
+            .iter()
+            .map(|tunnel| LocalIpsecTunnel {
+                name: tunnel.name.clone(),
+                local_address: tunnel.local_address,
+                remote_address: tunnel.gcp_address,
+                local_inside_ip: tunnel.local_inside_ip,
+                remote_inside_ip: tunnel.gcp_inside_ip,
+                inside_prefix_len: 30,
+                // Route based: BGP decides what goes into the tunnel
+                local_subnets: vec!["0.0.0.0/0".to_string()],
+                remote_subnets: vec!["0.0.0.0/0".to_string()],
+                psk_secret: tunnel.psk_secret.clone(),
+            })
+            .collect()
+    }
+
+    /// The VPN as a cloud connection over its preferred tunnel
+    pub fn connection(&self) -> CloudConnection {
+        let tunnel = self.tunnels.first();
+        CloudConnection {
+            provider: CloudProvider::GCP,
+            region: self.region.clone(),
+            vpc_id: self.network.clone(),
+            local_ip: tunnel.map(|t| t.local_inside_ip.to_string()).unwrap_or_default(),
+            remote_ip: tunnel.map(|t| t.gcp_inside_ip.to_string()).unwrap_or_default(),
+            tunnel_id: 0,
+            connected: true,
+            latency_ms: 0.0,
+        }
+    }
+}
+
+/// State of one HA VPN tunnel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HaVpnTunnelStatus {
+    pub name: String,
+    /// GCP tunnel status, e.g. `ESTABLISHED`; `MISSING` if it is gone
+    pub status: String,
+    pub bgp_up: bool,
+}
+
+impl HaVpnTunnelStatus {
+    pub fn is_up(&self) -> bool {
+        self.status == "ESTABLISHED" && self.bgp_up
+    }
+
+    /// The tunnel will not come up by itself and must be recreated
+    fn is_failed(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "MISSING" | "FAILED" | "NEGOTIATION_FAILURE" | "AUTHORIZATION_ERROR" | "NO_INCOMING_PACKETS" | "REJECTED" | "STOPPED"
+        )
+    }
+}
+
+/// State of both tunnels of an HA VPN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HaVpnStatus {
+    pub tunnels: Vec<HaVpnTunnelStatus>,
+}
+
+impl HaVpnStatus {
+    /// Both tunnels carry traffic, as the 99.99% SLA requires
+    pub fn is_redundant(&self) -> bool {
+        self.tunnels.len() == 2 && self.tunnels.iter().all(HaVpnTunnelStatus::is_up)
+    }
+
+    pub fn up_count(&self) -> usize {
+        self.tunnels.iter().filter(|t| t.is_up()).count()
+    }
+}
+
 /// GCP connector
 pub struct GcpConnector {
     config: GcpConfig,
+    compute: Arc<dyn ComputeApi>,
+    secrets: Option<Arc<SecretManager>>,
+    ipsec: Option<Arc<dyn LocalIpsec>>,
 }
 
 impl GcpConnector {
     pub fn new(config: GcpConfig) -> Self {
-        Self { config }
+        Self {
+            compute: Arc::new(GcloudCli::new(&config)),
+            config,
+            secrets: None,
+            ipsec: None,
+        }
+    }
+
+    /// Reach Compute Engine through something other than `gcloud`
+    pub fn with_compute_api(mut self, compute: Arc<dyn ComputeApi>) -> Self {
+        self.compute = compute;
+        self
+    }
+
+    /// Where tunnel pre-shared keys are kept
+    pub fn with_secrets(mut self, secrets: Arc<SecretManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Configure the local side of HA VPN tunnels
+    pub fn with_local_ipsec(mut self, ipsec: Arc<dyn LocalIpsec>) -> Self {
+        self.ipsec = Some(ipsec);
+        self
     }
 
     /// Connect to GCP VPC
@@ -79,6 +356,343 @@ impl GcpConnector {
 
         Ok(())
     }
+
+    /// Bring up an HA VPN from our two uplinks to the configured network
+    ///
+    /// Creates whatever an earlier run did not: the HA VPN gateway, the
+    /// external gateway for our uplinks, the Cloud Router and, per tunnel,
+    /// the tunnel, its router interface and BGP peer. The tunnel keys are
+    /// generated here and kept in patronus-secrets. When a [`LocalIpsec`] is
+    /// set, both tunnels are configured locally; BGP comes from
+    /// [`HaVpnAttachment::apply_bgp`].
+    pub async fn connect_ha_vpn(&self, spec: &HaVpnSpec) -> Result<HaVpnAttachment> {
+        self.secrets()?;
+        let region = self.config.region.clone();
+        let network = self.config.network_name.clone();
+        let gateway = format!("{}-gw", spec.name);
+        let external_gateway = format!("{}-peer", spec.name);
+        let router = format!("{}-router", spec.name);
+
+        if self.describe("vpn-gateways", &gateway, true).await?.is_none() {
+            tracing::info!("Creating HA VPN gateway {} in {}", gateway, region);
+            self.compute.call(&args(&["vpn-gateways", "create", &gateway, "--network", &network, "--region", &region])).await?;
+        }
+        let gateway_addresses = self.describe("vpn-gateways", &gateway, true).await?
+            .map(|gw| interface_addresses(&gw, "vpnInterfaces"))
+            .filter(|addresses| addresses.len() == 2)
+            .with_context(|| format!("HA VPN gateway {} does not have two interfaces", gateway))?;
+
+        let interfaces = format!("0={},1={}", spec.local_interfaces[0], spec.local_interfaces[1]);
+        match self.describe("external-vpn-gateways", &external_gateway, false).await? {
+            Some(existing) => {
+                if interface_addresses(&existing, "interfaces") != spec.local_interfaces {
+                    anyhow::bail!(
+                        "External VPN gateway {} exists with other addresses; tear the VPN down first",
+                        external_gateway
+                    );
+                }
+            }
+            None => {
+                tracing::info!("Creating external VPN gateway {} for {}", external_gateway, interfaces);
+                self.compute.call(&args(&["external-vpn-gateways", "create", &external_gateway, "--interfaces", &interfaces])).await?;
+            }
+        }
+
+        if self.describe("routers", &router, true).await?.is_none() {
+            tracing::info!("Creating Cloud Router {} (ASN {})", router, spec.cloud_router_asn);
+            self.compute.call(&args(&[
+                "routers", "create", &router,
+                "--network", &network,
+                "--region", &region,
+                "--asn", &spec.cloud_router_asn.to_string(),
+            ])).await?;
+        }
+
+        let mut attachment = HaVpnAttachment {
+            spec: spec.clone(),
+            region,
+            network,
+            gateway,
+            external_gateway,
+            router,
+            tunnels: Vec::new(),
+        };
+        for index in 0..2 {
+            let (gcp_inside_ip, local_inside_ip) = inside_addresses(&spec.inside_cidrs[index])?;
+            let name = format!("{}-t{}", spec.name, index);
+            attachment.tunnels.push(HaVpnTunnel {
+                index,
+                gcp_address: gateway_addresses[index],
+                local_address: spec.local_interfaces[index],
+                gcp_inside_ip,
+                local_inside_ip,
+                router_interface: format!("{}-if", name),
+                bgp_peer: format!("{}-bgp", name),
+                advertised_route_priority: TUNNEL_MED[index],
+                psk_secret: format!("gcp/havpn/{}/{}", attachment.network, name),
+                name,
+            });
+        }
+
+        for tunnel in &attachment.tunnels {
+            self.ensure_tunnel(&attachment, tunnel).await?;
+        }
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                self.configure_local(ipsec.as_ref(), &tunnel).await?;
+            }
+        }
+
+        tracing::info!("HA VPN {} up to network {}", attachment.spec.name, attachment.network);
+        Ok(attachment)
+    }
+
+    /// Tunnel and BGP session state of both tunnels
+    pub async fn ha_vpn_status(&self, attachment: &HaVpnAttachment) -> Result<HaVpnStatus> {
+        let router_status = self.compute.call(&args(&[
+            "routers", "get-status", &attachment.router, "--region", &attachment.region,
+        ])).await?;
+        let peers = router_status.pointer("/result/bgpPeerStatus").map(list_of).unwrap_or_default();
+
+        let mut tunnels = Vec::new();
+        for tunnel in &attachment.tunnels {
+            let status = self.describe("vpn-tunnels", &tunnel.name, true).await?
+                .map(|t| str_at(&t, "status").unwrap_or("UNKNOWN").to_string())
+                .unwrap_or_else(|| "MISSING".to_string());
+            let bgp_up = peers.iter()
+                .any(|p| str_at(p, "name") == Some(tunnel.bgp_peer.as_str()) && str_at(p, "status") == Some("UP"));
+            tunnels.push(HaVpnTunnelStatus { name: tunnel.name.clone(), status, bgp_up });
+        }
+        Ok(HaVpnStatus { tunnels })
+    }
+
+    /// Restore redundancy when only one tunnel is up
+    ///
+    /// The down tunnel's local side is reconfigured, and a tunnel GCP
+    /// reports as failed is recreated with its router interface and BGP
+    /// peer. Tunnels still negotiating are left to finish. With both tunnels
+    /// down the fault is more likely our uplinks, so nothing is touched and
+    /// an error is returned. Returns the names of the tunnels repaired.
+    pub async fn repair_ha_vpn(&self, attachment: &HaVpnAttachment) -> Result<Vec<String>> {
+        let status = self.ha_vpn_status(attachment).await?;
+        match status.up_count() {
+            2 => return Ok(Vec::new()),
+            0 => anyhow::bail!("Both tunnels of HA VPN {} are down; check the local uplinks", attachment.spec.name),
+            _ => {}
+        }
+
+        let mut repaired = Vec::new();
+        for (tunnel, state) in attachment.tunnels.iter().zip(&status.tunnels) {
+            if state.is_up() {
+                continue;
+            }
+            tracing::warn!("HA VPN tunnel {} is down ({}, BGP {})", tunnel.name, state.status, if state.bgp_up { "up" } else { "down" });
+
+            if state.is_failed() {
+                self.remove_tunnel(attachment, tunnel).await?;
+            } else if state.status != "ESTABLISHED" {
+                // Still coming up
+                continue;
+            }
+            self.ensure_tunnel(attachment, tunnel).await?;
+            if let Some(ipsec) = &self.ipsec {
+                if let Some(local) = attachment.local_tunnels().into_iter().find(|t| t.name == tunnel.name) {
+                    self.configure_local(ipsec.as_ref(), &local).await?;
+                }
+            }
+            repaired.push(tunnel.name.clone());
+        }
+        Ok(repaired)
+    }
+
+    /// Remove an HA VPN
+    ///
+    /// Resources go in dependency order: BGP peers, router interfaces and
+    /// tunnels, then the Cloud Router, the external gateway and the HA VPN
+    /// gateway. Parts already gone are skipped, so an interrupted teardown
+    /// can be re-run. Call it along with
+    /// [`MultiCloudManager::remove_connection`](crate::MultiCloudManager::remove_connection).
+    pub async fn remove_ha_vpn(&self, attachment: &HaVpnAttachment) -> Result<()> {
+        for tunnel in &attachment.tunnels {
+            self.remove_tunnel(attachment, tunnel).await?;
+        }
+        let region = attachment.region.as_str();
+        if self.describe("routers", &attachment.router, true).await?.is_some() {
+            self.compute.call(&args(&["routers", "delete", &attachment.router, "--region", region])).await?;
+        }
+        if self.describe("external-vpn-gateways", &attachment.external_gateway, false).await?.is_some() {
+            self.compute.call(&args(&["external-vpn-gateways", "delete", &attachment.external_gateway])).await?;
+        }
+        if self.describe("vpn-gateways", &attachment.gateway, true).await?.is_some() {
+            self.compute.call(&args(&["vpn-gateways", "delete", &attachment.gateway, "--region", region])).await?;
+        }
+
+        if let Some(ipsec) = &self.ipsec {
+            for tunnel in attachment.local_tunnels() {
+                ipsec.remove(&tunnel).await?;
+            }
+        }
+        if let Some(secrets) = &self.secrets {
+            for tunnel in &attachment.tunnels {
+                if secrets.get_secret(&tunnel.psk_secret).await?.is_some() {
+                    secrets.delete_secret(&tunnel.psk_secret).await?;
+                }
+            }
+        }
+
+        tracing::info!("Removed HA VPN {} from network {}", attachment.spec.name, attachment.network);
+        Ok(())
+    }
+
+    /// Create the tunnel, its router interface and BGP peer where missing,
+    /// and correct the advertised route priority
+    async fn ensure_tunnel(&self, attachment: &HaVpnAttachment, tunnel: &HaVpnTunnel) -> Result<()> {
+        let region = attachment.region.as_str();
+        let psk = match self.secrets()?.get_secret(&tunnel.psk_secret).await? {
+            Some(psk) => psk,
+            None => {
+                let psk = generate_psk();
+                self.secrets()?.store_secret(
+                    &tunnel.psk_secret,
+                    psk.clone(),
+                    SecretType::General,
+                    format!("Pre-shared key of HA VPN tunnel {}", tunnel.name),
+                    None,
+                ).await?;
+                psk
+            }
+        };
+
+        if self.describe("vpn-tunnels", &tunnel.name, true).await?.is_none() {
+            tracing::info!("Creating HA VPN tunnel {}", tunnel.name);
+            let index = tunnel.index.to_string();
+            self.compute.call(&args(&[
+                "vpn-tunnels", "create", &tunnel.name,
+                "--region", region,
+                "--vpn-gateway", &attachment.gateway,
+                "--interface", &index,
+                "--peer-external-gateway", &attachment.external_gateway,
+                "--peer-external-gateway-interface", &index,
+                "--router", &attachment.router,
+                "--ike-version", "2",
+                "--shared-secret", psk.expose_secret(),
+            ])).await?;
+        }
+
+        let router = self.describe("routers", &attachment.router, true).await?
+            .with_context(|| format!("Cloud Router {} is gone", attachment.router))?;
+        let has_interface = router.get("interfaces").map(list_of).unwrap_or_default().iter()
+            .any(|i| str_at(i, "name") == Some(tunnel.router_interface.as_str()));
+        if !has_interface {
+            self.compute.call(&args(&[
+                "routers", "add-interface", &attachment.router,
+                "--region", region,
+                "--interface-name", &tunnel.router_interface,
+                "--vpn-tunnel", &tunnel.name,
+                "--ip-address", &tunnel.gcp_inside_ip.to_string(),
+                "--mask-length", "30",
+            ])).await?;
+        }
+
+        let priority = tunnel.advertised_route_priority.to_string();
+        let peer = router.get("bgpPeers").map(list_of).unwrap_or_default().into_iter()
+            .find(|p| str_at(p, "name") == Some(tunnel.bgp_peer.as_str()));
+        match peer {
+            None => {
+                self.compute.call(&args(&[
+                    "routers", "add-bgp-peer", &attachment.router,
+                    "--region", region,
+                    "--peer-name", &tunnel.bgp_peer,
+                    "--interface", &tunnel.router_interface,
+                    "--peer-ip-address", &tunnel.local_inside_ip.to_string(),
+                    "--peer-asn", &attachment.spec.local_asn.to_string(),
+                    "--advertised-route-priority", &priority,
+                ])).await?;
+            }
+            Some(peer) if peer.get("advertisedRoutePriority").and_then(Value::as_u64) != Some(tunnel.advertised_route_priority as u64) => {
+                self.compute.call(&args(&[
+                    "routers", "update-bgp-peer", &attachment.router,
+                    "--region", region,
+                    "--peer-name", &tunnel.bgp_peer,
+                    "--advertised-route-priority", &priority,
+                ])).await?;
+            }
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Remove a tunnel's BGP peer, router interface and the tunnel itself
+    async fn remove_tunnel(&self, attachment: &HaVpnAttachment, tunnel: &HaVpnTunnel) -> Result<()> {
+        let region = attachment.region.as_str();
+        if let Some(router) = self.describe("routers", &attachment.router, true).await? {
+            let has = |key: &str, name: &str| {
+                router.get(key).map(list_of).unwrap_or_default().iter().any(|item| str_at(item, "name") == Some(name))
+            };
+            if has("bgpPeers", &tunnel.bgp_peer) {
+                self.compute.call(&args(&[
+                    "routers", "remove-bgp-peer", &attachment.router, "--region", region, "--peer-name", &tunnel.bgp_peer,
+                ])).await?;
+            }
+            if has("interfaces", &tunnel.router_interface) {
+                self.compute.call(&args(&[
+                    "routers", "remove-interface", &attachment.router, "--region", region, "--interface-name", &tunnel.router_interface,
+                ])).await?;
+            }
+        }
+        if self.describe("vpn-tunnels", &tunnel.name, true).await?.is_some() {
+            tracing::info!("Deleting HA VPN tunnel {}", tunnel.name);
+            self.compute.call(&args(&["vpn-tunnels", "delete", &tunnel.name, "--region", region])).await?;
+        }
+        Ok(())
+    }
+
+    async fn configure_local(&self, ipsec: &dyn LocalIpsec, tunnel: &LocalIpsecTunnel) -> Result<()> {
+        let psk = self.secrets()?.get_secret(&tunnel.psk_secret).await?
+            .ok_or_else(|| anyhow::anyhow!("Pre-shared key {} went missing", tunnel.psk_secret))?;
+        ipsec.configure(tunnel, &psk).await
+    }
+
+    /// A resource, or `None` if it does not exist
+    async fn describe(&self, kind: &str, name: &str, regional: bool) -> Result<Option<Value>> {
+        let mut describe = args(&[kind, "describe", name]);
+        if regional {
+            describe.extend(args(&["--region", &self.config.region]));
+        }
+        let output = self.compute.call(&describe).await?;
+        Ok((!output.is_null()).then_some(output))
+    }
+
+    fn secrets(&self) -> Result<&Arc<SecretManager>> {
+        self.secrets.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("A secret manager is needed to keep the tunnel pre-shared keys"))
+    }
+
+    /// Health probe for a connection made by this connector
+    pub fn health_probe(&self, link: LinkType) -> Arc<dyn ConnectionProbe> {
+        health::probe_for(CloudProvider::GCP, link)
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+/// Interface addresses of a gateway, in interface id order
+fn interface_addresses(gateway: &Value, key: &str) -> Vec<Ipv4Addr> {
+    let mut interfaces: Vec<(u64, Ipv4Addr)> = gateway.get(key).map(list_of).unwrap_or_default().iter()
+        .filter_map(|i| Some((i.get("id").and_then(Value::as_u64).unwrap_or(0), str_at(i, "ipAddress")?.parse().ok()?)))
+        .collect();
+    interfaces.sort();
+    interfaces.into_iter().map(|(_, ip)| ip).collect()
+}
+
+fn str_at<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn list_of(value: &Value) -> Vec<Value> {
+    value.as_array().cloned().unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -101,4 +715,310 @@ mod tests {
         assert_eq!(connection.region, "us-central1");
         assert!(connection.connected);
     }
+
+    use crate::ipsec::LocalIpsecTunnel;
+    use patronus_secrets::{MemoryStore, SecretString};
+    use serde_json::json;
+    use std::collections::{BTreeMap, HashSet};
+    use std::sync::Mutex;
+
+    /// Compute Engine kept in memory, keyed by (kind, name)
+    #[derive(Default)]
+    struct FakeCompute {
+        state: Mutex<ComputeState>,
+    }
+
+    #[derive(Default)]
+    struct ComputeState {
+        resources: BTreeMap<(String, String), Value>,
+        /// BGP peers whose session is down
+        down_peers: HashSet<String>,
+        /// "kind verb name" per call
+        calls: Vec<String>,
+    }
+
+    impl ComputeState {
+        fn position(&self, call: &str) -> usize {
+            self.calls.iter().position(|c| c == call).unwrap_or_else(|| panic!("{} not called", call))
+        }
+
+        fn mutating_calls(&self) -> usize {
+            self.calls.iter().filter(|c| !c.contains(" describe ") && !c.contains(" get-status ")).count()
+        }
+    }
+
+    fn flag(args: &[String], name: &str) -> String {
+        let i = args.iter().position(|a| a == name).unwrap_or_else(|| panic!("missing {}", name));
+        args[i + 1].clone()
+    }
+
+    #[async_trait]
+    impl ComputeApi for FakeCompute {
+        async fn call(&self, args: &[String]) -> Result<Value> {
+            let mut state = self.state.lock().unwrap();
+            let (kind, verb, name) = (args[0].clone(), args[1].clone(), args[2].clone());
+            state.calls.push(format!("{} {} {}", kind, verb, name));
+            let key = (kind.clone(), name.clone());
+
+            Ok(match (kind.as_str(), verb.as_str()) {
+                (_, "describe") => state.resources.get(&key).cloned().unwrap_or(Value::Null),
+                (_, "delete") => {
+                    state.resources.remove(&key).expect("deleting a missing resource");
+                    Value::Null
+                }
+                ("vpn-gateways", "create") => {
+                    let gateway = json!({
+                        "name": name,
+                        "vpnInterfaces": [{ "id": 1, "ipAddress": "35.242.1.2" }, { "id": 0, "ipAddress": "35.242.1.1" }],
+                    });
+                    state.resources.insert(key, gateway);
+                    Value::Null
+                }
+                ("external-vpn-gateways", "create") => {
+                    let interfaces: Vec<Value> = flag(args, "--interfaces").split(',')
+                        .map(|i| i.split_once('=').unwrap())
+                        .map(|(id, ip)| json!({ "id": id.parse::<u64>().unwrap(), "ipAddress": ip }))
+                        .collect();
+                    state.resources.insert(key, json!({ "name": name, "interfaces": interfaces }));
+                    Value::Null
+                }
+                ("routers", "create") => {
+                    let asn: u64 = flag(args, "--asn").parse().unwrap();
+                    state.resources.insert(key, json!({ "name": name, "bgp": { "asn": asn }, "interfaces": [], "bgpPeers": [] }));
+                    Value::Null
+                }
+                ("vpn-tunnels", "create") => {
+                    let router = ("routers".to_string(), flag(args, "--router"));
+                    assert!(state.resources.contains_key(&router), "tunnel created before its router");
+                    state.resources.insert(key, json!({
+                        "name": name,
+                        "status": "ESTABLISHED",
+                        "vpnGatewayInterface": flag(args, "--interface").parse::<u64>().unwrap(),
+                        "sharedSecret": flag(args, "--shared-secret"),
+                    }));
+                    Value::Null
+                }
+                ("routers", "add-interface") => {
+                    let interface = json!({ "name": flag(args, "--interface-name"), "linkedVpnTunnel": flag(args, "--vpn-tunnel") });
+                    state.resources.get_mut(&key).unwrap()["interfaces"].as_array_mut().unwrap().push(interface);
+                    Value::Null
+                }
+                ("routers", "add-bgp-peer") => {
+                    let peer = json!({
+                        "name": flag(args, "--peer-name"),
+                        "interfaceName": flag(args, "--interface"),
+                        "peerIpAddress": flag(args, "--peer-ip-address"),
+                        "peerAsn": flag(args, "--peer-asn").parse::<u64>().unwrap(),
+                        "advertisedRoutePriority": flag(args, "--advertised-route-priority").parse::<u64>().unwrap(),
+                    });
+                    state.resources.get_mut(&key).unwrap()["bgpPeers"].as_array_mut().unwrap().push(peer);
+                    Value::Null
+                }
+                ("routers", "remove-bgp-peer") | ("routers", "remove-interface") => {
+                    let (list, item) = if verb == "remove-bgp-peer" {
+                        ("bgpPeers", flag(args, "--peer-name"))
+                    } else {
+                        ("interfaces", flag(args, "--interface-name"))
+                    };
+                    let router = state.resources.get_mut(&key).unwrap();
+                    if list == "interfaces" {
+                        let peers = router["bgpPeers"].as_array().unwrap();
+                        assert!(!peers.iter().any(|p| p["interfaceName"] == item), "interface removed before its BGP peer");
+                    }
+                    router[list].as_array_mut().unwrap().retain(|i| i["name"] != item);
+                    Value::Null
+                }
+                ("routers", "get-status") => {
+                    let peers: Vec<Value> = state.resources[&key]["bgpPeers"].as_array().unwrap().iter()
+                        .map(|p| p["name"].as_str().unwrap().to_string())
+                        .map(|name| json!({ "status": if state.down_peers.contains(&name) { "DOWN" } else { "UP" }, "name": name }))
+                        .collect();
+                    json!({ "result": { "bgpPeerStatus": peers } })
+                }
+                other => panic!("unexpected call {:?}", other),
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeIpsec {
+        tunnels: Mutex<Vec<(LocalIpsecTunnel, String)>>,
+        configured: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl LocalIpsec for FakeIpsec {
+        async fn configure(&self, tunnel: &LocalIpsecTunnel, psk: &SecretString) -> Result<()> {
+            *self.configured.lock().unwrap() += 1;
+            let mut tunnels = self.tunnels.lock().unwrap();
+            tunnels.retain(|(t, _)| t.name != tunnel.name);
+            tunnels.push((tunnel.clone(), psk.expose_secret().to_string()));
+            Ok(())
+        }
+
+        async fn remove(&self, tunnel: &LocalIpsecTunnel) -> Result<()> {
+            self.tunnels.lock().unwrap().retain(|(t, _)| t.name != tunnel.name);
+            Ok(())
+        }
+    }
+
+    fn ha_connector(compute: Arc<FakeCompute>, ipsec: Arc<FakeIpsec>) -> (GcpConnector, Arc<SecretManager>) {
+        let secrets = Arc::new(SecretManager::new(Arc::new(MemoryStore::new())));
+        let connector = GcpConnector::new(GcpConfig {
+            project_id: "corp-net".to_string(),
+            service_account_key: String::new(),
+            region: "us-central1".to_string(),
+            network_name: "prod-vpc".to_string(),
+        })
+        .with_compute_api(compute)
+        .with_secrets(secrets.clone())
+        .with_local_ipsec(ipsec);
+        (connector, secrets)
+    }
+
+    fn ha_spec() -> HaVpnSpec {
+        HaVpnSpec {
+            name: "dc1".to_string(),
+            local_interfaces: ["198.51.100.10".parse().unwrap(), "203.0.113.10".parse().unwrap()],
+            local_asn: 65010,
+            cloud_router_asn: 64520,
+            inside_cidrs: ["169.254.20.0/30".to_string(), "169.254.20.4/30".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_ha_vpn() {
+        let compute = Arc::new(FakeCompute::default());
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = ha_connector(compute.clone(), ipsec.clone());
+
+        let attachment = connector.connect_ha_vpn(&ha_spec()).await.unwrap();
+        assert_eq!(attachment.tunnels.len(), 2);
+        let (t0, t1) = (&attachment.tunnels[0], &attachment.tunnels[1]);
+        assert_eq!(t0.gcp_address, "35.242.1.1".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(t1.gcp_address, "35.242.1.2".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(t1.local_address, "203.0.113.10".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(t1.gcp_inside_ip, "169.254.20.5".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(t1.local_inside_ip, "169.254.20.6".parse::<Ipv4Addr>().unwrap());
+
+        let psk = secrets.get_secret(&t0.psk_secret).await.unwrap().unwrap();
+        {
+            let state = compute.state.lock().unwrap();
+            let router = &state.resources[&("routers".to_string(), "dc1-router".to_string())];
+            let peers = router["bgpPeers"].as_array().unwrap();
+            assert_eq!(peers.len(), 2);
+            assert_eq!(peers[0]["peerIpAddress"], "169.254.20.2");
+            assert_eq!(peers[0]["advertisedRoutePriority"], 100);
+            assert_eq!(peers[1]["advertisedRoutePriority"], 200);
+
+            // The tunnel and our side share the stored key
+            let tunnel = &state.resources[&("vpn-tunnels".to_string(), "dc1-t0".to_string())];
+            assert_eq!(tunnel["sharedSecret"], psk.expose_secret());
+            assert_eq!(ipsec.tunnels.lock().unwrap()[0].1, psk.expose_secret());
+        }
+        assert_eq!(ipsec.tunnels.lock().unwrap().len(), 2);
+
+        // Tunnel 0 is preferred in both directions
+        let mut bgp = BgpConfig {
+            asn: 65010,
+            router_id: "10.0.0.1".parse().unwrap(),
+            neighbors: vec![],
+            networks: vec![],
+            route_maps: vec![],
+            timers: Default::default(),
+            dampening: None,
+        };
+        attachment.apply_bgp(&mut bgp);
+        attachment.apply_bgp(&mut bgp);
+        assert_eq!(bgp.neighbors.len(), 2);
+        assert_eq!(bgp.route_maps.len(), 4);
+        assert_eq!(bgp.neighbors[0].route_map_in.as_deref(), Some("dc1-t0-in"));
+        let set = |name: &str| bgp.route_maps.iter().find(|m| m.name == name).unwrap().rules[0].set_actions.clone();
+        assert!(matches!(set("dc1-t0-in")[..], [SetAction::LocalPreference { value: 200 }]));
+        assert!(matches!(set("dc1-t1-in")[..], [SetAction::LocalPreference { value: 100 }]));
+        assert!(matches!(set("dc1-t0-out")[..], [SetAction::Med { value: 100 }]));
+        assert!(matches!(set("dc1-t1-out")[..], [SetAction::Med { value: 200 }]));
+
+        // Re-running changes nothing
+        let before = compute.state.lock().unwrap().mutating_calls();
+        connector.connect_ha_vpn(&ha_spec()).await.unwrap();
+        assert_eq!(compute.state.lock().unwrap().mutating_calls(), before);
+
+        // Different uplinks need a teardown first
+        let mut moved = ha_spec();
+        moved.local_interfaces[1] = "192.0.2.10".parse().unwrap();
+        assert!(connector.connect_ha_vpn(&moved).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repair_single_tunnel() {
+        let compute = Arc::new(FakeCompute::default());
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, _) = ha_connector(compute.clone(), ipsec.clone());
+        let attachment = connector.connect_ha_vpn(&ha_spec()).await.unwrap();
+
+        assert!(connector.ha_vpn_status(&attachment).await.unwrap().is_redundant());
+        assert!(connector.repair_ha_vpn(&attachment).await.unwrap().is_empty());
+
+        {
+            let mut state = compute.state.lock().unwrap();
+            let t1 = state.resources.get_mut(&("vpn-tunnels".to_string(), "dc1-t1".to_string())).unwrap();
+            t1["status"] = json!("NEGOTIATION_FAILURE");
+            state.down_peers.insert("dc1-t1-bgp".to_string());
+            state.calls.clear();
+        }
+        let status = connector.ha_vpn_status(&attachment).await.unwrap();
+        assert_eq!(status.up_count(), 1);
+        assert!(!status.is_redundant());
+
+        let configured = *ipsec.configured.lock().unwrap();
+        assert_eq!(connector.repair_ha_vpn(&attachment).await.unwrap(), vec!["dc1-t1".to_string()]);
+        {
+            let state = compute.state.lock().unwrap();
+            assert!(state.position("routers remove-bgp-peer dc1-router") < state.position("vpn-tunnels delete dc1-t1"));
+            assert!(state.position("vpn-tunnels delete dc1-t1") < state.position("vpn-tunnels create dc1-t1"));
+            assert!(!state.calls.iter().any(|c| c.ends_with("dc1-t0") && !c.contains("describe")));
+            assert_eq!(state.resources[&("routers".to_string(), "dc1-router".to_string())]["bgpPeers"].as_array().unwrap().len(), 2);
+        }
+        assert_eq!(*ipsec.configured.lock().unwrap(), configured + 1);
+
+        // With both down the uplinks are suspect; nothing is recreated
+        {
+            let mut state = compute.state.lock().unwrap();
+            state.down_peers.insert("dc1-t0-bgp".to_string());
+            state.down_peers.insert("dc1-t1-bgp".to_string());
+        }
+        assert!(connector.repair_ha_vpn(&attachment).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remove_ha_vpn_in_dependency_order() {
+        let compute = Arc::new(FakeCompute::default());
+        let ipsec = Arc::new(FakeIpsec::default());
+        let (connector, secrets) = ha_connector(compute.clone(), ipsec.clone());
+        let attachment = connector.connect_ha_vpn(&ha_spec()).await.unwrap();
+        compute.state.lock().unwrap().calls.clear();
+
+        connector.remove_ha_vpn(&attachment).await.unwrap();
+        {
+            let state = compute.state.lock().unwrap();
+            assert!(state.resources.is_empty());
+            let order = [
+                "routers remove-bgp-peer dc1-router",
+                "routers remove-interface dc1-router",
+                "vpn-tunnels delete dc1-t1",
+                "routers delete dc1-router",
+                "external-vpn-gateways delete dc1-peer",
+                "vpn-gateways delete dc1-gw",
+            ];
+            for pair in order.windows(2) {
+                assert!(state.position(pair[0]) < state.position(pair[1]), "{} before {}", pair[0], pair[1]);
+            }
+        }
+        assert!(ipsec.tunnels.lock().unwrap().is_empty());
+        assert!(secrets.get_secret(&attachment.tunnels[0].psk_secret).await.unwrap().is_none());
+
+        // Safe to run again
+        connector.remove_ha_vpn(&attachment).await.unwrap();
+    }
 }
@@ -0,0 +1,243 @@
+//! Connection Health
+//!
+//! Active liveness checks for cloud connections. Dedicated links (Direct
+//! Connect, ExpressRoute, Cloud Interconnect) are judged by their BGP
+//! session, since the physical port can stay up while routing is gone; VPN
+//! tunnels by whether the far tunnel address answers. A connection is marked
+//! down after several consecutive failed probes and up again on the first
+//! success, and each transition is published as a [`HealthEvent`].
+
+use crate::manager::{CloudConnection, CloudProvider};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// How a connection reaches the cloud
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkType {
+    /// IPsec tunnel over the internet
+    Vpn,
+    /// Private circuit with a BGP session to the provider edge
+    Dedicated,
+}
+
+/// Outcome of one probe
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub up: bool,
+    pub latency_ms: Option<f64>,
+    pub detail: String,
+}
+
+impl ProbeResult {
+    pub fn up(latency_ms: Option<f64>, detail: impl Into<String>) -> Self {
+        Self { up: true, latency_ms, detail: detail.into() }
+    }
+
+    pub fn down(detail: impl Into<String>) -> Self {
+        Self { up: false, latency_ms: None, detail: detail.into() }
+    }
+}
+
+/// Checks whether a cloud connection is passing traffic
+#[async_trait]
+pub trait ConnectionProbe: Send + Sync {
+    async fn probe(&self, connection: &CloudConnection) -> ProbeResult;
+}
+
+/// VPN liveness: a single ICMP echo to the remote tunnel address
+pub struct TunnelProbe {
+    pub timeout: Duration,
+}
+
+impl Default for TunnelProbe {
+    fn default() -> Self {
+        Self { timeout: Duration::from_secs(1) }
+    }
+}
+
+#[async_trait]
+impl ConnectionProbe for TunnelProbe {
+    async fn probe(&self, connection: &CloudConnection) -> ProbeResult {
+        let timeout = self.timeout.as_secs().max(1).to_string();
+        let output = match tokio::process::Command::new("ping")
+            .args(["-c", "1", "-W", &timeout, &connection.remote_ip])
+            .kill_on_drop(true)
+            .output()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => return ProbeResult::down(format!("ping failed: {}", e)),
+        };
+        if !output.status.success() {
+            return ProbeResult::down(format!("no reply from {}", connection.remote_ip));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let latency = stdout
+            .split("time=")
+            .nth(1)
+            .and_then(|s| s.split_whitespace().next())
+            .and_then(|s| s.parse::<f64>().ok());
+        ProbeResult::up(latency, format!("tunnel to {} is up", connection.remote_ip))
+    }
+}
+
+/// Dedicated-link liveness: the FRR BGP session with the provider edge
+pub struct BgpSessionProbe {
+    /// Shown in probe details, e.g. "Direct Connect"
+    pub label: &'static str,
+    /// Peer address; the connection's remote address when unset
+    pub neighbor: Option<String>,
+}
+
+#[async_trait]
+impl ConnectionProbe for BgpSessionProbe {
+    async fn probe(&self, connection: &CloudConnection) -> ProbeResult {
+        let neighbor = self.neighbor.as_deref().unwrap_or(&connection.remote_ip);
+        let output = match tokio::process::Command::new("vtysh")
+            .args(["-c", &format!("show bgp neighbors {} json", neighbor)])
+            .kill_on_drop(true)
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                return ProbeResult::down(format!("vtysh: {}", String::from_utf8_lossy(&output.stderr).trim()))
+            }
+            Err(e) => return ProbeResult::down(format!("vtysh failed: {}", e)),
+        };
+
+        match parse_bgp_state(&String::from_utf8_lossy(&output.stdout), neighbor) {
+            Some(state) if state == "Established" => {
+                ProbeResult::up(None, format!("{} BGP session with {} established", self.label, neighbor))
+            }
+            Some(state) => ProbeResult::down(format!("{} BGP session with {} is {}", self.label, neighbor, state)),
+            None => ProbeResult::down(format!("{} BGP neighbor {} not configured", self.label, neighbor)),
+        }
+    }
+}
+
+/// The `bgpState` of `neighbor` in `show bgp neighbors <ip> json` output
+pub fn parse_bgp_state(json: &str, neighbor: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    value.get(neighbor)?.get("bgpState")?.as_str().map(str::to_string)
+}
+
+/// Probe matching a link type, labelled for the provider's dedicated product
+pub fn probe_for(provider: CloudProvider, link: LinkType) -> std::sync::Arc<dyn ConnectionProbe> {
+    match link {
+        LinkType::Vpn => std::sync::Arc::new(TunnelProbe::default()),
+        LinkType::Dedicated => std::sync::Arc::new(BgpSessionProbe {
+            label: match provider {
+                CloudProvider::AWS => "Direct Connect",
+                CloudProvider::Azure => "ExpressRoute",
+                CloudProvider::GCP => "Cloud Interconnect",
+            },
+            neighbor: None,
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthState {
+    /// Not probed yet
+    Unknown,
+    Up,
+    Down,
+}
+
+/// Liveness of one connection as seen by its probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionHealth {
+    pub state: HealthState,
+    pub latency_ms: Option<f64>,
+    pub detail: String,
+    pub consecutive_failures: u32,
+    pub last_checked: Option<SystemTime>,
+    pub last_change: Option<SystemTime>,
+}
+
+impl Default for ConnectionHealth {
+    fn default() -> Self {
+        Self {
+            state: HealthState::Unknown,
+            latency_ms: None,
+            detail: String::new(),
+            consecutive_failures: 0,
+            last_checked: None,
+            last_change: None,
+        }
+    }
+}
+
+impl ConnectionHealth {
+    /// Fold in a probe result, returning the previous state if it changed
+    ///
+    /// The connection goes down after `failure_threshold` failures in a row
+    /// and comes back up on the first success.
+    pub fn record(&mut self, result: ProbeResult, failure_threshold: u32, now: SystemTime) -> Option<HealthState> {
+        let previous = self.state;
+        self.last_checked = Some(now);
+        self.detail = result.detail;
+        self.latency_ms = result.latency_ms;
+
+        if result.up {
+            self.consecutive_failures = 0;
+            self.state = HealthState::Up;
+        } else {
+            self.consecutive_failures += 1;
+            if self.consecutive_failures >= failure_threshold.max(1) {
+                self.state = HealthState::Down;
+            }
+        }
+
+        if self.state == previous {
+            return None;
+        }
+        self.last_change = Some(now);
+        Some(previous)
+    }
+}
+
+/// A connection changed health state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthEvent {
+    pub provider: CloudProvider,
+    pub region: String,
+    pub from: HealthState,
+    pub to: HealthState,
+    pub detail: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_down_after_threshold_up_on_first_success() {
+        let mut health = ConnectionHealth::default();
+        let now = SystemTime::now();
+
+        assert_eq!(health.record(ProbeResult::up(Some(4.0), "ok"), 3, now), Some(HealthState::Unknown));
+        assert_eq!(health.state, HealthState::Up);
+
+        assert_eq!(health.record(ProbeResult::down("Idle"), 3, now), None);
+        assert_eq!(health.record(ProbeResult::down("Idle"), 3, now), None);
+        assert_eq!(health.state, HealthState::Up);
+        assert_eq!(health.record(ProbeResult::down("Idle"), 3, now), Some(HealthState::Up));
+        assert_eq!(health.state, HealthState::Down);
+        assert_eq!(health.detail, "Idle");
+
+        assert_eq!(health.record(ProbeResult::up(None, "ok"), 3, now), Some(HealthState::Down));
+        assert_eq!(health.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_parse_bgp_state() {
+        let json = r#"{"169.254.10.1":{"remoteAs":64512,"bgpState":"Active","bgpTimerUpMsec":0}}"#;
+        assert_eq!(parse_bgp_state(json, "169.254.10.1").as_deref(), Some("Active"));
+        assert_eq!(parse_bgp_state(json, "169.254.10.2"), None);
+        assert_eq!(parse_bgp_state("{}", "169.254.10.1"), None);
+    }
+}
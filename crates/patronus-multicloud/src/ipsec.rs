@@ -0,0 +1,218 @@
+//! Local IPsec
+//!
+//! This node's end of the route-based VPN tunnels cloud connectors set up.
+//! Each tunnel gets an XFRM interface carrying its inside address, so BGP
+//! can run over it and routes can point into it.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use patronus_secrets::SecretString;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+/// Local end of a VPN tunnel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalIpsecTunnel {
+    pub name: String,
+    pub local_address: Ipv4Addr,
+    pub remote_address: Ipv4Addr,
+    pub local_inside_ip: Ipv4Addr,
+    pub remote_inside_ip: Ipv4Addr,
+    /// Prefix length of the local inside address; the remote one is
+    /// reached through the tunnel either way
+    pub inside_prefix_len: u8,
+    pub local_subnets: Vec<String>,
+    pub remote_subnets: Vec<String>,
+    /// patronus-secrets key of the pre-shared key
+    pub psk_secret: String,
+}
+
+impl LocalIpsecTunnel {
+    /// XFRM interface id, stable across runs
+    pub fn if_id(&self) -> u32 {
+        self.name.bytes().fold(0x811c9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x01000193)) % 0xfffe + 1
+    }
+
+    /// Interface name; tunnel names can exceed the kernel's 15 characters
+    pub fn interface(&self) -> String {
+        format!("xfrm{}", self.if_id())
+    }
+}
+
+/// Cloud and local addresses in a tunnel's inside /30: the cloud takes the
+/// first host address and we the second
+pub(crate) fn inside_addresses(cidr: &str) -> Result<(Ipv4Addr, Ipv4Addr)> {
+    let network: ipnetwork::Ipv4Network = cidr.parse()
+        .with_context(|| format!("Invalid tunnel inside CIDR {}", cidr))?;
+    if network.prefix() != 30 {
+        anyhow::bail!("Tunnel inside CIDR {} is not a /30", cidr);
+    }
+    let base = u32::from(network.network());
+    Ok((Ipv4Addr::from(base + 1), Ipv4Addr::from(base + 2)))
+}
+
+/// A random pre-shared key of 32 letters and digits, which every cloud's
+/// VPN gateway accepts
+pub(crate) fn generate_psk() -> SecretString {
+    let generated: String = patronus_secrets::crypto::generate_token(48)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(32)
+        .collect();
+    SecretString::from(generated)
+}
+
+/// Configures this node's side of IPsec tunnels
+#[async_trait]
+pub trait LocalIpsec: Send + Sync {
+    async fn configure(&self, tunnel: &LocalIpsecTunnel, psk: &SecretString) -> Result<()>;
+    async fn remove(&self, tunnel: &LocalIpsecTunnel) -> Result<()>;
+}
+
+/// [`LocalIpsec`] with strongSwan's swanctl and an XFRM interface per tunnel
+pub struct Swanctl {
+    pub conf_dir: PathBuf,
+}
+
+impl Default for Swanctl {
+    fn default() -> Self {
+        Self { conf_dir: PathBuf::from("/etc/swanctl/conf.d") }
+    }
+}
+
+impl Swanctl {
+    async fn run(program: &str, args: &[&str]) -> Result<()> {
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}", program))?;
+        if !output.status.success() {
+            anyhow::bail!("{} {}: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LocalIpsec for Swanctl {
+    async fn configure(&self, tunnel: &LocalIpsecTunnel, psk: &SecretString) -> Result<()> {
+        let path = self.conf_dir.join(format!("{}.conf", tunnel.name));
+        tokio::fs::write(&path, swanctl_conf(tunnel, psk))
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        // Replace rather than add so a re-run picks up changed addresses
+        let interface = tunnel.interface();
+        let if_id = tunnel.if_id().to_string();
+        let _ = Self::run("ip", &["link", "del", &interface]).await;
+        Self::run("ip", &["link", "add", &interface, "type", "xfrm", "if_id", &if_id]).await?;
+        let address = format!("{}/{}", tunnel.local_inside_ip, tunnel.inside_prefix_len);
+        Self::run("ip", &["addr", "add", &address, "dev", &interface]).await?;
+        Self::run("ip", &["link", "set", &interface, "up"]).await?;
+        let peer = format!("{}/32", tunnel.remote_inside_ip);
+        Self::run("ip", &["route", "replace", &peer, "dev", &interface]).await?;
+        Self::run("swanctl", &["--load-all"]).await
+    }
+
+    async fn remove(&self, tunnel: &LocalIpsecTunnel) -> Result<()> {
+        let path = self.conf_dir.join(format!("{}.conf", tunnel.name));
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => {}
+        }
+        let _ = Self::run("ip", &["link", "del", &tunnel.interface()]).await;
+        Self::run("swanctl", &["--load-all"]).await
+    }
+}
+
+/// swanctl.conf for a tunnel, with IKE and ESP proposals AWS and Azure accept
+pub fn swanctl_conf(tunnel: &LocalIpsecTunnel, psk: &SecretString) -> String {
+    let name = &tunnel.name;
+    format!(
+        r#"connections {{
+    {name} {{
+        version = 2
+        local_addrs = {local}
+        remote_addrs = {remote}
+        proposals = aes256-sha256-modp2048,aes128-sha1-modp1024
+        dpd_delay = 10s
+        if_id_in = {if_id}
+        if_id_out = {if_id}
+        local {{
+            auth = psk
+            id = {local}
+        }}
+        remote {{
+            auth = psk
+            id = {remote}
+        }}
+        children {{
+            {name} {{
+                local_ts = {local_ts}
+                remote_ts = {remote_ts}
+                esp_proposals = aes256-sha256-modp2048,aes128-sha1-modp1024
+                start_action = start
+                dpd_action = restart
+            }}
+        }}
+    }}
+}}
+
+secrets {{
+    ike-{name} {{
+        id-local = {local}
+        id-remote = {remote}
+        secret = "{psk}"
+    }}
+}}
+"#,
+        local = tunnel.local_address,
+        remote = tunnel.remote_address,
+        if_id = tunnel.if_id(),
+        local_ts = tunnel.local_subnets.join(","),
+        remote_ts = tunnel.remote_subnets.join(","),
+        psk = psk.expose_secret(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunnel(name: &str) -> LocalIpsecTunnel {
+        LocalIpsecTunnel {
+            name: name.to_string(),
+            local_address: "198.51.100.7".parse().unwrap(),
+            remote_address: "52.0.0.1".parse().unwrap(),
+            local_inside_ip: "169.254.10.2".parse().unwrap(),
+            remote_inside_ip: "169.254.10.1".parse().unwrap(),
+            inside_prefix_len: 30,
+            local_subnets: vec!["0.0.0.0/0".to_string()],
+            remote_subnets: vec!["0.0.0.0/0".to_string()],
+            psk_secret: "aws/tgw/vpn-1/tunnel1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_swanctl_conf() {
+        let tunnel = tunnel("branch-1-t1");
+        let conf = swanctl_conf(&tunnel, &SecretString::from("Tunnel1Key_abcdef"));
+        assert!(conf.contains("remote_addrs = 52.0.0.1"));
+        assert!(conf.contains(&format!("if_id_out = {}", tunnel.if_id())));
+        assert!(conf.contains(r#"secret = "Tunnel1Key_abcdef""#));
+        assert_eq!(conf.matches('{').count(), conf.matches('}').count());
+    }
+
+    #[test]
+    fn test_interface_names() {
+        let long = tunnel("site-westeurope-hub-primary-link-0");
+        assert_eq!(long.if_id(), tunnel("site-westeurope-hub-primary-link-0").if_id());
+        assert_ne!(long.if_id(), tunnel("site-westeurope-hub-primary-link-1").if_id());
+        assert!(long.interface().len() <= 15);
+    }
+}
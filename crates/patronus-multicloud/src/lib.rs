@@ -7,10 +7,28 @@
 
 pub mod aws;
 pub mod azure;
+pub mod cost;
+pub mod failover;
 pub mod gcp;
+pub mod health;
+pub mod ipsec;
 pub mod manager;
+pub mod routes;
+#[cfg(feature = "sdwan")]
+pub mod sdwan;
+pub mod terraform;
 
-pub use aws::AwsConnector;
-pub use azure::AzureConnector;
-pub use gcp::GcpConnector;
+pub use aws::{AwsConnector, TgwRouting, TgwVpnAttachment, TgwVpnSpec, TransitGateway};
+pub use azure::{ArmClient, AzureApi, AzureConnector, Backoff, VwanAttachment, VwanHubConnection, VwanSiteLink, VwanSiteSpec, VwanTunnel};
+pub use gcp::{ComputeApi, GcloudCli, GcpConnector, HaVpnAttachment, HaVpnSpec, HaVpnStatus, HaVpnTunnel, HaVpnTunnelStatus};
+pub use cost::{BillingPeriod, ConnectionSpend, CostBreakdown, CostModel, CostReport, Pricing, PricingTable, UsageLedger};
+pub use failover::{
+    BgpFailover, FailoverAction, FailoverEvent, FailoverEventKind, FailoverGroup, FailoverLogEntry, FailoverRoute,
+    FailoverSimulation,
+};
+pub use health::{ConnectionHealth, ConnectionProbe, HealthEvent, HealthState, LinkType};
+pub use ipsec::{LocalIpsec, LocalIpsecTunnel, Swanctl};
 pub use manager::{MultiCloudManager, CloudProvider, CloudConnection};
+pub use routes::{CloudRoute, RouteFilter, RoutePolicy};
+#[cfg(feature = "sdwan")]
+pub use sdwan::{SdwanEgressFeed, SdwanFailover};
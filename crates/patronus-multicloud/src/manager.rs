@@ -2,14 +2,23 @@
 //!
 //! Manages connections to multiple cloud providers
 
+use crate::cost::{self, BillingPeriod, CostBreakdown, CostModel, CostReport, Pricing, PricingTable, TrafficRecord, UsageLedger};
+use crate::failover::{
+    FailoverAction, FailoverEvent, FailoverGroup, FailoverLogEntry, FailoverRoute, FailoverSimulation, FailoverState,
+};
+use crate::health::{ConnectionHealth, ConnectionProbe, HealthEvent, HealthState};
+use crate::routes::{CloudRoute, RoutePolicy, RouteReflector};
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
 use anyhow::Result;
 
 /// Cloud provider type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum CloudProvider {
     AWS,
     Azure,
@@ -32,12 +41,39 @@ pub struct CloudConnection {
 /// Multi-cloud connectivity manager
 pub struct MultiCloudManager {
     connections: Arc<RwLock<HashMap<String, CloudConnection>>>,
+    routes: Arc<RwLock<RouteReflector>>,
+    pricing: Arc<RwLock<PricingTable>>,
+    traffic: Arc<RwLock<HashMap<String, TrafficRecord>>>,
+    usage: Arc<RwLock<HashMap<String, UsageLedger>>>,
+    cost_models: Arc<RwLock<HashMap<String, CostModel>>>,
+    failover: Arc<RwLock<HashMap<String, (FailoverGroup, FailoverState)>>>,
+    failover_events: broadcast::Sender<FailoverEvent>,
+    failover_actions: Arc<RwLock<Vec<Arc<dyn FailoverAction>>>>,
+    failover_log: Arc<RwLock<VecDeque<FailoverLogEntry>>>,
+    probes: Arc<RwLock<HashMap<String, Arc<dyn ConnectionProbe>>>>,
+    health: Arc<RwLock<HashMap<String, ConnectionHealth>>>,
+    health_events: broadcast::Sender<HealthEvent>,
+    /// Consecutive failed probes before a connection is marked down
+    failure_threshold: u32,
 }
 
 impl MultiCloudManager {
     pub fn new() -> Self {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            routes: Arc::new(RwLock::new(RouteReflector::new())),
+            pricing: Arc::new(RwLock::new(PricingTable::default())),
+            traffic: Arc::new(RwLock::new(HashMap::new())),
+            usage: Arc::new(RwLock::new(HashMap::new())),
+            cost_models: Arc::new(RwLock::new(HashMap::new())),
+            failover: Arc::new(RwLock::new(HashMap::new())),
+            failover_events: broadcast::channel(64).0,
+            failover_actions: Arc::new(RwLock::new(Vec::new())),
+            failover_log: Arc::new(RwLock::new(VecDeque::new())),
+            probes: Arc::new(RwLock::new(HashMap::new())),
+            health: Arc::new(RwLock::new(HashMap::new())),
+            health_events: broadcast::channel(64).0,
+            failure_threshold: 3,
         }
     }
 
@@ -54,6 +90,12 @@ impl MultiCloudManager {
         let mut connections = self.connections.write().await;
         let key = format!("{:?}_{}", provider, region);
         connections.remove(&key);
+        self.routes.write().await.remove(&key);
+        self.traffic.write().await.remove(&key);
+        self.usage.write().await.remove(&key);
+        self.cost_models.write().await.remove(&key);
+        self.probes.write().await.remove(&key);
+        self.health.write().await.remove(&key);
         Ok(())
     }
 
@@ -82,6 +124,436 @@ impl MultiCloudManager {
         }
         Ok(())
     }
+
+    /// Set the import/export route filters for a connection
+    pub async fn set_route_policy(&self, provider: CloudProvider, region: &str, policy: RoutePolicy) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        self.routes.write().await.set_policy(&key, policy);
+        Ok(())
+    }
+
+    /// Record prefixes learned on a cloud connection so they are reflected to
+    /// the other connections. Returns how many passed the import filter.
+    pub async fn learn_routes(&self, provider: CloudProvider, region: &str, prefixes: Vec<IpNetwork>) -> Result<usize> {
+        let key = self.existing_key(provider, region).await?;
+        let next_hop = self.connections.read().await[&key].remote_ip.clone();
+        let routes = prefixes.into_iter().map(|p| (p, Vec::new())).collect();
+        Ok(self.routes.write().await.learn(&key, &next_hop, routes))
+    }
+
+    /// Withdraw prefixes previously learned on a connection
+    pub async fn withdraw_routes(&self, provider: CloudProvider, region: &str, prefixes: &[IpNetwork]) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        self.routes.write().await.withdraw(&key, prefixes);
+        Ok(())
+    }
+
+    /// Routes the hub advertises to a connection, learned from the other
+    /// connected clouds and passed through its export filter
+    pub async fn advertised_routes(&self, provider: CloudProvider, region: &str) -> Vec<CloudRoute> {
+        let key = format!("{:?}_{}", provider, region);
+        let active: Vec<String> = self.connections.read().await
+            .iter()
+            .filter(|(_, c)| c.connected)
+            .map(|(k, _)| k.clone())
+            .collect();
+        self.routes.read().await.advertised(&key, &active)
+    }
+
+    async fn existing_key(&self, provider: CloudProvider, region: &str) -> Result<String> {
+        let key = format!("{:?}_{}", provider, region);
+        if !self.connections.read().await.contains_key(&key) {
+            anyhow::bail!("No {:?} connection in region {}", provider, region);
+        }
+        Ok(key)
+    }
+
+    /// Replace the pricing table used for cost estimates
+    pub async fn set_pricing(&self, table: PricingTable) {
+        *self.pricing.write().await = table;
+    }
+
+    /// Override the rate for one provider region
+    pub async fn set_region_pricing(&self, provider: CloudProvider, region: &str, pricing: Pricing) {
+        self.pricing.write().await.set_region(provider, region, pricing);
+    }
+
+    /// Set the billing terms of a connection
+    pub async fn set_cost_model(&self, provider: CloudProvider, region: &str, model: CostModel) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        self.cost_models.write().await.insert(key, model);
+        Ok(())
+    }
+
+    /// Record egress counted on a connection over `interval`, ending now
+    ///
+    /// With the `sdwan` feature, `SdwanEgressFeed` records it from the
+    /// SD-WAN traffic statistics.
+    pub async fn record_traffic(&self, provider: CloudProvider, region: &str, egress_bytes: u64, interval: Duration) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        self.usage.write().await.entry(key.clone()).or_default().add(egress_bytes, SystemTime::now());
+        self.traffic.write().await.entry(key).or_default().add(egress_bytes, interval);
+        Ok(())
+    }
+
+    /// Record a cumulative egress counter read at `at`, such as the
+    /// `bytes_matched` of the SD-WAN policy steering into the connection
+    ///
+    /// Only the growth since the previous reading is counted; a counter that
+    /// went backwards is taken to have restarted from zero.
+    pub async fn record_counter(&self, provider: CloudProvider, region: &str, total_bytes: u64, at: SystemTime) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        let delta = self.usage.write().await.entry(key.clone()).or_default().add_counter(total_bytes, at);
+        if let Some((bytes, interval)) = delta {
+            self.traffic.write().await.entry(key).or_default().add(bytes, interval);
+        }
+        Ok(())
+    }
+
+    /// Bill the recorded egress over `period` per connection and provider,
+    /// applying each connection's cost model
+    pub async fn get_cost_report(&self, period: BillingPeriod) -> CostReport {
+        let connections = self.connections.read().await;
+        let usage = self.usage.read().await;
+        let models = self.cost_models.read().await;
+        let pricing = self.pricing.read().await;
+
+        let spend = connections.iter()
+            .map(|(key, conn)| {
+                let bytes = usage.get(key).map(|u| u.bytes_between(period.start, period.end)).unwrap_or(0);
+                let model = models.get(key).copied().unwrap_or_default();
+                cost::bill(conn.provider, &conn.region, bytes, period.hours(), &model, &pricing)
+            })
+            .collect();
+        CostReport::from_connections(period, spend)
+    }
+
+    /// Project spend over `period` (e.g. [`cost::MONTH`]) per connection and
+    /// per provider, from connection hours and the recorded egress rate,
+    /// applying each connection's cost model
+    pub async fn estimate_cost(&self, period: Duration) -> CostBreakdown {
+        let connections = self.connections.read().await;
+        let traffic = self.traffic.read().await;
+        let models = self.cost_models.read().await;
+        let pricing = self.pricing.read().await;
+
+        let costs = connections.iter()
+            .map(|(key, conn)| {
+                let recorded = traffic.get(key).copied().unwrap_or_default();
+                let model = models.get(key).copied().unwrap_or_default();
+                cost::estimate(conn.provider, &conn.region, &recorded, &model, &pricing, period)
+            })
+            .collect();
+        CostBreakdown::from_connections(period, costs)
+    }
+
+    /// Add or replace a failover group; every member must be a known connection
+    pub async fn add_failover_group(&self, group: FailoverGroup) -> Result<()> {
+        if group.members.is_empty() {
+            anyhow::bail!("Failover group {} has no members", group.name);
+        }
+        let connections = self.connections.read().await;
+        if let Some(missing) = group.members.iter().find(|m| !connections.contains_key(*m)) {
+            anyhow::bail!("Failover group {} references unknown connection {}", group.name, missing);
+        }
+        drop(connections);
+
+        self.failover.write().await.insert(group.name.clone(), (group, FailoverState::default()));
+        Ok(())
+    }
+
+    pub async fn remove_failover_group(&self, name: &str) {
+        self.failover.write().await.remove(name);
+    }
+
+    /// Subscribe to failover and failback events
+    pub fn subscribe_failover(&self) -> broadcast::Receiver<FailoverEvent> {
+        self.failover_events.subscribe()
+    }
+
+    /// Move failover group prefixes according to current connection health
+    pub async fn evaluate_failover(&self) -> Vec<FailoverEvent> {
+        self.evaluate_failover_at(Instant::now()).await
+    }
+
+    async fn evaluate_failover_at(&self, now: Instant) -> Vec<FailoverEvent> {
+        let events = {
+            let connections = self.connections.read().await;
+            let mut groups = self.failover.write().await;
+            evaluate_groups(&mut groups, &connections, now)
+        };
+        self.carry_out(&events, false).await;
+        events
+    }
+
+    /// Move a group to `target` and keep it there until `target` fails or
+    /// [`Self::failback`] releases it
+    pub async fn manual_failover(&self, group: &str, target: &str) -> Result<Option<FailoverEvent>> {
+        let event = {
+            let connections = self.connections.read().await;
+            let mut groups = self.failover.write().await;
+            let (group, state) = groups.get_mut(group)
+                .ok_or_else(|| anyhow::anyhow!("No failover group {}", group))?;
+            if !group.members.iter().any(|m| m == target) {
+                anyhow::bail!("{} is not a member of failover group {}", target, group.name);
+            }
+            if !connections.get(target).is_some_and(|c| group.accepts(c)) {
+                anyhow::bail!("Cannot fail group {} over to {}: it is not healthy", group.name, target);
+            }
+            state.pin(group, target)
+        };
+        self.carry_out(event.as_slice(), true).await;
+        Ok(event)
+    }
+
+    /// Release a manual failover and return a group to its most preferred
+    /// healthy member right away
+    pub async fn failback(&self, group: &str) -> Result<Option<FailoverEvent>> {
+        let event = {
+            let connections = self.connections.read().await;
+            let mut groups = self.failover.write().await;
+            let (group, state) = groups.get_mut(group)
+                .ok_or_else(|| anyhow::anyhow!("No failover group {}", group))?;
+            state.failback(group, |key| connections.get(key).is_some_and(|c| group.accepts(c)))
+        };
+        self.carry_out(event.as_slice(), true).await;
+        Ok(event)
+    }
+
+    /// Run a switchover on every registered action, e.g. a [`crate::failover::BgpFailover`]
+    pub async fn add_failover_action(&self, action: Arc<dyn FailoverAction>) {
+        self.failover_actions.write().await.push(action);
+    }
+
+    /// Switchovers, oldest first, optionally for one group
+    pub async fn failover_log(&self, group: Option<&str>) -> Vec<FailoverLogEntry> {
+        self.failover_log.read().await
+            .iter()
+            .filter(|entry| group.is_none_or(|g| entry.event.group == g))
+            .cloned()
+            .collect()
+    }
+
+    /// What would switch over if `down` connections failed now
+    ///
+    /// Nothing is changed and no action runs. Recoveries are not shown, as
+    /// they wait out the dampening period.
+    pub async fn simulate_failover(&self, down: &[&str]) -> FailoverSimulation {
+        let mut connections = self.connections.read().await.clone();
+        for key in down {
+            if let Some(connection) = connections.get_mut(*key) {
+                connection.connected = false;
+            }
+        }
+        let mut groups = self.failover.read().await.clone();
+        let before = self.failover_routes().await;
+        let events = evaluate_groups(&mut groups, &connections, Instant::now());
+        simulation(events, before, group_routes(&groups, &connections))
+    }
+
+    /// What [`Self::manual_failover`] would change, without changing it
+    pub async fn simulate_manual_failover(&self, group: &str, target: &str) -> Result<FailoverSimulation> {
+        let connections = self.connections.read().await.clone();
+        let mut groups = self.failover.read().await.clone();
+        let (entry, state) = groups.get_mut(group)
+            .ok_or_else(|| anyhow::anyhow!("No failover group {}", group))?;
+        if !entry.members.iter().any(|m| m == target) {
+            anyhow::bail!("{} is not a member of failover group {}", target, group);
+        }
+        let before = self.failover_routes().await;
+        let events = state.pin(entry, target).into_iter().collect();
+        Ok(simulation(events, before, group_routes(&groups, &connections)))
+    }
+
+    /// Apply, log and publish switchovers
+    async fn carry_out(&self, events: &[FailoverEvent], manual: bool) {
+        let actions = self.failover_actions.read().await.clone();
+        for event in events {
+            let routes: Vec<FailoverRoute> = self.failover_routes().await
+                .into_iter()
+                .filter(|r| r.group == event.group)
+                .collect();
+
+            let mut action_errors = Vec::new();
+            for action in &actions {
+                if let Err(e) = action.apply(event, &routes).await {
+                    tracing::error!("Failover action for group {} failed: {:#}", event.group, e);
+                    action_errors.push(format!("{:#}", e));
+                }
+            }
+
+            let mut log = self.failover_log.write().await;
+            if log.len() == FAILOVER_LOG_SIZE {
+                log.pop_front();
+            }
+            log.push_back(FailoverLogEntry {
+                at: SystemTime::now(),
+                event: event.clone(),
+                manual,
+                action_errors,
+            });
+            drop(log);
+            let _ = self.failover_events.send(event.clone());
+        }
+    }
+
+    /// Connection currently carrying a failover group's prefixes
+    pub async fn active_member(&self, group: &str) -> Option<String> {
+        self.failover.read().await.get(group).and_then(|(_, state)| state.active.clone())
+    }
+
+    /// Prefixes of every failover group and the connection each is routed over
+    pub async fn failover_routes(&self) -> Vec<FailoverRoute> {
+        let connections = self.connections.read().await;
+        let groups = self.failover.read().await;
+        group_routes(&groups, &connections)
+    }
+
+    /// Consecutive failed probes before a connection is marked down
+    pub fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Probe used to poll a connection's health, e.g. from the connector's
+    /// `health_probe`
+    pub async fn set_health_probe(&self, provider: CloudProvider, region: &str, probe: Arc<dyn ConnectionProbe>) -> Result<()> {
+        let key = self.existing_key(provider, region).await?;
+        self.probes.write().await.insert(key, probe);
+        Ok(())
+    }
+
+    /// Subscribe to connection health transitions
+    pub fn subscribe_health(&self) -> broadcast::Receiver<HealthEvent> {
+        self.health_events.subscribe()
+    }
+
+    /// Last known health of a connection; `Unknown` until it is probed
+    pub async fn get_health(&self, provider: CloudProvider, region: &str) -> ConnectionHealth {
+        let key = format!("{:?}_{}", provider, region);
+        self.health.read().await.get(&key).cloned().unwrap_or_default()
+    }
+
+    /// Probe every connection that has a health probe once
+    ///
+    /// Health transitions update the connection's status, are published to
+    /// [`Self::subscribe_health`] and re-evaluate the failover groups.
+    pub async fn poll_health(&self) -> Vec<HealthEvent> {
+        let mut targets: Vec<(String, CloudConnection, Arc<dyn ConnectionProbe>)> = {
+            let probes = self.probes.read().await;
+            let connections = self.connections.read().await;
+            probes.iter()
+                .filter_map(|(key, probe)| Some((key.clone(), connections.get(key)?.clone(), Arc::clone(probe))))
+                .collect()
+        };
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut events = Vec::new();
+        for (key, conn, probe) in targets {
+            let result = probe.probe(&conn).await;
+            let latency = result.latency_ms;
+
+            let (transition, state, detail) = {
+                let mut health = self.health.write().await;
+                let entry = health.entry(key).or_default();
+                let transition = entry.record(result, self.failure_threshold, SystemTime::now());
+                (transition, entry.state, entry.detail.clone())
+            };
+
+            match state {
+                HealthState::Up => {
+                    let _ = self.update_status(conn.provider, &conn.region, true, latency.unwrap_or(conn.latency_ms)).await;
+                }
+                HealthState::Down => {
+                    let _ = self.update_status(conn.provider, &conn.region, false, 0.0).await;
+                }
+                HealthState::Unknown => {}
+            }
+
+            let Some(from) = transition else {
+                continue;
+            };
+            match state {
+                HealthState::Down => tracing::warn!("Cloud connection {:?} {} is down: {}", conn.provider, conn.region, detail),
+                _ => tracing::info!("Cloud connection {:?} {} is {:?}", conn.provider, conn.region, state),
+            }
+            let event = HealthEvent {
+                provider: conn.provider,
+                region: conn.region,
+                from,
+                to: state,
+                detail,
+            };
+            let _ = self.health_events.send(event.clone());
+            events.push(event);
+        }
+
+        if !events.is_empty() {
+            self.evaluate_failover().await;
+        }
+        events
+    }
+
+    /// Poll connection health every `interval` until the handle is aborted
+    pub fn spawn_health_monitor(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.poll_health().await;
+            }
+        })
+    }
+
+    /// Export all connections as Terraform HCL
+    pub async fn export_terraform(&self) -> String {
+        let connections = self.connections.read().await;
+        let connections: Vec<CloudConnection> = connections.values().cloned().collect();
+        crate::terraform::render(&connections)
+    }
+}
+
+/// Switchovers kept in the failover log
+const FAILOVER_LOG_SIZE: usize = 1000;
+
+type FailoverGroups = HashMap<String, (FailoverGroup, FailoverState)>;
+
+fn evaluate_groups(groups: &mut FailoverGroups, connections: &HashMap<String, CloudConnection>, now: Instant) -> Vec<FailoverEvent> {
+    let mut events = Vec::new();
+    for (group, state) in groups.values_mut() {
+        let healthy = |key: &str| connections.get(key).is_some_and(|c| group.accepts(c));
+        if let Some(event) = state.evaluate(group, healthy, now) {
+            events.push(event);
+        }
+    }
+    events.sort_by(|a, b| a.group.cmp(&b.group));
+    events
+}
+
+fn group_routes(groups: &FailoverGroups, connections: &HashMap<String, CloudConnection>) -> Vec<FailoverRoute> {
+    let mut routes: Vec<FailoverRoute> = groups.values()
+        .filter_map(|(group, state)| {
+            let active = state.active.as_ref()?;
+            let next_hop = connections.get(active)?.remote_ip.clone();
+            Some(group.prefixes.iter().map(move |prefix| FailoverRoute {
+                prefix: *prefix,
+                group: group.name.clone(),
+                connection: active.clone(),
+                next_hop: next_hop.clone(),
+            }))
+        })
+        .flatten()
+        .collect();
+    routes.sort_by(|a, b| (a.prefix, &a.group).cmp(&(b.prefix, &b.group)));
+    routes
+}
+
+fn simulation(events: Vec<FailoverEvent>, before: Vec<FailoverRoute>, after: Vec<FailoverRoute>) -> FailoverSimulation {
+    FailoverSimulation {
+        withdrawn: before.iter().filter(|r| !after.contains(r)).cloned().collect(),
+        advertised: after.iter().filter(|r| !before.contains(r)).cloned().collect(),
+        events,
+    }
 }
 
 impl Default for MultiCloudManager {
@@ -149,4 +621,369 @@ mod tests {
         assert_eq!(aws_connections.len(), 1);
         assert_eq!(aws_connections[0].region, "us-east-1");
     }
+
+    #[tokio::test]
+    async fn test_route_propagation_three_clouds() {
+        use crate::routes::{FilterAction, RouteFilter, RouteFilterEntry};
+
+        let manager = MultiCloudManager::new();
+        for (provider, region, remote_ip) in [
+            (CloudProvider::AWS, "us-east-1", "172.31.0.1"),
+            (CloudProvider::Azure, "eastus", "10.1.0.1"),
+            (CloudProvider::GCP, "us-central1", "10.128.0.1"),
+        ] {
+            manager.add_connection(CloudConnection {
+                provider,
+                region: region.to_string(),
+                vpc_id: format!("net-{}", region),
+                local_ip: "10.0.0.1".to_string(),
+                remote_ip: remote_ip.to_string(),
+                tunnel_id: 1,
+                connected: true,
+                latency_ms: 5.0,
+            }).await.unwrap();
+        }
+
+        let aws_prefix: IpNetwork = "10.50.0.0/16".parse().unwrap();
+        let accepted = manager.learn_routes(CloudProvider::AWS, "us-east-1", vec![aws_prefix]).await.unwrap();
+        assert_eq!(accepted, 1);
+
+        for (provider, region) in [(CloudProvider::Azure, "eastus"), (CloudProvider::GCP, "us-central1")] {
+            let routes = manager.advertised_routes(provider, region).await;
+            assert_eq!(routes.len(), 1);
+            assert_eq!(routes[0].prefix, aws_prefix);
+            assert_eq!(routes[0].source, "AWS_us-east-1");
+            assert_eq!(routes[0].next_hop, "172.31.0.1");
+        }
+        // Never reflected back to its source
+        assert!(manager.advertised_routes(CloudProvider::AWS, "us-east-1").await.is_empty());
+
+        // GCP refuses anything inside 10.50.0.0/16 on export
+        manager.set_route_policy(CloudProvider::GCP, "us-central1", RoutePolicy {
+            import: RouteFilter::permit_all(),
+            export: RouteFilter {
+                entries: vec![
+                    RouteFilterEntry { sequence: 10, action: FilterAction::Deny, prefix: aws_prefix, ge: None, le: Some(32) },
+                    RouteFilterEntry { sequence: 20, action: FilterAction::Permit, prefix: "0.0.0.0/0".parse().unwrap(), ge: None, le: Some(32) },
+                ],
+            },
+        }).await.unwrap();
+        assert!(manager.advertised_routes(CloudProvider::GCP, "us-central1").await.is_empty());
+        assert_eq!(manager.advertised_routes(CloudProvider::Azure, "eastus").await.len(), 1);
+
+        // Routes from a down connection are not reflected
+        manager.update_status(CloudProvider::AWS, "us-east-1", false, 0.0).await.unwrap();
+        assert!(manager.advertised_routes(CloudProvider::Azure, "eastus").await.is_empty());
+
+        assert!(manager.learn_routes(CloudProvider::AWS, "eu-west-1", vec![aws_prefix]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cost_report_from_counters() {
+        let manager = MultiCloudManager::new();
+        for (provider, region) in [(CloudProvider::GCP, "us-central1"), (CloudProvider::AWS, "us-east-1")] {
+            manager.add_connection(CloudConnection {
+                provider,
+                region: region.to_string(),
+                vpc_id: "net".to_string(),
+                local_ip: "10.0.0.1".to_string(),
+                remote_ip: "10.1.0.1".to_string(),
+                tunnel_id: 1,
+                connected: true,
+                latency_ms: 5.0,
+            }).await.unwrap();
+        }
+        manager.set_cost_model(CloudProvider::GCP, "us-central1", CostModel {
+            pricing: Some(Pricing { connection_hourly: 0.0, egress_per_gb: 0.02 }),
+            free_egress_gb_per_month: 1.0,
+            ..Default::default()
+        }).await.unwrap();
+        assert!(manager.set_cost_model(CloudProvider::Azure, "eastus", CostModel::default()).await.is_err());
+
+        let gib = 1u64 << 30;
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(500_000 * 3600);
+        let at = |hours: u64| start + Duration::from_secs(hours * 3600);
+        for (hour, counter) in [(0, 40 * gib), (1, 50 * gib), (2, 61 * gib), (30, 70 * gib)] {
+            manager.record_counter(CloudProvider::GCP, "us-central1", counter, at(hour)).await.unwrap();
+        }
+
+        // The first day saw 21 GiB, 1 GiB of it free at 30 GiB a month
+        let report = manager.get_cost_report(BillingPeriod::new(start, at(24))).await;
+        let gcp = report.connections.iter().find(|c| c.provider == CloudProvider::GCP).unwrap();
+        assert!((gcp.egress_gb - 21.0).abs() < 1e-9);
+        assert!((gcp.free_gb - 24.0 / 730.0).abs() < 1e-9);
+        assert!((gcp.total - (21.0 - 24.0 / 730.0) * 0.02).abs() < 1e-9);
+
+        let aws = report.connections.iter().find(|c| c.provider == CloudProvider::AWS).unwrap();
+        assert_eq!(aws.egress_gb, 0.0);
+        assert!((report.per_provider[&CloudProvider::AWS] - 24.0 * 0.05).abs() < 1e-9);
+        assert!((report.total - gcp.total - aws.total).abs() < 1e-9);
+        assert_eq!(report.to_csv().lines().count(), 1 + 2 + 2 + 1);
+
+        // Later growth lands outside the day
+        let whole = manager.get_cost_report(BillingPeriod::new(start, at(48))).await;
+        assert!((whole.connections[1].egress_gb - 30.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_cost_sums_connections() {
+        let manager = MultiCloudManager::new();
+        for (provider, region) in [
+            (CloudProvider::AWS, "us-east-1"),
+            (CloudProvider::AWS, "eu-west-1"),
+            (CloudProvider::Azure, "eastus"),
+            (CloudProvider::GCP, "mars-north1"),
+        ] {
+            manager.add_connection(CloudConnection {
+                provider,
+                region: region.to_string(),
+                vpc_id: "net".to_string(),
+                local_ip: "10.0.0.1".to_string(),
+                remote_ip: "10.1.0.1".to_string(),
+                tunnel_id: 1,
+                connected: true,
+                latency_ms: 5.0,
+            }).await.unwrap();
+        }
+        manager.set_region_pricing(CloudProvider::AWS, "eu-west-1", Pricing { connection_hourly: 0.1, egress_per_gb: 0.05 }).await;
+        manager.set_cost_model(CloudProvider::Azure, "eastus", CostModel {
+            free_egress_gb_per_month: 30.0,
+            committed_use_discount: 0.5,
+            ..Default::default()
+        }).await.unwrap();
+
+        // 10 GiB/day on us-east-1, 1 GiB/hour on Azure, nothing elsewhere
+        let gib = 1u64 << 30;
+        manager.record_traffic(CloudProvider::AWS, "us-east-1", 10 * gib, Duration::from_secs(86_400)).await.unwrap();
+        manager.record_traffic(CloudProvider::Azure, "eastus", gib, Duration::from_secs(3600)).await.unwrap();
+        assert!(manager.record_traffic(CloudProvider::GCP, "us-central1", gib, Duration::from_secs(1)).await.is_err());
+
+        let breakdown = manager.estimate_cost(cost::MONTH).await;
+        assert_eq!(breakdown.period_hours, 730.0);
+        assert_eq!(breakdown.connections.len(), 4);
+
+        let approx = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        let by_region = |region: &str| breakdown.connections.iter().find(|c| c.region == region).unwrap();
+
+        let us = by_region("us-east-1");
+        assert!(approx(us.egress_gb, 10.0 * 730.0 / 24.0));
+        assert!(approx(us.total, 730.0 * 0.05 + 10.0 * 730.0 / 24.0 * 0.09));
+        assert!(approx(by_region("eu-west-1").total, 730.0 * 0.1));
+        // The estimate is billed like the report: free tier, then the discount
+        let azure = by_region("eastus");
+        assert!(approx(azure.free_gb, 30.0));
+        assert!(approx(azure.total, (730.0 * 0.19 + 700.0 * 0.087) * 0.5));
+
+        let gcp = by_region("mars-north1");
+        assert!(gcp.default_rate);
+        assert!(breakdown.uses_default_rates);
+        assert!(approx(gcp.total, 730.0 * 0.10));
+
+        let aws = breakdown.per_provider[&CloudProvider::AWS];
+        assert!(approx(aws, us.total + by_region("eu-west-1").total));
+        let sum: f64 = breakdown.connections.iter().map(|c| c.total).sum();
+        assert!(approx(breakdown.total, sum));
+        assert!(approx(breakdown.per_provider.values().sum::<f64>(), breakdown.total));
+    }
+
+    async fn failover_manager() -> MultiCloudManager {
+        let manager = MultiCloudManager::new();
+        for (provider, region, remote_ip) in [
+            (CloudProvider::AWS, "us-east-1", "169.254.10.1"),
+            (CloudProvider::Azure, "eastus", "10.2.0.1"),
+        ] {
+            manager.add_connection(CloudConnection {
+                provider,
+                region: region.to_string(),
+                vpc_id: "net".to_string(),
+                local_ip: "10.0.0.1".to_string(),
+                remote_ip: remote_ip.to_string(),
+                tunnel_id: 1,
+                connected: true,
+                latency_ms: 5.0,
+            }).await.unwrap();
+        }
+        manager.add_failover_group(FailoverGroup {
+            name: "datacenter".to_string(),
+            members: vec!["AWS_us-east-1".to_string(), "Azure_eastus".to_string()],
+            prefixes: vec!["10.50.0.0/16".parse().unwrap(), "10.51.0.0/16".parse().unwrap()],
+            dampening: Duration::from_secs(60),
+            max_latency_ms: None,
+        }).await.unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_backup_and_back() {
+        use crate::failover::FailoverEventKind;
+
+        let manager = failover_manager().await;
+        let mut events = manager.subscribe_failover();
+        let t0 = Instant::now();
+        let next_hops = |routes: Vec<FailoverRoute>| routes.into_iter().map(|r| r.next_hop).collect::<Vec<_>>();
+
+        manager.evaluate_failover_at(t0).await;
+        assert_eq!(next_hops(manager.failover_routes().await), vec!["169.254.10.1", "169.254.10.1"]);
+
+        // Direct Connect drops: prefixes move to Azure straight away
+        manager.update_status(CloudProvider::AWS, "us-east-1", false, 0.0).await.unwrap();
+        let moved = manager.evaluate_failover_at(t0 + Duration::from_secs(5)).await;
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].kind, FailoverEventKind::FailedOver);
+        assert_eq!(moved[0].prefixes.len(), 2);
+        assert_eq!(manager.active_member("datacenter").await.as_deref(), Some("Azure_eastus"));
+        assert_eq!(next_hops(manager.failover_routes().await), vec!["10.2.0.1", "10.2.0.1"]);
+
+        // Recovery is held until the primary has been up for the dampening period
+        manager.update_status(CloudProvider::AWS, "us-east-1", true, 4.0).await.unwrap();
+        assert!(manager.evaluate_failover_at(t0 + Duration::from_secs(10)).await.is_empty());
+        assert!(manager.evaluate_failover_at(t0 + Duration::from_secs(69)).await.is_empty());
+        assert_eq!(manager.active_member("datacenter").await.as_deref(), Some("Azure_eastus"));
+
+        let back = manager.evaluate_failover_at(t0 + Duration::from_secs(70)).await;
+        assert_eq!(back[0].kind, FailoverEventKind::FailedBack);
+        assert_eq!(next_hops(manager.failover_routes().await), vec!["169.254.10.1", "169.254.10.1"]);
+
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![FailoverEventKind::Activated, FailoverEventKind::FailedOver, FailoverEventKind::FailedBack]);
+
+        assert!(manager.add_failover_group(FailoverGroup {
+            name: "bad".to_string(),
+            members: vec!["GCP_us-central1".to_string()],
+            prefixes: Vec::new(),
+            dampening: Duration::ZERO,
+            max_latency_ms: None,
+        }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_reporting_down_flips_health() {
+        use crate::failover::FailoverEventKind;
+        use crate::health::ProbeResult;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        struct Switch(AtomicBool);
+
+        #[async_trait::async_trait]
+        impl ConnectionProbe for Switch {
+            async fn probe(&self, _connection: &CloudConnection) -> ProbeResult {
+                if self.0.load(Ordering::SeqCst) {
+                    ProbeResult::up(Some(3.0), "Established")
+                } else {
+                    ProbeResult::down("BGP session is Idle")
+                }
+            }
+        }
+
+        let manager = failover_manager().await.with_failure_threshold(2);
+        manager.evaluate_failover().await;
+        let aws = Arc::new(Switch(AtomicBool::new(true)));
+        manager.set_health_probe(CloudProvider::AWS, "us-east-1", aws.clone()).await.unwrap();
+        assert!(manager.set_health_probe(CloudProvider::GCP, "us-east-1", aws.clone()).await.is_err());
+        let mut health_events = manager.subscribe_health();
+        let mut failover_events = manager.subscribe_failover();
+
+        assert_eq!(manager.get_health(CloudProvider::AWS, "us-east-1").await.state, HealthState::Unknown);
+        let events = manager.poll_health().await;
+        assert_eq!((events[0].from, events[0].to), (HealthState::Unknown, HealthState::Up));
+
+        // One failure is tolerated, the second marks the link down
+        aws.0.store(false, Ordering::SeqCst);
+        assert!(manager.poll_health().await.is_empty());
+        assert!(manager.get_provider_connections(CloudProvider::AWS).await[0].connected);
+        let events = manager.poll_health().await;
+        assert_eq!((events[0].from, events[0].to), (HealthState::Up, HealthState::Down));
+
+        let health = manager.get_health(CloudProvider::AWS, "us-east-1").await;
+        assert_eq!(health.state, HealthState::Down);
+        assert_eq!(health.detail, "BGP session is Idle");
+        assert!(!manager.get_provider_connections(CloudProvider::AWS).await[0].connected);
+
+        // The failover group consumed the transition
+        let event = failover_events.try_recv().unwrap();
+        assert_eq!(event.kind, FailoverEventKind::FailedOver);
+        assert_eq!(manager.active_member("datacenter").await.as_deref(), Some("Azure_eastus"));
+
+        let published: Vec<_> = std::iter::from_fn(|| health_events.try_recv().ok()).map(|e| e.to).collect();
+        assert_eq!(published, vec![HealthState::Up, HealthState::Down]);
+
+        // The monitor picks up recovery
+        aws.0.store(true, Ordering::SeqCst);
+        let monitor = Arc::new(manager).spawn_health_monitor(Duration::from_millis(10));
+        let event = tokio::time::timeout(Duration::from_secs(5), health_events.recv()).await.unwrap().unwrap();
+        assert_eq!(event.to, HealthState::Up);
+        monitor.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manual_failover_simulation_and_log() {
+        use crate::failover::FailoverEventKind;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(FailoverEventKind, Vec<String>)>>);
+
+        #[async_trait::async_trait]
+        impl FailoverAction for Recorder {
+            async fn apply(&self, event: &FailoverEvent, routes: &[FailoverRoute]) -> Result<()> {
+                let next_hops = routes.iter().map(|r| r.next_hop.clone()).collect();
+                self.0.lock().unwrap().push((event.kind, next_hops));
+                Ok(())
+            }
+        }
+
+        let manager = failover_manager().await;
+        let recorder = Arc::new(Recorder::default());
+        manager.add_failover_action(recorder.clone()).await;
+        manager.evaluate_failover().await;
+
+        // A dry run of losing Direct Connect changes nothing
+        let simulation = manager.simulate_failover(&["AWS_us-east-1"]).await;
+        assert_eq!(simulation.events.len(), 1);
+        assert_eq!(simulation.events[0].to.as_deref(), Some("Azure_eastus"));
+        assert_eq!(simulation.withdrawn.len(), 2);
+        assert!(simulation.advertised.iter().all(|r| r.next_hop == "10.2.0.1"));
+        assert_eq!(manager.active_member("datacenter").await.as_deref(), Some("AWS_us-east-1"));
+        let preview = manager.simulate_manual_failover("datacenter", "Azure_eastus").await.unwrap();
+        assert_eq!(preview.events[0].kind, FailoverEventKind::Manual);
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+
+        // Pinned to Azure, the healthy primary does not take traffic back
+        let event = manager.manual_failover("datacenter", "Azure_eastus").await.unwrap().unwrap();
+        assert_eq!(event.kind, FailoverEventKind::Manual);
+        assert!(manager.evaluate_failover_at(Instant::now() + Duration::from_secs(600)).await.is_empty());
+        assert!(manager.manual_failover("datacenter", "GCP_us-central1").await.is_err());
+        assert!(manager.manual_failover("nope", "Azure_eastus").await.is_err());
+
+        let event = manager.failback("datacenter").await.unwrap().unwrap();
+        assert_eq!(event.kind, FailoverEventKind::FailedBack);
+        assert!(manager.failback("datacenter").await.unwrap().is_none());
+
+        // A slow primary counts as failed when the group sets a latency ceiling
+        manager.add_failover_group(FailoverGroup {
+            name: "latency".to_string(),
+            members: vec!["AWS_us-east-1".to_string(), "Azure_eastus".to_string()],
+            prefixes: vec!["10.60.0.0/16".parse().unwrap()],
+            dampening: Duration::from_secs(60),
+            max_latency_ms: Some(50.0),
+        }).await.unwrap();
+        manager.update_status(CloudProvider::AWS, "us-east-1", true, 120.0).await.unwrap();
+        let events = manager.evaluate_failover().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].to.as_deref(), Some("Azure_eastus"));
+        assert!(manager.manual_failover("latency", "AWS_us-east-1").await.is_err());
+
+        let log = manager.failover_log(Some("datacenter")).await;
+        let entries: Vec<_> = log.iter().map(|e| (e.event.kind, e.manual)).collect();
+        assert_eq!(entries, vec![
+            (FailoverEventKind::Activated, false),
+            (FailoverEventKind::Manual, true),
+            (FailoverEventKind::FailedBack, true),
+        ]);
+        assert_eq!(manager.failover_log(None).await.len(), 4);
+
+        let applied = recorder.0.lock().unwrap().clone();
+        assert_eq!(applied[1], (FailoverEventKind::Manual, vec!["10.2.0.1".to_string(), "10.2.0.1".to_string()]));
+        assert_eq!(applied.len(), 4);
+    }
 }
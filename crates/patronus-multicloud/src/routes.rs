@@ -0,0 +1,243 @@
+//! Cloud Route Propagation
+//!
+//! Reflects prefixes learned on one cloud connection to the others through
+//! the Patronus hub. Each connection has an import filter (what we accept from
+//! it) and an export filter (what we advertise to it). Routes are never
+//! advertised back to the connection they were learned from.
+
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterAction {
+    Permit,
+    Deny,
+}
+
+/// Prefix filter entry, matched like an FRR prefix-list line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteFilterEntry {
+    pub sequence: u32,
+    pub action: FilterAction,
+    pub prefix: IpNetwork,
+    pub ge: Option<u8>,  // Greater than or equal
+    pub le: Option<u8>,  // Less than or equal
+}
+
+impl RouteFilterEntry {
+    pub fn matches(&self, candidate: &IpNetwork) -> bool {
+        if !self.prefix.contains(candidate.network()) {
+            return false;
+        }
+        let len = candidate.prefix();
+        match (self.ge, self.le) {
+            (None, None) => len == self.prefix.prefix(),
+            (ge, le) => {
+                len >= ge.unwrap_or(self.prefix.prefix())
+                    && len <= le.unwrap_or(if candidate.is_ipv4() { 32 } else { 128 })
+            }
+        }
+    }
+}
+
+/// Ordered prefix filter. An empty filter permits everything; otherwise the
+/// lowest-sequence matching entry decides and unmatched prefixes are denied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteFilter {
+    pub entries: Vec<RouteFilterEntry>,
+}
+
+impl RouteFilter {
+    pub fn permit_all() -> Self {
+        Self::default()
+    }
+
+    pub fn permits(&self, prefix: &IpNetwork) -> bool {
+        if self.entries.is_empty() {
+            return true;
+        }
+        let mut entries: Vec<&RouteFilterEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| e.sequence);
+        entries
+            .into_iter()
+            .find(|e| e.matches(prefix))
+            .map(|e| e.action == FilterAction::Permit)
+            .unwrap_or(false)
+    }
+}
+
+/// Import/export filters for one cloud connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutePolicy {
+    pub import: RouteFilter,
+    pub export: RouteFilter,
+}
+
+/// A prefix learned on a cloud connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudRoute {
+    pub prefix: IpNetwork,
+    /// Connection the route was learned from
+    pub source: String,
+    /// Next hop on the source connection (its remote tunnel address)
+    pub next_hop: String,
+    /// Connections the route has traversed, source first
+    pub path: Vec<String>,
+}
+
+/// Route table shared by all cloud connections
+#[derive(Debug, Default)]
+pub struct RouteReflector {
+    learned: HashMap<String, BTreeMap<IpNetwork, CloudRoute>>,
+    policies: HashMap<String, RoutePolicy>,
+}
+
+impl RouteReflector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_policy(&mut self, connection: &str, policy: RoutePolicy) {
+        // Re-apply the new import filter to what is already learned
+        if let Some(routes) = self.learned.get_mut(connection) {
+            routes.retain(|prefix, _| policy.import.permits(prefix));
+        }
+        self.policies.insert(connection.to_string(), policy);
+    }
+
+    pub fn policy(&self, connection: &str) -> RoutePolicy {
+        self.policies.get(connection).cloned().unwrap_or_default()
+    }
+
+    /// Record routes learned on a connection, returning how many were accepted.
+    ///
+    /// Routes whose path already contains the connection are looped back
+    /// reflections and are dropped along with anything the import filter denies.
+    pub fn learn(&mut self, connection: &str, next_hop: &str, routes: Vec<(IpNetwork, Vec<String>)>) -> usize {
+        let policy = self.policy(connection);
+        let table = self.learned.entry(connection.to_string()).or_default();
+        let mut accepted = 0;
+
+        for (prefix, path) in routes {
+            if path.iter().any(|hop| hop == connection) || !policy.import.permits(&prefix) {
+                tracing::debug!("Rejected {} from {}", prefix, connection);
+                continue;
+            }
+            let mut full_path = vec![connection.to_string()];
+            full_path.extend(path);
+            table.insert(prefix, CloudRoute {
+                prefix,
+                source: connection.to_string(),
+                next_hop: next_hop.to_string(),
+                path: full_path,
+            });
+            accepted += 1;
+        }
+
+        accepted
+    }
+
+    pub fn withdraw(&mut self, connection: &str, prefixes: &[IpNetwork]) {
+        if let Some(table) = self.learned.get_mut(connection) {
+            for prefix in prefixes {
+                table.remove(prefix);
+            }
+        }
+    }
+
+    /// Forget everything about a connection
+    pub fn remove(&mut self, connection: &str) {
+        self.learned.remove(connection);
+        self.policies.remove(connection);
+    }
+
+    pub fn learned(&self, connection: &str) -> Vec<CloudRoute> {
+        self.learned
+            .get(connection)
+            .map(|t| t.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Routes to advertise to `target`, one per prefix.
+    ///
+    /// Only routes from sources in `active` are reflected. When several
+    /// sources carry the same prefix the shortest path wins, ties broken by
+    /// source name so the choice is stable.
+    pub fn advertised(&self, target: &str, active: &[String]) -> Vec<CloudRoute> {
+        let export = self.policy(target).export;
+        let mut best: BTreeMap<IpNetwork, &CloudRoute> = BTreeMap::new();
+
+        for (source, table) in &self.learned {
+            if source == target || !active.contains(source) {
+                continue;
+            }
+            for route in table.values() {
+                if route.path.iter().any(|hop| hop == target) || !export.permits(&route.prefix) {
+                    continue;
+                }
+                let better = match best.get(&route.prefix) {
+                    Some(current) => (route.path.len(), &route.source) < (current.path.len(), &current.source),
+                    None => true,
+                };
+                if better {
+                    best.insert(route.prefix, route);
+                }
+            }
+        }
+
+        best.into_values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn entry(sequence: u32, action: FilterAction, prefix: &str, ge: Option<u8>, le: Option<u8>) -> RouteFilterEntry {
+        RouteFilterEntry { sequence, action, prefix: net(prefix), ge, le }
+    }
+
+    #[test]
+    fn test_filter_matching() {
+        let filter = RouteFilter {
+            entries: vec![
+                entry(10, FilterAction::Deny, "10.99.0.0/16", None, Some(32)),
+                entry(20, FilterAction::Permit, "10.0.0.0/8", None, Some(24)),
+            ],
+        };
+
+        assert!(filter.permits(&net("10.1.0.0/16")));
+        assert!(filter.permits(&net("10.1.2.0/24")));
+        assert!(!filter.permits(&net("10.1.2.128/25")));
+        assert!(!filter.permits(&net("10.99.1.0/24")));
+        assert!(!filter.permits(&net("192.168.0.0/24")));
+        assert!(RouteFilter::permit_all().permits(&net("192.168.0.0/24")));
+
+        let exact = entry(5, FilterAction::Permit, "172.16.0.0/12", None, None);
+        assert!(exact.matches(&net("172.16.0.0/12")));
+        assert!(!exact.matches(&net("172.16.1.0/24")));
+    }
+
+    #[test]
+    fn test_import_filter_and_loop_prevention() {
+        let mut rib = RouteReflector::new();
+        rib.set_policy("AWS_us-east-1", RoutePolicy {
+            import: RouteFilter { entries: vec![entry(10, FilterAction::Permit, "10.0.0.0/8", None, Some(24))] },
+            export: RouteFilter::permit_all(),
+        });
+
+        let accepted = rib.learn("AWS_us-east-1", "172.31.0.1", vec![
+            (net("10.10.0.0/16"), vec![]),
+            (net("192.168.1.0/24"), vec![]),
+            (net("10.20.0.0/16"), vec!["Azure_eastus".into(), "AWS_us-east-1".into()]),
+        ]);
+
+        assert_eq!(accepted, 1);
+        assert_eq!(rib.learned("AWS_us-east-1")[0].prefix, net("10.10.0.0/16"));
+    }
+}
@@ -0,0 +1,267 @@
+//! SD-WAN Integration
+//!
+//! [`SdwanFailover`] installs an SD-WAN routing policy for each prefix of a
+//! failover group while it has an active member, removes them when every
+//! member is down, and re-selects paths for active flows on every
+//! switchover so traffic does not stay pinned to the path chosen before it.
+//!
+//! [`SdwanEgressFeed`] records each connection's egress from the traffic
+//! statistics of the policies steering into it.
+
+use crate::failover::{FailoverAction, FailoverEvent, FailoverRoute};
+use crate::manager::{CloudProvider, MultiCloudManager};
+use anyhow::Result;
+use async_trait::async_trait;
+use patronus_sdwan::policy::{MatchRules, PathPreference, RoutingPolicy};
+use patronus_sdwan::routing::RoutingEngine;
+use patronus_sdwan::traffic_stats::TrafficStatsCollector;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Mutex;
+
+/// First policy ID used for failover policies, clear of configured ones
+const POLICY_ID_BASE: u64 = 1 << 32;
+
+/// Keeps SD-WAN routing policies in step with failover switchovers
+///
+/// Only policies this action installed are replaced or removed.
+pub struct SdwanFailover {
+    engine: Arc<RoutingEngine>,
+    preference: PathPreference,
+    next_id: AtomicU64,
+    /// Group name -> names of the policies installed for it
+    installed: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl SdwanFailover {
+    pub fn new(engine: Arc<RoutingEngine>) -> Self {
+        Self {
+            engine,
+            preference: PathPreference::LowestLatency,
+            next_id: AtomicU64::new(POLICY_ID_BASE),
+            installed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the path preference of the installed policies
+    pub fn with_preference(mut self, preference: PathPreference) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    fn policy_name(route: &FailoverRoute) -> String {
+        format!("failover-{}-{}", route.group, route.prefix)
+    }
+}
+
+#[async_trait]
+impl FailoverAction for SdwanFailover {
+    async fn apply(&self, event: &FailoverEvent, routes: &[FailoverRoute]) -> Result<()> {
+        let mut installed = self.installed.lock().await;
+        for name in installed.remove(&event.group).unwrap_or_default() {
+            self.engine.remove_policy(&name).await;
+        }
+
+        let mut names = Vec::new();
+        for route in routes {
+            let name = Self::policy_name(route);
+            self.engine.add_policy(RoutingPolicy {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                name: name.clone(),
+                // Matched before the built-in policies
+                priority: 0,
+                match_rules: MatchRules { dst_ip: Some(route.prefix.to_string()), ..Default::default() },
+                path_preference: self.preference.clone(),
+                enabled: true,
+            }).await;
+            names.push(name);
+        }
+        if !names.is_empty() {
+            installed.insert(event.group.clone(), names);
+        }
+        drop(installed);
+
+        self.engine.reevaluate_all_flows().await?;
+        tracing::info!("Updated SD-WAN policies of failover group {} ({} prefixes)", event.group, routes.len());
+        Ok(())
+    }
+}
+
+/// Records connection egress from SD-WAN traffic statistics
+///
+/// Each connection is fed by the routing policies steering traffic into
+/// it: the growth of their `bytes_matched` counters between polls is
+/// recorded as the connection's egress.
+pub struct SdwanEgressFeed {
+    traffic_stats: Arc<TrafficStatsCollector>,
+    manager: Arc<MultiCloudManager>,
+    /// Connection and the policy IDs steering into it
+    connections: Vec<(CloudProvider, String, Vec<u64>)>,
+    /// `bytes_matched` and `first_seen` per policy at the previous poll
+    last_counters: HashMap<u64, (u64, SystemTime)>,
+    last_poll: Option<Instant>,
+}
+
+impl SdwanEgressFeed {
+    pub fn new(traffic_stats: Arc<TrafficStatsCollector>, manager: Arc<MultiCloudManager>) -> Self {
+        Self {
+            traffic_stats,
+            manager,
+            connections: Vec::new(),
+            last_counters: HashMap::new(),
+            last_poll: None,
+        }
+    }
+
+    /// Count traffic matched by `policy_ids` as egress of a connection
+    pub fn with_connection(mut self, provider: CloudProvider, region: &str, policy_ids: impl IntoIterator<Item = u64>) -> Self {
+        self.connections.push((provider, region.to_string(), policy_ids.into_iter().collect()));
+        self
+    }
+
+    /// Record the egress of every connection since the last poll
+    ///
+    /// The first poll only takes the counters as a baseline. A counter that
+    /// was reset since, or went backwards, counts all of its value as new.
+    pub async fn poll(&mut self) -> Result<()> {
+        let counters: HashMap<u64, (u64, SystemTime)> = self.traffic_stats.get_all_policy_stats().await
+            .into_iter()
+            .map(|(id, stats)| (id, (stats.bytes_matched, stats.first_seen)))
+            .collect();
+        let now = Instant::now();
+
+        if let Some(last_poll) = self.last_poll {
+            let interval = now.duration_since(last_poll);
+            for (provider, region, policy_ids) in &self.connections {
+                let egress_bytes: u64 = policy_ids.iter()
+                    .filter_map(|id| counters.get(id).map(|counter| (id, *counter)))
+                    .map(|(id, (total, since))| match self.last_counters.get(id) {
+                        Some(&(last, last_since)) if since == last_since && total >= last => total - last,
+                        _ => total,
+                    })
+                    .sum();
+                self.manager.record_traffic(*provider, region, egress_bytes, interval).await?;
+            }
+        }
+
+        self.last_counters = counters;
+        self.last_poll = Some(now);
+        Ok(())
+    }
+
+    /// Poll every `interval` until the task is dropped
+    pub async fn start(mut self, interval: Duration) {
+        tracing::info!("Starting SD-WAN egress feed for {} connections ({:?} interval)", self.connections.len(), interval);
+
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.poll().await {
+                tracing::warn!("Failed to record SD-WAN egress: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failover::FailoverEventKind;
+    use patronus_sdwan::database::Database;
+
+    fn event(kind: FailoverEventKind, to: Option<&str>) -> FailoverEvent {
+        FailoverEvent {
+            group: "prod".to_string(),
+            kind,
+            from: None,
+            to: to.map(str::to_string),
+            prefixes: vec!["10.50.0.0/16".parse().unwrap(), "10.60.0.0/16".parse().unwrap()],
+        }
+    }
+
+    fn routes(event: &FailoverEvent, connection: &str) -> Vec<FailoverRoute> {
+        event.prefixes.iter().map(|prefix| FailoverRoute {
+            prefix: *prefix,
+            group: event.group.clone(),
+            connection: connection.to_string(),
+            next_hop: "169.254.10.1".to_string(),
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_egress_from_policy_stats() {
+        use crate::cost::BillingPeriod;
+        use crate::manager::CloudConnection;
+        use patronus_sdwan::FlowKey;
+
+        let manager = Arc::new(MultiCloudManager::new());
+        manager.add_connection(CloudConnection {
+            provider: CloudProvider::AWS,
+            region: "us-east-1".to_string(),
+            vpc_id: "vpc-12345".to_string(),
+            local_ip: "10.0.0.1".to_string(),
+            remote_ip: "172.31.0.1".to_string(),
+            tunnel_id: 1,
+            connected: true,
+            latency_ms: 5.0,
+        }).await.unwrap();
+        let traffic_stats = Arc::new(TrafficStatsCollector::new(None));
+        let flow = |dst: &str| FlowKey {
+            src_ip: "10.0.0.5".parse().unwrap(),
+            dst_ip: dst.parse().unwrap(),
+            src_port: 40000,
+            dst_port: 443,
+            protocol: 6,
+        };
+        let gib = 1u64 << 30;
+
+        // Traffic from before the feed started is not billed again
+        traffic_stats.record_packet(10, flow("172.31.5.5"), 5 * gib).await;
+        let mut feed = SdwanEgressFeed::new(traffic_stats.clone(), manager.clone())
+            .with_connection(CloudProvider::AWS, "us-east-1", [10, 11]);
+        feed.poll().await.unwrap();
+
+        traffic_stats.record_packet(10, flow("172.31.5.5"), 2 * gib).await;
+        traffic_stats.record_packet(11, flow("172.31.9.9"), gib).await;
+        // Another policy's traffic leaves through some other connection
+        traffic_stats.record_packet(12, flow("8.8.8.8"), 4 * gib).await;
+        feed.poll().await.unwrap();
+
+        // A reset counter counts whole
+        traffic_stats.reset_policy_stats(11).await;
+        traffic_stats.record_packet(11, flow("172.31.9.9"), gib).await;
+        feed.poll().await.unwrap();
+
+        let report = manager.get_cost_report(BillingPeriod::last(Duration::from_secs(3600))).await;
+        assert!((report.connections[0].egress_gb - 4.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_policies_follow_switchovers() {
+        let engine = Arc::new(RoutingEngine::new(Arc::new(Database::new_in_memory().await.unwrap())));
+        engine.start().await.unwrap();
+        let builtin = engine.list_policies().await.len();
+        let sdwan = SdwanFailover::new(engine.clone());
+        let failover_policies = |policies: Vec<RoutingPolicy>| {
+            policies.into_iter().filter(|p| p.name.starts_with("failover-")).collect::<Vec<_>>()
+        };
+
+        let activated = event(FailoverEventKind::Activated, Some("AWS_us-east-1"));
+        sdwan.apply(&activated, &routes(&activated, "AWS_us-east-1")).await.unwrap();
+        let moved = event(FailoverEventKind::FailedOver, Some("Azure_eastus"));
+        sdwan.apply(&moved, &routes(&moved, "Azure_eastus")).await.unwrap();
+
+        let policies = failover_policies(engine.list_policies().await);
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0].match_rules.dst_ip.as_deref(), Some("10.50.0.0/16"));
+        // Sorted ahead of the built-in policies
+        assert_eq!(engine.list_policies().await[0].name, policies[0].name);
+
+        let down = event(FailoverEventKind::AllDown, None);
+        sdwan.apply(&down, &[]).await.unwrap();
+        assert!(failover_policies(engine.list_policies().await).is_empty());
+        assert_eq!(engine.list_policies().await.len(), builtin);
+    }
+}
@@ -0,0 +1,337 @@
+//! Terraform Export
+//!
+//! Renders the connections held by the manager as Terraform HCL so the cloud
+//! side can be managed as infrastructure-as-code. Secrets (tunnel PSKs, BGP
+//! keys) are never written inline; each one becomes a `sensitive` variable.
+
+use crate::manager::{CloudConnection, CloudProvider};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Render a Terraform configuration for the given connections.
+///
+/// Every connection gets its own aliased provider block (one per region) and
+/// a resource label derived from provider and region, so the output is stable
+/// for a given set of connections regardless of insertion order.
+pub fn render(connections: &[CloudConnection]) -> String {
+    let mut connections: Vec<&CloudConnection> = connections.iter().collect();
+    connections.sort_by_key(|c| label(c));
+
+    let providers: BTreeSet<CloudProvider> = connections.iter().map(|c| c.provider).collect();
+
+    let mut out = String::new();
+    out.push_str("# Generated by Patronus. Secrets are supplied through the sensitive variables below.\n\n");
+
+    out.push_str("terraform {\n  required_providers {\n");
+    for provider in &providers {
+        let (name, source, version) = match provider {
+            CloudProvider::AWS => ("aws", "hashicorp/aws", ">= 5.0"),
+            CloudProvider::Azure => ("azurerm", "hashicorp/azurerm", ">= 3.0"),
+            CloudProvider::GCP => ("google", "hashicorp/google", ">= 5.0"),
+        };
+        let _ = writeln!(
+            out,
+            "    {} = {{\n      source  = {}\n      version = {}\n    }}",
+            name,
+            quote(source),
+            quote(version)
+        );
+    }
+    out.push_str("  }\n}\n\n");
+
+    out.push_str(
+        "variable \"patronus_bgp_asn\" {\n  description = \"BGP ASN announced by the Patronus side of every connection\"\n  type        = number\n  default     = 65000\n}\n\n",
+    );
+    if providers.contains(&CloudProvider::Azure) {
+        variable(&mut out, "azure_resource_group", "Resource group holding the Azure gateways", false);
+    }
+    if providers.contains(&CloudProvider::GCP) {
+        variable(&mut out, "gcp_project", "GCP project owning the interconnect attachments", false);
+    }
+
+    for conn in connections {
+        match conn.provider {
+            CloudProvider::AWS => aws(&mut out, conn),
+            CloudProvider::Azure => azure(&mut out, conn),
+            CloudProvider::GCP => gcp(&mut out, conn),
+        }
+    }
+
+    out
+}
+
+/// Resource label for a connection, e.g. `aws_us_east_1`.
+pub fn label(conn: &CloudConnection) -> String {
+    let provider = match conn.provider {
+        CloudProvider::AWS => "aws",
+        CloudProvider::Azure => "azure",
+        CloudProvider::GCP => "gcp",
+    };
+    format!("{}_{}", provider, slug(&conn.region))
+}
+
+/// Site-to-site VPN terminating on a VPN gateway, or on the transit gateway
+/// directly when the connection targets one (which creates the TGW attachment).
+fn aws(out: &mut String, conn: &CloudConnection) {
+    let name = label(conn);
+    let provider = format!("aws.{}", name);
+
+    let _ = writeln!(out, "provider \"aws\" {{\n  alias  = {}\n  region = {}\n}}\n", quote(&name), quote(&conn.region));
+    variable(out, &format!("{}_tunnel1_psk", name), &format!("Pre-shared key for tunnel 1 of {}", name), true);
+    variable(out, &format!("{}_tunnel2_psk", name), &format!("Pre-shared key for tunnel 2 of {}", name), true);
+
+    let _ = writeln!(
+        out,
+        "resource \"aws_customer_gateway\" \"{name}\" {{\n  provider   = {provider}\n  bgp_asn    = var.patronus_bgp_asn\n  ip_address = {ip}\n  type       = \"ipsec.1\"\n\n  tags = {{\n    Name = {tag}\n  }}\n}}\n",
+        ip = quote(&conn.local_ip),
+        tag = quote(&format!("patronus-{}", name)),
+    );
+
+    let attachment = match conn.vpc_id.strip_prefix("tgw-") {
+        Some(_) => format!("transit_gateway_id    = {}", quote(&conn.vpc_id)),
+        None => {
+            let _ = writeln!(
+                out,
+                "resource \"aws_vpn_gateway\" \"{name}\" {{\n  provider = {provider}\n  vpc_id   = {vpc}\n\n  tags = {{\n    Name = {tag}\n  }}\n}}\n",
+                vpc = quote(&conn.vpc_id),
+                tag = quote(&format!("patronus-{}", name)),
+            );
+            format!("vpn_gateway_id        = aws_vpn_gateway.{}.id", name)
+        }
+    };
+
+    let _ = writeln!(
+        out,
+        "resource \"aws_vpn_connection\" \"{name}\" {{\n  provider              = {provider}\n  customer_gateway_id   = aws_customer_gateway.{name}.id\n  {attachment}\n  type                  = \"ipsec.1\"\n  static_routes_only    = false\n  tunnel1_preshared_key = var.{name}_tunnel1_psk\n  tunnel2_preshared_key = var.{name}_tunnel2_psk\n\n  tags = {{\n    Name     = {tag}\n    Patronus = {tunnel}\n  }}\n}}\n",
+        tag = quote(&format!("patronus-{}", name)),
+        tunnel = quote(&conn.tunnel_id.to_string()),
+    );
+}
+
+/// Route-based VNet gateway with a local network gateway for the Patronus
+/// side and an IPsec connection between them.
+fn azure(out: &mut String, conn: &CloudConnection) {
+    let name = label(conn);
+    let provider = format!("azurerm.{}", name);
+    // The connection may carry either a bare VNet name or a full ARM id.
+    let vnet = conn.vpc_id.rsplit('/').next().unwrap_or(&conn.vpc_id);
+    let location = quote(&conn.region);
+
+    let _ = writeln!(out, "provider \"azurerm\" {{\n  alias = {}\n  features {{}}\n}}\n", quote(&name));
+    variable(out, &format!("{}_shared_key", name), &format!("IPsec shared key for {}", name), true);
+
+    let _ = writeln!(
+        out,
+        "data \"azurerm_subnet\" \"{name}_gateway\" {{\n  provider             = {provider}\n  name                 = \"GatewaySubnet\"\n  virtual_network_name = {vnet}\n  resource_group_name  = var.azure_resource_group\n}}\n",
+        vnet = quote(vnet),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"azurerm_public_ip\" \"{name}\" {{\n  provider            = {provider}\n  name                = {pip}\n  location            = {location}\n  resource_group_name = var.azure_resource_group\n  allocation_method   = \"Static\"\n  sku                 = \"Standard\"\n}}\n",
+        pip = quote(&format!("patronus-{}-pip", name)),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"azurerm_virtual_network_gateway\" \"{name}\" {{\n  provider            = {provider}\n  name                = {gw}\n  location            = {location}\n  resource_group_name = var.azure_resource_group\n  type                = \"Vpn\"\n  vpn_type            = \"RouteBased\"\n  sku                 = \"VpnGw1\"\n  enable_bgp          = true\n\n  ip_configuration {{\n    name                          = \"gateway\"\n    public_ip_address_id          = azurerm_public_ip.{name}.id\n    private_ip_address_allocation = \"Dynamic\"\n    subnet_id                     = data.azurerm_subnet.{name}_gateway.id\n  }}\n}}\n",
+        gw = quote(&format!("patronus-{}-vng", name)),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"azurerm_local_network_gateway\" \"{name}\" {{\n  provider            = {provider}\n  name                = {lng}\n  location            = {location}\n  resource_group_name = var.azure_resource_group\n  gateway_address     = {ip}\n\n  bgp_settings {{\n    asn                 = var.patronus_bgp_asn\n    bgp_peering_address = {ip}\n  }}\n}}\n",
+        lng = quote(&format!("patronus-{}-lng", name)),
+        ip = quote(&conn.local_ip),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"azurerm_virtual_network_gateway_connection\" \"{name}\" {{\n  provider                   = {provider}\n  name                       = {cn}\n  location                   = {location}\n  resource_group_name        = var.azure_resource_group\n  type                       = \"IPsec\"\n  virtual_network_gateway_id = azurerm_virtual_network_gateway.{name}.id\n  local_network_gateway_id   = azurerm_local_network_gateway.{name}.id\n  enable_bgp                 = true\n  shared_key                 = var.{name}_shared_key\n}}\n",
+        cn = quote(&format!("patronus-{}-tunnel-{}", name, conn.tunnel_id)),
+    );
+}
+
+/// Cloud Router with a dedicated interconnect VLAN attachment and a BGP peer
+/// towards the Patronus side, authenticated with an MD5 key variable.
+fn gcp(out: &mut String, conn: &CloudConnection) {
+    let name = label(conn);
+    let provider = format!("google.{}", name);
+    let region = quote(&conn.region);
+    let router = format!("patronus-{}-router", name.replace('_', "-"));
+    let attachment = format!("patronus-{}-vlan", name.replace('_', "-"));
+
+    let _ = writeln!(
+        out,
+        "provider \"google\" {{\n  alias   = {}\n  project = var.gcp_project\n  region  = {}\n}}\n",
+        quote(&name),
+        region
+    );
+    variable(out, &format!("{}_interconnect", name), &format!("URL of the dedicated interconnect used by {}", name), false);
+    variable(out, &format!("{}_bgp_md5_key", name), &format!("BGP MD5 authentication key for {}", name), true);
+
+    let _ = writeln!(
+        out,
+        "resource \"google_compute_router\" \"{name}\" {{\n  provider = {provider}\n  name     = {router}\n  region   = {region}\n  network  = {network}\n\n  bgp {{\n    asn = 16550\n  }}\n}}\n",
+        router = quote(&router),
+        network = quote(&conn.vpc_id),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"google_compute_interconnect_attachment\" \"{name}\" {{\n  provider      = {provider}\n  name          = {attachment}\n  region        = {region}\n  type          = \"DEDICATED\"\n  interconnect  = var.{name}_interconnect\n  router        = google_compute_router.{name}.id\n  vlan_tag8021q = {vlan}\n}}\n",
+        attachment = quote(&attachment),
+        vlan = 1000 + conn.tunnel_id % 3000,
+    );
+    let _ = writeln!(
+        out,
+        "resource \"google_compute_router_interface\" \"{name}\" {{\n  provider                = {provider}\n  name                    = {iface}\n  region                  = {region}\n  router                  = google_compute_router.{name}.name\n  interconnect_attachment = google_compute_interconnect_attachment.{name}.self_link\n}}\n",
+        iface = quote(&format!("{}-if", attachment)),
+    );
+    let _ = writeln!(
+        out,
+        "resource \"google_compute_router_peer\" \"{name}\" {{\n  provider        = {provider}\n  name            = {peer}\n  region          = {region}\n  router          = google_compute_router.{name}.name\n  interface       = google_compute_router_interface.{name}.name\n  peer_asn        = var.patronus_bgp_asn\n  peer_ip_address = {ip}\n\n  md5_authentication_key {{\n    name = {peer}\n    key  = var.{name}_bgp_md5_key\n  }}\n}}\n",
+        peer = quote(&format!("patronus-{}-peer", name.replace('_', "-"))),
+        ip = quote(&conn.local_ip),
+    );
+}
+
+fn variable(out: &mut String, name: &str, description: &str, sensitive: bool) {
+    let _ = write!(
+        out,
+        "variable \"{}\" {{\n  description = {}\n  type        = string\n",
+        name,
+        quote(description)
+    );
+    if sensitive {
+        out.push_str("  sensitive   = true\n");
+    }
+    out.push_str("}\n\n");
+}
+
+/// Lowercase identifier safe for Terraform labels and aliases.
+fn slug(value: &str) -> String {
+    let slug: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if slug.is_empty() {
+        "default".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Quote a string literal, escaping HCL template sequences.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                quoted.push(c);
+                quoted.push(c);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hcl::{Body, Expression};
+
+    fn connection(provider: CloudProvider, region: &str, vpc_id: &str, tunnel_id: u32) -> CloudConnection {
+        CloudConnection {
+            provider,
+            region: region.to_string(),
+            vpc_id: vpc_id.to_string(),
+            local_ip: "203.0.113.10".to_string(),
+            remote_ip: "172.31.0.1".to_string(),
+            tunnel_id,
+            connected: true,
+            latency_ms: 5.0,
+        }
+    }
+
+    fn blocks<'a>(body: &'a Body, kind: &'a str) -> impl Iterator<Item = &'a hcl::Block> {
+        body.blocks().filter(move |b| b.identifier() == kind)
+    }
+
+    fn labels(block: &hcl::Block) -> Vec<&str> {
+        block.labels().iter().map(|l| l.as_str()).collect()
+    }
+
+    #[test]
+    fn test_export_parses_and_references_each_connection() {
+        let connections = vec![
+            connection(CloudProvider::AWS, "us-east-1", "vpc-12345", 1),
+            connection(CloudProvider::AWS, "eu-west-1", "tgw-0abc", 2),
+            connection(CloudProvider::Azure, "eastus", "vnet-12345", 3),
+            connection(CloudProvider::GCP, "us-central1", "default", 5),
+        ];
+
+        let hcl = render(&connections);
+        let body: Body = hcl::from_str(&hcl).expect("generated HCL parses");
+
+        let resources: Vec<Vec<&str>> = blocks(&body, "resource").map(labels).collect();
+        for (kind, name) in [
+            ("aws_vpn_connection", "aws_us_east_1"),
+            ("aws_vpn_gateway", "aws_us_east_1"),
+            ("aws_vpn_connection", "aws_eu_west_1"),
+            ("azurerm_virtual_network_gateway", "azure_eastus"),
+            ("azurerm_virtual_network_gateway_connection", "azure_eastus"),
+            ("google_compute_interconnect_attachment", "gcp_us_central1"),
+            ("google_compute_router_peer", "gcp_us_central1"),
+        ] {
+            assert!(resources.contains(&vec![kind, name]), "missing {}.{}", kind, name);
+        }
+        // The TGW connection attaches to the gateway instead of a VPN gateway.
+        assert!(!resources.contains(&vec!["aws_vpn_gateway", "aws_eu_west_1"]));
+        assert!(hcl.contains("transit_gateway_id    = \"tgw-0abc\""));
+
+        let providers: Vec<String> = blocks(&body, "provider")
+            .filter_map(|b| match b.body().attributes().find(|a| a.key() == "alias")?.expr() {
+                Expression::String(alias) => Some(alias.clone()),
+                _ => None,
+            })
+            .collect();
+        for conn in &connections {
+            assert!(providers.contains(&label(conn)));
+            assert!(hcl.contains(&format!("\"{}\"", conn.vpc_id)));
+        }
+
+        // Every secret is a declared, sensitive variable and every var.* reference resolves.
+        let variables: Vec<(&str, bool)> = blocks(&body, "variable")
+            .map(|b| {
+                let sensitive = b.body().attributes().any(|a| a.key() == "sensitive" && *a.expr() == Expression::Bool(true));
+                (b.labels()[0].as_str(), sensitive)
+            })
+            .collect();
+        for secret in [
+            "aws_us_east_1_tunnel1_psk",
+            "aws_eu_west_1_tunnel2_psk",
+            "azure_eastus_shared_key",
+            "gcp_us_central1_bgp_md5_key",
+        ] {
+            assert!(variables.contains(&(secret, true)), "{} not a sensitive variable", secret);
+        }
+        for reference in hcl.split("var.").skip(1) {
+            let name: String = reference.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+            assert!(variables.iter().any(|(v, _)| *v == name), "undeclared variable {}", name);
+        }
+    }
+
+    #[test]
+    fn test_quote_escapes_templates() {
+        assert_eq!(quote("a\"b"), "\"a\\\"b\"");
+        assert_eq!(quote("${x}"), "\"$${x}\"");
+        assert_eq!(quote("%{if}"), "\"%%{if}\"");
+        assert_eq!(quote("$5"), "\"$5\"");
+        assert_eq!(slug("us-east-1"), "us_east_1");
+    }
+}
@@ -1,6 +1,9 @@
 //! Network interface management
 
-use patronus_core::{types::{Interface, IpNetwork}, Error, Result};
+use patronus_core::{
+    types::{Interface, InterfaceStats, InterfaceStatsProvider, IpNetwork},
+    Error, Result,
+};
 use rtnetlink::{new_connection, Handle};
 use futures::TryStreamExt;
 use std::net::IpAddr;
@@ -97,6 +100,65 @@ impl InterfaceManager {
         Ok(interfaces)
     }
 
+    /// Traffic counters and link state of all interfaces
+    pub async fn stats(&self) -> Result<Vec<InterfaceStats>> {
+        use netlink_packet_route::link::{LinkAttribute, LinkFlag};
+
+        let mut links = self.handle.link().get().execute();
+        let mut stats = Vec::new();
+
+        while let Some(msg) = links
+            .try_next()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to get interfaces: {}", e)))?
+        {
+            let mut entry = InterfaceStats {
+                index: msg.header.index,
+                name: format!("interface{}", msg.header.index),
+                mtu: 1500,
+                admin_up: msg.header.flags.iter().any(|f| matches!(f, LinkFlag::Up)),
+                oper_up: msg.header.flags.iter().any(|f| matches!(f, LinkFlag::Running)),
+                ..InterfaceStats::default()
+            };
+
+            for attr in &msg.attributes {
+                match attr {
+                    LinkAttribute::IfName(name) => entry.name = name.clone(),
+                    LinkAttribute::Mtu(mtu) => entry.mtu = *mtu,
+                    LinkAttribute::Address(addr) => {
+                        entry.mac_address = Some(addr.iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<Vec<_>>()
+                            .join(":"));
+                    }
+                    LinkAttribute::Stats64(counters) => {
+                        entry.rx_bytes = counters.rx_bytes;
+                        entry.tx_bytes = counters.tx_bytes;
+                        entry.rx_packets = counters.rx_packets;
+                        entry.tx_packets = counters.tx_packets;
+                        entry.rx_errors = counters.rx_errors;
+                        entry.tx_errors = counters.tx_errors;
+                        entry.rx_dropped = counters.rx_dropped;
+                        entry.tx_dropped = counters.tx_dropped;
+                    }
+                    _ => {}
+                }
+            }
+
+            // Link speed is only exposed through sysfs, in Mbit/s
+            entry.speed_bps = tokio::fs::read_to_string(format!("/sys/class/net/{}/speed", entry.name))
+                .await
+                .ok()
+                .and_then(|s| s.trim().parse::<i64>().ok())
+                .filter(|mbps| *mbps > 0)
+                .map(|mbps| mbps as u64 * 1_000_000);
+
+            stats.push(entry);
+        }
+
+        Ok(stats)
+    }
+
     /// Get IP addresses for a specific interface
     async fn get_interface_ips(&self, index: u32) -> Result<Vec<IpAddr>> {
         let mut addresses = self.handle.address().get().execute();
@@ -251,3 +313,10 @@ impl InterfaceManager {
         Ok(())
     }
 }
+
+#[async_trait::async_trait]
+impl InterfaceStatsProvider for InterfaceManager {
+    async fn interface_stats(&self) -> Result<Vec<InterfaceStats>> {
+        self.stats().await
+    }
+}
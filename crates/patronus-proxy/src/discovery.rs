@@ -0,0 +1,240 @@
+//! Backend Service Discovery
+//!
+//! Sources of backend servers that change at runtime. [`HAProxyManager`]
+//! polls them, adds the servers that appear and drains the ones that go
+//! away; see [`HAProxyManager::poll_discovery`].
+//!
+//! [`HAProxyManager`]: crate::HAProxyManager
+//! [`HAProxyManager::poll_discovery`]: crate::HAProxyManager::poll_discovery
+
+use async_trait::async_trait;
+use patronus_core::{Error, Result};
+use patronus_diagnostics::{DnsClient, DnsQueryStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+
+/// Highest weight HAProxy accepts
+const MAX_WEIGHT: u32 = 256;
+
+/// A server reported by a discovery source
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DiscoveredServer {
+    pub address: IpAddr,
+    pub port: u16,
+    /// Load balancing weight; the template's when unset
+    pub weight: Option<u32>,
+    /// Only used when no primary server is up
+    pub backup: bool,
+}
+
+impl DiscoveredServer {
+    pub fn new(address: IpAddr, port: u16) -> Self {
+        Self { address, port, weight: None, backup: false }
+    }
+
+    pub fn endpoint(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+}
+
+/// A source of backend servers
+#[async_trait]
+pub trait ServiceDiscovery: Send + Sync {
+    /// The servers that should currently be in the backend
+    async fn resolve(&self) -> Result<Vec<DiscoveredServer>>;
+}
+
+/// A fixed server list that can be replaced at runtime, e.g. from an API
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    servers: RwLock<Vec<DiscoveredServer>>,
+}
+
+impl StaticResolver {
+    pub fn new(servers: Vec<DiscoveredServer>) -> Self {
+        Self { servers: RwLock::new(servers) }
+    }
+
+    pub fn set(&self, servers: Vec<DiscoveredServer>) {
+        *self.servers.write().unwrap() = servers;
+    }
+}
+
+#[async_trait]
+impl ServiceDiscovery for StaticResolver {
+    async fn resolve(&self) -> Result<Vec<DiscoveredServer>> {
+        Ok(self.servers.read().unwrap().clone())
+    }
+}
+
+/// Servers from DNS SRV records, e.g. `_http._tcp.app.service.consul`
+///
+/// Records with the lowest priority are primaries; the rest become backup
+/// servers. SRV weights above HAProxy's maximum of 256 are capped, and a
+/// weight of 0 keeps the template's. Targets are resolved with the system
+/// resolver.
+pub struct DnsSrvResolver {
+    name: String,
+    servers: Vec<SocketAddr>,
+    client: DnsClient,
+}
+
+impl DnsSrvResolver {
+    /// Query the resolvers in /etc/resolv.conf for `name`
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            servers: patronus_diagnostics::dns::system_resolvers(),
+            client: DnsClient::new(),
+        }
+    }
+
+    /// Query these servers instead, in order until one answers
+    pub fn with_servers(mut self, servers: Vec<SocketAddr>) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    async fn srv_records(&self) -> Result<Vec<SrvRecord>> {
+        for server in &self.servers {
+            let answer = self.client.query(&self.name, "SRV", *server).await?;
+            match answer.status {
+                DnsQueryStatus::NoError => {
+                    return Ok(answer.records.iter()
+                        .filter(|r| r.record_type == "SRV")
+                        .filter_map(|r| SrvRecord::parse(&r.value))
+                        .collect());
+                }
+                DnsQueryStatus::NxDomain => return Ok(Vec::new()),
+                status => {
+                    tracing::debug!("SRV lookup of {} via {}: {:?}", self.name, server, status);
+                }
+            }
+        }
+        Err(Error::Network(format!("No DNS server answered the SRV lookup of {}", self.name)))
+    }
+}
+
+#[async_trait]
+impl ServiceDiscovery for DnsSrvResolver {
+    async fn resolve(&self) -> Result<Vec<DiscoveredServer>> {
+        let records = self.srv_records().await?;
+        let primary = records.iter().map(|r| r.priority).min();
+
+        let mut servers = Vec::new();
+        for record in records {
+            // A target of "." means the service is explicitly not offered
+            if record.target.is_empty() || record.target == "." {
+                continue;
+            }
+            let addresses = tokio::net::lookup_host((record.target.trim_end_matches('.'), record.port))
+                .await
+                .map_err(|e| Error::Network(format!("Failed to resolve {}: {}", record.target, e)))?;
+            for address in addresses {
+                servers.push(DiscoveredServer {
+                    address: address.ip(),
+                    port: record.port,
+                    weight: (record.weight > 0).then(|| u32::from(record.weight).min(MAX_WEIGHT)),
+                    backup: Some(record.priority) != primary,
+                });
+            }
+        }
+        Ok(servers)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+impl SrvRecord {
+    /// Presentation form: "priority weight port target"
+    fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split_whitespace();
+        let record = Self {
+            priority: fields.next()?.parse().ok()?,
+            weight: fields.next()?.parse().ok()?,
+            port: fields.next()?.parse().ok()?,
+            target: fields.next()?.to_string(),
+        };
+        fields.next().is_none().then_some(record)
+    }
+}
+
+/// How a discovery source's server set changed between two polls
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerDiff {
+    pub backend: String,
+    pub added: Vec<DiscoveredServer>,
+    /// No longer reported; these are drained before they are dropped
+    pub removed: Vec<DiscoveredServer>,
+    /// Still reported with a different weight or backup flag
+    pub changed: Vec<DiscoveredServer>,
+    /// Names of servers whose drain period ran out and were dropped
+    pub expired: Vec<String>,
+}
+
+impl ServerDiff {
+    /// Compare two server sets by address and port
+    pub fn between(backend: &str, previous: &[DiscoveredServer], current: &[DiscoveredServer]) -> Self {
+        let previous: BTreeMap<SocketAddr, &DiscoveredServer> = previous.iter().map(|s| (s.endpoint(), s)).collect();
+        let current: BTreeMap<SocketAddr, &DiscoveredServer> = current.iter().map(|s| (s.endpoint(), s)).collect();
+
+        let mut diff = Self { backend: backend.to_string(), ..Self::default() };
+        for (endpoint, server) in &current {
+            match previous.get(endpoint) {
+                None => diff.added.push((*server).clone()),
+                Some(old) if old != server => diff.changed.push((*server).clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = previous.iter()
+            .filter(|(endpoint, _)| !current.contains_key(endpoint))
+            .map(|(_, server)| (*server).clone())
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.expired.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(address: &str, port: u16) -> DiscoveredServer {
+        DiscoveredServer::new(address.parse().unwrap(), port)
+    }
+
+    #[test]
+    fn test_diff_by_endpoint() {
+        let before = vec![server("10.0.0.1", 80), server("10.0.0.2", 80), server("10.0.0.3", 80)];
+        let mut heavier = server("10.0.0.2", 80);
+        heavier.weight = Some(200);
+        let after = vec![heavier.clone(), server("10.0.0.3", 80), server("10.0.0.3", 8080)];
+
+        let diff = ServerDiff::between("app", &before, &after);
+        assert_eq!(diff.added, vec![server("10.0.0.3", 8080)]);
+        assert_eq!(diff.removed, vec![server("10.0.0.1", 80)]);
+        assert_eq!(diff.changed, vec![heavier]);
+        assert!(ServerDiff::between("app", &after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_parse_srv() {
+        assert_eq!(
+            SrvRecord::parse("10 60 8080 web1.example.com."),
+            Some(SrvRecord { priority: 10, weight: 60, port: 8080, target: "web1.example.com.".to_string() })
+        );
+        assert_eq!(SrvRecord::parse("10 60 8080"), None);
+        assert_eq!(SrvRecord::parse("10 60 99999 web1."), None);
+    }
+}
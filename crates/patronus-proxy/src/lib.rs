@@ -2,11 +2,16 @@
 //!
 //! Provides HAProxy integration for load balancing and reverse proxy functionality.
 
+pub mod discovery;
 pub mod haproxy;
+pub mod outlier;
 
 pub use haproxy::{
     HAProxyManager, HAProxyConfig, Frontend, Backend, BackendServer,
     ProxyMode, BalanceAlgorithm, HealthCheck, HealthCheckMethod,
-    AccessControlList, AclCondition, BackendRule, StatsConfig,
+    AccessControlList, AclCondition, BackendRule, HeaderAction, HeaderRule, HttpDirection, StatsConfig,
     HAProxyStats, BackendStats, ServerStats,
+    FrontendTls, TlsCertificate, CertificateSource, Persistence,
 };
+pub use discovery::{DiscoveredServer, DnsSrvResolver, ServerDiff, ServiceDiscovery, StaticResolver};
+pub use outlier::{OutlierDetection, OutlierDetector, ResponseOutcome, ServerProbe, TcpProbe};
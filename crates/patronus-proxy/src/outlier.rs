@@ -0,0 +1,215 @@
+//! Outlier Detection
+//!
+//! Passive circuit breaking for backend servers. Responses observed for a
+//! server are fed to an [`OutlierDetector`]; after enough consecutive 5xx
+//! responses, timeouts or connection failures the server is ejected for a
+//! cooldown, and after the cooldown it must pass a probe to be re-admitted.
+//! This complements HAProxy's active health checks, which only see the
+//! check endpoint.
+
+use crate::haproxy::BackendServer;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// When to eject a server, configured per server in its `HealthCheck`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutlierDetection {
+    /// Consecutive failed responses that eject the server
+    pub consecutive_failures: u32,
+    /// Seconds an ejected server stays out before it is probed
+    pub cooldown: u32,
+}
+
+impl Default for OutlierDetection {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 5,
+            cooldown: 30,
+        }
+    }
+}
+
+/// What happened to one request sent to a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResponseOutcome {
+    /// HTTP status; 5xx counts as a failure
+    Status(u16),
+    /// Completed without an HTTP status, e.g. a TCP session
+    Success,
+    Timeout,
+    ConnectFailed,
+}
+
+impl ResponseOutcome {
+    pub fn is_failure(&self) -> bool {
+        match self {
+            ResponseOutcome::Status(status) => (500..600).contains(status),
+            ResponseOutcome::Success => false,
+            ResponseOutcome::Timeout | ResponseOutcome::ConnectFailed => true,
+        }
+    }
+}
+
+/// Checks whether an ejected server can take traffic again
+#[async_trait]
+pub trait ServerProbe: Send + Sync {
+    async fn probe(&self, server: &BackendServer) -> bool;
+}
+
+/// [`ServerProbe`] that opens a TCP connection to the check port
+#[derive(Debug, Default)]
+pub struct TcpProbe;
+
+#[async_trait]
+impl ServerProbe for TcpProbe {
+    async fn probe(&self, server: &BackendServer) -> bool {
+        let port = server.check.check_port.unwrap_or(server.port);
+        let timeout = Duration::from_secs(server.check.timeout.max(1) as u64);
+        matches!(
+            tokio::time::timeout(timeout, tokio::net::TcpStream::connect((server.address, port))).await,
+            Ok(Ok(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ServerState {
+    consecutive_failures: u32,
+    ejected_until: Option<Instant>,
+    ejections: u64,
+}
+
+/// Tracks consecutive failures and ejections per (backend, server)
+#[derive(Debug, Default)]
+pub struct OutlierDetector {
+    servers: HashMap<(String, String), ServerState>,
+}
+
+impl OutlierDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a response; true if it ejected the server
+    ///
+    /// Responses from an ejected server (e.g. requests already in flight)
+    /// do not extend its ejection.
+    pub fn record(
+        &mut self,
+        backend: &str,
+        server: &str,
+        outcome: ResponseOutcome,
+        policy: &OutlierDetection,
+        now: Instant,
+    ) -> bool {
+        let state = self.servers.entry((backend.to_string(), server.to_string())).or_default();
+        if state.ejected_until.is_some() {
+            return false;
+        }
+        if !outcome.is_failure() {
+            state.consecutive_failures = 0;
+            return false;
+        }
+
+        state.consecutive_failures += 1;
+        if state.consecutive_failures < policy.consecutive_failures.max(1) {
+            return false;
+        }
+        state.consecutive_failures = 0;
+        state.ejected_until = Some(now + Duration::from_secs(policy.cooldown as u64));
+        state.ejections += 1;
+        true
+    }
+
+    pub fn is_ejected(&self, backend: &str, server: &str) -> bool {
+        self.state(backend, server).is_some_and(|s| s.ejected_until.is_some())
+    }
+
+    /// Ejected servers whose cooldown is over, as (backend, server)
+    pub fn due_for_probe(&self, now: Instant) -> Vec<(String, String)> {
+        let mut due: Vec<(String, String)> = self.servers.iter()
+            .filter(|(_, state)| state.ejected_until.is_some_and(|until| until <= now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        due.sort();
+        due
+    }
+
+    /// Record a probe of an ejected server; true if it was re-admitted
+    ///
+    /// A failed probe keeps the server out for another cooldown.
+    pub fn probe_result(
+        &mut self,
+        backend: &str,
+        server: &str,
+        success: bool,
+        policy: &OutlierDetection,
+        now: Instant,
+    ) -> bool {
+        let Some(state) = self.servers.get_mut(&(backend.to_string(), server.to_string())) else {
+            return false;
+        };
+        if state.ejected_until.is_none() {
+            return false;
+        }
+        if success {
+            state.ejected_until = None;
+            true
+        } else {
+            state.ejected_until = Some(now + Duration::from_secs(policy.cooldown as u64));
+            false
+        }
+    }
+
+    /// Times the server has been ejected
+    pub fn ejections(&self, backend: &str, server: &str) -> u64 {
+        self.state(backend, server).map_or(0, |s| s.ejections)
+    }
+
+    /// Stop tracking servers that are no longer configured
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, &str) -> bool) {
+        self.servers.retain(|(backend, server), _| keep(backend, server));
+    }
+
+    fn state(&self, backend: &str, server: &str) -> Option<&ServerState> {
+        self.servers.get(&(backend.to_string(), server.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eject_after_consecutive_failures() {
+        let policy = OutlierDetection { consecutive_failures: 3, cooldown: 30 };
+        let mut detector = OutlierDetector::new();
+        let t0 = Instant::now();
+
+        // A success in between resets the count
+        for outcome in [ResponseOutcome::Status(502), ResponseOutcome::Timeout, ResponseOutcome::Status(404)] {
+            assert!(!detector.record("app", "web1", outcome, &policy, t0));
+        }
+        assert!(!detector.record("app", "web1", ResponseOutcome::Status(500), &policy, t0));
+        assert!(!detector.record("app", "web1", ResponseOutcome::ConnectFailed, &policy, t0));
+        assert!(detector.record("app", "web1", ResponseOutcome::Status(503), &policy, t0));
+        assert!(detector.is_ejected("app", "web1"));
+        assert!(!detector.is_ejected("app", "web2"));
+
+        // Probed only once the cooldown is over
+        assert!(detector.due_for_probe(t0 + Duration::from_secs(29)).is_empty());
+        let later = t0 + Duration::from_secs(30);
+        assert_eq!(detector.due_for_probe(later), vec![("app".to_string(), "web1".to_string())]);
+
+        // A failed probe starts another cooldown
+        assert!(!detector.probe_result("app", "web1", false, &policy, later));
+        assert!(detector.due_for_probe(later + Duration::from_secs(29)).is_empty());
+
+        let later = later + Duration::from_secs(30);
+        assert!(detector.probe_result("app", "web1", true, &policy, later));
+        assert!(!detector.is_ejected("app", "web1"));
+        assert_eq!(detector.ejections("app", "web1"), 1);
+    }
+}
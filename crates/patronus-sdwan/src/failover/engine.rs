@@ -3,12 +3,14 @@
 //! This module implements the core failover logic that monitors path health
 //! and automatically switches between primary and backup paths.
 
-use super::{FailoverEvent, FailoverPolicy, FailoverState};
+use super::{FailoverEvent, FailoverPolicy, FailoverState, PredictionThresholds};
 use crate::database::Database;
 use crate::health::{BfdHealthMonitor, HealthMonitor, PathHealth};
 use crate::types::PathId;
+use patronus_ml::{CalibrationReport, CalibrationTracker, FailoverPrediction, PredictionRecord};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, RwLock};
 use tokio::time::{interval, Duration};
 
@@ -34,6 +36,15 @@ pub struct FailoverEngine {
 
     /// Channel for receiving BFD state changes
     bfd_state_rx: Arc<RwLock<Option<mpsc::Receiver<(PathId, PathHealth)>>>>,
+
+    /// Failure probabilities that trigger pre-emptive failover
+    prediction_thresholds: PredictionThresholds,
+
+    /// Link class of each path, for picking its prediction threshold
+    path_classes: Arc<RwLock<HashMap<PathId, String>>>,
+
+    /// Failure predictions scored against actual path failures
+    calibration: Arc<RwLock<CalibrationTracker>>,
 }
 
 impl FailoverEngine {
@@ -50,6 +61,10 @@ impl FailoverEngine {
             states: Arc::new(RwLock::new(HashMap::new())),
             eval_interval_secs: 5, // Evaluate every 5 seconds
             bfd_state_rx: Arc::new(RwLock::new(None)),
+            prediction_thresholds: PredictionThresholds::default(),
+            path_classes: Arc::new(RwLock::new(HashMap::new())),
+            // Did the path fail within 5 minutes, over its last 1000 predictions
+            calibration: Arc::new(RwLock::new(CalibrationTracker::new(300, 1000))),
         }
     }
 
@@ -67,6 +82,121 @@ impl FailoverEngine {
         self
     }
 
+    /// Set the failure probabilities that trigger pre-emptive failover
+    pub fn with_prediction_thresholds(mut self, thresholds: PredictionThresholds) -> Self {
+        self.prediction_thresholds = thresholds;
+        self
+    }
+
+    /// Score predictions over `horizon_secs`, keeping `window` per path
+    pub fn with_calibration(mut self, horizon_secs: u64, window: usize) -> Self {
+        self.calibration = Arc::new(RwLock::new(CalibrationTracker::new(horizon_secs, window)));
+        self
+    }
+
+    /// Set the link class a path's prediction threshold is chosen by
+    pub async fn set_path_class(&self, path_id: PathId, class: impl Into<String>) {
+        let mut classes = self.path_classes.write().await;
+        classes.insert(path_id, class.into());
+    }
+
+    /// Act on a failure prediction for a path
+    ///
+    /// The prediction is recorded for calibration. If its probability
+    /// reaches the threshold of the path's link class, every enabled policy
+    /// currently using the path as its primary fails over to a backup.
+    /// Returns whether any policy failed over.
+    pub async fn handle_prediction(
+        &self,
+        path_id: PathId,
+        prediction: &FailoverPrediction,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let class = {
+            let classes = self.path_classes.read().await;
+            classes.get(&path_id).cloned()
+        };
+        let threshold = self.prediction_thresholds.threshold_for(class.as_deref());
+        let triggered = !prediction.suppressed && prediction.failure_probability >= threshold;
+
+        {
+            let mut calibration = self.calibration.write().await;
+            calibration.record(&path_id.to_string(), prediction, triggered, unix_now());
+        }
+
+        if !triggered {
+            return Ok(false);
+        }
+
+        let policies: Vec<FailoverPolicy> = {
+            let policies = self.policies.read().await;
+            policies
+                .values()
+                .filter(|p| p.enabled && p.primary_path_id == path_id)
+                .cloned()
+                .collect()
+        };
+
+        let contributors: Vec<String> = prediction
+            .explanation
+            .iter()
+            .map(|c| format!("{}={:.3} ({:+.2})", c.feature, c.value, c.contribution))
+            .collect();
+
+        let mut failed_over = false;
+        for policy in policies {
+            let state = {
+                let states = self.states.read().await;
+                states.get(&policy.policy_id).cloned()
+            };
+            let mut state = state.unwrap_or_else(|| FailoverState::new(policy.policy_id, policy.primary_path_id));
+            if !state.using_primary {
+                continue;
+            }
+
+            let primary_score = self.get_path_health_score(&path_id).await;
+            let reason = format!(
+                "Predicted primary failure ({:.2} >= {:.2}): {}",
+                prediction.failure_probability,
+                threshold,
+                contributors.join(", ")
+            );
+            self.execute_failover(&policy, &mut state, primary_score, reason).await?;
+            failed_over = true;
+
+            let mut states = self.states.write().await;
+            states.insert(policy.policy_id, state);
+        }
+
+        Ok(failed_over)
+    }
+
+    /// Record that a path failed, resolving its pending predictions
+    ///
+    /// Policy evaluation records failures of primary paths itself; this is
+    /// for failures seen elsewhere.
+    pub async fn record_path_failure(&self, path_id: PathId) {
+        let mut calibration = self.calibration.write().await;
+        calibration.record_failure(&path_id.to_string(), unix_now());
+    }
+
+    /// Recent failure predictions for a path, oldest first
+    pub async fn get_prediction_history(&self, path_id: PathId) -> Vec<PredictionRecord> {
+        let calibration = self.calibration.read().await;
+        calibration.get_prediction_history(&path_id.to_string())
+    }
+
+    /// How well failure predictions matched path failures
+    ///
+    /// Covers every path, or only `path_id` if given.
+    pub async fn calibration_report(&self, path_id: Option<PathId>) -> CalibrationReport {
+        let mut calibration = self.calibration.write().await;
+        calibration.resolve(unix_now());
+        match path_id {
+            Some(path_id) => calibration.report_for(&path_id.to_string()),
+            None => calibration.report(),
+        }
+    }
+
     /// Add a failover policy
     pub async fn add_policy(&self, policy: FailoverPolicy) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Validate policy
@@ -225,11 +355,20 @@ impl FailoverEngine {
         // Get path health (with BFD fallback)
         let primary_score = self.get_path_health_score(&policy.primary_path_id).await;
 
+        // A failed primary is the outcome predictions are scored against
+        if policy.should_failover(primary_score) {
+            self.record_path_failure(policy.primary_path_id).await;
+        }
+
         // Check if we're currently using primary
         if state.using_primary {
             // On primary - check if we should failover
             if policy.should_failover(primary_score) {
-                self.execute_failover(policy, &mut state, primary_score).await?;
+                let reason = format!(
+                    "Primary health ({:.1}) below threshold ({:.1})",
+                    primary_score, policy.failover_threshold
+                );
+                self.execute_failover(policy, &mut state, primary_score, reason).await?;
             } else {
                 // Primary is healthy, update state
                 state.mark_primary_healthy();
@@ -269,6 +408,7 @@ impl FailoverEngine {
         policy: &FailoverPolicy,
         state: &mut FailoverState,
         primary_score: f64,
+        reason: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Get health for all backup paths (using BFD if available)
         let mut backup_health = Vec::new();
@@ -302,10 +442,7 @@ impl FailoverEngine {
                     backup_id,
                     primary_score,
                     backup_score,
-                    format!(
-                        "Primary health ({:.1}) below threshold ({:.1})",
-                        primary_score, policy.failover_threshold
-                    ),
+                    reason,
                 );
 
                 self.log_event(&event).await?;
@@ -465,6 +602,13 @@ impl FailoverEngine {
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -538,4 +682,64 @@ mod tests {
         let result = engine.add_policy(policy).await;
         assert!(result.is_err());
     }
+
+    fn prediction(probability: f64) -> FailoverPrediction {
+        FailoverPrediction {
+            failure_probability: probability,
+            confidence: 1.0,
+            should_failover: false,
+            recommend_reroute: false,
+            suppressed: false,
+            time_to_failure_seconds: None,
+            time_to_failure: None,
+            reason: String::new(),
+            explanation: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prediction_threshold_per_link_class() {
+        let db = Arc::new(Database::new_in_memory().await.unwrap());
+        let health_monitor = Arc::new(HealthMonitor::new(db.clone(), HealthConfig::default()).await.unwrap());
+        let engine = FailoverEngine::new(db, health_monitor)
+            .with_prediction_thresholds(PredictionThresholds::new(0.6).with_class("lte", 0.9));
+
+        engine
+            .add_policy(FailoverPolicy::new(1, "fibre".to_string(), PathId::new(10), vec![PathId::new(20)]))
+            .await
+            .unwrap();
+        engine
+            .add_policy(FailoverPolicy::new(2, "lte".to_string(), PathId::new(30), vec![PathId::new(40)]))
+            .await
+            .unwrap();
+        engine.set_path_class(PathId::new(30), "lte").await;
+
+        // 0.8 clears the default threshold but not the LTE one
+        assert!(engine.handle_prediction(PathId::new(10), &prediction(0.8)).await.unwrap());
+        assert!(!engine.handle_prediction(PathId::new(30), &prediction(0.8)).await.unwrap());
+
+        let fibre = engine.get_state(1).await.unwrap();
+        assert!(!fibre.using_primary);
+        assert_eq!(fibre.active_path_id, PathId::new(20));
+        assert!(engine.get_state(2).await.unwrap().using_primary);
+
+        // Suppressed predictions never trigger
+        let mut suppressed = prediction(0.95);
+        suppressed.suppressed = true;
+        assert!(!engine.handle_prediction(PathId::new(30), &suppressed).await.unwrap());
+        assert!(engine.handle_prediction(PathId::new(30), &prediction(0.95)).await.unwrap());
+
+        let history = engine.get_prediction_history(PathId::new(30)).await;
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history.iter().map(|r| r.predicted_failure).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+
+        engine.record_path_failure(PathId::new(30)).await;
+        let report = engine.calibration_report(Some(PathId::new(30))).await;
+        assert_eq!(report.resolved, 3);
+        assert_eq!((report.true_positives, report.false_negatives), (1, 2));
+        assert_eq!(engine.calibration_report(None).await.pending, 1);
+    }
 }
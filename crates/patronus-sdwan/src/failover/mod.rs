@@ -9,6 +9,8 @@
 //! - **Health-Based Triggers**: Automatic failover on path degradation
 //! - **Smart Failback**: Hysteresis prevents flapping
 //! - **Event Logging**: Complete audit trail of failover events
+//! - **Predictive Failover**: Pre-emptive failover on ML failure predictions,
+//!   with per-link-class thresholds and calibration tracking
 //!
 //! # Example
 //!
@@ -36,10 +38,13 @@
 mod engine;
 mod events;
 mod policy;
+mod predictive;
 
 pub use engine::FailoverEngine;
 pub use events::{FailoverEvent, FailoverEventType};
 pub use policy::FailoverPolicy;
+pub use predictive::PredictionThresholds;
+pub use patronus_ml::{CalibrationReport, FailoverPrediction, FeatureContribution, PredictionRecord};
 
 use crate::types::PathId;
 use serde::{Deserialize, Serialize};
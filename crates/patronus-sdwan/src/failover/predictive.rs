@@ -0,0 +1,83 @@
+//! Pre-emptive failover on predicted path failures
+//!
+//! Failure predictions from `patronus_ml::PredictiveFailover` can move
+//! traffic off a primary path before its health score drops. How likely a
+//! failure must be depends on the path's link class: a metered LTE backup
+//! warrants more certainty than a second fibre circuit.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Failure probability thresholds for pre-emptive failover, per link class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionThresholds {
+    /// Threshold for paths without a class, or with a class not listed
+    pub default_threshold: f64,
+
+    /// Thresholds by link class (e.g. "broadband", "mpls", "lte")
+    pub by_class: HashMap<String, f64>,
+}
+
+impl PredictionThresholds {
+    /// Create thresholds with a single default
+    pub fn new(default_threshold: f64) -> Self {
+        Self {
+            default_threshold,
+            by_class: HashMap::new(),
+        }
+    }
+
+    /// Set the threshold for a link class
+    pub fn with_class(mut self, class: impl Into<String>, threshold: f64) -> Self {
+        self.by_class.insert(class.into(), threshold);
+        self
+    }
+
+    /// Threshold applying to a path of `class`
+    pub fn threshold_for(&self, class: Option<&str>) -> f64 {
+        class
+            .and_then(|c| self.by_class.get(c))
+            .copied()
+            .unwrap_or(self.default_threshold)
+    }
+
+    /// Validate threshold values
+    pub fn validate(&self) -> Result<(), String> {
+        let out_of_range = |t: f64| !(0.0..=1.0).contains(&t);
+        if out_of_range(self.default_threshold) {
+            return Err("Default prediction threshold must be between 0 and 1".to_string());
+        }
+        if let Some((class, _)) = self.by_class.iter().find(|(_, t)| out_of_range(**t)) {
+            return Err(format!("Prediction threshold for class {} must be between 0 and 1", class));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PredictionThresholds {
+    fn default() -> Self {
+        // Matches PredictiveFailover's own failover threshold
+        Self::new(0.75)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_for_class() {
+        let thresholds = PredictionThresholds::new(0.7)
+            .with_class("lte", 0.9)
+            .with_class("mpls", 0.5);
+
+        assert_eq!(thresholds.threshold_for(Some("lte")), 0.9);
+        assert_eq!(thresholds.threshold_for(Some("mpls")), 0.5);
+        assert_eq!(thresholds.threshold_for(Some("satellite")), 0.7);
+        assert_eq!(thresholds.threshold_for(None), 0.7);
+        assert!(thresholds.validate().is_ok());
+
+        assert!(PredictionThresholds::new(0.7).with_class("lte", 1.5).validate().is_err());
+        assert!(PredictionThresholds::new(-0.1).validate().is_err());
+    }
+}
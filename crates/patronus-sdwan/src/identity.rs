@@ -0,0 +1,141 @@
+//! Persistent site identity
+//!
+//! The site ID, name and Ed25519 signing key other sites know this one by.
+//! Keeping them on disk lets a site survive restarts and, through
+//! [`SiteIdentity::node_component`], move to replacement hardware without
+//! being re-enrolled in the mesh.
+
+use crate::types::SiteId;
+use crate::{Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::SigningKey;
+use patronus_core::node::FileComponent;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Node component name of the site identity
+pub const NODE_COMPONENT: &str = "sdwan-identity";
+
+/// Who this site is in the mesh
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SiteIdentity {
+    pub site_id: SiteId,
+    pub site_name: String,
+    /// Base64 Ed25519 secret key
+    signing_key: String,
+}
+
+impl std::fmt::Debug for SiteIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SiteIdentity")
+            .field("site_id", &self.site_id)
+            .field("site_name", &self.site_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SiteIdentity {
+    /// A new site with a random ID and signing key
+    pub fn generate(site_name: &str) -> Self {
+        use rand::RngCore;
+
+        let mut secret_bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut secret_bytes);
+        Self {
+            site_id: SiteId::generate(),
+            site_name: site_name.to_string(),
+            signing_key: STANDARD.encode(secret_bytes),
+        }
+    }
+
+    pub fn signing_key(&self) -> Result<SigningKey> {
+        let bytes: [u8; 32] = STANDARD
+            .decode(&self.signing_key)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| Error::InvalidConfig("site signing key must be 32 bytes of base64".to_string()))?;
+        Ok(SigningKey::from_bytes(&bytes))
+    }
+
+    /// Parse and check a serialized identity
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let identity: Self = serde_json::from_slice(bytes)?;
+        identity.signing_key()?;
+        Ok(identity)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Write the identity, readable by the owner only
+    pub fn save(&self, path: &Path) -> Result<()> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Load the identity at `path`, creating one for `site_name` on first start
+    pub fn load_or_generate(path: &Path, site_name: &str) -> Result<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+        let identity = Self::generate(site_name);
+        identity.save(path)?;
+        tracing::info!(site_id = %identity.site_id, "Generated new site identity");
+        Ok(identity)
+    }
+
+    /// Carries the identity file at `path` in node export archives
+    pub fn node_component(path: &Path) -> FileComponent {
+        FileComponent::new(NODE_COMPONENT, path)
+            .sensitive()
+            .with_validator(|payload| Self::from_bytes(payload).map(|_| ()).map_err(|e| e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus_core::node::NodeComponent;
+
+    #[test]
+    fn test_identity_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identity.json");
+
+        let identity = SiteIdentity::load_or_generate(&path, "branch-1").unwrap();
+        let reloaded = SiteIdentity::load_or_generate(&path, "ignored").unwrap();
+        assert_eq!(reloaded.site_id, identity.site_id);
+        assert_eq!(reloaded.site_name, "branch-1");
+        assert_eq!(reloaded.signing_key().unwrap().to_bytes(), identity.signing_key().unwrap().to_bytes());
+
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn test_node_component_rejects_bad_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let component = SiteIdentity::node_component(&dir.path().join("identity.json"));
+        assert!(NodeComponent::sensitive(&component));
+
+        let bad = br#"{"site_id":"5b0f1c3e-8d43-4c6a-9a57-1f0e2d3c4b5a","site_name":"x","signing_key":"c2hvcnQ="}"#;
+        assert!(component.stage(bad.to_vec()).await.is_err());
+
+        let good = serde_json::to_vec(&SiteIdentity::generate("x")).unwrap();
+        assert!(component.stage(good).await.is_ok());
+    }
+}
@@ -26,6 +26,7 @@
 //! ```
 
 pub mod mesh;
+pub mod identity;
 pub mod monitor;
 pub mod routing;
 pub mod types;
@@ -47,6 +48,7 @@ pub mod qos;
 
 pub use error::{Error, Result};
 pub use types::{SiteId, PathId, FlowKey, FlowRecord, FlowStats};
+pub use identity::SiteIdentity;
 
 use std::sync::Arc;
 
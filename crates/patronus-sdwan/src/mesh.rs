@@ -1,6 +1,6 @@
 //! Mesh management - automatic site discovery and peering
 
-use crate::{database::Database, peering::PeeringManager, types::*, Error, Result};
+use crate::{database::Database, identity::SiteIdentity, peering::PeeringManager, types::*, Error, Result};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
 use std::collections::HashMap;
@@ -159,7 +159,20 @@ impl MeshManager {
         let mut secret_bytes = [0u8; 32];
         use rand::RngCore;
         OsRng.fill_bytes(&mut secret_bytes);
-        let signing_key = SigningKey::from_bytes(&secret_bytes);
+        Self::with_signing_key(site_id, site_name, SigningKey::from_bytes(&secret_bytes), db)
+    }
+
+    /// Create a mesh manager for a persisted site identity
+    pub fn with_identity(identity: &SiteIdentity, db: Arc<Database>) -> Result<Self> {
+        Ok(Self::with_signing_key(
+            identity.site_id,
+            identity.site_name.clone(),
+            identity.signing_key()?,
+            db,
+        ))
+    }
+
+    fn with_signing_key(site_id: SiteId, site_name: String, signing_key: SigningKey, db: Arc<Database>) -> Self {
         let verifying_key = signing_key.verifying_key();
 
         let (announcement_tx, announcement_rx) = mpsc::channel(100);
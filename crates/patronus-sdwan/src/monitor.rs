@@ -7,6 +7,8 @@
 //! - Path availability
 //!
 //! Results are stored in the database and used for intelligent routing decisions.
+//! Each collection round is also published to [`PathMonitor::subscribe`] receivers,
+//! e.g. to size FEC redundancy from the measured loss.
 
 use crate::{database::Database, types::*, Error, Result};
 use std::collections::HashMap;
@@ -14,7 +16,7 @@ use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 
@@ -36,12 +38,16 @@ const BANDWIDTH_TEST_DURATION: Duration = Duration::from_secs(5);
 /// Bandwidth test packet size - 1KB chunks
 const BANDWIDTH_PACKET_SIZE: usize = 1024;
 
+/// Metrics updates buffered per subscriber before it starts lagging
+const METRICS_CHANNEL_CAPACITY: usize = 256;
+
 /// Path monitor measures quality metrics for all paths
 pub struct PathMonitor {
     db: Arc<Database>,
     running: Arc<RwLock<bool>>,
     tasks: Arc<RwLock<Vec<JoinHandle<()>>>>,
     probe_results: Arc<RwLock<HashMap<PathId, ProbeHistory>>>,
+    metrics_tx: broadcast::Sender<(PathId, PathMetrics)>,
 }
 
 /// Probe history for a path
@@ -234,9 +240,15 @@ impl PathMonitor {
             running: Arc::new(RwLock::new(false)),
             tasks: Arc::new(RwLock::new(Vec::new())),
             probe_results: Arc::new(RwLock::new(HashMap::new())),
+            metrics_tx: broadcast::channel(METRICS_CHANNEL_CAPACITY).0,
         }
     }
 
+    /// Receive the metrics of every path each time they are collected
+    pub fn subscribe(&self) -> broadcast::Receiver<(PathId, PathMetrics)> {
+        self.metrics_tx.subscribe()
+    }
+
     /// Start the path monitor
     pub async fn start(&self) -> Result<()> {
         let mut running = self.running.write().await;
@@ -406,6 +418,7 @@ impl PathMonitor {
         let db = self.db.clone();
         let running = self.running.clone();
         let probe_results = self.probe_results.clone();
+        let metrics_tx = self.metrics_tx.clone();
 
         let task = tokio::spawn(async move {
             info!("Starting metrics collector");
@@ -421,6 +434,9 @@ impl PathMonitor {
                 for (path_id, history) in results.iter() {
                     let metrics = history.to_metrics();
 
+                    // No subscribers is fine
+                    let _ = metrics_tx.send((*path_id, metrics));
+
                     // Store in database
                     if let Err(e) = db.store_path_metrics(*path_id, &metrics).await {
                         error!(
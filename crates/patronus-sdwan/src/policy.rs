@@ -46,6 +46,21 @@ pub struct MatchRules {
 
     /// Match application class
     pub application_class: Option<ApplicationClass>,
+
+    /// Match DSCP code point (0-63)
+    #[serde(default)]
+    pub dscp: Option<u8>,
+
+    /// Match 802.1Q VLAN ID
+    #[serde(default)]
+    pub vlan_id: Option<u16>,
+}
+
+/// Per-packet markings that are not part of the flow 5-tuple
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketMarking {
+    pub dscp: Option<u8>,
+    pub vlan_id: Option<u16>,
 }
 
 /// Path selection preference
@@ -282,7 +297,22 @@ pub struct PolicyMatcher;
 
 impl PolicyMatcher {
     /// Check if a flow matches the policy rules
+    ///
+    /// Rules on DSCP or VLAN never match here, since the flow key does not
+    /// carry them; use [`matches_marked`](Self::matches_marked).
     pub fn matches(flow: &FlowKey, rules: &MatchRules) -> bool {
+        Self::matches_marked(flow, &PacketMarking::default(), rules)
+    }
+
+    /// Check if a flow with the given packet markings matches the policy rules
+    pub fn matches_marked(flow: &FlowKey, marking: &PacketMarking, rules: &MatchRules) -> bool {
+        if rules.dscp.is_some() && rules.dscp != marking.dscp {
+            return false;
+        }
+        if rules.vlan_id.is_some() && rules.vlan_id != marking.vlan_id {
+            return false;
+        }
+
         // Match protocol
         if let Some(protocol) = rules.protocol {
             if flow.protocol != protocol {
@@ -450,12 +480,14 @@ impl PolicyEngine {
 
     /// Find the best matching policy for a flow
     pub fn find_matching_policy(&self, flow: &FlowKey) -> Option<&RoutingPolicy> {
-        for policy in &self.policies {
-            if policy.enabled && PolicyMatcher::matches(flow, &policy.match_rules) {
-                return Some(policy);
-            }
-        }
-        None
+        self.find_matching_policy_marked(flow, &PacketMarking::default())
+    }
+
+    /// Find the best matching policy for a flow carrying `marking`
+    pub fn find_matching_policy_marked(&self, flow: &FlowKey, marking: &PacketMarking) -> Option<&RoutingPolicy> {
+        self.policies
+            .iter()
+            .find(|policy| policy.enabled && PolicyMatcher::matches_marked(flow, marking, &policy.match_rules))
     }
 }
 
@@ -532,6 +564,31 @@ mod tests {
         assert!(!PolicyMatcher::matches(&flow, &rules));
     }
 
+    #[test]
+    fn test_policy_matching_dscp_and_vlan() {
+        let flow = FlowKey {
+            src_ip: "192.168.1.1".parse().unwrap(),
+            dst_ip: "10.0.0.1".parse().unwrap(),
+            src_port: 12345,
+            dst_port: 80,
+            protocol: 6,
+        };
+        let rules = MatchRules {
+            dscp: Some(46),
+            ..Default::default()
+        };
+
+        assert!(!PolicyMatcher::matches(&flow, &rules));
+        let ef = PacketMarking { dscp: Some(46), vlan_id: Some(20) };
+        assert!(PolicyMatcher::matches_marked(&flow, &ef, &rules));
+
+        let rules = MatchRules {
+            vlan_id: Some(30),
+            ..Default::default()
+        };
+        assert!(!PolicyMatcher::matches_marked(&flow, &ef, &rules));
+    }
+
     #[test]
     fn test_application_class() {
         assert_eq!(ApplicationClass::from_flow(6, 80), ApplicationClass::Web);
@@ -4,13 +4,14 @@
 
 use crate::dpi::ApplicationType;
 use crate::types::FlowKey;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use tracing::{debug, trace, warn};
 
 /// QoS class priority levels
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum QosClass {
     /// Real-time traffic (VoIP, gaming) - highest priority
     RealTime = 0,
@@ -89,6 +89,8 @@ impl RoutingEngine {
                 dst_port_range: Some((5060, 5061)), // SIP
                 protocol: None,
                 application_class: None,
+                dscp: None,
+                vlan_id: None,
             },
             path_preference: PathPreference::Custom(PathScoringWeights::latency_sensitive()),
             enabled: true,
@@ -106,6 +108,8 @@ impl RoutingEngine {
                 dst_port_range: Some((27000, 28000)), // Steam/Gaming ports
                 protocol: Some(17), // UDP
                 application_class: None,
+                dscp: None,
+                vlan_id: None,
             },
             path_preference: PathPreference::LowestLatency,
             enabled: true,
@@ -123,6 +127,8 @@ impl RoutingEngine {
                 dst_port_range: Some((20, 21)), // FTP
                 protocol: Some(6), // TCP
                 application_class: None,
+                dscp: None,
+                vlan_id: None,
             },
             path_preference: PathPreference::HighestBandwidth,
             enabled: true,
@@ -140,6 +146,8 @@ impl RoutingEngine {
                 dst_port_range: None,
                 protocol: None,
                 application_class: None,
+                dscp: None,
+                vlan_id: None,
             },
             path_preference: PathPreference::Custom(PathScoringWeights::latency_sensitive()),
             enabled: true,
@@ -6,8 +6,12 @@
 use crate::types::PathId;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// On-demand verification runs kept per path
+const MAX_VERIFICATIONS: usize = 20;
 
 /// SLA configuration for a path
 #[derive(Debug, Clone)]
@@ -85,6 +89,20 @@ impl SlaMeasurement {
     }
 }
 
+/// An on-demand throughput test run over a path
+///
+/// Refers to the stored test result by id, so the full per-interval data
+/// stays with the diagnostics throughput history.
+#[derive(Debug, Clone)]
+pub struct ThroughputVerification {
+    /// Id of the throughput test result
+    pub run_id: Uuid,
+    pub mbps: f64,
+    /// UDP loss seen during the run, if it was a UDP test
+    pub packet_loss_pct: Option<f64>,
+    pub timestamp: SystemTime,
+}
+
 /// Latency sample
 #[derive(Debug, Clone)]
 struct LatencySample {
@@ -134,6 +152,9 @@ pub struct SlaMonitor {
 
     /// Latest SLA results per path
     results: Arc<RwLock<HashMap<PathId, SlaMeasurement>>>,
+
+    /// On-demand throughput runs per path, oldest first
+    verifications: Arc<RwLock<HashMap<PathId, Vec<ThroughputVerification>>>>,
 }
 
 impl SlaMonitor {
@@ -143,6 +164,7 @@ impl SlaMonitor {
             configs: Arc::new(RwLock::new(HashMap::new())),
             measurements: Arc::new(RwLock::new(HashMap::new())),
             results: Arc::new(RwLock::new(HashMap::new())),
+            verifications: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -285,6 +307,29 @@ impl SlaMonitor {
         self.results.read().unwrap().clone()
     }
 
+    /// Record an on-demand throughput test run over a path
+    pub fn record_verification(&self, path_id: &PathId, verification: ThroughputVerification) {
+        debug!("Throughput verification {} on path {}: {:.1} Mbps",
+               verification.run_id, path_id, verification.mbps);
+
+        let mut verifications = self.verifications.write().unwrap();
+        let runs = verifications.entry(*path_id).or_default();
+        runs.push(verification);
+        if runs.len() > MAX_VERIFICATIONS {
+            runs.remove(0);
+        }
+    }
+
+    /// Throughput test runs recorded for a path, oldest first
+    pub fn get_verifications(&self, path_id: &PathId) -> Vec<ThroughputVerification> {
+        self.verifications.read().unwrap().get(path_id).cloned().unwrap_or_default()
+    }
+
+    /// Most recent throughput test run for a path
+    pub fn latest_verification(&self, path_id: &PathId) -> Option<ThroughputVerification> {
+        self.verifications.read().unwrap().get(path_id)?.last().cloned()
+    }
+
     /// Select best path based on SLA requirements
     pub fn select_best_path(
         &self,
@@ -355,6 +400,29 @@ mod tests {
         assert!(monitor.configs.read().unwrap().contains_key(&path_id));
     }
 
+    #[test]
+    fn test_sla_verification_history() {
+        let monitor = SlaMonitor::new();
+        let path_id = PathId::new(7);
+        assert!(monitor.latest_verification(&path_id).is_none());
+
+        let runs: Vec<Uuid> = (0..MAX_VERIFICATIONS + 2).map(|_| Uuid::new_v4()).collect();
+        for (i, run_id) in runs.iter().enumerate() {
+            monitor.record_verification(&path_id, ThroughputVerification {
+                run_id: *run_id,
+                mbps: 900.0 + i as f64,
+                packet_loss_pct: None,
+                timestamp: SystemTime::now(),
+            });
+        }
+
+        let recorded = monitor.get_verifications(&path_id);
+        assert_eq!(recorded.len(), MAX_VERIFICATIONS);
+        assert_eq!(recorded[0].run_id, runs[2]);
+        assert_eq!(monitor.latest_verification(&path_id).unwrap().run_id, runs[MAX_VERIFICATIONS + 1]);
+        assert!(monitor.get_verifications(&PathId::new(8)).is_empty());
+    }
+
     #[test]
     fn test_sla_measurement_compliance() {
         let measurement = SlaMeasurement {
@@ -60,6 +60,9 @@ pub struct FlowStats {
     /// Byte count
     pub bytes: u64,
 
+    /// First seen timestamp
+    pub first_seen: SystemTime,
+
     /// Last seen timestamp
     pub last_seen: SystemTime,
 }
@@ -115,6 +118,7 @@ impl TrafficStatsCollector {
                 policy_id,
                 packets: 0,
                 bytes: 0,
+                first_seen: now,
                 last_seen: now,
             });
 
@@ -210,6 +214,11 @@ impl TrafficStatsCollector {
         flows.clear();
     }
 
+    /// Get a snapshot of all active flows
+    pub async fn get_active_flows(&self) -> Vec<FlowStats> {
+        self.active_flows.read().await.values().cloned().collect()
+    }
+
     /// Get total active flows
     pub async fn get_total_active_flows(&self) -> u64 {
         self.active_flows.read().await.len() as u64
@@ -209,7 +209,10 @@ impl SecretManager {
     }
 
     /// Validate secret based on its type
-    fn validate_secret(&self, secret: &SecretString, secret_type: SecretType) -> Result<()> {
+    ///
+    /// [`Self::store_secret`] applies this itself; it is exposed so callers
+    /// can check a batch of secrets before storing any of them.
+    pub fn validate_secret(&self, secret: &SecretString, secret_type: SecretType) -> Result<()> {
         let value = secret.expose_secret();
 
         match secret_type {
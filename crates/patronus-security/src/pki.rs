@@ -1,20 +1,44 @@
 //! PKI (Public Key Infrastructure)
+//!
+//! Issued certificates carry a random serial and a SHA-256 fingerprint of
+//! their DER encoding, which callers can use as a stable identity. Revoked
+//! serials are kept in memory and published through [`CertificateAuthority::crl_pem`].
 
 use anyhow::Result;
-use rcgen::{Certificate as RcgenCertificate, CertificateParams, DnType};
+use rand::RngCore;
+use rcgen::{
+    Certificate as RcgenCertificate, CertificateParams, CertificateRevocationList,
+    CertificateRevocationListParams, DnType, ExtendedKeyUsagePurpose, KeyIdMethod,
+    RevokedCertParams, SerialNumber,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
 
 pub struct CertificateAuthority {
     ca_cert: RcgenCertificate,
+    ca_pem: String,
+    /// Revoked serials (hex) and when they were revoked
+    revoked: Mutex<HashMap<String, OffsetDateTime>>,
+    crl_number: Mutex<u64>,
 }
 
 pub struct Certificate {
     pub cert_pem: String,
     pub key_pem: String,
+    /// Serial number as lowercase hex
+    pub serial: String,
+    /// SHA-256 of the DER certificate as lowercase hex
+    pub fingerprint: String,
+    pub not_after: SystemTime,
 }
 
 impl CertificateAuthority {
     pub fn new() -> Result<Self> {
         let mut params = CertificateParams::new(vec!["Patronus CA".to_string()]);
+        params.distinguished_name.push(DnType::CommonName, "Patronus CA");
         params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
         params.key_usages = vec![
             rcgen::KeyUsagePurpose::DigitalSignature,
@@ -23,31 +47,126 @@ impl CertificateAuthority {
         ];
 
         let ca_cert = RcgenCertificate::from_params(params)?;
+        let ca_pem = ca_cert.serialize_pem()?;
 
-        Ok(Self { ca_cert })
+        Ok(Self {
+            ca_cert,
+            ca_pem,
+            revoked: Mutex::new(HashMap::new()),
+            crl_number: Mutex::new(0),
+        })
+    }
+
+    /// The CA certificate clients and servers should trust
+    pub fn ca_cert_pem(&self) -> &str {
+        &self.ca_pem
     }
 
     pub fn issue_certificate(&self, common_name: &str, validity_days: u32) -> Result<Certificate> {
+        self.issue(common_name, validity_days, Vec::new())
+    }
+
+    /// Issue a certificate usable only for TLS client authentication
+    pub fn issue_client_certificate(&self, common_name: &str, validity_days: u32) -> Result<Certificate> {
+        self.issue(common_name, validity_days, vec![ExtendedKeyUsagePurpose::ClientAuth])
+    }
+
+    fn issue(
+        &self,
+        common_name: &str,
+        validity_days: u32,
+        extended_key_usages: Vec<ExtendedKeyUsagePurpose>,
+    ) -> Result<Certificate> {
         let mut params = CertificateParams::new(vec![common_name.to_string()]);
         params.distinguished_name.push(DnType::CommonName, common_name);
+        params.extended_key_usages = extended_key_usages;
+
+        let now = OffsetDateTime::now_utc();
+        let not_after = now + time::Duration::days(i64::from(validity_days));
+        params.not_before = now;
+        params.not_after = not_after;
+
+        // Positive 127-bit serial, as RFC 5280 asks for at most 20 octets
+        let mut serial = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut serial);
+        serial[0] &= 0x7f;
+        params.serial_number = Some(SerialNumber::from(serial.to_vec()));
 
         let cert = RcgenCertificate::from_params(params)?;
         let cert_pem = cert.serialize_pem_with_signer(&self.ca_cert)?;
         let key_pem = cert.serialize_private_key_pem();
+        let fingerprint = fingerprint(&cert_pem)?;
 
         tracing::info!("Issued certificate for {} (valid for {} days)", common_name, validity_days);
 
         Ok(Certificate {
             cert_pem,
             key_pem,
+            serial: hex::encode(serial),
+            fingerprint,
+            not_after: SystemTime::UNIX_EPOCH + Duration::from_secs(not_after.unix_timestamp().max(0) as u64),
         })
     }
 
+    /// Add `serial` (hex, colons allowed) to the revocation list
     pub fn revoke_certificate(&self, serial: &str) -> Result<()> {
+        let serial = normalize_serial(serial)?;
         tracing::info!("Revoking certificate {}", serial);
-        // In production: add to CRL
+        self.revoked.lock().unwrap().entry(serial).or_insert_with(OffsetDateTime::now_utc);
         Ok(())
     }
+
+    pub fn is_revoked(&self, serial: &str) -> bool {
+        normalize_serial(serial).is_ok_and(|s| self.revoked.lock().unwrap().contains_key(&s))
+    }
+
+    /// Sign a CRL of every revoked serial, valid for `next_update`
+    pub fn crl_pem(&self, next_update: Duration) -> Result<String> {
+        let revoked_certs = self
+            .revoked
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(serial, revoked_at)| {
+                Ok(RevokedCertParams {
+                    serial_number: SerialNumber::from(hex::decode(serial)?),
+                    revocation_time: *revoked_at,
+                    reason_code: None,
+                    invalidity_date: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let crl_number = {
+            let mut number = self.crl_number.lock().unwrap();
+            *number += 1;
+            *number
+        };
+        let now = OffsetDateTime::now_utc();
+        let crl = CertificateRevocationList::from_params(CertificateRevocationListParams {
+            this_update: now,
+            next_update: now + time::Duration::try_from(next_update)?,
+            crl_number: SerialNumber::from(crl_number),
+            issuing_distribution_point: None,
+            revoked_certs,
+            alg: &rcgen::PKCS_ECDSA_P256_SHA256,
+            key_identifier_method: KeyIdMethod::Sha256,
+        })?;
+        Ok(crl.serialize_pem_with_signer(&self.ca_cert)?)
+    }
+}
+
+/// SHA-256 fingerprint (lowercase hex) of a PEM certificate
+pub fn fingerprint(cert_pem: &str) -> Result<String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(cert_pem.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Invalid certificate PEM: {}", e))?;
+    Ok(hex::encode(Sha256::digest(&pem.contents)))
+}
+
+fn normalize_serial(serial: &str) -> Result<String> {
+    let serial = serial.replace(':', "").to_lowercase();
+    hex::decode(&serial).map_err(|_| anyhow::anyhow!("Invalid certificate serial {}", serial))?;
+    Ok(serial)
 }
 
 impl Default for CertificateAuthority {
@@ -59,6 +178,7 @@ impl Default for CertificateAuthority {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use x509_parser::prelude::*;
 
     #[test]
     fn test_ca_creation() {
@@ -67,4 +187,29 @@ mod tests {
         assert!(!cert.cert_pem.is_empty());
         assert!(!cert.key_pem.is_empty());
     }
+
+    #[test]
+    fn test_client_certificate_and_revocation() {
+        let ca = CertificateAuthority::new().unwrap();
+        let cert = ca.issue_client_certificate("sensor-1", 30).unwrap();
+        assert_eq!(cert.fingerprint, fingerprint(&cert.cert_pem).unwrap());
+
+        let (_, pem) = parse_x509_pem(cert.cert_pem.as_bytes()).unwrap();
+        let x509 = pem.parse_x509().unwrap();
+        assert_eq!(hex::encode(x509.raw_serial()), cert.serial);
+        let eku = x509.extended_key_usage().unwrap().unwrap().value;
+        assert!(eku.client_auth && !eku.server_auth);
+        let days = (x509.validity().not_after.timestamp() - x509.validity().not_before.timestamp()) / 86_400;
+        assert_eq!(days, 30);
+
+        assert!(!ca.is_revoked(&cert.serial));
+        ca.revoke_certificate(&cert.serial.to_uppercase()).unwrap();
+        assert!(ca.is_revoked(&cert.serial));
+
+        let crl_pem = ca.crl_pem(Duration::from_secs(3600)).unwrap();
+        let (_, pem) = parse_x509_pem(crl_pem.as_bytes()).unwrap();
+        let (_, crl) = parse_x509_crl(&pem.contents).unwrap();
+        let revoked: Vec<_> = crl.iter_revoked_certificates().map(|r| hex::encode(r.raw_serial())).collect();
+        assert_eq!(revoked, vec![cert.serial.clone()]);
+    }
 }
@@ -0,0 +1,200 @@
+//! Issue Correlation
+//!
+//! One fault usually shows up as several issues: an interface going down
+//! takes the tunnels and routes over it down and breaches their SLAs. The
+//! [`IssueCorrelator`] follows declared dependencies from each affected
+//! resource up to its root and groups issues with the same root that were
+//! detected close together, so only the root cause is remediated.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::detector::{Issue, IssueSeverity};
+
+/// Issues that share a root resource and time window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueGroup {
+    pub id: Uuid,
+    /// The most upstream resource the issues depend on
+    pub root_resource_id: String,
+    /// Ordered from closest to the root to furthest, then by severity
+    pub issues: Vec<Issue>,
+    /// Highest severity of any issue in the group
+    pub severity: IssueSeverity,
+    pub first_detected: DateTime<Utc>,
+    pub last_detected: DateTime<Utc>,
+}
+
+impl IssueGroup {
+    /// A group of one issue, with no symptoms
+    pub fn single(issue: Issue) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            root_resource_id: issue.affected_resource_id.clone(),
+            severity: issue.severity.clone(),
+            first_detected: issue.detected_at,
+            last_detected: issue.detected_at,
+            issues: vec![issue],
+        }
+    }
+
+    /// The issue to remediate: the one closest to the root, the most
+    /// severe of those if several
+    pub fn root_cause(&self) -> &Issue {
+        &self.issues[0]
+    }
+
+    /// Issues expected to clear once the root cause is fixed
+    pub fn symptoms(&self) -> &[Issue] {
+        &self.issues[1..]
+    }
+}
+
+/// Groups issues by the resource they ultimately depend on
+#[derive(Debug, Clone)]
+pub struct IssueCorrelator {
+    /// resource -> the resource it depends on
+    upstream: HashMap<String, String>,
+    window: Duration,
+}
+
+impl IssueCorrelator {
+    /// Correlate issues detected within `window` of each other
+    pub fn new(window: std::time::Duration) -> Self {
+        Self {
+            upstream: HashMap::new(),
+            window: Duration::from_std(window).unwrap_or(Duration::MAX),
+        }
+    }
+
+    /// Declare that `resource` fails when `upstream` does, e.g. a tunnel
+    /// on an interface
+    pub fn add_dependency(&mut self, resource: impl Into<String>, upstream: impl Into<String>) {
+        self.upstream.insert(resource.into(), upstream.into());
+    }
+
+    /// The chain from `resource` to its root, starting with `resource`
+    ///
+    /// A dependency cycle ends the chain where it would repeat.
+    pub fn path_to_root(&self, resource: &str) -> Vec<String> {
+        let mut path = vec![resource.to_string()];
+        while let Some(next) = self.upstream.get(path.last().expect("path is never empty")) {
+            if path.contains(next) {
+                break;
+            }
+            path.push(next.clone());
+        }
+        path
+    }
+
+    pub fn root_of(&self, resource: &str) -> String {
+        self.path_to_root(resource).pop().expect("path is never empty")
+    }
+
+    /// Group `issues` by root resource, starting a new group when an issue
+    /// comes more than the window after the previous one with that root
+    ///
+    /// Groups are ordered by first detection.
+    pub fn correlate(&self, mut issues: Vec<Issue>) -> Vec<IssueGroup> {
+        issues.sort_by_key(|i| i.detected_at);
+
+        let mut groups: Vec<(IssueGroup, Vec<usize>)> = Vec::new();
+        let mut open: HashMap<String, usize> = HashMap::new();
+        for issue in issues {
+            let path = self.path_to_root(&issue.affected_resource_id);
+            // Hops from the issue's resource up to the root
+            let depth = path.len() - 1;
+            let root = path.into_iter().last().expect("path is never empty");
+
+            let current = open.get(&root)
+                .copied()
+                .filter(|g| issue.detected_at - groups[*g].0.last_detected <= self.window);
+            match current {
+                Some(index) => {
+                    let (group, depths) = &mut groups[index];
+                    if issue.severity.rank() > group.severity.rank() {
+                        group.severity = issue.severity.clone();
+                    }
+                    group.last_detected = issue.detected_at;
+                    group.issues.push(issue);
+                    depths.push(depth);
+                }
+                None => {
+                    open.insert(root.clone(), groups.len());
+                    let group = IssueGroup { root_resource_id: root, ..IssueGroup::single(issue) };
+                    groups.push((group, vec![depth]));
+                }
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(mut group, depths)| {
+                // Fewest hops from the root first
+                let mut ordered: Vec<(usize, Issue)> = depths.into_iter().zip(group.issues).collect();
+                ordered.sort_by(|(da, a), (db, b)| da.cmp(db).then(b.severity.rank().cmp(&a.severity.rank())));
+                group.issues = ordered.into_iter().map(|(_, issue)| issue).collect();
+                group
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::IssueType;
+
+    fn issue(issue_type: IssueType, severity: IssueSeverity, resource: &str, at: DateTime<Utc>) -> Issue {
+        let mut issue = Issue::new(issue_type, severity, format!("{} degraded", resource), resource);
+        issue.detected_at = at;
+        issue
+    }
+
+    #[test]
+    fn test_downstream_issues_collapse_into_one_group() {
+        let mut correlator = IssueCorrelator::new(std::time::Duration::from_secs(60));
+        correlator.add_dependency("tunnel-hq", "eth0");
+        correlator.add_dependency("route-10.1.0.0/16", "tunnel-hq");
+        correlator.add_dependency("sla-voice", "route-10.1.0.0/16");
+        correlator.add_dependency("tunnel-dr", "eth1");
+        assert_eq!(correlator.path_to_root("sla-voice"), vec!["sla-voice", "route-10.1.0.0/16", "tunnel-hq", "eth0"]);
+
+        let t0 = Utc::now();
+        let issues = vec![
+            issue(IssueType::HighLatency, IssueSeverity::Medium, "sla-voice", t0 + Duration::seconds(20)),
+            issue(IssueType::PacketLoss, IssueSeverity::High, "route-10.1.0.0/16", t0 + Duration::seconds(5)),
+            issue(IssueType::TunnelDown, IssueSeverity::Critical, "tunnel-hq", t0),
+            issue(IssueType::HighLatency, IssueSeverity::Medium, "tunnel-dr", t0 + Duration::seconds(10)),
+            // Same root, but long after the others settled
+            issue(IssueType::PacketLoss, IssueSeverity::Medium, "tunnel-hq", t0 + Duration::seconds(300)),
+        ];
+
+        let groups = correlator.correlate(issues);
+        assert_eq!(groups.len(), 3);
+
+        let outage = &groups[0];
+        assert_eq!(outage.root_resource_id, "eth0");
+        assert_eq!(outage.issues.len(), 3);
+        assert_eq!(outage.severity, IssueSeverity::Critical);
+        assert_eq!(outage.root_cause().affected_resource_id, "tunnel-hq");
+        assert_eq!(outage.symptoms()[0].affected_resource_id, "route-10.1.0.0/16");
+        assert_eq!(outage.symptoms()[1].affected_resource_id, "sla-voice");
+        assert_eq!((outage.first_detected, outage.last_detected), (t0, t0 + Duration::seconds(20)));
+
+        assert_eq!(groups[1].root_resource_id, "eth1");
+        assert_eq!(groups[2].root_resource_id, "eth0");
+        assert_eq!(groups[2].issues.len(), 1);
+    }
+
+    #[test]
+    fn test_dependency_cycle_terminates() {
+        let mut correlator = IssueCorrelator::new(std::time::Duration::from_secs(60));
+        correlator.add_dependency("a", "b");
+        correlator.add_dependency("b", "a");
+        assert_eq!(correlator.root_of("a"), "b");
+        assert_eq!(correlator.root_of("unrelated"), "unrelated");
+    }
+}
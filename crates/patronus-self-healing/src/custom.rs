@@ -0,0 +1,87 @@
+//! Custom Remediation Executors
+//!
+//! Operators register executors for issues the built-in actions don't
+//! cover, keyed by [`IssueType`]. The [`RemediationEngine`] tries the
+//! executors registered for an issue's type, highest priority first, until
+//! one succeeds; they replace the built-in actions for that type.
+//!
+//! [`RemediationEngine`]: crate::RemediationEngine
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::detector::{Issue, IssueType};
+
+/// Remediation for a particular kind of issue
+#[async_trait]
+pub trait CustomExecutor: Send + Sync {
+    /// Unique name, recorded in attempts as `RemediationAction::Custom`
+    fn name(&self) -> &str;
+
+    async fn execute(&self, issue: &Issue) -> Result<()>;
+
+    /// Undo `execute` after it made the issue worse; `None` if it cannot
+    /// be undone
+    async fn revert(&self, _issue: &Issue) -> Option<Result<()>> {
+        None
+    }
+}
+
+struct Registration {
+    priority: u32,
+    executor: Arc<dyn CustomExecutor>,
+}
+
+/// Custom executors by issue type
+#[derive(Default)]
+pub struct ExecutorRegistry {
+    by_type: HashMap<IssueType, Vec<Registration>>,
+}
+
+impl ExecutorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an executor for `issue_type`, replacing one of the same name
+    ///
+    /// Higher priorities are tried first; equal priorities in registration
+    /// order.
+    pub fn register(&mut self, issue_type: IssueType, priority: u32, executor: Arc<dyn CustomExecutor>) {
+        let executors = self.by_type.entry(issue_type.clone()).or_default();
+        executors.retain(|r| r.executor.name() != executor.name());
+        let position = executors.iter().position(|r| r.priority < priority).unwrap_or(executors.len());
+        tracing::info!(
+            "Registered remediation executor {} for {:?} at priority {}",
+            executor.name(), issue_type, priority
+        );
+        executors.insert(position, Registration { priority, executor });
+    }
+
+    pub fn unregister(&mut self, issue_type: &IssueType, name: &str) -> bool {
+        let Some(executors) = self.by_type.get_mut(issue_type) else {
+            return false;
+        };
+        let before = executors.len();
+        executors.retain(|r| r.executor.name() != name);
+        before != executors.len()
+    }
+
+    /// Executors for `issue_type` in the order they are tried
+    pub fn executors_for(&self, issue_type: &IssueType) -> Vec<Arc<dyn CustomExecutor>> {
+        self.by_type
+            .get(issue_type)
+            .map(|executors| executors.iter().map(|r| Arc::clone(&r.executor)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, issue_type: &IssueType, name: &str) -> Option<Arc<dyn CustomExecutor>> {
+        self.by_type
+            .get(issue_type)?
+            .iter()
+            .find(|r| r.executor.name() == name)
+            .map(|r| Arc::clone(&r.executor))
+    }
+}
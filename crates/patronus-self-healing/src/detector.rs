@@ -15,6 +15,8 @@ pub enum IssueType {
     CapacityExhausted,
     SecurityThreat,
     ConfigurationError,
+    /// An operator-defined issue, remediated by custom executors
+    Custom(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -25,6 +27,18 @@ pub enum IssueSeverity {
     Low,
 }
 
+impl IssueSeverity {
+    /// Higher is more severe
+    pub fn rank(&self) -> u8 {
+        match self {
+            IssueSeverity::Critical => 3,
+            IssueSeverity::High => 2,
+            IssueSeverity::Medium => 1,
+            IssueSeverity::Low => 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Issue {
     pub id: Uuid,
@@ -6,8 +6,11 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::time::{interval, Duration};
 use anyhow::Result;
+use crate::correlation::{IssueCorrelator, IssueGroup};
 use crate::detector::IssueDetector;
+use crate::detector::Issue;
 use crate::remediation::{RemediationEngine, RemediationExecutor, RemediationAttempt};
+use crate::throttle::{Admission, BreakerState, Escalation, Throttle};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealingStats {
@@ -15,6 +18,25 @@ pub struct HealingStats {
     pub remediations_attempted: u64,
     pub remediations_succeeded: u64,
     pub remediations_failed: u64,
+    /// Remediations undone because they made things worse; also counted
+    /// as failed
+    #[serde(default)]
+    pub remediations_rolled_back: u64,
+    /// Issues left alone as symptoms of a correlated root cause
+    #[serde(default)]
+    pub issues_correlated: u64,
+    /// Remediations deferred by a rate limit
+    #[serde(default)]
+    pub remediations_throttled: u64,
+    /// Remediations skipped while the circuit breaker was open
+    #[serde(default)]
+    pub remediations_paused: u64,
+    /// Times a low success rate paused automated remediation
+    #[serde(default)]
+    pub breaker_trips: u64,
+    /// Issues handed to a human while remediation was paused
+    #[serde(default)]
+    pub issues_escalated: u64,
     pub last_run: Option<String>,
 }
 
@@ -25,6 +47,12 @@ impl Default for HealingStats {
             remediations_attempted: 0,
             remediations_succeeded: 0,
             remediations_failed: 0,
+            remediations_rolled_back: 0,
+            issues_correlated: 0,
+            remediations_throttled: 0,
+            remediations_paused: 0,
+            breaker_trips: 0,
+            issues_escalated: 0,
             last_run: None,
         }
     }
@@ -36,6 +64,9 @@ pub struct HealingLoop<E: RemediationExecutor> {
     stats: Arc<RwLock<HealingStats>>,
     interval_secs: u64,
     enabled: Arc<RwLock<bool>>,
+    correlator: Option<IssueCorrelator>,
+    throttle: Option<RwLock<Throttle>>,
+    escalation: Option<Arc<dyn Escalation>>,
 }
 
 impl<E: RemediationExecutor + 'static> HealingLoop<E> {
@@ -50,6 +81,42 @@ impl<E: RemediationExecutor + 'static> HealingLoop<E> {
             stats: Arc::new(RwLock::new(HealingStats::default())),
             interval_secs,
             enabled: Arc::new(RwLock::new(true)),
+            correlator: None,
+            throttle: None,
+            escalation: None,
+        }
+    }
+
+    /// Group the issues of each pass and remediate only their root causes
+    pub fn with_correlator(mut self, correlator: IssueCorrelator) -> Self {
+        self.correlator = Some(correlator);
+        self
+    }
+
+    /// Rate-limit remediations and pause them when too many fail
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = Some(RwLock::new(throttle));
+        self
+    }
+
+    /// Where issues go while automated remediation is paused
+    pub fn with_escalation(mut self, escalation: impl Escalation + 'static) -> Self {
+        self.escalation = Some(Arc::new(escalation));
+        self
+    }
+
+    pub async fn breaker_state(&self) -> BreakerState {
+        match &self.throttle {
+            Some(throttle) => throttle.read().await.breaker_state(std::time::Instant::now()),
+            None => BreakerState::Closed,
+        }
+    }
+
+    /// Resume automated remediation paused by the circuit breaker
+    pub async fn resume_remediation(&self) {
+        if let Some(throttle) = &self.throttle {
+            throttle.write().await.close();
+            tracing::info!("Automated remediation resumed");
         }
     }
 
@@ -84,35 +151,84 @@ impl<E: RemediationExecutor + 'static> HealingLoop<E> {
         let detector = self.detector.read().await;
         let mut engine = self.engine.write().await;
         let mut stats = self.stats.write().await;
+        let mut throttle = match &self.throttle {
+            Some(throttle) => Some(throttle.write().await),
+            None => None,
+        };
 
         // Detect issues across all resources
+        let mut issues = Vec::new();
         for (resource_id, metrics) in resource_metrics {
-            let issues = detector.detect_tunnel_issues(resource_id, metrics);
-
-            for issue in issues {
+            for issue in detector.detect_tunnel_issues(resource_id, metrics) {
                 stats.issues_detected += 1;
                 tracing::warn!("Issue detected: {:?} on {}", issue.issue_type, resource_id);
+                issues.push(issue);
+            }
+        }
 
-                // Attempt remediation
-                if issue.auto_remediable {
-                    stats.remediations_attempted += 1;
-
-                    match engine.remediate(&issue).await {
-                        Ok(attempt) => {
-                            if attempt.status == crate::remediation::RemediationStatus::Succeeded {
-                                stats.remediations_succeeded += 1;
-                            } else {
-                                stats.remediations_failed += 1;
-                            }
-                            all_attempts.push(attempt);
-                        }
-                        Err(e) => {
-                            stats.remediations_failed += 1;
-                            tracing::error!("Remediation error: {}", e);
+        let groups = match &self.correlator {
+            Some(correlator) => correlator.correlate(issues),
+            None => issues.into_iter().map(IssueGroup::single).collect(),
+        };
+        for group in groups {
+            // Attempt remediation of the root cause only
+            let root = group.root_cause();
+            if !root.auto_remediable {
+                continue;
+            }
+            stats.issues_correlated += group.symptoms().len() as u64;
+
+            if let Some(throttle) = throttle.as_mut() {
+                match throttle.admit(&root.issue_type, std::time::Instant::now()) {
+                    Admission::Allowed => {}
+                    Admission::Throttled => {
+                        stats.remediations_throttled += 1;
+                        tracing::debug!("Remediation of {:?} on {} deferred by rate limit", root.issue_type, root.affected_resource_id);
+                        continue;
+                    }
+                    Admission::Paused => {
+                        stats.remediations_paused += 1;
+                        if throttle.needs_escalation(root) {
+                            let reason = "Automated remediation is paused after repeated failures";
+                            self.escalate(root, reason, &mut stats).await;
                         }
+                        continue;
                     }
                 }
             }
+            stats.remediations_attempted += 1;
+
+            let succeeded = match engine.remediate_group(&group).await {
+                Ok(attempt) => {
+                    let succeeded = attempt.status == crate::remediation::RemediationStatus::Succeeded;
+                    if succeeded {
+                        stats.remediations_succeeded += 1;
+                    } else {
+                        stats.remediations_failed += 1;
+                    }
+                    if attempt.status == crate::remediation::RemediationStatus::RolledBack {
+                        stats.remediations_rolled_back += 1;
+                    }
+                    all_attempts.push(attempt);
+                    succeeded
+                }
+                Err(e) => {
+                    stats.remediations_failed += 1;
+                    tracing::error!("Remediation error: {}", e);
+                    false
+                }
+            };
+
+            if let Some(throttle) = throttle.as_mut() {
+                if throttle.record_outcome(succeeded, std::time::Instant::now()) {
+                    stats.breaker_trips += 1;
+                    let rate = throttle.success_rate().unwrap_or_default() * 100.0;
+                    tracing::error!("Automated remediation paused: success rate dropped to {:.0}%", rate);
+                    throttle.needs_escalation(root);
+                    let reason = format!("Remediation failed and paused automated remediation ({:.0}% success)", rate);
+                    self.escalate(root, &reason, &mut stats).await;
+                }
+            }
         }
 
         stats.last_run = Some(chrono::Utc::now().to_rfc3339());
@@ -120,6 +236,17 @@ impl<E: RemediationExecutor + 'static> HealingLoop<E> {
         Ok(all_attempts)
     }
 
+    async fn escalate(&self, issue: &Issue, reason: &str, stats: &mut HealingStats) {
+        let Some(escalation) = &self.escalation else {
+            tracing::error!("{:?} on {} needs manual attention: {}", issue.issue_type, issue.affected_resource_id, reason);
+            return;
+        };
+        match escalation.escalate(issue, reason).await {
+            Ok(()) => stats.issues_escalated += 1,
+            Err(e) => tracing::error!("Failed to escalate issue {}: {}", issue.id, e),
+        }
+    }
+
     pub async fn run_once(&self, resource_metrics: &HashMap<String, HashMap<String, f64>>) -> Result<Vec<RemediationAttempt>> {
         self.detect_and_remediate(resource_metrics).await
     }
@@ -156,7 +283,7 @@ impl<E: RemediationExecutor + 'static> HealingLoop<E> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::detector::IssueDetector;
+    use crate::detector::{IssueDetector, IssueType};
     use crate::remediation::{RemediationEngine, RemediationExecutor};
     use async_trait::async_trait;
 
@@ -254,6 +381,117 @@ mod tests {
         assert_eq!(stats.issues_detected, 0);
     }
 
+    #[tokio::test]
+    async fn test_correlated_issues_remediated_once() {
+        let mut correlator = IssueCorrelator::new(std::time::Duration::from_secs(60));
+        for tunnel in ["tunnel-1", "tunnel-2", "tunnel-3"] {
+            correlator.add_dependency(tunnel, "eth0");
+        }
+        let loop_instance = HealingLoop::new(IssueDetector::new(), RemediationEngine::new(MockExecutor), 60)
+            .with_correlator(correlator);
+
+        let mut resource_metrics = HashMap::new();
+        for tunnel in ["tunnel-1", "tunnel-2", "tunnel-3"] {
+            resource_metrics.insert(tunnel.to_string(), HashMap::from([("state".to_string(), 0.0)]));
+        }
+
+        let attempts = loop_instance.detect_and_remediate(&resource_metrics).await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert!(attempts[0].group_id.is_some());
+
+        let stats = loop_instance.get_stats().await;
+        assert_eq!(stats.issues_detected, 3);
+        assert_eq!(stats.remediations_attempted, 1);
+        assert_eq!(stats.issues_correlated, 2);
+    }
+
+    fn tunnels_down(count: usize) -> HashMap<String, HashMap<String, f64>> {
+        (0..count)
+            .map(|i| (format!("tunnel-{}", i), HashMap::from([("state".to_string(), 0.0)])))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_per_type_limit_defers_remediation() {
+        use crate::throttle::RateLimit;
+
+        let throttle = Throttle::new()
+            .with_type_limit(IssueType::TunnelDown, RateLimit::new(2, Duration::from_secs(3600)));
+        let loop_instance = HealingLoop::new(IssueDetector::new(), RemediationEngine::new(MockExecutor), 60)
+            .with_throttle(throttle);
+
+        let mut resource_metrics = tunnels_down(4);
+        resource_metrics.insert("tunnel-slow".to_string(), HashMap::from([("latency_ms".to_string(), 150.0)]));
+
+        let attempts = loop_instance.detect_and_remediate(&resource_metrics).await.unwrap();
+        // Two tunnel restarts plus the unaffected latency issue
+        assert_eq!(attempts.len(), 3);
+        let stats = loop_instance.get_stats().await;
+        assert_eq!(stats.remediations_attempted, 3);
+        assert_eq!(stats.remediations_throttled, 2);
+
+        // Still within the hour: every tunnel restart is deferred
+        let attempts = loop_instance.detect_and_remediate(&tunnels_down(4)).await.unwrap();
+        assert!(attempts.is_empty());
+        assert_eq!(loop_instance.get_stats().await.remediations_throttled, 6);
+    }
+
+    struct FailingExecutor;
+
+    #[async_trait]
+    impl RemediationExecutor for FailingExecutor {
+        async fn restart_tunnel(&self, _: &str) -> Result<()> { anyhow::bail!("tunnel daemon unreachable") }
+        async fn switch_path(&self, _: &str, _: &str) -> Result<()> { Ok(()) }
+        async fn restart_bgp_session(&self, _: &str) -> Result<()> { Ok(()) }
+        async fn scale_bandwidth(&self, _: &str, _: u64) -> Result<()> { Ok(()) }
+        async fn reroute_traffic(&self, _: &str, _: &str) -> Result<()> { Ok(()) }
+        async fn rollback_config(&self, _: &str) -> Result<()> { Ok(()) }
+        async fn block_traffic(&self, _: &str) -> Result<()> { Ok(()) }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingEscalation(Arc<std::sync::Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Escalation for RecordingEscalation {
+        async fn escalate(&self, issue: &Issue, _reason: &str) -> Result<()> {
+            self.0.lock().unwrap().push(issue.affected_resource_id.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_low_success_rate_trips_breaker() {
+        use crate::throttle::CircuitBreaker;
+
+        let escalation = RecordingEscalation::default();
+        let breaker = CircuitBreaker { min_attempts: 3, ..CircuitBreaker::default() };
+        let loop_instance = HealingLoop::new(IssueDetector::new(), RemediationEngine::new(FailingExecutor), 60)
+            .with_throttle(Throttle::new().with_circuit_breaker(breaker))
+            .with_escalation(escalation.clone());
+
+        let attempts = loop_instance.detect_and_remediate(&tunnels_down(5)).await.unwrap();
+        // The third failure trips the breaker; the rest go to a human
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(loop_instance.breaker_state().await, BreakerState::Open);
+        let stats = loop_instance.get_stats().await;
+        assert_eq!(stats.remediations_failed, 3);
+        assert_eq!(stats.breaker_trips, 1);
+        assert_eq!(stats.remediations_paused, 2);
+        assert_eq!(stats.issues_escalated, 3);
+
+        // Each issue is escalated once per pause
+        assert!(loop_instance.detect_and_remediate(&tunnels_down(5)).await.unwrap().is_empty());
+        let stats = loop_instance.get_stats().await;
+        assert_eq!(stats.remediations_paused, 7);
+        assert_eq!(stats.issues_escalated, 5);
+        assert_eq!(escalation.0.lock().unwrap().len(), 5);
+
+        loop_instance.resume_remediation().await;
+        assert_eq!(loop_instance.breaker_state().await, BreakerState::Closed);
+        assert_eq!(loop_instance.detect_and_remediate(&tunnels_down(1)).await.unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_multiple_issues() {
         let detector = IssueDetector::new();
@@ -2,10 +2,19 @@
 //!
 //! Automatic detection and remediation of network issues
 
+pub mod correlation;
+pub mod custom;
 pub mod detector;
 pub mod remediation;
 pub mod healing_loop;
+pub mod throttle;
 
+pub use correlation::{IssueCorrelator, IssueGroup};
+pub use custom::{CustomExecutor, ExecutorRegistry};
 pub use detector::{Issue, IssueDetector, IssueType, IssueSeverity};
-pub use remediation::{RemediationAction, RemediationAttempt, RemediationEngine, RemediationExecutor, RemediationStatus};
+pub use remediation::{
+    RemediationAction, RemediationAttempt, RemediationEngine, RemediationExecutor, RemediationStatus,
+    RemediationVerifier,
+};
 pub use healing_loop::{HealingLoop, HealingStats};
+pub use throttle::{Admission, BreakerState, CircuitBreaker, Escalation, RateLimit, Throttle};
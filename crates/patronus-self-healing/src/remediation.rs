@@ -2,12 +2,19 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use anyhow::Result;
 use async_trait::async_trait;
+use crate::correlation::IssueGroup;
+use crate::custom::{CustomExecutor, ExecutorRegistry};
 use crate::detector::{Issue, IssueType};
 
+/// Relative rise of an issue's metric that counts as getting worse
+const WORSENING_TOLERANCE: f64 = 0.1;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum RemediationAction {
     RestartTunnel,
@@ -18,6 +25,29 @@ pub enum RemediationAction {
     RollbackConfiguration,
     BlockTraffic,
     NotifyOperator,
+    /// Undoes `SwitchToBackupPath`
+    RestorePrimaryPath,
+    /// Undoes `RerouteTraffic`
+    RestoreRoute,
+    /// Undoes `BlockTraffic`
+    UnblockTraffic,
+    /// Handled by the named custom executor
+    Custom(String),
+}
+
+impl RemediationAction {
+    /// The action that undoes this one, where there is an obvious one
+    ///
+    /// Restarts have nothing to undo, and a bandwidth change or
+    /// configuration rollback needs state only the executor has.
+    pub fn default_inverse(&self) -> Option<RemediationAction> {
+        match self {
+            RemediationAction::SwitchToBackupPath => Some(RemediationAction::RestorePrimaryPath),
+            RemediationAction::RerouteTraffic => Some(RemediationAction::RestoreRoute),
+            RemediationAction::BlockTraffic => Some(RemediationAction::UnblockTraffic),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -39,6 +69,15 @@ pub struct RemediationAttempt {
     pub completed_at: Option<DateTime<Utc>>,
     pub error: Option<String>,
     pub rollback_performed: bool,
+    /// Why the action was rolled back, or why rolling it back failed
+    #[serde(default)]
+    pub rollback_reason: Option<String>,
+    /// The inverse action that was run
+    #[serde(default)]
+    pub rollback_action: Option<RemediationAction>,
+    /// The correlated group whose root cause this remediated
+    #[serde(default)]
+    pub group_id: Option<Uuid>,
 }
 
 impl RemediationAttempt {
@@ -52,6 +91,9 @@ impl RemediationAttempt {
             completed_at: None,
             error: None,
             rollback_performed: false,
+            rollback_reason: None,
+            rollback_action: None,
+            group_id: None,
         }
     }
 
@@ -73,6 +115,7 @@ impl RemediationAttempt {
     pub fn rollback(&mut self) {
         self.status = RemediationStatus::RolledBack;
         self.rollback_performed = true;
+        self.completed_at = Some(Utc::now());
     }
 }
 
@@ -85,12 +128,66 @@ pub trait RemediationExecutor: Send + Sync {
     async fn reroute_traffic(&self, from: &str, to: &str) -> Result<()>;
     async fn rollback_config(&self, checkpoint_id: &str) -> Result<()>;
     async fn block_traffic(&self, source: &str) -> Result<()>;
+
+    async fn unblock_traffic(&self, source: &str) -> Result<()> {
+        anyhow::bail!("Unblocking {} is not supported by this executor", source)
+    }
+
+    /// The action that undoes `action`, run if the remediation makes things
+    /// worse; `None` if it cannot be undone
+    fn inverse(&self, action: &RemediationAction) -> Option<RemediationAction> {
+        action.default_inverse()
+    }
+}
+
+/// Re-measures a resource after it was remediated
+#[async_trait]
+pub trait RemediationVerifier: Send + Sync {
+    /// The issues present on `resource_id` now
+    async fn recheck(&self, resource_id: &str) -> Result<Vec<Issue>>;
+}
+
+/// Why `after` is worse than `before`, if it is
+///
+/// Worse means the same issue at a higher severity, one of its metrics up
+/// by more than 10% (every detector metric is higher-is-worse), or a
+/// different issue more severe than the original.
+pub fn worsening(before: &Issue, after: &[Issue]) -> Option<String> {
+    for issue in after {
+        if issue.issue_type != before.issue_type {
+            if issue.severity.rank() > before.severity.rank() {
+                return Some(format!(
+                    "New {:?} issue {:?} is more severe than the {:?} {:?} being fixed",
+                    issue.severity, issue.issue_type, before.severity, before.issue_type
+                ));
+            }
+            continue;
+        }
+        if issue.severity.rank() > before.severity.rank() {
+            return Some(format!(
+                "{:?} went from {:?} to {:?}",
+                issue.issue_type, before.severity, issue.severity
+            ));
+        }
+        let mut metrics: Vec<(&String, &f64)> = issue.metrics.iter().collect();
+        metrics.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, value) in metrics {
+            let Some(old) = before.metrics.get(name) else { continue };
+            if *value > old + old.abs() * WORSENING_TOLERANCE {
+                return Some(format!("{} rose from {} to {}", name, old, value));
+            }
+        }
+    }
+    None
 }
 
 pub struct RemediationEngine<E: RemediationExecutor> {
     executor: E,
     attempts: HashMap<Uuid, RemediationAttempt>,
     action_map: HashMap<IssueType, Vec<RemediationAction>>,
+    verifier: Option<Arc<dyn RemediationVerifier>>,
+    settle_time: Duration,
+    custom: ExecutorRegistry,
 }
 
 impl<E: RemediationExecutor> RemediationEngine<E> {
@@ -137,10 +234,40 @@ impl<E: RemediationExecutor> RemediationEngine<E> {
             executor,
             attempts: HashMap::new(),
             action_map,
+            verifier: None,
+            settle_time: Duration::from_secs(10),
+            custom: ExecutorRegistry::new(),
         }
     }
 
+    /// Handle `issue_type` with `executor`; see [`ExecutorRegistry::register`]
+    pub fn register_executor(&mut self, issue_type: IssueType, priority: u32, executor: Arc<dyn CustomExecutor>) {
+        self.custom.register(issue_type, priority, executor);
+    }
+
+    pub fn unregister_executor(&mut self, issue_type: &IssueType, name: &str) -> bool {
+        self.custom.unregister(issue_type, name)
+    }
+
+    /// Re-check the resource after each successful action and roll the
+    /// action back if the issue got worse
+    pub fn with_verifier(mut self, verifier: Arc<dyn RemediationVerifier>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// How long to let the network settle before re-checking
+    pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Actions for the issue, in the order they would be tried
     pub fn get_remediation_actions(&self, issue: &Issue) -> Vec<RemediationAction> {
+        let custom = self.custom.executors_for(&issue.issue_type);
+        if !custom.is_empty() {
+            return custom.iter().map(|e| RemediationAction::Custom(e.name().to_string())).collect();
+        }
         self.action_map
             .get(&issue.issue_type)
             .cloned()
@@ -152,6 +279,11 @@ impl<E: RemediationExecutor> RemediationEngine<E> {
             anyhow::bail!("Issue is not auto-remediable");
         }
 
+        let custom = self.custom.executors_for(&issue.issue_type);
+        if !custom.is_empty() {
+            return Ok(self.remediate_custom(issue, custom).await);
+        }
+
         let actions = self.get_remediation_actions(issue);
 
         if actions.is_empty() {
@@ -171,6 +303,7 @@ impl<E: RemediationExecutor> RemediationEngine<E> {
             Ok(_) => {
                 attempt.succeed();
                 tracing::info!("Remediation succeeded: {:?}", action);
+                self.verify(issue, &mut attempt).await;
             }
             Err(e) => {
                 attempt.fail(e.to_string());
@@ -183,6 +316,115 @@ impl<E: RemediationExecutor> RemediationEngine<E> {
         Ok(attempt)
     }
 
+    /// Remediate a correlated group once, through its root cause
+    ///
+    /// The symptoms are left alone on the expectation that they clear with
+    /// the root cause.
+    pub async fn remediate_group(&mut self, group: &IssueGroup) -> Result<RemediationAttempt> {
+        let root = group.root_cause();
+        if !group.symptoms().is_empty() {
+            tracing::info!(
+                "Remediating {:?} on {} for {} correlated issues under {}",
+                root.issue_type, root.affected_resource_id, group.issues.len(), group.root_resource_id
+            );
+        }
+        let mut attempt = self.remediate(root).await?;
+        if !group.symptoms().is_empty() {
+            attempt.group_id = Some(group.id);
+            for stored in self.attempts.values_mut().filter(|a| a.issue_id == root.id) {
+                stored.group_id = Some(group.id);
+            }
+        }
+        Ok(attempt)
+    }
+
+    /// Try custom executors in order until one fixes the issue
+    ///
+    /// Every executor tried gets its own attempt. Returns the successful
+    /// attempt, or the last one if none succeeded.
+    async fn remediate_custom(&mut self, issue: &Issue, executors: Vec<Arc<dyn CustomExecutor>>) -> RemediationAttempt {
+        let mut last = None;
+        for executor in executors {
+            let mut attempt = RemediationAttempt::new(issue.id, RemediationAction::Custom(executor.name().to_string()));
+            attempt.start();
+            tracing::info!("Starting custom remediation {} for issue {}", executor.name(), issue.id);
+
+            match executor.execute(issue).await {
+                Ok(()) => {
+                    attempt.succeed();
+                    self.verify(issue, &mut attempt).await;
+                }
+                Err(e) => {
+                    attempt.fail(e.to_string());
+                    tracing::warn!("Custom remediation {} failed: {}", executor.name(), e);
+                }
+            }
+            self.attempts.insert(attempt.id, attempt.clone());
+            if attempt.status == RemediationStatus::Succeeded {
+                return attempt;
+            }
+            last = Some(attempt);
+        }
+        last.expect("at least one executor was tried")
+    }
+
+    /// Re-check after a successful action and roll it back if the issue got
+    /// worse
+    ///
+    /// A failed re-check leaves the attempt succeeded. Without an inverse,
+    /// or if the inverse fails, the attempt fails instead.
+    async fn verify(&self, issue: &Issue, attempt: &mut RemediationAttempt) {
+        let Some(verifier) = &self.verifier else {
+            return;
+        };
+        tokio::time::sleep(self.settle_time).await;
+        let after = match verifier.recheck(&issue.affected_resource_id).await {
+            Ok(after) => after,
+            Err(e) => {
+                tracing::warn!("Could not re-check {} after {:?}: {}", issue.affected_resource_id, attempt.action, e);
+                return;
+            }
+        };
+        let Some(reason) = worsening(issue, &after) else {
+            return;
+        };
+
+        tracing::warn!(
+            "{:?} made {} worse ({}); rolling back",
+            attempt.action, issue.affected_resource_id, reason
+        );
+        let undo = match &attempt.action {
+            RemediationAction::Custom(name) => match self.custom.get(&issue.issue_type, name) {
+                Some(executor) => executor.revert(issue).await.map(|result| (attempt.action.clone(), result)),
+                None => None,
+            },
+            action => match self.executor.inverse(action) {
+                Some(inverse) => {
+                    let result = self.execute_action(&inverse, &issue.affected_resource_id).await;
+                    Some((inverse, result))
+                }
+                None => None,
+            },
+        };
+        let Some((inverse, result)) = undo else {
+            attempt.fail(format!("{}; {:?} cannot be rolled back", reason, attempt.action));
+            attempt.rollback_reason = Some(reason);
+            return;
+        };
+        match result {
+            Ok(()) => {
+                attempt.rollback();
+                tracing::info!("Rolled back {:?} with {:?}", attempt.action, inverse);
+            }
+            Err(e) => {
+                attempt.fail(format!("{}; rollback with {:?} failed: {}", reason, inverse, e));
+                tracing::error!("Rollback of {:?} with {:?} failed: {}", attempt.action, inverse, e);
+            }
+        }
+        attempt.rollback_reason = Some(reason);
+        attempt.rollback_action = Some(inverse);
+    }
+
     async fn execute_action(&self, action: &RemediationAction, resource_id: &str) -> Result<()> {
         match action {
             RemediationAction::RestartTunnel => {
@@ -213,6 +455,20 @@ impl<E: RemediationExecutor> RemediationEngine<E> {
                 tracing::warn!("Manual intervention required for {}", resource_id);
                 Ok(())
             }
+            RemediationAction::RestorePrimaryPath => {
+                let primary = format!("{}-primary", resource_id);
+                self.executor.switch_path(resource_id, &primary).await
+            }
+            RemediationAction::RestoreRoute => {
+                let alternate = format!("{}-alt", resource_id);
+                self.executor.reroute_traffic(&alternate, resource_id).await
+            }
+            RemediationAction::UnblockTraffic => {
+                self.executor.unblock_traffic(resource_id).await
+            }
+            RemediationAction::Custom(name) => {
+                anyhow::bail!("Custom action {} runs through its registered executor", name)
+            }
         }
     }
 
@@ -373,6 +629,164 @@ mod tests {
         assert!(result.is_err());
     }
 
+    /// Records every executor call
+    #[derive(Default)]
+    struct RecordingExecutor {
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl RecordingExecutor {
+        fn record(&self, call: String) -> Result<()> {
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl RemediationExecutor for Arc<RecordingExecutor> {
+        async fn restart_tunnel(&self, id: &str) -> Result<()> { self.record(format!("restart_tunnel {}", id)) }
+        async fn switch_path(&self, id: &str, path: &str) -> Result<()> { self.record(format!("switch_path {} {}", id, path)) }
+        async fn restart_bgp_session(&self, id: &str) -> Result<()> { self.record(format!("restart_bgp {}", id)) }
+        async fn scale_bandwidth(&self, id: &str, capacity: u64) -> Result<()> { self.record(format!("scale {} {}", id, capacity)) }
+        async fn reroute_traffic(&self, from: &str, to: &str) -> Result<()> { self.record(format!("reroute {} {}", from, to)) }
+        async fn rollback_config(&self, id: &str) -> Result<()> { self.record(format!("rollback_config {}", id)) }
+        async fn block_traffic(&self, id: &str) -> Result<()> { self.record(format!("block {}", id)) }
+    }
+
+    /// Reports a fixed set of issues on re-check
+    struct FixedVerifier(Vec<Issue>);
+
+    #[async_trait]
+    impl RemediationVerifier for FixedVerifier {
+        async fn recheck(&self, _resource_id: &str) -> Result<Vec<Issue>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn latency(ms: f64, severity: IssueSeverity) -> Issue {
+        Issue::new(IssueType::HighLatency, severity, "High latency", "tunnel-1").with_metric("latency_ms", ms)
+    }
+
+    async fn remediate_with(issue: &Issue, after: Vec<Issue>) -> (RemediationAttempt, Vec<String>) {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut engine = RemediationEngine::new(Arc::clone(&executor))
+            .with_verifier(Arc::new(FixedVerifier(after)))
+            .with_settle_time(Duration::ZERO);
+        let attempt = engine.remediate(issue).await.unwrap();
+        assert_eq!(engine.get_attempt(&attempt.id).unwrap().status, attempt.status);
+        let calls = executor.calls.lock().unwrap().clone();
+        (attempt, calls)
+    }
+
+    #[tokio::test]
+    async fn test_worsening_rolls_back() {
+        let issue = latency(150.0, IssueSeverity::Medium);
+        let (attempt, calls) = remediate_with(&issue, vec![latency(420.0, IssueSeverity::High)]).await;
+
+        assert_eq!(calls, vec!["switch_path tunnel-1 tunnel-1-backup", "switch_path tunnel-1 tunnel-1-primary"]);
+        assert_eq!(attempt.status, RemediationStatus::RolledBack);
+        assert!(attempt.rollback_performed);
+        assert_eq!(attempt.rollback_action, Some(RemediationAction::RestorePrimaryPath));
+        assert_eq!(attempt.rollback_reason.as_deref(), Some("HighLatency went from Medium to High"));
+
+        // Same severity, but the metric is clearly up
+        let (attempt, _) = remediate_with(&issue, vec![latency(180.0, IssueSeverity::Medium)]).await;
+        assert_eq!(attempt.status, RemediationStatus::RolledBack);
+        assert_eq!(attempt.rollback_reason.as_deref(), Some("latency_ms rose from 150 to 180"));
+
+        // A more severe issue of another kind
+        let down = Issue::new(IssueType::TunnelDown, IssueSeverity::Critical, "Tunnel is down", "tunnel-1");
+        let (attempt, _) = remediate_with(&issue, vec![down]).await;
+        assert_eq!(attempt.status, RemediationStatus::RolledBack);
+    }
+
+    #[tokio::test]
+    async fn test_improvement_is_kept() {
+        let issue = latency(150.0, IssueSeverity::Medium);
+        let (attempt, calls) = remediate_with(&issue, vec![latency(155.0, IssueSeverity::Medium)]).await;
+        assert_eq!(attempt.status, RemediationStatus::Succeeded);
+        assert_eq!(calls.len(), 1);
+
+        let (attempt, _) = remediate_with(&issue, vec![]).await;
+        assert_eq!(attempt.status, RemediationStatus::Succeeded);
+        assert!(!attempt.rollback_performed);
+    }
+
+    #[tokio::test]
+    async fn test_worsening_without_inverse_fails() {
+        let issue = Issue::new(IssueType::BgpPeerDown, IssueSeverity::High, "BGP peer is down", "peer-1");
+        let down = Issue::new(IssueType::BgpPeerDown, IssueSeverity::Critical, "BGP peer is down", "peer-1");
+        let (attempt, calls) = remediate_with(&issue, vec![down]).await;
+
+        assert_eq!(calls, vec!["restart_bgp peer-1"]);
+        assert_eq!(attempt.status, RemediationStatus::Failed);
+        assert!(!attempt.rollback_performed);
+        assert!(attempt.error.unwrap().contains("RestartBgpSession cannot be rolled back"));
+    }
+
+    struct ScriptedExecutor {
+        name: &'static str,
+        fail: bool,
+        runs: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl CustomExecutor for ScriptedExecutor {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn execute(&self, _issue: &Issue) -> Result<()> {
+            self.runs.lock().unwrap().push(self.name);
+            if self.fail {
+                anyhow::bail!("{} could not reach the DHCP server", self.name);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_executors_in_priority_order() {
+        let runs = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let executor = |name, fail| Arc::new(ScriptedExecutor { name, fail, runs: Arc::clone(&runs) });
+        let pool_exhausted = IssueType::Custom("dhcp-pool-exhausted".to_string());
+
+        let mut engine = RemediationEngine::new(MockExecutor);
+        engine.register_executor(pool_exhausted.clone(), 10, executor("expand-pool", false));
+        engine.register_executor(pool_exhausted.clone(), 50, executor("flush-stale-leases", true));
+        engine.register_executor(pool_exhausted.clone(), 5, executor("page-oncall", false));
+
+        let issue = Issue::new(pool_exhausted.clone(), IssueSeverity::High, "Pool lan is full", "dhcp-lan");
+        assert_eq!(
+            engine.get_remediation_actions(&issue),
+            vec![
+                RemediationAction::Custom("flush-stale-leases".to_string()),
+                RemediationAction::Custom("expand-pool".to_string()),
+                RemediationAction::Custom("page-oncall".to_string()),
+            ]
+        );
+
+        let attempt = engine.remediate(&issue).await.unwrap();
+        assert_eq!(attempt.action, RemediationAction::Custom("expand-pool".to_string()));
+        assert_eq!(attempt.status, RemediationStatus::Succeeded);
+        assert_eq!(*runs.lock().unwrap(), vec!["flush-stale-leases", "expand-pool"]);
+
+        let attempts = engine.get_attempts_for_issue(&issue.id);
+        assert_eq!(attempts.len(), 2);
+        let failed = attempts.iter().find(|a| a.status == RemediationStatus::Failed).unwrap();
+        assert_eq!(failed.error.as_deref(), Some("flush-stale-leases could not reach the DHCP server"));
+
+        // Every executor failing leaves the last failure
+        assert!(engine.unregister_executor(&pool_exhausted, "expand-pool"));
+        assert!(engine.unregister_executor(&pool_exhausted, "page-oncall"));
+        let attempt = engine.remediate(&issue).await.unwrap();
+        assert_eq!(attempt.status, RemediationStatus::Failed);
+
+        // Other issue types keep the built-in actions
+        let down = Issue::new(IssueType::TunnelDown, IssueSeverity::Critical, "Tunnel is down", "tunnel-1");
+        assert_eq!(engine.remediate(&down).await.unwrap().action, RemediationAction::RestartTunnel);
+    }
+
     #[tokio::test]
     async fn test_get_attempts_for_issue() {
         let executor = MockExecutor;
@@ -0,0 +1,299 @@
+//! Remediation Throttling
+//!
+//! Keeps an issue storm from turning into a remediation storm. A
+//! [`Throttle`] caps remediations globally and per issue type over sliding
+//! windows, and its [`CircuitBreaker`] pauses automated remediation when
+//! too few recent remediations succeed; while paused, issues go to an
+//! [`Escalation`] for a human to handle.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::detector::{Issue, IssueType};
+
+/// At most `max` remediations in any `per` window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub max: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn new(max: u32, per: Duration) -> Self {
+        Self { max, per }
+    }
+
+    fn allows(&self, history: Option<&VecDeque<Instant>>, now: Instant) -> bool {
+        let recent = history.map_or(0, |history| {
+            history.iter().filter(|at| now.duration_since(**at) < self.per).count()
+        });
+        recent < self.max as usize
+    }
+}
+
+/// When to pause automated remediation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    /// Outcomes the success rate is computed over
+    pub window: usize,
+    /// Outcomes needed in the window before the breaker can trip
+    pub min_attempts: usize,
+    /// Trip when the success rate falls below this fraction
+    pub min_success_rate: f64,
+    /// How long to pause before trying a single remediation again
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_attempts: 5,
+            min_success_rate: 0.5,
+            cooldown: Duration::from_secs(900),
+        }
+    }
+}
+
+/// Whether the breaker lets remediations through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BreakerState {
+    Closed,
+    /// Paused after the success rate dropped
+    Open,
+    /// Cooldown over; one trial remediation decides whether to close
+    HalfOpen,
+}
+
+/// What the throttle decided for one remediation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    /// Over a rate limit; deferred to a later pass
+    Throttled,
+    /// The circuit breaker is open; escalate instead
+    Paused,
+}
+
+/// Hands issues to a human while automated remediation is paused
+#[async_trait]
+pub trait Escalation: Send + Sync {
+    async fn escalate(&self, issue: &Issue, reason: &str) -> Result<()>;
+}
+
+/// Rate limits and circuit breaker for automated remediation
+#[derive(Debug, Default)]
+pub struct Throttle {
+    global_limit: Option<RateLimit>,
+    type_limits: HashMap<IssueType, RateLimit>,
+    default_type_limit: Option<RateLimit>,
+    breaker: Option<CircuitBreaker>,
+    admitted: VecDeque<Instant>,
+    admitted_by_type: HashMap<IssueType, VecDeque<Instant>>,
+    outcomes: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    trial_in_flight: bool,
+    /// Issues escalated since the breaker opened, by resource and type
+    escalated: HashSet<(String, IssueType)>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit remediations across all issue types
+    pub fn with_global_limit(mut self, limit: RateLimit) -> Self {
+        self.global_limit = Some(limit);
+        self
+    }
+
+    /// Limit remediations of one issue type
+    pub fn with_type_limit(mut self, issue_type: IssueType, limit: RateLimit) -> Self {
+        self.type_limits.insert(issue_type, limit);
+        self
+    }
+
+    /// Limit for issue types without a limit of their own
+    pub fn with_default_type_limit(mut self, limit: RateLimit) -> Self {
+        self.default_type_limit = Some(limit);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    pub fn breaker_state(&self, now: Instant) -> BreakerState {
+        match (self.breaker, self.opened_at) {
+            (Some(breaker), Some(opened_at)) if now.duration_since(opened_at) >= breaker.cooldown => {
+                BreakerState::HalfOpen
+            }
+            (_, Some(_)) => BreakerState::Open,
+            (_, None) => BreakerState::Closed,
+        }
+    }
+
+    /// Success rate over the breaker window; `None` before any outcome
+    pub fn success_rate(&self) -> Option<f64> {
+        if self.outcomes.is_empty() {
+            return None;
+        }
+        let successes = self.outcomes.iter().filter(|success| **success).count();
+        Some(successes as f64 / self.outcomes.len() as f64)
+    }
+
+    /// Decide whether a remediation for `issue_type` may run now, counting
+    /// it against the limits if so
+    pub fn admit(&mut self, issue_type: &IssueType, now: Instant) -> Admission {
+        let half_open = match self.breaker_state(now) {
+            BreakerState::Open => return Admission::Paused,
+            // Only one trial at a time
+            BreakerState::HalfOpen if self.trial_in_flight => return Admission::Paused,
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => false,
+        };
+
+        self.prune(now);
+        let type_limit = self.type_limits.get(issue_type).or(self.default_type_limit.as_ref());
+        let over_global = self.global_limit.is_some_and(|limit| !limit.allows(Some(&self.admitted), now));
+        let over_type = type_limit.is_some_and(|limit| !limit.allows(self.admitted_by_type.get(issue_type), now));
+        if over_global || over_type {
+            return Admission::Throttled;
+        }
+
+        self.trial_in_flight = half_open;
+        self.admitted.push_back(now);
+        self.admitted_by_type.entry(issue_type.clone()).or_default().push_back(now);
+        Admission::Allowed
+    }
+
+    /// Record how an admitted remediation went; true if this tripped the
+    /// breaker
+    pub fn record_outcome(&mut self, success: bool, now: Instant) -> bool {
+        let Some(breaker) = self.breaker else {
+            return false;
+        };
+
+        if self.trial_in_flight {
+            self.trial_in_flight = false;
+            if success {
+                self.close();
+                return false;
+            }
+            // Failed trial: pause for another cooldown
+            self.opened_at = Some(now);
+            return false;
+        }
+        if self.opened_at.is_some() {
+            return false;
+        }
+
+        self.outcomes.push_back(success);
+        while self.outcomes.len() > breaker.window.max(1) {
+            self.outcomes.pop_front();
+        }
+        let tripped = self.outcomes.len() >= breaker.min_attempts
+            && self.success_rate().is_some_and(|rate| rate < breaker.min_success_rate);
+        if tripped {
+            self.opened_at = Some(now);
+        }
+        tripped
+    }
+
+    /// Resume automated remediation, e.g. after a human dealt with the
+    /// cause of the failures
+    pub fn close(&mut self) {
+        self.opened_at = None;
+        self.trial_in_flight = false;
+        self.outcomes.clear();
+        self.escalated.clear();
+    }
+
+    /// Whether `issue` still needs escalating in the current pause; each
+    /// resource and issue type is escalated once until the breaker closes
+    pub fn needs_escalation(&mut self, issue: &Issue) -> bool {
+        self.escalated.insert((issue.affected_resource_id.clone(), issue.issue_type.clone()))
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let longest = self.type_limits.values()
+            .chain(self.default_type_limit.iter())
+            .chain(self.global_limit.iter())
+            .map(|limit| limit.per)
+            .max()
+            .unwrap_or_default();
+        let expired = |at: &Instant| now.duration_since(*at) >= longest;
+        while self.admitted.front().is_some_and(expired) {
+            self.admitted.pop_front();
+        }
+        self.admitted_by_type.retain(|_, history| {
+            while history.front().is_some_and(expired) {
+                history.pop_front();
+            }
+            !history.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits_slide() {
+        let mut throttle = Throttle::new()
+            .with_global_limit(RateLimit::new(3, Duration::from_secs(60)))
+            .with_type_limit(IssueType::TunnelDown, RateLimit::new(1, Duration::from_secs(10)));
+        let t0 = Instant::now();
+
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t0), Admission::Allowed);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t0), Admission::Throttled);
+        assert_eq!(throttle.admit(&IssueType::HighLatency, t0), Admission::Allowed);
+
+        let t1 = t0 + Duration::from_secs(10);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t1), Admission::Allowed);
+        // Global limit of 3 a minute reached
+        assert_eq!(throttle.admit(&IssueType::PacketLoss, t1), Admission::Throttled);
+        assert_eq!(throttle.admit(&IssueType::PacketLoss, t0 + Duration::from_secs(60)), Admission::Allowed);
+    }
+
+    #[test]
+    fn test_breaker_half_open_trial() {
+        let breaker = CircuitBreaker {
+            window: 4,
+            min_attempts: 4,
+            min_success_rate: 0.5,
+            cooldown: Duration::from_secs(60),
+        };
+        let mut throttle = Throttle::new().with_circuit_breaker(breaker);
+        let t0 = Instant::now();
+
+        for success in [true, false, false] {
+            assert_eq!(throttle.admit(&IssueType::TunnelDown, t0), Admission::Allowed);
+            assert!(!throttle.record_outcome(success, t0));
+        }
+        assert!(throttle.record_outcome(false, t0));
+        assert_eq!(throttle.breaker_state(t0), BreakerState::Open);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t0), Admission::Paused);
+
+        // After the cooldown a single trial goes through; it fails
+        let t1 = t0 + Duration::from_secs(60);
+        assert_eq!(throttle.breaker_state(t1), BreakerState::HalfOpen);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t1), Admission::Allowed);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t1), Admission::Paused);
+        throttle.record_outcome(false, t1);
+        assert_eq!(throttle.breaker_state(t1), BreakerState::Open);
+
+        // The next trial succeeds and closes the breaker
+        let t2 = t1 + Duration::from_secs(60);
+        assert_eq!(throttle.admit(&IssueType::TunnelDown, t2), Admission::Allowed);
+        throttle.record_outcome(true, t2);
+        assert_eq!(throttle.breaker_state(t2), BreakerState::Closed);
+        assert_eq!(throttle.success_rate(), None);
+    }
+}
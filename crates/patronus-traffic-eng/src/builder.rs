@@ -0,0 +1,668 @@
+//! Demand Matrix Construction
+//!
+//! [`DemandMatrixBuilder`] turns a stream of flow records into per-pair
+//! demand history over fixed intervals, split by application class. Flow
+//! sources are converted to [`FlowStat`]s first, SD-WAN traffic statistics
+//! by `SdwanDemandFeed` with the `sdwan` feature; [`SiteMap`] maps their
+//! addresses to sites.
+//!
+//! Storage is sparse: only pairs that carried traffic are kept, and only
+//! the intervals in which they did. An interval the collector never
+//! reported is missing and is flagged, never filled in.
+
+use anyhow::Result;
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::demand::{percentile_of, DemandMatrix, DemandPredictor, FlowStat, TrafficDemand, DEFAULT_MAX_HISTORY};
+
+/// Maps addresses to the sites they belong to, longest prefix first
+#[derive(Debug, Clone, Default)]
+pub struct SiteMap {
+    prefixes: Vec<(IpAddr, u8, String)>,
+}
+
+impl SiteMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, network: IpAddr, prefix_len: u8, site: impl Into<String>) -> Result<()> {
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            anyhow::bail!("Invalid prefix length /{} for {}", prefix_len, network);
+        }
+        self.prefixes.push((network, prefix_len, site.into()));
+        Ok(())
+    }
+
+    pub fn site_of(&self, address: IpAddr) -> Option<&str> {
+        self.prefixes.iter()
+            .filter(|(network, len, _)| prefix_contains(*network, *len, address))
+            .max_by_key(|(_, len, _)| *len)
+            .map(|(_, _, site)| site.as_str())
+    }
+
+    /// A flow between the sites of two addresses; `None` if either is
+    /// outside every known site
+    pub fn flow(
+        &self,
+        source: IpAddr,
+        destination: IpAddr,
+        bytes: u64,
+        interval_secs: f64,
+        priority: u8,
+    ) -> Option<FlowStat> {
+        Some(FlowStat::new(
+            self.site_of(source)?.to_string(),
+            self.site_of(destination)?.to_string(),
+            bytes,
+            interval_secs,
+            priority,
+        ))
+    }
+}
+
+fn prefix_contains(network: IpAddr, prefix_len: u8, address: IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(address) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(address) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Demand of one directed pair over the retained intervals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairDemand {
+    pub source: String,
+    pub destination: String,
+    /// `None` for all classes together
+    pub app_class: Option<String>,
+    pub mean_mbps: f64,
+    pub p95_mbps: f64,
+    pub peak_mbps: f64,
+    pub priority: u8,
+    /// Collected intervals the figures are computed over
+    pub intervals: usize,
+    /// Intervals in the window the collector did not report
+    pub missing_intervals: usize,
+}
+
+impl PairDemand {
+    /// Demand to plan for: the 95th percentile
+    pub fn to_traffic_demand(&self) -> TrafficDemand {
+        TrafficDemand::new(self.source.clone(), self.destination.clone(), self.p95_mbps, self.priority)
+    }
+}
+
+/// The hour of the day (UTC) with the highest mean demand
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BusyHour {
+    pub hour: u32,
+    pub mean_mbps: f64,
+    /// Collected intervals that started in that hour
+    pub intervals: usize,
+}
+
+/// Bytes per interval for one class of one pair, oldest first
+type Samples = VecDeque<(i64, u64)>;
+
+#[derive(Debug, Default)]
+struct PairSeries {
+    priority: u8,
+    /// By class id; 0 is unclassified traffic
+    classes: Vec<(u16, Samples)>,
+}
+
+impl PairSeries {
+    fn first_interval(&self) -> Option<i64> {
+        self.classes.iter().filter_map(|(_, samples)| samples.front().map(|s| s.0)).min()
+    }
+
+    fn bytes_at(&self, interval: i64, class: Option<u16>) -> u64 {
+        self.classes.iter()
+            .filter(|(id, _)| class.is_none_or(|class| class == *id))
+            .filter_map(|(_, samples)| {
+                samples.binary_search_by_key(&interval, |s| s.0).ok().map(|i| samples[i].1)
+            })
+            .sum()
+    }
+
+    fn has_class(&self, class: Option<u16>) -> bool {
+        class.is_none_or(|class| self.classes.iter().any(|(id, _)| *id == class))
+    }
+}
+
+/// Builds demand matrices from flow records
+///
+/// Each call to [`ingest`](Self::ingest) reports a collection: the interval
+/// it happened in counts as collected even if it carried no flows, so quiet
+/// pairs read as zero demand. Collected intervals older than the newest one
+/// are final and are fed to the [`DemandPredictor`]; flows for them that
+/// arrive later still count towards the matrices but not the predictor.
+pub struct DemandMatrixBuilder {
+    interval_secs: i64,
+    retention: usize,
+    site_ids: HashMap<String, u32>,
+    sites: Vec<String>,
+    class_ids: HashMap<String, u16>,
+    classes: Vec<String>,
+    pairs: HashMap<(u32, u32), PairSeries>,
+    collected: BTreeSet<i64>,
+    fed_through: Option<i64>,
+    predictor: DemandPredictor,
+}
+
+impl DemandMatrixBuilder {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval_secs: interval.as_secs().max(1) as i64,
+            retention: DEFAULT_MAX_HISTORY,
+            site_ids: HashMap::new(),
+            sites: Vec::new(),
+            class_ids: HashMap::new(),
+            // Class id 0 is unclassified traffic
+            classes: vec![String::new()],
+            pairs: HashMap::new(),
+            collected: BTreeSet::new(),
+            fed_through: None,
+            predictor: DemandPredictor::new(DEFAULT_MAX_HISTORY),
+        }
+    }
+
+    /// Intervals of history to keep, in the builder and the predictor
+    pub fn with_retention(mut self, intervals: usize) -> Self {
+        self.retention = intervals.max(1);
+        self.predictor = DemandPredictor::new(self.retention);
+        self
+    }
+
+    /// Record a collection made at `collected_at` and the flows it returned
+    ///
+    /// Flows are placed in the interval they were observed in. A flow
+    /// stamped after the collection is put in the collection's interval,
+    /// and one stamped a whole window or more away from it is dropped, so
+    /// a skewed exporter clock cannot push history out of the window.
+    /// Returns how many flows carried demand between sites.
+    pub fn ingest(&mut self, collected_at: DateTime<Utc>, flows: &[FlowStat]) -> usize {
+        let collection = self.interval_of(collected_at);
+        let window = self.retention as i64;
+        let placed: Vec<(&FlowStat, i64)> = flows.iter()
+            .filter_map(|flow| {
+                let interval = self.interval_of(flow.observed_at);
+                if (interval - collection).abs() >= window {
+                    tracing::debug!("Dropping flow {} -> {} observed at {}, outside the window of collection at {}",
+                        flow.source, flow.destination, flow.observed_at, collected_at);
+                    return None;
+                }
+                Some((flow, interval.min(collection)))
+            })
+            .collect();
+
+        self.collected.insert(collection);
+        for (_, interval) in &placed {
+            self.collected.insert(*interval);
+        }
+        self.evict();
+        let oldest = self.collected.first().copied().unwrap_or_default();
+
+        let mut accepted = 0;
+        for (flow, interval) in placed {
+            if flow.source == flow.destination || interval < oldest {
+                continue;
+            }
+            let key = (self.site_id(&flow.source), self.site_id(&flow.destination));
+            let class = flow.app_class.as_deref().map_or(0, |class| self.class_id(class));
+            let pair = self.pairs.entry(key).or_default();
+            pair.priority = pair.priority.max(flow.priority.min(7));
+            let samples = match pair.classes.iter().position(|(id, _)| *id == class) {
+                Some(i) => &mut pair.classes[i].1,
+                None => {
+                    pair.classes.push((class, VecDeque::new()));
+                    &mut pair.classes.last_mut().unwrap().1
+                }
+            };
+            add_bytes(samples, interval, flow.bytes);
+            accepted += 1;
+        }
+
+        self.feed_predictor();
+        accepted
+    }
+
+    /// Directed pairs with traffic in the window
+    pub fn pair_count(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Stored (pair, class, interval) samples; intervals without traffic
+    /// take no space
+    pub fn sample_count(&self) -> usize {
+        self.pairs.values()
+            .flat_map(|pair| pair.classes.iter())
+            .map(|(_, samples)| samples.len())
+            .sum()
+    }
+
+    /// Application classes seen in the window
+    pub fn app_classes(&self) -> Vec<&str> {
+        let mut classes: Vec<&str> = self.class_ids.iter()
+            .filter(|(_, id)| self.pairs.values().any(|pair| pair.has_class(Some(**id))))
+            .map(|(class, _)| class.as_str())
+            .collect();
+        classes.sort();
+        classes
+    }
+
+    /// Start of each interval between the oldest and newest collected one
+    /// that the collector never reported
+    pub fn missing_intervals(&self) -> Vec<DateTime<Utc>> {
+        let (Some(first), Some(last)) = (self.collected.first(), self.collected.last()) else {
+            return Vec::new();
+        };
+        (*first..*last)
+            .filter(|interval| !self.collected.contains(interval))
+            .map(|interval| self.start_of(interval))
+            .collect()
+    }
+
+    /// Per-pair demand over the window for one application class, or all
+    /// traffic with `None`, ordered by source and destination
+    pub fn demands(&self, app_class: Option<&str>) -> Vec<PairDemand> {
+        let Some(class) = self.class_filter(app_class) else {
+            return Vec::new();
+        };
+        let missing = self.missing_intervals().len();
+
+        let mut demands: Vec<PairDemand> = self.pairs.iter()
+            .filter(|(_, pair)| pair.has_class(class))
+            .filter_map(|(&(source, destination), pair)| {
+                let mut rates = self.rates(pair, class);
+                let mean = rates.iter().sum::<f64>() / rates.len().max(1) as f64;
+                let peak = rates.iter().copied().fold(0.0, f64::max);
+                let p95 = percentile_of(&mut rates, 95.0)?;
+                Some(PairDemand {
+                    source: self.sites[source as usize].clone(),
+                    destination: self.sites[destination as usize].clone(),
+                    app_class: app_class.map(str::to_string),
+                    mean_mbps: mean,
+                    p95_mbps: p95,
+                    peak_mbps: peak,
+                    priority: pair.priority,
+                    intervals: rates.len(),
+                    missing_intervals: missing,
+                })
+            })
+            .collect();
+        demands.sort_by(|a, b| (&a.source, &a.destination).cmp(&(&b.source, &b.destination)));
+        demands
+    }
+
+    /// Demand history for one application class, or all traffic with
+    /// `None`: one entry per pair and collected interval, none for
+    /// missing intervals
+    pub fn matrix(&self, app_class: Option<&str>) -> DemandMatrix {
+        let mut matrix = DemandMatrix::new(self.retention);
+        let Some(class) = self.class_filter(app_class) else {
+            return matrix;
+        };
+        for (&(source, destination), pair) in &self.pairs {
+            if !pair.has_class(class) {
+                continue;
+            }
+            for &interval in &self.collected {
+                matrix.add_demand(TrafficDemand {
+                    source: self.sites[source as usize].clone(),
+                    destination: self.sites[destination as usize].clone(),
+                    bandwidth_mbps: self.rate_mbps(pair.bytes_at(interval, class)),
+                    timestamp: self.start_of(interval),
+                    priority: pair.priority,
+                });
+            }
+        }
+        matrix
+    }
+
+    /// A demand matrix per application class seen in the window
+    pub fn matrices_by_class(&self) -> HashMap<String, DemandMatrix> {
+        self.app_classes().into_iter()
+            .map(|class| (class.to_string(), self.matrix(Some(class))))
+            .collect()
+    }
+
+    /// The hour of the day with the highest mean total demand, over the
+    /// collected intervals that start in it
+    pub fn busy_hour(&self, app_class: Option<&str>) -> Option<BusyHour> {
+        let class = self.class_filter(app_class)?;
+        let mut totals: HashMap<i64, u64> = HashMap::new();
+        for pair in self.pairs.values() {
+            for (_, samples) in pair.classes.iter().filter(|(id, _)| class.is_none_or(|class| class == *id)) {
+                for &(interval, bytes) in samples {
+                    *totals.entry(interval).or_default() += bytes;
+                }
+            }
+        }
+
+        let mut hours = [(0.0, 0usize); 24];
+        for &interval in &self.collected {
+            let hour = &mut hours[self.start_of(interval).hour() as usize];
+            hour.0 += self.rate_mbps(totals.get(&interval).copied().unwrap_or_default());
+            hour.1 += 1;
+        }
+        hours.iter().enumerate()
+            .filter(|(_, (_, intervals))| *intervals > 0)
+            .map(|(hour, (sum, intervals))| BusyHour {
+                hour: hour as u32,
+                mean_mbps: sum / *intervals as f64,
+                intervals: *intervals,
+            })
+            .fold(None, |busiest: Option<BusyHour>, hour| match busiest {
+                Some(busiest) if busiest.mean_mbps >= hour.mean_mbps => Some(busiest),
+                _ => Some(hour),
+            })
+    }
+
+    /// Predictor trained on every final interval, all classes together
+    pub fn predictor(&self) -> &DemandPredictor {
+        &self.predictor
+    }
+
+    fn interval_of(&self, at: DateTime<Utc>) -> i64 {
+        at.timestamp().div_euclid(self.interval_secs)
+    }
+
+    fn start_of(&self, interval: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(interval * self.interval_secs, 0).unwrap_or_default()
+    }
+
+    fn rate_mbps(&self, bytes: u64) -> f64 {
+        bytes as f64 * 8.0 / self.interval_secs as f64 / 1_000_000.0
+    }
+
+    fn site_id(&mut self, site: &str) -> u32 {
+        if let Some(id) = self.site_ids.get(site) {
+            return *id;
+        }
+        let id = self.sites.len() as u32;
+        self.sites.push(site.to_string());
+        self.site_ids.insert(site.to_string(), id);
+        id
+    }
+
+    fn class_id(&mut self, class: &str) -> u16 {
+        if let Some(id) = self.class_ids.get(class) {
+            return *id;
+        }
+        let id = self.classes.len() as u16;
+        self.classes.push(class.to_string());
+        self.class_ids.insert(class.to_string(), id);
+        id
+    }
+
+    /// `Some(None)` for all classes, `None` for a class never seen
+    fn class_filter(&self, app_class: Option<&str>) -> Option<Option<u16>> {
+        match app_class {
+            Some(class) => self.class_ids.get(class).map(|id| Some(*id)),
+            None => Some(None),
+        }
+    }
+
+    fn rates(&self, pair: &PairSeries, class: Option<u16>) -> Vec<f64> {
+        self.collected.iter()
+            .map(|interval| self.rate_mbps(pair.bytes_at(*interval, class)))
+            .collect()
+    }
+
+    /// Drop intervals that fell out of the window, and pairs left without
+    /// traffic
+    fn evict(&mut self) {
+        let Some(newest) = self.collected.last().copied() else {
+            return;
+        };
+        let oldest = newest - self.retention as i64 + 1;
+        if self.collected.first().is_some_and(|first| *first >= oldest) {
+            return;
+        }
+        self.collected = self.collected.split_off(&oldest);
+
+        let sites = &self.sites;
+        let predictor = &mut self.predictor;
+        self.pairs.retain(|&(source, destination), pair| {
+            for (_, samples) in &mut pair.classes {
+                while samples.front().is_some_and(|s| s.0 < oldest) {
+                    samples.pop_front();
+                }
+            }
+            pair.classes.retain(|(_, samples)| !samples.is_empty());
+            if pair.classes.is_empty() {
+                predictor.remove_pair(&sites[source as usize], &sites[destination as usize]);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Feed the predictor every collected interval older than the newest
+    /// that it has not seen yet
+    fn feed_predictor(&mut self) {
+        let Some(newest) = self.collected.last().copied() else {
+            return;
+        };
+        let from = self.fed_through.map_or(i64::MIN, |fed| fed + 1);
+        let pending: Vec<i64> = self.collected.range(from..newest).copied().collect();
+        for interval in pending {
+            let timestamp = self.start_of(interval);
+            for (&(source, destination), pair) in &self.pairs {
+                // Pairs join the history when they first carry traffic
+                if pair.first_interval().is_none_or(|first| first > interval) {
+                    continue;
+                }
+                self.predictor.add_observation(TrafficDemand {
+                    source: self.sites[source as usize].clone(),
+                    destination: self.sites[destination as usize].clone(),
+                    bandwidth_mbps: self.rate_mbps(pair.bytes_at(interval, None)),
+                    timestamp,
+                    priority: pair.priority,
+                });
+            }
+            self.fed_through = Some(interval);
+        }
+    }
+}
+
+/// Add bytes to an interval, keeping samples ordered by interval
+fn add_bytes(samples: &mut Samples, interval: i64, bytes: u64) {
+    match samples.back_mut() {
+        Some(last) if last.0 == interval => last.1 += bytes,
+        Some(last) if last.0 > interval => match samples.binary_search_by_key(&interval, |s| s.0) {
+            Ok(i) => samples[i].1 += bytes,
+            Err(i) => samples.insert(i, (interval, bytes)),
+        },
+        _ => samples.push_back((interval, bytes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: i64 = 300;
+
+    fn at(interval: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_100 - 1_700_000_100 % INTERVAL + interval * INTERVAL, 0).unwrap()
+    }
+
+    /// A flow carrying `mbps` on average over interval `interval`
+    fn flow(source: &str, destination: &str, mbps: f64, interval: i64) -> FlowStat {
+        let bytes = (mbps * 1_000_000.0 / 8.0 * INTERVAL as f64) as u64;
+        FlowStat::new(source.to_string(), destination.to_string(), bytes, INTERVAL as f64, 3)
+            .with_observed_at(at(interval) + chrono::Duration::seconds(10))
+    }
+
+    fn builder() -> DemandMatrixBuilder {
+        DemandMatrixBuilder::new(Duration::from_secs(INTERVAL as u64))
+    }
+
+    #[test]
+    fn test_percentiles_and_missing_intervals() {
+        let mut builder = builder();
+        for i in 0..20 {
+            // The collector was down for interval 7
+            if i == 7 {
+                continue;
+            }
+            let mut flows = vec![flow("hq", "branch", (i + 1) as f64, i)];
+            if i % 2 == 0 {
+                flows.push(flow("hq", "branch", 4.0, i).with_app_class("video"));
+            }
+            builder.ingest(at(i), &flows);
+        }
+        // A collection with no flows still counts as collected
+        builder.ingest(at(20), &[]);
+
+        assert_eq!(builder.missing_intervals(), vec![at(7)]);
+
+        let all = builder.demands(None);
+        assert_eq!(all.len(), 1);
+        let hq = &all[0];
+        assert_eq!((hq.source.as_str(), hq.destination.as_str()), ("hq", "branch"));
+        assert_eq!(hq.intervals, 20);
+        assert_eq!(hq.missing_intervals, 1);
+        // Interval 20 was quiet; the busiest carried 19 + 4, the next 17 + 4
+        assert!((hq.peak_mbps - 23.0).abs() < 1e-6);
+        assert!((hq.p95_mbps - 21.0).abs() < 1e-6);
+        assert!(hq.p95_mbps < hq.peak_mbps && hq.mean_mbps < hq.p95_mbps);
+
+        let video = builder.demands(Some("video"));
+        assert!((video[0].peak_mbps - 4.0).abs() < 1e-6);
+        assert!((video[0].mean_mbps - 2.0).abs() < 1e-6);
+        assert!(builder.demands(Some("voice")).is_empty());
+        assert_eq!(builder.app_classes(), vec!["video"]);
+
+        let matrices = builder.matrices_by_class();
+        assert_eq!(matrices.len(), 1);
+        assert!((matrices["video"].get_percentile_demand("hq", "branch", 95.0).unwrap() - 4.0).abs() < 1e-6);
+        // No entry was made up for the missing interval
+        let matrix = builder.matrix(None);
+        assert!(matrix.get_percentile_demand("hq", "branch", 100.0).is_some());
+        assert!((matrix.get_current_demand("hq", "branch").unwrap().bandwidth_mbps).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_predictor_trains_on_final_intervals() {
+        let mut builder = builder();
+        builder.ingest(at(0), &[flow("a", "b", 10.0, 0)]);
+        assert!(builder.predictor().get_matrix().get_current_demand("a", "b").is_none());
+
+        // Interval 2 missing; c to d joins at interval 3
+        builder.ingest(at(1), &[flow("a", "b", 20.0, 1)]);
+        builder.ingest(at(3), &[flow("a", "b", 30.0, 3), flow("c", "d", 5.0, 3)]);
+        builder.ingest(at(4), &[flow("c", "d", 5.0, 4)]);
+
+        let predictor = builder.predictor();
+        assert!((predictor.get_matrix().get_average_demand("a", "b").unwrap() - 20.0).abs() < 1e-6);
+        assert!((predictor.get_matrix().get_percentile_demand("a", "b", 0.0).unwrap() - 10.0).abs() < 1e-6);
+        assert!(predictor.is_demand_increasing("a", "b"));
+        assert!((predictor.get_matrix().get_average_demand("c", "d").unwrap() - 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_busy_hour() {
+        let mut builder = builder().with_retention(2 * 288);
+        // Two days, 12 intervals per hour; 14:00 carries the most traffic
+        let day_start = at(0).timestamp() - at(0).timestamp() % 86_400;
+        for i in 0..(2 * 288) {
+            let start = DateTime::from_timestamp(day_start + i * INTERVAL, 0).unwrap();
+            let mbps = if start.hour() == 14 { 80.0 } else { 10.0 };
+            let flow = FlowStat::new("a".into(), "b".into(), (mbps * 1_000_000.0 / 8.0 * 300.0) as u64, 300.0, 1)
+                .with_observed_at(start);
+            builder.ingest(start, &[flow]);
+        }
+
+        let busy = builder.busy_hour(None).unwrap();
+        assert_eq!(busy.hour, 14);
+        assert_eq!(busy.intervals, 24);
+        assert!((busy.mean_mbps - 80.0).abs() < 1e-6);
+        assert!(builder.busy_hour(Some("video")).is_none());
+    }
+
+    #[test]
+    fn test_retention_evicts_idle_pairs() {
+        let mut builder = builder().with_retention(3);
+        builder.ingest(at(0), &[flow("a", "b", 10.0, 0), flow("b", "a", 1.0, 0)]);
+        builder.ingest(at(1), &[flow("a", "b", 10.0, 1)]);
+        builder.ingest(at(2), &[flow("a", "b", 10.0, 2)]);
+        assert_eq!(builder.pair_count(), 2);
+
+        builder.ingest(at(3), &[flow("a", "b", 10.0, 3)]);
+        assert_eq!(builder.pair_count(), 1);
+        assert_eq!(builder.sample_count(), 3);
+        assert!(builder.predictor().get_matrix().get_current_demand("b", "a").is_none());
+        assert_eq!(builder.demands(None)[0].intervals, 3);
+    }
+
+    #[test]
+    fn test_skewed_timestamps_keep_history() {
+        let mut builder = builder().with_retention(4);
+        for i in 0..4 {
+            builder.ingest(at(i), &[flow("a", "b", 10.0, i)]);
+        }
+
+        // An exporter clock a day ahead must not evict the window
+        let accepted = builder.ingest(at(4), &[flow("a", "b", 10.0, 288), flow("c", "d", 2.0, 5)]);
+        assert_eq!(accepted, 1);
+        assert_eq!(builder.demands(None)[0].intervals, 4);
+        assert!(builder.missing_intervals().is_empty());
+        // The slightly-ahead flow counts in the collection's interval
+        let cd = builder.matrix(None);
+        assert!((cd.get_current_demand("c", "d").unwrap().bandwidth_mbps - 2.0).abs() < 1e-6);
+        assert_eq!(cd.get_current_demand("c", "d").unwrap().timestamp, at(4));
+
+        // A flow stamped a window or more before its collection is dropped too
+        assert_eq!(builder.ingest(at(5), &[flow("a", "b", 10.0, -100)]), 0);
+        assert_eq!(builder.demands(None)[0].intervals, 4);
+    }
+
+    #[test]
+    fn test_sparse_with_many_sites() {
+        let mut builder = builder();
+        // 400 sites, each talking to 50 others: 20,000 of 159,600 pairs
+        for i in 0..2 {
+            let flows: Vec<FlowStat> = (0..400)
+                .flat_map(|s| (1..=50).map(move |d| (s, (s + d) % 400)))
+                .map(|(s, d)| flow(&format!("site-{}", s), &format!("site-{}", d), 1.0, i))
+                .collect();
+            assert_eq!(builder.ingest(at(i), &flows), 20_000);
+        }
+        assert_eq!(builder.pair_count(), 20_000);
+        assert_eq!(builder.sample_count(), 40_000);
+        assert_eq!(builder.predictor().get_matrix().get_all_pairs().len(), 20_000);
+    }
+
+    #[test]
+    fn test_site_map_longest_prefix() {
+        let mut sites = SiteMap::new();
+        sites.insert("10.0.0.0".parse().unwrap(), 8, "corp").unwrap();
+        sites.insert("10.1.0.0".parse().unwrap(), 16, "branch").unwrap();
+        sites.insert("2001:db8::".parse().unwrap(), 32, "dc").unwrap();
+        assert!(sites.insert("10.0.0.0".parse().unwrap(), 33, "bad").is_err());
+
+        assert_eq!(sites.site_of("10.1.2.3".parse().unwrap()), Some("branch"));
+        assert_eq!(sites.site_of("10.2.2.3".parse().unwrap()), Some("corp"));
+        assert_eq!(sites.site_of("2001:db8::1".parse().unwrap()), Some("dc"));
+        assert_eq!(sites.site_of("192.168.1.1".parse().unwrap()), None);
+
+        let flow = sites.flow("10.1.0.5".parse().unwrap(), "2001:db8::5".parse().unwrap(), 1000, 10.0, 2).unwrap();
+        assert_eq!((flow.source.as_str(), flow.destination.as_str()), ("branch", "dc"));
+        assert!(sites.flow("10.1.0.5".parse().unwrap(), "8.8.8.8".parse().unwrap(), 1000, 10.0, 2).is_none());
+    }
+}
@@ -1,8 +1,20 @@
 //! Traffic Demand Matrix and Prediction
+//!
+//! Demand comes from flow statistics: [`DemandMatrix::from_flows`] turns one
+//! measurement interval into per-pair demand, and a [`DemandTracker`] folds
+//! interval after interval into a history the predictor can work from.
+//! Demand is directional, so A to B and B to A are separate entries.
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// History kept for matrices built from flows: a day of 5-minute intervals
+pub const DEFAULT_MAX_HISTORY: usize = 288;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrafficDemand {
@@ -29,6 +41,88 @@ impl TrafficDemand {
     }
 }
 
+/// Traffic of one flow over a measurement interval
+///
+/// Endpoints are network nodes (sites or routers), not hosts; whatever
+/// collects the flows maps addresses to the nodes they enter and leave by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowStat {
+    pub source: String,
+    pub destination: String,
+    pub bytes: u64,
+    /// Length of the measurement interval
+    pub interval_secs: f64,
+    pub priority: u8,
+    pub observed_at: DateTime<Utc>,
+    /// Application class from DPI, e.g. "video"
+    #[serde(default)]
+    pub app_class: Option<String>,
+}
+
+impl FlowStat {
+    pub fn new(source: String, destination: String, bytes: u64, interval_secs: f64, priority: u8) -> Self {
+        Self {
+            source,
+            destination,
+            bytes,
+            interval_secs,
+            priority: priority.min(7),
+            observed_at: Utc::now(),
+            app_class: None,
+        }
+    }
+
+    pub fn with_app_class(mut self, class: impl Into<String>) -> Self {
+        self.app_class = Some(class.into());
+        self
+    }
+
+    pub fn with_observed_at(mut self, at: DateTime<Utc>) -> Self {
+        self.observed_at = at;
+        self
+    }
+
+    pub fn rate_mbps(&self) -> f64 {
+        self.bytes as f64 * 8.0 / self.interval_secs / 1_000_000.0
+    }
+}
+
+/// Sum flows into one demand per directed pair
+///
+/// A pair's priority is that of its most important flow. Flows that stay
+/// on a node or have no interval carry no demand between nodes.
+fn aggregate(flows: &[FlowStat]) -> HashMap<(String, String), TrafficDemand> {
+    let mut pairs: HashMap<(String, String), TrafficDemand> = HashMap::new();
+    for flow in flows {
+        if flow.source == flow.destination || flow.interval_secs.is_nan() || flow.interval_secs <= 0.0 {
+            continue;
+        }
+        let demand = pairs
+            .entry((flow.source.clone(), flow.destination.clone()))
+            .or_insert_with(|| TrafficDemand {
+                source: flow.source.clone(),
+                destination: flow.destination.clone(),
+                bandwidth_mbps: 0.0,
+                timestamp: flow.observed_at,
+                priority: 0,
+            });
+        demand.bandwidth_mbps += flow.rate_mbps();
+        demand.timestamp = demand.timestamp.max(flow.observed_at);
+        demand.priority = demand.priority.max(flow.priority.min(7));
+    }
+    pairs
+}
+
+/// Nearest-rank percentile of `values`, which are sorted in place
+pub(crate) fn percentile_of(values: &mut [f64], percentile: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * values.len() as f64).ceil() as usize;
+    Some(values[rank.clamp(1, values.len()) - 1])
+}
+
 /// Demand Matrix: Traffic demands between all source-destination pairs
 pub struct DemandMatrix {
     demands: HashMap<(String, String), Vec<TrafficDemand>>,
@@ -43,6 +137,15 @@ impl DemandMatrix {
         }
     }
 
+    /// Demand observed in one measurement interval of flows
+    pub fn from_flows(flows: &[FlowStat]) -> Self {
+        let mut matrix = Self::new(DEFAULT_MAX_HISTORY);
+        for demand in aggregate(flows).into_values() {
+            matrix.add_demand(demand);
+        }
+        matrix
+    }
+
     pub fn add_demand(&mut self, demand: TrafficDemand) {
         let key = (demand.source.clone(), demand.destination.clone());
         let history = self.demands.entry(key).or_insert_with(Vec::new);
@@ -80,10 +183,24 @@ impl DemandMatrix {
         })
     }
 
+    /// Demand not exceeded in `percentile` percent of the history
+    /// (nearest rank)
+    pub fn get_percentile_demand(&self, source: &str, destination: &str, percentile: f64) -> Option<f64> {
+        let key = (source.to_string(), destination.to_string());
+        let demands = self.demands.get(&key)?;
+        let mut values: Vec<f64> = demands.iter().map(|d| d.bandwidth_mbps).collect();
+        percentile_of(&mut values, percentile)
+    }
+
     pub fn get_all_pairs(&self) -> Vec<(String, String)> {
         self.demands.keys().cloned().collect()
     }
 
+    /// Forget a pair and its history
+    pub fn remove_pair(&mut self, source: &str, destination: &str) -> bool {
+        self.demands.remove(&(source.to_string(), destination.to_string())).is_some()
+    }
+
     pub fn total_demand(&self) -> f64 {
         self.demands.values()
             .filter_map(|demands| demands.last())
@@ -120,6 +237,11 @@ impl DemandPredictor {
         &self.matrix
     }
 
+    /// Forget a pair and its history
+    pub fn remove_pair(&mut self, source: &str, destination: &str) -> bool {
+        self.matrix.remove_pair(source, destination)
+    }
+
     /// Predict future demand using exponential moving average
     pub fn predict_demand(&self, source: &str, destination: &str, alpha: f64) -> Option<f64> {
         let key = (source.to_string(), destination.to_string());
@@ -169,6 +291,96 @@ impl DemandPredictor {
     }
 }
 
+/// Keeps a demand history up to date from successive intervals of flows
+///
+/// Each interval adds a demand sample for every pair seen in it, and a zero
+/// sample for known pairs that went quiet, so averages and predictions
+/// follow demand down as well as up. Pairs quiet for `expire_after`
+/// intervals in a row are dropped.
+pub struct DemandTracker {
+    predictor: DemandPredictor,
+    idle_intervals: HashMap<(String, String), u32>,
+    expire_after: u32,
+}
+
+impl DemandTracker {
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            predictor: DemandPredictor::new(max_history),
+            idle_intervals: HashMap::new(),
+            expire_after: 12,
+        }
+    }
+
+    pub fn with_expiry(mut self, intervals: u32) -> Self {
+        self.expire_after = intervals.max(1);
+        self
+    }
+
+    /// Fold in one measurement interval, returning how many pairs carried traffic
+    pub fn update(&mut self, flows: &[FlowStat]) -> usize {
+        let mut observed = aggregate(flows);
+        let active = observed.len();
+        let now = Utc::now();
+
+        for key in self.predictor.matrix.get_all_pairs() {
+            if observed.contains_key(&key) {
+                continue;
+            }
+            let idle = self.idle_intervals.entry(key.clone()).or_insert(0);
+            *idle += 1;
+            if *idle >= self.expire_after {
+                self.idle_intervals.remove(&key);
+                self.predictor.matrix.remove_pair(&key.0, &key.1);
+                continue;
+            }
+
+            let priority = self.predictor.matrix.get_current_demand(&key.0, &key.1).map_or(0, |d| d.priority);
+            observed.insert(key.clone(), TrafficDemand {
+                source: key.0,
+                destination: key.1,
+                bandwidth_mbps: 0.0,
+                timestamp: now,
+                priority,
+            });
+        }
+
+        for (key, demand) in observed {
+            if demand.bandwidth_mbps > 0.0 {
+                self.idle_intervals.remove(&key);
+            }
+            self.predictor.add_observation(demand);
+        }
+
+        active
+    }
+
+    pub fn matrix(&self) -> &DemandMatrix {
+        self.predictor.get_matrix()
+    }
+
+    pub fn predictor(&self) -> &DemandPredictor {
+        &self.predictor
+    }
+
+    /// Update `tracker` with the flows `collect` returns every `interval`
+    /// until aborted
+    pub fn spawn<F>(tracker: Arc<RwLock<Self>>, collect: F, interval: Duration) -> JoinHandle<()>
+    where
+        F: Fn() -> Vec<FlowStat> + Send + Sync + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let flows = collect();
+                let active = tracker.write().await.update(&flows);
+                tracing::debug!("Demand updated from {} flows over {} pairs", flows.len(), active);
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,6 +477,20 @@ mod tests {
         assert_eq!(peak, Some(500.0));
     }
 
+    #[test]
+    fn test_percentile_demand() {
+        let mut matrix = DemandMatrix::new(100);
+
+        for bandwidth in 1..=20 {
+            matrix.add_demand(TrafficDemand::new("a".to_string(), "b".to_string(), bandwidth as f64, 1));
+        }
+
+        assert_eq!(matrix.get_percentile_demand("a", "b", 95.0), Some(19.0));
+        assert_eq!(matrix.get_percentile_demand("a", "b", 100.0), Some(20.0));
+        assert_eq!(matrix.get_percentile_demand("a", "b", 0.0), Some(1.0));
+        assert_eq!(matrix.get_percentile_demand("b", "a", 95.0), None);
+    }
+
     #[test]
     fn test_total_demand() {
         let mut matrix = DemandMatrix::new(100);
@@ -349,4 +575,64 @@ mod tests {
         let pairs = matrix.get_all_pairs();
         assert_eq!(pairs.len(), 2);
     }
+
+    fn flow(source: &str, destination: &str, mbps: f64, priority: u8) -> FlowStat {
+        // 10 s interval
+        FlowStat::new(source.to_string(), destination.to_string(), (mbps * 1_250_000.0) as u64, 10.0, priority)
+    }
+
+    #[test]
+    fn test_from_flows() {
+        let flows = vec![
+            flow("a", "b", 40.0, 1),
+            flow("a", "b", 60.0, 5),
+            flow("b", "a", 5.0, 2),
+            flow("a", "c", 12.5, 0),
+            flow("c", "c", 100.0, 7),
+            FlowStat::new("c".to_string(), "a".to_string(), 1_000, 0.0, 1),
+        ];
+
+        let matrix = DemandMatrix::from_flows(&flows);
+
+        assert_eq!(matrix.get_all_pairs().len(), 3);
+        let ab = matrix.get_current_demand("a", "b").unwrap();
+        assert!((ab.bandwidth_mbps - 100.0).abs() < 1e-9);
+        assert_eq!(ab.priority, 5);
+        // The reverse direction keeps its own, smaller demand
+        let ba = matrix.get_current_demand("b", "a").unwrap();
+        assert!((ba.bandwidth_mbps - 5.0).abs() < 1e-9);
+        assert_eq!(ba.priority, 2);
+        assert!((matrix.get_current_demand("a", "c").unwrap().bandwidth_mbps - 12.5).abs() < 1e-9);
+        assert!(matrix.get_current_demand("c", "c").is_none());
+        assert!(matrix.get_current_demand("c", "a").is_none());
+        assert!((matrix.total_demand() - 117.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tracker_follows_shifting_demand() {
+        let mut tracker = DemandTracker::new(10).with_expiry(3);
+
+        assert_eq!(tracker.update(&[flow("a", "b", 100.0, 3), flow("b", "a", 10.0, 3)]), 2);
+        assert_eq!(tracker.update(&[flow("a", "b", 150.0, 3)]), 1);
+
+        let matrix = tracker.matrix();
+        assert!((matrix.get_current_demand("a", "b").unwrap().bandwidth_mbps - 150.0).abs() < 1e-9);
+        assert!((matrix.get_average_demand("a", "b").unwrap() - 125.0).abs() < 1e-9);
+        // B to A went quiet and decays rather than holding its last value
+        let ba = matrix.get_current_demand("b", "a").unwrap();
+        assert_eq!(ba.bandwidth_mbps, 0.0);
+        assert_eq!(ba.priority, 3);
+        assert!(tracker.predictor().is_demand_increasing("a", "b"));
+
+        tracker.update(&[flow("a", "b", 150.0, 3)]);
+        tracker.update(&[flow("a", "b", 150.0, 3), flow("b", "a", 20.0, 3)]);
+        // Traffic resumed before expiry, restarting the count
+        for _ in 0..2 {
+            tracker.update(&[flow("a", "b", 150.0, 3)]);
+        }
+        assert!(tracker.matrix().get_current_demand("b", "a").is_some());
+        tracker.update(&[flow("a", "b", 150.0, 3)]);
+        assert!(tracker.matrix().get_current_demand("b", "a").is_none());
+        assert_eq!(tracker.matrix().get_all_pairs(), vec![("a".to_string(), "b".to_string())]);
+    }
 }
@@ -2,12 +2,21 @@
 //!
 //! Advanced traffic management with ML-based optimization
 
+pub mod builder;
 pub mod demand;
 pub mod path;
 pub mod optimizer;
 pub mod tunnel;
+#[cfg(feature = "sdwan")]
+pub mod sdwan_feed;
 
-pub use demand::{TrafficDemand, DemandMatrix, DemandPredictor};
-pub use path::{PathComputation, PathConstraints, ComputedPath};
-pub use optimizer::{TrafficOptimizer, OptimizationObjective, OptimizationResult};
-pub use tunnel::{TunnelManager, Tunnel, TunnelState};
+pub use builder::{BusyHour, DemandMatrixBuilder, PairDemand, SiteMap};
+pub use demand::{TrafficDemand, DemandMatrix, DemandPredictor, DemandTracker, FlowStat};
+pub use path::{PathComputation, PathConstraints, ComputedPath, DisjointLevel, DisjointPaths};
+pub use optimizer::{
+    TrafficOptimizer, OptimizationObjective, OptimizationResult, WeightedObjective, ObjectiveOutcome, TradeOff,
+    SimulationScenario, SimulationResult, LinkProjection, FlowProjection,
+};
+pub use tunnel::{TunnelManager, Tunnel, TunnelState, Admission};
+#[cfg(feature = "sdwan")]
+pub use sdwan_feed::SdwanDemandFeed;
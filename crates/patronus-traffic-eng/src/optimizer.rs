@@ -1,18 +1,85 @@
 //! Traffic Optimization Engine
+//!
+//! [`TrafficOptimizer::optimize`] serves a single objective.
+//! [`TrafficOptimizer::optimize_weighted`] balances several: each flow's
+//! candidate paths are reduced to the Pareto-optimal ones, and the weights
+//! pick among those. The plan is then compared with plans optimized for
+//! each objective alone to report what was traded for what.
+//! [`TrafficOptimizer::simulate`] plans against a hypothetical demand
+//! matrix and failed links to preview the result before applying anything.
+//! With [`TrafficOptimizer::with_cost_hint`], a flow that has several paths
+//! within its service level takes the cheapest of them.
 
 use crate::demand::DemandMatrix;
 use crate::path::{PathComputation, PathConstraints, ComputedPath};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OptimizationObjective {
     MinimizeLatency,
     MaximizeThroughput,
     BalanceLoad,
+    /// Link prices set with [`PathComputation::set_link_price`]; hop count
+    /// when none are set
     MinimizeCost,
 }
 
+impl OptimizationObjective {
+    fn higher_is_better(&self) -> bool {
+        matches!(self, OptimizationObjective::MaximizeThroughput)
+    }
+}
+
+/// One objective of a multi-objective optimization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightedObjective {
+    pub objective: OptimizationObjective,
+    /// Relative importance; weights are normalized to sum to 1
+    pub weight: f64,
+}
+
+impl WeightedObjective {
+    pub fn new(objective: OptimizationObjective, weight: f64) -> Self {
+        Self { objective, weight }
+    }
+}
+
+/// How one objective fared in a weighted plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectiveOutcome {
+    pub objective: OptimizationObjective,
+    /// Normalized weight
+    pub weight: f64,
+    /// Value of the chosen plan, in the objective's own units
+    pub achieved: f64,
+    /// Best value of the plans optimized for a single objective
+    pub best: f64,
+    /// Worst value of the plans optimized for a single objective
+    pub worst: f64,
+    /// How much of the gap from `best` to `worst` was given up, 0 to 100
+    pub sacrificed_percent: f64,
+}
+
+/// What a weighted plan gave up, and for what
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeOff {
+    pub outcomes: Vec<ObjectiveOutcome>,
+    /// Objective pairs where optimizing either alone makes the other worse
+    pub conflicts: Vec<(OptimizationObjective, OptimizationObjective)>,
+}
+
+impl TradeOff {
+    pub fn outcome(&self, objective: OptimizationObjective) -> Option<&ObjectiveOutcome> {
+        self.outcomes.iter().find(|o| o.objective == objective)
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlowAllocation {
     pub source: String,
@@ -28,14 +95,90 @@ pub struct OptimizationResult {
     pub total_bandwidth_allocated: f64,
     pub average_path_length: f64,
     pub max_link_utilization: f64,
+    /// For a weighted plan, the weighted share of each objective's range
+    /// given up: 0 when every objective is at its best
     pub objective_value: f64,
     pub converged: bool,
+    /// Set for weighted plans
+    #[serde(default)]
+    pub trade_off: Option<TradeOff>,
+}
+
+/// A what-if question for [`TrafficOptimizer::simulate`]
+#[derive(Clone)]
+pub struct SimulationScenario<'a> {
+    /// Current demand, or a hypothetical one
+    pub demand: &'a DemandMatrix,
+    /// Links taken out of the topology, in both directions
+    pub failed_links: Vec<(String, String)>,
+}
+
+impl<'a> SimulationScenario<'a> {
+    pub fn new(demand: &'a DemandMatrix) -> Self {
+        Self {
+            demand,
+            failed_links: Vec::new(),
+        }
+    }
+
+    pub fn with_failed_link(mut self, from: String, to: String) -> Self {
+        self.failed_links.push((from, to));
+        self
+    }
 }
 
+/// Projected load of one link direction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkProjection {
+    pub from: String,
+    pub to: String,
+    pub capacity_mbps: f64,
+    pub allocated_mbps: f64,
+    pub utilization_percent: f64,
+    /// Taken out by the scenario; carries nothing
+    pub failed: bool,
+}
+
+/// Whether one demand would get its service level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowProjection {
+    pub source: String,
+    pub destination: String,
+    pub priority: u8,
+    pub bandwidth_mbps: f64,
+    /// None when the demand could not be placed
+    pub path: Option<Vec<String>>,
+    pub latency_ms: Option<f64>,
+    pub latency_budget_ms: f64,
+    /// Placed, within its latency budget, and on links below capacity
+    pub sla_met: bool,
+}
+
+/// Outcome of a what-if simulation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub plan: OptimizationResult,
+    pub links: Vec<LinkProjection>,
+    pub flows: Vec<FlowProjection>,
+    /// Share of demands whose SLA is met, 0 to 100
+    pub sla_compliance_percent: f64,
+    /// Demands placed on a different path than without the failures
+    pub rerouted: Vec<(String, String)>,
+}
+
+/// A demand waiting for a path: (source, destination, priority, bandwidth)
+type PendingFlow = (String, String, u8, f64);
+
+type LinkUsage = HashMap<(String, String), f64>;
+
 pub struct TrafficOptimizer {
     path_computation: PathComputation,
     objective: OptimizationObjective,
     max_iterations: usize,
+    candidate_paths: usize,
+    cost_hint: bool,
+    /// (source, destination) pairs that keep their best path regardless of price
+    cost_overrides: HashSet<(String, String)>,
 }
 
 impl TrafficOptimizer {
@@ -44,6 +187,9 @@ impl TrafficOptimizer {
             path_computation,
             objective,
             max_iterations: 100,
+            candidate_paths: 4,
+            cost_hint: false,
+            cost_overrides: HashSet::new(),
         }
     }
 
@@ -52,29 +198,47 @@ impl TrafficOptimizer {
         self
     }
 
-    /// Optimize traffic allocation based on demand matrix
-    pub fn optimize(&self, demand_matrix: &DemandMatrix) -> OptimizationResult {
-        let mut flows = Vec::new();
-        let mut link_usage: HashMap<(String, String), f64> = HashMap::new();
+    /// How many paths per flow a weighted optimization chooses between
+    pub fn with_candidate_paths(mut self, k: usize) -> Self {
+        self.candidate_paths = k.max(1);
+        self
+    }
 
-        // Get all source-destination pairs
-        let pairs = demand_matrix.get_all_pairs();
+    /// Prefer the cheapest of the paths that meet a flow's service level
+    ///
+    /// Applies to [`optimize`](Self::optimize): of up to `candidate_paths`
+    /// paths meeting the constraints and the flow's latency budget, the one
+    /// with the lowest [`path_price`](PathComputation::path_price) is taken.
+    /// Prices are set per link, e.g. from each cloud connection's effective
+    /// cost per GB.
+    pub fn with_cost_hint(mut self, enabled: bool) -> Self {
+        self.cost_hint = enabled;
+        self
+    }
 
-        // Sort by priority (high priority first)
-        let mut prioritized_pairs: Vec<_> = pairs.iter()
-            .filter_map(|(src, dst)| {
-                let demand = demand_matrix.get_current_demand(src, dst)?;
-                Some((src.clone(), dst.clone(), demand.priority, demand.bandwidth_mbps))
-            })
-            .collect();
+    /// Exempt demands from `source` to `destination` from the cost hint, so
+    /// they always take the best path for the objective
+    pub fn set_cost_override(&mut self, source: &str, destination: &str, overridden: bool) {
+        let pair = (source.to_string(), destination.to_string());
+        if overridden {
+            self.cost_overrides.insert(pair);
+        } else {
+            self.cost_overrides.remove(&pair);
+        }
+    }
 
-        prioritized_pairs.sort_by(|a, b| b.2.cmp(&a.2));
+    /// Optimize traffic allocation based on demand matrix
+    pub fn optimize(&self, demand_matrix: &DemandMatrix) -> OptimizationResult {
+        let mut flows = Vec::new();
+        let mut link_usage: LinkUsage = HashMap::new();
 
         // Allocate flows based on priority
-        for (source, destination, priority, bandwidth) in prioritized_pairs {
+        for (source, destination, priority, bandwidth) in Self::prioritized_flows(demand_matrix) {
             let constraints = self.build_constraints(priority, bandwidth);
 
-            if let Some(path) = self.path_computation.compute_path(&source, &destination, &constraints) {
+            if let Some(path) = self.cheapest_within_sla(&source, &destination, priority, &constraints)
+                .or_else(|| self.path_computation.compute_path(&source, &destination, &constraints))
+            {
                 if path.meets_constraints {
                     // Update link usage
                     for i in 0..path.hops.len().saturating_sub(1) {
@@ -93,7 +257,316 @@ impl TrafficOptimizer {
             }
         }
 
-        // Calculate metrics
+        let objective_value = self.calculate_objective_value(&flows);
+        self.build_result(flows, &link_usage, objective_value, None)
+    }
+
+    /// Optimize for several objectives at once
+    ///
+    /// Each flow, highest priority first, takes one of up to
+    /// `candidate_paths` paths with room for it. Paths beaten on every
+    /// objective by another candidate are discarded; of the rest, the one
+    /// with the lowest weighted sum of min-max normalized objective values
+    /// wins. The result's [`TradeOff`] compares the plan with those
+    /// optimized for each objective alone.
+    pub fn optimize_weighted(
+        &self,
+        demand_matrix: &DemandMatrix,
+        objectives: &[WeightedObjective],
+    ) -> Result<OptimizationResult> {
+        if objectives.is_empty() {
+            anyhow::bail!("Weighted optimization needs at least one objective");
+        }
+        for (i, weighted) in objectives.iter().enumerate() {
+            if !weighted.weight.is_finite() || weighted.weight < 0.0 {
+                anyhow::bail!("Weight of {:?} must be a non-negative number, got {}", weighted.objective, weighted.weight);
+            }
+            if objectives[..i].iter().any(|o| o.objective == weighted.objective) {
+                anyhow::bail!("Objective {:?} is listed more than once", weighted.objective);
+            }
+        }
+        let total_weight: f64 = objectives.iter().map(|o| o.weight).sum();
+        if total_weight <= 0.0 {
+            anyhow::bail!("Objective weights sum to zero");
+        }
+
+        let weights: Vec<(OptimizationObjective, f64)> = objectives
+            .iter()
+            .map(|o| (o.objective, o.weight / total_weight))
+            .collect();
+        let pending = Self::prioritized_flows(demand_matrix);
+        let (flows, link_usage) = self.plan_weighted(&pending, &weights);
+
+        // Plans for each objective alone bound what is achievable
+        let anchors: Vec<Vec<f64>> = weights
+            .iter()
+            .map(|(objective, _)| {
+                let (anchor_flows, _) = self.plan_weighted(&pending, &[(*objective, 1.0)]);
+                weights.iter().map(|(o, _)| self.oriented_value(o, &anchor_flows)).collect()
+            })
+            .collect();
+
+        let mut outcomes = Vec::with_capacity(weights.len());
+        let mut objective_value = 0.0;
+        for (i, (objective, weight)) in weights.iter().enumerate() {
+            let achieved = self.oriented_value(objective, &flows);
+            let best = anchors.iter().map(|a| a[i]).fold(f64::INFINITY, f64::min).min(achieved);
+            let worst = anchors.iter().map(|a| a[i]).fold(f64::NEG_INFINITY, f64::max).max(achieved);
+            let sacrificed = if worst - best > f64::EPSILON {
+                (achieved - best) / (worst - best)
+            } else {
+                0.0
+            };
+            objective_value += weight * sacrificed;
+
+            let natural = |v: f64| if objective.higher_is_better() { -v } else { v };
+            outcomes.push(ObjectiveOutcome {
+                objective: *objective,
+                weight: *weight,
+                achieved: natural(achieved),
+                best: natural(best),
+                worst: natural(worst),
+                sacrificed_percent: sacrificed * 100.0,
+            });
+        }
+
+        let mut conflicts = Vec::new();
+        for i in 0..weights.len() {
+            for j in i + 1..weights.len() {
+                if is_worse(anchors[i][j], anchors[j][j]) && is_worse(anchors[j][i], anchors[i][i]) {
+                    conflicts.push((weights[i].0, weights[j].0));
+                }
+            }
+        }
+
+        Ok(self.build_result(flows, &link_usage, objective_value, Some(TradeOff { outcomes, conflicts })))
+    }
+
+    /// Preview the plan for a scenario without changing anything
+    ///
+    /// Plans `scenario.demand` on a copy of the topology without the failed
+    /// links, using this optimizer's objective, and projects the load on
+    /// every link and each demand's SLA: placed, within the latency budget
+    /// of its priority, and on links with room. Nothing is applied; tunnels
+    /// are left as they are.
+    pub fn simulate(&self, scenario: &SimulationScenario) -> Result<SimulationResult> {
+        let mut path_computation = self.path_computation.clone();
+        for (from, to) in &scenario.failed_links {
+            if !path_computation.remove_link(from, to) {
+                anyhow::bail!("Link {} - {} is not in the topology", from, to);
+            }
+        }
+        let failed = |from: &str, to: &str| {
+            scenario.failed_links.iter().any(|(a, b)| (a == from && b == to) || (a == to && b == from))
+        };
+
+        let simulated = TrafficOptimizer {
+            path_computation,
+            objective: self.objective,
+            max_iterations: self.max_iterations,
+            candidate_paths: self.candidate_paths,
+            cost_hint: self.cost_hint,
+            cost_overrides: self.cost_overrides.clone(),
+        };
+        let plan = simulated.optimize(scenario.demand);
+        let intact = if scenario.failed_links.is_empty() {
+            plan.clone()
+        } else {
+            self.optimize(scenario.demand)
+        };
+
+        let mut link_usage: LinkUsage = HashMap::new();
+        for flow in &plan.flows {
+            for link in flow.path.windows(2) {
+                *link_usage.entry((link[0].clone(), link[1].clone())).or_insert(0.0) += flow.allocated_bandwidth;
+            }
+        }
+        let links: Vec<LinkProjection> = self
+            .path_computation
+            .links()
+            .into_iter()
+            .map(|(from, to, metrics)| {
+                let allocated = link_usage.get(&(from.to_string(), to.to_string())).copied().unwrap_or(0.0);
+                LinkProjection {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    capacity_mbps: metrics.bandwidth_mbps,
+                    allocated_mbps: allocated,
+                    utilization_percent: allocated / metrics.bandwidth_mbps * 100.0,
+                    failed: failed(from, to),
+                }
+            })
+            .collect();
+        let overloaded = |from: &str, to: &str| {
+            links.iter().any(|l| l.from == from && l.to == to && l.utilization_percent > 100.0)
+        };
+
+        let mut flows = Vec::new();
+        let mut rerouted = Vec::new();
+        for (source, destination, priority, bandwidth) in Self::prioritized_flows(scenario.demand) {
+            let placed = plan.flows.iter().find(|f| f.source == source && f.destination == destination);
+            let path = placed.map(|f| f.path.clone());
+            let latency = path.as_ref().map(|p| simulated.calculate_path_latency(p));
+            let budget = latency_budget_ms(priority);
+            let sla_met = match (&path, latency) {
+                (Some(path), Some(latency)) => {
+                    latency <= budget && !path.windows(2).any(|link| overloaded(&link[0], &link[1]))
+                }
+                _ => false,
+            };
+
+            let before = intact.flows.iter().find(|f| f.source == source && f.destination == destination);
+            if let (Some(before), Some(path)) = (before, &path) {
+                if &before.path != path {
+                    rerouted.push((source.clone(), destination.clone()));
+                }
+            }
+
+            flows.push(FlowProjection {
+                source,
+                destination,
+                priority,
+                bandwidth_mbps: bandwidth,
+                path,
+                latency_ms: latency,
+                latency_budget_ms: budget,
+                sla_met,
+            });
+        }
+
+        let sla_compliance_percent = if flows.is_empty() {
+            100.0
+        } else {
+            flows.iter().filter(|f| f.sla_met).count() as f64 / flows.len() as f64 * 100.0
+        };
+
+        Ok(SimulationResult {
+            plan,
+            links,
+            flows,
+            sla_compliance_percent,
+            rerouted,
+        })
+    }
+
+    /// Demands with a current measurement, highest priority first
+    fn prioritized_flows(demand_matrix: &DemandMatrix) -> Vec<PendingFlow> {
+        let mut pending: Vec<_> = demand_matrix
+            .get_all_pairs()
+            .iter()
+            .filter_map(|(src, dst)| {
+                let demand = demand_matrix.get_current_demand(src, dst)?;
+                Some((src.clone(), dst.clone(), demand.priority, demand.bandwidth_mbps))
+            })
+            .collect();
+
+        pending.sort_by_key(|p| std::cmp::Reverse(p.2));
+        pending
+    }
+
+    fn plan_weighted(
+        &self,
+        pending: &[PendingFlow],
+        weights: &[(OptimizationObjective, f64)],
+    ) -> (Vec<FlowAllocation>, LinkUsage) {
+        let mut flows = Vec::new();
+        let mut link_usage: LinkUsage = HashMap::new();
+
+        for (source, destination, priority, bandwidth) in pending {
+            let candidates: Vec<(ComputedPath, Vec<f64>)> = self
+                .path_computation
+                .compute_alternative_paths(source, destination, self.candidate_paths, &PathConstraints::new())
+                .into_iter()
+                .filter(|path| path.meets_constraints && self.headroom(&path.hops, *bandwidth, &link_usage) >= 0.0)
+                .map(|path| {
+                    let scores = weights
+                        .iter()
+                        .map(|(o, _)| self.candidate_score(o, &path.hops, *bandwidth, &link_usage))
+                        .collect();
+                    (path, scores)
+                })
+                .collect();
+
+            let Some(index) = choose_candidate(&candidates, weights) else {
+                tracing::debug!("No path with room for {} Mbps from {} to {}", bandwidth, source, destination);
+                continue;
+            };
+            let (path, _) = &candidates[index];
+
+            for link in path.hops.windows(2) {
+                *link_usage.entry((link[0].clone(), link[1].clone())).or_insert(0.0) += bandwidth;
+            }
+            flows.push(FlowAllocation {
+                source: source.clone(),
+                destination: destination.clone(),
+                path: path.hops.clone(),
+                allocated_bandwidth: *bandwidth,
+                priority: *priority,
+            });
+        }
+
+        (flows, link_usage)
+    }
+
+    /// Bandwidth left on the tightest link of `hops` after adding `bandwidth`
+    fn headroom(&self, hops: &[String], bandwidth: f64, link_usage: &LinkUsage) -> f64 {
+        hops.windows(2)
+            .map(|link| {
+                let used = link_usage
+                    .get(&(link[0].clone(), link[1].clone()))
+                    .copied()
+                    .unwrap_or(0.0);
+                self.path_computation
+                    .get_link(&link[0], &link[1])
+                    .map_or(f64::NEG_INFINITY, |metrics| metrics.available_bandwidth() - used - bandwidth)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// How good a path is for one flow on `objective`; lower is better
+    fn candidate_score(
+        &self,
+        objective: &OptimizationObjective,
+        hops: &[String],
+        bandwidth: f64,
+        link_usage: &LinkUsage,
+    ) -> f64 {
+        match objective {
+            OptimizationObjective::MinimizeLatency => self.calculate_path_latency(hops),
+            OptimizationObjective::MinimizeCost => self.path_computation.path_price(hops),
+            OptimizationObjective::BalanceLoad => hops
+                .windows(2)
+                .filter_map(|link| {
+                    let metrics = self.path_computation.get_link(&link[0], &link[1])?;
+                    let used = link_usage
+                        .get(&(link[0].clone(), link[1].clone()))
+                        .copied()
+                        .unwrap_or(0.0);
+                    Some((used + bandwidth) / metrics.bandwidth_mbps * 100.0)
+                })
+                .fold(0.0, f64::max),
+            // Prefer roomier paths, which leave space for later flows
+            OptimizationObjective::MaximizeThroughput => -self.headroom(hops, bandwidth, link_usage),
+        }
+    }
+
+    /// Plan value on `objective`, negated where higher is better
+    fn oriented_value(&self, objective: &OptimizationObjective, flows: &[FlowAllocation]) -> f64 {
+        let value = self.objective_value(objective, flows);
+        if objective.higher_is_better() {
+            -value
+        } else {
+            value
+        }
+    }
+
+    fn build_result(
+        &self,
+        flows: Vec<FlowAllocation>,
+        link_usage: &LinkUsage,
+        objective_value: f64,
+        trade_off: Option<TradeOff>,
+    ) -> OptimizationResult {
         let total_bandwidth = flows.iter().map(|f| f.allocated_bandwidth).sum();
         let avg_path_length = if !flows.is_empty() {
             flows.iter().map(|f| f.path.len()).sum::<usize>() as f64 / flows.len() as f64
@@ -101,8 +574,7 @@ impl TrafficOptimizer {
             0.0
         };
 
-        let max_link_util = self.calculate_max_link_utilization(&link_usage);
-        let objective_value = self.calculate_objective_value(&flows);
+        let max_link_util = self.calculate_max_link_utilization(link_usage);
 
         OptimizationResult {
             flows,
@@ -111,7 +583,34 @@ impl TrafficOptimizer {
             max_link_utilization: max_link_util,
             objective_value,
             converged: true,
+            trade_off,
+        }
+    }
+
+    /// With the cost hint on, the lowest-priced path meeting `constraints`
+    /// and the latency budget; ties go to the better path for the objective
+    fn cheapest_within_sla(
+        &self,
+        source: &str,
+        destination: &str,
+        priority: u8,
+        constraints: &PathConstraints,
+    ) -> Option<ComputedPath> {
+        if !self.cost_hint || self.cost_overrides.contains(&(source.to_string(), destination.to_string())) {
+            return None;
         }
+
+        let mut sla = constraints.clone();
+        let budget = latency_budget_ms(priority);
+        sla.max_latency_ms = Some(sla.max_latency_ms.map_or(budget, |limit| limit.min(budget)));
+
+        self.path_computation
+            .compute_alternative_paths(source, destination, self.candidate_paths, &sla)
+            .into_iter()
+            .filter(|path| path.meets_constraints)
+            .min_by(|a, b| {
+                self.path_computation.path_price(&a.hops).total_cmp(&self.path_computation.path_price(&b.hops))
+            })
     }
 
     fn build_constraints(&self, priority: u8, bandwidth: f64) -> PathConstraints {
@@ -119,10 +618,7 @@ impl TrafficOptimizer {
 
         match self.objective {
             OptimizationObjective::MinimizeLatency => {
-                constraints.max_latency_ms = Some(100.0);
-                if priority >= 5 {
-                    constraints.max_latency_ms = Some(50.0);
-                }
+                constraints.max_latency_ms = Some(latency_budget_ms(priority));
             }
             OptimizationObjective::MaximizeThroughput => {
                 constraints.min_bandwidth_mbps = Some(bandwidth);
@@ -153,7 +649,11 @@ impl TrafficOptimizer {
     }
 
     fn calculate_objective_value(&self, flows: &[FlowAllocation]) -> f64 {
-        match self.objective {
+        self.objective_value(&self.objective, flows)
+    }
+
+    fn objective_value(&self, objective: &OptimizationObjective, flows: &[FlowAllocation]) -> f64 {
+        match objective {
             OptimizationObjective::MinimizeLatency => {
                 // Average path latency (lower is better)
                 let total_latency: f64 = flows.iter()
@@ -184,15 +684,15 @@ impl TrafficOptimizer {
                 self.calculate_max_link_utilization(&link_usage)
             }
             OptimizationObjective::MinimizeCost => {
-                // Average hop count (lower is better)
-                let total_hops: usize = flows.iter()
-                    .map(|f| f.path.len().saturating_sub(1))
+                // Average path price (lower is better)
+                let total_price: f64 = flows.iter()
+                    .map(|f| self.path_computation.path_price(&f.path))
                     .sum();
 
                 if flows.is_empty() {
                     0.0
                 } else {
-                    total_hops as f64 / flows.len() as f64
+                    total_price / flows.len() as f64
                 }
             }
         }
@@ -236,6 +736,57 @@ impl TrafficOptimizer {
     }
 }
 
+/// Latency a demand of `priority` should see at most
+fn latency_budget_ms(priority: u8) -> f64 {
+    if priority >= 5 {
+        50.0
+    } else {
+        100.0
+    }
+}
+
+/// Index of the candidate to use: Pareto-optimal, then lowest weighted
+/// score with each objective min-max normalized across those candidates
+fn choose_candidate<T>(candidates: &[(T, Vec<f64>)], weights: &[(OptimizationObjective, f64)]) -> Option<usize> {
+    let dominates = |a: &[f64], b: &[f64]| {
+        a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+    };
+    let front: Vec<usize> = (0..candidates.len())
+        .filter(|&i| !candidates.iter().any(|(_, other)| dominates(other, &candidates[i].1)))
+        .collect();
+
+    let ranges: Vec<(f64, f64)> = (0..weights.len())
+        .map(|o| {
+            front.iter().map(|&i| candidates[i].1[o]).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            })
+        })
+        .collect();
+    let weighted = |scores: &[f64]| -> f64 {
+        scores
+            .iter()
+            .zip(weights)
+            .zip(&ranges)
+            .map(|((score, (_, weight)), (lo, hi))| {
+                if hi - lo > f64::EPSILON {
+                    weight * (score - lo) / (hi - lo)
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    };
+
+    front
+        .into_iter()
+        .min_by(|&a, &b| weighted(&candidates[a].1).total_cmp(&weighted(&candidates[b].1)))
+}
+
+/// `value` is measurably worse than `reference`, both lower-is-better
+fn is_worse(value: f64, reference: f64) -> bool {
+    value - reference > 1e-9 * reference.abs().max(1.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -413,4 +964,240 @@ mod tests {
         // Should calculate some utilization
         assert!(result.max_link_utilization >= 0.0);
     }
+
+    /// A -- C direct is cheap but slow; A -- B -- C is fast but priced
+    fn create_priced_optimizer() -> (TrafficOptimizer, DemandMatrix) {
+        let link = |latency_ms| LinkMetrics {
+            latency_ms,
+            bandwidth_mbps: 1000.0,
+            utilization_percent: 20.0,
+            loss_percent: 0.1,
+        };
+
+        let mut pc = PathComputation::new();
+        pc.add_link("A".to_string(), "C".to_string(), link(80.0));
+        pc.add_link("A".to_string(), "B".to_string(), link(10.0));
+        pc.add_link("B".to_string(), "C".to_string(), link(15.0));
+        pc.set_link_price("A".to_string(), "B".to_string(), 5.0);
+        pc.set_link_price("B".to_string(), "C".to_string(), 5.0);
+
+        let mut matrix = DemandMatrix::new(100);
+        matrix.add_demand(TrafficDemand::new("A".to_string(), "C".to_string(), 100.0, 5));
+
+        (TrafficOptimizer::new(pc, OptimizationObjective::MinimizeLatency), matrix)
+    }
+
+    fn plan_latency(optimizer: &TrafficOptimizer, result: &OptimizationResult) -> f64 {
+        optimizer.calculate_path_latency(&result.flows[0].path)
+    }
+
+    #[test]
+    fn test_weighted_shift_towards_cost() {
+        let (optimizer, matrix) = create_priced_optimizer();
+
+        let latency_first = optimizer
+            .optimize_weighted(&matrix, &[
+                WeightedObjective::new(OptimizationObjective::MinimizeLatency, 0.8),
+                WeightedObjective::new(OptimizationObjective::MinimizeCost, 0.2),
+            ])
+            .unwrap();
+        let cost_first = optimizer
+            .optimize_weighted(&matrix, &[
+                WeightedObjective::new(OptimizationObjective::MinimizeLatency, 0.2),
+                WeightedObjective::new(OptimizationObjective::MinimizeCost, 0.8),
+            ])
+            .unwrap();
+
+        assert_eq!(latency_first.flows[0].path, vec!["A", "B", "C"]);
+        assert_eq!(cost_first.flows[0].path, vec!["A", "C"]);
+
+        let price = |r: &OptimizationResult| optimizer.path_computation.path_price(&r.flows[0].path);
+        assert!(price(&cost_first) < price(&latency_first));
+        assert!(plan_latency(&optimizer, &cost_first) > plan_latency(&optimizer, &latency_first));
+
+        // The cheaper plan reports the latency it gave up
+        let trade_off = cost_first.trade_off.unwrap();
+        assert_eq!(
+            trade_off.conflicts,
+            vec![(OptimizationObjective::MinimizeLatency, OptimizationObjective::MinimizeCost)]
+        );
+        let latency = trade_off.outcome(OptimizationObjective::MinimizeLatency).unwrap();
+        assert_eq!(latency.achieved, 80.0);
+        assert_eq!(latency.best, 25.0);
+        assert_eq!(latency.sacrificed_percent, 100.0);
+        assert_eq!(trade_off.outcome(OptimizationObjective::MinimizeCost).unwrap().sacrificed_percent, 0.0);
+        assert!((cost_first.objective_value - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weighted_objectives_agree() {
+        let (optimizer, matrix) = create_test_optimizer();
+
+        let result = optimizer
+            .optimize_weighted(&matrix, &[
+                WeightedObjective::new(OptimizationObjective::MinimizeLatency, 1.0),
+                WeightedObjective::new(OptimizationObjective::MinimizeCost, 1.0),
+                WeightedObjective::new(OptimizationObjective::BalanceLoad, 1.0),
+            ])
+            .unwrap();
+
+        assert_eq!(result.flows.len(), 1);
+        let trade_off = result.trade_off.unwrap();
+        assert!(!trade_off.has_conflicts());
+        assert!(trade_off.outcomes.iter().all(|o| o.sacrificed_percent == 0.0));
+        assert!((trade_off.outcomes[0].weight - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(result.objective_value, 0.0);
+    }
+
+    #[test]
+    fn test_weighted_skips_full_paths() {
+        let (mut optimizer, mut matrix) = create_priced_optimizer();
+        optimizer.path_computation.add_link("D".to_string(), "A".to_string(), LinkMetrics {
+            latency_ms: 5.0,
+            bandwidth_mbps: 1000.0,
+            utilization_percent: 0.0,
+            loss_percent: 0.0,
+        });
+
+        // 800 Mbps is free on A -> C, and the first flow takes 100 of it
+        matrix.add_demand(TrafficDemand::new("D".to_string(), "C".to_string(), 750.0, 1));
+        let result = optimizer
+            .optimize_weighted(&matrix, &[WeightedObjective::new(OptimizationObjective::MinimizeCost, 1.0)])
+            .unwrap();
+
+        assert_eq!(result.flows.len(), 2);
+        assert_eq!(result.flows[0].path, vec!["A", "C"]);
+        assert_eq!(result.flows[1].path, vec!["D", "A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_weighted_rejects_bad_weights() {
+        let (optimizer, matrix) = create_test_optimizer();
+        let latency = |weight| WeightedObjective::new(OptimizationObjective::MinimizeLatency, weight);
+
+        assert!(optimizer.optimize_weighted(&matrix, &[]).is_err());
+        assert!(optimizer.optimize_weighted(&matrix, &[latency(-1.0)]).is_err());
+        assert!(optimizer.optimize_weighted(&matrix, &[latency(0.0)]).is_err());
+        assert!(optimizer.optimize_weighted(&matrix, &[latency(f64::NAN)]).is_err());
+        assert!(optimizer.optimize_weighted(&matrix, &[latency(1.0), latency(2.0)]).is_err());
+    }
+
+    /// A -- B -- C with a slower detour A -- D -- C
+    fn create_detour_optimizer() -> (TrafficOptimizer, DemandMatrix) {
+        let link = |latency_ms| LinkMetrics {
+            latency_ms,
+            bandwidth_mbps: 1000.0,
+            utilization_percent: 0.0,
+            loss_percent: 0.0,
+        };
+
+        let mut pc = PathComputation::new();
+        pc.add_link("A".to_string(), "B".to_string(), link(10.0));
+        pc.add_link("B".to_string(), "C".to_string(), link(10.0));
+        pc.add_link("A".to_string(), "D".to_string(), link(20.0));
+        pc.add_link("D".to_string(), "C".to_string(), link(20.0));
+
+        let mut matrix = DemandMatrix::new(100);
+        matrix.add_demand(TrafficDemand::new("A".to_string(), "C".to_string(), 400.0, 5));
+        matrix.add_demand(TrafficDemand::new("A".to_string(), "B".to_string(), 200.0, 2));
+
+        (TrafficOptimizer::new(pc, OptimizationObjective::MinimizeLatency), matrix)
+    }
+
+    fn projection<'a>(result: &'a SimulationResult, from: &str, to: &str) -> &'a LinkProjection {
+        result.links.iter().find(|l| l.from == from && l.to == to).unwrap()
+    }
+
+    #[test]
+    fn test_simulate_link_failure_reroutes() {
+        let (optimizer, matrix) = create_detour_optimizer();
+
+        let baseline = optimizer.simulate(&SimulationScenario::new(&matrix)).unwrap();
+        assert_eq!(projection(&baseline, "B", "C").utilization_percent, 40.0);
+        assert_eq!(projection(&baseline, "A", "D").allocated_mbps, 0.0);
+        assert!(baseline.rerouted.is_empty());
+        assert_eq!(baseline.sla_compliance_percent, 100.0);
+
+        let scenario = SimulationScenario::new(&matrix).with_failed_link("B".to_string(), "C".to_string());
+        let result = optimizer.simulate(&scenario).unwrap();
+
+        assert_eq!(result.rerouted, vec![("A".to_string(), "C".to_string())]);
+        let a_to_c = result.flows.iter().find(|f| f.destination == "C").unwrap();
+        assert_eq!(a_to_c.path.as_deref().unwrap(), ["A", "D", "C"]);
+        assert_eq!(a_to_c.latency_ms, Some(40.0));
+        assert!(a_to_c.sla_met);
+
+        assert!(projection(&result, "B", "C").failed);
+        assert!(projection(&result, "C", "B").failed);
+        assert_eq!(projection(&result, "B", "C").allocated_mbps, 0.0);
+        assert_eq!(projection(&result, "A", "D").utilization_percent, 40.0);
+        assert_eq!(projection(&result, "A", "B").utilization_percent, 20.0);
+
+        // The optimizer's own topology is untouched
+        assert!(optimizer.path_computation.get_link("B", "C").is_some());
+    }
+
+    #[test]
+    fn test_simulate_reports_sla_misses() {
+        let (optimizer, _) = create_detour_optimizer();
+
+        let mut matrix = DemandMatrix::new(100);
+        matrix.add_demand(TrafficDemand::new("A".to_string(), "C".to_string(), 800.0, 5));
+        matrix.add_demand(TrafficDemand::new("B".to_string(), "C".to_string(), 100.0, 6));
+
+        // A's way round the failure takes 40 ms, inside its budget
+        let scenario = SimulationScenario::new(&matrix).with_failed_link("A".to_string(), "B".to_string());
+        let result = optimizer.simulate(&scenario).unwrap();
+        assert_eq!(result.sla_compliance_percent, 100.0);
+
+        // With both of A's links down it can't be placed at all
+        let scenario = SimulationScenario::new(&matrix)
+            .with_failed_link("A".to_string(), "D".to_string())
+            .with_failed_link("A".to_string(), "B".to_string());
+        let result = optimizer.simulate(&scenario).unwrap();
+        let a_to_c = result.flows.iter().find(|f| f.source == "A").unwrap();
+        assert!(a_to_c.path.is_none());
+        assert!(!a_to_c.sla_met);
+        assert_eq!(result.sla_compliance_percent, 50.0);
+
+        let unknown = SimulationScenario::new(&matrix).with_failed_link("A".to_string(), "Z".to_string());
+        assert!(optimizer.simulate(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_cost_hint_prefers_cheaper_path_within_sla() {
+        let link = |latency_ms| LinkMetrics {
+            latency_ms,
+            bandwidth_mbps: 1000.0,
+            utilization_percent: 20.0,
+            loss_percent: 0.1,
+        };
+
+        // The direct link is the better path but a pricier connection
+        let mut pc = PathComputation::new();
+        pc.add_link("A".to_string(), "C".to_string(), link(30.0));
+        pc.add_link("A".to_string(), "B".to_string(), link(30.0));
+        pc.add_link("B".to_string(), "C".to_string(), link(40.0));
+        pc.set_link_price("A".to_string(), "C".to_string(), 10.0);
+
+        let demand = |priority| {
+            let mut matrix = DemandMatrix::new(100);
+            matrix.add_demand(TrafficDemand::new("A".to_string(), "C".to_string(), 100.0, priority));
+            matrix
+        };
+
+        let optimizer = TrafficOptimizer::new(pc, OptimizationObjective::MinimizeLatency);
+        assert_eq!(optimizer.optimize(&demand(3)).flows[0].path, vec!["A", "C"]);
+
+        // Both paths are within the 100 ms budget
+        let mut optimizer = optimizer.with_cost_hint(true);
+        assert_eq!(optimizer.optimize(&demand(3)).flows[0].path, vec!["A", "B", "C"]);
+
+        optimizer.set_cost_override("A", "C", true);
+        assert_eq!(optimizer.optimize(&demand(3)).flows[0].path, vec!["A", "C"]);
+        optimizer.set_cost_override("A", "C", false);
+
+        // At priority 5 the cheaper path misses its 50 ms budget
+        assert_eq!(optimizer.optimize(&demand(5)).flows[0].path, vec!["A", "C"]);
+    }
 }
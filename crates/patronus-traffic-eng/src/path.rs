@@ -88,6 +88,30 @@ impl ComputedPath {
     }
 }
 
+/// How far a backup path must stay away from the primary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisjointLevel {
+    /// No link carries both paths
+    Link,
+    /// No node besides source and destination is on both paths
+    Node,
+}
+
+/// A primary path and a backup kept apart from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisjointPaths {
+    pub primary: ComputedPath,
+    pub backup: ComputedPath,
+    pub level: DisjointLevel,
+    /// The topology does not allow fully disjoint paths; the two overlap
+    /// as little as possible
+    pub partially_disjoint: bool,
+    /// Links on both paths
+    pub shared_links: Vec<(String, String)>,
+    /// Nodes besides source and destination on both paths
+    pub shared_nodes: Vec<String>,
+}
+
 #[derive(Clone)]
 struct PathNode {
     node: String,
@@ -118,14 +142,18 @@ impl PartialOrd for PathNode {
     }
 }
 
+#[derive(Clone)]
 pub struct PathComputation {
     topology: HashMap<String, HashMap<String, LinkMetrics>>,
+    /// Price of carrying traffic over a link, relative to a default of 1.0
+    prices: HashMap<(String, String), f64>,
 }
 
 impl PathComputation {
     pub fn new() -> Self {
         Self {
             topology: HashMap::new(),
+            prices: HashMap::new(),
         }
     }
 
@@ -146,6 +174,44 @@ impl PathComputation {
         self.topology.get(from)?.get(to)
     }
 
+    /// Remove a link in both directions; returns whether it existed
+    pub fn remove_link(&mut self, from: &str, to: &str) -> bool {
+        let forward = self.topology.get_mut(from).and_then(|links| links.remove(to)).is_some();
+        let reverse = self.topology.get_mut(to).and_then(|links| links.remove(from)).is_some();
+        forward || reverse
+    }
+
+    /// Every link direction, sorted
+    pub fn links(&self) -> Vec<(&str, &str, &LinkMetrics)> {
+        let mut links: Vec<_> = self
+            .topology
+            .iter()
+            .flat_map(|(from, links)| links.iter().map(move |(to, metrics)| (from.as_str(), to.as_str(), metrics)))
+            .collect();
+        links.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        links
+    }
+
+    /// Set what using a link costs, e.g. 5.0 for an MPLS circuit next to
+    /// broadband at 1.0; applies in both directions
+    pub fn set_link_price(&mut self, from: String, to: String, price: f64) {
+        self.prices.insert((from.clone(), to.clone()), price);
+        self.prices.insert((to, from), price);
+    }
+
+    /// Price of a link, 1.0 unless set
+    pub fn link_price(&self, from: &str, to: &str) -> f64 {
+        self.prices
+            .get(&(from.to_string(), to.to_string()))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Sum of link prices along `hops`; the hop count when no prices are set
+    pub fn path_price(&self, hops: &[String]) -> f64 {
+        hops.windows(2).map(|pair| self.link_price(&pair[0], &pair[1])).sum()
+    }
+
     /// Compute shortest path using Dijkstra's algorithm with constraints
     pub fn compute_path(
         &self,
@@ -325,6 +391,188 @@ impl PathComputation {
         paths
     }
 
+    /// The best path plus, for each of its links, the best path avoiding
+    /// that link; up to `k` distinct paths, cheapest first
+    ///
+    /// Unlike [`compute_k_paths`](Self::compute_k_paths), alternatives may
+    /// share links with the best path, so a single uplink does not rule
+    /// them out.
+    pub fn compute_alternative_paths(
+        &self,
+        source: &str,
+        destination: &str,
+        k: usize,
+        constraints: &PathConstraints,
+    ) -> Vec<ComputedPath> {
+        let Some(best) = self.compute_path(source, destination, constraints) else {
+            return Vec::new();
+        };
+
+        let mut paths = vec![best.clone()];
+        for link in best.hops.windows(2) {
+            let excluded = HashSet::from([(link[0].clone(), link[1].clone())]);
+            if let Some(p) = self.compute_path_excluding(source, destination, constraints, &excluded) {
+                if !paths.iter().any(|known| known.hops == p.hops) {
+                    paths.push(p);
+                }
+            }
+        }
+
+        paths.sort_by(|a, b| a.total_cost.total_cmp(&b.total_cost));
+        paths.truncate(k);
+        paths
+    }
+
+    /// A primary path and a backup that shares no links (or, at
+    /// [`DisjointLevel::Node`], no transit nodes) with it
+    ///
+    /// The pair is chosen together, so a cheap primary cannot block the
+    /// only way around it. Where the topology cannot keep the paths fully
+    /// apart, they share as few links or nodes as possible and are marked
+    /// `partially_disjoint`. The cheaper path is the primary. `None` if
+    /// there is no path at all.
+    pub fn compute_disjoint(
+        &self,
+        source: &str,
+        destination: &str,
+        level: DisjointLevel,
+        constraints: &PathConstraints,
+    ) -> Option<DisjointPaths> {
+        if source == destination {
+            let path = self.compute_path(source, destination, constraints)?;
+            return Some(DisjointPaths {
+                primary: path.clone(),
+                backup: path,
+                level,
+                partially_disjoint: false,
+                shared_links: Vec::new(),
+                shared_nodes: Vec::new(),
+            });
+        }
+
+        let mut nodes: Vec<&String> = self.topology.keys().collect();
+        nodes.sort();
+        let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (n.as_str(), i)).collect();
+        let (&src, &dst) = (index.get(source)?, index.get(destination)?);
+
+        // Sharing anything costs more than any simple path, so overlap is
+        // only used when there is no other way
+        let penalty = 1.0 + self.topology.values().flat_map(|links| links.values()).map(LinkMetrics::cost).sum::<f64>();
+        // At node level each node is split into an entry and an exit vertex
+        // joined by an edge, so the node itself can only carry one path
+        let (entry, exit): (VertexOf, VertexOf) = match level {
+            DisjointLevel::Link => (|i: usize| i, |i: usize| i),
+            DisjointLevel::Node => (|i: usize| 2 * i, |i: usize| 2 * i + 1),
+        };
+        let vertices = match level {
+            DisjointLevel::Link => nodes.len(),
+            DisjointLevel::Node => 2 * nodes.len(),
+        };
+
+        let mut graph = FlowGraph::new(vertices);
+        if level == DisjointLevel::Node {
+            for i in 0..nodes.len() {
+                if i == src || i == dst {
+                    graph.add_edge(entry(i), exit(i), 2, 0.0);
+                } else {
+                    graph.add_edge(entry(i), exit(i), 1, 0.0);
+                    graph.add_edge(entry(i), exit(i), 1, penalty);
+                }
+            }
+        }
+        for (from, links) in &self.topology {
+            for (to, link) in links {
+                let allowed = |node: &String| node == source || node == destination || !constraints.excluded_nodes.contains(node);
+                let too_thin = constraints.min_bandwidth_mbps.is_some_and(|min| link.available_bandwidth() < min);
+                if !allowed(from) || !allowed(to) || too_thin {
+                    continue;
+                }
+                let (u, v) = (exit(index[from.as_str()]), entry(index[to.as_str()]));
+                graph.add_edge(u, v, 1, link.cost());
+                graph.add_edge(u, v, 1, link.cost() + penalty);
+            }
+        }
+
+        if !graph.augment(exit(src), entry(dst)) {
+            return None;
+        }
+        // A second unit always fits through the penalty edges
+        graph.augment(exit(src), entry(dst));
+
+        let mut paths: Vec<ComputedPath> = (0..2)
+            .map(|_| {
+                let mut hops: Vec<String> = graph
+                    .take_path(exit(src), entry(dst))
+                    .into_iter()
+                    .map(|v| match level {
+                        DisjointLevel::Link => nodes[v].clone(),
+                        DisjointLevel::Node => nodes[v / 2].clone(),
+                    })
+                    .collect();
+                hops.dedup();
+                self.path_from_hops(hops, constraints)
+            })
+            .collect();
+        paths.sort_by(|a, b| a.total_cost.total_cmp(&b.total_cost));
+        let backup = paths.pop()?;
+        let primary = paths.pop()?;
+
+        let links = |path: &ComputedPath| -> HashSet<(String, String)> {
+            path.hops
+                .windows(2)
+                .map(|pair| if pair[0] <= pair[1] {
+                    (pair[0].clone(), pair[1].clone())
+                } else {
+                    (pair[1].clone(), pair[0].clone())
+                })
+                .collect()
+        };
+        let backup_links = links(&backup);
+        let mut shared_links: Vec<(String, String)> = links(&primary).into_iter().filter(|l| backup_links.contains(l)).collect();
+        shared_links.sort();
+
+        let transit = |path: &ComputedPath| -> HashSet<String> {
+            path.hops[1..path.hops.len() - 1].iter().cloned().collect()
+        };
+        let backup_nodes = transit(&backup);
+        let mut shared_nodes: Vec<String> = transit(&primary).into_iter().filter(|n| backup_nodes.contains(n)).collect();
+        shared_nodes.sort();
+
+        let partially_disjoint = !shared_links.is_empty() || (level == DisjointLevel::Node && !shared_nodes.is_empty());
+        Some(DisjointPaths {
+            primary,
+            backup,
+            level,
+            partially_disjoint,
+            shared_links,
+            shared_nodes,
+        })
+    }
+
+    /// Metrics of a path given by its hops
+    fn path_from_hops(&self, hops: Vec<String>, constraints: &PathConstraints) -> ComputedPath {
+        let mut latency = 0.0;
+        let mut min_bandwidth = f64::MAX;
+        let mut cost = 0.0;
+        for pair in hops.windows(2) {
+            if let Some(link) = self.get_link(&pair[0], &pair[1]) {
+                latency += link.latency_ms;
+                min_bandwidth = min_bandwidth.min(link.available_bandwidth());
+                cost += link.cost();
+            }
+        }
+        let meets = self.check_constraints(&hops, latency, min_bandwidth, constraints);
+
+        ComputedPath {
+            max_utilization: self.calculate_max_utilization(&hops),
+            hops,
+            total_latency_ms: latency,
+            min_bandwidth_mbps: min_bandwidth,
+            total_cost: cost,
+            meets_constraints: meets,
+        }
+    }
+
     fn compute_path_excluding(
         &self,
         source: &str,
@@ -398,6 +646,97 @@ impl PathComputation {
     }
 }
 
+/// Residual edge of the two-unit flow behind [`PathComputation::compute_disjoint`]
+struct FlowEdge {
+    to: usize,
+    /// Capacity before any flow; 0 for reverse edges
+    original: u8,
+    capacity: u8,
+    cost: f64,
+    reverse: usize,
+}
+
+/// Flow graph vertex for a node index
+type VertexOf = fn(usize) -> usize;
+
+struct FlowGraph {
+    edges: Vec<Vec<FlowEdge>>,
+}
+
+impl FlowGraph {
+    fn new(vertices: usize) -> Self {
+        Self {
+            edges: (0..vertices).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, capacity: u8, cost: f64) {
+        let forward = self.edges[from].len();
+        let reverse = self.edges[to].len() + usize::from(from == to);
+        self.edges[from].push(FlowEdge { to, original: capacity, capacity, cost, reverse });
+        self.edges[to].push(FlowEdge { to: from, original: 0, capacity: 0, cost: -cost, reverse: forward });
+    }
+
+    /// Push one unit along the cheapest residual path; false if there is none
+    fn augment(&mut self, source: usize, sink: usize) -> bool {
+        // Bellman-Ford, as cancelling flow makes residual costs negative
+        let n = self.edges.len();
+        let mut dist = vec![f64::INFINITY; n];
+        let mut prev: Vec<Option<(usize, usize)>> = vec![None; n];
+        dist[source] = 0.0;
+        for _ in 0..n {
+            let mut changed = false;
+            for from in 0..n {
+                if dist[from].is_infinite() {
+                    continue;
+                }
+                for (i, edge) in self.edges[from].iter().enumerate() {
+                    let next = dist[from] + edge.cost;
+                    if edge.capacity > 0 && next < dist[edge.to] - 1e-9 {
+                        dist[edge.to] = next;
+                        prev[edge.to] = Some((from, i));
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        if dist[sink].is_infinite() {
+            return false;
+        }
+
+        let mut vertex = sink;
+        while let Some((from, i)) = prev[vertex] {
+            self.edges[from][i].capacity -= 1;
+            let reverse = self.edges[from][i].reverse;
+            self.edges[vertex][reverse].capacity += 1;
+            vertex = from;
+        }
+        true
+    }
+
+    /// Take one unit of flow from `source` to `sink`, as vertices visited
+    fn take_path(&mut self, source: usize, sink: usize) -> Vec<usize> {
+        let mut path = vec![source];
+        let mut vertex = source;
+        while vertex != sink {
+            let Some(edge) = self.edges[vertex].iter_mut().find(|e| e.original > e.capacity) else {
+                break;
+            };
+            edge.capacity += 1;
+            vertex = edge.to;
+            // Cut out any loop the flow closed
+            if let Some(seen) = path.iter().position(|v| *v == vertex) {
+                path.truncate(seen);
+            }
+            path.push(vertex);
+        }
+        path
+    }
+}
+
 impl Default for PathComputation {
     fn default() -> Self {
         Self::new()
@@ -594,4 +933,103 @@ mod tests {
         let no_link = pc.get_link("A", "D");
         assert!(no_link.is_none());
     }
+
+    #[test]
+    fn test_compute_alternative_paths() {
+        let pc = create_test_topology();
+        let constraints = PathConstraints::new();
+
+        let paths = pc.compute_alternative_paths("A", "D", 4, &constraints);
+
+        // Every route to D goes through B, which rules out k-paths' second pick
+        assert_eq!(pc.compute_k_paths("A", "D", 4, &constraints).len(), 1);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].hops, vec!["A", "B", "D"]);
+        assert_eq!(paths[1].hops, vec!["A", "C", "B", "D"]);
+        assert!(paths[0].total_cost <= paths[1].total_cost);
+    }
+
+    #[test]
+    fn test_path_price() {
+        let mut pc = create_test_topology();
+        let path = vec!["A".to_string(), "B".to_string(), "D".to_string()];
+
+        // Unpriced links count one each
+        assert_eq!(pc.path_price(&path), 2.0);
+
+        pc.set_link_price("D".to_string(), "B".to_string(), 4.0);
+        assert_eq!(pc.link_price("B", "D"), 4.0);
+        assert_eq!(pc.path_price(&path), 5.0);
+    }
+
+    fn plain_link(latency_ms: f64) -> LinkMetrics {
+        LinkMetrics {
+            latency_ms,
+            bandwidth_mbps: 1000.0,
+            utilization_percent: 0.0,
+            loss_percent: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_compute_disjoint_avoids_trap() {
+        // The shortest path A-B-C-D uses a link of each disjoint route, so
+        // removing it and searching again finds no backup
+        let mut pc = PathComputation::new();
+        pc.add_link("A".to_string(), "B".to_string(), plain_link(1.0));
+        pc.add_link("B".to_string(), "C".to_string(), plain_link(1.0));
+        pc.add_link("C".to_string(), "D".to_string(), plain_link(1.0));
+        pc.add_link("A".to_string(), "C".to_string(), plain_link(5.0));
+        pc.add_link("B".to_string(), "D".to_string(), plain_link(5.0));
+
+        let pair = pc.compute_disjoint("A", "D", DisjointLevel::Link, &PathConstraints::new()).unwrap();
+        assert!(!pair.partially_disjoint);
+        assert!(pair.shared_links.is_empty());
+        let mut routes = vec![pair.primary.hops.clone(), pair.backup.hops.clone()];
+        routes.sort();
+        assert_eq!(routes, vec![vec!["A", "B", "D"], vec!["A", "C", "D"]]);
+        assert_eq!(pair.primary.total_latency_ms, 6.0);
+    }
+
+    #[test]
+    fn test_compute_disjoint_falls_back_to_partial() {
+        // Every route to D ends on B-D
+        let pc = create_test_topology();
+
+        let pair = pc.compute_disjoint("A", "D", DisjointLevel::Link, &PathConstraints::new()).unwrap();
+        assert!(pair.partially_disjoint);
+        assert_eq!(pair.shared_links, vec![("B".to_string(), "D".to_string())]);
+        assert_eq!(pair.primary.hops, vec!["A", "B", "D"]);
+        assert_eq!(pair.backup.hops, vec!["A", "C", "B", "D"]);
+
+        assert!(pc.compute_disjoint("A", "E", DisjointLevel::Link, &PathConstraints::new()).is_none());
+    }
+
+    #[test]
+    fn test_compute_disjoint_node_level() {
+        // Two link-disjoint routes that both pass through X
+        let mut pc = PathComputation::new();
+        pc.add_link("A".to_string(), "X".to_string(), plain_link(1.0));
+        pc.add_link("X".to_string(), "D".to_string(), plain_link(1.0));
+        pc.add_link("A".to_string(), "Y".to_string(), plain_link(1.0));
+        pc.add_link("Y".to_string(), "X".to_string(), plain_link(1.0));
+        pc.add_link("X".to_string(), "Z".to_string(), plain_link(1.0));
+        pc.add_link("Z".to_string(), "D".to_string(), plain_link(1.0));
+
+        let by_link = pc.compute_disjoint("A", "D", DisjointLevel::Link, &PathConstraints::new()).unwrap();
+        assert!(!by_link.partially_disjoint);
+        assert_eq!(by_link.shared_nodes, vec!["X"]);
+
+        let by_node = pc.compute_disjoint("A", "D", DisjointLevel::Node, &PathConstraints::new()).unwrap();
+        assert!(by_node.partially_disjoint);
+        assert_eq!(by_node.shared_nodes, vec!["X"]);
+        assert!(by_node.shared_links.is_empty());
+
+        // A second way around X makes full node disjointness possible
+        pc.add_link("Y".to_string(), "Z".to_string(), plain_link(3.0));
+        let by_node = pc.compute_disjoint("A", "D", DisjointLevel::Node, &PathConstraints::new()).unwrap();
+        assert!(!by_node.partially_disjoint);
+        assert_eq!(by_node.primary.hops, vec!["A", "X", "D"]);
+        assert_eq!(by_node.backup.hops, vec!["A", "Y", "Z", "D"]);
+    }
 }
@@ -0,0 +1,155 @@
+//! SD-WAN demand feed
+//!
+//! Polls the SD-WAN traffic statistics and turns each flow's bytes since
+//! the previous poll into a [`FlowStat`] between the sites of its
+//! endpoints, ready for a [`DemandMatrixBuilder`].
+
+use chrono::{DateTime, Utc};
+use patronus_sdwan::traffic_stats::{FlowStats, TrafficStatsCollector};
+use patronus_sdwan::FlowKey;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::builder::{DemandMatrixBuilder, SiteMap};
+use crate::demand::FlowStat;
+
+/// Feeds SD-WAN traffic statistics into a [`DemandMatrixBuilder`]
+pub struct SdwanDemandFeed {
+    traffic_stats: Arc<TrafficStatsCollector>,
+    sites: SiteMap,
+    /// Application class of the traffic each routing policy matches
+    policy_classes: HashMap<u64, String>,
+    /// Byte counters at the previous poll
+    last_seen: HashMap<FlowKey, u64>,
+    last_poll: Option<DateTime<Utc>>,
+}
+
+impl SdwanDemandFeed {
+    pub fn new(traffic_stats: Arc<TrafficStatsCollector>, sites: SiteMap) -> Self {
+        Self {
+            traffic_stats,
+            sites,
+            policy_classes: HashMap::new(),
+            last_seen: HashMap::new(),
+            last_poll: None,
+        }
+    }
+
+    /// Count traffic matched by `policy_id` as application class `class`
+    pub fn with_policy_class(mut self, policy_id: u64, class: impl Into<String>) -> Self {
+        self.policy_classes.insert(policy_id, class.into());
+        self
+    }
+
+    /// Flows that carried traffic between sites since the last poll
+    pub async fn collect(&mut self) -> Vec<FlowStat> {
+        self.collect_at(Utc::now()).await
+    }
+
+    /// Collect and ingest the flows as one collection
+    ///
+    /// Returns how many flows the builder accepted.
+    pub async fn poll(&mut self, builder: &mut DemandMatrixBuilder) -> usize {
+        let now = Utc::now();
+        let flows = self.collect_at(now).await;
+        builder.ingest(now, &flows)
+    }
+
+    async fn collect_at(&mut self, now: DateTime<Utc>) -> Vec<FlowStat> {
+        let active = self.traffic_stats.get_active_flows().await;
+        let mut flows = Vec::new();
+
+        let mut current = HashMap::with_capacity(active.len());
+        for stats in active {
+            let previous = self.last_seen.get(&stats.flow_key).copied().unwrap_or(0);
+            current.insert(stats.flow_key, stats.bytes);
+
+            let bytes = stats.bytes.saturating_sub(previous);
+            if bytes == 0 {
+                continue;
+            }
+            if let Some(flow) = self.to_flow_stat(&stats, bytes, now) {
+                flows.push(flow);
+            }
+        }
+
+        // Forget flows the traffic stats have expired
+        self.last_seen = current;
+        self.last_poll = Some(now);
+        flows
+    }
+
+    /// Convert a flow's bytes since the last poll into a flow between sites
+    fn to_flow_stat(&self, stats: &FlowStats, bytes: u64, now: DateTime<Utc>) -> Option<FlowStat> {
+        let key = &stats.flow_key;
+        let since = self.last_poll.unwrap_or_else(|| DateTime::<Utc>::from(stats.first_seen));
+        let interval_secs = (now - since).num_milliseconds() as f64 / 1000.0;
+
+        let flow = self.sites
+            .flow(key.src_ip, key.dst_ip, bytes, interval_secs.max(1.0), 0)?
+            .with_observed_at(DateTime::<Utc>::from(stats.last_seen));
+        Some(match self.policy_classes.get(&stats.policy_id) {
+            Some(class) => flow.with_app_class(class.clone()),
+            None => flow,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn flow_key(src: &str, dst: &str) -> FlowKey {
+        FlowKey {
+            src_ip: src.parse().unwrap(),
+            dst_ip: dst.parse().unwrap(),
+            src_port: 40000,
+            dst_port: 443,
+            protocol: 6,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_feeds_deltas_between_sites() {
+        let traffic_stats = Arc::new(TrafficStatsCollector::new(None));
+        let mut sites = SiteMap::new();
+        sites.insert("10.1.0.0".parse().unwrap(), 16, "hq").unwrap();
+        sites.insert("10.2.0.0".parse().unwrap(), 16, "branch").unwrap();
+        let mut feed = SdwanDemandFeed::new(traffic_stats.clone(), sites).with_policy_class(7, "video");
+        let mut builder = DemandMatrixBuilder::new(Duration::from_secs(3600));
+
+        let video = flow_key("10.1.0.5", "10.2.0.9");
+        let web = flow_key("10.2.0.9", "10.1.0.5");
+        let internet = flow_key("10.1.0.5", "8.8.8.8");
+        for _ in 0..4 {
+            traffic_stats.record_packet(7, video, 1_500).await;
+        }
+        traffic_stats.record_packet(1, web, 500).await;
+        traffic_stats.record_packet(1, internet, 9_000).await;
+
+        // Both collections land in the same interval
+        let now = Utc::now();
+
+        // Traffic leaving every known site is not site-to-site demand
+        let flows = feed.collect_at(now).await;
+        assert_eq!(builder.ingest(now, &flows), 2);
+
+        // Only what a flow carried since the last poll is fed again
+        traffic_stats.record_packet(7, video, 1_000).await;
+        let flows = feed.collect_at(now).await;
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].bytes, 1_000);
+        assert_eq!(flows[0].app_class.as_deref(), Some("video"));
+        builder.ingest(now, &flows);
+        assert!(feed.collect().await.is_empty());
+
+        let hq = &builder.demands(None)[1];
+        assert_eq!((hq.source.as_str(), hq.destination.as_str()), ("hq", "branch"));
+        assert!((hq.peak_mbps - 7_000.0 * 8.0 / 3600.0 / 1_000_000.0).abs() < 1e-12);
+        assert_eq!(builder.app_classes(), vec!["video"]);
+        let video = builder.demands(Some("video"));
+        assert_eq!(video.len(), 1);
+        assert_eq!(video[0].source, "hq");
+    }
+}
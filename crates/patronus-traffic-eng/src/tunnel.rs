@@ -1,7 +1,13 @@
 //! Tunnel Management for Traffic Engineering
+//!
+//! Tunnels admitted with [`TunnelManager::admit_tunnel`] reserve their
+//! bandwidth on every link of their path. When a link is full, a tunnel
+//! may preempt tunnels whose holding priority is below its own priority,
+//! RSVP-TE style: the preempted tunnels release their bandwidth and are
+//! rerouted over what capacity is left, or stay down.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
@@ -12,6 +18,8 @@ pub enum TunnelState {
     Down,
     Up,
     Degraded,
+    /// Torn down by a higher-priority tunnel and holding no bandwidth
+    Preempted,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,7 +82,15 @@ pub struct Tunnel {
     pub bandwidth_mbps: f64,
     pub reserved_bandwidth: f64,
     pub state: TunnelState,
+    /// Setup priority; higher wins
     pub priority: u8,
+    /// How hard the tunnel holds on to its bandwidth once admitted; it can
+    /// be preempted by tunnels with a higher priority
+    #[serde(default)]
+    pub holding_priority: u8,
+    /// Tunnel that took this one's bandwidth
+    #[serde(default)]
+    pub preempted_by: Option<Uuid>,
     pub metrics: TunnelMetrics,
     pub created_at: DateTime<Utc>,
 }
@@ -98,11 +114,22 @@ impl Tunnel {
             reserved_bandwidth: bandwidth_mbps,
             state: TunnelState::Down,
             priority,
+            holding_priority: priority,
+            preempted_by: None,
             metrics: TunnelMetrics::new(),
             created_at: Utc::now(),
         }
     }
 
+    /// Hold bandwidth at a different priority than the one set up with
+    ///
+    /// Holding below the setup priority lets tunnels preempt each other in
+    /// turn, so it is raised to at least the setup priority.
+    pub fn with_holding_priority(mut self, holding_priority: u8) -> Self {
+        self.holding_priority = holding_priority.max(self.priority);
+        self
+    }
+
     pub fn bring_up(&mut self) {
         self.state = TunnelState::Up;
     }
@@ -123,6 +150,11 @@ impl Tunnel {
         self.path.len().saturating_sub(1)
     }
 
+    /// Links the tunnel reserves bandwidth on
+    fn links(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.path.windows(2).map(|hop| (hop[0].clone(), hop[1].clone()))
+    }
+
     pub fn utilization_percent(&self) -> f64 {
         if self.reserved_bandwidth == 0.0 {
             return 0.0;
@@ -135,17 +167,138 @@ impl Tunnel {
     }
 }
 
+/// Result of admitting a tunnel
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Admission {
+    pub tunnel_id: Uuid,
+    /// Tunnels torn down to make room
+    pub preempted: Vec<Uuid>,
+    /// Preempted tunnels that found another path
+    pub rerouted: Vec<Uuid>,
+}
+
 pub struct TunnelManager {
     tunnels: Arc<RwLock<HashMap<Uuid, Tunnel>>>,
+    /// Capacity of each link direction in Mbps; links without one are not
+    /// admission controlled
+    capacities: Arc<RwLock<HashMap<(String, String), f64>>>,
 }
 
 impl TunnelManager {
     pub fn new() -> Self {
         Self {
             tunnels: Arc::new(RwLock::new(HashMap::new())),
+            capacities: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Set the capacity tunnels can reserve on a link, in each direction
+    pub async fn set_link_capacity(&self, from: String, to: String, capacity_mbps: f64) {
+        let mut capacities = self.capacities.write().await;
+        capacities.insert((from.clone(), to.clone()), capacity_mbps);
+        capacities.insert((to, from), capacity_mbps);
+    }
+
+    /// Capacity of a link direction left after every reservation
+    pub async fn residual_capacity(&self, from: &str, to: &str) -> Option<f64> {
+        let capacities = self.capacities.read().await;
+        let tunnels = self.tunnels.read().await;
+        let capacity = capacities.get(&(from.to_string(), to.to_string()))?;
+        let reserved = reservations(&tunnels, &HashSet::new());
+        Some(capacity - reserved.get(&(from.to_string(), to.to_string())).copied().unwrap_or(0.0))
+    }
+
+    /// Admit `tunnel`, reserving its bandwidth along its path
+    ///
+    /// Where a link lacks room, tunnels holding it at a lower priority than
+    /// the new tunnel's are preempted, lowest first, as few as needed.
+    /// Preempted tunnels are rerouted onto the shortest path with enough
+    /// capacity left, without preempting anything themselves, and otherwise
+    /// stay [`TunnelState::Preempted`]. Fails, changing nothing, if even
+    /// preempting every eligible tunnel leaves too little room.
+    pub async fn admit_tunnel(&self, mut tunnel: Tunnel) -> anyhow::Result<Admission> {
+        let capacities = self.capacities.read().await;
+        let mut tunnels = self.tunnels.write().await;
+
+        let residual = |tunnels: &HashMap<Uuid, Tunnel>, excluded: &HashSet<Uuid>| -> HashMap<(String, String), f64> {
+            let reserved = reservations(tunnels, excluded);
+            capacities.iter()
+                .map(|(link, capacity)| (link.clone(), capacity - reserved.get(link).copied().unwrap_or(0.0)))
+                .collect()
+        };
+        let fits = |residual: &HashMap<(String, String), f64>, tunnel: &Tunnel| {
+            tunnel.links().all(|link| residual.get(&link).is_none_or(|left| *left >= tunnel.reserved_bandwidth))
+        };
+
+        // Tunnels that could give way, lowest holding priority and most
+        // recent first
+        let path_links: HashSet<(String, String)> = tunnel.links().collect();
+        let mut candidates: Vec<&Tunnel> = tunnels.values()
+            .filter(|t| t.state != TunnelState::Preempted && t.holding_priority < tunnel.priority)
+            .filter(|t| t.links().any(|link| path_links.contains(&link)))
+            .collect();
+        candidates.sort_by(|a, b| a.holding_priority.cmp(&b.holding_priority).then(b.created_at.cmp(&a.created_at)));
+
+        let mut preempted: Vec<Uuid> = Vec::new();
+        let mut excluded = HashSet::new();
+        for candidate in candidates {
+            if fits(&residual(&tunnels, &excluded), &tunnel) {
+                break;
+            }
+            let starved: HashSet<(String, String)> = {
+                let left = residual(&tunnels, &excluded);
+                tunnel.links().filter(|link| left.get(link).is_some_and(|l| *l < tunnel.reserved_bandwidth)).collect()
+            };
+            if candidate.links().any(|link| starved.contains(&link)) {
+                excluded.insert(candidate.id);
+                preempted.push(candidate.id);
+            }
+        }
+        if !fits(&residual(&tunnels, &excluded), &tunnel) {
+            anyhow::bail!(
+                "Not enough capacity for tunnel {} ({} Mbps) on {:?}, even with preemption",
+                tunnel.name, tunnel.reserved_bandwidth, tunnel.path
+            );
+        }
+
+        // Spare tunnels a later, larger preemption made unnecessary
+        for id in preempted.clone().iter().rev() {
+            excluded.remove(id);
+            if fits(&residual(&tunnels, &excluded), &tunnel) {
+                preempted.retain(|p| p != id);
+            } else {
+                excluded.insert(*id);
+            }
+        }
+
+        let tunnel_id = tunnel.id;
+        tunnel.preempted_by = None;
+        tracing::info!("Admitted tunnel {} on {:?}, preempting {} tunnel(s)", tunnel.name, tunnel.path, preempted.len());
+        tunnels.insert(tunnel_id, tunnel);
+
+        let mut rerouted = Vec::new();
+        for id in &preempted {
+            let victim = tunnels.get_mut(id).expect("preempted tunnels exist");
+            victim.state = TunnelState::Preempted;
+            victim.preempted_by = Some(tunnel_id);
+            let victim = victim.clone();
+
+            let left = residual(&tunnels, &HashSet::new());
+            match shortest_path_with_room(&left, &victim.source, &victim.destination, victim.reserved_bandwidth) {
+                Some(path) => {
+                    tracing::info!("Preempted tunnel {} rerouted over {:?}", victim.name, path);
+                    let tunnel = tunnels.get_mut(id).expect("preempted tunnels exist");
+                    tunnel.path = path;
+                    tunnel.state = TunnelState::Down;
+                    rerouted.push(*id);
+                }
+                None => tracing::warn!("Preempted tunnel {} found no other path", victim.name),
+            }
+        }
+
+        Ok(Admission { tunnel_id, preempted, rerouted })
+    }
+
     pub async fn create_tunnel(
         &self,
         name: String,
@@ -310,6 +463,55 @@ impl TunnelManager {
     }
 }
 
+/// Bandwidth reserved on each link direction by tunnels not in `excluded`
+fn reservations(tunnels: &HashMap<Uuid, Tunnel>, excluded: &HashSet<Uuid>) -> HashMap<(String, String), f64> {
+    let mut reserved = HashMap::new();
+    for tunnel in tunnels.values() {
+        if tunnel.state == TunnelState::Preempted || excluded.contains(&tunnel.id) {
+            continue;
+        }
+        for link in tunnel.links() {
+            *reserved.entry(link).or_insert(0.0) += tunnel.reserved_bandwidth;
+        }
+    }
+    reserved
+}
+
+/// Fewest-hop path over capacity-controlled links with `bandwidth` left
+fn shortest_path_with_room(
+    residual: &HashMap<(String, String), f64>,
+    source: &str,
+    destination: &str,
+    bandwidth: f64,
+) -> Option<Vec<String>> {
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([source]);
+    let mut seen = HashSet::from([source]);
+    while let Some(node) = queue.pop_front() {
+        if node == destination {
+            let mut path = vec![destination.to_string()];
+            let mut at = destination;
+            while let Some(prev) = previous.get(at) {
+                path.push(prev.to_string());
+                at = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let mut next: Vec<&str> = residual.iter()
+            .filter(|((from, to), left)| from == node && **left >= bandwidth && !seen.contains(to.as_str()))
+            .map(|((_, to), _)| to.as_str())
+            .collect();
+        next.sort();
+        for to in next {
+            seen.insert(to);
+            previous.insert(to, node);
+            queue.push_back(to);
+        }
+    }
+    None
+}
+
 impl Default for TunnelManager {
     fn default() -> Self {
         Self::new()
@@ -713,4 +915,76 @@ mod tests {
         let tunnel = manager.get_tunnel(&id).await.unwrap();
         assert_eq!(tunnel.reserved_bandwidth, 1500.0);
     }
+
+    fn hops(nodes: &[&str]) -> Vec<String> {
+        nodes.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn tunnel(name: &str, path: &[&str], bandwidth_mbps: f64, priority: u8) -> Tunnel {
+        Tunnel::new(
+            name.to_string(),
+            path[0].to_string(),
+            path[path.len() - 1].to_string(),
+            hops(path),
+            bandwidth_mbps,
+            priority,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_preemption_of_lowest_priority_tunnel() {
+        let manager = TunnelManager::new();
+        manager.set_link_capacity("A".to_string(), "B".to_string(), 1000.0).await;
+
+        let bulk = manager.admit_tunnel(tunnel("bulk", &["A", "B"], 400.0, 1)).await.unwrap();
+        let video = manager.admit_tunnel(tunnel("video", &["A", "B"], 400.0, 3)).await.unwrap();
+        let backup = manager.admit_tunnel(tunnel("backup", &["A", "B"], 200.0, 2)).await.unwrap();
+        assert_eq!(manager.residual_capacity("A", "B").await, Some(0.0));
+        assert!(bulk.preempted.is_empty() && video.preempted.is_empty() && backup.preempted.is_empty());
+
+        // The link is full; the voice tunnel takes the lowest-priority one's room
+        let voice = manager.admit_tunnel(tunnel("voice", &["A", "B"], 300.0, 6)).await.unwrap();
+        assert_eq!(voice.preempted, vec![bulk.tunnel_id]);
+        assert!(voice.rerouted.is_empty());
+
+        let preempted = manager.get_tunnel(&bulk.tunnel_id).await.unwrap();
+        assert_eq!(preempted.state, TunnelState::Preempted);
+        assert_eq!(preempted.preempted_by, Some(voice.tunnel_id));
+        assert_eq!(manager.residual_capacity("A", "B").await, Some(100.0));
+        assert_ne!(manager.get_tunnel(&backup.tunnel_id).await.unwrap().state, TunnelState::Preempted);
+
+        // Nothing it outranks is left to make room for another
+        let err = manager.admit_tunnel(tunnel("more", &["A", "B"], 300.0, 2)).await.unwrap_err();
+        assert!(err.to_string().contains("Not enough capacity"));
+        assert_eq!(manager.get_tunnel_count().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_preempted_tunnel_reroutes() {
+        let manager = TunnelManager::new();
+        manager.set_link_capacity("A".to_string(), "C".to_string(), 1000.0).await;
+        manager.set_link_capacity("A".to_string(), "B".to_string(), 500.0).await;
+        manager.set_link_capacity("B".to_string(), "C".to_string(), 500.0).await;
+
+        let low = manager.admit_tunnel(tunnel("low", &["A", "C"], 600.0, 1)).await.unwrap();
+        let held = manager.admit_tunnel(tunnel("held", &["A", "C"], 300.0, 1).with_holding_priority(7)).await.unwrap();
+
+        let high = manager.admit_tunnel(tunnel("high", &["A", "C"], 500.0, 5)).await.unwrap();
+        assert_eq!(high.preempted, vec![low.tunnel_id]);
+
+        // Too big for the detour, so it stays preempted
+        assert!(high.rerouted.is_empty());
+        assert_eq!(manager.get_tunnel(&held.tunnel_id).await.unwrap().holding_priority, 7);
+
+        let small = manager.admit_tunnel(tunnel("small", &["A", "C"], 150.0, 1)).await.unwrap();
+        let urgent = manager.admit_tunnel(tunnel("urgent", &["A", "C"], 150.0, 6)).await.unwrap();
+        assert_eq!(urgent.preempted, vec![small.tunnel_id]);
+        assert_eq!(urgent.rerouted, vec![small.tunnel_id]);
+
+        let rerouted = manager.get_tunnel(&small.tunnel_id).await.unwrap();
+        assert_eq!(rerouted.path, hops(&["A", "B", "C"]));
+        assert_eq!(rerouted.state, TunnelState::Down);
+        assert_eq!(manager.residual_capacity("A", "B").await, Some(350.0));
+        assert_eq!(manager.residual_capacity("A", "C").await, Some(50.0));
+    }
 }
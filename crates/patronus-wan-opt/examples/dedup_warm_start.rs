@@ -0,0 +1,70 @@
+//! Cold vs warm dedup hit rates across a restart
+//!
+//! Replays the same corpus (a nightly backup stand-in) through a freshly
+//! started deduplicator, once with an empty disk cache and once with the
+//! cache left behind by the previous run.
+//!
+//! ```text
+//! cargo run --release -p patronus-wan-opt --example dedup_warm_start
+//! ```
+
+use anyhow::Result;
+use patronus_wan_opt::Deduplicator;
+use std::path::Path;
+use std::time::Instant;
+
+const CHUNK_SIZE: usize = 4096;
+const CACHE_CAP: u64 = 256 * 1024 * 1024;
+
+/// 32 MiB of pseudo-random blocks where about a quarter repeat within the run
+fn corpus() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let blocks: Vec<Vec<u8>> = (0..6144)
+        .map(|_| (0..CHUNK_SIZE / 8).flat_map(|_| next().to_le_bytes()).collect())
+        .collect();
+    (0..8192)
+        .flat_map(|i| {
+            let block = if i % 4 == 3 { (next() % 6144) as usize } else { i * 3 / 4 };
+            blocks[block].clone()
+        })
+        .collect()
+}
+
+async fn run(label: &str, cache_dir: &Path, data: &[u8]) -> Result<()> {
+    let dedup = Deduplicator::with_chunk_size(CHUNK_SIZE).with_disk_cache(cache_dir, CACHE_CAP)?;
+
+    let start = Instant::now();
+    dedup.deduplicate(data).await;
+    let elapsed = start.elapsed();
+    dedup.flush().await?;
+
+    let stats = dedup.get_stats().await;
+    println!(
+        "{:<6} hit rate {:>6.2}%  saved {:>5.1} MiB  disk hits {:>5}  cache {:>5.1}% full  {:>7.1} MiB/s",
+        label,
+        stats.hit_ratio() * 100.0,
+        stats.bytes_saved() as f64 / (1024.0 * 1024.0),
+        stats.disk_hits,
+        stats.disk_cache_utilization() * 100.0,
+        data.len() as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64(),
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let data = corpus();
+    let dir = tempfile::tempdir()?;
+
+    println!("Corpus: {} MiB in {} byte chunks", data.len() / (1024 * 1024), CHUNK_SIZE);
+    run("cold", dir.path(), &data).await?;
+    run("warm", dir.path(), &data).await?;
+    Ok(())
+}
@@ -0,0 +1,664 @@
+//! On-disk Chunk Cache
+//!
+//! Keeps deduplicated chunks across restarts so a rebooted node does not
+//! start with an empty dictionary. Chunks are appended to a data file as
+//! self-verifying records (hash, length, bytes); an index file maps each
+//! hash to its record and preserves the LRU order.
+//!
+//! The index is only trusted when its checksum is intact and it belongs to
+//! the current data file. Otherwise the index is rebuilt by scanning the
+//! data file, and a torn record at the end of the file (from a crash during
+//! an append) is truncated away. Records appended after the index was last
+//! flushed are picked up the same way.
+//!
+//! Evicted chunks stay in the data file until [`ChunkCache::compact`]
+//! rewrites it with only the live records.
+
+use crate::dedup::ChunkHash;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const DATA_FILE: &str = "chunks.dat";
+const INDEX_FILE: &str = "chunks.idx";
+
+const DATA_MAGIC: &[u8; 8] = b"PWOCHUNK";
+const INDEX_MAGIC: &[u8; 8] = b"PWOINDEX";
+const FORMAT_VERSION: u32 = 1;
+
+/// Magic, version, chunk size, generation
+const DATA_HEADER_LEN: u64 = 8 + 4 + 4 + 8;
+/// Hash and payload length in front of every record
+const RECORD_HEADER_LEN: u64 = 32 + 4;
+
+/// Where a chunk's record lives in the data file
+#[derive(Debug, Clone, Copy)]
+struct CacheEntry {
+    offset: u64,
+    len: u32,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn record_len(&self) -> u64 {
+        RECORD_HEADER_LEN + self.len as u64
+    }
+}
+
+/// Size-capped, persistent chunk store with LRU eviction
+pub struct ChunkCache {
+    dir: PathBuf,
+    data: File,
+    chunk_size: usize,
+    /// Identifies the data file an index was written for; changes on
+    /// every rewrite of the data file
+    generation: u64,
+    entries: HashMap<ChunkHash, CacheEntry>,
+    /// Use tick -> hash, oldest first
+    lru: BTreeMap<u64, ChunkHash>,
+    tick: u64,
+    /// Size of the live records, headers included
+    live_bytes: u64,
+    file_len: u64,
+    capacity_bytes: u64,
+    evicted: u64,
+    dirty: bool,
+}
+
+impl ChunkCache {
+    /// Open the cache in `dir`, creating it if needed
+    ///
+    /// `chunk_size` must match the deduplicator's, since chunk boundaries
+    /// depend on it; a cache written with another size is discarded.
+    pub fn open(dir: impl AsRef<Path>, chunk_size: usize, capacity_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create chunk cache directory {}", dir.display()))?;
+
+        let data_path = dir.join(DATA_FILE);
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&data_path)
+            .with_context(|| format!("Failed to open chunk cache {}", data_path.display()))?;
+
+        let mut cache = Self {
+            dir,
+            data,
+            chunk_size,
+            generation: 0,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            live_bytes: 0,
+            file_len: 0,
+            capacity_bytes,
+            evicted: 0,
+            dirty: false,
+        };
+
+        match cache.read_data_header()? {
+            Some(generation) => {
+                cache.generation = generation;
+                cache.file_len = cache.data.metadata()?.len();
+                cache.load_index()?;
+            }
+            None => cache.reset()?,
+        }
+
+        cache.evict_to_capacity();
+        Ok(cache)
+    }
+
+    /// Whether `hash` is cached, marking it as recently used
+    pub fn touch(&mut self, hash: &ChunkHash) -> bool {
+        let Some(entry) = self.entries.get_mut(hash) else {
+            return false;
+        };
+
+        self.tick += 1;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.lru.insert(self.tick, *hash);
+        self.dirty = true;
+        true
+    }
+
+    pub fn contains(&self, hash: &ChunkHash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Read a chunk back, dropping it if the record no longer matches its hash
+    pub fn get(&mut self, hash: &ChunkHash) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entries.get(hash).copied() else {
+            return Ok(None);
+        };
+
+        let mut buf = vec![0u8; entry.len as usize];
+        self.data.seek(SeekFrom::Start(entry.offset + RECORD_HEADER_LEN))?;
+        self.data.read_exact(&mut buf)?;
+
+        if hash_chunk(&buf) != *hash {
+            warn!("Dropping corrupt chunk record at offset {}", entry.offset);
+            self.remove(hash);
+            return Ok(None);
+        }
+        Ok(Some(buf))
+    }
+
+    /// Append a chunk as most recently used, returning the number evicted
+    pub fn insert(&mut self, hash: ChunkHash, data: &[u8]) -> Result<u64> {
+        if self.touch(&hash) {
+            return Ok(0);
+        }
+
+        let offset = self.file_len;
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + data.len());
+        record.extend_from_slice(&hash);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+
+        self.data.seek(SeekFrom::Start(offset))?;
+        self.data.write_all(&record)?;
+        self.file_len += record.len() as u64;
+
+        self.tick += 1;
+        let entry = CacheEntry { offset, len: data.len() as u32, last_used: self.tick };
+        self.live_bytes += entry.record_len();
+        self.entries.insert(hash, entry);
+        self.lru.insert(self.tick, hash);
+        self.dirty = true;
+
+        let evicted = self.evict_to_capacity();
+
+        // Bound the space held by evicted records
+        if self.dead_bytes() > self.capacity_bytes.max(DATA_HEADER_LEN) {
+            self.compact()?;
+        }
+
+        Ok(evicted)
+    }
+
+    /// Persist the index so the next open can start warm
+    ///
+    /// The index is written to a temporary file and renamed into place
+    /// after the data file is synced, so it never refers to records that
+    /// did not reach the disk.
+    pub fn flush(&mut self) -> Result<()> {
+        self.data.sync_data()?;
+
+        let mut buf = Vec::with_capacity(40 + self.entries.len() * 44 + 32);
+        buf.extend_from_slice(INDEX_MAGIC);
+        buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        buf.extend_from_slice(&self.generation.to_le_bytes());
+        buf.extend_from_slice(&self.file_len.to_le_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+
+        // Oldest first, so loading replays the LRU order
+        for hash in self.lru.values() {
+            let entry = &self.entries[hash];
+            buf.extend_from_slice(hash);
+            buf.extend_from_slice(&entry.offset.to_le_bytes());
+            buf.extend_from_slice(&entry.len.to_le_bytes());
+        }
+
+        let checksum: [u8; 32] = Sha256::digest(&buf).into();
+        buf.extend_from_slice(&checksum);
+
+        let path = self.dir.join(INDEX_FILE);
+        write_atomic(&path, &buf)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Rewrite the data file with only the live records, returning the
+    /// number of bytes reclaimed
+    pub fn compact(&mut self) -> Result<u64> {
+        let before = self.file_len;
+        let generation = next_generation(self.generation);
+
+        let mut buf = Vec::with_capacity((DATA_HEADER_LEN + self.live_bytes) as usize);
+        buf.extend_from_slice(&data_header(self.chunk_size, generation));
+
+        let mut relocated = Vec::with_capacity(self.entries.len());
+        let hashes: Vec<ChunkHash> = self.lru.values().copied().collect();
+        for hash in hashes {
+            let Some(data) = self.get(&hash)? else {
+                continue;
+            };
+            relocated.push((hash, buf.len() as u64));
+            buf.extend_from_slice(&hash);
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&data);
+        }
+
+        let path = self.dir.join(DATA_FILE);
+        write_atomic(&path, &buf)?;
+        self.data = OpenOptions::new().read(true).write(true).open(&path)?;
+        self.generation = generation;
+        self.file_len = buf.len() as u64;
+        for (hash, offset) in relocated {
+            if let Some(entry) = self.entries.get_mut(&hash) {
+                entry.offset = offset;
+            }
+        }
+        self.flush()?;
+
+        let reclaimed = before.saturating_sub(self.file_len);
+        info!("Compacted chunk cache {}: reclaimed {} bytes", self.dir.display(), reclaimed);
+        Ok(reclaimed)
+    }
+
+    /// Drop every chunk and truncate the data file
+    pub fn clear(&mut self) -> Result<()> {
+        self.reset()?;
+        self.flush()
+    }
+
+    /// Number of cached chunks
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes held by live records
+    pub fn live_bytes(&self) -> u64 {
+        self.live_bytes
+    }
+
+    /// Size of the data file, including evicted records not yet compacted
+    pub fn file_bytes(&self) -> u64 {
+        self.file_len
+    }
+
+    pub fn capacity_bytes(&self) -> u64 {
+        self.capacity_bytes
+    }
+
+    /// Chunks evicted since the cache was opened
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    fn dead_bytes(&self) -> u64 {
+        self.file_len.saturating_sub(DATA_HEADER_LEN + self.live_bytes)
+    }
+
+    fn remove(&mut self, hash: &ChunkHash) {
+        if let Some(entry) = self.entries.remove(hash) {
+            self.lru.remove(&entry.last_used);
+            self.live_bytes -= entry.record_len();
+            self.dirty = true;
+        }
+    }
+
+    fn evict_to_capacity(&mut self) -> u64 {
+        let mut evicted = 0;
+        while self.live_bytes > self.capacity_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.live_bytes -= entry.record_len();
+                evicted += 1;
+            }
+        }
+        if evicted > 0 {
+            self.evicted += evicted;
+            self.dirty = true;
+        }
+        evicted
+    }
+
+    /// Start over with an empty data file
+    fn reset(&mut self) -> Result<()> {
+        self.generation = next_generation(self.generation);
+        self.data.set_len(0)?;
+        self.data.seek(SeekFrom::Start(0))?;
+        self.data.write_all(&data_header(self.chunk_size, self.generation))?;
+        self.data.sync_data()?;
+
+        self.entries.clear();
+        self.lru.clear();
+        self.live_bytes = 0;
+        self.file_len = DATA_HEADER_LEN;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Generation of a usable data file, or `None` if it must be recreated
+    fn read_data_header(&mut self) -> Result<Option<u64>> {
+        let mut header = [0u8; DATA_HEADER_LEN as usize];
+        self.data.seek(SeekFrom::Start(0))?;
+        if self.data.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let chunk_size = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        if &header[..8] != DATA_MAGIC || version != FORMAT_VERSION {
+            warn!("Discarding unrecognised chunk cache in {}", self.dir.display());
+            return Ok(None);
+        }
+        if chunk_size != self.chunk_size {
+            warn!(
+                "Discarding chunk cache in {}: chunk size {} does not match {}",
+                self.dir.display(), chunk_size, self.chunk_size
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(u64::from_le_bytes(header[16..24].try_into().unwrap())))
+    }
+
+    /// Load the index, falling back to a scan of the data file
+    fn load_index(&mut self) -> Result<()> {
+        let path = self.dir.join(INDEX_FILE);
+        let scan_from = match std::fs::read(&path) {
+            Ok(buf) => match self.parse_index(&buf) {
+                Ok(indexed_len) => indexed_len,
+                Err(reason) => {
+                    warn!("Rebuilding chunk cache index {}: {}", path.display(), reason);
+                    DATA_HEADER_LEN
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DATA_HEADER_LEN,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read chunk cache index {}", path.display()));
+            }
+        };
+
+        if scan_from == DATA_HEADER_LEN {
+            self.entries.clear();
+            self.lru.clear();
+            self.live_bytes = 0;
+        }
+        let scanned = self.scan(scan_from)?;
+
+        info!(
+            "Opened chunk cache {} with {} chunks ({} recovered from the data file)",
+            self.dir.display(), self.entries.len(), scanned
+        );
+        Ok(())
+    }
+
+    /// Decode and verify an index, returning the data file length it covers
+    fn parse_index(&mut self, buf: &[u8]) -> std::result::Result<u64, String> {
+        const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + 8;
+        const ENTRY_LEN: usize = 32 + 8 + 4;
+
+        if buf.len() < HEADER_LEN + 32 {
+            return Err("file too short".to_string());
+        }
+
+        let (body, checksum) = buf.split_at(buf.len() - 32);
+        if Sha256::digest(body).as_slice() != checksum {
+            return Err("checksum mismatch".to_string());
+        }
+        if &body[..8] != INDEX_MAGIC {
+            return Err("not a chunk cache index".to_string());
+        }
+
+        let version = u32::from_le_bytes(body[8..12].try_into().unwrap());
+        let chunk_size = u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize;
+        let generation = u64::from_le_bytes(body[16..24].try_into().unwrap());
+        let data_len = u64::from_le_bytes(body[24..32].try_into().unwrap());
+        let count = u64::from_le_bytes(body[32..40].try_into().unwrap()) as usize;
+
+        if version != FORMAT_VERSION || chunk_size != self.chunk_size {
+            return Err("index format does not match".to_string());
+        }
+        if generation != self.generation {
+            return Err("index belongs to an older data file".to_string());
+        }
+        if data_len > self.file_len {
+            return Err("data file is shorter than the index".to_string());
+        }
+        if body.len() != HEADER_LEN + count * ENTRY_LEN {
+            return Err("entry count does not match".to_string());
+        }
+
+        for raw in body[HEADER_LEN..].chunks_exact(ENTRY_LEN) {
+            let hash: ChunkHash = raw[..32].try_into().unwrap();
+            let offset = u64::from_le_bytes(raw[32..40].try_into().unwrap());
+            let len = u32::from_le_bytes(raw[40..44].try_into().unwrap());
+            if offset < DATA_HEADER_LEN || offset + RECORD_HEADER_LEN + len as u64 > data_len {
+                return Err(format!("entry at offset {} is out of range", offset));
+            }
+
+            self.tick += 1;
+            let entry = CacheEntry { offset, len, last_used: self.tick };
+            self.live_bytes += entry.record_len();
+            self.entries.insert(hash, entry);
+            self.lru.insert(self.tick, hash);
+        }
+
+        Ok(data_len)
+    }
+
+    /// Index the records from `offset` on, truncating a torn tail record;
+    /// returns the number of chunks found
+    fn scan(&mut self, offset: u64) -> Result<usize> {
+        self.data.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&self.data);
+        let mut pos = offset;
+        let mut found = Vec::new();
+
+        loop {
+            let mut header = [0u8; RECORD_HEADER_LEN as usize];
+            if reader.read_exact(&mut header).is_err() {
+                break;
+            }
+            let hash: ChunkHash = header[..32].try_into().unwrap();
+            let len = u32::from_le_bytes(header[32..36].try_into().unwrap());
+
+            let mut data = vec![0u8; len as usize];
+            if reader.read_exact(&mut data).is_err() || hash_chunk(&data) != hash {
+                break;
+            }
+            found.push((hash, pos, len));
+            pos += RECORD_HEADER_LEN + len as u64;
+        }
+        drop(reader);
+
+        if pos < self.file_len {
+            warn!(
+                "Truncating {} bytes of incomplete records from chunk cache {}",
+                self.file_len - pos, self.dir.display()
+            );
+            self.data.set_len(pos)?;
+            self.file_len = pos;
+        }
+
+        let count = found.len();
+        for (hash, offset, len) in found {
+            // A later copy of a chunk supersedes an earlier one
+            self.remove(&hash);
+            self.tick += 1;
+            let entry = CacheEntry { offset, len, last_used: self.tick };
+            self.live_bytes += entry.record_len();
+            self.entries.insert(hash, entry);
+            self.lru.insert(self.tick, hash);
+        }
+        if count > 0 {
+            self.dirty = true;
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for ChunkCache {
+    fn drop(&mut self) {
+        if self.dirty {
+            if let Err(e) = self.flush() {
+                warn!("Failed to save chunk cache index {}: {}", self.dir.display(), e);
+            }
+        }
+    }
+}
+
+fn hash_chunk(data: &[u8]) -> ChunkHash {
+    Sha256::digest(data).into()
+}
+
+fn data_header(chunk_size: usize, generation: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(DATA_HEADER_LEN as usize);
+    header.extend_from_slice(DATA_MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&(chunk_size as u32).to_le_bytes());
+    header.extend_from_slice(&generation.to_le_bytes());
+    header
+}
+
+/// A generation different from `previous`
+fn next_generation(previous: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    if now == previous { now + 1 } else { now }
+}
+
+/// Write `buf` to a temporary file, sync it and rename it over `path`
+fn write_atomic(path: &Path, buf: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(buf)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to move {} into place", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(byte: u8) -> (ChunkHash, Vec<u8>) {
+        let data = vec![byte; 16];
+        (hash_chunk(&data), data)
+    }
+
+    #[test]
+    fn test_warm_start_after_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, data_a) = chunk(b'a');
+        let (b, data_b) = chunk(b'b');
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        cache.insert(a, &data_a).unwrap();
+        cache.insert(b, &data_b).unwrap();
+        cache.flush().unwrap();
+        drop(cache);
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&a).unwrap().unwrap(), data_a);
+        assert_eq!(cache.get(&b).unwrap().unwrap(), data_b);
+    }
+
+    #[test]
+    fn test_unflushed_records_are_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, data_a) = chunk(b'a');
+        let (b, data_b) = chunk(b'b');
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        cache.insert(a, &data_a).unwrap();
+        cache.flush().unwrap();
+        cache.insert(b, &data_b).unwrap();
+        // Simulate a crash: no flush on drop
+        cache.dirty = false;
+        drop(cache);
+
+        let cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        assert!(cache.contains(&a));
+        assert!(cache.contains(&b));
+    }
+
+    #[test]
+    fn test_corrupt_index_and_torn_record_are_rebuilt() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, data_a) = chunk(b'a');
+        let (b, data_b) = chunk(b'b');
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        cache.insert(a, &data_a).unwrap();
+        cache.insert(b, &data_b).unwrap();
+        cache.flush().unwrap();
+        drop(cache);
+
+        // Partially written index and a half-appended record
+        let index = dir.path().join(INDEX_FILE);
+        let bytes = std::fs::read(&index).unwrap();
+        std::fs::write(&index, &bytes[..bytes.len() / 2]).unwrap();
+        let data = dir.path().join(DATA_FILE);
+        let len = std::fs::metadata(&data).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&data).unwrap().set_len(len - 4).unwrap();
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&a).unwrap().unwrap(), data_a);
+        assert!(!cache.contains(&b));
+        assert_eq!(cache.file_bytes(), DATA_HEADER_LEN + RECORD_HEADER_LEN + 16);
+
+        // Appends after the truncated tail are readable again
+        cache.insert(b, &data_b).unwrap();
+        assert_eq!(cache.get(&b).unwrap().unwrap(), data_b);
+    }
+
+    #[test]
+    fn test_eviction_and_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = RECORD_HEADER_LEN + 16;
+        let mut cache = ChunkCache::open(dir.path(), 16, 2 * record).unwrap();
+
+        let (a, data_a) = chunk(b'a');
+        let (b, data_b) = chunk(b'b');
+        let (c, data_c) = chunk(b'c');
+        cache.insert(a, &data_a).unwrap();
+        cache.insert(b, &data_b).unwrap();
+        cache.touch(&a);
+        assert_eq!(cache.insert(c, &data_c).unwrap(), 1);
+
+        assert!(!cache.contains(&b));
+        assert_eq!(cache.live_bytes(), 2 * record);
+        assert_eq!(cache.file_bytes(), DATA_HEADER_LEN + 3 * record);
+
+        assert_eq!(cache.compact().unwrap(), record);
+        assert_eq!(cache.file_bytes(), DATA_HEADER_LEN + 2 * record);
+        assert_eq!(cache.get(&a).unwrap().unwrap(), data_a);
+        assert_eq!(cache.get(&c).unwrap().unwrap(), data_c);
+        drop(cache);
+
+        // The compacted file and its index reopen cleanly
+        let cache = ChunkCache::open(dir.path(), 16, 2 * record).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&a) && cache.contains(&c));
+    }
+
+    #[test]
+    fn test_other_chunk_size_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, data_a) = chunk(b'a');
+
+        let mut cache = ChunkCache::open(dir.path(), 16, 1024).unwrap();
+        cache.insert(a, &data_a).unwrap();
+        drop(cache);
+
+        let cache = ChunkCache::open(dir.path(), 32, 1024).unwrap();
+        assert!(cache.is_empty());
+    }
+}
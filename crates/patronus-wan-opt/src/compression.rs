@@ -1,14 +1,29 @@
 //! Compression Module
 //!
-//! Supports multiple compression algorithms optimized for different use cases
+//! Supports multiple compression algorithms optimized for different use cases.
+//! The adaptive mode samples each chunk and picks the algorithm that gives
+//! the best effective throughput for the link and CPU budget.
+//!
+//! Flows compressed with [`Compressor::compress_flow`] are sampled once, over
+//! their first `sample_size` bytes, and keep that decision. When the monitor
+//! reports high CPU usage or an idle link, every decision is stepped down to
+//! a lighter algorithm. Each frame carries its algorithm tag, so the peer
+//! always decompresses with the algorithm actually used. A flow's decision
+//! is forgotten once it has been idle for `flow_idle_timeout_secs`, or when
+//! it is the least recently used of `max_flows` tracked flows.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use flate2::read::{GzDecoder, GzEncoder};
 use flate2::Compression as GzCompression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
 
 /// Compression algorithm
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CompressionType {
     /// No compression
     None,
@@ -18,33 +33,484 @@ pub enum CompressionType {
     Lz4,
     /// Zstd (best ratio, good speed)
     Zstd,
+    /// Pick None, LZ4 or Zstd per chunk based on a sample of the data
+    Adaptive,
+}
+
+impl CompressionType {
+    /// Tag identifying the algorithm in adaptive framing
+    fn tag(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Gzip => 1,
+            CompressionType::Lz4 => 2,
+            CompressionType::Zstd => 3,
+            CompressionType::Adaptive => u8::MAX,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(CompressionType::None),
+            1 => Some(CompressionType::Gzip),
+            2 => Some(CompressionType::Lz4),
+            3 => Some(CompressionType::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Position on the None < LZ4 < Zstd/Gzip cost ladder
+    fn weight(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Lz4 => 1,
+            CompressionType::Gzip | CompressionType::Zstd | CompressionType::Adaptive => 2,
+        }
+    }
+
+    /// Step down `steps` rungs towards no compression
+    fn lighten(self, steps: u8) -> Self {
+        match self.weight().saturating_sub(steps) {
+            0 => CompressionType::None,
+            1 if self.weight() > 1 => CompressionType::Lz4,
+            _ => self,
+        }
+    }
+}
+
+/// Tuning for adaptive algorithm selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveConfig {
+    /// Bytes sampled from the start of each chunk
+    pub sample_size: usize,
+
+    /// WAN link bandwidth in megabits per second
+    pub link_bandwidth_mbps: f64,
+
+    /// Share of one CPU core available for compression (0.0 - 1.0)
+    pub cpu_budget: f64,
+
+    /// Sample entropy (bits per byte) above which data is treated as incompressible
+    pub entropy_threshold: f64,
+
+    /// CPU usage (0.0 - 1.0) above which algorithms step down one level
+    pub cpu_high_threshold: f64,
+
+    /// CPU usage above which compression is skipped altogether
+    pub cpu_critical_threshold: f64,
+
+    /// Link utilization (0.0 - 1.0) below which algorithms step down one
+    /// level, since a link with headroom gains little from compression
+    pub link_idle_threshold: f64,
+
+    /// Most flows whose decision is remembered; the least recently used
+    /// flow is forgotten to make room
+    pub max_flows: usize,
+
+    /// Seconds after which a flow without traffic is forgotten
+    pub flow_idle_timeout_secs: u64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            sample_size: 4096,
+            link_bandwidth_mbps: 100.0,
+            cpu_budget: 0.5,
+            entropy_threshold: 7.5,
+            cpu_high_threshold: 0.75,
+            cpu_critical_threshold: 0.95,
+            link_idle_threshold: 0.2,
+            max_flows: 65536,
+            flow_idle_timeout_secs: 300,
+        }
+    }
+}
+
+/// Approximate single-core compression speeds (MB/s) used by the throughput model
+const LZ4_SPEED_MBPS: f64 = 500.0;
+const ZSTD_SPEED_MBPS: f64 = 150.0;
+
+/// Load reported by the monitor, as fractions of capacity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemLoad {
+    pub cpu_usage: f64,
+    pub link_utilization: f64,
+}
+
+/// Bytes through one algorithm
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AlgorithmBytes {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// Current state of adaptive selection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdaptiveState {
+    /// Last load reported by the monitor
+    pub load: Option<SystemLoad>,
+    /// Levels every decision is currently stepped down by
+    pub backoff_steps: u8,
+    /// Flows still collecting their sample
+    pub flows_sampling: usize,
+    /// Flows with a sticky decision, by the algorithm chosen from their sample
+    pub flows_by_algorithm: HashMap<CompressionType, usize>,
+}
+
+/// Per-algorithm compression statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressionStats {
+    pub chunks_total: u64,
+    pub chunks_none: u64,
+    pub chunks_gzip: u64,
+    pub chunks_lz4: u64,
+    pub chunks_zstd: u64,
+    /// Chunks sent uncompressed because their data was already compressed
+    /// or too random to compress
+    pub chunks_bypassed: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Output/input size ratio over all chunks (0.0 before any input)
+    #[serde(default)]
+    pub ratio: f64,
+    /// Algorithm used for the most recent chunk
+    pub last_algorithm: Option<CompressionType>,
+    #[serde(default)]
+    pub bytes_by_algorithm: HashMap<CompressionType, AlgorithmBytes>,
+    #[serde(default)]
+    pub adaptive: AdaptiveState,
+}
+
+impl CompressionStats {
+    fn record(&mut self, algorithm: CompressionType, bytes_in: usize, bytes_out: usize) {
+        self.chunks_total += 1;
+        match algorithm {
+            CompressionType::None => self.chunks_none += 1,
+            CompressionType::Gzip => self.chunks_gzip += 1,
+            CompressionType::Lz4 => self.chunks_lz4 += 1,
+            CompressionType::Zstd => self.chunks_zstd += 1,
+            CompressionType::Adaptive => {}
+        }
+        self.bytes_in += bytes_in as u64;
+        self.bytes_out += bytes_out as u64;
+        let bytes = self.bytes_by_algorithm.entry(algorithm).or_default();
+        bytes.bytes_in += bytes_in as u64;
+        bytes.bytes_out += bytes_out as u64;
+        self.ratio = self.compression_ratio();
+        self.last_algorithm = Some(algorithm);
+    }
+
+    /// Overall output/input size ratio
+    pub fn compression_ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            0.0
+        } else {
+            self.bytes_out as f64 / self.bytes_in as f64
+        }
+    }
+
+    /// Bytes removed by compression; negative if framing outgrew the input
+    pub fn bytes_saved(&self) -> i64 {
+        self.bytes_in as i64 - self.bytes_out as i64
+    }
+}
+
+/// Algorithm picked for some data, and whether it was picked because the
+/// data looked incompressible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Selection {
+    algorithm: CompressionType,
+    bypass: bool,
+}
+
+impl From<CompressionType> for Selection {
+    fn from(algorithm: CompressionType) -> Self {
+        Self { algorithm, bypass: false }
+    }
+}
+
+/// Adaptive decision for one flow
+struct FlowState {
+    /// Leading bytes of the flow, until `sample_size` is reached
+    sample: Vec<u8>,
+    /// Choice from the first chunk, used until the sample is complete
+    provisional: Option<Selection>,
+    /// Algorithm chosen once the sample was complete
+    decision: Option<Selection>,
+    /// Algorithm of the last frame, after any backoff
+    current: Option<CompressionType>,
+    /// When the flow last sent a chunk
+    last_seen: Instant,
+}
+
+impl FlowState {
+    fn new(now: Instant) -> Self {
+        Self { sample: Vec::new(), provisional: None, decision: None, current: None, last_seen: now }
+    }
 }
 
 /// Data compressor
 pub struct Compressor {
     compression_type: CompressionType,
+    adaptive: AdaptiveConfig,
+    load: Mutex<Option<SystemLoad>>,
+    flows: Mutex<HashMap<u64, FlowState>>,
+    stats: Mutex<CompressionStats>,
 }
 
 impl Compressor {
     /// Create new compressor with specified algorithm
     pub fn new(compression_type: CompressionType) -> Self {
-        Self { compression_type }
+        Self {
+            compression_type,
+            adaptive: AdaptiveConfig::default(),
+            load: Mutex::new(None),
+            flows: Mutex::new(HashMap::new()),
+            stats: Mutex::new(CompressionStats::default()),
+        }
+    }
+
+    /// Create an adaptive compressor with custom tuning
+    pub fn adaptive(config: AdaptiveConfig) -> Self {
+        Self {
+            compression_type: CompressionType::Adaptive,
+            adaptive: config,
+            load: Mutex::new(None),
+            flows: Mutex::new(HashMap::new()),
+            stats: Mutex::new(CompressionStats::default()),
+        }
     }
 
     /// Compress data
+    ///
+    /// In adaptive mode the output starts with a one-byte algorithm tag.
     pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (selection, algorithm) = match self.compression_type {
+            CompressionType::Adaptive => {
+                let selection = self.select(data);
+                (selection, selection.algorithm.lighten(self.backoff_steps()))
+            }
+            fixed => (fixed.into(), fixed),
+        };
+
+        let mut out = if self.compression_type == CompressionType::Adaptive {
+            vec![algorithm.tag()]
+        } else {
+            Vec::new()
+        };
+        out.extend(self.compress_with(algorithm, data)?);
+
+        self.record(selection, algorithm, data.len(), out.len());
+        Ok(out)
+    }
+
+    /// Compress the next chunk of a flow into a tagged frame
+    ///
+    /// The flow's algorithm is chosen from its first `sample_size` bytes and
+    /// then kept for the rest of the flow, stepped down while the reported
+    /// load calls for backoff. A fixed-algorithm compressor uses its own
+    /// algorithm for every flow. Decode frames with
+    /// [`decompress_frame`](Self::decompress_frame).
+    pub fn compress_flow(&self, flow_id: u64, data: &[u8]) -> Result<Vec<u8>> {
+        self.compress_flow_at(flow_id, data, Instant::now())
+    }
+
+    fn compress_flow_at(&self, flow_id: u64, data: &[u8], now: Instant) -> Result<Vec<u8>> {
+        let steps = self.backoff_steps();
+        let (selection, algorithm) = {
+            let mut flows = self.flows.lock().unwrap();
+            if !flows.contains_key(&flow_id) {
+                self.make_room(&mut flows, now);
+            }
+            let flow = flows.entry(flow_id).or_insert_with(|| FlowState::new(now));
+            flow.last_seen = now;
+
+            let base = match (self.compression_type, flow.decision) {
+                (_, Some(decision)) => decision,
+                (CompressionType::Adaptive, None) => {
+                    let wanted = self.adaptive.sample_size.saturating_sub(flow.sample.len());
+                    flow.sample.extend_from_slice(&data[..wanted.min(data.len())]);
+                    // Trial compressions run at most twice per flow: on the
+                    // first chunk and once the sample is complete
+                    if flow.sample.len() >= self.adaptive.sample_size {
+                        let choice = self.select(&flow.sample);
+                        debug!("Flow {} settled on {:?}", flow_id, choice.algorithm);
+                        flow.decision = Some(choice);
+                        flow.sample = Vec::new();
+                        choice
+                    } else if let Some(provisional) = flow.provisional {
+                        provisional
+                    } else {
+                        let choice = self.select(&flow.sample);
+                        if !flow.sample.is_empty() {
+                            flow.provisional = Some(choice);
+                        }
+                        choice
+                    }
+                }
+                (fixed, None) => {
+                    flow.decision = Some(fixed.into());
+                    fixed.into()
+                }
+            };
+
+            let algorithm = base.algorithm.lighten(steps);
+            if flow.current.is_some_and(|c| c != algorithm) {
+                debug!("Flow {} switching from {:?} to {:?}", flow_id, flow.current, algorithm);
+            }
+            flow.current = Some(algorithm);
+            (base, algorithm)
+        };
+
+        let mut out = vec![algorithm.tag()];
+        out.extend(self.compress_with(algorithm, data)?);
+
+        self.record(selection, algorithm, data.len(), out.len());
+        Ok(out)
+    }
+
+    /// Drop idle flows, then the least recently used one if still at `max_flows`
+    fn make_room(&self, flows: &mut HashMap<u64, FlowState>, now: Instant) {
+        if flows.len() < self.adaptive.max_flows.max(1) {
+            return;
+        }
+
+        let idle = Duration::from_secs(self.adaptive.flow_idle_timeout_secs);
+        flows.retain(|_, f| now.saturating_duration_since(f.last_seen) < idle);
+
+        if flows.len() >= self.adaptive.max_flows.max(1) {
+            let oldest = flows.iter().min_by_key(|(_, f)| f.last_seen).map(|(&id, _)| id);
+            if let Some(id) = oldest {
+                debug!("Flow table full, forgetting flow {}", id);
+                flows.remove(&id);
+            }
+        }
+    }
+
+    fn record(&self, selection: Selection, algorithm: CompressionType, bytes_in: usize, bytes_out: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.record(algorithm, bytes_in, bytes_out);
+        if selection.bypass && algorithm == CompressionType::None {
+            stats.chunks_bypassed += 1;
+        }
+    }
+
+    /// Forget a finished flow's decision
+    pub fn end_flow(&self, flow_id: u64) -> bool {
+        self.flows.lock().unwrap().remove(&flow_id).is_some()
+    }
+
+    /// Record the latest CPU usage and link utilization from the monitor
+    pub fn report_load(&self, load: SystemLoad) {
+        let previous = self.load.lock().unwrap().replace(load);
+        let steps = self.backoff_steps();
+        if previous.is_none_or(|p| self.steps_for(&p) != steps) {
+            debug!("Compression backoff now {} level(s) at {:?}", steps, load);
+        }
+    }
+
+    /// Decompress data
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
         match self.compression_type {
-            CompressionType::None => Ok(data.to_vec()),
+            CompressionType::Adaptive => self.decompress_frame(data),
+            fixed => self.decompress_with(fixed, data),
+        }
+    }
+
+    /// Decompress a frame starting with its algorithm tag, as produced in
+    /// adaptive mode and by [`compress_flow`](Self::compress_flow)
+    pub fn decompress_frame(&self, frame: &[u8]) -> Result<Vec<u8>> {
+        let (&tag, payload) = frame.split_first().context("Empty adaptive frame")?;
+        match CompressionType::from_tag(tag) {
+            Some(algorithm) => self.decompress_with(algorithm, payload),
+            None => bail!("Unknown compression tag {}", tag),
+        }
+    }
+
+    /// Levels to step algorithms down by under the last reported load
+    fn backoff_steps(&self) -> u8 {
+        self.load.lock().unwrap().map_or(0, |load| self.steps_for(&load))
+    }
+
+    fn steps_for(&self, load: &SystemLoad) -> u8 {
+        if load.cpu_usage >= self.adaptive.cpu_critical_threshold {
+            return 2;
+        }
+        u8::from(load.cpu_usage >= self.adaptive.cpu_high_threshold)
+            + u8::from(load.link_utilization < self.adaptive.link_idle_threshold)
+    }
+
+    /// Choose the algorithm giving the best effective throughput for `data`
+    ///
+    /// Only looks at the data; statistics are left alone.
+    pub fn select_algorithm(&self, data: &[u8]) -> CompressionType {
+        self.select(data).algorithm
+    }
+
+    fn select(&self, data: &[u8]) -> Selection {
+        let sample = &data[..data.len().min(self.adaptive.sample_size)];
+        if sample.is_empty() {
+            return CompressionType::None.into();
+        }
+
+        if is_precompressed(sample) || shannon_entropy(sample) > self.adaptive.entropy_threshold {
+            return Selection { algorithm: CompressionType::None, bypass: true };
+        }
+
+        // Effective throughput is bounded by whichever is slower: compressing
+        // with the CPU budget, or sending the compressed bytes over the link
+        let link = self.adaptive.link_bandwidth_mbps / 8.0;
+        let budget = self.adaptive.cpu_budget.clamp(0.01, 1.0);
+        let ratio = |compressed: Result<Vec<u8>>| {
+            compressed.map(|c| (c.len() as f64 / sample.len() as f64).max(0.01)).unwrap_or(1.0)
+        };
+
+        let candidates = [
+            (CompressionType::None, link),
+            (CompressionType::Lz4, (LZ4_SPEED_MBPS * budget).min(link / ratio(self.compress_lz4(sample)))),
+            (CompressionType::Zstd, (ZSTD_SPEED_MBPS * budget).min(link / ratio(self.compress_zstd(sample)))),
+        ];
+
+        candidates
+            .iter()
+            .fold(candidates[0], |best, c| if c.1 > best.1 { *c } else { best })
+            .0
+            .into()
+    }
+
+    /// Statistics, including which algorithm each chunk used
+    pub fn stats(&self) -> CompressionStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.adaptive.load = *self.load.lock().unwrap();
+        stats.adaptive.backoff_steps = self.backoff_steps();
+        for flow in self.flows.lock().unwrap().values() {
+            match flow.decision {
+                Some(decision) => *stats.adaptive.flows_by_algorithm.entry(decision.algorithm).or_default() += 1,
+                None => stats.adaptive.flows_sampling += 1,
+            }
+        }
+        stats
+    }
+
+    /// Reset statistics
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = CompressionStats::default();
+    }
+
+    fn compress_with(&self, algorithm: CompressionType, data: &[u8]) -> Result<Vec<u8>> {
+        match algorithm {
+            CompressionType::None | CompressionType::Adaptive => Ok(data.to_vec()),
             CompressionType::Gzip => self.compress_gzip(data),
             CompressionType::Lz4 => self.compress_lz4(data),
             CompressionType::Zstd => self.compress_zstd(data),
         }
     }
 
-    /// Decompress data
-    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
-        match self.compression_type {
-            CompressionType::None => Ok(data.to_vec()),
+    fn decompress_with(&self, algorithm: CompressionType, data: &[u8]) -> Result<Vec<u8>> {
+        match algorithm {
+            CompressionType::None | CompressionType::Adaptive => Ok(data.to_vec()),
             CompressionType::Gzip => self.decompress_gzip(data),
             CompressionType::Lz4 => self.decompress_lz4(data),
             CompressionType::Zstd => self.decompress_zstd(data),
@@ -100,6 +566,40 @@ impl Compressor {
     }
 }
 
+/// Recognise common compressed formats by their magic bytes
+fn is_precompressed(data: &[u8]) -> bool {
+    const MAGICS: &[&[u8]] = &[
+        &[0x1f, 0x8b],                         // gzip
+        &[0x28, 0xb5, 0x2f, 0xfd],             // zstd
+        &[0x04, 0x22, 0x4d, 0x18],             // lz4 frame
+        &[0xfd, b'7', b'z', b'X', b'Z', 0x00], // xz
+        b"BZh",                                // bzip2
+        b"PK\x03\x04",                         // zip
+        &[0xff, 0xd8, 0xff],                   // jpeg
+        b"\x89PNG",                            // png
+    ];
+
+    MAGICS.iter().any(|m| data.starts_with(m))
+}
+
+/// Shannon entropy of `data` in bits per byte
+fn shannon_entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
 impl Default for Compressor {
     fn default() -> Self {
         Self::new(CompressionType::Zstd)
@@ -167,4 +667,239 @@ mod tests {
         let ratio = compressor.compression_ratio(original_size, compressed_size);
         assert_eq!(ratio, 0.5);
     }
+
+    /// Deterministic incompressible bytes (xorshift)
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    /// Log-like text with varied content, compressible but not trivially so
+    fn log_text(lines: usize) -> Vec<u8> {
+        const HOSTS: &[&str] = &["edge-01", "edge-02", "core-sw", "branch-nyc", "branch-lon", "vpn-gw"];
+        const EVENTS: &[&str] = &[
+            "session established with peer",
+            "interface flapped, link down",
+            "dhcp lease renewed for client",
+            "firewall rule matched, packet dropped",
+            "tunnel rekey completed successfully",
+            "high latency detected on path",
+        ];
+
+        let random = random_bytes(lines * 4);
+        let mut text = String::new();
+        for (i, r) in random.chunks(4).enumerate() {
+            text.push_str(&format!(
+                "2024-03-{:02}T{:02}:{:02}:{:02} {} {} {}.{}.{}.{} seq={}\n",
+                r[0] % 28 + 1,
+                r[1] % 24,
+                r[2] % 60,
+                r[3] % 60,
+                HOSTS[r[0] as usize % HOSTS.len()],
+                EVENTS[r[1] as usize % EVENTS.len()],
+                r[2],
+                r[3],
+                r[0],
+                r[1],
+                i
+            ));
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn test_adaptive_random_data_bypasses() {
+        let compressor = Compressor::new(CompressionType::Adaptive);
+        let data = random_bytes(16 * 1024);
+
+        assert_eq!(compressor.select_algorithm(&data), CompressionType::None);
+
+        let compressed = compressor.compress(&data).unwrap();
+        assert_eq!(compressed.len(), data.len() + 1);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+
+        let stats = compressor.stats();
+        assert_eq!(stats.chunks_none, 1);
+        assert_eq!(stats.chunks_bypassed, 1);
+        assert_eq!(stats.last_algorithm, Some(CompressionType::None));
+    }
+
+    #[test]
+    fn test_adaptive_text_uses_zstd() {
+        let compressor = Compressor::new(CompressionType::Adaptive);
+        let data = log_text(400);
+
+        let compressed = compressor.compress(&data).unwrap();
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+
+        let stats = compressor.stats();
+        assert_eq!(stats.chunks_zstd, 1);
+        assert_eq!(stats.last_algorithm, Some(CompressionType::Zstd));
+    }
+
+    #[test]
+    fn test_adaptive_precompressed_bypasses() {
+        let gzip = Compressor::new(CompressionType::Gzip)
+            .compress(&b"already compressed ".repeat(500))
+            .unwrap();
+
+        let compressor = Compressor::new(CompressionType::Adaptive);
+        assert_eq!(compressor.select_algorithm(&gzip), CompressionType::None);
+    }
+
+    #[test]
+    fn test_adaptive_tight_cpu_budget_prefers_lz4() {
+        // With little CPU to spare, Zstd's speed becomes the bottleneck
+        let compressor = Compressor::adaptive(AdaptiveConfig {
+            cpu_budget: 0.05,
+            ..AdaptiveConfig::default()
+        });
+        let data = log_text(400);
+
+        assert_eq!(compressor.select_algorithm(&data), CompressionType::Lz4);
+    }
+
+    #[test]
+    fn test_flow_decision_is_sticky() {
+        let compressor = Compressor::adaptive(AdaptiveConfig {
+            sample_size: 8 * 1024,
+            ..AdaptiveConfig::default()
+        });
+        let peer = Compressor::new(CompressionType::None);
+        let text = log_text(400);
+        let random = random_bytes(4096);
+
+        // The first chunk only half fills the sample, so the flow is still open
+        let first = compressor.compress_flow(1, &text[..4096]).unwrap();
+        assert_eq!(first[0], CompressionType::Zstd.tag());
+        assert_eq!(compressor.stats().adaptive.flows_sampling, 1);
+
+        let second = compressor.compress_flow(1, &text[4096..8192]).unwrap();
+        assert_eq!(second[0], CompressionType::Zstd.tag());
+
+        // Random payload later in the flow keeps the flow's decision
+        let third = compressor.compress_flow(1, &random).unwrap();
+        assert_eq!(third[0], CompressionType::Zstd.tag());
+        assert_eq!(peer.decompress_frame(&third).unwrap(), random);
+
+        // A flow that starts with incompressible data is passed through
+        let jpeg: Vec<u8> = [0xff, 0xd8, 0xff, 0xe0].iter().copied().chain(random_bytes(8 * 1024)).collect();
+        let frame = compressor.compress_flow(2, &jpeg).unwrap();
+        assert_eq!(frame[0], CompressionType::None.tag());
+        assert_eq!(compressor.compress_flow(2, &text[..4096]).unwrap()[0], CompressionType::None.tag());
+
+        let stats = compressor.stats();
+        assert_eq!(stats.adaptive.flows_sampling, 0);
+        assert_eq!(stats.adaptive.flows_by_algorithm[&CompressionType::Zstd], 1);
+        assert_eq!(stats.adaptive.flows_by_algorithm[&CompressionType::None], 1);
+        assert_eq!(stats.bytes_by_algorithm[&CompressionType::Zstd].bytes_in, 8192 + 4096);
+        assert_eq!(stats.bytes_by_algorithm[&CompressionType::None].bytes_in, jpeg.len() as u64 + 4096);
+        assert_eq!(stats.chunks_bypassed, 2);
+
+        assert!(compressor.end_flow(1));
+        assert!(!compressor.end_flow(1));
+    }
+
+    #[test]
+    fn test_flow_table_expires_idle_and_caps_flows() {
+        let compressor = Compressor::adaptive(AdaptiveConfig {
+            max_flows: 2,
+            flow_idle_timeout_secs: 60,
+            ..AdaptiveConfig::default()
+        });
+        let chunk = &log_text(400)[..4096];
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let tracked = || {
+            let stats = compressor.stats();
+            stats.adaptive.flows_sampling + stats.adaptive.flows_by_algorithm.values().sum::<usize>()
+        };
+
+        compressor.compress_flow_at(1, chunk, at(0)).unwrap();
+        compressor.compress_flow_at(2, chunk, at(1)).unwrap();
+        compressor.compress_flow_at(1, chunk, at(2)).unwrap();
+
+        // Full: the least recently used flow makes room
+        compressor.compress_flow_at(3, chunk, at(3)).unwrap();
+        assert_eq!(tracked(), 2);
+        assert!(!compressor.end_flow(2));
+
+        // Both remaining flows have been idle past the timeout
+        compressor.compress_flow_at(1, chunk, at(4)).unwrap();
+        compressor.compress_flow_at(4, chunk, at(100)).unwrap();
+        assert_eq!(tracked(), 1);
+        assert!(compressor.end_flow(4));
+    }
+
+    #[test]
+    fn test_select_algorithm_has_no_side_effects() {
+        let compressor = Compressor::new(CompressionType::Adaptive);
+        for _ in 0..3 {
+            assert_eq!(compressor.select_algorithm(&random_bytes(4096)), CompressionType::None);
+        }
+        assert_eq!(compressor.stats().chunks_bypassed, 0);
+        assert_eq!(compressor.stats().chunks_total, 0);
+    }
+
+    #[test]
+    fn test_load_backoff_lightens_flows() {
+        let compressor = Compressor::new(CompressionType::Adaptive);
+        let peer = Compressor::new(CompressionType::Gzip);
+        let text = log_text(400);
+        let chunk = &text[..4096];
+
+        let busy_link = |cpu_usage| SystemLoad { cpu_usage, link_utilization: 0.9 };
+        let cases = [
+            (busy_link(0.3), CompressionType::Zstd, 0),
+            (busy_link(0.8), CompressionType::Lz4, 1),
+            (busy_link(0.97), CompressionType::None, 2),
+            (SystemLoad { cpu_usage: 0.3, link_utilization: 0.1 }, CompressionType::Lz4, 1),
+            (SystemLoad { cpu_usage: 0.8, link_utilization: 0.1 }, CompressionType::None, 2),
+        ];
+
+        for (load, expected, steps) in cases {
+            compressor.report_load(load);
+            let frame = compressor.compress_flow(7, chunk).unwrap();
+            assert_eq!(frame[0], expected.tag(), "{:?}", load);
+            assert_eq!(peer.decompress_frame(&frame).unwrap(), chunk);
+
+            let stats = compressor.stats();
+            assert_eq!(stats.adaptive.backoff_steps, steps);
+            assert_eq!(stats.adaptive.load, Some(load));
+        }
+
+        // Per-chunk adaptive mode backs off too
+        let frame = compressor.compress(chunk).unwrap();
+        assert_eq!(frame[0], CompressionType::None.tag());
+
+        let stats = compressor.stats();
+        assert_eq!(stats.bytes_by_algorithm[&CompressionType::Lz4].bytes_in, 2 * 4096);
+        // Backing off to no compression is not a bypass
+        assert_eq!(stats.chunks_bypassed, 0);
+        assert!(stats.bytes_by_algorithm[&CompressionType::Zstd].bytes_out < 4096);
+    }
+
+    #[test]
+    fn test_fixed_compressor_flows_back_off() {
+        let compressor = Compressor::new(CompressionType::Gzip);
+        let data = b"Hello, World! ".repeat(100);
+
+        assert_eq!(compressor.compress_flow(1, &data).unwrap()[0], CompressionType::Gzip.tag());
+        compressor.report_load(SystemLoad { cpu_usage: 0.8, link_utilization: 0.5 });
+        let frame = compressor.compress_flow(1, &data).unwrap();
+        assert_eq!(frame[0], CompressionType::Lz4.tag());
+        assert_eq!(compressor.decompress_frame(&frame).unwrap(), data);
+
+        // Unframed chunks keep the fixed algorithm the peer expects
+        let compressed = compressor.compress(&data).unwrap();
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+    }
 }
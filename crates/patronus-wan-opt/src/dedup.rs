@@ -1,17 +1,31 @@
 //! Data Deduplication
 //!
 //! Uses content-defined chunking and SHA-256 hashing to detect and eliminate
-//! duplicate data across the WAN
+//! duplicate data across the WAN. The chunk store is bounded with LRU
+//! eviction and can be snapshotted to disk so a restarted node keeps its
+//! dictionary. A persistent [`ChunkCache`] can back the in-memory store with
+//! a larger on-disk dictionary that is reused across restarts.
 
+use crate::chunk_cache::ChunkCache;
+use anyhow::{Context, Result};
 use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 
 /// Chunk size for deduplication (default 4KB)
 const DEFAULT_CHUNK_SIZE: usize = 4096;
 
+/// Default chunk store capacity (256MB)
+const DEFAULT_CAPACITY_BYTES: usize = 256 * 1024 * 1024;
+
+/// Snapshot file magic and format version
+const SNAPSHOT_MAGIC: &[u8; 8] = b"PWODEDUP";
+const SNAPSHOT_VERSION: u32 = 1;
+
 /// Deduplication statistics
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DedupStats {
@@ -21,6 +35,23 @@ pub struct DedupStats {
     pub chunks_total: u64,
     pub chunks_unique: u64,
     pub chunks_duplicate: u64,
+    /// Chunks dropped from the store to stay within capacity
+    #[serde(default)]
+    pub chunks_evicted: u64,
+    /// Duplicates found only in the on-disk cache
+    #[serde(default)]
+    pub disk_hits: u64,
+    /// Chunks dropped from the on-disk cache to stay within its cap
+    #[serde(default)]
+    pub disk_chunks_evicted: u64,
+    /// Bytes of live chunks in the on-disk cache
+    #[serde(default)]
+    pub disk_cache_bytes: u64,
+    /// Size of the cache file, including evicted chunks not yet compacted
+    #[serde(default)]
+    pub disk_cache_file_bytes: u64,
+    #[serde(default)]
+    pub disk_cache_capacity_bytes: u64,
 }
 
 impl DedupStats {
@@ -37,42 +68,174 @@ impl DedupStats {
     pub fn space_savings_pct(&self) -> f64 {
         self.dedup_ratio() * 100.0
     }
+
+    /// Share of chunks that were already known
+    pub fn hit_ratio(&self) -> f64 {
+        if self.chunks_total == 0 {
+            0.0
+        } else {
+            self.chunks_duplicate as f64 / self.chunks_total as f64
+        }
+    }
+
+    /// Bytes that did not need to be sent
+    pub fn bytes_saved(&self) -> u64 {
+        self.duplicate_bytes
+    }
+
+    /// Fraction of the on-disk cache cap in use
+    pub fn disk_cache_utilization(&self) -> f64 {
+        if self.disk_cache_capacity_bytes == 0 {
+            0.0
+        } else {
+            self.disk_cache_bytes as f64 / self.disk_cache_capacity_bytes as f64
+        }
+    }
 }
 
 /// Chunk hash (SHA-256)
-type ChunkHash = [u8; 32];
+pub(crate) type ChunkHash = [u8; 32];
+
+/// Stored chunk with its position in the LRU order
+struct ChunkEntry {
+    data: Vec<u8>,
+    last_used: u64,
+}
+
+/// Size-capped chunk store with least-recently-used eviction
+struct ChunkStore {
+    chunks: HashMap<ChunkHash, ChunkEntry>,
+    /// Use tick -> hash, oldest first
+    lru: BTreeMap<u64, ChunkHash>,
+    tick: u64,
+    bytes: usize,
+    capacity_bytes: usize,
+}
+
+impl ChunkStore {
+    fn new(capacity_bytes: usize) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            capacity_bytes,
+        }
+    }
+
+    /// Mark a chunk as used, returning whether it is stored
+    fn touch(&mut self, hash: &ChunkHash) -> bool {
+        let Some(entry) = self.chunks.get_mut(hash) else {
+            return false;
+        };
+
+        self.tick += 1;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.lru.insert(self.tick, *hash);
+        true
+    }
+
+    fn get(&self, hash: &ChunkHash) -> Option<&[u8]> {
+        self.chunks.get(hash).map(|e| e.data.as_slice())
+    }
+
+    /// Insert a chunk as most recently used, returning the number evicted
+    fn insert(&mut self, hash: ChunkHash, data: Vec<u8>) -> u64 {
+        if self.touch(&hash) {
+            return 0;
+        }
+
+        self.tick += 1;
+        self.bytes += data.len();
+        self.lru.insert(self.tick, hash);
+        self.chunks.insert(hash, ChunkEntry { data, last_used: self.tick });
+
+        let mut evicted = 0;
+        while self.bytes > self.capacity_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.chunks.remove(&oldest) {
+                self.bytes -= entry.data.len();
+                evicted += 1;
+            }
+        }
+
+        evicted
+    }
+
+    /// Chunks from least to most recently used
+    fn iter_lru(&self) -> impl Iterator<Item = (&ChunkHash, &[u8])> {
+        self.lru.values().map(|hash| (hash, self.chunks[hash].data.as_slice()))
+    }
+
+    fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.lru.clear();
+        self.bytes = 0;
+    }
+}
 
 /// Data deduplicator
 pub struct Deduplicator {
     chunk_size: usize,
-    chunk_store: Arc<RwLock<HashMap<ChunkHash, Vec<u8>>>>,
+    chunk_store: Arc<RwLock<ChunkStore>>,
+    disk_cache: Option<Arc<RwLock<ChunkCache>>>,
     stats: Arc<RwLock<DedupStats>>,
 }
 
 impl Deduplicator {
     /// Create new deduplicator
     pub fn new() -> Self {
-        Self {
-            chunk_size: DEFAULT_CHUNK_SIZE,
-            chunk_store: Arc::new(RwLock::new(HashMap::new())),
-            stats: Arc::new(RwLock::new(DedupStats::default())),
-        }
+        Self::with_chunk_size(DEFAULT_CHUNK_SIZE)
     }
 
     /// Create deduplicator with custom chunk size
     pub fn with_chunk_size(chunk_size: usize) -> Self {
         Self {
             chunk_size,
-            chunk_store: Arc::new(RwLock::new(HashMap::new())),
+            chunk_store: Arc::new(RwLock::new(ChunkStore::new(DEFAULT_CAPACITY_BYTES))),
+            disk_cache: None,
             stats: Arc::new(RwLock::new(DedupStats::default())),
         }
     }
 
+    /// Limit the chunk store to `capacity_bytes`, evicting least recently
+    /// used chunks beyond it
+    pub fn with_capacity_bytes(self, capacity_bytes: usize) -> Self {
+        Self {
+            chunk_store: Arc::new(RwLock::new(ChunkStore::new(capacity_bytes))),
+            ..self
+        }
+    }
+
+    /// Back the in-memory store with a persistent cache in `dir`, capped at
+    /// `capacity_bytes`
+    ///
+    /// Chunks already in the cache count as duplicates straight away, so a
+    /// restarted node dedups against what it saw before the restart.
+    pub fn with_disk_cache(self, dir: impl AsRef<Path>, capacity_bytes: u64) -> Result<Self> {
+        let cache = ChunkCache::open(dir, self.chunk_size, capacity_bytes)?;
+        Ok(Self {
+            disk_cache: Some(Arc::new(RwLock::new(cache))),
+            ..self
+        })
+    }
+
     /// Deduplicate data, returns chunk hashes
     pub async fn deduplicate(&self, data: &[u8]) -> Vec<ChunkHash> {
         let mut hashes = Vec::new();
         let mut chunk_store = self.chunk_store.write().await;
         let mut stats = self.stats.write().await;
+        let mut disk = match &self.disk_cache {
+            Some(cache) => Some(cache.write().await),
+            None => None,
+        };
 
         stats.total_bytes += data.len() as u64;
 
@@ -80,15 +243,28 @@ impl Deduplicator {
             let hash = Self::hash_chunk(chunk);
             stats.chunks_total += 1;
 
-            if chunk_store.contains_key(&hash) {
+            if chunk_store.touch(&hash) {
                 // Duplicate chunk
+                if let Some(disk) = disk.as_mut() {
+                    disk.touch(&hash);
+                }
+                stats.chunks_duplicate += 1;
+                stats.duplicate_bytes += chunk.len() as u64;
+            } else if disk.as_mut().is_some_and(|disk| disk.touch(&hash)) {
+                // Duplicate of a chunk only the disk cache still holds
+                stats.disk_hits += 1;
                 stats.chunks_duplicate += 1;
                 stats.duplicate_bytes += chunk.len() as u64;
             } else {
                 // Unique chunk
-                chunk_store.insert(hash, chunk.to_vec());
+                stats.chunks_evicted += chunk_store.insert(hash, chunk.to_vec());
                 stats.chunks_unique += 1;
                 stats.unique_bytes += chunk.len() as u64;
+                if let Some(disk) = disk.as_mut() {
+                    if let Err(e) = disk.insert(hash, chunk) {
+                        warn!("Failed to add chunk to disk cache: {}", e);
+                    }
+                }
             }
 
             hashes.push(hash);
@@ -100,14 +276,26 @@ impl Deduplicator {
     /// Reconstruct data from chunk hashes
     pub async fn reconstruct(&self, hashes: &[ChunkHash]) -> Option<Vec<u8>> {
         let chunk_store = self.chunk_store.read().await;
+        let mut disk = match &self.disk_cache {
+            Some(cache) => Some(cache.write().await),
+            None => None,
+        };
         let mut data = Vec::new();
 
         for hash in hashes {
             if let Some(chunk) = chunk_store.get(hash) {
                 data.extend_from_slice(chunk);
-            } else {
+                continue;
+            }
+
+            match disk.as_mut().map(|disk| disk.get(hash)) {
+                Some(Ok(Some(chunk))) => data.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    warn!("Failed to read chunk from disk cache: {}", e);
+                    return None;
+                }
                 // Missing chunk
-                return None;
+                _ => return None,
             }
         }
 
@@ -116,7 +304,115 @@ impl Deduplicator {
 
     /// Get statistics
     pub async fn get_stats(&self) -> DedupStats {
-        self.stats.read().await.clone()
+        let mut stats = self.stats.read().await.clone();
+        if let Some(cache) = &self.disk_cache {
+            let cache = cache.read().await;
+            stats.disk_chunks_evicted = cache.evicted();
+            stats.disk_cache_bytes = cache.live_bytes();
+            stats.disk_cache_file_bytes = cache.file_bytes();
+            stats.disk_cache_capacity_bytes = cache.capacity_bytes();
+        }
+        stats
+    }
+
+    /// Number of chunks in the store
+    pub async fn chunk_count(&self) -> usize {
+        self.chunk_store.read().await.len()
+    }
+
+    /// Persist the disk cache index so the next start is warm
+    pub async fn flush(&self) -> Result<()> {
+        match &self.disk_cache {
+            Some(cache) => cache.write().await.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Reclaim disk cache space held by evicted chunks, returning the
+    /// number of bytes freed
+    pub async fn compact(&self) -> Result<u64> {
+        match &self.disk_cache {
+            Some(cache) => cache.write().await.compact(),
+            None => Ok(0),
+        }
+    }
+
+    /// Write the chunk store to `path`
+    ///
+    /// The snapshot is written to a temporary file and renamed into place,
+    /// so a crash mid-save leaves the previous snapshot intact.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let chunk_store = self.chunk_store.read().await;
+
+        let mut buf = Vec::with_capacity(chunk_store.bytes + chunk_store.len() * 36 + 64);
+        buf.extend_from_slice(SNAPSHOT_MAGIC);
+        buf.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        buf.extend_from_slice(&(chunk_store.len() as u64).to_le_bytes());
+
+        // Oldest first, so loading replays the LRU order
+        for (hash, data) in chunk_store.iter_lru() {
+            buf.extend_from_slice(hash);
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(data);
+        }
+        let count = chunk_store.len();
+        drop(chunk_store);
+
+        let checksum: [u8; 32] = Sha256::digest(&buf).into();
+        buf.extend_from_slice(&checksum);
+
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, &buf).await
+            .with_context(|| format!("Failed to write dedup snapshot {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path).await
+            .with_context(|| format!("Failed to move dedup snapshot into {}", path.display()))?;
+
+        info!("Saved {} dedup chunks to {}", count, path.display());
+        Ok(())
+    }
+
+    /// Restore chunks from a snapshot written by [`save`](Self::save)
+    ///
+    /// Returns the number of chunks restored. A missing, corrupt or
+    /// incompatible snapshot is logged and ignored, leaving the store as it
+    /// was. Chunks beyond the store's capacity are evicted oldest first.
+    pub async fn load(&self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+
+        let buf = match tokio::fs::read(path).await {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read dedup snapshot {}", path.display()));
+            }
+        };
+
+        let chunks = match parse_snapshot(&buf, self.chunk_size) {
+            Ok(chunks) => chunks,
+            Err(reason) => {
+                warn!("Ignoring dedup snapshot {}: {}", path.display(), reason);
+                return Ok(0);
+            }
+        };
+
+        let count = chunks.len();
+        let mut chunk_store = self.chunk_store.write().await;
+        let mut evicted = 0;
+        for (hash, data) in chunks {
+            evicted += chunk_store.insert(hash, data);
+        }
+        drop(chunk_store);
+        self.stats.write().await.chunks_evicted += evicted;
+
+        info!("Restored {} dedup chunks from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// Reset statistics, keeping stored chunks
+    pub async fn reset_stats(&self) {
+        *self.stats.write().await = DedupStats::default();
     }
 
     /// Clear chunk store (for testing)
@@ -125,6 +421,11 @@ impl Deduplicator {
         let mut stats = self.stats.write().await;
         chunk_store.clear();
         *stats = DedupStats::default();
+        if let Some(cache) = &self.disk_cache {
+            if let Err(e) = cache.write().await.clear() {
+                warn!("Failed to clear disk cache: {}", e);
+            }
+        }
     }
 
     /// Hash a chunk using SHA-256
@@ -135,6 +436,59 @@ impl Deduplicator {
     }
 }
 
+/// Decode and verify a snapshot, returning chunks oldest first
+fn parse_snapshot(buf: &[u8], chunk_size: usize) -> std::result::Result<Vec<(ChunkHash, Vec<u8>)>, String> {
+    const HEADER_LEN: usize = 8 + 4 + 4 + 8;
+
+    if buf.len() < HEADER_LEN + 32 {
+        return Err("file too short".to_string());
+    }
+
+    let (body, checksum) = buf.split_at(buf.len() - 32);
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err("checksum mismatch".to_string());
+    }
+
+    if &body[..8] != SNAPSHOT_MAGIC {
+        return Err("not a dedup snapshot".to_string());
+    }
+
+    let version = u32::from_le_bytes(body[8..12].try_into().unwrap());
+    if version != SNAPSHOT_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+
+    // Chunk boundaries depend on the chunk size, so other sizes never match
+    let snapshot_chunk_size = u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize;
+    if snapshot_chunk_size != chunk_size {
+        return Err(format!("chunk size {} does not match {}", snapshot_chunk_size, chunk_size));
+    }
+
+    let count = u64::from_le_bytes(body[16..24].try_into().unwrap());
+    let mut chunks = Vec::new();
+    let mut pos = HEADER_LEN;
+
+    for _ in 0..count {
+        let header = body.get(pos..pos + 36).ok_or("truncated chunk header")?;
+        let hash: ChunkHash = header[..32].try_into().unwrap();
+        let len = u32::from_le_bytes(header[32..36].try_into().unwrap()) as usize;
+        pos += 36;
+
+        let data = body.get(pos..pos + len).ok_or("truncated chunk data")?;
+        if Deduplicator::hash_chunk(data) != hash {
+            return Err("chunk hash mismatch".to_string());
+        }
+        chunks.push((hash, data.to_vec()));
+        pos += len;
+    }
+
+    if pos != body.len() {
+        return Err("trailing data".to_string());
+    }
+
+    Ok(chunks)
+}
+
 impl Default for Deduplicator {
     fn default() -> Self {
         Self::new()
@@ -199,4 +553,132 @@ mod tests {
         assert_eq!(stats.chunks_duplicate, 4);
         assert_eq!(stats.space_savings_pct(), 50.0);
     }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let dedup = Deduplicator::with_chunk_size(4).with_capacity_bytes(8);
+
+        dedup.deduplicate(b"AAAA").await;
+        dedup.deduplicate(b"BBBB").await;
+        // Touch A so B is the least recently used
+        dedup.deduplicate(b"AAAA").await;
+        dedup.deduplicate(b"CCCC").await;
+
+        assert_eq!(dedup.chunk_count().await, 2);
+        assert_eq!(dedup.get_stats().await.chunks_evicted, 1);
+
+        let hashes = dedup.deduplicate(b"AAAA").await;
+        assert!(dedup.reconstruct(&hashes).await.is_some());
+        assert!(dedup.reconstruct(&[Deduplicator::hash_chunk(b"BBBB")]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dedup_ratio_preserved_across_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.snapshot");
+        let data: Vec<u8> = (0..64 * 1024u32).map(|i| (i * 7 % 251) as u8).collect();
+
+        // Dedup ratio of a pass over already-seen traffic
+        let before = Deduplicator::with_chunk_size(1024);
+        before.deduplicate(&data).await;
+        let cold = before.get_stats().await;
+        before.deduplicate(&data).await;
+        let warm = before.get_stats().await;
+        let warm_ratio = (warm.duplicate_bytes - cold.duplicate_bytes) as f64 / data.len() as f64;
+        assert_eq!(warm_ratio, 1.0);
+
+        before.save(&path).await.unwrap();
+
+        // A restarted node sees the same traffic as duplicate straight away
+        let after = Deduplicator::with_chunk_size(1024);
+        assert_eq!(after.load(&path).await.unwrap(), before.chunk_count().await);
+        let hashes = after.deduplicate(&data).await;
+
+        assert_eq!(after.get_stats().await.dedup_ratio(), warm_ratio);
+        assert_eq!(after.reconstruct(&hashes).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_snapshot_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.snapshot");
+
+        let dedup = Deduplicator::with_chunk_size(8);
+        dedup.deduplicate(b"0123456789ABCDEF").await;
+        dedup.save(&path).await.unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mid = bytes.len() / 2;
+        bytes[mid] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let restored = Deduplicator::with_chunk_size(8);
+        assert_eq!(restored.load(&path).await.unwrap(), 0);
+        assert_eq!(restored.chunk_count().await, 0);
+
+        // Truncated files and garbage are ignored too
+        std::fs::write(&path, &bytes[..20]).unwrap();
+        assert_eq!(restored.load(&path).await.unwrap(), 0);
+        std::fs::write(&path, b"not a snapshot at all, just some bytes that are long enough").unwrap();
+        assert_eq!(restored.load(&path).await.unwrap(), 0);
+
+        // Missing snapshot is a normal first start
+        assert_eq!(restored.load(dir.path().join("missing")).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_with_other_chunk_size_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.snapshot");
+
+        let dedup = Deduplicator::with_chunk_size(8);
+        dedup.deduplicate(b"0123456789ABCDEF").await;
+        dedup.save(&path).await.unwrap();
+
+        assert_eq!(Deduplicator::with_chunk_size(16).load(&path).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..32 * 1024u32).map(|i| (i * 13 % 241) as u8).collect();
+
+        let before = Deduplicator::with_chunk_size(1024)
+            .with_disk_cache(dir.path(), 1024 * 1024)
+            .unwrap();
+        before.deduplicate(&data).await;
+        before.flush().await.unwrap();
+        drop(before);
+
+        let after = Deduplicator::with_chunk_size(1024)
+            .with_disk_cache(dir.path(), 1024 * 1024)
+            .unwrap();
+        let hashes = after.deduplicate(&data).await;
+
+        let stats = after.get_stats().await;
+        assert_eq!(stats.hit_ratio(), 1.0);
+        assert_eq!(stats.disk_hits, stats.chunks_total);
+        assert_eq!(stats.bytes_saved(), data.len() as u64);
+        assert!(stats.disk_cache_utilization() > 0.0);
+        assert_eq!(after.reconstruct(&hashes).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_compact_reclaims_evicted_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        // Room for two 8 byte chunks and their record headers
+        let dedup = Deduplicator::with_chunk_size(8)
+            .with_disk_cache(dir.path(), 2 * 44)
+            .unwrap();
+
+        dedup.deduplicate(b"AAAAAAAABBBBBBBBCCCCCCCC").await;
+        let stats = dedup.get_stats().await;
+        assert_eq!(stats.disk_chunks_evicted, 1);
+        assert_eq!(stats.disk_cache_utilization(), 1.0);
+        assert!(stats.disk_cache_file_bytes > stats.disk_cache_bytes + 24);
+
+        assert_eq!(dedup.compact().await.unwrap(), 44);
+        let stats = dedup.get_stats().await;
+        assert_eq!(stats.disk_cache_file_bytes, stats.disk_cache_bytes + 24);
+    }
 }
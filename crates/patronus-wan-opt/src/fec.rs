@@ -1,10 +1,24 @@
 //! Forward Error Correction (FEC)
 //!
 //! Implements FEC to reduce retransmissions over lossy WAN links
-//! Uses Reed-Solomon coding for error correction
+//! Uses Reed-Solomon coding for error correction. In adaptive mode the
+//! encoder sizes parity from the measured path loss.
+//!
+//! Blocks sent with [`FecEncoder::encode_packets`] carry a [`BlockHeader`] in
+//! every packet, so the decoder follows parity changes mid-stream.
+//! [`PathFecController`] keeps one adaptive encoder per path and can follow
+//! loss measurements from the SD-WAN path monitor.
 
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
 
 /// FEC statistics
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -13,6 +27,21 @@ pub struct FecStats {
     pub packets_decoded: u64,
     pub errors_corrected: u64,
     pub unrecoverable_errors: u64,
+    /// Blocks whose missing data shards were rebuilt from parity
+    #[serde(default)]
+    pub packets_recovered: u64,
+    /// Parity shards currently added per block
+    #[serde(default)]
+    pub parity_shards: u64,
+    /// Current parity/data ratio
+    #[serde(default)]
+    pub redundancy: f64,
+    /// Payload bytes passed to the encoder
+    #[serde(default)]
+    pub data_bytes: u64,
+    /// Padding and parity bytes added on top of the payload
+    #[serde(default)]
+    pub overhead_bytes: u64,
 }
 
 impl FecStats {
@@ -24,12 +53,115 @@ impl FecStats {
             self.errors_corrected as f64 / self.packets_decoded as f64
         }
     }
+
+    /// Added bytes as a fraction of the payload
+    pub fn overhead_ratio(&self) -> f64 {
+        if self.data_bytes == 0 {
+            0.0
+        } else {
+            self.overhead_bytes as f64 / self.data_bytes as f64
+        }
+    }
+}
+
+/// Bounds and tuning for loss-driven redundancy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdaptiveFecConfig {
+    /// Lowest parity/data ratio, used on a clean link
+    pub min_redundancy: f64,
+
+    /// Highest parity/data ratio
+    pub max_redundancy: f64,
+
+    /// Multiplier on measured loss, to cover bursts
+    pub loss_headroom: f64,
+
+    /// Fraction of the gap to the lower target closed per update when loss
+    /// falls; increases take effect immediately
+    pub backoff: f64,
+
+    /// How often [`PathFecController`] lets redundancy decay on a path
+    #[serde(default = "default_adaptation_interval")]
+    pub adaptation_interval: Duration,
+}
+
+fn default_adaptation_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for AdaptiveFecConfig {
+    fn default() -> Self {
+        Self {
+            min_redundancy: 0.125,
+            max_redundancy: 0.5,
+            loss_headroom: 2.0,
+            backoff: 0.1,
+            adaptation_interval: default_adaptation_interval(),
+        }
+    }
+}
+
+/// Generation parameters sent in front of every shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub block_id: u32,
+    /// Position of the shard in the block, data shards first
+    pub index: u8,
+    pub data_shards: u8,
+    pub parity_shards: u8,
+    /// Payload length before padding
+    pub original_len: u32,
+}
+
+impl BlockHeader {
+    pub const LEN: usize = 12;
+    const VERSION: u8 = 1;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0u8; Self::LEN];
+        buf[0] = Self::VERSION;
+        buf[1..5].copy_from_slice(&self.block_id.to_be_bytes());
+        buf[5] = self.index;
+        buf[6] = self.data_shards;
+        buf[7] = self.parity_shards;
+        buf[8..12].copy_from_slice(&self.original_len.to_be_bytes());
+        buf
+    }
+
+    /// Split a packet into its header and shard
+    pub fn parse(packet: &[u8]) -> Result<(Self, &[u8])> {
+        if packet.len() < Self::LEN {
+            bail!("FEC packet too short");
+        }
+        if packet[0] != Self::VERSION {
+            bail!("Unsupported FEC header version {}", packet[0]);
+        }
+
+        let header = Self {
+            block_id: u32::from_be_bytes(packet[1..5].try_into().unwrap()),
+            index: packet[5],
+            data_shards: packet[6],
+            parity_shards: packet[7],
+            original_len: u32::from_be_bytes(packet[8..12].try_into().unwrap()),
+        };
+        if header.data_shards == 0 || header.index as usize >= header.total_shards() {
+            bail!("Invalid FEC header {:?}", header);
+        }
+        Ok((header, &packet[Self::LEN..]))
+    }
+
+    pub fn total_shards(&self) -> usize {
+        self.data_shards as usize + self.parity_shards as usize
+    }
 }
 
 /// FEC encoder
 pub struct FecEncoder {
     data_shards: usize,
     parity_shards: usize,
+    adaptive: Option<AdaptiveFecConfig>,
+    redundancy: f64,
+    next_block: u32,
     stats: FecStats,
 }
 
@@ -40,17 +172,91 @@ impl FecEncoder {
     /// * `data_shards` - Number of data shards (typically 4-16)
     /// * `parity_shards` - Number of parity shards (typically 1-4)
     pub fn new(data_shards: usize, parity_shards: usize) -> Self {
+        let redundancy = parity_shards as f64 / data_shards.max(1) as f64;
         Self {
             data_shards,
             parity_shards,
-            stats: FecStats::default(),
+            adaptive: None,
+            redundancy,
+            next_block: 0,
+            stats: FecStats {
+                parity_shards: parity_shards as u64,
+                redundancy,
+                ..FecStats::default()
+            },
         }
     }
 
+    /// Create an encoder whose parity follows the loss reported through
+    /// [`update_loss`](Self::update_loss), starting at the minimum redundancy
+    pub fn adaptive(data_shards: usize, config: AdaptiveFecConfig) -> Self {
+        let mut encoder = Self::new(data_shards, 0);
+        encoder.redundancy = config.min_redundancy;
+        encoder.adaptive = Some(config);
+        encoder.apply_redundancy();
+        encoder
+    }
+
+    /// Feed a packet loss measurement (percent) from the path monitor
+    ///
+    /// Has no effect on fixed-ratio encoders.
+    pub fn update_loss(&mut self, loss_pct: f64) {
+        let (Some(config), Some(target)) = (&self.adaptive, self.target_redundancy(loss_pct)) else {
+            return;
+        };
+
+        if target >= self.redundancy {
+            self.redundancy = target;
+        } else {
+            self.redundancy -= (self.redundancy - target) * config.backoff.clamp(0.0, 1.0);
+            // The decay is exponential; settle once within a rounding error
+            if self.redundancy - target < 1e-3 {
+                self.redundancy = target;
+            }
+        }
+
+        self.apply_redundancy();
+    }
+
+    /// Redundancy an adaptive encoder aims for at `loss_pct`
+    fn target_redundancy(&self, loss_pct: f64) -> Option<f64> {
+        let config = self.adaptive.as_ref()?;
+
+        // Parity needed so that `headroom` times the expected losses in a
+        // block of data + parity shards can be recovered
+        let expected = (loss_pct / 100.0).clamp(0.0, 1.0) * config.loss_headroom;
+        let target = if expected < 1.0 {
+            expected / (1.0 - expected)
+        } else {
+            config.max_redundancy
+        };
+        Some(target.clamp(config.min_redundancy, config.max_redundancy))
+    }
+
+    /// Parity shards currently added per block
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    fn apply_redundancy(&mut self) {
+        let Some(config) = &self.adaptive else {
+            return;
+        };
+
+        let k = self.data_shards as f64;
+        let min = (config.min_redundancy * k - 1e-9).ceil().max(0.0) as usize;
+        let max = ((config.max_redundancy * k + 1e-9).floor() as usize).max(min);
+
+        self.parity_shards = ((self.redundancy * k - 1e-9).ceil().max(0.0) as usize).clamp(min, max);
+        self.stats.parity_shards = self.parity_shards as u64;
+        self.stats.redundancy = self.parity_shards as f64 / self.data_shards.max(1) as f64;
+    }
+
     /// Encode data with FEC
     /// Returns data shards + parity shards
     pub fn encode(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
-        let shard_size = (data.len() + self.data_shards - 1) / self.data_shards;
+        // Reed-Solomon needs non-empty shards
+        let shard_size = data.len().div_ceil(self.data_shards).max(1);
         let mut shards = Vec::new();
 
         // Split data into shards
@@ -69,25 +275,63 @@ impl FecEncoder {
             shards.push(shard);
         }
 
-        // Generate parity shards (simplified - real implementation would use Reed-Solomon)
-        for _ in 0..self.parity_shards {
-            let mut parity = vec![0u8; shard_size];
-            for shard in &shards {
-                for (i, &byte) in shard.iter().enumerate() {
-                    parity[i] ^= byte; // XOR for simple parity
-                }
-            }
-            shards.push(parity);
+        // Any `data_shards` of the block are enough to rebuild it
+        if self.parity_shards > 0 {
+            shards.extend(std::iter::repeat_n(vec![0u8; shard_size], self.parity_shards));
+            ReedSolomon::new(self.data_shards, self.parity_shards)
+                .and_then(|rs| rs.encode(&mut shards))
+                .map_err(|e| anyhow!("Reed-Solomon encoding failed: {:?}", e))?;
         }
 
         self.stats.packets_encoded += shards.len() as u64;
+        self.stats.data_bytes += data.len() as u64;
+        self.stats.overhead_bytes += (shards.len() * shard_size - data.len()) as u64;
         Ok(shards)
     }
 
+    /// Encode `data` as one block of packets, each prefixed with a
+    /// [`BlockHeader`]
+    pub fn encode_packets(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        if self.data_shards == 0 || self.data_shards + self.parity_shards > u8::MAX as usize {
+            bail!("Unsupported FEC geometry {}+{}", self.data_shards, self.parity_shards);
+        }
+        let original_len = u32::try_from(data.len()).map_err(|_| anyhow!("FEC block too large"))?;
+
+        let header = BlockHeader {
+            block_id: self.next_block,
+            index: 0,
+            data_shards: self.data_shards as u8,
+            parity_shards: self.parity_shards as u8,
+            original_len,
+        };
+        self.next_block = self.next_block.wrapping_add(1);
+
+        let shards = self.encode(data)?;
+        self.stats.overhead_bytes += (shards.len() * BlockHeader::LEN) as u64;
+        Ok(shards
+            .into_iter()
+            .enumerate()
+            .map(|(index, shard)| {
+                let mut packet = BlockHeader { index: index as u8, ..header }.to_bytes().to_vec();
+                packet.extend_from_slice(&shard);
+                packet
+            })
+            .collect())
+    }
+
     /// Get statistics
     pub fn stats(&self) -> &FecStats {
         &self.stats
     }
+
+    /// Reset counters, keeping the current parity settings
+    pub fn reset_stats(&mut self) {
+        self.stats = FecStats {
+            parity_shards: self.stats.parity_shards,
+            redundancy: self.stats.redundancy,
+            ..FecStats::default()
+        };
+    }
 }
 
 impl Default for FecEncoder {
@@ -96,80 +340,156 @@ impl Default for FecEncoder {
     }
 }
 
+/// Blocks a decoder waits on before giving up on the oldest
+const DEFAULT_REORDER_WINDOW: u32 = 16;
+
+/// Recently finished block ids remembered to drop late shards
+const FINISHED_HISTORY: usize = 64;
+
+/// Shards received so far for one block
+struct PendingBlock {
+    header: BlockHeader,
+    shards: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
 /// FEC decoder
 pub struct FecDecoder {
     data_shards: usize,
     parity_shards: usize,
+    pending: BTreeMap<u32, PendingBlock>,
+    finished: VecDeque<u32>,
+    reorder_window: u32,
     stats: FecStats,
 }
 
 impl FecDecoder {
     /// Create new FEC decoder
+    ///
+    /// `parity_shards` is the most parity a block may carry; blocks from an
+    /// adaptive encoder may carry fewer.
     pub fn new(data_shards: usize, parity_shards: usize) -> Self {
         Self {
             data_shards,
             parity_shards,
+            pending: BTreeMap::new(),
+            finished: VecDeque::new(),
+            reorder_window: DEFAULT_REORDER_WINDOW,
             stats: FecStats::default(),
         }
     }
 
+    /// Feed one packet from [`FecEncoder::encode_packets`]
+    ///
+    /// Returns the block's payload as soon as enough of its shards have
+    /// arrived. Blocks falling more than the reorder window behind the
+    /// newest one are given up and counted as unrecoverable.
+    pub fn receive(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (header, shard) = BlockHeader::parse(packet)?;
+        if self.finished.contains(&header.block_id) {
+            return Ok(None);
+        }
+        self.expire_before(header.block_id);
+
+        let block = self.pending.entry(header.block_id).or_insert_with(|| PendingBlock {
+            header: BlockHeader { index: 0, ..header },
+            shards: vec![None; header.total_shards()],
+            received: 0,
+        });
+        if block.header != (BlockHeader { index: 0, ..header }) {
+            bail!("Inconsistent FEC headers for block {}", header.block_id);
+        }
+
+        let slot = &mut block.shards[header.index as usize];
+        if slot.is_none() {
+            *slot = Some(shard.to_vec());
+            block.received += 1;
+        }
+        if block.received < header.data_shards as usize {
+            return Ok(None);
+        }
+
+        let block = self.pending.remove(&header.block_id).unwrap();
+        self.mark_finished(header.block_id);
+        self.reconstruct(block.shards, block.header.data_shards as usize, block.header.original_len as usize)
+            .map(Some)
+    }
+
+    /// Give up on every incomplete block, returning how many there were
+    pub fn finish(&mut self) -> usize {
+        let abandoned = std::mem::take(&mut self.pending);
+        for id in abandoned.keys() {
+            self.mark_finished(*id);
+        }
+        self.stats.unrecoverable_errors += abandoned.len() as u64;
+        abandoned.len()
+    }
+
+    fn expire_before(&mut self, newest: u32) {
+        let expired: Vec<u32> = self.pending.keys()
+            .copied()
+            .filter(|&id| {
+                let age = newest.wrapping_sub(id);
+                age > self.reorder_window && age < u32::MAX / 2
+            })
+            .collect();
+
+        for id in expired {
+            self.pending.remove(&id);
+            self.mark_finished(id);
+            self.stats.unrecoverable_errors += 1;
+            debug!("Gave up on FEC block {}", id);
+        }
+    }
+
+    fn mark_finished(&mut self, id: u32) {
+        if self.finished.len() == FINISHED_HISTORY {
+            self.finished.pop_front();
+        }
+        self.finished.push_back(id);
+    }
+
     /// Decode data from shards (some may be missing/corrupted)
     ///
     /// # Arguments
     /// * `shards` - All shards (None for missing shards)
     /// * `original_size` - Original data size before encoding
     pub fn decode(&mut self, shards: Vec<Option<Vec<u8>>>, original_size: usize) -> Result<Vec<u8>> {
-        self.stats.packets_decoded += 1;
-
-        let total_shards = self.data_shards + self.parity_shards;
-        if shards.len() != total_shards {
+        let max_shards = self.data_shards + self.parity_shards;
+        if shards.len() < self.data_shards || shards.len() > max_shards {
+            self.stats.packets_decoded += 1;
             anyhow::bail!("Invalid shard count");
         }
 
-        let available: Vec<usize> = shards.iter()
-            .enumerate()
-            .filter_map(|(i, s)| if s.is_some() { Some(i) } else { None })
-            .collect();
+        self.reconstruct(shards, self.data_shards, original_size)
+    }
+
+    fn reconstruct(&mut self, mut shards: Vec<Option<Vec<u8>>>, data_shards: usize, original_size: usize) -> Result<Vec<u8>> {
+        self.stats.packets_decoded += 1;
 
-        if available.len() < self.data_shards {
+        let available = shards.iter().filter(|s| s.is_some()).count();
+        if available < data_shards {
             self.stats.unrecoverable_errors += 1;
             anyhow::bail!("Not enough shards to reconstruct data");
         }
 
         // Check if we needed to use parity shards
-        let missing_data_shards = (0..self.data_shards)
-            .filter(|i| shards[*i].is_none())
-            .count();
-
+        let missing_data_shards = shards[..data_shards].iter().filter(|s| s.is_none()).count();
         if missing_data_shards > 0 {
+            ReedSolomon::new(data_shards, shards.len() - data_shards)
+                .and_then(|rs| rs.reconstruct_data(&mut shards))
+                .map_err(|e| {
+                    self.stats.unrecoverable_errors += 1;
+                    anyhow!("Reed-Solomon reconstruction failed: {:?}", e)
+                })?;
             self.stats.errors_corrected += missing_data_shards as u64;
+            self.stats.packets_recovered += 1;
         }
 
-        // Reconstruct data shards
-        let shard_size = shards.iter()
-            .find_map(|s| s.as_ref().map(|v| v.len()))
-            .unwrap_or(0);
-
-        let mut data = Vec::new();
-        for i in 0..self.data_shards {
-            if let Some(shard) = &shards[i] {
-                data.extend_from_slice(shard);
-            } else {
-                // Reconstruct from parity (simplified)
-                let mut reconstructed = vec![0u8; shard_size];
-
-                // XOR all available shards to reconstruct
-                for &idx in &available {
-                    if let Some(shard) = &shards[idx] {
-                        for (j, &byte) in shard.iter().enumerate() {
-                            reconstructed[j] ^= byte;
-                        }
-                    }
-                }
-
-                data.extend_from_slice(&reconstructed);
-            }
-        }
+        let mut data: Vec<u8> = shards.into_iter()
+            .take(data_shards)
+            .flat_map(|s| s.unwrap_or_default())
+            .collect();
 
         // Trim to original size
         data.truncate(original_size);
@@ -188,6 +508,112 @@ impl Default for FecDecoder {
     }
 }
 
+/// Loss-driven state of one path
+struct PathFec {
+    encoder: FecEncoder,
+    /// Worst loss seen since the last adaptation
+    window_loss: f64,
+    window_start: Instant,
+}
+
+/// Adaptive FEC encoders for many paths
+///
+/// Rising loss raises a path's redundancy as soon as it is reported. Lower
+/// loss is applied at most once per adaptation interval, using the worst
+/// sample of the interval, so redundancy decays at the same slow pace
+/// however often the monitor reports.
+pub struct PathFecController<K> {
+    data_shards: usize,
+    config: AdaptiveFecConfig,
+    paths: Mutex<HashMap<K, PathFec>>,
+}
+
+impl<K: Eq + Hash + Clone + std::fmt::Debug + Send + 'static> PathFecController<K> {
+    pub fn new(data_shards: usize, config: AdaptiveFecConfig) -> Self {
+        Self {
+            data_shards,
+            config,
+            paths: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a packet loss measurement (percent) for `path`
+    pub fn record_loss(&self, path: K, loss_pct: f64) {
+        self.record_loss_at(path, loss_pct, Instant::now());
+    }
+
+    fn record_loss_at(&self, path: K, loss_pct: f64, now: Instant) {
+        let mut paths = self.paths.lock().unwrap();
+        let state = paths.entry(path.clone()).or_insert_with(|| self.new_path(now));
+        state.window_loss = state.window_loss.max(loss_pct);
+
+        let before = state.encoder.parity_shards();
+        if state.encoder.target_redundancy(loss_pct).is_some_and(|t| t > state.encoder.redundancy) {
+            state.encoder.update_loss(loss_pct);
+        }
+        if now.duration_since(state.window_start) >= self.config.adaptation_interval {
+            state.encoder.update_loss(state.window_loss);
+            state.window_loss = 0.0;
+            state.window_start = now;
+        }
+
+        let after = state.encoder.parity_shards();
+        if after != before {
+            debug!("FEC parity for path {:?} now {}+{} at {:.1}% loss", path, self.data_shards, after, loss_pct);
+        }
+    }
+
+    /// Encode a block for `path` with its current redundancy
+    pub fn encode_packets(&self, path: &K, data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut paths = self.paths.lock().unwrap();
+        let state = paths.entry(path.clone()).or_insert_with(|| self.new_path(Instant::now()));
+        state.encoder.encode_packets(data)
+    }
+
+    /// Statistics of the encoder for `path`
+    pub fn stats(&self, path: &K) -> Option<FecStats> {
+        self.paths.lock().unwrap().get(path).map(|s| s.encoder.stats().clone())
+    }
+
+    /// Forget a path that no longer exists
+    pub fn remove_path(&self, path: &K) -> bool {
+        self.paths.lock().unwrap().remove(path).is_some()
+    }
+
+    /// Apply loss measurements from a broadcast feed, such as the SD-WAN
+    /// path monitor's, until the sender goes away
+    ///
+    /// `extract` maps each message to a path and its loss percentage.
+    pub fn follow<T, F>(self: Arc<Self>, mut rx: broadcast::Receiver<T>, extract: F) -> JoinHandle<()>
+    where
+        T: Clone + Send + 'static,
+        F: Fn(T) -> (K, f64) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(message) => {
+                        let (path, loss_pct) = extract(message);
+                        self.record_loss(path, loss_pct);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("FEC controller skipped {} loss measurements", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    fn new_path(&self, now: Instant) -> PathFec {
+        PathFec {
+            encoder: FecEncoder::adaptive(self.data_shards, self.config.clone()),
+            window_loss: 0.0,
+            window_start: now,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -227,13 +653,10 @@ mod tests {
         shards_opt[3] = None;
 
         let decoded = decoder.decode(shards_opt, data.len()).unwrap();
-
-        // Note: Simple XOR parity won't perfectly reconstruct, but demonstrates concept
-        // Real implementation would use Reed-Solomon
-        assert_eq!(decoded.len(), data.len());
+        assert_eq!(&decoded[..], data);
 
         let stats = decoder.stats();
-        assert!(stats.errors_corrected > 0);
+        assert_eq!(stats.errors_corrected, 2);
     }
 
     #[test]
@@ -267,4 +690,255 @@ mod tests {
         let stats = decoder.stats();
         assert_eq!(stats.unrecoverable_errors, 1);
     }
+
+    #[test]
+    fn test_rising_loss_increases_parity_monotonically() {
+        let mut encoder = FecEncoder::adaptive(16, AdaptiveFecConfig::default());
+        assert_eq!(encoder.parity_shards(), 2);
+
+        let mut last = encoder.stats().redundancy;
+        for step in 0..=20 {
+            encoder.update_loss(step as f64);
+            let redundancy = encoder.stats().redundancy;
+            assert!(redundancy >= last, "redundancy fell from {} to {} at {}% loss", last, redundancy, step);
+            last = redundancy;
+        }
+
+        // Capped at max_redundancy
+        assert_eq!(encoder.parity_shards(), 8);
+        assert_eq!(encoder.stats().redundancy, 0.5);
+    }
+
+    #[test]
+    fn test_clean_link_backs_off_gradually() {
+        let mut encoder = FecEncoder::adaptive(16, AdaptiveFecConfig::default());
+        encoder.update_loss(20.0);
+        let high = encoder.parity_shards();
+        assert_eq!(high, 8);
+
+        // One clean sample barely moves it, a few bring it down part way
+        encoder.update_loss(0.0);
+        assert_eq!(encoder.parity_shards(), high);
+        for _ in 0..4 {
+            encoder.update_loss(0.0);
+        }
+        assert!(encoder.parity_shards() < high);
+        assert!(encoder.parity_shards() > 2);
+
+        for _ in 0..100 {
+            encoder.update_loss(0.0);
+        }
+        assert_eq!(encoder.parity_shards(), 2);
+
+        let shards = encoder.encode(b"clean link").unwrap();
+        assert_eq!(shards.len(), 18);
+    }
+
+    #[test]
+    fn test_fixed_encoder_ignores_loss() {
+        let mut encoder = FecEncoder::new(8, 2);
+        encoder.update_loss(30.0);
+        assert_eq!(encoder.parity_shards(), 2);
+        assert_eq!(encoder.stats().redundancy, 0.25);
+    }
+
+    #[test]
+    fn test_decoder_accepts_fewer_parity_shards() {
+        let mut encoder = FecEncoder::adaptive(4, AdaptiveFecConfig::default());
+        let mut decoder = FecDecoder::new(4, 2);
+
+        let data = b"Adaptive block";
+        let mut shards: Vec<Option<Vec<u8>>> = encoder.encode(data).unwrap().into_iter().map(Some).collect();
+        assert_eq!(shards.len(), 5);
+        shards[2] = None;
+
+        decoder.decode(shards, data.len()).unwrap();
+        assert_eq!(decoder.stats().packets_recovered, 1);
+    }
+
+    /// Deterministic packet loss
+    struct LossyLink(u64);
+
+    impl LossyLink {
+        fn drops(&mut self, loss_pct: f64) -> bool {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % 10_000) as f64 / 100.0 < loss_pct
+        }
+    }
+
+    #[test]
+    fn test_decoder_follows_parity_changes_mid_stream() {
+        let mut encoder = FecEncoder::adaptive(4, AdaptiveFecConfig::default());
+        let mut decoder = FecDecoder::new(4, 0);
+
+        let first = encoder.encode_packets(b"before the loss spike").unwrap();
+        encoder.update_loss(20.0);
+        let second = encoder.encode_packets(b"during the loss spike").unwrap();
+        assert_eq!(first.len(), 5);
+        assert_eq!(second.len(), 6);
+
+        let (header, _) = BlockHeader::parse(&second[5]).unwrap();
+        assert_eq!(header, BlockHeader { block_id: 1, index: 5, data_shards: 4, parity_shards: 2, original_len: 21 });
+
+        // Two shards of the second block lost, delivered out of order
+        let mut delivered = Vec::new();
+        for packet in [&second[5], &first[0], &second[0], &first[1], &second[3], &first[4], &first[2], &second[4]] {
+            if let Some(data) = decoder.receive(packet).unwrap() {
+                delivered.push(data);
+            }
+        }
+        // Late shard of a finished block is ignored
+        assert!(decoder.receive(&first[3]).unwrap().is_none());
+
+        assert_eq!(delivered, vec![b"before the loss spike".to_vec(), b"during the loss spike".to_vec()]);
+        assert_eq!(decoder.stats().packets_recovered, 2);
+        assert_eq!(decoder.finish(), 0);
+    }
+
+    #[test]
+    fn test_decoder_gives_up_on_stale_blocks() {
+        let mut encoder = FecEncoder::new(4, 1);
+        let mut decoder = FecDecoder::new(4, 1);
+
+        let lost = encoder.encode_packets(b"mostly lost").unwrap();
+        decoder.receive(&lost[0]).unwrap();
+        for _ in 0..DEFAULT_REORDER_WINDOW + 1 {
+            for packet in encoder.encode_packets(b"fine").unwrap() {
+                decoder.receive(&packet).unwrap();
+            }
+        }
+
+        assert_eq!(decoder.stats().unrecoverable_errors, 1);
+        assert!(decoder.receive(&lost[1]).unwrap().is_none());
+        assert!(BlockHeader::parse(&[2; 16]).is_err());
+    }
+
+    #[test]
+    fn test_controller_rises_immediately_and_decays_per_interval() {
+        let config = AdaptiveFecConfig::default();
+        let interval = config.adaptation_interval;
+        let controller = PathFecController::new(16, config);
+        let start = Instant::now();
+
+        controller.record_loss_at("wan1", 0.0, start);
+        assert_eq!(controller.stats(&"wan1").unwrap().parity_shards, 2);
+
+        // Loss rising is applied on the sample that reports it
+        controller.record_loss_at("wan1", 20.0, start + interval / 10);
+        assert_eq!(controller.stats(&"wan1").unwrap().parity_shards, 8);
+
+        // Many clean samples inside one interval change nothing
+        for i in 2..10 {
+            controller.record_loss_at("wan1", 0.0, start + interval * i / 10);
+        }
+        assert_eq!(controller.stats(&"wan1").unwrap().parity_shards, 8);
+
+        // The interval still containing the spike keeps redundancy up
+        controller.record_loss_at("wan1", 0.0, start + interval);
+        assert_eq!(controller.stats(&"wan1").unwrap().parity_shards, 8);
+
+        // Clean intervals then decay it slowly
+        let mut parity = Vec::new();
+        for i in 2..80 {
+            controller.record_loss_at("wan1", 0.0, start + interval * i);
+            parity.push(controller.stats(&"wan1").unwrap().parity_shards);
+        }
+        assert!(parity.windows(2).all(|w| w[1] <= w[0]));
+        assert!(parity[2] > 2 && parity[2] < 8, "{:?}", parity);
+        assert_eq!(*parity.last().unwrap(), 2);
+
+        assert!(controller.stats(&"wan2").is_none());
+        assert!(controller.remove_path(&"wan1"));
+    }
+
+    #[tokio::test]
+    async fn test_controller_follows_monitor_feed() {
+        let controller = Arc::new(PathFecController::new(8, AdaptiveFecConfig::default()));
+        let (tx, rx) = broadcast::channel(8);
+        let task = controller.clone().follow(rx, |(path, loss): (u64, f64)| (path, loss));
+
+        tx.send((7, 15.0)).unwrap();
+        drop(tx);
+        task.await.unwrap();
+
+        assert_eq!(controller.stats(&7).unwrap().parity_shards, 4);
+    }
+
+    /// Send `blocks_per_interval` blocks per trace step over a link with the
+    /// step's loss, returning (payload bytes delivered, wire bytes, blocks lost)
+    fn replay_trace(
+        trace: &[f64],
+        blocks_per_interval: usize,
+        mut encode: impl FnMut(usize, &[u8]) -> Vec<Vec<u8>>,
+    ) -> (usize, usize, u64) {
+        let mut link = LossyLink(0x9e37_79b9_7f4a_7c15);
+        let mut decoder = FecDecoder::default();
+        let block: Vec<u8> = (0..1200u32).map(|i| (i % 251) as u8).collect();
+        let (mut delivered, mut wire) = (0, 0);
+
+        for (step, &loss) in trace.iter().enumerate() {
+            for _ in 0..blocks_per_interval {
+                for packet in encode(step, &block) {
+                    wire += packet.len();
+                    if link.drops(loss) {
+                        continue;
+                    }
+                    if let Some(data) = decoder.receive(&packet).unwrap() {
+                        assert_eq!(data, block);
+                        delivered += data.len();
+                    }
+                }
+            }
+        }
+        decoder.finish();
+        (delivered, wire, decoder.stats().unrecoverable_errors)
+    }
+
+    #[test]
+    fn test_adaptive_fec_improves_goodput_on_loss_trace() {
+        // Packet loss per adaptation interval: clean, a lossy spell, clean
+        let trace = [0.0, 0.5, 0.0, 12.0, 15.0, 10.0, 14.0, 12.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let config = AdaptiveFecConfig::default();
+        let interval = config.adaptation_interval;
+        let start = Instant::now();
+
+        let mut fixed = FecEncoder::new(8, 1);
+        let (static_goodput, _, static_lost) =
+            replay_trace(&trace, 200, |_, data| fixed.encode_packets(data).unwrap());
+
+        // The monitor reports each interval's loss at the start of the next
+        let controller = PathFecController::new(8, config);
+        let mut reported = None;
+        let (adaptive_goodput, adaptive_wire, adaptive_lost) = replay_trace(&trace, 200, |step, data| {
+            if reported != Some(step) {
+                let previous = step.checked_sub(1).map_or(0.0, |s| trace[s]);
+                controller.record_loss_at("wan1", previous, start + interval * step as u32);
+                reported = Some(step);
+            }
+            controller.encode_packets(&"wan1", data).unwrap()
+        });
+
+        let offered = trace.len() * 200 * 1200;
+        assert!(adaptive_goodput as f64 > offered as f64 * 0.95);
+        assert!(
+            adaptive_goodput as f64 > static_goodput as f64 * 1.08,
+            "adaptive delivered {} bytes, static {}", adaptive_goodput, static_goodput
+        );
+        assert!(adaptive_lost * 3 < static_lost, "adaptive lost {} blocks, static {}", adaptive_lost, static_lost);
+
+        // Protecting every block heavily costs more per delivered byte
+        let (heavy_goodput, heavy_wire, _) = {
+            let mut heavy = FecEncoder::new(8, 4);
+            replay_trace(&trace, 200, |_, data| heavy.encode_packets(data).unwrap())
+        };
+        let efficiency = |goodput: usize, wire: usize| goodput as f64 / wire as f64;
+        assert!(efficiency(adaptive_goodput, adaptive_wire) > efficiency(heavy_goodput, heavy_wire));
+        assert!(adaptive_wire < heavy_wire);
+
+        // Redundancy is on its way back down after the clean tail
+        let stats = controller.stats(&"wan1").unwrap();
+        assert!(stats.parity_shards < 4, "{:?}", stats);
+    }
 }
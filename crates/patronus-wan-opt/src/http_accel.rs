@@ -0,0 +1,613 @@
+//! HTTP Acceleration
+//!
+//! Caches HTTP objects on the near side of the WAN link. Fresh objects are
+//! served without a round trip, conditional requests that match a fresh
+//! object are answered with `304 Not Modified` locally, and stale objects
+//! are revalidated with a conditional request so an unchanged body never
+//! crosses the link twice.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// HTTP request as seen by the accelerator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Absolute URL, used as the cache key
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Add a header
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// First value of header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// First value of header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Far side of the WAN link
+#[async_trait]
+pub trait HttpOrigin: Send + Sync {
+    async fn fetch(&self, request: &HttpRequest) -> Result<HttpResponse>;
+}
+
+/// HTTP cache limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpCacheConfig {
+    /// Total body bytes cached
+    pub capacity_bytes: usize,
+
+    /// Largest single object cached
+    pub max_object_bytes: usize,
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity_bytes: 64 * 1024 * 1024,
+            max_object_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// HTTP cache counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpCacheStats {
+    /// Requests answered from a fresh cached object
+    pub hits: u64,
+    /// Cacheable requests sent to the origin in full
+    pub misses: u64,
+    /// Conditional requests answered with a local 304
+    pub not_modified_local: u64,
+    /// Stale objects the origin confirmed unchanged
+    pub revalidated: u64,
+    /// Requests passed straight through (non-GET/HEAD)
+    pub bypassed: u64,
+    /// Objects dropped to stay within capacity
+    pub evicted: u64,
+    /// Body bytes that did not cross the WAN
+    pub bytes_saved: u64,
+}
+
+impl HttpCacheStats {
+    /// WAN round trips avoided entirely
+    pub fn round_trips_saved(&self) -> u64 {
+        self.hits + self.not_modified_local
+    }
+
+    /// Fraction of cacheable requests served without a full origin fetch
+    pub fn hit_rate(&self) -> f64 {
+        let served = self.hits + self.not_modified_local + self.revalidated;
+        let total = served + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            served as f64 / total as f64
+        }
+    }
+}
+
+/// Cached object
+struct CachedObject {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fresh_until: Instant,
+    last_used: u64,
+}
+
+impl CachedObject {
+    /// Whether the client's conditional headers match this object
+    fn satisfies(&self, request: &HttpRequest) -> bool {
+        if let Some(if_none_match) = request.header("if-none-match") {
+            return self.etag.as_deref().is_some_and(|etag| etag_matches(if_none_match, etag));
+        }
+
+        // Exact match against Last-Modified, as browsers echo it verbatim
+        match (request.header("if-modified-since"), &self.last_modified) {
+            (Some(since), Some(modified)) => since == modified,
+            _ => false,
+        }
+    }
+
+    fn response(&self, request: &HttpRequest) -> HttpResponse {
+        let body = if request.method == "HEAD" { Vec::new() } else { self.body.clone() };
+        HttpResponse {
+            status: self.status,
+            headers: self.headers.clone(),
+            body,
+        }
+    }
+
+    fn not_modified(&self) -> HttpResponse {
+        let headers = self.headers.iter()
+            .filter(|(name, _)| {
+                ["etag", "last-modified", "cache-control", "expires", "date", "vary"]
+                    .iter()
+                    .any(|h| name.eq_ignore_ascii_case(h))
+            })
+            .cloned()
+            .collect();
+
+        HttpResponse {
+            status: 304,
+            headers,
+            body: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    objects: HashMap<String, CachedObject>,
+    /// Use tick -> URL, oldest first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    stats: HttpCacheStats,
+}
+
+impl CacheState {
+    fn touch(&mut self, url: &str) {
+        if let Some(object) = self.objects.get_mut(url) {
+            self.tick += 1;
+            self.lru.remove(&object.last_used);
+            object.last_used = self.tick;
+            self.lru.insert(self.tick, url.to_string());
+        }
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some(object) = self.objects.remove(url) {
+            self.lru.remove(&object.last_used);
+            self.bytes -= object.body.len();
+        }
+    }
+
+    fn insert(&mut self, url: &str, mut object: CachedObject, capacity_bytes: usize) {
+        self.remove(url);
+
+        self.tick += 1;
+        object.last_used = self.tick;
+        self.bytes += object.body.len();
+        self.lru.insert(self.tick, url.to_string());
+        self.objects.insert(url.to_string(), object);
+
+        while self.bytes > capacity_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(object) = self.objects.remove(&oldest) {
+                self.bytes -= object.body.len();
+                self.stats.evicted += 1;
+            }
+        }
+    }
+}
+
+/// Near-side HTTP object cache
+pub struct HttpAccelerator {
+    config: HttpCacheConfig,
+    state: Mutex<CacheState>,
+}
+
+impl HttpAccelerator {
+    pub fn new(config: HttpCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Answer `request`, going to `origin` only when the cache cannot
+    pub async fn handle(&self, request: &HttpRequest, origin: &dyn HttpOrigin) -> Result<HttpResponse> {
+        if request.method != "GET" && request.method != "HEAD" {
+            // Writes invalidate what we hold for the URL
+            {
+                let mut state = self.state.lock().unwrap();
+                state.remove(&request.url);
+                state.stats.bypassed += 1;
+            }
+            return origin.fetch(request).await;
+        }
+
+        let now = Instant::now();
+        let revalidate = {
+            let mut state = self.state.lock().unwrap();
+            let fresh = state.objects.get(&request.url).map(|o| now < o.fresh_until);
+
+            match fresh {
+                Some(true) => {
+                    state.touch(&request.url);
+                    let object = &state.objects[&request.url];
+                    let saved = object.body.len();
+                    let response = if object.satisfies(request) {
+                        object.not_modified()
+                    } else {
+                        object.response(request)
+                    };
+
+                    if response.status == 304 {
+                        state.stats.not_modified_local += 1;
+                    } else {
+                        state.stats.hits += 1;
+                    }
+                    state.stats.bytes_saved += saved as u64;
+                    return Ok(response);
+                }
+                Some(false) => {
+                    let object = &state.objects[&request.url];
+                    Some((object.etag.clone(), object.last_modified.clone()))
+                }
+                None => None,
+            }
+        };
+
+        match revalidate {
+            Some((etag, last_modified)) => self.revalidate(request, origin, etag, last_modified).await,
+            None => self.fetch(request, origin).await,
+        }
+    }
+
+    /// Counters
+    pub fn stats(&self) -> HttpCacheStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Number of cached objects
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all cached objects
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.objects.clear();
+        state.lru.clear();
+        state.bytes = 0;
+    }
+
+    async fn fetch(&self, request: &HttpRequest, origin: &dyn HttpOrigin) -> Result<HttpResponse> {
+        // Ask the origin for the full object; the client's validators only
+        // apply to the copy it holds
+        let mut upstream = request.clone();
+        upstream.headers.retain(|(name, _)| {
+            !name.eq_ignore_ascii_case("if-none-match") && !name.eq_ignore_ascii_case("if-modified-since")
+        });
+
+        let response = origin.fetch(&upstream).await?;
+        self.state.lock().unwrap().stats.misses += 1;
+
+        let Some(object) = self.cacheable(&response) else {
+            return Ok(response);
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.insert(&request.url, object, self.config.capacity_bytes);
+        let object = &state.objects[&request.url];
+        if object.satisfies(request) {
+            return Ok(object.not_modified());
+        }
+        Ok(object.response(request))
+    }
+
+    async fn revalidate(
+        &self,
+        request: &HttpRequest,
+        origin: &dyn HttpOrigin,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Result<HttpResponse> {
+        let mut upstream = HttpRequest {
+            method: request.method.clone(),
+            url: request.url.clone(),
+            headers: request.headers.iter()
+                .filter(|(name, _)| {
+                    !name.eq_ignore_ascii_case("if-none-match") && !name.eq_ignore_ascii_case("if-modified-since")
+                })
+                .cloned()
+                .collect(),
+        };
+        if let Some(etag) = &etag {
+            upstream.headers.push(("If-None-Match".to_string(), etag.clone()));
+        }
+        if let Some(modified) = &last_modified {
+            upstream.headers.push(("If-Modified-Since".to_string(), modified.clone()));
+        }
+
+        let response = origin.fetch(&upstream).await?;
+        if response.status != 304 {
+            self.state.lock().unwrap().remove(&request.url);
+            return match self.cacheable(&response) {
+                Some(object) => {
+                    let mut state = self.state.lock().unwrap();
+                    state.stats.misses += 1;
+                    state.insert(&request.url, object, self.config.capacity_bytes);
+                    let object = &state.objects[&request.url];
+                    Ok(if object.satisfies(request) { object.not_modified() } else { object.response(request) })
+                }
+                None => {
+                    self.state.lock().unwrap().stats.misses += 1;
+                    Ok(response)
+                }
+            };
+        }
+
+        // Unchanged: extend freshness from the 304's caching headers
+        let reply = {
+            let mut state = self.state.lock().unwrap();
+            state.touch(&request.url);
+            let refreshed = state.objects.get_mut(&request.url).map(|object| {
+                object.fresh_until = Instant::now() + freshness(&response.headers).unwrap_or_default();
+                let reply = if object.satisfies(request) { object.not_modified() } else { object.response(request) };
+                (reply, object.body.len() as u64)
+            });
+
+            refreshed.map(|(reply, saved)| {
+                state.stats.revalidated += 1;
+                state.stats.bytes_saved += saved;
+                reply
+            })
+        };
+
+        match reply {
+            Some(reply) => Ok(reply),
+            // Evicted while revalidating
+            None => self.fetch(request, origin).await,
+        }
+    }
+
+    /// Cache entry for `response`, if it may and should be stored
+    fn cacheable(&self, response: &HttpResponse) -> Option<CachedObject> {
+        if response.status != 200 || response.body.len() > self.config.max_object_bytes {
+            return None;
+        }
+
+        let cache_control = response.header("cache-control").unwrap_or("").to_ascii_lowercase();
+        if cache_control.split(',').any(|d| matches!(d.trim(), "no-store" | "private")) {
+            return None;
+        }
+
+        let max_age = freshness(&response.headers);
+        let etag = response.header("etag").map(str::to_string);
+        let last_modified = response.header("last-modified").map(str::to_string);
+
+        // Without freshness or a validator the object could never be reused
+        if max_age.is_none_or(|age| age.is_zero()) && etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(CachedObject {
+            status: response.status,
+            headers: response.headers.clone(),
+            body: response.body.clone(),
+            etag,
+            last_modified,
+            fresh_until: Instant::now() + max_age.unwrap_or_default(),
+            last_used: 0,
+        })
+    }
+}
+
+impl Default for HttpAccelerator {
+    fn default() -> Self {
+        Self::new(HttpCacheConfig::default())
+    }
+}
+
+/// Freshness lifetime from `Cache-Control` (`no-cache` means zero)
+fn freshness(headers: &[(String, String)]) -> Option<Duration> {
+    let cache_control = find_header(headers, "cache-control")?.to_ascii_lowercase();
+
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        if directive == "no-cache" {
+            return Some(Duration::ZERO);
+        }
+        if let Some(secs) = directive.strip_prefix("s-maxage=").or_else(|| directive.strip_prefix("max-age=")) {
+            if let Ok(secs) = secs.trim_matches('"').parse::<u64>() {
+                // s-maxage applies to shared caches and wins over max-age
+                if directive.starts_with("s-maxage") || max_age.is_none() {
+                    max_age = Some(Duration::from_secs(secs));
+                }
+            }
+        }
+    }
+
+    max_age
+}
+
+/// Whether an `If-None-Match` list matches `etag` (weak comparison)
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip(etag);
+    if_none_match.split(',').any(|candidate| candidate.trim() == "*" || strip(candidate) == etag)
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Origin serving one object, recording every request it receives
+    struct MockOrigin {
+        etag: &'static str,
+        cache_control: &'static str,
+        body: Vec<u8>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl MockOrigin {
+        fn new(cache_control: &'static str) -> Self {
+            Self {
+                etag: "\"v1\"",
+                cache_control,
+                body: b"<html>quarterly report</html>".repeat(100),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn requests(&self) -> Vec<HttpRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl HttpOrigin for MockOrigin {
+        async fn fetch(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            self.requests.lock().unwrap().push(request.clone());
+
+            let headers = vec![
+                ("ETag".to_string(), self.etag.to_string()),
+                ("Cache-Control".to_string(), self.cache_control.to_string()),
+            ];
+            if request.header("if-none-match") == Some(self.etag) {
+                return Ok(HttpResponse { status: 304, headers, body: Vec::new() });
+            }
+            Ok(HttpResponse { status: 200, headers, body: self.body.clone() })
+        }
+    }
+
+    const URL: &str = "http://intranet.example/report.html";
+
+    #[tokio::test]
+    async fn test_local_304_short_circuit() {
+        let origin = MockOrigin::new("max-age=300");
+        let accel = HttpAccelerator::default();
+
+        let first = accel.handle(&HttpRequest::get(URL), &origin).await.unwrap();
+        assert_eq!(first.status, 200);
+        assert_eq!(first.body, origin.body);
+
+        // The client revalidates its copy: answered locally, no WAN round trip
+        let conditional = HttpRequest::get(URL).with_header("If-None-Match", "\"v1\"");
+        let reply = accel.handle(&conditional, &origin).await.unwrap();
+        assert_eq!(reply.status, 304);
+        assert!(reply.body.is_empty());
+        assert_eq!(reply.header("etag"), Some("\"v1\""));
+
+        // A different validator gets the full cached object
+        let changed = HttpRequest::get(URL).with_header("If-None-Match", "\"v0\"");
+        assert_eq!(accel.handle(&changed, &origin).await.unwrap().status, 200);
+
+        assert_eq!(origin.requests().len(), 1);
+        let stats = accel.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.not_modified_local, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.round_trips_saved(), 2);
+        assert_eq!(stats.bytes_saved, 2 * origin.body.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_stale_object_is_revalidated() {
+        let origin = MockOrigin::new("no-cache");
+        let accel = HttpAccelerator::default();
+
+        accel.handle(&HttpRequest::get(URL), &origin).await.unwrap();
+        let reply = accel.handle(&HttpRequest::get(URL), &origin).await.unwrap();
+
+        // The origin is asked conditionally and the cached body is served
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, origin.body);
+        let requests = origin.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+
+        let stats = accel.stats();
+        assert_eq!(stats.revalidated, 1);
+        assert_eq!(stats.bytes_saved, origin.body.len() as u64);
+        assert_eq!(stats.round_trips_saved(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable_and_bypassed_requests() {
+        let origin = MockOrigin::new("no-store");
+        let accel = HttpAccelerator::default();
+
+        accel.handle(&HttpRequest::get(URL), &origin).await.unwrap();
+        assert!(accel.is_empty());
+
+        let post = HttpRequest { method: "POST".to_string(), ..HttpRequest::get(URL) };
+        accel.handle(&post, &origin).await.unwrap();
+
+        let stats = accel.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.bypassed, 1);
+        assert_eq!(origin.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_evicts_least_recently_used() {
+        let origin = MockOrigin::new("max-age=300");
+        let accel = HttpAccelerator::new(HttpCacheConfig {
+            capacity_bytes: origin.body.len() * 2,
+            max_object_bytes: origin.body.len(),
+        });
+
+        for page in ["a", "b", "c"] {
+            accel.handle(&HttpRequest::get(&format!("{}?{}", URL, page)), &origin).await.unwrap();
+        }
+
+        assert_eq!(accel.len(), 2);
+        assert_eq!(accel.stats().evicted, 1);
+    }
+
+    #[test]
+    fn test_freshness_parsing() {
+        let headers = |cc: &str| vec![("Cache-Control".to_string(), cc.to_string())];
+        assert_eq!(freshness(&headers("public, max-age=60")), Some(Duration::from_secs(60)));
+        assert_eq!(freshness(&headers("s-maxage=10, max-age=60")), Some(Duration::from_secs(10)));
+        assert_eq!(freshness(&headers("max-age=60, no-cache")), Some(Duration::ZERO));
+        assert_eq!(freshness(&[]), None);
+
+        assert!(etag_matches("\"a\", W/\"b\"", "\"b\""));
+        assert!(etag_matches("*", "\"x\""));
+        assert!(!etag_matches("\"a\"", "\"b\""));
+    }
+}
@@ -1,17 +1,32 @@
 //! WAN Optimization Module
 //!
 //! Provides WAN optimization techniques for improving throughput and reducing bandwidth:
-//! - Data deduplication
-//! - Protocol optimization
+//! - Data deduplication, with a persistent on-disk chunk cache
+//! - Protocol optimization, with HTTP and SMB/CIFS acceleration and split-TCP
 //! - Compression
 //! - Forward Error Correction (FEC)
+//! - Combined bandwidth savings statistics
 
 pub mod dedup;
+pub mod chunk_cache;
 pub mod protocol;
+pub mod http_accel;
+pub mod smb_accel;
+pub mod split_tcp;
 pub mod compression;
 pub mod fec;
+pub mod stats;
 
 pub use dedup::{Deduplicator, DedupStats};
-pub use protocol::{ProtocolOptimizer, ProtocolType};
-pub use compression::{Compressor, CompressionType};
-pub use fec::{FecEncoder, FecDecoder, FecStats};
+pub use chunk_cache::ChunkCache;
+pub use protocol::{Accelerator, ProtocolOptimizer, ProtocolStats, ProtocolType};
+pub use http_accel::{HttpAccelerator, HttpCacheConfig, HttpCacheStats, HttpOrigin, HttpRequest, HttpResponse};
+pub use smb_accel::{SmbAccelerator, SmbBackend, SmbCacheConfig, SmbCacheStats, SmbFileInfo};
+pub use split_tcp::{
+    BypassReason, PathEstimate, SplitDecision, SplitTcpConfig, SplitTcpProxy, SplitTcpStats, TcpSessionStats,
+};
+pub use compression::{
+    AdaptiveConfig, AdaptiveState, AlgorithmBytes, Compressor, CompressionStats, CompressionType, SystemLoad,
+};
+pub use fec::{AdaptiveFecConfig, BlockHeader, FecEncoder, FecDecoder, FecStats, PathFecController};
+pub use stats::WanOptStats;
@@ -1,15 +1,25 @@
 //! Protocol Optimization
 //!
 //! Optimizes specific protocol behaviors for WAN links:
-//! - TCP window scaling
+//! - TCP window scaling, with a split-TCP proxy for high-BDP links
 //! - HTTP/HTTPS optimization
 //! - DNS caching
 //! - SMB/CIFS optimization
+//!
+//! HTTP and SMB flows are handed to protocol-specific accelerators; other
+//! protocols, and any protocol with acceleration turned off, bypass them.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::http_accel::{HttpAccelerator, HttpCacheStats};
+use crate::smb_accel::{SmbAccelerator, SmbCacheStats};
+use crate::split_tcp::{SplitTcpConfig, SplitTcpProxy, SplitTcpStats};
 
 /// Protocol type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProtocolType {
     Tcp,
     Http,
@@ -20,11 +30,49 @@ pub enum ProtocolType {
     Other,
 }
 
+impl ProtocolType {
+    /// Protocol usually found on a TCP destination port
+    pub fn from_port(port: u16) -> Self {
+        match port {
+            80 | 8080 => ProtocolType::Http,
+            443 | 8443 => ProtocolType::Https,
+            53 => ProtocolType::Dns,
+            139 | 445 => ProtocolType::Smb,
+            2049 => ProtocolType::Nfs,
+            _ => ProtocolType::Tcp,
+        }
+    }
+}
+
+/// Accelerator selected for a flow
+pub enum Accelerator<'a> {
+    Http(&'a HttpAccelerator),
+    Smb(&'a SmbAccelerator),
+    /// Forward the flow untouched
+    Bypass,
+}
+
+/// Per-accelerator counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    pub http: HttpCacheStats,
+    pub smb: SmbCacheStats,
+    /// Flows forwarded without an accelerator
+    pub bypassed: u64,
+    #[serde(default)]
+    pub split_tcp: SplitTcpStats,
+}
+
 /// Protocol optimizer
 pub struct ProtocolOptimizer {
     tcp_window_size: u32,
     http_persistent_connections: bool,
     dns_cache_enabled: bool,
+    http: HttpAccelerator,
+    smb: SmbAccelerator,
+    bypassed_protocols: HashSet<ProtocolType>,
+    bypassed: AtomicU64,
+    split_tcp: Arc<SplitTcpProxy>,
 }
 
 impl ProtocolOptimizer {
@@ -34,6 +82,64 @@ impl ProtocolOptimizer {
             tcp_window_size: 65535 * 4, // 256KB window
             http_persistent_connections: true,
             dns_cache_enabled: true,
+            http: HttpAccelerator::default(),
+            smb: SmbAccelerator::default(),
+            bypassed_protocols: HashSet::new(),
+            bypassed: AtomicU64::new(0),
+            split_tcp: Arc::new(SplitTcpProxy::new(SplitTcpConfig::default())),
+        }
+    }
+
+    /// Use the given accelerators instead of the defaults
+    pub fn with_accelerators(mut self, http: HttpAccelerator, smb: SmbAccelerator) -> Self {
+        self.http = http;
+        self.smb = smb;
+        self
+    }
+
+    /// Terminate and re-originate TCP with `config`
+    pub fn with_split_tcp(mut self, config: SplitTcpConfig) -> Self {
+        self.split_tcp = Arc::new(SplitTcpProxy::new(config));
+        self
+    }
+
+    /// Split-TCP proxy, to serve a transparent listener with
+    pub fn split_tcp(&self) -> Arc<SplitTcpProxy> {
+        self.split_tcp.clone()
+    }
+
+    /// Accelerator for a flow of `protocol`
+    ///
+    /// HTTPS is bypassed: without terminating TLS there is nothing to cache.
+    pub fn accelerator(&self, protocol: ProtocolType) -> Accelerator<'_> {
+        if !self.bypassed_protocols.contains(&protocol) {
+            match protocol {
+                ProtocolType::Http => return Accelerator::Http(&self.http),
+                ProtocolType::Smb => return Accelerator::Smb(&self.smb),
+                _ => {}
+            }
+        }
+
+        self.bypassed.fetch_add(1, Ordering::Relaxed);
+        Accelerator::Bypass
+    }
+
+    /// Turn acceleration for `protocol` off or back on
+    pub fn set_bypass(&mut self, protocol: ProtocolType, bypass: bool) {
+        if bypass {
+            self.bypassed_protocols.insert(protocol);
+        } else {
+            self.bypassed_protocols.remove(&protocol);
+        }
+    }
+
+    /// Accelerator counters
+    pub fn stats(&self) -> ProtocolStats {
+        ProtocolStats {
+            http: self.http.stats(),
+            smb: self.smb.stats(),
+            bypassed: self.bypassed.load(Ordering::Relaxed),
+            split_tcp: self.split_tcp.stats(),
         }
     }
 
@@ -183,4 +289,21 @@ mod tests {
         let tcp_opts = optimizer.optimize_tcp(1);
         assert_eq!(tcp_opts.window_size, 524288);
     }
+
+    #[test]
+    fn test_accelerator_selection() {
+        let mut optimizer = ProtocolOptimizer::new();
+
+        assert!(matches!(optimizer.accelerator(ProtocolType::Http), Accelerator::Http(_)));
+        assert!(matches!(optimizer.accelerator(ProtocolType::Smb), Accelerator::Smb(_)));
+        assert!(matches!(optimizer.accelerator(ProtocolType::Https), Accelerator::Bypass));
+        assert!(matches!(optimizer.accelerator(ProtocolType::Other), Accelerator::Bypass));
+
+        optimizer.set_bypass(ProtocolType::Smb, true);
+        assert!(matches!(optimizer.accelerator(ProtocolType::Smb), Accelerator::Bypass));
+        optimizer.set_bypass(ProtocolType::Smb, false);
+        assert!(matches!(optimizer.accelerator(ProtocolType::Smb), Accelerator::Smb(_)));
+
+        assert_eq!(optimizer.stats().bypassed, 3);
+    }
 }
@@ -0,0 +1,387 @@
+//! SMB/CIFS Acceleration
+//!
+//! SMB clients issue many small reads and metadata queries, each a WAN
+//! round trip. The accelerator reads ahead on sequential access, growing
+//! the window while the client keeps reading sequentially, and caches file
+//! metadata for a short TTL. Writes pass through and invalidate both.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// File metadata returned by a stat/query-info request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmbFileInfo {
+    pub size: u64,
+    /// Last write time, seconds since the Unix epoch
+    pub modified: u64,
+    pub is_directory: bool,
+}
+
+/// File server on the far side of the WAN link
+#[async_trait]
+pub trait SmbBackend: Send + Sync {
+    /// Read up to `len` bytes at `offset`; shorter at end of file
+    async fn read(&self, path: &str, offset: u64, len: usize) -> Result<Vec<u8>>;
+
+    async fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<()>;
+
+    async fn stat(&self, path: &str) -> Result<SmbFileInfo>;
+}
+
+/// Read-ahead and metadata cache tuning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmbCacheConfig {
+    /// Read-ahead window on the first read of a run
+    pub initial_read_ahead: usize,
+
+    /// Largest read-ahead window; the window doubles on each sequential read
+    pub max_read_ahead: usize,
+
+    /// How long file metadata is served from cache
+    pub metadata_ttl: Duration,
+
+    /// Most files with read-ahead buffers or cached metadata
+    pub max_files: usize,
+}
+
+impl Default for SmbCacheConfig {
+    fn default() -> Self {
+        Self {
+            initial_read_ahead: 64 * 1024,
+            max_read_ahead: 4 * 1024 * 1024,
+            metadata_ttl: Duration::from_secs(5),
+            max_files: 1024,
+        }
+    }
+}
+
+/// SMB cache counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SmbCacheStats {
+    /// Reads served from a read-ahead buffer
+    pub read_hits: u64,
+    /// Reads that went to the server
+    pub read_misses: u64,
+    /// Bytes fetched beyond what the client asked for
+    pub bytes_prefetched: u64,
+    /// Metadata queries served from cache
+    pub metadata_hits: u64,
+    pub metadata_misses: u64,
+    /// Writes passed through, invalidating cached state
+    pub writes: u64,
+}
+
+impl SmbCacheStats {
+    /// WAN round trips avoided
+    pub fn round_trips_saved(&self) -> u64 {
+        self.read_hits + self.metadata_hits
+    }
+}
+
+/// Read-ahead buffer for one file
+struct ReadAhead {
+    offset: u64,
+    data: Vec<u8>,
+    window: usize,
+    /// The read that filled the buffer reached end of file
+    at_eof: bool,
+    last_used: Instant,
+}
+
+impl ReadAhead {
+    fn end(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+
+    /// Bytes `[offset, offset + len)` if buffered; a short read at EOF counts
+    /// when the buffer already reaches it
+    fn slice(&self, offset: u64, len: usize) -> Option<Vec<u8>> {
+        if offset < self.offset {
+            return None;
+        }
+        let start = (offset - self.offset) as usize;
+        let end = start + len;
+        if end <= self.data.len() {
+            Some(self.data[start..end].to_vec())
+        } else if self.at_eof && start <= self.data.len() {
+            Some(self.data[start..].to_vec())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+struct SmbState {
+    read_ahead: HashMap<String, ReadAhead>,
+    metadata: HashMap<String, (SmbFileInfo, Instant)>,
+    stats: SmbCacheStats,
+}
+
+/// Near-side SMB read-ahead and metadata cache
+pub struct SmbAccelerator {
+    config: SmbCacheConfig,
+    state: Mutex<SmbState>,
+}
+
+impl SmbAccelerator {
+    pub fn new(config: SmbCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(SmbState::default()),
+        }
+    }
+
+    /// Read through the read-ahead buffer
+    pub async fn read(&self, backend: &dyn SmbBackend, path: &str, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let window = {
+            let mut state = self.state.lock().unwrap();
+
+            if let Some(buffer) = state.read_ahead.get_mut(path) {
+                if let Some(data) = buffer.slice(offset, len) {
+                    buffer.last_used = Instant::now();
+                    state.stats.read_hits += 1;
+                    return Ok(data);
+                }
+            }
+
+            // Sequential access continues where the last buffer ended
+            match state.read_ahead.get(path) {
+                Some(buffer) if offset >= buffer.offset && offset <= buffer.end() => {
+                    (buffer.window * 2).min(self.config.max_read_ahead)
+                }
+                _ => self.config.initial_read_ahead,
+            }
+        };
+
+        let fetch_len = len.max(window);
+        let data = backend.read(path, offset, fetch_len).await?;
+
+        let mut state = self.state.lock().unwrap();
+        state.stats.read_misses += 1;
+        state.stats.bytes_prefetched += data.len().saturating_sub(len) as u64;
+
+        let reply = data[..len.min(data.len())].to_vec();
+        state.read_ahead.insert(path.to_string(), ReadAhead {
+            offset,
+            at_eof: data.len() < fetch_len,
+            data,
+            window,
+            last_used: Instant::now(),
+        });
+        self.evict(&mut state);
+
+        Ok(reply)
+    }
+
+    /// Write through to the server, dropping cached state for the file
+    pub async fn write(&self, backend: &dyn SmbBackend, path: &str, offset: u64, data: &[u8]) -> Result<()> {
+        self.invalidate(path);
+        backend.write(path, offset, data).await?;
+        self.state.lock().unwrap().stats.writes += 1;
+        Ok(())
+    }
+
+    /// File metadata, from cache while within the TTL
+    pub async fn stat(&self, backend: &dyn SmbBackend, path: &str) -> Result<SmbFileInfo> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let cached = state.metadata.get(path)
+                .filter(|(_, fetched)| fetched.elapsed() < self.config.metadata_ttl)
+                .map(|(info, _)| info.clone());
+            if let Some(info) = cached {
+                state.stats.metadata_hits += 1;
+                return Ok(info);
+            }
+        }
+
+        let info = backend.stat(path).await?;
+
+        let mut state = self.state.lock().unwrap();
+        state.stats.metadata_misses += 1;
+        state.metadata.insert(path.to_string(), (info.clone(), Instant::now()));
+        self.evict(&mut state);
+
+        Ok(info)
+    }
+
+    /// Forget cached data and metadata for `path`
+    pub fn invalidate(&self, path: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.read_ahead.remove(path);
+        state.metadata.remove(path);
+    }
+
+    /// Counters
+    pub fn stats(&self) -> SmbCacheStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Keep at most `max_files` read-ahead buffers and metadata entries
+    fn evict(&self, state: &mut SmbState) {
+        while state.read_ahead.len() > self.config.max_files {
+            let Some(oldest) = state.read_ahead.iter()
+                .min_by_key(|(_, b)| b.last_used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            state.read_ahead.remove(&oldest);
+        }
+
+        while state.metadata.len() > self.config.max_files {
+            let Some(oldest) = state.metadata.iter()
+                .min_by_key(|(_, (_, fetched))| *fetched)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            state.metadata.remove(&oldest);
+        }
+    }
+}
+
+impl Default for SmbAccelerator {
+    fn default() -> Self {
+        Self::new(SmbCacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory file server counting requests
+    struct MockServer {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+        reads: Mutex<Vec<(u64, usize)>>,
+        stats: Mutex<u64>,
+    }
+
+    impl MockServer {
+        fn new(path: &str, size: usize) -> Self {
+            let data = (0..size).map(|i| (i % 251) as u8).collect();
+            Self {
+                files: Mutex::new(HashMap::from([(path.to_string(), data)])),
+                reads: Mutex::new(Vec::new()),
+                stats: Mutex::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SmbBackend for MockServer {
+        async fn read(&self, path: &str, offset: u64, len: usize) -> Result<Vec<u8>> {
+            self.reads.lock().unwrap().push((offset, len));
+            let files = self.files.lock().unwrap();
+            let data = files.get(path).ok_or_else(|| anyhow::anyhow!("No such file"))?;
+            let start = (offset as usize).min(data.len());
+            Ok(data[start..(start + len).min(data.len())].to_vec())
+        }
+
+        async fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let file = files.entry(path.to_string()).or_default();
+            let end = offset as usize + data.len();
+            if file.len() < end {
+                file.resize(end, 0);
+            }
+            file[offset as usize..end].copy_from_slice(data);
+            Ok(())
+        }
+
+        async fn stat(&self, path: &str) -> Result<SmbFileInfo> {
+            *self.stats.lock().unwrap() += 1;
+            let size = self.files.lock().unwrap().get(path).map(|f| f.len()).unwrap_or(0);
+            Ok(SmbFileInfo { size: size as u64, modified: 1_700_000_000, is_directory: false })
+        }
+    }
+
+    const PATH: &str = "\\\\fileserver\\share\\design.dwg";
+
+    fn config() -> SmbCacheConfig {
+        SmbCacheConfig {
+            initial_read_ahead: 16 * 1024,
+            max_read_ahead: 64 * 1024,
+            ..SmbCacheConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sequential_reads_use_read_ahead() {
+        let server = MockServer::new(PATH, 256 * 1024);
+        let accel = SmbAccelerator::new(config());
+
+        // A client reading the whole file in 4KB requests
+        let mut file = Vec::new();
+        for offset in (0..256 * 1024u64).step_by(4096) {
+            file.extend(accel.read(&server, PATH, offset, 4096).await.unwrap());
+        }
+        assert_eq!(file, server.files.lock().unwrap()[PATH]);
+
+        // Windows of 16K, 32K, then 64K until EOF instead of 64 round trips
+        let reads = server.reads.lock().unwrap().clone();
+        let windows: Vec<usize> = reads.iter().map(|(_, len)| *len).collect();
+        assert_eq!(windows, vec![16384, 32768, 65536, 65536, 65536, 65536]);
+
+        let stats = accel.stats();
+        assert_eq!(stats.read_misses, 6);
+        assert_eq!(stats.read_hits, 64 - 6);
+        assert_eq!(stats.round_trips_saved(), 58);
+
+        // Reading at EOF is answered from the buffer
+        assert!(accel.read(&server, PATH, 256 * 1024, 4096).await.unwrap().is_empty());
+        assert_eq!(accel.stats().read_hits, 59);
+    }
+
+    #[tokio::test]
+    async fn test_random_reads_do_not_grow_window() {
+        let server = MockServer::new(PATH, 1024 * 1024);
+        let accel = SmbAccelerator::new(config());
+
+        accel.read(&server, PATH, 0, 4096).await.unwrap();
+        accel.read(&server, PATH, 512 * 1024, 4096).await.unwrap();
+
+        let windows: Vec<usize> = server.reads.lock().unwrap().iter().map(|(_, len)| *len).collect();
+        assert_eq!(windows, vec![16384, 16384]);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_cache_and_write_invalidation() {
+        let server = MockServer::new(PATH, 1000);
+        let accel = SmbAccelerator::new(config());
+
+        assert_eq!(accel.stat(&server, PATH).await.unwrap().size, 1000);
+        assert_eq!(accel.stat(&server, PATH).await.unwrap().size, 1000);
+        assert_eq!(*server.stats.lock().unwrap(), 1);
+        accel.read(&server, PATH, 0, 100).await.unwrap();
+
+        // Writes invalidate both metadata and buffered data
+        accel.write(&server, PATH, 0, &[0xff; 2000]).await.unwrap();
+        assert_eq!(accel.stat(&server, PATH).await.unwrap().size, 2000);
+        assert_eq!(accel.read(&server, PATH, 0, 4).await.unwrap(), vec![0xff; 4]);
+
+        let stats = accel.stats();
+        assert_eq!(stats.metadata_hits, 1);
+        assert_eq!(stats.metadata_misses, 2);
+        assert_eq!(stats.read_misses, 2);
+        assert_eq!(stats.writes, 1);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_expires() {
+        let server = MockServer::new(PATH, 10);
+        let accel = SmbAccelerator::new(SmbCacheConfig {
+            metadata_ttl: Duration::ZERO,
+            ..config()
+        });
+
+        accel.stat(&server, PATH).await.unwrap();
+        accel.stat(&server, PATH).await.unwrap();
+        assert_eq!(accel.stats().metadata_misses, 2);
+    }
+}
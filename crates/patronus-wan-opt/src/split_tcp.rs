@@ -0,0 +1,724 @@
+//! Split-TCP Optimization
+//!
+//! Terminates client TCP connections at the appliance and re-originates
+//! them across the WAN, so each leg runs its own congestion control:
+//! - The LAN leg is acknowledged locally by this host's stack, SACK
+//!   included, so the client's ACK clock runs at LAN round-trip time
+//! - The WAN leg is opened with large socket buffers, which makes the
+//!   kernel negotiate a large window scale for the high-BDP segment
+//!
+//! Connections are intercepted with TPROXY, so the accepted socket's local
+//! address is the original destination. Ports in the exclusion list, such
+//! as BGP with its TCP-MD5 signatures, must stay end-to-end and should not
+//! be redirected at all; [`SplitTcpConfig::nft_rules`] leaves them out. If
+//! one arrives anyway, or the session table is full, the connection is
+//! relayed without tuning or session state.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+use crate::protocol::ProtocolType;
+
+/// Closed sessions kept for [`SplitTcpProxy::stats`]
+const RECENT_SESSIONS: usize = 64;
+
+/// Split-TCP configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitTcpConfig {
+    /// Socket buffer size on both legs, which bounds the advertised window
+    pub window_bytes: usize,
+    /// Optimized sessions tracked at once; further connections bypass
+    pub max_sessions: usize,
+    /// Protocols to optimize
+    pub protocols: HashSet<ProtocolType>,
+    /// Port classifications overriding [`ProtocolType::from_port`]
+    pub port_protocols: HashMap<u16, ProtocolType>,
+    /// Ports that must stay end-to-end (BGP, MSDP and LDP use TCP-MD5)
+    pub excluded_ports: BTreeSet<u16>,
+    /// Window an unoptimized end-to-end connection is assumed to get, for
+    /// the "before" estimates
+    pub baseline_window_bytes: u32,
+    pub connect_timeout: Duration,
+}
+
+impl Default for SplitTcpConfig {
+    fn default() -> Self {
+        Self {
+            window_bytes: 4 * 1024 * 1024,
+            max_sessions: 4096,
+            protocols: [
+                ProtocolType::Tcp,
+                ProtocolType::Http,
+                ProtocolType::Https,
+                ProtocolType::Smb,
+                ProtocolType::Nfs,
+                ProtocolType::Other,
+            ]
+            .into_iter()
+            .collect(),
+            port_protocols: HashMap::new(),
+            excluded_ports: [179, 639, 646].into_iter().collect(),
+            baseline_window_bytes: 65535,
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SplitTcpConfig {
+    pub fn protocol_for(&self, port: u16) -> ProtocolType {
+        self.port_protocols.get(&port).copied().unwrap_or_else(|| ProtocolType::from_port(port))
+    }
+
+    /// nftables rules sending TCP from `interface` to a TPROXY listener on
+    /// `proxy_port`, skipping excluded ports
+    ///
+    /// Needs the usual policy route for marked packets (`ip rule add fwmark
+    /// 1 lookup 100` and `ip route add local 0.0.0.0/0 dev lo table 100`).
+    pub fn nft_rules(&self, interface: &str, proxy_port: u16) -> String {
+        let excluded = self.excluded_ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", ");
+        let skip = if excluded.is_empty() {
+            String::new()
+        } else {
+            format!("        tcp dport {{ {} }} return\n", excluded)
+        };
+        format!(
+            "table inet wan_opt_split_tcp {{\n    chain prerouting {{\n        type filter hook prerouting priority mangle\n{}        iifname \"{}\" meta l4proto tcp tproxy to :{} meta mark set 1 accept\n    }}\n}}\n",
+            skip, interface, proxy_port
+        )
+    }
+}
+
+/// Why a connection was relayed without optimization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BypassReason {
+    /// Destination port is in the exclusion list
+    Excluded,
+    /// Protocol is not configured for optimization
+    Protocol,
+    /// Session table is full
+    TableFull,
+}
+
+/// How a connection will be handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDecision {
+    Optimize(ProtocolType),
+    Bypass(BypassReason),
+}
+
+/// Round trip and achievable throughput for one way of carrying a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PathEstimate {
+    /// Round trip the client's ACK clock runs at
+    pub rtt_us: u64,
+    /// Window-limited throughput
+    pub throughput_bps: f64,
+}
+
+/// One optimized connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpSessionStats {
+    pub id: u64,
+    pub client: SocketAddr,
+    pub destination: SocketAddr,
+    pub protocol: ProtocolType,
+    pub duration_ms: u64,
+    pub bytes_to_server: u64,
+    pub bytes_to_client: u64,
+    /// Client to appliance
+    pub lan_rtt_us: Option<u64>,
+    /// Appliance to server
+    pub wan_rtt_us: Option<u64>,
+    /// One end-to-end connection with the baseline window
+    pub before: Option<PathEstimate>,
+    /// Split at the appliance with the configured window
+    pub after: Option<PathEstimate>,
+    /// Bytes moved in both directions over the session's lifetime
+    pub measured_bps: f64,
+    /// Ended by a reset rather than a close, if it has ended
+    pub reset: bool,
+}
+
+/// Split-TCP counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SplitTcpStats {
+    pub active_sessions: usize,
+    pub sessions_total: u64,
+    pub bypassed_excluded: u64,
+    pub bypassed_protocol: u64,
+    pub bypassed_table_full: u64,
+    /// Sessions torn down because one side reset
+    pub resets: u64,
+    pub connect_failures: u64,
+    /// Active sessions, then recently closed ones
+    pub sessions: Vec<TcpSessionStats>,
+}
+
+struct Session {
+    client: SocketAddr,
+    destination: SocketAddr,
+    protocol: ProtocolType,
+    started: Instant,
+    bytes_to_server: AtomicU64,
+    bytes_to_client: AtomicU64,
+    lan_rtt: Mutex<Option<Duration>>,
+    wan_rtt: Mutex<Option<Duration>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    sessions_total: AtomicU64,
+    bypassed_excluded: AtomicU64,
+    bypassed_protocol: AtomicU64,
+    bypassed_table_full: AtomicU64,
+    resets: AtomicU64,
+    connect_failures: AtomicU64,
+}
+
+/// Split-TCP proxy
+pub struct SplitTcpProxy {
+    config: SplitTcpConfig,
+    sessions: Mutex<HashMap<u64, Arc<Session>>>,
+    recent: Mutex<VecDeque<TcpSessionStats>>,
+    next_id: AtomicU64,
+    counters: Counters,
+}
+
+impl SplitTcpProxy {
+    pub fn new(config: SplitTcpConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+            recent: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(1),
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn config(&self) -> &SplitTcpConfig {
+        &self.config
+    }
+
+    /// Transparent listener for TPROXY-redirected connections
+    ///
+    /// Requires `CAP_NET_ADMIN`. The buffers are set before listening so
+    /// accepted connections advertise the large window too.
+    pub fn bind_transparent(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = socket2::Socket::new(
+            socket2::Domain::for_address(addr),
+            socket2::Type::STREAM,
+            Some(socket2::Protocol::TCP),
+        )?;
+        socket.set_reuse_address(true)?;
+        socket.set_ip_transparent(true)?;
+        socket.set_recv_buffer_size(self.config.window_bytes)?;
+        socket.set_send_buffer_size(self.config.window_bytes)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    /// Accept connections from a transparent listener until it fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        let own = listener.local_addr()?;
+        loop {
+            let (client, peer) = listener.accept().await?;
+            let destination = client.local_addr()?;
+            if destination == own {
+                tracing::warn!("Dropping connection from {} made directly to the split-TCP listener", peer);
+                continue;
+            }
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.handle(client, destination).await {
+                    tracing::debug!("Split-TCP connection {} -> {} ended: {}", peer, destination, e);
+                }
+            });
+        }
+    }
+
+    /// How a new connection to `destination` would be handled
+    pub fn decide(&self, destination: SocketAddr) -> SplitDecision {
+        let port = destination.port();
+        if self.config.excluded_ports.contains(&port) {
+            return SplitDecision::Bypass(BypassReason::Excluded);
+        }
+        let protocol = self.config.protocol_for(port);
+        if !self.config.protocols.contains(&protocol) {
+            return SplitDecision::Bypass(BypassReason::Protocol);
+        }
+        if self.sessions.lock().unwrap().len() >= self.config.max_sessions {
+            return SplitDecision::Bypass(BypassReason::TableFull);
+        }
+        SplitDecision::Optimize(protocol)
+    }
+
+    /// Carry `client` to `destination`, optimized or bypassed
+    ///
+    /// Returns once both directions have closed. If either side resets, the
+    /// other is reset too and the session is dropped.
+    pub async fn handle(&self, mut client: TcpStream, destination: SocketAddr) -> io::Result<()> {
+        let client_addr = client.peer_addr()?;
+        let started = Instant::now();
+        let admitted = match self.decide(destination) {
+            SplitDecision::Optimize(protocol) => self.register(Session {
+                client: client_addr,
+                destination,
+                protocol,
+                started,
+                bytes_to_server: AtomicU64::new(0),
+                bytes_to_client: AtomicU64::new(0),
+                lan_rtt: Mutex::new(None),
+                wan_rtt: Mutex::new(None),
+            }),
+            SplitDecision::Bypass(reason) => Err(reason),
+        };
+        let (id, session) = match admitted {
+            Ok(admitted) => admitted,
+            Err(reason) => {
+                let counter = match reason {
+                    BypassReason::Excluded => &self.counters.bypassed_excluded,
+                    BypassReason::Protocol => &self.counters.bypassed_protocol,
+                    BypassReason::TableFull => &self.counters.bypassed_table_full,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Bypassing {} -> {} ({:?})", client_addr, destination, reason);
+                return self.bypass(client, destination).await;
+            }
+        };
+
+        let mut upstream = match self.connect(destination, self.config.window_bytes).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                self.sessions.lock().unwrap().remove(&id);
+                self.counters.connect_failures.fetch_add(1, Ordering::Relaxed);
+                reset(&client);
+                return Err(e);
+            }
+        };
+        let _ = client.set_nodelay(true);
+        *session.lan_rtt.lock().unwrap() = tcp_rtt(&client);
+        *session.wan_rtt.lock().unwrap() = tcp_rtt(&upstream).or(Some(started.elapsed()));
+        self.counters.sessions_total.fetch_add(1, Ordering::Relaxed);
+        let mut guard = SessionGuard { proxy: self, id, session: session.clone(), reset: false };
+
+        let result = relay(&mut client, &mut upstream, &session, self.config.window_bytes).await;
+
+        // Latest smoothed RTTs, for the closed-session record
+        if let Some(rtt) = tcp_rtt(&client) {
+            *session.lan_rtt.lock().unwrap() = Some(rtt);
+        }
+        if let Some(rtt) = tcp_rtt(&upstream) {
+            *session.wan_rtt.lock().unwrap() = Some(rtt);
+        }
+
+        if result.is_err() {
+            reset(&client);
+            reset(&upstream);
+            guard.reset = true;
+        }
+        result
+    }
+
+    /// Add a session to the table unless it is full
+    fn register(&self, session: Session) -> Result<(u64, Arc<Session>), BypassReason> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= self.config.max_sessions {
+            return Err(BypassReason::TableFull);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let session = Arc::new(session);
+        sessions.insert(id, session.clone());
+        Ok((id, session))
+    }
+
+    /// Relay without tuning or session state
+    async fn bypass(&self, mut client: TcpStream, destination: SocketAddr) -> io::Result<()> {
+        let mut upstream = match tokio::time::timeout(self.config.connect_timeout, TcpStream::connect(destination)).await {
+            Ok(Ok(upstream)) => upstream,
+            Ok(Err(e)) => {
+                reset(&client);
+                return Err(e);
+            }
+            Err(_) => {
+                reset(&client);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"));
+            }
+        };
+        match tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                reset(&client);
+                reset(&upstream);
+                Err(e)
+            }
+        }
+    }
+
+    async fn connect(&self, destination: SocketAddr, window_bytes: usize) -> io::Result<TcpStream> {
+        let socket = if destination.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        // Set before connecting, as the window scale is fixed by the handshake
+        socket.set_recv_buffer_size(window_bytes as u32)?;
+        socket.set_send_buffer_size(window_bytes as u32)?;
+        let stream = tokio::time::timeout(self.config.connect_timeout, socket.connect(destination))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn stats(&self) -> SplitTcpStats {
+        let mut sessions: Vec<TcpSessionStats> = self.sessions.lock().unwrap()
+            .iter()
+            .map(|(id, session)| self.session_stats(*id, session, false))
+            .collect();
+        sessions.sort_by_key(|s| s.id);
+        let active_sessions = sessions.len();
+        sessions.extend(self.recent.lock().unwrap().iter().cloned());
+
+        let c = &self.counters;
+        SplitTcpStats {
+            active_sessions,
+            sessions_total: c.sessions_total.load(Ordering::Relaxed),
+            bypassed_excluded: c.bypassed_excluded.load(Ordering::Relaxed),
+            bypassed_protocol: c.bypassed_protocol.load(Ordering::Relaxed),
+            bypassed_table_full: c.bypassed_table_full.load(Ordering::Relaxed),
+            resets: c.resets.load(Ordering::Relaxed),
+            connect_failures: c.connect_failures.load(Ordering::Relaxed),
+            sessions,
+        }
+    }
+
+    fn session_stats(&self, id: u64, session: &Session, reset: bool) -> TcpSessionStats {
+        let duration = session.started.elapsed();
+        let bytes_to_server = session.bytes_to_server.load(Ordering::Relaxed);
+        let bytes_to_client = session.bytes_to_client.load(Ordering::Relaxed);
+        let lan_rtt = *session.lan_rtt.lock().unwrap();
+        let wan_rtt = *session.wan_rtt.lock().unwrap();
+        let (before, after) = match wan_rtt {
+            Some(wan) => {
+                let (before, after) = estimate(
+                    lan_rtt.unwrap_or_default(),
+                    wan,
+                    self.config.baseline_window_bytes as f64,
+                    self.config.window_bytes as f64,
+                );
+                (Some(before), Some(after))
+            }
+            None => (None, None),
+        };
+
+        TcpSessionStats {
+            id,
+            client: session.client,
+            destination: session.destination,
+            protocol: session.protocol,
+            duration_ms: duration.as_millis() as u64,
+            bytes_to_server,
+            bytes_to_client,
+            lan_rtt_us: lan_rtt.map(|d| d.as_micros() as u64),
+            wan_rtt_us: wan_rtt.map(|d| d.as_micros() as u64),
+            before,
+            after,
+            measured_bps: (bytes_to_server + bytes_to_client) as f64 * 8.0 / duration.as_secs_f64().max(1e-6),
+            reset,
+        }
+    }
+}
+
+/// Window-limited estimates without and with the split
+///
+/// End to end, one window is in flight per LAN plus WAN round trip. Split,
+/// the client only waits for the LAN leg and the WAN leg runs with the large
+/// window, so the slower of the two legs sets the pace.
+fn estimate(lan_rtt: Duration, wan_rtt: Duration, baseline_window: f64, window: f64) -> (PathEstimate, PathEstimate) {
+    let floor = Duration::from_micros(1);
+    let end_to_end = (lan_rtt + wan_rtt).max(floor);
+    let before = PathEstimate {
+        rtt_us: end_to_end.as_micros() as u64,
+        throughput_bps: baseline_window * 8.0 / end_to_end.as_secs_f64(),
+    };
+
+    let lan = lan_rtt.max(floor);
+    let wan = wan_rtt.max(floor);
+    let after = PathEstimate {
+        rtt_us: lan.as_micros() as u64,
+        throughput_bps: (baseline_window * 8.0 / lan.as_secs_f64()).min(window * 8.0 / wan.as_secs_f64()),
+    };
+    (before, after)
+}
+
+/// Removes a session from the table however its task ends
+struct SessionGuard<'a> {
+    proxy: &'a SplitTcpProxy,
+    id: u64,
+    session: Arc<Session>,
+    reset: bool,
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        self.proxy.sessions.lock().unwrap().remove(&self.id);
+        if self.reset {
+            self.proxy.counters.resets.fetch_add(1, Ordering::Relaxed);
+        }
+        let stats = self.proxy.session_stats(self.id, &self.session, self.reset);
+        let mut recent = self.proxy.recent.lock().unwrap();
+        if recent.len() == RECENT_SESSIONS {
+            recent.pop_front();
+        }
+        recent.push_back(stats);
+    }
+}
+
+/// Copy both directions, passing half-closes through, until both are done
+/// or either fails
+async fn relay(client: &mut TcpStream, upstream: &mut TcpStream, session: &Session, buf_size: usize) -> io::Result<()> {
+    let buf_size = buf_size.clamp(16 * 1024, 1024 * 1024);
+    let (mut client_read, mut client_write) = client.split();
+    let (mut upstream_read, mut upstream_write) = upstream.split();
+    tokio::try_join!(
+        pump(&mut client_read, &mut upstream_write, &session.bytes_to_server, buf_size),
+        pump(&mut upstream_read, &mut client_write, &session.bytes_to_client, buf_size),
+    )?;
+    Ok(())
+}
+
+async fn pump<R, W>(from: &mut R, to: &mut W, counter: &AtomicU64, buf_size: usize) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; buf_size];
+    loop {
+        let n = from.read(&mut buf).await?;
+        if n == 0 {
+            to.shutdown().await?;
+            return Ok(());
+        }
+        to.write_all(&buf[..n]).await?;
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// Make the socket send a reset when it is dropped
+fn reset(stream: &TcpStream) {
+    let _ = socket2::SockRef::from(stream).set_linger(Some(Duration::ZERO));
+}
+
+/// Smoothed round-trip time of a TCP socket, from `TCP_INFO`
+#[cfg(target_os = "linux")]
+fn tcp_rtt(stream: &TcpStream) -> Option<Duration> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: tcp_info is plain data, so all-zero is a valid value
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    // SAFETY: info and len describe a writable buffer of the right size and
+    // the fd stays open for the duration of the call
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0 && info.tcpi_rtt > 0).then(|| Duration::from_micros(info.tcpi_rtt as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tcp_rtt(_stream: &TcpStream) -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Server that echoes until the client closes, or resets after reading
+    /// `reset_after` bytes
+    async fn origin(reset_after: Option<usize>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 64 * 1024];
+                    let mut seen = 0;
+                    loop {
+                        let n = stream.read(&mut buf).await.unwrap_or(0);
+                        if n == 0 {
+                            return;
+                        }
+                        seen += n;
+                        if reset_after.is_some_and(|limit| seen >= limit) {
+                            reset(&stream);
+                            return;
+                        }
+                        stream.write_all(&buf[..n]).await.unwrap();
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    /// Front end handing every connection to the proxy for `destination`
+    async fn front(proxy: Arc<SplitTcpProxy>, destination: SocketAddr) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (client, _) = listener.accept().await.unwrap();
+                let proxy = proxy.clone();
+                tokio::spawn(async move {
+                    let _ = proxy.handle(client, destination).await;
+                });
+            }
+        });
+        addr
+    }
+
+    async fn wait_until(proxy: &SplitTcpProxy, done: impl Fn(&SplitTcpStats) -> bool) -> SplitTcpStats {
+        for _ in 0..200 {
+            let stats = proxy.stats();
+            if done(&stats) {
+                return stats;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("split-TCP proxy did not reach the expected state");
+    }
+
+    fn config(excluded_port: Option<u16>) -> SplitTcpConfig {
+        let mut config = SplitTcpConfig {
+            max_sessions: 1,
+            ..Default::default()
+        };
+        config.excluded_ports.extend(excluded_port);
+        config
+    }
+
+    #[tokio::test]
+    async fn test_optimized_session_relays_and_reports_estimates() {
+        let destination = origin(None).await;
+        let proxy = Arc::new(SplitTcpProxy::new(config(None)));
+        let addr = front(proxy.clone(), destination).await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let payload: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let (mut read_half, mut write_half) = client.split();
+        let (_, echoed) = tokio::join!(
+            async { write_half.write_all(&payload).await.unwrap() },
+            async {
+                let mut echoed = vec![0u8; payload.len()];
+                read_half.read_exact(&mut echoed).await.unwrap();
+                echoed
+            }
+        );
+        assert_eq!(echoed, payload);
+
+        let stats = proxy.stats();
+        assert_eq!(stats.active_sessions, 1);
+        let session = &stats.sessions[0];
+        assert_eq!(session.destination, destination);
+        assert_eq!(session.protocol, ProtocolType::Tcp);
+        assert_eq!(session.bytes_to_server, 200_000);
+        assert!(session.wan_rtt_us.is_some());
+        let (before, after) = (session.before.unwrap(), session.after.unwrap());
+        assert!(after.rtt_us <= before.rtt_us);
+        assert!(after.throughput_bps >= before.throughput_bps);
+
+        drop(client);
+        let stats = wait_until(&proxy, |s| s.active_sessions == 0).await;
+        assert_eq!(stats.sessions_total, 1);
+        assert_eq!(stats.resets, 0);
+        assert!(!stats.sessions[0].reset);
+    }
+
+    #[tokio::test]
+    async fn test_bypass_when_excluded_or_table_full() {
+        let destination = origin(None).await;
+        let proxy = Arc::new(SplitTcpProxy::new(config(Some(destination.port()))));
+        assert_eq!(proxy.decide(destination), SplitDecision::Bypass(BypassReason::Excluded));
+        assert_eq!(
+            proxy.decide("192.0.2.1:179".parse().unwrap()),
+            SplitDecision::Bypass(BypassReason::Excluded)
+        );
+        assert_eq!(
+            proxy.decide("192.0.2.1:53".parse().unwrap()),
+            SplitDecision::Bypass(BypassReason::Protocol)
+        );
+        assert_eq!(
+            proxy.decide("192.0.2.1:445".parse().unwrap()),
+            SplitDecision::Optimize(ProtocolType::Smb)
+        );
+
+        // Excluded traffic still flows end to end
+        let addr = front(proxy.clone(), destination).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"keepalive").await.unwrap();
+        let mut buf = [0u8; 9];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"keepalive");
+        assert_eq!(proxy.stats().bypassed_excluded, 1);
+        assert_eq!(proxy.active_sessions(), 0);
+
+        // One session fills the table, so the next connection bypasses
+        let destination = origin(None).await;
+        let proxy = Arc::new(SplitTcpProxy::new(config(None)));
+        let addr = front(proxy.clone(), destination).await;
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"a").await.unwrap();
+        first.read_exact(&mut [0u8; 1]).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second.write_all(b"b").await.unwrap();
+        let mut buf = [0u8; 1];
+        second.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"b");
+
+        let stats = proxy.stats();
+        assert_eq!(stats.active_sessions, 1);
+        assert_eq!(stats.bypassed_table_full, 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_reset_tears_down_session() {
+        let destination = origin(Some(4)).await;
+        let proxy = Arc::new(SplitTcpProxy::new(config(None)));
+        let addr = front(proxy.clone(), destination).await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"boom").await.unwrap();
+        let mut buf = [0u8; 16];
+        let err = client.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        let stats = wait_until(&proxy, |s| s.active_sessions == 0 && s.resets == 1).await;
+        assert!(stats.sessions[0].reset);
+        assert_eq!(stats.sessions[0].bytes_to_server, 4);
+    }
+
+    #[test]
+    fn test_nft_rules_skip_excluded_ports() {
+        let rules = SplitTcpConfig::default().nft_rules("lan0", 9040);
+        assert!(rules.contains("tcp dport { 179, 639, 646 } return"));
+        assert!(rules.contains("iifname \"lan0\" meta l4proto tcp tproxy to :9040"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Combined WAN Optimization Statistics
+//!
+//! Aggregates deduplication, compression and FEC counters into a single
+//! "bandwidth saved" figure. The stages are assumed to run in pipeline
+//! order: dedup removes repeated chunks, compression shrinks what is left,
+//! and FEC adds padding and parity on the way out.
+
+use crate::compression::{CompressionStats, Compressor};
+use crate::dedup::{DedupStats, Deduplicator};
+use crate::fec::{FecEncoder, FecStats};
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of all WAN optimization stages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WanOptStats {
+    pub dedup: DedupStats,
+    pub compression: CompressionStats,
+    pub fec: FecStats,
+}
+
+impl WanOptStats {
+    /// Snapshot the statistics of each stage
+    pub async fn collect(dedup: &Deduplicator, compressor: &Compressor, fec: &FecEncoder) -> Self {
+        Self {
+            dedup: dedup.get_stats().await,
+            compression: compressor.stats(),
+            fec: fec.stats().clone(),
+        }
+    }
+
+    /// Reset the statistics of each stage
+    pub async fn reset(dedup: &Deduplicator, compressor: &Compressor, fec: &mut FecEncoder) {
+        dedup.reset_stats().await;
+        compressor.reset_stats();
+        fec.reset_stats();
+    }
+
+    /// Bytes offered by applications, measured at the first active stage
+    pub fn original_bytes(&self) -> u64 {
+        if self.dedup.total_bytes > 0 {
+            self.dedup.total_bytes
+        } else if self.compression.bytes_in > 0 {
+            self.compression.bytes_in
+        } else {
+            self.fec.data_bytes
+        }
+    }
+
+    /// Net bytes kept off the wire; negative if FEC overhead outweighs the savings
+    pub fn bandwidth_saved(&self) -> i64 {
+        self.dedup.duplicate_bytes as i64
+            + self.compression.bytes_saved()
+            - self.fec.overhead_bytes as i64
+    }
+
+    /// Bytes actually sent after all stages
+    pub fn wire_bytes(&self) -> u64 {
+        (self.original_bytes() as i64 - self.bandwidth_saved()).max(0) as u64
+    }
+
+    /// Net savings as a percentage of the original bytes
+    pub fn savings_pct(&self) -> f64 {
+        let original = self.original_bytes();
+        if original == 0 {
+            0.0
+        } else {
+            self.bandwidth_saved() as f64 / original as f64 * 100.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionType;
+
+    #[test]
+    fn test_aggregate_mixed_workload() {
+        // 10,000 bytes offered, 4,000 of them repeats; the remaining 6,000
+        // compress to 2,500; FEC adds 500 bytes of parity
+        let stats = WanOptStats {
+            dedup: DedupStats {
+                total_bytes: 10_000,
+                unique_bytes: 6_000,
+                duplicate_bytes: 4_000,
+                ..DedupStats::default()
+            },
+            compression: CompressionStats {
+                bytes_in: 6_000,
+                bytes_out: 2_500,
+                ..CompressionStats::default()
+            },
+            fec: FecStats {
+                data_bytes: 2_500,
+                overhead_bytes: 500,
+                ..FecStats::default()
+            },
+        };
+
+        assert_eq!(stats.original_bytes(), 10_000);
+        assert_eq!(stats.bandwidth_saved(), 4_000 + 3_500 - 500);
+        assert_eq!(stats.wire_bytes(), 3_000);
+        assert!((stats.savings_pct() - 70.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fec_overhead_can_outweigh_savings() {
+        let stats = WanOptStats {
+            compression: CompressionStats {
+                bytes_in: 1_000,
+                bytes_out: 1_000,
+                ..CompressionStats::default()
+            },
+            fec: FecStats {
+                data_bytes: 1_000,
+                overhead_bytes: 250,
+                ..FecStats::default()
+            },
+            ..WanOptStats::default()
+        };
+
+        assert_eq!(stats.original_bytes(), 1_000);
+        assert_eq!(stats.bandwidth_saved(), -250);
+        assert_eq!(stats.wire_bytes(), 1_250);
+        assert!((stats.savings_pct() + 25.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_empty_stats() {
+        let stats = WanOptStats::default();
+        assert_eq!(stats.original_bytes(), 0);
+        assert_eq!(stats.bandwidth_saved(), 0);
+        assert_eq!(stats.savings_pct(), 0.0);
+        assert_eq!(stats.compression.compression_ratio(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_collect_and_reset() {
+        let dedup = Deduplicator::with_chunk_size(64);
+        let compressor = Compressor::new(CompressionType::Zstd);
+        let mut fec = FecEncoder::new(4, 1);
+
+        let block = b"GET /api/v1/status HTTP/1.1\r\nHost: branch-office\r\n\r\n".repeat(8);
+        for _ in 0..3 {
+            dedup.deduplicate(&block).await;
+        }
+        let compressed = compressor.compress(&block).unwrap();
+        fec.encode(&compressed).unwrap();
+
+        let stats = WanOptStats::collect(&dedup, &compressor, &fec).await;
+        assert_eq!(stats.original_bytes(), 3 * block.len() as u64);
+        assert_eq!(stats.compression.bytes_in, block.len() as u64);
+        assert_eq!(stats.compression.bytes_out, compressed.len() as u64);
+        assert!((stats.compression.ratio - compressed.len() as f64 / block.len() as f64).abs() < 1e-9);
+        assert_eq!(stats.fec.data_bytes, compressed.len() as u64);
+        assert_eq!(
+            stats.bandwidth_saved(),
+            stats.dedup.duplicate_bytes as i64 + stats.compression.bytes_saved() - stats.fec.overhead_bytes as i64,
+        );
+        assert!(stats.bandwidth_saved() > 0);
+
+        WanOptStats::reset(&dedup, &compressor, &mut fec).await;
+        let stats = WanOptStats::collect(&dedup, &compressor, &fec).await;
+        assert_eq!(stats.bandwidth_saved(), 0);
+        assert_eq!(stats.compression.ratio, 0.0);
+        assert_eq!(stats.fec.parity_shards, 1);
+        assert!(dedup.chunk_count().await > 0);
+    }
+}
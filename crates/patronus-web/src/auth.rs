@@ -44,6 +44,11 @@ impl UserRole {
     pub fn is_admin(&self) -> bool {
         matches!(self, UserRole::Admin)
     }
+
+    /// Packet payloads in live captures are shown to admins only
+    pub fn can_view_packet_payloads(&self) -> bool {
+        matches!(self, UserRole::Admin)
+    }
 }
 
 /// User record stored in the user database
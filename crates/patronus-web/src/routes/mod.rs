@@ -29,6 +29,7 @@ pub fn build_router(
         // WebSocket routes (temporarily disabled due to handler issues)
         // .route("/ws/metrics", get(crate::websocket::ws_metrics_handler))
         // .route("/ws/logs", get(crate::websocket::ws_logs_handler))
+        .route("/ws/capture", get(crate::websocket::ws_capture_handler))
 
         // Protected page routes (HTML) - require authentication
         .route("/", get(crate::simple_handlers::simple_index))
@@ -4,6 +4,7 @@ use patronus_config::ConfigStore;
 use patronus_firewall::RuleManager;
 use patronus_core::types::{FirewallRule as CoreFirewallRule, ChainType, FirewallAction};
 use crate::auth::AuthState;
+use patronus_diagnostics::PacketCaptureManager;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -16,6 +17,7 @@ pub struct AppState {
     pub system: Arc<SystemManager>,
     pub monitoring: Arc<MonitoringManager>,
     pub config_store: Arc<ConfigStore>,
+    pub captures: Arc<PacketCaptureManager>,
     pub auth: AuthState,
 }
 
@@ -31,6 +33,7 @@ impl AppState {
             system: Arc::new(SystemManager::new()),
             monitoring: Arc::new(MonitoringManager::new()),
             config_store: Arc::new(config_store),
+            captures: Arc::new(PacketCaptureManager::new()),
             auth: AuthState::new(),
         }
     }
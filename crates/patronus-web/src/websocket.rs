@@ -5,20 +5,32 @@
 //! - Live firewall logs
 //! - VPN connection events
 //! - System alerts and notifications
+//! - Live packet capture
 
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        Query, State,
     },
-    response::IntoResponse,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
 };
-use futures::{sink::SinkExt, stream::StreamExt};
+use futures::{sink::{Sink, SinkExt}, stream::{self, Stream, StreamExt}};
+use patronus_diagnostics::{CaptureConfig, LiveCapture, LiveCaptureOptions, LivePacket, PacketDetails};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::broadcast;
 use tokio::time::{interval, Duration};
 
+use crate::auth::{AuthError, AuthUser};
+
+/// Highest packet rate a live capture view may request
+const MAX_CAPTURE_RATE: u32 = 1000;
+
+/// Keepalive interval for live capture sockets; a failed ping ends the capture
+const CAPTURE_PING_INTERVAL: Duration = Duration::from_secs(5);
+
 /// WebSocket message types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -66,6 +78,14 @@ pub enum WsMessage {
         message: String,
     },
 
+    /// Packet from a live capture
+    CapturePacket {
+        capture_id: String,
+        /// One in this many packets is being shown
+        sample_rate: u32,
+        packet: PacketDetails,
+    },
+
     /// Ping/Pong for keepalive
     Ping,
     Pong,
@@ -124,19 +144,7 @@ async fn handle_metrics_socket(
 
     // Spawn task to send broadcasts to this client
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            let json = match serde_json::to_string(&msg) {
-                Ok(j) => j,
-                Err(e) => {
-                    tracing::error!("Failed to serialize WebSocket message: {}", e);
-                    continue;
-                }
-            };
-
-            if sender.send(Message::Text(json)).await.is_err() {
-                break;
-            }
-        }
+        forward_broadcasts(&mut rx, &mut sender, |_| true).await;
     });
 
     // Spawn task to receive messages from client (mostly ping/pong)
@@ -175,21 +183,135 @@ async fn handle_logs_socket(socket: WebSocket, broadcaster: Arc<WsBroadcaster>)
 
     // Filter for log entries only
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            // Only send log entries on this channel
-            if matches!(msg, WsMessage::LogEntry { .. }) {
-                let json = match serde_json::to_string(&msg) {
-                    Ok(j) => j,
-                    Err(_) => continue,
-                };
-
-                if sender.send(Message::Text(json)).await.is_err() {
-                    break;
-                }
+        forward_broadcasts(&mut rx, &mut sender, |msg| matches!(msg, WsMessage::LogEntry { .. })).await;
+    });
+
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            if matches!(msg, Message::Close(_)) {
+                break;
             }
         }
     });
 
+    tokio::select! {
+        _ = &mut send_task => recv_task.abort(),
+        _ = &mut recv_task => send_task.abort(),
+    };
+}
+
+/// Send the broadcasts that pass `keep` to a socket until it closes
+///
+/// A client that falls behind skips what it missed instead of being
+/// disconnected.
+async fn forward_broadcasts<S>(
+    rx: &mut broadcast::Receiver<WsMessage>,
+    sender: &mut S,
+    keep: fn(&WsMessage) -> bool,
+) where
+    S: Sink<Message> + Unpin,
+{
+    loop {
+        let msg = match rx.recv().await {
+            Ok(msg) => msg,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                tracing::debug!("WebSocket client skipped {} lagged messages", missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        if !keep(&msg) {
+            continue;
+        }
+
+        let json = match serde_json::to_string(&msg) {
+            Ok(j) => j,
+            Err(e) => {
+                tracing::error!("Failed to serialize WebSocket message: {}", e);
+                continue;
+            }
+        };
+
+        if sender.send(Message::Text(json)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Query parameters for a live capture view
+#[derive(Debug, Deserialize)]
+pub struct CaptureStreamParams {
+    pub interface: String,
+    /// BPF filter, applied in the kernel
+    pub filter: Option<String>,
+    /// Packets per second to show; faster traffic is sampled
+    pub max_rate: Option<u32>,
+    /// Ask for hex payloads; honoured only with payload permission
+    #[serde(default)]
+    pub payload: bool,
+}
+
+/// WebSocket handler for a live packet capture view
+///
+/// The capture runs for as long as the socket stays open.
+pub async fn ws_capture_handler(
+    ws: WebSocketUpgrade,
+    auth_user: AuthUser,
+    State(state): State<crate::state::AppState>,
+    Query(params): Query<CaptureStreamParams>,
+) -> Response {
+    let role = auth_user.session.role;
+    if !role.can_modify() {
+        return AuthError::Forbidden.into_response();
+    }
+
+    let config = CaptureConfig {
+        interface: params.interface,
+        filter: params.filter.filter(|f| !f.trim().is_empty()),
+        ..CaptureConfig::default()
+    };
+    let options = LiveCaptureOptions {
+        max_packets_per_sec: params.max_rate.unwrap_or(50).clamp(1, MAX_CAPTURE_RATE),
+        include_payload: params.payload && role.can_view_packet_payloads(),
+        ..LiveCaptureOptions::default()
+    };
+
+    let capture = match state.captures.start_live_capture(config, options) {
+        Ok(capture) => capture,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response();
+        }
+    };
+
+    tracing::info!("User {} started live capture {} on {}",
+        auth_user.session.username, capture.id, capture.interface);
+
+    ws.on_upgrade(move |socket| handle_capture_socket(socket, capture))
+}
+
+/// Stream a live capture to this socket until it closes
+///
+/// Packets come from the capture's own bounded channel rather than the
+/// shared broadcaster, so a busy capture cannot crowd out other sockets.
+async fn handle_capture_socket(socket: WebSocket, mut capture: LiveCapture) {
+    let (mut sender, mut receiver) = socket.split();
+    let capture_id = capture.id.clone();
+
+    // Owns the capture: aborting this task drops it, which kills tcpdump
+    let send_id = capture_id.clone();
+    let mut send_task = tokio::spawn(async move {
+        let packets = stream::unfold(&mut capture, |capture| async move {
+            Some((capture.recv().await?, capture))
+        });
+        stream_capture(&mut sender, &send_id, packets).await;
+
+        if let Err(e) = capture.stop().await {
+            tracing::warn!("Failed to stop live capture {}: {}", send_id, e);
+        }
+    });
+
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
             if matches!(msg, Message::Close(_)) {
@@ -198,10 +320,54 @@ async fn handle_logs_socket(socket: WebSocket, broadcaster: Arc<WsBroadcaster>)
         }
     });
 
+    // Whichever side ends first, the capture is torn down with the socket
     tokio::select! {
         _ = &mut send_task => recv_task.abort(),
         _ = &mut recv_task => send_task.abort(),
     };
+
+    tracing::info!("Live capture {} closed", capture_id);
+}
+
+/// Send capture packets to a socket, pinging it while traffic is quiet
+///
+/// Returns when the capture ends or the socket goes away. A slow browser
+/// misses packets: the capture drops them rather than stalling.
+async fn stream_capture<S, P>(sender: &mut S, capture_id: &str, packets: P)
+where
+    S: Sink<Message> + Unpin,
+    P: Stream<Item = LivePacket>,
+{
+    let mut packets = std::pin::pin!(packets);
+    let mut ping = interval(CAPTURE_PING_INTERVAL);
+
+    loop {
+        let live = tokio::select! {
+            live = packets.next() => match live {
+                Some(live) => live,
+                None => break,
+            },
+            _ = ping.tick() => {
+                if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let msg = WsMessage::CapturePacket {
+            capture_id: capture_id.to_string(),
+            sample_rate: live.sample_rate,
+            packet: live.packet,
+        };
+        let json = match serde_json::to_string(&msg) {
+            Ok(j) => j,
+            Err(_) => continue,
+        };
+        if sender.send(Message::Text(json)).await.is_err() {
+            break;
+        }
+    }
 }
 
 /// Start background task to generate and broadcast metrics
@@ -337,6 +503,100 @@ mod tests {
         assert!(json.contains("50.0"));
     }
 
+    #[test]
+    fn test_capture_packet_serialization() {
+        let msg = WsMessage::CapturePacket {
+            capture_id: "c1".to_string(),
+            sample_rate: 4,
+            packet: PacketDetails {
+                packet_number: 12,
+                details: "UDP 10.0.0.1:53 -> 10.0.0.2:40000 90 bytes".to_string(),
+                timestamp: None,
+                source: Some("10.0.0.1:53".to_string()),
+                destination: Some("10.0.0.2:40000".to_string()),
+                protocol: Some("UDP".to_string()),
+                payload_hex: None,
+            },
+        };
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["type"], "capture_packet");
+        assert_eq!(json["sample_rate"], 4);
+        assert_eq!(json["packet"]["protocol"], "UDP");
+        assert!(json["packet"]["payload_hex"].is_null());
+    }
+
+    #[test]
+    fn test_payload_permission() {
+        use crate::auth::UserRole;
+
+        assert!(UserRole::Admin.can_view_packet_payloads());
+        assert!(!UserRole::Operator.can_view_packet_payloads());
+        assert!(!UserRole::ReadOnly.can_view_packet_payloads());
+    }
+
+    fn live_packet(n: u32) -> LivePacket {
+        LivePacket {
+            packet: PacketDetails {
+                packet_number: n,
+                details: format!("TCP packet {}", n),
+                timestamp: None,
+                source: None,
+                destination: None,
+                protocol: Some("TCP".to_string()),
+                payload_hex: None,
+            },
+            sample_rate: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capture_does_not_disconnect_metrics_subscribers() {
+        let broadcaster = WsBroadcaster::new();
+        let mut rx = broadcaster.subscribe();
+        let (mut metrics_socket, mut metrics_frames) = futures::channel::mpsc::unbounded();
+        let metrics_task = tokio::spawn(async move {
+            forward_broadcasts(&mut rx, &mut metrics_socket, |_| true).await;
+        });
+
+        // A capture at the highest rate streams next to the metrics socket
+        let (mut capture_socket, capture_frames) = futures::channel::mpsc::unbounded();
+        let packets = stream::iter((0..MAX_CAPTURE_RATE).map(live_packet));
+        stream_capture(&mut capture_socket, "c1", packets).await;
+        drop(capture_socket);
+        let frames: Vec<Message> = capture_frames.collect().await;
+        let sent = frames.iter().filter(|m| matches!(m, Message::Text(t) if t.contains("capture_packet"))).count();
+        assert_eq!(sent, MAX_CAPTURE_RATE as usize);
+
+        // Overrun the metrics subscriber; it skips ahead instead of hanging up
+        for _ in 0..250 {
+            broadcast_alert(&broadcaster, "info", "test", "flood");
+        }
+        broadcaster.broadcast(WsMessage::SystemMetrics {
+            cpu: 1.0,
+            memory: 2.0,
+            disk: 3.0,
+            network_rx: 4,
+            network_tx: 5,
+        });
+
+        let metrics = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(frame) = metrics_frames.next().await {
+                match frame {
+                    Message::Text(text) if text.contains("system_metrics") => return Some(text),
+                    Message::Text(text) => assert!(!text.contains("capture_packet")),
+                    _ => {}
+                }
+            }
+            None
+        })
+        .await
+        .unwrap();
+        assert!(metrics.is_some());
+        assert!(!metrics_task.is_finished());
+        metrics_task.abort();
+    }
+
     #[test]
     fn test_broadcast() {
         let broadcaster = WsBroadcaster::new();